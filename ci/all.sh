@@ -26,6 +26,12 @@ ci/all.sh "$PARENT_DIR"
 echo "***                                           DNS UTILITY TAIL                                        ***"
 echo "*********************************************************************************************************"
 echo "*********************************************************************************************************"
+echo "***                                          SUBSTRATUM CLI HEAD                                        ***"
+cd "$CI_DIR/../substratum_cli"
+ci/all.sh "$PARENT_DIR"
+echo "***                                          SUBSTRATUM CLI TAIL                                        ***"
+echo "*********************************************************************************************************"
+echo "*********************************************************************************************************"
 echo "***                                             NODE UI HEAD                                          ***"
 cd "$CI_DIR/../node-ui"
 ci/all.sh
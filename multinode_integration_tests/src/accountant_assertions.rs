@@ -0,0 +1,186 @@
+// Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use crate::substratum_node::SubstratumNode;
+use crate::substratum_real_node::SubstratumRealNode;
+use node_lib::sub_lib::wallet::Wallet;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+
+// Accountants write to their databases a little after the traffic they're charging for, so balances
+// are read over and over until they match or this long has passed.
+pub const DEFAULT_BALANCE_TIMEOUT: Duration = Duration::from_millis(5000);
+
+// Receivables are keyed by the consuming wallet that owes the node, payables by the earning wallet
+// the node owes. An expected balance of 0 is also satisfied by there being no account at all.
+pub fn assert_balances(
+    node: &SubstratumRealNode,
+    expected_receivables: &[(Wallet, i64)],
+    expected_payables: &[(Wallet, i64)],
+) {
+    assert_balances_within(
+        node,
+        expected_receivables,
+        expected_payables,
+        DEFAULT_BALANCE_TIMEOUT,
+    )
+}
+
+pub fn assert_balances_within(
+    node: &SubstratumRealNode,
+    expected_receivables: &[(Wallet, i64)],
+    expected_payables: &[(Wallet, i64)],
+    timeout: Duration,
+) {
+    let result = await_balances(
+        || {
+            let daos = node.daos();
+            let receivables = expected_receivables
+                .iter()
+                .map(|(wallet, _)| {
+                    daos.receivable
+                        .account_status(wallet)
+                        .map(|account| account.balance)
+                })
+                .collect();
+            let payables = expected_payables
+                .iter()
+                .map(|(wallet, _)| {
+                    daos.payable
+                        .account_status(wallet)
+                        .map(|account| account.balance)
+                })
+                .collect();
+            (receivables, payables)
+        },
+        expected_receivables,
+        expected_payables,
+        timeout,
+    );
+    if let Err(mismatches) = result {
+        panic!(
+            "After {:?}, {}'s balances were still wrong:\n{}",
+            timeout,
+            node.name(),
+            mismatches.join("\n")
+        )
+    }
+}
+
+type ActualBalances = (Vec<Option<i64>>, Vec<Option<i64>>);
+
+fn await_balances<F>(
+    read_balances: F,
+    expected_receivables: &[(Wallet, i64)],
+    expected_payables: &[(Wallet, i64)],
+    timeout: Duration,
+) -> Result<(), Vec<String>>
+where
+    F: Fn() -> ActualBalances,
+{
+    let time_limit = Instant::now() + timeout;
+    loop {
+        let (receivables, payables) = read_balances();
+        let mut mismatches = find_mismatches("receivable from", expected_receivables, receivables);
+        mismatches.extend(find_mismatches("payable to", expected_payables, payables));
+        if mismatches.is_empty() {
+            return Ok(());
+        }
+        if Instant::now() >= time_limit {
+            return Err(mismatches);
+        }
+        thread::sleep(Duration::from_millis(250))
+    }
+}
+
+fn find_mismatches(
+    direction: &str,
+    expected: &[(Wallet, i64)],
+    actual: Vec<Option<i64>>,
+) -> Vec<String> {
+    expected
+        .iter()
+        .zip(actual.into_iter())
+        .filter_map(|((wallet, expected_balance), actual_balance_opt)| {
+            match (*expected_balance, actual_balance_opt) {
+                (0, None) => None,
+                (expected_balance, Some(actual_balance)) if actual_balance == expected_balance => {
+                    None
+                }
+                (expected_balance, None) => Some(format!(
+                    "  {} {}: expected {}, but there is no account",
+                    direction, wallet.address, expected_balance
+                )),
+                (expected_balance, Some(actual_balance)) => Some(format!(
+                    "  {} {}: expected {}, but found {}",
+                    direction, wallet.address, expected_balance, actual_balance
+                )),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    #[test]
+    fn balances_that_match_at_once_are_accepted() {
+        let expected_receivables = vec![(Wallet::new("consuming"), 100)];
+        let expected_payables = vec![(Wallet::new("earning"), 0)];
+
+        let result = await_balances(
+            || (vec![Some(100)], vec![None]),
+            &expected_receivables,
+            &expected_payables,
+            Duration::from_millis(0),
+        );
+
+        assert_eq!(result, Ok(()));
+    }
+
+    #[test]
+    fn balances_are_read_again_until_they_match() {
+        let readings = RefCell::new(vec![
+            (vec![None], vec![Some(10)]),
+            (vec![Some(50)], vec![Some(10)]),
+            (vec![Some(100)], vec![Some(20)]),
+        ]);
+        let expected_receivables = vec![(Wallet::new("consuming"), 100)];
+        let expected_payables = vec![(Wallet::new("earning"), 20)];
+
+        let result = await_balances(
+            || readings.borrow_mut().remove(0),
+            &expected_receivables,
+            &expected_payables,
+            Duration::from_millis(5000),
+        );
+
+        assert_eq!(result, Ok(()));
+        assert_eq!(readings.borrow().len(), 0);
+    }
+
+    #[test]
+    fn balances_that_never_match_are_reported_once_time_is_up() {
+        let expected_receivables = vec![
+            (Wallet::new("consuming"), 100),
+            (Wallet::new("other_consuming"), 0),
+        ];
+        let expected_payables = vec![(Wallet::new("earning"), 20)];
+
+        let result = await_balances(
+            || (vec![Some(99), Some(0)], vec![None]),
+            &expected_receivables,
+            &expected_payables,
+            Duration::from_millis(300),
+        );
+
+        assert_eq!(
+            result,
+            Err(vec![
+                String::from("  receivable from consuming: expected 100, but found 99"),
+                String::from("  payable to earning: expected 20, but there is no account"),
+            ])
+        );
+    }
+}
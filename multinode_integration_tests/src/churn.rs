@@ -0,0 +1,314 @@
+// Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use crate::substratum_node::SubstratumNode;
+use crate::substratum_real_node::SubstratumRealNode;
+use node_lib::sub_lib::accountant::TEMPORARY_CONSUMING_WALLET;
+use node_lib::sub_lib::wallet::Wallet;
+use rand::Rng;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+
+pub const DEFAULT_BROWSE_TIMEOUT: Duration = Duration::from_millis(10000);
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChurnSchedule {
+    // Of the Nodes that may be churned, what percentage goes down each round; at least one does
+    pub percent: usize,
+    pub rounds: usize,
+    // How long the victims stay down, with browsing going on the whole time
+    pub downtime: Duration,
+    // How long the cluster is left whole after the victims come back, before the next round
+    pub settle_time: Duration,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChurnRound {
+    pub victims: Vec<String>,
+    // What each browse while the victims were down came back with: the size of the response, or
+    // why there wasn't one
+    pub browses: Vec<Result<usize, String>>,
+    // Balances that were smaller after a victim came back than when it went down
+    pub lost_balances: Vec<String>,
+}
+
+// Everything a Node has on its books: what it's owed by the consuming wallet, and what it owes
+// each of the earning wallets it was asked about
+#[derive(Clone, Debug, PartialEq)]
+pub struct Books {
+    pub receivable: i64,
+    pub payables: Vec<(Wallet, i64)>,
+}
+
+impl Books {
+    pub fn read(node: &SubstratumRealNode, earning_wallets: &[Wallet]) -> Books {
+        let daos = node.daos();
+        Books {
+            receivable: daos
+                .receivable
+                .account_status(&TEMPORARY_CONSUMING_WALLET)
+                .map(|account| account.balance)
+                .unwrap_or(0),
+            payables: earning_wallets
+                .iter()
+                .map(|wallet| {
+                    let balance = daos
+                        .payable
+                        .account_status(wallet)
+                        .map(|account| account.balance)
+                        .unwrap_or(0);
+                    (wallet.clone(), balance)
+                })
+                .collect(),
+        }
+    }
+
+    // A Node's balances only grow while browsing goes on, so any that shrank across a restart was
+    // lost by it
+    pub fn losses_since(&self, before: &Books, name: &str) -> Vec<String> {
+        let mut losses = vec![];
+        if self.receivable < before.receivable {
+            losses.push(format!(
+                "  {} was owed {} before it went down, but only {} after it came back",
+                name, before.receivable, self.receivable
+            ));
+        }
+        before
+            .payables
+            .iter()
+            .zip(self.payables.iter())
+            .filter(|((_, then), (_, now))| now < then)
+            .for_each(|((wallet, then), (_, now))| {
+                losses.push(format!(
+                    "  {} owed {} {} before it went down, but only {} after it came back",
+                    name, wallet.address, then, now
+                ))
+            });
+        losses
+    }
+}
+
+// Which of candidate_count Nodes go down for a round
+pub fn choose_victims<R: Rng>(candidate_count: usize, percent: usize, rng: &mut R) -> Vec<usize> {
+    let count = ((candidate_count * percent + 50) / 100)
+        .max(1)
+        .min(candidate_count);
+    let mut indexes: Vec<usize> = (0..candidate_count).collect();
+    rng.shuffle(&mut indexes);
+    indexes.truncate(count);
+    indexes.sort();
+    indexes
+}
+
+// A browse through node: one HTTP request for host, answered with the size of the response
+pub fn browse(node: &SubstratumRealNode, host: &str) -> Result<usize, String> {
+    let mut client = node.make_client(80);
+    client.set_timeout(DEFAULT_BROWSE_TIMEOUT);
+    client.send_chunk(Vec::from(
+        format!("GET / HTTP/1.1\r\nHost: {}\r\n\r\n", host).as_bytes(),
+    ));
+    let response = client.wait_for_chunk();
+    if response.starts_with(b"HTTP/1.1 ") {
+        Ok(response.len())
+    } else if response.is_empty() {
+        Err(format!("No response from {} through {}", host, node.name()))
+    } else {
+        Err(format!(
+            "Strange response from {} through {}: {}",
+            host,
+            node.name(),
+            String::from_utf8_lossy(&response)
+        ))
+    }
+}
+
+// Kills and restarts churnable Nodes on schedule, calling traffic over and over while they're
+// down. The Nodes traffic goes through must not be among the churnable ones. Every victim's books
+// are read just after it goes down and again after it comes back, with earning_wallets being the
+// wallets whose payables are compared.
+pub fn run_churn<F>(
+    churnable: &[SubstratumRealNode],
+    earning_wallets: &[Wallet],
+    schedule: &ChurnSchedule,
+    mut traffic: F,
+) -> Vec<ChurnRound>
+where
+    F: FnMut() -> Result<usize, String>,
+{
+    let mut rng = rand::thread_rng();
+    (0..schedule.rounds)
+        .map(|_| {
+            let victims: Vec<&SubstratumRealNode> =
+                choose_victims(churnable.len(), schedule.percent, &mut rng)
+                    .into_iter()
+                    .map(|index| &churnable[index])
+                    .collect();
+            let books_before: Vec<Books> = victims
+                .iter()
+                .map(|victim| {
+                    victim.kill();
+                    Books::read(victim, earning_wallets)
+                })
+                .collect();
+            let back_at = Instant::now() + schedule.downtime;
+            let mut browses = vec![traffic()];
+            while Instant::now() < back_at {
+                browses.push(traffic());
+            }
+            let lost_balances = victims
+                .iter()
+                .zip(books_before.iter())
+                .flat_map(|(victim, before)| {
+                    if let Err(e) = victim.restart() {
+                        return vec![format!("  {} didn't come back: {}", victim.name(), e)];
+                    }
+                    Books::read(victim, earning_wallets).losses_since(before, victim.name())
+                })
+                .collect();
+            thread::sleep(schedule.settle_time);
+            ChurnRound {
+                victims: victims
+                    .iter()
+                    .map(|victim| victim.name().to_string())
+                    .collect(),
+                browses,
+                lost_balances,
+            }
+        })
+        .collect()
+}
+
+// Panics unless at least min_success_percent of the browses in every round succeeded, and no
+// victim lost anything from its books
+pub fn assert_churn_survived(rounds: &[ChurnRound], min_success_percent: usize) {
+    let failures = churn_failures(rounds, min_success_percent);
+    if !failures.is_empty() {
+        panic!("The mesh didn't survive churn:\n{}", failures.join("\n"))
+    }
+}
+
+fn churn_failures(rounds: &[ChurnRound], min_success_percent: usize) -> Vec<String> {
+    rounds
+        .iter()
+        .enumerate()
+        .flat_map(|(index, round)| {
+            let successes = round.browses.iter().filter(|browse| browse.is_ok()).count();
+            let mut failures = vec![];
+            if successes * 100 < round.browses.len() * min_success_percent {
+                failures.push(format!(
+                    "Round {}, with {} down: only {} of {} browses succeeded; the first failure was {}",
+                    index,
+                    round.victims.join(", "),
+                    successes,
+                    round.browses.len(),
+                    round
+                        .browses
+                        .iter()
+                        .find_map(|browse| browse.clone().err())
+                        .unwrap_or_default()
+                ));
+            }
+            if !round.lost_balances.is_empty() {
+                failures.push(format!(
+                    "Round {}, with {} down: balances were lost\n{}",
+                    index,
+                    round.victims.join(", "),
+                    round.lost_balances.join("\n")
+                ));
+            }
+            failures
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round(browses: Vec<Result<usize, String>>, lost_balances: Vec<&str>) -> ChurnRound {
+        ChurnRound {
+            victims: vec![String::from("test_node_2"), String::from("test_node_4")],
+            browses,
+            lost_balances: lost_balances.into_iter().map(String::from).collect(),
+        }
+    }
+
+    #[test]
+    fn the_right_number_of_distinct_victims_is_chosen() {
+        let mut rng = rand::thread_rng();
+
+        (0..50).for_each(|_| {
+            let victims = choose_victims(10, 25, &mut rng);
+            assert_eq!(victims.len(), 3);
+            assert_eq!(victims.windows(2).all(|pair| pair[0] < pair[1]), true);
+            assert_eq!(victims.iter().all(|index| *index < 10), true);
+        });
+        assert_eq!(choose_victims(10, 1, &mut rng).len(), 1);
+        assert_eq!(choose_victims(3, 100, &mut rng), vec![0, 1, 2]);
+        assert_eq!(choose_victims(3, 500, &mut rng), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn shrinking_balances_are_losses() {
+        let wallet = Wallet::new("earner");
+        let before = Books {
+            receivable: 100,
+            payables: vec![(wallet.clone(), 50)],
+        };
+        let grown = Books {
+            receivable: 120,
+            payables: vec![(wallet.clone(), 50)],
+        };
+        let shrunk = Books {
+            receivable: 90,
+            payables: vec![(wallet.clone(), 40)],
+        };
+
+        assert_eq!(
+            grown.losses_since(&before, "test_node_2"),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            shrunk.losses_since(&before, "test_node_2"),
+            vec![
+                String::from(
+                    "  test_node_2 was owed 100 before it went down, but only 90 after it came back"
+                ),
+                String::from(
+                    "  test_node_2 owed earner 50 before it went down, but only 40 after it came back"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn rounds_with_too_few_successful_browses_or_lost_balances_are_failures() {
+        let rounds = vec![
+            round(vec![Ok(100), Ok(100), Err(String::from("booga"))], vec![]),
+            round(
+                vec![
+                    Ok(100),
+                    Err(String::from("booga")),
+                    Err(String::from("agoob")),
+                ],
+                vec![],
+            ),
+            round(vec![Ok(100)], vec!["  test_node_2 lost it"]),
+        ];
+
+        let result = churn_failures(&rounds, 60);
+
+        assert_eq!(
+            result,
+            vec![
+                String::from("Round 1, with test_node_2, test_node_4 down: only 1 of 3 browses succeeded; the first failure was booga"),
+                String::from("Round 2, with test_node_2, test_node_4 down: balances were lost\n  test_node_2 lost it"),
+            ]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "The mesh didn't survive churn:\nRound 0")]
+    fn assert_churn_survived_panics_on_failures() {
+        assert_churn_survived(&[round(vec![Err(String::from("booga"))], vec![])], 50);
+    }
+}
@@ -2,6 +2,7 @@
 use crate::substratum_node::SubstratumNode;
 use node_lib::neighborhood::gossip::Gossip;
 use node_lib::neighborhood::gossip::GossipNodeRecord;
+use node_lib::neighborhood::neighborhood_database::LoadAdvertisement;
 use node_lib::neighborhood::neighborhood_database::NodeRecordInner;
 use node_lib::neighborhood::neighborhood_database::NodeSignatures;
 use node_lib::sub_lib::cryptde::CryptDE;
@@ -9,14 +10,31 @@ use node_lib::sub_lib::cryptde::PublicKey;
 use node_lib::sub_lib::cryptde_null::CryptDENull;
 use node_lib::sub_lib::dispatcher::Component;
 use node_lib::sub_lib::hopper::IncipientCoresPackage;
+use node_lib::sub_lib::neighborhood::RateCard;
+use node_lib::sub_lib::node_addr::NodeAddr;
 use node_lib::sub_lib::route::Route;
 use node_lib::sub_lib::route::RouteSegment;
 use node_lib::sub_lib::wallet::Wallet;
+use serde_derive::Serialize;
+
+// The encodings of Gossip that Nodes have put on the wire, oldest first. They match the golden/
+// directories of the node's wire_compat tests.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GossipProtocolVersion {
+    V0, // before Nodes advertised their load
+    V1,
+}
+
+pub const CURRENT_GOSSIP_PROTOCOL_VERSION: GossipProtocolVersion = GossipProtocolVersion::V1;
 
 pub struct GossipBuilder {
     consuming_wallet: Option<Wallet>,
     node_info: Vec<GossipBuilderNodeInfo>,
     connection_pairs: Vec<(PublicKey, PublicKey)>,
+    protocol_version: GossipProtocolVersion,
+    sequence_opt: Option<u32>,
+    ack_opt: Option<u32>,
+    delta: bool,
 }
 
 impl GossipBuilder {
@@ -25,9 +43,35 @@ impl GossipBuilder {
             consuming_wallet,
             node_info: vec![],
             connection_pairs: vec![],
+            protocol_version: CURRENT_GOSSIP_PROTOCOL_VERSION,
+            sequence_opt: None,
+            ack_opt: None,
+            delta: false,
         }
     }
 
+    // Only build_cores_package() pays attention to this; build() always makes current Gossip.
+    pub fn protocol_version(mut self, protocol_version: GossipProtocolVersion) -> Self {
+        self.protocol_version = protocol_version;
+        self
+    }
+
+    // V0 Gossip has no room for these; they're dropped when it's built.
+    pub fn sequence(mut self, sequence: u32) -> Self {
+        self.sequence_opt = Some(sequence);
+        self
+    }
+
+    pub fn ack(mut self, ack: u32) -> Self {
+        self.ack_opt = Some(ack);
+        self
+    }
+
+    pub fn delta(mut self) -> Self {
+        self.delta = true;
+        self
+    }
+
     pub fn add_node(
         mut self,
         node: &dyn SubstratumNode,
@@ -46,6 +90,12 @@ impl GossipBuilder {
                 consuming_wallet: node.consuming_wallet().clone(),
                 neighbors: vec![],
                 version: 0,
+                load: LoadAdvertisement::default(),
+                offers_exit_tls: false,
+                relay_only: false,
+                rate_card: RateCard::default(),
+                country_opt: None,
+                exit_sla_opt: None,
             },
             cryptde: Box::new(CryptDENull::from(&node.public_key())),
         });
@@ -62,6 +112,12 @@ impl GossipBuilder {
                 consuming_wallet: gnr.inner.consuming_wallet.clone(),
                 neighbors: vec![],
                 version: gnr.inner.version,
+                load: gnr.inner.load,
+                offers_exit_tls: gnr.inner.offers_exit_tls,
+                relay_only: gnr.inner.relay_only,
+                rate_card: gnr.inner.rate_card,
+                country_opt: gnr.inner.country_opt.clone(),
+                exit_sla_opt: gnr.inner.exit_sla_opt.clone(),
             },
             cryptde: Box::new(CryptDENull::from(&gnr.public_key())),
         });
@@ -109,28 +165,166 @@ impl GossipBuilder {
                     to_key, from_key, from_key)
             }
         });
-        Gossip { node_records }
+        Gossip {
+            node_records,
+            observed_ip_opt: None,
+            ttl_opt: None,
+            sequence_opt: self.sequence_opt,
+            ack_opt: self.ack_opt,
+            delta: self.delta,
+        }
     }
 
     pub fn build_cores_package(self, from: &PublicKey, to: &PublicKey) -> IncipientCoresPackage {
         let consuming_wallet = self.consuming_wallet.clone();
+        let protocol_version = self.protocol_version;
         let gossip = self.build();
-        IncipientCoresPackage::new(
-            &CryptDENull::new(),
-            Route::one_way(
-                RouteSegment::new(vec![from, to], Component::Neighborhood),
-                &CryptDENull::from(from),
-                consuming_wallet,
-            )
-            .unwrap(),
-            gossip,
-            to,
+        let route = Route::one_way(
+            RouteSegment::new(vec![from, to], Component::Neighborhood),
+            &CryptDENull::from(from),
+            consuming_wallet,
         )
+        .unwrap();
+        match protocol_version {
+            GossipProtocolVersion::V0 => {
+                IncipientCoresPackage::new(&CryptDENull::new(), route, V0Gossip::from(gossip), to)
+            }
+            GossipProtocolVersion::V1 => {
+                IncipientCoresPackage::new(&CryptDENull::new(), route, gossip, to)
+            }
+        }
         .unwrap()
     }
 }
 
+// Gossip as V0 Nodes sent it. Nobody checks a record's signatures against its encoding yet, so the
+// ones made for the current encoding are passed along as they are.
+#[derive(Serialize)]
+struct V0Gossip {
+    node_records: Vec<V0GossipNodeRecord>,
+}
+
+#[derive(Serialize)]
+struct V0GossipNodeRecord {
+    inner: V0NodeRecordInner,
+    signatures: NodeSignatures,
+}
+
+#[derive(Serialize)]
+struct V0NodeRecordInner {
+    public_key: PublicKey,
+    node_addr_opt: Option<NodeAddr>,
+    earning_wallet: Wallet,
+    consuming_wallet: Option<Wallet>,
+    is_bootstrap_node: bool,
+    neighbors: Vec<PublicKey>,
+    version: u32,
+}
+
+impl From<Gossip> for V0Gossip {
+    fn from(gossip: Gossip) -> Self {
+        V0Gossip {
+            node_records: gossip
+                .node_records
+                .into_iter()
+                .map(|gnr| V0GossipNodeRecord {
+                    signatures: gnr.signatures,
+                    inner: V0NodeRecordInner {
+                        public_key: gnr.inner.public_key,
+                        node_addr_opt: gnr.inner.node_addr_opt,
+                        earning_wallet: gnr.inner.earning_wallet,
+                        consuming_wallet: gnr.inner.consuming_wallet,
+                        is_bootstrap_node: gnr.inner.is_bootstrap_node,
+                        neighbors: gnr.inner.neighbors,
+                        version: gnr.inner.version,
+                    },
+                })
+                .collect(),
+        }
+    }
+}
+
 struct GossipBuilderNodeInfo {
     node_record_inner: NodeRecordInner,
     cryptde: Box<dyn CryptDE>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use node_lib::neighborhood::neighborhood_database::LoadAdvertisement;
+    use node_lib::sub_lib::cryptde::decodex;
+    use node_lib::sub_lib::cryptde::PlainData;
+    use std::net::IpAddr;
+    use std::str::FromStr;
+
+    fn make_inner(key: &[u8]) -> NodeRecordInner {
+        NodeRecordInner {
+            public_key: PublicKey::new(key),
+            node_addr_opt: Some(NodeAddr::new(
+                &IpAddr::from_str("1.2.3.4").unwrap(),
+                &vec![1234],
+            )),
+            earning_wallet: Wallet::new("earning"),
+            consuming_wallet: None,
+            is_bootstrap_node: false,
+            neighbors: vec![],
+            version: 3,
+            load: LoadAdvertisement::default(),
+            offers_exit_tls: true,
+            relay_only: true,
+            rate_card: RateCard::default(),
+            country_opt: None,
+            exit_sla_opt: None,
+        }
+    }
+
+    fn decrypt_payload(package: &IncipientCoresPackage, to: &PublicKey) -> PlainData {
+        CryptDENull::from(to)
+            .decode(&package.payload)
+            .expect("payload could not be decrypted")
+    }
+
+    #[test]
+    fn cores_package_carries_current_gossip_by_default() {
+        let from = PublicKey::new(b"from");
+        let to = PublicKey::new(b"to");
+        let subject = GossipBuilder::new(None).add_fictional_node(make_inner(b"from"));
+
+        let result = subject.build_cores_package(&from, &to);
+
+        let gossip: Gossip = decodex(&CryptDENull::from(&to), &result.payload).unwrap();
+        assert_eq!(gossip.node_records[0].inner, make_inner(b"from"));
+    }
+
+    #[test]
+    fn v0_cores_package_leaves_out_everything_v0_nodes_never_sent() {
+        let from = PublicKey::new(b"from");
+        let to = PublicKey::new(b"to");
+        let subject = GossipBuilder::new(None)
+            .add_fictional_node(make_inner(b"from"))
+            .add_fictional_node(make_inner(b"other"))
+            .add_connection(&from, &PublicKey::new(b"other"))
+            .protocol_version(GossipProtocolVersion::V0);
+
+        let result = subject.build_cores_package(&from, &to);
+
+        let serialized = decrypt_payload(&result, &to);
+        let contains = |text: &[u8]| {
+            serialized
+                .as_slice()
+                .windows(text.len())
+                .any(|window| window == text)
+        };
+        assert!(!contains(b"load"));
+        assert!(!contains(b"offers_exit_tls"));
+        assert!(!contains(b"relay_only"));
+        let gossip: Gossip = serde_cbor::de::from_slice(serialized.as_slice()).unwrap();
+        let mut expected_inner = make_inner(b"from");
+        expected_inner.neighbors = vec![PublicKey::new(b"other")];
+        expected_inner.offers_exit_tls = false;
+        expected_inner.relay_only = false;
+        assert_eq!(gossip.node_records[0].inner, expected_inner);
+        assert_eq!(gossip.node_records.len(), 2);
+    }
+}
@@ -0,0 +1,175 @@
+// Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use crate::substratum_node::SubstratumNode;
+use crate::substratum_node_cluster::SubstratumNodeCluster;
+use crate::substratum_real_node::NodeStartupConfigBuilder;
+use crate::substratum_real_node::SubstratumRealNode;
+use node_lib::sub_lib::cryptde::PublicKey;
+use std::env;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+
+// How long a cluster may take for every Node to know about every other one, unless
+// GOSSIP_CONVERGENCE_BOUND_MILLIS in the environment says otherwise
+pub const DEFAULT_CONVERGENCE_BOUND: Duration = Duration::from_millis(30000);
+pub const CONVERGENCE_BOUND_ENV_VAR: &str = "GOSSIP_CONVERGENCE_BOUND_MILLIS";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Topology {
+    // Each Node is started with the one before it as its only neighbor
+    Line,
+    // Each Node is started with the first one as its only neighbor
+    Star,
+}
+
+impl Topology {
+    fn neighbor_of(self, index: usize) -> Option<usize> {
+        match (self, index) {
+            (_, 0) => None,
+            (Topology::Line, index) => Some(index - 1),
+            (Topology::Star, _) => Some(0),
+        }
+    }
+}
+
+pub fn convergence_bound() -> Duration {
+    match env::var(CONVERGENCE_BOUND_ENV_VAR) {
+        Ok(ref value) if !value.is_empty() => Duration::from_millis(value.parse::<u64>().expect(
+            &format!("Invalid {}: '{}'", CONVERGENCE_BOUND_ENV_VAR, value),
+        )),
+        _ => DEFAULT_CONVERGENCE_BOUND,
+    }
+}
+
+// The first Node is a bootstrap Node and the rest are standard. All of them are started with fault
+// injection, because that's how their Neighborhood databases are read.
+pub fn start_topology(
+    cluster: &mut SubstratumNodeCluster,
+    topology: Topology,
+    node_count: usize,
+) -> Vec<SubstratumRealNode> {
+    let mut nodes: Vec<SubstratumRealNode> = vec![];
+    (0..node_count).for_each(|index| {
+        let builder = match topology.neighbor_of(index) {
+            None => NodeStartupConfigBuilder::bootstrap(),
+            Some(neighbor) => {
+                NodeStartupConfigBuilder::standard().neighbor(nodes[neighbor].node_reference())
+            }
+        };
+        nodes.push(cluster.start_real_node(builder.fault_injection().build()));
+    });
+    nodes
+}
+
+// How long it took, from now, for every Node's database to contain every one of the Nodes
+pub fn measure_convergence(nodes: &[SubstratumRealNode], bound: Duration) -> Duration {
+    let expected: Vec<PublicKey> = nodes.iter().map(|node| node.public_key()).collect();
+    match await_convergence(|index| nodes[index].known_node_keys(), &expected, bound) {
+        Ok(elapsed) => elapsed,
+        Err(stragglers) => panic!(
+            "After {:?}, Gossip still hadn't converged:\n{}",
+            bound,
+            stragglers.join("\n")
+        ),
+    }
+}
+
+fn await_convergence<F>(
+    read_known_keys: F,
+    expected: &[PublicKey],
+    bound: Duration,
+) -> Result<Duration, Vec<String>>
+where
+    F: Fn(usize) -> Result<Vec<PublicKey>, String>,
+{
+    let start = Instant::now();
+    loop {
+        let stragglers: Vec<String> = (0..expected.len())
+            .filter_map(|index| match read_known_keys(index) {
+                Ok(known) => {
+                    let missing = expected.iter().filter(|key| !known.contains(key)).count();
+                    if missing == 0 {
+                        None
+                    } else {
+                        Some(format!(
+                            "  Node {} ({}) doesn't know about {} of the {} Nodes",
+                            index,
+                            expected[index],
+                            missing,
+                            expected.len()
+                        ))
+                    }
+                }
+                Err(e) => Some(format!(
+                    "  Node {} ({}) couldn't be asked: {}",
+                    index, expected[index], e
+                )),
+            })
+            .collect();
+        let elapsed = start.elapsed();
+        if stragglers.is_empty() {
+            return Ok(elapsed);
+        }
+        if elapsed >= bound {
+            return Err(stragglers);
+        }
+        thread::sleep(Duration::from_millis(250))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    fn keys() -> Vec<PublicKey> {
+        vec![PublicKey::new(b"AAA"), PublicKey::new(b"BBB")]
+    }
+
+    #[test]
+    fn topologies_pick_the_right_neighbors() {
+        assert_eq!(Topology::Line.neighbor_of(0), None);
+        assert_eq!(Topology::Line.neighbor_of(3), Some(2));
+        assert_eq!(Topology::Star.neighbor_of(0), None);
+        assert_eq!(Topology::Star.neighbor_of(3), Some(0));
+    }
+
+    #[test]
+    fn databases_are_read_again_until_every_node_knows_every_other() {
+        let readings = RefCell::new(vec![
+            Ok(vec![PublicKey::new(b"AAA")]),
+            Ok(keys()),
+            Ok(keys()),
+            Ok(keys()),
+        ]);
+
+        let result = await_convergence(
+            |_| readings.borrow_mut().remove(0),
+            &keys(),
+            Duration::from_millis(5000),
+        );
+
+        assert_eq!(result.is_ok(), true);
+        assert_eq!(readings.borrow().len(), 0);
+    }
+
+    #[test]
+    fn nodes_that_never_converge_are_reported_once_time_is_up() {
+        let result = await_convergence(
+            |index| match index {
+                0 => Ok(vec![PublicKey::new(b"AAA")]),
+                _ => Err(String::from("ERROR: booga")),
+            },
+            &keys(),
+            Duration::from_millis(300),
+        );
+
+        assert_eq!(
+            result,
+            Err(vec![
+                String::from("  Node 0 (QUFB) doesn't know about 1 of the 2 Nodes"),
+                String::from("  Node 1 (QkJC) couldn't be asked: ERROR: booga"),
+            ])
+        );
+    }
+}
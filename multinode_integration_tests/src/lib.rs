@@ -1,7 +1,10 @@
 // Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
 
+pub mod accountant_assertions;
+pub mod churn;
 pub mod command;
 pub mod gossip_builder;
+pub mod gossip_convergence;
 pub mod main;
 pub mod mock_bootstrap_node;
 pub mod substratum_client;
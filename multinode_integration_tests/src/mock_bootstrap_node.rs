@@ -75,6 +75,10 @@ impl SubstratumNode for MockBootstrapNode {
     fn make_client(&self, _port: u16) -> SubstratumNodeClient {
         panic!("Bootstrap nodes (even mock ones) can't have clients")
     }
+
+    fn make_tls_client(&self, _port: u16, _domain: &str) -> SubstratumNodeClient {
+        panic!("Bootstrap nodes (even mock ones) can't have clients")
+    }
 }
 
 impl MockBootstrapNode {
@@ -213,6 +217,12 @@ impl MockBootstrapNode {
                 is_bootstrap_node: node.inner.is_bootstrap_node,
                 neighbors: vec![],
                 version: node.inner.version,
+                load: node.inner.load,
+                offers_exit_tls: node.inner.offers_exit_tls,
+                relay_only: node.inner.relay_only,
+                rate_card: node.inner.rate_card,
+                country_opt: node.inner.country_opt.clone(),
+                exit_sla_opt: node.inner.exit_sla_opt.clone(),
             },
             signatures: NodeSignatures {
                 complete: CryptData::new(&[]),
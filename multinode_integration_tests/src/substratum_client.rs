@@ -1,7 +1,13 @@
 // Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use native_tls::TlsConnector;
+use native_tls::TlsStream;
+use node_lib::sub_lib::utils::index_of;
+use std::ascii;
+use std::io;
 use std::io::ErrorKind;
 use std::io::Read;
 use std::io::Write;
+use std::mem;
 use std::net::Shutdown;
 use std::net::SocketAddr;
 use std::net::TcpStream;
@@ -9,9 +15,78 @@ use std::thread;
 use std::time::Duration;
 use std::time::Instant;
 
+enum ClientStream {
+    Plain(TcpStream),
+    Tls(TlsStream<TcpStream>),
+}
+
+impl Read for ClientStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            ClientStream::Plain(stream) => stream.read(buf),
+            ClientStream::Tls(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for ClientStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            ClientStream::Plain(stream) => stream.write(buf),
+            ClientStream::Tls(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            ClientStream::Plain(stream) => stream.flush(),
+            ClientStream::Tls(stream) => stream.flush(),
+        }
+    }
+}
+
+impl ClientStream {
+    fn tcp_stream(&self) -> &TcpStream {
+        match self {
+            ClientStream::Plain(stream) => stream,
+            ClientStream::Tls(stream) => stream.get_ref(),
+        }
+    }
+}
+
+// One step of a session: a request, and what has to be somewhere in the response to it
+pub struct Exchange {
+    request: Vec<u8>,
+    expected: Vec<Vec<u8>>,
+    timeout: Option<Duration>,
+}
+
+impl Exchange {
+    pub fn new(request: &[u8]) -> Exchange {
+        Exchange {
+            request: Vec::from(request),
+            expected: vec![],
+            timeout: None,
+        }
+    }
+
+    pub fn expecting(mut self, expected: &[u8]) -> Exchange {
+        self.expected.push(Vec::from(expected));
+        self
+    }
+
+    // Otherwise, the client's timeout
+    pub fn within(mut self, timeout: Duration) -> Exchange {
+        self.timeout = Some(timeout);
+        self
+    }
+}
+
 pub struct SubstratumNodeClient {
-    stream: TcpStream,
+    stream: ClientStream,
     timeout: Duration,
+    // Read after the end of the last response, so it's the start of the next one
+    pending: Vec<u8>,
 }
 
 impl SubstratumNodeClient {
@@ -23,8 +98,33 @@ impl SubstratumNodeClient {
             .expect("Setting read timeout to 10ms");
 
         SubstratumNodeClient {
-            stream,
+            stream: ClientStream::Plain(stream),
             timeout: Duration::from_secs(60),
+            pending: vec![],
+        }
+    }
+
+    // The Node only ever sees the encrypted bytes; the handshake is with the server for domain
+    pub fn new_tls(socket_addr: SocketAddr, domain: &str) -> SubstratumNodeClient {
+        let timeout = Duration::from_secs(60);
+        let stream = TcpStream::connect(&socket_addr)
+            .expect(format!("Connecting to {}", socket_addr).as_str());
+        stream
+            .set_read_timeout(Some(timeout))
+            .expect("Setting read timeout for the TLS handshake");
+        let tls_stream = TlsConnector::new()
+            .expect("Creating TLS connector")
+            .connect(domain, stream)
+            .expect(format!("TLS handshake with {} through {}", domain, socket_addr).as_str());
+        tls_stream
+            .get_ref()
+            .set_read_timeout(Some(Duration::from_millis(10)))
+            .expect("Setting read timeout to 10ms");
+
+        SubstratumNodeClient {
+            stream: ClientStream::Tls(tls_stream),
+            timeout,
+            pending: vec![],
         }
     }
 
@@ -34,11 +134,14 @@ impl SubstratumNodeClient {
 
     pub fn send_chunk(&mut self, chunk: Vec<u8>) {
         self.stream
-            .write(&chunk[..])
+            .write_all(&chunk[..])
             .expect(format!("Writing {} bytes", chunk.len()).as_str());
     }
 
     pub fn wait_for_chunk(&mut self) -> Vec<u8> {
+        if !self.pending.is_empty() {
+            return mem::replace(&mut self.pending, vec![]);
+        }
         let mut output: Vec<u8> = vec![];
         let mut buf: [u8; 65536] = [0; 65536];
         let mut begin = Instant::now();
@@ -68,7 +171,252 @@ impl SubstratumNodeClient {
         }
     }
 
+    // Exactly count bytes, for protocols like WebSocket that HTTP responses don't frame
+    pub fn wait_for_bytes(&mut self, count: usize, timeout: Duration) -> Vec<u8> {
+        self.read_until(timeout, "bytes", |data| {
+            if data.len() >= count {
+                Some(count)
+            } else {
+                None
+            }
+        })
+    }
+
+    // Exactly one response, however it's framed, so the next one on the connection can be
+    // waited for in turn
+    pub fn wait_for_http_response(&mut self, timeout: Duration) -> Vec<u8> {
+        self.read_until(timeout, "an HTTP response", http_response_length)
+    }
+
+    // Sends each request in turn over this one connection, waiting for the response to each before
+    // sending the next, and panics unless every response has everything its exchange expects
+    pub fn run_session(&mut self, exchanges: Vec<Exchange>) -> Vec<Vec<u8>> {
+        exchanges
+            .into_iter()
+            .enumerate()
+            .map(|(index, exchange)| {
+                self.send_chunk(exchange.request);
+                let response =
+                    self.wait_for_http_response(exchange.timeout.unwrap_or(self.timeout));
+                exchange.expected.iter().for_each(|expected| {
+                    assert_eq!(
+                        index_of(&response, expected).is_some(),
+                        true,
+                        "Response {} should have contained\n{}\nbut was\n{}",
+                        index,
+                        printable(expected),
+                        printable(&response)
+                    )
+                });
+                response
+            })
+            .collect()
+    }
+
     pub fn shutdown(&mut self) {
-        self.stream.shutdown(Shutdown::Both).expect("Shutting down");
+        if let ClientStream::Tls(ref mut stream) = self.stream {
+            stream.shutdown().is_ok();
+        }
+        self.stream
+            .tcp_stream()
+            .shutdown(Shutdown::Both)
+            .expect("Shutting down");
+    }
+
+    // complete_length says how much of what's been read so far makes up what's being waited for, if
+    // all of it has been read; if the connection closes first, whatever's been read is returned
+    fn read_until<F>(&mut self, timeout: Duration, what: &str, complete_length: F) -> Vec<u8>
+    where
+        F: Fn(&[u8]) -> Option<usize>,
+    {
+        let deadline = Instant::now() + timeout;
+        let mut buf: [u8; 65536] = [0; 65536];
+        loop {
+            if let Some(length) = complete_length(&self.pending) {
+                let rest = self.pending.split_off(length);
+                return mem::replace(&mut self.pending, rest);
+            }
+            if Instant::now() >= deadline {
+                panic!(
+                    "Timed out after waiting {:?} for {}; got only\n{}",
+                    timeout,
+                    what,
+                    printable(&self.pending)
+                );
+            }
+            match self.stream.read(&mut buf) {
+                Ok(0) => return mem::replace(&mut self.pending, vec![]),
+                Ok(n) => self.pending.extend_from_slice(&buf[0..n]),
+                Err(ref e)
+                    if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => {}
+                Err(e) => panic!("Couldn't read {}: {:?}", what, e),
+            }
+        }
+    }
+}
+
+// Bodies may well be binary, so anything that isn't printable ASCII is escaped
+pub fn printable(data: &[u8]) -> String {
+    data.iter()
+        .flat_map(|byte| ascii::escape_default(*byte))
+        .map(char::from)
+        .collect()
+}
+
+// None until the whole of the first response in data is there. One with neither a Content-Length
+// nor chunks only ends when the connection closes, so it's never complete here.
+fn http_response_length(data: &[u8]) -> Option<usize> {
+    let body_offset = index_of(data, b"\r\n\r\n")? + 4;
+    let head = String::from_utf8_lossy(&data[0..body_offset]).to_lowercase();
+    let mut lines = head.split("\r\n");
+    let status = lines
+        .next()?
+        .split_whitespace()
+        .nth(1)
+        .and_then(|code| code.parse::<u16>().ok())
+        .unwrap_or(200);
+    if status < 200 || status == 204 || status == 304 {
+        return Some(body_offset);
+    }
+    let headers: Vec<(&str, &str)> = lines
+        .filter_map(|line| {
+            let colon = line.find(':')?;
+            Some((line[0..colon].trim(), line[colon + 1..].trim()))
+        })
+        .collect();
+    let header = |name: &str| {
+        headers
+            .iter()
+            .find(|(header_name, _)| *header_name == name)
+            .map(|(_, value)| *value)
+    };
+    if header("transfer-encoding").map_or(false, |value| value.contains("chunked")) {
+        chunked_body_length(&data[body_offset..]).map(|length| body_offset + length)
+    } else {
+        let content_length = header("content-length")?.parse::<usize>().ok()?;
+        if data.len() >= body_offset + content_length {
+            Some(body_offset + content_length)
+        } else {
+            None
+        }
+    }
+}
+
+fn chunked_body_length(body: &[u8]) -> Option<usize> {
+    let mut offset = 0;
+    loop {
+        let line_end = offset + index_of(&body[offset..], b"\r\n")?;
+        let size_line = String::from_utf8_lossy(&body[offset..line_end]).to_string();
+        let size_digits = size_line.split(';').next().unwrap_or_default().trim();
+        let size = usize::from_str_radix(size_digits, 16).ok()?;
+        offset = line_end + 2;
+        if size == 0 {
+            // Then trailers, if any, and an empty line
+            loop {
+                let line_end = offset + index_of(&body[offset..], b"\r\n")?;
+                let empty = line_end == offset;
+                offset = line_end + 2;
+                if empty {
+                    return Some(offset);
+                }
+            }
+        }
+        offset += size + 2;
+        if offset > body.len() {
+            return None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::IpAddr;
+    use std::net::Ipv4Addr;
+    use std::net::TcpListener;
+
+    fn serve_once<F>(respond: F) -> SocketAddr
+    where
+        F: FnOnce(TcpStream) + Send + 'static,
+    {
+        let listener =
+            TcpListener::bind(SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 0)).unwrap();
+        let local_addr = listener.local_addr().unwrap();
+        thread::spawn(move || respond(listener.accept().unwrap().0));
+        local_addr
+    }
+
+    fn read_request(stream: &mut TcpStream) -> Vec<u8> {
+        let mut request = vec![];
+        let mut buf = [0u8; 1024];
+        while index_of(&request, b"\r\n\r\n").is_none() {
+            let n = stream.read(&mut buf).unwrap();
+            request.extend_from_slice(&buf[0..n]);
+        }
+        request
+    }
+
+    #[test]
+    fn http_responses_are_framed_by_content_length_chunks_or_status() {
+        let with_length = b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nbooganext";
+        let chunked = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: gzip, chunked\r\n\r\n\
+                        4\r\nboog\r\n1;ext=x\r\na\r\n0\r\nTrailer: yes\r\n\r\nnext";
+        let no_content = b"HTTP/1.1 204 No Content\r\nContent-Length: 5\r\n\r\nnext";
+        let close_delimited = b"HTTP/1.0 200 OK\r\n\r\nbooga";
+
+        assert_eq!(
+            http_response_length(with_length),
+            Some(with_length.len() - 4)
+        );
+        assert_eq!(http_response_length(&with_length[0..40]), None);
+        assert_eq!(http_response_length(chunked), Some(chunked.len() - 4));
+        assert_eq!(http_response_length(&chunked[0..70]), None);
+        assert_eq!(http_response_length(no_content), Some(no_content.len() - 4));
+        assert_eq!(http_response_length(close_delimited), None);
+    }
+
+    #[test]
+    fn a_session_gets_one_response_per_request_however_they_arrive() {
+        let binary_body: &[u8] = &[0x00, 0xFF, b'\r', b'\n', 0x80];
+        let local_addr = serve_once(move |mut stream| {
+            read_request(&mut stream);
+            // Both at once, so the second has been read before it's asked for
+            let mut responses =
+                Vec::from(&b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nfirst"[..]);
+            responses.extend_from_slice(b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\n");
+            responses.extend_from_slice(binary_body);
+            stream.write_all(&responses).unwrap();
+            read_request(&mut stream);
+        });
+        let mut subject = SubstratumNodeClient::new(local_addr);
+
+        let result = subject.run_session(vec![
+            Exchange::new(b"GET /first HTTP/1.1\r\nHost: booga.com\r\n\r\n").expecting(b"first"),
+            Exchange::new(b"GET /second HTTP/1.1\r\nHost: booga.com\r\n\r\n")
+                .expecting(binary_body)
+                .within(Duration::from_secs(5)),
+        ]);
+
+        assert_eq!(
+            result[0],
+            Vec::from(&b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nfirst"[..])
+        );
+        assert_eq!(&result[1][result[1].len() - 5..], binary_body);
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Timed out after waiting 100ms for an HTTP response; got only\nHTTP/1.1 200 OK\\r\\nContent-Length: 9\\r\\n\\r\\n\\x00\\xff"
+    )]
+    fn waiting_for_a_response_that_does_not_finish_arriving_times_out() {
+        let local_addr = serve_once(|mut stream| {
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 9\r\n\r\n\x00\xff")
+                .unwrap();
+            thread::sleep(Duration::from_secs(1));
+        });
+        let mut subject = SubstratumNodeClient::new(local_addr);
+
+        subject.wait_for_http_response(Duration::from_millis(100));
     }
 }
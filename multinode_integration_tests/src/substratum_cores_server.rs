@@ -5,6 +5,7 @@ use crate::substratum_node_cluster::SubstratumNodeCluster;
 use node_lib::discriminator::Discriminator;
 use node_lib::discriminator::DiscriminatorFactory;
 use node_lib::discriminator::UnmaskedChunk;
+use node_lib::hopper::hop_layer::HopLayer;
 use node_lib::hopper::live_cores_package::LiveCoresPackage;
 use node_lib::http_request_start_finder::HttpRequestDiscriminatorFactory;
 use node_lib::json_discriminator_factory::JsonDiscriminatorFactory;
@@ -29,6 +30,7 @@ use std::sync::mpsc::Receiver;
 use std::thread;
 use std::thread::JoinHandle;
 use std::time::Duration;
+use std::time::Instant;
 
 // TODO: Cover this with tests and put it in the production tree.
 pub struct DiscriminatorCluster {
@@ -155,9 +157,12 @@ impl SubstratumCoresServer {
 
     pub fn wait_for_package(&self, timeout: Duration) -> LiveCoresPackage {
         let chunk = self.get_next_chunk(timeout);
-        let decoded_chunk = self
-            .cryptde
-            .decode(&CryptData::new(&chunk.chunk[..]))
+        let decoded_chunk = HopLayer::new()
+            .decode(
+                &self.cryptde,
+                &CryptData::new(&chunk.chunk[..]),
+                Instant::now(),
+            )
             .unwrap();
         serde_cbor::de::from_slice::<LiveCoresPackage>(decoded_chunk.as_slice())
             .expect(format!("Error deserializing LCP from {:?}", chunk.chunk).as_str())
@@ -6,6 +6,7 @@ use crate::substratum_node::NodeReference;
 use crate::substratum_node::PortSelector;
 use crate::substratum_node::SubstratumNode;
 use crate::substratum_node::SubstratumNodeUtils;
+use node_lib::hopper::hop_layer::HopLayer;
 use node_lib::hopper::live_cores_package::LiveCoresPackage;
 use node_lib::json_masquerader::JsonMasquerader;
 use node_lib::masquerader::Masquerader;
@@ -39,6 +40,7 @@ use std::net::TcpStream;
 use std::rc::Rc;
 use std::thread;
 use std::time::Duration;
+use std::time::Instant;
 
 pub struct SubstratumMockNode {
     control_stream: RefCell<TcpStream>,
@@ -102,6 +104,10 @@ impl SubstratumNode for SubstratumMockNode {
     fn make_client(&self, _port: u16) -> SubstratumNodeClient {
         unimplemented!()
     }
+
+    fn make_tls_client(&self, _port: u16, _domain: &str) -> SubstratumNodeClient {
+        unimplemented!()
+    }
 }
 
 impl SubstratumMockNode {
@@ -235,9 +241,12 @@ impl SubstratumMockNode {
     ) -> Result<(SocketAddr, SocketAddr, LiveCoresPackage), io::Error> {
         let data_hunk = self.wait_for_data(timeout)?;
         let unmasked_data = masquerader.try_unmask(&data_hunk.data[..]).unwrap().chunk;
-        let decrypted_data = self
-            .cryptde()
-            .decode(&CryptData::new(&unmasked_data[..]))
+        let decrypted_data = HopLayer::new()
+            .decode(
+                self.cryptde(),
+                &CryptData::new(&unmasked_data[..]),
+                Instant::now(),
+            )
             .unwrap();
         let live_cores_package =
             serde_cbor::de::from_slice::<LiveCoresPackage>(decrypted_data.as_slice()).unwrap();
@@ -34,7 +34,18 @@ impl FromStr for NodeReference {
     type Err = String;
 
     fn from_str(string_rep: &str) -> Result<Self, <Self as FromStr>::Err> {
-        let pieces: Vec<&str> = string_rep.split(":").collect();
+        let pieces: Vec<&str> = match string_rep.find(":[") {
+            // An IPv6 address is bracketed so its colons aren't mistaken for separators
+            Some(index) => match string_rep[(index + 2)..].find("]:") {
+                Some(close) => vec![
+                    &string_rep[..index],
+                    &string_rep[(index + 2)..(index + 2 + close)],
+                    &string_rep[(index + 2 + close + 2)..],
+                ],
+                None => vec![string_rep],
+            },
+            None => string_rep.split(":").collect(),
+        };
         if pieces.len() != 3 {
             return Err(format!("A NodeReference must have the form <public_key>:<IP address>:<port list>, not '{}'", string_rep));
         }
@@ -48,7 +59,10 @@ impl FromStr for NodeReference {
 impl fmt::Display for NodeReference {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let public_key_string = base64::encode_config(&self.public_key.as_slice(), STANDARD_NO_PAD);
-        let ip_addr_string = format!("{}", self.node_addr.ip_addr());
+        let ip_addr_string = match self.node_addr.ip_addr() {
+            IpAddr::V4(ip_addr) => format!("{}", ip_addr),
+            IpAddr::V6(ip_addr) => format!("[{}]", ip_addr),
+        };
         let port_list_string = self
             .node_addr
             .ports()
@@ -149,6 +163,8 @@ pub trait SubstratumNode: Any {
     fn consuming_wallet(&self) -> Option<Wallet>;
 
     fn make_client(&self, port: u16) -> SubstratumNodeClient;
+
+    fn make_tls_client(&self, port: u16, domain: &str) -> SubstratumNodeClient;
 }
 
 pub struct SubstratumNodeUtils {}
@@ -319,6 +335,21 @@ mod tests {
         );
     }
 
+    #[test]
+    fn node_reference_from_string_handles_ipv6_addresses() {
+        let key = PublicKey::new(&b"Booga"[..]);
+        let string = format!("{}:[2001:db8::1]:1234,2345", key);
+
+        let result = NodeReference::from_str(string.as_str()).unwrap();
+
+        assert_eq!(result.public_key, key);
+        assert_eq!(
+            result.node_addr,
+            NodeAddr::new(&IpAddr::from_str("2001:db8::1").unwrap(), &vec!(1234, 2345))
+        );
+        assert_eq!(format!("{}", result), string);
+    }
+
     #[test]
     fn node_reference_can_display_itself() {
         let subject = NodeReference::new(
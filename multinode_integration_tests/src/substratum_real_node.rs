@@ -5,6 +5,7 @@ use crate::substratum_node::NodeReference;
 use crate::substratum_node::PortSelector;
 use crate::substratum_node::SubstratumNode;
 use crate::substratum_node::SubstratumNodeUtils;
+use base64::STANDARD_NO_PAD;
 use node_lib::accountant::db_initializer::Daos;
 use node_lib::accountant::db_initializer::DbInitializer;
 use node_lib::accountant::db_initializer::DbInitializerReal;
@@ -16,6 +17,10 @@ use node_lib::sub_lib::neighborhood::sentinel_ip_addr;
 use node_lib::sub_lib::node_addr::NodeAddr;
 use node_lib::sub_lib::wallet::Wallet;
 use regex::Regex;
+use std::cell::Cell;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
 use std::net::IpAddr;
 use std::net::Ipv4Addr;
 use std::net::SocketAddr;
@@ -24,13 +29,23 @@ use std::str::FromStr;
 use std::thread;
 use std::time::Duration;
 
+// Where a Node started with NodeStartupConfigBuilder::fault_injection() takes fault injection
+// commands, inside its own container
+pub const FAULT_INJECTION_PORT: u16 = 5444;
+
+// Where libfaketime lives in test_node_image, and the file in a Node's home directory that tells it
+// how far ahead of real time the Node's clock is
+pub const LIBFAKETIME_PATH: &str = "/usr/lib/x86_64-linux-gnu/faketime/libfaketime.so.1";
+pub const FAKE_TIME_FILE: &str = "faketimerc";
+pub const CONFIG_FILE: &str = "config.toml";
+
 #[derive(PartialEq, Clone, Debug, Copy)]
 pub enum NodeType {
     Standard,
     Bootstrap,
 }
 
-#[derive(PartialEq, Clone)]
+#[derive(PartialEq, Clone, Debug)]
 pub struct NodeStartupConfig {
     pub ip: IpAddr,
     pub dns_servers: Vec<IpAddr>,
@@ -40,6 +55,9 @@ pub struct NodeStartupConfig {
     pub dns_target: IpAddr,
     pub dns_port: u16,
     pub earning_wallet: Wallet,
+    pub extra_args: Vec<String>,
+    pub env: HashMap<String, String>,
+    pub config_file: Option<String>,
 }
 
 impl NodeStartupConfig {
@@ -53,6 +71,9 @@ impl NodeStartupConfig {
             dns_target: sentinel_ip_addr(),
             dns_port: 0,
             earning_wallet: accountant::DEFAULT_EARNING_WALLET.clone(),
+            extra_args: vec![],
+            env: HashMap::new(),
+            config_file: None,
         }
     }
 
@@ -84,11 +105,53 @@ impl NodeStartupConfig {
         args.push(format!("{}", self.dns_port));
         args.push("--log_level".to_string());
         args.push("trace".to_string());
+        let (uid, gid) = Self::run_as_ids();
+        args.push("--run_as_uid".to_string());
+        args.push(uid);
+        args.push("--run_as_gid".to_string());
+        args.push(gid);
         args.push("--data_directory".to_string());
         args.push("/node_root/home".to_string());
+        if self.config_file.is_some() {
+            args.push("--config_file".to_string());
+            args.push(format!("/node_root/home/{}", CONFIG_FILE));
+        }
+        args.extend(self.extra_args.clone());
         args
     }
 
+    // The Node hands its home directory over to the test runner's own user and group before it drops
+    // privileges to them, so that both can read and write what's in there. A runner that is root
+    // can read and write anything, but a Node can't drop to root, so those Nodes take the image's
+    // default user instead.
+    fn run_as_ids() -> (String, String) {
+        match (Self::runner_id("-u"), Self::runner_id("-g")) {
+            (ref uid, _) if uid == "0" => ("1000".to_string(), "1000".to_string()),
+            (uid, gid) => (uid, gid),
+        }
+    }
+
+    fn runner_id(flag: &str) -> String {
+        Command::new("id", Command::strings(vec![flag]))
+            .stdout_or_stderr()
+            .expect("Couldn't find the test runner's ids")
+            .trim()
+            .to_string()
+    }
+
+    fn fakes_time(&self) -> bool {
+        self.env.contains_key("FAKETIME_TIMESTAMP_FILE")
+    }
+
+    fn make_env_args(&self) -> Vec<String> {
+        let mut names: Vec<&String> = self.env.keys().collect();
+        names.sort();
+        names
+            .into_iter()
+            .flat_map(|name| vec!["-e".to_string(), format!("{}={}", name, self.env[name])])
+            .collect()
+    }
+
     fn join_ip_addrs(ip_addrs: &Vec<IpAddr>) -> String {
         ip_addrs
             .iter()
@@ -107,6 +170,9 @@ pub struct NodeStartupConfigBuilder {
     dns_target: IpAddr,
     dns_port: u16,
     earning_wallet: Wallet,
+    extra_args: Vec<String>,
+    env: HashMap<String, String>,
+    config_file: Option<String>,
 }
 
 impl NodeStartupConfigBuilder {
@@ -120,6 +186,9 @@ impl NodeStartupConfigBuilder {
             dns_target: IpAddr::from_str("127.0.0.1").unwrap(),
             dns_port: 53,
             earning_wallet: accountant::DEFAULT_EARNING_WALLET.clone(),
+            extra_args: vec![],
+            env: HashMap::new(),
+            config_file: None,
         }
     }
 
@@ -133,6 +202,9 @@ impl NodeStartupConfigBuilder {
             dns_target: IpAddr::from_str("127.0.0.1").unwrap(),
             dns_port: 53,
             earning_wallet: accountant::DEFAULT_EARNING_WALLET.clone(),
+            extra_args: vec![],
+            env: HashMap::new(),
+            config_file: None,
         }
     }
 
@@ -146,6 +218,9 @@ impl NodeStartupConfigBuilder {
             dns_target: IpAddr::from_str("127.0.0.1").unwrap(),
             dns_port: 53,
             earning_wallet: accountant::DEFAULT_EARNING_WALLET.clone(),
+            extra_args: vec![],
+            env: HashMap::new(),
+            config_file: None,
         }
     }
 
@@ -159,6 +234,9 @@ impl NodeStartupConfigBuilder {
             dns_target: config.dns_target.clone(),
             dns_port: config.dns_port,
             earning_wallet: config.earning_wallet.clone(),
+            extra_args: config.extra_args.clone(),
+            env: config.env.clone(),
+            config_file: config.config_file.clone(),
         }
     }
 
@@ -207,6 +285,48 @@ impl NodeStartupConfigBuilder {
         self
     }
 
+    // Appended to the command line after everything make_args() produces
+    pub fn extra_args(mut self, value: Vec<String>) -> NodeStartupConfigBuilder {
+        self.extra_args = value;
+        self
+    }
+
+    // Only useful if the Node under test was built with --features fault_injection
+    pub fn fault_injection(mut self) -> NodeStartupConfigBuilder {
+        self.extra_args.push("--fault_injection_port".to_string());
+        self.extra_args.push(format!("{}", FAULT_INJECTION_PORT));
+        self
+    }
+
+    // Runs the Node under libfaketime, so that a test can move its clock forward with
+    // SubstratumRealNode::advance_time() instead of sleeping. Call it after env(), which replaces
+    // the whole environment.
+    pub fn fake_time(mut self) -> NodeStartupConfigBuilder {
+        self.env
+            .insert(String::from("LD_PRELOAD"), String::from(LIBFAKETIME_PATH));
+        self.env.insert(
+            String::from("FAKETIME_TIMESTAMP_FILE"),
+            format!("/node_root/home/{}", FAKE_TIME_FILE),
+        );
+        // Read the file on every clock call rather than every few seconds
+        self.env
+            .insert(String::from("FAKETIME_NO_CACHE"), String::from("1"));
+        self
+    }
+
+    // Set in the container's environment
+    pub fn env(mut self, value: HashMap<String, String>) -> NodeStartupConfigBuilder {
+        self.env = value;
+        self
+    }
+
+    // TOML, written to the Node's home directory and named with --config_file, for parameters the
+    // Node should find there rather than on its command line, which still beats it
+    pub fn config_file(mut self, value: &str) -> NodeStartupConfigBuilder {
+        self.config_file = Some(String::from(value));
+        self
+    }
+
     pub fn build(self) -> NodeStartupConfig {
         NodeStartupConfig {
             ip: self.ip,
@@ -217,6 +337,9 @@ impl NodeStartupConfigBuilder {
             dns_target: self.dns_target,
             dns_port: self.dns_port,
             earning_wallet: self.earning_wallet,
+            extra_args: self.extra_args,
+            env: self.env,
+            config_file: self.config_file,
         }
     }
 }
@@ -232,7 +355,7 @@ impl SubstratumNode for SubstratumRealNode {
     }
 
     fn node_reference(&self) -> NodeReference {
-        self.guts.node_reference.clone()
+        self.guts.node_reference.borrow().clone()
     }
 
     fn public_key(&self) -> PublicKey {
@@ -271,6 +394,11 @@ impl SubstratumNode for SubstratumRealNode {
         let socket_addr = SocketAddr::new(self.ip_address(), port);
         SubstratumNodeClient::new(socket_addr)
     }
+
+    fn make_tls_client(&self, port: u16, domain: &str) -> SubstratumNodeClient {
+        let socket_addr = SocketAddr::new(self.ip_address(), port);
+        SubstratumNodeClient::new_tls(socket_addr, domain)
+    }
 }
 
 impl SubstratumRealNode {
@@ -298,14 +426,116 @@ impl SubstratumRealNode {
         let guts = Rc::new(SubstratumRealNodeGuts {
             name,
             container_ip: ip_addr,
-            node_reference,
+            node_reference: RefCell::new(node_reference),
+            startup_config: real_startup_config,
             earning_wallet,
             consuming_wallet: Some(TEMPORARY_CONSUMING_WALLET.clone()),
             root_dir,
+            fake_time_offset: Cell::new(Duration::from_secs(0)),
         });
         SubstratumRealNode { guts }
     }
 
+    // Stops the Node's container abruptly, as if its machine had lost power. Its home directory,
+    // with its key and its database, is left where it is for restart().
+    pub fn kill(&self) {
+        SubstratumNodeUtils::stop(self.name())
+    }
+
+    // Starts a killed Node again in a fresh container, with the same startup configuration, IP
+    // address and home directory. It should come back with the same public key, because it reads
+    // its key from its home directory; its clandestine ports may be different.
+    pub fn restart(&self) -> Result<(), String> {
+        SubstratumNodeUtils::clean_up_existing_container(self.name());
+        let name = self.name().to_string();
+        Self::do_docker_run_in_existing_home(
+            &self.guts.startup_config,
+            &self.guts.root_dir,
+            self.guts.container_ip,
+            &name,
+        )?;
+        let node_reference = Self::extract_node_reference(&name)?;
+        if node_reference.public_key != self.public_key() {
+            return Err(format!(
+                "Node {} came back as {} instead of {}",
+                name,
+                node_reference.public_key,
+                self.public_key()
+            ));
+        }
+        *self.guts.node_reference.borrow_mut() = node_reference;
+        Ok(())
+    }
+
+    pub fn inject_fault(&self, command: &str) -> Result<(), String> {
+        self.control_command(command).map(|_| ())
+    }
+
+    // Every Node in this Node's Neighborhood database, itself included. Like inject_fault(), only
+    // for a Node started with NodeStartupConfigBuilder::fault_injection().
+    pub fn known_node_keys(&self) -> Result<Vec<PublicKey>, String> {
+        Self::parse_known_node_keys(&self.control_command("known_nodes")?)
+    }
+
+    fn parse_known_node_keys(answer: &str) -> Result<Vec<PublicKey>, String> {
+        answer
+            .split(',')
+            .filter(|key| !key.is_empty())
+            .map(|key| match base64::decode_config(key, STANDARD_NO_PAD) {
+                Ok(data) => Ok(PublicKey::new(&data)),
+                Err(e) => Err(format!("Bad key '{}' in known_nodes answer: {:?}", key, e)),
+            })
+            .collect()
+    }
+
+    // The control socket only listens on 127.0.0.1, so the command is sent from inside the
+    // container; see node_lib's fault_injection module for the commands it understands. Whatever
+    // follows the "OK" is the answer.
+    fn control_command(&self, command: &str) -> Result<String, String> {
+        let script = format!(
+            "exec 3<>/dev/tcp/127.0.0.1/{}; echo '{}' >&3; read -r response <&3; echo $response",
+            FAULT_INJECTION_PORT, command
+        );
+        let mut docker_command = Command::new(
+            "docker",
+            Command::strings(vec!["exec", self.name(), "bash", "-c", script.as_str()]),
+        );
+        let response = docker_command.stdout_or_stderr()?;
+        match response.trim() {
+            "OK" => Ok(String::new()),
+            response if response.starts_with("OK ") => Ok(response[3..].to_string()),
+            response => Err(response.to_string()),
+        }
+    }
+
+    // Only for a Node started with NodeStartupConfigBuilder::fake_time(). Moves the Node's clock
+    // this much further ahead of real time, all at once; libfaketime only deals in whole seconds.
+    pub fn advance_time(&self, by: Duration) -> Result<(), String> {
+        let offset = self.guts.fake_time_offset.get() + by;
+        Self::write_fake_time_offset(&self.name().to_string(), offset)?;
+        self.guts.fake_time_offset.set(offset);
+        Ok(())
+    }
+
+    pub fn fake_time_offset(&self) -> Duration {
+        self.guts.fake_time_offset.get()
+    }
+
+    // Written from the test runner's side of the home directory mount, and renamed into place so
+    // the Node never reads a half-written file
+    fn write_fake_time_offset(name: &String, offset: Duration) -> Result<(), String> {
+        let home_dir = Self::node_home_dir(&SubstratumNodeUtils::find_project_root(), name);
+        let temp_path = format!("{}/{}.new", home_dir, FAKE_TIME_FILE);
+        let path = format!("{}/{}", home_dir, FAKE_TIME_FILE);
+        fs::write(&temp_path, Self::fake_time_spec(offset))
+            .and_then(|_| fs::rename(&temp_path, &path))
+            .map_err(|e| format!("Couldn't set fake time for node {}: {}", name, e))
+    }
+
+    fn fake_time_spec(offset: Duration) -> String {
+        format!("+{}\n", offset.as_secs())
+    }
+
     pub fn root_dir(&self) -> String {
         self.guts.root_dir.clone()
     }
@@ -335,8 +565,6 @@ impl SubstratumRealNode {
         name: &String,
     ) -> Result<(), String> {
         let name_string = name.clone();
-        let node_command_dir = format!("{}/node/target/release", root_dir);
-        let host_node_home_dir = Self::node_home_dir(root_dir, name);
         let test_runner_node_home_dir =
             Self::node_home_dir(&SubstratumNodeUtils::find_project_root(), name);
         Command::new(
@@ -356,18 +584,28 @@ impl SubstratumRealNode {
                 name_string, test_runner_node_home_dir
             ),
         }
-        match Command::new(
-            "chmod",
-            Command::strings(vec!["777", test_runner_node_home_dir.as_str()]),
-        )
-        .wait_for_exit()
-        {
-            0 => (),
-            _ => panic!(
-                "Couldn't chmod 777 home directory for node {} at {}",
-                name_string, test_runner_node_home_dir
-            ),
+        if startup_config.fakes_time() {
+            Self::write_fake_time_offset(name, Duration::from_secs(0))?;
+        }
+        if let Some(ref contents) = startup_config.config_file {
+            fs::write(
+                format!("{}/{}", test_runner_node_home_dir, CONFIG_FILE),
+                contents,
+            )
+            .map_err(|e| format!("Couldn't write config file for node {}: {}", name, e))?;
         }
+        Self::do_docker_run_in_existing_home(startup_config, root_dir, ip_addr, name)
+    }
+
+    fn do_docker_run_in_existing_home(
+        startup_config: &NodeStartupConfig,
+        root_dir: &String,
+        ip_addr: IpAddr,
+        name: &String,
+    ) -> Result<(), String> {
+        let name_string = name.clone();
+        let node_command_dir = format!("{}/node/target/release", root_dir);
+        let host_node_home_dir = Self::node_home_dir(root_dir, name);
         let node_args = startup_config.make_args();
         let docker_command = "docker";
         let ip_addr_string = format!("{}", ip_addr);
@@ -390,9 +628,12 @@ impl SubstratumRealNode {
             home_v_param.as_str(),
             "-e",
             "RUST_BACKTRACE=full",
+        ]);
+        docker_args.extend(startup_config.make_env_args());
+        docker_args.extend(Command::strings(vec![
             "test_node_image",
             "/node_root/node/SubstratumNode",
-        ]);
+        ]));
         docker_args.extend(node_args);
         let mut command = Command::new(docker_command, docker_args);
         command.stdout_or_stderr()?;
@@ -400,7 +641,10 @@ impl SubstratumRealNode {
     }
 
     fn extract_node_reference(name: &String) -> Result<NodeReference, String> {
-        let regex = Regex::new(r"SubstratumNode local descriptor: ([^:]+:[\d.]+:[\d,]*)").unwrap();
+        let regex = Regex::new(
+            r"SubstratumNode local descriptor: ([^:]+:(?:[\d.]+|\[[\da-fA-F:.]+\]):[\d,]*)",
+        )
+        .unwrap();
         let mut retries_left = 5;
         loop {
             thread::sleep(Duration::from_millis(100));
@@ -430,10 +674,12 @@ impl SubstratumRealNode {
 struct SubstratumRealNodeGuts {
     name: String,
     container_ip: IpAddr,
-    node_reference: NodeReference,
+    node_reference: RefCell<NodeReference>,
+    startup_config: NodeStartupConfig,
     earning_wallet: Wallet,
     consuming_wallet: Option<Wallet>,
     root_dir: String,
+    fake_time_offset: Cell<Duration>,
 }
 
 impl Drop for SubstratumRealNodeGuts {
@@ -556,6 +802,11 @@ mod tests {
             dns_target: IpAddr::from_str("255.255.255.255").unwrap(),
             dns_port: 54,
             earning_wallet: Wallet::new("booga"),
+            extra_args: Command::strings(vec!["--booga", "1"]),
+            env: vec![(String::from("BOOGA"), String::from("1"))]
+                .into_iter()
+                .collect(),
+            config_file: Some(String::from("ui_port = 5334\n")),
         };
         let ip_addr = IpAddr::from_str("1.2.3.4").unwrap();
         let one_neighbor_key = PublicKey::new(&[1, 2, 3, 4]);
@@ -600,6 +851,9 @@ mod tests {
         assert_eq!(result.dns_target, dns_target);
         assert_eq!(result.dns_port, 35);
         assert_eq!(result.earning_wallet, Wallet::new("booga"));
+        assert_eq!(result.extra_args, original.extra_args);
+        assert_eq!(result.env, original.env);
+        assert_eq!(result.config_file, original.config_file);
     }
 
     #[test]
@@ -622,6 +876,7 @@ mod tests {
 
         let result = subject.make_args();
 
+        let (uid, gid) = NodeStartupConfig::run_as_ids();
         assert_eq!(
             result,
             Command::strings(vec!(
@@ -645,9 +900,163 @@ mod tests {
                 "53",
                 "--log_level",
                 "trace",
+                "--run_as_uid",
+                uid.as_str(),
+                "--run_as_gid",
+                gid.as_str(),
                 "--data_directory",
                 "/node_root/home",
             ))
         );
     }
+
+    #[test]
+    fn nodes_run_as_the_test_runner_but_never_as_root() {
+        let runner_uid = NodeStartupConfig::runner_id("-u");
+
+        let (uid, gid) = NodeStartupConfig::run_as_ids();
+
+        assert_ne!(uid, "0".to_string());
+        assert_ne!(gid, "".to_string());
+        if runner_uid != "0" {
+            assert_eq!(uid, runner_uid);
+            assert_eq!(gid, NodeStartupConfig::runner_id("-g"));
+        }
+    }
+
+    #[test]
+    fn config_file_is_named_in_the_node_home_directory_before_the_extra_args() {
+        let subject = NodeStartupConfigBuilder::zero_hop()
+            .config_file("ui_port = 5334\n")
+            .extra_args(Command::strings(vec!["--crypto", "null"]))
+            .build();
+
+        let result = subject.make_args();
+
+        assert_eq!(
+            result[result.len() - 4..].to_vec(),
+            Command::strings(vec![
+                "--config_file",
+                "/node_root/home/config.toml",
+                "--crypto",
+                "null"
+            ])
+        );
+        assert_eq!(
+            NodeStartupConfigBuilder::zero_hop()
+                .build()
+                .make_args()
+                .contains(&String::from("--config_file")),
+            false
+        );
+    }
+
+    #[test]
+    fn extra_args_follow_the_standard_ones() {
+        let subject = NodeStartupConfigBuilder::zero_hop()
+            .extra_args(Command::strings(vec!["--crypto", "null"]))
+            .build();
+
+        let result = subject.make_args();
+
+        assert_eq!(
+            result[result.len() - 4..].to_vec(),
+            Command::strings(vec![
+                "--data_directory",
+                "/node_root/home",
+                "--crypto",
+                "null"
+            ])
+        );
+    }
+
+    #[test]
+    fn fault_injection_adds_the_control_port_to_the_extra_args() {
+        let subject = NodeStartupConfigBuilder::zero_hop()
+            .extra_args(Command::strings(vec!["--crypto", "null"]))
+            .fault_injection()
+            .build();
+
+        assert_eq!(
+            subject.extra_args,
+            Command::strings(vec!["--crypto", "null", "--fault_injection_port", "5444"])
+        );
+    }
+
+    #[test]
+    fn fake_time_preloads_libfaketime_pointed_at_the_file_in_the_node_home_directory() {
+        let subject = NodeStartupConfigBuilder::zero_hop()
+            .env(
+                vec![(String::from("BOOGA"), String::from("1"))]
+                    .into_iter()
+                    .collect(),
+            )
+            .fake_time()
+            .build();
+
+        assert_eq!(subject.fakes_time(), true);
+        assert_eq!(
+            subject.make_env_args(),
+            Command::strings(vec![
+                "-e",
+                "BOOGA=1",
+                "-e",
+                "FAKETIME_NO_CACHE=1",
+                "-e",
+                "FAKETIME_TIMESTAMP_FILE=/node_root/home/faketimerc",
+                "-e",
+                "LD_PRELOAD=/usr/lib/x86_64-linux-gnu/faketime/libfaketime.so.1",
+            ])
+        );
+        assert_eq!(
+            NodeStartupConfigBuilder::zero_hop().build().fakes_time(),
+            false
+        );
+    }
+
+    #[test]
+    fn known_node_keys_are_parsed_from_the_control_socket_answer() {
+        assert_eq!(
+            SubstratumRealNode::parse_known_node_keys("QUFB,QkJC"),
+            Ok(vec![PublicKey::new(b"AAA"), PublicKey::new(b"BBB")])
+        );
+        assert_eq!(SubstratumRealNode::parse_known_node_keys(""), Ok(vec![]));
+        assert_eq!(
+            SubstratumRealNode::parse_known_node_keys("QUFB,!!!")
+                .err()
+                .unwrap()
+                .starts_with("Bad key '!!!' in known_nodes answer: "),
+            true
+        );
+    }
+
+    #[test]
+    fn fake_time_spec_is_a_relative_offset_in_whole_seconds() {
+        assert_eq!(
+            SubstratumRealNode::fake_time_spec(Duration::from_secs(0)),
+            "+0\n"
+        );
+        assert_eq!(
+            SubstratumRealNode::fake_time_spec(Duration::from_millis(86_400_999)),
+            "+86400\n"
+        );
+    }
+
+    #[test]
+    fn env_becomes_sorted_docker_env_args() {
+        let env: HashMap<String, String> = vec![
+            (String::from("ZEBRA"), String::from("stripes")),
+            (String::from("AARDVARK"), String::from("ants")),
+        ]
+        .into_iter()
+        .collect();
+        let subject = NodeStartupConfigBuilder::zero_hop().env(env).build();
+
+        let result = subject.make_env_args();
+
+        assert_eq!(
+            result,
+            Command::strings(vec!["-e", "AARDVARK=ants", "-e", "ZEBRA=stripes"])
+        );
+    }
 }
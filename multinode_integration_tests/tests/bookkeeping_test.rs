@@ -8,11 +8,11 @@ use node_lib::accountant::payable_dao::PayableAccount;
 use node_lib::accountant::receivable_dao::ReceivableAccount;
 use node_lib::sub_lib::cryptde::CryptDE;
 use node_lib::sub_lib::cryptde::PlainData;
-use node_lib::sub_lib::hopper::TEMPORARY_PER_ROUTING_BYTE_RATE;
-use node_lib::sub_lib::hopper::TEMPORARY_PER_ROUTING_RATE;
+use node_lib::sub_lib::hopper::DEFAULT_PER_ROUTING_BYTE_RATE;
+use node_lib::sub_lib::hopper::DEFAULT_PER_ROUTING_RATE;
 use node_lib::sub_lib::proxy_client::ClientResponsePayload;
-use node_lib::sub_lib::proxy_client::TEMPORARY_PER_EXIT_BYTE_RATE;
-use node_lib::sub_lib::proxy_client::TEMPORARY_PER_EXIT_RATE;
+use node_lib::sub_lib::proxy_client::DEFAULT_PER_EXIT_BYTE_RATE;
+use node_lib::sub_lib::proxy_client::DEFAULT_PER_EXIT_RATE;
 use node_lib::sub_lib::proxy_server::ClientRequestPayload;
 use node_lib::sub_lib::proxy_server::ProxyProtocol;
 use node_lib::sub_lib::sequence_buffer::SequencedPacket;
@@ -64,8 +64,7 @@ fn provided_and_consumed_services_are_recorded_in_databases() {
     let param_block = ParamBlock {
         before,
         after: SystemTime::now(),
-        request_len: request.len(),
-        response_len: response.len(),
+        exchanges: vec![Exchange::http(request.len(), response.len())],
         originating_node: originating_node.clone(),
         routing_node: routing_node.clone(),
         exit_node: exit_node.clone(),
@@ -76,17 +75,110 @@ fn provided_and_consumed_services_are_recorded_in_databases() {
     check_exit_charges(&param_block);
 }
 
+#[test]
+fn http_and_tls_retrievals_are_charged_by_the_byte_to_every_node_on_the_route() {
+    let mut cluster = SubstratumNodeCluster::start().unwrap();
+
+    let bootstrap = cluster.start_mock_bootstrap_node(vec![5550]);
+
+    let _nodes = (0..4)
+        .map(|idx| {
+            cluster.start_real_node(
+                NodeStartupConfigBuilder::standard()
+                    .neighbor(bootstrap.node_reference())
+                    .earning_wallet(make_wallet_from(idx))
+                    .build(),
+            )
+        })
+        .collect::<Vec<SubstratumRealNode>>();
+    thread::sleep(Duration::from_millis(2000));
+
+    // The bootstrap Node strings its Nodes together in a chain, and routes are two hops long, so
+    // traffic from the first Node goes through the second to the third and back. The fourth Node
+    // is never on the route, and nobody should owe it or be owed by it.
+    let routing_node_keys = bootstrap.routing_node_keys();
+    let originating_node = cluster
+        .get_real_node_by_key(&bootstrap.originating_node_key())
+        .unwrap();
+    let routing_node = cluster.get_real_node_by_key(&routing_node_keys[0]).unwrap();
+    let exit_node = cluster.get_real_node_by_key(&routing_node_keys[1]).unwrap();
+    let bystander_node = cluster
+        .get_real_node_by_key(&bootstrap.exit_node_key())
+        .unwrap();
+    let http_request = "GET / HTTP/1.1\r\nHost: example.com\r\n\r\n".as_bytes();
+    let tls_request = make_tls_client_hello("example.com");
+    let before = SystemTime::now();
+
+    let mut http_client = originating_node.make_client(80);
+    http_client.send_chunk(Vec::from(http_request));
+    let http_response = http_client.wait_for_chunk();
+    let mut tls_client = originating_node.make_client(443);
+    tls_client.send_chunk(tls_request.clone());
+    let tls_response = tls_client.wait_for_chunk();
+    thread::sleep(Duration::from_millis(500)); // give the Accountants time to write
+
+    assert_eq!(
+        tls_response.first(),
+        Some(&0x15),
+        "Expected a TLS alert, but got {:?}",
+        tls_response
+    );
+    let param_block = ParamBlock {
+        before,
+        after: SystemTime::now(),
+        exchanges: vec![
+            Exchange::http(http_request.len(), http_response.len()),
+            Exchange::tls(tls_request.len(), tls_response.len()),
+        ],
+        originating_node: originating_node.clone(),
+        routing_node: routing_node.clone(),
+        exit_node: exit_node.clone(),
+    };
+    check_originating_charges(&param_block);
+    check_routing_charges(&param_block);
+    check_exit_charges(&param_block);
+    check_no_charges_involving(&param_block, &bystander_node);
+}
+
 #[derive(Debug)]
 struct ParamBlock {
     before: SystemTime,
     after: SystemTime,
-    request_len: usize,
-    response_len: usize,
+    exchanges: Vec<Exchange>,
     originating_node: SubstratumRealNode,
     routing_node: SubstratumRealNode,
     exit_node: SubstratumRealNode,
 }
 
+// One request and its response, each assumed to travel as a single CORES package
+#[derive(Debug, Clone, Copy)]
+struct Exchange {
+    protocol: ProxyProtocol,
+    target_port: u16,
+    request_len: usize,
+    response_len: usize,
+}
+
+impl Exchange {
+    fn http(request_len: usize, response_len: usize) -> Exchange {
+        Exchange {
+            protocol: ProxyProtocol::HTTP,
+            target_port: 80,
+            request_len,
+            response_len,
+        }
+    }
+
+    fn tls(request_len: usize, response_len: usize) -> Exchange {
+        Exchange {
+            protocol: ProxyProtocol::TLS,
+            target_port: 443,
+            request_len,
+            response_len,
+        }
+    }
+}
+
 fn check_originating_charges(param_block: &ParamBlock) {
     let receivable_account = receivable_account_status(
         &param_block.originating_node,
@@ -106,10 +198,10 @@ fn check_originating_charges(param_block: &ParamBlock) {
     assert_eq!(receivable_account, None);
     let (cores_request_bytes, expected_request_routing_charge) =
         cores_payload_request_routing_charges(&param_block);
-    let expected_request_exit_charge = calculate_exit_charge(param_block.request_len);
+    let expected_request_exit_charge = exit_charges(&param_block, |e| e.request_len);
     let (cores_response_bytes, expected_response_routing_charge) =
         cores_payload_response_routing_charges(&param_block);
-    let expected_response_exit_charge = calculate_exit_charge(param_block.response_len);
+    let expected_response_exit_charge = exit_charges(&param_block, |e| e.response_len);
     println!(
         "request_routing_charge: {}, response_routing_charge: {}",
         expected_request_routing_charge, expected_response_routing_charge
@@ -117,7 +209,8 @@ fn check_originating_charges(param_block: &ParamBlock) {
     assert_eq!(
         payable_routing_account.balance as u64,
         expected_request_routing_charge + expected_response_routing_charge,
-        "Balance should be calculated for 2 routing services and {} + {} bytes",
+        "Balance should be calculated for {} routing services and {} + {} bytes",
+        param_block.exchanges.len() * 2,
         cores_request_bytes,
         cores_response_bytes
     );
@@ -130,7 +223,8 @@ fn check_originating_charges(param_block: &ParamBlock) {
     assert_eq!(
         payable_exit_account.balance as u64,
         expected_request_exit_charge + expected_response_exit_charge,
-        "Balance should be calculated for 2 exit services and {} + {} bytes",
+        "Balance should be calculated for {} exit services and {} + {} bytes",
+        param_block.exchanges.len() * 2,
         cores_request_bytes,
         cores_response_bytes
     );
@@ -143,11 +237,29 @@ fn check_originating_charges(param_block: &ParamBlock) {
 }
 
 fn cores_payload_request_routing_charges(param_block: &ParamBlock) -> (usize, u64) {
-    calculate_request_routing_charge(param_block.request_len, &param_block.exit_node.cryptde())
+    sum_charges(param_block.exchanges.iter().map(|exchange| {
+        calculate_request_routing_charge(exchange, &param_block.exit_node.cryptde())
+    }))
 }
 
 fn cores_payload_response_routing_charges(param_block: &ParamBlock) -> (usize, u64) {
-    calculate_response_routing_charge(param_block.response_len, &param_block.exit_node.cryptde())
+    sum_charges(param_block.exchanges.iter().map(|exchange| {
+        calculate_response_routing_charge(exchange.response_len, &param_block.exit_node.cryptde())
+    }))
+}
+
+fn exit_charges(param_block: &ParamBlock, bytes: fn(&Exchange) -> usize) -> u64 {
+    param_block
+        .exchanges
+        .iter()
+        .map(|exchange| calculate_exit_charge(bytes(exchange)))
+        .sum()
+}
+
+fn sum_charges<I: Iterator<Item = (usize, u64)>>(charges: I) -> (usize, u64) {
+    charges.fold((0, 0), |(total_bytes, total_charge), (bytes, charge)| {
+        (total_bytes + bytes, total_charge + charge)
+    })
 }
 
 fn check_routing_charges(param_block: &ParamBlock) {
@@ -166,15 +278,21 @@ fn check_routing_charges(param_block: &ParamBlock) {
     );
 
     let (request_bytes, expected_request_charge) =
-        calculate_request_routing_charge(param_block.request_len, &param_block.exit_node.cryptde());
-    let (response_bytes, expected_response_charge) = calculate_response_routing_charge(
-        param_block.response_len,
-        &param_block.originating_node.cryptde(),
-    );
+        sum_charges(param_block.exchanges.iter().map(|exchange| {
+            calculate_request_routing_charge(exchange, &param_block.exit_node.cryptde())
+        }));
+    let (response_bytes, expected_response_charge) =
+        sum_charges(param_block.exchanges.iter().map(|exchange| {
+            calculate_response_routing_charge(
+                exchange.response_len,
+                &param_block.originating_node.cryptde(),
+            )
+        }));
     assert_eq!(
         receivable_account.balance as u64,
         expected_request_charge + expected_response_charge,
-        "Balance should be calculated for 2 routing services and {} + {} bytes",
+        "Balance should be calculated for {} routing services and {} + {} bytes",
+        param_block.exchanges.len() * 2,
         request_bytes,
         response_bytes
     );
@@ -202,14 +320,23 @@ fn check_exit_charges(param_block: &ParamBlock) {
         &param_block.routing_node.earning_wallet(),
     );
 
-    let expected_request_charge = calculate_exit_charge(param_block.request_len);
-    let expected_response_charge = calculate_exit_charge(param_block.response_len);
+    let expected_request_charge = exit_charges(&param_block, |e| e.request_len);
+    let expected_response_charge = exit_charges(&param_block, |e| e.response_len);
     assert_eq!(
         receivable_account.balance as u64,
         expected_request_charge + expected_response_charge,
-        "Balance should be calculated for 2 exit services and {} + {} bytes",
-        param_block.request_len,
-        param_block.response_len
+        "Balance should be calculated for {} exit services and {} + {} bytes",
+        param_block.exchanges.len() * 2,
+        param_block
+            .exchanges
+            .iter()
+            .map(|e| e.request_len)
+            .sum::<usize>(),
+        param_block
+            .exchanges
+            .iter()
+            .map(|e| e.response_len)
+            .sum::<usize>()
     );
     assert_timestamp_between(
         &param_block.before,
@@ -220,6 +347,36 @@ fn check_exit_charges(param_block: &ParamBlock) {
     assert_eq!(payable_routing_account, None);
 }
 
+fn check_no_charges_involving(param_block: &ParamBlock, bystander_node: &SubstratumRealNode) {
+    vec![
+        &param_block.originating_node,
+        &param_block.routing_node,
+        &param_block.exit_node,
+    ]
+    .into_iter()
+    .for_each(|node| {
+        assert_eq!(
+            payable_account_status(node, &bystander_node.earning_wallet()),
+            None,
+            "{} should owe the bystander nothing",
+            node.name()
+        );
+        assert_eq!(
+            payable_account_status(bystander_node, &node.earning_wallet()),
+            None,
+            "The bystander should owe {} nothing",
+            node.name()
+        );
+    });
+    assert_eq!(
+        receivable_account_status(
+            bystander_node,
+            &param_block.originating_node.consuming_wallet().unwrap()
+        ),
+        None
+    );
+}
+
 fn payable_account_status(
     node: &SubstratumRealNode,
     earning_wallet: &Wallet,
@@ -236,8 +393,8 @@ fn receivable_account_status(
     receivable_dao.account_status(consuming_wallet)
 }
 
-fn calculate_request_routing_charge(bytes: usize, exit_cryptde: &CryptDE) -> (usize, u64) {
-    let payload: ClientRequestPayload = make_request_payload(bytes, exit_cryptde);
+fn calculate_request_routing_charge(exchange: &Exchange, exit_cryptde: &CryptDE) -> (usize, u64) {
+    let payload: ClientRequestPayload = make_request_payload(exchange, exit_cryptde);
     let payload_ser = PlainData::from(serde_cbor::ser::to_vec(&payload).unwrap());
     let payload_enc = exit_cryptde
         .encode(&exit_cryptde.public_key(), &payload_ser)
@@ -245,7 +402,7 @@ fn calculate_request_routing_charge(bytes: usize, exit_cryptde: &CryptDE) -> (us
     let payload_len = payload_enc.len();
     (
         payload_len,
-        TEMPORARY_PER_ROUTING_RATE + (TEMPORARY_PER_ROUTING_BYTE_RATE * payload_len as u64),
+        DEFAULT_PER_ROUTING_RATE + (DEFAULT_PER_ROUTING_BYTE_RATE * payload_len as u64),
     )
 }
 
@@ -258,12 +415,12 @@ fn calculate_response_routing_charge(bytes: usize, originating_cryptde: &CryptDE
     let payload_len = payload_enc.len();
     (
         payload_len,
-        TEMPORARY_PER_ROUTING_RATE + (TEMPORARY_PER_ROUTING_BYTE_RATE * payload_len as u64),
+        DEFAULT_PER_ROUTING_RATE + (DEFAULT_PER_ROUTING_BYTE_RATE * payload_len as u64),
     )
 }
 
 fn calculate_exit_charge(bytes: usize) -> u64 {
-    TEMPORARY_PER_EXIT_RATE + (TEMPORARY_PER_EXIT_BYTE_RATE * bytes as u64)
+    DEFAULT_PER_EXIT_RATE + (DEFAULT_PER_EXIT_BYTE_RATE * bytes as u64)
 }
 
 fn assert_timestamp_between(before: &SystemTime, timestamp: &SystemTime, after: &SystemTime) {
@@ -284,17 +441,22 @@ fn assert_timestamp_between(before: &SystemTime, timestamp: &SystemTime, after:
     );
 }
 
-fn make_request_payload(bytes: usize, cryptde: &CryptDE) -> ClientRequestPayload {
+fn make_request_payload(exchange: &Exchange, cryptde: &CryptDE) -> ClientRequestPayload {
     ClientRequestPayload {
         stream_key: StreamKey::new(
             cryptde.public_key(),
             SocketAddr::from_str("1.2.3.4:5678").unwrap(),
         ),
-        sequenced_packet: SequencedPacket::new(make_garbage_data(bytes), 0, true),
+        sequenced_packet: SequencedPacket::new(make_garbage_data(exchange.request_len), 0, true),
         target_hostname: Some("example.com".to_string()),
-        target_port: 80,
-        protocol: ProxyProtocol::HTTP,
+        target_port: exchange.target_port,
+        protocol: exchange.protocol,
         originator_public_key: cryptde.public_key(),
+        tls_at_exit: false,
+        upload_window: None,
+        udp: false,
+        dns_server: None,
+        cancel: false,
     }
 }
 
@@ -305,6 +467,10 @@ fn make_response_payload(bytes: usize, cryptde: &CryptDE) -> ClientResponsePaylo
             SocketAddr::from_str("1.2.3.4:5678").unwrap(),
         ),
         sequenced_packet: SequencedPacket::new(make_garbage_data(bytes), 0, true),
+        requests_received: None,
+        truncated: false,
+        udp: false,
+        exit_failed: false,
     }
 }
 
@@ -316,6 +482,36 @@ fn make_wallet_from(n: usize) -> Wallet {
     Wallet::new(address.as_str())
 }
 
+// Offers only a cipher suite no real server will accept, so that the whole response is one short
+// alert record, which reaches the exit Node in a single read.
+fn make_tls_client_hello(host_name: &str) -> Vec<u8> {
+    let name = host_name.as_bytes();
+    let mut extension = vec![0x00, 0x00]; // extension_type: server_name
+    extension.extend(u16_bytes(name.len() + 5)); // extension_length
+    extension.extend(u16_bytes(name.len() + 3)); // server_name_list_length
+    extension.push(0x00); // server_name_type: host_name
+    extension.extend(u16_bytes(name.len())); // server_name_length
+    extension.extend(name);
+    let mut body = vec![0x03, 0x03]; // client_version: TLS 1.2
+    body.extend(vec![0x00; 32]); // random: don't care
+    body.push(0x00); // session_id_length
+    body.extend(vec![0x00, 0x02, 0x00, 0x01]); // cipher_suites: TLS_RSA_WITH_NULL_MD5 only
+    body.extend(vec![0x01, 0x00]); // compression_methods: null only
+    body.extend(u16_bytes(extension.len())); // extensions_length
+    body.extend(extension);
+    let mut handshake = vec![0x01, 0x00]; // handshake_type: ClientHello, length high byte
+    handshake.extend(u16_bytes(body.len()));
+    handshake.extend(body);
+    let mut record = vec![0x16, 0x03, 0x01]; // content_type: Handshake, version: TLS 1.0
+    record.extend(u16_bytes(handshake.len()));
+    record.extend(handshake);
+    record
+}
+
+fn u16_bytes(value: usize) -> Vec<u8> {
+    vec![(value >> 8) as u8, (value & 0xFF) as u8]
+}
+
 fn make_garbage_data(bytes: usize) -> Vec<u8> {
     let mut data = Vec::with_capacity(bytes);
     for _ in 0..bytes {
@@ -0,0 +1,65 @@
+// Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+
+use multinode_integration_tests_lib::churn::assert_churn_survived;
+use multinode_integration_tests_lib::churn::browse;
+use multinode_integration_tests_lib::churn::run_churn;
+use multinode_integration_tests_lib::churn::ChurnSchedule;
+use multinode_integration_tests_lib::gossip_convergence::convergence_bound;
+use multinode_integration_tests_lib::gossip_convergence::measure_convergence;
+use multinode_integration_tests_lib::substratum_node::SubstratumNode;
+use multinode_integration_tests_lib::substratum_node_cluster::SubstratumNodeCluster;
+use multinode_integration_tests_lib::substratum_real_node::NodeStartupConfigBuilder;
+use multinode_integration_tests_lib::substratum_real_node::SubstratumRealNode;
+use node_lib::sub_lib::wallet::Wallet;
+use std::time::Duration;
+
+#[test]
+fn browsing_continues_and_books_survive_while_a_third_of_the_mesh_churns() {
+    let mut cluster = SubstratumNodeCluster::start().unwrap();
+    let bootstrap = cluster.start_real_node(
+        NodeStartupConfigBuilder::bootstrap()
+            .fault_injection()
+            .build(),
+    );
+    let start_standard = |cluster: &mut SubstratumNodeCluster, index: usize| {
+        cluster.start_real_node(
+            NodeStartupConfigBuilder::standard()
+                .neighbor(bootstrap.node_reference())
+                .earning_wallet(make_wallet_from(index))
+                .fault_injection()
+                .build(),
+        )
+    };
+    let originating_node = start_standard(&mut cluster, 0);
+    let churnable: Vec<SubstratumRealNode> = (1..7)
+        .map(|index| start_standard(&mut cluster, index))
+        .collect();
+    let mut everybody = vec![bootstrap.clone(), originating_node.clone()];
+    everybody.extend(churnable.iter().cloned());
+    measure_convergence(&everybody, convergence_bound());
+    let earning_wallets: Vec<Wallet> = (0..7).map(make_wallet_from).collect();
+    browse(&originating_node, "example.com").unwrap();
+
+    let rounds = run_churn(
+        &churnable,
+        &earning_wallets,
+        &ChurnSchedule {
+            percent: 33,
+            rounds: 3,
+            downtime: Duration::from_secs(20),
+            settle_time: Duration::from_secs(10),
+        },
+        || browse(&originating_node, "example.com"),
+    );
+
+    assert_churn_survived(&rounds, 50);
+    browse(&originating_node, "example.com").unwrap();
+}
+
+fn make_wallet_from(n: usize) -> Wallet {
+    let mut address = String::from("0x");
+    for _ in 0..40 {
+        address.push(((n + '0' as usize) as u8) as char);
+    }
+    Wallet::new(address.as_str())
+}
@@ -1,5 +1,6 @@
 // Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
 
+use multinode_integration_tests_lib::substratum_client::Exchange;
 use multinode_integration_tests_lib::substratum_node::NodeReference;
 use multinode_integration_tests_lib::substratum_node::PortSelector;
 use multinode_integration_tests_lib::substratum_node::SubstratumNode;
@@ -8,6 +9,7 @@ use multinode_integration_tests_lib::substratum_real_node::NodeStartupConfigBuil
 use node_lib::json_masquerader::JsonMasquerader;
 use node_lib::neighborhood::gossip::Gossip;
 use node_lib::neighborhood::gossip::GossipNodeRecord;
+use node_lib::neighborhood::neighborhood_database::LoadAdvertisement;
 use node_lib::neighborhood::neighborhood_database::NodeRecord;
 use node_lib::neighborhood::neighborhood_database::NodeRecordInner;
 use node_lib::neighborhood::neighborhood_database::NodeSignatures;
@@ -17,6 +19,7 @@ use node_lib::sub_lib::cryptde_null::CryptDENull;
 use node_lib::sub_lib::dispatcher::Component;
 use node_lib::sub_lib::hopper::IncipientCoresPackage;
 use node_lib::sub_lib::http_server_impersonator;
+use node_lib::sub_lib::neighborhood::RateCard;
 use node_lib::sub_lib::proxy_client::ClientResponsePayload;
 use node_lib::sub_lib::proxy_server::ClientRequestPayload;
 use node_lib::sub_lib::proxy_server::ProxyProtocol;
@@ -111,6 +114,11 @@ fn cores_package_to_http_request_and_http_response_to_cores_package_test() {
         target_port: 80,
         protocol: ProxyProtocol::HTTP,
         originator_public_key: ne1_noderef.public_key.clone(),
+        tls_at_exit: false,
+        upload_window: None,
+        udp: false,
+        dns_server: None,
+        cancel: false,
     };
     let route = Route::round_trip(
         RouteSegment::new(
@@ -312,6 +320,26 @@ fn multiple_stream_zero_hop_test() {
     );
 }
 
+#[test]
+fn tls_keep_alive_zero_hop_test() {
+    let mut cluster = SubstratumNodeCluster::start().unwrap();
+    let zero_hop_node = cluster.start_real_node(NodeStartupConfigBuilder::zero_hop().build());
+    let mut client = zero_hop_node.make_tls_client(443, "www.example.com");
+
+    let responses = client.run_session(vec![
+        Exchange::new(b"GET / HTTP/1.1\r\nHost: www.example.com\r\n\r\n")
+            .expecting(b"HTTP/1.1 200 OK\r\n")
+            .expecting(
+                b"This domain is established to be used for illustrative examples in documents.",
+            ),
+        Exchange::new(b"GET / HTTP/1.1\r\nHost: www.example.com\r\n\r\n")
+            .expecting(b"HTTP/1.1 200 OK\r\n")
+            .within(Duration::from_secs(10)),
+    ]);
+
+    assert_eq!(responses.len(), 2);
+}
+
 fn make_gossip(pairs: Vec<(&NodeReference, bool)>) -> Gossip {
     let node_ref_count = pairs.len() as usize;
     let mut gossip_node_records = pairs.into_iter().fold(vec![], |so_far, pair| {
@@ -328,6 +356,12 @@ fn make_gossip(pairs: Vec<(&NodeReference, bool)>) -> Gossip {
             consuming_wallet: Some(Wallet::new("consuming")),
             neighbors: vec![],
             version: 0,
+            load: LoadAdvertisement::default(),
+            offers_exit_tls: false,
+            relay_only: false,
+            rate_card: RateCard::default(),
+            country_opt: None,
+            exit_sla_opt: None,
         };
         let (complete_signature, obscured_signature) = {
             let mut nr = NodeRecord::new(
@@ -363,5 +397,10 @@ fn make_gossip(pairs: Vec<(&NodeReference, bool)>) -> Gossip {
     }
     Gossip {
         node_records: gossip_node_records,
+        observed_ip_opt: None,
+        ttl_opt: None,
+        sequence_opt: None,
+        ack_opt: None,
+        delta: false,
     }
 }
@@ -0,0 +1,32 @@
+// Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+
+use multinode_integration_tests_lib::gossip_convergence::convergence_bound;
+use multinode_integration_tests_lib::gossip_convergence::measure_convergence;
+use multinode_integration_tests_lib::gossip_convergence::start_topology;
+use multinode_integration_tests_lib::gossip_convergence::Topology;
+use multinode_integration_tests_lib::substratum_node_cluster::SubstratumNodeCluster;
+
+// These need a Node built with --features fault_injection, so that its Neighborhood database can be
+// read; see NodeStartupConfigBuilder::fault_injection()
+
+#[test]
+fn gossip_converges_along_a_line_of_nodes() {
+    let mut cluster = SubstratumNodeCluster::start().unwrap();
+    let nodes = start_topology(&mut cluster, Topology::Line, 6);
+    let bound = convergence_bound();
+
+    let elapsed = measure_convergence(&nodes, bound);
+
+    assert!(elapsed <= bound);
+}
+
+#[test]
+fn gossip_converges_around_a_star_of_nodes() {
+    let mut cluster = SubstratumNodeCluster::start().unwrap();
+    let nodes = start_topology(&mut cluster, Topology::Star, 6);
+    let bound = convergence_bound();
+
+    let elapsed = measure_convergence(&nodes, bound);
+
+    assert!(elapsed <= bound);
+}
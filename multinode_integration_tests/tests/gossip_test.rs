@@ -1,16 +1,22 @@
 // Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
 
+use multinode_integration_tests_lib::gossip_builder::GossipBuilder;
+use multinode_integration_tests_lib::gossip_builder::GossipProtocolVersion;
 use multinode_integration_tests_lib::substratum_cores_server::SubstratumCoresServer;
+use multinode_integration_tests_lib::substratum_node::PortSelector;
 use multinode_integration_tests_lib::substratum_node::SubstratumNode;
 use multinode_integration_tests_lib::substratum_node_cluster::SubstratumNodeCluster;
 use multinode_integration_tests_lib::substratum_real_node::NodeStartupConfigBuilder;
+use node_lib::json_masquerader::JsonMasquerader;
 use node_lib::neighborhood::gossip::Gossip;
 use node_lib::neighborhood::gossip::GossipNodeRecord;
+use node_lib::neighborhood::neighborhood_database::LoadAdvertisement;
 use node_lib::neighborhood::neighborhood_database::NodeRecord;
 use node_lib::neighborhood::neighborhood_database::NodeRecordInner;
 use node_lib::neighborhood::neighborhood_database::NodeSignatures;
 use node_lib::sub_lib::accountant;
 use node_lib::sub_lib::cryptde_null::CryptDENull;
+use node_lib::sub_lib::neighborhood::RateCard;
 use node_lib::test_utils::test_utils::assert_contains;
 use std::net::IpAddr;
 use std::str::FromStr;
@@ -43,6 +49,12 @@ fn when_bootstrapping_from_a_node_then_the_node_sends_gossip_upon_startup() {
         consuming_wallet: Some(accountant::TEMPORARY_CONSUMING_WALLET.clone()),
         neighbors: vec![bootstrap_node_ref.public_key.clone()],
         version: 0,
+        load: LoadAdvertisement::default(),
+        offers_exit_tls: false,
+        relay_only: false,
+        rate_card: RateCard::default(),
+        country_opt: None,
+        exit_sla_opt: None,
     };
     let (complete_signature, obscured_signature) = {
         let mut nr = NodeRecord::new(
@@ -103,3 +115,49 @@ fn when_bootstrapping_from_a_standard_node_then_the_gossip_reveals_that_it_is_st
 
     assert!(!neighbor_record.inner.is_bootstrap_node);
 }
+
+#[test]
+fn standard_node_accepts_gossip_from_nodes_that_speak_the_oldest_protocol_version() {
+    let mut cluster = SubstratumNodeCluster::start().unwrap();
+    let mock_bootstrap = cluster.start_mock_node(vec![5550]);
+    let mock_standard = cluster.start_mock_node(vec![5551]);
+    let subject = cluster.start_real_node(
+        NodeStartupConfigBuilder::standard()
+            .neighbor(mock_bootstrap.node_reference())
+            .build(),
+    );
+
+    // This gossip is from the real node (subject) bootstrapping
+    mock_bootstrap
+        .wait_for_gossip(Duration::from_millis(1000))
+        .unwrap();
+
+    let cores_package = GossipBuilder::new(None)
+        .add_node(&mock_bootstrap, true, true)
+        .add_node(&subject, false, true)
+        .add_node(&mock_standard, false, true)
+        .add_connection(&mock_bootstrap.public_key(), &subject.public_key())
+        .add_connection(&mock_bootstrap.public_key(), &mock_standard.public_key())
+        .add_connection(&mock_standard.public_key(), &mock_bootstrap.public_key())
+        .protocol_version(GossipProtocolVersion::V0)
+        .build_cores_package(&mock_bootstrap.public_key(), &subject.public_key());
+    mock_bootstrap
+        .transmit_package(
+            5550,
+            cores_package,
+            &JsonMasquerader::new(),
+            &subject.public_key(),
+            subject.socket_addr(PortSelector::First),
+        )
+        .unwrap();
+
+    // If the subject understood the old Gossip, it has learned of the mock standard Node from it
+    // and introduces itself
+    let gossip = mock_standard
+        .wait_for_gossip(Duration::from_millis(1000))
+        .expect("the subject did not accept V0 Gossip");
+    assert!(gossip
+        .node_records
+        .iter()
+        .any(|record| record.public_key() == subject.public_key()));
+}
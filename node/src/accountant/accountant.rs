@@ -1,29 +1,61 @@
 // Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use super::config_dao::ConfigDao;
 use super::db_initializer::DbInitializer;
 use super::db_initializer::DbInitializerReal;
+use super::journal_dao::JournalDao;
+use super::maintenance_dao::MaintenanceDao;
+use super::maintenance_schedule::MaintenanceSchedule;
+use super::maintenance_schedule::MAINTENANCE_CHECK_INTERVAL;
 use super::payable_dao::PayableDao;
 use super::receivable_dao::ReceivableDao;
 use crate::sub_lib::accountant::AccountantConfig;
 use crate::sub_lib::accountant::AccountantSubs;
+use crate::sub_lib::accountant::Balances;
+use crate::sub_lib::accountant::BalancesQueryMessage;
+use crate::sub_lib::accountant::ConfigChangedMessage;
+use crate::sub_lib::accountant::CorrelationId;
+use crate::sub_lib::accountant::ReceivableAgingQueryMessage;
+use crate::sub_lib::accountant::ReceivableAgingReport;
 use crate::sub_lib::accountant::ReportExitServiceConsumedMessage;
 use crate::sub_lib::accountant::ReportExitServiceProvidedMessage;
 use crate::sub_lib::accountant::ReportRoutingServiceConsumedMessage;
 use crate::sub_lib::accountant::ReportRoutingServiceProvidedMessage;
+use crate::sub_lib::accountant::SubscribeToConfigChangesMessage;
+use crate::sub_lib::hopper::PaymentStandingMessage;
 use crate::sub_lib::logger::Logger;
 use crate::sub_lib::peer_actors::BindMessage;
+use crate::sub_lib::peer_actors::ShutdownMessage;
+use crate::sub_lib::ui_gateway::UiAlert;
+use crate::sub_lib::ui_gateway::UiMessage;
 use crate::sub_lib::wallet::Wallet;
 use actix::Actor;
 use actix::Addr;
+use actix::AsyncContext;
 use actix::Context;
 use actix::Handler;
+use actix::MessageResult;
+use actix::Recipient;
 use actix::Syn;
 use std::fs;
+use std::time::Instant;
 
 pub struct Accountant {
     config: AccountantConfig,
     db_initializer: Box<DbInitializer>,
     payable_dao: Option<Box<PayableDao>>,
     receivable_dao: Option<Box<ReceivableDao>>,
+    config_dao: Option<Box<ConfigDao>>,
+    maintenance_dao: Option<Box<MaintenanceDao>>,
+    journal_dao: Option<Box<JournalDao>>,
+    maintenance_schedule: MaintenanceSchedule,
+    // Once the database is found to be corrupt, nothing more is written to it: charges go
+    // unrecorded, so routing and exit service are free until the operator repairs it
+    degraded: bool,
+    // Once the Node is going down, nothing more is written either
+    closed: bool,
+    pending_config_subscribers: Vec<Recipient<Syn, ConfigChangedMessage>>,
+    ui_message_sub: Option<Recipient<Syn, UiMessage>>,
+    payment_standing_sub: Option<Recipient<Syn, PaymentStandingMessage>>,
     logger: Logger,
 }
 
@@ -34,8 +66,12 @@ impl Actor for Accountant {
 impl Handler<BindMessage> for Accountant {
     type Result = ();
 
-    fn handle(&mut self, _msg: BindMessage, _ctx: &mut Self::Context) -> Self::Result {
+    fn handle(&mut self, msg: BindMessage, ctx: &mut Self::Context) -> Self::Result {
+        self.ui_message_sub = Some(msg.peer_actors.ui_gateway.ui_message_sub.clone());
+        self.payment_standing_sub = Some(msg.peer_actors.hopper.payment_standing.clone());
         self.establish_data_directory();
+        self.report_payment_standing();
+        self.schedule_maintenance_check(ctx);
         self.logger.info(String::from("Accountant bound"));
         ()
     }
@@ -58,6 +94,7 @@ impl Handler<ReportRoutingServiceProvidedMessage> for Accountant {
             msg.byte_rate,
             msg.payload_size,
             &msg.consuming_wallet,
+            msg.correlation_id_opt.as_ref(),
         );
         ()
     }
@@ -80,6 +117,7 @@ impl Handler<ReportExitServiceProvidedMessage> for Accountant {
             msg.byte_rate,
             msg.payload_size,
             &msg.consuming_wallet,
+            msg.correlation_id_opt.as_ref(),
         );
         ()
     }
@@ -102,6 +140,7 @@ impl Handler<ReportRoutingServiceConsumedMessage> for Accountant {
             msg.byte_rate,
             msg.payload_size,
             &msg.earning_wallet,
+            msg.correlation_id_opt.as_ref(),
         );
         ()
     }
@@ -124,11 +163,92 @@ impl Handler<ReportExitServiceConsumedMessage> for Accountant {
             msg.byte_rate,
             msg.payload_size,
             &msg.earning_wallet,
+            msg.correlation_id_opt.as_ref(),
         );
         ()
     }
 }
 
+// Reports are handled in the order they arrive, so every one sent before this has been written by
+// now. The database is closed rather than left for the process to drop mid-write.
+impl Handler<ShutdownMessage> for Accountant {
+    type Result = ();
+
+    fn handle(&mut self, _msg: ShutdownMessage, _ctx: &mut Self::Context) -> Self::Result {
+        self.closed = true;
+        self.payable_dao = None;
+        self.receivable_dao = None;
+        self.config_dao = None;
+        self.maintenance_dao = None;
+        self.journal_dao = None;
+        self.logger.info(String::from("Books closed for shutdown"));
+        ()
+    }
+}
+
+// Subscriptions can arrive before the database is open; they're held until it is
+impl Handler<SubscribeToConfigChangesMessage> for Accountant {
+    type Result = ();
+
+    fn handle(
+        &mut self,
+        msg: SubscribeToConfigChangesMessage,
+        _ctx: &mut Self::Context,
+    ) -> Self::Result {
+        match self.config_dao.as_mut() {
+            Some(config_dao) => config_dao.subscribe(msg.subscriber),
+            None => self.pending_config_subscribers.push(msg.subscriber),
+        }
+        ()
+    }
+}
+
+impl Handler<ReceivableAgingQueryMessage> for Accountant {
+    type Result = MessageResult<ReceivableAgingQueryMessage>;
+
+    fn handle(
+        &mut self,
+        _msg: ReceivableAgingQueryMessage,
+        _ctx: &mut Self::Context,
+    ) -> <Self as Handler<ReceivableAgingQueryMessage>>::Result {
+        if self.degraded || self.closed {
+            return MessageResult(ReceivableAgingReport::default());
+        }
+        MessageResult(
+            self.receivable_dao
+                .as_ref()
+                .expect("Accountant not bound")
+                .receivable_aging(),
+        )
+    }
+}
+
+impl Handler<BalancesQueryMessage> for Accountant {
+    type Result = MessageResult<BalancesQueryMessage>;
+
+    fn handle(
+        &mut self,
+        _msg: BalancesQueryMessage,
+        _ctx: &mut Self::Context,
+    ) -> <Self as Handler<BalancesQueryMessage>>::Result {
+        if self.degraded || self.closed {
+            return MessageResult(Balances::default());
+        }
+        MessageResult(Balances {
+            receivable: self
+                .receivable_dao
+                .as_ref()
+                .expect("Accountant not bound")
+                .total_owed(),
+            payable: self
+                .payable_dao
+                .as_ref()
+                .expect("Accountant not bound")
+                .total_owed(),
+        })
+    }
+}
+
 impl Accountant {
     pub fn new(config: AccountantConfig) -> Accountant {
         Accountant {
@@ -136,6 +256,15 @@ impl Accountant {
             db_initializer: Box::new(DbInitializerReal::new()),
             payable_dao: None,
             receivable_dao: None,
+            config_dao: None,
+            maintenance_dao: None,
+            journal_dao: None,
+            maintenance_schedule: MaintenanceSchedule::new(Instant::now()),
+            degraded: false,
+            closed: false,
+            pending_config_subscribers: vec![],
+            ui_message_sub: None,
+            payment_standing_sub: None,
             logger: Logger::new("Accountant"),
         }
     }
@@ -155,6 +284,12 @@ impl Accountant {
             report_exit_service_consumed: addr
                 .clone()
                 .recipient::<ReportExitServiceConsumedMessage>(),
+            subscribe_to_config_changes: addr
+                .clone()
+                .recipient::<SubscribeToConfigChangesMessage>(),
+            receivable_aging_query: addr.clone().recipient::<ReceivableAgingQueryMessage>(),
+            balances_query: addr.clone().recipient::<BalancesQueryMessage>(),
+            shutdown: addr.clone().recipient::<ShutdownMessage>(),
         }
     }
 
@@ -166,6 +301,13 @@ impl Accountant {
             .expect("Could not initialize database");
         self.payable_dao = Some(daos.payable);
         self.receivable_dao = Some(daos.receivable);
+        let mut config_dao = daos.config;
+        self.pending_config_subscribers
+            .drain(..)
+            .for_each(|subscriber| config_dao.subscribe(subscriber));
+        self.config_dao = Some(config_dao);
+        self.maintenance_dao = Some(daos.maintenance);
+        self.journal_dao = Some(daos.journal);
     }
 
     fn create_data_directory_if_necessary(&self) {
@@ -182,33 +324,134 @@ impl Accountant {
     }
 
     fn record_service_provided(
-        &self,
+        &mut self,
         service_rate: u64,
         byte_rate: u64,
         payload_size: usize,
         wallet: &Wallet,
+        correlation_id_opt: Option<&CorrelationId>,
     ) {
+        if self.degraded || self.closed {
+            return;
+        }
+        self.maintenance_schedule.activity(Instant::now());
         let byte_charge = byte_rate * (payload_size as u64);
         let total_charge = service_rate + byte_charge;
-        self.receivable_dao
-            .as_ref()
-            .expect("Accountant not bound")
-            .more_money_receivable(wallet, total_charge);
+        let receivable_dao = self.receivable_dao.as_ref().expect("Accountant not bound");
+        let result = receivable_dao
+            .more_money_receivable(wallet, total_charge)
+            .and_then(|_| match correlation_id_opt {
+                Some(correlation_id) => receivable_dao.more_money_receivable_for_stream(
+                    wallet,
+                    correlation_id,
+                    total_charge,
+                ),
+                None => Ok(()),
+            });
+        if let Err(e) = result {
+            self.degrade(e);
+        }
     }
 
     fn record_service_consumed(
-        &self,
+        &mut self,
         service_rate: u64,
         byte_rate: u64,
         payload_size: usize,
         wallet: &Wallet,
+        correlation_id_opt: Option<&CorrelationId>,
     ) {
+        if self.degraded || self.closed {
+            return;
+        }
+        self.maintenance_schedule.activity(Instant::now());
         let byte_charge = byte_rate * (payload_size as u64);
         let total_charge = service_rate + byte_charge;
-        self.payable_dao
+        let payable_dao = self.payable_dao.as_ref().expect("Accountant not bound");
+        let result = payable_dao
+            .more_money_payable(wallet, total_charge)
+            .and_then(|_| match correlation_id_opt {
+                Some(correlation_id) => {
+                    payable_dao.more_money_payable_for_stream(wallet, correlation_id, total_charge)
+                }
+                None => Ok(()),
+            });
+        if let Err(e) = result {
+            self.degrade(e);
+        }
+    }
+
+    fn schedule_maintenance_check(&self, ctx: &mut Context<Self>) {
+        ctx.run_later(MAINTENANCE_CHECK_INTERVAL, |act, ctx| {
+            act.maintain_database_if_due(Instant::now());
+            act.report_payment_standing();
+            act.schedule_maintenance_check(ctx);
+        });
+    }
+
+    // A database that fails its integrity check isn't checkpointed or compacted: a checkpoint would
+    // add up damaged entries, and VACUUM would only copy the damage
+    fn maintain_database_if_due(&mut self, now: Instant) {
+        if self.degraded || self.closed || !self.maintenance_schedule.due(now) {
+            return;
+        }
+        self.maintenance_schedule.done(now);
+        let maintenance_dao = self.maintenance_dao.as_ref().expect("Accountant not bound");
+        let journal_dao = self.journal_dao.as_ref().expect("Accountant not bound");
+        match maintenance_dao
+            .check_integrity()
+            .and_then(|_| journal_dao.checkpoint())
+            .map(|_| maintenance_dao.vacuum())
+        {
+            Ok(Ok(())) => {
+                self.logger.info(String::from(
+                    "Database passed its integrity check and was compacted",
+                ));
+                self.alert(UiAlert::new("database_checked"));
+            }
+            Ok(Err(e)) => {
+                self.logger.warning(format!(
+                    "Database passed its integrity check, but couldn't be compacted: {}",
+                    e
+                ));
+                self.alert(UiAlert::new("database_not_compacted").with("error", &e));
+            }
+            Err(e) => self.degrade(e),
+        }
+    }
+
+    // Lets the Hopper give consumers who are behind on paying a smaller share of a busy relay
+    fn report_payment_standing(&self) {
+        if self.degraded || self.closed {
+            return;
+        }
+        let delinquent_wallets = self
+            .receivable_dao
             .as_ref()
             .expect("Accountant not bound")
-            .more_money_payable(wallet, total_charge);
+            .delinquent_wallets();
+        if let Some(payment_standing_sub) = &self.payment_standing_sub {
+            payment_standing_sub
+                .try_send(PaymentStandingMessage { delinquent_wallets })
+                .expect("Hopper is dead");
+        }
+    }
+
+    fn degrade(&mut self, error: String) {
+        self.degraded = true;
+        self.logger.error(format!(
+            "Database is corrupt: {}; no longer recording charges, so service is free until it's repaired",
+            error
+        ));
+        self.alert(UiAlert::new("database_corrupt").with("error", &error));
+    }
+
+    fn alert(&self, alert: UiAlert) {
+        if let Some(ui_message_sub) = &self.ui_message_sub {
+            ui_message_sub
+                .try_send(UiMessage::AlertMessage(alert))
+                .expect("UiGateway is dead");
+        }
     }
 }
 
@@ -217,169 +460,35 @@ pub mod tests {
     use super::super::db_initializer::Daos;
     use super::super::db_initializer::InitializationError;
     use super::super::local_test_utils::BASE_TEST_DIR;
-    use super::super::payable_dao::PayableAccount;
-    use super::super::receivable_dao;
+    use super::super::maintenance_schedule::QUIET_PERIOD;
     use super::*;
+    use crate::sub_lib::accountant::AgingBucket;
     use crate::sub_lib::accountant::ReportRoutingServiceConsumedMessage;
     use crate::sub_lib::wallet::Wallet;
+    use crate::test_utils::accountant_mocks::BannedNodeDaoMock;
+    use crate::test_utils::accountant_mocks::ConfigDaoMock;
+    use crate::test_utils::accountant_mocks::DbInitializerMock;
+    use crate::test_utils::accountant_mocks::JournalDaoMock;
+    use crate::test_utils::accountant_mocks::MaintenanceDaoMock;
+    use crate::test_utils::accountant_mocks::NodeRecordDaoMock;
+    use crate::test_utils::accountant_mocks::PayableDaoMock;
+    use crate::test_utils::accountant_mocks::ReceivableDaoMock;
     use crate::test_utils::logging::init_test_logging;
     use crate::test_utils::logging::TestLogHandler;
+    use crate::test_utils::recorder::make_recorder;
     use crate::test_utils::recorder::peer_actors_builder;
+    use crate::test_utils::recorder::Recorder;
+    use crate::test_utils::test_utils::make_meaningless_stream_key;
     use actix::msgs;
     use actix::Arbiter;
     use actix::System;
-    use std::cell::RefCell;
+    use futures::future::Future;
     use std::fs::File;
     use std::io::Read;
     use std::io::Write;
     use std::path::PathBuf;
     use std::sync::Arc;
     use std::sync::Mutex;
-    use std::time::SystemTime;
-
-    struct DbInitializerMock {
-        initialize_parameters: Arc<Mutex<Vec<PathBuf>>>,
-        initialize_results: RefCell<Vec<Result<Daos, InitializationError>>>,
-    }
-
-    impl DbInitializer for DbInitializerMock {
-        fn initialize(&self, path: &PathBuf) -> Result<Daos, InitializationError> {
-            self.initialize_parameters
-                .lock()
-                .unwrap()
-                .push(path.clone());
-            self.initialize_results.borrow_mut().remove(0)
-        }
-    }
-
-    impl DbInitializerMock {
-        fn new() -> DbInitializerMock {
-            DbInitializerMock {
-                initialize_parameters: Arc::new(Mutex::new(vec![])),
-                initialize_results: RefCell::new(vec![]),
-            }
-        }
-
-        fn initialize_parameters(
-            mut self,
-            parameters: Arc<Mutex<Vec<PathBuf>>>,
-        ) -> DbInitializerMock {
-            self.initialize_parameters = parameters;
-            self
-        }
-
-        fn initialize_result(self, result: Result<Daos, InitializationError>) -> DbInitializerMock {
-            self.initialize_results.borrow_mut().push(result);
-            self
-        }
-    }
-
-    #[derive(Debug)]
-    struct PayableDaoMock {
-        more_money_payable_parameters: Arc<Mutex<Vec<(Wallet, u64)>>>,
-    }
-
-    impl PayableDao for PayableDaoMock {
-        fn more_money_payable(&self, wallet_address: &Wallet, amount: u64) {
-            self.more_money_payable_parameters
-                .lock()
-                .unwrap()
-                .push((wallet_address.clone(), amount));
-        }
-
-        fn payment_sent(&self, _wallet_address: &Wallet, _pending_payment_transaction: &str) {
-            unimplemented!()
-        }
-
-        fn payment_confirmed(
-            &self,
-            _wallet_address: &Wallet,
-            _amount: u64,
-            _confirmation_noticed_timestamp: &SystemTime,
-        ) {
-            unimplemented!()
-        }
-
-        fn account_status(&self, _wallet_address: &Wallet) -> Option<PayableAccount> {
-            unimplemented!()
-        }
-    }
-
-    impl PayableDaoMock {
-        fn new() -> PayableDaoMock {
-            PayableDaoMock {
-                more_money_payable_parameters: Arc::new(Mutex::new(vec![])),
-            }
-        }
-
-        fn more_money_payable_parameters(
-            mut self,
-            parameters: Arc<Mutex<Vec<(Wallet, u64)>>>,
-        ) -> Self {
-            self.more_money_payable_parameters = parameters;
-            self
-        }
-    }
-
-    #[derive(Debug)]
-    struct ReceivableDaoMock {
-        more_money_receivable_parameters: Arc<Mutex<Vec<(Wallet, u64)>>>,
-        more_money_received_parameters: Arc<Mutex<Vec<(Wallet, u64, SystemTime)>>>,
-    }
-
-    impl ReceivableDao for ReceivableDaoMock {
-        fn more_money_receivable(&self, wallet_address: &Wallet, amount: u64) {
-            self.more_money_receivable_parameters
-                .lock()
-                .unwrap()
-                .push((wallet_address.clone(), amount));
-        }
-
-        fn more_money_received(
-            &self,
-            wallet_address: &Wallet,
-            amount: u64,
-            timestamp: &SystemTime,
-        ) {
-            self.more_money_received_parameters.lock().unwrap().push((
-                wallet_address.clone(),
-                amount,
-                timestamp.clone(),
-            ));
-        }
-
-        fn account_status(
-            &self,
-            _wallet_address: &Wallet,
-        ) -> Option<receivable_dao::ReceivableAccount> {
-            unimplemented!()
-        }
-    }
-
-    impl ReceivableDaoMock {
-        fn new() -> ReceivableDaoMock {
-            ReceivableDaoMock {
-                more_money_receivable_parameters: Arc::new(Mutex::new(vec![])),
-                more_money_received_parameters: Arc::new(Mutex::new(vec![])),
-            }
-        }
-
-        fn more_money_receivable_parameters(
-            mut self,
-            parameters: Arc<Mutex<Vec<(Wallet, u64)>>>,
-        ) -> Self {
-            self.more_money_receivable_parameters = parameters;
-            self
-        }
-
-        fn _more_money_received_parameters(
-            mut self,
-            parameters: Arc<Mutex<Vec<(Wallet, u64, SystemTime)>>>,
-        ) -> Self {
-            self.more_money_received_parameters = parameters;
-            self
-        }
-    }
 
     #[test]
     fn report_routing_service_provided_message_is_received() {
@@ -393,12 +502,21 @@ pub mod tests {
         };
         let dbi_initialize_parameters_arc = Arc::new(Mutex::new(vec![]));
         let more_money_receivable_parameters_arc = Arc::new(Mutex::new(vec![]));
+        let more_money_receivable_for_stream_parameters_arc = Arc::new(Mutex::new(vec![]));
         let daos = Daos {
             payable: Box::new(PayableDaoMock::new()),
             receivable: Box::new(
                 ReceivableDaoMock::new()
-                    .more_money_receivable_parameters(more_money_receivable_parameters_arc.clone()),
+                    .more_money_receivable_parameters(more_money_receivable_parameters_arc.clone())
+                    .more_money_receivable_for_stream_parameters(
+                        more_money_receivable_for_stream_parameters_arc.clone(),
+                    ),
             ),
+            config: Box::new(ConfigDaoMock::new()),
+            node_record: Box::new(NodeRecordDaoMock::new()),
+            banned_node: Box::new(BannedNodeDaoMock::new()),
+            maintenance: Box::new(MaintenanceDaoMock::new()),
+            journal: Box::new(JournalDaoMock::new()),
         };
         let db_initializer = DbInitializerMock::new()
             .initialize_parameters(dbi_initialize_parameters_arc.clone())
@@ -419,6 +537,7 @@ pub mod tests {
                 payload_size: 1234,
                 service_rate: 42,
                 byte_rate: 24,
+                correlation_id_opt: None,
             })
             .unwrap();
 
@@ -431,11 +550,259 @@ pub mod tests {
             more_money_receivable_parameters[0],
             (Wallet::new("booga"), (1 * 42) + (1234 * 24))
         );
+        assert_eq!(
+            more_money_receivable_for_stream_parameters_arc
+                .lock()
+                .unwrap()
+                .len(),
+            0
+        );
         TestLogHandler::new().exists_log_containing(
             "DEBUG: Accountant: Charging routing of 1234 bytes to wallet booga",
         );
     }
 
+    #[test]
+    fn receivable_aging_query_is_answered_by_the_receivable_dao() {
+        let config = AccountantConfig {
+            data_directory: PathBuf::from(format!(
+                "{}/receivable_aging_query_is_answered_by_the_receivable_dao/home",
+                BASE_TEST_DIR
+            )),
+        };
+        let report = ReceivableAgingReport {
+            under_30_days: AgingBucket {
+                accounts: 1,
+                balance: 100,
+            },
+            over_60_days: AgingBucket {
+                accounts: 2,
+                balance: 2,
+            },
+            ..ReceivableAgingReport::default()
+        };
+        let daos = Daos {
+            payable: Box::new(PayableDaoMock::new()),
+            receivable: Box::new(ReceivableDaoMock::new().receivable_aging_result(report.clone())),
+            config: Box::new(ConfigDaoMock::new()),
+            node_record: Box::new(NodeRecordDaoMock::new()),
+            banned_node: Box::new(BannedNodeDaoMock::new()),
+            maintenance: Box::new(MaintenanceDaoMock::new()),
+            journal: Box::new(JournalDaoMock::new()),
+        };
+        let mut subject = Accountant::new(config);
+        subject.db_initializer = Box::new(DbInitializerMock::new().initialize_result(Ok(daos)));
+        let system = System::new("receivable_aging_query_is_answered_by_the_receivable_dao");
+        let subject_addr: Addr<Syn, Accountant> = subject.start();
+        subject_addr
+            .try_send(BindMessage {
+                peer_actors: peer_actors_builder().build(),
+            })
+            .unwrap();
+
+        let future = subject_addr.send(ReceivableAgingQueryMessage {});
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+        assert_eq!(future.wait().unwrap(), report);
+    }
+
+    #[test]
+    fn balances_query_is_answered_with_what_each_dao_says_is_owed() {
+        let config = AccountantConfig {
+            data_directory: PathBuf::from(format!(
+                "{}/balances_query_is_answered_with_what_each_dao_says_is_owed/home",
+                BASE_TEST_DIR
+            )),
+        };
+        let daos = Daos {
+            payable: Box::new(PayableDaoMock::new().total_owed_result(321)),
+            receivable: Box::new(ReceivableDaoMock::new().total_owed_result(1234)),
+            config: Box::new(ConfigDaoMock::new()),
+            node_record: Box::new(NodeRecordDaoMock::new()),
+            banned_node: Box::new(BannedNodeDaoMock::new()),
+            maintenance: Box::new(MaintenanceDaoMock::new()),
+            journal: Box::new(JournalDaoMock::new()),
+        };
+        let mut subject = Accountant::new(config);
+        subject.db_initializer = Box::new(DbInitializerMock::new().initialize_result(Ok(daos)));
+        let system = System::new("balances_query_is_answered_with_what_each_dao_says_is_owed");
+        let subject_addr: Addr<Syn, Accountant> = subject.start();
+        subject_addr
+            .try_send(BindMessage {
+                peer_actors: peer_actors_builder().build(),
+            })
+            .unwrap();
+
+        let future = subject_addr.send(BalancesQueryMessage {});
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+        assert_eq!(
+            future.wait().unwrap(),
+            Balances {
+                receivable: 1234,
+                payable: 321,
+            }
+        );
+    }
+
+    #[test]
+    fn a_write_the_database_refuses_stops_the_accountant_recording_charges() {
+        init_test_logging();
+        let config = AccountantConfig {
+            data_directory: PathBuf::from(format!(
+                "{}/a_write_the_database_refuses_stops_the_accountant_recording_charges/home",
+                BASE_TEST_DIR
+            )),
+        };
+        let more_money_receivable_parameters_arc = Arc::new(Mutex::new(vec![]));
+        let daos = Daos {
+            payable: Box::new(PayableDaoMock::new()),
+            receivable: Box::new(
+                ReceivableDaoMock::new()
+                    .more_money_receivable_parameters(more_money_receivable_parameters_arc.clone())
+                    .more_money_receivable_result(Err(String::from("disk I/O error"))),
+            ),
+            config: Box::new(ConfigDaoMock::new()),
+            node_record: Box::new(NodeRecordDaoMock::new()),
+            banned_node: Box::new(BannedNodeDaoMock::new()),
+            maintenance: Box::new(MaintenanceDaoMock::new()),
+            journal: Box::new(JournalDaoMock::new()),
+        };
+        let mut subject = Accountant::new(config);
+        subject.db_initializer = Box::new(DbInitializerMock::new().initialize_result(Ok(daos)));
+        let (ui_gateway, _, ui_gateway_recording_arc) = make_recorder();
+        let system =
+            System::new("a_write_the_database_refuses_stops_the_accountant_recording_charges");
+        let subject_addr: Addr<Syn, Accountant> = subject.start();
+        subject_addr
+            .try_send(BindMessage {
+                peer_actors: peer_actors_builder().ui_gateway(ui_gateway).build(),
+            })
+            .unwrap();
+        let message = ReportRoutingServiceProvidedMessage {
+            consuming_wallet: Wallet::new("booga"),
+            payload_size: 1234,
+            service_rate: 42,
+            byte_rate: 24,
+            correlation_id_opt: None,
+        };
+
+        subject_addr.try_send(message.clone()).unwrap();
+        subject_addr.try_send(message).unwrap();
+        let future = subject_addr.send(ReceivableAgingQueryMessage {});
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+        assert_eq!(future.wait().unwrap(), ReceivableAgingReport::default());
+        let more_money_receivable_parameters = more_money_receivable_parameters_arc.lock().unwrap();
+        assert_eq!(more_money_receivable_parameters.len(), 1);
+        let ui_gateway_recording = ui_gateway_recording_arc.lock().unwrap();
+        assert_eq!(
+            ui_gateway_recording.get_record::<UiMessage>(0),
+            &UiMessage::AlertMessage(
+                UiAlert::new("database_corrupt").with("error", "disk I/O error")
+            )
+        );
+        assert_eq!(ui_gateway_recording.len(), 1);
+        TestLogHandler::new().exists_log_containing(
+            "ERROR: Accountant: Database is corrupt: disk I/O error; no longer recording charges, so service is free until it's repaired",
+        );
+    }
+
+    #[test]
+    fn a_quiet_accountant_checks_checkpoints_and_compacts_its_database() {
+        init_test_logging();
+        let vacuum_parameters_arc = Arc::new(Mutex::new(vec![]));
+        let checkpoint_parameters_arc = Arc::new(Mutex::new(vec![]));
+        let mut subject = Accountant::new(AccountantConfig {
+            data_directory: PathBuf::new(),
+        });
+        subject.maintenance_dao = Some(Box::new(
+            MaintenanceDaoMock::new()
+                .check_integrity_result(Ok(()))
+                .vacuum_parameters(vacuum_parameters_arc.clone())
+                .vacuum_result(Ok(())),
+        ));
+        subject.journal_dao = Some(Box::new(
+            JournalDaoMock::new()
+                .checkpoint_parameters(checkpoint_parameters_arc.clone())
+                .checkpoint_result(Ok(())),
+        ));
+        let now = Instant::now() + QUIET_PERIOD;
+
+        subject.maintain_database_if_due(now);
+        subject.maintain_database_if_due(now + MAINTENANCE_CHECK_INTERVAL);
+
+        assert_eq!(vacuum_parameters_arc.lock().unwrap().len(), 1);
+        assert_eq!(checkpoint_parameters_arc.lock().unwrap().len(), 1);
+        assert_eq!(subject.degraded, false);
+        TestLogHandler::new().exists_log_containing(
+            "INFO: Accountant: Database passed its integrity check and was compacted",
+        );
+    }
+
+    #[test]
+    fn a_database_that_fails_its_integrity_check_is_left_alone() {
+        init_test_logging();
+        let vacuum_parameters_arc = Arc::new(Mutex::new(vec![]));
+        let checkpoint_parameters_arc = Arc::new(Mutex::new(vec![]));
+        let more_money_payable_parameters_arc = Arc::new(Mutex::new(vec![]));
+        let mut subject = Accountant::new(AccountantConfig {
+            data_directory: PathBuf::new(),
+        });
+        subject.maintenance_dao = Some(Box::new(
+            MaintenanceDaoMock::new()
+                .check_integrity_result(Err(String::from("row 7 missing from index")))
+                .vacuum_parameters(vacuum_parameters_arc.clone()),
+        ));
+        subject.journal_dao = Some(Box::new(
+            JournalDaoMock::new().checkpoint_parameters(checkpoint_parameters_arc.clone()),
+        ));
+        subject.payable_dao = Some(Box::new(
+            PayableDaoMock::new()
+                .more_money_payable_parameters(more_money_payable_parameters_arc.clone()),
+        ));
+
+        subject.maintain_database_if_due(Instant::now() + QUIET_PERIOD);
+        subject.record_service_consumed(42, 24, 1234, &Wallet::new("booga"), None);
+
+        assert_eq!(subject.degraded, true);
+        assert_eq!(vacuum_parameters_arc.lock().unwrap().len(), 0);
+        assert_eq!(checkpoint_parameters_arc.lock().unwrap().len(), 0);
+        assert_eq!(more_money_payable_parameters_arc.lock().unwrap().len(), 0);
+        TestLogHandler::new().exists_log_containing(
+            "ERROR: Accountant: Database is corrupt: row 7 missing from index; no longer recording charges",
+        );
+    }
+
+    #[test]
+    fn a_journal_that_cannot_be_checkpointed_degrades_the_accountant() {
+        init_test_logging();
+        let vacuum_parameters_arc = Arc::new(Mutex::new(vec![]));
+        let mut subject = Accountant::new(AccountantConfig {
+            data_directory: PathBuf::new(),
+        });
+        subject.maintenance_dao = Some(Box::new(
+            MaintenanceDaoMock::new()
+                .check_integrity_result(Ok(()))
+                .vacuum_parameters(vacuum_parameters_arc.clone()),
+        ));
+        subject.journal_dao = Some(Box::new(
+            JournalDaoMock::new()
+                .checkpoint_result(Err(String::from("no such table: journal_checkpoint"))),
+        ));
+
+        subject.maintain_database_if_due(Instant::now() + QUIET_PERIOD);
+
+        assert_eq!(subject.degraded, true);
+        assert_eq!(vacuum_parameters_arc.lock().unwrap().len(), 0);
+        TestLogHandler::new().exists_log_containing(
+            "ERROR: Accountant: Database is corrupt: no such table: journal_checkpoint; no longer recording charges",
+        );
+    }
+
     #[test]
     fn report_routing_service_consumed_message_is_received() {
         init_test_logging();
@@ -454,6 +821,11 @@ pub mod tests {
                     .more_money_payable_parameters(more_money_payable_parameters_arc.clone()),
             ),
             receivable: Box::new(ReceivableDaoMock::new()),
+            config: Box::new(ConfigDaoMock::new()),
+            node_record: Box::new(NodeRecordDaoMock::new()),
+            banned_node: Box::new(BannedNodeDaoMock::new()),
+            maintenance: Box::new(MaintenanceDaoMock::new()),
+            journal: Box::new(JournalDaoMock::new()),
         };
         let db_initializer = DbInitializerMock::new()
             .initialize_parameters(dbi_initialize_parameters_arc.clone())
@@ -474,6 +846,7 @@ pub mod tests {
                 payload_size: 1234,
                 service_rate: 42,
                 byte_rate: 24,
+                correlation_id_opt: None,
             })
             .unwrap();
 
@@ -498,12 +871,25 @@ pub mod tests {
             data_directory: PathBuf::new(),
         };
         let more_money_receivable_parameters_arc = Arc::new(Mutex::new(vec![]));
+        let more_money_receivable_for_stream_parameters_arc = Arc::new(Mutex::new(vec![]));
         let daos = Daos {
             payable: Box::new(PayableDaoMock::new()),
             receivable: Box::new(
                 ReceivableDaoMock::new()
-                    .more_money_receivable_parameters(more_money_receivable_parameters_arc.clone()),
+                    .more_money_receivable_parameters(more_money_receivable_parameters_arc.clone())
+                    .more_money_receivable_for_stream_parameters(
+                        more_money_receivable_for_stream_parameters_arc.clone(),
+                    ),
             ),
+            config: Box::new(ConfigDaoMock::new()),
+            node_record: Box::new(NodeRecordDaoMock::new()),
+            banned_node: Box::new(BannedNodeDaoMock::new()),
+            maintenance: Box::new(MaintenanceDaoMock::new()),
+            journal: Box::new(JournalDaoMock::new()),
+        };
+        let correlation_id = CorrelationId {
+            stream_key: make_meaningless_stream_key(),
+            epoch: 430_000,
         };
         let db_initializer = DbInitializerMock::new().initialize_result(Ok(daos));
         let mut subject = Accountant::new(config);
@@ -522,6 +908,7 @@ pub mod tests {
                 payload_size: 1234,
                 service_rate: 42,
                 byte_rate: 24,
+                correlation_id_opt: Some(correlation_id),
             })
             .unwrap();
 
@@ -532,6 +919,12 @@ pub mod tests {
             more_money_receivable_parameters[0],
             (Wallet::new("booga"), (1 * 42) + (1234 * 24))
         );
+        assert_eq!(
+            *more_money_receivable_for_stream_parameters_arc
+                .lock()
+                .unwrap(),
+            vec![(Wallet::new("booga"), correlation_id, (1 * 42) + (1234 * 24))]
+        );
         TestLogHandler::new().exists_log_containing(
             "DEBUG: Accountant: Charging exit service for 1234 bytes to wallet booga",
         );
@@ -549,12 +942,25 @@ pub mod tests {
         };
         let dbi_initialize_parameters_arc = Arc::new(Mutex::new(vec![]));
         let more_money_payable_parameters_arc = Arc::new(Mutex::new(vec![]));
+        let more_money_payable_for_stream_parameters_arc = Arc::new(Mutex::new(vec![]));
         let daos = Daos {
             payable: Box::new(
                 PayableDaoMock::new()
-                    .more_money_payable_parameters(more_money_payable_parameters_arc.clone()),
+                    .more_money_payable_parameters(more_money_payable_parameters_arc.clone())
+                    .more_money_payable_for_stream_parameters(
+                        more_money_payable_for_stream_parameters_arc.clone(),
+                    ),
             ),
             receivable: Box::new(ReceivableDaoMock::new()),
+            config: Box::new(ConfigDaoMock::new()),
+            node_record: Box::new(NodeRecordDaoMock::new()),
+            banned_node: Box::new(BannedNodeDaoMock::new()),
+            maintenance: Box::new(MaintenanceDaoMock::new()),
+            journal: Box::new(JournalDaoMock::new()),
+        };
+        let correlation_id = CorrelationId {
+            stream_key: make_meaningless_stream_key(),
+            epoch: 430_000,
         };
         let db_initializer = DbInitializerMock::new()
             .initialize_parameters(dbi_initialize_parameters_arc.clone())
@@ -575,6 +981,7 @@ pub mod tests {
                 payload_size: 1234,
                 service_rate: 42,
                 byte_rate: 24,
+                correlation_id_opt: Some(correlation_id),
             })
             .unwrap();
 
@@ -587,11 +994,132 @@ pub mod tests {
             more_money_payable_parameters[0],
             (Wallet::new("booga"), (1 * 42) + (1234 * 24))
         );
+        assert_eq!(
+            *more_money_payable_for_stream_parameters_arc.lock().unwrap(),
+            vec![(Wallet::new("booga"), correlation_id, (1 * 42) + (1234 * 24))]
+        );
         TestLogHandler::new().exists_log_containing(
             "DEBUG: Accountant: Accruing debt to wallet booga for consuming exit service 1234 bytes",
         );
     }
 
+    #[test]
+    fn reports_before_shutdown_are_recorded_and_reports_after_it_are_not() {
+        init_test_logging();
+        let config = AccountantConfig {
+            data_directory: PathBuf::from(format!(
+                "{}/reports_before_shutdown_are_recorded_and_reports_after_it_are_not/home",
+                BASE_TEST_DIR
+            )),
+        };
+        let more_money_payable_parameters_arc = Arc::new(Mutex::new(vec![]));
+        let daos = Daos {
+            payable: Box::new(
+                PayableDaoMock::new()
+                    .more_money_payable_parameters(more_money_payable_parameters_arc.clone()),
+            ),
+            receivable: Box::new(ReceivableDaoMock::new()),
+            config: Box::new(ConfigDaoMock::new()),
+            node_record: Box::new(NodeRecordDaoMock::new()),
+            banned_node: Box::new(BannedNodeDaoMock::new()),
+            maintenance: Box::new(MaintenanceDaoMock::new()),
+            journal: Box::new(JournalDaoMock::new()),
+        };
+        let mut subject = Accountant::new(config);
+        subject.db_initializer = Box::new(DbInitializerMock::new().initialize_result(Ok(daos)));
+        let system =
+            System::new("reports_before_shutdown_are_recorded_and_reports_after_it_are_not");
+        let subject_addr: Addr<Syn, Accountant> = subject.start();
+        subject_addr
+            .try_send(BindMessage {
+                peer_actors: peer_actors_builder().build(),
+            })
+            .unwrap();
+        let report = |earning_wallet: &str| ReportExitServiceConsumedMessage {
+            earning_wallet: Wallet::new(earning_wallet),
+            payload_size: 1234,
+            service_rate: 42,
+            byte_rate: 24,
+            correlation_id_opt: None,
+        };
+
+        subject_addr.try_send(report("before")).unwrap();
+        subject_addr.try_send(ShutdownMessage {}).unwrap();
+        subject_addr.try_send(report("after")).unwrap();
+        let aging_future = subject_addr.send(ReceivableAgingQueryMessage {});
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+        assert_eq!(
+            *more_money_payable_parameters_arc.lock().unwrap(),
+            vec![(Wallet::new("before"), 42 + (1234 * 24))]
+        );
+        assert_eq!(
+            aging_future.wait().unwrap(),
+            ReceivableAgingReport::default()
+        );
+        TestLogHandler::new().exists_log_containing("INFO: Accountant: Books closed for shutdown");
+    }
+
+    #[test]
+    fn config_change_subscriptions_are_passed_to_the_config_dao_before_and_after_binding() {
+        let data_dir = PathBuf::from(format!(
+            "{}/config_change_subscriptions_are_passed_to_the_config_dao_before_and_after_binding/home",
+            BASE_TEST_DIR
+        ));
+        let config = AccountantConfig {
+            data_directory: data_dir.clone(),
+        };
+        let subscribe_parameters_arc = Arc::new(Mutex::new(vec![]));
+        let daos = Daos {
+            payable: Box::new(PayableDaoMock::new()),
+            receivable: Box::new(ReceivableDaoMock::new()),
+            config: Box::new(
+                ConfigDaoMock::new().subscribe_parameters(subscribe_parameters_arc.clone()),
+            ),
+            node_record: Box::new(NodeRecordDaoMock::new()),
+            banned_node: Box::new(BannedNodeDaoMock::new()),
+            maintenance: Box::new(MaintenanceDaoMock::new()),
+            journal: Box::new(JournalDaoMock::new()),
+        };
+        let db_initializer = DbInitializerMock::new().initialize_result(Ok(daos));
+        let mut subject = Accountant::new(config);
+        subject.db_initializer = Box::new(db_initializer);
+        let system = System::new(
+            "config_change_subscriptions_are_passed_to_the_config_dao_before_and_after_binding",
+        );
+        let (early, _, _) = make_recorder();
+        let early_addr: Addr<Syn, Recorder> = early.start();
+        let (late, _, _) = make_recorder();
+        let late_addr: Addr<Syn, Recorder> = late.start();
+        let subject_addr: Addr<Syn, Accountant> = subject.start();
+        let subject_subs = Accountant::make_subs_from(&subject_addr);
+
+        subject_subs
+            .subscribe_to_config_changes
+            .try_send(SubscribeToConfigChangesMessage {
+                subscriber: early_addr.recipient::<ConfigChangedMessage>(),
+            })
+            .unwrap();
+        subject_subs
+            .bind
+            .try_send(BindMessage {
+                peer_actors: peer_actors_builder().build(),
+            })
+            .unwrap();
+        subject_subs
+            .subscribe_to_config_changes
+            .try_send(SubscribeToConfigChangesMessage {
+                subscriber: late_addr.recipient::<ConfigChangedMessage>(),
+            })
+            .unwrap();
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+        let subscribe_parameters = subscribe_parameters_arc.lock().unwrap();
+        assert_eq!(subscribe_parameters.len(), 2);
+    }
+
     #[test]
     fn nonexistent_directory_is_created_when_possible() {
         let data_dir = PathBuf::from(format!(
@@ -721,4 +1249,46 @@ pub mod tests {
         fs::set_permissions(parent_dir, permissions).unwrap();
         PathBuf::from(parent_dir)
     }
+
+    #[test]
+    fn binding_tells_the_hopper_which_wallets_are_behind_on_paying() {
+        let config = AccountantConfig {
+            data_directory: PathBuf::from(format!(
+                "{}/binding_tells_the_hopper_which_wallets_are_behind_on_paying/home",
+                BASE_TEST_DIR
+            )),
+        };
+        let daos = Daos {
+            payable: Box::new(PayableDaoMock::new()),
+            receivable: Box::new(
+                ReceivableDaoMock::new().delinquent_wallets_result(vec![Wallet::new("deadbeat")]),
+            ),
+            config: Box::new(ConfigDaoMock::new()),
+            node_record: Box::new(NodeRecordDaoMock::new()),
+            banned_node: Box::new(BannedNodeDaoMock::new()),
+            maintenance: Box::new(MaintenanceDaoMock::new()),
+            journal: Box::new(JournalDaoMock::new()),
+        };
+        let mut subject = Accountant::new(config);
+        subject.db_initializer = Box::new(DbInitializerMock::new().initialize_result(Ok(daos)));
+        let (hopper, _, hopper_recording_arc) = make_recorder();
+        let system = System::new("binding_tells_the_hopper_which_wallets_are_behind_on_paying");
+        let subject_addr: Addr<Syn, Accountant> = subject.start();
+
+        subject_addr
+            .try_send(BindMessage {
+                peer_actors: peer_actors_builder().hopper(hopper).build(),
+            })
+            .unwrap();
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+        let hopper_recording = hopper_recording_arc.lock().unwrap();
+        assert_eq!(
+            hopper_recording.get_record::<PaymentStandingMessage>(0),
+            &PaymentStandingMessage {
+                delinquent_wallets: vec![Wallet::new("deadbeat")],
+            }
+        );
+    }
 }
@@ -0,0 +1,100 @@
+// Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use super::dao_utils;
+use crate::sub_lib::cryptde::PublicKey;
+use rusqlite::types::ToSql;
+use rusqlite::Connection;
+use rusqlite::NO_PARAMS;
+use std::fmt::Debug;
+use std::time::SystemTime;
+
+// Nodes the Neighborhood has banned for misbehaving, so that they stay banned after a restart
+pub trait BannedNodeDao: Debug {
+    fn ban(&self, public_key: &PublicKey, banned: &SystemTime);
+
+    fn load_all(&self) -> Vec<PublicKey>;
+}
+
+#[derive(Debug)]
+pub struct BannedNodeDaoReal {
+    conn: Connection,
+}
+
+impl BannedNodeDao for BannedNodeDaoReal {
+    // Banning a Node again leaves the time of the first ban alone
+    fn ban(&self, public_key: &PublicKey, banned: &SystemTime) {
+        let params: &[&ToSql] = &[&public_key.as_slice(), &dao_utils::to_time_t(banned)];
+        match self.conn.execute(
+            "insert or ignore into banned_node (public_key, banned_timestamp) values (?, ?)",
+            params,
+        ) {
+            Ok(_) => (),
+            Err(e) => panic!("Database is corrupt: {}", e),
+        }
+    }
+
+    fn load_all(&self) -> Vec<PublicKey> {
+        let mut stmt = self
+            .conn
+            .prepare("select public_key from banned_node")
+            .expect("Internal error");
+        let rows = match stmt.query_map(NO_PARAMS, |row| row.get(0)) {
+            Ok(rows) => rows,
+            Err(e) => panic!("Database is corrupt: {}", e),
+        };
+        rows.map(|row| {
+            let public_key: Vec<u8> = match row {
+                Ok(public_key) => public_key,
+                Err(e) => panic!("Database is corrupt: {}", e),
+            };
+            PublicKey::from(public_key)
+        })
+        .collect()
+    }
+}
+
+impl BannedNodeDaoReal {
+    pub fn new(conn: Connection) -> BannedNodeDaoReal {
+        BannedNodeDaoReal { conn }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::db_initializer::DbInitializer;
+    use super::super::db_initializer::DbInitializerReal;
+    use super::super::local_test_utils::*;
+    use super::*;
+
+    #[test]
+    fn banned_nodes_load_by_public_key() {
+        let home_dir = ensure_node_home_directory_exists("banned_nodes_load_by_public_key");
+        let subject = DbInitializerReal::new()
+            .initialize(&home_dir)
+            .unwrap()
+            .banned_node;
+        let one = PublicKey::new(b"one");
+        let another = PublicKey::new(b"another");
+
+        subject.ban(&one, &SystemTime::now());
+        subject.ban(&another, &SystemTime::now());
+        let mut result = subject.load_all();
+
+        result.sort_by_key(|key| key.as_slice().to_vec());
+        assert_eq!(result, vec![another, one]);
+    }
+
+    #[test]
+    fn banning_a_node_twice_keeps_one_entry() {
+        let home_dir = ensure_node_home_directory_exists("banning_a_node_twice_keeps_one_entry");
+        let subject = DbInitializerReal::new()
+            .initialize(&home_dir)
+            .unwrap()
+            .banned_node;
+        let key = PublicKey::new(b"booga");
+
+        subject.ban(&key, &SystemTime::now());
+        subject.ban(&key, &SystemTime::now());
+
+        assert_eq!(subject.load_all(), vec![key]);
+    }
+}
@@ -0,0 +1,267 @@
+// Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use crate::sub_lib::accountant::ConfigChangedMessage;
+use crate::sub_lib::wallet::Wallet;
+use actix::Recipient;
+use actix::Syn;
+use rusqlite::types::ToSql;
+use rusqlite::Connection;
+use rusqlite::OptionalExtension;
+use std::fmt;
+use std::fmt::Debug;
+use std::fmt::Formatter;
+use std::str::FromStr;
+use std::time::Duration;
+
+// Everything in the config table is stored as text; the typed accessors are written in terms of
+// get_string and set_string, so an implementation only has to supply those two and subscribe.
+pub trait ConfigDao: Debug {
+    fn get_string(&self, name: &str) -> Option<String>;
+
+    fn set_string(&mut self, name: &str, value: &str);
+
+    fn subscribe(&mut self, subscriber: Recipient<Syn, ConfigChangedMessage>);
+
+    fn get_u64(&self, name: &str, default: u64) -> u64 {
+        parse_or_default(name, self.get_string(name), default, "u64")
+    }
+
+    fn set_u64(&mut self, name: &str, value: u64) {
+        self.set_string(name, &format!("{}", value))
+    }
+
+    fn get_bool(&self, name: &str, default: bool) -> bool {
+        parse_or_default(name, self.get_string(name), default, "bool")
+    }
+
+    fn set_bool(&mut self, name: &str, value: bool) {
+        self.set_string(name, &format!("{}", value))
+    }
+
+    fn get_wallet(&self, name: &str, default: &Wallet) -> Wallet {
+        match self.get_string(name) {
+            Some(address) => Wallet::new(&address),
+            None => default.clone(),
+        }
+    }
+
+    fn set_wallet(&mut self, name: &str, value: &Wallet) {
+        self.set_string(name, &value.address)
+    }
+
+    // Durations are stored as whole milliseconds
+    fn get_duration(&self, name: &str, default: Duration) -> Duration {
+        match self.get_string(name) {
+            Some(_) => Duration::from_millis(self.get_u64(name, 0)),
+            None => default,
+        }
+    }
+
+    fn set_duration(&mut self, name: &str, value: Duration) {
+        let millis = value.as_secs() * 1000 + u64::from(value.subsec_millis());
+        self.set_u64(name, millis)
+    }
+}
+
+fn parse_or_default<T: FromStr>(
+    name: &str,
+    value: Option<String>,
+    default: T,
+    type_name: &str,
+) -> T {
+    match value {
+        None => default,
+        Some(value) => match value.parse::<T>() {
+            Ok(parsed) => parsed,
+            Err(_) => panic!(
+                "Database is corrupt: config value '{}' for {} is not a {}",
+                value, name, type_name
+            ),
+        },
+    }
+}
+
+pub struct ConfigDaoReal {
+    conn: Connection,
+    subscribers: Vec<Recipient<Syn, ConfigChangedMessage>>,
+}
+
+impl Debug for ConfigDaoReal {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "ConfigDaoReal ({} subscribers)", self.subscribers.len())
+    }
+}
+
+impl ConfigDao for ConfigDaoReal {
+    fn get_string(&self, name: &str) -> Option<String> {
+        let mut stmt = self
+            .conn
+            .prepare("select value from config where name = ?")
+            .expect("Internal error");
+        match stmt.query_row(&[name], |row| row.get(0)).optional() {
+            Ok(value) => value,
+            Err(e) => panic!("Database is corrupt: {:?}", e),
+        }
+    }
+
+    fn set_string(&mut self, name: &str, value: &str) {
+        if self.get_string(name).as_ref().map(|v| v.as_str()) == Some(value) {
+            return;
+        }
+        {
+            let mut stmt = self
+                .conn
+                .prepare("insert or replace into config (name, value) values (?, ?)")
+                .expect("Internal error");
+            let params: &[&ToSql] = &[&name, &value];
+            if let Err(e) = stmt.execute(params) {
+                panic!("Database is corrupt: {}", e)
+            }
+        }
+        let msg = ConfigChangedMessage {
+            name: String::from(name),
+            value: String::from(value),
+        };
+        self.subscribers.iter().for_each(|subscriber| {
+            subscriber
+                .try_send(msg.clone())
+                .expect("Config subscriber is dead")
+        });
+    }
+
+    fn subscribe(&mut self, subscriber: Recipient<Syn, ConfigChangedMessage>) {
+        self.subscribers.push(subscriber);
+    }
+}
+
+impl ConfigDaoReal {
+    pub fn new(conn: Connection) -> ConfigDaoReal {
+        ConfigDaoReal {
+            conn,
+            subscribers: vec![],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::db_initializer::DbInitializer;
+    use super::super::db_initializer::DbInitializerReal;
+    use super::super::db_initializer::CURRENT_SCHEMA_VERSION;
+    use super::super::local_test_utils::ensure_node_home_directory_exists;
+    use super::*;
+    use crate::test_utils::recorder::make_recorder;
+    use actix::msgs;
+    use actix::Actor;
+    use actix::Addr;
+    use actix::Arbiter;
+    use actix::System;
+
+    fn make_subject(name: &str) -> Box<ConfigDao> {
+        let home_dir = ensure_node_home_directory_exists(name);
+        DbInitializerReal::new()
+            .initialize(&home_dir)
+            .unwrap()
+            .config
+    }
+
+    #[test]
+    fn get_string_finds_existing_values_and_not_missing_ones() {
+        let subject = make_subject("get_string_finds_existing_values_and_not_missing_ones");
+
+        assert_eq!(
+            subject.get_string("schema_version"),
+            Some(String::from(CURRENT_SCHEMA_VERSION))
+        );
+        assert_eq!(subject.get_string("booga"), None);
+    }
+
+    #[test]
+    fn typed_values_round_trip() {
+        let mut subject = make_subject("typed_values_round_trip");
+
+        subject.set_u64("number", 1234567890123);
+        subject.set_bool("flag", true);
+        subject.set_wallet("wallet", &Wallet::new("0x1234"));
+        subject.set_duration("interval", Duration::from_millis(2345));
+
+        assert_eq!(subject.get_u64("number", 0), 1234567890123);
+        assert_eq!(subject.get_bool("flag", false), true);
+        assert_eq!(
+            subject.get_wallet("wallet", &Wallet::new("default")),
+            Wallet::new("0x1234")
+        );
+        assert_eq!(
+            subject.get_duration("interval", Duration::from_secs(0)),
+            Duration::from_millis(2345)
+        );
+        assert_eq!(subject.get_string("interval"), Some(String::from("2345")));
+    }
+
+    #[test]
+    fn missing_values_produce_defaults() {
+        let subject = make_subject("missing_values_produce_defaults");
+
+        assert_eq!(subject.get_u64("number", 42), 42);
+        assert_eq!(subject.get_bool("flag", true), true);
+        assert_eq!(
+            subject.get_wallet("wallet", &Wallet::new("default")),
+            Wallet::new("default")
+        );
+        assert_eq!(
+            subject.get_duration("interval", Duration::from_secs(5)),
+            Duration::from_secs(5)
+        );
+    }
+
+    #[test]
+    fn setting_an_existing_value_replaces_it() {
+        let mut subject = make_subject("setting_an_existing_value_replaces_it");
+        subject.set_u64("number", 1);
+
+        subject.set_u64("number", 2);
+
+        assert_eq!(subject.get_u64("number", 0), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "Database is corrupt: config value 'booga' for number is not a u64")]
+    fn unparseable_value_is_reported_as_corruption() {
+        let mut subject = make_subject("unparseable_value_is_reported_as_corruption");
+        subject.set_string("number", "booga");
+
+        subject.get_u64("number", 0);
+    }
+
+    #[test]
+    fn subscribers_are_told_about_changes_but_not_about_non_changes() {
+        let system = System::new("subscribers_are_told_about_changes_but_not_about_non_changes");
+        let (recorder, _, recording_arc) = make_recorder();
+        let addr: Addr<Syn, _> = recorder.start();
+        let mut subject =
+            make_subject("subscribers_are_told_about_changes_but_not_about_non_changes");
+        subject.subscribe(addr.recipient::<ConfigChangedMessage>());
+
+        subject.set_bool("flag", true);
+        subject.set_bool("flag", true);
+        subject.set_u64("number", 5);
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+        let recording = recording_arc.lock().unwrap();
+        assert_eq!(
+            recording.get_record::<ConfigChangedMessage>(0),
+            &ConfigChangedMessage {
+                name: String::from("flag"),
+                value: String::from("true"),
+            }
+        );
+        assert_eq!(
+            recording.get_record::<ConfigChangedMessage>(1),
+            &ConfigChangedMessage {
+                name: String::from("number"),
+                value: String::from("5"),
+            }
+        );
+        assert_eq!(recording.len(), 2);
+    }
+}
@@ -1,4 +1,5 @@
 // Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use rusqlite::Connection;
 use std::time::Duration;
 use std::time::SystemTime;
 
@@ -13,3 +14,23 @@ pub fn from_time_t(time_t: i64) -> SystemTime {
     let interval = Duration::from_secs(time_t as u64);
     SystemTime::UNIX_EPOCH + interval
 }
+
+// Begins an immediate transaction, so that no other connection can write until it's over, and
+// commits it if f succeeds or rolls it back if it doesn't
+pub fn in_transaction<T, F>(conn: &Connection, f: F) -> Result<T, String>
+where
+    F: FnOnce() -> Result<T, String>,
+{
+    conn.execute_batch("begin immediate")
+        .map_err(|e| format!("{}", e))?;
+    match f() {
+        Ok(result) => conn
+            .execute_batch("commit")
+            .map(|_| result)
+            .map_err(|e| format!("{}", e)),
+        Err(e) => {
+            conn.execute_batch("rollback").is_ok();
+            Err(e)
+        }
+    }
+}
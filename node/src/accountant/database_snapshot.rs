@@ -0,0 +1,467 @@
+// Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use super::db_initializer::DbInitializer;
+use super::db_initializer::DbInitializerReal;
+use super::db_initializer::CURRENT_SCHEMA_VERSION;
+use super::db_initializer::DATABASE_FILE;
+use rusqlite::types::ToSql;
+use rusqlite::Connection;
+use rusqlite::OpenFlags;
+use rusqlite::NO_PARAMS;
+use serde_derive::Deserialize;
+use serde_derive::Serialize;
+use std::path::Path;
+use std::path::PathBuf;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PayableRow {
+    pub wallet_address: String,
+    pub balance: i64,
+    pub last_paid_timestamp: i64,
+    pub pending_payment_transaction: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ReceivableRow {
+    pub wallet_address: String,
+    pub balance: i64,
+    pub last_received_timestamp: i64,
+}
+
+// One row of payable_stream or receivable_stream
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct StreamRow {
+    pub wallet_address: String,
+    pub stream_key: String,
+    pub epoch: i64,
+    pub amount: i64,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct JournalRow {
+    pub id: i64,
+    pub account: String,
+    pub wallet_address: String,
+    pub entry: String,
+    pub amount: i64,
+    pub timestamp: i64,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct JournalCheckpointRow {
+    pub account: String,
+    pub wallet_address: String,
+    pub balance: i64,
+    pub journal_id: i64,
+}
+
+// Everything in a Node's database, row for row, so that it can be carried to another machine.
+// The schema version travels along so that a snapshot is never restored into a schema it
+// doesn't fit. Other Nodes' records are left behind: they'll be gossiped again.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DatabaseSnapshot {
+    pub schema_version: String,
+    pub config: Vec<(String, String)>,
+    pub payable: Vec<PayableRow>,
+    pub receivable: Vec<ReceivableRow>,
+    pub payable_stream: Vec<StreamRow>,
+    pub receivable_stream: Vec<StreamRow>,
+    pub journal: Vec<JournalRow>,
+    pub journal_checkpoint: Vec<JournalCheckpointRow>,
+}
+
+impl DatabaseSnapshot {
+    pub fn take(data_directory: &Path) -> Result<DatabaseSnapshot, String> {
+        let database_file_path = data_directory.join(DATABASE_FILE);
+        if !database_file_path.exists() {
+            return Err(format!("No Node database at {:?}", database_file_path));
+        }
+        let conn =
+            Connection::open_with_flags(&database_file_path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+                .map_err(|e| format!("Couldn't open {:?}: {}", database_file_path, e))?;
+        let mut config = DatabaseSnapshot::select(
+            &conn,
+            "select name, value from config order by name",
+            |row| (row.get(0), row.get(1)),
+        )?;
+        let schema_version = match config.iter().position(|(name, _)| name == "schema_version") {
+            Some(index) => config.remove(index).1,
+            None => return Err(format!("{:?} has no schema version", database_file_path)),
+        };
+        let payable = DatabaseSnapshot::select(
+            &conn,
+            "select wallet_address, balance, last_paid_timestamp, pending_payment_transaction from payable order by wallet_address",
+            |row| PayableRow {
+                wallet_address: row.get(0),
+                balance: row.get(1),
+                last_paid_timestamp: row.get(2),
+                pending_payment_transaction: row.get(3),
+            },
+        )?;
+        let receivable = DatabaseSnapshot::select(
+            &conn,
+            "select wallet_address, balance, last_received_timestamp from receivable order by wallet_address",
+            |row| ReceivableRow {
+                wallet_address: row.get(0),
+                balance: row.get(1),
+                last_received_timestamp: row.get(2),
+            },
+        )?;
+        let payable_stream = DatabaseSnapshot::select_stream_rows(&conn, "payable_stream")?;
+        let receivable_stream = DatabaseSnapshot::select_stream_rows(&conn, "receivable_stream")?;
+        let journal = DatabaseSnapshot::select(
+            &conn,
+            "select id, account, wallet_address, entry, amount, timestamp from journal order by id",
+            |row| JournalRow {
+                id: row.get(0),
+                account: row.get(1),
+                wallet_address: row.get(2),
+                entry: row.get(3),
+                amount: row.get(4),
+                timestamp: row.get(5),
+            },
+        )?;
+        let journal_checkpoint = DatabaseSnapshot::select(
+            &conn,
+            "select account, wallet_address, balance, journal_id from journal_checkpoint order by account, wallet_address",
+            |row| JournalCheckpointRow {
+                account: row.get(0),
+                wallet_address: row.get(1),
+                balance: row.get(2),
+                journal_id: row.get(3),
+            },
+        )?;
+        Ok(DatabaseSnapshot {
+            schema_version,
+            config,
+            payable,
+            receivable,
+            payable_stream,
+            receivable_stream,
+            journal,
+            journal_checkpoint,
+        })
+    }
+
+    // Only into a directory that doesn't have a database yet: a restore never overwrites a Node's
+    // accounts
+    pub fn restore(&self, data_directory: &Path) -> Result<(), String> {
+        if self.schema_version != CURRENT_SCHEMA_VERSION {
+            return Err(format!(
+                "Snapshot is of database schema {}, but this Node uses {}",
+                self.schema_version, CURRENT_SCHEMA_VERSION
+            ));
+        }
+        let database_file_path = data_directory.join(DATABASE_FILE);
+        if database_file_path.exists() {
+            return Err(format!(
+                "There's already a Node database at {:?}; not overwriting it",
+                database_file_path
+            ));
+        }
+        DbInitializerReal::new()
+            .initialize(&PathBuf::from(data_directory))
+            .map_err(|e| {
+                format!(
+                    "Couldn't create a database in {:?}: {:?}",
+                    data_directory, e
+                )
+            })?;
+        let mut conn =
+            Connection::open_with_flags(&database_file_path, OpenFlags::SQLITE_OPEN_READ_WRITE)
+                .map_err(|e| format!("Couldn't open {:?}: {}", database_file_path, e))?;
+        let tx = conn.transaction().map_err(|e| format!("{}", e))?;
+        for (name, value) in &self.config {
+            let params: &[&ToSql] = &[name, value];
+            DatabaseSnapshot::insert(
+                &tx,
+                "insert or replace into config (name, value) values (?, ?)",
+                params,
+            )?;
+        }
+        for row in &self.payable {
+            let params: &[&ToSql] = &[
+                &row.wallet_address,
+                &row.balance,
+                &row.last_paid_timestamp,
+                &row.pending_payment_transaction,
+            ];
+            DatabaseSnapshot::insert(&tx, "insert into payable (wallet_address, balance, last_paid_timestamp, pending_payment_transaction) values (?, ?, ?, ?)", params)?;
+        }
+        for row in &self.receivable {
+            let params: &[&ToSql] = &[
+                &row.wallet_address,
+                &row.balance,
+                &row.last_received_timestamp,
+            ];
+            DatabaseSnapshot::insert(&tx, "insert into receivable (wallet_address, balance, last_received_timestamp) values (?, ?, ?)", params)?;
+        }
+        for (table, rows) in &[
+            ("payable_stream", &self.payable_stream),
+            ("receivable_stream", &self.receivable_stream),
+        ] {
+            for row in rows.iter() {
+                let params: &[&ToSql] = &[
+                    &row.wallet_address,
+                    &row.stream_key,
+                    &row.epoch,
+                    &row.amount,
+                ];
+                DatabaseSnapshot::insert(
+                    &tx,
+                    format!(
+                        "insert into {} (wallet_address, stream_key, epoch, amount) values (?, ?, ?, ?)",
+                        table
+                    )
+                    .as_str(),
+                    params,
+                )?;
+            }
+        }
+        // With their IDs, so that the checkpoints still mark the same place in the journal
+        for row in &self.journal {
+            let params: &[&ToSql] = &[
+                &row.id,
+                &row.account,
+                &row.wallet_address,
+                &row.entry,
+                &row.amount,
+                &row.timestamp,
+            ];
+            DatabaseSnapshot::insert(&tx, "insert into journal (id, account, wallet_address, entry, amount, timestamp) values (?, ?, ?, ?, ?, ?)", params)?;
+        }
+        for row in &self.journal_checkpoint {
+            let params: &[&ToSql] = &[
+                &row.account,
+                &row.wallet_address,
+                &row.balance,
+                &row.journal_id,
+            ];
+            DatabaseSnapshot::insert(&tx, "insert into journal_checkpoint (account, wallet_address, balance, journal_id) values (?, ?, ?, ?)", params)?;
+        }
+        tx.commit().map_err(|e| format!("{}", e))
+    }
+
+    fn select<T, F>(conn: &Connection, sql: &str, f: F) -> Result<Vec<T>, String>
+    where
+        F: FnMut(&rusqlite::Row<'_, '_>) -> T,
+    {
+        let mut stmt = conn.prepare(sql).map_err(|e| format!("{}", e))?;
+        let rows = stmt.query_map(NO_PARAMS, f).map_err(|e| format!("{}", e))?;
+        rows.map(|row| row.map_err(|e| format!("{}", e))).collect()
+    }
+
+    fn select_stream_rows(conn: &Connection, table: &str) -> Result<Vec<StreamRow>, String> {
+        DatabaseSnapshot::select(
+            conn,
+            format!(
+                "select wallet_address, stream_key, epoch, amount from {} order by wallet_address, stream_key, epoch",
+                table
+            )
+            .as_str(),
+            |row| StreamRow {
+                wallet_address: row.get(0),
+                stream_key: row.get(1),
+                epoch: row.get(2),
+                amount: row.get(3),
+            },
+        )
+    }
+
+    fn insert(conn: &Connection, sql: &str, params: &[&ToSql]) -> Result<(), String> {
+        conn.execute(sql, params)
+            .map(|_| ())
+            .map_err(|e| format!("{}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::local_test_utils::ensure_node_home_directory_exists;
+    use super::*;
+    use crate::sub_lib::accountant::CorrelationId;
+    use crate::sub_lib::wallet::Wallet;
+    use crate::test_utils::test_utils::make_meaningless_stream_key;
+
+    fn make_populated_database(name: &str) -> PathBuf {
+        let home_dir = ensure_node_home_directory_exists(name);
+        let mut daos = DbInitializerReal::new().initialize(&home_dir).unwrap();
+        daos.config.set_string("booga", "agoob");
+        daos.payable
+            .more_money_payable(&Wallet::new("payee"), 1234)
+            .unwrap();
+        daos.receivable
+            .more_money_receivable(&Wallet::new("payer"), 2345)
+            .unwrap();
+        let correlation_id = CorrelationId {
+            stream_key: make_meaningless_stream_key(),
+            epoch: 430_000,
+        };
+        daos.payable
+            .more_money_payable_for_stream(&Wallet::new("payee"), &correlation_id, 1234)
+            .unwrap();
+        daos.receivable
+            .more_money_receivable_for_stream(&Wallet::new("payer"), &correlation_id, 2345)
+            .unwrap();
+        daos.journal.checkpoint().unwrap();
+        let conn = Connection::open_with_flags(
+            &home_dir.join(DATABASE_FILE),
+            OpenFlags::SQLITE_OPEN_READ_WRITE,
+        )
+        .unwrap();
+        conn.execute(
+            "update payable set pending_payment_transaction = '0xTRANSACTION'",
+            NO_PARAMS,
+        )
+        .unwrap();
+        home_dir
+    }
+
+    #[test]
+    fn take_reads_every_table() {
+        let home_dir = make_populated_database("take_reads_every_table");
+
+        let result = DatabaseSnapshot::take(&home_dir).unwrap();
+
+        assert_eq!(result.schema_version, CURRENT_SCHEMA_VERSION);
+        assert_eq!(
+            result.config,
+            vec![(String::from("booga"), String::from("agoob"))]
+        );
+        assert_eq!(result.payable.len(), 1);
+        assert_eq!(result.payable[0].wallet_address, String::from("payee"));
+        assert_eq!(result.payable[0].balance, 1234);
+        assert_eq!(
+            result.payable[0].pending_payment_transaction,
+            Some(String::from("0xTRANSACTION"))
+        );
+        assert_eq!(result.receivable.len(), 1);
+        assert_eq!(result.receivable[0].wallet_address, String::from("payer"));
+        assert_eq!(result.receivable[0].balance, 2345);
+        let stream_key = format!("{:?}", make_meaningless_stream_key());
+        assert_eq!(
+            result.payable_stream,
+            vec![StreamRow {
+                wallet_address: String::from("payee"),
+                stream_key: stream_key.clone(),
+                epoch: 430_000,
+                amount: 1234,
+            }]
+        );
+        assert_eq!(
+            result.receivable_stream,
+            vec![StreamRow {
+                wallet_address: String::from("payer"),
+                stream_key,
+                epoch: 430_000,
+                amount: 2345,
+            }]
+        );
+        assert_eq!(
+            result
+                .journal
+                .iter()
+                .map(|row| (row.id, row.account.as_str(), row.amount))
+                .collect::<Vec<(i64, &str, i64)>>(),
+            vec![(1, "payable", 1234), (2, "receivable", 2345)]
+        );
+        assert_eq!(
+            result.journal_checkpoint,
+            vec![
+                JournalCheckpointRow {
+                    account: String::from("payable"),
+                    wallet_address: String::from("payee"),
+                    balance: 1234,
+                    journal_id: 2,
+                },
+                JournalCheckpointRow {
+                    account: String::from("receivable"),
+                    wallet_address: String::from("payer"),
+                    balance: 2345,
+                    journal_id: 2,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn take_complains_when_there_is_no_database() {
+        let home_dir =
+            ensure_node_home_directory_exists("take_complains_when_there_is_no_database");
+
+        let result = DatabaseSnapshot::take(&home_dir);
+
+        assert_eq!(
+            result,
+            Err(format!(
+                "No Node database at {:?}",
+                home_dir.join(DATABASE_FILE)
+            ))
+        );
+    }
+
+    #[test]
+    fn restore_reproduces_the_snapshot_in_a_new_directory() {
+        let original_dir =
+            make_populated_database("restore_reproduces_the_snapshot_in_a_new_directory");
+        let snapshot = DatabaseSnapshot::take(&original_dir).unwrap();
+        let new_dir = ensure_node_home_directory_exists(
+            "restore_reproduces_the_snapshot_in_a_new_directory/new",
+        );
+
+        snapshot.restore(&new_dir).unwrap();
+
+        assert_eq!(DatabaseSnapshot::take(&new_dir).unwrap(), snapshot);
+        let daos = DbInitializerReal::new().initialize(&new_dir).unwrap();
+        assert_eq!(
+            daos.receivable
+                .account_status(&Wallet::new("payer"))
+                .unwrap()
+                .balance,
+            2345
+        );
+        assert_eq!(daos.journal.recalculate_balances(), Ok(vec![]));
+    }
+
+    #[test]
+    fn restore_will_not_overwrite_an_existing_database() {
+        let home_dir = make_populated_database("restore_will_not_overwrite_an_existing_database");
+        let snapshot = DatabaseSnapshot::take(&home_dir).unwrap();
+
+        let result = snapshot.restore(&home_dir);
+
+        assert_eq!(
+            result,
+            Err(format!(
+                "There's already a Node database at {:?}; not overwriting it",
+                home_dir.join(DATABASE_FILE)
+            ))
+        );
+    }
+
+    #[test]
+    fn restore_refuses_a_snapshot_of_a_different_schema() {
+        let home_dir =
+            ensure_node_home_directory_exists("restore_refuses_a_snapshot_of_a_different_schema");
+        let snapshot = DatabaseSnapshot {
+            schema_version: String::from("booga"),
+            config: vec![],
+            payable: vec![],
+            receivable: vec![],
+            payable_stream: vec![],
+            receivable_stream: vec![],
+            journal: vec![],
+            journal_checkpoint: vec![],
+        };
+
+        let result = snapshot.restore(&home_dir);
+
+        assert_eq!(
+            result,
+            Err(format!(
+                "Snapshot is of database schema booga, but this Node uses {}",
+                CURRENT_SCHEMA_VERSION
+            ))
+        );
+        assert_eq!(home_dir.join(DATABASE_FILE).exists(), false);
+    }
+}
@@ -1,16 +1,37 @@
 // Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use super::banned_node_dao::BannedNodeDao;
+use super::banned_node_dao::BannedNodeDaoReal;
+use super::config_dao::ConfigDao;
+use super::config_dao::ConfigDaoReal;
+use super::dao_utils;
+use super::journal_dao::JournalDao;
+use super::journal_dao::JournalDaoReal;
+use super::maintenance_dao::MaintenanceDao;
+use super::maintenance_dao::MaintenanceDaoReal;
+use super::node_record_dao::NodeRecordDao;
+use super::node_record_dao::NodeRecordDaoReal;
 use super::payable_dao::PayableDao;
 use super::payable_dao::PayableDaoReal;
 use super::receivable_dao::ReceivableDao;
 use super::receivable_dao::ReceivableDaoReal;
+use lazy_static::lazy_static;
+use rusqlite::types::ToSql;
 use rusqlite::Connection;
 use rusqlite::OpenFlags;
 use rusqlite::NO_PARAMS;
 use std::collections::HashMap;
 use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::SystemTime;
 
 pub const DATABASE_FILE: &str = "node_data.sqlite";
-pub const CURRENT_SCHEMA_VERSION: &str = "0.0.1";
+pub const CURRENT_SCHEMA_VERSION: &str = "0.0.5";
+
+lazy_static! {
+    // Both the Accountant and the Neighborhood initialize the database when they're bound, and
+    // only one of them should be creating or migrating it at a time
+    static ref INITIALIZATION: Mutex<()> = Mutex::new(());
+}
 
 #[derive(Debug, PartialEq)]
 pub enum InitializationError {
@@ -21,6 +42,11 @@ pub enum InitializationError {
 pub struct Daos {
     pub payable: Box<PayableDao>,
     pub receivable: Box<ReceivableDao>,
+    pub config: Box<ConfigDao>,
+    pub node_record: Box<NodeRecordDao>,
+    pub banned_node: Box<BannedNodeDao>,
+    pub maintenance: Box<MaintenanceDao>,
+    pub journal: Box<JournalDao>,
 }
 
 pub trait DbInitializer {
@@ -31,13 +57,16 @@ pub struct DbInitializerReal {}
 
 impl DbInitializer for DbInitializerReal {
     fn initialize(&self, path: &PathBuf) -> Result<Daos, InitializationError> {
+        let _guard = INITIALIZATION
+            .lock()
+            .expect("Database initialization poisoned");
         let mut flags = OpenFlags::empty();
         flags.insert(OpenFlags::SQLITE_OPEN_READ_WRITE);
         let database_file_path = &path.join(DATABASE_FILE);
         let conn = match Connection::open_with_flags(database_file_path, flags) {
             Ok(conn) => {
                 let config = self.extract_configurations(&conn);
-                match self.check_version(config.get(&String::from("schema_version"))) {
+                match self.check_version(&conn, config.get(&String::from("schema_version"))) {
                     Ok(_) => conn,
                     Err(e) => return Err(e),
                 }
@@ -59,9 +88,29 @@ impl DbInitializer for DbInitializerReal {
         let conn = Connection::open_with_flags(database_file_path, flags)
             .expect("Database suddenly disappeared");
         let receivable = ReceivableDaoReal::new(conn);
+        let conn = Connection::open_with_flags(database_file_path, flags)
+            .expect("Database suddenly disappeared");
+        let config = ConfigDaoReal::new(conn);
+        let conn = Connection::open_with_flags(database_file_path, flags)
+            .expect("Database suddenly disappeared");
+        let node_record = NodeRecordDaoReal::new(conn);
+        let conn = Connection::open_with_flags(database_file_path, flags)
+            .expect("Database suddenly disappeared");
+        let banned_node = BannedNodeDaoReal::new(conn);
+        let conn = Connection::open_with_flags(database_file_path, flags)
+            .expect("Database suddenly disappeared");
+        let maintenance = MaintenanceDaoReal::new(conn);
+        let conn = Connection::open_with_flags(database_file_path, flags)
+            .expect("Database suddenly disappeared");
+        let journal = JournalDaoReal::new(conn);
         Ok(Daos {
             payable: Box::new(payable),
             receivable: Box::new(receivable),
+            config: Box::new(config),
+            node_record: Box::new(node_record),
+            banned_node: Box::new(banned_node),
+            maintenance: Box::new(maintenance),
+            journal: Box::new(journal),
         })
     }
 }
@@ -75,7 +124,11 @@ impl DbInitializerReal {
         self.create_config_table(conn)?;
         self.initialize_config(conn)?;
         self.create_payable_table(conn)?;
-        self.create_receivable_table(conn)
+        self.create_receivable_table(conn)?;
+        self.create_stream_tables(conn)?;
+        self.create_node_record_table(conn)?;
+        self.create_banned_node_table(conn)?;
+        self.create_journal_tables(conn)
     }
 
     fn create_config_table(&self, conn: &Connection) -> Result<(), InitializationError> {
@@ -145,6 +198,149 @@ impl DbInitializerReal {
         Ok(())
     }
 
+    // What was charged or owed for each stream, by correlation ID, so that a consumer's payable and
+    // an exit's receivable can be compared stream by stream. Nothing here is ever paid.
+    fn create_stream_tables(&self, conn: &Connection) -> Result<(), InitializationError> {
+        ["payable_stream", "receivable_stream"]
+            .iter()
+            .for_each(|table| {
+                conn.execute(
+                    format!(
+                        "create table if not exists {} (
+                            wallet_address text not null,
+                            stream_key text not null,
+                            epoch integer not null,
+                            amount integer not null,
+                            primary key (wallet_address, stream_key, epoch)
+                        )",
+                        table
+                    )
+                    .as_str(),
+                    NO_PARAMS,
+                )
+                .expect(format!("Can't create {} table", table).as_str());
+            });
+        Ok(())
+    }
+
+    fn create_node_record_table(&self, conn: &Connection) -> Result<(), InitializationError> {
+        conn.execute(
+            "create table if not exists node_record (
+                public_key blob primary key,
+                node_addr text null,
+                version integer not null,
+                earning_wallet text not null,
+                consuming_wallet text null,
+                signed_record blob not null,
+                last_gossiped_timestamp integer not null
+            )",
+            NO_PARAMS,
+        )
+        .expect("Can't create node_record table");
+        Ok(())
+    }
+
+    fn create_banned_node_table(&self, conn: &Connection) -> Result<(), InitializationError> {
+        conn.execute(
+            "create table if not exists banned_node (
+                public_key blob primary key,
+                banned_timestamp integer not null
+            )",
+            NO_PARAMS,
+        )
+        .expect("Can't create banned_node table");
+        Ok(())
+    }
+
+    // Every change to a payable or receivable balance, and checkpoints of what those changes added up
+    // to. journal_balance is what each balance should be: its last checkpoint plus what's been
+    // journaled since.
+    fn create_journal_tables(&self, conn: &Connection) -> Result<(), InitializationError> {
+        conn.execute(
+            "create table if not exists journal (
+                id integer primary key autoincrement,
+                account text not null,
+                wallet_address text not null,
+                entry text not null,
+                amount integer not null,
+                timestamp integer not null
+            )",
+            NO_PARAMS,
+        )
+        .expect("Can't create journal table");
+        conn.execute(
+            "create index if not exists idx_journal_account_wallet_address on journal (account, wallet_address)",
+            NO_PARAMS,
+        )
+        .expect("Can't create journal account index");
+        conn.execute(
+            "create table if not exists journal_checkpoint (
+                account text not null,
+                wallet_address text not null,
+                balance integer not null,
+                journal_id integer not null,
+                primary key (account, wallet_address)
+            )",
+            NO_PARAMS,
+        )
+        .expect("Can't create journal_checkpoint table");
+        conn.execute(
+            "create view if not exists journal_balance as
+                select account, wallet_address, sum(amount) as balance from (
+                    select account, wallet_address, balance as amount from journal_checkpoint
+                    union all
+                    select account, wallet_address, amount from journal
+                    where id > (select coalesce(max(journal_id), 0) from journal_checkpoint)
+                ) group by account, wallet_address",
+            NO_PARAMS,
+        )
+        .expect("Can't create journal_balance view");
+        Ok(())
+    }
+
+    fn migrate_from_0_0_1(&self, conn: &Connection) -> Result<(), InitializationError> {
+        self.create_stream_tables(conn)?;
+        self.migrate_from_0_0_2(conn)
+    }
+
+    fn migrate_from_0_0_2(&self, conn: &Connection) -> Result<(), InitializationError> {
+        self.create_node_record_table(conn)?;
+        self.migrate_from_0_0_3(conn)
+    }
+
+    fn migrate_from_0_0_3(&self, conn: &Connection) -> Result<(), InitializationError> {
+        self.create_banned_node_table(conn)?;
+        self.migrate_from_0_0_4(conn)
+    }
+
+    // Balances from before there was a journal go into it as opening balances, so that it adds up
+    // to them
+    fn migrate_from_0_0_4(&self, conn: &Connection) -> Result<(), InitializationError> {
+        self.create_journal_tables(conn)?;
+        let now = dao_utils::to_time_t(&SystemTime::now());
+        ["payable", "receivable"].iter().for_each(|account| {
+            let params: &[&ToSql] = &[account, &now];
+            conn.execute(
+                format!(
+                    "insert into journal (account, wallet_address, entry, amount, timestamp) \
+                     select ?, wallet_address, 'opening_balance', balance, ? from {} \
+                     where balance != 0 order by wallet_address",
+                    account
+                )
+                .as_str(),
+                params,
+            )
+            .expect(format!("Can't journal opening {} balances", account).as_str());
+        });
+        let params: &[&ToSql] = &[&CURRENT_SCHEMA_VERSION];
+        conn.execute(
+            "update config set value = ? where name = 'schema_version'",
+            params,
+        )
+        .expect("Can't update schema version");
+        Ok(())
+    }
+
     fn extract_configurations(&self, conn: &Connection) -> HashMap<String, String> {
         let mut stmt = conn.prepare("select name, value from config").unwrap();
         let config_contents = stmt
@@ -155,25 +351,32 @@ impl DbInitializerReal {
         config_contents.collect::<HashMap<String, String>>()
     }
 
-    fn check_version(&self, version: Option<&String>) -> Result<(), InitializationError> {
-        match version {
-            None => Err(InitializationError::IncompatibleVersion),
-            Some(v_ref) => {
-                if *v_ref == CURRENT_SCHEMA_VERSION {
-                    Ok(())
-                } else {
-                    Err(InitializationError::IncompatibleVersion)
-                }
-            }
+    fn check_version(
+        &self,
+        conn: &Connection,
+        version: Option<&String>,
+    ) -> Result<(), InitializationError> {
+        match version.map(|v| v.as_str()) {
+            Some(CURRENT_SCHEMA_VERSION) => Ok(()),
+            Some("0.0.1") => self.migrate_from_0_0_1(conn),
+            Some("0.0.2") => self.migrate_from_0_0_2(conn),
+            Some("0.0.3") => self.migrate_from_0_0_3(conn),
+            Some("0.0.4") => self.migrate_from_0_0_4(conn),
+            _ => Err(InitializationError::IncompatibleVersion),
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use super::super::journal_dao::Account;
+    use super::super::journal_dao::EntryKind;
     use super::super::local_test_utils::ensure_node_home_directory_exists;
     use super::*;
+    use crate::neighborhood::neighborhood_test_utils::make_node_record;
+    use crate::sub_lib::wallet::Wallet;
     use rusqlite::OpenFlags;
+    use std::time::SystemTime;
 
     #[test]
     fn nonexistent_database_is_created() {
@@ -205,6 +408,41 @@ mod tests {
             .unwrap();
         let mut receivable_contents = stmt.query_map(NO_PARAMS, |_| 42).unwrap();
         assert!(receivable_contents.next().is_none());
+        ["payable_stream", "receivable_stream"]
+            .iter()
+            .for_each(|table| {
+                let mut stmt = conn
+                    .prepare(
+                        format!(
+                            "select wallet_address, stream_key, epoch, amount from {}",
+                            table
+                        )
+                        .as_str(),
+                    )
+                    .unwrap();
+                let mut stream_contents = stmt.query_map(NO_PARAMS, |_| 42).unwrap();
+                assert!(stream_contents.next().is_none());
+            });
+        let mut stmt = conn
+            .prepare("select public_key, node_addr, version, earning_wallet, consuming_wallet, signed_record, last_gossiped_timestamp from node_record")
+            .unwrap();
+        let mut node_record_contents = stmt.query_map(NO_PARAMS, |_| 42).unwrap();
+        assert!(node_record_contents.next().is_none());
+        let mut stmt = conn
+            .prepare("select public_key, banned_timestamp from banned_node")
+            .unwrap();
+        let mut banned_node_contents = stmt.query_map(NO_PARAMS, |_| 42).unwrap();
+        assert!(banned_node_contents.next().is_none());
+        let mut stmt = conn
+            .prepare("select id, account, wallet_address, entry, amount, timestamp from journal")
+            .unwrap();
+        let mut journal_contents = stmt.query_map(NO_PARAMS, |_| 42).unwrap();
+        assert!(journal_contents.next().is_none());
+        let mut stmt = conn
+            .prepare("select account, wallet_address, balance, journal_id from journal_checkpoint")
+            .unwrap();
+        let mut journal_checkpoint_contents = stmt.query_map(NO_PARAMS, |_| 42).unwrap();
+        assert!(journal_checkpoint_contents.next().is_none());
     }
 
     #[test]
@@ -243,11 +481,167 @@ mod tests {
         );
         assert_eq!(
             config_contents.next().unwrap().unwrap(),
-            (String::from("schema_version"), String::from("0.0.1"))
+            (
+                String::from("schema_version"),
+                String::from(CURRENT_SCHEMA_VERSION)
+            )
         );
         assert!(config_contents.next().is_none());
     }
 
+    #[test]
+    fn existing_database_at_version_0_0_1_gets_stream_tables_and_keeps_its_accounts() {
+        let home_dir = ensure_node_home_directory_exists(
+            "existing_database_at_version_0_0_1_gets_stream_tables_and_keeps_its_accounts",
+        );
+        {
+            DbInitializerReal::new().initialize(&home_dir).unwrap();
+            let mut flags = OpenFlags::empty();
+            flags.insert(OpenFlags::SQLITE_OPEN_READ_WRITE);
+            let conn = Connection::open_with_flags(&home_dir.join(DATABASE_FILE), flags).unwrap();
+            conn.execute_batch(
+                "drop view journal_balance; drop table journal_checkpoint; drop table journal; drop table payable_stream; drop table receivable_stream; drop table node_record; drop table banned_node; \
+                 update config set value = '0.0.1' where name = 'schema_version'; \
+                 insert into receivable (wallet_address, balance, last_received_timestamp) values ('debtor', 1234, 0)",
+            )
+            .unwrap();
+        }
+        let subject = DbInitializerReal::new();
+
+        let daos = subject.initialize(&home_dir).unwrap();
+
+        assert_eq!(
+            daos.config.get_string("schema_version"),
+            Some(String::from(CURRENT_SCHEMA_VERSION))
+        );
+        assert_eq!(
+            daos.receivable
+                .account_status(&Wallet::new("debtor"))
+                .unwrap()
+                .balance,
+            1234
+        );
+        let mut flags = OpenFlags::empty();
+        flags.insert(OpenFlags::SQLITE_OPEN_READ_ONLY);
+        let conn = Connection::open_with_flags(&home_dir.join(DATABASE_FILE), flags).unwrap();
+        let mut stmt = conn
+            .prepare("select count(*) from payable_stream, receivable_stream, node_record")
+            .unwrap();
+        let count: i64 = stmt.query_row(NO_PARAMS, |row| row.get(0)).unwrap();
+        assert_eq!(count, 0);
+    }
+
+    #[test]
+    fn existing_database_at_version_0_0_2_gets_node_record_table_and_keeps_its_accounts() {
+        let home_dir = ensure_node_home_directory_exists(
+            "existing_database_at_version_0_0_2_gets_node_record_table_and_keeps_its_accounts",
+        );
+        {
+            DbInitializerReal::new().initialize(&home_dir).unwrap();
+            let mut flags = OpenFlags::empty();
+            flags.insert(OpenFlags::SQLITE_OPEN_READ_WRITE);
+            let conn = Connection::open_with_flags(&home_dir.join(DATABASE_FILE), flags).unwrap();
+            conn.execute_batch(
+                "drop view journal_balance; drop table journal_checkpoint; drop table journal; drop table node_record; drop table banned_node; \
+                 update config set value = '0.0.2' where name = 'schema_version'; \
+                 insert into payable (wallet_address, balance, last_paid_timestamp, pending_payment_transaction) values ('creditor', 2345, 0, null)",
+            )
+            .unwrap();
+        }
+        let subject = DbInitializerReal::new();
+
+        let daos = subject.initialize(&home_dir).unwrap();
+
+        assert_eq!(
+            daos.config.get_string("schema_version"),
+            Some(String::from(CURRENT_SCHEMA_VERSION))
+        );
+        assert_eq!(
+            daos.payable
+                .account_status(&Wallet::new("creditor"))
+                .unwrap()
+                .balance,
+            2345
+        );
+        assert_eq!(daos.node_record.load_all(), vec![]);
+    }
+
+    #[test]
+    fn existing_database_at_version_0_0_3_gets_banned_node_table_and_keeps_its_node_records() {
+        let home_dir = ensure_node_home_directory_exists(
+            "existing_database_at_version_0_0_3_gets_banned_node_table_and_keeps_its_node_records",
+        );
+        let node_record = make_node_record(1234, true, false);
+        {
+            let daos = DbInitializerReal::new().initialize(&home_dir).unwrap();
+            daos.node_record.save(&node_record, &SystemTime::now());
+            let mut flags = OpenFlags::empty();
+            flags.insert(OpenFlags::SQLITE_OPEN_READ_WRITE);
+            let conn = Connection::open_with_flags(&home_dir.join(DATABASE_FILE), flags).unwrap();
+            conn.execute_batch(
+                "drop view journal_balance; drop table journal_checkpoint; drop table journal; drop table banned_node; \
+                 update config set value = '0.0.3' where name = 'schema_version'",
+            )
+            .unwrap();
+        }
+        let subject = DbInitializerReal::new();
+
+        let daos = subject.initialize(&home_dir).unwrap();
+
+        assert_eq!(
+            daos.config.get_string("schema_version"),
+            Some(String::from(CURRENT_SCHEMA_VERSION))
+        );
+        assert_eq!(daos.node_record.load_all(), vec![node_record]);
+        assert_eq!(daos.banned_node.load_all(), vec![]);
+    }
+
+    #[test]
+    fn existing_database_at_version_0_0_4_gets_a_journal_that_adds_up_to_its_balances() {
+        let home_dir = ensure_node_home_directory_exists(
+            "existing_database_at_version_0_0_4_gets_a_journal_that_adds_up_to_its_balances",
+        );
+        {
+            DbInitializerReal::new().initialize(&home_dir).unwrap();
+            let mut flags = OpenFlags::empty();
+            flags.insert(OpenFlags::SQLITE_OPEN_READ_WRITE);
+            let conn = Connection::open_with_flags(&home_dir.join(DATABASE_FILE), flags).unwrap();
+            conn.execute_batch(
+                "drop view journal_balance; drop table journal_checkpoint; drop table journal; \
+                 update config set value = '0.0.4' where name = 'schema_version'; \
+                 insert into payable (wallet_address, balance, last_paid_timestamp, pending_payment_transaction) values ('creditor', 2345, 0, null); \
+                 insert into payable (wallet_address, balance, last_paid_timestamp, pending_payment_transaction) values ('settled', 0, 0, null); \
+                 insert into receivable (wallet_address, balance, last_received_timestamp) values ('debtor', 1234, 0)",
+            )
+            .unwrap();
+        }
+        let subject = DbInitializerReal::new();
+
+        let daos = subject.initialize(&home_dir).unwrap();
+
+        assert_eq!(
+            daos.config.get_string("schema_version"),
+            Some(String::from(CURRENT_SCHEMA_VERSION))
+        );
+        let payable_entries = daos
+            .journal
+            .entries(Account::Payable, &Wallet::new("creditor"));
+        assert_eq!(payable_entries.len(), 1);
+        assert_eq!(payable_entries[0].kind, EntryKind::OpeningBalance);
+        assert_eq!(payable_entries[0].amount, 2345);
+        assert_eq!(
+            daos.journal
+                .entries(Account::Payable, &Wallet::new("settled")),
+            vec![]
+        );
+        let receivable_entries = daos
+            .journal
+            .entries(Account::Receivable, &Wallet::new("debtor"));
+        assert_eq!(receivable_entries.len(), 1);
+        assert_eq!(receivable_entries[0].amount, 1234);
+        assert_eq!(daos.journal.recalculate_balances(), Ok(vec![]));
+    }
+
     #[test]
     fn existing_database_with_no_version_is_rejected() {
         let home_dir =
@@ -0,0 +1,351 @@
+// Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use super::dao_utils;
+use crate::sub_lib::wallet::Wallet;
+use rusqlite::types::ToSql;
+use rusqlite::Connection;
+use rusqlite::NO_PARAMS;
+use std::fmt::Debug;
+use std::time::SystemTime;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Account {
+    Payable,
+    Receivable,
+}
+
+impl Account {
+    // Also the name of the table that holds the account's balance
+    pub fn name(&self) -> &'static str {
+        match self {
+            Account::Payable => "payable",
+            Account::Receivable => "receivable",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EntryKind {
+    Service,
+    Payment,
+    // What an account's balance already was when the journal was started
+    OpeningBalance,
+}
+
+impl EntryKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            EntryKind::Service => "service",
+            EntryKind::Payment => "payment",
+            EntryKind::OpeningBalance => "opening_balance",
+        }
+    }
+
+    fn from_name(name: &str) -> EntryKind {
+        match name {
+            "service" => EntryKind::Service,
+            "payment" => EntryKind::Payment,
+            "opening_balance" => EntryKind::OpeningBalance,
+            _ => panic!("Database is corrupt: unknown journal entry '{}'", name),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct JournalEntry {
+    pub id: i64,
+    pub account: Account,
+    pub wallet_address: Wallet,
+    pub kind: EntryKind,
+    pub amount: i64,
+    pub timestamp: SystemTime,
+}
+
+// Every change to a payable or receivable balance is appended to the journal along with the change
+// itself, and nothing in the journal is ever changed or deleted. The balances in the payable and
+// receivable tables are only a running total of it, which the journal can always be asked to redo.
+pub trait JournalDao: Debug {
+    // Oldest first
+    fn entries(&self, account: Account, wallet_address: &Wallet) -> Vec<JournalEntry>;
+
+    // Folds the entries since the last checkpoint into a new one, so that recalculating balances
+    // doesn't have to add up the whole journal
+    fn checkpoint(&self) -> Result<(), String>;
+
+    // Sets every balance to what the journal says it should be, and tells which ones were wrong
+    fn recalculate_balances(&self) -> Result<Vec<(Account, Wallet)>, String>;
+}
+
+#[derive(Debug)]
+pub struct JournalDaoReal {
+    conn: Connection,
+}
+
+impl JournalDao for JournalDaoReal {
+    fn entries(&self, account: Account, wallet_address: &Wallet) -> Vec<JournalEntry> {
+        let mut stmt = self
+            .conn
+            .prepare(
+                "select id, entry, amount, timestamp from journal where account = ? and wallet_address = ? \
+                 order by id",
+            )
+            .expect("Internal error");
+        let params: &[&ToSql] = &[&account.name(), &wallet_address.address];
+        let rows = match stmt.query_map(params, |row| {
+            (row.get(0), row.get(1), row.get(2), row.get(3))
+        }) {
+            Ok(rows) => rows,
+            Err(e) => panic!("Database is corrupt: {}", e),
+        };
+        rows.map(|row| {
+            let (id, kind, amount, timestamp): (i64, String, i64, i64) = match row {
+                Ok(values) => values,
+                Err(e) => panic!("Database is corrupt: {}", e),
+            };
+            JournalEntry {
+                id,
+                account,
+                wallet_address: wallet_address.clone(),
+                kind: EntryKind::from_name(&kind),
+                amount,
+                timestamp: dao_utils::from_time_t(timestamp),
+            }
+        })
+        .collect()
+    }
+
+    // A single statement, so a checkpoint is taken whole or not at all. Every account in the old
+    // checkpoint is carried into the new one, so they all share the same journal_id.
+    fn checkpoint(&self) -> Result<(), String> {
+        self.conn
+            .execute(
+                "insert into journal_checkpoint (account, wallet_address, balance, journal_id) \
+                 select account, wallet_address, balance, (select coalesce(max(id), 0) from journal) \
+                 from journal_balance where true \
+                 on conflict (account, wallet_address) do update set balance = excluded.balance, \
+                 journal_id = excluded.journal_id",
+                NO_PARAMS,
+            )
+            .map(|_| ())
+            .map_err(|e| format!("{}", e))
+    }
+
+    fn recalculate_balances(&self) -> Result<Vec<(Account, Wallet)>, String> {
+        dao_utils::in_transaction(&self.conn, || {
+            let mut corrected = vec![];
+            for account in &[Account::Payable, Account::Receivable] {
+                let wrong = format!(
+                    "balance != coalesce((select jb.balance from journal_balance jb \
+                     where jb.account = '{0}' and jb.wallet_address = {0}.wallet_address), 0)",
+                    account.name()
+                );
+                let mut stmt = self
+                    .conn
+                    .prepare(
+                        format!(
+                            "select wallet_address from {} where {} order by wallet_address",
+                            account.name(),
+                            wrong
+                        )
+                        .as_str(),
+                    )
+                    .expect("Internal error");
+                let rows = stmt
+                    .query_map(NO_PARAMS, |row| row.get::<_, String>(0))
+                    .map_err(|e| format!("{}", e))?;
+                for row in rows {
+                    let wallet_address = row.map_err(|e| format!("{}", e))?;
+                    corrected.push((*account, Wallet::new(&wallet_address)));
+                }
+                self.conn
+                    .execute(
+                        format!(
+                            "update {0} set balance = coalesce((select jb.balance from journal_balance jb \
+                             where jb.account = '{0}' and jb.wallet_address = {0}.wallet_address), 0) \
+                             where {1}",
+                            account.name(),
+                            wrong
+                        )
+                        .as_str(),
+                        NO_PARAMS,
+                    )
+                    .map_err(|e| format!("{}", e))?;
+            }
+            Ok(corrected)
+        })
+    }
+}
+
+impl JournalDaoReal {
+    pub fn new(conn: Connection) -> JournalDaoReal {
+        JournalDaoReal { conn }
+    }
+}
+
+// For the DAOs that change balances, inside the same transaction as the change
+pub fn append(
+    conn: &Connection,
+    account: Account,
+    wallet_address: &Wallet,
+    kind: EntryKind,
+    amount: i64,
+    timestamp: &SystemTime,
+) -> Result<(), String> {
+    let mut stmt = conn
+        .prepare(
+            "insert into journal (account, wallet_address, entry, amount, timestamp) values (?, ?, ?, ?, ?)",
+        )
+        .expect("Internal error");
+    let params: &[&ToSql] = &[
+        &account.name(),
+        &wallet_address.address,
+        &kind.name(),
+        &amount,
+        &dao_utils::to_time_t(timestamp),
+    ];
+    match stmt.execute(params) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("{}", e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::db_initializer;
+    use super::super::db_initializer::DbInitializer;
+    use super::super::db_initializer::DbInitializerReal;
+    use super::super::local_test_utils::ensure_node_home_directory_exists;
+    use super::*;
+    use rusqlite::OpenFlags;
+    use std::path::PathBuf;
+
+    fn open(home_dir: &PathBuf) -> Connection {
+        Connection::open_with_flags(
+            &home_dir.join(db_initializer::DATABASE_FILE),
+            OpenFlags::SQLITE_OPEN_READ_WRITE,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn every_change_to_a_balance_is_journaled() {
+        let home_dir = ensure_node_home_directory_exists("every_change_to_a_balance_is_journaled");
+        let daos = DbInitializerReal::new().initialize(&home_dir).unwrap();
+        let wallet = Wallet::new("booga");
+        let paid_at = dao_utils::from_time_t(1_000_000);
+
+        daos.receivable
+            .more_money_receivable(&wallet, 1234)
+            .unwrap();
+        daos.receivable.more_money_received(&wallet, 1000, &paid_at);
+        daos.payable.more_money_payable(&wallet, 2345).unwrap();
+        daos.payable.payment_sent(&wallet, "0xTRANSACTION");
+        daos.payable.payment_confirmed(&wallet, 2000, &paid_at);
+
+        let receivable_entries = daos.journal.entries(Account::Receivable, &wallet);
+        assert_eq!(
+            receivable_entries
+                .iter()
+                .map(|entry| (entry.kind, entry.amount))
+                .collect::<Vec<(EntryKind, i64)>>(),
+            vec![(EntryKind::Service, 1234), (EntryKind::Payment, -1000)]
+        );
+        assert_eq!(receivable_entries[1].timestamp, paid_at);
+        let payable_entries = daos.journal.entries(Account::Payable, &wallet);
+        assert_eq!(
+            payable_entries
+                .iter()
+                .map(|entry| (entry.kind, entry.amount))
+                .collect::<Vec<(EntryKind, i64)>>(),
+            vec![(EntryKind::Service, 2345), (EntryKind::Payment, -2000)]
+        );
+        assert_eq!(payable_entries[0].id < payable_entries[1].id, true);
+        assert_eq!(daos.journal.recalculate_balances(), Ok(vec![]));
+    }
+
+    #[test]
+    fn recalculation_puts_back_balances_that_went_wrong() {
+        let home_dir =
+            ensure_node_home_directory_exists("recalculation_puts_back_balances_that_went_wrong");
+        let daos = DbInitializerReal::new().initialize(&home_dir).unwrap();
+        daos.payable
+            .more_money_payable(&Wallet::new("creditor"), 1000)
+            .unwrap();
+        daos.payable
+            .more_money_payable(&Wallet::new("untouched"), 500)
+            .unwrap();
+        daos.receivable
+            .more_money_receivable(&Wallet::new("debtor"), 2000)
+            .unwrap();
+        daos.payable
+            .payment_sent(&Wallet::new("unpaid"), "0xTRANSACTION");
+        open(&home_dir)
+            .execute_batch(
+                "update payable set balance = 999999 where wallet_address = 'creditor'; \
+                 update payable set balance = 42 where wallet_address = 'unpaid'; \
+                 update receivable set balance = -1 where wallet_address = 'debtor'",
+            )
+            .unwrap();
+
+        let result = daos.journal.recalculate_balances();
+
+        assert_eq!(
+            result,
+            Ok(vec![
+                (Account::Payable, Wallet::new("creditor")),
+                (Account::Payable, Wallet::new("unpaid")),
+                (Account::Receivable, Wallet::new("debtor")),
+            ])
+        );
+        let balance_of = |wallet: &str| {
+            daos.payable
+                .account_status(&Wallet::new(wallet))
+                .unwrap()
+                .balance
+        };
+        assert_eq!(balance_of("creditor"), 1000);
+        assert_eq!(balance_of("untouched"), 500);
+        assert_eq!(balance_of("unpaid"), 0);
+        assert_eq!(
+            daos.receivable
+                .account_status(&Wallet::new("debtor"))
+                .unwrap()
+                .balance,
+            2000
+        );
+    }
+
+    #[test]
+    fn recalculation_starts_from_the_last_checkpoint() {
+        let home_dir =
+            ensure_node_home_directory_exists("recalculation_starts_from_the_last_checkpoint");
+        let daos = DbInitializerReal::new().initialize(&home_dir).unwrap();
+        let wallet = Wallet::new("debtor");
+        daos.receivable
+            .more_money_receivable(&wallet, 1000)
+            .unwrap();
+        daos.journal.checkpoint().unwrap();
+        daos.receivable.more_money_receivable(&wallet, 200).unwrap();
+        daos.journal.checkpoint().unwrap();
+        daos.receivable.more_money_receivable(&wallet, 30).unwrap();
+        // Were the checkpointed entries added up again, this would change the balance
+        open(&home_dir)
+            .execute("delete from journal where amount = 1000", NO_PARAMS)
+            .unwrap();
+
+        let result = daos.journal.recalculate_balances();
+
+        assert_eq!(result, Ok(vec![]));
+        assert_eq!(
+            daos.receivable.account_status(&wallet).unwrap().balance,
+            1230
+        );
+        let checkpoint: (i64, i64) = open(&home_dir)
+            .query_row(
+                "select balance, journal_id from journal_checkpoint where wallet_address = 'debtor'",
+                NO_PARAMS,
+                |row| (row.get(0), row.get(1)),
+            )
+            .unwrap();
+        assert_eq!(checkpoint, (1200, 2));
+    }
+}
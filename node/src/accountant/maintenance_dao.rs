@@ -0,0 +1,114 @@
+// Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use rusqlite::Connection;
+use rusqlite::NO_PARAMS;
+use std::fmt::Debug;
+
+// Looking after the database file itself rather than anything in it
+pub trait MaintenanceDao: Debug {
+    // Ok, or what SQLite found wrong
+    fn check_integrity(&self) -> Result<(), String>;
+
+    // Gives back the space left by deleted rows, then brings the query planner's statistics up to date
+    fn vacuum(&self) -> Result<(), String>;
+}
+
+#[derive(Debug)]
+pub struct MaintenanceDaoReal {
+    conn: Connection,
+}
+
+impl MaintenanceDao for MaintenanceDaoReal {
+    fn check_integrity(&self) -> Result<(), String> {
+        let mut stmt = self
+            .conn
+            .prepare("pragma integrity_check")
+            .map_err(|e| format!("{}", e))?;
+        let rows = stmt
+            .query_map(NO_PARAMS, |row| row.get::<_, String>(0))
+            .map_err(|e| format!("{}", e))?;
+        let problems = rows
+            .map(|row| row.map_err(|e| format!("{}", e)))
+            .collect::<Result<Vec<String>, String>>()?;
+        if problems == vec![String::from("ok")] {
+            Ok(())
+        } else {
+            Err(problems.join("; "))
+        }
+    }
+
+    fn vacuum(&self) -> Result<(), String> {
+        self.conn
+            .execute_batch("vacuum; analyze;")
+            .map_err(|e| format!("{}", e))
+    }
+}
+
+impl MaintenanceDaoReal {
+    pub fn new(conn: Connection) -> MaintenanceDaoReal {
+        MaintenanceDaoReal { conn }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::db_initializer;
+    use super::super::db_initializer::DbInitializer;
+    use super::super::db_initializer::DbInitializerReal;
+    use super::super::local_test_utils::*;
+    use super::*;
+    use crate::sub_lib::wallet::Wallet;
+    use std::fs;
+    use std::fs::OpenOptions;
+    use std::io::Seek;
+    use std::io::SeekFrom;
+    use std::io::Write;
+
+    #[test]
+    fn a_healthy_database_passes_its_integrity_check_and_can_be_vacuumed() {
+        let home_dir = ensure_node_home_directory_exists(
+            "a_healthy_database_passes_its_integrity_check_and_can_be_vacuumed",
+        );
+        let daos = DbInitializerReal::new().initialize(&home_dir).unwrap();
+        daos.receivable
+            .more_money_receivable(&Wallet::new("booga"), 1234)
+            .unwrap();
+        let subject = daos.maintenance;
+
+        assert_eq!(subject.check_integrity(), Ok(()));
+        assert_eq!(subject.vacuum(), Ok(()));
+        assert_eq!(
+            daos.receivable
+                .account_status(&Wallet::new("booga"))
+                .unwrap()
+                .balance,
+            1234
+        );
+    }
+
+    #[test]
+    fn a_damaged_database_fails_its_integrity_check() {
+        let home_dir =
+            ensure_node_home_directory_exists("a_damaged_database_fails_its_integrity_check");
+        let database_path = home_dir.join(db_initializer::DATABASE_FILE);
+        {
+            let daos = DbInitializerReal::new().initialize(&home_dir).unwrap();
+            (0..100).for_each(|index| {
+                daos.receivable
+                    .more_money_receivable(&Wallet::new(&format!("wallet {}", index)), 1234)
+                    .unwrap()
+            });
+        }
+        let length = fs::metadata(&database_path).unwrap().len();
+        let mut file = OpenOptions::new().write(true).open(&database_path).unwrap();
+        // Everything after the header page is scribbled over
+        file.seek(SeekFrom::Start(4096)).unwrap();
+        file.write_all(&vec![0x5A; (length - 4096) as usize])
+            .unwrap();
+        drop(file);
+        let subject = MaintenanceDaoReal::new(Connection::open(&database_path).unwrap());
+
+        let result = subject.check_integrity();
+
+        assert_eq!(result.is_err(), true);
+    }
+}
@@ -0,0 +1,87 @@
+// Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use std::time::Duration;
+use std::time::Instant;
+
+pub const MAINTENANCE_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+pub const QUIET_PERIOD: Duration = Duration::from_secs(5 * 60);
+pub const MAINTENANCE_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+// When the Accountant next looks after its database. The first check comes with the first quiet
+// period after startup, and the rest at most once every MAINTENANCE_INTERVAL. A quiet period is
+// QUIET_PERIOD without a charge recorded: VACUUM holds a lock on the whole file while it runs, and
+// charges that arrive meanwhile would have to wait for it.
+pub struct MaintenanceSchedule {
+    last_activity: Instant,
+    last_maintenance_opt: Option<Instant>,
+}
+
+impl MaintenanceSchedule {
+    pub fn new(now: Instant) -> MaintenanceSchedule {
+        MaintenanceSchedule {
+            last_activity: now,
+            last_maintenance_opt: None,
+        }
+    }
+
+    pub fn activity(&mut self, now: Instant) {
+        self.last_activity = now;
+    }
+
+    pub fn due(&self, now: Instant) -> bool {
+        let quiet = now.duration_since(self.last_activity) >= QUIET_PERIOD;
+        let overdue = match self.last_maintenance_opt {
+            Some(last_maintenance) => now.duration_since(last_maintenance) >= MAINTENANCE_INTERVAL,
+            None => true,
+        };
+        quiet && overdue
+    }
+
+    pub fn done(&mut self, now: Instant) {
+        self.last_maintenance_opt = Some(now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn constants_have_correct_values() {
+        assert_eq!(MAINTENANCE_INTERVAL, Duration::from_secs(86400));
+        assert_eq!(QUIET_PERIOD, Duration::from_secs(300));
+        assert_eq!(MAINTENANCE_CHECK_INTERVAL, Duration::from_secs(60));
+    }
+
+    #[test]
+    fn maintenance_waits_for_a_quiet_period() {
+        let start = Instant::now();
+        let mut subject = MaintenanceSchedule::new(start);
+
+        assert_eq!(
+            subject.due(start + QUIET_PERIOD - Duration::from_secs(1)),
+            false
+        );
+        subject.activity(start + QUIET_PERIOD - Duration::from_secs(1));
+        assert_eq!(subject.due(start + QUIET_PERIOD), false);
+        assert_eq!(
+            subject.due(start + QUIET_PERIOD * 2 - Duration::from_secs(1)),
+            true
+        );
+    }
+
+    #[test]
+    fn maintenance_comes_around_once_per_interval() {
+        let start = Instant::now();
+        let mut subject = MaintenanceSchedule::new(start);
+        subject.done(start + QUIET_PERIOD);
+
+        assert_eq!(
+            subject.due(start + QUIET_PERIOD + MAINTENANCE_INTERVAL - Duration::from_secs(1)),
+            false
+        );
+        assert_eq!(
+            subject.due(start + QUIET_PERIOD + MAINTENANCE_INTERVAL),
+            true
+        );
+    }
+}
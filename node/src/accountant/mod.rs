@@ -1,9 +1,16 @@
 // Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
 
 pub mod accountant;
+pub mod banned_node_dao;
+pub mod config_dao;
 pub mod dao_utils;
+pub mod database_snapshot;
 pub mod db_initializer;
+pub mod journal_dao;
 #[cfg(test)]
 mod local_test_utils;
+pub mod maintenance_dao;
+pub mod maintenance_schedule;
+pub mod node_record_dao;
 pub mod payable_dao;
 pub mod receivable_dao;
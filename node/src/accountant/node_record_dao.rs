@@ -0,0 +1,168 @@
+// Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use super::dao_utils;
+use crate::neighborhood::gossip::GossipNodeRecord;
+use crate::neighborhood::neighborhood_database::NodeRecord;
+use rusqlite::types::ToSql;
+use rusqlite::Connection;
+use rusqlite::NO_PARAMS;
+use serde_cbor;
+use std::fmt::Debug;
+use std::time::SystemTime;
+
+// Other Nodes' records, as they were last gossiped, so that a restarted Node doesn't have to learn
+// the whole Neighborhood over again from its --neighbors
+pub trait NodeRecordDao: Debug {
+    fn save(&self, node_record: &NodeRecord, last_gossiped: &SystemTime);
+
+    fn load_all(&self) -> Vec<NodeRecord>;
+
+    fn age_out(&self, cutoff: &SystemTime) -> usize;
+}
+
+#[derive(Debug)]
+pub struct NodeRecordDaoReal {
+    conn: Connection,
+}
+
+impl NodeRecordDao for NodeRecordDaoReal {
+    // The record must be signed: it's kept the way it goes out in Gossip, signatures and all
+    fn save(&self, node_record: &NodeRecord, last_gossiped: &SystemTime) {
+        let gossip_node_record = GossipNodeRecord::from(node_record, true);
+        let signed_record = serde_cbor::ser::to_vec(&gossip_node_record)
+            .expect("NodeRecord could not be serialized");
+        let mut stmt = self
+            .conn
+            .prepare(
+                "insert into node_record (public_key, node_addr, version, earning_wallet, consuming_wallet, signed_record, last_gossiped_timestamp) \
+                 values (?, ?, ?, ?, ?, ?, ?) \
+                 on conflict (public_key) do update set node_addr = excluded.node_addr, version = excluded.version, \
+                 earning_wallet = excluded.earning_wallet, consuming_wallet = excluded.consuming_wallet, \
+                 signed_record = excluded.signed_record, last_gossiped_timestamp = excluded.last_gossiped_timestamp",
+            )
+            .expect("Internal error");
+        let params: &[&ToSql] = &[
+            &node_record.public_key().as_slice(),
+            &node_record
+                .node_addr_opt()
+                .map(|node_addr| format!("{}", node_addr)),
+            &(node_record.version() as i64),
+            &node_record.earning_wallet().address,
+            &node_record.consuming_wallet().map(|wallet| wallet.address),
+            &signed_record,
+            &dao_utils::to_time_t(last_gossiped),
+        ];
+        match stmt.execute(params) {
+            Ok(_) => (),
+            Err(e) => panic!("Database is corrupt: {}", e),
+        }
+    }
+
+    fn load_all(&self) -> Vec<NodeRecord> {
+        let mut stmt = self
+            .conn
+            .prepare("select signed_record from node_record")
+            .expect("Internal error");
+        let rows = match stmt.query_map(NO_PARAMS, |row| row.get(0)) {
+            Ok(rows) => rows,
+            Err(e) => panic!("Database is corrupt: {}", e),
+        };
+        rows.map(|row| {
+            let signed_record: Vec<u8> = match row {
+                Ok(signed_record) => signed_record,
+                Err(e) => panic!("Database is corrupt: {}", e),
+            };
+            match serde_cbor::de::from_slice::<GossipNodeRecord>(&signed_record[..]) {
+                Ok(gossip_node_record) => gossip_node_record.to_node_record(),
+                Err(e) => panic!("Database is corrupt: {}", e),
+            }
+        })
+        .collect()
+    }
+
+    fn age_out(&self, cutoff: &SystemTime) -> usize {
+        let params: &[&ToSql] = &[&dao_utils::to_time_t(cutoff)];
+        match self.conn.execute(
+            "delete from node_record where last_gossiped_timestamp < ?",
+            params,
+        ) {
+            Ok(count) => count,
+            Err(e) => panic!("Database is corrupt: {}", e),
+        }
+    }
+}
+
+impl NodeRecordDaoReal {
+    pub fn new(conn: Connection) -> NodeRecordDaoReal {
+        NodeRecordDaoReal { conn }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::db_initializer::DbInitializer;
+    use super::super::db_initializer::DbInitializerReal;
+    use super::super::local_test_utils::*;
+    use super::*;
+    use crate::neighborhood::neighborhood_test_utils::make_node_record;
+    use crate::sub_lib::cryptde_null::CryptDENull;
+    use std::time::Duration;
+
+    #[test]
+    fn saved_records_load_with_their_signatures() {
+        let home_dir =
+            ensure_node_home_directory_exists("saved_records_load_with_their_signatures");
+        let subject = DbInitializerReal::new()
+            .initialize(&home_dir)
+            .unwrap()
+            .node_record;
+        let one = make_node_record(1234, true, false);
+        let mut another = make_node_record(2345, false, true);
+        another.neighbors_mut().push(one.public_key().clone());
+        another.sign(&CryptDENull::from(another.public_key()));
+
+        subject.save(&one, &SystemTime::now());
+        subject.save(&another, &SystemTime::now());
+        let mut result = subject.load_all();
+
+        result.sort_by_key(|node_record| node_record.public_key().as_slice().to_vec());
+        assert_eq!(result, vec![one, another]);
+    }
+
+    #[test]
+    fn saving_a_record_again_replaces_it() {
+        let home_dir = ensure_node_home_directory_exists("saving_a_record_again_replaces_it");
+        let subject = DbInitializerReal::new()
+            .initialize(&home_dir)
+            .unwrap()
+            .node_record;
+        let mut node_record = make_node_record(1234, true, false);
+        subject.save(&node_record, &SystemTime::now());
+        node_record.increment_version();
+        node_record.sign(&CryptDENull::from(node_record.public_key()));
+
+        subject.save(&node_record, &SystemTime::now());
+
+        assert_eq!(subject.load_all(), vec![node_record]);
+    }
+
+    #[test]
+    fn age_out_deletes_only_records_not_gossiped_since_the_cutoff() {
+        let home_dir = ensure_node_home_directory_exists(
+            "age_out_deletes_only_records_not_gossiped_since_the_cutoff",
+        );
+        let subject = DbInitializerReal::new()
+            .initialize(&home_dir)
+            .unwrap()
+            .node_record;
+        let now = SystemTime::now();
+        let stale = make_node_record(1234, true, false);
+        let fresh = make_node_record(2345, true, false);
+        subject.save(&stale, &(now - Duration::from_secs(3601)));
+        subject.save(&fresh, &(now - Duration::from_secs(3599)));
+
+        let result = subject.age_out(&(now - Duration::from_secs(3600)));
+
+        assert_eq!(result, 1);
+        assert_eq!(subject.load_all(), vec![fresh]);
+    }
+}
@@ -1,9 +1,16 @@
 // Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
 use super::dao_utils;
+use super::journal_dao;
+use super::journal_dao::Account;
+use super::journal_dao::EntryKind;
+use crate::sub_lib::accountant::CorrelationId;
+use crate::sub_lib::time_source::TimeSource;
+use crate::sub_lib::time_source::TimeSourceReal;
 use crate::sub_lib::wallet::Wallet;
 use rusqlite::types::ToSql;
 use rusqlite::Connection;
 use rusqlite::OptionalExtension;
+use rusqlite::NO_PARAMS;
 use std::fmt::Debug;
 use std::time::SystemTime;
 
@@ -16,7 +23,14 @@ pub struct PayableAccount {
 }
 
 pub trait PayableDao: Debug {
-    fn more_money_payable(&self, wallet_address: &Wallet, amount: u64);
+    fn more_money_payable(&self, wallet_address: &Wallet, amount: u64) -> Result<(), String>;
+
+    fn more_money_payable_for_stream(
+        &self,
+        wallet_address: &Wallet,
+        correlation_id: &CorrelationId,
+        amount: u64,
+    ) -> Result<(), String>;
 
     fn payment_sent(&self, wallet_address: &Wallet, pending_payment_transaction: &str);
 
@@ -28,36 +42,121 @@ pub trait PayableDao: Debug {
     );
 
     fn account_status(&self, wallet_address: &Wallet) -> Option<PayableAccount>;
+
+    fn total_owed(&self) -> i64;
 }
 
 #[derive(Debug)]
 pub struct PayableDaoReal {
     conn: Connection,
+    time_source: Box<dyn TimeSource>,
 }
 
 impl PayableDao for PayableDaoReal {
-    fn more_money_payable(&self, wallet_address: &Wallet, amount: u64) {
-        match self.try_update(wallet_address, amount) {
-            Ok(true) => (),
-            Ok(false) => match self.try_insert(wallet_address, amount) {
-                Ok(_) => (),
-                Err(e) => panic!("Database is corrupt: {}", e),
-            },
-            Err(e) => panic!("Database is corrupt: {}", e),
-        };
+    fn more_money_payable(&self, wallet_address: &Wallet, amount: u64) -> Result<(), String> {
+        dao_utils::in_transaction(&self.conn, || {
+            journal_dao::append(
+                &self.conn,
+                Account::Payable,
+                wallet_address,
+                EntryKind::Service,
+                amount as i64,
+                &self.time_source.now(),
+            )?;
+            match self.try_update(wallet_address, amount) {
+                Ok(true) => Ok(()),
+                Ok(false) => self.try_insert(wallet_address, amount),
+                Err(e) => Err(e),
+            }
+        })
     }
 
-    fn payment_sent(&self, _wallet_address: &Wallet, _pending_payment_transaction: &str) {
-        unimplemented!()
+    // Doesn't touch the balance, which is what actually gets paid. This is just so what we owe an
+    // exit for a stream can be lined up against what the exit says it's owed for it.
+    fn more_money_payable_for_stream(
+        &self,
+        wallet_address: &Wallet,
+        correlation_id: &CorrelationId,
+        amount: u64,
+    ) -> Result<(), String> {
+        let mut stmt = self
+            .conn
+            .prepare(
+                "insert into payable_stream (wallet_address, stream_key, epoch, amount) values (?, ?, ?, ?) \
+                 on conflict (wallet_address, stream_key, epoch) do update set amount = amount + excluded.amount",
+            )
+            .expect("Internal error");
+        let params: &[&ToSql] = &[
+            &wallet_address.address,
+            &format!("{:?}", correlation_id.stream_key),
+            &(correlation_id.epoch as i64),
+            &(amount as i64),
+        ];
+        match stmt.execute(params) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(format!("{}", e)),
+        }
+    }
+
+    // A payment can be sent to a wallet we haven't recorded owing anything yet; it gets an account
+    // with a zero balance so that the confirmation has somewhere to land.
+    fn payment_sent(&self, wallet_address: &Wallet, pending_payment_transaction: &str) {
+        let timestamp = dao_utils::to_time_t(&self.time_source.now());
+        let mut stmt = self.conn
+            .prepare(
+                "insert into payable (wallet_address, balance, last_paid_timestamp, pending_payment_transaction) values (?, 0, ?, ?) \
+                 on conflict (wallet_address) do update set pending_payment_transaction = excluded.pending_payment_transaction",
+            )
+            .expect("Internal error");
+        let params: &[&ToSql] = &[
+            &wallet_address.address,
+            &timestamp,
+            &pending_payment_transaction,
+        ];
+        match stmt.execute(params) {
+            Ok(_) => (),
+            Err(e) => panic!("Database is corrupt: {}", e),
+        }
     }
 
+    // The payment is journaled in the same transaction, so the balance, the timestamp, the pending
+    // transaction and the journal all change together or not at all. Paying more than is owed
+    // leaves the wallet in credit with a negative balance, and a confirmation noticed late doesn't
+    // move last_paid_timestamp back in time.
     fn payment_confirmed(
         &self,
-        _wallet_address: &Wallet,
-        _amount: u64,
-        _confirmation_noticed_timestamp: &SystemTime,
+        wallet_address: &Wallet,
+        amount: u64,
+        confirmation_noticed_timestamp: &SystemTime,
     ) {
-        unimplemented!()
+        let result = dao_utils::in_transaction(&self.conn, || {
+            journal_dao::append(
+                &self.conn,
+                Account::Payable,
+                wallet_address,
+                EntryKind::Payment,
+                -(amount as i64),
+                confirmation_noticed_timestamp,
+            )?;
+            let mut stmt = self.conn
+                .prepare(
+                    "insert into payable (wallet_address, balance, last_paid_timestamp, pending_payment_transaction) values (?, ?, ?, null) \
+                     on conflict (wallet_address) do update set balance = balance + excluded.balance, \
+                     last_paid_timestamp = max(last_paid_timestamp, excluded.last_paid_timestamp), \
+                     pending_payment_transaction = null",
+                )
+                .expect("Internal error");
+            let params: &[&ToSql] = &[
+                &wallet_address.address,
+                &(-(amount as i64)),
+                &dao_utils::to_time_t(confirmation_noticed_timestamp),
+            ];
+            stmt.execute(params).map_err(|e| format!("{}", e))
+        });
+        match result {
+            Ok(_) => (),
+            Err(e) => panic!("Database is corrupt: {}", e),
+        }
     }
 
     fn account_status(&self, wallet_address: &Wallet) -> Option<PayableAccount> {
@@ -83,11 +182,26 @@ impl PayableDao for PayableDaoReal {
             Err(e) => panic!("Database is corrupt: {:?}", e),
         }
     }
+
+    // Accounts in credit don't make up for the ones that owe
+    fn total_owed(&self) -> i64 {
+        match self.conn.query_row(
+            "select coalesce(sum(balance), 0) from payable where balance > 0",
+            NO_PARAMS,
+            |row| row.get(0),
+        ) {
+            Ok(total) => total,
+            Err(e) => panic!("Database is corrupt: {}", e),
+        }
+    }
 }
 
 impl PayableDaoReal {
     pub fn new(conn: Connection) -> PayableDaoReal {
-        PayableDaoReal { conn }
+        PayableDaoReal {
+            conn,
+            time_source: Box::new(TimeSourceReal::default()),
+        }
     }
 
     fn try_update(&self, wallet_address: &Wallet, amount: u64) -> Result<bool, String> {
@@ -104,7 +218,7 @@ impl PayableDaoReal {
     }
 
     fn try_insert(&self, wallet_address: &Wallet, amount: u64) -> Result<(), String> {
-        let timestamp = dao_utils::to_time_t(&SystemTime::now());
+        let timestamp = dao_utils::to_time_t(&self.time_source.now());
         let mut stmt = self.conn
             .prepare("insert into payable (wallet_address, balance, last_paid_timestamp, pending_payment_transaction) values (?, ?, ?, null)")
             .expect("Internal error");
@@ -128,8 +242,11 @@ mod tests {
     use super::super::db_initializer::DbInitializerReal;
     use super::super::local_test_utils::ensure_node_home_directory_exists;
     use super::*;
+    use crate::test_utils::test_utils::make_meaningless_stream_key;
+    use crate::test_utils::time_source_mock::TimeSourceMock;
     use rusqlite::OpenFlags;
     use rusqlite::NO_PARAMS;
+    use std::time::Duration;
 
     #[test]
     fn more_money_payable_works_for_new_address() {
@@ -143,7 +260,7 @@ mod tests {
                 .unwrap()
                 .payable;
 
-            subject.more_money_payable(&wallet, 1234);
+            subject.more_money_payable(&wallet, 1234).unwrap();
             subject.account_status(&wallet).unwrap()
         };
 
@@ -175,7 +292,7 @@ mod tests {
                 .initialize(&home_dir)
                 .unwrap()
                 .payable;
-            subject.more_money_payable(&wallet, 1234);
+            subject.more_money_payable(&wallet, 1234).unwrap();
             let mut flags = OpenFlags::empty();
             flags.insert(OpenFlags::SQLITE_OPEN_READ_WRITE);
             let conn =
@@ -190,7 +307,7 @@ mod tests {
         };
 
         let status = {
-            subject.more_money_payable(&wallet, 2345);
+            subject.more_money_payable(&wallet, 2345).unwrap();
             subject.account_status(&wallet).unwrap()
         };
 
@@ -199,6 +316,243 @@ mod tests {
         assert_eq!(status.last_paid_timestamp, SystemTime::UNIX_EPOCH);
     }
 
+    #[test]
+    fn more_money_payable_for_stream_adds_up_by_stream_and_epoch() {
+        let home_dir = ensure_node_home_directory_exists(
+            "more_money_payable_for_stream_adds_up_by_stream_and_epoch",
+        );
+        let wallet = Wallet::new("booga");
+        let stream_key = make_meaningless_stream_key();
+        let correlation_id = CorrelationId {
+            stream_key,
+            epoch: 430_000,
+        };
+        let next_correlation_id = CorrelationId {
+            stream_key,
+            epoch: 430_001,
+        };
+        let subject = DbInitializerReal::new()
+            .initialize(&home_dir)
+            .unwrap()
+            .payable;
+
+        subject
+            .more_money_payable_for_stream(&wallet, &correlation_id, 1234)
+            .unwrap();
+        subject
+            .more_money_payable_for_stream(&wallet, &correlation_id, 2345)
+            .unwrap();
+        subject
+            .more_money_payable_for_stream(&wallet, &next_correlation_id, 3456)
+            .unwrap();
+
+        let conn = Connection::open_with_flags(
+            &home_dir.join(db_initializer::DATABASE_FILE),
+            OpenFlags::SQLITE_OPEN_READ_ONLY,
+        )
+        .unwrap();
+        let mut stmt = conn
+            .prepare("select wallet_address, stream_key, epoch, amount from payable_stream order by epoch")
+            .unwrap();
+        let rows = stmt
+            .query_map(NO_PARAMS, |row| {
+                (row.get(0), row.get(1), row.get(2), row.get(3))
+            })
+            .unwrap()
+            .map(|row| row.unwrap())
+            .collect::<Vec<(String, String, i64, i64)>>();
+        assert_eq!(
+            rows,
+            vec![
+                (
+                    String::from("booga"),
+                    format!("{:?}", stream_key),
+                    430_000,
+                    3579
+                ),
+                (
+                    String::from("booga"),
+                    format!("{:?}", stream_key),
+                    430_001,
+                    3456
+                ),
+            ]
+        );
+        assert_eq!(subject.account_status(&wallet), None);
+    }
+
+    #[test]
+    fn new_payable_account_is_timestamped_by_the_time_source() {
+        let home_dir = ensure_node_home_directory_exists(
+            "new_payable_account_is_timestamped_by_the_time_source",
+        );
+        DbInitializerReal::new().initialize(&home_dir).unwrap();
+        let mut flags = OpenFlags::empty();
+        flags.insert(OpenFlags::SQLITE_OPEN_READ_WRITE);
+        let conn =
+            Connection::open_with_flags(&home_dir.join(db_initializer::DATABASE_FILE), flags)
+                .unwrap();
+        let time_source = TimeSourceMock::new(SystemTime::UNIX_EPOCH + Duration::from_secs(1000));
+        let mut subject = PayableDaoReal::new(conn);
+        subject.time_source = Box::new(time_source.clone());
+        time_source.advance(Duration::from_secs(234));
+        let wallet = Wallet::new("booga");
+
+        subject.more_money_payable(&wallet, 1234).unwrap();
+
+        let status = subject.account_status(&wallet).unwrap();
+        assert_eq!(
+            status.last_paid_timestamp,
+            SystemTime::UNIX_EPOCH + Duration::from_secs(1234)
+        );
+    }
+
+    #[test]
+    fn payment_sent_records_the_pending_transaction() {
+        let home_dir =
+            ensure_node_home_directory_exists("payment_sent_records_the_pending_transaction");
+        let wallet = Wallet::new("booga");
+        let subject = DbInitializerReal::new()
+            .initialize(&home_dir)
+            .unwrap()
+            .payable;
+        subject.more_money_payable(&wallet, 1234).unwrap();
+        let before = subject.account_status(&wallet).unwrap();
+
+        subject.payment_sent(&wallet, "0xdeadbeef");
+
+        assert_eq!(
+            subject.account_status(&wallet),
+            Some(PayableAccount {
+                wallet_address: wallet,
+                balance: 1234,
+                last_paid_timestamp: before.last_paid_timestamp,
+                pending_payment_transaction: Some(String::from("0xdeadbeef")),
+            })
+        );
+    }
+
+    #[test]
+    fn payment_sent_to_an_unknown_wallet_opens_an_empty_account() {
+        let home_dir = ensure_node_home_directory_exists(
+            "payment_sent_to_an_unknown_wallet_opens_an_empty_account",
+        );
+        DbInitializerReal::new().initialize(&home_dir).unwrap();
+        let conn = Connection::open(&home_dir.join(db_initializer::DATABASE_FILE)).unwrap();
+        let time_source = TimeSourceMock::new(SystemTime::UNIX_EPOCH + Duration::from_secs(1000));
+        let mut subject = PayableDaoReal::new(conn);
+        subject.time_source = Box::new(time_source);
+        let wallet = Wallet::new("booga");
+
+        subject.payment_sent(&wallet, "0xdeadbeef");
+
+        assert_eq!(
+            subject.account_status(&wallet),
+            Some(PayableAccount {
+                wallet_address: wallet,
+                balance: 0,
+                last_paid_timestamp: SystemTime::UNIX_EPOCH + Duration::from_secs(1000),
+                pending_payment_transaction: Some(String::from("0xdeadbeef")),
+            })
+        );
+    }
+
+    #[test]
+    fn payment_confirmed_pays_down_the_balance_and_clears_the_pending_transaction() {
+        let home_dir = ensure_node_home_directory_exists(
+            "payment_confirmed_pays_down_the_balance_and_clears_the_pending_transaction",
+        );
+        let wallet = Wallet::new("booga");
+        let subject = DbInitializerReal::new()
+            .initialize(&home_dir)
+            .unwrap()
+            .payable;
+        subject.more_money_payable(&wallet, 1234).unwrap();
+        subject.payment_sent(&wallet, "0xdeadbeef");
+        let confirmed_at = SystemTime::UNIX_EPOCH + Duration::from_secs(2_000_000_000);
+
+        subject.payment_confirmed(&wallet, 1000, &confirmed_at);
+
+        assert_eq!(
+            subject.account_status(&wallet),
+            Some(PayableAccount {
+                wallet_address: wallet,
+                balance: 234,
+                last_paid_timestamp: confirmed_at,
+                pending_payment_transaction: None,
+            })
+        );
+    }
+
+    #[test]
+    fn payment_confirmed_leaves_an_overpaid_account_in_credit() {
+        let home_dir = ensure_node_home_directory_exists(
+            "payment_confirmed_leaves_an_overpaid_account_in_credit",
+        );
+        let wallet = Wallet::new("booga");
+        let subject = DbInitializerReal::new()
+            .initialize(&home_dir)
+            .unwrap()
+            .payable;
+        subject.more_money_payable(&wallet, 1234).unwrap();
+
+        subject.payment_confirmed(
+            &wallet,
+            2000,
+            &(SystemTime::UNIX_EPOCH + Duration::from_secs(2_000_000_000)),
+        );
+
+        let status = subject.account_status(&wallet).unwrap();
+        assert_eq!(status.balance, -766);
+    }
+
+    #[test]
+    fn payment_confirmed_for_an_unknown_wallet_opens_an_account_in_credit() {
+        let home_dir = ensure_node_home_directory_exists(
+            "payment_confirmed_for_an_unknown_wallet_opens_an_account_in_credit",
+        );
+        let wallet = Wallet::new("booga");
+        let subject = DbInitializerReal::new()
+            .initialize(&home_dir)
+            .unwrap()
+            .payable;
+        let confirmed_at = SystemTime::UNIX_EPOCH + Duration::from_secs(2_000_000_000);
+
+        subject.payment_confirmed(&wallet, 500, &confirmed_at);
+
+        assert_eq!(
+            subject.account_status(&wallet),
+            Some(PayableAccount {
+                wallet_address: wallet,
+                balance: -500,
+                last_paid_timestamp: confirmed_at,
+                pending_payment_transaction: None,
+            })
+        );
+    }
+
+    #[test]
+    fn payment_confirmed_late_does_not_move_the_timestamp_back() {
+        let home_dir = ensure_node_home_directory_exists(
+            "payment_confirmed_late_does_not_move_the_timestamp_back",
+        );
+        let wallet = Wallet::new("booga");
+        let subject = DbInitializerReal::new()
+            .initialize(&home_dir)
+            .unwrap()
+            .payable;
+        subject.more_money_payable(&wallet, 1234).unwrap();
+        let later = SystemTime::UNIX_EPOCH + Duration::from_secs(2_000_000_000);
+        let earlier = later - Duration::from_secs(3600);
+
+        subject.payment_confirmed(&wallet, 100, &later);
+        subject.payment_confirmed(&wallet, 200, &earlier);
+
+        let status = subject.account_status(&wallet).unwrap();
+        assert_eq!(status.balance, 934);
+        assert_eq!(status.last_paid_timestamp, later);
+    }
+
     #[test]
     fn payable_account_status_works_when_account_doesnt_exist() {
         let home_dir = ensure_node_home_directory_exists(
@@ -214,4 +568,24 @@ mod tests {
 
         assert_eq!(result, None);
     }
+
+    #[test]
+    fn total_owed_adds_up_only_the_accounts_that_are_owed_something() {
+        let home_dir = ensure_node_home_directory_exists(
+            "total_owed_adds_up_only_the_accounts_that_are_owed_something",
+        );
+        let subject = DbInitializerReal::new()
+            .initialize(&home_dir)
+            .unwrap()
+            .payable;
+        subject
+            .more_money_payable(&Wallet::new("one"), 100)
+            .unwrap();
+        subject.more_money_payable(&Wallet::new("two"), 23).unwrap();
+        subject.payment_confirmed(&Wallet::new("overpaid"), 500, &SystemTime::now());
+
+        let result = subject.total_owed();
+
+        assert_eq!(result, 123);
+    }
 }
@@ -1,9 +1,19 @@
 // Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
 use super::dao_utils;
+use super::journal_dao;
+use super::journal_dao::Account;
+use super::journal_dao::EntryKind;
+use crate::sub_lib::accountant::AgingBucket;
+use crate::sub_lib::accountant::CorrelationId;
+use crate::sub_lib::accountant::ReceivableAgingReport;
+use crate::sub_lib::accountant::AGING_BUCKET_WIDTH;
+use crate::sub_lib::time_source::TimeSource;
+use crate::sub_lib::time_source::TimeSourceReal;
 use crate::sub_lib::wallet::Wallet;
 use rusqlite::types::ToSql;
 use rusqlite::Connection;
 use rusqlite::OptionalExtension;
+use rusqlite::NO_PARAMS;
 use std::fmt::Debug;
 use std::time::SystemTime;
 
@@ -15,32 +25,111 @@ pub struct ReceivableAccount {
 }
 
 pub trait ReceivableDao: Debug {
-    fn more_money_receivable(&self, wallet_address: &Wallet, amount: u64);
+    fn more_money_receivable(&self, wallet_address: &Wallet, amount: u64) -> Result<(), String>;
+
+    fn more_money_receivable_for_stream(
+        &self,
+        wallet_address: &Wallet,
+        correlation_id: &CorrelationId,
+        amount: u64,
+    ) -> Result<(), String>;
 
     fn more_money_received(&self, wallet_address: &Wallet, amount: u64, timestamp: &SystemTime);
 
     fn account_status(&self, wallet_address: &Wallet) -> Option<ReceivableAccount>;
+
+    fn receivable_aging(&self) -> ReceivableAgingReport;
+
+    fn delinquent_wallets(&self) -> Vec<Wallet>;
+
+    fn total_owed(&self) -> i64;
 }
 
 #[derive(Debug)]
 pub struct ReceivableDaoReal {
     conn: Connection,
+    time_source: Box<dyn TimeSource>,
 }
 
 impl ReceivableDao for ReceivableDaoReal {
-    fn more_money_receivable(&self, wallet_address: &Wallet, amount: u64) {
-        match self.try_update(wallet_address, amount) {
-            Ok(true) => (),
-            Ok(false) => match self.try_insert(wallet_address, amount) {
-                Ok(_) => (),
-                Err(e) => panic!("Database is corrupt: {}", e),
-            },
-            Err(e) => panic!("Database is corrupt: {}", e),
-        };
+    fn more_money_receivable(&self, wallet_address: &Wallet, amount: u64) -> Result<(), String> {
+        dao_utils::in_transaction(&self.conn, || {
+            journal_dao::append(
+                &self.conn,
+                Account::Receivable,
+                wallet_address,
+                EntryKind::Service,
+                amount as i64,
+                &self.time_source.now(),
+            )?;
+            match self.try_update(wallet_address, amount) {
+                Ok(true) => Ok(()),
+                Ok(false) => self.try_insert(wallet_address, amount),
+                Err(e) => Err(e),
+            }
+        })
+    }
+
+    // The balance is charged separately, by more_money_receivable; this only records which stream
+    // the charge was for.
+    fn more_money_receivable_for_stream(
+        &self,
+        wallet_address: &Wallet,
+        correlation_id: &CorrelationId,
+        amount: u64,
+    ) -> Result<(), String> {
+        let mut stmt = self
+            .conn
+            .prepare(
+                "insert into receivable_stream (wallet_address, stream_key, epoch, amount) values (?, ?, ?, ?) \
+                 on conflict (wallet_address, stream_key, epoch) do update set amount = amount + excluded.amount",
+            )
+            .expect("Internal error");
+        let params: &[&ToSql] = &[
+            &wallet_address.address,
+            &format!("{:?}", correlation_id.stream_key),
+            &(correlation_id.epoch as i64),
+            &(amount as i64),
+        ];
+        match stmt.execute(params) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(format!("{}", e)),
+        }
     }
 
-    fn more_money_received(&self, _wallet_address: &Wallet, _amount: u64, _timestamp: &SystemTime) {
-        unimplemented!()
+    // Journaled in the same transaction, so the journal and the balance change together or not at
+    // all. A wallet that pays more than it owes, or pays before it owes anything, is left in credit
+    // with a negative balance. A payment noticed late doesn't move last_received_timestamp back in
+    // time.
+    fn more_money_received(&self, wallet_address: &Wallet, amount: u64, timestamp: &SystemTime) {
+        let result = dao_utils::in_transaction(&self.conn, || {
+            journal_dao::append(
+                &self.conn,
+                Account::Receivable,
+                wallet_address,
+                EntryKind::Payment,
+                -(amount as i64),
+                timestamp,
+            )?;
+            let mut stmt = self
+                .conn
+                .prepare(
+                    "insert into receivable (wallet_address, balance, last_received_timestamp) values (?, ?, ?) \
+                     on conflict (wallet_address) do update set balance = balance + excluded.balance, \
+                     last_received_timestamp = max(last_received_timestamp, excluded.last_received_timestamp)",
+                )
+                .expect("Internal error");
+            let params: &[&ToSql] = &[
+                &wallet_address.address,
+                &(-(amount as i64)),
+                &dao_utils::to_time_t(timestamp),
+            ];
+            stmt.execute(params).map_err(|e| format!("{}", e))
+        });
+        match result {
+            Ok(_) => (),
+            Err(e) => panic!("Database is corrupt: {}", e),
+        }
     }
 
     fn account_status(&self, wallet_address: &Wallet) -> Option<ReceivableAccount> {
@@ -66,11 +155,85 @@ impl ReceivableDao for ReceivableDaoReal {
             Err(e) => panic!("Database is corrupt: {:?}", e),
         }
     }
+
+    fn receivable_aging(&self) -> ReceivableAgingReport {
+        let now = dao_utils::to_time_t(&self.time_source.now());
+        let width = AGING_BUCKET_WIDTH.as_secs() as i64;
+        let mut stmt = self
+            .conn
+            .prepare(
+                "select case when last_received_timestamp > ? then 0 when last_received_timestamp > ? then 1 else 2 end as bucket, \
+                 count(*), sum(balance) from receivable where balance > 0 group by bucket",
+            )
+            .expect("Internal error");
+        let params: &[&ToSql] = &[&(now - width), &(now - 2 * width)];
+        let rows = match stmt.query_map(params, |row| (row.get(0), row.get(1), row.get(2))) {
+            Ok(rows) => rows,
+            Err(e) => panic!("Database is corrupt: {}", e),
+        };
+        let mut report = ReceivableAgingReport::default();
+        rows.for_each(|row| {
+            let (bucket_index, accounts, balance): (i64, i64, i64) = match row {
+                Ok(values) => values,
+                Err(e) => panic!("Database is corrupt: {}", e),
+            };
+            let bucket = AgingBucket {
+                accounts: accounts as u64,
+                balance,
+            };
+            match bucket_index {
+                0 => report.under_30_days = bucket,
+                1 => report.from_30_to_60_days = bucket,
+                _ => report.over_60_days = bucket,
+            }
+        });
+        report
+    }
+
+    // Wallets that owe something and haven't paid anything in the first aging bucket's width
+    fn delinquent_wallets(&self) -> Vec<Wallet> {
+        let now = dao_utils::to_time_t(&self.time_source.now());
+        let width = AGING_BUCKET_WIDTH.as_secs() as i64;
+        let mut stmt = self
+            .conn
+            .prepare(
+                "select wallet_address from receivable where balance > 0 and last_received_timestamp <= ? \
+                 order by wallet_address",
+            )
+            .expect("Internal error");
+        let rows = match stmt.query_map(&[now - width], |row| row.get(0)) {
+            Ok(rows) => rows,
+            Err(e) => panic!("Database is corrupt: {}", e),
+        };
+        rows.map(|row| {
+            let wallet_address: String = match row {
+                Ok(wallet_address) => wallet_address,
+                Err(e) => panic!("Database is corrupt: {}", e),
+            };
+            Wallet::new(&wallet_address)
+        })
+        .collect()
+    }
+
+    // Wallets in credit don't make up for the ones that owe
+    fn total_owed(&self) -> i64 {
+        match self.conn.query_row(
+            "select coalesce(sum(balance), 0) from receivable where balance > 0",
+            NO_PARAMS,
+            |row| row.get(0),
+        ) {
+            Ok(total) => total,
+            Err(e) => panic!("Database is corrupt: {}", e),
+        }
+    }
 }
 
 impl ReceivableDaoReal {
     pub fn new(conn: Connection) -> ReceivableDaoReal {
-        ReceivableDaoReal { conn }
+        ReceivableDaoReal {
+            conn,
+            time_source: Box::new(TimeSourceReal::default()),
+        }
     }
 
     fn try_update(&self, wallet_address: &Wallet, amount: u64) -> Result<bool, String> {
@@ -87,7 +250,7 @@ impl ReceivableDaoReal {
     }
 
     fn try_insert(&self, wallet_address: &Wallet, amount: u64) -> Result<(), String> {
-        let timestamp = dao_utils::to_time_t(&SystemTime::now());
+        let timestamp = dao_utils::to_time_t(&self.time_source.now());
         let mut stmt = self.conn.prepare ("insert into receivable (wallet_address, balance, last_received_timestamp) values (?, ?, ?)").expect ("Internal error");
         let params: &[&ToSql] = &[
             &wallet_address.address,
@@ -108,8 +271,13 @@ mod tests {
     use super::super::db_initializer::DbInitializerReal;
     use super::super::local_test_utils::*;
     use super::*;
+    use crate::test_utils::test_utils::make_meaningless_stream_key;
+    use crate::test_utils::time_source_mock::TimeSourceMock;
     use rusqlite::OpenFlags;
     use rusqlite::NO_PARAMS;
+    use std::panic;
+    use std::panic::AssertUnwindSafe;
+    use std::time::Duration;
 
     #[test]
     fn more_money_receivable_works_for_new_address() {
@@ -123,7 +291,7 @@ mod tests {
                 .unwrap()
                 .receivable;
 
-            subject.more_money_receivable(&wallet, 1234);
+            subject.more_money_receivable(&wallet, 1234).unwrap();
             subject.account_status(&wallet).unwrap()
         };
 
@@ -155,7 +323,7 @@ mod tests {
                 .initialize(&home_dir)
                 .unwrap()
                 .receivable;
-            subject.more_money_receivable(&wallet, 1234);
+            subject.more_money_receivable(&wallet, 1234).unwrap();
             let mut flags = OpenFlags::empty();
             flags.insert(OpenFlags::SQLITE_OPEN_READ_WRITE);
             let conn =
@@ -170,7 +338,7 @@ mod tests {
         };
 
         let status = {
-            subject.more_money_receivable(&wallet, 2345);
+            subject.more_money_receivable(&wallet, 2345).unwrap();
             subject.account_status(&wallet).unwrap()
         };
 
@@ -179,6 +347,253 @@ mod tests {
         assert_eq!(status.last_received_timestamp, SystemTime::UNIX_EPOCH);
     }
 
+    #[test]
+    fn more_money_receivable_for_stream_adds_up_by_stream_and_epoch() {
+        let home_dir = ensure_node_home_directory_exists(
+            "more_money_receivable_for_stream_adds_up_by_stream_and_epoch",
+        );
+        let wallet = Wallet::new("booga");
+        let stream_key = make_meaningless_stream_key();
+        let correlation_id = CorrelationId {
+            stream_key,
+            epoch: 430_000,
+        };
+        let next_correlation_id = CorrelationId {
+            stream_key,
+            epoch: 430_001,
+        };
+        let subject = DbInitializerReal::new()
+            .initialize(&home_dir)
+            .unwrap()
+            .receivable;
+
+        subject
+            .more_money_receivable_for_stream(&wallet, &correlation_id, 1234)
+            .unwrap();
+        subject
+            .more_money_receivable_for_stream(&wallet, &correlation_id, 2345)
+            .unwrap();
+        subject
+            .more_money_receivable_for_stream(&wallet, &next_correlation_id, 3456)
+            .unwrap();
+
+        let conn = Connection::open_with_flags(
+            &home_dir.join(db_initializer::DATABASE_FILE),
+            OpenFlags::SQLITE_OPEN_READ_ONLY,
+        )
+        .unwrap();
+        let mut stmt = conn
+            .prepare("select wallet_address, stream_key, epoch, amount from receivable_stream order by epoch")
+            .unwrap();
+        let rows = stmt
+            .query_map(NO_PARAMS, |row| {
+                (row.get(0), row.get(1), row.get(2), row.get(3))
+            })
+            .unwrap()
+            .map(|row| row.unwrap())
+            .collect::<Vec<(String, String, i64, i64)>>();
+        assert_eq!(
+            rows,
+            vec![
+                (
+                    String::from("booga"),
+                    format!("{:?}", stream_key),
+                    430_000,
+                    3579
+                ),
+                (
+                    String::from("booga"),
+                    format!("{:?}", stream_key),
+                    430_001,
+                    3456
+                ),
+            ]
+        );
+        assert_eq!(subject.account_status(&wallet), None);
+    }
+
+    #[test]
+    fn new_receivable_account_is_timestamped_by_the_time_source() {
+        let home_dir = ensure_node_home_directory_exists(
+            "new_receivable_account_is_timestamped_by_the_time_source",
+        );
+        DbInitializerReal::new().initialize(&home_dir).unwrap();
+        let mut flags = OpenFlags::empty();
+        flags.insert(OpenFlags::SQLITE_OPEN_READ_WRITE);
+        let conn =
+            Connection::open_with_flags(&home_dir.join(db_initializer::DATABASE_FILE), flags)
+                .unwrap();
+        let time_source = TimeSourceMock::new(SystemTime::UNIX_EPOCH + Duration::from_secs(1000));
+        let mut subject = ReceivableDaoReal::new(conn);
+        subject.time_source = Box::new(time_source.clone());
+        time_source.advance(Duration::from_secs(234));
+        let wallet = Wallet::new("booga");
+
+        subject.more_money_receivable(&wallet, 1234).unwrap();
+
+        let status = subject.account_status(&wallet).unwrap();
+        assert_eq!(
+            status.last_received_timestamp,
+            SystemTime::UNIX_EPOCH + Duration::from_secs(1234)
+        );
+    }
+
+    #[test]
+    fn more_money_received_pays_down_the_balance_and_records_when() {
+        let home_dir = ensure_node_home_directory_exists(
+            "more_money_received_pays_down_the_balance_and_records_when",
+        );
+        let wallet = Wallet::new("booga");
+        let subject = DbInitializerReal::new()
+            .initialize(&home_dir)
+            .unwrap()
+            .receivable;
+        subject.more_money_receivable(&wallet, 1234).unwrap();
+        let paid_at = SystemTime::UNIX_EPOCH + Duration::from_secs(2_000_000_000);
+
+        subject.more_money_received(&wallet, 1000, &paid_at);
+
+        assert_eq!(
+            subject.account_status(&wallet),
+            Some(ReceivableAccount {
+                wallet_address: wallet,
+                balance: 234,
+                last_received_timestamp: paid_at,
+            })
+        );
+    }
+
+    #[test]
+    fn more_money_received_leaves_an_overpaid_account_in_credit() {
+        let home_dir = ensure_node_home_directory_exists(
+            "more_money_received_leaves_an_overpaid_account_in_credit",
+        );
+        let wallet = Wallet::new("booga");
+        let subject = DbInitializerReal::new()
+            .initialize(&home_dir)
+            .unwrap()
+            .receivable;
+        subject.more_money_receivable(&wallet, 1234).unwrap();
+        let paid_at = SystemTime::UNIX_EPOCH + Duration::from_secs(2_000_000_000);
+
+        subject.more_money_received(&wallet, 2000, &paid_at);
+
+        let status = subject.account_status(&wallet).unwrap();
+        assert_eq!(status.balance, -766);
+        assert_eq!(status.last_received_timestamp, paid_at);
+    }
+
+    #[test]
+    fn more_money_received_from_an_unknown_wallet_opens_an_account_in_credit() {
+        let home_dir = ensure_node_home_directory_exists(
+            "more_money_received_from_an_unknown_wallet_opens_an_account_in_credit",
+        );
+        let wallet = Wallet::new("booga");
+        let subject = DbInitializerReal::new()
+            .initialize(&home_dir)
+            .unwrap()
+            .receivable;
+        let paid_at = SystemTime::UNIX_EPOCH + Duration::from_secs(2_000_000_000);
+
+        subject.more_money_received(&wallet, 500, &paid_at);
+
+        assert_eq!(
+            subject.account_status(&wallet),
+            Some(ReceivableAccount {
+                wallet_address: wallet,
+                balance: -500,
+                last_received_timestamp: paid_at,
+            })
+        );
+    }
+
+    #[test]
+    fn more_money_received_late_does_not_move_the_timestamp_back() {
+        let home_dir = ensure_node_home_directory_exists(
+            "more_money_received_late_does_not_move_the_timestamp_back",
+        );
+        let wallet = Wallet::new("booga");
+        let subject = DbInitializerReal::new()
+            .initialize(&home_dir)
+            .unwrap()
+            .receivable;
+        subject.more_money_receivable(&wallet, 1234).unwrap();
+        let later = SystemTime::UNIX_EPOCH + Duration::from_secs(2_000_000_000);
+        let earlier = later - Duration::from_secs(3600);
+
+        subject.more_money_received(&wallet, 100, &later);
+        subject.more_money_received(&wallet, 200, &earlier);
+
+        let status = subject.account_status(&wallet).unwrap();
+        assert_eq!(status.balance, 934);
+        assert_eq!(status.last_received_timestamp, later);
+    }
+
+    #[test]
+    fn more_money_received_changes_nothing_when_the_database_refuses_the_write() {
+        let home_dir = ensure_node_home_directory_exists(
+            "more_money_received_changes_nothing_when_the_database_refuses_the_write",
+        );
+        let wallet = Wallet::new("booga");
+        DbInitializerReal::new()
+            .initialize(&home_dir)
+            .unwrap()
+            .receivable
+            .more_money_receivable(&wallet, 1234)
+            .unwrap();
+        let read_only_subject = ReceivableDaoReal::new(
+            Connection::open_with_flags(
+                &home_dir.join(db_initializer::DATABASE_FILE),
+                OpenFlags::SQLITE_OPEN_READ_ONLY,
+            )
+            .unwrap(),
+        );
+
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            read_only_subject.more_money_received(&wallet, 1000, &SystemTime::now())
+        }));
+
+        assert_eq!(result.is_err(), true);
+        let status = read_only_subject.account_status(&wallet).unwrap();
+        assert_eq!(status.balance, 1234);
+    }
+
+    #[test]
+    fn more_money_receivable_reports_a_write_the_database_refuses() {
+        let home_dir = ensure_node_home_directory_exists(
+            "more_money_receivable_reports_a_write_the_database_refuses",
+        );
+        let wallet = Wallet::new("booga");
+        DbInitializerReal::new()
+            .initialize(&home_dir)
+            .unwrap()
+            .receivable
+            .more_money_receivable(&wallet, 1234)
+            .unwrap();
+        let read_only_subject = ReceivableDaoReal::new(
+            Connection::open_with_flags(
+                &home_dir.join(db_initializer::DATABASE_FILE),
+                OpenFlags::SQLITE_OPEN_READ_ONLY,
+            )
+            .unwrap(),
+        );
+
+        let result = read_only_subject.more_money_receivable(&wallet, 1000);
+        let stream_result = read_only_subject.more_money_receivable_for_stream(
+            &wallet,
+            &CorrelationId {
+                stream_key: make_meaningless_stream_key(),
+                epoch: 0,
+            },
+            1000,
+        );
+
+        assert_eq!(result.is_err(), true);
+        assert_eq!(stream_result.is_err(), true);
+        let status = read_only_subject.account_status(&wallet).unwrap();
+        assert_eq!(status.balance, 1234);
+    }
+
     #[test]
     fn receivable_account_status_works_when_account_doesnt_exist() {
         let home_dir = ensure_node_home_directory_exists(
@@ -194,4 +609,134 @@ mod tests {
 
         assert_eq!(result, None);
     }
+
+    #[test]
+    fn receivable_aging_sorts_balances_into_buckets_by_last_payment() {
+        let home_dir = ensure_node_home_directory_exists(
+            "receivable_aging_sorts_balances_into_buckets_by_last_payment",
+        );
+        DbInitializerReal::new().initialize(&home_dir).unwrap();
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(100_000_000);
+        let days_ago = |days: u64| now - Duration::from_secs(days * 24 * 60 * 60);
+        let mut flags = OpenFlags::empty();
+        flags.insert(OpenFlags::SQLITE_OPEN_READ_WRITE);
+        let conn =
+            Connection::open_with_flags(&home_dir.join(db_initializer::DATABASE_FILE), flags)
+                .unwrap();
+        vec![
+            ("fresh", 100, days_ago(0)),
+            ("recent", 200, days_ago(29)),
+            ("thirty", 40, days_ago(30)),
+            ("fifty_nine", 50, days_ago(59)),
+            ("sixty", 6, days_ago(60)),
+            ("ancient", 7, days_ago(365)),
+            ("paid_up", 0, days_ago(90)),
+            ("overpaid", -1000, days_ago(10)),
+        ]
+        .into_iter()
+        .for_each(|(wallet_address, balance, timestamp)| {
+            let params: &[&ToSql] = &[
+                &wallet_address,
+                &(balance as i64),
+                &dao_utils::to_time_t(&timestamp),
+            ];
+            conn.execute("insert into receivable (wallet_address, balance, last_received_timestamp) values (?, ?, ?)", params).unwrap();
+        });
+        let mut subject = ReceivableDaoReal::new(conn);
+        subject.time_source = Box::new(TimeSourceMock::new(now));
+
+        let result = subject.receivable_aging();
+
+        assert_eq!(
+            result,
+            ReceivableAgingReport {
+                under_30_days: AgingBucket {
+                    accounts: 2,
+                    balance: 300
+                },
+                from_30_to_60_days: AgingBucket {
+                    accounts: 2,
+                    balance: 90
+                },
+                over_60_days: AgingBucket {
+                    accounts: 2,
+                    balance: 13
+                },
+            }
+        );
+    }
+
+    #[test]
+    fn receivable_aging_is_empty_when_nobody_owes_anything() {
+        let home_dir = ensure_node_home_directory_exists(
+            "receivable_aging_is_empty_when_nobody_owes_anything",
+        );
+        let subject = DbInitializerReal::new()
+            .initialize(&home_dir)
+            .unwrap()
+            .receivable;
+
+        let result = subject.receivable_aging();
+
+        assert_eq!(result, ReceivableAgingReport::default());
+    }
+
+    #[test]
+    fn delinquent_wallets_are_the_ones_that_owe_and_have_not_paid_for_a_while() {
+        let home_dir = ensure_node_home_directory_exists(
+            "delinquent_wallets_are_the_ones_that_owe_and_have_not_paid_for_a_while",
+        );
+        DbInitializerReal::new().initialize(&home_dir).unwrap();
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(100_000_000);
+        let days_ago = |days: u64| now - Duration::from_secs(days * 24 * 60 * 60);
+        let mut flags = OpenFlags::empty();
+        flags.insert(OpenFlags::SQLITE_OPEN_READ_WRITE);
+        let conn =
+            Connection::open_with_flags(&home_dir.join(db_initializer::DATABASE_FILE), flags)
+                .unwrap();
+        vec![
+            ("recent", 200, days_ago(29)),
+            ("thirty", 40, days_ago(30)),
+            ("ancient", 7, days_ago(365)),
+            ("paid_up", 0, days_ago(90)),
+            ("overpaid", -1000, days_ago(90)),
+        ]
+        .into_iter()
+        .for_each(|(wallet_address, balance, timestamp)| {
+            let params: &[&ToSql] = &[
+                &wallet_address,
+                &(balance as i64),
+                &dao_utils::to_time_t(&timestamp),
+            ];
+            conn.execute("insert into receivable (wallet_address, balance, last_received_timestamp) values (?, ?, ?)", params).unwrap();
+        });
+        let mut subject = ReceivableDaoReal::new(conn);
+        subject.time_source = Box::new(TimeSourceMock::new(now));
+
+        let result = subject.delinquent_wallets();
+
+        assert_eq!(result, vec![Wallet::new("ancient"), Wallet::new("thirty")]);
+    }
+
+    #[test]
+    fn total_owed_adds_up_only_the_wallets_that_owe_something() {
+        let home_dir = ensure_node_home_directory_exists(
+            "total_owed_adds_up_only_the_wallets_that_owe_something",
+        );
+        let subject = DbInitializerReal::new()
+            .initialize(&home_dir)
+            .unwrap()
+            .receivable;
+        subject
+            .more_money_receivable(&Wallet::new("one"), 100)
+            .unwrap();
+        subject
+            .more_money_receivable(&Wallet::new("two"), 23)
+            .unwrap();
+        subject.more_money_received(&Wallet::new("overpaid"), 500, &SystemTime::now());
+
+        let result = subject.total_owed();
+
+        assert_eq!(result, 123);
+    }
 }
@@ -1,41 +1,140 @@
 // Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
 use super::accountant::accountant::Accountant;
+use super::blockchain_bridge::blockchain_bridge::BlockchainBridge;
+use super::blockchain_bridge::blockchain_interface::BlockchainInterfaceJsonRpc;
 use super::bootstrapper;
 use super::bootstrapper::BootstrapperConfig;
+use super::cover_traffic::CoverTraffic;
+use super::diagnostics;
 use super::discriminator::DiscriminatorFactory;
 use super::dispatcher::Dispatcher;
 use super::hopper::hopper::Hopper;
+use super::metrics::metrics::Metrics;
 use super::neighborhood::neighborhood::Neighborhood;
+use super::neighborhood::route_exclusion::RouteExclusionConfig;
+use super::probe_resistance::ProbeResistance;
 use super::proxy_client::proxy_client::ProxyClient;
+use super::proxy_server::privacy_policy::PrivacyPolicy;
 use super::proxy_server::proxy_server::ProxyServer;
+use super::proxy_server::retransmission_buffer::RETRANSMISSION_SPILL_DIRECTORY;
 use super::stream_handler_pool::StreamHandlerPool;
 use super::stream_handler_pool::StreamHandlerPoolSubs;
 use super::stream_messages::PoolBindMessage;
 use super::ui_gateway::ui_gateway::UiGateway;
 use crate::sub_lib::accountant::AccountantConfig;
 use crate::sub_lib::accountant::AccountantSubs;
+use crate::sub_lib::blockchain_bridge::BlockchainBridgeConfig;
 use crate::sub_lib::cryptde::CryptDE;
-use crate::sub_lib::cryptde_null::CryptDENull;
 use crate::sub_lib::dispatcher::DispatcherSubs;
 use crate::sub_lib::hopper::HopperSubs;
+use crate::sub_lib::hopper::RelayFairnessConfig;
+use crate::sub_lib::metrics::MetricsSubs;
 use crate::sub_lib::neighborhood::BootstrapNeighborhoodNowMessage;
+use crate::sub_lib::neighborhood::CapacityClass;
+use crate::sub_lib::neighborhood::ExitSla;
+use crate::sub_lib::neighborhood::NeighborBanConfig;
 use crate::sub_lib::neighborhood::NeighborhoodConfig;
 use crate::sub_lib::neighborhood::NeighborhoodSubs;
+use crate::sub_lib::neighborhood::NodeRecordPersistenceConfig;
+use crate::sub_lib::neighborhood::RateCard;
 use crate::sub_lib::peer_actors::BindMessage;
 use crate::sub_lib::peer_actors::PeerActors;
+use crate::sub_lib::performance_profile::PerformanceProfile;
+use crate::sub_lib::proxy_client::DnsResolverConfig;
+use crate::sub_lib::proxy_client::ExitConnectTimeouts;
+use crate::sub_lib::proxy_client::ExitLogConfig;
+use crate::sub_lib::proxy_client::ExitPolicy;
+use crate::sub_lib::proxy_client::ExitThroughputConfig;
 use crate::sub_lib::proxy_client::ProxyClientSubs;
+use crate::sub_lib::proxy_server::AdaptiveHopsConfig;
 use crate::sub_lib::proxy_server::ProxyServerSubs;
+use crate::sub_lib::proxy_server::RetransmissionConfig;
 use crate::sub_lib::ui_gateway::UiGatewayConfig;
 use crate::sub_lib::ui_gateway::UiGatewaySubs;
+use crate::sub_lib::ui_gateway::UiMessage;
+use crate::sub_lib::wallet::Wallet;
+use crate::sub_lib::wallet::WalletRotation;
 use actix::Actor;
+use actix::ActorAddress;
 use actix::Addr;
+use actix::Arbiter;
+use actix::Context;
 use actix::Recipient;
 use actix::Syn;
 use actix::System;
+use num_cpus;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::net::IpAddr;
 use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::sync::mpsc;
 use std::sync::mpsc::Sender;
 use std::thread;
+use std::time::Duration;
+
+// The actors that can be given an arbiter, and so a thread, of their own. The others read and write
+// sockets through the system arbiter's reactor and stay on it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum DedicatedActor {
+    Hopper,
+    Accountant,
+    Neighborhood,
+}
+
+impl DedicatedActor {
+    pub fn from_name(name: &str) -> Option<DedicatedActor> {
+        match name.trim() {
+            "hopper" => Some(DedicatedActor::Hopper),
+            "accountant" => Some(DedicatedActor::Accountant),
+            "neighborhood" => Some(DedicatedActor::Neighborhood),
+            _ => None,
+        }
+    }
+}
+
+// Which actors get arbiters of their own; everything else shares the system arbiter.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ArbiterLayout {
+    dedicated: HashSet<DedicatedActor>,
+}
+
+impl ArbiterLayout {
+    pub fn new(dedicated: Vec<DedicatedActor>) -> ArbiterLayout {
+        ArbiterLayout {
+            dedicated: dedicated.into_iter().collect(),
+        }
+    }
+
+    pub fn shared() -> ArbiterLayout {
+        ArbiterLayout::new(vec![])
+    }
+
+    // On a busy Node the Hopper's encryption is the first thing to crowd the other actors out,
+    // then the Accountant waiting on SQLite, then the Neighborhood's route searches, so that's the
+    // order they're moved out as cores allow. With only one core, more threads would just mean
+    // more context switches, so everything shares.
+    pub fn for_cpu_count(cpu_count: usize) -> ArbiterLayout {
+        ArbiterLayout::new(match cpu_count {
+            0..=1 => vec![],
+            2..=3 => vec![DedicatedActor::Hopper],
+            4..=7 => vec![DedicatedActor::Hopper, DedicatedActor::Accountant],
+            _ => vec![
+                DedicatedActor::Hopper,
+                DedicatedActor::Accountant,
+                DedicatedActor::Neighborhood,
+            ],
+        })
+    }
+
+    pub fn for_this_machine() -> ArbiterLayout {
+        ArbiterLayout::for_cpu_count(num_cpus::get())
+    }
+
+    pub fn is_dedicated(&self, actor: DedicatedActor) -> bool {
+        self.dedicated.contains(&actor)
+    }
+}
 
 pub trait ActorSystemFactory: Send {
     fn make_and_start_actors(
@@ -53,8 +152,12 @@ impl ActorSystemFactory for ActorSystemFactoryReal {
         config: BootstrapperConfig,
         actor_factory: Box<dyn ActorFactory>,
     ) -> StreamHandlerPoolSubs {
-        let cryptde: &'static CryptDENull =
-            unsafe { bootstrapper::CRYPT_DE_OPT.as_ref().expect("Internal error") };
+        let cryptde: &'static dyn CryptDE = unsafe {
+            bootstrapper::CRYPT_DE_OPT
+                .as_ref()
+                .expect("Internal error")
+                .as_ref()
+        };
         let (tx, rx) = mpsc::channel();
 
         // TODO: this thread::spawn goes away with actix 0.7
@@ -80,19 +183,130 @@ impl ActorSystemFactoryReal {
         tx: Sender<StreamHandlerPoolSubs>,
     ) {
         // make all the actors
-        let (dispatcher_subs, pool_bind_sub) = actor_factory.make_and_start_dispatcher();
-        let proxy_server_subs = actor_factory
-            .make_and_start_proxy_server(cryptde, config.neighborhood_config.is_decentralized());
-        let proxy_client_subs =
-            actor_factory.make_and_start_proxy_client(cryptde, config.dns_servers);
-        let hopper_subs = actor_factory
-            .make_and_start_hopper(cryptde, config.neighborhood_config.is_bootstrap_node);
-        let neighborhood_subs =
-            actor_factory.make_and_start_neighborhood(cryptde, config.neighborhood_config);
-        let accountant_subs = actor_factory.make_and_start_accountant(config.accountant_config);
+        let (dispatcher_subs, pool_bind_sub) = actor_factory.make_and_start_dispatcher(cryptde);
+        let node_record_persistence_config_opt =
+            config
+                .node_record_retention
+                .map(|retention| NodeRecordPersistenceConfig {
+                    data_directory: config.accountant_config.data_directory.clone(),
+                    retention,
+                });
+        let neighbor_ban_config_opt = match config.ban_threshold {
+            0 => None,
+            threshold => Some(NeighborBanConfig {
+                data_directory: config.accountant_config.data_directory.clone(),
+                threshold,
+            }),
+        };
+        let retransmission_config = RetransmissionConfig {
+            memory_limit: config.retransmission_memory,
+            spill_directory_opt: if config.retransmission_spill {
+                Some(
+                    config
+                        .accountant_config
+                        .data_directory
+                        .join(RETRANSMISSION_SPILL_DIRECTORY),
+                )
+            } else {
+                None
+            },
+        };
+        let proxy_server_subs = actor_factory.make_and_start_proxy_server(
+            cryptde,
+            config.neighborhood_config.is_decentralized(),
+            config.protocol_pack_ports.clone(),
+            config.privacy_policy.clone(),
+            config.privacy_policy_path.clone(),
+            config.request_exit_tls,
+            config.request_exit_dns_server,
+            config.exit_location.clone(),
+            config.upload_window,
+            retransmission_config,
+            config.socks_port,
+            config.http_proxy_port,
+            config.adaptive_hops.clone(),
+        );
+        let exit_log_config = ExitLogConfig {
+            level: config.exit_log_level,
+            data_directory: config.accountant_config.data_directory.clone(),
+        };
+        let proxy_client_subs = actor_factory.make_and_start_proxy_client(
+            cryptde,
+            config.dns_servers,
+            config.dns_resolver_config,
+            config.exit_throughput_config,
+            config.exit_connect_timeouts,
+            exit_log_config,
+            config.offer_exit_tls,
+            config.max_report_delay,
+            config.rate_card,
+            config.exit_policy,
+        );
+        let hopper_subs = actor_factory.make_and_start_hopper(
+            cryptde,
+            config.neighborhood_config.is_bootstrap_node,
+            config.performance_profile,
+            config.rate_card,
+            config.fragment_mtu,
+            config.pad_packets,
+            config.relay_fairness_config,
+            config.arbiter_layout.is_dedicated(DedicatedActor::Hopper),
+        );
+        // Cover traffic needs other Nodes to go to
+        let cover_traffic_opt = match config.cover_traffic {
+            Some(packages_per_minute) if config.neighborhood_config.is_decentralized() => {
+                Some(packages_per_minute)
+            }
+            _ => None,
+        };
+        // The BlockchainBridge only has work to do when there's a standby wallet to switch to or
+        // earning wallets to consolidate
+        let blockchain_bridge_config = BlockchainBridgeConfig {
+            consuming_opt: match (
+                config.neighborhood_config.consuming_wallet.clone(),
+                config.standby_consuming_wallet,
+            ) {
+                (Some(consuming_wallet), Some(standby_config)) => {
+                    Some((consuming_wallet, standby_config))
+                }
+                _ => None,
+            },
+            consolidation_opt: config.earning_consolidation,
+        };
+        let neighborhood_subs = actor_factory.make_and_start_neighborhood(
+            cryptde,
+            config.neighborhood_config,
+            config.capacity_class,
+            config.earning_wallet_rotation,
+            config.offer_exit_tls,
+            config.relay_only,
+            config.route_exclusion_config,
+            config.geoip_database_path,
+            node_record_persistence_config_opt,
+            neighbor_ban_config_opt,
+            config.performance_profile,
+            config.rate_card,
+            config.country.clone(),
+            config.exit_sla.clone(),
+            config.node_addr_version,
+            config
+                .arbiter_layout
+                .is_dedicated(DedicatedActor::Neighborhood),
+        );
+        let accountant_subs = actor_factory.make_and_start_accountant(
+            config.accountant_config,
+            config
+                .arbiter_layout
+                .is_dedicated(DedicatedActor::Accountant),
+        );
         let ui_gateway_subs = actor_factory.make_and_start_ui_gateway(config.ui_gateway_config);
-        let stream_handler_pool_subs = actor_factory
-            .make_and_start_stream_handler_pool(config.clandestine_discriminator_factories);
+        let metrics_subs = actor_factory.make_and_start_metrics(config.metrics_port);
+        let stream_handler_pool_subs = actor_factory.make_and_start_stream_handler_pool(
+            cryptde,
+            config.clandestine_discriminator_factories,
+            config.performance_profile,
+            config.probe_resistance,
+        );
 
         // collect all the subs
         let peer_actors = PeerActors {
@@ -103,6 +317,7 @@ impl ActorSystemFactoryReal {
             neighborhood: neighborhood_subs.clone(),
             accountant: accountant_subs.clone(),
             ui_gateway: ui_gateway_subs.clone(),
+            metrics: metrics_subs,
         };
 
         //bind all the actors
@@ -155,6 +370,29 @@ impl ActorSystemFactoryReal {
                 peer_actors: peer_actors.clone(),
             })
             .expect("UiGateway is dead");
+        peer_actors
+            .metrics
+            .bind
+            .try_send(BindMessage {
+                peer_actors: peer_actors.clone(),
+            })
+            .expect("Metrics is dead");
+        if let Some(packages_per_minute) = cover_traffic_opt {
+            actor_factory
+                .make_and_start_cover_traffic(cryptde, packages_per_minute)
+                .try_send(BindMessage {
+                    peer_actors: peer_actors.clone(),
+                })
+                .expect("CoverTraffic is dead");
+        }
+        if blockchain_bridge_config.has_work() {
+            actor_factory
+                .make_and_start_blockchain_bridge(blockchain_bridge_config)
+                .try_send(BindMessage {
+                    peer_actors: peer_actors.clone(),
+                })
+                .expect("BlockchainBridge is dead");
+        }
         stream_handler_pool_subs
             .bind
             .try_send(PoolBindMessage {
@@ -182,41 +420,104 @@ impl ActorSystemFactoryReal {
 }
 
 pub trait ActorFactory: Send {
-    fn make_and_start_dispatcher(&self) -> (DispatcherSubs, Recipient<Syn, PoolBindMessage>);
+    fn make_and_start_dispatcher(
+        &self,
+        cryptde: &'static dyn CryptDE,
+    ) -> (DispatcherSubs, Recipient<Syn, PoolBindMessage>);
     fn make_and_start_proxy_server(
         &self,
         cryptde: &'static dyn CryptDE,
         is_decentralized: bool,
+        protocol_pack_ports: HashMap<u16, String>,
+        privacy_policy_opt: Option<PrivacyPolicy>,
+        privacy_policy_path_opt: Option<PathBuf>,
+        request_exit_tls: bool,
+        exit_dns_server_opt: Option<IpAddr>,
+        exit_location_opt: Option<String>,
+        upload_window_opt: Option<u64>,
+        retransmission_config: RetransmissionConfig,
+        socks_port_opt: Option<u16>,
+        http_proxy_port_opt: Option<u16>,
+        adaptive_hops_config_opt: Option<AdaptiveHopsConfig>,
     ) -> ProxyServerSubs;
     fn make_and_start_hopper(
         &self,
         cryptde: &'static dyn CryptDE,
         is_bootstrap_node: bool,
+        performance_profile: PerformanceProfile,
+        rate_card: RateCard,
+        fragment_mtu_opt: Option<usize>,
+        pad_packets: bool,
+        relay_fairness_opt: Option<RelayFairnessConfig>,
+        dedicated_arbiter: bool,
     ) -> HopperSubs;
     fn make_and_start_neighborhood(
         &self,
         cryptde: &'static dyn CryptDE,
         config: NeighborhoodConfig,
+        capacity_class: CapacityClass,
+        earning_wallet_rotation: Option<WalletRotation>,
+        offer_exit_tls: bool,
+        relay_only: bool,
+        route_exclusion_config_opt: Option<RouteExclusionConfig>,
+        geoip_database_path_opt: Option<PathBuf>,
+        node_record_persistence_config_opt: Option<NodeRecordPersistenceConfig>,
+        neighbor_ban_config_opt: Option<NeighborBanConfig>,
+        performance_profile: PerformanceProfile,
+        rate_card: RateCard,
+        country_opt: Option<String>,
+        exit_sla_opt: Option<ExitSla>,
+        node_addr_version: u32,
+        dedicated_arbiter: bool,
     ) -> NeighborhoodSubs;
-    fn make_and_start_accountant(&self, config: AccountantConfig) -> AccountantSubs;
+    fn make_and_start_accountant(
+        &self,
+        config: AccountantConfig,
+        dedicated_arbiter: bool,
+    ) -> AccountantSubs;
     fn make_and_start_ui_gateway(&self, config: UiGatewayConfig) -> UiGatewaySubs;
+    fn make_and_start_metrics(&self, port_opt: Option<u16>) -> MetricsSubs;
     fn make_and_start_stream_handler_pool(
         &self,
+        cryptde: &'static dyn CryptDE,
         clandestine_discriminator_factories: Vec<Box<dyn DiscriminatorFactory>>,
+        performance_profile: PerformanceProfile,
+        probe_resistance: ProbeResistance,
     ) -> StreamHandlerPoolSubs;
     fn make_and_start_proxy_client(
         &self,
         cryptde: &'static dyn CryptDE,
         dns_servers: Vec<SocketAddr>,
+        dns_resolver_config: DnsResolverConfig,
+        exit_throughput_config: ExitThroughputConfig,
+        exit_connect_timeouts: ExitConnectTimeouts,
+        exit_log_config: ExitLogConfig,
+        offer_exit_tls: bool,
+        max_report_delay: Duration,
+        rate_card: RateCard,
+        exit_policy: ExitPolicy,
     ) -> ProxyClientSubs;
+    fn make_and_start_cover_traffic(
+        &self,
+        cryptde: &'static dyn CryptDE,
+        packages_per_minute: u32,
+    ) -> Recipient<Syn, BindMessage>;
+    fn make_and_start_blockchain_bridge(
+        &self,
+        config: BlockchainBridgeConfig,
+    ) -> Recipient<Syn, BindMessage>;
 }
 
 pub struct ActorFactoryReal {}
 
 impl ActorFactory for ActorFactoryReal {
-    fn make_and_start_dispatcher(&self) -> (DispatcherSubs, Recipient<Syn, PoolBindMessage>) {
-        let dispatcher = Dispatcher::new();
+    fn make_and_start_dispatcher(
+        &self,
+        cryptde: &'static dyn CryptDE,
+    ) -> (DispatcherSubs, Recipient<Syn, PoolBindMessage>) {
+        let dispatcher = Dispatcher::new(cryptde);
         let addr: Addr<Syn, Dispatcher> = dispatcher.start();
+        diagnostics::watch_mailbox("Dispatcher", &addr);
         (
             Dispatcher::make_subs_from(&addr),
             addr.recipient::<PoolBindMessage>(),
@@ -227,9 +528,50 @@ impl ActorFactory for ActorFactoryReal {
         &self,
         cryptde: &'static dyn CryptDE,
         is_decentralized: bool,
+        protocol_pack_ports: HashMap<u16, String>,
+        privacy_policy_opt: Option<PrivacyPolicy>,
+        privacy_policy_path_opt: Option<PathBuf>,
+        request_exit_tls: bool,
+        exit_dns_server_opt: Option<IpAddr>,
+        exit_location_opt: Option<String>,
+        upload_window_opt: Option<u64>,
+        retransmission_config: RetransmissionConfig,
+        socks_port_opt: Option<u16>,
+        http_proxy_port_opt: Option<u16>,
+        adaptive_hops_config_opt: Option<AdaptiveHopsConfig>,
     ) -> ProxyServerSubs {
-        let proxy_server = ProxyServer::new(cryptde, is_decentralized);
+        let mut proxy_server = ProxyServer::new(cryptde, is_decentralized);
+        proxy_server.map_protocol_packs(&protocol_pack_ports);
+        if let Some(privacy_policy) = privacy_policy_opt {
+            proxy_server.enable_privacy_filter(&privacy_policy);
+        }
+        if let Some(privacy_policy_path) = privacy_policy_path_opt {
+            proxy_server.reload_privacy_policy_from(&privacy_policy_path);
+        }
+        if request_exit_tls {
+            proxy_server.request_exit_tls();
+        }
+        if let Some(exit_dns_server) = exit_dns_server_opt {
+            proxy_server.request_exit_dns_server(exit_dns_server);
+        }
+        if let Some(exit_location) = exit_location_opt {
+            proxy_server.request_exit_location(exit_location);
+        }
+        if let Some(upload_window) = upload_window_opt {
+            proxy_server.limit_upload_window(upload_window);
+        }
+        proxy_server.enable_retransmission(&retransmission_config);
+        if let Some(socks_port) = socks_port_opt {
+            proxy_server.accept_socks_on(socks_port);
+        }
+        if let Some(http_proxy_port) = http_proxy_port_opt {
+            proxy_server.accept_http_proxy_on(http_proxy_port);
+        }
+        if let Some(adaptive_hops_config) = adaptive_hops_config_opt {
+            proxy_server.adapt_hop_count(&adaptive_hops_config);
+        }
         let addr: Addr<Syn, ProxyServer> = proxy_server.start();
+        diagnostics::watch_mailbox("ProxyServer", &addr);
         ProxyServer::make_subs_from(&addr)
     }
 
@@ -237,9 +579,23 @@ impl ActorFactory for ActorFactoryReal {
         &self,
         cryptde: &'static dyn CryptDE,
         is_bootstrap_node: bool,
+        performance_profile: PerformanceProfile,
+        rate_card: RateCard,
+        fragment_mtu_opt: Option<usize>,
+        pad_packets: bool,
+        relay_fairness_opt: Option<RelayFairnessConfig>,
+        dedicated_arbiter: bool,
     ) -> HopperSubs {
-        let hopper = Hopper::new(cryptde, is_bootstrap_node);
-        let addr: Addr<Syn, Hopper> = hopper.start();
+        let addr: Addr<Syn, Hopper> = start_actor(dedicated_arbiter, move || {
+            let mut hopper = Hopper::new(cryptde, is_bootstrap_node);
+            hopper.sample_load_every(performance_profile.load_sample_interval);
+            hopper.charge(rate_card);
+            hopper.fragment_payloads_over(fragment_mtu_opt);
+            hopper.pad_packets(pad_packets);
+            hopper.limit_relay_throughput(relay_fairness_opt);
+            hopper
+        });
+        diagnostics::watch_mailbox("Hopper", &addr);
         Hopper::make_subs_from(&addr)
     }
 
@@ -247,30 +603,98 @@ impl ActorFactory for ActorFactoryReal {
         &self,
         cryptde: &'static dyn CryptDE,
         config: NeighborhoodConfig,
+        capacity_class: CapacityClass,
+        earning_wallet_rotation: Option<WalletRotation>,
+        offer_exit_tls: bool,
+        relay_only: bool,
+        route_exclusion_config_opt: Option<RouteExclusionConfig>,
+        geoip_database_path_opt: Option<PathBuf>,
+        node_record_persistence_config_opt: Option<NodeRecordPersistenceConfig>,
+        neighbor_ban_config_opt: Option<NeighborBanConfig>,
+        performance_profile: PerformanceProfile,
+        rate_card: RateCard,
+        country_opt: Option<String>,
+        exit_sla_opt: Option<ExitSla>,
+        node_addr_version: u32,
+        dedicated_arbiter: bool,
     ) -> NeighborhoodSubs {
-        let neighborhood = Neighborhood::new(cryptde, config);
-        let addr: Addr<Syn, Neighborhood> = neighborhood.start();
+        let addr: Addr<Syn, Neighborhood> = start_actor(dedicated_arbiter, move || {
+            let mut neighborhood = Neighborhood::new(cryptde, config);
+            neighborhood.adopt_performance_profile(performance_profile);
+            neighborhood.advertise_capacity(capacity_class);
+            neighborhood.advertise_rate_card(rate_card);
+            if let Some(country) = country_opt {
+                neighborhood.advertise_country(country);
+            }
+            if let Some(exit_sla) = exit_sla_opt {
+                neighborhood.advertise_exit_sla(exit_sla);
+            }
+            neighborhood.start_at_version(node_addr_version);
+            if let Some(rotation) = earning_wallet_rotation {
+                neighborhood.rotate_earning_wallets(rotation);
+            }
+            if offer_exit_tls {
+                neighborhood.advertise_exit_tls();
+            }
+            if relay_only {
+                neighborhood.advertise_relay_only();
+            }
+            if let Some(route_exclusion_config) = route_exclusion_config_opt {
+                neighborhood.exclude_from_routes(route_exclusion_config);
+            }
+            if let Some(geoip_database_path) = geoip_database_path_opt {
+                neighborhood.reload_geoip_database_from(&geoip_database_path);
+            }
+            if let Some(node_record_persistence_config) = node_record_persistence_config_opt {
+                neighborhood.persist_node_records(node_record_persistence_config);
+            }
+            if let Some(neighbor_ban_config) = neighbor_ban_config_opt {
+                neighborhood.ban_misbehaving_neighbors(neighbor_ban_config);
+            }
+            neighborhood
+        });
+        diagnostics::watch_mailbox("Neighborhood", &addr);
         Neighborhood::make_subs_from(&addr)
     }
 
-    fn make_and_start_accountant(&self, config: AccountantConfig) -> AccountantSubs {
-        let accountant = Accountant::new(config);
-        let addr: Addr<Syn, Accountant> = accountant.start();
+    fn make_and_start_accountant(
+        &self,
+        config: AccountantConfig,
+        dedicated_arbiter: bool,
+    ) -> AccountantSubs {
+        let addr: Addr<Syn, Accountant> =
+            start_actor(dedicated_arbiter, move || Accountant::new(config));
+        diagnostics::watch_mailbox("Accountant", &addr);
         Accountant::make_subs_from(&addr)
     }
 
     fn make_and_start_ui_gateway(&self, config: UiGatewayConfig) -> UiGatewaySubs {
-        let ui_gateway = UiGateway::new(&config);
+        let mut ui_gateway = UiGateway::new(&config);
+        ui_gateway.handle_signals();
         let addr: Addr<Syn, UiGateway> = ui_gateway.start();
+        diagnostics::watch_mailbox("UiGateway", &addr);
+        diagnostics::alert_ui_with(addr.clone().recipient::<UiMessage>());
         UiGateway::make_subs_from(&addr)
     }
 
+    fn make_and_start_metrics(&self, port_opt: Option<u16>) -> MetricsSubs {
+        let addr: Addr<Syn, Metrics> = Metrics::new(port_opt).start();
+        diagnostics::watch_mailbox("Metrics", &addr);
+        Metrics::make_subs_from(&addr)
+    }
+
     fn make_and_start_stream_handler_pool(
         &self,
+        cryptde: &'static dyn CryptDE,
         clandestine_discriminator_factories: Vec<Box<dyn DiscriminatorFactory>>,
+        performance_profile: PerformanceProfile,
+        probe_resistance: ProbeResistance,
     ) -> StreamHandlerPoolSubs {
-        let pool = StreamHandlerPool::new(clandestine_discriminator_factories);
+        let mut pool = StreamHandlerPool::new(clandestine_discriminator_factories);
+        pool.read_in_chunks_of(performance_profile.stream_read_buffer_size);
+        pool.knock_with(cryptde, probe_resistance);
         let addr: Addr<Syn, StreamHandlerPool> = pool.start();
+        diagnostics::watch_mailbox("StreamHandlerPool", &addr);
         StreamHandlerPool::make_subs_from(&addr)
     }
 
@@ -278,40 +702,133 @@ impl ActorFactory for ActorFactoryReal {
         &self,
         cryptde: &'static dyn CryptDE,
         dns_servers: Vec<SocketAddr>,
+        dns_resolver_config: DnsResolverConfig,
+        exit_throughput_config: ExitThroughputConfig,
+        exit_connect_timeouts: ExitConnectTimeouts,
+        exit_log_config: ExitLogConfig,
+        offer_exit_tls: bool,
+        max_report_delay: Duration,
+        rate_card: RateCard,
+        exit_policy: ExitPolicy,
     ) -> ProxyClientSubs {
-        let proxy_client = ProxyClient::new(cryptde, dns_servers);
+        let mut proxy_client = ProxyClient::new(cryptde, dns_servers);
+        proxy_client.resolve_dns_with(&dns_resolver_config);
+        proxy_client.limit_exit_throughput(&exit_throughput_config);
+        proxy_client.limit_connect_time(&exit_connect_timeouts);
+        proxy_client.limit_report_delay(max_report_delay);
+        proxy_client.charge(rate_card);
+        proxy_client.enforce_exit_policy(&exit_policy);
+        proxy_client.enable_exit_log(&exit_log_config);
+        if offer_exit_tls {
+            proxy_client.offer_exit_tls();
+        }
         let addr: Addr<Syn, ProxyClient> = proxy_client.start();
+        diagnostics::watch_mailbox("ProxyClient", &addr);
         ProxyClient::make_subs_from(&addr)
     }
+
+    fn make_and_start_cover_traffic(
+        &self,
+        cryptde: &'static dyn CryptDE,
+        packages_per_minute: u32,
+    ) -> Recipient<Syn, BindMessage> {
+        let addr: Addr<Syn, CoverTraffic> = CoverTraffic::new(cryptde, packages_per_minute).start();
+        diagnostics::watch_mailbox("CoverTraffic", &addr);
+        addr.recipient::<BindMessage>()
+    }
+
+    fn make_and_start_blockchain_bridge(
+        &self,
+        config: BlockchainBridgeConfig,
+    ) -> Recipient<Syn, BindMessage> {
+        // Balance queries block until the blockchain service answers, so they mustn't hold up
+        // any other actor
+        let addr: Addr<Syn, BlockchainBridge> = start_actor(true, move || {
+            let interface = BlockchainInterfaceJsonRpc::new(
+                config
+                    .blockchain_service_url()
+                    .expect("BlockchainBridge has nothing to watch"),
+            );
+            BlockchainBridge::new(config, Box::new(interface))
+        });
+        diagnostics::watch_mailbox("BlockchainBridge", &addr);
+        addr.recipient::<BindMessage>()
+    }
+}
+
+// An actor on a dedicated arbiter is made on that arbiter's thread, so it needn't be Send itself.
+fn start_actor<A, F>(dedicated_arbiter: bool, make_actor: F) -> Addr<Syn, A>
+where
+    A: Actor<Context = Context<A>> + ActorAddress<A, Addr<Syn, A>>,
+    F: FnOnce() -> A + Send + 'static,
+{
+    if dedicated_arbiter {
+        Arbiter::start(move |_| make_actor())
+    } else {
+        make_actor().start()
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::bootstrapper::CryptoBackend;
     use crate::bootstrapper::CRYPT_DE_OPT;
+    use crate::neighborhood::route_exclusion::AsnDatabase;
+    use crate::neighborhood::route_exclusion::RouteExclusion;
     use crate::stream_messages::AddStreamMsg;
     use crate::stream_messages::RemoveStreamMsg;
+    use crate::sub_lib::accountant::BalancesQueryMessage;
+    use crate::sub_lib::accountant::ReceivableAgingQueryMessage;
     use crate::sub_lib::accountant::ReportExitServiceConsumedMessage;
     use crate::sub_lib::accountant::ReportExitServiceProvidedMessage;
     use crate::sub_lib::accountant::ReportRoutingServiceConsumedMessage;
     use crate::sub_lib::accountant::ReportRoutingServiceProvidedMessage;
+    use crate::sub_lib::accountant::SubscribeToConfigChangesMessage;
+    use crate::sub_lib::blockchain_bridge::EarningConsolidationConfig;
+    use crate::sub_lib::blockchain_bridge::ServiceUrl;
+    use crate::sub_lib::blockchain_bridge::StandbyConsumingWalletConfig;
     use crate::sub_lib::crash_point::CrashPoint;
     use crate::sub_lib::cryptde::PlainData;
+    use crate::sub_lib::cryptde::PublicKey;
+    use crate::sub_lib::cryptde_null::CryptDENull;
     use crate::sub_lib::dispatcher::InboundClientData;
+    use crate::sub_lib::dispatcher::StreamShutdownMsg;
     use crate::sub_lib::hopper::ExpiredCoresPackage;
+    use crate::sub_lib::hopper::HopperStatisticsQueryMessage;
     use crate::sub_lib::hopper::IncipientCoresPackage;
+    use crate::sub_lib::hopper::PaymentStandingMessage;
+    use crate::sub_lib::hopper::RegisterEndpointMessage;
+    use crate::sub_lib::metrics::MetricsReport;
     use crate::sub_lib::neighborhood::DispatcherNodeQueryMessage;
+    use crate::sub_lib::neighborhood::GossipStatsQueryMessage;
+    use crate::sub_lib::neighborhood::NeighborMisbehaviorMessage;
+    use crate::sub_lib::neighborhood::NeighborhoodStatusQueryMessage;
+    use crate::sub_lib::neighborhood::NodeProblemReport;
     use crate::sub_lib::neighborhood::NodeQueryMessage;
     use crate::sub_lib::neighborhood::RemoveNeighborMessage;
+    use crate::sub_lib::neighborhood::RouteFeedbackMessage;
     use crate::sub_lib::neighborhood::RouteQueryMessage;
+    use crate::sub_lib::neighborhood::RoutingLoadMessage;
+    use crate::sub_lib::neighborhood::SetConsumingWalletMessage;
+    use crate::sub_lib::neighborhood::TopologyQueryMessage;
+    use crate::sub_lib::node_addr::NodeAddr;
+    use crate::sub_lib::peer_actors::ShutdownMessage;
+    use crate::sub_lib::peer_actors::StreamCountQueryMessage;
+    use crate::sub_lib::proxy_client::DnsProtocol;
+    use crate::sub_lib::proxy_client::ExitLogLevel;
+    use crate::sub_lib::proxy_client::ExitThroughputQueryMessage;
     use crate::sub_lib::proxy_client::InboundServerData;
+    use crate::sub_lib::proxy_server::AdaptiveHopsMessage;
     use crate::sub_lib::proxy_server::AddReturnRouteMessage;
+    use crate::sub_lib::proxy_server::AddRouteMessage;
     use crate::sub_lib::stream_handler_pool::DispatcherNodeQueryResponse;
     use crate::sub_lib::stream_handler_pool::TransmitDataMsg;
     use crate::sub_lib::ui_gateway::FromUiMessage;
+    use crate::sub_lib::ui_gateway::ReloadConfigMessage;
     use crate::sub_lib::ui_gateway::UiGatewayConfig;
     use crate::sub_lib::ui_gateway::UiMessage;
-    use crate::sub_lib::wallet::Wallet;
+    use crate::test_utils::recorder::make_recorder;
     use crate::test_utils::recorder::Recorder;
     use crate::test_utils::recorder::Recording;
     use crate::test_utils::test_utils::cryptde;
@@ -334,17 +851,24 @@ mod tests {
         accountant: RefCell<Option<Recorder>>,
         stream_handler_pool: RefCell<Option<Recorder>>,
         ui_gateway: RefCell<Option<Recorder>>,
+        metrics: RefCell<Option<Recorder>>,
+        cover_traffic: RefCell<Option<Recorder>>,
+        blockchain_bridge: RefCell<Option<Recorder>>,
 
         parameters: Parameters<'a>,
     }
 
     impl<'a> ActorFactory for ActorFactoryMock<'a> {
-        fn make_and_start_dispatcher(&self) -> (DispatcherSubs, Recipient<Syn, PoolBindMessage>) {
+        fn make_and_start_dispatcher(
+            &self,
+            _cryptde: &'static dyn CryptDE,
+        ) -> (DispatcherSubs, Recipient<Syn, PoolBindMessage>) {
             let addr: Addr<Syn, Recorder> = ActorFactoryMock::start_recorder(&self.dispatcher);
             let dispatcher_subs = DispatcherSubs {
                 ibcd_sub: addr.clone().recipient::<InboundClientData>(),
                 bind: addr.clone().recipient::<BindMessage>(),
                 from_dispatcher_client: addr.clone().recipient::<TransmitDataMsg>(),
+                stream_shutdown_sub: addr.clone().recipient::<StreamShutdownMsg>(),
             };
             (dispatcher_subs, addr.recipient::<PoolBindMessage>())
         }
@@ -353,18 +877,48 @@ mod tests {
             &self,
             cryptde: &'a dyn CryptDE,
             is_decentralized: bool,
+            protocol_pack_ports: HashMap<u16, String>,
+            privacy_policy_opt: Option<PrivacyPolicy>,
+            privacy_policy_path_opt: Option<PathBuf>,
+            request_exit_tls: bool,
+            exit_dns_server_opt: Option<IpAddr>,
+            exit_location_opt: Option<String>,
+            upload_window_opt: Option<u64>,
+            retransmission_config: RetransmissionConfig,
+            socks_port_opt: Option<u16>,
+            http_proxy_port_opt: Option<u16>,
+            adaptive_hops_config_opt: Option<AdaptiveHopsConfig>,
         ) -> ProxyServerSubs {
             self.parameters
                 .proxy_server_params
                 .lock()
                 .unwrap()
-                .get_or_insert((cryptde, is_decentralized));
+                .get_or_insert((
+                    cryptde,
+                    is_decentralized,
+                    protocol_pack_ports,
+                    privacy_policy_opt,
+                    privacy_policy_path_opt,
+                    request_exit_tls,
+                    exit_dns_server_opt,
+                    exit_location_opt,
+                    upload_window_opt,
+                    retransmission_config,
+                    socks_port_opt,
+                    http_proxy_port_opt,
+                    adaptive_hops_config_opt,
+                ));
             let addr: Addr<Syn, Recorder> = ActorFactoryMock::start_recorder(&self.proxy_server);
             ProxyServerSubs {
                 bind: addr.clone().recipient::<BindMessage>(),
                 from_dispatcher: addr.clone().recipient::<InboundClientData>(),
                 from_hopper: addr.clone().recipient::<ExpiredCoresPackage>(),
                 add_return_route: addr.clone().recipient::<AddReturnRouteMessage>(),
+                add_route: addr.clone().recipient::<AddRouteMessage>(),
+                reload_config: addr.clone().recipient::<ReloadConfigMessage>(),
+                adaptive_hops: addr.clone().recipient::<AdaptiveHopsMessage>(),
+                stream_count_query: addr.clone().recipient::<StreamCountQueryMessage>(),
+                shutdown: addr.clone().recipient::<ShutdownMessage>(),
             }
         }
 
@@ -372,17 +926,35 @@ mod tests {
             &self,
             cryptde: &'a dyn CryptDE,
             is_bootstrap_node: bool,
+            performance_profile: PerformanceProfile,
+            rate_card: RateCard,
+            fragment_mtu_opt: Option<usize>,
+            pad_packets: bool,
+            relay_fairness_opt: Option<RelayFairnessConfig>,
+            dedicated_arbiter: bool,
         ) -> HopperSubs {
             self.parameters
                 .hopper_params
                 .lock()
                 .unwrap()
-                .get_or_insert((cryptde, is_bootstrap_node));
+                .get_or_insert((
+                    cryptde,
+                    is_bootstrap_node,
+                    performance_profile,
+                    rate_card,
+                    fragment_mtu_opt,
+                    pad_packets,
+                    relay_fairness_opt,
+                    dedicated_arbiter,
+                ));
             let addr: Addr<Syn, Recorder> = ActorFactoryMock::start_recorder(&self.hopper);
             HopperSubs {
                 bind: addr.clone().recipient::<BindMessage>(),
                 from_hopper_client: addr.clone().recipient::<IncipientCoresPackage>(),
                 from_dispatcher: addr.clone().recipient::<InboundClientData>(),
+                register_endpoint: addr.clone().recipient::<RegisterEndpointMessage>(),
+                statistics_query: addr.clone().recipient::<HopperStatisticsQueryMessage>(),
+                payment_standing: addr.clone().recipient::<PaymentStandingMessage>(),
             }
         }
 
@@ -390,12 +962,43 @@ mod tests {
             &self,
             cryptde: &'a dyn CryptDE,
             config: NeighborhoodConfig,
+            capacity_class: CapacityClass,
+            earning_wallet_rotation: Option<WalletRotation>,
+            offer_exit_tls: bool,
+            relay_only: bool,
+            route_exclusion_config_opt: Option<RouteExclusionConfig>,
+            geoip_database_path_opt: Option<PathBuf>,
+            node_record_persistence_config_opt: Option<NodeRecordPersistenceConfig>,
+            neighbor_ban_config_opt: Option<NeighborBanConfig>,
+            performance_profile: PerformanceProfile,
+            rate_card: RateCard,
+            country_opt: Option<String>,
+            exit_sla_opt: Option<ExitSla>,
+            node_addr_version: u32,
+            dedicated_arbiter: bool,
         ) -> NeighborhoodSubs {
             self.parameters
                 .neighborhood_params
                 .lock()
                 .unwrap()
-                .get_or_insert((cryptde, config));
+                .get_or_insert((
+                    cryptde,
+                    config,
+                    capacity_class,
+                    earning_wallet_rotation,
+                    offer_exit_tls,
+                    relay_only,
+                    route_exclusion_config_opt,
+                    geoip_database_path_opt,
+                    node_record_persistence_config_opt,
+                    neighbor_ban_config_opt,
+                    performance_profile,
+                    rate_card,
+                    country_opt,
+                    exit_sla_opt,
+                    node_addr_version,
+                    dedicated_arbiter,
+                ));
             let addr: Addr<Syn, Recorder> = ActorFactoryMock::start_recorder(&self.neighborhood);
             NeighborhoodSubs {
                 bind: addr.clone().recipient::<BindMessage>(),
@@ -405,15 +1008,29 @@ mod tests {
                 from_hopper: addr.clone().recipient::<ExpiredCoresPackage>(),
                 dispatcher_node_query: addr.clone().recipient::<DispatcherNodeQueryMessage>(),
                 remove_neighbor: addr.clone().recipient::<RemoveNeighborMessage>(),
+                set_consuming_wallet: addr.clone().recipient::<SetConsumingWalletMessage>(),
+                routing_load: addr.clone().recipient::<RoutingLoadMessage>(),
+                route_feedback: addr.clone().recipient::<RouteFeedbackMessage>(),
+                neighbor_misbehavior: addr.clone().recipient::<NeighborMisbehaviorMessage>(),
+                node_problem: addr.clone().recipient::<NodeProblemReport>(),
+                gossip_stats_query: addr.clone().recipient::<GossipStatsQueryMessage>(),
+                topology_query: addr.clone().recipient::<TopologyQueryMessage>(),
+                status_query: addr.clone().recipient::<NeighborhoodStatusQueryMessage>(),
+                reload_config: addr.clone().recipient::<ReloadConfigMessage>(),
+                shutdown: addr.clone().recipient::<ShutdownMessage>(),
             }
         }
 
-        fn make_and_start_accountant(&self, config: AccountantConfig) -> AccountantSubs {
+        fn make_and_start_accountant(
+            &self,
+            config: AccountantConfig,
+            dedicated_arbiter: bool,
+        ) -> AccountantSubs {
             self.parameters
                 .accountant_params
                 .lock()
                 .unwrap()
-                .get_or_insert(config);
+                .get_or_insert((config, dedicated_arbiter));
             let addr: Addr<Syn, Recorder> = ActorFactoryMock::start_recorder(&self.accountant);
             AccountantSubs {
                 bind: addr.clone().recipient::<BindMessage>(),
@@ -429,6 +1046,12 @@ mod tests {
                 report_exit_service_consumed: addr
                     .clone()
                     .recipient::<ReportExitServiceConsumedMessage>(),
+                subscribe_to_config_changes: addr
+                    .clone()
+                    .recipient::<SubscribeToConfigChangesMessage>(),
+                receivable_aging_query: addr.clone().recipient::<ReceivableAgingQueryMessage>(),
+                balances_query: addr.clone().recipient::<BalancesQueryMessage>(),
+                shutdown: addr.clone().recipient::<ShutdownMessage>(),
             }
         }
 
@@ -446,10 +1069,31 @@ mod tests {
             }
         }
 
+        fn make_and_start_metrics(&self, port_opt: Option<u16>) -> MetricsSubs {
+            self.parameters
+                .metrics_params
+                .lock()
+                .unwrap()
+                .get_or_insert(port_opt);
+            let addr: Addr<Syn, Recorder> = ActorFactoryMock::start_recorder(&self.metrics);
+            MetricsSubs {
+                bind: addr.clone().recipient::<BindMessage>(),
+                report: addr.clone().recipient::<MetricsReport>(),
+            }
+        }
+
         fn make_and_start_stream_handler_pool(
             &self,
+            cryptde: &'a dyn CryptDE,
             _: Vec<Box<dyn DiscriminatorFactory>>,
+            performance_profile: PerformanceProfile,
+            probe_resistance: ProbeResistance,
         ) -> StreamHandlerPoolSubs {
+            self.parameters
+                .stream_handler_pool_params
+                .lock()
+                .unwrap()
+                .get_or_insert((cryptde, performance_profile, probe_resistance));
             let addr: Addr<Syn, Recorder> =
                 ActorFactoryMock::start_recorder(&self.stream_handler_pool);
             StreamHandlerPoolSubs {
@@ -465,19 +1109,69 @@ mod tests {
             &self,
             cryptde: &'a dyn CryptDE,
             dns_servers: Vec<SocketAddr>,
+            dns_resolver_config: DnsResolverConfig,
+            exit_throughput_config: ExitThroughputConfig,
+            exit_connect_timeouts: ExitConnectTimeouts,
+            exit_log_config: ExitLogConfig,
+            offer_exit_tls: bool,
+            max_report_delay: Duration,
+            rate_card: RateCard,
+            exit_policy: ExitPolicy,
         ) -> ProxyClientSubs {
             self.parameters
                 .proxy_client_params
                 .lock()
                 .unwrap()
-                .get_or_insert((cryptde, dns_servers));
+                .get_or_insert((
+                    cryptde,
+                    dns_servers,
+                    dns_resolver_config,
+                    exit_throughput_config,
+                    exit_connect_timeouts,
+                    exit_log_config,
+                    offer_exit_tls,
+                    max_report_delay,
+                    rate_card,
+                    exit_policy,
+                ));
             let addr: Addr<Syn, Recorder> = ActorFactoryMock::start_recorder(&self.proxy_client);
             ProxyClientSubs {
                 bind: addr.clone().recipient::<BindMessage>(),
                 from_hopper: addr.clone().recipient::<ExpiredCoresPackage>(),
                 inbound_server_data: addr.clone().recipient::<InboundServerData>(),
+                stream_count_query: addr.clone().recipient::<StreamCountQueryMessage>(),
+                exit_throughput_query: addr.clone().recipient::<ExitThroughputQueryMessage>(),
+                shutdown: addr.clone().recipient::<ShutdownMessage>(),
             }
         }
+
+        fn make_and_start_cover_traffic(
+            &self,
+            cryptde: &'a dyn CryptDE,
+            packages_per_minute: u32,
+        ) -> Recipient<Syn, BindMessage> {
+            self.parameters
+                .cover_traffic_params
+                .lock()
+                .unwrap()
+                .get_or_insert((cryptde, packages_per_minute));
+            let addr: Addr<Syn, Recorder> = ActorFactoryMock::start_recorder(&self.cover_traffic);
+            addr.recipient::<BindMessage>()
+        }
+
+        fn make_and_start_blockchain_bridge(
+            &self,
+            config: BlockchainBridgeConfig,
+        ) -> Recipient<Syn, BindMessage> {
+            self.parameters
+                .blockchain_bridge_params
+                .lock()
+                .unwrap()
+                .get_or_insert(config);
+            let addr: Addr<Syn, Recorder> =
+                ActorFactoryMock::start_recorder(&self.blockchain_bridge);
+            addr.recipient::<BindMessage>()
+        }
     }
 
     struct Recordings {
@@ -489,16 +1183,91 @@ mod tests {
         accountant: Arc<Mutex<Recording>>,
         stream_handler_pool: Arc<Mutex<Recording>>,
         ui_gateway: Arc<Mutex<Recording>>,
+        metrics: Arc<Mutex<Recording>>,
+        cover_traffic: Arc<Mutex<Recording>>,
+        blockchain_bridge: Arc<Mutex<Recording>>,
     }
 
     #[derive(Clone)]
     struct Parameters<'a> {
-        proxy_client_params: Arc<Mutex<Option<(&'a dyn CryptDE, Vec<SocketAddr>)>>>,
-        proxy_server_params: Arc<Mutex<Option<(&'a dyn CryptDE, bool)>>>,
-        hopper_params: Arc<Mutex<Option<(&'a dyn CryptDE, bool)>>>,
-        neighborhood_params: Arc<Mutex<Option<(&'a dyn CryptDE, NeighborhoodConfig)>>>,
-        accountant_params: Arc<Mutex<Option<AccountantConfig>>>,
+        proxy_client_params: Arc<
+            Mutex<
+                Option<(
+                    &'a dyn CryptDE,
+                    Vec<SocketAddr>,
+                    DnsResolverConfig,
+                    ExitThroughputConfig,
+                    ExitConnectTimeouts,
+                    ExitLogConfig,
+                    bool,
+                    Duration,
+                    RateCard,
+                    ExitPolicy,
+                )>,
+            >,
+        >,
+        proxy_server_params: Arc<
+            Mutex<
+                Option<(
+                    &'a dyn CryptDE,
+                    bool,
+                    HashMap<u16, String>,
+                    Option<PrivacyPolicy>,
+                    Option<PathBuf>,
+                    bool,
+                    Option<IpAddr>,
+                    Option<String>,
+                    Option<u64>,
+                    RetransmissionConfig,
+                    Option<u16>,
+                    Option<u16>,
+                    Option<AdaptiveHopsConfig>,
+                )>,
+            >,
+        >,
+        hopper_params: Arc<
+            Mutex<
+                Option<(
+                    &'a dyn CryptDE,
+                    bool,
+                    PerformanceProfile,
+                    RateCard,
+                    Option<usize>,
+                    bool,
+                    Option<RelayFairnessConfig>,
+                    bool,
+                )>,
+            >,
+        >,
+        neighborhood_params: Arc<
+            Mutex<
+                Option<(
+                    &'a dyn CryptDE,
+                    NeighborhoodConfig,
+                    CapacityClass,
+                    Option<WalletRotation>,
+                    bool,
+                    bool,
+                    Option<RouteExclusionConfig>,
+                    Option<PathBuf>,
+                    Option<NodeRecordPersistenceConfig>,
+                    Option<NeighborBanConfig>,
+                    PerformanceProfile,
+                    RateCard,
+                    Option<String>,
+                    Option<ExitSla>,
+                    u32,
+                    bool,
+                )>,
+            >,
+        >,
+        accountant_params: Arc<Mutex<Option<(AccountantConfig, bool)>>>,
         ui_gateway_params: Arc<Mutex<Option<UiGatewayConfig>>>,
+        metrics_params: Arc<Mutex<Option<Option<u16>>>>,
+        stream_handler_pool_params:
+            Arc<Mutex<Option<(&'a dyn CryptDE, PerformanceProfile, ProbeResistance)>>>,
+        cover_traffic_params: Arc<Mutex<Option<(&'a dyn CryptDE, u32)>>>,
+        blockchain_bridge_params: Arc<Mutex<Option<BlockchainBridgeConfig>>>,
     }
 
     impl<'a> Parameters<'a> {
@@ -510,6 +1279,10 @@ mod tests {
                 neighborhood_params: Arc::new(Mutex::new(None)),
                 accountant_params: Arc::new(Mutex::new(None)),
                 ui_gateway_params: Arc::new(Mutex::new(None)),
+                metrics_params: Arc::new(Mutex::new(None)),
+                stream_handler_pool_params: Arc::new(Mutex::new(None)),
+                cover_traffic_params: Arc::new(Mutex::new(None)),
+                blockchain_bridge_params: Arc::new(Mutex::new(None)),
             }
         }
 
@@ -530,6 +1303,9 @@ mod tests {
                 accountant: RefCell::new(Some(Recorder::new())),
                 stream_handler_pool: RefCell::new(Some(Recorder::new())),
                 ui_gateway: RefCell::new(Some(Recorder::new())),
+                metrics: RefCell::new(Some(Recorder::new())),
+                cover_traffic: RefCell::new(Some(Recorder::new())),
+                blockchain_bridge: RefCell::new(Some(Recorder::new())),
 
                 parameters: Parameters::new(),
             }
@@ -550,6 +1326,19 @@ mod tests {
                     .unwrap()
                     .get_recording(),
                 ui_gateway: self.ui_gateway.borrow().as_ref().unwrap().get_recording(),
+                metrics: self.metrics.borrow().as_ref().unwrap().get_recording(),
+                cover_traffic: self
+                    .cover_traffic
+                    .borrow()
+                    .as_ref()
+                    .unwrap()
+                    .get_recording(),
+                blockchain_bridge: self
+                    .blockchain_bridge
+                    .borrow()
+                    .as_ref()
+                    .unwrap()
+                    .get_recording(),
             }
         }
 
@@ -569,6 +1358,7 @@ mod tests {
         let config = BootstrapperConfig {
             crash_point: CrashPoint::None,
             dns_servers: vec![],
+            dns_resolver_config: DnsResolverConfig::default(),
             neighborhood_config: NeighborhoodConfig {
                 neighbor_configs: vec![],
                 is_bootstrap_node: false,
@@ -581,11 +1371,65 @@ mod tests {
                 data_directory: PathBuf::new(),
             },
             clandestine_discriminator_factories: Vec::new(),
-            ui_gateway_config: UiGatewayConfig { ui_port: 5335 },
+            ui_gateway_config: UiGatewayConfig {
+                ui_port: 5335,
+                startup_summary: None,
+                firewall_directory: None,
+                alias_directory: None,
+                remote_access: None,
+                local_token: None,
+                token_directory: None,
+            },
+            exit_throughput_config: ExitThroughputConfig {
+                upstream_bytes_per_second: None,
+                downstream_bytes_per_second: None,
+            },
+            exit_connect_timeouts: ExitConnectTimeouts::default(),
+            capacity_class: CapacityClass::Medium,
+            earning_wallet_rotation: None,
+            standby_consuming_wallet: None,
+            earning_consolidation: None,
+            protocol_pack_ports: HashMap::new(),
+            exit_log_level: ExitLogLevel::Off,
+            privacy_policy: None,
+            privacy_policy_path: None,
+            request_exit_tls: false,
+            request_exit_dns_server: None,
+            exit_location: None,
+            offer_exit_tls: false,
+            relay_only: false,
+            persistent_identity: false,
+            crypto_backend: CryptoBackend::Null,
+            manage_firewall: false,
+            probe_resistance: ProbeResistance::Off,
+            max_report_delay: Duration::from_secs(60),
+            route_exclusion_config: None,
+            geoip_database_path: None,
+            node_record_retention: None,
+            ban_threshold: 0,
+            upload_window: None,
+            retransmission_memory: 1000,
+            retransmission_spill: false,
+            socks_port: None,
+            http_proxy_port: None,
+            arbiter_layout: ArbiterLayout::shared(),
+            performance_profile: PerformanceProfile::standard(),
+            rate_card: RateCard::default(),
+            country: None,
+            exit_policy: ExitPolicy::default(),
+            exit_sla: None,
+            adaptive_hops: None,
+            fragment_mtu: None,
+            pad_packets: false,
+            cover_traffic: None,
+            relay_fairness_config: None,
+            metrics_port: None,
+            regenerate_ports: false,
+            node_addr_version: 0,
         };
         let subject = ActorSystemFactoryReal {};
         unsafe {
-            CRYPT_DE_OPT = Some(CryptDENull::new());
+            CRYPT_DE_OPT = Some(Box::new(CryptDENull::new()));
         }
 
         subject.make_and_start_actors(config, Box::new(actor_factory));
@@ -598,6 +1442,7 @@ mod tests {
         Recording::get::<BindMessage>(&recordings.neighborhood, 0);
         Recording::get::<BindMessage>(&recordings.accountant, 0);
         Recording::get::<BindMessage>(&recordings.ui_gateway, 0);
+        Recording::get::<BindMessage>(&recordings.metrics, 0);
         Recording::get::<PoolBindMessage>(&recordings.stream_handler_pool, 0);
         Recording::get::<BootstrapNeighborhoodNowMessage>(&recordings.neighborhood, 1);
     }
@@ -610,6 +1455,15 @@ mod tests {
         let config = BootstrapperConfig {
             crash_point: CrashPoint::None,
             dns_servers: vec![],
+            dns_resolver_config: DnsResolverConfig {
+                protocol: DnsProtocol::Tls,
+                tls_dns_names: vec![(
+                    IpAddr::V4(Ipv4Addr::new(1, 1, 1, 1)),
+                    String::from("cloudflare-dns.com"),
+                )]
+                .into_iter()
+                .collect(),
+            },
             neighborhood_config: NeighborhoodConfig {
                 neighbor_configs: vec![],
                 is_bootstrap_node: false,
@@ -622,7 +1476,97 @@ mod tests {
                 data_directory: PathBuf::new(),
             },
             clandestine_discriminator_factories: Vec::new(),
-            ui_gateway_config: UiGatewayConfig { ui_port: 5335 },
+            ui_gateway_config: UiGatewayConfig {
+                ui_port: 5335,
+                startup_summary: None,
+                firewall_directory: None,
+                alias_directory: None,
+                remote_access: None,
+                local_token: None,
+                token_directory: None,
+            },
+            exit_throughput_config: ExitThroughputConfig {
+                upstream_bytes_per_second: Some(1000),
+                downstream_bytes_per_second: Some(5000),
+            },
+            exit_connect_timeouts: ExitConnectTimeouts {
+                default: Duration::from_secs(5),
+                by_port: vec![(993, Duration::from_secs(30))].into_iter().collect(),
+            },
+            capacity_class: CapacityClass::High,
+            earning_wallet_rotation: Some(WalletRotation::new(
+                vec![Wallet::new("first"), Wallet::new("second")],
+                Duration::from_secs(3600),
+            )),
+            standby_consuming_wallet: None,
+            earning_consolidation: None,
+            protocol_pack_ports: vec![(8080, String::from("http"))].into_iter().collect(),
+            exit_log_level: ExitLogLevel::Aggregate,
+            privacy_policy: Some(
+                PrivacyPolicy::from_json(r#"{"strip_headers": ["DNT"]}"#).unwrap(),
+            ),
+            privacy_policy_path: Some(PathBuf::from("privacy.json")),
+            request_exit_tls: true,
+            request_exit_dns_server: Some(IpAddr::V4(Ipv4Addr::new(9, 9, 9, 9))),
+            exit_location: Some(String::from("DE")),
+            offer_exit_tls: true,
+            relay_only: true,
+            persistent_identity: false,
+            crypto_backend: CryptoBackend::Null,
+            manage_firewall: false,
+            probe_resistance: ProbeResistance::Silent,
+            max_report_delay: Duration::from_secs(15),
+            route_exclusion_config: Some(RouteExclusionConfig {
+                database: AsnDatabase::from_tsv("1.2.0.0\t1.2.255.255\t7922\tUS\tCOMCAST\n")
+                    .unwrap(),
+                exclusions: vec![RouteExclusion::OwnAsn],
+            }),
+            geoip_database_path: Some(PathBuf::from("ip2asn.tsv")),
+            node_record_retention: Some(Duration::from_secs(86400)),
+            ban_threshold: 7,
+            upload_window: Some(32),
+            retransmission_memory: 2000,
+            retransmission_spill: true,
+            socks_port: Some(1080),
+            http_proxy_port: Some(8118),
+            arbiter_layout: ArbiterLayout::new(vec![
+                DedicatedActor::Hopper,
+                DedicatedActor::Neighborhood,
+            ]),
+            performance_profile: PerformanceProfile::low_power(),
+            rate_card: RateCard {
+                routing_service_rate: 10,
+                routing_byte_rate: 20,
+                exit_service_rate: 30,
+                exit_byte_rate: 40,
+            },
+            country: Some(String::from("FR")),
+            exit_policy: ExitPolicy {
+                blocked_ports: vec![25],
+                blocked_hostnames: vec![String::from("blocked.com")],
+                blocked_ip_addrs: vec![IpAddr::V4(Ipv4Addr::new(6, 6, 6, 6))],
+                offered_dns_servers: vec![IpAddr::V4(Ipv4Addr::new(9, 9, 9, 9))],
+            },
+            exit_sla: Some(ExitSla {
+                max_streams: 50,
+                max_stream_bytes_per_second: 1000000,
+                content_policy_hash: String::from("hash"),
+            }),
+            adaptive_hops: Some(AdaptiveHopsConfig {
+                min_hops: 1,
+                max_hops: 4,
+                latency_threshold: Duration::from_millis(900),
+            }),
+            fragment_mtu: Some(1200),
+            pad_packets: true,
+            cover_traffic: Some(6),
+            relay_fairness_config: Some(RelayFairnessConfig {
+                bytes_per_second: 500000,
+                weighted_by_standing: true,
+            }),
+            metrics_port: Some(9100),
+            regenerate_ports: false,
+            node_addr_version: 3,
         };
         let (tx, rx) = mpsc::channel();
         let system = System::new("SubstratumNode");
@@ -642,23 +1586,366 @@ mod tests {
         check_bind_message(&recordings.proxy_server);
         check_bind_message(&recordings.neighborhood);
         check_bind_message(&recordings.ui_gateway);
-        let (cryptde, is_bootstrap_node) = Parameters::get(parameters.hopper_params);
+        check_bind_message(&recordings.metrics);
+        let (
+            cryptde,
+            is_bootstrap_node,
+            hopper_performance_profile,
+            hopper_rate_card,
+            hopper_fragment_mtu_opt,
+            hopper_pad_packets,
+            hopper_relay_fairness_opt,
+            hopper_dedicated_arbiter,
+        ) = Parameters::get(parameters.hopper_params);
         check_cryptde(cryptde);
         assert_eq!(is_bootstrap_node, false);
-        let (cryptde, dns_servers) = Parameters::get(parameters.proxy_client_params);
+        assert_eq!(hopper_performance_profile, PerformanceProfile::low_power());
+        assert_eq!(hopper_rate_card, config.rate_card);
+        assert_eq!(hopper_fragment_mtu_opt, Some(1200));
+        assert_eq!(hopper_pad_packets, true);
+        assert_eq!(hopper_relay_fairness_opt, config.relay_fairness_config);
+        assert_eq!(hopper_dedicated_arbiter, true);
+        let (
+            cryptde,
+            dns_servers,
+            dns_resolver_config,
+            exit_throughput_config,
+            exit_connect_timeouts,
+            exit_log_config,
+            offer_exit_tls,
+            max_report_delay,
+            proxy_client_rate_card,
+            exit_policy,
+        ) = Parameters::get(parameters.proxy_client_params);
         check_cryptde(cryptde);
         assert_eq!(dns_servers, config.dns_servers);
-        let (actual_cryptde, actual_is_decentralized) =
-            Parameters::get(parameters.proxy_server_params);
+        assert_eq!(dns_resolver_config, config.dns_resolver_config);
+        assert_eq!(exit_throughput_config, config.exit_throughput_config);
+        assert_eq!(exit_connect_timeouts, config.exit_connect_timeouts);
+        assert_eq!(
+            exit_log_config,
+            ExitLogConfig {
+                level: ExitLogLevel::Aggregate,
+                data_directory: PathBuf::new(),
+            }
+        );
+        assert_eq!(offer_exit_tls, true);
+        assert_eq!(max_report_delay, Duration::from_secs(15));
+        assert_eq!(proxy_client_rate_card, config.rate_card);
+        assert_eq!(exit_policy, config.exit_policy);
+        let (
+            actual_cryptde,
+            actual_is_decentralized,
+            actual_protocol_pack_ports,
+            actual_privacy_policy_opt,
+            actual_privacy_policy_path_opt,
+            actual_request_exit_tls,
+            actual_exit_dns_server_opt,
+            actual_exit_location_opt,
+            actual_upload_window_opt,
+            actual_retransmission_config,
+            actual_socks_port_opt,
+            actual_http_proxy_port_opt,
+            actual_adaptive_hops_config_opt,
+        ) = Parameters::get(parameters.proxy_server_params);
         check_cryptde(actual_cryptde);
         assert_eq!(actual_is_decentralized, false);
-        let (cryptde, neighborhood_config) = Parameters::get(parameters.neighborhood_params);
+        assert_eq!(actual_protocol_pack_ports, config.protocol_pack_ports);
+        assert_eq!(actual_privacy_policy_opt, config.privacy_policy);
+        assert_eq!(
+            actual_privacy_policy_path_opt,
+            Some(PathBuf::from("privacy.json"))
+        );
+        assert_eq!(actual_request_exit_tls, true);
+        assert_eq!(
+            actual_exit_dns_server_opt,
+            Some(IpAddr::V4(Ipv4Addr::new(9, 9, 9, 9)))
+        );
+        assert_eq!(actual_exit_location_opt, Some(String::from("DE")));
+        assert_eq!(actual_upload_window_opt, Some(32));
+        assert_eq!(
+            actual_retransmission_config,
+            RetransmissionConfig {
+                memory_limit: 2000,
+                spill_directory_opt: Some(PathBuf::from("retransmission")),
+            }
+        );
+        assert_eq!(actual_socks_port_opt, Some(1080));
+        assert_eq!(actual_http_proxy_port_opt, Some(8118));
+        assert_eq!(actual_adaptive_hops_config_opt, config.adaptive_hops);
+        let (
+            cryptde,
+            neighborhood_config,
+            capacity_class,
+            earning_wallet_rotation,
+            offers_exit_tls,
+            relay_only,
+            route_exclusion_config_opt,
+            geoip_database_path_opt,
+            node_record_persistence_config_opt,
+            neighbor_ban_config_opt,
+            neighborhood_performance_profile,
+            neighborhood_rate_card,
+            neighborhood_country_opt,
+            neighborhood_exit_sla_opt,
+            node_addr_version,
+            neighborhood_dedicated_arbiter,
+        ) = Parameters::get(parameters.neighborhood_params);
         check_cryptde(cryptde);
         assert_eq!(neighborhood_config, config.neighborhood_config);
+        assert_eq!(capacity_class, CapacityClass::High);
+        assert_eq!(earning_wallet_rotation, config.earning_wallet_rotation);
+        assert_eq!(offers_exit_tls, true);
+        assert_eq!(relay_only, true);
+        assert_eq!(route_exclusion_config_opt, config.route_exclusion_config);
+        assert_eq!(geoip_database_path_opt, Some(PathBuf::from("ip2asn.tsv")));
+        assert_eq!(
+            node_record_persistence_config_opt,
+            Some(NodeRecordPersistenceConfig {
+                data_directory: PathBuf::new(),
+                retention: Duration::from_secs(86400),
+            })
+        );
+        assert_eq!(
+            neighbor_ban_config_opt,
+            Some(NeighborBanConfig {
+                data_directory: PathBuf::new(),
+                threshold: 7,
+            })
+        );
+        assert_eq!(
+            neighborhood_performance_profile,
+            PerformanceProfile::low_power()
+        );
+        assert_eq!(neighborhood_rate_card, config.rate_card);
+        assert_eq!(neighborhood_country_opt, Some(String::from("FR")));
+        assert_eq!(neighborhood_exit_sla_opt, config.exit_sla);
+        assert_eq!(node_addr_version, 3);
+        assert_eq!(neighborhood_dedicated_arbiter, true);
+        let (_, accountant_dedicated_arbiter) = Parameters::get(parameters.accountant_params);
+        assert_eq!(accountant_dedicated_arbiter, false);
         let ui_gateway_config = Parameters::get(parameters.ui_gateway_params);
         assert_eq!(ui_gateway_config.ui_port, 5335);
+        assert_eq!(Parameters::get(parameters.metrics_params), Some(9100));
+        let (
+            stream_handler_pool_cryptde,
+            stream_handler_pool_performance_profile,
+            stream_handler_pool_probe_resistance,
+        ) = Parameters::get(parameters.stream_handler_pool_params);
+        check_cryptde(stream_handler_pool_cryptde);
+        assert_eq!(
+            stream_handler_pool_performance_profile,
+            PerformanceProfile::low_power()
+        );
+        assert_eq!(
+            stream_handler_pool_probe_resistance,
+            ProbeResistance::Silent
+        );
         let _stream_handler_pool_subs = rx.recv().unwrap();
         // more...more...what? How to check contents of _stream_handler_pool_subs?
+        // No neighbors, so nowhere for cover traffic to go
+        assert_eq!(
+            parameters.cover_traffic_params.lock().unwrap().is_none(),
+            true
+        );
+        assert_eq!(recordings.cover_traffic.lock().unwrap().len(), 0);
+        // No standby consuming wallet or earning wallets to consolidate, so nothing for the
+        // BlockchainBridge to do
+        assert_eq!(
+            parameters
+                .blockchain_bridge_params
+                .lock()
+                .unwrap()
+                .is_none(),
+            true
+        );
+        assert_eq!(recordings.blockchain_bridge.lock().unwrap().len(), 0);
+    }
+
+    #[test]
+    fn prepare_initial_messages_starts_and_binds_cover_traffic_for_a_decentralized_node() {
+        let actor_factory = ActorFactoryMock::new();
+        let recordings = actor_factory.get_recordings();
+        let parameters = actor_factory.make_parameters();
+        let mut config = BootstrapperConfig::new();
+        config.neighborhood_config.neighbor_configs = vec![(
+            PublicKey::new(&b"neighbor"[..]),
+            NodeAddr::new(&IpAddr::V4(Ipv4Addr::new(5, 6, 7, 8)), &vec![1234]),
+        )];
+        config.neighborhood_config.clandestine_port_list = vec![2345];
+        config.cover_traffic = Some(6);
+        let (tx, _rx) = mpsc::channel();
+        let system = System::new(
+            "prepare_initial_messages_starts_and_binds_cover_traffic_for_a_decentralized_node",
+        );
+
+        ActorSystemFactoryReal::prepare_initial_messages(
+            cryptde(),
+            config,
+            Box::new(actor_factory),
+            tx,
+        );
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+        let (cryptde, packages_per_minute) = Parameters::get(parameters.cover_traffic_params);
+        check_cryptde(cryptde);
+        assert_eq!(packages_per_minute, 6);
+        check_bind_message(&recordings.cover_traffic);
+    }
+
+    #[test]
+    fn prepare_initial_messages_starts_and_binds_blockchain_bridge_for_a_standby_consuming_wallet()
+    {
+        let actor_factory = ActorFactoryMock::new();
+        let recordings = actor_factory.get_recordings();
+        let parameters = actor_factory.make_parameters();
+        let standby_config = StandbyConsumingWalletConfig {
+            wallet: Wallet::new("standby"),
+            threshold: 1000,
+            blockchain_service_url: ServiceUrl::parse("http://127.0.0.1:8545").unwrap(),
+        };
+        let mut config = BootstrapperConfig::new();
+        config.neighborhood_config.consuming_wallet = Some(Wallet::new("consuming"));
+        config.standby_consuming_wallet = Some(standby_config.clone());
+        let (tx, _rx) = mpsc::channel();
+        let system = System::new(
+            "prepare_initial_messages_starts_and_binds_blockchain_bridge_for_a_standby_consuming_wallet",
+        );
+
+        ActorSystemFactoryReal::prepare_initial_messages(
+            cryptde(),
+            config,
+            Box::new(actor_factory),
+            tx,
+        );
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+        assert_eq!(
+            Parameters::get(parameters.blockchain_bridge_params),
+            BlockchainBridgeConfig {
+                consuming_opt: Some((Wallet::new("consuming"), standby_config)),
+                consolidation_opt: None,
+            }
+        );
+        check_bind_message(&recordings.blockchain_bridge);
+    }
+
+    #[test]
+    fn prepare_initial_messages_starts_and_binds_blockchain_bridge_for_earning_wallets_to_consolidate(
+    ) {
+        let actor_factory = ActorFactoryMock::new();
+        let recordings = actor_factory.get_recordings();
+        let parameters = actor_factory.make_parameters();
+        let consolidation_config = EarningConsolidationConfig {
+            rotation: WalletRotation::new(
+                vec![Wallet::new("first"), Wallet::new("second")],
+                Duration::from_secs(3600),
+            ),
+            consolidation_wallet: Wallet::new("consolidation"),
+            threshold: 1000,
+            blockchain_service_url: ServiceUrl::parse("http://127.0.0.1:8545").unwrap(),
+        };
+        let mut config = BootstrapperConfig::new();
+        config.earning_consolidation = Some(consolidation_config.clone());
+        let (tx, _rx) = mpsc::channel();
+        let system = System::new(
+            "prepare_initial_messages_starts_and_binds_blockchain_bridge_for_earning_wallets_to_consolidate",
+        );
+
+        ActorSystemFactoryReal::prepare_initial_messages(
+            cryptde(),
+            config,
+            Box::new(actor_factory),
+            tx,
+        );
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+        assert_eq!(
+            Parameters::get(parameters.blockchain_bridge_params),
+            BlockchainBridgeConfig {
+                consuming_opt: None,
+                consolidation_opt: Some(consolidation_config),
+            }
+        );
+        check_bind_message(&recordings.blockchain_bridge);
+    }
+
+    #[test]
+    fn arbiter_layout_moves_more_actors_out_as_cores_allow() {
+        let dedicated = |cpu_count| {
+            let layout = ArbiterLayout::for_cpu_count(cpu_count);
+            vec![
+                DedicatedActor::Hopper,
+                DedicatedActor::Accountant,
+                DedicatedActor::Neighborhood,
+            ]
+            .into_iter()
+            .filter(|actor| layout.is_dedicated(*actor))
+            .collect::<Vec<DedicatedActor>>()
+        };
+
+        assert_eq!(dedicated(1), vec![]);
+        assert_eq!(dedicated(2), vec![DedicatedActor::Hopper]);
+        assert_eq!(
+            dedicated(4),
+            vec![DedicatedActor::Hopper, DedicatedActor::Accountant]
+        );
+        assert_eq!(
+            dedicated(16),
+            vec![
+                DedicatedActor::Hopper,
+                DedicatedActor::Accountant,
+                DedicatedActor::Neighborhood,
+            ]
+        );
+    }
+
+    #[test]
+    fn dedicated_actors_are_known_by_name() {
+        assert_eq!(
+            DedicatedActor::from_name("hopper"),
+            Some(DedicatedActor::Hopper)
+        );
+        assert_eq!(
+            DedicatedActor::from_name(" accountant "),
+            Some(DedicatedActor::Accountant)
+        );
+        assert_eq!(
+            DedicatedActor::from_name("neighborhood"),
+            Some(DedicatedActor::Neighborhood)
+        );
+        assert_eq!(DedicatedActor::from_name("dispatcher"), None);
+    }
+
+    #[test]
+    fn an_actor_on_a_dedicated_arbiter_runs_on_a_thread_of_its_own() {
+        let system = System::new("an_actor_on_a_dedicated_arbiter_runs_on_a_thread_of_its_own");
+        let (shared_recorder, _, shared_recording_arc) = make_recorder();
+        let (dedicated_recorder, _, dedicated_recording_arc) = make_recorder();
+        let (tx, rx) = mpsc::channel();
+
+        let shared_addr = start_actor(false, move || shared_recorder);
+        let dedicated_addr = start_actor(true, move || {
+            tx.send(thread::current().id()).unwrap();
+            dedicated_recorder
+        });
+        shared_addr
+            .try_send(BootstrapNeighborhoodNowMessage {})
+            .unwrap();
+        dedicated_addr
+            .try_send(BootstrapNeighborhoodNowMessage {})
+            .unwrap();
+
+        let dedicated_thread_id = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        thread::sleep(Duration::from_millis(100));
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+        assert_ne!(dedicated_thread_id, thread::current().id());
+        assert_eq!(shared_recording_arc.lock().unwrap().len(), 1);
+        assert_eq!(dedicated_recording_arc.lock().unwrap().len(), 1);
     }
 
     fn check_bind_message(recording: &Arc<Mutex<Recording>>) {
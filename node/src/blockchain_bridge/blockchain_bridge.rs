@@ -0,0 +1,594 @@
+// Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use super::blockchain_interface::BlockchainInterface;
+use crate::sub_lib::blockchain_bridge::BlockchainBridgeConfig;
+use crate::sub_lib::blockchain_bridge::EarningConsolidationConfig;
+use crate::sub_lib::blockchain_bridge::StandbyConsumingWalletConfig;
+use crate::sub_lib::blockchain_bridge::BALANCE_CHECK_INTERVAL;
+use crate::sub_lib::logger::Logger;
+use crate::sub_lib::neighborhood::SetConsumingWalletMessage;
+use crate::sub_lib::peer_actors::BindMessage;
+use crate::sub_lib::ui_gateway::UiAlert;
+use crate::sub_lib::ui_gateway::UiMessage;
+use crate::sub_lib::wallet::Wallet;
+use crate::ui_gateway::message_catalog;
+use actix::Actor;
+use actix::AsyncContext;
+use actix::Context;
+use actix::Handler;
+use actix::Recipient;
+use actix::Syn;
+use std::time::Duration;
+use std::time::SystemTime;
+
+// Keeps an eye on the balance of the consuming wallet the Node pays for routing from. When the
+// primary wallet runs low and the standby still has enough, it moves the Node over to the standby,
+// so that a long session doesn't lose paid routing partway through. UIs are alerted at each stage,
+// so the operator knows which wallet to top up. There's no moving back: once the Node is on the
+// standby, it stays there until it's restarted.
+//
+// It also keeps an eye on the earning wallets the Node has rotated away from. It can't move what
+// they've earned, because the Node never has their keys, but once enough has piled up in them it
+// tells UIs where to move it.
+pub struct BlockchainBridge {
+    consuming_opt: Option<(Wallet, StandbyConsumingWalletConfig)>,
+    consolidation_opt: Option<EarningConsolidationConfig>,
+    interface: Box<dyn BlockchainInterface>,
+    check_interval: Duration,
+    on_standby: bool,
+    // Whether UIs have already been told that the wallet in use is running low
+    low_balance_alerted: bool,
+    // Whether UIs have already been told to consolidate the earning wallets
+    consolidation_alerted: bool,
+    set_consuming_wallet_sub: Option<Recipient<Syn, SetConsumingWalletMessage>>,
+    ui_message_sub: Option<Recipient<Syn, UiMessage>>,
+    logger: Logger,
+}
+
+impl Actor for BlockchainBridge {
+    type Context = Context<Self>;
+}
+
+impl Handler<BindMessage> for BlockchainBridge {
+    type Result = ();
+
+    fn handle(&mut self, msg: BindMessage, ctx: &mut Self::Context) -> Self::Result {
+        self.set_consuming_wallet_sub = Some(msg.peer_actors.neighborhood.set_consuming_wallet);
+        self.ui_message_sub = Some(msg.peer_actors.ui_gateway.ui_message_sub);
+        self.check_balances();
+        self.schedule_balance_check(ctx);
+        ()
+    }
+}
+
+impl BlockchainBridge {
+    pub fn new(
+        config: BlockchainBridgeConfig,
+        interface: Box<dyn BlockchainInterface>,
+    ) -> BlockchainBridge {
+        BlockchainBridge {
+            consuming_opt: config.consuming_opt,
+            consolidation_opt: config.consolidation_opt,
+            interface,
+            check_interval: BALANCE_CHECK_INTERVAL,
+            on_standby: false,
+            low_balance_alerted: false,
+            consolidation_alerted: false,
+            set_consuming_wallet_sub: None,
+            ui_message_sub: None,
+            logger: Logger::new("BlockchainBridge"),
+        }
+    }
+
+    fn schedule_balance_check(&self, ctx: &mut Context<Self>) {
+        ctx.run_later(self.check_interval, |act, ctx| {
+            act.check_balances();
+            act.schedule_balance_check(ctx);
+        });
+    }
+
+    fn check_balances(&mut self) {
+        self.check_consuming_balance();
+        self.check_earning_balances();
+    }
+
+    fn check_consuming_balance(&mut self) {
+        let (primary, standby, threshold) = match self.consuming_opt {
+            Some((ref primary, ref standby_config)) => (
+                primary.clone(),
+                standby_config.wallet.clone(),
+                standby_config.threshold,
+            ),
+            None => return,
+        };
+        let wallet = if self.on_standby {
+            standby.clone()
+        } else {
+            primary
+        };
+        let balance = match self.balance_of(&wallet, "consuming") {
+            Some(balance) => balance,
+            None => return,
+        };
+        if balance >= threshold {
+            self.low_balance_alerted = false;
+            return;
+        }
+        if self.on_standby {
+            let alert = UiAlert::new("standby_consuming_wallet_low")
+                .with("wallet", &wallet.address)
+                .with("balance", &balance.to_string())
+                .with("threshold", &threshold.to_string());
+            self.alert_once(alert);
+            return;
+        }
+        let standby_balance = match self.balance_of(&standby, "consuming") {
+            Some(standby_balance) => standby_balance,
+            None => return,
+        };
+        if standby_balance < threshold {
+            let alert = UiAlert::new("consuming_wallets_low")
+                .with("wallet", &wallet.address)
+                .with("balance", &balance.to_string())
+                .with("standby", &standby.address)
+                .with("standby_balance", &standby_balance.to_string())
+                .with("threshold", &threshold.to_string());
+            self.alert_once(alert);
+            return;
+        }
+        self.on_standby = true;
+        self.low_balance_alerted = false;
+        self.set_consuming_wallet_sub
+            .as_ref()
+            .expect("BlockchainBridge unbound: no Neighborhood")
+            .try_send(SetConsumingWalletMessage {
+                wallet: standby.clone(),
+            })
+            .expect("Neighborhood is dead");
+        self.alert(
+            UiAlert::new("consuming_wallet_switched")
+                .with("wallet", &wallet.address)
+                .with("balance", &balance.to_string())
+                .with("standby", &standby.address)
+                .with("threshold", &threshold.to_string()),
+        );
+    }
+
+    // The wallet being advertised now is left alone until the Node has moved on from it
+    fn check_earning_balances(&mut self) {
+        let config = match self.consolidation_opt {
+            Some(ref config) => config.clone(),
+            None => return,
+        };
+        let current = config.rotation.wallet_at(SystemTime::now());
+        let mut checked: Vec<&Wallet> = vec![];
+        let mut balances: Vec<(Wallet, u128)> = vec![];
+        for wallet in config.rotation.wallets.iter() {
+            if wallet == current
+                || wallet == &config.consolidation_wallet
+                || checked.contains(&wallet)
+            {
+                continue;
+            }
+            checked.push(wallet);
+            match self.balance_of(wallet, "earning") {
+                Some(0) => (),
+                Some(balance) => balances.push((wallet.clone(), balance)),
+                None => return,
+            }
+        }
+        let total: u128 = balances.iter().map(|(_, balance)| balance).sum();
+        if total < config.threshold {
+            self.consolidation_alerted = false;
+            return;
+        }
+        if self.consolidation_alerted {
+            return;
+        }
+        self.consolidation_alerted = true;
+        let wallets = balances
+            .iter()
+            .map(|(wallet, balance)| format!("{} ({} wei)", wallet.address, balance))
+            .collect::<Vec<String>>()
+            .join(", ");
+        self.alert(
+            UiAlert::new("earning_wallets_to_consolidate")
+                .with("total", &total.to_string())
+                .with("wallets", &wallets)
+                .with("wallet", &config.consolidation_wallet.address),
+        );
+    }
+
+    fn balance_of(&self, wallet: &Wallet, kind: &str) -> Option<u128> {
+        match self.interface.get_balance(wallet) {
+            Ok(balance) => Some(balance),
+            Err(e) => {
+                self.logger.warning(format!(
+                    "Couldn't find out the balance of {} wallet {}: {}",
+                    kind, wallet.address, e
+                ));
+                None
+            }
+        }
+    }
+
+    // A wallet that stays low is only mentioned the first time it's seen to be
+    fn alert_once(&mut self, alert: UiAlert) {
+        if !self.low_balance_alerted {
+            self.low_balance_alerted = true;
+            self.alert(alert);
+        }
+    }
+
+    fn alert(&self, alert: UiAlert) {
+        self.logger.warning(message_catalog::render(&alert));
+        self.ui_message_sub
+            .as_ref()
+            .expect("BlockchainBridge unbound: no UiGateway")
+            .try_send(UiMessage::AlertMessage(alert))
+            .expect("UiGateway is dead");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sub_lib::blockchain_bridge::ServiceUrl;
+    use crate::sub_lib::wallet::WalletRotation;
+    use crate::test_utils::logging::init_test_logging;
+    use crate::test_utils::logging::TestLogHandler;
+    use crate::test_utils::recorder::make_recorder;
+    use crate::test_utils::recorder::peer_actors_builder;
+    use crate::test_utils::recorder::Recorder;
+    use crate::test_utils::recorder::Recording;
+    use actix::msgs;
+    use actix::Addr;
+    use actix::Arbiter;
+    use actix::System;
+    use std::sync::Arc;
+    use std::sync::Mutex;
+    use std::thread;
+
+    const PRIMARY: &str = "0xbDfeFf9A1f4A1bdF483d680046344316019C58CF";
+    const STANDBY: &str = "0x9707f21F95B9839A54605100Ca69dCc2e7eaA26B";
+    const THRESHOLD: u128 = 1_000_000;
+
+    struct BlockchainInterfaceMock {
+        get_balance_params: Arc<Mutex<Vec<Wallet>>>,
+        get_balance_results: Arc<Mutex<Vec<Result<u128, String>>>>,
+    }
+
+    impl BlockchainInterface for BlockchainInterfaceMock {
+        fn get_balance(&self, wallet: &Wallet) -> Result<u128, String> {
+            self.get_balance_params.lock().unwrap().push(wallet.clone());
+            let mut results = self.get_balance_results.lock().unwrap();
+            if results.is_empty() {
+                Err(String::from("No more balances"))
+            } else {
+                results.remove(0)
+            }
+        }
+    }
+
+    impl BlockchainInterfaceMock {
+        fn new(results: Vec<Result<u128, String>>) -> BlockchainInterfaceMock {
+            BlockchainInterfaceMock {
+                get_balance_params: Arc::new(Mutex::new(vec![])),
+                get_balance_results: Arc::new(Mutex::new(results)),
+            }
+        }
+    }
+
+    fn make_subject(interface: BlockchainInterfaceMock) -> BlockchainBridge {
+        BlockchainBridge::new(
+            BlockchainBridgeConfig {
+                consuming_opt: Some((
+                    Wallet::new(PRIMARY),
+                    StandbyConsumingWalletConfig {
+                        wallet: Wallet::new(STANDBY),
+                        threshold: THRESHOLD,
+                        blockchain_service_url: ServiceUrl::parse("http://127.0.0.1:8545").unwrap(),
+                    },
+                )),
+                consolidation_opt: None,
+            },
+            Box::new(interface),
+        )
+    }
+
+    const EARNING: [&str; 3] = [
+        "0x1111111111111111111111111111111111111111",
+        "0x2222222222222222222222222222222222222222",
+        "0x3333333333333333333333333333333333333333",
+    ];
+    const CONSOLIDATION: &str = "0x4444444444444444444444444444444444444444";
+
+    fn make_consolidating_subject(interface: BlockchainInterfaceMock) -> BlockchainBridge {
+        BlockchainBridge::new(
+            BlockchainBridgeConfig {
+                consuming_opt: None,
+                consolidation_opt: Some(EarningConsolidationConfig {
+                    rotation: earning_rotation(),
+                    consolidation_wallet: Wallet::new(CONSOLIDATION),
+                    threshold: THRESHOLD,
+                    blockchain_service_url: ServiceUrl::parse("http://127.0.0.1:8545").unwrap(),
+                }),
+            },
+            Box::new(interface),
+        )
+    }
+
+    fn earning_rotation() -> WalletRotation {
+        WalletRotation::new(
+            EARNING.iter().map(|address| Wallet::new(address)).collect(),
+            Duration::from_secs(86400),
+        )
+    }
+
+    // Every earning wallet but the one being advertised now
+    fn rotated_away_from() -> Vec<Wallet> {
+        let rotation = earning_rotation();
+        let current = rotation.wallet_at(SystemTime::now()).clone();
+        rotation
+            .wallets
+            .into_iter()
+            .filter(|wallet| wallet != &current)
+            .collect()
+    }
+
+    fn check_once(
+        balances: Vec<Result<u128, String>>,
+    ) -> (Vec<Wallet>, Vec<SetConsumingWalletMessage>, Vec<UiAlert>) {
+        check_subject_once(make_subject, balances)
+    }
+
+    fn check_subject_once(
+        make_subject: fn(BlockchainInterfaceMock) -> BlockchainBridge,
+        balances: Vec<Result<u128, String>>,
+    ) -> (Vec<Wallet>, Vec<SetConsumingWalletMessage>, Vec<UiAlert>) {
+        let system = System::new("check_once");
+        let interface = BlockchainInterfaceMock::new(balances);
+        let params_arc = interface.get_balance_params.clone();
+        let addr: Addr<Syn, BlockchainBridge> = make_subject(interface).start();
+        let (neighborhood, _, neighborhood_recording_arc) = make_recorder();
+        let (ui_gateway, _, ui_gateway_recording_arc) = make_recorder();
+        let peer_actors = peer_actors_builder()
+            .neighborhood(neighborhood)
+            .ui_gateway(ui_gateway)
+            .build();
+
+        addr.try_send(BindMessage { peer_actors }).unwrap();
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+        let neighborhood_recording = neighborhood_recording_arc.lock().unwrap();
+        let switches = (0..neighborhood_recording.len())
+            .map(|index| {
+                neighborhood_recording
+                    .get_record::<SetConsumingWalletMessage>(index)
+                    .clone()
+            })
+            .collect();
+        let params = params_arc.lock().unwrap().clone();
+        (params, switches, alerts_in(&ui_gateway_recording_arc))
+    }
+
+    fn alerts_in(recording_arc: &Arc<Mutex<Recording>>) -> Vec<UiAlert> {
+        let recording = recording_arc.lock().unwrap();
+        (0..recording.len())
+            .map(|index| match recording.get_record::<UiMessage>(index) {
+                UiMessage::AlertMessage(alert) => alert.clone(),
+                other => panic!("Expected an alert, got {:?}", other),
+            })
+            .collect()
+    }
+
+    fn alert_codes_in(recording_arc: &Arc<Mutex<Recording>>) -> Vec<String> {
+        alerts_in(recording_arc)
+            .into_iter()
+            .map(|alert| alert.code)
+            .collect()
+    }
+
+    fn await_balance_queries(params_arc: &Arc<Mutex<Vec<Wallet>>>, count: usize) {
+        for _ in 0..500 {
+            if params_arc.lock().unwrap().len() >= count {
+                return;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        panic!("Never saw {} balance queries", count);
+    }
+
+    #[test]
+    fn a_primary_wallet_with_enough_left_is_kept() {
+        let (params, switches, alerts) = check_once(vec![Ok(THRESHOLD)]);
+
+        assert_eq!(params, vec![Wallet::new(PRIMARY)]);
+        assert_eq!(switches, vec![]);
+        assert_eq!(alerts, vec![]);
+    }
+
+    #[test]
+    fn a_low_primary_wallet_is_swapped_for_a_standby_with_enough_left() {
+        let (params, switches, alerts) = check_once(vec![Ok(THRESHOLD - 1), Ok(THRESHOLD)]);
+
+        assert_eq!(params, vec![Wallet::new(PRIMARY), Wallet::new(STANDBY)]);
+        assert_eq!(
+            switches,
+            vec![SetConsumingWalletMessage {
+                wallet: Wallet::new(STANDBY)
+            }]
+        );
+        assert_eq!(
+            alerts,
+            vec![UiAlert::new("consuming_wallet_switched")
+                .with("wallet", PRIMARY)
+                .with("balance", "999999")
+                .with("standby", STANDBY)
+                .with("threshold", "1000000")]
+        );
+    }
+
+    #[test]
+    fn a_low_primary_wallet_is_kept_if_the_standby_is_low_too() {
+        let (params, switches, alerts) = check_once(vec![Ok(5), Ok(6)]);
+
+        assert_eq!(params, vec![Wallet::new(PRIMARY), Wallet::new(STANDBY)]);
+        assert_eq!(switches, vec![]);
+        assert_eq!(
+            alerts,
+            vec![UiAlert::new("consuming_wallets_low")
+                .with("wallet", PRIMARY)
+                .with("balance", "5")
+                .with("standby", STANDBY)
+                .with("standby_balance", "6")
+                .with("threshold", "1000000")]
+        );
+    }
+
+    #[test]
+    fn a_balance_that_cant_be_found_out_changes_nothing() {
+        init_test_logging();
+
+        let (params, switches, alerts) = check_once(vec![Err(String::from("booga"))]);
+
+        assert_eq!(params, vec![Wallet::new(PRIMARY)]);
+        assert_eq!(switches, vec![]);
+        assert_eq!(alerts, vec![]);
+        TestLogHandler::new().exists_log_containing(&format!(
+            "WARN: BlockchainBridge: Couldn't find out the balance of consuming wallet {}: booga",
+            PRIMARY
+        ));
+    }
+
+    #[test]
+    fn a_low_standby_is_alerted_once_each_time_it_runs_low() {
+        let interface = BlockchainInterfaceMock::new(vec![
+            Ok(0),
+            Ok(THRESHOLD),
+            Ok(THRESHOLD - 1),
+            Ok(THRESHOLD - 2),
+            Ok(THRESHOLD),
+            Ok(THRESHOLD - 1),
+        ]);
+        let params_arc = interface.get_balance_params.clone();
+        let (neighborhood, _, neighborhood_recording_arc) = make_recorder();
+        let (ui_gateway, ui_gateway_awaiter, ui_gateway_recording_arc) = make_recorder();
+        thread::spawn(move || {
+            let system = System::new("a_low_standby_is_alerted_once_each_time_it_runs_low");
+            let mut subject = make_subject(interface);
+            subject.check_interval = Duration::from_millis(10);
+            let addr: Addr<Syn, BlockchainBridge> = subject.start();
+            let peer_actors = peer_actors_builder()
+                .neighborhood(neighborhood)
+                .ui_gateway(ui_gateway)
+                .build();
+
+            addr.try_send(BindMessage { peer_actors }).unwrap();
+
+            system.run();
+        });
+        ui_gateway_awaiter.await_message_count(3);
+        await_balance_queries(&params_arc, 8);
+        assert_eq!(
+            alert_codes_in(&ui_gateway_recording_arc),
+            vec![
+                String::from("consuming_wallet_switched"),
+                String::from("standby_consuming_wallet_low"),
+                String::from("standby_consuming_wallet_low"),
+            ]
+        );
+        assert_eq!(
+            params_arc.lock().unwrap()[..6].to_vec(),
+            vec![
+                Wallet::new(PRIMARY),
+                Wallet::new(STANDBY),
+                Wallet::new(STANDBY),
+                Wallet::new(STANDBY),
+                Wallet::new(STANDBY),
+                Wallet::new(STANDBY),
+            ]
+        );
+        assert_eq!(neighborhood_recording_arc.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn a_wallet_that_stays_low_is_only_alerted_once() {
+        let interface = BlockchainInterfaceMock::new(vec![Ok(1), Ok(2), Ok(1), Ok(2)]);
+        let params_arc = interface.get_balance_params.clone();
+        let (ui_gateway, ui_gateway_awaiter, ui_gateway_recording_arc) = make_recorder();
+        thread::spawn(move || {
+            let system = System::new("a_wallet_that_stays_low_is_only_alerted_once");
+            let mut subject = make_subject(interface);
+            subject.check_interval = Duration::from_millis(10);
+            let addr: Addr<Syn, BlockchainBridge> = subject.start();
+            let peer_actors = peer_actors_builder()
+                .neighborhood(Recorder::new())
+                .ui_gateway(ui_gateway)
+                .build();
+
+            addr.try_send(BindMessage { peer_actors }).unwrap();
+
+            system.run();
+        });
+        ui_gateway_awaiter.await_message_count(1);
+        await_balance_queries(&params_arc, 5);
+        assert_eq!(
+            alert_codes_in(&ui_gateway_recording_arc),
+            vec![String::from("consuming_wallets_low")]
+        );
+    }
+
+    #[test]
+    fn earning_wallets_rotated_away_from_are_to_be_consolidated_once_they_hold_enough_between_them()
+    {
+        let (params, switches, alerts) = check_subject_once(
+            make_consolidating_subject,
+            vec![Ok(THRESHOLD / 2), Ok(THRESHOLD - THRESHOLD / 2)],
+        );
+
+        let rotated_away_from = rotated_away_from();
+        assert_eq!(params, rotated_away_from);
+        assert_eq!(switches, vec![]);
+        assert_eq!(
+            alerts,
+            vec![UiAlert::new("earning_wallets_to_consolidate")
+                .with("total", "1000000")
+                .with(
+                    "wallets",
+                    &format!(
+                        "{} (500000 wei), {} (500000 wei)",
+                        rotated_away_from[0].address, rotated_away_from[1].address
+                    )
+                )
+                .with("wallet", CONSOLIDATION)]
+        );
+    }
+
+    #[test]
+    fn earning_wallets_holding_less_than_the_threshold_are_left_alone() {
+        let (params, _, alerts) =
+            check_subject_once(make_consolidating_subject, vec![Ok(0), Ok(THRESHOLD - 1)]);
+
+        assert_eq!(params, rotated_away_from());
+        assert_eq!(alerts, vec![]);
+    }
+
+    #[test]
+    fn earning_wallets_are_not_totalled_if_one_balance_cant_be_found_out() {
+        init_test_logging();
+
+        let (params, _, alerts) = check_subject_once(
+            make_consolidating_subject,
+            vec![Err(String::from("booga")), Ok(THRESHOLD)],
+        );
+
+        let rotated_away_from = rotated_away_from();
+        assert_eq!(params, vec![rotated_away_from[0].clone()]);
+        assert_eq!(alerts, vec![]);
+        TestLogHandler::new().exists_log_containing(&format!(
+            "WARN: BlockchainBridge: Couldn't find out the balance of earning wallet {}: booga",
+            rotated_away_from[0].address
+        ));
+    }
+}
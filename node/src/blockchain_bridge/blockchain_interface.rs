@@ -0,0 +1,248 @@
+// Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use crate::sub_lib::blockchain_bridge::ServiceUrl;
+use crate::sub_lib::blockchain_bridge::BLOCKCHAIN_SERVICE_TIMEOUT;
+use crate::sub_lib::wallet::Wallet;
+use native_tls::TlsConnector;
+use serde_json;
+use serde_json::Value;
+use std::io::Read;
+use std::io::Write;
+use std::net::TcpStream;
+use std::net::ToSocketAddrs;
+use std::time::Duration;
+
+// What the BlockchainBridge needs to find out from the blockchain
+pub trait BlockchainInterface {
+    // In wei
+    fn get_balance(&self, wallet: &Wallet) -> Result<u128, String>;
+}
+
+// Asks an Ethereum JSON-RPC service, with one HTTP request per question. The BlockchainBridge has an
+// arbiter of its own, so it can afford to wait for the answer.
+pub struct BlockchainInterfaceJsonRpc {
+    url: ServiceUrl,
+    timeout: Duration,
+}
+
+impl BlockchainInterface for BlockchainInterfaceJsonRpc {
+    fn get_balance(&self, wallet: &Wallet) -> Result<u128, String> {
+        let request = serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "eth_getBalance",
+            "params": [wallet.address, "latest"],
+        });
+        let body = self.post(&request.to_string())?;
+        BlockchainInterfaceJsonRpc::parse_balance(&body)
+    }
+}
+
+impl BlockchainInterfaceJsonRpc {
+    pub fn new(url: ServiceUrl) -> BlockchainInterfaceJsonRpc {
+        BlockchainInterfaceJsonRpc {
+            url,
+            timeout: BLOCKCHAIN_SERVICE_TIMEOUT,
+        }
+    }
+
+    fn post(&self, body: &str) -> Result<String, String> {
+        // HTTP/1.0, so that the answer can't come back chunked
+        let request = format!(
+            "POST {} HTTP/1.0\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+            self.url.path,
+            self.url.host,
+            body.len(),
+            body
+        );
+        let host = self.url.host.trim_start_matches('[').trim_end_matches(']');
+        let addr = match (host, self.url.port).to_socket_addrs() {
+            Ok(mut addrs) => match addrs.next() {
+                Some(addr) => addr,
+                None => return Err(format!("Couldn't resolve {}", host)),
+            },
+            Err(e) => return Err(format!("Couldn't resolve {}: {}", host, e)),
+        };
+        let stream = TcpStream::connect_timeout(&addr, self.timeout)
+            .map_err(|e| format!("Couldn't connect to {}: {}", addr, e))?;
+        stream
+            .set_read_timeout(Some(self.timeout))
+            .and_then(|_| stream.set_write_timeout(Some(self.timeout)))
+            .map_err(|e| format!("Couldn't set timeouts: {}", e))?;
+        let response = if self.url.tls {
+            let connector =
+                TlsConnector::new().map_err(|e| format!("Couldn't set up TLS: {}", e))?;
+            let tls_stream = connector
+                .connect(host, stream)
+                .map_err(|e| format!("TLS handshake with {} failed: {}", host, e))?;
+            BlockchainInterfaceJsonRpc::exchange(tls_stream, &request)?
+        } else {
+            BlockchainInterfaceJsonRpc::exchange(stream, &request)?
+        };
+        BlockchainInterfaceJsonRpc::body_of(&String::from_utf8_lossy(&response))
+    }
+
+    fn exchange<S: Read + Write>(mut stream: S, request: &str) -> Result<Vec<u8>, String> {
+        stream
+            .write_all(request.as_bytes())
+            .map_err(|e| format!("Couldn't send request: {}", e))?;
+        let mut response = vec![];
+        stream
+            .read_to_end(&mut response)
+            .map_err(|e| format!("Couldn't read response: {}", e))?;
+        Ok(response)
+    }
+
+    fn body_of(response: &str) -> Result<String, String> {
+        let mut parts = response.splitn(2, "\r\n\r\n");
+        let head = parts.next().unwrap_or("");
+        let status_line = head.lines().next().unwrap_or("");
+        if status_line.split_whitespace().nth(1) != Some("200") {
+            return Err(format!("Blockchain service answered '{}'", status_line));
+        }
+        match parts.next() {
+            Some(body) => Ok(String::from(body)),
+            None => Err(String::from("Blockchain service answered with no body")),
+        }
+    }
+
+    fn parse_balance(body: &str) -> Result<u128, String> {
+        let value: Value =
+            serde_json::from_str(body).map_err(|e| format!("Couldn't parse '{}': {}", body, e))?;
+        if let Some(message) = value["error"]["message"].as_str() {
+            return Err(format!("Blockchain service refused: {}", message));
+        }
+        let quantity = match value["result"].as_str() {
+            Some(quantity) => quantity,
+            None => return Err(format!("No result in '{}'", body)),
+        };
+        let hex = if quantity.starts_with("0x") {
+            &quantity[2..]
+        } else {
+            ""
+        };
+        u128::from_str_radix(hex, 16)
+            .map_err(|_| format!("Balance '{}' isn't a hexadecimal quantity", quantity))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::thread;
+
+    fn serve_once(response: &'static str) -> (u16, thread::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let handle = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = vec![];
+            let mut buf = [0u8; 1024];
+            while !request.ends_with(b"}") {
+                let len = stream.read(&mut buf).unwrap();
+                if len == 0 {
+                    break;
+                }
+                request.extend_from_slice(&buf[..len]);
+            }
+            stream.write_all(response.as_bytes()).unwrap();
+            String::from_utf8(request).unwrap()
+        });
+        (port, handle)
+    }
+
+    fn local_url(port: u16) -> ServiceUrl {
+        ServiceUrl::parse(&format!("http://127.0.0.1:{}/rpc", port)).unwrap()
+    }
+
+    #[test]
+    fn get_balance_asks_for_the_latest_balance_of_the_wallet() {
+        let (port, handle) = serve_once(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n\r\n{\"jsonrpc\":\"2.0\",\"id\":1,\"result\":\"0xde0b6b3a7640000\"}",
+        );
+        let subject = BlockchainInterfaceJsonRpc::new(local_url(port));
+
+        let result =
+            subject.get_balance(&Wallet::new("0xbDfeFf9A1f4A1bdF483d680046344316019C58CF"));
+
+        assert_eq!(result, Ok(1_000_000_000_000_000_000));
+        let request = handle.join().unwrap();
+        assert_eq!(request.starts_with("POST /rpc HTTP/1.0\r\n"), true);
+        assert_eq!(request.contains("\r\nHost: 127.0.0.1\r\n"), true);
+        let body: Value =
+            serde_json::from_str(request.splitn(2, "\r\n\r\n").nth(1).unwrap()).unwrap();
+        assert_eq!(body["method"], "eth_getBalance");
+        assert_eq!(
+            body["params"],
+            serde_json::json!(["0xbDfeFf9A1f4A1bdF483d680046344316019C58CF", "latest"])
+        );
+    }
+
+    #[test]
+    fn get_balance_reports_an_http_error() {
+        let (port, handle) = serve_once("HTTP/1.1 401 Unauthorized\r\n\r\n");
+        let subject = BlockchainInterfaceJsonRpc::new(local_url(port));
+
+        let result = subject.get_balance(&Wallet::new("0x1234"));
+
+        assert_eq!(
+            result,
+            Err(String::from(
+                "Blockchain service answered 'HTTP/1.1 401 Unauthorized'"
+            ))
+        );
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn get_balance_reports_a_service_it_cant_connect_to() {
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let subject = BlockchainInterfaceJsonRpc::new(local_url(port));
+
+        let result = subject.get_balance(&Wallet::new("0x1234"));
+
+        let error = result.err().unwrap();
+        assert_eq!(
+            error.starts_with(&format!("Couldn't connect to 127.0.0.1:{}: ", port)),
+            true,
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn balance_is_read_from_a_hexadecimal_quantity() {
+        assert_eq!(
+            BlockchainInterfaceJsonRpc::parse_balance(r#"{"id":1,"result":"0x0"}"#),
+            Ok(0)
+        );
+        assert_eq!(
+            BlockchainInterfaceJsonRpc::parse_balance(
+                r#"{"id":1,"result":"0x3635C9ADC5DEA00000"}"#
+            ),
+            Ok(1_000_000_000_000_000_000_000)
+        );
+    }
+
+    #[test]
+    fn balance_errors_are_reported() {
+        assert_eq!(
+            BlockchainInterfaceJsonRpc::parse_balance(
+                r#"{"id":1,"error":{"code":-32602,"message":"invalid address"}}"#
+            ),
+            Err(String::from("Blockchain service refused: invalid address"))
+        );
+        assert_eq!(
+            BlockchainInterfaceJsonRpc::parse_balance(r#"{"id":1}"#),
+            Err(String::from("No result in '{\"id\":1}'"))
+        );
+        assert_eq!(
+            BlockchainInterfaceJsonRpc::parse_balance(r#"{"id":1,"result":"1000"}"#),
+            Err(String::from("Balance '1000' isn't a hexadecimal quantity"))
+        );
+    }
+}
@@ -0,0 +1,4 @@
+// Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+
+pub mod blockchain_bridge;
+pub mod blockchain_interface;
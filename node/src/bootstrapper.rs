@@ -1,60 +1,199 @@
 // Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use crate::accountant::db_initializer::DbInitializer;
+use crate::accountant::db_initializer::DbInitializerReal;
+use crate::accountant::db_initializer::CURRENT_SCHEMA_VERSION;
 use crate::actor_system_factory::ActorFactoryReal;
 use crate::actor_system_factory::ActorSystemFactory;
 use crate::actor_system_factory::ActorSystemFactoryReal;
+use crate::actor_system_factory::ArbiterLayout;
+use crate::actor_system_factory::DedicatedActor;
 use crate::configuration::Configuration;
 use crate::crash_test_dummy::CrashTestDummy;
 use crate::discriminator::DiscriminatorFactory;
+#[cfg(feature = "fault_injection")]
+use crate::fault_injection::FaultInjector;
+use crate::firewall::FirewallManager;
+use crate::hopper::fragmentation::MIN_FRAGMENT_MTU;
+use crate::identity_bundle;
 use crate::listener_handler::ListenerHandler;
 use crate::listener_handler::ListenerHandlerFactory;
 use crate::listener_handler::ListenerHandlerFactoryReal;
+use crate::neighborhood::route_exclusion::AsnDatabase;
+use crate::neighborhood::route_exclusion::RouteExclusion;
+use crate::neighborhood::route_exclusion::RouteExclusionConfig;
+use crate::network_probe::ProbeConfig;
+use crate::probe_resistance::ProbeResistance;
+use crate::proxy_server::privacy_policy::PrivacyPolicy;
+use crate::proxy_server::protocol_pack::standard_protocol_pack_ports;
+use crate::proxy_server::retransmission_buffer::DEFAULT_RETRANSMISSION_MEMORY;
+use crate::proxy_server::upload_window::DEFAULT_UPLOAD_WINDOW;
 use crate::sub_lib::accountant;
 use crate::sub_lib::accountant::AccountantConfig;
+use crate::sub_lib::blockchain_bridge::EarningConsolidationConfig;
+use crate::sub_lib::blockchain_bridge::ServiceUrl;
+use crate::sub_lib::blockchain_bridge::StandbyConsumingWalletConfig;
 use crate::sub_lib::crash_point::CrashPoint;
 use crate::sub_lib::cryptde::CryptDE;
+use crate::sub_lib::cryptde::PrivateKey;
 use crate::sub_lib::cryptde::PublicKey;
 use crate::sub_lib::cryptde_null::CryptDENull;
+use crate::sub_lib::cryptde_real::CryptDEReal;
+use crate::sub_lib::hopper::RelayFairnessConfig;
 use crate::sub_lib::logger::Logger;
 use crate::sub_lib::main_tools::StdStreams;
 use crate::sub_lib::neighborhood::sentinel_ip_addr;
+use crate::sub_lib::neighborhood::CapacityClass;
+use crate::sub_lib::neighborhood::ExitSla;
 use crate::sub_lib::neighborhood::NeighborhoodConfig;
+use crate::sub_lib::neighborhood::RateCard;
+use crate::sub_lib::neighborhood::DEFAULT_BAN_THRESHOLD;
 use crate::sub_lib::node_addr::NodeAddr;
 use crate::sub_lib::parameter_finder::ParameterFinder;
+use crate::sub_lib::performance_profile::PerformanceProfile;
+use crate::sub_lib::proxy_client::DnsProtocol;
+use crate::sub_lib::proxy_client::DnsResolverConfig;
+use crate::sub_lib::proxy_client::ExitConnectTimeouts;
+use crate::sub_lib::proxy_client::ExitLogLevel;
+use crate::sub_lib::proxy_client::ExitPolicy;
+use crate::sub_lib::proxy_client::ExitThroughputConfig;
+use crate::sub_lib::proxy_server::AdaptiveHopsConfig;
 use crate::sub_lib::socket_server::SocketServer;
+use crate::sub_lib::ui_gateway::StartupSummary;
 use crate::sub_lib::ui_gateway::UiGatewayConfig;
+use crate::sub_lib::ui_gateway::UiRemoteAccessConfig;
 use crate::sub_lib::ui_gateway::DEFAULT_UI_PORT;
+use crate::sub_lib::ui_gateway::MIN_UI_TOKEN_LENGTH;
 use crate::sub_lib::wallet::Wallet;
+use crate::sub_lib::wallet::WalletRotation;
+use crate::sub_lib::wallet::DEFAULT_WALLET_ROTATION_PERIOD;
 use base64;
 use dirs::data_dir;
 use futures::try_ready;
 use regex::Regex;
+use std::collections::HashMap;
+use std::fs;
 use std::net::IpAddr;
 use std::net::Ipv4Addr;
 use std::net::SocketAddr;
+use std::path::Path;
 use std::path::PathBuf;
 use std::str::FromStr;
+use std::time::Duration;
 use std::vec::Vec;
 use tokio::prelude::stream::futures_unordered::FuturesUnordered;
 use tokio::prelude::Async;
 use tokio::prelude::Future;
 use tokio::prelude::Stream;
 
-pub static mut CRYPT_DE_OPT: Option<CryptDENull> = None;
+pub static mut CRYPT_DE_OPT: Option<Box<dyn CryptDE>> = None;
+
+const RESOLV_CONF_PATH: &str = "/etc/resolv.conf";
+
+// Names in the database's config table
+pub const CLANDESTINE_PORTS_CONFIG: &str = "clandestine_ports";
+pub const NODE_ADDR_VERSION_CONFIG: &str = "node_addr_version";
+
+// Null encodes nothing at all, and is only for tests and for talking to Nodes that use it too;
+// Nodes with different backends can't understand each other
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CryptoBackend {
+    Null,
+    Real,
+}
+
+impl CryptoBackend {
+    pub fn from_name(name: &str) -> Option<CryptoBackend> {
+        match name {
+            "null" => Some(CryptoBackend::Null),
+            "real" => Some(CryptoBackend::Real),
+            _ => None,
+        }
+    }
+
+    pub fn cryptde_name(&self) -> &'static str {
+        match self {
+            CryptoBackend::Null => "CryptDENull",
+            CryptoBackend::Real => "CryptDEReal",
+        }
+    }
+
+    fn make_cryptde(&self) -> Box<dyn CryptDE> {
+        match self {
+            CryptoBackend::Null => Box::new(CryptDENull::new()),
+            CryptoBackend::Real => Box::new(CryptDEReal::new()),
+        }
+    }
+
+    fn cryptde_from(&self, private_key: &PrivateKey) -> Result<Box<dyn CryptDE>, String> {
+        match self {
+            CryptoBackend::Null => Ok(Box::new(CryptDENull::from(
+                &CryptDENull::public_from_private(private_key),
+            ))),
+            CryptoBackend::Real => Ok(Box::new(CryptDEReal::from_private_key(private_key)?)),
+        }
+    }
+}
 
 #[derive(Clone)]
 pub struct BootstrapperConfig {
     pub dns_servers: Vec<SocketAddr>,
+    pub dns_resolver_config: DnsResolverConfig,
     pub neighborhood_config: NeighborhoodConfig,
     pub accountant_config: AccountantConfig,
     pub crash_point: CrashPoint,
     pub clandestine_discriminator_factories: Vec<Box<dyn DiscriminatorFactory>>,
     pub ui_gateway_config: UiGatewayConfig,
+    pub exit_throughput_config: ExitThroughputConfig,
+    pub exit_connect_timeouts: ExitConnectTimeouts,
+    pub capacity_class: CapacityClass,
+    pub earning_wallet_rotation: Option<WalletRotation>,
+    pub standby_consuming_wallet: Option<StandbyConsumingWalletConfig>,
+    pub earning_consolidation: Option<EarningConsolidationConfig>,
+    pub protocol_pack_ports: HashMap<u16, String>,
+    pub exit_log_level: ExitLogLevel,
+    pub privacy_policy: Option<PrivacyPolicy>,
+    pub privacy_policy_path: Option<PathBuf>,
+    pub request_exit_tls: bool,
+    pub request_exit_dns_server: Option<IpAddr>,
+    pub exit_location: Option<String>,
+    pub offer_exit_tls: bool,
+    pub relay_only: bool,
+    pub persistent_identity: bool,
+    pub crypto_backend: CryptoBackend,
+    pub manage_firewall: bool,
+    pub probe_resistance: ProbeResistance,
+    pub max_report_delay: Duration,
+    pub route_exclusion_config: Option<RouteExclusionConfig>,
+    pub geoip_database_path: Option<PathBuf>,
+    pub node_record_retention: Option<Duration>,
+    pub ban_threshold: u32,
+    pub upload_window: Option<u64>,
+    pub retransmission_memory: usize,
+    pub retransmission_spill: bool,
+    pub socks_port: Option<u16>,
+    pub http_proxy_port: Option<u16>,
+    pub arbiter_layout: ArbiterLayout,
+    pub performance_profile: PerformanceProfile,
+    pub rate_card: RateCard,
+    pub country: Option<String>,
+    pub exit_policy: ExitPolicy,
+    pub exit_sla: Option<ExitSla>,
+    pub adaptive_hops: Option<AdaptiveHopsConfig>,
+    pub fragment_mtu: Option<usize>,
+    pub pad_packets: bool,
+    pub cover_traffic: Option<u32>,
+    pub relay_fairness_config: Option<RelayFairnessConfig>,
+    pub metrics_port: Option<u16>,
+    pub regenerate_ports: bool,
+    // The version this Node's NodeRecord starts at
+    pub node_addr_version: u32,
 }
 
 impl BootstrapperConfig {
     pub fn new() -> BootstrapperConfig {
         BootstrapperConfig {
             dns_servers: vec![],
+            dns_resolver_config: DnsResolverConfig::default(),
             neighborhood_config: NeighborhoodConfig {
                 neighbor_configs: vec![],
                 is_bootstrap_node: false,
@@ -70,7 +209,59 @@ impl BootstrapperConfig {
             clandestine_discriminator_factories: vec![],
             ui_gateway_config: UiGatewayConfig {
                 ui_port: DEFAULT_UI_PORT,
+                startup_summary: None,
+                firewall_directory: None,
+                alias_directory: None,
+                remote_access: None,
+                local_token: None,
+                token_directory: None,
+            },
+            exit_throughput_config: ExitThroughputConfig {
+                upstream_bytes_per_second: None,
+                downstream_bytes_per_second: None,
             },
+            exit_connect_timeouts: ExitConnectTimeouts::default(),
+            capacity_class: CapacityClass::default(),
+            earning_wallet_rotation: None,
+            standby_consuming_wallet: None,
+            earning_consolidation: None,
+            protocol_pack_ports: standard_protocol_pack_ports(),
+            exit_log_level: ExitLogLevel::Off,
+            privacy_policy: None,
+            privacy_policy_path: None,
+            request_exit_tls: false,
+            request_exit_dns_server: None,
+            exit_location: None,
+            offer_exit_tls: false,
+            relay_only: false,
+            persistent_identity: false,
+            crypto_backend: CryptoBackend::Null,
+            manage_firewall: false,
+            probe_resistance: ProbeResistance::Off,
+            max_report_delay: accountant::DEFAULT_MAX_REPORT_DELAY,
+            route_exclusion_config: None,
+            geoip_database_path: None,
+            node_record_retention: None,
+            ban_threshold: DEFAULT_BAN_THRESHOLD,
+            upload_window: Some(DEFAULT_UPLOAD_WINDOW),
+            retransmission_memory: DEFAULT_RETRANSMISSION_MEMORY,
+            retransmission_spill: false,
+            socks_port: None,
+            http_proxy_port: None,
+            arbiter_layout: ArbiterLayout::shared(),
+            performance_profile: PerformanceProfile::standard(),
+            rate_card: RateCard::default(),
+            country: None,
+            exit_policy: ExitPolicy::default(),
+            exit_sla: None,
+            adaptive_hops: None,
+            fragment_mtu: None,
+            pad_packets: false,
+            cover_traffic: None,
+            relay_fairness_config: None,
+            metrics_port: None,
+            regenerate_ports: false,
+            node_addr_version: 0,
         }
     }
 }
@@ -105,28 +296,66 @@ impl SocketServer for Bootstrapper {
     fn initialize_as_privileged(&mut self, args: &Vec<String>, streams: &mut StdStreams<'_>) {
         let mut configuration = Configuration::new();
         configuration.establish(args);
-        let cryptde_ref = Bootstrapper::initialize_cryptde();
         let mut config = BootstrapperConfig::new();
         Bootstrapper::parse_args(args, &mut config);
+        let cryptde_ref = Bootstrapper::initialize_cryptde(
+            config.crypto_backend,
+            if config.persistent_identity {
+                Some(&config.accountant_config.data_directory)
+            } else {
+                None
+            },
+        );
+        if config.persistent_identity {
+            Bootstrapper::keep_clandestine_ports(&mut configuration, &mut config);
+        }
         Bootstrapper::add_clandestine_port_info(&configuration, &mut config);
+        config.protocol_pack_ports = configuration.protocol_pack_ports.clone();
+        config.socks_port = configuration.socks_port;
+        config.http_proxy_port = configuration.http_proxy_port;
+        config.relay_only = !configuration.serves_consumers();
+        Bootstrapper::warn_about_exit_tls(&config, streams);
+        if config.manage_firewall {
+            Bootstrapper::open_firewall(&mut config, streams);
+        }
+        #[cfg(feature = "fault_injection")]
+        Bootstrapper::start_fault_injection(&ParameterFinder::new(args.clone()));
         Bootstrapper::report_local_descriptor(
             cryptde_ref,
             config.neighborhood_config.local_ip_addr,
             config.neighborhood_config.clandestine_port_list.clone(),
             streams,
         );
+        let summary =
+            Bootstrapper::make_startup_summary(cryptde_ref, &config, Path::new(RESOLV_CONF_PATH));
+        Logger::new("Bootstrapper").info(format!(
+            "SubstratumNode startup summary: {}",
+            summary.to_json()
+        ));
+        config.ui_gateway_config.startup_summary = Some(summary);
         self.config = Some(config);
         self.listener_handlers =
             FuturesUnordered::<Box<dyn ListenerHandler<Item = (), Error = ()>>>::new();
 
+        let local_ip_is_ipv6 = self
+            .config
+            .as_ref()
+            .expect("Internal error")
+            .neighborhood_config
+            .local_ip_addr
+            .is_ipv6();
         configuration
             .port_configurations
             .iter()
             .for_each(|(port, port_configuration)| {
                 let mut listener_handler = self.listener_handler_factory.make();
-                match listener_handler
-                    .bind_port_and_configuration(*port, port_configuration.clone())
-                {
+                // Neighbors can only reach an IPv6 Node's clandestine ports over IPv6
+                let port_configuration = if local_ip_is_ipv6 && port_configuration.is_clandestine {
+                    port_configuration.clone().ipv6()
+                } else {
+                    port_configuration.clone()
+                };
+                match listener_handler.bind_port_and_configuration(*port, port_configuration) {
                     Ok(()) => (),
                     Err(e) => panic!("Could not listen on port {}: {}", port, e.to_string()),
                 }
@@ -163,23 +392,121 @@ impl Bootstrapper {
         }
     }
 
+    pub fn data_directory(args: &Vec<String>) -> PathBuf {
+        Bootstrapper::parse_data_dir(&ParameterFinder::new(args.clone()), &RealDirsWrapper {})
+    }
+
+    // --probe asks the DNS servers plain DNS questions on port 53, whatever --dns_protocol says
+    pub fn probe_config(args: &Vec<String>) -> ProbeConfig {
+        let finder = ParameterFinder::new(args.clone());
+        let dns_servers = if finder
+            .find_value_for(
+                "--dns_servers",
+                "--dns_servers <servers> where 'servers' is a comma-separated list of IP addresses",
+            )
+            .is_some()
+        {
+            Bootstrapper::parse_dns_server_entries(&finder)
+                .into_iter()
+                .map(|(ip_addr, _)| SocketAddr::new(ip_addr, 53))
+                .collect()
+        } else {
+            vec![]
+        };
+        ProbeConfig {
+            ip_addr_opt: Some(Bootstrapper::parse_ip(&finder))
+                .filter(|ip_addr| *ip_addr != sentinel_ip_addr()),
+            neighbor_addrs: Bootstrapper::parse_neighbor_configs(&finder, "--neighbor")
+                .into_iter()
+                .map(|(_, node_addr)| node_addr)
+                .collect(),
+            dns_servers,
+        }
+    }
+
     fn parse_args(args: &Vec<String>, config: &mut BootstrapperConfig) {
         let finder = ParameterFinder::new(args.clone());
         let local_ip_addr = Bootstrapper::parse_ip(&finder);
         config.crash_point = Bootstrapper::parse_crash_point(&finder);
         config.dns_servers = Bootstrapper::parse_dns_servers(&finder);
+        config.dns_resolver_config = Bootstrapper::parse_dns_resolver_config(&finder);
         config.neighborhood_config.neighbor_configs =
             Bootstrapper::parse_neighbor_configs(&finder, "--neighbor");
         config.neighborhood_config.is_bootstrap_node = Bootstrapper::parse_node_type(&finder);
         config.neighborhood_config.local_ip_addr = local_ip_addr;
         config.ui_gateway_config.ui_port = Bootstrapper::parse_ui_port(&finder);
+        config.ui_gateway_config.remote_access = Bootstrapper::parse_ui_remote(&finder);
+        config.ui_gateway_config.local_token = Bootstrapper::parse_ui_token(&finder);
         config.accountant_config.data_directory =
             Bootstrapper::parse_data_dir(&finder, &RealDirsWrapper {});
-        config.neighborhood_config.earning_wallet = Bootstrapper::parse_wallet_address(&finder)
+        config.ui_gateway_config.alias_directory =
+            Some(config.accountant_config.data_directory.clone());
+        config.ui_gateway_config.token_directory =
+            Some(config.accountant_config.data_directory.clone());
+        config.exit_throughput_config = ExitThroughputConfig {
+            upstream_bytes_per_second: Bootstrapper::parse_throughput_limit(
+                &finder,
+                "--exit_upstream_limit",
+            ),
+            downstream_bytes_per_second: Bootstrapper::parse_throughput_limit(
+                &finder,
+                "--exit_downstream_limit",
+            ),
+        };
+        config.exit_connect_timeouts = Bootstrapper::parse_exit_connect_timeouts(&finder);
+        config.capacity_class = Bootstrapper::parse_capacity_class(&finder);
+        config.exit_log_level = Bootstrapper::parse_exit_log_level(&finder);
+        config.privacy_policy = Bootstrapper::parse_privacy_policy(&finder);
+        config.privacy_policy_path = Bootstrapper::parse_file_path(&finder, "--privacy_policy");
+        config.request_exit_tls = Bootstrapper::parse_on_off(&finder, "--request_exit_tls");
+        config.request_exit_dns_server = Bootstrapper::parse_request_exit_dns_server(&finder);
+        config.exit_location = Bootstrapper::parse_country_code(&finder, "--exit_location");
+        config.offer_exit_tls = Bootstrapper::parse_on_off(&finder, "--offer_exit_tls");
+        config.persistent_identity = Bootstrapper::parse_on_off(&finder, "--persistent_identity");
+        config.crypto_backend = Bootstrapper::parse_crypto_backend(&finder);
+        config.manage_firewall = Bootstrapper::parse_on_off(&finder, "--manage_firewall");
+        config.probe_resistance = Bootstrapper::parse_probe_resistance(&finder);
+        config.max_report_delay = Bootstrapper::parse_max_report_delay(&finder);
+        config.route_exclusion_config = Bootstrapper::parse_route_exclusion_config(&finder);
+        config.geoip_database_path = Bootstrapper::parse_file_path(&finder, "--geoip_database");
+        config.node_record_retention = Bootstrapper::parse_node_record_retention(&finder);
+        config.ban_threshold = Bootstrapper::parse_ban_threshold(&finder);
+        config.upload_window = Bootstrapper::parse_upload_window(&finder);
+        config.retransmission_memory = Bootstrapper::parse_retransmission_memory(&finder);
+        config.retransmission_spill = Bootstrapper::parse_on_off(&finder, "--retransmission_spill");
+        let low_power = Bootstrapper::parse_on_off(&finder, "--low_power");
+        config.performance_profile = if low_power {
+            PerformanceProfile::low_power()
+        } else {
+            PerformanceProfile::standard()
+        };
+        config.arbiter_layout = Bootstrapper::parse_arbiter_layout(&finder, low_power);
+        config.rate_card = Bootstrapper::parse_rate_card(&finder);
+        config.country = Bootstrapper::parse_country_code(&finder, "--country");
+        config.exit_policy = Bootstrapper::parse_exit_policy(&finder);
+        config.exit_sla = Bootstrapper::parse_exit_sla(&finder, &config.exit_policy);
+        config.adaptive_hops = Bootstrapper::parse_adaptive_hops(&finder);
+        config.fragment_mtu = Bootstrapper::parse_fragment_mtu(&finder);
+        config.pad_packets = Bootstrapper::parse_on_off(&finder, "--pad_packets");
+        config.cover_traffic = Bootstrapper::parse_cover_traffic(&finder);
+        config.relay_fairness_config = Bootstrapper::parse_relay_fairness_config(&finder);
+        config.metrics_port = Bootstrapper::parse_metrics_port(&finder);
+        config.regenerate_ports = Bootstrapper::parse_on_off(&finder, "--regenerate_ports");
+        let earning_wallet_opt = Bootstrapper::parse_wallet_address(&finder);
+        config.neighborhood_config.earning_wallet = earning_wallet_opt
+            .clone()
             .unwrap_or(accountant::DEFAULT_EARNING_WALLET.clone());
-        // TODO: In real life this should come from a command-line parameter
+        config.earning_wallet_rotation = Bootstrapper::parse_wallet_rotation(&finder);
+        config.earning_consolidation = Bootstrapper::parse_earning_consolidation(
+            &finder,
+            &earning_wallet_opt,
+            &config.earning_wallet_rotation,
+        );
+        let consuming_wallet_opt = Bootstrapper::parse_consuming_wallet(&finder);
+        config.standby_consuming_wallet =
+            Bootstrapper::parse_standby_consuming_wallet(&finder, &consuming_wallet_opt);
         config.neighborhood_config.consuming_wallet =
-            Some(accountant::TEMPORARY_CONSUMING_WALLET.clone());
+            Some(consuming_wallet_opt.unwrap_or(accountant::TEMPORARY_CONSUMING_WALLET.clone()));
     }
 
     fn parse_crash_point(finder: &ParameterFinder) -> CrashPoint {
@@ -218,6 +545,150 @@ impl Bootstrapper {
         }
     }
 
+    fn parse_consuming_wallet(finder: &ParameterFinder) -> Option<Wallet> {
+        let usage = "--consuming_wallet <address> where 'address' is an Ethereum wallet address";
+        match finder.find_value_for("--consuming_wallet", usage) {
+            Some(address) => {
+                if !Bootstrapper::is_valid_ethereum_address(&address) {
+                    panic!(
+                        "--consuming_wallet requires a valid Ethereum wallet address, not '{}'",
+                        address
+                    );
+                }
+                Some(Wallet::new(address.as_str()))
+            }
+            None => None,
+        }
+    }
+
+    // The placeholder consuming wallet has no balance to watch, so a standby needs a real primary
+    fn parse_standby_consuming_wallet(
+        finder: &ParameterFinder,
+        consuming_wallet_opt: &Option<Wallet>,
+    ) -> Option<StandbyConsumingWalletConfig> {
+        let usage =
+            "--standby_consuming_wallet <address> where 'address' is an Ethereum wallet address";
+        let wallet = match finder.find_value_for("--standby_consuming_wallet", usage) {
+            Some(address) => {
+                if !Bootstrapper::is_valid_ethereum_address(&address) {
+                    panic!(
+                        "--standby_consuming_wallet requires a valid Ethereum wallet address, not '{}'",
+                        address
+                    );
+                }
+                Wallet::new(address.as_str())
+            }
+            None => return None,
+        };
+        match consuming_wallet_opt {
+            Some(ref consuming_wallet) if consuming_wallet == &wallet => {
+                panic!(
+                    "--standby_consuming_wallet must be a different wallet from --consuming_wallet"
+                )
+            }
+            Some(_) => (),
+            None => panic!("--standby_consuming_wallet needs --consuming_wallet too"),
+        }
+        let usage = "--consuming_wallet_threshold <wei>";
+        let threshold = match finder.find_value_for("--consuming_wallet_threshold", usage) {
+            Some(threshold_string) => match str::parse::<u128>(threshold_string.as_str()) {
+                Ok(threshold) if threshold > 0 => threshold,
+                _ => panic!(
+                    "Invalid amount for --consuming_wallet_threshold <wei>: '{}'",
+                    threshold_string
+                ),
+            },
+            None => panic!("--standby_consuming_wallet needs --consuming_wallet_threshold too"),
+        };
+        let blockchain_service_url =
+            Bootstrapper::parse_blockchain_service_url(finder, "--standby_consuming_wallet");
+        Some(StandbyConsumingWalletConfig {
+            wallet,
+            threshold,
+            blockchain_service_url,
+        })
+    }
+
+    fn parse_blockchain_service_url(finder: &ParameterFinder, needed_by: &str) -> ServiceUrl {
+        let usage = "--blockchain_service_url <URL of an Ethereum JSON-RPC service>";
+        match finder.find_value_for("--blockchain_service_url", usage) {
+            Some(url) => match ServiceUrl::parse(&url) {
+                Ok(service_url) => service_url,
+                Err(e) => panic!("Invalid URL for --blockchain_service_url: {}", e),
+            },
+            None => panic!("{} needs --blockchain_service_url too", needed_by),
+        }
+    }
+
+    // The rotated earning wallets are consolidated into the --wallet_address one. The Node only
+    // says when it's time; moving the money takes the keys to those wallets, which it never has.
+    fn parse_earning_consolidation(
+        finder: &ParameterFinder,
+        earning_wallet_opt: &Option<Wallet>,
+        rotation_opt: &Option<WalletRotation>,
+    ) -> Option<EarningConsolidationConfig> {
+        let usage = "--consolidation_threshold <wei>";
+        let threshold = match finder.find_value_for("--consolidation_threshold", usage) {
+            Some(threshold_string) => match str::parse::<u128>(threshold_string.as_str()) {
+                Ok(threshold) if threshold > 0 => threshold,
+                _ => panic!(
+                    "Invalid amount for --consolidation_threshold <wei>: '{}'",
+                    threshold_string
+                ),
+            },
+            None => return None,
+        };
+        let rotation = match rotation_opt {
+            Some(rotation) => rotation.clone(),
+            None => panic!("--consolidation_threshold needs --wallet_rotation too"),
+        };
+        let consolidation_wallet = match earning_wallet_opt {
+            Some(wallet) => wallet.clone(),
+            None => {
+                panic!("--consolidation_threshold needs --wallet_address too, to consolidate into")
+            }
+        };
+        let blockchain_service_url =
+            Bootstrapper::parse_blockchain_service_url(finder, "--consolidation_threshold");
+        Some(EarningConsolidationConfig {
+            rotation,
+            consolidation_wallet,
+            threshold,
+            blockchain_service_url,
+        })
+    }
+
+    fn parse_wallet_rotation(finder: &ParameterFinder) -> Option<WalletRotation> {
+        let usage = "--wallet_rotation <addresses> where 'addresses' is a comma-separated list of Ethereum wallet addresses";
+        let wallets: Vec<Wallet> = match finder.find_value_for("--wallet_rotation", usage) {
+            Some(addresses) => addresses
+                .split(",")
+                .map(|address| {
+                    if !Bootstrapper::is_valid_ethereum_address(address) {
+                        panic!(
+                            "--wallet_rotation requires valid Ethereum wallet addresses, not '{}'",
+                            address
+                        );
+                    }
+                    Wallet::new(address)
+                })
+                .collect(),
+            None => return None,
+        };
+        let usage = "--wallet_rotation_period <seconds>";
+        let period = match finder.find_value_for("--wallet_rotation_period", usage) {
+            Some(seconds_string) => match str::parse::<u64>(seconds_string.as_str()) {
+                Ok(seconds) if seconds > 0 => Duration::from_secs(seconds),
+                _ => panic!(
+                    "Invalid number of seconds for --wallet_rotation_period <seconds>: '{}'",
+                    seconds_string
+                ),
+            },
+            None => DEFAULT_WALLET_ROTATION_PERIOD,
+        };
+        Some(WalletRotation::new(wallets, period))
+    }
+
     fn parse_ip(finder: &ParameterFinder) -> IpAddr {
         let usage = "--ip <public IP address>";
         match finder.find_value_for("--ip", usage) {
@@ -250,679 +721,3236 @@ impl Bootstrapper {
         }
     }
 
-    fn parse_data_dir(finder: &ParameterFinder, dirs_wrapper: &DirsWrapper) -> PathBuf {
-        let usage = "--data_directory <directory>";
-        match finder.find_value_for("--data_directory", usage) {
-            Some(data_directory) => PathBuf::from(data_directory),
-            None => dirs_wrapper.data_dir().expect("Could not provide a default data directory, please specify one with --data_directory")
+    // The identity and token files are read now, so that a mistake in them stops the Node before
+    // it starts rather than when the first UI connects
+    fn parse_ui_remote(finder: &ParameterFinder) -> Option<UiRemoteAccessConfig> {
+        let usage = "--ui_remote <IP address>,<PKCS #12 identity file>,<token file>";
+        let value = finder.find_value_for("--ui_remote", usage)?;
+        let pieces: Vec<&str> = value.split(',').collect();
+        if pieces.len() != 3 {
+            panic!("Invalid value for {}: '{}'", usage, value)
         }
+        let interface = IpAddr::from_str(pieces[0])
+            .unwrap_or_else(|_| panic!("Invalid IP address for {}: '{}'", usage, pieces[0]));
+        let identity = fs::read(pieces[1])
+            .unwrap_or_else(|e| panic!("Couldn't read {} for {}: {}", pieces[1], usage, e));
+        let token = Bootstrapper::read_ui_token(pieces[2], usage);
+        Some(UiRemoteAccessConfig {
+            interface,
+            identity,
+            token,
+        })
     }
 
-    fn parse_dns_servers(finder: &ParameterFinder) -> Vec<SocketAddr> {
-        let parameter_tag = "--dns_servers";
-        let usage =
-            "--dns_servers <servers> where 'servers' is a comma-separated list of IP addresses";
+    // Only matters while the UI port is on localhost: --ui_remote brings its own token
+    fn parse_ui_token(finder: &ParameterFinder) -> Option<String> {
+        let usage = "--ui_token <token file>";
+        let path = finder.find_value_for("--ui_token", usage)?;
+        Some(Bootstrapper::read_ui_token(&path, usage))
+    }
 
-        let dns_server_strings: Vec<String> = match finder.find_value_for(parameter_tag, usage) {
-            Some(dns_server_string) => dns_server_string
-                .split(",")
-                .map(|s| String::from(s))
-                .collect(),
-            None => panic!(usage),
-        };
-        dns_server_strings
-            .iter()
-            .map(|string| match IpAddr::from_str(string) {
-                Ok(addr) => SocketAddr::new(addr, 53),
-                Err(_) => panic!(
-                    "Invalid IP address for --dns_servers <servers>: '{}'",
-                    string
+    fn read_ui_token(path: &str, usage: &str) -> String {
+        let token = fs::read_to_string(path)
+            .unwrap_or_else(|e| panic!("Couldn't read {} for {}: {}", path, usage, e))
+            .trim()
+            .to_string();
+        if token.chars().count() < MIN_UI_TOKEN_LENGTH {
+            panic!(
+                "Invalid token in {} for {}: it must be at least {} characters long",
+                path, usage, MIN_UI_TOKEN_LENGTH
+            )
+        }
+        token
+    }
+
+    fn parse_node_record_retention(finder: &ParameterFinder) -> Option<Duration> {
+        let usage = "--node_record_retention <hours>";
+        match finder.find_value_for("--node_record_retention", usage) {
+            Some(hours_string) => match str::parse::<u64>(hours_string.as_str()) {
+                Ok(hours) if hours > 0 => Some(Duration::from_secs(hours * 3600)),
+                _ => panic!(
+                    "Invalid number of hours for --node_record_retention <hours>: '{}'",
+                    hours_string
                 ),
-            })
-            .collect()
+            },
+            None => None,
+        }
     }
 
-    fn parse_node_type(finder: &ParameterFinder) -> bool {
-        let usage = "--node_type standard|bootstrap";
-        match finder.find_value_for("--node_type", usage) {
-            None => false,
-            Some(ref node_type) if node_type == "standard" => false,
-            Some(ref node_type) if node_type == "bootstrap" => true,
-            Some(ref node_type) => panic!(
-                "--node_type must be either standard or bootstrap, not {}",
-                node_type
-            ),
+    fn parse_ban_threshold(finder: &ParameterFinder) -> u32 {
+        let usage = "--ban_threshold <demerits>";
+        match finder.find_value_for("--ban_threshold", usage) {
+            Some(demerits_string) => match str::parse::<u32>(demerits_string.as_str()) {
+                Ok(demerits) => demerits,
+                _ => panic!(
+                    "Invalid demerit count for --ban_threshold <demerits>: '{}'",
+                    demerits_string
+                ),
+            },
+            None => DEFAULT_BAN_THRESHOLD,
         }
     }
 
-    fn parse_neighbor_configs(
-        finder: &ParameterFinder,
-        parameter_tag: &str,
-    ) -> Vec<(PublicKey, NodeAddr)> {
-        let usage = &format!(
-            "{} <public key>:<IP address>:<port>,<port>,...",
-            parameter_tag
-        )[..];
-        finder
-            .find_values_for(parameter_tag, usage)
-            .into_iter()
-            .map(|s| Bootstrapper::parse_neighbor_config(s, parameter_tag))
-            .collect()
+    fn parse_max_report_delay(finder: &ParameterFinder) -> Duration {
+        let usage = "--max_report_delay <seconds>";
+        match finder.find_value_for("--max_report_delay", usage) {
+            Some(seconds_string) => match str::parse::<u64>(seconds_string.as_str()) {
+                Ok(seconds) if seconds > 0 => Duration::from_secs(seconds),
+                _ => panic!(
+                    "Invalid number of seconds for --max_report_delay <seconds>: '{}'",
+                    seconds_string
+                ),
+            },
+            None => accountant::DEFAULT_MAX_REPORT_DELAY,
+        }
     }
 
-    fn parse_neighbor_config(input: String, parameter_tag: &str) -> (PublicKey, NodeAddr) {
-        let pieces: Vec<&str> = input.splitn(2, ":").collect();
-        if pieces.len() != 2 {
-            panic!(
-                "{} <public key>:<IP address>:<port>,<port>,... (not {} {})",
-                parameter_tag, parameter_tag, input
-            )
+    fn parse_upload_window(finder: &ParameterFinder) -> Option<u64> {
+        let usage = "--upload_window <packets, or 0 for no limit>";
+        match finder.find_value_for("--upload_window", usage) {
+            Some(packets_string) => match str::parse::<u64>(packets_string.as_str()) {
+                Ok(0) => None,
+                Ok(packets) => Some(packets),
+                Err(_) => panic!("Invalid window for {}: '{}'", usage, packets_string),
+            },
+            None => Some(DEFAULT_UPLOAD_WINDOW),
         }
-        let public_key = PublicKey::new(
-            &base64::decode(pieces[0]).expect(
-                format!(
-                    "Invalid Base64 for {} <public key>: '{}'",
-                    parameter_tag, pieces[0]
-                )
-                .as_str(),
-            )[..],
-        );
-        if public_key.is_empty() {
-            panic!("Blank public key for --neighbor {}", input)
+    }
+
+    fn parse_fragment_mtu(finder: &ParameterFinder) -> Option<usize> {
+        let usage = "--fragment_mtu <bytes>";
+        match finder.find_value_for("--fragment_mtu", usage) {
+            Some(bytes_string) => match str::parse::<usize>(bytes_string.as_str()) {
+                Ok(bytes) if bytes >= MIN_FRAGMENT_MTU => Some(bytes),
+                _ => panic!(
+                    "Invalid size for {}: '{}' (must be at least {})",
+                    usage, bytes_string, MIN_FRAGMENT_MTU
+                ),
+            },
+            None => None,
         }
-        let node_addr = NodeAddr::from_str(&pieces[1]).expect(
-            format!(
-                "Invalid NodeAddr for {} <NodeAddr>: '{}'",
-                parameter_tag, pieces[1]
-            )
-            .as_str(),
-        );
-        (public_key, node_addr)
     }
 
-    // TODO Possibly should be a method on BootstrapperConfig
-    fn add_clandestine_port_info(configuration: &Configuration, config: &mut BootstrapperConfig) {
-        let clandestine_ports = configuration.clandestine_ports();
-        config.clandestine_discriminator_factories = if clandestine_ports.is_empty() {
-            vec![]
-        } else {
-            configuration
-                .port_configurations
-                .get(&clandestine_ports[0])
-                .expect("Malformed configuration")
-                .discriminator_factories
-                .clone()
-        };
-        config.neighborhood_config.clandestine_port_list = clandestine_ports;
+    fn parse_cover_traffic(finder: &ParameterFinder) -> Option<u32> {
+        let usage = "--cover_traffic <packages per minute>";
+        match finder.find_value_for("--cover_traffic", usage) {
+            Some(rate_string) => match str::parse::<u32>(rate_string.as_str()) {
+                Ok(0) => None,
+                Ok(rate) => Some(rate),
+                Err(_) => panic!("Invalid rate for {}: '{}'", usage, rate_string),
+            },
+            None => None,
+        }
     }
 
-    fn initialize_cryptde() -> &'static dyn CryptDE {
-        let mut exemplar = CryptDENull::new();
-        exemplar.generate_key_pair();
-        let cryptde: &'static CryptDENull = unsafe {
-            CRYPT_DE_OPT = Some(exemplar);
-            CRYPT_DE_OPT.as_ref().expect("Internal error")
-        };
-        cryptde
+    // Weighting by payment standing only means something once relayed packages are queued
+    fn parse_relay_fairness_config(finder: &ParameterFinder) -> Option<RelayFairnessConfig> {
+        Bootstrapper::parse_throughput_limit(finder, "--relay_limit").map(|bytes_per_second| {
+            RelayFairnessConfig {
+                bytes_per_second,
+                weighted_by_standing: Bootstrapper::parse_on_off(
+                    finder,
+                    "--relay_weights_by_standing",
+                ),
+            }
+        })
     }
 
-    fn report_local_descriptor(
-        cryptde: &dyn CryptDE,
-        ip_addr: IpAddr,
-        ports: Vec<u16>,
-        streams: &mut StdStreams<'_>,
-    ) {
-        let port_strings: Vec<String> = ports.iter().map(|n| format!("{}", n)).collect();
-        let port_list = port_strings.join(",");
-        let descriptor_msg = format!(
-            "SubstratumNode local descriptor: {}:{}:{}",
-            base64::encode_config(&cryptde.public_key().as_slice(), base64::STANDARD_NO_PAD),
-            ip_addr,
-            port_list
-        );
-        writeln!(streams.stdout, "{}", descriptor_msg).expect("Internal error");
-        Logger::new("Bootstrapper").info(descriptor_msg);
+    fn parse_metrics_port(finder: &ParameterFinder) -> Option<u16> {
+        let usage = "--metrics_port <port number>";
+        match finder.find_value_for("--metrics_port", usage) {
+            Some(port_string) => match str::parse::<u16>(port_string.as_str()) {
+                Ok(port_number) if port_number >= 1024 => Some(port_number),
+                _ => panic!("Invalid port for {}: '{}'", usage, port_string),
+            },
+            None => None,
+        }
     }
-}
 
-struct RealDirsWrapper {}
+    fn parse_retransmission_memory(finder: &ParameterFinder) -> usize {
+        let usage = "--retransmission_memory <kilobytes per stream>";
+        match finder.find_value_for("--retransmission_memory", usage) {
+            Some(kilobytes_string) => match str::parse::<usize>(kilobytes_string.as_str()) {
+                Ok(kilobytes) if kilobytes > 0 => kilobytes * 1024,
+                _ => panic!("Invalid size for {}: '{}'", usage, kilobytes_string),
+            },
+            None => DEFAULT_RETRANSMISSION_MEMORY,
+        }
+    }
 
-trait DirsWrapper {
-    fn data_dir(&self) -> Option<PathBuf>;
-}
+    fn parse_throughput_limit(finder: &ParameterFinder, parameter_tag: &str) -> Option<u64> {
+        let usage = &format!("{} <bytes per second>", parameter_tag)[..];
+        match finder.find_value_for(parameter_tag, usage) {
+            Some(limit_string) => match str::parse::<u64>(limit_string.as_str()) {
+                Ok(limit) if limit > 0 => Some(limit),
+                _ => panic!("Invalid limit for {}: '{}'", usage, limit_string),
+            },
+            None => None,
+        }
+    }
 
-impl DirsWrapper for RealDirsWrapper {
-    fn data_dir(&self) -> Option<PathBuf> {
-        data_dir()
+    fn parse_rate(finder: &ParameterFinder, parameter_tag: &str) -> Option<(u64, u64)> {
+        let usage = &format!("{} <service rate>,<byte rate>", parameter_tag)[..];
+        match finder.find_value_for(parameter_tag, usage) {
+            Some(rate_string) => {
+                let rates: Vec<Result<u64, _>> =
+                    rate_string.split(',').map(str::parse::<u64>).collect();
+                match rates.as_slice() {
+                    [Ok(service_rate), Ok(byte_rate)] => Some((*service_rate, *byte_rate)),
+                    _ => panic!("Invalid rates for {}: '{}'", usage, rate_string),
+                }
+            }
+            None => None,
+        }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::actor_system_factory::ActorFactory;
-    use crate::configuration::PortConfiguration;
-    use crate::discriminator::Discriminator;
-    use crate::discriminator::UnmaskedChunk;
-    use crate::node_test_utils::extract_log;
-    use crate::node_test_utils::make_stream_handler_pool_subs_from;
-    use crate::node_test_utils::TestLogOwner;
-    use crate::stream_handler_pool::StreamHandlerPoolSubs;
-    use crate::stream_messages::AddStreamMsg;
-    use crate::sub_lib::cryptde::PlainData;
-    use crate::sub_lib::parameter_finder::ParameterFinder;
-    use crate::sub_lib::stream_connector::ConnectionInfo;
-    use crate::test_utils::logging::init_test_logging;
-    use crate::test_utils::logging::TestLog;
-    use crate::test_utils::logging::TestLogHandler;
-    use crate::test_utils::recorder::make_recorder;
-    use crate::test_utils::recorder::RecordAwaiter;
-    use crate::test_utils::recorder::Recording;
-    use crate::test_utils::test_utils::assert_contains;
-    use crate::test_utils::test_utils::FakeStreamHolder;
-    use crate::test_utils::tokio_wrapper_mocks::ReadHalfWrapperMock;
-    use crate::test_utils::tokio_wrapper_mocks::WriteHalfWrapperMock;
-    use actix::Recipient;
-    use actix::Syn;
-    use actix::System;
-    use regex::Regex;
-    use std::cell::RefCell;
-    use std::io;
-    use std::io::Error;
-    use std::io::ErrorKind;
-    use std::marker::Sync;
-    use std::net::Ipv4Addr;
-    use std::net::SocketAddr;
-    use std::ops::DerefMut;
-    use std::str::FromStr;
-    use std::sync::mpsc;
-    use std::sync::Arc;
-    use std::sync::Mutex;
-    use std::thread;
-    use tokio;
-    use tokio::prelude::Async;
-
-    struct MockDirsWrapper {}
+    // Either pair of rates left unspecified stays at the default
+    fn parse_rate_card(finder: &ParameterFinder) -> RateCard {
+        let mut rate_card = RateCard::default();
+        if let Some((service_rate, byte_rate)) = Bootstrapper::parse_rate(finder, "--routing_rate")
+        {
+            rate_card.routing_service_rate = service_rate;
+            rate_card.routing_byte_rate = byte_rate;
+        }
+        if let Some((service_rate, byte_rate)) = Bootstrapper::parse_rate(finder, "--exit_rate") {
+            rate_card.exit_service_rate = service_rate;
+            rate_card.exit_byte_rate = byte_rate;
+        }
+        rate_card
+    }
 
-    impl DirsWrapper for MockDirsWrapper {
-        fn data_dir(&self) -> Option<PathBuf> {
-            Some(PathBuf::from("mocked/path"))
+    // Blocked hosts may be given by name, which blocks everything under that name too, or by IP
+    // address: "25,465" and "ads.com,6.6.6.6"
+    fn parse_exit_policy(finder: &ParameterFinder) -> ExitPolicy {
+        let mut exit_policy = ExitPolicy::default();
+        let usage = "--exit_blocked_ports <port>[,<port>...]";
+        if let Some(ports_string) = finder.find_value_for("--exit_blocked_ports", usage) {
+            exit_policy.blocked_ports = ports_string
+                .split(',')
+                .map(|port_string| match str::parse::<u16>(port_string) {
+                    Ok(port) if port > 0 => port,
+                    _ => panic!("Invalid port for {}: '{}'", usage, port_string),
+                })
+                .collect();
+        }
+        let usage = "--exit_blocked_hosts <hostname or IP address>[,<hostname or IP address>...]";
+        if let Some(hosts_string) = finder.find_value_for("--exit_blocked_hosts", usage) {
+            for host_string in hosts_string.split(',') {
+                let hostname = host_string.trim_end_matches('.').to_lowercase();
+                if hostname.is_empty() {
+                    panic!("Invalid host for {}: '{}'", usage, hosts_string)
+                }
+                match IpAddr::from_str(&hostname) {
+                    Ok(ip_addr) => exit_policy.blocked_ip_addrs.push(ip_addr),
+                    Err(_) => exit_policy.blocked_hostnames.push(hostname),
+                }
+            }
+        }
+        let usage = "--offer_exit_dns_servers <IP address>[,<IP address>...]";
+        if let Some(servers_string) = finder.find_value_for("--offer_exit_dns_servers", usage) {
+            exit_policy.offered_dns_servers = servers_string
+                .split(',')
+                .map(|server_string| match IpAddr::from_str(server_string) {
+                    Ok(ip_addr) => ip_addr,
+                    Err(_) => panic!("Invalid IP address for {}: '{}'", usage, server_string),
+                })
+                .collect();
         }
+        exit_policy
     }
 
-    struct BadMockDirsWrapper {}
+    // What this Node promises the streams it exits: "50,1000000" is fifty streams at a time, each
+    // of them served at up to a million bytes a second. The policy it advertises alongside is a
+    // digest of what --exit_blocked_ports and --exit_blocked_hosts refuse.
+    fn parse_exit_sla(finder: &ParameterFinder, exit_policy: &ExitPolicy) -> Option<ExitSla> {
+        let usage = "--exit_sla <max streams>,<max bytes per second per stream>";
+        finder
+            .find_value_for("--exit_sla", usage)
+            .map(|sla_string| {
+                let pieces: Vec<&str> = sla_string.split(',').collect();
+                match (
+                    pieces.len(),
+                    pieces.get(0).and_then(|s| str::parse::<u32>(s).ok()),
+                    pieces.get(1).and_then(|s| str::parse::<u64>(s).ok()),
+                ) {
+                    (2, Some(max_streams), Some(max_stream_bytes_per_second))
+                        if max_streams > 0 && max_stream_bytes_per_second > 0 =>
+                    {
+                        ExitSla {
+                            max_streams,
+                            max_stream_bytes_per_second,
+                            content_policy_hash: exit_policy.summary_hash(),
+                        }
+                    }
+                    _ => panic!("Invalid SLA for {}: '{}'", usage, sla_string),
+                }
+            })
+    }
 
-    impl DirsWrapper for BadMockDirsWrapper {
-        fn data_dir(&self) -> Option<PathBuf> {
-            None
+    // Bounds on the minimum hop count, which is moved between them to keep round trips under the
+    // threshold
+    fn parse_adaptive_hops(finder: &ParameterFinder) -> Option<AdaptiveHopsConfig> {
+        let usage = "--adaptive_hops <min hops>,<max hops>,<round trip threshold in milliseconds>";
+        finder
+            .find_value_for("--adaptive_hops", usage)
+            .map(|adaptive_hops_string| {
+                let pieces: Vec<&str> = adaptive_hops_string.split(',').collect();
+                match (
+                    pieces.len(),
+                    pieces.get(0).and_then(|s| str::parse::<usize>(s).ok()),
+                    pieces.get(1).and_then(|s| str::parse::<usize>(s).ok()),
+                    pieces.get(2).and_then(|s| str::parse::<u64>(s).ok()),
+                ) {
+                    (3, Some(min_hops), Some(max_hops), Some(threshold_ms))
+                        if min_hops > 0 && max_hops >= min_hops && threshold_ms > 0 =>
+                    {
+                        AdaptiveHopsConfig {
+                            min_hops,
+                            max_hops,
+                            latency_threshold: Duration::from_millis(threshold_ms),
+                        }
+                    }
+                    _ => panic!(
+                        "Invalid hop count bounds for {}: '{}'",
+                        usage, adaptive_hops_string
+                    ),
+                }
+            })
+    }
+
+    // A public DNS server, such as 9.9.9.9, that exits are asked to look hostnames up with
+    fn parse_request_exit_dns_server(finder: &ParameterFinder) -> Option<IpAddr> {
+        let usage = "--request_exit_dns_server <IP address>";
+        finder
+            .find_value_for("--request_exit_dns_server", usage)
+            .map(|server_string| match IpAddr::from_str(&server_string) {
+                Ok(ip_addr) => ip_addr,
+                Err(_) => panic!("Invalid IP address for {}: '{}'", usage, server_string),
+            })
+    }
+
+    // Two letters, as in ISO 3166-1 alpha-2; kept in upper case
+    fn parse_country_code(finder: &ParameterFinder, parameter_tag: &str) -> Option<String> {
+        let usage = format!("{} <ISO country code>", parameter_tag);
+        finder
+            .find_value_for(parameter_tag, &usage)
+            .map(|country_string| {
+                if country_string.len() == 2
+                    && country_string.chars().all(|c| c.is_ascii_alphabetic())
+                {
+                    country_string.to_ascii_uppercase()
+                } else {
+                    panic!("Invalid country code for {}: '{}'", usage, country_string)
+                }
+            })
+    }
+
+    // The first value is for every port without one of its own: "10,993:30,995:30"
+    fn parse_exit_connect_timeouts(finder: &ParameterFinder) -> ExitConnectTimeouts {
+        let usage = "--exit_connect_timeout <seconds>[,<port>:<seconds>...]";
+        let timeouts_string = match finder.find_value_for("--exit_connect_timeout", usage) {
+            Some(timeouts_string) => timeouts_string,
+            None => return ExitConnectTimeouts::default(),
+        };
+        let parse_seconds = |seconds_string: &str| match str::parse::<u64>(seconds_string) {
+            Ok(seconds) if seconds > 0 => Duration::from_secs(seconds),
+            _ => panic!(
+                "Invalid number of seconds for {}: '{}'",
+                usage, timeouts_string
+            ),
+        };
+        let mut pieces = timeouts_string.split(',');
+        let mut connect_timeouts = ExitConnectTimeouts {
+            default: parse_seconds(pieces.next().expect("split() yielded nothing")),
+            by_port: HashMap::new(),
+        };
+        for piece in pieces {
+            let port_and_seconds: Vec<&str> = piece.split(':').collect();
+            match (
+                port_and_seconds.len(),
+                str::parse::<u16>(port_and_seconds[0]),
+            ) {
+                (2, Ok(port)) if port > 0 => {
+                    connect_timeouts
+                        .by_port
+                        .insert(port, parse_seconds(port_and_seconds[1]));
+                }
+                _ => panic!("Invalid port override for {}: '{}'", usage, piece),
+            }
         }
+        connect_timeouts
     }
 
-    struct ListenerHandlerFactoryMock {
-        log: TestLog,
-        mocks: RefCell<Vec<Box<dyn ListenerHandler<Item = (), Error = ()>>>>,
+    fn parse_capacity_class(finder: &ParameterFinder) -> CapacityClass {
+        let usage = "--capacity_class low|medium|high";
+        match finder.find_value_for("--capacity_class", usage) {
+            None => CapacityClass::default(),
+            Some(name) => match CapacityClass::from_name(&name) {
+                Some(capacity_class) => capacity_class,
+                None => panic!(
+                    "--capacity_class must be low, medium, or high, not {}",
+                    name
+                ),
+            },
+        }
     }
 
-    unsafe impl Sync for ListenerHandlerFactoryMock {}
+    // A low-power Node gets one thread for its actors, however many cores it reports, unless
+    // --dedicated_arbiters says otherwise.
+    fn parse_arbiter_layout(finder: &ParameterFinder, low_power: bool) -> ArbiterLayout {
+        let usage = "--dedicated_arbiters <actors>|none where 'actors' is a comma-separated list of hopper, accountant, and neighborhood";
+        match finder.find_value_for("--dedicated_arbiters", usage) {
+            None if low_power => ArbiterLayout::shared(),
+            None => ArbiterLayout::for_this_machine(),
+            Some(ref names) if names.trim() == "none" => ArbiterLayout::shared(),
+            Some(names) => ArbiterLayout::new(
+                names
+                    .split(',')
+                    .map(|name| match DedicatedActor::from_name(name) {
+                        Some(actor) => actor,
+                        None => panic!(
+                            "--dedicated_arbiters must name hopper, accountant, or neighborhood, not {}",
+                            name
+                        ),
+                    })
+                    .collect(),
+            ),
+        }
+    }
 
-    impl ListenerHandlerFactory for ListenerHandlerFactoryMock {
-        fn make(&self) -> Box<dyn ListenerHandler<Item = (), Error = ()>> {
-            self.log.log(format!("make ()"));
-            self.mocks.borrow_mut().remove(0)
+    fn parse_exit_log_level(finder: &ParameterFinder) -> ExitLogLevel {
+        let usage = "--exit_log off|aggregate|full";
+        match finder.find_value_for("--exit_log", usage) {
+            None => ExitLogLevel::Off,
+            Some(name) => match ExitLogLevel::from_name(&name) {
+                Some(exit_log_level) => exit_log_level,
+                None => panic!("--exit_log must be off, aggregate, or full, not {}", name),
+            },
         }
     }
 
-    impl ListenerHandlerFactoryMock {
-        fn new() -> ListenerHandlerFactoryMock {
-            ListenerHandlerFactoryMock {
-                log: TestLog::new(),
-                mocks: RefCell::new(vec![]),
-            }
+    fn parse_crypto_backend(finder: &ParameterFinder) -> CryptoBackend {
+        let usage = "--crypto null|real";
+        match finder.find_value_for("--crypto", usage) {
+            None => CryptoBackend::Null,
+            Some(name) => match CryptoBackend::from_name(&name) {
+                Some(crypto_backend) => crypto_backend,
+                None => panic!("--crypto must be null or real, not {}", name),
+            },
         }
+    }
 
-        fn add(&mut self, mock: Box<dyn ListenerHandler<Item = (), Error = ()>>) {
-            self.mocks.borrow_mut().push(mock)
+    fn parse_probe_resistance(finder: &ParameterFinder) -> ProbeResistance {
+        let usage = "--probe_resistance off|silent|http";
+        match finder.find_value_for("--probe_resistance", usage) {
+            None => ProbeResistance::Off,
+            Some(name) => match ProbeResistance::from_name(&name) {
+                Some(probe_resistance) => probe_resistance,
+                None => panic!(
+                    "--probe_resistance must be off, silent, or http, not {}",
+                    name
+                ),
+            },
         }
     }
 
-    struct ListenerHandlerNull {
-        log: Arc<Mutex<TestLog>>,
-        bind_port_and_discriminator_factories_result: Option<io::Result<()>>,
-        port_configuration_parameter: Option<PortConfiguration>,
-        add_stream_sub: Option<Recipient<Syn, AddStreamMsg>>,
-        add_stream_msgs: Arc<Mutex<Vec<AddStreamMsg>>>,
-        _listen_results: Vec<Box<dyn ListenerHandler<Item = (), Error = ()>>>,
+    fn parse_privacy_policy(finder: &ParameterFinder) -> Option<PrivacyPolicy> {
+        let usage = "--privacy_policy <path to JSON policy file>";
+        let path = finder.find_value_for("--privacy_policy", usage)?;
+        match PrivacyPolicy::load(&PathBuf::from(path)) {
+            Ok(privacy_policy) => Some(privacy_policy),
+            Err(msg) => panic!("{}", msg),
+        }
     }
 
-    impl ListenerHandler for ListenerHandlerNull {
-        fn bind_port_and_configuration(
-            &mut self,
-            port: u16,
-            discriminator_factories: PortConfiguration,
-        ) -> io::Result<()> {
-            self.log
-                .lock()
-                .unwrap()
-                .log(format!("bind_port_and_configuration ({}, ...)", port));
-            self.port_configuration_parameter = Some(discriminator_factories);
-            self.bind_port_and_discriminator_factories_result
-                .take()
-                .unwrap()
+    // Remembered so that a reload from the UI can read the file again; whether it could be read the
+    // first time is the business of the parser for what's in it
+    fn parse_file_path(finder: &ParameterFinder, parameter: &str) -> Option<PathBuf> {
+        let usage = format!("{} <path>", parameter);
+        finder
+            .find_value_for(parameter, &usage)
+            .map(|path| PathBuf::from(path))
+    }
+
+    fn parse_route_exclusion_config(finder: &ParameterFinder) -> Option<RouteExclusionConfig> {
+        let usage = "--route_exclusions <exclusion>,... where an exclusion is own_asn, asn:<AS number>, country:<country code>, or exit_country:<country code>";
+        let exclusions = match finder.find_value_for("--route_exclusions", usage) {
+            None => vec![],
+            Some(names) => names
+                .split(',')
+                .map(|name| match RouteExclusion::from_name(name) {
+                    Some(exclusion) => exclusion,
+                    None => panic!("Invalid route exclusion: '{}'. Usage: {}", name, usage),
+                })
+                .collect(),
+        };
+        let database_usage = "--geoip_database <path to IP-to-ASN TSV file>";
+        let database = match finder.find_value_for("--geoip_database", database_usage) {
+            None if exclusions.is_empty() => return None,
+            None => panic!("--route_exclusions needs a --geoip_database to look addresses up in"),
+            Some(path) => match AsnDatabase::load(&PathBuf::from(path)) {
+                Ok(database) => database,
+                Err(msg) => panic!("{}", msg),
+            },
+        };
+        // A database without exclusions is still loaded, so that a bad one is caught now rather
+        // than when exclusions are added
+        if exclusions.is_empty() {
+            return None;
         }
+        Some(RouteExclusionConfig {
+            database,
+            exclusions,
+        })
+    }
 
-        fn bind_subs(&mut self, add_stream_sub: Recipient<Syn, AddStreamMsg>) {
-            let logger = Logger::new("ListenerHandler");
-            logger.error(format!("bind_subscribers (add_stream_sub)"));
+    fn parse_on_off(finder: &ParameterFinder, parameter: &str) -> bool {
+        let usage = format!("{} on|off", parameter);
+        match finder.find_value_for(parameter, &usage) {
+            None => false,
+            Some(ref value) if value == "on" => true,
+            Some(ref value) if value == "off" => false,
+            Some(value) => panic!("{} must be on or off, not {}", parameter, value),
+        }
+    }
 
-            self.add_stream_sub = Some(add_stream_sub);
+    #[cfg(feature = "fault_injection")]
+    fn start_fault_injection(finder: &ParameterFinder) {
+        let usage = "--fault_injection_port <port number>";
+        let port = match finder.find_value_for("--fault_injection_port", usage) {
+            Some(port_string) => match str::parse::<u16>(port_string.as_str()) {
+                Ok(port_number) => port_number,
+                Err(_) => panic!("Invalid port for {}: '{}'", usage, port_string),
+            },
+            None => return,
+        };
+        match FaultInjector::global().listen(port) {
+            Ok(local_addr) => Logger::new("Bootstrapper").warning(format!(
+                "Fault injection control socket is listening on {}",
+                local_addr
+            )),
+            Err(e) => panic!(
+                "Couldn't listen for fault injection commands on port {}: {}",
+                port, e
+            ),
         }
     }
 
-    impl Future for ListenerHandlerNull {
-        type Item = ();
-        type Error = ();
+    fn parse_data_dir(finder: &ParameterFinder, dirs_wrapper: &DirsWrapper) -> PathBuf {
+        let usage = "--data_directory <directory>";
+        match finder.find_value_for("--data_directory", usage) {
+            Some(data_directory) => PathBuf::from(data_directory),
+            None => dirs_wrapper.data_dir().expect("Could not provide a default data directory, please specify one with --data_directory")
+        }
+    }
 
-        fn poll(&mut self) -> Result<Async<<Self as Future>::Item>, <Self as Future>::Error> {
-            self.log.lock().unwrap().log(format!("poll (...)"));
-            let mut add_stream_msgs = self.add_stream_msgs.lock().unwrap();
-            let add_stream_sub = self.add_stream_sub.as_ref().unwrap();
-            while add_stream_msgs.len() > 0 {
-                let add_stream_msg = add_stream_msgs.remove(0);
-                add_stream_sub
-                    .try_send(add_stream_msg)
-                    .expect("StreamHandlerPool is dead");
+    fn parse_dns_servers(finder: &ParameterFinder) -> Vec<SocketAddr> {
+        let port = Bootstrapper::parse_dns_protocol(finder).default_port();
+        Bootstrapper::parse_dns_server_entries(finder)
+            .into_iter()
+            .map(|(ip_addr, _)| SocketAddr::new(ip_addr, port))
+            .collect()
+    }
+
+    // Each server in --dns_servers may be followed by the name on its TLS certificate, as in
+    // 1.1.1.1#cloudflare-dns.com; only --dns_protocol tls makes any use of it.
+    fn parse_dns_server_entries(finder: &ParameterFinder) -> Vec<(IpAddr, Option<String>)> {
+        let parameter_tag = "--dns_servers";
+        let usage =
+            "--dns_servers <servers> where 'servers' is a comma-separated list of IP addresses";
+
+        let dns_server_strings: Vec<String> = match finder.find_value_for(parameter_tag, usage) {
+            Some(dns_server_string) => dns_server_string
+                .split(",")
+                .map(|s| String::from(s))
+                .collect(),
+            None => panic!(usage),
+        };
+        dns_server_strings
+            .iter()
+            .map(|string| {
+                let mut pieces = string.splitn(2, '#');
+                let ip_string = pieces.next().expect("Internal error");
+                let tls_dns_name = pieces.next().map(String::from);
+                match IpAddr::from_str(ip_string) {
+                    Ok(ip_addr) => (ip_addr, tls_dns_name),
+                    Err(_) => panic!(
+                        "Invalid IP address for --dns_servers <servers>: '{}'",
+                        ip_string
+                    ),
+                }
+            })
+            .collect()
+    }
+
+    fn parse_dns_protocol(finder: &ParameterFinder) -> DnsProtocol {
+        let usage = "--dns_protocol udp|tcp|tls";
+        match finder.find_value_for("--dns_protocol", usage) {
+            None => DnsProtocol::Udp,
+            Some(name) => match DnsProtocol::from_name(&name) {
+                Some(protocol) => protocol,
+                None => panic!("--dns_protocol must be udp, tcp, or tls, not {}", name),
+            },
+        }
+    }
+
+    fn parse_dns_resolver_config(finder: &ParameterFinder) -> DnsResolverConfig {
+        let protocol = Bootstrapper::parse_dns_protocol(finder);
+        let entries = Bootstrapper::parse_dns_server_entries(finder);
+        if protocol == DnsProtocol::Tls {
+            if let Some((ip_addr, _)) = entries.iter().find(|(_, name)| name.is_none()) {
+                panic!(
+                    "--dns_protocol tls needs the TLS name of every DNS server, as in {}#dns.example.com",
+                    ip_addr
+                )
             }
-            Ok(Async::NotReady)
+        }
+        DnsResolverConfig {
+            protocol,
+            tls_dns_names: entries
+                .into_iter()
+                .filter_map(|(ip_addr, name_opt)| name_opt.map(|name| (ip_addr, name)))
+                .collect(),
         }
     }
 
-    impl TestLogOwner for ListenerHandlerNull {
-        fn get_test_log(&self) -> Arc<Mutex<TestLog>> {
-            self.log.clone()
+    fn parse_node_type(finder: &ParameterFinder) -> bool {
+        let usage = "--node_type standard|bootstrap";
+        match finder.find_value_for("--node_type", usage) {
+            None => false,
+            Some(ref node_type) if node_type == "standard" => false,
+            Some(ref node_type) if node_type == "bootstrap" => true,
+            Some(ref node_type) => panic!(
+                "--node_type must be either standard or bootstrap, not {}",
+                node_type
+            ),
         }
     }
 
-    impl ListenerHandlerNull {
-        fn new(add_stream_msgs: Vec<AddStreamMsg>) -> ListenerHandlerNull {
-            ListenerHandlerNull {
-                log: Arc::new(Mutex::new(TestLog::new())),
-                bind_port_and_discriminator_factories_result: None,
-                port_configuration_parameter: None,
-                add_stream_sub: None,
-                add_stream_msgs: Arc::new(Mutex::new(add_stream_msgs)),
-                _listen_results: vec![],
-            }
+    fn parse_neighbor_configs(
+        finder: &ParameterFinder,
+        parameter_tag: &str,
+    ) -> Vec<(PublicKey, NodeAddr)> {
+        let usage = &format!(
+            "{} <public key>:<IP address>:<port>,<port>,...",
+            parameter_tag
+        )[..];
+        finder
+            .find_values_for(parameter_tag, usage)
+            .into_iter()
+            .map(|s| Bootstrapper::parse_neighbor_config(s, parameter_tag))
+            .collect()
+    }
+
+    fn parse_neighbor_config(input: String, parameter_tag: &str) -> (PublicKey, NodeAddr) {
+        let pieces: Vec<&str> = input.splitn(2, ":").collect();
+        if pieces.len() != 2 {
+            panic!(
+                "{} <public key>:<IP address>:<port>,<port>,... (not {} {})",
+                parameter_tag, parameter_tag, input
+            )
+        }
+        let public_key = PublicKey::new(
+            &base64::decode(pieces[0]).expect(
+                format!(
+                    "Invalid Base64 for {} <public key>: '{}'",
+                    parameter_tag, pieces[0]
+                )
+                .as_str(),
+            )[..],
+        );
+        if public_key.is_empty() {
+            panic!("Blank public key for --neighbor {}", input)
         }
+        let node_addr = NodeAddr::from_str(&pieces[1]).expect(
+            format!(
+                "Invalid NodeAddr for {} <NodeAddr>: '{}'",
+                parameter_tag, pieces[1]
+            )
+            .as_str(),
+        );
+        (public_key, node_addr)
+    }
+
+    // TODO Possibly should be a method on BootstrapperConfig
+    fn add_clandestine_port_info(configuration: &Configuration, config: &mut BootstrapperConfig) {
+        let clandestine_ports = configuration.clandestine_ports();
+        config.clandestine_discriminator_factories = if clandestine_ports.is_empty() {
+            vec![]
+        } else {
+            configuration
+                .port_configurations
+                .get(&clandestine_ports[0])
+                .expect("Malformed configuration")
+                .discriminator_factories
+                .clone()
+        };
+        config.neighborhood_config.clandestine_port_list = clandestine_ports;
+    }
+
+    fn initialize_cryptde(
+        crypto_backend: CryptoBackend,
+        identity_directory: Option<&Path>,
+    ) -> &'static dyn CryptDE {
+        let exemplar = match identity_directory {
+            None => {
+                let mut exemplar = crypto_backend.make_cryptde();
+                exemplar.generate_key_pair();
+                exemplar
+            }
+            Some(directory) => Bootstrapper::load_or_create_identity(crypto_backend, directory),
+        };
+        let cryptde: &'static dyn CryptDE = unsafe {
+            CRYPT_DE_OPT = Some(exemplar);
+            CRYPT_DE_OPT.as_ref().expect("Internal error").as_ref()
+        };
+        cryptde
+    }
+
+    // With --persistent_identity, the Node keeps the same key pair (and therefore the same public
+    // key and descriptor) from one start to the next
+    fn load_or_create_identity(
+        crypto_backend: CryptoBackend,
+        directory: &Path,
+    ) -> Box<dyn CryptDE> {
+        match identity_bundle::read_private_key(directory) {
+            Ok(Some(private_key)) => match crypto_backend.cryptde_from(&private_key) {
+                Ok(cryptde) => cryptde,
+                Err(msg) => panic!(
+                    "{} in {:?} isn't a {} key: {}",
+                    identity_bundle::CLANDESTINE_KEY_FILE,
+                    directory,
+                    crypto_backend.cryptde_name(),
+                    msg
+                ),
+            },
+            Ok(None) => {
+                let mut cryptde = crypto_backend.make_cryptde();
+                cryptde.generate_key_pair();
+                if let Err(msg) =
+                    identity_bundle::write_private_key(directory, &cryptde.private_key())
+                {
+                    panic!("{}", msg)
+                }
+                cryptde
+            }
+            Err(msg) => panic!("{}", msg),
+        }
+    }
+
+    // A Node that keeps its identity keeps its clandestine ports too, so that the NodeAddr its
+    // neighbors remember for it stays good. Whenever they change anyway (--regenerate_ports, or a
+    // saved port someone else has taken), the NodeRecord starts at a higher version than before, so
+    // that neighbors take the new NodeAddr over the one they know.
+    fn keep_clandestine_ports(configuration: &mut Configuration, config: &mut BootstrapperConfig) {
+        if configuration.clandestine_ports().is_empty() {
+            return;
+        }
+        let data_directory = &config.accountant_config.data_directory;
+        fs::create_dir_all(data_directory).expect(&format!(
+            "Cannot create specified data directory at {:?}",
+            data_directory
+        ));
+        let mut config_dao = DbInitializerReal::new()
+            .initialize(data_directory)
+            .expect("Could not initialize database")
+            .config;
+        let saved_ports_opt = config_dao
+            .get_string(CLANDESTINE_PORTS_CONFIG)
+            .map(|ports_str| Bootstrapper::parse_saved_ports(&ports_str));
+        let mut version = config_dao.get_u64(NODE_ADDR_VERSION_CONFIG, 0);
+        let reused = match &saved_ports_opt {
+            Some(saved_ports) if !config.regenerate_ports => {
+                configuration.reuse_clandestine_ports(saved_ports)
+            }
+            _ => false,
+        };
+        if !reused {
+            if saved_ports_opt.is_some() {
+                version += 1;
+            }
+            let mut ports = configuration.clandestine_ports();
+            ports.sort();
+            let ports_str = ports
+                .iter()
+                .map(|port| format!("{}", port))
+                .collect::<Vec<String>>()
+                .join(",");
+            config_dao.set_string(CLANDESTINE_PORTS_CONFIG, &ports_str);
+            config_dao.set_u64(NODE_ADDR_VERSION_CONFIG, version);
+            Logger::new("Bootstrapper").info(format!(
+                "Saved clandestine ports {} at NodeAddr version {}",
+                ports_str, version
+            ));
+        }
+        config.node_addr_version = version as u32;
+    }
+
+    fn parse_saved_ports(ports_str: &str) -> Vec<u16> {
+        ports_str
+            .split(",")
+            .map(|port_str| match port_str.parse::<u16>() {
+                Ok(port) => port,
+                Err(_) => panic!(
+                    "Database is corrupt: config value '{}' for {} is not a list of ports",
+                    ports_str, CLANDESTINE_PORTS_CONFIG
+                ),
+            })
+            .collect()
+    }
+
+    fn report_local_descriptor(
+        cryptde: &dyn CryptDE,
+        ip_addr: IpAddr,
+        ports: Vec<u16>,
+        streams: &mut StdStreams<'_>,
+    ) {
+        // Multinode tests scrape this line from stdout; the log gets the whole startup summary
+        let descriptor_msg = format!(
+            "SubstratumNode local descriptor: {}",
+            Bootstrapper::local_descriptor(cryptde, ip_addr, &ports)
+        );
+        writeln!(streams.stdout, "{}", descriptor_msg).expect("Internal error");
+    }
+
+    // Either side of exit TLS exposes plaintext to the exit Node, so it must never be on quietly
+    fn warn_about_exit_tls(config: &BootstrapperConfig, streams: &mut StdStreams<'_>) {
+        let mut warnings = vec![];
+        if config.request_exit_tls {
+            warnings.push("--request_exit_tls is on: your HTTP requests will leave this Node unencrypted, and the exit Node will be able to read and alter them and their responses");
+        }
+        if config.offer_exit_tls {
+            warnings.push("--offer_exit_tls is on: this Node will see other people's traffic in the clear before encrypting it for their servers");
+        }
+        warnings.into_iter().for_each(|warning| {
+            writeln!(streams.stderr, "*** WARNING *** {}", warning).expect("Internal error");
+            Logger::new("Bootstrapper").warning(format!("*** WARNING *** {}", warning));
+        });
+    }
+
+    // A firewall that can't be managed isn't fatal: the Node may still be reachable, and if it isn't
+    // the operator can open the ports by hand
+    fn open_firewall(config: &mut BootstrapperConfig, streams: &mut StdStreams<'_>) {
+        let data_directory = config.accountant_config.data_directory.clone();
+        let manager = FirewallManager::new(&data_directory);
+        let result = manager
+            .detect()
+            .and_then(|kind| manager.open(kind, &config.neighborhood_config.clandestine_port_list));
+        if let Err(msg) = result {
+            let warning = format!(
+                "Couldn't open the firewall for clandestine ports {:?}: {}",
+                config.neighborhood_config.clandestine_port_list, msg
+            );
+            writeln!(streams.stderr, "*** WARNING *** {}", warning).expect("Internal error");
+            Logger::new("Bootstrapper").warning(warning);
+        }
+        config.ui_gateway_config.firewall_directory = Some(data_directory);
+    }
+
+    fn local_descriptor(cryptde: &dyn CryptDE, ip_addr: IpAddr, ports: &Vec<u16>) -> String {
+        let port_strings: Vec<String> = ports.iter().map(|n| format!("{}", n)).collect();
+        let ip_addr_string = match ip_addr {
+            IpAddr::V4(_) => format!("{}", ip_addr),
+            IpAddr::V6(_) => format!("[{}]", ip_addr),
+        };
+        format!(
+            "{}:{}:{}",
+            base64::encode_config(&cryptde.public_key().as_slice(), base64::STANDARD_NO_PAD),
+            ip_addr_string,
+            port_strings.join(",")
+        )
+    }
+
+    fn make_startup_summary(
+        cryptde: &dyn CryptDE,
+        config: &BootstrapperConfig,
+        resolv_conf_path: &Path,
+    ) -> StartupSummary {
+        let neighborhood_config = &config.neighborhood_config;
+        StartupSummary {
+            version: String::from(env!("CARGO_PKG_VERSION")),
+            mode: String::from(if neighborhood_config.is_decentralized() {
+                "decentralized"
+            } else {
+                "zero-hop"
+            }),
+            node_type: String::from(if neighborhood_config.is_bootstrap_node {
+                "bootstrap"
+            } else {
+                "standard"
+            }),
+            node_descriptor: Bootstrapper::local_descriptor(
+                cryptde,
+                neighborhood_config.local_ip_addr,
+                &neighborhood_config.clandestine_port_list,
+            ),
+            clandestine_ports: neighborhood_config.clandestine_port_list.clone(),
+            ui_port: config.ui_gateway_config.ui_port,
+            dns_servers: config
+                .dns_servers
+                .iter()
+                .map(|server| format!("{}", server))
+                .collect(),
+            dns_subversion: Bootstrapper::dns_subversion_status(resolv_conf_path),
+            earning_wallet: neighborhood_config.earning_wallet.fingerprint(),
+            consuming_wallet: neighborhood_config
+                .consuming_wallet
+                .as_ref()
+                .map(|wallet| wallet.fingerprint()),
+            crypto_backend: String::from(config.crypto_backend.cryptde_name()),
+            db_schema_version: String::from(CURRENT_SCHEMA_VERSION),
+            adaptive_hops: config.adaptive_hops.is_some(),
+        }
+    }
+
+    // DNS is subverted when the system resolver's first nameserver is this machine, which is
+    // where the Node answers DNS queries.
+    fn dns_subversion_status(resolv_conf_path: &Path) -> String {
+        let contents = match fs::read_to_string(resolv_conf_path) {
+            Ok(contents) => contents,
+            Err(_) => return String::from("unknown"),
+        };
+        let first_nameserver = contents
+            .lines()
+            .map(|line| line.trim())
+            .filter(|line| !line.starts_with('#'))
+            .filter_map(|line| {
+                let mut words = line.split_whitespace();
+                match words.next() {
+                    Some("nameserver") => words.next(),
+                    _ => None,
+                }
+            })
+            .next();
+        String::from(match first_nameserver {
+            Some("127.0.0.1") => "subverted",
+            Some(_) => "not subverted",
+            None => "unknown",
+        })
+    }
+}
+
+struct RealDirsWrapper {}
+
+trait DirsWrapper {
+    fn data_dir(&self) -> Option<PathBuf>;
+}
+
+impl DirsWrapper for RealDirsWrapper {
+    fn data_dir(&self) -> Option<PathBuf> {
+        data_dir()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::actor_system_factory::ActorFactory;
+    use crate::configuration::PortConfiguration;
+    use crate::discriminator::Discriminator;
+    use crate::discriminator::UnmaskedChunk;
+    use crate::node_test_utils::extract_log;
+    use crate::node_test_utils::make_stream_handler_pool_subs_from;
+    use crate::node_test_utils::TestLogOwner;
+    use crate::stream_handler_pool::StreamHandlerPoolSubs;
+    use crate::stream_messages::AddStreamMsg;
+    use crate::sub_lib::cryptde::PlainData;
+    use crate::sub_lib::parameter_finder::ParameterFinder;
+    use crate::sub_lib::stream_connector::ConnectionInfo;
+    use crate::test_utils::logging::init_test_logging;
+    use crate::test_utils::logging::TestLog;
+    use crate::test_utils::logging::TestLogHandler;
+    use crate::test_utils::recorder::make_recorder;
+    use crate::test_utils::recorder::RecordAwaiter;
+    use crate::test_utils::recorder::Recording;
+    use crate::test_utils::test_utils::assert_contains;
+    use crate::test_utils::test_utils::FakeStreamHolder;
+    use crate::test_utils::tokio_wrapper_mocks::ReadHalfWrapperMock;
+    use crate::test_utils::tokio_wrapper_mocks::WriteHalfWrapperMock;
+    use actix::Recipient;
+    use actix::Syn;
+    use actix::System;
+    use regex::Regex;
+    use std::cell::RefCell;
+    use std::io;
+    use std::io::Error;
+    use std::io::ErrorKind;
+    use std::marker::Sync;
+    use std::net::Ipv4Addr;
+    use std::net::SocketAddr;
+    use std::ops::DerefMut;
+    use std::str::FromStr;
+    use std::sync::mpsc;
+    use std::sync::Arc;
+    use std::sync::Mutex;
+    use std::thread;
+    use tokio;
+    use tokio::prelude::Async;
+
+    struct MockDirsWrapper {}
+
+    impl DirsWrapper for MockDirsWrapper {
+        fn data_dir(&self) -> Option<PathBuf> {
+            Some(PathBuf::from("mocked/path"))
+        }
+    }
+
+    struct BadMockDirsWrapper {}
+
+    impl DirsWrapper for BadMockDirsWrapper {
+        fn data_dir(&self) -> Option<PathBuf> {
+            None
+        }
+    }
+
+    struct ListenerHandlerFactoryMock {
+        log: TestLog,
+        mocks: RefCell<Vec<Box<dyn ListenerHandler<Item = (), Error = ()>>>>,
+    }
+
+    unsafe impl Sync for ListenerHandlerFactoryMock {}
+
+    impl ListenerHandlerFactory for ListenerHandlerFactoryMock {
+        fn make(&self) -> Box<dyn ListenerHandler<Item = (), Error = ()>> {
+            self.log.log(format!("make ()"));
+            self.mocks.borrow_mut().remove(0)
+        }
+    }
+
+    impl ListenerHandlerFactoryMock {
+        fn new() -> ListenerHandlerFactoryMock {
+            ListenerHandlerFactoryMock {
+                log: TestLog::new(),
+                mocks: RefCell::new(vec![]),
+            }
+        }
+
+        fn add(&mut self, mock: Box<dyn ListenerHandler<Item = (), Error = ()>>) {
+            self.mocks.borrow_mut().push(mock)
+        }
+    }
+
+    struct ListenerHandlerNull {
+        log: Arc<Mutex<TestLog>>,
+        bind_port_and_discriminator_factories_result: Option<io::Result<()>>,
+        port_configuration_parameter: Option<PortConfiguration>,
+        add_stream_sub: Option<Recipient<Syn, AddStreamMsg>>,
+        add_stream_msgs: Arc<Mutex<Vec<AddStreamMsg>>>,
+        _listen_results: Vec<Box<dyn ListenerHandler<Item = (), Error = ()>>>,
+    }
+
+    impl ListenerHandler for ListenerHandlerNull {
+        fn bind_port_and_configuration(
+            &mut self,
+            port: u16,
+            discriminator_factories: PortConfiguration,
+        ) -> io::Result<()> {
+            self.log
+                .lock()
+                .unwrap()
+                .log(format!("bind_port_and_configuration ({}, ...)", port));
+            self.port_configuration_parameter = Some(discriminator_factories);
+            self.bind_port_and_discriminator_factories_result
+                .take()
+                .unwrap()
+        }
+
+        fn bind_subs(&mut self, add_stream_sub: Recipient<Syn, AddStreamMsg>) {
+            let logger = Logger::new("ListenerHandler");
+            logger.error(format!("bind_subscribers (add_stream_sub)"));
+
+            self.add_stream_sub = Some(add_stream_sub);
+        }
+    }
+
+    impl Future for ListenerHandlerNull {
+        type Item = ();
+        type Error = ();
+
+        fn poll(&mut self) -> Result<Async<<Self as Future>::Item>, <Self as Future>::Error> {
+            self.log.lock().unwrap().log(format!("poll (...)"));
+            let mut add_stream_msgs = self.add_stream_msgs.lock().unwrap();
+            let add_stream_sub = self.add_stream_sub.as_ref().unwrap();
+            while add_stream_msgs.len() > 0 {
+                let add_stream_msg = add_stream_msgs.remove(0);
+                add_stream_sub
+                    .try_send(add_stream_msg)
+                    .expect("StreamHandlerPool is dead");
+            }
+            Ok(Async::NotReady)
+        }
+    }
+
+    impl TestLogOwner for ListenerHandlerNull {
+        fn get_test_log(&self) -> Arc<Mutex<TestLog>> {
+            self.log.clone()
+        }
+    }
+
+    impl ListenerHandlerNull {
+        fn new(add_stream_msgs: Vec<AddStreamMsg>) -> ListenerHandlerNull {
+            ListenerHandlerNull {
+                log: Arc::new(Mutex::new(TestLog::new())),
+                bind_port_and_discriminator_factories_result: None,
+                port_configuration_parameter: None,
+                add_stream_sub: None,
+                add_stream_msgs: Arc::new(Mutex::new(add_stream_msgs)),
+                _listen_results: vec![],
+            }
+        }
+
+        fn bind_port_result(mut self, result: io::Result<()>) -> ListenerHandlerNull {
+            self.bind_port_and_discriminator_factories_result = Some(result);
+            self
+        }
+    }
+
+    fn make_default_cli_params() -> Vec<String> {
+        vec![
+            String::from("--dns_servers"),
+            String::from("222.222.222.222"),
+            String::from("--port_count"),
+            String::from("0"),
+        ]
+    }
+
+    #[test]
+    fn knows_its_name() {
+        let subject = BootstrapperBuilder::new().build();
+
+        let result = subject.name();
+
+        assert_eq!(result, String::from("Dispatcher"));
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Missing value for --wallet_address: --wallet_address <address> where 'address' is an Ethereum wallet address"
+    )]
+    fn parse_wallet_address_requires_an_address() {
+        let finder = ParameterFinder::new(vec![String::from("--wallet_address")]);
+
+        Bootstrapper::parse_wallet_address(&finder);
+    }
+
+    #[test]
+    #[should_panic(expected = "--wallet_address requires a valid Ethereum wallet address")]
+    fn parse_wallet_address_requires_an_address_that_is_42_characters_long() {
+        let finder = ParameterFinder::new(vec![
+            String::from("--wallet_address"),
+            String::from("my-favorite-wallet.com"),
+        ]);
+
+        Bootstrapper::parse_wallet_address(&finder);
+    }
+
+    #[test]
+    #[should_panic(expected = "--wallet_address requires a valid Ethereum wallet address")]
+    fn parse_wallet_address_must_start_with_0x() {
+        let finder = ParameterFinder::new(vec![
+            String::from("--wallet_address"),
+            String::from("x0my-favorite-wallet.com222222222222222222"),
+        ]);
+
+        Bootstrapper::parse_wallet_address(&finder);
+    }
+
+    #[test]
+    #[should_panic(expected = "--wallet_address requires a valid Ethereum wallet address")]
+    fn parse_wallet_address_must_contain_only_hex_characters() {
+        let finder = ParameterFinder::new(vec![
+            String::from("--wallet_address"),
+            String::from("0x9707f21F95B9839A54605100Ca69dCc2e7eaA26q"),
+        ]);
+
+        Bootstrapper::parse_wallet_address(&finder);
+    }
+
+    #[test]
+    fn parse_wallet_address_returns_none_if_no_address_supplied() {
+        let finder = ParameterFinder::new(vec![]);
+
+        assert_eq!(Bootstrapper::parse_wallet_address(&finder), None);
+    }
+
+    #[test]
+    fn parse_wallet_rotation_returns_none_if_no_addresses_supplied() {
+        let finder = ParameterFinder::new(vec![]);
+
+        assert_eq!(Bootstrapper::parse_wallet_rotation(&finder), None);
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "--wallet_rotation requires valid Ethereum wallet addresses, not 'booga'"
+    )]
+    fn parse_wallet_rotation_requires_valid_addresses() {
+        let finder = ParameterFinder::new(
+            vec![
+                "--wallet_rotation",
+                "0xbDfeFf9A1f4A1bdF483d680046344316019C58CF,booga",
+            ]
+            .into_iter()
+            .map(String::from)
+            .collect(),
+        );
+
+        Bootstrapper::parse_wallet_rotation(&finder);
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Invalid number of seconds for --wallet_rotation_period <seconds>: '0'"
+    )]
+    fn parse_wallet_rotation_rejects_a_zero_period() {
+        let finder = ParameterFinder::new(
+            vec![
+                "--wallet_rotation",
+                "0xbDfeFf9A1f4A1bdF483d680046344316019C58CF",
+                "--wallet_rotation_period",
+                "0",
+            ]
+            .into_iter()
+            .map(String::from)
+            .collect(),
+        );
+
+        Bootstrapper::parse_wallet_rotation(&finder);
+    }
+
+    #[test]
+    fn parse_wallet_rotation_defaults_to_a_daily_period() {
+        let finder = ParameterFinder::new(
+            vec![
+                "--wallet_rotation",
+                "0xbDfeFf9A1f4A1bdF483d680046344316019C58CF,0x9707f21F95B9839A54605100Ca69dCc2e7eaA26B",
+            ]
+            .into_iter()
+            .map(String::from)
+            .collect(),
+        );
+
+        let result = Bootstrapper::parse_wallet_rotation(&finder);
+
+        assert_eq!(
+            result,
+            Some(WalletRotation::new(
+                vec![
+                    Wallet::new("0xbDfeFf9A1f4A1bdF483d680046344316019C58CF"),
+                    Wallet::new("0x9707f21F95B9839A54605100Ca69dCc2e7eaA26B"),
+                ],
+                DEFAULT_WALLET_ROTATION_PERIOD,
+            ))
+        );
+    }
+
+    fn make_standby_finder(args: Vec<&str>) -> ParameterFinder {
+        ParameterFinder::new(args.into_iter().map(String::from).collect())
+    }
+
+    fn primary_consuming_wallet() -> Option<Wallet> {
+        Some(Wallet::new("0x2345678901234567890123456789012345678901"))
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "--consuming_wallet requires a valid Ethereum wallet address, not 'booga'"
+    )]
+    fn parse_consuming_wallet_requires_a_valid_address() {
+        let finder = make_standby_finder(vec!["--consuming_wallet", "booga"]);
+
+        Bootstrapper::parse_consuming_wallet(&finder);
+    }
+
+    #[test]
+    fn parse_standby_consuming_wallet_returns_none_if_no_standby_supplied() {
+        let finder = make_standby_finder(vec!["--consuming_wallet_threshold", "1000"]);
+
+        let result =
+            Bootstrapper::parse_standby_consuming_wallet(&finder, &primary_consuming_wallet());
+
+        assert_eq!(result, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "--standby_consuming_wallet needs --consuming_wallet too")]
+    fn parse_standby_consuming_wallet_needs_a_primary_to_stand_by_for() {
+        let finder = make_standby_finder(vec![
+            "--standby_consuming_wallet",
+            "0x3456789012345678901234567890123456789012",
+            "--consuming_wallet_threshold",
+            "1000",
+            "--blockchain_service_url",
+            "http://127.0.0.1:8545",
+        ]);
+
+        Bootstrapper::parse_standby_consuming_wallet(&finder, &None);
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "--standby_consuming_wallet must be a different wallet from --consuming_wallet"
+    )]
+    fn parse_standby_consuming_wallet_rejects_the_primary_itself() {
+        let finder = make_standby_finder(vec![
+            "--standby_consuming_wallet",
+            "0x2345678901234567890123456789012345678901",
+            "--consuming_wallet_threshold",
+            "1000",
+            "--blockchain_service_url",
+            "http://127.0.0.1:8545",
+        ]);
+
+        Bootstrapper::parse_standby_consuming_wallet(&finder, &primary_consuming_wallet());
+    }
+
+    #[test]
+    #[should_panic(expected = "--standby_consuming_wallet needs --consuming_wallet_threshold too")]
+    fn parse_standby_consuming_wallet_needs_a_threshold() {
+        let finder = make_standby_finder(vec![
+            "--standby_consuming_wallet",
+            "0x3456789012345678901234567890123456789012",
+            "--blockchain_service_url",
+            "http://127.0.0.1:8545",
+        ]);
+
+        Bootstrapper::parse_standby_consuming_wallet(&finder, &primary_consuming_wallet());
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid amount for --consuming_wallet_threshold <wei>: '0'")]
+    fn parse_standby_consuming_wallet_rejects_a_zero_threshold() {
+        let finder = make_standby_finder(vec![
+            "--standby_consuming_wallet",
+            "0x3456789012345678901234567890123456789012",
+            "--consuming_wallet_threshold",
+            "0",
+            "--blockchain_service_url",
+            "http://127.0.0.1:8545",
+        ]);
+
+        Bootstrapper::parse_standby_consuming_wallet(&finder, &primary_consuming_wallet());
+    }
+
+    #[test]
+    #[should_panic(expected = "--standby_consuming_wallet needs --blockchain_service_url too")]
+    fn parse_standby_consuming_wallet_needs_a_blockchain_service() {
+        let finder = make_standby_finder(vec![
+            "--standby_consuming_wallet",
+            "0x3456789012345678901234567890123456789012",
+            "--consuming_wallet_threshold",
+            "1000",
+        ]);
+
+        Bootstrapper::parse_standby_consuming_wallet(&finder, &primary_consuming_wallet());
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Invalid URL for --blockchain_service_url: 'ws://127.0.0.1:8546' doesn't start with http:// or https://"
+    )]
+    fn parse_standby_consuming_wallet_rejects_a_blockchain_service_it_cant_talk_to() {
+        let finder = make_standby_finder(vec![
+            "--standby_consuming_wallet",
+            "0x3456789012345678901234567890123456789012",
+            "--consuming_wallet_threshold",
+            "1000",
+            "--blockchain_service_url",
+            "ws://127.0.0.1:8546",
+        ]);
+
+        Bootstrapper::parse_standby_consuming_wallet(&finder, &primary_consuming_wallet());
+    }
+
+    fn earning_rotation() -> Option<WalletRotation> {
+        Some(WalletRotation::new(
+            vec![
+                Wallet::new("0x9707f21F95B9839A54605100Ca69dCc2e7eaA26B"),
+                Wallet::new("0x3456789012345678901234567890123456789012"),
+            ],
+            DEFAULT_WALLET_ROTATION_PERIOD,
+        ))
+    }
+
+    fn consolidation_wallet() -> Option<Wallet> {
+        Some(Wallet::new("0xbDfeFf9A1f4A1bdF483d680046344316019C58CF"))
+    }
+
+    #[test]
+    fn parse_earning_consolidation_returns_none_without_a_threshold() {
+        let finder = make_standby_finder(vec!["--blockchain_service_url", "http://127.0.0.1:8545"]);
+
+        let result = Bootstrapper::parse_earning_consolidation(
+            &finder,
+            &consolidation_wallet(),
+            &earning_rotation(),
+        );
+
+        assert_eq!(result, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "--consolidation_threshold needs --wallet_rotation too")]
+    fn parse_earning_consolidation_needs_wallets_to_consolidate() {
+        let finder = make_standby_finder(vec![
+            "--consolidation_threshold",
+            "1000",
+            "--blockchain_service_url",
+            "http://127.0.0.1:8545",
+        ]);
+
+        Bootstrapper::parse_earning_consolidation(&finder, &consolidation_wallet(), &None);
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "--consolidation_threshold needs --wallet_address too, to consolidate into"
+    )]
+    fn parse_earning_consolidation_needs_a_wallet_to_consolidate_into() {
+        let finder = make_standby_finder(vec![
+            "--consolidation_threshold",
+            "1000",
+            "--blockchain_service_url",
+            "http://127.0.0.1:8545",
+        ]);
+
+        Bootstrapper::parse_earning_consolidation(&finder, &None, &earning_rotation());
+    }
+
+    #[test]
+    #[should_panic(expected = "--consolidation_threshold needs --blockchain_service_url too")]
+    fn parse_earning_consolidation_needs_a_blockchain_service() {
+        let finder = make_standby_finder(vec!["--consolidation_threshold", "1000"]);
+
+        Bootstrapper::parse_earning_consolidation(
+            &finder,
+            &consolidation_wallet(),
+            &earning_rotation(),
+        );
+    }
+
+    #[test]
+    fn parse_earning_consolidation_handles_happy_path() {
+        let finder = make_standby_finder(vec![
+            "--consolidation_threshold",
+            "1000000000000000000",
+            "--blockchain_service_url",
+            "http://127.0.0.1:8545",
+        ]);
+
+        let result = Bootstrapper::parse_earning_consolidation(
+            &finder,
+            &consolidation_wallet(),
+            &earning_rotation(),
+        );
+
+        assert_eq!(
+            result,
+            Some(EarningConsolidationConfig {
+                rotation: earning_rotation().unwrap(),
+                consolidation_wallet: consolidation_wallet().unwrap(),
+                threshold: 1_000_000_000_000_000_000,
+                blockchain_service_url: ServiceUrl::parse("http://127.0.0.1:8545").unwrap(),
+            })
+        );
+    }
+
+    #[test]
+    fn parse_wallet_address_handles_happy_path() {
+        let finder = ParameterFinder::new(vec![
+            String::from("--wallet_address"),
+            String::from("0xbDfeFf9A1f4A1bdF483d680046344316019C58CF"),
+        ]);
+
+        assert_eq!(
+            Bootstrapper::parse_wallet_address(&finder),
+            Some(Wallet::new("0xbDfeFf9A1f4A1bdF483d680046344316019C58CF"))
+        );
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "--dns_servers <servers> where 'servers' is a comma-separated list of IP addresses"
+    )]
+    fn parse_dns_servers_requires_dns_servers() {
+        let finder = ParameterFinder::new(vec![
+            String::from("--not_dns_servers"),
+            String::from("1.2.3.4"),
+        ]);
+
+        Bootstrapper::parse_dns_servers(&finder);
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid IP address for --dns_servers <servers>: '1.2.3.256'")]
+    fn parse_dns_servers_catches_invalid_ip_addresses() {
+        let finder = ParameterFinder::new(vec![
+            String::from("--dns_servers"),
+            String::from("1.2.3.256"),
+        ]);
+
+        Bootstrapper::parse_dns_servers(&finder);
+    }
+
+    #[test]
+    fn parse_dns_servers_ignores_second_server_list() {
+        let finder = ParameterFinder::new(
+            vec![
+                "--dns_servers",
+                "1.2.3.4,2.3.4.5",
+                "--dns_servers",
+                "3.4.5.6",
+            ]
+            .into_iter()
+            .map(String::from)
+            .collect(),
+        );
+
+        let socket_addrs = Bootstrapper::parse_dns_servers(&finder);
+
+        assert_eq!(
+            socket_addrs,
+            vec!(
+                SocketAddr::from_str("1.2.3.4:53").unwrap(),
+                SocketAddr::from_str("2.3.4.5:53").unwrap()
+            )
+        )
+    }
+
+    #[test]
+    fn parse_dns_servers_uses_the_dns_over_tls_port_for_tls() {
+        let finder = ParameterFinder::new(
+            vec![
+                "--dns_servers",
+                "1.1.1.1#cloudflare-dns.com,9.9.9.9#dns.quad9.net",
+                "--dns_protocol",
+                "tls",
+            ]
+            .into_iter()
+            .map(String::from)
+            .collect(),
+        );
+
+        let socket_addrs = Bootstrapper::parse_dns_servers(&finder);
+
+        assert_eq!(
+            socket_addrs,
+            vec!(
+                SocketAddr::from_str("1.1.1.1:853").unwrap(),
+                SocketAddr::from_str("9.9.9.9:853").unwrap()
+            )
+        )
+    }
+
+    #[test]
+    fn parse_dns_protocol_defaults_to_udp() {
+        let finder = ParameterFinder::new(vec![]);
+
+        let result = Bootstrapper::parse_dns_protocol(&finder);
+
+        assert_eq!(result, DnsProtocol::Udp)
+    }
+
+    #[test]
+    fn parse_dns_protocol_works() {
+        let finder = ParameterFinder::new(
+            vec!["--dns_protocol", "tcp"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+        );
+
+        let result = Bootstrapper::parse_dns_protocol(&finder);
+
+        assert_eq!(result, DnsProtocol::Tcp)
+    }
+
+    #[test]
+    #[should_panic(expected = "--dns_protocol must be udp, tcp, or tls, not https")]
+    fn parse_dns_protocol_complains_about_unknown_protocols() {
+        let finder = ParameterFinder::new(
+            vec!["--dns_protocol", "https"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+        );
+
+        Bootstrapper::parse_dns_protocol(&finder);
+    }
+
+    #[test]
+    fn parse_dns_resolver_config_collects_tls_names_by_server() {
+        let finder = ParameterFinder::new(
+            vec![
+                "--dns_servers",
+                "1.1.1.1#cloudflare-dns.com,9.9.9.9#dns.quad9.net",
+                "--dns_protocol",
+                "tls",
+            ]
+            .into_iter()
+            .map(String::from)
+            .collect(),
+        );
+
+        let result = Bootstrapper::parse_dns_resolver_config(&finder);
+
+        assert_eq!(
+            result,
+            DnsResolverConfig {
+                protocol: DnsProtocol::Tls,
+                tls_dns_names: vec![
+                    (
+                        IpAddr::from_str("1.1.1.1").unwrap(),
+                        String::from("cloudflare-dns.com")
+                    ),
+                    (
+                        IpAddr::from_str("9.9.9.9").unwrap(),
+                        String::from("dns.quad9.net")
+                    ),
+                ]
+                .into_iter()
+                .collect(),
+            }
+        )
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "--dns_protocol tls needs the TLS name of every DNS server, as in 9.9.9.9#dns.example.com"
+    )]
+    fn parse_dns_resolver_config_complains_about_tls_servers_without_names() {
+        let finder = ParameterFinder::new(
+            vec![
+                "--dns_servers",
+                "1.1.1.1#cloudflare-dns.com,9.9.9.9",
+                "--dns_protocol",
+                "tls",
+            ]
+            .into_iter()
+            .map(String::from)
+            .collect(),
+        );
+
+        Bootstrapper::parse_dns_resolver_config(&finder);
+    }
+
+    #[test]
+    fn tls_names_make_no_difference_to_plain_dns_servers() {
+        let finder = ParameterFinder::new(
+            vec!["--dns_servers", "1.1.1.1#cloudflare-dns.com,9.9.9.9"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+        );
+
+        let result = Bootstrapper::parse_dns_resolver_config(&finder);
+        let socket_addrs = Bootstrapper::parse_dns_servers(&finder);
+
+        assert_eq!(result.protocol, DnsProtocol::Udp);
+        assert_eq!(
+            socket_addrs,
+            vec!(
+                SocketAddr::from_str("1.1.1.1:53").unwrap(),
+                SocketAddr::from_str("9.9.9.9:53").unwrap()
+            )
+        )
+    }
+
+    #[test]
+    #[should_panic(expected = "--neighbor <public key>:<IP address>:<port>,<port>,...")]
+    fn parse_neighbor_configs_requires_two_pieces_to_a_configuration() {
+        let finder = ParameterFinder::new(
+            vec!["--neighbor", "only_one_piece"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+        );
+
+        Bootstrapper::parse_neighbor_configs(&finder, "--neighbor");
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid Base64 for --neighbor <public key>: 'bad_key'")]
+    fn parse_neighbor_configs_complains_about_bad_base_64() {
+        let finder = ParameterFinder::new(
+            vec!["--neighbor", "bad_key:1.2.3.4:1234,2345"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+        );
+
+        Bootstrapper::parse_neighbor_configs(&finder, "--neighbor");
+    }
+
+    #[test]
+    #[should_panic(expected = "Blank public key for --neighbor :1.2.3.4:1234,2345")]
+    fn parse_neighbor_configs_complains_about_blank_public_key() {
+        let finder = ParameterFinder::new(
+            vec!["--neighbor", ":1.2.3.4:1234,2345"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+        );
+
+        Bootstrapper::parse_neighbor_configs(&finder, "--neighbor");
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid NodeAddr for --neighbor <NodeAddr>: 'BadNodeAddr'")]
+    fn parse_neighbor_configs_complains_about_bad_node_addr() {
+        let finder = ParameterFinder::new(
+            vec!["--neighbor", "R29vZEtleQ==:BadNodeAddr"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+        );
+
+        Bootstrapper::parse_neighbor_configs(&finder, "--neighbor");
+    }
+
+    #[test]
+    fn parse_neighbor_configs_handles_the_happy_path() {
+        let finder = ParameterFinder::new(
+            vec![
+                "--booga",
+                "R29vZEtleQ:1.2.3.4:1234,2345,3456",
+                "--irrelevant",
+                "parameter",
+                "--booga",
+                "QW5vdGhlckdvb2RLZXk:2.3.4.5:4567,5678,6789",
+            ]
+            .into_iter()
+            .map(String::from)
+            .collect(),
+        );
+
+        let result = Bootstrapper::parse_neighbor_configs(&finder, "--booga");
+
+        assert_eq!(
+            result,
+            vec!(
+                (
+                    PublicKey::new(b"GoodKey"),
+                    NodeAddr::new(
+                        &IpAddr::from_str("1.2.3.4").unwrap(),
+                        &vec!(1234, 2345, 3456),
+                    )
+                ),
+                (
+                    PublicKey::new(b"AnotherGoodKey"),
+                    NodeAddr::new(
+                        &IpAddr::from_str("2.3.4.5").unwrap(),
+                        &vec!(4567, 5678, 6789),
+                    )
+                )
+            )
+        )
+    }
+
+    #[test]
+    fn parse_neighbor_configs_handles_ipv6_neighbors() {
+        let finder = ParameterFinder::new(
+            vec!["--neighbor", "R29vZEtleQ:[2001:db8::1]:1234,2345"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+        );
+
+        let result = Bootstrapper::parse_neighbor_configs(&finder, "--neighbor");
+
+        assert_eq!(
+            result,
+            vec!((
+                PublicKey::new(b"GoodKey"),
+                NodeAddr::new(&IpAddr::from_str("2001:db8::1").unwrap(), &vec!(1234, 2345),)
+            ))
+        )
+    }
+
+    #[test]
+    fn parse_node_type_handles_standard() {
+        let finder = ParameterFinder::new(
+            vec!["--node_type", "standard"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+        );
+
+        let result = Bootstrapper::parse_node_type(&finder);
+
+        assert_eq!(result, false);
+    }
+
+    #[test]
+    fn parse_node_type_handles_bootstrap() {
+        let finder = ParameterFinder::new(
+            vec!["--node_type", "bootstrap"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+        );
+
+        let result = Bootstrapper::parse_node_type(&finder);
+
+        assert_eq!(result, true);
+    }
+
+    #[test]
+    fn parse_node_type_defaults_to_standard() {
+        let finder = ParameterFinder::new(
+            vec!["--irrelevant", "parameter"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+        );
+
+        let result = Bootstrapper::parse_node_type(&finder);
+
+        assert_eq!(result, false);
+    }
+
+    #[test]
+    #[should_panic(expected = "--node_type must be either standard or bootstrap, not booga")]
+    fn parse_node_type_complains_about_bad_node_type() {
+        let finder = ParameterFinder::new(
+            vec!["--node_type", "booga"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+        );
+
+        Bootstrapper::parse_node_type(&finder);
+    }
+
+    #[test]
+    fn parse_ip_defaults() {
+        let finder = ParameterFinder::new(
+            vec!["--irrelevant", "parameter"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+        );
+
+        let result = Bootstrapper::parse_ip(&finder);
+
+        assert_eq!(result, sentinel_ip_addr())
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid IP address for --ip <public IP address>: 'booga'")]
+    fn parse_complains_about_bad_ip_address() {
+        let finder = ParameterFinder::new(
+            vec!["--ip", "booga"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+        );
+
+        Bootstrapper::parse_ip(&finder);
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid port for --ui_port <port number>: 'booga'")]
+    fn parse_complains_about_non_numeric_ui_port() {
+        let finder = ParameterFinder::new(
+            vec!["--ui_port", "booga"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+        );
+
+        Bootstrapper::parse_ui_port(&finder);
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid port for --ui_port <port number>: '1023'")]
+    fn parse_complains_about_ui_port_too_low() {
+        let finder = ParameterFinder::new(
+            vec!["--ui_port", "1023"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+        );
+
+        Bootstrapper::parse_ui_port(&finder);
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid port for --ui_port <port number>: '65536'")]
+    fn parse_complains_about_ui_port_too_high() {
+        let finder = ParameterFinder::new(
+            vec!["--ui_port", "65536"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+        );
+
+        Bootstrapper::parse_ui_port(&finder);
+    }
+
+    #[test]
+    fn parse_ui_port_works() {
+        let finder = ParameterFinder::new(
+            vec!["--ui_port", "5335"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+        );
+
+        let result = Bootstrapper::parse_ui_port(&finder);
+
+        assert_eq!(result, 5335)
+    }
+
+    fn make_ui_remote_files(name: &str, token: &str) -> (String, String) {
+        let directory = PathBuf::from(format!("generated/test/bootstrapper/{}", name));
+        fs::remove_dir_all(&directory).is_ok();
+        fs::create_dir_all(&directory).unwrap();
+        let identity_path = directory.join("identity.p12");
+        let token_path = directory.join("token");
+        fs::write(&identity_path, b"identity bytes").unwrap();
+        fs::write(&token_path, token).unwrap();
+        (
+            identity_path.to_str().unwrap().to_string(),
+            token_path.to_str().unwrap().to_string(),
+        )
+    }
+
+    fn parse_ui_remote(value: &str) -> Option<UiRemoteAccessConfig> {
+        Bootstrapper::parse_ui_remote(&ParameterFinder::new(vec![
+            String::from("--ui_remote"),
+            String::from(value),
+        ]))
+    }
+
+    #[test]
+    fn parse_ui_remote_reads_the_identity_and_the_token() {
+        let (identity_path, token_path) =
+            make_ui_remote_files("parse_ui_remote_works", "  correct horse battery staple\n");
+
+        let result = parse_ui_remote(&format!("10.0.0.5,{},{}", identity_path, token_path));
+
+        assert_eq!(
+            result,
+            Some(UiRemoteAccessConfig {
+                interface: IpAddr::from_str("10.0.0.5").unwrap(),
+                identity: b"identity bytes".to_vec(),
+                token: String::from("correct horse battery staple"),
+            })
+        );
+    }
+
+    #[test]
+    fn parse_ui_remote_defaults_to_local_access_only() {
+        let result = Bootstrapper::parse_ui_remote(&ParameterFinder::new(vec![]));
+
+        assert_eq!(result, None);
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Invalid value for --ui_remote <IP address>,<PKCS #12 identity file>,<token file>: '10.0.0.5,identity.p12'"
+    )]
+    fn parse_ui_remote_complains_about_missing_files() {
+        parse_ui_remote("10.0.0.5,identity.p12");
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Invalid IP address for --ui_remote <IP address>,<PKCS #12 identity file>,<token file>: 'booga'"
+    )]
+    fn parse_ui_remote_complains_about_a_bad_ip_address() {
+        parse_ui_remote("booga,identity.p12,token");
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "for --ui_remote <IP address>,<PKCS #12 identity file>,<token file>: it must be at least 16 characters long"
+    )]
+    fn parse_ui_remote_complains_about_a_short_token() {
+        let (identity_path, token_path) =
+            make_ui_remote_files("parse_ui_remote_complains_about_a_short_token", "hunter2\n");
+
+        parse_ui_remote(&format!("10.0.0.5,{},{}", identity_path, token_path));
+    }
+
+    #[test]
+    fn parse_ui_token_reads_the_token() {
+        let (_, token_path) = make_ui_remote_files(
+            "parse_ui_token_reads_the_token",
+            "correct horse battery staple\n",
+        );
+
+        let result = Bootstrapper::parse_ui_token(&ParameterFinder::new(vec![
+            String::from("--ui_token"),
+            token_path,
+        ]));
+
+        assert_eq!(result, Some(String::from("correct horse battery staple")));
+    }
+
+    #[test]
+    fn parse_ui_token_defaults_to_no_token() {
+        let result = Bootstrapper::parse_ui_token(&ParameterFinder::new(vec![]));
+
+        assert_eq!(result, None);
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "for --ui_token <token file>: it must be at least 16 characters long"
+    )]
+    fn parse_ui_token_complains_about_a_short_token() {
+        let (_, token_path) =
+            make_ui_remote_files("parse_ui_token_complains_about_a_short_token", "hunter2\n");
+
+        Bootstrapper::parse_ui_token(&ParameterFinder::new(vec![
+            String::from("--ui_token"),
+            token_path,
+        ]));
+    }
+
+    #[test]
+    fn parse_ui_port_defaults() {
+        let finder = ParameterFinder::new(vec![]);
+
+        let result = Bootstrapper::parse_ui_port(&finder);
+
+        assert_eq!(result, DEFAULT_UI_PORT)
+    }
+
+    #[test]
+    fn parse_node_record_retention_works() {
+        let finder = ParameterFinder::new(
+            vec!["--node_record_retention", "24"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+        );
+
+        let result = Bootstrapper::parse_node_record_retention(&finder);
+
+        assert_eq!(result, Some(Duration::from_secs(86400)))
+    }
+
+    #[test]
+    fn parse_node_record_retention_defaults_to_none() {
+        let finder = ParameterFinder::new(vec![]);
+
+        let result = Bootstrapper::parse_node_record_retention(&finder);
+
+        assert_eq!(result, None)
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid number of hours for --node_record_retention <hours>: '0'")]
+    fn parse_complains_about_zero_node_record_retention() {
+        let finder = ParameterFinder::new(
+            vec!["--node_record_retention", "0"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+        );
+
+        Bootstrapper::parse_node_record_retention(&finder);
+    }
+
+    #[test]
+    fn parse_ban_threshold_works() {
+        let finder = ParameterFinder::new(
+            vec!["--ban_threshold", "0"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+        );
+
+        let result = Bootstrapper::parse_ban_threshold(&finder);
+
+        assert_eq!(result, 0)
+    }
+
+    #[test]
+    fn parse_ban_threshold_defaults_to_default_ban_threshold() {
+        let finder = ParameterFinder::new(vec![]);
+
+        let result = Bootstrapper::parse_ban_threshold(&finder);
+
+        assert_eq!(result, DEFAULT_BAN_THRESHOLD)
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid demerit count for --ban_threshold <demerits>: 'lots'")]
+    fn parse_complains_about_bad_ban_threshold() {
+        let finder = ParameterFinder::new(
+            vec!["--ban_threshold", "lots"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+        );
+
+        Bootstrapper::parse_ban_threshold(&finder);
+    }
+
+    #[test]
+    fn parse_max_report_delay_works() {
+        let finder = ParameterFinder::new(
+            vec!["--max_report_delay", "10"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+        );
+
+        let result = Bootstrapper::parse_max_report_delay(&finder);
+
+        assert_eq!(result, Duration::from_secs(10))
+    }
+
+    #[test]
+    fn parse_max_report_delay_defaults() {
+        let finder = ParameterFinder::new(vec![]);
+
+        let result = Bootstrapper::parse_max_report_delay(&finder);
+
+        assert_eq!(result, accountant::DEFAULT_MAX_REPORT_DELAY)
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid number of seconds for --max_report_delay <seconds>: '0'")]
+    fn parse_complains_about_zero_max_report_delay() {
+        let finder = ParameterFinder::new(
+            vec!["--max_report_delay", "0"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+        );
+
+        Bootstrapper::parse_max_report_delay(&finder);
+    }
+
+    #[test]
+    fn parse_exit_connect_timeouts_works() {
+        let finder = ParameterFinder::new(
+            vec!["--exit_connect_timeout", "5,993:30,995:20"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+        );
+
+        let result = Bootstrapper::parse_exit_connect_timeouts(&finder);
+
+        let mut by_port = HashMap::new();
+        by_port.insert(993, Duration::from_secs(30));
+        by_port.insert(995, Duration::from_secs(20));
+        assert_eq!(
+            result,
+            ExitConnectTimeouts {
+                default: Duration::from_secs(5),
+                by_port,
+            }
+        )
+    }
+
+    #[test]
+    fn parse_exit_connect_timeouts_defaults() {
+        let finder = ParameterFinder::new(vec![]);
+
+        let result = Bootstrapper::parse_exit_connect_timeouts(&finder);
+
+        assert_eq!(result, ExitConnectTimeouts::default())
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Invalid number of seconds for --exit_connect_timeout <seconds>[,<port>:<seconds>...]: '5,993:0'"
+    )]
+    fn parse_exit_connect_timeouts_complains_about_zero_seconds() {
+        let finder = ParameterFinder::new(
+            vec!["--exit_connect_timeout", "5,993:0"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+        );
+
+        Bootstrapper::parse_exit_connect_timeouts(&finder);
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Invalid port override for --exit_connect_timeout <seconds>[,<port>:<seconds>...]: '993'"
+    )]
+    fn parse_exit_connect_timeouts_complains_about_override_without_seconds() {
+        let finder = ParameterFinder::new(
+            vec!["--exit_connect_timeout", "5,993"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+        );
+
+        Bootstrapper::parse_exit_connect_timeouts(&finder);
+    }
+
+    #[test]
+    fn parse_rate_card_defaults() {
+        let finder = ParameterFinder::new(vec![]);
+
+        let result = Bootstrapper::parse_rate_card(&finder);
+
+        assert_eq!(result, RateCard::default())
+    }
+
+    #[test]
+    fn parse_rate_card_takes_routing_and_exit_rates_separately() {
+        let finder = ParameterFinder::new(
+            vec!["--routing_rate", "10,20", "--exit_rate", "30,40"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+        );
+
+        let result = Bootstrapper::parse_rate_card(&finder);
+
+        assert_eq!(
+            result,
+            RateCard {
+                routing_service_rate: 10,
+                routing_byte_rate: 20,
+                exit_service_rate: 30,
+                exit_byte_rate: 40,
+            }
+        )
+    }
+
+    #[test]
+    fn parse_rate_card_leaves_unspecified_rates_at_the_default() {
+        let finder = ParameterFinder::new(
+            vec!["--exit_rate", "0,0"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+        );
+
+        let result = Bootstrapper::parse_rate_card(&finder);
+
+        assert_eq!(
+            result,
+            RateCard {
+                exit_service_rate: 0,
+                exit_byte_rate: 0,
+                ..RateCard::default()
+            }
+        )
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid rates for --routing_rate <service rate>,<byte rate>: '10'")]
+    fn parse_rate_card_complains_about_a_missing_byte_rate() {
+        let finder = ParameterFinder::new(
+            vec!["--routing_rate", "10"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+        );
+
+        Bootstrapper::parse_rate_card(&finder);
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid rates for --exit_rate <service rate>,<byte rate>: '1,-2'")]
+    fn parse_rate_card_complains_about_negative_rates() {
+        let finder = ParameterFinder::new(
+            vec!["--exit_rate", "1,-2"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+        );
+
+        Bootstrapper::parse_rate_card(&finder);
+    }
+
+    #[test]
+    fn parse_exit_policy_blocks_nothing_by_default() {
+        let finder = ParameterFinder::new(vec![]);
+
+        let result = Bootstrapper::parse_exit_policy(&finder);
+
+        assert_eq!(result, ExitPolicy::default())
+    }
+
+    #[test]
+    fn parse_exit_policy_sorts_blocked_hosts_into_names_and_addresses() {
+        let finder = ParameterFinder::new(
+            vec![
+                "--exit_blocked_ports",
+                "25,465",
+                "--exit_blocked_hosts",
+                "Ads.com.,6.6.6.6,tracker.net,::1",
+            ]
+            .into_iter()
+            .map(String::from)
+            .collect(),
+        );
+
+        let result = Bootstrapper::parse_exit_policy(&finder);
+
+        assert_eq!(
+            result,
+            ExitPolicy {
+                blocked_ports: vec![25, 465],
+                blocked_hostnames: vec![String::from("ads.com"), String::from("tracker.net")],
+                blocked_ip_addrs: vec![
+                    IpAddr::from_str("6.6.6.6").unwrap(),
+                    IpAddr::from_str("::1").unwrap(),
+                ],
+                offered_dns_servers: vec![],
+            }
+        )
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid port for --exit_blocked_ports <port>[,<port>...]: '0'")]
+    fn parse_exit_policy_complains_about_port_zero() {
+        let finder = ParameterFinder::new(
+            vec!["--exit_blocked_ports", "25,0"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+        );
+
+        Bootstrapper::parse_exit_policy(&finder);
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid port for --exit_blocked_ports <port>[,<port>...]: 'smtp'")]
+    fn parse_exit_policy_complains_about_a_port_that_is_not_a_number() {
+        let finder = ParameterFinder::new(
+            vec!["--exit_blocked_ports", "smtp"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+        );
+
+        Bootstrapper::parse_exit_policy(&finder);
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Invalid host for --exit_blocked_hosts <hostname or IP address>[,<hostname or IP address>...]: 'ads.com,,tracker.net'"
+    )]
+    fn parse_exit_policy_complains_about_an_empty_host() {
+        let finder = ParameterFinder::new(
+            vec!["--exit_blocked_hosts", "ads.com,,tracker.net"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+        );
+
+        Bootstrapper::parse_exit_policy(&finder);
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Invalid IP address for --offer_exit_dns_servers <IP address>[,<IP address>...]: 'dns.quad9.net'"
+    )]
+    fn parse_exit_policy_complains_about_an_offered_dns_server_that_is_not_an_ip_address() {
+        let finder = ParameterFinder::new(
+            vec!["--offer_exit_dns_servers", "1.1.1.1,dns.quad9.net"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+        );
+
+        Bootstrapper::parse_exit_policy(&finder);
+    }
+
+    #[test]
+    fn parse_exit_sla_works() {
+        let finder = ParameterFinder::new(
+            vec!["--exit_sla", "50,1000000"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+        );
+        let exit_policy = ExitPolicy {
+            blocked_ports: vec![25],
+            ..ExitPolicy::default()
+        };
+
+        let result = Bootstrapper::parse_exit_sla(&finder, &exit_policy);
+
+        assert_eq!(
+            result,
+            Some(ExitSla {
+                max_streams: 50,
+                max_stream_bytes_per_second: 1000000,
+                content_policy_hash: exit_policy.summary_hash(),
+            })
+        );
+        assert_eq!(
+            Bootstrapper::parse_exit_sla(&ParameterFinder::new(vec![]), &exit_policy),
+            None
+        );
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Invalid SLA for --exit_sla <max streams>,<max bytes per second per stream>: '50,0'"
+    )]
+    fn parse_exit_sla_complains_about_a_rate_of_zero() {
+        let finder = ParameterFinder::new(
+            vec!["--exit_sla", "50,0"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+        );
+
+        Bootstrapper::parse_exit_sla(&finder, &ExitPolicy::default());
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Invalid SLA for --exit_sla <max streams>,<max bytes per second per stream>: '50'"
+    )]
+    fn parse_exit_sla_complains_about_a_missing_rate() {
+        let finder = ParameterFinder::new(
+            vec!["--exit_sla", "50"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+        );
+
+        Bootstrapper::parse_exit_sla(&finder, &ExitPolicy::default());
+    }
+
+    #[test]
+    fn parse_adaptive_hops_works() {
+        let finder = ParameterFinder::new(
+            vec!["--adaptive_hops", "2,5,1500"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+        );
+
+        assert_eq!(
+            Bootstrapper::parse_adaptive_hops(&finder),
+            Some(AdaptiveHopsConfig {
+                min_hops: 2,
+                max_hops: 5,
+                latency_threshold: Duration::from_millis(1500),
+            })
+        );
+        assert_eq!(
+            Bootstrapper::parse_adaptive_hops(&ParameterFinder::new(vec![])),
+            None
+        );
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Invalid hop count bounds for --adaptive_hops <min hops>,<max hops>,<round trip threshold in milliseconds>: '4,2,1500'"
+    )]
+    fn parse_adaptive_hops_complains_about_a_maximum_below_the_minimum() {
+        let finder = ParameterFinder::new(
+            vec!["--adaptive_hops", "4,2,1500"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+        );
+
+        Bootstrapper::parse_adaptive_hops(&finder);
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Invalid hop count bounds for --adaptive_hops <min hops>,<max hops>,<round trip threshold in milliseconds>: '0,2,1500'"
+    )]
+    fn parse_adaptive_hops_complains_about_a_minimum_of_zero() {
+        let finder = ParameterFinder::new(
+            vec!["--adaptive_hops", "0,2,1500"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+        );
+
+        Bootstrapper::parse_adaptive_hops(&finder);
+    }
+
+    #[test]
+    fn parse_fragment_mtu_works() {
+        let finder = ParameterFinder::new(
+            vec!["--fragment_mtu", "1200"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+        );
+
+        assert_eq!(Bootstrapper::parse_fragment_mtu(&finder), Some(1200));
+        assert_eq!(
+            Bootstrapper::parse_fragment_mtu(&ParameterFinder::new(vec![])),
+            None
+        );
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Invalid size for --fragment_mtu <bytes>: '511' (must be at least 512)"
+    )]
+    fn parse_fragment_mtu_complains_about_a_tiny_mtu() {
+        let finder = ParameterFinder::new(
+            vec!["--fragment_mtu", "511"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+        );
+
+        Bootstrapper::parse_fragment_mtu(&finder);
+    }
+
+    #[test]
+    fn parse_cover_traffic_works() {
+        let parse = |rate: &str| {
+            Bootstrapper::parse_cover_traffic(&ParameterFinder::new(
+                vec!["--cover_traffic", rate]
+                    .into_iter()
+                    .map(String::from)
+                    .collect(),
+            ))
+        };
+
+        assert_eq!(parse("6"), Some(6));
+        assert_eq!(parse("0"), None);
+        assert_eq!(
+            Bootstrapper::parse_cover_traffic(&ParameterFinder::new(vec![])),
+            None
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid rate for --cover_traffic <packages per minute>: 'lots'")]
+    fn parse_cover_traffic_complains_about_a_bad_rate() {
+        let finder = ParameterFinder::new(
+            vec!["--cover_traffic", "lots"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+        );
+
+        Bootstrapper::parse_cover_traffic(&finder);
+    }
+
+    #[test]
+    fn parse_relay_fairness_config_works() {
+        let parse = |args: Vec<&str>| {
+            Bootstrapper::parse_relay_fairness_config(&ParameterFinder::new(
+                args.into_iter().map(String::from).collect(),
+            ))
+        };
+
+        assert_eq!(
+            parse(vec!["--relay_limit", "500000"]),
+            Some(RelayFairnessConfig {
+                bytes_per_second: 500000,
+                weighted_by_standing: false,
+            })
+        );
+        assert_eq!(
+            parse(vec![
+                "--relay_limit",
+                "500000",
+                "--relay_weights_by_standing",
+                "on"
+            ]),
+            Some(RelayFairnessConfig {
+                bytes_per_second: 500000,
+                weighted_by_standing: true,
+            })
+        );
+        assert_eq!(parse(vec!["--relay_weights_by_standing", "on"]), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid limit for --relay_limit <bytes per second>: '0'")]
+    fn parse_relay_fairness_config_complains_about_a_limit_of_zero() {
+        let finder = ParameterFinder::new(
+            vec!["--relay_limit", "0"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+        );
+
+        Bootstrapper::parse_relay_fairness_config(&finder);
+    }
+
+    #[test]
+    fn parse_metrics_port_works() {
+        let parse = |args: Vec<&str>| {
+            Bootstrapper::parse_metrics_port(&ParameterFinder::new(
+                args.into_iter().map(String::from).collect(),
+            ))
+        };
+
+        assert_eq!(parse(vec!["--metrics_port", "9100"]), Some(9100));
+        assert_eq!(parse(vec![]), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid port for --metrics_port <port number>: '80'")]
+    fn parse_metrics_port_complains_about_a_privileged_port() {
+        let finder = ParameterFinder::new(
+            vec!["--metrics_port", "80"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+        );
+
+        Bootstrapper::parse_metrics_port(&finder);
+    }
+
+    #[test]
+    fn parse_request_exit_dns_server_works() {
+        let requested = ParameterFinder::new(
+            vec!["--request_exit_dns_server", "9.9.9.9"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+        );
+        let unrequested = ParameterFinder::new(vec![]);
+
+        assert_eq!(
+            Bootstrapper::parse_request_exit_dns_server(&requested),
+            Some(IpAddr::from_str("9.9.9.9").unwrap())
+        );
+        assert_eq!(
+            Bootstrapper::parse_request_exit_dns_server(&unrequested),
+            None
+        );
+    }
+
+    #[test]
+    fn parse_country_code_works() {
+        let finder = ParameterFinder::new(
+            vec!["--country", "de", "--exit_location", "FR"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+        );
+
+        assert_eq!(
+            Bootstrapper::parse_country_code(&finder, "--country"),
+            Some(String::from("DE"))
+        );
+        assert_eq!(
+            Bootstrapper::parse_country_code(&finder, "--exit_location"),
+            Some(String::from("FR"))
+        );
+        assert_eq!(
+            Bootstrapper::parse_country_code(&ParameterFinder::new(vec![]), "--country"),
+            None
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid country code for --exit_location <ISO country code>: 'DEU'")]
+    fn parse_country_code_complains_about_anything_but_two_letters() {
+        let finder = ParameterFinder::new(
+            vec!["--exit_location", "DEU"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+        );
+
+        Bootstrapper::parse_country_code(&finder, "--exit_location");
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Invalid IP address for --request_exit_dns_server <IP address>: 'dns.quad9.net'"
+    )]
+    fn parse_request_exit_dns_server_complains_about_a_hostname() {
+        let finder = ParameterFinder::new(
+            vec!["--request_exit_dns_server", "dns.quad9.net"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+        );
+
+        Bootstrapper::parse_request_exit_dns_server(&finder);
+    }
+
+    #[test]
+    fn parse_upload_window_works() {
+        let finder = ParameterFinder::new(
+            vec!["--upload_window", "16"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+        );
+
+        let result = Bootstrapper::parse_upload_window(&finder);
+
+        assert_eq!(result, Some(16))
+    }
+
+    #[test]
+    fn parse_upload_window_defaults() {
+        let finder = ParameterFinder::new(vec![]);
+
+        let result = Bootstrapper::parse_upload_window(&finder);
+
+        assert_eq!(result, Some(DEFAULT_UPLOAD_WINDOW))
+    }
+
+    #[test]
+    fn parse_upload_window_turns_pacing_off_with_zero() {
+        let finder = ParameterFinder::new(
+            vec!["--upload_window", "0"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+        );
+
+        let result = Bootstrapper::parse_upload_window(&finder);
+
+        assert_eq!(result, None)
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Invalid window for --upload_window <packets, or 0 for no limit>: 'lots'"
+    )]
+    fn parse_complains_about_bad_upload_window() {
+        let finder = ParameterFinder::new(
+            vec!["--upload_window", "lots"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+        );
 
-        fn bind_port_result(mut self, result: io::Result<()>) -> ListenerHandlerNull {
-            self.bind_port_and_discriminator_factories_result = Some(result);
-            self
-        }
+        Bootstrapper::parse_upload_window(&finder);
     }
 
-    fn make_default_cli_params() -> Vec<String> {
-        vec![
-            String::from("--dns_servers"),
-            String::from("222.222.222.222"),
-            String::from("--port_count"),
-            String::from("0"),
-        ]
+    #[test]
+    fn parse_retransmission_memory_works() {
+        let finder = ParameterFinder::new(
+            vec!["--retransmission_memory", "64"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+        );
+
+        let result = Bootstrapper::parse_retransmission_memory(&finder);
+
+        assert_eq!(result, 65536)
     }
 
     #[test]
-    fn knows_its_name() {
-        let subject = BootstrapperBuilder::new().build();
+    fn parse_retransmission_memory_defaults() {
+        let finder = ParameterFinder::new(vec![]);
 
-        let result = subject.name();
+        let result = Bootstrapper::parse_retransmission_memory(&finder);
 
-        assert_eq!(result, String::from("Dispatcher"));
+        assert_eq!(result, DEFAULT_RETRANSMISSION_MEMORY)
     }
 
     #[test]
     #[should_panic(
-        expected = "Missing value for --wallet_address: --wallet_address <address> where 'address' is an Ethereum wallet address"
+        expected = "Invalid size for --retransmission_memory <kilobytes per stream>: '0'"
     )]
-    fn parse_wallet_address_requires_an_address() {
-        let finder = ParameterFinder::new(vec![String::from("--wallet_address")]);
+    fn parse_complains_about_zero_retransmission_memory() {
+        let finder = ParameterFinder::new(
+            vec!["--retransmission_memory", "0"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+        );
 
-        Bootstrapper::parse_wallet_address(&finder);
+        Bootstrapper::parse_retransmission_memory(&finder);
     }
 
     #[test]
-    #[should_panic(expected = "--wallet_address requires a valid Ethereum wallet address")]
-    fn parse_wallet_address_requires_an_address_that_is_42_characters_long() {
-        let finder = ParameterFinder::new(vec![
-            String::from("--wallet_address"),
-            String::from("my-favorite-wallet.com"),
-        ]);
+    fn parse_throughput_limit_defaults_to_none() {
+        let finder = ParameterFinder::new(vec![]);
 
-        Bootstrapper::parse_wallet_address(&finder);
+        let result = Bootstrapper::parse_throughput_limit(&finder, "--exit_upstream_limit");
+
+        assert_eq!(result, None)
     }
 
     #[test]
-    #[should_panic(expected = "--wallet_address requires a valid Ethereum wallet address")]
-    fn parse_wallet_address_must_start_with_0x() {
-        let finder = ParameterFinder::new(vec![
-            String::from("--wallet_address"),
-            String::from("x0my-favorite-wallet.com222222222222222222"),
-        ]);
+    #[should_panic(
+        expected = "Invalid limit for --exit_downstream_limit <bytes per second>: 'booga'"
+    )]
+    fn parse_complains_about_non_numeric_throughput_limit() {
+        let finder = ParameterFinder::new(
+            vec!["--exit_downstream_limit", "booga"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+        );
 
-        Bootstrapper::parse_wallet_address(&finder);
+        Bootstrapper::parse_throughput_limit(&finder, "--exit_downstream_limit");
     }
 
     #[test]
-    #[should_panic(expected = "--wallet_address requires a valid Ethereum wallet address")]
-    fn parse_wallet_address_must_contain_only_hex_characters() {
-        let finder = ParameterFinder::new(vec![
-            String::from("--wallet_address"),
-            String::from("0x9707f21F95B9839A54605100Ca69dCc2e7eaA26q"),
-        ]);
+    #[should_panic(expected = "Invalid limit for --exit_upstream_limit <bytes per second>: '0'")]
+    fn parse_complains_about_zero_throughput_limit() {
+        let finder = ParameterFinder::new(
+            vec!["--exit_upstream_limit", "0"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+        );
 
-        Bootstrapper::parse_wallet_address(&finder);
+        Bootstrapper::parse_throughput_limit(&finder, "--exit_upstream_limit");
     }
 
     #[test]
-    fn parse_wallet_address_returns_none_if_no_address_supplied() {
+    fn parse_capacity_class_defaults_to_medium() {
         let finder = ParameterFinder::new(vec![]);
 
-        assert_eq!(Bootstrapper::parse_wallet_address(&finder), None);
+        let result = Bootstrapper::parse_capacity_class(&finder);
+
+        assert_eq!(result, CapacityClass::Medium)
     }
 
     #[test]
-    fn parse_wallet_address_handles_happy_path() {
-        let finder = ParameterFinder::new(vec![
-            String::from("--wallet_address"),
-            String::from("0xbDfeFf9A1f4A1bdF483d680046344316019C58CF"),
-        ]);
-
-        assert_eq!(
-            Bootstrapper::parse_wallet_address(&finder),
-            Some(Wallet::new("0xbDfeFf9A1f4A1bdF483d680046344316019C58CF"))
+    #[should_panic(expected = "--capacity_class must be low, medium, or high, not booga")]
+    fn parse_capacity_class_complains_about_bad_capacity_class() {
+        let finder = ParameterFinder::new(
+            vec!["--capacity_class", "booga"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
         );
+
+        Bootstrapper::parse_capacity_class(&finder);
     }
 
     #[test]
-    #[should_panic(
-        expected = "--dns_servers <servers> where 'servers' is a comma-separated list of IP addresses"
-    )]
-    fn parse_dns_servers_requires_dns_servers() {
-        let finder = ParameterFinder::new(vec![
-            String::from("--not_dns_servers"),
-            String::from("1.2.3.4"),
-        ]);
+    fn parse_arbiter_layout_defaults_to_what_suits_this_machine() {
+        let finder = ParameterFinder::new(vec![]);
 
-        Bootstrapper::parse_dns_servers(&finder);
+        let result = Bootstrapper::parse_arbiter_layout(&finder, false);
+
+        assert_eq!(result, ArbiterLayout::for_this_machine())
     }
 
     #[test]
-    #[should_panic(expected = "Invalid IP address for --dns_servers <servers>: '1.2.3.256'")]
-    fn parse_dns_servers_catches_invalid_ip_addresses() {
-        let finder = ParameterFinder::new(vec![
-            String::from("--dns_servers"),
-            String::from("1.2.3.256"),
-        ]);
+    fn parse_arbiter_layout_keeps_a_low_power_node_on_one_arbiter() {
+        let finder = ParameterFinder::new(vec![]);
 
-        Bootstrapper::parse_dns_servers(&finder);
+        let result = Bootstrapper::parse_arbiter_layout(&finder, true);
+
+        assert_eq!(result, ArbiterLayout::shared())
     }
 
     #[test]
-    fn parse_dns_servers_ignores_second_server_list() {
+    fn parse_arbiter_layout_lets_a_low_power_node_ask_for_dedicated_arbiters() {
         let finder = ParameterFinder::new(
-            vec![
-                "--dns_servers",
-                "1.2.3.4,2.3.4.5",
-                "--dns_servers",
-                "3.4.5.6",
-            ]
-            .into_iter()
-            .map(String::from)
-            .collect(),
+            vec!["--dedicated_arbiters", "hopper"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
         );
 
-        let socket_addrs = Bootstrapper::parse_dns_servers(&finder);
+        let result = Bootstrapper::parse_arbiter_layout(&finder, true);
 
-        assert_eq!(
-            socket_addrs,
-            vec!(
-                SocketAddr::from_str("1.2.3.4:53").unwrap(),
-                SocketAddr::from_str("2.3.4.5:53").unwrap()
-            )
-        )
+        assert_eq!(result, ArbiterLayout::new(vec![DedicatedActor::Hopper]))
     }
 
     #[test]
-    #[should_panic(expected = "--neighbor <public key>:<IP address>:<port>,<port>,...")]
-    fn parse_neighbor_configs_requires_two_pieces_to_a_configuration() {
+    fn parse_arbiter_layout_works() {
         let finder = ParameterFinder::new(
-            vec!["--neighbor", "only_one_piece"]
+            vec!["--dedicated_arbiters", "hopper,accountant"]
                 .into_iter()
                 .map(String::from)
                 .collect(),
         );
 
-        Bootstrapper::parse_neighbor_configs(&finder, "--neighbor");
+        let result = Bootstrapper::parse_arbiter_layout(&finder, false);
+
+        assert_eq!(
+            result,
+            ArbiterLayout::new(vec![DedicatedActor::Hopper, DedicatedActor::Accountant])
+        )
     }
 
     #[test]
-    #[should_panic(expected = "Invalid Base64 for --neighbor <public key>: 'bad_key'")]
-    fn parse_neighbor_configs_complains_about_bad_base_64() {
+    fn parse_arbiter_layout_can_share_one_arbiter_among_everything() {
         let finder = ParameterFinder::new(
-            vec!["--neighbor", "bad_key:1.2.3.4:1234,2345"]
+            vec!["--dedicated_arbiters", "none"]
                 .into_iter()
                 .map(String::from)
                 .collect(),
         );
 
-        Bootstrapper::parse_neighbor_configs(&finder, "--neighbor");
+        let result = Bootstrapper::parse_arbiter_layout(&finder, false);
+
+        assert_eq!(result, ArbiterLayout::shared())
     }
 
     #[test]
-    #[should_panic(expected = "Blank public key for --neighbor :1.2.3.4:1234,2345")]
-    fn parse_neighbor_configs_complains_about_blank_public_key() {
+    #[should_panic(
+        expected = "--dedicated_arbiters must name hopper, accountant, or neighborhood, not dispatcher"
+    )]
+    fn parse_arbiter_layout_complains_about_actors_that_cant_have_an_arbiter() {
         let finder = ParameterFinder::new(
-            vec!["--neighbor", ":1.2.3.4:1234,2345"]
+            vec!["--dedicated_arbiters", "hopper,dispatcher"]
                 .into_iter()
                 .map(String::from)
                 .collect(),
         );
 
-        Bootstrapper::parse_neighbor_configs(&finder, "--neighbor");
+        Bootstrapper::parse_arbiter_layout(&finder, false);
     }
 
     #[test]
-    #[should_panic(expected = "Invalid NodeAddr for --neighbor <NodeAddr>: 'BadNodeAddr'")]
-    fn parse_neighbor_configs_complains_about_bad_node_addr() {
+    fn parse_exit_log_level_works() {
         let finder = ParameterFinder::new(
-            vec!["--neighbor", "R29vZEtleQ==:BadNodeAddr"]
+            vec!["--exit_log", "aggregate"]
                 .into_iter()
                 .map(String::from)
                 .collect(),
         );
 
-        Bootstrapper::parse_neighbor_configs(&finder, "--neighbor");
+        let result = Bootstrapper::parse_exit_log_level(&finder);
+
+        assert_eq!(result, ExitLogLevel::Aggregate)
     }
 
     #[test]
-    fn parse_neighbor_configs_handles_the_happy_path() {
-        let finder = ParameterFinder::new(
-            vec![
-                "--booga",
-                "R29vZEtleQ:1.2.3.4:1234,2345,3456",
-                "--irrelevant",
-                "parameter",
-                "--booga",
-                "QW5vdGhlckdvb2RLZXk:2.3.4.5:4567,5678,6789",
-            ]
-            .into_iter()
-            .map(String::from)
-            .collect(),
-        );
+    fn parse_exit_log_level_defaults_to_off() {
+        let finder = ParameterFinder::new(vec![]);
 
-        let result = Bootstrapper::parse_neighbor_configs(&finder, "--booga");
+        let result = Bootstrapper::parse_exit_log_level(&finder);
 
-        assert_eq!(
-            result,
-            vec!(
-                (
-                    PublicKey::new(b"GoodKey"),
-                    NodeAddr::new(
-                        &IpAddr::from_str("1.2.3.4").unwrap(),
-                        &vec!(1234, 2345, 3456),
-                    )
-                ),
-                (
-                    PublicKey::new(b"AnotherGoodKey"),
-                    NodeAddr::new(
-                        &IpAddr::from_str("2.3.4.5").unwrap(),
-                        &vec!(4567, 5678, 6789),
-                    )
-                )
-            )
-        )
+        assert_eq!(result, ExitLogLevel::Off)
     }
 
     #[test]
-    fn parse_node_type_handles_standard() {
+    #[should_panic(expected = "--exit_log must be off, aggregate, or full, not booga")]
+    fn parse_exit_log_level_complains_about_bad_level() {
         let finder = ParameterFinder::new(
-            vec!["--node_type", "standard"]
+            vec!["--exit_log", "booga"]
                 .into_iter()
                 .map(String::from)
                 .collect(),
         );
 
-        let result = Bootstrapper::parse_node_type(&finder);
-
-        assert_eq!(result, false);
+        Bootstrapper::parse_exit_log_level(&finder);
     }
 
     #[test]
-    fn parse_node_type_handles_bootstrap() {
+    fn parse_probe_resistance_works() {
         let finder = ParameterFinder::new(
-            vec!["--node_type", "bootstrap"]
+            vec!["--probe_resistance", "http"]
                 .into_iter()
                 .map(String::from)
                 .collect(),
         );
 
-        let result = Bootstrapper::parse_node_type(&finder);
+        let result = Bootstrapper::parse_probe_resistance(&finder);
 
-        assert_eq!(result, true);
+        assert_eq!(result, ProbeResistance::Http)
     }
 
     #[test]
-    fn parse_node_type_defaults_to_standard() {
+    fn parse_probe_resistance_defaults_to_off() {
+        let finder = ParameterFinder::new(vec![]);
+
+        let result = Bootstrapper::parse_probe_resistance(&finder);
+
+        assert_eq!(result, ProbeResistance::Off)
+    }
+
+    #[test]
+    #[should_panic(expected = "--probe_resistance must be off, silent, or http, not booga")]
+    fn parse_probe_resistance_complains_about_bad_value() {
         let finder = ParameterFinder::new(
-            vec!["--irrelevant", "parameter"]
+            vec!["--probe_resistance", "booga"]
                 .into_iter()
                 .map(String::from)
                 .collect(),
         );
 
-        let result = Bootstrapper::parse_node_type(&finder);
+        Bootstrapper::parse_probe_resistance(&finder);
+    }
 
-        assert_eq!(result, false);
+    #[test]
+    fn parse_privacy_policy_defaults_to_none() {
+        let finder = ParameterFinder::new(vec![]);
+
+        let result = Bootstrapper::parse_privacy_policy(&finder);
+
+        assert_eq!(result, None)
     }
 
     #[test]
-    #[should_panic(expected = "--node_type must be either standard or bootstrap, not booga")]
-    fn parse_node_type_complains_about_bad_node_type() {
+    #[should_panic(
+        expected = "Couldn't read privacy policy \"generated/test/bootstrapper/nonexistent.json\""
+    )]
+    fn parse_privacy_policy_complains_about_missing_file() {
         let finder = ParameterFinder::new(
-            vec!["--node_type", "booga"]
+            vec![
+                "--privacy_policy",
+                "generated/test/bootstrapper/nonexistent.json",
+            ]
+            .into_iter()
+            .map(String::from)
+            .collect(),
+        );
+
+        Bootstrapper::parse_privacy_policy(&finder);
+    }
+
+    #[test]
+    fn parse_file_path_defaults_to_none() {
+        let finder = ParameterFinder::new(vec![]);
+
+        let result = Bootstrapper::parse_file_path(&finder, "--privacy_policy");
+
+        assert_eq!(result, None)
+    }
+
+    #[test]
+    fn parse_file_path_remembers_the_path_it_was_given() {
+        let finder = ParameterFinder::new(
+            vec!["--geoip_database", "some/where/ip2asn.tsv"]
                 .into_iter()
                 .map(String::from)
                 .collect(),
         );
 
-        Bootstrapper::parse_node_type(&finder);
+        let result = Bootstrapper::parse_file_path(&finder, "--geoip_database");
+
+        assert_eq!(result, Some(PathBuf::from("some/where/ip2asn.tsv")))
+    }
+
+    #[test]
+    fn parse_route_exclusion_config_defaults_to_none() {
+        let finder = ParameterFinder::new(vec![]);
+
+        let result = Bootstrapper::parse_route_exclusion_config(&finder);
+
+        assert_eq!(result, None)
+    }
+
+    #[test]
+    fn parse_route_exclusion_config_loads_the_database_and_reads_the_exclusions() {
+        let directory = PathBuf::from(
+            "generated/test/bootstrapper/parse_route_exclusion_config_loads_the_database_and_reads_the_exclusions",
+        );
+        fs::remove_dir_all(&directory).is_ok();
+        fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("ip2asn.tsv");
+        let tsv = "1.2.0.0\t1.2.255.255\t7922\tUS\tCOMCAST\n";
+        fs::write(&path, tsv).unwrap();
+        let finder = ParameterFinder::new(
+            vec![
+                "--geoip_database",
+                path.to_str().unwrap(),
+                "--route_exclusions",
+                "own_asn,exit_country:cn",
+            ]
+            .into_iter()
+            .map(String::from)
+            .collect(),
+        );
+
+        let result = Bootstrapper::parse_route_exclusion_config(&finder);
+
+        assert_eq!(
+            result,
+            Some(RouteExclusionConfig {
+                database: AsnDatabase::from_tsv(tsv).unwrap(),
+                exclusions: vec![
+                    RouteExclusion::OwnAsn,
+                    RouteExclusion::ExitCountry(String::from("CN"))
+                ],
+            })
+        )
     }
 
     #[test]
-    fn parse_ip_defaults() {
+    #[should_panic(expected = "Invalid route exclusion: 'booga'")]
+    fn parse_route_exclusion_config_complains_about_bad_exclusion() {
         let finder = ParameterFinder::new(
-            vec!["--irrelevant", "parameter"]
+            vec!["--route_exclusions", "own_asn,booga"]
                 .into_iter()
                 .map(String::from)
                 .collect(),
         );
 
-        let result = Bootstrapper::parse_ip(&finder);
-
-        assert_eq!(result, sentinel_ip_addr())
+        Bootstrapper::parse_route_exclusion_config(&finder);
     }
 
     #[test]
-    #[should_panic(expected = "Invalid IP address for --ip <public IP address>: 'booga'")]
-    fn parse_complains_about_bad_ip_address() {
+    #[should_panic(
+        expected = "--route_exclusions needs a --geoip_database to look addresses up in"
+    )]
+    fn parse_route_exclusion_config_complains_about_missing_database() {
         let finder = ParameterFinder::new(
-            vec!["--ip", "booga"]
+            vec!["--route_exclusions", "own_asn"]
                 .into_iter()
                 .map(String::from)
                 .collect(),
         );
 
-        Bootstrapper::parse_ip(&finder);
+        Bootstrapper::parse_route_exclusion_config(&finder);
     }
 
     #[test]
-    #[should_panic(expected = "Invalid port for --ui_port <port number>: 'booga'")]
-    fn parse_complains_about_non_numeric_ui_port() {
+    #[should_panic(
+        expected = "Couldn't read GeoIP database \"generated/test/bootstrapper/nonexistent.tsv\""
+    )]
+    fn parse_route_exclusion_config_complains_about_unreadable_database() {
         let finder = ParameterFinder::new(
-            vec!["--ui_port", "booga"]
-                .into_iter()
-                .map(String::from)
-                .collect(),
+            vec![
+                "--geoip_database",
+                "generated/test/bootstrapper/nonexistent.tsv",
+                "--route_exclusions",
+                "own_asn",
+            ]
+            .into_iter()
+            .map(String::from)
+            .collect(),
         );
 
-        Bootstrapper::parse_ui_port(&finder);
+        Bootstrapper::parse_route_exclusion_config(&finder);
     }
 
     #[test]
-    #[should_panic(expected = "Invalid port for --ui_port <port number>: '1023'")]
-    fn parse_complains_about_ui_port_too_low() {
+    fn parse_on_off_works() {
         let finder = ParameterFinder::new(
-            vec!["--ui_port", "1023"]
+            vec!["--request_exit_tls", "on", "--offer_exit_tls", "off"]
                 .into_iter()
                 .map(String::from)
                 .collect(),
         );
 
-        Bootstrapper::parse_ui_port(&finder);
+        assert_eq!(
+            Bootstrapper::parse_on_off(&finder, "--request_exit_tls"),
+            true
+        );
+        assert_eq!(
+            Bootstrapper::parse_on_off(&finder, "--offer_exit_tls"),
+            false
+        );
     }
 
     #[test]
-    #[should_panic(expected = "Invalid port for --ui_port <port number>: '65536'")]
-    fn parse_complains_about_ui_port_too_high() {
-        let finder = ParameterFinder::new(
-            vec!["--ui_port", "65536"]
-                .into_iter()
-                .map(String::from)
-                .collect(),
-        );
+    fn parse_on_off_defaults_to_off() {
+        let finder = ParameterFinder::new(vec![]);
 
-        Bootstrapper::parse_ui_port(&finder);
+        let result = Bootstrapper::parse_on_off(&finder, "--offer_exit_tls");
+
+        assert_eq!(result, false)
     }
 
     #[test]
-    fn parse_ui_port_works() {
+    #[should_panic(expected = "--request_exit_tls must be on or off, not booga")]
+    fn parse_on_off_complains_about_bad_value() {
         let finder = ParameterFinder::new(
-            vec!["--ui_port", "5335"]
+            vec!["--request_exit_tls", "booga"]
                 .into_iter()
                 .map(String::from)
                 .collect(),
         );
 
-        let result = Bootstrapper::parse_ui_port(&finder);
+        Bootstrapper::parse_on_off(&finder, "--request_exit_tls");
+    }
 
-        assert_eq!(result, 5335)
+    #[test]
+    fn initialize_as_privileged_warns_loudly_about_exit_tls() {
+        init_test_logging();
+        let mut subject = BootstrapperBuilder::new()
+            .add_listener_handler(Box::new(
+                ListenerHandlerNull::new(vec![]).bind_port_result(Ok(())),
+            ))
+            .add_listener_handler(Box::new(
+                ListenerHandlerNull::new(vec![]).bind_port_result(Ok(())),
+            ))
+            .build();
+        let mut holder = FakeStreamHolder::new();
+
+        subject.initialize_as_privileged(
+            &vec![
+                String::from("--dns_servers"),
+                String::from("1.1.1.1"),
+                String::from("--request_exit_tls"),
+                String::from("on"),
+                String::from("--offer_exit_tls"),
+                String::from("on"),
+            ],
+            &mut holder.streams(),
+        );
+
+        let stderr = holder.stderr.get_string();
+        assert_eq!(
+            stderr.contains("*** WARNING *** --request_exit_tls is on: "),
+            true,
+            "{}",
+            stderr
+        );
+        assert_eq!(
+            stderr.contains("*** WARNING *** --offer_exit_tls is on: "),
+            true,
+            "{}",
+            stderr
+        );
+        TestLogHandler::new().exists_log_containing(
+            "WARN: Bootstrapper: *** WARNING *** --request_exit_tls is on: ",
+        );
+        let config = subject.config.unwrap();
+        assert_eq!(config.request_exit_tls, true);
+        assert_eq!(config.offer_exit_tls, true);
     }
 
     #[test]
-    fn parse_ui_port_defaults() {
-        let finder = ParameterFinder::new(vec![]);
+    fn probe_config_takes_what_it_needs_from_the_node_command_line() {
+        let args: Vec<String> = vec![
+            "--ip",
+            "1.2.3.4",
+            "--dns_servers",
+            "8.8.8.8,1.1.1.1#cloudflare-dns.com",
+            "--dns_protocol",
+            "tls",
+            "--neighbor",
+            "R29vZEtleQ==:2.3.4.5:1234,2345",
+        ]
+        .into_iter()
+        .map(String::from)
+        .collect();
 
-        let result = Bootstrapper::parse_ui_port(&finder);
+        let result = Bootstrapper::probe_config(&args);
 
-        assert_eq!(result, DEFAULT_UI_PORT)
+        assert_eq!(
+            result,
+            ProbeConfig {
+                ip_addr_opt: Some(IpAddr::from_str("1.2.3.4").unwrap()),
+                neighbor_addrs: vec![NodeAddr::new(
+                    &IpAddr::from_str("2.3.4.5").unwrap(),
+                    &vec![1234, 2345]
+                )],
+                dns_servers: vec![
+                    SocketAddr::from_str("8.8.8.8:53").unwrap(),
+                    SocketAddr::from_str("1.1.1.1:53").unwrap(),
+                ],
+            }
+        );
+    }
+
+    #[test]
+    fn probe_config_does_without_what_isnt_there() {
+        let result = Bootstrapper::probe_config(&vec![]);
+
+        assert_eq!(
+            result,
+            ProbeConfig {
+                ip_addr_opt: None,
+                neighbor_addrs: vec![],
+                dns_servers: vec![],
+            }
+        );
     }
 
     #[test]
@@ -988,6 +4016,62 @@ mod tests {
             "0xbDfeFf9A1f4A1bdF483d680046344316019C58CF",
             "--data_directory",
             "~/.booga",
+            "--exit_upstream_limit",
+            "100000",
+            "--exit_downstream_limit",
+            "1000000",
+            "--capacity_class",
+            "high",
+            "--wallet_rotation",
+            "0x9707f21F95B9839A54605100Ca69dCc2e7eaA26B",
+            "--wallet_rotation_period",
+            "3600",
+            "--consuming_wallet",
+            "0x2345678901234567890123456789012345678901",
+            "--standby_consuming_wallet",
+            "0x3456789012345678901234567890123456789012",
+            "--consuming_wallet_threshold",
+            "1000000000000000000",
+            "--blockchain_service_url",
+            "http://127.0.0.1:8545",
+            "--routing_rate",
+            "5,6",
+            "--exit_rate",
+            "7,8",
+            "--exit_blocked_ports",
+            "25",
+            "--exit_blocked_hosts",
+            "ads.com,6.6.6.6",
+            "--offer_exit_dns_servers",
+            "9.9.9.9,1.1.1.1",
+            "--request_exit_dns_server",
+            "9.9.9.9",
+            "--exit_location",
+            "de",
+            "--country",
+            "FR",
+            "--exit_sla",
+            "50,1000000",
+            "--adaptive_hops",
+            "1,4,900",
+            "--fragment_mtu",
+            "1200",
+            "--pad_packets",
+            "on",
+            "--cover_traffic",
+            "6",
+            "--relay_limit",
+            "500000",
+            "--relay_weights_by_standing",
+            "on",
+            "--metrics_port",
+            "9100",
+            "--regenerate_ports",
+            "on",
+            "--retransmission_memory",
+            "512",
+            "--retransmission_spill",
+            "on",
         ]
         .into_iter()
         .map(String::from)
@@ -1032,6 +4116,98 @@ mod tests {
             config.accountant_config.data_directory,
             PathBuf::from("~/.booga")
         );
+        assert_eq!(
+            config.ui_gateway_config.alias_directory,
+            Some(PathBuf::from("~/.booga"))
+        );
+        assert_eq!(
+            config.ui_gateway_config.token_directory,
+            Some(PathBuf::from("~/.booga"))
+        );
+        assert_eq!(
+            config.exit_throughput_config,
+            ExitThroughputConfig {
+                upstream_bytes_per_second: Some(100000),
+                downstream_bytes_per_second: Some(1000000),
+            }
+        );
+        assert_eq!(config.capacity_class, CapacityClass::High);
+        assert_eq!(
+            config.earning_wallet_rotation,
+            Some(WalletRotation::new(
+                vec![Wallet::new("0x9707f21F95B9839A54605100Ca69dCc2e7eaA26B")],
+                Duration::from_secs(3600),
+            ))
+        );
+        assert_eq!(
+            config.neighborhood_config.consuming_wallet,
+            Some(Wallet::new("0x2345678901234567890123456789012345678901"))
+        );
+        assert_eq!(
+            config.standby_consuming_wallet,
+            Some(StandbyConsumingWalletConfig {
+                wallet: Wallet::new("0x3456789012345678901234567890123456789012"),
+                threshold: 1_000_000_000_000_000_000,
+                blockchain_service_url: ServiceUrl::parse("http://127.0.0.1:8545").unwrap(),
+            })
+        );
+        assert_eq!(
+            config.rate_card,
+            RateCard {
+                routing_service_rate: 5,
+                routing_byte_rate: 6,
+                exit_service_rate: 7,
+                exit_byte_rate: 8,
+            }
+        );
+        assert_eq!(
+            config.exit_policy,
+            ExitPolicy {
+                blocked_ports: vec![25],
+                blocked_hostnames: vec![String::from("ads.com")],
+                blocked_ip_addrs: vec![IpAddr::from_str("6.6.6.6").unwrap()],
+                offered_dns_servers: vec![
+                    IpAddr::from_str("9.9.9.9").unwrap(),
+                    IpAddr::from_str("1.1.1.1").unwrap(),
+                ],
+            }
+        );
+        assert_eq!(
+            config.request_exit_dns_server,
+            Some(IpAddr::from_str("9.9.9.9").unwrap())
+        );
+        assert_eq!(config.exit_location, Some(String::from("DE")));
+        assert_eq!(config.country, Some(String::from("FR")));
+        assert_eq!(
+            config.exit_sla,
+            Some(ExitSla {
+                max_streams: 50,
+                max_stream_bytes_per_second: 1000000,
+                content_policy_hash: config.exit_policy.summary_hash(),
+            })
+        );
+        assert_eq!(config.retransmission_memory, 524288);
+        assert_eq!(config.retransmission_spill, true);
+        assert_eq!(
+            config.adaptive_hops,
+            Some(AdaptiveHopsConfig {
+                min_hops: 1,
+                max_hops: 4,
+                latency_threshold: Duration::from_millis(900),
+            })
+        );
+        assert_eq!(config.fragment_mtu, Some(1200));
+        assert_eq!(config.pad_packets, true);
+        assert_eq!(config.cover_traffic, Some(6));
+        assert_eq!(
+            config.relay_fairness_config,
+            Some(RelayFairnessConfig {
+                bytes_per_second: 500000,
+                weighted_by_standing: true,
+            })
+        );
+        assert_eq!(config.metrics_port, Some(9100));
+        assert_eq!(config.regenerate_ports, true);
     }
 
     #[test]
@@ -1049,6 +4225,12 @@ mod tests {
             config.neighborhood_config.earning_wallet,
             accountant::DEFAULT_EARNING_WALLET.clone()
         );
+        assert_eq!(
+            config.neighborhood_config.consuming_wallet,
+            Some(accountant::TEMPORARY_CONSUMING_WALLET.clone())
+        );
+        assert_eq!(config.standby_consuming_wallet, None);
+        assert_eq!(config.earning_consolidation, None);
     }
 
     #[test]
@@ -1124,40 +4306,153 @@ mod tests {
         subject.initialize_as_privileged(
             &vec![
                 String::from("--dns_servers"),
-                String::from("222.222.222.222"),
-                String::from("--port_count"),
-                String::from("1"),
+                String::from("222.222.222.222"),
+                String::from("--port_count"),
+                String::from("1"),
+            ],
+            &mut FakeStreamHolder::new().streams(),
+        );
+
+        let config = subject.config.unwrap();
+        assert_eq!(config.neighborhood_config.clandestine_port_list.len(), 1);
+        let mut clandestine_discriminators = config
+            .clandestine_discriminator_factories
+            .into_iter()
+            .map(|factory| factory.make())
+            .collect::<Vec<Discriminator>>();
+        let mut discriminator = clandestine_discriminators.remove(0);
+        discriminator.add_data(&b"{\"component\": \"NBHD\", \"bodyText\": \"Booga\"}"[..]);
+        assert_eq!(
+            discriminator.take_chunk(),
+            Some(UnmaskedChunk {
+                chunk: b"Booga".to_vec(),
+                last_chunk: true,
+                sequenced: false,
+            })
+        ); // TODO: Where is this 'true' coming from?  Is it a problem?
+        assert_eq!(clandestine_discriminators.len(), 0);
+    }
+
+    #[test]
+    fn initialize_as_root_stores_dns_servers_and_passes_them_to_actor_system_factory_for_proxy_client_in_initialize_as_unprivileged(
+    ) {
+        let actor_system_factory = ActorSystemFactoryMock::new();
+        let dns_servers_arc = actor_system_factory.dnss.clone();
+        let mut subject = BootstrapperBuilder::new()
+            .actor_system_factory(Box::new(actor_system_factory))
+            .add_listener_handler(Box::new(
+                ListenerHandlerNull::new(vec![]).bind_port_result(Ok(())),
+            ))
+            .add_listener_handler(Box::new(
+                ListenerHandlerNull::new(vec![]).bind_port_result(Ok(())),
+            ))
+            .add_listener_handler(Box::new(
+                ListenerHandlerNull::new(vec![]).bind_port_result(Ok(())),
+            ))
+            .build();
+
+        subject.initialize_as_privileged(
+            &vec![
+                String::from("--dns_servers"),
+                String::from("1.2.3.4,2.3.4.5"),
+                String::from("--port_count"),
+                String::from("0"),
+            ],
+            &mut FakeStreamHolder::new().streams(),
+        );
+
+        subject.initialize_as_unprivileged();
+
+        let dns_servers_guard = dns_servers_arc.lock().unwrap();
+        assert_eq!(
+            dns_servers_guard.as_ref().unwrap(),
+            &vec!(
+                SocketAddr::from_str("1.2.3.4:53").unwrap(),
+                SocketAddr::from_str("2.3.4.5:53").unwrap()
+            )
+        )
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid IP address for --dns_servers <servers>: 'booga'")]
+    fn initialize_as_root_complains_about_dns_servers_syntax_errors() {
+        let mut subject = BootstrapperBuilder::new()
+            .add_listener_handler(Box::new(
+                ListenerHandlerNull::new(vec![]).bind_port_result(Ok(())),
+            ))
+            .add_listener_handler(Box::new(
+                ListenerHandlerNull::new(vec![]).bind_port_result(Ok(())),
+            ))
+            .build();
+
+        subject.initialize_as_privileged(
+            &vec![
+                String::from("--dns_servers"),
+                String::from("booga,booga"),
+                String::from("--port_count"),
+                String::from("0"),
+            ],
+            &mut FakeStreamHolder::new().streams(),
+        );
+    }
+
+    #[test]
+    fn initialize_as_privileged_passes_protocol_pack_ports_along() {
+        let mut subject = BootstrapperBuilder::new()
+            .add_listener_handler(Box::new(
+                ListenerHandlerNull::new(vec![]).bind_port_result(Ok(())),
+            ))
+            .add_listener_handler(Box::new(
+                ListenerHandlerNull::new(vec![]).bind_port_result(Ok(())),
+            ))
+            .build();
+
+        subject.initialize_as_privileged(
+            &vec![
+                String::from("--dns_servers"),
+                String::from("1.1.1.1"),
+                String::from("--protocol_packs"),
+                String::from("8080:http,8443:tls"),
+            ],
+            &mut FakeStreamHolder::new().streams(),
+        );
+
+        let mut expected = HashMap::new();
+        expected.insert(8080, String::from("http"));
+        expected.insert(8443, String::from("tls"));
+        assert_eq!(subject.config.unwrap().protocol_pack_ports, expected);
+    }
+
+    #[test]
+    fn initialize_as_privileged_passes_socks_port_along() {
+        let mut subject = BootstrapperBuilder::new()
+            .add_listener_handler(Box::new(
+                ListenerHandlerNull::new(vec![]).bind_port_result(Ok(())),
+            ))
+            .add_listener_handler(Box::new(
+                ListenerHandlerNull::new(vec![]).bind_port_result(Ok(())),
+            ))
+            .add_listener_handler(Box::new(
+                ListenerHandlerNull::new(vec![]).bind_port_result(Ok(())),
+            ))
+            .build();
+
+        subject.initialize_as_privileged(
+            &vec![
+                String::from("--dns_servers"),
+                String::from("1.1.1.1"),
+                String::from("--socks_port"),
+                String::from("1080"),
             ],
             &mut FakeStreamHolder::new().streams(),
         );
 
-        let config = subject.config.unwrap();
-        assert_eq!(config.neighborhood_config.clandestine_port_list.len(), 1);
-        let mut clandestine_discriminators = config
-            .clandestine_discriminator_factories
-            .into_iter()
-            .map(|factory| factory.make())
-            .collect::<Vec<Discriminator>>();
-        let mut discriminator = clandestine_discriminators.remove(0);
-        discriminator.add_data(&b"{\"component\": \"NBHD\", \"bodyText\": \"Booga\"}"[..]);
-        assert_eq!(
-            discriminator.take_chunk(),
-            Some(UnmaskedChunk {
-                chunk: b"Booga".to_vec(),
-                last_chunk: true,
-                sequenced: false,
-            })
-        ); // TODO: Where is this 'true' coming from?  Is it a problem?
-        assert_eq!(clandestine_discriminators.len(), 0);
+        assert_eq!(subject.config.unwrap().socks_port, Some(1080));
     }
 
     #[test]
-    fn initialize_as_root_stores_dns_servers_and_passes_them_to_actor_system_factory_for_proxy_client_in_initialize_as_unprivileged(
-    ) {
-        let actor_system_factory = ActorSystemFactoryMock::new();
-        let dns_servers_arc = actor_system_factory.dnss.clone();
+    fn initialize_as_privileged_passes_http_proxy_port_along() {
         let mut subject = BootstrapperBuilder::new()
-            .actor_system_factory(Box::new(actor_system_factory))
             .add_listener_handler(Box::new(
                 ListenerHandlerNull::new(vec![]).bind_port_result(Ok(())),
             ))
@@ -1172,32 +4467,81 @@ mod tests {
         subject.initialize_as_privileged(
             &vec![
                 String::from("--dns_servers"),
-                String::from("1.2.3.4,2.3.4.5"),
-                String::from("--port_count"),
-                String::from("0"),
+                String::from("1.1.1.1"),
+                String::from("--http_proxy_port"),
+                String::from("8118"),
             ],
             &mut FakeStreamHolder::new().streams(),
         );
 
-        subject.initialize_as_unprivileged();
+        assert_eq!(subject.config.unwrap().http_proxy_port, Some(8118));
+    }
+
+    #[test]
+    fn initialize_as_privileged_adopts_the_low_power_profile_when_asked() {
+        let mut subject = BootstrapperBuilder::new().build();
+
+        subject.initialize_as_privileged(
+            &vec![
+                String::from("--dns_servers"),
+                String::from("1.1.1.1"),
+                String::from("--listen"),
+                String::from("none"),
+                String::from("--low_power"),
+                String::from("on"),
+            ],
+            &mut FakeStreamHolder::new().streams(),
+        );
+
+        let config = subject.config.unwrap();
+        assert_eq!(config.performance_profile, PerformanceProfile::low_power());
+        assert_eq!(config.arbiter_layout, ArbiterLayout::shared());
+    }
+
+    #[test]
+    fn initialize_as_privileged_uses_the_standard_profile_by_default() {
+        let mut subject = BootstrapperBuilder::new().build();
+
+        subject.initialize_as_privileged(
+            &vec![
+                String::from("--dns_servers"),
+                String::from("1.1.1.1"),
+                String::from("--listen"),
+                String::from("none"),
+            ],
+            &mut FakeStreamHolder::new().streams(),
+        );
 
-        let dns_servers_guard = dns_servers_arc.lock().unwrap();
         assert_eq!(
-            dns_servers_guard.as_ref().unwrap(),
-            &vec!(
-                SocketAddr::from_str("1.2.3.4:53").unwrap(),
-                SocketAddr::from_str("2.3.4.5:53").unwrap()
-            )
-        )
+            subject.config.unwrap().performance_profile,
+            PerformanceProfile::standard()
+        );
     }
 
     #[test]
-    #[should_panic(expected = "Invalid IP address for --dns_servers <servers>: 'booga'")]
-    fn initialize_as_root_complains_about_dns_servers_syntax_errors() {
+    fn initialize_as_privileged_with_no_consumer_listeners_is_relay_only() {
+        let mut subject = BootstrapperBuilder::new().build();
+
+        subject.initialize_as_privileged(
+            &vec![
+                String::from("--dns_servers"),
+                String::from("1.1.1.1"),
+                String::from("--listen"),
+                String::from("none"),
+            ],
+            &mut FakeStreamHolder::new().streams(),
+        );
+
+        assert_eq!(subject.listener_handlers.len(), 0);
+        let config = subject.config.unwrap();
+        assert_eq!(config.relay_only, true);
+        assert_eq!(config.socks_port, None);
+        assert_eq!(config.http_proxy_port, None);
+    }
+
+    #[test]
+    fn initialize_as_privileged_with_consumer_listeners_is_not_relay_only() {
         let mut subject = BootstrapperBuilder::new()
-            .add_listener_handler(Box::new(
-                ListenerHandlerNull::new(vec![]).bind_port_result(Ok(())),
-            ))
             .add_listener_handler(Box::new(
                 ListenerHandlerNull::new(vec![]).bind_port_result(Ok(())),
             ))
@@ -1206,12 +4550,15 @@ mod tests {
         subject.initialize_as_privileged(
             &vec![
                 String::from("--dns_servers"),
-                String::from("booga,booga"),
-                String::from("--port_count"),
-                String::from("0"),
+                String::from("1.1.1.1"),
+                String::from("--listen"),
+                String::from("tls"),
             ],
             &mut FakeStreamHolder::new().streams(),
         );
+
+        assert_eq!(subject.listener_handlers.len(), 1);
+        assert_eq!(subject.config.unwrap().relay_only, false);
     }
 
     #[test]
@@ -1247,7 +4594,7 @@ mod tests {
         let cryptde_ref = {
             let mut streams = holder.streams();
 
-            let cryptde_ref = Bootstrapper::initialize_cryptde();
+            let cryptde_ref = Bootstrapper::initialize_cryptde(CryptoBackend::Null, None);
             Bootstrapper::report_local_descriptor(cryptde_ref, ip_addr, ports, &mut streams);
 
             cryptde_ref
@@ -1269,13 +4616,6 @@ mod tests {
             .unwrap()
             .as_str();
         assert_eq!(captured_descriptor, expected_descriptor);
-        TestLogHandler::new().exists_log_containing(
-            format!(
-                "INFO: Bootstrapper: SubstratumNode local descriptor: {}",
-                expected_descriptor
-            )
-            .as_str(),
-        );
 
         let expected_data = PlainData::new(b"ho'q ;iaerh;frjhvs;lkjerre");
         let crypt_data = cryptde_ref
@@ -1285,6 +4625,363 @@ mod tests {
         assert_eq!(decrypted_data, expected_data)
     }
 
+    #[test]
+    fn local_descriptor_brackets_ipv6_addresses() {
+        let cryptde = CryptDENull::new();
+
+        let result = Bootstrapper::local_descriptor(
+            &cryptde,
+            IpAddr::from_str("2001:db8::1").unwrap(),
+            &vec![3456, 4567],
+        );
+
+        assert_eq!(
+            result,
+            format!(
+                "{}:[2001:db8::1]:3456,4567",
+                base64::encode_config(&cryptde.public_key().as_slice(), base64::STANDARD_NO_PAD)
+            )
+        );
+    }
+
+    #[test]
+    fn persistent_identity_is_created_once_and_then_reused() {
+        let directory = PathBuf::from(
+            "generated/test/bootstrapper/persistent_identity_is_created_once_and_then_reused",
+        );
+        fs::remove_dir_all(&directory).is_ok();
+
+        let first = Bootstrapper::load_or_create_identity(CryptoBackend::Null, &directory);
+        let second = Bootstrapper::load_or_create_identity(CryptoBackend::Null, &directory);
+
+        assert_ne!(first.private_key().as_slice(), &b"uninitialized"[..]);
+        assert_eq!(second.private_key(), first.private_key());
+        assert_eq!(second.public_key(), first.public_key());
+        assert_eq!(
+            identity_bundle::read_private_key(&directory).unwrap(),
+            Some(first.private_key())
+        );
+    }
+
+    #[test]
+    fn persistent_real_identity_is_created_once_and_then_reused() {
+        let directory = PathBuf::from(
+            "generated/test/bootstrapper/persistent_real_identity_is_created_once_and_then_reused",
+        );
+        fs::remove_dir_all(&directory).is_ok();
+
+        let first = Bootstrapper::load_or_create_identity(CryptoBackend::Real, &directory);
+        let second = Bootstrapper::load_or_create_identity(CryptoBackend::Real, &directory);
+
+        assert_eq!(second.private_key(), first.private_key());
+        assert_eq!(second.public_key(), first.public_key());
+        let expected_data = PlainData::new(b"booga");
+        let crypt_data = first.encode(&second.public_key(), &expected_data).unwrap();
+        assert_ne!(crypt_data.as_slice(), expected_data.as_slice());
+        assert_eq!(second.decode(&crypt_data).unwrap(), expected_data);
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "isn't a CryptDEReal key: A CryptDEReal private key is 64 bytes long, not 13"
+    )]
+    fn persistent_identity_refuses_a_key_made_for_another_backend() {
+        let directory = PathBuf::from(
+            "generated/test/bootstrapper/persistent_identity_refuses_a_key_made_for_another_backend",
+        );
+        fs::remove_dir_all(&directory).is_ok();
+        identity_bundle::write_private_key(&directory, &PrivateKey::new(b"uninitialized")).unwrap();
+
+        Bootstrapper::load_or_create_identity(CryptoBackend::Real, &directory);
+    }
+
+    #[test]
+    fn persistent_identity_keeps_its_clandestine_ports_until_told_to_regenerate_them() {
+        let data_directory = "generated/test/bootstrapper/persistent_identity_keeps_its_clandestine_ports_until_told_to_regenerate_them";
+        fs::remove_dir_all(data_directory).is_ok();
+        let start = |regenerate_ports: &str| {
+            let args: Vec<String> = vec![
+                "command",
+                "--dns_servers",
+                "1.1.1.1",
+                "--port_count",
+                "2",
+                "--persistent_identity",
+                "on",
+                "--regenerate_ports",
+                regenerate_ports,
+                "--data_directory",
+                data_directory,
+            ]
+            .into_iter()
+            .map(String::from)
+            .collect();
+            let mut configuration = Configuration::new();
+            configuration.establish(&args);
+            let mut config = BootstrapperConfig::new();
+            Bootstrapper::parse_args(&args, &mut config);
+            Bootstrapper::keep_clandestine_ports(&mut configuration, &mut config);
+            let mut ports = configuration.clandestine_ports();
+            ports.sort();
+            (ports, config.node_addr_version)
+        };
+
+        let (first_ports, first_version) = start("off");
+        let (second_ports, second_version) = start("off");
+        let (third_ports, third_version) = start("on");
+
+        assert_eq!(first_ports.len(), 2);
+        assert_eq!(first_version, 0);
+        assert_eq!(second_ports, first_ports);
+        assert_eq!(second_version, 0);
+        assert_ne!(third_ports, first_ports);
+        assert_eq!(third_version, 1);
+        let config_dao = DbInitializerReal::new()
+            .initialize(&PathBuf::from(data_directory))
+            .unwrap()
+            .config;
+        assert_eq!(
+            config_dao.get_string(CLANDESTINE_PORTS_CONFIG),
+            Some(format!("{},{}", third_ports[0], third_ports[1]))
+        );
+        assert_eq!(config_dao.get_u64(NODE_ADDR_VERSION_CONFIG, 0), 1);
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Database is corrupt: config value '1234,booga' for clandestine_ports is not a list of ports"
+    )]
+    fn saved_ports_that_are_not_ports_are_a_corrupt_database() {
+        Bootstrapper::parse_saved_ports("1234,booga");
+    }
+
+    #[test]
+    fn parse_crypto_backend_defaults_to_null() {
+        let finder = ParameterFinder::new(vec![]);
+
+        let result = Bootstrapper::parse_crypto_backend(&finder);
+
+        assert_eq!(result, CryptoBackend::Null);
+    }
+
+    #[test]
+    fn parse_crypto_backend_works() {
+        let finder = ParameterFinder::new(vec![String::from("--crypto"), String::from("real")]);
+
+        let result = Bootstrapper::parse_crypto_backend(&finder);
+
+        assert_eq!(result, CryptoBackend::Real);
+    }
+
+    #[test]
+    #[should_panic(expected = "--crypto must be null or real, not rot13")]
+    fn parse_crypto_backend_complains_about_bad_value() {
+        let finder = ParameterFinder::new(vec![String::from("--crypto"), String::from("rot13")]);
+
+        Bootstrapper::parse_crypto_backend(&finder);
+    }
+
+    #[test]
+    fn persistent_identity_is_off_unless_requested() {
+        let args: Vec<String> = vec!["--dns_servers", "1.1.1.1"]
+            .into_iter()
+            .map(String::from)
+            .collect();
+        let mut config = BootstrapperConfig::new();
+        Bootstrapper::parse_args(&args, &mut config);
+        assert_eq!(config.persistent_identity, false);
+
+        let mut args = args.clone();
+        args.extend(vec![
+            String::from("--persistent_identity"),
+            String::from("on"),
+        ]);
+        Bootstrapper::parse_args(&args, &mut config);
+        assert_eq!(config.persistent_identity, true);
+    }
+
+    #[test]
+    fn firewall_is_left_alone_unless_requested() {
+        let args: Vec<String> = vec!["--dns_servers", "1.1.1.1"]
+            .into_iter()
+            .map(String::from)
+            .collect();
+        let mut config = BootstrapperConfig::new();
+        Bootstrapper::parse_args(&args, &mut config);
+        assert_eq!(config.manage_firewall, false);
+        assert_eq!(config.ui_gateway_config.firewall_directory, None);
+
+        let mut args = args.clone();
+        args.extend(vec![String::from("--manage_firewall"), String::from("on")]);
+        Bootstrapper::parse_args(&args, &mut config);
+        assert_eq!(config.manage_firewall, true);
+    }
+
+    fn write_resolv_conf(name: &str, contents: &str) -> PathBuf {
+        let directory = PathBuf::from(format!("generated/test/bootstrapper/{}", name));
+        fs::create_dir_all(&directory).is_ok();
+        let path = directory.join("resolv.conf");
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn startup_summary_describes_configuration() {
+        let cryptde = CryptDENull::new();
+        let mut config = BootstrapperConfig::new();
+        config.dns_servers = vec![SocketAddr::from_str("1.2.3.4:53").unwrap()];
+        config.neighborhood_config.local_ip_addr = IpAddr::from_str("2.3.4.5").unwrap();
+        config.neighborhood_config.clandestine_port_list = vec![3456, 4567];
+        config.neighborhood_config.neighbor_configs = vec![(
+            PublicKey::new(b"GoodKey"),
+            NodeAddr::new(&IpAddr::from_str("1.2.3.4").unwrap(), &vec![1234]),
+        )];
+        config.neighborhood_config.is_bootstrap_node = true;
+        config.neighborhood_config.earning_wallet =
+            Wallet::new("0xbDfeFf9A1f4A1bdF483d680046344316019C58CF");
+        config.neighborhood_config.consuming_wallet = Some(Wallet::new("consuming"));
+        config.ui_gateway_config.ui_port = 5335;
+        config.adaptive_hops = Some(AdaptiveHopsConfig {
+            min_hops: 1,
+            max_hops: 4,
+            latency_threshold: Duration::from_millis(900),
+        });
+        let resolv_conf = write_resolv_conf(
+            "startup_summary_describes_configuration",
+            "nameserver 127.0.0.1\n",
+        );
+
+        let result = Bootstrapper::make_startup_summary(&cryptde, &config, &resolv_conf);
+
+        assert_eq!(
+            result,
+            StartupSummary {
+                version: String::from(env!("CARGO_PKG_VERSION")),
+                mode: String::from("decentralized"),
+                node_type: String::from("bootstrap"),
+                node_descriptor: format!(
+                    "{}:2.3.4.5:3456,4567",
+                    base64::encode_config(
+                        &cryptde.public_key().as_slice(),
+                        base64::STANDARD_NO_PAD
+                    )
+                ),
+                clandestine_ports: vec![3456, 4567],
+                ui_port: 5335,
+                dns_servers: vec![String::from("1.2.3.4:53")],
+                dns_subversion: String::from("subverted"),
+                earning_wallet: String::from("0xbDfe...58CF"),
+                consuming_wallet: Some(String::from("consuming")),
+                crypto_backend: String::from("CryptDENull"),
+                db_schema_version: String::from(CURRENT_SCHEMA_VERSION),
+                adaptive_hops: true,
+            }
+        );
+    }
+
+    #[test]
+    fn startup_summary_reports_zero_hop_standard_node() {
+        let cryptde = CryptDENull::new();
+        let config = BootstrapperConfig::new();
+        let resolv_conf = write_resolv_conf(
+            "startup_summary_reports_zero_hop_standard_node",
+            "nameserver 127.0.0.1\n",
+        );
+
+        let result = Bootstrapper::make_startup_summary(&cryptde, &config, &resolv_conf);
+
+        assert_eq!(result.mode, String::from("zero-hop"));
+        assert_eq!(result.node_type, String::from("standard"));
+        assert_eq!(result.consuming_wallet, None);
+    }
+
+    #[test]
+    fn startup_summary_names_the_real_crypto_backend() {
+        let cryptde = CryptDEReal::new();
+        let mut config = BootstrapperConfig::new();
+        config.crypto_backend = CryptoBackend::Real;
+        let resolv_conf = write_resolv_conf(
+            "startup_summary_names_the_real_crypto_backend",
+            "nameserver 127.0.0.1\n",
+        );
+
+        let result = Bootstrapper::make_startup_summary(&cryptde, &config, &resolv_conf);
+
+        assert_eq!(result.crypto_backend, String::from("CryptDEReal"));
+    }
+
+    #[test]
+    fn dns_subversion_status_looks_at_first_nameserver() {
+        let subverted = write_resolv_conf(
+            "dns_subversion_status_looks_at_first_nameserver/subverted",
+            "# generated\ndomain example.com\nnameserver 127.0.0.1\nnameserver 8.8.8.8\n",
+        );
+        let not_subverted = write_resolv_conf(
+            "dns_subversion_status_looks_at_first_nameserver/not_subverted",
+            "nameserver 8.8.8.8\nnameserver 127.0.0.1\n",
+        );
+        let empty = write_resolv_conf(
+            "dns_subversion_status_looks_at_first_nameserver/empty",
+            "# nameserver 127.0.0.1\n",
+        );
+        let missing = PathBuf::from("generated/test/bootstrapper/nonexistent/resolv.conf");
+
+        assert_eq!(
+            Bootstrapper::dns_subversion_status(&subverted),
+            String::from("subverted")
+        );
+        assert_eq!(
+            Bootstrapper::dns_subversion_status(&not_subverted),
+            String::from("not subverted")
+        );
+        assert_eq!(
+            Bootstrapper::dns_subversion_status(&empty),
+            String::from("unknown")
+        );
+        assert_eq!(
+            Bootstrapper::dns_subversion_status(&missing),
+            String::from("unknown")
+        );
+    }
+
+    #[test]
+    fn initialize_as_privileged_logs_startup_summary_and_hands_it_to_ui_gateway() {
+        init_test_logging();
+        let mut subject = BootstrapperBuilder::new()
+            .add_listener_handler(Box::new(
+                ListenerHandlerNull::new(vec![]).bind_port_result(Ok(())),
+            ))
+            .add_listener_handler(Box::new(
+                ListenerHandlerNull::new(vec![]).bind_port_result(Ok(())),
+            ))
+            .build();
+
+        subject.initialize_as_privileged(
+            &vec![
+                String::from("--dns_servers"),
+                String::from("1.1.1.1"),
+                String::from("--port_count"),
+                String::from("0"),
+            ],
+            &mut FakeStreamHolder::new().streams(),
+        );
+
+        let summary = subject
+            .config
+            .unwrap()
+            .ui_gateway_config
+            .startup_summary
+            .unwrap();
+        assert_eq!(summary.dns_servers, vec![String::from("1.1.1.1:53")]);
+        TestLogHandler::new().exists_log_containing(
+            format!(
+                "INFO: Bootstrapper: SubstratumNode startup summary: {}",
+                summary.to_json()
+            )
+            .as_str(),
+        );
+    }
+
     #[test]
     fn initialize_as_unprivileged_moves_streams_from_listener_handlers_to_stream_handler_pool() {
         init_test_logging();
@@ -0,0 +1,548 @@
+// Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use crate::sub_lib::parameter_finder::ParameterFinder;
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fs;
+use std::io::Write;
+use toml::value::Table;
+use toml::Value;
+
+// SUB_NODE_ is the one for container deployments; either will do
+pub const ENVIRONMENT_PREFIXES: &[&str] = &["SUB_NODE_", "SUBSTRATUM_"];
+pub const CONFIG_FILE_PARAMETER: &str = "config_file";
+// Given more than once on the command line, as a list in a config file, and separated by whitespace
+// in an environment variable
+pub const REPEATABLE_PARAMETERS: &[&str] = &["neighbor"];
+
+// Everything that can come from the environment or a config file as well as from the command line.
+// A new command-line parameter has to be added here before it can come from anywhere else.
+pub const PARAMETERS: &[&str] = &[
+    "adaptive_hops",
+    "ban_threshold",
+    "blockchain_service_url",
+    "capacity_class",
+    "consuming_wallet",
+    "consuming_wallet_threshold",
+    "country",
+    "cover_traffic",
+    "crash_point",
+    "crypto",
+    "data_directory",
+    "dedicated_arbiters",
+    "dns_port",
+    "dns_protocol",
+    "dns_servers",
+    "dns_target",
+    "exit_blocked_hosts",
+    "exit_blocked_ports",
+    "exit_connect_timeout",
+    "exit_downstream_limit",
+    "exit_location",
+    "exit_log",
+    "exit_rate",
+    "exit_sla",
+    "exit_upstream_limit",
+    "fault_injection_port",
+    "fragment_mtu",
+    "geoip_database",
+    "http_proxy_port",
+    "ip",
+    "listen",
+    "log_compress",
+    "log_format",
+    "log_level",
+    "log_max_files",
+    "log_max_size",
+    "low_power",
+    "manage_firewall",
+    "max_report_delay",
+    "metrics_port",
+    "neighbor",
+    "node_record_retention",
+    "node_type",
+    "offer_exit_dns_servers",
+    "offer_exit_tls",
+    "pad_packets",
+    "persistent_identity",
+    "port_count",
+    "privacy_policy",
+    "probe_resistance",
+    "protocol_packs",
+    "regenerate_ports",
+    "relay_limit",
+    "relay_weights_by_standing",
+    "request_exit_dns_server",
+    "request_exit_tls",
+    "retransmission_memory",
+    "retransmission_spill",
+    "route_exclusions",
+    "routing_rate",
+    "run_as_gid",
+    "run_as_uid",
+    "socks_port",
+    "standby_consuming_wallet",
+    "ui_port",
+    "ui_remote",
+    "ui_token",
+    "upload_window",
+    "wallet_address",
+    "wallet_rotation",
+    "wallet_rotation_period",
+];
+
+// Adds to the command line every parameter it doesn't have that's in the environment, and then every
+// one neither of them has that's in the config file, so that everything that reads parameters
+// finds them all in the same place. A parameter that can be given more than once, like --neighbor,
+// takes all its values from the first of the three that has it. Environment variables that can't be
+// used are skipped with a warning on stderr, since the logger isn't running yet; a parameter given
+// under both prefixes is a usage error.
+pub fn complete_args<E>(
+    args: &Vec<String>,
+    environment: E,
+    stderr: &mut dyn Write,
+) -> Result<Vec<String>, String>
+where
+    E: IntoIterator<Item = (OsString, OsString)>,
+{
+    let environment_parameters = from_environment(environment, stderr)?;
+    let config_file_opt = ParameterFinder::new(args.clone())
+        .find_value_for("--config_file", "--config_file <path to TOML file>")
+        .or_else(|| {
+            environment_parameters
+                .get(CONFIG_FILE_PARAMETER)
+                .map(|values| values[0].clone())
+        });
+    let file_parameters = match config_file_opt {
+        Some(path) => from_config_file(&path),
+        None => BTreeMap::new(),
+    };
+    // Arguments after the program name come in tag-value pairs, and a value that looks like a tag
+    // doesn't count
+    let mut given_tags: Vec<String> = args.iter().skip(1).step_by(2).cloned().collect();
+    let mut completed = args.clone();
+    let mut add_missing = |parameters: BTreeMap<String, Vec<String>>| {
+        for (name, values) in parameters {
+            let tag = format!("--{}", name);
+            if name == CONFIG_FILE_PARAMETER || given_tags.contains(&tag) {
+                continue;
+            }
+            values.into_iter().for_each(|value| {
+                completed.push(tag.clone());
+                completed.push(value);
+            });
+            given_tags.push(tag);
+        }
+    };
+    add_missing(environment_parameters);
+    add_missing(file_parameters);
+    Ok(completed)
+}
+
+// SUB_NODE_DNS_SERVERS or SUBSTRATUM_DNS_SERVERS is --dns_servers, and so on
+fn from_environment<E>(
+    environment: E,
+    stderr: &mut dyn Write,
+) -> Result<BTreeMap<String, Vec<String>>, String>
+where
+    E: IntoIterator<Item = (OsString, OsString)>,
+{
+    let mut parameters: BTreeMap<String, (String, Vec<String>)> = BTreeMap::new();
+    for (variable, value) in environment {
+        // Whatever else is in the environment is none of our business, however it's encoded
+        let variable = match variable.into_string() {
+            Ok(variable) => variable,
+            Err(_) => continue,
+        };
+        let name = match ENVIRONMENT_PREFIXES
+            .iter()
+            .find(|prefix| variable.starts_with(*prefix))
+        {
+            Some(prefix) => variable[prefix.len()..].to_lowercase(),
+            None => continue,
+        };
+        if name != CONFIG_FILE_PARAMETER && !PARAMETERS.contains(&name.as_str()) {
+            warn(
+                stderr,
+                format!("Ignoring {}: there is no --{} parameter", variable, name),
+            );
+            continue;
+        }
+        let value = match value.into_string() {
+            Ok(value) => value,
+            Err(_) => {
+                warn(
+                    stderr,
+                    format!("Ignoring {}: its value isn't valid UTF-8", variable),
+                );
+                continue;
+            }
+        };
+        if let Some((other_variable, _)) = parameters.get(&name) {
+            return Err(format!(
+                "Parameter '{}' is given by both {} and {}: use one or the other",
+                name, other_variable, variable
+            ));
+        }
+        let values = if REPEATABLE_PARAMETERS.contains(&name.as_str()) {
+            value.split_whitespace().map(String::from).collect()
+        } else {
+            vec![value]
+        };
+        parameters.insert(name, (variable, values));
+    }
+    Ok(parameters
+        .into_iter()
+        .map(|(name, (_, values))| (name, values))
+        .collect())
+}
+
+fn warn(stderr: &mut dyn Write, warning: String) {
+    writeln!(stderr, "*** WARNING *** {}", warning).expect("Internal error");
+}
+
+fn from_config_file(path: &str) -> BTreeMap<String, Vec<String>> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) => panic!("Couldn't read config file {}: {}", path, e),
+    };
+    let table: Table = match toml::from_str(&contents) {
+        Ok(table) => table,
+        Err(e) => panic!("Invalid config file {}: {}", path, e),
+    };
+    table
+        .into_iter()
+        .map(|(key, value)| {
+            if !PARAMETERS.contains(&key.as_str()) {
+                panic!("Unknown parameter '{}' in config file {}", key, path)
+            }
+            let values = match value {
+                Value::Array(elements) => elements
+                    .into_iter()
+                    .map(|element| scalar_value(&key, element, path))
+                    .collect(),
+                value => vec![scalar_value(&key, value, path)],
+            };
+            (key, values)
+        })
+        .collect()
+}
+
+// As it would be written on the command line, where switches are on or off
+fn scalar_value(key: &str, value: Value, path: &str) -> String {
+    match value {
+        Value::String(string) => string,
+        Value::Integer(integer) => format!("{}", integer),
+        Value::Float(float) => format!("{}", float),
+        Value::Boolean(true) => String::from("on"),
+        Value::Boolean(false) => String::from("off"),
+        _ => panic!(
+            "Invalid value for '{}' in config file {}: must be a string, a number, true, false, \
+             or a list of those",
+            key, path
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::test_utils::ByteArrayWriter;
+    use std::path::PathBuf;
+
+    const BASE_TEST_DIR: &str = "generated/test/config_file";
+
+    fn write_config_file(name: &str, contents: &str) -> String {
+        let directory = PathBuf::from(format!("{}/{}", BASE_TEST_DIR, name));
+        fs::remove_dir_all(&directory).is_ok();
+        fs::create_dir_all(&directory).is_ok();
+        let path = directory.join("config.toml");
+        fs::write(&path, contents).unwrap();
+        path.to_string_lossy().to_string()
+    }
+
+    fn strings(strs: Vec<&str>) -> Vec<String> {
+        strs.into_iter().map(String::from).collect()
+    }
+
+    fn environment(pairs: Vec<(&str, &str)>) -> Vec<(OsString, OsString)> {
+        pairs
+            .into_iter()
+            .map(|(name, value)| (OsString::from(name), OsString::from(value)))
+            .collect()
+    }
+
+    #[test]
+    fn constants_have_correct_values() {
+        assert_eq!(ENVIRONMENT_PREFIXES, &["SUB_NODE_", "SUBSTRATUM_"]);
+        assert_eq!(CONFIG_FILE_PARAMETER, "config_file");
+        assert_eq!(REPEATABLE_PARAMETERS, &["neighbor"]);
+    }
+
+    #[test]
+    fn command_line_beats_environment_beats_config_file() {
+        let path = write_config_file(
+            "command_line_beats_environment_beats_config_file",
+            "dns_servers = \"1.1.1.1\"\n\
+             log_level = \"info\"\n\
+             neighbor = [\"AQIDBA:1.2.3.4:1234\", \"BQYHCA:5.6.7.8:5678\"]\n\
+             port_count = 3\n\
+             persistent_identity = true\n\
+             ui_port = 5334\n",
+        );
+        let args = strings(vec![
+            "SubstratumNode",
+            "--config_file",
+            path.as_str(),
+            "--dns_servers",
+            "8.8.8.8",
+        ]);
+
+        let result = complete_args(
+            &args,
+            environment(vec![
+                ("SUBSTRATUM_LOG_LEVEL", "debug"),
+                ("SUBSTRATUM_NEIGHBOR", "CQoLDA:9.10.11.12:9101"),
+                ("PATH", "/usr/bin"),
+            ]),
+            &mut ByteArrayWriter::new(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            result,
+            strings(vec![
+                "SubstratumNode",
+                "--config_file",
+                path.as_str(),
+                "--dns_servers",
+                "8.8.8.8",
+                "--log_level",
+                "debug",
+                "--neighbor",
+                "CQoLDA:9.10.11.12:9101",
+                "--persistent_identity",
+                "on",
+                "--port_count",
+                "3",
+                "--ui_port",
+                "5334",
+            ])
+        );
+    }
+
+    #[test]
+    fn config_file_can_be_named_in_the_environment() {
+        let path = write_config_file(
+            "config_file_can_be_named_in_the_environment",
+            "log_level = \"trace\"\n",
+        );
+        let args = strings(vec!["SubstratumNode"]);
+
+        let result = complete_args(
+            &args,
+            environment(vec![("SUBSTRATUM_CONFIG_FILE", path.as_str())]),
+            &mut ByteArrayWriter::new(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            result,
+            strings(vec!["SubstratumNode", "--log_level", "trace"])
+        );
+    }
+
+    #[test]
+    fn container_variables_give_parameters_and_separate_repeated_values() {
+        let args = strings(vec!["SubstratumNode", "--log_level", "error"]);
+
+        let result = complete_args(
+            &args,
+            environment(vec![
+                ("SUB_NODE_DNS_SERVERS", "1.1.1.1,8.8.8.8"),
+                (
+                    "SUB_NODE_NEIGHBOR",
+                    " AQIDBA:1.2.3.4:1234,2345\n  BQYHCA:[2001:db8::1]:5678 ",
+                ),
+                ("SUB_NODE_LOG_LEVEL", "trace"),
+                ("SUBSTRATUM_DATA_DIRECTORY", "/node_root/data"),
+            ]),
+            &mut ByteArrayWriter::new(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            result,
+            strings(vec![
+                "SubstratumNode",
+                "--log_level",
+                "error",
+                "--data_directory",
+                "/node_root/data",
+                "--dns_servers",
+                "1.1.1.1,8.8.8.8",
+                "--neighbor",
+                "AQIDBA:1.2.3.4:1234,2345",
+                "--neighbor",
+                "BQYHCA:[2001:db8::1]:5678",
+            ])
+        );
+    }
+
+    #[test]
+    fn a_parameter_given_with_both_prefixes_is_a_usage_error() {
+        let result = complete_args(
+            &strings(vec!["SubstratumNode"]),
+            environment(vec![
+                ("SUBSTRATUM_LOG_LEVEL", "debug"),
+                ("SUB_NODE_LOG_LEVEL", "debug"),
+            ]),
+            &mut ByteArrayWriter::new(),
+        );
+
+        assert_eq!(
+            result,
+            Err(String::from(
+                "Parameter 'log_level' is given by both SUBSTRATUM_LOG_LEVEL and \
+                 SUB_NODE_LOG_LEVEL: use one or the other"
+            ))
+        );
+    }
+
+    #[test]
+    fn unknown_container_variable_is_skipped_with_a_warning() {
+        let mut stderr = ByteArrayWriter::new();
+
+        let result = complete_args(
+            &strings(vec!["SubstratumNode"]),
+            environment(vec![("SUB_NODE_BOOGA", "1"), ("SUB_NODE_UI_PORT", "5334")]),
+            &mut stderr,
+        )
+        .unwrap();
+
+        assert_eq!(result, strings(vec!["SubstratumNode", "--ui_port", "5334"]));
+        assert_eq!(
+            stderr.get_string(),
+            "*** WARNING *** Ignoring SUB_NODE_BOOGA: there is no --booga parameter\n"
+        );
+    }
+
+    #[test]
+    fn nothing_is_added_without_environment_or_config_file() {
+        let args = strings(vec!["SubstratumNode", "--dns_servers", "8.8.8.8"]);
+
+        let result = complete_args(&args, vec![], &mut ByteArrayWriter::new()).unwrap();
+
+        assert_eq!(result, args);
+    }
+
+    #[test]
+    #[should_panic(expected = "Unknown parameter 'dns_server' in config file ")]
+    fn unknown_key_in_config_file_is_named() {
+        let path = write_config_file(
+            "unknown_key_in_config_file_is_named",
+            "dns_server = \"8.8.8.8\"\n",
+        );
+        let args = strings(vec!["SubstratumNode", "--config_file", path.as_str()]);
+
+        complete_args(&args, vec![], &mut ByteArrayWriter::new()).unwrap();
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Invalid value for 'dns_servers' in config file generated/test/config_file/\
+                    value_of_wrong_type_in_config_file_is_named/config.toml: must be a string, a \
+                    number, true, false, or a list of those"
+    )]
+    fn value_of_wrong_type_in_config_file_is_named() {
+        let path = write_config_file(
+            "value_of_wrong_type_in_config_file_is_named",
+            "[dns_servers]\nprimary = \"8.8.8.8\"\n",
+        );
+        let args = strings(vec!["SubstratumNode", "--config_file", path.as_str()]);
+
+        complete_args(&args, vec![], &mut ByteArrayWriter::new()).unwrap();
+    }
+
+    #[test]
+    fn unknown_environment_variable_is_skipped_with_a_warning() {
+        let mut stderr = ByteArrayWriter::new();
+
+        let result = complete_args(
+            &strings(vec!["SubstratumNode"]),
+            environment(vec![
+                ("SUBSTRATUM_BOOGA", "1"),
+                ("SUBSTRATUM_UI_PORT", "5334"),
+            ]),
+            &mut stderr,
+        )
+        .unwrap();
+
+        assert_eq!(result, strings(vec!["SubstratumNode", "--ui_port", "5334"]));
+        assert_eq!(
+            stderr.get_string(),
+            "*** WARNING *** Ignoring SUBSTRATUM_BOOGA: there is no --booga parameter\n"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn environment_variables_that_are_not_utf8_are_skipped() {
+        use std::os::unix::ffi::OsStringExt;
+        let mut stderr = ByteArrayWriter::new();
+
+        let result = complete_args(
+            &strings(vec!["SubstratumNode"]),
+            vec![
+                (OsString::from_vec(vec![0x48, 0xFF]), OsString::from("1")),
+                (
+                    OsString::from("SUBSTRATUM_LOG_LEVEL"),
+                    OsString::from_vec(vec![0x69, 0xFF]),
+                ),
+                (OsString::from("SUBSTRATUM_UI_PORT"), OsString::from("5334")),
+            ],
+            &mut stderr,
+        )
+        .unwrap();
+
+        assert_eq!(result, strings(vec!["SubstratumNode", "--ui_port", "5334"]));
+        assert_eq!(
+            stderr.get_string(),
+            "*** WARNING *** Ignoring SUBSTRATUM_LOG_LEVEL: its value isn't valid UTF-8\n"
+        );
+    }
+
+    #[test]
+    fn a_value_that_looks_like_a_tag_does_not_hide_that_parameter() {
+        let args = strings(vec!["SubstratumNode", "--crash_point", "--log_level"]);
+
+        let result = complete_args(
+            &args,
+            environment(vec![("SUBSTRATUM_LOG_LEVEL", "debug")]),
+            &mut ByteArrayWriter::new(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            result,
+            strings(vec![
+                "SubstratumNode",
+                "--crash_point",
+                "--log_level",
+                "--log_level",
+                "debug",
+            ])
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid config file ")]
+    fn config_file_that_is_not_toml_is_rejected() {
+        let path = write_config_file(
+            "config_file_that_is_not_toml_is_rejected",
+            "dns_servers: 8.8.8.8\n",
+        );
+        let args = strings(vec!["SubstratumNode", "--config_file", path.as_str()]);
+
+        complete_args(&args, vec![], &mut ByteArrayWriter::new()).unwrap();
+    }
+}
@@ -1,41 +1,93 @@
 // Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
 use crate::discriminator::DiscriminatorFactory;
-use crate::http_request_start_finder::HttpRequestDiscriminatorFactory;
 use crate::json_discriminator_factory::JsonDiscriminatorFactory;
+use crate::proxy_server::protocol_pack::protocol_pack_named;
+use crate::proxy_server::protocol_pack::protocol_pack_names;
+use crate::proxy_server::protocol_pack::standard_protocol_pack_ports;
+use crate::socks_discriminator_factory::SocksDiscriminatorFactory;
 use crate::sub_lib::parameter_finder::ParameterFinder;
-use crate::tls_discriminator_factory::TlsDiscriminatorFactory;
 use std::collections::HashMap;
 use std::net::IpAddr;
 use std::net::Ipv4Addr;
 use std::net::SocketAddr;
+use std::net::TcpListener;
 use std::net::UdpSocket;
 
+const SOCKS_LISTENER: &str = "socks";
+const HTTP_PROXY_LISTENER: &str = "http_proxy";
+
 // TODO: This should be subsumed into BootstrapperConfig
 pub struct Configuration {
     pub port_configurations: HashMap<u16, PortConfiguration>,
+    pub protocol_pack_ports: HashMap<u16, String>,
+    pub socks_port: Option<u16>,
+    pub http_proxy_port: Option<u16>,
 }
 
 impl Configuration {
     pub fn new() -> Configuration {
         Configuration {
             port_configurations: HashMap::new(),
+            protocol_pack_ports: HashMap::new(),
+            socks_port: None,
+            http_proxy_port: None,
         }
     }
 
     pub fn establish(&mut self, args: &Vec<String>) {
-        self.port_configurations.insert(
-            80,
-            PortConfiguration::new(
-                vec![Box::new(HttpRequestDiscriminatorFactory::new())],
-                false,
-            ),
-        );
-        self.port_configurations.insert(
-            443,
-            PortConfiguration::new(vec![Box::new(TlsDiscriminatorFactory::new())], false),
+        let finder = ParameterFinder::new(args.clone());
+        let listeners_opt = Configuration::parse_listeners(&finder);
+        let listens_for = |name: &str| match &listeners_opt {
+            None => true,
+            Some(listeners) => listeners.iter().any(|listener| listener == name),
+        };
+        // Ports whose listeners are turned off keep their protocol packs, so that SOCKS5 clients
+        // can still name them as targets
+        self.protocol_pack_ports = Configuration::parse_protocol_packs(&finder);
+        for (port, name) in self.protocol_pack_ports.iter() {
+            if !listens_for(name) {
+                continue;
+            }
+            let protocol_pack = protocol_pack_named(name).expect("Protocol pack disappeared");
+            self.port_configurations.insert(
+                *port,
+                PortConfiguration::new(vec![protocol_pack.discriminator_factory()], false),
+            );
+        }
+
+        self.socks_port = Configuration::parse_socks_port(&finder, &self.protocol_pack_ports);
+        if !listens_for(SOCKS_LISTENER) {
+            self.socks_port = None;
+        } else if listeners_opt.is_some() && self.socks_port.is_none() {
+            panic!("--listen socks needs a --socks_port to listen on")
+        }
+        if let Some(socks_port) = self.socks_port {
+            self.port_configurations.insert(
+                socks_port,
+                PortConfiguration::new(vec![Box::new(SocksDiscriminatorFactory::new())], false)
+                    .local_only(),
+            );
+        }
+
+        self.http_proxy_port = Configuration::parse_http_proxy_port(
+            &finder,
+            &self.protocol_pack_ports,
+            self.socks_port,
         );
+        if !listens_for(HTTP_PROXY_LISTENER) {
+            self.http_proxy_port = None;
+        } else if listeners_opt.is_some() && self.http_proxy_port.is_none() {
+            panic!("--listen http_proxy needs an --http_proxy_port to listen on")
+        }
+        if let Some(http_proxy_port) = self.http_proxy_port {
+            self.port_configurations.insert(
+                http_proxy_port,
+                PortConfiguration::new(vec![Box::new(SocksDiscriminatorFactory::new())], false)
+                    .local_only(),
+            );
+        }
 
-        let port_count = Configuration::parse_port_count(&ParameterFinder::new(args.clone()));
+        let port_count = Configuration::parse_port_count(&finder);
         for _ in 0..port_count {
             let port = Configuration::find_free_port();
             self.port_configurations.insert(
@@ -52,19 +104,143 @@ impl Configuration {
             .collect()
     }
 
+    // False for a pure relay or exit: every port it listens on is for other Nodes
+    pub fn serves_consumers(&self) -> bool {
+        self.port_configurations
+            .values()
+            .any(|port_configuration| !port_configuration.is_clandestine)
+    }
+
     pub fn clandestine_ports(&self) -> Vec<u16> {
         self.all_ports()
             .into_iter()
-            .filter(|port| (*port != 80) && (*port != 443))
+            .filter(|port| self.port_configurations[port].is_clandestine)
             .collect()
     }
 
+    // Ports saved from an earlier run take the place of the ones establish() found, as long as
+    // there are as many of them as --port_count asks for and nothing else has one now
+    pub fn reuse_clandestine_ports(&mut self, saved_ports: &[u16]) -> bool {
+        let found_ports = self.clandestine_ports();
+        let usable = |port: &u16| {
+            found_ports.contains(port)
+                || (!self.port_configurations.contains_key(port) && Configuration::is_free(*port))
+        };
+        if (saved_ports.len() != found_ports.len()) || !saved_ports.iter().all(usable) {
+            return false;
+        }
+        found_ports.iter().for_each(|port| {
+            self.port_configurations.remove(port);
+        });
+        saved_ports.iter().for_each(|port| {
+            self.port_configurations.insert(
+                *port,
+                PortConfiguration::new(vec![Box::new(JsonDiscriminatorFactory::new())], true),
+            );
+        });
+        true
+    }
+
+    fn is_free(port: u16) -> bool {
+        TcpListener::bind(SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), port)).is_ok()
+    }
+
     fn find_free_port() -> u16 {
         let socket = UdpSocket::bind(SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 0))
             .expect("Not enough free ports");
         socket.local_addr().expect("Bind failed").port()
     }
 
+    fn parse_protocol_packs(finder: &ParameterFinder) -> HashMap<u16, String> {
+        let usage = format!(
+            "--protocol_packs <comma-separated list of port:pack, where pack is one of {}>",
+            protocol_pack_names().join(", ")
+        );
+        let protocol_packs_str = match finder.find_value_for("--protocol_packs", &usage) {
+            None => return standard_protocol_pack_ports(),
+            Some(protocol_packs_str) => protocol_packs_str,
+        };
+        protocol_packs_str
+            .split(",")
+            .map(|entry| {
+                let pieces: Vec<&str> = entry.split(":").collect();
+                if pieces.len() != 2 {
+                    panic!("{}, not '{}'", usage, entry)
+                }
+                let port = match pieces[0].parse::<u16>() {
+                    Ok(port) => port,
+                    Err(_) => panic!("{}, not '{}'", usage, entry),
+                };
+                if protocol_pack_named(pieces[1]).is_none() {
+                    panic!("{}, not '{}'", usage, entry)
+                }
+                (port, String::from(pieces[1]))
+            })
+            .collect()
+    }
+
+    // None when --listen isn't given, in which case every configured listener is opened
+    fn parse_listeners(finder: &ParameterFinder) -> Option<Vec<String>> {
+        let mut names = protocol_pack_names();
+        names.push(SOCKS_LISTENER);
+        names.push(HTTP_PROXY_LISTENER);
+        let usage = format!(
+            "--listen <comma-separated list of {}, or none>",
+            names.join(", ")
+        );
+        let listeners_str = finder.find_value_for("--listen", &usage)?;
+        if listeners_str == "none" {
+            return Some(vec![]);
+        }
+        Some(
+            listeners_str
+                .split(",")
+                .map(|listener| {
+                    if !names.iter().any(|name| *name == listener) {
+                        panic!("{}, not '{}'", usage, listener)
+                    }
+                    String::from(listener)
+                })
+                .collect(),
+        )
+    }
+
+    fn parse_socks_port(
+        finder: &ParameterFinder,
+        protocol_pack_ports: &HashMap<u16, String>,
+    ) -> Option<u16> {
+        let usage = "--socks_port <port for SOCKS5 clients on this machine>";
+        let socks_port_str = finder.find_value_for("--socks_port", usage)?;
+        match socks_port_str.parse::<u16>() {
+            Ok(0) | Err(_) => panic!("{}, not '{}'", usage, socks_port_str),
+            Ok(port) if protocol_pack_ports.contains_key(&port) => panic!(
+                "{}: port {} is already taken by --protocol_packs",
+                usage, port
+            ),
+            Ok(port) => Some(port),
+        }
+    }
+
+    fn parse_http_proxy_port(
+        finder: &ParameterFinder,
+        protocol_pack_ports: &HashMap<u16, String>,
+        socks_port: Option<u16>,
+    ) -> Option<u16> {
+        let usage = "--http_proxy_port <port for HTTP proxy clients on this machine>";
+        let http_proxy_port_str = finder.find_value_for("--http_proxy_port", usage)?;
+        match http_proxy_port_str.parse::<u16>() {
+            Ok(0) | Err(_) => panic!("{}, not '{}'", usage, http_proxy_port_str),
+            Ok(port) if protocol_pack_ports.contains_key(&port) => panic!(
+                "{}: port {} is already taken by --protocol_packs",
+                usage, port
+            ),
+            Ok(port) if socks_port == Some(port) => {
+                panic!("{}: port {} is already taken by --socks_port", usage, port)
+            }
+            Ok(port) => Some(port),
+        }
+    }
+
     fn parse_port_count(finder: &ParameterFinder) -> usize {
         let usage = "--port_count <number of clandestine ports to open, default = 0>";
         match finder.find_value_for("--port_count", usage) {
@@ -84,6 +260,8 @@ impl Configuration {
 pub struct PortConfiguration {
     pub discriminator_factories: Vec<Box<dyn DiscriminatorFactory>>,
     pub is_clandestine: bool,
+    pub is_local_only: bool,
+    pub is_ipv6: bool,
 }
 
 impl PortConfiguration {
@@ -94,8 +272,22 @@ impl PortConfiguration {
         PortConfiguration {
             discriminator_factories,
             is_clandestine,
+            is_local_only: false,
+            is_ipv6: false,
         }
     }
+
+    // For ports that only programs on this machine should reach
+    pub fn local_only(mut self) -> PortConfiguration {
+        self.is_local_only = true;
+        self
+    }
+
+    // For ports that should hear from IPv6 peers as well as IPv4 ones
+    pub fn ipv6(mut self) -> PortConfiguration {
+        self.is_ipv6 = true;
+        self
+    }
 }
 
 #[cfg(test)]
@@ -218,6 +410,251 @@ mod tests {
         });
     }
 
+    #[test]
+    fn no_parameters_maps_standard_ports_to_protocol_packs() {
+        let args = vec![String::from("command")];
+        let mut subject = Configuration::new();
+
+        subject.establish(&args);
+
+        assert_eq!(subject.protocol_pack_ports, standard_protocol_pack_ports());
+    }
+
+    #[test]
+    fn protocol_packs_parameter_chooses_listening_ports() {
+        let args = vec![
+            String::from("command"),
+            String::from("--protocol_packs"),
+            String::from("8080:http,443:tls,8081:http"),
+        ];
+        let mut subject = Configuration::new();
+
+        subject.establish(&args);
+
+        let mut expected = HashMap::new();
+        expected.insert(8080, String::from("http"));
+        expected.insert(8081, String::from("http"));
+        expected.insert(443, String::from("tls"));
+        assert_eq!(subject.protocol_pack_ports, expected);
+        assert_eq!(subject.port_configurations.contains_key(&80), false);
+        let mut port_8081_configuration = subject.port_configurations.remove(&8081).unwrap();
+        assert!(!port_8081_configuration.is_clandestine);
+        let http_factory = port_8081_configuration.discriminator_factories.remove(0);
+        let mut http_discriminator = http_factory.make();
+        http_discriminator.add_data("GET http://url.com HTTP/1.1\r\n\r\n".as_bytes());
+        assert_eq!(http_discriminator.take_chunk().is_some(), true);
+        assert_eq!(subject.clandestine_ports().len(), 0);
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "--protocol_packs <comma-separated list of port:pack, where pack is one of http, tls>, not '8080:smtp'"
+    )]
+    fn parse_protocol_packs_rejects_unknown_packs() {
+        let finder = ParameterFinder::new(vec![
+            String::from("--protocol_packs"),
+            String::from("80:http,8080:smtp"),
+        ]);
+
+        Configuration::parse_protocol_packs(&finder);
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "--protocol_packs <comma-separated list of port:pack, where pack is one of http, tls>, not 'booga:http'"
+    )]
+    fn parse_protocol_packs_rejects_bad_ports() {
+        let finder = ParameterFinder::new(vec![
+            String::from("--protocol_packs"),
+            String::from("booga:http"),
+        ]);
+
+        Configuration::parse_protocol_packs(&finder);
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "--protocol_packs <comma-separated list of port:pack, where pack is one of http, tls>, not '80'"
+    )]
+    fn parse_protocol_packs_rejects_entries_without_packs() {
+        let finder =
+            ParameterFinder::new(vec![String::from("--protocol_packs"), String::from("80")]);
+
+        Configuration::parse_protocol_packs(&finder);
+    }
+
+    #[test]
+    fn socks_port_parameter_adds_local_only_listening_port() {
+        let args = vec![
+            String::from("command"),
+            String::from("--socks_port"),
+            String::from("1080"),
+        ];
+        let mut subject = Configuration::new();
+
+        subject.establish(&args);
+
+        assert_eq!(subject.socks_port, Some(1080));
+        let mut port_1080_configuration = subject.port_configurations.remove(&1080).unwrap();
+        assert!(!port_1080_configuration.is_clandestine);
+        assert!(port_1080_configuration.is_local_only);
+        let socks_factory = port_1080_configuration.discriminator_factories.remove(0);
+        let mut socks_discriminator = socks_factory.make();
+        socks_discriminator.add_data(&[0x05, 0x01, 0x00]);
+        assert_eq!(
+            socks_discriminator.take_chunk(),
+            Some(UnmaskedChunk::new(vec![0x05, 0x01, 0x00], true, true))
+        );
+        assert!(!subject.port_configurations[&80].is_local_only);
+    }
+
+    #[test]
+    fn no_parameters_produces_no_socks_port() {
+        let args = vec![String::from("command")];
+        let mut subject = Configuration::new();
+
+        subject.establish(&args);
+
+        assert_eq!(subject.socks_port, None);
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "--socks_port <port for SOCKS5 clients on this machine>: port 443 is already taken by --protocol_packs"
+    )]
+    fn parse_socks_port_rejects_protocol_pack_ports() {
+        let finder = ParameterFinder::new(vec![String::from("--socks_port"), String::from("443")]);
+
+        Configuration::parse_socks_port(&finder, &standard_protocol_pack_ports());
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "--socks_port <port for SOCKS5 clients on this machine>, not 'booga'"
+    )]
+    fn parse_socks_port_rejects_bad_ports() {
+        let finder =
+            ParameterFinder::new(vec![String::from("--socks_port"), String::from("booga")]);
+
+        Configuration::parse_socks_port(&finder, &standard_protocol_pack_ports());
+    }
+
+    #[test]
+    fn http_proxy_port_parameter_adds_local_only_listening_port() {
+        let args = vec![
+            String::from("command"),
+            String::from("--http_proxy_port"),
+            String::from("8118"),
+        ];
+        let mut subject = Configuration::new();
+
+        subject.establish(&args);
+
+        assert_eq!(subject.http_proxy_port, Some(8118));
+        let port_8118_configuration = subject.port_configurations.remove(&8118).unwrap();
+        assert!(!port_8118_configuration.is_clandestine);
+        assert!(port_8118_configuration.is_local_only);
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "--http_proxy_port <port for HTTP proxy clients on this machine>: port 1080 is already taken by --socks_port"
+    )]
+    fn parse_http_proxy_port_rejects_the_socks_port() {
+        let finder = ParameterFinder::new(vec![
+            String::from("--http_proxy_port"),
+            String::from("1080"),
+        ]);
+
+        Configuration::parse_http_proxy_port(&finder, &standard_protocol_pack_ports(), Some(1080));
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "--http_proxy_port <port for HTTP proxy clients on this machine>, not '0'"
+    )]
+    fn parse_http_proxy_port_rejects_bad_ports() {
+        let finder =
+            ParameterFinder::new(vec![String::from("--http_proxy_port"), String::from("0")]);
+
+        Configuration::parse_http_proxy_port(&finder, &standard_protocol_pack_ports(), None);
+    }
+
+    #[test]
+    fn listen_parameter_opens_only_the_listeners_it_names() {
+        let args = vec![
+            String::from("command"),
+            String::from("--listen"),
+            String::from("tls,socks"),
+            String::from("--socks_port"),
+            String::from("1080"),
+        ];
+        let mut subject = Configuration::new();
+
+        subject.establish(&args);
+
+        assert_eq!(subject.port_configurations.contains_key(&80), false);
+        assert_eq!(subject.port_configurations.contains_key(&443), true);
+        assert_eq!(subject.port_configurations.contains_key(&1080), true);
+        assert_eq!(subject.socks_port, Some(1080));
+        assert_eq!(subject.protocol_pack_ports, standard_protocol_pack_ports());
+        assert_eq!(subject.serves_consumers(), true);
+    }
+
+    #[test]
+    fn listen_none_leaves_only_clandestine_ports() {
+        let args = vec![
+            String::from("command"),
+            String::from("--listen"),
+            String::from("none"),
+            String::from("--socks_port"),
+            String::from("1080"),
+            String::from("--port_count"),
+            String::from("1"),
+        ];
+        let mut subject = Configuration::new();
+
+        subject.establish(&args);
+
+        assert_eq!(subject.all_ports(), subject.clandestine_ports());
+        assert_eq!(subject.all_ports().len(), 1);
+        assert_eq!(subject.socks_port, None);
+        assert_eq!(subject.serves_consumers(), false);
+    }
+
+    #[test]
+    #[should_panic(expected = "--listen socks needs a --socks_port to listen on")]
+    fn listen_socks_without_a_socks_port_is_refused() {
+        let args = vec![
+            String::from("command"),
+            String::from("--listen"),
+            String::from("http,socks"),
+        ];
+        let mut subject = Configuration::new();
+
+        subject.establish(&args);
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "--listen <comma-separated list of http, tls, socks, http_proxy, or none>, not 'smtp'"
+    )]
+    fn parse_listeners_rejects_unknown_listeners() {
+        let finder =
+            ParameterFinder::new(vec![String::from("--listen"), String::from("http,smtp")]);
+
+        Configuration::parse_listeners(&finder);
+    }
+
+    #[test]
+    fn parse_listeners_defaults_to_none() {
+        let finder = ParameterFinder::new(vec![]);
+
+        let result = Configuration::parse_listeners(&finder);
+
+        assert_eq!(result, None);
+    }
+
     #[test]
     #[should_panic(expected = "--port_count <clandestine port count> needs a number, not 'booga'")]
     fn parse_port_count_rejects_badly_formatted_port_count() {
@@ -256,6 +693,53 @@ mod tests {
         assert_eq!(ports.len(), 3);
     }
 
+    #[test]
+    fn saved_clandestine_ports_replace_the_ones_found() {
+        let args: Vec<String> = vec!["command", "--port_count", "2"]
+            .into_iter()
+            .map(String::from)
+            .collect();
+        let mut subject = Configuration::new();
+        subject.establish(&args);
+        let saved_ports = vec![
+            Configuration::find_free_port(),
+            subject.clandestine_ports()[0],
+        ];
+
+        let result = subject.reuse_clandestine_ports(&saved_ports);
+
+        assert_eq!(result, true);
+        let mut clandestine_ports = subject.clandestine_ports();
+        clandestine_ports.sort();
+        let mut expected_ports = saved_ports.clone();
+        expected_ports.sort();
+        assert_eq!(clandestine_ports, expected_ports);
+        assert_eq!(subject.all_ports().len(), 4);
+    }
+
+    #[test]
+    fn saved_clandestine_ports_are_not_reused_unless_there_are_enough_of_them_and_they_are_free() {
+        let args: Vec<String> = vec!["command", "--port_count", "1"]
+            .into_iter()
+            .map(String::from)
+            .collect();
+        let mut subject = Configuration::new();
+        subject.establish(&args);
+        let found_ports = subject.clandestine_ports();
+        let taken =
+            TcpListener::bind(SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), 0)).unwrap();
+        let taken_port = taken.local_addr().unwrap().port();
+
+        assert_eq!(subject.reuse_clandestine_ports(&[taken_port]), false);
+        assert_eq!(subject.reuse_clandestine_ports(&[80]), false);
+        assert_eq!(subject.reuse_clandestine_ports(&[]), false);
+        assert_eq!(
+            subject.reuse_clandestine_ports(&[found_ports[0], Configuration::find_free_port()]),
+            false
+        );
+        assert_eq!(subject.clandestine_ports(), found_ports);
+    }
+
     #[test]
     fn clandestine_ports_returns_only_clandestine_ports() {
         let mut subject = Configuration::new();
@@ -0,0 +1,348 @@
+// Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use crate::proxy_server::proxy_server::DEFAULT_MINIMUM_HOP_COUNT;
+use crate::sub_lib::accountant::ReportRoutingServiceConsumedMessage;
+use crate::sub_lib::cryptde::CryptDE;
+use crate::sub_lib::cryptde::PlainData;
+use crate::sub_lib::cryptde::PublicKey;
+use crate::sub_lib::hopper::CompositePayload;
+use crate::sub_lib::hopper::IncipientCoresPackage;
+use crate::sub_lib::logger::Logger;
+use crate::sub_lib::neighborhood::ExpectedService;
+use crate::sub_lib::neighborhood::ExpectedServices;
+use crate::sub_lib::neighborhood::RouteQueryMessage;
+use crate::sub_lib::neighborhood::RouteQueryResponse;
+use crate::sub_lib::peer_actors::BindMessage;
+use actix::Actor;
+use actix::AsyncContext;
+use actix::Context;
+use actix::Handler;
+use actix::Message;
+use actix::Recipient;
+use actix::Syn;
+use rand::Rng;
+use std::time::Duration;
+use tokio::prelude::future::Future;
+
+// Cover packages carry no more than this many bytes of filler
+pub const MAX_COVER_FILLER: usize = 1024;
+
+#[derive(Message)]
+struct SendCoverPackage {}
+
+// Now and then, sends a package full of nothing along a random route, so that someone watching a
+// Node's connections can't tell from their timing when it's really being used. The packages are
+// composites with no parts, which the Hopper at the far end throws away.
+pub struct CoverTraffic {
+    cryptde: &'static dyn CryptDE,
+    interval: Duration,
+    route_source: Option<Recipient<Syn, RouteQueryMessage>>,
+    hopper: Option<Recipient<Syn, IncipientCoresPackage>>,
+    accountant_routing_sub: Option<Recipient<Syn, ReportRoutingServiceConsumedMessage>>,
+    logger: Logger,
+}
+
+impl Actor for CoverTraffic {
+    type Context = Context<Self>;
+}
+
+impl Handler<BindMessage> for CoverTraffic {
+    type Result = ();
+
+    fn handle(&mut self, msg: BindMessage, ctx: &mut Self::Context) -> Self::Result {
+        self.route_source = Some(msg.peer_actors.neighborhood.route_query);
+        self.hopper = Some(msg.peer_actors.hopper.from_hopper_client);
+        self.accountant_routing_sub =
+            Some(msg.peer_actors.accountant.report_routing_service_consumed);
+        self.schedule_next(ctx);
+        ()
+    }
+}
+
+impl Handler<SendCoverPackage> for CoverTraffic {
+    type Result = ();
+
+    fn handle(&mut self, _msg: SendCoverPackage, ctx: &mut Self::Context) -> Self::Result {
+        self.send_cover_package();
+        self.schedule_next(ctx);
+        ()
+    }
+}
+
+impl CoverTraffic {
+    pub fn new(cryptde: &'static dyn CryptDE, packages_per_minute: u32) -> CoverTraffic {
+        if packages_per_minute == 0 {
+            panic!("Cover traffic can't be sent at zero packages per minute");
+        }
+        CoverTraffic {
+            cryptde,
+            interval: Duration::from_millis(60_000 / u64::from(packages_per_minute)),
+            route_source: None,
+            hopper: None,
+            accountant_routing_sub: None,
+            logger: Logger::new("CoverTraffic"),
+        }
+    }
+
+    // Anywhere from half the interval to half again as long, so the packages don't keep time
+    fn schedule_next(&self, ctx: &mut Context<Self>) {
+        let millis = self.interval.as_secs() * 1000 + u64::from(self.interval.subsec_millis());
+        let delay = rand::thread_rng().gen_range(millis / 2, millis + millis / 2 + 1);
+        ctx.run_later(Duration::from_millis(delay), |_act, ctx| {
+            ctx.notify(SendCoverPackage {})
+        });
+    }
+
+    fn send_cover_package(&self) {
+        let route_source = self
+            .route_source
+            .as_ref()
+            .expect("CoverTraffic unbound: no Neighborhood")
+            .clone();
+        let hopper = self
+            .hopper
+            .as_ref()
+            .expect("CoverTraffic unbound: no Hopper")
+            .clone();
+        let accountant_routing_sub = self
+            .accountant_routing_sub
+            .as_ref()
+            .expect("CoverTraffic unbound: no Accountant")
+            .clone();
+        let cryptde = self.cryptde;
+        let logger = self.logger.clone();
+        tokio::spawn(
+            route_source
+                .send(RouteQueryMessage::cover_route_request(
+                    DEFAULT_MINIMUM_HOP_COUNT,
+                ))
+                .then(move |route_result| {
+                    match route_result {
+                        Ok(Ok(response)) => CoverTraffic::transmit(
+                            cryptde,
+                            response,
+                            &hopper,
+                            &accountant_routing_sub,
+                            &logger,
+                        ),
+                        // Not enough Nodes known yet; there'll be another chance
+                        Ok(Err(refusal)) => {
+                            logger.debug(format!("No route for cover traffic: {}", refusal))
+                        }
+                        Err(e) => logger.error(format!(
+                            "Neighborhood refused to answer route request: {}",
+                            e
+                        )),
+                    }
+                    Ok(())
+                }),
+        );
+    }
+
+    fn transmit(
+        cryptde: &'static dyn CryptDE,
+        response: RouteQueryResponse,
+        hopper: &Recipient<Syn, IncipientCoresPackage>,
+        accountant_routing_sub: &Recipient<Syn, ReportRoutingServiceConsumedMessage>,
+        logger: &Logger,
+    ) {
+        let over = match response.expected_services {
+            ExpectedServices::RoundTrip(over, _, _) => over,
+            ExpectedServices::OneWay(over) => over,
+        };
+        let exit_key: PublicKey = match over.iter().find_map(|service| match service {
+            ExpectedService::Exit(key, _, _) => Some(key.clone()),
+            _ => None,
+        }) {
+            Some(key) => key,
+            None => {
+                logger.error(String::from("Cover traffic route has no exit: not sent"));
+                return;
+            }
+        };
+        let mut rng = rand::thread_rng();
+        let filler: Vec<u8> = (0..rng.gen_range(0, MAX_COVER_FILLER + 1))
+            .map(|_| rng.gen::<u8>())
+            .collect();
+        let payload = CompositePayload {
+            filler_opt: Some(PlainData::from(filler)),
+            ..CompositePayload::new()
+        };
+        let package = match IncipientCoresPackage::new(cryptde, response.route, payload, &exit_key)
+        {
+            Ok(package) => package,
+            Err(e) => {
+                logger.error(format!("Couldn't make cover package: {}", e));
+                return;
+            }
+        };
+        let payload_size = package.payload.len();
+        // Relays charge for cover packages the same as for any others
+        over.iter().for_each(|service| {
+            if let ExpectedService::Routing(_, earning_wallet, rate_card) = service {
+                accountant_routing_sub
+                    .try_send(ReportRoutingServiceConsumedMessage {
+                        earning_wallet: earning_wallet.clone(),
+                        payload_size,
+                        service_rate: rate_card.routing_service_rate,
+                        byte_rate: rate_card.routing_byte_rate,
+                        correlation_id_opt: None,
+                    })
+                    .expect("Accountant is dead");
+            }
+        });
+        logger.debug(format!(
+            "Sending {}-byte cover package to {}",
+            payload_size, exit_key
+        ));
+        hopper.try_send(package).expect("Hopper is dead");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sub_lib::cryptde::decodex;
+    use crate::sub_lib::cryptde_null::CryptDENull;
+    use crate::sub_lib::dispatcher::Component;
+    use crate::sub_lib::neighborhood::RateCard;
+    use crate::sub_lib::neighborhood::RouteRefusal;
+    use crate::sub_lib::route::Route;
+    use crate::sub_lib::route::RouteSegment;
+    use crate::sub_lib::wallet::Wallet;
+    use crate::test_utils::recorder::make_recorder;
+    use crate::test_utils::recorder::peer_actors_builder;
+    use crate::test_utils::test_utils::cryptde;
+    use actix::Addr;
+    use actix::System;
+    use std::thread;
+
+    fn cover_route_response() -> RouteQueryResponse {
+        let cryptde = cryptde();
+        let relay_key = PublicKey::new(&[1]);
+        let exit_key = PublicKey::new(&[2]);
+        let route = Route::round_trip(
+            RouteSegment::new(
+                vec![&cryptde.public_key(), &relay_key, &exit_key],
+                Component::Composite,
+            ),
+            RouteSegment::new(
+                vec![&exit_key, &relay_key, &cryptde.public_key()],
+                Component::Composite,
+            ),
+            cryptde,
+            Some(Wallet::new("consuming")),
+            1234,
+        )
+        .unwrap();
+        RouteQueryResponse {
+            route,
+            expected_services: ExpectedServices::RoundTrip(
+                vec![
+                    ExpectedService::Nothing,
+                    ExpectedService::Routing(
+                        relay_key.clone(),
+                        Wallet::new("relay"),
+                        RateCard::default(),
+                    ),
+                    ExpectedService::Exit(
+                        exit_key.clone(),
+                        Wallet::new("exit"),
+                        RateCard::default(),
+                    ),
+                ],
+                vec![],
+                1234,
+            ),
+            valid_for: Duration::from_secs(60),
+            score: 100,
+            exit_sla_opt: None,
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Cover traffic can't be sent at zero packages per minute")]
+    fn zero_packages_per_minute_is_rejected() {
+        CoverTraffic::new(cryptde(), 0);
+    }
+
+    #[test]
+    fn packages_per_minute_become_an_interval() {
+        assert_eq!(
+            CoverTraffic::new(cryptde(), 4).interval,
+            Duration::from_millis(15000)
+        );
+        assert_eq!(
+            CoverTraffic::new(cryptde(), 120).interval,
+            Duration::from_millis(500)
+        );
+    }
+
+    #[test]
+    fn cover_package_goes_along_a_random_route_and_is_paid_for() {
+        let (neighborhood, _, neighborhood_recording_arc) = make_recorder();
+        let neighborhood = neighborhood.route_query_response(Ok(cover_route_response()));
+        let (hopper, hopper_awaiter, hopper_recording_arc) = make_recorder();
+        let (accountant, accountant_awaiter, accountant_recording_arc) = make_recorder();
+        thread::spawn(move || {
+            let system = System::new("cover_package_goes_along_a_random_route_and_is_paid_for");
+            let peer_actors = peer_actors_builder()
+                .neighborhood(neighborhood)
+                .hopper(hopper)
+                .accountant(accountant)
+                .build();
+            let subject = CoverTraffic::new(cryptde(), 1);
+            let addr: Addr<Syn, CoverTraffic> = subject.start();
+            addr.try_send(BindMessage { peer_actors }).unwrap();
+
+            addr.try_send(SendCoverPackage {}).unwrap();
+
+            system.run();
+        });
+        hopper_awaiter.await_message_count(1);
+        accountant_awaiter.await_message_count(1);
+        let neighborhood_recording = neighborhood_recording_arc.lock().unwrap();
+        assert_eq!(
+            neighborhood_recording.get_record::<RouteQueryMessage>(0),
+            &RouteQueryMessage::cover_route_request(DEFAULT_MINIMUM_HOP_COUNT)
+        );
+        let hopper_recording = hopper_recording_arc.lock().unwrap();
+        let package = hopper_recording.get_record::<IncipientCoresPackage>(0);
+        assert_eq!(package.route, cover_route_response().route);
+        let payload: CompositePayload =
+            decodex(&CryptDENull::from(&PublicKey::new(&[2])), &package.payload).unwrap();
+        assert_eq!(payload.parts, vec![]);
+        assert_eq!(payload.filler_opt.unwrap().len() <= MAX_COVER_FILLER, true);
+        let accountant_recording = accountant_recording_arc.lock().unwrap();
+        let report = accountant_recording.get_record::<ReportRoutingServiceConsumedMessage>(0);
+        assert_eq!(report.earning_wallet, Wallet::new("relay"));
+        assert_eq!(report.payload_size, package.payload.len());
+        assert_eq!(accountant_recording.len(), 1);
+    }
+
+    #[test]
+    fn no_cover_package_is_sent_without_a_route() {
+        let (neighborhood, neighborhood_awaiter, _) = make_recorder();
+        let neighborhood = neighborhood.route_query_response(Err(RouteRefusal::NotEnoughNodes {
+            known: 1,
+            required: 2,
+        }));
+        let (hopper, _, hopper_recording_arc) = make_recorder();
+        thread::spawn(move || {
+            let system = System::new("no_cover_package_is_sent_without_a_route");
+            let peer_actors = peer_actors_builder()
+                .neighborhood(neighborhood)
+                .hopper(hopper)
+                .build();
+            let subject = CoverTraffic::new(cryptde(), 1);
+            let addr: Addr<Syn, CoverTraffic> = subject.start();
+            addr.try_send(BindMessage { peer_actors }).unwrap();
+
+            addr.try_send(SendCoverPackage {}).unwrap();
+
+            system.run();
+        });
+        neighborhood_awaiter.await_message_count(1);
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(hopper_recording_arc.lock().unwrap().len(), 0);
+    }
+}
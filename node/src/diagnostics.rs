@@ -0,0 +1,391 @@
+// Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use crate::sub_lib::logger;
+use crate::sub_lib::logger::Logger;
+use crate::sub_lib::ui_gateway::UiAlert;
+use crate::sub_lib::ui_gateway::UiMessage;
+use actix::Actor;
+use actix::Addr;
+use actix::Recipient;
+use actix::Syn;
+use backtrace::Backtrace;
+use lazy_static::lazy_static;
+use regex::Captures;
+use regex::Regex;
+use serde_derive::Serialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::net::Ipv6Addr;
+use std::panic;
+use std::panic::PanicInfo;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::thread;
+use std::time::SystemTime;
+
+// Inside the data directory
+pub const DIAGNOSTICS_DIRECTORY: &str = "diagnostics";
+pub const REDACTED: &str = "<redacted>";
+
+lazy_static! {
+    // actix 0.5.7 keeps the number of messages waiting in a mailbox to itself, so all there is to
+    // know about one from outside is whether it's still open
+    static ref MAILBOXES: Mutex<BTreeMap<String, Box<dyn Fn() -> bool + Send>>> =
+        Mutex::new(BTreeMap::new());
+    static ref UI_MESSAGE_SUB: Mutex<Option<Recipient<Syn, UiMessage>>> = Mutex::new(None);
+    static ref HANDLER_FRAME: Regex =
+        Regex::new(r"<node_lib::(?:\w+::)*(\w+) as actix::handler::Handler<")
+            .expect("Internal error");
+    static ref IPV4_ADDRESS: Regex =
+        Regex::new(r"\b\d{1,3}(?:\.\d{1,3}){3}\b").expect("Internal error");
+    static ref IPV6_CANDIDATE: Regex =
+        Regex::new(r"[0-9A-Fa-f:]*:[0-9A-Fa-f:]*").expect("Internal error");
+    static ref HOST_NAME: Regex =
+        Regex::new(r"\b(?:[A-Za-z0-9-]+\.)+[A-Za-z]{2,}\b").expect("Internal error");
+    // Public keys, stream keys, wallet addresses, tokens
+    static ref KEY_CANDIDATE: Regex =
+        Regex::new(r"[A-Za-z0-9+/_-]{16,}=*").expect("Internal error");
+}
+
+#[derive(Serialize)]
+struct Mailbox {
+    open: bool,
+}
+
+#[derive(Serialize)]
+struct DiagnosticBundle {
+    timestamp: String,
+    version: &'static str,
+    thread: String,
+    actor: String,
+    message: String,
+    location: Option<String>,
+    backtrace: Vec<String>,
+    mailboxes: BTreeMap<String, Mailbox>,
+    recent_log: Vec<String>,
+}
+
+pub fn watch_mailbox<A: Actor>(actor: &str, addr: &Addr<Syn, A>) {
+    let addr = addr.clone();
+    MAILBOXES
+        .lock()
+        .expect("Mailboxes are poisoned")
+        .insert(String::from(actor), Box::new(move || addr.connected()));
+}
+
+pub fn alert_ui_with(ui_message_sub: Recipient<Syn, UiMessage>) {
+    *UI_MESSAGE_SUB.lock().expect("UI message sub is poisoned") = Some(ui_message_sub);
+}
+
+// From here on, when an actor panics in one of its handlers, what led up to it is written to a
+// bundle in <data directory>/diagnostics, and UIs are told where. Panics anywhere else are left
+// alone. Either way, the panic is reported as it always was first.
+pub fn capture_actor_panics(data_directory: &Path) {
+    let directory = data_directory.join(DIAGNOSTICS_DIRECTORY);
+    let previous_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        previous_hook(info);
+        let frames = frames(&Backtrace::new());
+        if let Some(actor) = actor_in(&frames) {
+            let bundle = make_bundle(&SystemTime::now(), actor, info, frames);
+            // Held only to be set, so never while anything can panic
+            let ui_message_sub_opt = match UI_MESSAGE_SUB.lock() {
+                Ok(ui_message_sub_opt) => ui_message_sub_opt.clone(),
+                Err(_) => None,
+            };
+            report(&directory, &bundle, ui_message_sub_opt.as_ref());
+        }
+    }));
+}
+
+fn frames(backtrace: &Backtrace) -> Vec<String> {
+    backtrace
+        .frames()
+        .iter()
+        .flat_map(|frame| frame.symbols())
+        .map(|symbol| {
+            let name = match symbol.name() {
+                Some(name) => format!("{:#}", name),
+                None => String::from("<unknown>"),
+            };
+            match (symbol.filename(), symbol.lineno()) {
+                (Some(filename), Some(lineno)) => {
+                    format!("{} ({}:{})", name, filename.display(), lineno)
+                }
+                _ => name,
+            }
+        })
+        .collect()
+}
+
+// The innermost handler on the stack is the one that panicked
+fn actor_in(frames: &[String]) -> Option<String> {
+    frames
+        .iter()
+        .filter_map(|frame| HANDLER_FRAME.captures(frame))
+        .map(|captures| String::from(&captures[1]))
+        .next()
+}
+
+fn make_bundle(
+    timestamp: &SystemTime,
+    actor: String,
+    info: &PanicInfo<'_>,
+    frames: Vec<String>,
+) -> DiagnosticBundle {
+    let message = if let Some(message) = info.payload().downcast_ref::<&str>() {
+        String::from(*message)
+    } else if let Some(message) = info.payload().downcast_ref::<String>() {
+        message.clone()
+    } else {
+        String::from("<not a string>")
+    };
+    DiagnosticBundle {
+        timestamp: Logger::timestamp_as_string(timestamp),
+        version: env!("CARGO_PKG_VERSION"),
+        thread: String::from(thread::current().name().unwrap_or("<unnamed>")),
+        actor,
+        message: redact(&message),
+        location: info
+            .location()
+            .map(|location| format!("{}:{}", location.file(), location.line())),
+        backtrace: frames,
+        mailboxes: mailboxes(),
+        recent_log: logger::recent_records()
+            .iter()
+            .map(|record| redact(record))
+            .collect(),
+    }
+}
+
+fn mailboxes() -> BTreeMap<String, Mailbox> {
+    match MAILBOXES.lock() {
+        Ok(mailboxes) => mailboxes
+            .iter()
+            .map(|(actor, is_open)| (actor.clone(), Mailbox { open: is_open() }))
+            .collect(),
+        Err(_) => BTreeMap::new(),
+    }
+}
+
+// Nothing here may panic: this runs inside the panic hook. An actor on the SubstratumNode thread
+// takes the UiGateway down with it, so the alert gets out only when the actor that panicked had a
+// dedicated arbiter; otherwise the bundle has to be found in the data directory.
+fn report(
+    directory: &Path,
+    bundle: &DiagnosticBundle,
+    ui_message_sub_opt: Option<&Recipient<Syn, UiMessage>>,
+) {
+    match write_bundle(directory, bundle) {
+        Ok(path) => {
+            eprintln!(
+                "{} crashed; diagnostics are in {}",
+                bundle.actor,
+                path.display()
+            );
+            if let Some(ui_message_sub) = ui_message_sub_opt {
+                ui_message_sub
+                    .try_send(UiMessage::AlertMessage(
+                        UiAlert::new("actor_panicked")
+                            .with("actor", &bundle.actor)
+                            .with("path", &path.to_string_lossy()),
+                    ))
+                    .is_ok();
+            }
+        }
+        Err(e) => eprintln!(
+            "{} crashed, but its diagnostics couldn't be written to {}: {}",
+            bundle.actor,
+            directory.display(),
+            e
+        ),
+    }
+}
+
+fn write_bundle(directory: &Path, bundle: &DiagnosticBundle) -> io::Result<PathBuf> {
+    fs::create_dir_all(directory)?;
+    let path = directory.join(bundle_file_name(&bundle.timestamp));
+    let json = serde_json::to_string_pretty(bundle)
+        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+    fs::write(&path, json)?;
+    Ok(path)
+}
+
+// panic_20190314_154501.123.json for 2019-03-14 15:45:01.123
+fn bundle_file_name(timestamp: &str) -> String {
+    let compact: String = timestamp
+        .chars()
+        .filter(|c| *c != '-' && *c != ':')
+        .map(|c| if c == ' ' { '_' } else { c })
+        .collect();
+    format!("panic_{}.json", compact)
+}
+
+// Addresses, host names, and anything long enough to be a key, in case the bundle is passed
+// around; an IPv6 address only counts if it isn't part of something longer, like a Rust path
+fn redact(text: &str) -> String {
+    let text = IPV4_ADDRESS.replace_all(text, REDACTED);
+    let text = IPV6_CANDIDATE.replace_all(&text, |captures: &Captures<'_>| {
+        let candidate = captures.get(0).expect("Internal error");
+        let before = text[..candidate.start()].chars().next_back();
+        let after = text[candidate.end()..].chars().next();
+        let in_word =
+            |c_opt: Option<char>| c_opt.map_or(false, |c| c.is_alphanumeric() || c == '_');
+        if !in_word(before) && !in_word(after) && candidate.as_str().parse::<Ipv6Addr>().is_ok() {
+            String::from(REDACTED)
+        } else {
+            String::from(candidate.as_str())
+        }
+    });
+    let text = HOST_NAME.replace_all(&text, REDACTED);
+    KEY_CANDIDATE
+        .replace_all(&text, |captures: &Captures<'_>| {
+            if captures[0].chars().any(|c| c.is_ascii_digit()) {
+                String::from(REDACTED)
+            } else {
+                String::from(&captures[0])
+            }
+        })
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::recorder::make_recorder;
+    use crate::test_utils::recorder::Recorder;
+    use actix::msgs;
+    use actix::Arbiter;
+    use actix::System;
+    use std::time::Duration;
+    use std::time::UNIX_EPOCH;
+
+    const BASE_TEST_DIR: &str = "generated/test/diagnostics";
+
+    fn make_directory(name: &str) -> PathBuf {
+        let directory = PathBuf::from(format!("{}/{}", BASE_TEST_DIR, name));
+        fs::remove_dir_all(&directory).is_ok();
+        directory
+    }
+
+    fn make_test_bundle() -> DiagnosticBundle {
+        DiagnosticBundle {
+            timestamp: Logger::timestamp_as_string(
+                &(UNIX_EPOCH + Duration::from_millis(1_500_000_000_123)),
+            ),
+            version: "1.2.3",
+            thread: String::from("SubstratumNode"),
+            actor: String::from("Hopper"),
+            message: String::from("Booga"),
+            location: Some(String::from("src/hopper/hopper.rs:123")),
+            backtrace: vec![String::from("frame")],
+            mailboxes: vec![(String::from("Hopper"), Mailbox { open: true })]
+                .into_iter()
+                .collect(),
+            recent_log: vec![String::from("record")],
+        }
+    }
+
+    #[test]
+    fn constants_have_correct_values() {
+        assert_eq!(DIAGNOSTICS_DIRECTORY, "diagnostics");
+        assert_eq!(REDACTED, "<redacted>");
+    }
+
+    #[test]
+    fn the_innermost_handler_names_the_actor() {
+        let frames = vec![
+            String::from("backtrace::backtrace::trace"),
+            String::from(
+                "node_lib::hopper::routing_service::RoutingService::route (src/hopper/routing_service.rs:87)",
+            ),
+            String::from(
+                "<node_lib::hopper::hopper::Hopper as actix::handler::Handler<node_lib::sub_lib::hopper::IncipientCoresPackage>>::handle",
+            ),
+            String::from(
+                "<node_lib::dispatcher::Dispatcher as actix::handler::Handler<node_lib::sub_lib::dispatcher::InboundClientData>>::handle",
+            ),
+        ];
+
+        let result = actor_in(&frames);
+
+        assert_eq!(result, Some(String::from("Hopper")));
+    }
+
+    #[test]
+    fn a_panic_outside_any_handler_has_no_actor() {
+        let frames = vec![
+            String::from("backtrace::backtrace::trace"),
+            String::from("node_lib::bootstrapper::Bootstrapper::initialize_as_privileged"),
+        ];
+
+        let result = actor_in(&frames);
+
+        assert_eq!(result, None);
+    }
+
+    #[test]
+    fn addresses_host_names_and_keys_are_redacted() {
+        let result = redact(
+            "Couldn't reach 1.2.3.4:5678, [fe80::1]:80 or 2001:db8:0:0:0:0:0:1 for www.example.com \
+             from AQIDBAUGBwgJCgsMDQ4PEA or wallet 0x0123456789abcdef0123456789ABCDEF01234567",
+        );
+
+        assert_eq!(
+            result,
+            "Couldn't reach <redacted>:5678, [<redacted>]:80 or <redacted> for <redacted> \
+             from <redacted> or wallet <redacted>"
+        );
+    }
+
+    #[test]
+    fn ordinary_text_is_not_redacted() {
+        let text = "2017-07-14 02:40:00.123 ThreadId(1): ERROR: node_lib::hopper::Hopper: \
+                    initialize_as_unprivileged failed with code 42";
+
+        let result = redact(text);
+
+        assert_eq!(result, text);
+    }
+
+    #[test]
+    fn bundle_is_written_to_a_timestamped_file_and_announced() {
+        let directory = make_directory("bundle_is_written_to_a_timestamped_file_and_announced");
+        let system = System::new("bundle_is_written_to_a_timestamped_file_and_announced");
+        let (ui_gateway, _, ui_gateway_recording_arc) = make_recorder();
+        let ui_gateway_addr: Addr<Syn, Recorder> = ui_gateway.start();
+        let ui_message_sub = ui_gateway_addr.recipient::<UiMessage>();
+
+        report(&directory, &make_test_bundle(), Some(&ui_message_sub));
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+        let path = directory.join("panic_20170714_024000.123.json");
+        let written: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(
+            written,
+            serde_json::json!({
+                "timestamp": "2017-07-14 02:40:00.123",
+                "version": "1.2.3",
+                "thread": "SubstratumNode",
+                "actor": "Hopper",
+                "message": "Booga",
+                "location": "src/hopper/hopper.rs:123",
+                "backtrace": ["frame"],
+                "mailboxes": {"Hopper": {"open": true}},
+                "recent_log": ["record"],
+            })
+        );
+        let ui_gateway_recording = ui_gateway_recording_arc.lock().unwrap();
+        assert_eq!(
+            ui_gateway_recording.get_record::<UiMessage>(0),
+            &UiMessage::AlertMessage(
+                UiAlert::new("actor_panicked")
+                    .with("actor", "Hopper")
+                    .with("path", &path.to_string_lossy())
+            )
+        );
+    }
+}
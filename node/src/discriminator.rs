@@ -1,6 +1,7 @@
 // Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
 use crate::masquerader::Masquerader;
 use crate::sub_lib::framer::Framer;
+use crate::sub_lib::framer::FramingStats;
 use crate::sub_lib::logger::Logger;
 
 #[derive(Debug, PartialEq, Clone)]
@@ -34,7 +35,8 @@ impl Clone for Box<dyn DiscriminatorFactory> {
 pub struct Discriminator {
     framer: Box<dyn Framer>,
     masqueraders: Vec<Box<dyn Masquerader>>,
-    _logger: Logger,
+    unmaskable_frames: u64,
+    logger: Logger,
 }
 
 impl Discriminator {
@@ -45,7 +47,8 @@ impl Discriminator {
         Discriminator {
             framer,
             masqueraders,
-            _logger: Logger::new("Discriminator"),
+            unmaskable_frames: 0,
+            logger: Logger::new("Discriminator"),
         }
     }
 
@@ -53,19 +56,31 @@ impl Discriminator {
         self.framer.add_data(data);
     }
 
+    // A frame that no Masquerader recognizes is skipped rather than allowed to hide the frames
+    // queued up behind it.
     pub fn take_chunk(&mut self) -> Option<UnmaskedChunk> {
-        let frame = match self.framer.take_frame() {
-            Some(frame) => frame,
-            None => return None,
-        };
-        for masquerader in &self.masqueraders {
-            match masquerader.try_unmask(&frame.chunk[..]) {
-                Some(chunk) => return Some(chunk),
-                None => (),
+        while let Some(frame) = self.framer.take_frame() {
+            for masquerader in &self.masqueraders {
+                match masquerader.try_unmask(&frame.chunk[..]) {
+                    Some(chunk) => return Some(chunk),
+                    None => (),
+                }
             }
+            self.unmaskable_frames += 1;
+            self.logger.warning(format!(
+                "Discarding {}-byte frame that no Masquerader could unmask",
+                frame.chunk.len()
+            ));
         }
         None
     }
+
+    pub fn stats(&self) -> FramingStats {
+        FramingStats {
+            unmaskable_frames: self.unmaskable_frames,
+            ..self.framer.stats()
+        }
+    }
 }
 
 #[cfg(test)]
@@ -269,4 +284,27 @@ mod tests {
             Some(UnmaskedChunk::new(Vec::from(&b"choose me"[..]), true, true))
         );
     }
+
+    #[test]
+    fn skips_unmaskable_frames_to_get_to_the_ones_behind_them() {
+        let mut framer = FramerMock::new();
+        framer.add_data(&b"garbage"[..]);
+        framer.add_data(&b"booga"[..]);
+        let masquerader = MasqueraderMock::new()
+            .try_unmask_result(None)
+            .try_unmask_result(Some(UnmaskedChunk::new(
+                Vec::from(&b"unmasked"[..]),
+                true,
+                false,
+            )));
+        let mut subject = Discriminator::new(Box::new(framer), vec![Box::new(masquerader)]);
+
+        let result = subject.take_chunk();
+
+        assert_eq!(
+            result,
+            Some(UnmaskedChunk::new(Vec::from(&b"unmasked"[..]), true, false))
+        );
+        assert_eq!(subject.stats().unmaskable_frames, 1);
+    }
 }
@@ -1,7 +1,14 @@
 // Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
 use crate::stream_messages::PoolBindMessage;
+use crate::sub_lib::connection_identity::proof_context;
+use crate::sub_lib::connection_identity::IdentityMessage;
+use crate::sub_lib::connection_identity::CHALLENGE_NONCE_LENGTH;
+use crate::sub_lib::cryptde::CryptDE;
+use crate::sub_lib::cryptde::PublicKey;
 use crate::sub_lib::dispatcher::DispatcherSubs;
+use crate::sub_lib::dispatcher::Endpoint;
 use crate::sub_lib::dispatcher::InboundClientData;
+use crate::sub_lib::dispatcher::StreamShutdownMsg;
 use crate::sub_lib::logger::Logger;
 use crate::sub_lib::peer_actors::BindMessage;
 use crate::sub_lib::stream_handler_pool::TransmitDataMsg;
@@ -12,11 +19,23 @@ use actix::Context;
 use actix::Handler;
 use actix::Recipient;
 use actix::Syn;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+
+// What the Dispatcher knows about who is at the other end of a clandestine connection
+#[derive(Clone, Debug, PartialEq)]
+enum ConnectionIdentity {
+    Challenged(Vec<u8>),
+    Bound(PublicKey),
+    Unproven,
+}
 
 pub struct Dispatcher {
     to_proxy_server: Option<Recipient<Syn, InboundClientData>>,
     to_hopper: Option<Recipient<Syn, InboundClientData>>,
     to_stream: Option<Recipient<Syn, TransmitDataMsg>>,
+    cryptde: &'static dyn CryptDE,
+    connection_identities: HashMap<SocketAddr, ConnectionIdentity>,
     logger: Logger,
 }
 
@@ -45,13 +64,35 @@ impl Handler<PoolBindMessage> for Dispatcher {
 impl Handler<InboundClientData> for Dispatcher {
     type Result = ();
 
-    fn handle(&mut self, msg: InboundClientData, _ctx: &mut Self::Context) {
+    fn handle(&mut self, mut msg: InboundClientData, _ctx: &mut Self::Context) {
         if msg.is_clandestine {
+            match IdentityMessage::from_data(&msg.data) {
+                Some(Ok(identity_message)) => {
+                    self.handle_identity_message(msg.peer_addr, identity_message);
+                    return;
+                }
+                Some(Err(e)) => {
+                    self.logger.warning(format!("{} from {}", e, msg.peer_addr));
+                    return;
+                }
+                None => (),
+            }
+            let peer_addr = msg.peer_addr;
+            let last_data = msg.last_data;
+            let identity_opt = self.connection_identities.get(&peer_addr).cloned();
+            if let Some(ConnectionIdentity::Bound(public_key)) = &identity_opt {
+                msg.neighbor_key_opt = Some(public_key.clone());
+            }
             self.to_hopper
                 .as_ref()
                 .expect("Hopper unbound in Dispatcher")
                 .try_send(msg)
                 .expect("Hopper is dead");
+            if last_data {
+                self.connection_identities.remove(&peer_addr);
+            } else if identity_opt.is_none() {
+                self.challenge(peer_addr);
+            }
         } else {
             self.to_proxy_server
                 .as_ref()
@@ -62,6 +103,16 @@ impl Handler<InboundClientData> for Dispatcher {
     }
 }
 
+// A stream can die without the Dispatcher ever seeing its last data, and a new connection from the
+// same address mustn't inherit what was proven on the old one
+impl Handler<StreamShutdownMsg> for Dispatcher {
+    type Result = ();
+
+    fn handle(&mut self, msg: StreamShutdownMsg, _ctx: &mut Self::Context) {
+        self.connection_identities.remove(&msg.peer_addr);
+    }
+}
+
 impl Handler<TransmitDataMsg> for Dispatcher {
     type Result = ();
 
@@ -80,11 +131,13 @@ impl Handler<TransmitDataMsg> for Dispatcher {
 }
 
 impl Dispatcher {
-    pub fn new() -> Dispatcher {
+    pub fn new(cryptde: &'static dyn CryptDE) -> Dispatcher {
         Dispatcher {
             to_proxy_server: None,
             to_stream: None,
             to_hopper: None,
+            cryptde,
+            connection_identities: HashMap::new(),
             logger: Logger::new("Dispatcher"),
         }
     }
@@ -94,17 +147,120 @@ impl Dispatcher {
             ibcd_sub: addr.clone().recipient::<InboundClientData>(),
             bind: addr.clone().recipient::<BindMessage>(),
             from_dispatcher_client: addr.clone().recipient::<TransmitDataMsg>(),
+            stream_shutdown_sub: addr.clone().recipient::<StreamShutdownMsg>(),
+        }
+    }
+
+    fn challenge(&mut self, peer_addr: SocketAddr) {
+        let mut nonce = vec![0u8; CHALLENGE_NONCE_LENGTH];
+        self.cryptde.random(&mut nonce);
+        self.connection_identities
+            .insert(peer_addr, ConnectionIdentity::Challenged(nonce.clone()));
+        self.send_identity_message(
+            peer_addr,
+            IdentityMessage::Challenge {
+                nonce,
+                public_key: self.cryptde.public_key(),
+            },
+        );
+    }
+
+    fn handle_identity_message(&mut self, peer_addr: SocketAddr, message: IdentityMessage) {
+        match message {
+            IdentityMessage::Challenge { nonce, public_key } => {
+                let own_key = self.cryptde.public_key();
+                // Otherwise a Node could get a proof of our own identity by echoing our challenge
+                if public_key == own_key {
+                    self.logger.warning(format!(
+                        "Ignoring identity challenge from {} in this Node's own name",
+                        peer_addr
+                    ));
+                    return;
+                }
+                match self
+                    .cryptde
+                    .sign(&proof_context(&public_key, &own_key, &nonce))
+                {
+                    Ok(signature) => self.send_identity_message(
+                        peer_addr,
+                        IdentityMessage::Proof {
+                            public_key: own_key,
+                            signature,
+                        },
+                    ),
+                    Err(e) => self.logger.error(format!(
+                        "Couldn't sign identity challenge from {}: {:?}",
+                        peer_addr, e
+                    )),
+                }
+            }
+            IdentityMessage::Proof {
+                public_key,
+                signature,
+            } => {
+                let nonce = match self.connection_identities.get(&peer_addr) {
+                    Some(ConnectionIdentity::Challenged(nonce)) => nonce.clone(),
+                    _ => {
+                        self.logger.warning(format!(
+                            "Ignoring unrequested identity proof for {} from {}",
+                            public_key.fingerprint(),
+                            peer_addr
+                        ));
+                        return;
+                    }
+                };
+                let own_key = self.cryptde.public_key();
+                let identity = if public_key != own_key
+                    && self.cryptde.verify_signature(
+                        &proof_context(&own_key, &public_key, &nonce),
+                        &signature,
+                        &public_key,
+                    ) {
+                    self.logger.info(format!(
+                        "Clandestine connection from {} belongs to Node {}",
+                        peer_addr,
+                        public_key.fingerprint()
+                    ));
+                    ConnectionIdentity::Bound(public_key)
+                } else {
+                    self.logger.warning(format!(
+                        "Clandestine connection from {} failed to prove it belongs to Node {}",
+                        peer_addr,
+                        public_key.fingerprint()
+                    ));
+                    ConnectionIdentity::Unproven
+                };
+                self.connection_identities.insert(peer_addr, identity);
+            }
         }
     }
+
+    fn send_identity_message(&self, peer_addr: SocketAddr, message: IdentityMessage) {
+        self.to_stream
+            .as_ref()
+            .expect("StreamHandlerPool unbound in Dispatcher")
+            .try_send(TransmitDataMsg {
+                endpoint: Endpoint::Socket(peer_addr),
+                last_data: false,
+                sequence_number: None,
+                data: message.to_data(),
+            })
+            .expect("StreamHandlerPool is dead");
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::node_test_utils::make_stream_handler_pool_subs_from;
-    use crate::sub_lib::dispatcher::Endpoint;
+    use crate::sub_lib::connection_identity::IDENTITY_MAGIC;
+    use crate::sub_lib::cryptde::CryptData;
+    use crate::test_utils::logging::init_test_logging;
+    use crate::test_utils::logging::TestLogHandler;
+    use crate::test_utils::recorder::make_recorder;
     use crate::test_utils::recorder::peer_actors_builder;
     use crate::test_utils::recorder::Recorder;
+    use crate::test_utils::test_utils::cryptde;
     use actix::msgs;
     use actix::Addr;
     use actix::Arbiter;
@@ -115,7 +271,7 @@ mod tests {
     #[test]
     fn sends_inbound_data_for_proxy_server_to_proxy_server() {
         let system = System::new("test");
-        let subject = Dispatcher::new();
+        let subject = Dispatcher::new(cryptde());
         let subject_addr: Addr<Syn, Dispatcher> = subject.start();
         let subject_ibcd = subject_addr.clone().recipient::<InboundClientData>();
         let proxy_server = Recorder::new();
@@ -131,6 +287,7 @@ mod tests {
             last_data: false,
             is_clandestine: false,
             data: data.clone(),
+            neighbor_key_opt: None,
         };
         let mut peer_actors = peer_actors_builder().proxy_server(proxy_server).build();
         peer_actors.dispatcher = Dispatcher::make_subs_from(&subject_addr);
@@ -159,7 +316,7 @@ mod tests {
     #[test]
     fn sends_inbound_data_for_hopper_to_hopper() {
         let system = System::new("test");
-        let subject = Dispatcher::new();
+        let subject = Dispatcher::new(cryptde());
         let subject_addr: Addr<Syn, Dispatcher> = subject.start();
         let subject_ibcd = subject_addr.clone().recipient::<InboundClientData>();
         let hopper = Recorder::new();
@@ -175,9 +332,17 @@ mod tests {
             is_clandestine: true,
             sequence_number: None,
             data: data.clone(),
+            neighbor_key_opt: None,
         };
         let mut peer_actors = peer_actors_builder().hopper(hopper).build();
         peer_actors.dispatcher = Dispatcher::make_subs_from(&subject_addr);
+        subject_addr
+            .try_send(PoolBindMessage {
+                dispatcher_subs: peer_actors.dispatcher.clone(),
+                stream_handler_pool_subs: make_stream_handler_pool_subs_from(None),
+                neighborhood_subs: peer_actors.neighborhood.clone(),
+            })
+            .unwrap();
         subject_addr.try_send(BindMessage { peer_actors }).unwrap();
 
         subject_ibcd.try_send(ibcd_in).unwrap();
@@ -204,7 +369,7 @@ mod tests {
     #[should_panic(expected = "ProxyServer unbound in Dispatcher")]
     fn inbound_client_data_handler_panics_when_proxy_server_is_unbound() {
         let system = System::new("test");
-        let subject = Dispatcher::new();
+        let subject = Dispatcher::new(cryptde());
         let subject_addr: Addr<Syn, Dispatcher> = subject.start();
         let subject_ibcd = subject_addr.recipient::<InboundClientData>();
         let peer_addr = SocketAddr::from_str("1.2.3.4:8765").unwrap();
@@ -217,6 +382,7 @@ mod tests {
             is_clandestine: false,
             sequence_number: Some(0),
             data: data.clone(),
+            neighbor_key_opt: None,
         };
 
         subject_ibcd.try_send(ibcd_in).unwrap();
@@ -229,7 +395,7 @@ mod tests {
     #[should_panic(expected = "Hopper unbound in Dispatcher")]
     fn inbound_client_data_handler_panics_when_hopper_is_unbound() {
         let system = System::new("test");
-        let subject = Dispatcher::new();
+        let subject = Dispatcher::new(cryptde());
         let subject_addr: Addr<Syn, Dispatcher> = subject.start();
         let subject_ibcd = subject_addr.recipient::<InboundClientData>();
         let peer_addr = SocketAddr::from_str("1.2.3.4:8765").unwrap();
@@ -242,6 +408,7 @@ mod tests {
             is_clandestine: true,
             sequence_number: None,
             data: data.clone(),
+            neighbor_key_opt: None,
         };
 
         subject_ibcd.try_send(ibcd_in).unwrap();
@@ -254,7 +421,7 @@ mod tests {
     #[should_panic(expected = "StreamHandlerPool unbound in Dispatcher")]
     fn panics_when_stream_handler_pool_is_unbound() {
         let system = System::new("test");
-        let subject = Dispatcher::new();
+        let subject = Dispatcher::new(cryptde());
         let subject_addr: Addr<Syn, Dispatcher> = subject.start();
         let subject_obcd = subject_addr.recipient::<TransmitDataMsg>();
         let socket_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
@@ -275,7 +442,7 @@ mod tests {
     #[test]
     fn forwards_outbound_data_to_stream_handler_pool() {
         let system = System::new("test");
-        let subject = Dispatcher::new();
+        let subject = Dispatcher::new(cryptde());
         let subject_addr: Addr<Syn, Dispatcher> = subject.start();
         let subject_obcd = subject_addr.clone().recipient::<TransmitDataMsg>();
         let stream_handler_pool = Recorder::new();
@@ -321,4 +488,336 @@ mod tests {
         assert_eq!(actual_data, data);
         assert_eq!(recording.len(), 1);
     }
+
+    fn clandestine_data(
+        peer_addr: SocketAddr,
+        last_data: bool,
+        data: Vec<u8>,
+    ) -> InboundClientData {
+        InboundClientData {
+            peer_addr,
+            reception_port: Some(1234),
+            last_data,
+            is_clandestine: true,
+            sequence_number: None,
+            data,
+            neighbor_key_opt: None,
+        }
+    }
+
+    fn identity_message_to(peer_addr: SocketAddr, message: IdentityMessage) -> TransmitDataMsg {
+        TransmitDataMsg {
+            endpoint: Endpoint::Socket(peer_addr),
+            last_data: false,
+            sequence_number: None,
+            data: message.to_data(),
+        }
+    }
+
+    fn start_bound_subject(
+        hopper: Recorder,
+        stream_handler_pool: Recorder,
+    ) -> Addr<Syn, Dispatcher> {
+        let subject = Dispatcher::new(cryptde());
+        let subject_addr: Addr<Syn, Dispatcher> = subject.start();
+        let mut peer_actors = peer_actors_builder().hopper(hopper).build();
+        peer_actors.dispatcher = Dispatcher::make_subs_from(&subject_addr);
+        subject_addr
+            .try_send(PoolBindMessage {
+                dispatcher_subs: peer_actors.dispatcher.clone(),
+                stream_handler_pool_subs: make_stream_handler_pool_subs_from(Some(
+                    stream_handler_pool,
+                )),
+                neighborhood_subs: peer_actors.neighborhood.clone(),
+            })
+            .unwrap();
+        subject_addr.try_send(BindMessage { peer_actors }).unwrap();
+        subject_addr
+    }
+
+    #[test]
+    fn first_clandestine_data_from_a_connection_is_forwarded_and_the_neighbor_is_challenged() {
+        let system = System::new(
+            "first_clandestine_data_from_a_connection_is_forwarded_and_the_neighbor_is_challenged",
+        );
+        let (hopper, _, hopper_recording_arc) = make_recorder();
+        let (stream_handler_pool, _, pool_recording_arc) = make_recorder();
+        let peer_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
+        let subject = start_bound_subject(hopper, stream_handler_pool);
+
+        subject
+            .try_send(clandestine_data(peer_addr, false, vec![1, 2, 3]))
+            .unwrap();
+        subject
+            .try_send(clandestine_data(peer_addr, false, vec![4, 5, 6]))
+            .unwrap();
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+        let hopper_recording = hopper_recording_arc.lock().unwrap();
+        assert_eq!(
+            hopper_recording.get_record::<InboundClientData>(0),
+            &clandestine_data(peer_addr, false, vec![1, 2, 3])
+        );
+        assert_eq!(
+            hopper_recording.get_record::<InboundClientData>(1),
+            &clandestine_data(peer_addr, false, vec![4, 5, 6])
+        );
+        let pool_recording = pool_recording_arc.lock().unwrap();
+        assert_eq!(
+            pool_recording.get_record::<TransmitDataMsg>(0),
+            &identity_message_to(
+                peer_addr,
+                IdentityMessage::Challenge {
+                    nonce: vec![b'4'; CHALLENGE_NONCE_LENGTH],
+                    public_key: cryptde().public_key(),
+                }
+            )
+        );
+        assert_eq!(pool_recording.len(), 1);
+    }
+
+    #[test]
+    fn a_challenge_is_answered_with_a_proof_and_not_forwarded() {
+        let system = System::new("a_challenge_is_answered_with_a_proof_and_not_forwarded");
+        let (hopper, _, hopper_recording_arc) = make_recorder();
+        let (stream_handler_pool, _, pool_recording_arc) = make_recorder();
+        let peer_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
+        let subject = start_bound_subject(hopper, stream_handler_pool);
+        let challenger_key = PublicKey::new(&b"challenger"[..]);
+        let challenge = IdentityMessage::Challenge {
+            nonce: vec![1, 2, 3, 4],
+            public_key: challenger_key.clone(),
+        };
+
+        subject
+            .try_send(clandestine_data(peer_addr, false, challenge.to_data()))
+            .unwrap();
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+        let hopper_recording = hopper_recording_arc.lock().unwrap();
+        assert_eq!(hopper_recording.len(), 0);
+        let pool_recording = pool_recording_arc.lock().unwrap();
+        assert_eq!(
+            pool_recording.get_record::<TransmitDataMsg>(0),
+            &identity_message_to(
+                peer_addr,
+                IdentityMessage::Proof {
+                    public_key: cryptde().public_key(),
+                    signature: cryptde()
+                        .sign(&proof_context(
+                            &challenger_key,
+                            &cryptde().public_key(),
+                            &[1, 2, 3, 4]
+                        ))
+                        .unwrap(),
+                }
+            )
+        );
+        assert_eq!(pool_recording.len(), 1);
+    }
+
+    #[test]
+    fn data_from_a_connection_that_has_proven_its_identity_carries_the_neighbor_key() {
+        init_test_logging();
+        let system = System::new(
+            "data_from_a_connection_that_has_proven_its_identity_carries_the_neighbor_key",
+        );
+        let (hopper, _, hopper_recording_arc) = make_recorder();
+        let (stream_handler_pool, _, pool_recording_arc) = make_recorder();
+        let peer_addr = SocketAddr::from_str("2.3.4.5:6789").unwrap();
+        let neighbor_key = PublicKey::new(&b"neighbor"[..]);
+        let subject = start_bound_subject(hopper, stream_handler_pool);
+        let proof = IdentityMessage::Proof {
+            public_key: neighbor_key.clone(),
+            signature: cryptde()
+                .sign(&proof_context(
+                    &cryptde().public_key(),
+                    &neighbor_key,
+                    &[b'4'; CHALLENGE_NONCE_LENGTH],
+                ))
+                .unwrap(),
+        };
+
+        subject
+            .try_send(clandestine_data(peer_addr, false, vec![1, 2, 3]))
+            .unwrap();
+        subject
+            .try_send(clandestine_data(peer_addr, false, proof.to_data()))
+            .unwrap();
+        subject
+            .try_send(clandestine_data(peer_addr, true, vec![4, 5, 6]))
+            .unwrap();
+        subject
+            .try_send(clandestine_data(peer_addr, false, vec![7, 8, 9]))
+            .unwrap();
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+        let hopper_recording = hopper_recording_arc.lock().unwrap();
+        assert_eq!(
+            hopper_recording
+                .get_record::<InboundClientData>(0)
+                .neighbor_key_opt,
+            None
+        );
+        assert_eq!(
+            hopper_recording
+                .get_record::<InboundClientData>(1)
+                .neighbor_key_opt,
+            Some(neighbor_key.clone())
+        );
+        assert_eq!(
+            hopper_recording
+                .get_record::<InboundClientData>(2)
+                .neighbor_key_opt,
+            None
+        );
+        assert_eq!(hopper_recording.len(), 3);
+        let pool_recording = pool_recording_arc.lock().unwrap();
+        assert_eq!(pool_recording.len(), 2); // one challenge per connection
+        TestLogHandler::new().exists_log_containing(&format!(
+            "INFO: Dispatcher: Clandestine connection from 2.3.4.5:6789 belongs to Node {}",
+            neighbor_key.fingerprint()
+        ));
+    }
+
+    #[test]
+    fn unrequested_and_malformed_identity_messages_are_ignored() {
+        init_test_logging();
+        let system = System::new("unrequested_and_malformed_identity_messages_are_ignored");
+        let (hopper, _, hopper_recording_arc) = make_recorder();
+        let (stream_handler_pool, _, pool_recording_arc) = make_recorder();
+        let peer_addr = SocketAddr::from_str("3.4.5.6:7890").unwrap();
+        let neighbor_key = PublicKey::new(&b"impostor"[..]);
+        let subject = start_bound_subject(hopper, stream_handler_pool);
+        let proof = IdentityMessage::Proof {
+            public_key: neighbor_key.clone(),
+            signature: CryptData::new(&b"signed"[..]),
+        };
+        let mut malformed = IDENTITY_MAGIC.to_vec();
+        malformed.extend(&[0xFF, 0xFF]);
+
+        subject
+            .try_send(clandestine_data(peer_addr, false, proof.to_data()))
+            .unwrap();
+        subject
+            .try_send(clandestine_data(peer_addr, false, malformed))
+            .unwrap();
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+        let hopper_recording = hopper_recording_arc.lock().unwrap();
+        assert_eq!(hopper_recording.len(), 0);
+        let pool_recording = pool_recording_arc.lock().unwrap();
+        assert_eq!(pool_recording.len(), 0);
+        let tlh = TestLogHandler::new();
+        tlh.exists_log_containing(&format!(
+            "WARN: Dispatcher: Ignoring unrequested identity proof for {} from 3.4.5.6:7890",
+            neighbor_key.fingerprint()
+        ));
+        tlh.exists_log_containing("WARN: Dispatcher: Malformed identity message: ");
+    }
+
+    #[test]
+    fn a_challenge_in_our_own_name_is_not_answered() {
+        init_test_logging();
+        let system = System::new("a_challenge_in_our_own_name_is_not_answered");
+        let (hopper, _, hopper_recording_arc) = make_recorder();
+        let (stream_handler_pool, _, pool_recording_arc) = make_recorder();
+        let peer_addr = SocketAddr::from_str("4.5.6.7:8901").unwrap();
+        let subject = start_bound_subject(hopper, stream_handler_pool);
+        let challenge = IdentityMessage::Challenge {
+            nonce: vec![1, 2, 3, 4],
+            public_key: cryptde().public_key(),
+        };
+
+        subject
+            .try_send(clandestine_data(peer_addr, false, challenge.to_data()))
+            .unwrap();
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+        assert_eq!(hopper_recording_arc.lock().unwrap().len(), 0);
+        assert_eq!(pool_recording_arc.lock().unwrap().len(), 0);
+        TestLogHandler::new().exists_log_containing(
+            "WARN: Dispatcher: Ignoring identity challenge from 4.5.6.7:8901 in this Node's own name",
+        );
+    }
+
+    #[test]
+    fn a_proof_in_our_own_name_does_not_bind_the_connection() {
+        init_test_logging();
+        let system = System::new("a_proof_in_our_own_name_does_not_bind_the_connection");
+        let (hopper, _, hopper_recording_arc) = make_recorder();
+        let (stream_handler_pool, _, _) = make_recorder();
+        let peer_addr = SocketAddr::from_str("5.6.7.8:9012").unwrap();
+        let subject = start_bound_subject(hopper, stream_handler_pool);
+        let proof = IdentityMessage::Proof {
+            public_key: cryptde().public_key(),
+            signature: CryptData::new(&b"signed"[..]),
+        };
+
+        subject
+            .try_send(clandestine_data(peer_addr, false, vec![1, 2, 3]))
+            .unwrap();
+        subject
+            .try_send(clandestine_data(peer_addr, false, proof.to_data()))
+            .unwrap();
+        subject
+            .try_send(clandestine_data(peer_addr, false, vec![4, 5, 6]))
+            .unwrap();
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+        let hopper_recording = hopper_recording_arc.lock().unwrap();
+        assert_eq!(
+            hopper_recording
+                .get_record::<InboundClientData>(1)
+                .neighbor_key_opt,
+            None
+        );
+        TestLogHandler::new().exists_log_containing(&format!(
+            "WARN: Dispatcher: Clandestine connection from 5.6.7.8:9012 failed to prove it belongs to Node {}",
+            cryptde().public_key().fingerprint()
+        ));
+    }
+
+    #[test]
+    fn a_connection_identity_is_forgotten_when_its_stream_shuts_down() {
+        let system = System::new("a_connection_identity_is_forgotten_when_its_stream_shuts_down");
+        let (hopper, _, hopper_recording_arc) = make_recorder();
+        let (stream_handler_pool, _, pool_recording_arc) = make_recorder();
+        let peer_addr = SocketAddr::from_str("6.7.8.9:1234").unwrap();
+        let neighbor_key = PublicKey::new(&b"neighbor"[..]);
+        let subject = start_bound_subject(hopper, stream_handler_pool);
+        let proof = IdentityMessage::Proof {
+            public_key: neighbor_key.clone(),
+            signature: CryptData::new(&b"signed"[..]),
+        };
+
+        subject
+            .try_send(clandestine_data(peer_addr, false, vec![1, 2, 3]))
+            .unwrap();
+        subject
+            .try_send(clandestine_data(peer_addr, false, proof.to_data()))
+            .unwrap();
+        subject.try_send(StreamShutdownMsg { peer_addr }).unwrap();
+        subject
+            .try_send(clandestine_data(peer_addr, false, vec![4, 5, 6]))
+            .unwrap();
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+        let hopper_recording = hopper_recording_arc.lock().unwrap();
+        assert_eq!(
+            hopper_recording
+                .get_record::<InboundClientData>(1)
+                .neighbor_key_opt,
+            None
+        );
+        let pool_recording = pool_recording_arc.lock().unwrap();
+        assert_eq!(pool_recording.len(), 2); // the new connection is challenged afresh
+    }
 }
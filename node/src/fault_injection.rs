@@ -0,0 +1,304 @@
+// Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use crate::sub_lib::cryptde::PublicKey;
+use crate::sub_lib::logger::Logger;
+use lazy_static::lazy_static;
+use std::io;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Write;
+use std::net::IpAddr;
+use std::net::Ipv4Addr;
+use std::net::SocketAddr;
+use std::net::TcpListener;
+use std::net::TcpStream;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+// Hooks that let a multinode test make an otherwise real Node misbehave in exactly the way it
+// wants, without a separate malicious Node binary, and ask it what it knows. None of this is
+// compiled in without the fault_injection feature, and the control socket only ever listens on
+// 127.0.0.1. It takes one command per line and answers each with "OK" or "ERROR: <reason>":
+//
+//     drop <n>          the Hopper throws away the next n CORES packages it receives
+//     corrupt <n>       the Hopper scrambles the payloads of the next n CORES packages it routes
+//     delay <millis>    the Hopper waits this long before handling each CORES package (0 to stop)
+//     clear             back to normal
+//     known_nodes       answers "OK <key>,<key>..." with the public key of every Node in the
+//                       Neighborhood's database, this one included, sorted
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Faults {
+    pub drops_pending: usize,
+    pub corruptions_pending: usize,
+    pub hopper_delay: Option<Duration>,
+}
+
+lazy_static! {
+    static ref GLOBAL_FAULT_INJECTOR: FaultInjector = FaultInjector::new();
+}
+
+#[derive(Clone)]
+pub struct FaultInjector {
+    faults: Arc<Mutex<Faults>>,
+    known_nodes: Arc<Mutex<Vec<PublicKey>>>,
+}
+
+impl FaultInjector {
+    pub fn new() -> FaultInjector {
+        FaultInjector {
+            faults: Arc::new(Mutex::new(Faults::default())),
+            known_nodes: Arc::new(Mutex::new(vec![])),
+        }
+    }
+
+    // The one the control socket and the Hopper share
+    pub fn global() -> FaultInjector {
+        GLOBAL_FAULT_INJECTOR.clone()
+    }
+
+    pub fn faults(&self) -> Faults {
+        self.faults
+            .lock()
+            .expect("Fault injector is poisoned")
+            .clone()
+    }
+
+    pub fn apply_command(&self, command: &str) -> Result<(), String> {
+        let words: Vec<&str> = command.split_whitespace().collect();
+        let mut faults = self.faults.lock().expect("Fault injector is poisoned");
+        match words.as_slice() {
+            ["drop", count] => faults.drops_pending = FaultInjector::parse_number(count)? as usize,
+            ["corrupt", count] => {
+                faults.corruptions_pending = FaultInjector::parse_number(count)? as usize
+            }
+            ["delay", millis] => {
+                faults.hopper_delay = match FaultInjector::parse_number(millis)? {
+                    0 => None,
+                    millis => Some(Duration::from_millis(millis)),
+                }
+            }
+            ["clear"] => *faults = Faults::default(),
+            _ => {
+                return Err(format!(
+                    "Unrecognized fault injection command: '{}'",
+                    command
+                ))
+            }
+        }
+        Ok(())
+    }
+
+    // The Neighborhood calls this whenever its database may have changed
+    pub fn report_known_nodes(&self, mut keys: Vec<PublicKey>) {
+        keys.sort_by(|a, b| a.as_slice().cmp(b.as_slice()));
+        *self.known_nodes.lock().expect("Fault injector is poisoned") = keys;
+    }
+
+    pub fn known_nodes(&self) -> Vec<PublicKey> {
+        self.known_nodes
+            .lock()
+            .expect("Fault injector is poisoned")
+            .clone()
+    }
+
+    pub fn take_drop(&self) -> bool {
+        let mut faults = self.faults.lock().expect("Fault injector is poisoned");
+        FaultInjector::take(&mut faults.drops_pending)
+    }
+
+    pub fn take_corruption(&self) -> bool {
+        let mut faults = self.faults.lock().expect("Fault injector is poisoned");
+        FaultInjector::take(&mut faults.corruptions_pending)
+    }
+
+    pub fn hopper_delay(&self) -> Option<Duration> {
+        self.faults().hopper_delay
+    }
+
+    // Every byte is inverted, so the result is the same length but won't decrypt or deserialize
+    pub fn corrupt(data: &[u8]) -> Vec<u8> {
+        data.iter().map(|byte| !byte).collect()
+    }
+
+    pub fn listen(&self, port: u16) -> io::Result<SocketAddr> {
+        let listener = TcpListener::bind(SocketAddr::new(
+            IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+            port,
+        ))?;
+        let local_addr = listener.local_addr()?;
+        let injector = self.clone();
+        thread::spawn(move || {
+            let logger = Logger::new("FaultInjector");
+            listener
+                .incoming()
+                .for_each(|stream_result| match stream_result {
+                    Ok(stream) => injector.serve(stream, &logger),
+                    Err(e) => logger.error(format!("Couldn't accept control connection: {}", e)),
+                })
+        });
+        Ok(local_addr)
+    }
+
+    fn serve(&self, stream: TcpStream, logger: &Logger) {
+        let mut writer = match stream.try_clone() {
+            Ok(writer) => writer,
+            Err(e) => {
+                logger.error(format!("Couldn't answer control connection: {}", e));
+                return;
+            }
+        };
+        for line in BufReader::new(stream).lines() {
+            let command = match line {
+                Ok(command) => command,
+                Err(_) => return,
+            };
+            let response = if command.trim() == "known_nodes" {
+                format!(
+                    "OK {}",
+                    self.known_nodes()
+                        .iter()
+                        .map(|key| format!("{}", key))
+                        .collect::<Vec<String>>()
+                        .join(",")
+                )
+            } else {
+                match self.apply_command(&command) {
+                    Ok(()) => {
+                        logger.warning(format!("Injecting fault: {}", command));
+                        String::from("OK")
+                    }
+                    Err(msg) => format!("ERROR: {}", msg),
+                }
+            };
+            if writeln!(writer, "{}", response).is_err() {
+                return;
+            }
+        }
+    }
+
+    fn parse_number(text: &str) -> Result<u64, String> {
+        text.parse::<u64>()
+            .map_err(|_| format!("'{}' is not a non-negative number", text))
+    }
+
+    fn take(pending: &mut usize) -> bool {
+        if *pending == 0 {
+            false
+        } else {
+            *pending -= 1;
+            true
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn drops_are_taken_until_there_are_none_left() {
+        let subject = FaultInjector::new();
+
+        subject.apply_command("drop 2").unwrap();
+
+        assert_eq!(subject.take_drop(), true);
+        assert_eq!(subject.take_drop(), true);
+        assert_eq!(subject.take_drop(), false);
+        assert_eq!(subject.take_corruption(), false);
+    }
+
+    #[test]
+    fn corruptions_are_taken_until_there_are_none_left() {
+        let subject = FaultInjector::new();
+
+        subject.apply_command("corrupt 1").unwrap();
+
+        assert_eq!(subject.take_corruption(), true);
+        assert_eq!(subject.take_corruption(), false);
+        assert_eq!(subject.take_drop(), false);
+    }
+
+    #[test]
+    fn delay_is_set_and_turned_off() {
+        let subject = FaultInjector::new();
+
+        subject.apply_command("delay 250").unwrap();
+        assert_eq!(subject.hopper_delay(), Some(Duration::from_millis(250)));
+
+        subject.apply_command("delay 0").unwrap();
+        assert_eq!(subject.hopper_delay(), None);
+    }
+
+    #[test]
+    fn clear_removes_every_fault() {
+        let subject = FaultInjector::new();
+        subject.apply_command("drop 3").unwrap();
+        subject.apply_command("corrupt 4").unwrap();
+        subject.apply_command("delay 500").unwrap();
+
+        subject.apply_command("clear").unwrap();
+
+        assert_eq!(subject.faults(), Faults::default());
+    }
+
+    #[test]
+    fn bad_commands_are_rejected_and_change_nothing() {
+        let subject = FaultInjector::new();
+
+        assert_eq!(
+            subject.apply_command("explode"),
+            Err(String::from(
+                "Unrecognized fault injection command: 'explode'"
+            ))
+        );
+        assert_eq!(
+            subject.apply_command("drop -1"),
+            Err(String::from("'-1' is not a non-negative number"))
+        );
+        assert_eq!(subject.faults(), Faults::default());
+    }
+
+    #[test]
+    fn corrupt_changes_every_byte_but_not_the_length() {
+        let result = FaultInjector::corrupt(&[0x00, 0x0F, 0xFF]);
+
+        assert_eq!(result, vec![0xFF, 0xF0, 0x00]);
+    }
+
+    #[test]
+    fn control_socket_applies_commands_and_answers_them() {
+        let subject = FaultInjector::new();
+        let local_addr = subject.listen(0).unwrap();
+        let mut stream = TcpStream::connect(local_addr).unwrap();
+
+        stream.write_all(b"drop 5\nbooga\n").unwrap();
+        stream.shutdown(std::net::Shutdown::Write).unwrap();
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert_eq!(
+            response,
+            String::from("OK\nERROR: Unrecognized fault injection command: 'booga'\n")
+        );
+        assert_eq!(subject.faults().drops_pending, 5);
+        assert_eq!(local_addr.ip(), IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)));
+    }
+
+    #[test]
+    fn control_socket_answers_with_the_known_nodes_sorted() {
+        let subject = FaultInjector::new();
+        subject.report_known_nodes(vec![PublicKey::new(b"BBB"), PublicKey::new(b"AAA")]);
+        let local_addr = subject.listen(0).unwrap();
+        let mut stream = TcpStream::connect(local_addr).unwrap();
+
+        stream.write_all(b"known_nodes\n").unwrap();
+        stream.shutdown(std::net::Shutdown::Write).unwrap();
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert_eq!(response, String::from("OK QUFB,QkJC\n"));
+        assert_eq!(subject.faults(), Faults::default());
+    }
+}
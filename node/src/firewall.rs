@@ -0,0 +1,445 @@
+// Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use crate::sub_lib::logger::Logger;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
+
+// Written in the data directory, so that rules a Node couldn't take down when it stopped (because
+// it had already dropped privileges, or because it was killed) get taken down the next time it
+// starts with --manage_firewall.
+pub const FIREWALL_RULES_FILE: &str = "firewall_rules";
+
+const RULE_NAME_PREFIX: &str = "SubstratumNode clandestine port";
+
+pub trait CommandRunner: Send {
+    fn run(&self, program: &str, args: &[String]) -> Result<String, String>;
+}
+
+pub struct CommandRunnerReal {}
+
+impl CommandRunner for CommandRunnerReal {
+    fn run(&self, program: &str, args: &[String]) -> Result<String, String> {
+        match Command::new(program).args(args).output() {
+            Ok(ref output) if output.status.success() => {
+                Ok(String::from_utf8_lossy(&output.stdout).to_string())
+            }
+            Ok(output) => Err(format!(
+                "{} {} failed: {}",
+                program,
+                args.join(" "),
+                String::from_utf8_lossy(&output.stderr).trim()
+            )),
+            Err(e) => Err(format!("Couldn't run {}: {}", program, e)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FirewallKind {
+    WindowsFirewall,
+    Ufw,
+    Firewalld,
+}
+
+impl FirewallKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            FirewallKind::WindowsFirewall => "windows",
+            FirewallKind::Ufw => "ufw",
+            FirewallKind::Firewalld => "firewalld",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<FirewallKind> {
+        match name {
+            "windows" => Some(FirewallKind::WindowsFirewall),
+            "ufw" => Some(FirewallKind::Ufw),
+            "firewalld" => Some(FirewallKind::Firewalld),
+            _ => None,
+        }
+    }
+
+    // firewalld rules are made at runtime only, so a reboot takes them down too
+    fn open_command(self, port: u16) -> (&'static str, Vec<String>) {
+        match self {
+            FirewallKind::WindowsFirewall => (
+                "netsh",
+                FirewallKind::netsh_args("add", port, &["dir=in", "action=allow", "protocol=TCP"]),
+            ),
+            FirewallKind::Ufw => ("ufw", vec![String::from("allow"), format!("{}/tcp", port)]),
+            FirewallKind::Firewalld => ("firewall-cmd", vec![format!("--add-port={}/tcp", port)]),
+        }
+    }
+
+    fn close_command(self, port: u16) -> (&'static str, Vec<String>) {
+        match self {
+            FirewallKind::WindowsFirewall => (
+                "netsh",
+                FirewallKind::netsh_args("delete", port, &["protocol=TCP"]),
+            ),
+            FirewallKind::Ufw => (
+                "ufw",
+                vec![
+                    String::from("delete"),
+                    String::from("allow"),
+                    format!("{}/tcp", port),
+                ],
+            ),
+            FirewallKind::Firewalld => {
+                ("firewall-cmd", vec![format!("--remove-port={}/tcp", port)])
+            }
+        }
+    }
+
+    fn netsh_args(verb: &str, port: u16, extra: &[&str]) -> Vec<String> {
+        let mut args: Vec<String> = vec!["advfirewall", "firewall", verb, "rule"]
+            .into_iter()
+            .map(String::from)
+            .collect();
+        args.push(format!("name={} {}", RULE_NAME_PREFIX, port));
+        args.extend(extra.iter().map(|arg| String::from(*arg)));
+        args.push(format!("localport={}", port));
+        args
+    }
+}
+
+pub struct FirewallManager {
+    runner: Box<dyn CommandRunner>,
+    data_directory: PathBuf,
+    logger: Logger,
+}
+
+impl FirewallManager {
+    pub fn new(data_directory: &Path) -> FirewallManager {
+        FirewallManager {
+            runner: Box::new(CommandRunnerReal {}),
+            data_directory: data_directory.to_path_buf(),
+            logger: Logger::new("Firewall"),
+        }
+    }
+
+    pub fn detect(&self) -> Result<FirewallKind, String> {
+        if cfg!(windows) {
+            return Ok(FirewallKind::WindowsFirewall);
+        }
+        if let Ok(status) = self.runner.run("ufw", &[String::from("status")]) {
+            if status.contains("Status: active") {
+                return Ok(FirewallKind::Ufw);
+            }
+        }
+        if let Ok(state) = self.runner.run("firewall-cmd", &[String::from("--state")]) {
+            if state.trim() == "running" {
+                return Ok(FirewallKind::Firewalld);
+            }
+        }
+        Err(String::from(
+            "--manage_firewall can't find a firewall it knows how to manage (Windows Firewall, an active ufw, or a running firewalld)",
+        ))
+    }
+
+    // Opens a rule for each port, after taking down whatever the last run left behind; only the
+    // rules that actually got made are recorded
+    pub fn open(&self, kind: FirewallKind, ports: &[u16]) -> Result<(), String> {
+        self.close_recorded();
+        let mut opened = vec![];
+        let mut failures = vec![];
+        ports.iter().for_each(|port| {
+            let (program, args) = kind.open_command(*port);
+            match self.runner.run(program, &args) {
+                Ok(_) => opened.push(*port),
+                Err(e) => failures.push(e),
+            }
+        });
+        self.record(kind, &opened)?;
+        if !opened.is_empty() {
+            self.logger.info(format!(
+                "Opened {} firewall for clandestine ports {:?}",
+                kind.name(),
+                opened
+            ));
+        }
+        if failures.is_empty() {
+            Ok(())
+        } else {
+            Err(failures.join("; "))
+        }
+    }
+
+    pub fn close_recorded(&self) {
+        let (kind, ports) = match self.read_record() {
+            Some(record) => record,
+            None => return,
+        };
+        let remaining: Vec<u16> = ports
+            .into_iter()
+            .filter(|port| {
+                let (program, args) = kind.close_command(*port);
+                match self.runner.run(program, &args) {
+                    Ok(_) => false,
+                    Err(e) => {
+                        self.logger.warning(format!(
+                            "Couldn't close {} firewall for port {}: {}. It will be closed the next time the Node is started with --manage_firewall on",
+                            kind.name(),
+                            port,
+                            e
+                        ));
+                        true
+                    }
+                }
+            })
+            .collect();
+        if let Err(e) = self.record(kind, &remaining) {
+            self.logger.error(e)
+        }
+    }
+
+    fn record_path(&self) -> PathBuf {
+        self.data_directory.join(FIREWALL_RULES_FILE)
+    }
+
+    fn record(&self, kind: FirewallKind, ports: &[u16]) -> Result<(), String> {
+        let path = self.record_path();
+        if ports.is_empty() {
+            return match fs::remove_file(&path) {
+                Ok(_) => Ok(()),
+                Err(_) if !path.exists() => Ok(()),
+                Err(e) => Err(format!("Couldn't remove {:?}: {}", path, e)),
+            };
+        }
+        let port_strings: Vec<String> = ports.iter().map(|port| format!("{}", port)).collect();
+        fs::create_dir_all(&self.data_directory)
+            .and_then(|_| {
+                fs::write(
+                    &path,
+                    format!("{} {}\n", kind.name(), port_strings.join(",")),
+                )
+            })
+            .map_err(|e| format!("Couldn't write {:?}: {}", path, e))
+    }
+
+    fn read_record(&self) -> Option<(FirewallKind, Vec<u16>)> {
+        let contents = fs::read_to_string(self.record_path()).ok()?;
+        let mut words = contents.split_whitespace();
+        let kind = FirewallKind::from_name(words.next()?)?;
+        let ports = words
+            .next()?
+            .split(',')
+            .filter_map(|port| port.parse::<u16>().ok())
+            .collect();
+        Some((kind, ports))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::logging::init_test_logging;
+    use crate::test_utils::logging::TestLogHandler;
+    use std::cell::RefCell;
+    use std::sync::Arc;
+    use std::sync::Mutex;
+
+    const BASE_TEST_DIR: &str = "generated/test/firewall";
+
+    struct CommandRunnerMock {
+        run_parameters: Arc<Mutex<Vec<String>>>,
+        run_results: RefCell<Vec<Result<String, String>>>,
+    }
+
+    impl CommandRunner for CommandRunnerMock {
+        fn run(&self, program: &str, args: &[String]) -> Result<String, String> {
+            self.run_parameters
+                .lock()
+                .unwrap()
+                .push(format!("{} {}", program, args.join(" ")));
+            self.run_results.borrow_mut().remove(0)
+        }
+    }
+
+    impl CommandRunnerMock {
+        fn new() -> CommandRunnerMock {
+            CommandRunnerMock {
+                run_parameters: Arc::new(Mutex::new(vec![])),
+                run_results: RefCell::new(vec![]),
+            }
+        }
+
+        fn run_parameters(mut self, parameters: &Arc<Mutex<Vec<String>>>) -> Self {
+            self.run_parameters = parameters.clone();
+            self
+        }
+
+        fn run_result(self, result: Result<String, String>) -> Self {
+            self.run_results.borrow_mut().push(result);
+            self
+        }
+    }
+
+    fn make_subject(name: &str, runner: CommandRunnerMock) -> FirewallManager {
+        let data_directory = PathBuf::from(format!("{}/{}", BASE_TEST_DIR, name));
+        fs::remove_dir_all(&data_directory).is_ok();
+        fs::create_dir_all(&data_directory).unwrap();
+        let mut subject = FirewallManager::new(&data_directory);
+        subject.runner = Box::new(runner);
+        subject
+    }
+
+    #[test]
+    fn commands_are_right_for_each_kind_of_firewall() {
+        let render =
+            |(program, args): (&str, Vec<String>)| format!("{} {}", program, args.join(" "));
+
+        assert_eq!(
+            render(FirewallKind::WindowsFirewall.open_command(1234)),
+            "netsh advfirewall firewall add rule name=SubstratumNode clandestine port 1234 dir=in action=allow protocol=TCP localport=1234"
+        );
+        assert_eq!(
+            render(FirewallKind::WindowsFirewall.close_command(1234)),
+            "netsh advfirewall firewall delete rule name=SubstratumNode clandestine port 1234 protocol=TCP localport=1234"
+        );
+        assert_eq!(
+            render(FirewallKind::Ufw.open_command(1234)),
+            "ufw allow 1234/tcp"
+        );
+        assert_eq!(
+            render(FirewallKind::Ufw.close_command(1234)),
+            "ufw delete allow 1234/tcp"
+        );
+        assert_eq!(
+            render(FirewallKind::Firewalld.open_command(1234)),
+            "firewall-cmd --add-port=1234/tcp"
+        );
+        assert_eq!(
+            render(FirewallKind::Firewalld.close_command(1234)),
+            "firewall-cmd --remove-port=1234/tcp"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn detect_prefers_active_ufw_and_falls_back_to_firewalld() {
+        let ufw = make_subject(
+            "detect_prefers_active_ufw",
+            CommandRunnerMock::new().run_result(Ok(String::from("Status: active\n"))),
+        );
+        let firewalld = make_subject(
+            "detect_falls_back_to_firewalld",
+            CommandRunnerMock::new()
+                .run_result(Ok(String::from("Status: inactive\n")))
+                .run_result(Ok(String::from("running\n"))),
+        );
+        let neither = make_subject(
+            "detect_finds_neither",
+            CommandRunnerMock::new()
+                .run_result(Err(String::from("Couldn't run ufw")))
+                .run_result(Err(String::from("Couldn't run firewall-cmd"))),
+        );
+
+        assert_eq!(ufw.detect(), Ok(FirewallKind::Ufw));
+        assert_eq!(firewalld.detect(), Ok(FirewallKind::Firewalld));
+        assert_eq!(
+            neither.detect(),
+            Err(String::from("--manage_firewall can't find a firewall it knows how to manage (Windows Firewall, an active ufw, or a running firewalld)"))
+        );
+    }
+
+    #[test]
+    fn open_makes_and_records_rules_and_close_takes_them_down() {
+        let run_parameters = Arc::new(Mutex::new(vec![]));
+        let subject = make_subject(
+            "open_makes_and_records_rules_and_close_takes_them_down",
+            CommandRunnerMock::new()
+                .run_parameters(&run_parameters)
+                .run_result(Ok(String::new()))
+                .run_result(Ok(String::new()))
+                .run_result(Ok(String::new()))
+                .run_result(Ok(String::new())),
+        );
+
+        subject.open(FirewallKind::Ufw, &[1234, 2345]).unwrap();
+        assert_eq!(
+            fs::read_to_string(subject.record_path()).unwrap(),
+            String::from("ufw 1234,2345\n")
+        );
+        subject.close_recorded();
+
+        assert_eq!(
+            *run_parameters.lock().unwrap(),
+            vec![
+                String::from("ufw allow 1234/tcp"),
+                String::from("ufw allow 2345/tcp"),
+                String::from("ufw delete allow 1234/tcp"),
+                String::from("ufw delete allow 2345/tcp"),
+            ]
+        );
+        assert_eq!(subject.record_path().exists(), false);
+    }
+
+    #[test]
+    fn open_takes_down_rules_left_by_the_last_run_first() {
+        let run_parameters = Arc::new(Mutex::new(vec![]));
+        let subject = make_subject(
+            "open_takes_down_rules_left_by_the_last_run_first",
+            CommandRunnerMock::new()
+                .run_parameters(&run_parameters)
+                .run_result(Ok(String::new()))
+                .run_result(Ok(String::new())),
+        );
+        fs::write(subject.record_path(), "firewalld 3456\n").unwrap();
+
+        subject.open(FirewallKind::Firewalld, &[4567]).unwrap();
+
+        assert_eq!(
+            *run_parameters.lock().unwrap(),
+            vec![
+                String::from("firewall-cmd --remove-port=3456/tcp"),
+                String::from("firewall-cmd --add-port=4567/tcp"),
+            ]
+        );
+        assert_eq!(
+            fs::read_to_string(subject.record_path()).unwrap(),
+            String::from("firewalld 4567\n")
+        );
+    }
+
+    #[test]
+    fn rules_that_cannot_be_closed_stay_recorded_for_next_time() {
+        init_test_logging();
+        let subject = make_subject(
+            "rules_that_cannot_be_closed_stay_recorded_for_next_time",
+            CommandRunnerMock::new()
+                .run_result(Err(String::from(
+                    "ERROR: You need to be root to run this script",
+                )))
+                .run_result(Ok(String::new())),
+        );
+        fs::write(subject.record_path(), "ufw 1234,2345\n").unwrap();
+
+        subject.close_recorded();
+
+        assert_eq!(
+            fs::read_to_string(subject.record_path()).unwrap(),
+            String::from("ufw 1234\n")
+        );
+        TestLogHandler::new().exists_log_containing("WARN: Firewall: Couldn't close ufw firewall for port 1234: ERROR: You need to be root to run this script. It will be closed the next time the Node is started with --manage_firewall on");
+    }
+
+    #[test]
+    fn open_records_only_the_rules_that_were_made() {
+        let subject = make_subject(
+            "open_records_only_the_rules_that_were_made",
+            CommandRunnerMock::new()
+                .run_result(Err(String::from("ufw allow 1234/tcp failed: nope")))
+                .run_result(Ok(String::new())),
+        );
+
+        let result = subject.open(FirewallKind::Ufw, &[1234, 2345]);
+
+        assert_eq!(result, Err(String::from("ufw allow 1234/tcp failed: nope")));
+        assert_eq!(
+            fs::read_to_string(subject.record_path()).unwrap(),
+            String::from("ufw 2345\n")
+        );
+    }
+}
@@ -1,6 +1,9 @@
 // Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
 
+use super::fragmentation;
+use super::hop_layer::HopLayer;
 use super::live_cores_package::LiveCoresPackage;
+use super::padding;
 use crate::sub_lib::cryptde::CryptDE;
 use crate::sub_lib::cryptde::CryptData;
 use crate::sub_lib::cryptde::PlainData;
@@ -13,14 +16,19 @@ use crate::sub_lib::stream_handler_pool::TransmitDataMsg;
 use actix::Recipient;
 use actix::Syn;
 use std::borrow::Borrow;
+use std::cell::RefCell;
 use std::net::SocketAddr;
 use std::str::FromStr;
+use std::time::Instant;
 
 pub struct ConsumingService {
     cryptde: &'static dyn CryptDE,
     _is_bootstrap_node: bool, // TODO: Remember to check this and refuse to consume if set
     to_dispatcher: Recipient<Syn, TransmitDataMsg>,
     to_hopper: Recipient<Syn, InboundClientData>,
+    hop_layer: RefCell<HopLayer>,
+    fragment_mtu_opt: Option<usize>,
+    pad_packets: bool,
     logger: Logger,
 }
 
@@ -36,10 +44,21 @@ impl ConsumingService {
             _is_bootstrap_node: is_bootstrap_node,
             to_dispatcher,
             to_hopper,
+            hop_layer: RefCell::new(HopLayer::new()),
+            fragment_mtu_opt: None,
+            pad_packets: false,
             logger: Logger::new("ConsumingService"),
         }
     }
 
+    pub fn fragment_payloads_over(&mut self, mtu_opt: Option<usize>) {
+        self.fragment_mtu_opt = mtu_opt;
+    }
+
+    pub fn pad_packets(&mut self, pad_packets: bool) {
+        self.pad_packets = pad_packets;
+    }
+
     pub fn consume(&self, incipient_cores_package: IncipientCoresPackage) {
         self.logger.debug(format!(
             "Received IncipientCoresPackage with {}-byte payload",
@@ -47,16 +66,20 @@ impl ConsumingService {
         ));
         match LiveCoresPackage::from_incipient(incipient_cores_package, self.cryptde.borrow()) {
             Ok((live_package, next_node_key)) => {
-                let encrypted_package =
-                    match self.serialize_and_encrypt_lcp(live_package, &next_node_key) {
-                        Ok(p) => p,
-                        Err(_) => {
-                            // TODO what should we do here? (nothing is unbound --so we don't need to blow up-- but we can't send this package)
-                            return ();
-                        }
-                    };
-
-                self.launch_lcp(encrypted_package, next_node_key);
+                self.fragment(live_package)
+                    .into_iter()
+                    .for_each(|live_package| {
+                        let encrypted_package =
+                            match self.serialize_and_encrypt_lcp(live_package, &next_node_key) {
+                                Ok(p) => p,
+                                Err(_) => {
+                                    // TODO what should we do here? (nothing is unbound --so we don't need to blow up-- but we can't send this package)
+                                    return ();
+                                }
+                            };
+
+                        self.launch_lcp(encrypted_package, next_node_key.clone());
+                    })
             }
             Err(e) => self.logger.error(e),
         };
@@ -64,11 +87,38 @@ impl ConsumingService {
         ()
     }
 
+    fn fragment(&self, live_package: LiveCoresPackage) -> Vec<LiveCoresPackage> {
+        match self.fragment_mtu_opt {
+            Some(mtu) if live_package.payload.len() > mtu => {
+                let payload_len = live_package.payload.len();
+                let fragments = fragmentation::fragment(live_package, mtu, rand::random::<u64>());
+                self.logger.debug(format!(
+                    "Split {}-byte payload into {} fragments",
+                    payload_len,
+                    fragments.len()
+                ));
+                fragments
+            }
+            _ => vec![live_package],
+        }
+    }
+
     fn serialize_and_encrypt_lcp(
         &self,
         live_package: LiveCoresPackage,
         next_node_key: &PublicKey,
     ) -> Result<CryptData, ()> {
+        let live_package = if self.pad_packets {
+            match padding::pad(live_package) {
+                Ok(package) => package,
+                Err(e) => {
+                    self.logger.error(format!("Couldn't pad package: {}", e));
+                    return Err(());
+                }
+            }
+        } else {
+            live_package
+        };
         let serialized_package = match serde_cbor::ser::to_vec(&live_package) {
             Ok(package) => package,
             Err(e) => {
@@ -78,10 +128,12 @@ impl ConsumingService {
             }
         };
 
-        let encrypted_package = match self
-            .cryptde
-            .encode(&next_node_key, &PlainData::new(&serialized_package[..]))
-        {
+        let encrypted_package = match self.hop_layer.borrow_mut().encode(
+            self.cryptde,
+            &next_node_key,
+            &PlainData::new(&serialized_package[..]),
+            Instant::now(),
+        ) {
             Ok(package) => package,
             Err(e) => {
                 self.logger
@@ -109,6 +161,7 @@ impl ConsumingService {
             sequence_number: None,
             is_clandestine: true,
             data: encrypted_package.into(),
+            neighbor_key_opt: None,
         };
         self.logger.debug(format!(
             "Sending InboundClientData with {}-byte payload to Hopper",
@@ -140,7 +193,9 @@ impl ConsumingService {
 #[cfg(test)]
 mod tests {
     use super::super::hopper::Hopper;
+    use super::super::live_cores_package::Fragment;
     use super::*;
+    use crate::sub_lib::cryptde_null::CryptDENull;
     use crate::sub_lib::dispatcher::Component;
     use crate::sub_lib::hopper::ExpiredCoresPackage;
     use crate::sub_lib::peer_actors::BindMessage;
@@ -154,8 +209,10 @@ mod tests {
     use crate::test_utils::recorder::Recorder;
     use crate::test_utils::test_utils::cryptde;
     use crate::test_utils::test_utils::zero_hop_route_response;
+    use actix::msgs;
     use actix::Actor;
     use actix::Addr;
+    use actix::Arbiter;
     use actix::System;
     use std::net::IpAddr;
     use std::str::FromStr;
@@ -200,16 +257,17 @@ mod tests {
             .unwrap()
             .0;
         let expected_lcp_ser = PlainData::new(&serde_cbor::ser::to_vec(&expected_lcp).unwrap());
-        let expected_lcp_enc = cryptde.encode(&destination_key, &expected_lcp_ser).unwrap();
-        assert_eq!(
-            *record,
-            TransmitDataMsg {
-                endpoint: Endpoint::Key(destination_key.clone()),
-                last_data: false,
-                sequence_number: None,
-                data: expected_lcp_enc.into(),
-            }
-        );
+        assert_eq!(record.endpoint, Endpoint::Key(destination_key.clone()));
+        assert_eq!(record.last_data, false);
+        assert_eq!(record.sequence_number, None);
+        let lcp_ser = HopLayer::new()
+            .decode(
+                &CryptDENull::from(&destination_key),
+                &CryptData::new(&record.data[..]),
+                Instant::now(),
+            )
+            .unwrap();
+        assert_eq!(lcp_ser, expected_lcp_ser);
     }
 
     #[test] // TODO: Rewrite test so that subject is ConsumingService rather than Hopper
@@ -271,4 +329,149 @@ mod tests {
             "ERROR: ConsumingService: Could not decrypt next hop: EmptyRoute",
         );
     }
+
+    #[test]
+    fn payloads_over_the_mtu_are_sent_in_fragments() {
+        let cryptde = cryptde();
+        let system = System::new("payloads_over_the_mtu_are_sent_in_fragments");
+        let (dispatcher, _, dispatcher_recording_arc) = make_recorder();
+        let peer_actors = peer_actors_builder().dispatcher(dispatcher).build();
+        let destination_key = PublicKey::new(&[65, 65, 65]);
+        let route = Route::one_way(
+            RouteSegment::new(
+                vec![&cryptde.public_key(), &destination_key],
+                Component::ProxyClient,
+            ),
+            cryptde,
+            Some(Wallet::new("wallet")),
+        )
+        .unwrap();
+        let incipient_cores_package = IncipientCoresPackage {
+            route,
+            payload: CryptData::new(&[0x5A; 1000]),
+        };
+        let mut subject = ConsumingService::new(
+            cryptde,
+            false,
+            peer_actors.dispatcher.from_dispatcher_client,
+            peer_actors.hopper.from_dispatcher,
+        );
+        subject.fragment_payloads_over(Some(400));
+
+        subject.consume(incipient_cores_package);
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+        let dispatcher_recording = dispatcher_recording_arc.lock().unwrap();
+        let fragments: Vec<LiveCoresPackage> = (0..dispatcher_recording.len())
+            .map(|index| {
+                let record = dispatcher_recording.get_record::<TransmitDataMsg>(index);
+                assert_eq!(record.endpoint, Endpoint::Key(destination_key.clone()));
+                let lcp_ser = HopLayer::new()
+                    .decode(
+                        &CryptDENull::from(&destination_key),
+                        &CryptData::new(&record.data[..]),
+                        Instant::now(),
+                    )
+                    .unwrap();
+                serde_cbor::de::from_slice(lcp_ser.as_slice()).unwrap()
+            })
+            .collect();
+        assert_eq!(
+            fragments
+                .iter()
+                .map(|fragment| fragment.payload.len())
+                .collect::<Vec<usize>>(),
+            vec![400, 400, 200]
+        );
+        let id = fragments[0].fragment_opt.as_ref().unwrap().id;
+        fragments.iter().enumerate().for_each(|(index, fragment)| {
+            assert_eq!(
+                fragment.fragment_opt,
+                Some(Fragment {
+                    id,
+                    index: index as u32,
+                    count: 3,
+                })
+            )
+        });
+    }
+
+    #[test]
+    fn payloads_are_not_fragmented_without_an_mtu() {
+        let cryptde = cryptde();
+        let system = System::new("payloads_are_not_fragmented_without_an_mtu");
+        let (dispatcher, _, dispatcher_recording_arc) = make_recorder();
+        let peer_actors = peer_actors_builder().dispatcher(dispatcher).build();
+        let destination_key = PublicKey::new(&[65, 65, 65]);
+        let route = Route::one_way(
+            RouteSegment::new(
+                vec![&cryptde.public_key(), &destination_key],
+                Component::ProxyClient,
+            ),
+            cryptde,
+            Some(Wallet::new("wallet")),
+        )
+        .unwrap();
+        let subject = ConsumingService::new(
+            cryptde,
+            false,
+            peer_actors.dispatcher.from_dispatcher_client,
+            peer_actors.hopper.from_dispatcher,
+        );
+
+        subject.consume(IncipientCoresPackage {
+            route,
+            payload: CryptData::new(&[0x5A; 100_000]),
+        });
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+        assert_eq!(dispatcher_recording_arc.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn packages_are_padded_to_a_standard_size_when_asked() {
+        let cryptde = cryptde();
+        let system = System::new("packages_are_padded_to_a_standard_size_when_asked");
+        let (dispatcher, _, dispatcher_recording_arc) = make_recorder();
+        let peer_actors = peer_actors_builder().dispatcher(dispatcher).build();
+        let destination_key = PublicKey::new(&[65, 65, 65]);
+        let route = Route::one_way(
+            RouteSegment::new(
+                vec![&cryptde.public_key(), &destination_key],
+                Component::ProxyClient,
+            ),
+            cryptde,
+            Some(Wallet::new("wallet")),
+        )
+        .unwrap();
+        let mut subject = ConsumingService::new(
+            cryptde,
+            false,
+            peer_actors.dispatcher.from_dispatcher_client,
+            peer_actors.hopper.from_dispatcher,
+        );
+        subject.pad_packets(true);
+
+        subject.consume(IncipientCoresPackage {
+            route,
+            payload: CryptData::new(&[0x5A; 300]),
+        });
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+        let dispatcher_recording = dispatcher_recording_arc.lock().unwrap();
+        let record = dispatcher_recording.get_record::<TransmitDataMsg>(0);
+        let lcp_ser = HopLayer::new()
+            .decode(
+                &CryptDENull::from(&destination_key),
+                &CryptData::new(&record.data[..]),
+                Instant::now(),
+            )
+            .unwrap();
+        assert_eq!(padding::PADDING_BUCKETS.contains(&lcp_ser.len()), true);
+        let lcp: LiveCoresPackage = serde_cbor::de::from_slice(lcp_ser.as_slice()).unwrap();
+        assert_eq!(lcp.payload, CryptData::new(&[0x5A; 300]));
+    }
 }
@@ -0,0 +1,229 @@
+// Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use crate::sub_lib::wallet::Wallet;
+use std::collections::HashMap;
+use std::collections::VecDeque;
+
+// How many bytes a queue may send per turn for each unit of its weight
+pub const FAIR_QUEUE_QUANTUM: usize = 4096;
+
+struct ConsumerQueue<T> {
+    items: VecDeque<(usize, T)>,
+    queued_bytes: usize,
+    deficit: usize,
+    turn_started: bool,
+}
+
+// Packages waiting their turn to go out, one queue per consuming wallet. The queues take turns
+// (deficit round robin): on its turn a queue sends as many bytes as its weight in quanta, plus
+// whatever it couldn't use on its last turn because its next package was too big. A consumer
+// with a lot to send gets its share and no more while others are waiting, and everything once
+// they aren't. A queue that's full turns away its consumer's packages, not anyone else's.
+pub struct FairQueue<T> {
+    quantum: usize,
+    max_queued_bytes: usize,
+    default_weight: usize,
+    weights: HashMap<Wallet, usize>,
+    queues: HashMap<Wallet, ConsumerQueue<T>>,
+    turns: VecDeque<Wallet>,
+}
+
+impl<T> FairQueue<T> {
+    pub fn new(quantum: usize, max_queued_bytes: usize, default_weight: usize) -> FairQueue<T> {
+        FairQueue {
+            quantum,
+            max_queued_bytes,
+            default_weight,
+            weights: HashMap::new(),
+            queues: HashMap::new(),
+            turns: VecDeque::new(),
+        }
+    }
+
+    // Wallets not mentioned go back to the default weight
+    pub fn set_weights(&mut self, weights: HashMap<Wallet, usize>) {
+        self.weights = weights;
+    }
+
+    pub fn weight_of(&self, wallet: &Wallet) -> usize {
+        *self.weights.get(wallet).unwrap_or(&self.default_weight)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.turns.is_empty()
+    }
+
+    pub fn queued_bytes(&self, wallet: &Wallet) -> usize {
+        self.queues
+            .get(wallet)
+            .map(|queue| queue.queued_bytes)
+            .unwrap_or(0)
+    }
+
+    // Hands item back if wallet's queue has no room for size more bytes
+    pub fn push(&mut self, wallet: &Wallet, size: usize, item: T) -> Result<(), T> {
+        if !self.queues.contains_key(wallet) {
+            self.queues.insert(
+                wallet.clone(),
+                ConsumerQueue {
+                    items: VecDeque::new(),
+                    queued_bytes: 0,
+                    deficit: 0,
+                    turn_started: false,
+                },
+            );
+            self.turns.push_back(wallet.clone());
+        }
+        let queue = self.queues.get_mut(wallet).expect("Queue disappeared");
+        if queue.queued_bytes > 0 && queue.queued_bytes + size > self.max_queued_bytes {
+            return Err(item);
+        }
+        queue.queued_bytes += size;
+        queue.items.push_back((size, item));
+        Ok(())
+    }
+
+    // Takes packages in turn until the next one would go over budget. The first package always
+    // goes if budget is positive, however big it is, so that no package is stuck for good behind a
+    // budget smaller than it; the caller pays that back out of later budgets.
+    pub fn pop(&mut self, budget: usize) -> Vec<T> {
+        let mut released = vec![];
+        let mut spent = 0;
+        while let Some(wallet) = self.turns.front().cloned() {
+            let weight = self.weight_of(&wallet);
+            let queue = self.queues.get_mut(&wallet).expect("Queue disappeared");
+            if !queue.turn_started {
+                queue.deficit += self.quantum * weight;
+                queue.turn_started = true;
+            }
+            let mut out_of_budget = false;
+            while let Some(size) = queue.items.front().map(|(size, _)| *size) {
+                if size > queue.deficit {
+                    break;
+                }
+                if spent + size > budget && !(spent == 0 && budget > 0) {
+                    out_of_budget = true;
+                    break;
+                }
+                let (_, item) = queue.items.pop_front().expect("Item disappeared");
+                queue.deficit -= size;
+                queue.queued_bytes -= size;
+                spent += size;
+                released.push(item);
+            }
+            if queue.items.is_empty() {
+                self.queues.remove(&wallet);
+                self.turns.pop_front();
+            } else if out_of_budget {
+                break;
+            } else {
+                queue.turn_started = false;
+                self.turns.rotate_left(1);
+            }
+        }
+        released
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names_of(released: Vec<(&'static str, usize)>) -> Vec<&'static str> {
+        released.into_iter().map(|(name, _)| name).collect()
+    }
+
+    #[test]
+    fn constants_have_correct_values() {
+        assert_eq!(FAIR_QUEUE_QUANTUM, 4096);
+    }
+
+    #[test]
+    fn a_heavy_consumer_takes_turns_with_a_light_one() {
+        let heavy = Wallet::new("heavy");
+        let light = Wallet::new("light");
+        let mut subject = FairQueue::new(100, 10_000, 1);
+        (0..5).for_each(|_| subject.push(&heavy, 100, ("heavy", 100)).unwrap());
+        subject.push(&light, 100, ("light", 100)).unwrap();
+        subject.push(&light, 100, ("light", 100)).unwrap();
+
+        let result = names_of(subject.pop(400));
+
+        assert_eq!(result, vec!["heavy", "light", "heavy", "light"]);
+        assert_eq!(subject.queued_bytes(&heavy), 300);
+        assert_eq!(subject.queued_bytes(&light), 0);
+        assert_eq!(names_of(subject.pop(1000)), vec!["heavy", "heavy", "heavy"]);
+        assert_eq!(subject.is_empty(), true);
+    }
+
+    #[test]
+    fn weights_divide_the_budget() {
+        let favored = Wallet::new("favored");
+        let other = Wallet::new("other");
+        let mut subject = FairQueue::new(100, 10_000, 1);
+        subject.set_weights(vec![(favored.clone(), 2)].into_iter().collect());
+        (0..6).for_each(|_| {
+            subject.push(&favored, 100, ("favored", 100)).unwrap();
+            subject.push(&other, 100, ("other", 100)).unwrap();
+        });
+
+        let result = names_of(subject.pop(600));
+
+        assert_eq!(
+            result,
+            vec!["favored", "favored", "other", "favored", "favored", "other"]
+        );
+        assert_eq!(subject.weight_of(&favored), 2);
+        assert_eq!(subject.weight_of(&other), 1);
+    }
+
+    #[test]
+    fn a_package_bigger_than_a_quantum_waits_until_enough_turns_have_gone_by() {
+        let big = Wallet::new("big");
+        let small = Wallet::new("small");
+        let mut subject = FairQueue::new(100, 10_000, 1);
+        subject.push(&big, 250, ("big", 250)).unwrap();
+        (0..3).for_each(|_| subject.push(&small, 100, ("small", 100)).unwrap());
+
+        let result = names_of(subject.pop(10_000));
+
+        assert_eq!(result, vec!["small", "small", "big", "small"]);
+    }
+
+    #[test]
+    fn the_first_package_goes_even_if_it_is_over_budget_but_nothing_goes_on_no_budget() {
+        let wallet = Wallet::new("wallet");
+        let mut subject = FairQueue::new(1000, 10_000, 1);
+        subject.push(&wallet, 500, ("first", 500)).unwrap();
+        subject.push(&wallet, 500, ("second", 500)).unwrap();
+
+        assert_eq!(names_of(subject.pop(0)), Vec::<&str>::new());
+        assert_eq!(names_of(subject.pop(100)), vec!["first"]);
+        assert_eq!(names_of(subject.pop(100)), vec!["second"]);
+    }
+
+    #[test]
+    fn a_full_queue_turns_away_only_its_own_consumer() {
+        let greedy = Wallet::new("greedy");
+        let modest = Wallet::new("modest");
+        let mut subject = FairQueue::new(100, 250, 1);
+        subject.push(&greedy, 100, ("greedy", 1)).unwrap();
+        subject.push(&greedy, 100, ("greedy", 2)).unwrap();
+
+        let result = subject.push(&greedy, 100, ("greedy", 3));
+
+        assert_eq!(result, Err(("greedy", 3)));
+        assert_eq!(subject.push(&modest, 100, ("modest", 1)), Ok(()));
+        assert_eq!(subject.queued_bytes(&greedy), 200);
+    }
+
+    #[test]
+    fn an_empty_queue_takes_one_package_of_any_size() {
+        let wallet = Wallet::new("wallet");
+        let mut subject = FairQueue::new(100, 250, 1);
+
+        let result = subject.push(&wallet, 1000, ("huge", 1000));
+
+        assert_eq!(result, Ok(()));
+        assert_eq!(names_of(subject.pop(1)), vec!["huge"]);
+    }
+}
@@ -0,0 +1,281 @@
+// Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use super::live_cores_package::Fragment;
+use super::live_cores_package::LiveCoresPackage;
+use crate::sub_lib::cryptde::CryptData;
+use std::collections::HashMap;
+use std::time::Duration;
+use std::time::Instant;
+
+pub const MIN_FRAGMENT_MTU: usize = 512;
+pub const MAX_FRAGMENT_COUNT: u32 = 4096;
+pub const REASSEMBLY_TIMEOUT: Duration = Duration::from_secs(30);
+
+// Packages over the same route as live_package, each carrying a numbered piece of its payload no
+// longer than mtu. A payload that already fits goes as it is.
+pub fn fragment(live_package: LiveCoresPackage, mtu: usize, id: u64) -> Vec<LiveCoresPackage> {
+    if live_package.payload.len() <= mtu {
+        return vec![live_package];
+    }
+    let pieces: Vec<&[u8]> = live_package.payload.as_slice().chunks(mtu).collect();
+    let count = pieces.len() as u32;
+    pieces
+        .into_iter()
+        .enumerate()
+        .map(|(index, piece)| LiveCoresPackage {
+            route: live_package.route.clone(),
+            payload: CryptData::new(piece),
+            fragment_opt: Some(Fragment {
+                id,
+                index: index as u32,
+                count,
+            }),
+            padding_opt: None,
+        })
+        .collect()
+}
+
+struct PartialPayload {
+    pieces: Vec<Option<CryptData>>,
+    missing: usize,
+    started: Instant,
+}
+
+// Holds fragments until every piece of their payload has arrived. A set that is still incomplete
+// when its timeout has passed since its first fragment came in is given up on.
+pub struct Reassembler {
+    timeout: Duration,
+    partials: HashMap<u64, PartialPayload>,
+}
+
+impl Reassembler {
+    pub fn new(timeout: Duration) -> Reassembler {
+        Reassembler {
+            timeout,
+            partials: HashMap::new(),
+        }
+    }
+
+    // The whole package, once this fragment completes its set; a package that isn't a fragment
+    // comes straight back
+    pub fn add(
+        &mut self,
+        live_package: LiveCoresPackage,
+        now: Instant,
+    ) -> Result<Option<LiveCoresPackage>, String> {
+        let fragment = match &live_package.fragment_opt {
+            Some(fragment) => fragment.clone(),
+            None => return Ok(Some(live_package)),
+        };
+        if fragment.count == 0
+            || fragment.count > MAX_FRAGMENT_COUNT
+            || fragment.index >= fragment.count
+        {
+            return Err(format!(
+                "Invalid fragment {} of {} for payload {}",
+                fragment.index, fragment.count, fragment.id
+            ));
+        }
+        let partial = self
+            .partials
+            .entry(fragment.id)
+            .or_insert_with(|| PartialPayload {
+                pieces: vec![None; fragment.count as usize],
+                missing: fragment.count as usize,
+                started: now,
+            });
+        if partial.pieces.len() != fragment.count as usize {
+            return Err(format!(
+                "Fragment {} for payload {} claims {} pieces, not {}",
+                fragment.index,
+                fragment.id,
+                fragment.count,
+                partial.pieces.len()
+            ));
+        }
+        let slot = &mut partial.pieces[fragment.index as usize];
+        if slot.is_none() {
+            partial.missing -= 1;
+        }
+        *slot = Some(live_package.payload);
+        if partial.missing > 0 {
+            return Ok(None);
+        }
+        let partial = self
+            .partials
+            .remove(&fragment.id)
+            .expect("Partial payload disappeared");
+        let payload: Vec<u8> = partial
+            .pieces
+            .into_iter()
+            .flat_map(|piece| -> Vec<u8> { piece.expect("Piece disappeared").into() })
+            .collect();
+        Ok(Some(LiveCoresPackage::new(
+            live_package.route,
+            CryptData::from(payload),
+        )))
+    }
+
+    // Gives up on the sets that have waited too long: for each, its id, how many pieces had
+    // arrived, and how many there should have been
+    pub fn expire(&mut self, now: Instant) -> Vec<(u64, usize, usize)> {
+        let timeout = self.timeout;
+        let expired: Vec<u64> = self
+            .partials
+            .iter()
+            .filter(|(_, partial)| now.duration_since(partial.started) >= timeout)
+            .map(|(id, _)| *id)
+            .collect();
+        expired
+            .into_iter()
+            .map(|id| {
+                let partial = self
+                    .partials
+                    .remove(&id)
+                    .expect("Partial payload disappeared");
+                let count = partial.pieces.len();
+                (id, count - partial.missing, count)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::test_utils::make_meaningless_route;
+
+    fn package(payload: &[u8]) -> LiveCoresPackage {
+        LiveCoresPackage::new(make_meaningless_route(), CryptData::new(payload))
+    }
+
+    #[test]
+    fn constants_have_correct_values() {
+        assert_eq!(MIN_FRAGMENT_MTU, 512);
+        assert_eq!(MAX_FRAGMENT_COUNT, 4096);
+        assert_eq!(REASSEMBLY_TIMEOUT, Duration::from_secs(30));
+    }
+
+    #[test]
+    fn a_payload_that_fits_is_not_fragmented() {
+        let result = fragment(package(&[1, 2, 3, 4]), 4, 1234);
+
+        assert_eq!(result, vec![package(&[1, 2, 3, 4])]);
+    }
+
+    #[test]
+    fn a_payload_that_does_not_fit_is_split_into_numbered_pieces() {
+        let result = fragment(package(&[1, 2, 3, 4, 5]), 2, 1234);
+
+        let pieces: Vec<(Vec<u8>, Option<Fragment>)> = result
+            .into_iter()
+            .map(|p| {
+                assert_eq!(p.route, make_meaningless_route());
+                (p.payload.into(), p.fragment_opt)
+            })
+            .collect();
+        let fragment = |index| {
+            Some(Fragment {
+                id: 1234,
+                index,
+                count: 3,
+            })
+        };
+        assert_eq!(
+            pieces,
+            vec![
+                (vec![1, 2], fragment(0)),
+                (vec![3, 4], fragment(1)),
+                (vec![5], fragment(2)),
+            ]
+        );
+    }
+
+    #[test]
+    fn fragments_are_reassembled_in_whatever_order_they_arrive() {
+        let now = Instant::now();
+        let mut fragments = fragment(package(&[1, 2, 3, 4, 5]), 2, 1234);
+        let mut subject = Reassembler::new(REASSEMBLY_TIMEOUT);
+
+        assert_eq!(subject.add(fragments.remove(2), now), Ok(None));
+        assert_eq!(subject.add(fragments.remove(0), now), Ok(None));
+        // A duplicate doesn't count twice
+        assert_eq!(
+            subject.add(fragment(package(&[1, 2, 3, 4, 5]), 2, 1234).remove(0), now),
+            Ok(None)
+        );
+        let result = subject.add(fragments.remove(0), now);
+
+        assert_eq!(result, Ok(Some(package(&[1, 2, 3, 4, 5]))));
+        assert_eq!(subject.expire(now + REASSEMBLY_TIMEOUT), vec![]);
+    }
+
+    #[test]
+    fn a_package_that_is_not_a_fragment_comes_straight_back() {
+        let mut subject = Reassembler::new(REASSEMBLY_TIMEOUT);
+
+        let result = subject.add(package(&[1, 2, 3]), Instant::now());
+
+        assert_eq!(result, Ok(Some(package(&[1, 2, 3]))));
+    }
+
+    #[test]
+    fn fragments_that_make_no_sense_are_rejected() {
+        let now = Instant::now();
+        let mut subject = Reassembler::new(REASSEMBLY_TIMEOUT);
+        let with_fragment = |id, index, count| LiveCoresPackage {
+            fragment_opt: Some(Fragment { id, index, count }),
+            ..package(&[1])
+        };
+        subject.add(with_fragment(1, 0, 2), now).unwrap();
+
+        assert_eq!(
+            subject.add(with_fragment(2, 2, 2), now),
+            Err(String::from("Invalid fragment 2 of 2 for payload 2"))
+        );
+        assert_eq!(
+            subject.add(with_fragment(3, 0, MAX_FRAGMENT_COUNT + 1), now),
+            Err(String::from("Invalid fragment 0 of 4097 for payload 3"))
+        );
+        assert_eq!(
+            subject.add(with_fragment(1, 1, 3), now),
+            Err(String::from(
+                "Fragment 1 for payload 1 claims 3 pieces, not 2"
+            ))
+        );
+    }
+
+    #[test]
+    fn incomplete_sets_are_given_up_on_after_the_timeout() {
+        let now = Instant::now();
+        let mut subject = Reassembler::new(Duration::from_secs(10));
+        let mut first = fragment(package(&[1, 2, 3, 4, 5]), 2, 1);
+        let mut second = fragment(package(&[1, 2, 3]), 2, 2);
+        subject.add(first.remove(0), now).unwrap();
+        subject.add(first.remove(0), now).unwrap();
+        subject
+            .add(second.remove(0), now + Duration::from_secs(5))
+            .unwrap();
+
+        assert_eq!(
+            subject.expire(now + Duration::from_secs(10) - Duration::from_millis(1)),
+            vec![]
+        );
+        assert_eq!(
+            subject.expire(now + Duration::from_secs(10)),
+            vec![(1, 2, 3)]
+        );
+        assert_eq!(
+            subject.add(second.remove(0), now + Duration::from_secs(11)),
+            Ok(Some(package(&[1, 2, 3])))
+        );
+        // The rest of a set that was given up on starts it over
+        assert_eq!(
+            subject.add(first.remove(0), now + Duration::from_secs(11)),
+            Ok(None)
+        );
+        assert_eq!(
+            subject.expire(now + Duration::from_secs(21)),
+            vec![(1, 1, 3)]
+        );
+    }
+}
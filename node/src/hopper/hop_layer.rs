@@ -0,0 +1,283 @@
+// Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use crate::sub_lib::cryptde::CryptDE;
+use crate::sub_lib::cryptde::CryptData;
+use crate::sub_lib::cryptde::CryptdecError;
+use crate::sub_lib::cryptde::PlainData;
+use crate::sub_lib::cryptde::PublicKey;
+use crate::sub_lib::cryptde::SymmetricKey;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::Duration;
+use std::time::Instant;
+
+pub const HOP_SESSION_LIFETIME: Duration = Duration::from_secs(600);
+
+// What goes over the wire between adjacent Nodes: the LiveCoresPackage encrypted with a session key,
+// and the session key itself encrypted for the receiving Node.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct HopPackage {
+    pub session_key: CryptData,
+    pub data: CryptData,
+}
+
+struct OutboundSession {
+    key: SymmetricKey,
+    encrypted_key: CryptData,
+    started: Instant,
+}
+
+struct InboundSession {
+    key: SymmetricKey,
+    last_used: Instant,
+}
+
+// The hop-to-hop encryption around each LiveCoresPackage, as distinct from the end-to-end
+// encryption inside it. A session key is made up for each neighbor and encrypted for it once;
+// every package sent to that neighbor carries the same encrypted key, so neither end does an
+// asymmetric operation for any package but the first one of a session. Sessions are replaced
+// after HOP_SESSION_LIFETIME, and a neighbor's session that hasn't been used for twice that long
+// is forgotten.
+pub struct HopLayer {
+    outbound: HashMap<PublicKey, OutboundSession>,
+    inbound: HashMap<CryptData, InboundSession>,
+}
+
+impl HopLayer {
+    pub fn new() -> HopLayer {
+        HopLayer {
+            outbound: HashMap::new(),
+            inbound: HashMap::new(),
+        }
+    }
+
+    pub fn encode(
+        &mut self,
+        cryptde: &dyn CryptDE,
+        neighbor_key: &PublicKey,
+        data: &PlainData,
+        now: Instant,
+    ) -> Result<CryptData, CryptdecError> {
+        let expired = match self.outbound.get(neighbor_key) {
+            Some(session) => now.duration_since(session.started) >= HOP_SESSION_LIFETIME,
+            None => true,
+        };
+        if expired {
+            let key = cryptde.generate_symmetric_key();
+            let encrypted_key = cryptde.encode(neighbor_key, &PlainData::new(key.as_slice()))?;
+            self.outbound.insert(
+                neighbor_key.clone(),
+                OutboundSession {
+                    key,
+                    encrypted_key,
+                    started: now,
+                },
+            );
+        }
+        let session = self.outbound.get(neighbor_key).expect("Internal error");
+        let package = HopPackage {
+            session_key: session.encrypted_key.clone(),
+            data: cryptde.encode_sym(&session.key, data)?,
+        };
+        match serde_cbor::ser::to_vec(&package) {
+            Ok(serialized) => Ok(CryptData::from(serialized)),
+            Err(e) => Err(CryptdecError::InvalidKey(format!(
+                "Couldn't serialize HopPackage: {}",
+                e
+            ))),
+        }
+    }
+
+    pub fn decode(
+        &mut self,
+        cryptde: &dyn CryptDE,
+        data: &CryptData,
+        now: Instant,
+    ) -> Result<PlainData, CryptdecError> {
+        let package = match serde_cbor::de::from_slice::<HopPackage>(data.as_slice()) {
+            Ok(package) => package,
+            // Nodes that don't keep sessions encrypt each whole package for its receiver
+            Err(_) => return cryptde.decode(data),
+        };
+        if !self.inbound.contains_key(&package.session_key) {
+            let key = SymmetricKey::from(cryptde.decode(&package.session_key)?.as_slice().to_vec());
+            self.inbound.retain(|_, session| {
+                now.duration_since(session.last_used) < HOP_SESSION_LIFETIME * 2
+            });
+            self.inbound.insert(
+                package.session_key.clone(),
+                InboundSession {
+                    key,
+                    last_used: now,
+                },
+            );
+        }
+        let session = self
+            .inbound
+            .get_mut(&package.session_key)
+            .expect("Internal error");
+        session.last_used = now;
+        cryptde.decode_sym(&session.key, &package.data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sub_lib::cryptde_null::CryptDENull;
+    use crate::sub_lib::cryptde_real::CryptDEReal;
+
+    fn session_key_of(data: &CryptData) -> CryptData {
+        serde_cbor::de::from_slice::<HopPackage>(data.as_slice())
+            .unwrap()
+            .session_key
+    }
+
+    #[test]
+    fn constants_have_correct_values() {
+        assert_eq!(HOP_SESSION_LIFETIME, Duration::from_secs(600));
+    }
+
+    #[test]
+    fn packages_encoded_for_a_neighbor_can_be_decoded_only_by_that_neighbor() {
+        let sender = CryptDEReal::new();
+        let receiver = CryptDEReal::new();
+        let eavesdropper = CryptDEReal::new();
+        let now = Instant::now();
+        let mut subject = HopLayer::new();
+
+        let encoded = subject
+            .encode(
+                &sender,
+                &receiver.public_key(),
+                &PlainData::new(b"booga"),
+                now,
+            )
+            .unwrap();
+
+        assert_eq!(
+            HopLayer::new().decode(&receiver, &encoded, now).unwrap(),
+            PlainData::new(b"booga")
+        );
+        assert_eq!(
+            HopLayer::new()
+                .decode(&eavesdropper, &encoded, now)
+                .is_err(),
+            true
+        );
+    }
+
+    #[test]
+    fn the_same_session_is_used_for_a_neighbor_until_it_expires() {
+        let cryptde = CryptDEReal::new();
+        let neighbor = CryptDEReal::new().public_key();
+        let other_neighbor = CryptDEReal::new().public_key();
+        let then = Instant::now();
+        let mut subject = HopLayer::new();
+        let data = PlainData::new(b"booga");
+
+        let first = subject.encode(&cryptde, &neighbor, &data, then).unwrap();
+        let second = subject
+            .encode(
+                &cryptde,
+                &neighbor,
+                &data,
+                then + HOP_SESSION_LIFETIME - Duration::from_millis(1),
+            )
+            .unwrap();
+        let other = subject
+            .encode(&cryptde, &other_neighbor, &data, then)
+            .unwrap();
+        let third = subject
+            .encode(&cryptde, &neighbor, &data, then + HOP_SESSION_LIFETIME)
+            .unwrap();
+
+        assert_eq!(session_key_of(&first), session_key_of(&second));
+        assert_ne!(first, second);
+        assert_ne!(session_key_of(&first), session_key_of(&other));
+        assert_ne!(session_key_of(&first), session_key_of(&third));
+    }
+
+    #[test]
+    fn the_receiver_remembers_sessions_until_they_have_been_idle_too_long() {
+        let sender = CryptDEReal::new();
+        let receiver = CryptDEReal::new();
+        let then = Instant::now();
+        let later = then + HOP_SESSION_LIFETIME * 2;
+        let mut sending_layer = HopLayer::new();
+        let mut subject = HopLayer::new();
+        let first = sending_layer
+            .encode(
+                &sender,
+                &receiver.public_key(),
+                &PlainData::new(b"one"),
+                then,
+            )
+            .unwrap();
+        let second = sending_layer
+            .encode(
+                &sender,
+                &receiver.public_key(),
+                &PlainData::new(b"two"),
+                then,
+            )
+            .unwrap();
+        let third = sending_layer
+            .encode(
+                &sender,
+                &receiver.public_key(),
+                &PlainData::new(b"three"),
+                later,
+            )
+            .unwrap();
+
+        subject.decode(&receiver, &first, then).unwrap();
+        subject.decode(&receiver, &second, then).unwrap();
+        assert_eq!(subject.inbound.len(), 1);
+
+        assert_eq!(
+            subject.decode(&receiver, &third, later).unwrap(),
+            PlainData::new(b"three")
+        );
+        assert_eq!(subject.inbound.len(), 1);
+        assert_eq!(subject.inbound.contains_key(&session_key_of(&third)), true);
+    }
+
+    #[test]
+    fn packages_encrypted_whole_for_the_receiver_are_still_decoded() {
+        let cryptde = CryptDENull::new();
+        let data = cryptde
+            .encode(&cryptde.public_key(), &PlainData::new(b"booga"))
+            .unwrap();
+        let mut subject = HopLayer::new();
+
+        let result = subject.decode(&cryptde, &data, Instant::now());
+
+        assert_eq!(result.unwrap(), PlainData::new(b"booga"));
+        assert_eq!(subject.inbound.is_empty(), true);
+    }
+
+    #[test]
+    fn tampered_packages_are_not_decoded() {
+        let sender = CryptDEReal::new();
+        let receiver = CryptDEReal::new();
+        let now = Instant::now();
+        let encoded = HopLayer::new()
+            .encode(
+                &sender,
+                &receiver.public_key(),
+                &PlainData::new(b"booga"),
+                now,
+            )
+            .unwrap();
+        let mut package = serde_cbor::de::from_slice::<HopPackage>(encoded.as_slice()).unwrap();
+        let mut tampered: Vec<u8> = package.data.into();
+        let last = tampered.len() - 1;
+        tampered[last] ^= 0x01;
+        package.data = CryptData::from(tampered);
+        let tampered_package = CryptData::from(serde_cbor::ser::to_vec(&package).unwrap());
+
+        let result = HopLayer::new().decode(&receiver, &tampered_package, now);
+
+        assert_eq!(result.is_err(), true);
+    }
+}
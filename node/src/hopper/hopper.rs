@@ -3,21 +3,41 @@ use super::consuming_service::ConsumingService;
 use super::routing_service::RoutingService;
 use crate::sub_lib::cryptde::CryptDE;
 use crate::sub_lib::dispatcher::InboundClientData;
+use crate::sub_lib::hopper::HopperStatistics;
+use crate::sub_lib::hopper::HopperStatisticsQueryMessage;
 use crate::sub_lib::hopper::HopperSubs;
 use crate::sub_lib::hopper::IncipientCoresPackage;
+use crate::sub_lib::hopper::PaymentStandingMessage;
+use crate::sub_lib::hopper::RegisterEndpointMessage;
+use crate::sub_lib::hopper::RelayFairnessConfig;
+use crate::sub_lib::neighborhood::RateCard;
+use crate::sub_lib::neighborhood::LOAD_SAMPLE_INTERVAL;
 use crate::sub_lib::peer_actors::BindMessage;
 use crate::sub_lib::utils::NODE_MAILBOX_CAPACITY;
 use actix::Actor;
 use actix::Addr;
+use actix::AsyncContext;
 use actix::Context;
 use actix::Handler;
+use actix::MessageResult;
 use actix::Syn;
+use std::time::Duration;
+use std::time::Instant;
+
+// How often relayed packages waiting for bandwidth are let go
+pub const RELAY_QUEUE_TICK: Duration = Duration::from_millis(20);
 
 pub struct Hopper {
     cryptde: &'static dyn CryptDE,
     is_bootstrap_node: bool,
     consuming_service: Option<ConsumingService>,
     routing_service: Option<RoutingService>,
+    pending_endpoints: Vec<RegisterEndpointMessage>,
+    load_sample_interval: Duration,
+    rate_card: RateCard,
+    fragment_mtu_opt: Option<usize>,
+    pad_packets: bool,
+    relay_fairness_opt: Option<RelayFairnessConfig>,
 }
 
 impl Actor for Hopper {
@@ -29,12 +49,15 @@ impl Handler<BindMessage> for Hopper {
 
     fn handle(&mut self, msg: BindMessage, ctx: &mut Self::Context) -> Self::Result {
         ctx.set_mailbox_capacity(NODE_MAILBOX_CAPACITY);
-        self.consuming_service = Some(ConsumingService::new(
+        let mut consuming_service = ConsumingService::new(
             self.cryptde,
             self.is_bootstrap_node,
             msg.peer_actors.dispatcher.from_dispatcher_client.clone(),
             msg.peer_actors.hopper.from_dispatcher,
-        ));
+        );
+        consuming_service.fragment_payloads_over(self.fragment_mtu_opt);
+        consuming_service.pad_packets(self.pad_packets);
+        self.consuming_service = Some(consuming_service);
         self.routing_service = Some(RoutingService::new(
             self.cryptde,
             self.is_bootstrap_node,
@@ -43,7 +66,33 @@ impl Handler<BindMessage> for Hopper {
             msg.peer_actors.neighborhood.from_hopper,
             msg.peer_actors.dispatcher.from_dispatcher_client,
             msg.peer_actors.accountant.report_routing_service_provided,
+            msg.peer_actors.neighborhood.routing_load,
+            msg.peer_actors.neighborhood.neighbor_misbehavior,
         ));
+        let routing_service = self.routing_service.as_mut().expect("Internal error");
+        routing_service.sample_load_every(self.load_sample_interval);
+        routing_service.charge(self.rate_card);
+        routing_service.pad_packets(self.pad_packets);
+        routing_service.report_metrics_to(msg.peer_actors.metrics.report);
+        if let Some(relay_fairness) = self.relay_fairness_opt.as_ref() {
+            routing_service.limit_relay_throughput(relay_fairness);
+            Hopper::schedule_relay_release(ctx);
+        }
+        self.pending_endpoints
+            .drain(..)
+            .for_each(|registration| routing_service.register_endpoint(registration));
+        ()
+    }
+}
+
+impl Handler<RegisterEndpointMessage> for Hopper {
+    type Result = ();
+
+    fn handle(&mut self, msg: RegisterEndpointMessage, _ctx: &mut Self::Context) -> Self::Result {
+        match self.routing_service.as_mut() {
+            Some(routing_service) => routing_service.register_endpoint(msg),
+            None => self.pending_endpoints.push(msg),
+        }
         ()
     }
 }
@@ -74,6 +123,33 @@ impl Handler<InboundClientData> for Hopper {
     }
 }
 
+impl Handler<PaymentStandingMessage> for Hopper {
+    type Result = ();
+
+    fn handle(&mut self, msg: PaymentStandingMessage, _ctx: &mut Self::Context) -> Self::Result {
+        if let Some(routing_service) = self.routing_service.as_ref() {
+            routing_service.set_payment_standing(&msg.delinquent_wallets);
+        }
+        ()
+    }
+}
+
+impl Handler<HopperStatisticsQueryMessage> for Hopper {
+    type Result = MessageResult<HopperStatisticsQueryMessage>;
+
+    fn handle(
+        &mut self,
+        _msg: HopperStatisticsQueryMessage,
+        _ctx: &mut Self::Context,
+    ) -> <Self as Handler<HopperStatisticsQueryMessage>>::Result {
+        // An unbound Hopper hasn't routed anything yet
+        MessageResult(match self.routing_service.as_ref() {
+            Some(routing_service) => routing_service.statistics(),
+            None => HopperStatistics::default(),
+        })
+    }
+}
+
 impl Hopper {
     pub fn new(cryptde: &'static dyn CryptDE, is_bootstrap_node: bool) -> Hopper {
         Hopper {
@@ -81,14 +157,57 @@ impl Hopper {
             is_bootstrap_node,
             consuming_service: None,
             routing_service: None,
+            pending_endpoints: vec![],
+            load_sample_interval: LOAD_SAMPLE_INTERVAL,
+            rate_card: RateCard::default(),
+            fragment_mtu_opt: None,
+            pad_packets: false,
+            relay_fairness_opt: None,
         }
     }
 
+    // How long to let relayed bytes pile up before telling the Neighborhood about them
+    pub fn sample_load_every(&mut self, interval: Duration) {
+        self.load_sample_interval = interval;
+    }
+
+    // What to charge for relaying other Nodes' CORES packages
+    pub fn charge(&mut self, rate_card: RateCard) {
+        self.rate_card = rate_card;
+    }
+
+    // Payloads longer than this go out in fragments, for the destination's Hopper to put back together
+    pub fn fragment_payloads_over(&mut self, mtu_opt: Option<usize>) {
+        self.fragment_mtu_opt = mtu_opt;
+    }
+
+    // Whether every package this Node sends or relays is padded to one of a few standard sizes
+    pub fn pad_packets(&mut self, pad_packets: bool) {
+        self.pad_packets = pad_packets;
+    }
+
+    // Whether packages relayed for other Nodes' consumers are held to a rate and shared out fairly
+    pub fn limit_relay_throughput(&mut self, relay_fairness_opt: Option<RelayFairnessConfig>) {
+        self.relay_fairness_opt = relay_fairness_opt;
+    }
+
+    fn schedule_relay_release(ctx: &mut Context<Self>) {
+        ctx.run_later(RELAY_QUEUE_TICK, |act, ctx| {
+            if let Some(routing_service) = act.routing_service.as_ref() {
+                routing_service.release_relayed(Instant::now());
+            }
+            Hopper::schedule_relay_release(ctx);
+        });
+    }
+
     pub fn make_subs_from(addr: &Addr<Syn, Hopper>) -> HopperSubs {
         HopperSubs {
             bind: addr.clone().recipient::<BindMessage>(),
             from_hopper_client: addr.clone().recipient::<IncipientCoresPackage>(),
             from_dispatcher: addr.clone().recipient::<InboundClientData>(),
+            register_endpoint: addr.clone().recipient::<RegisterEndpointMessage>(),
+            statistics_query: addr.clone().recipient::<HopperStatisticsQueryMessage>(),
+            payment_standing: addr.clone().recipient::<PaymentStandingMessage>(),
         }
     }
 }
@@ -104,6 +223,8 @@ mod tests {
     use crate::sub_lib::route::Route;
     use crate::sub_lib::route::RouteSegment;
     use crate::sub_lib::wallet::Wallet;
+    use crate::test_utils::recorder::make_recorder;
+    use crate::test_utils::recorder::peer_actors_builder;
     use crate::test_utils::test_utils::cryptde;
     use crate::test_utils::test_utils::route_to_proxy_client;
     use crate::test_utils::test_utils::PayloadMock;
@@ -111,8 +232,10 @@ mod tests {
     use actix::Actor;
     use actix::Arbiter;
     use actix::System;
+    use futures::future::Future;
     use std::net::SocketAddr;
     use std::str::FromStr;
+    use std::thread;
 
     #[test]
     #[should_panic(expected = "Hopper unbound: no RoutingService")]
@@ -141,6 +264,7 @@ mod tests {
             is_clandestine: false,
             sequence_number: None,
             data: encrypted_package,
+            neighbor_key_opt: None,
         };
         let system = System::new("panics_if_routing_service_is_unbound");
         let subject = Hopper::new(cryptde, false);
@@ -179,4 +303,127 @@ mod tests {
         Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
         system.run();
     }
+
+    #[test]
+    fn statistics_query_reports_what_the_routing_service_has_counted() {
+        let cryptde = cryptde();
+        let route = route_to_proxy_client(&cryptde.public_key(), cryptde);
+        let serialized_payload = serde_cbor::ser::to_vec(&PayloadMock::new()).unwrap();
+        let data = cryptde
+            .encode(
+                &cryptde.public_key(),
+                &PlainData::new(&serialized_payload[..]),
+            )
+            .unwrap();
+        let live_package = LiveCoresPackage::new(route, data);
+        let live_data = PlainData::new(&serde_cbor::ser::to_vec(&live_package).unwrap()[..]);
+        let encrypted_package = cryptde
+            .encode(&cryptde.public_key(), &live_data)
+            .unwrap()
+            .into();
+        let good_data = InboundClientData {
+            peer_addr: SocketAddr::from_str("1.2.3.4:5678").unwrap(),
+            reception_port: None,
+            last_data: false,
+            is_clandestine: false,
+            sequence_number: None,
+            data: encrypted_package,
+            neighbor_key_opt: None,
+        };
+        let bad_data = InboundClientData {
+            data: vec![],
+            ..good_data.clone()
+        };
+        let system = System::new("statistics_query_reports_what_the_routing_service_has_counted");
+        let subject = Hopper::new(cryptde, false);
+        let subject_addr: Addr<Syn, Hopper> = subject.start();
+        subject_addr
+            .try_send(BindMessage {
+                peer_actors: peer_actors_builder().build(),
+            })
+            .unwrap();
+        subject_addr.try_send(good_data).unwrap();
+        subject_addr.try_send(bad_data).unwrap();
+
+        let future = subject_addr.send(HopperStatisticsQueryMessage {});
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+        let statistics = future.wait().unwrap();
+        assert_eq!(statistics.delivered_to(Component::ProxyClient), 1);
+        assert_eq!(statistics.total_delivered(), 1);
+        assert_eq!(statistics.decrypt_failures, 1);
+        assert_eq!(statistics.forwarded, 0);
+    }
+
+    #[test]
+    fn unbound_hopper_reports_empty_statistics() {
+        let system = System::new("unbound_hopper_reports_empty_statistics");
+        let subject = Hopper::new(cryptde(), false);
+        let subject_addr: Addr<Syn, Hopper> = subject.start();
+
+        let future = subject_addr.send(HopperStatisticsQueryMessage {});
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+        assert_eq!(future.wait().unwrap(), HopperStatistics::default());
+    }
+
+    #[test]
+    fn hopper_with_limited_relay_throughput_lets_relayed_packages_go_on_its_own() {
+        let cryptde = cryptde();
+        let next_key = PublicKey::new(&[65, 65, 65]);
+        let route = Route::one_way(
+            RouteSegment::new(
+                vec![&cryptde.public_key(), &next_key],
+                Component::Neighborhood,
+            ),
+            cryptde,
+            Some(Wallet::new("wallet")),
+        )
+        .unwrap();
+        let payload = cryptde
+            .encode(&next_key, &PlainData::new(&b"abcd"[..]))
+            .unwrap();
+        let live_data = PlainData::new(
+            &serde_cbor::ser::to_vec(&LiveCoresPackage::new(route, payload)).unwrap()[..],
+        );
+        let inbound_client_data = InboundClientData {
+            peer_addr: SocketAddr::from_str("1.2.3.4:5678").unwrap(),
+            reception_port: None,
+            last_data: false,
+            is_clandestine: true,
+            sequence_number: None,
+            data: cryptde
+                .encode(&cryptde.public_key(), &live_data)
+                .unwrap()
+                .into(),
+            neighbor_key_opt: None,
+        };
+        let (dispatcher, dispatcher_awaiter, dispatcher_recording_arc) = make_recorder();
+        thread::spawn(move || {
+            let system = System::new(
+                "hopper_with_limited_relay_throughput_lets_relayed_packages_go_on_its_own",
+            );
+            let peer_actors = peer_actors_builder().dispatcher(dispatcher).build();
+            let mut subject = Hopper::new(cryptde, false);
+            subject.limit_relay_throughput(Some(RelayFairnessConfig {
+                bytes_per_second: 100_000,
+                weighted_by_standing: true,
+            }));
+            let subject_addr: Addr<Syn, Hopper> = subject.start();
+            subject_addr.try_send(BindMessage { peer_actors }).unwrap();
+            subject_addr
+                .try_send(PaymentStandingMessage {
+                    delinquent_wallets: vec![Wallet::new("wallet")],
+                })
+                .unwrap();
+
+            subject_addr.try_send(inbound_client_data).unwrap();
+
+            system.run();
+        });
+        dispatcher_awaiter.await_message_count(1);
+        assert_eq!(dispatcher_recording_arc.lock().unwrap().len(), 1);
+    }
 }
@@ -2,6 +2,7 @@
 
 use crate::sub_lib::cryptde::CryptDE;
 use crate::sub_lib::cryptde::CryptData;
+use crate::sub_lib::cryptde::PlainData;
 use crate::sub_lib::cryptde::PublicKey;
 use crate::sub_lib::hop::LiveHop;
 use crate::sub_lib::hopper::ExpiredCoresPackage;
@@ -15,11 +16,32 @@ use std::net::IpAddr;
 pub struct LiveCoresPackage {
     pub route: Route,
     pub payload: CryptData,
+    // Present when the payload is only one piece of a larger one, which the destination's Hopper
+    // puts back together before delivering it. Relays pass it along untouched.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fragment_opt: Option<Fragment>,
+    // Meaningless bytes that bring the package up to one of a few standard sizes; see the padding
+    // module. Each Hopper that pads replaces what the previous one put here.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub padding_opt: Option<PlainData>,
+}
+
+// Piece index of count pieces, all of them carried over the same route with the same id
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Fragment {
+    pub id: u64,
+    pub index: u32,
+    pub count: u32,
 }
 
 impl LiveCoresPackage {
     pub fn new(route: Route, payload: CryptData) -> LiveCoresPackage {
-        LiveCoresPackage { route, payload }
+        LiveCoresPackage {
+            route,
+            payload,
+            fragment_opt: None,
+            padding_opt: None,
+        }
     }
 
     pub fn to_next_live(
@@ -27,15 +49,14 @@ impl LiveCoresPackage {
         cryptde: &dyn CryptDE, // must be the CryptDE of the Node to which the top hop is encrypted
     ) -> Result<(LiveHop, LiveCoresPackage), RouteError> {
         let next_hop = self.route.shift(cryptde)?;
-        let next_live = LiveCoresPackage::new(self.route, self.payload);
-        Ok((next_hop, next_live))
+        Ok((next_hop, self))
     }
 
     pub fn from_incipient(
         incipient: IncipientCoresPackage,
         cryptde: &dyn CryptDE, // must be the CryptDE of the Node to which the top hop is encrypted
     ) -> Result<(LiveCoresPackage, PublicKey), String> {
-        let mut route = incipient.route.clone();
+        let mut route = incipient.route;
         let next_hop = match route.shift(cryptde) {
             Ok(h) => h,
             Err(e) => return Err(format!("Could not decrypt next hop: {:?}", e)),
@@ -296,6 +317,12 @@ mod tests {
         let original = LiveCoresPackage {
             route: make_meaningless_route(),
             payload: CryptData::new(&[1, 2, 3, 4]),
+            fragment_opt: Some(Fragment {
+                id: 1234,
+                index: 1,
+                count: 3,
+            }),
+            padding_opt: Some(PlainData::new(&[0, 0, 0])),
         };
 
         let serialized = serde_cbor::ser::to_vec(&original).unwrap();
@@ -1,6 +1,10 @@
 // Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
 
 mod consuming_service;
+mod fair_queue;
+pub mod fragmentation;
+pub mod hop_layer;
 pub mod hopper;
 pub mod live_cores_package;
+pub mod padding;
 mod routing_service;
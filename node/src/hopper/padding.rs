@@ -0,0 +1,138 @@
+// Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use super::live_cores_package::LiveCoresPackage;
+use crate::sub_lib::cryptde::PlainData;
+
+// Serialized packages are padded up to the smallest of these that will hold them; anything bigger
+// than the last one goes up to the next multiple of it
+pub const PADDING_BUCKETS: &[usize] = &[256, 512, 1024, 2048, 4096, 8192, 16384];
+
+pub fn bucket_for(size: usize) -> usize {
+    match PADDING_BUCKETS.iter().find(|bucket| **bucket >= size) {
+        Some(bucket) => *bucket,
+        None => {
+            let largest = PADDING_BUCKETS[PADDING_BUCKETS.len() - 1];
+            ((size + largest - 1) / largest) * largest
+        }
+    }
+}
+
+// The same package, with whatever padding makes it serialize to exactly one of the bucket sizes.
+// Padding it already had is thrown away first.
+pub fn pad(live_package: LiveCoresPackage) -> Result<LiveCoresPackage, String> {
+    let mut live_package = LiveCoresPackage {
+        padding_opt: Some(PlainData::new(&[])),
+        ..live_package
+    };
+    // Everything but the padding bytes and the length that precedes them
+    let base = serialized_len(&live_package)? - length_prefix(0);
+    let mut target = bucket_for(base + 1);
+    let filler = loop {
+        // A few rooms can't be filled exactly, because the length prefix grows as the filler does
+        match filler_for(target - base) {
+            Some(filler) => break filler,
+            None => target = bucket_for(target + 1),
+        }
+    };
+    live_package.padding_opt = Some(PlainData::new(&vec![0u8; filler]));
+    Ok(live_package)
+}
+
+fn serialized_len(live_package: &LiveCoresPackage) -> Result<usize, String> {
+    match serde_cbor::ser::to_vec(live_package) {
+        Ok(serialized) => Ok(serialized.len()),
+        Err(e) => Err(format!("Couldn't serialize package: {}", e)),
+    }
+}
+
+fn filler_for(room: usize) -> Option<usize> {
+    [1, 2, 3, 5]
+        .iter()
+        .find_map(|prefix| match room.checked_sub(*prefix) {
+            Some(filler) if length_prefix(filler) == *prefix => Some(filler),
+            _ => None,
+        })
+}
+
+// How many bytes CBOR spends saying how long a byte string is
+fn length_prefix(len: usize) -> usize {
+    match len {
+        0..=23 => 1,
+        24..=255 => 2,
+        256..=65535 => 3,
+        _ => 5,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sub_lib::cryptde::CryptData;
+    use crate::test_utils::test_utils::make_meaningless_route;
+
+    fn package(payload_len: usize) -> LiveCoresPackage {
+        LiveCoresPackage::new(
+            make_meaningless_route(),
+            CryptData::new(&vec![1u8; payload_len]),
+        )
+    }
+
+    #[test]
+    fn buckets_are_picked_by_size() {
+        assert_eq!(bucket_for(0), 256);
+        assert_eq!(bucket_for(256), 256);
+        assert_eq!(bucket_for(257), 512);
+        assert_eq!(bucket_for(16384), 16384);
+        assert_eq!(bucket_for(16385), 32768);
+        assert_eq!(bucket_for(40000), 49152);
+    }
+
+    #[test]
+    fn padded_packages_serialize_to_bucket_sizes() {
+        (0..20000).step_by(37).for_each(|payload_len| {
+            let unpadded_len = serialized_len(&package(payload_len)).unwrap();
+
+            let padded_len = serialized_len(&pad(package(payload_len)).unwrap()).unwrap();
+
+            assert_eq!(
+                PADDING_BUCKETS.contains(&padded_len) || padded_len % 16384 == 0,
+                true,
+                "{}-byte payload padded to {} bytes",
+                payload_len,
+                padded_len
+            );
+            assert_eq!(padded_len > unpadded_len, true);
+            assert_eq!(padded_len <= bucket_for(bucket_for(unpadded_len) + 1), true);
+        });
+    }
+
+    #[test]
+    fn rooms_that_cannot_be_filled_exactly_go_to_the_next_bucket() {
+        assert_eq!(filler_for(1), Some(0));
+        assert_eq!(filler_for(24), Some(23));
+        assert_eq!(filler_for(25), None);
+        assert_eq!(filler_for(26), Some(24));
+        assert_eq!(filler_for(258), None);
+        assert_eq!(filler_for(259), Some(256));
+    }
+
+    #[test]
+    fn padding_again_replaces_the_old_padding() {
+        let once = pad(package(100)).unwrap();
+
+        let twice = pad(once.clone()).unwrap();
+
+        assert_eq!(twice, once);
+    }
+
+    #[test]
+    fn padding_survives_the_trip_through_the_wire() {
+        let padded = pad(package(100)).unwrap();
+
+        let serialized = serde_cbor::ser::to_vec(&padded).unwrap();
+        let deserialized: LiveCoresPackage = serde_cbor::de::from_slice(&serialized).unwrap();
+
+        assert_eq!(deserialized.route, package(100).route);
+        assert_eq!(deserialized.payload, package(100).payload);
+        assert_eq!(deserialized, padded);
+    }
+}
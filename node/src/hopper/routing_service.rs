@@ -1,34 +1,114 @@
 // Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
 
+use super::fair_queue::FairQueue;
+use super::fair_queue::FAIR_QUEUE_QUANTUM;
+use super::fragmentation::Reassembler;
+use super::fragmentation::REASSEMBLY_TIMEOUT;
+use super::hop_layer::HopLayer;
 use super::live_cores_package::LiveCoresPackage;
+use super::padding;
+#[cfg(feature = "fault_injection")]
+use crate::fault_injection::FaultInjector;
 use crate::sub_lib::accountant::ReportRoutingServiceProvidedMessage;
 use crate::sub_lib::cryptde::CryptDE;
 use crate::sub_lib::cryptde::CryptData;
 use crate::sub_lib::cryptde::CryptdecError;
 use crate::sub_lib::cryptde::PlainData;
+use crate::sub_lib::cryptde::PublicKey;
 use crate::sub_lib::dispatcher::Component;
 use crate::sub_lib::dispatcher::Endpoint;
 use crate::sub_lib::dispatcher::InboundClientData;
 use crate::sub_lib::hop::LiveHop;
+use crate::sub_lib::hopper::CompositePart;
+use crate::sub_lib::hopper::CompositePayload;
 use crate::sub_lib::hopper::ExpiredCoresPackage;
-use crate::sub_lib::hopper::TEMPORARY_PER_ROUTING_BYTE_RATE;
-use crate::sub_lib::hopper::TEMPORARY_PER_ROUTING_RATE;
+use crate::sub_lib::hopper::HopperStatistics;
+use crate::sub_lib::hopper::RegisterEndpointMessage;
+use crate::sub_lib::hopper::RelayFairnessConfig;
 use crate::sub_lib::logger::Logger;
+use crate::sub_lib::metrics::MetricsReport;
+use crate::sub_lib::neighborhood::Misbehavior;
+use crate::sub_lib::neighborhood::NeighborMisbehaviorMessage;
+use crate::sub_lib::neighborhood::RateCard;
+use crate::sub_lib::neighborhood::RoutingLoadMessage;
+use crate::sub_lib::neighborhood::LOAD_SAMPLE_INTERVAL;
 use crate::sub_lib::stream_handler_pool::TransmitDataMsg;
 use crate::sub_lib::wallet::Wallet;
 use actix::Recipient;
 use actix::Syn;
 use std::borrow::Borrow;
+use std::cell::Cell;
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::net::IpAddr;
+use std::time::Duration;
+use std::time::Instant;
+
+// How much of a busy relay's bandwidth a consumer gets, relative to the others
+pub const GOOD_STANDING_WEIGHT: usize = 2;
+pub const DELINQUENT_WEIGHT: usize = 1;
+
+struct RegisteredEndpoint {
+    recipient: Recipient<Syn, ExpiredCoresPackage>,
+    available_on_bootstrap_node: bool,
+}
+
+struct QueuedRelay {
+    consuming_wallet: Wallet,
+    payload_size: usize,
+    transmit_msg: TransmitDataMsg,
+}
+
+// Relayed packages wait here until there's bandwidth to send them. Bandwidth that goes unused
+// piles up, but only a tenth of a second's worth, so a quiet spell can't be followed by a flood.
+struct RelayThrottle {
+    config: RelayFairnessConfig,
+    queue: FairQueue<QueuedRelay>,
+    allowance: i64,
+    last_release: Instant,
+}
+
+impl RelayThrottle {
+    fn release(&mut self, now: Instant) -> Vec<QueuedRelay> {
+        let elapsed = now.duration_since(self.last_release);
+        self.last_release = now;
+        let bytes_per_second = self.config.bytes_per_second;
+        let earned = elapsed.as_secs() * bytes_per_second
+            + u64::from(elapsed.subsec_nanos()) * bytes_per_second / 1_000_000_000;
+        let max_allowance = (bytes_per_second / 10).max(1) as i64;
+        self.allowance = (self.allowance + earned as i64).min(max_allowance);
+        if self.allowance <= 0 {
+            return vec![];
+        }
+        let released = self.queue.pop(self.allowance as usize);
+        released
+            .iter()
+            .for_each(|relay| self.allowance -= relay.transmit_msg.data.len() as i64);
+        released
+    }
+}
 
 pub struct RoutingService {
     cryptde: &'static dyn CryptDE,
     is_bootstrap_node: bool,
-    to_proxy_client: Recipient<Syn, ExpiredCoresPackage>,
-    to_proxy_server: Recipient<Syn, ExpiredCoresPackage>,
-    to_neighborhood: Recipient<Syn, ExpiredCoresPackage>,
+    endpoints: HashMap<Component, RegisteredEndpoint>,
     to_dispatcher: Recipient<Syn, TransmitDataMsg>,
     to_accountant_routing: Recipient<Syn, ReportRoutingServiceProvidedMessage>,
+    to_neighborhood_load: Recipient<Syn, RoutingLoadMessage>,
+    to_neighborhood_misbehavior: Recipient<Syn, NeighborMisbehaviorMessage>,
+    to_metrics_opt: Option<Recipient<Syn, MetricsReport>>,
+    load_sample_interval: Duration,
+    rate_card: RateCard,
+    load_sample_start: Cell<Instant>,
+    bytes_routed: Cell<u64>,
+    statistics: RefCell<HopperStatistics>,
+    hop_layer: RefCell<HopLayer>,
+    package_buffer: RefCell<Vec<u8>>,
+    reassembler: RefCell<Reassembler>,
+    pad_packets: bool,
+    relay_throttle_opt: Option<RefCell<RelayThrottle>>,
+    #[cfg(feature = "fault_injection")]
+    fault_injector: FaultInjector,
     logger: Logger,
 }
 
@@ -41,30 +121,164 @@ impl RoutingService {
         to_neighborhood: Recipient<Syn, ExpiredCoresPackage>,
         to_dispatcher: Recipient<Syn, TransmitDataMsg>,
         to_accountant_routing: Recipient<Syn, ReportRoutingServiceProvidedMessage>,
+        to_neighborhood_load: Recipient<Syn, RoutingLoadMessage>,
+        to_neighborhood_misbehavior: Recipient<Syn, NeighborMisbehaviorMessage>,
     ) -> RoutingService {
+        let mut endpoints = HashMap::new();
+        endpoints.insert(
+            Component::ProxyClient,
+            RegisteredEndpoint {
+                recipient: to_proxy_client,
+                available_on_bootstrap_node: false,
+            },
+        );
+        endpoints.insert(
+            Component::ProxyServer,
+            RegisteredEndpoint {
+                recipient: to_proxy_server,
+                available_on_bootstrap_node: false,
+            },
+        );
+        endpoints.insert(
+            Component::Neighborhood,
+            RegisteredEndpoint {
+                recipient: to_neighborhood,
+                available_on_bootstrap_node: true,
+            },
+        );
         RoutingService {
             cryptde,
             is_bootstrap_node,
-            to_proxy_client,
-            to_proxy_server,
-            to_neighborhood,
+            endpoints,
             to_dispatcher,
             to_accountant_routing,
+            to_neighborhood_load,
+            to_neighborhood_misbehavior,
+            to_metrics_opt: None,
+            load_sample_interval: LOAD_SAMPLE_INTERVAL,
+            rate_card: RateCard::default(),
+            load_sample_start: Cell::new(Instant::now()),
+            bytes_routed: Cell::new(0),
+            statistics: RefCell::new(HopperStatistics::default()),
+            hop_layer: RefCell::new(HopLayer::new()),
+            package_buffer: RefCell::new(vec![]),
+            reassembler: RefCell::new(Reassembler::new(REASSEMBLY_TIMEOUT)),
+            pad_packets: false,
+            relay_throttle_opt: None,
+            #[cfg(feature = "fault_injection")]
+            fault_injector: FaultInjector::global(),
             logger: Logger::new("RoutingService"),
         }
     }
 
+    pub fn sample_load_every(&mut self, interval: Duration) {
+        self.load_sample_interval = interval;
+    }
+
+    pub fn charge(&mut self, rate_card: RateCard) {
+        self.rate_card = rate_card;
+    }
+
+    pub fn report_metrics_to(&mut self, to_metrics: Recipient<Syn, MetricsReport>) {
+        self.to_metrics_opt = Some(to_metrics);
+    }
+
+    // A relayed package is a hop shorter than the one that came in, so its padding is done over
+    pub fn pad_packets(&mut self, pad_packets: bool) {
+        self.pad_packets = pad_packets;
+    }
+
+    // Relayed packages wait their turn instead of going straight out. Each consumer can have a
+    // second's worth of them waiting; past that, its packages are dropped.
+    pub fn limit_relay_throughput(&mut self, config: &RelayFairnessConfig) {
+        self.relay_throttle_opt = Some(RefCell::new(RelayThrottle {
+            config: *config,
+            queue: FairQueue::new(
+                FAIR_QUEUE_QUANTUM,
+                config.bytes_per_second as usize,
+                GOOD_STANDING_WEIGHT,
+            ),
+            allowance: 0,
+            last_release: Instant::now(),
+        }));
+    }
+
+    // Sends on as many waiting relayed packages as the bandwidth earned since last time allows
+    pub fn release_relayed(&self, now: Instant) {
+        let released = match self.relay_throttle_opt.as_ref() {
+            Some(throttle) => throttle.borrow_mut().release(now),
+            None => return,
+        };
+        released.into_iter().for_each(|relay| self.relay(relay));
+    }
+
+    pub fn set_payment_standing(&self, delinquent_wallets: &[Wallet]) {
+        let throttle = match self.relay_throttle_opt.as_ref() {
+            Some(throttle) => throttle,
+            None => return,
+        };
+        let mut throttle = throttle.borrow_mut();
+        if !throttle.config.weighted_by_standing {
+            return;
+        }
+        throttle.queue.set_weights(
+            delinquent_wallets
+                .iter()
+                .map(|wallet| (wallet.clone(), DELINQUENT_WEIGHT))
+                .collect(),
+        );
+    }
+
+    pub fn register_endpoint(&mut self, msg: RegisterEndpointMessage) {
+        if msg.component == Component::Hopper {
+            panic!("The Hopper can't be registered as an endpoint for its own packages");
+        }
+        if msg.component == Component::Composite {
+            panic!("Composite packages are split up by the Hopper and can't have an endpoint");
+        }
+        self.logger
+            .debug(format!("Registered endpoint for {:?}", msg.component));
+        self.endpoints.insert(
+            msg.component,
+            RegisteredEndpoint {
+                recipient: msg.recipient,
+                available_on_bootstrap_node: msg.available_on_bootstrap_node,
+            },
+        );
+    }
+
+    pub fn statistics(&self) -> HopperStatistics {
+        self.statistics.borrow().clone()
+    }
+
     pub fn route(&self, ibcd: InboundClientData) {
         let data_size = ibcd.data.len();
         self.logger.debug(format!(
             "Received {} bytes of InboundClientData from Dispatcher",
             data_size
         ));
+        self.report_metric(MetricsReport::CoresPackagesProcessed(1));
+        self.report_metric(MetricsReport::BytesRouted(data_size as u64));
         let sender_ip = ibcd.peer_addr.ip();
+        let sender_key_opt = ibcd.neighbor_key_opt.clone();
+        if let Some(sender_key) = &sender_key_opt {
+            self.count(|stats| *stats.received_from.entry(sender_key.clone()).or_insert(0) += 1);
+        }
         let last_data = ibcd.last_data;
         let live_package = match self.decrypt_and_deserialize_lcp(ibcd) {
             Ok(package) => package,
-            Err(_) => return (), // log already written
+            Err(_) => {
+                self.count(|stats| stats.decrypt_failures += 1);
+                self.report_undeliverable(&sender_key_opt);
+                return (); // log already written
+            }
+        };
+        let live_package = match self.inject_faults(live_package) {
+            Some(package) => package,
+            None => {
+                self.count(|stats| stats.dropped += 1);
+                return ();
+            }
         };
 
         let next_hop = match live_package.route.next_hop(self.cryptde.borrow()) {
@@ -72,27 +286,72 @@ impl RoutingService {
             Err(e) => {
                 self.logger
                     .error(format!("Invalid {}-byte CORES package: {:?}", data_size, e));
+                self.count(|stats| stats.dropped += 1);
+                self.report_undeliverable(&sender_key_opt);
                 return ();
             }
         };
 
         if self.should_route_data(next_hop.component) {
-            self.route_data(sender_ip, next_hop, live_package, last_data);
+            self.route_data(sender_ip, sender_key_opt, next_hop, live_package, last_data);
+        } else {
+            self.count(|stats| stats.dropped += 1);
         }
         ()
     }
 
+    // A neighbor that hands us packages we can't make sense of is either broken or hostile
+    fn report_undeliverable(&self, sender_key_opt: &Option<PublicKey>) {
+        if let Some(sender_key) = sender_key_opt {
+            self.to_neighborhood_misbehavior
+                .try_send(NeighborMisbehaviorMessage {
+                    public_key: sender_key.clone(),
+                    misbehavior: Misbehavior::UndeliverablePackage,
+                })
+                .expect("Neighborhood is dead");
+        }
+    }
+
     fn route_data(
         &self,
         sender_ip: IpAddr,
+        sender_key_opt: Option<PublicKey>,
         next_hop: LiveHop,
         live_package: LiveCoresPackage,
         last_data: bool,
     ) {
         if next_hop.component == Component::Hopper {
-            self.route_data_externally(live_package, next_hop.consuming_wallet, last_data);
-        } else {
-            self.route_data_internally(next_hop.component, sender_ip, live_package)
+            self.route_data_externally(live_package, next_hop, last_data);
+        } else if let Some(live_package) = self.reassemble(live_package) {
+            self.route_data_internally(next_hop.component, sender_ip, sender_key_opt, live_package)
+        }
+    }
+
+    // Fragments wait here until the last piece of their payload arrives
+    fn reassemble(&self, live_package: LiveCoresPackage) -> Option<LiveCoresPackage> {
+        if live_package.fragment_opt.is_none() {
+            return Some(live_package);
+        }
+        let now = Instant::now();
+        let mut reassembler = self.reassembler.borrow_mut();
+        reassembler
+            .expire(now)
+            .into_iter()
+            .for_each(|(id, received, count)| {
+                self.logger.warning(format!(
+                    "Gave up waiting for payload {}: only {} of its {} fragments arrived",
+                    id, received, count
+                ));
+                self.count(|stats| stats.dropped += 1);
+            });
+        match reassembler.add(live_package, now) {
+            Ok(live_package_opt) => live_package_opt,
+            Err(e) => {
+                self.logger
+                    .error(format!("Discarding CORES package: {}", e));
+                self.count(|stats| stats.dropped += 1);
+                None
+            }
         }
     }
 
@@ -100,97 +359,266 @@ impl RoutingService {
         &self,
         component: Component,
         immediate_neighbor_ip: IpAddr,
+        immediate_neighbor_key_opt: Option<PublicKey>,
         live_package: LiveCoresPackage,
     ) {
-        match component {
-            Component::Hopper => panic!("Internal error"),
-            Component::ProxyServer => self.handle_endpoint(
-                component,
-                &self.to_proxy_server,
-                live_package,
+        if component == Component::Composite {
+            return self.route_composite_internally(
                 immediate_neighbor_ip,
-            ),
-            Component::ProxyClient => self.handle_endpoint(
-                component,
-                &self.to_proxy_client,
+                immediate_neighbor_key_opt,
                 live_package,
-                immediate_neighbor_ip,
-            ),
-            Component::Neighborhood => self.handle_endpoint(
+            );
+        }
+        match self.endpoints.get(&component) {
+            Some(endpoint) => self.handle_endpoint(
                 component,
-                &self.to_neighborhood,
+                &endpoint.recipient,
                 live_package,
                 immediate_neighbor_ip,
+                immediate_neighbor_key_opt,
             ),
+            None => {
+                self.logger.error(format!(
+                    "No endpoint registered for {:?}: discarding CORES package with {}-byte payload",
+                    component,
+                    live_package.payload.len()
+                ));
+                self.count(|stats| stats.dropped += 1);
+            }
+        }
+    }
+
+    // Each part goes to its own Component as though it had come in a package of its own; a part that
+    // can't be delivered is dropped without disturbing the others.
+    fn route_composite_internally(
+        &self,
+        immediate_neighbor_ip: IpAddr,
+        immediate_neighbor_key_opt: Option<PublicKey>,
+        live_package: LiveCoresPackage,
+    ) {
+        let data_len = live_package.payload.len();
+        let mut expired_package =
+            match live_package.to_expired(immediate_neighbor_ip, self.cryptde.borrow()) {
+                Ok(pkg) => pkg,
+                Err(e) => {
+                    self.logger.error(format!(
+                        "Couldn't expire composite CORES package with {}-byte payload: {:?}",
+                        data_len, e
+                    ));
+                    self.count(|stats| stats.expire_failures += 1);
+                    return ();
+                }
+            };
+        expired_package.immediate_neighbor_key_opt = immediate_neighbor_key_opt;
+        let composite = match expired_package.payload::<CompositePayload>(self.cryptde.borrow()) {
+            Ok(composite) => composite,
+            Err(e) => {
+                self.logger.error(format!(
+                    "Couldn't decode composite payload from {}-byte CORES package: {}",
+                    data_len, e
+                ));
+                self.count(|stats| stats.dropped += 1);
+                return ();
+            }
+        };
+        composite
+            .parts
+            .into_iter()
+            .for_each(|part| self.route_composite_part(&expired_package, part));
+    }
+
+    fn route_composite_part(&self, expired_package: &ExpiredCoresPackage, part: CompositePart) {
+        let component = part.component;
+        if component == Component::Hopper || component == Component::Composite {
+            self.logger.error(format!(
+                "Composite payload can't carry a part for {:?}: discarding {}-byte part",
+                component,
+                part.data.len()
+            ));
+            self.count(|stats| stats.dropped += 1);
+            return ();
+        }
+        if !self.should_route_data(component) {
+            self.count(|stats| stats.dropped += 1);
+            return ();
         }
+        let endpoint = match self.endpoints.get(&component) {
+            Some(endpoint) => endpoint,
+            None => {
+                self.logger.error(format!(
+                    "No endpoint registered for {:?}: discarding {}-byte part of composite payload",
+                    component,
+                    part.data.len()
+                ));
+                self.count(|stats| stats.dropped += 1);
+                return ();
+            }
+        };
+        let payload = match self.cryptde.encode(&self.cryptde.public_key(), &part.data) {
+            Ok(payload) => payload,
+            Err(e) => {
+                self.logger.error(format!(
+                    "Couldn't re-encrypt {}-byte part of composite payload for {:?}: {:?}",
+                    part.data.len(),
+                    component,
+                    e
+                ));
+                self.count(|stats| stats.dropped += 1);
+                return ();
+            }
+        };
+        let part_package = ExpiredCoresPackage {
+            payload,
+            ..expired_package.clone()
+        };
+        self.logger.trace(format!(
+            "Forwarding part of composite payload to {:?}: {:?}",
+            component, part_package
+        ));
+        endpoint
+            .recipient
+            .try_send(part_package)
+            .expect(&format!("{:?} is dead", component));
+        self.count(|stats| *stats.delivered.entry(component).or_insert(0) += 1);
     }
 
     fn route_data_externally(
         &self,
         live_package: LiveCoresPackage,
-        consuming_wallet_opt: Option<Wallet>,
+        next_hop: LiveHop,
         last_data: bool,
     ) {
         let payload_size = live_package.payload.len();
-        match consuming_wallet_opt {
-            Some(consuming_wallet) => self
-                .to_accountant_routing
-                .try_send(ReportRoutingServiceProvidedMessage {
-                    consuming_wallet,
-                    payload_size,
-                    service_rate: TEMPORARY_PER_ROUTING_RATE,
-                    byte_rate: TEMPORARY_PER_ROUTING_BYTE_RATE,
-                })
-                .expect("Accountant is dead"),
+        let consuming_wallet = match next_hop.consuming_wallet {
+            Some(consuming_wallet) => consuming_wallet,
             None => {
                 self.logger.error(format!(
                     "Refusing to route CORES package with {}-byte payload without consuming wallet",
                     payload_size
                 ));
+                self.count(|stats| stats.dropped += 1);
                 return ();
             }
-        }
+        };
 
-        let transmit_msg = match self.to_transmit_data_msg(live_package, last_data) {
-            // crashpoint - need to figure out how to bubble up different kinds of errors, or just log and return
-            Err(_) => unimplemented!(),
-            Ok(m) => m,
+        let transmit_msg =
+            match self.to_transmit_data_msg(live_package, next_hop.public_key, last_data) {
+                // crashpoint - need to figure out how to bubble up different kinds of errors, or just log and return
+                Err(_) => unimplemented!(),
+                Ok(m) => m,
+            };
+        let relay = QueuedRelay {
+            consuming_wallet,
+            payload_size,
+            transmit_msg,
+        };
+
+        let throttle = match self.relay_throttle_opt.as_ref() {
+            Some(throttle) => throttle,
+            None => return self.relay(relay),
         };
+        let transmit_size = relay.transmit_msg.data.len();
+        let consuming_wallet = relay.consuming_wallet.clone();
+        if let Err(relay) =
+            throttle
+                .borrow_mut()
+                .queue
+                .push(&consuming_wallet, transmit_size, relay)
+        {
+            self.logger.debug(format!(
+                "Relay queue for {} is full: dropping CORES package with {}-byte payload",
+                consuming_wallet.address, relay.payload_size
+            ));
+            self.count(|stats| stats.dropped += 1);
+        }
+    }
 
+    // Consumers are charged only for the packages that actually go on
+    fn relay(&self, relay: QueuedRelay) {
+        self.to_accountant_routing
+            .try_send(ReportRoutingServiceProvidedMessage {
+                consuming_wallet: relay.consuming_wallet,
+                payload_size: relay.payload_size,
+                service_rate: self.rate_card.routing_service_rate,
+                byte_rate: self.rate_card.routing_byte_rate,
+                correlation_id_opt: None,
+            })
+            .expect("Accountant is dead");
         self.logger.debug(format!(
             "Relaying {}-byte LiveCoresPackage Dispatcher inside a TransmitDataMsg",
-            transmit_msg.data.len()
+            relay.transmit_msg.data.len()
         ));
+        let transmit_size = relay.transmit_msg.data.len();
         self.to_dispatcher
-            .try_send(transmit_msg)
+            .try_send(relay.transmit_msg)
             .expect("Dispatcher is dead");
+        self.count(|stats| stats.forwarded += 1);
+        self.record_routed_bytes(transmit_size);
+    }
+
+    fn record_routed_bytes(&self, bytes: usize) {
+        self.bytes_routed
+            .set(self.bytes_routed.get() + bytes as u64);
+        let interval = self.load_sample_start.get().elapsed();
+        if interval < self.load_sample_interval {
+            return;
+        }
+        self.to_neighborhood_load
+            .try_send(RoutingLoadMessage {
+                bytes_routed: self.bytes_routed.get(),
+                interval,
+            })
+            .expect("Neighborhood is dead");
+        self.bytes_routed.set(0);
+        self.load_sample_start.set(Instant::now());
     }
 
+    // The top hop has already been decoded into next_hop_key by route(); don't decode it again.
     fn to_transmit_data_msg(
         &self,
         live_package: LiveCoresPackage,
+        next_hop_key: PublicKey,
         last_data: bool,
     ) -> Result<TransmitDataMsg, CryptdecError> {
-        let (next_hop, next_live_package) = match live_package.to_next_live(self.cryptde.borrow()) {
+        let mut next_live_package = live_package;
+        match next_live_package
+            .route
+            .discard_top_hop(self.cryptde.borrow())
+        {
             // crashpoint - log error and return None?
             Err(_) => unimplemented!(),
-            Ok(p) => p,
+            Ok(()) => (),
         };
-        let next_live_package_ser = match serde_cbor::ser::to_vec(&next_live_package) {
+        if self.pad_packets {
+            next_live_package = match padding::pad(next_live_package) {
+                // crashpoint - log error and return None?
+                Err(_) => unimplemented!(),
+                Ok(p) => p,
+            };
+        }
+        // Serialize into the buffer the last package left behind, so that it doesn't have to be
+        // grown from nothing for every package relayed
+        let mut buffer = self.package_buffer.replace(vec![]);
+        buffer.clear();
+        match serde_cbor::ser::to_writer(&mut buffer, &next_live_package) {
             // crashpoint - log error and return None?
             Err(_) => unimplemented!(),
-            Ok(p) => p,
+            Ok(()) => (),
         };
-        let next_live_package_enc = match self.cryptde.encode(
-            &next_hop.public_key,
-            &PlainData::new(&next_live_package_ser[..]),
+        let next_live_package_ser = PlainData::from(buffer);
+        let next_live_package_enc = match self.hop_layer.borrow_mut().encode(
+            self.cryptde,
+            &next_hop_key,
+            &next_live_package_ser,
+            Instant::now(),
         ) {
             // crashpoint - log error and return None?
             Err(_) => unimplemented!(),
             Ok(p) => p,
         };
+        self.package_buffer.replace(next_live_package_ser.into());
         Ok(TransmitDataMsg {
-            endpoint: Endpoint::Key(next_hop.public_key),
+            endpoint: Endpoint::Key(next_hop_key),
             last_data,
             data: next_live_package_enc.into(),
             sequence_number: None,
@@ -198,7 +626,14 @@ impl RoutingService {
     }
 
     fn should_route_data(&self, component: Component) -> bool {
-        if component == Component::Neighborhood {
+        if component == Component::Composite {
+            return true; // each part is checked on its own once the package is split up
+        }
+        let available_on_bootstrap_node = match self.endpoints.get(&component) {
+            Some(endpoint) => endpoint.available_on_bootstrap_node,
+            None => false,
+        };
+        if available_on_bootstrap_node {
             true
         } else if self.is_bootstrap_node {
             self.logger.error(format!(
@@ -217,9 +652,10 @@ impl RoutingService {
         recipient: &Recipient<Syn, ExpiredCoresPackage>,
         live_package: LiveCoresPackage,
         immediate_neighbor_ip: IpAddr,
+        immediate_neighbor_key_opt: Option<PublicKey>,
     ) {
         let data_len = live_package.payload.len();
-        let expired_package =
+        let mut expired_package =
             match live_package.to_expired(immediate_neighbor_ip, self.cryptde.borrow()) {
                 Ok(pkg) => pkg,
                 Err(e) => {
@@ -227,26 +663,85 @@ impl RoutingService {
                         "Couldn't expire CORES package with {}-byte payload: {:?}",
                         data_len, e
                     ));
+                    self.count(|stats| stats.expire_failures += 1);
                     return ();
                 }
             };
+        expired_package.immediate_neighbor_key_opt = immediate_neighbor_key_opt;
         self.logger.trace(format!(
             "Forwarding ExpiredCoresPackage to {:?}: {:?}",
             component, expired_package
         ));
         recipient
             .try_send(expired_package)
-            .expect(&format!("{:?} is dead", component))
+            .expect(&format!("{:?} is dead", component));
+        self.count(|stats| *stats.delivered.entry(component).or_insert(0) += 1);
+    }
+
+    fn count<F>(&self, f: F)
+    where
+        F: FnOnce(&mut HopperStatistics),
+    {
+        let dropped = {
+            let mut statistics = self.statistics.borrow_mut();
+            let dropped_before = statistics.total_dropped();
+            f(&mut statistics);
+            statistics.total_dropped() - dropped_before
+        };
+        if dropped > 0 {
+            self.report_metric(MetricsReport::CoresPackagesDropped(dropped));
+        }
+    }
+
+    fn report_metric(&self, report: MetricsReport) {
+        if let Some(to_metrics) = self.to_metrics_opt.as_ref() {
+            to_metrics.try_send(report).expect("Metrics is dead");
+        }
+    }
+
+    #[cfg(not(feature = "fault_injection"))]
+    fn inject_faults(&self, live_package: LiveCoresPackage) -> Option<LiveCoresPackage> {
+        Some(live_package)
+    }
+
+    #[cfg(feature = "fault_injection")]
+    fn inject_faults(&self, live_package: LiveCoresPackage) -> Option<LiveCoresPackage> {
+        if let Some(delay) = self.fault_injector.hopper_delay() {
+            std::thread::sleep(delay);
+        }
+        if self.fault_injector.take_drop() {
+            self.logger.warning(format!(
+                "Fault injection: dropping CORES package with {}-byte payload",
+                live_package.payload.len()
+            ));
+            return None;
+        }
+        if self.fault_injector.take_corruption() {
+            self.logger.warning(format!(
+                "Fault injection: corrupting CORES package with {}-byte payload",
+                live_package.payload.len()
+            ));
+            let payload = CryptData::from(FaultInjector::corrupt(live_package.payload.as_slice()));
+            return Some(LiveCoresPackage {
+                payload,
+                ..live_package
+            });
+        }
+        Some(live_package)
     }
 
     fn decrypt_and_deserialize_lcp(&self, ibcd: InboundClientData) -> Result<LiveCoresPackage, ()> {
-        let decrypted_package = match self.cryptde.decode(&CryptData::new(&ibcd.data[..])) {
+        let data_len = ibcd.data.len();
+        let decrypted_package = match self.hop_layer.borrow_mut().decode(
+            self.cryptde,
+            &CryptData::from(ibcd.data),
+            Instant::now(),
+        ) {
             Ok(package) => package,
             Err(e) => {
                 self.logger.error(format!(
                     "Couldn't decrypt CORES package from {}-byte buffer: {:?}",
-                    ibcd.data.len(),
-                    e
+                    data_len, e
                 ));
                 return Err(());
             }
@@ -266,13 +761,19 @@ impl RoutingService {
 
 #[cfg(test)]
 mod tests {
+    use super::super::fragmentation;
     use super::super::hopper::Hopper;
+    use super::super::live_cores_package::Fragment;
     use super::*;
     use crate::sub_lib::accountant::ReportRoutingServiceProvidedMessage;
+    use crate::sub_lib::cryptde::encodex;
     use crate::sub_lib::cryptde::PublicKey;
     use crate::sub_lib::cryptde_null::CryptDENull;
     use crate::sub_lib::hopper::IncipientCoresPackage;
+    use crate::sub_lib::hopper::DEFAULT_PER_ROUTING_BYTE_RATE;
+    use crate::sub_lib::hopper::DEFAULT_PER_ROUTING_RATE;
     use crate::sub_lib::peer_actors::BindMessage;
+    use crate::sub_lib::peer_actors::PeerActors;
     use crate::sub_lib::route::Route;
     use crate::sub_lib::route::RouteSegment;
     use crate::sub_lib::wallet::Wallet;
@@ -316,6 +817,7 @@ mod tests {
             last_data: false,
             is_clandestine: false,
             data: data_enc.into(),
+            neighbor_key_opt: None,
         };
         thread::spawn(move || {
             let system = System::new("converts_live_message_to_expired_for_proxy_client");
@@ -337,6 +839,88 @@ mod tests {
         assert_eq!(*record, expected_ecp);
     }
 
+    #[test]
+    fn registered_endpoint_replaces_default_and_can_be_opened_to_bootstrap_nodes() {
+        let cryptde = cryptde();
+        let (default_proxy_client, _, default_proxy_client_recording_arc) = make_recorder();
+        let (registered, registered_awaiter, registered_recording_arc) = make_recorder();
+        let route = route_to_proxy_client(&cryptde.public_key(), cryptde);
+        let payload = PlainData::new(&b"abcd"[..]);
+        let lcp = LiveCoresPackage::new(
+            route,
+            cryptde.encode(&cryptde.public_key(), &payload).unwrap(),
+        );
+        let lcp_a = lcp.clone();
+        let data_ser = PlainData::new(&serde_cbor::ser::to_vec(&lcp).unwrap()[..]);
+        let data_enc = cryptde.encode(&cryptde.public_key(), &data_ser).unwrap();
+        let inbound_client_data = InboundClientData {
+            peer_addr: SocketAddr::from_str("1.2.3.4:5678").unwrap(),
+            reception_port: None,
+            sequence_number: None,
+            last_data: false,
+            is_clandestine: false,
+            data: data_enc.into(),
+            neighbor_key_opt: None,
+        };
+        thread::spawn(move || {
+            let system = System::new(
+                "registered_endpoint_replaces_default_and_can_be_opened_to_bootstrap_nodes",
+            );
+            let registered_addr: Addr<Syn, Recorder> = registered.start();
+            let peer_actors = peer_actors_builder()
+                .proxy_client(default_proxy_client)
+                .build();
+            let subject = Hopper::new(cryptde, true);
+            let subject_addr: Addr<Syn, Hopper> = subject.start();
+            subject_addr
+                .try_send(RegisterEndpointMessage {
+                    component: Component::ProxyClient,
+                    recipient: registered_addr.recipient::<ExpiredCoresPackage>(),
+                    available_on_bootstrap_node: true,
+                })
+                .unwrap();
+            subject_addr.try_send(BindMessage { peer_actors }).unwrap();
+
+            subject_addr.try_send(inbound_client_data).unwrap();
+
+            system.run();
+        });
+        registered_awaiter.await_message_count(1);
+        let registered_recording = registered_recording_arc.lock().unwrap();
+        let record = registered_recording.get_record::<ExpiredCoresPackage>(0);
+        let expected_ecp = lcp_a
+            .to_expired(IpAddr::from_str("1.2.3.4").unwrap(), cryptde)
+            .unwrap();
+        assert_eq!(*record, expected_ecp);
+        assert_eq!(default_proxy_client_recording_arc.lock().unwrap().len(), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "The Hopper can't be registered as an endpoint for its own packages")]
+    fn hopper_cannot_be_registered_as_an_endpoint() {
+        let _system = System::new("hopper_cannot_be_registered_as_an_endpoint");
+        let (recorder, _, _) = make_recorder();
+        let recorder_addr: Addr<Syn, Recorder> = recorder.start();
+        let peer_actors = peer_actors_builder().build();
+        let mut subject = RoutingService::new(
+            cryptde(),
+            false,
+            peer_actors.proxy_client.from_hopper,
+            peer_actors.proxy_server.from_hopper,
+            peer_actors.neighborhood.from_hopper,
+            peer_actors.dispatcher.from_dispatcher_client,
+            peer_actors.accountant.report_routing_service_provided,
+            peer_actors.neighborhood.routing_load,
+            peer_actors.neighborhood.neighbor_misbehavior,
+        );
+
+        subject.register_endpoint(RegisterEndpointMessage {
+            component: Component::Hopper,
+            recipient: recorder_addr.recipient::<ExpiredCoresPackage>(),
+            available_on_bootstrap_node: false,
+        });
+    }
+
     #[test] // TODO: Rewrite test so that subject is RoutingService rather than Hopper
     fn converts_live_message_to_expired_for_proxy_server() {
         let cryptde = cryptde();
@@ -359,6 +943,7 @@ mod tests {
             is_clandestine: false,
             sequence_number: None,
             data: data_enc.into(),
+            neighbor_key_opt: None,
         };
         thread::spawn(move || {
             let system = System::new("converts_live_message_to_expired_for_proxy_server");
@@ -399,6 +984,7 @@ mod tests {
             is_clandestine: false,
             sequence_number: None,
             data: data_enc.into(),
+            neighbor_key_opt: None,
         };
         let system = System::new("refuses_data_for_proxy_client_if_is_bootstrap_node");
         let subject = Hopper::new(cryptde, true);
@@ -434,6 +1020,7 @@ mod tests {
             last_data: false,
             is_clandestine: false,
             data: data_enc.into(),
+            neighbor_key_opt: None,
         };
         let system = System::new("refuses_data_for_proxy_server_if_is_bootstrap_node");
         let subject = Hopper::new(cryptde, true);
@@ -478,6 +1065,7 @@ mod tests {
             is_clandestine: true,
             sequence_number: None,
             data: data_enc.into(),
+            neighbor_key_opt: None,
         };
         let system = System::new("refuses_data_for_hopper_if_is_bootstrap_node");
         let subject = Hopper::new(cryptde, true);
@@ -520,6 +1108,7 @@ mod tests {
             is_clandestine: true,
             sequence_number: None,
             data: data_enc.into(),
+            neighbor_key_opt: None,
         };
         let system = System::new("accepts_data_for_neighborhood_if_is_bootstrap_node");
         let subject = Hopper::new(cryptde, true);
@@ -573,6 +1162,7 @@ mod tests {
             is_clandestine: true,
             sequence_number: None,
             data: data_enc.into(),
+            neighbor_key_opt: None,
         };
         let system =
             System::new("rejects_data_for_non_neighborhood_component_if_is_bootstrap_node");
@@ -621,6 +1211,7 @@ mod tests {
             is_clandestine: false,
             sequence_number: None,
             data: data_enc.into(),
+            neighbor_key_opt: None,
         };
         thread::spawn(move || {
             let system = System::new("converts_live_message_to_expired_for_proxy_server");
@@ -641,16 +1232,17 @@ mod tests {
         let record = dispatcher_recording.get_record::<TransmitDataMsg>(0);
         let expected_lcp = lcp_a.to_next_live(cryptde).unwrap().1;
         let expected_lcp_ser = PlainData::new(&serde_cbor::ser::to_vec(&expected_lcp).unwrap());
-        let expected_lcp_enc = cryptde.encode(&next_key, &expected_lcp_ser).unwrap();
-        assert_eq!(
-            *record,
-            TransmitDataMsg {
-                endpoint: Endpoint::Key(next_key.clone()),
-                last_data: true,
-                sequence_number: None,
-                data: expected_lcp_enc.into(),
-            }
-        );
+        assert_eq!(record.endpoint, Endpoint::Key(next_key.clone()));
+        assert_eq!(record.last_data, true);
+        assert_eq!(record.sequence_number, None);
+        let lcp_ser = HopLayer::new()
+            .decode(
+                &CryptDENull::from(&next_key),
+                &CryptData::new(&record.data[..]),
+                Instant::now(),
+            )
+            .unwrap();
+        assert_eq!(lcp_ser, expected_lcp_ser);
         accountant_awaiter.await_message_count(1);
         let accountant_recording = accountant_recording_arc.lock().unwrap();
         let message = accountant_recording.get_record::<ReportRoutingServiceProvidedMessage>(0);
@@ -659,66 +1251,828 @@ mod tests {
             ReportRoutingServiceProvidedMessage {
                 consuming_wallet,
                 payload_size: lcp.payload.len(),
-                service_rate: TEMPORARY_PER_ROUTING_RATE,
-                byte_rate: TEMPORARY_PER_ROUTING_BYTE_RATE
+                service_rate: DEFAULT_PER_ROUTING_RATE,
+                byte_rate: DEFAULT_PER_ROUTING_BYTE_RATE,
+                correlation_id_opt: None
             }
         )
     }
 
     #[test]
-    fn route_logs_and_ignores_cores_package_that_demands_routing_without_consuming_wallet() {
-        init_test_logging();
+    fn relayed_packages_are_padded_again_when_asked() {
         let cryptde = cryptde();
-        let origin_key = PublicKey::new(&[1, 2]);
-        let origin_cryptde = CryptDENull::from(&origin_key);
-        let destination_key = PublicKey::new(&[3, 4]);
-        let payload = PayloadMock::new();
+        let (dispatcher, dispatcher_awaiter, dispatcher_recording_arc) = make_recorder();
+        let next_key = PublicKey::new(&[65, 65, 65]);
         let route = Route::one_way(
             RouteSegment::new(
-                vec![&origin_key, &cryptde.public_key(), &destination_key],
-                Component::ProxyClient,
+                vec![&cryptde.public_key(), &next_key],
+                Component::Neighborhood,
             ),
-            &origin_cryptde,
-            None,
+            cryptde,
+            Some(Wallet::new("wallet")),
         )
         .unwrap();
-        let icp =
-            IncipientCoresPackage::new(&origin_cryptde, route, payload, &destination_key).unwrap();
-        let (lcp, _) = LiveCoresPackage::from_incipient(icp, &origin_cryptde).unwrap();
+        let payload = PlainData::new(&[0x5A; 3000]);
+        let lcp = padding::pad(LiveCoresPackage::new(
+            route,
+            cryptde.encode(&next_key, &payload).unwrap(),
+        ))
+        .unwrap();
         let data_ser = PlainData::new(&serde_cbor::ser::to_vec(&lcp).unwrap()[..]);
         let data_enc = cryptde.encode(&cryptde.public_key(), &data_ser).unwrap();
         let inbound_client_data = InboundClientData {
             peer_addr: SocketAddr::from_str("1.2.3.4:5678").unwrap(),
             reception_port: None,
-            last_data: true,
+            last_data: false,
             is_clandestine: true,
             sequence_number: None,
             data: data_enc.into(),
+            neighbor_key_opt: None,
         };
-        let system = System::new(
-            "route_logs_and_ignores_cores_package_that_demands_routing_without_consuming_wallet",
-        );
-        let (proxy_client, _, proxy_client_recording_arc) = make_recorder();
-        let (proxy_server, _, proxy_server_recording_arc) = make_recorder();
-        let (neighborhood, _, neighborhood_recording_arc) = make_recorder();
-        let (dispatcher, _, dispatcher_recording_arc) = make_recorder();
-        let peer_actors = peer_actors_builder()
-            .proxy_client(proxy_client)
-            .proxy_server(proxy_server)
-            .neighborhood(neighborhood)
-            .dispatcher(dispatcher)
-            .build();
-        let subject = RoutingService::new(
-            cryptde,
-            false,
-            peer_actors.proxy_client.from_hopper,
-            peer_actors.proxy_server.from_hopper,
-            peer_actors.neighborhood.from_hopper,
-            peer_actors.dispatcher.from_dispatcher_client,
-            peer_actors.accountant.report_routing_service_provided,
-        );
+        thread::spawn(move || {
+            let system = System::new("relayed_packages_are_padded_again_when_asked");
+            let peer_actors = peer_actors_builder().dispatcher(dispatcher).build();
+            let mut subject = Hopper::new(cryptde, false);
+            subject.pad_packets(true);
+            let subject_addr: Addr<Syn, Hopper> = subject.start();
+            subject_addr.try_send(BindMessage { peer_actors }).unwrap();
 
-        subject.route(inbound_client_data);
+            subject_addr.try_send(inbound_client_data).unwrap();
+
+            system.run();
+        });
+        dispatcher_awaiter.await_message_count(1);
+        let dispatcher_recording = dispatcher_recording_arc.lock().unwrap();
+        let record = dispatcher_recording.get_record::<TransmitDataMsg>(0);
+        let lcp_ser = HopLayer::new()
+            .decode(
+                &CryptDENull::from(&next_key),
+                &CryptData::new(&record.data[..]),
+                Instant::now(),
+            )
+            .unwrap();
+        // A hop shorter, but the same size as it was when it came in
+        assert_eq!(lcp_ser.len(), data_ser.len());
+        assert_eq!(padding::PADDING_BUCKETS.contains(&lcp_ser.len()), true);
+    }
+
+    #[test]
+    fn relayed_bytes_are_reported_to_the_neighborhood_once_the_sample_interval_is_up() {
+        let cryptde = cryptde();
+        let consuming_wallet = Wallet::new("wallet");
+        let next_key = PublicKey::new(&[65, 65, 65]);
+        let route = Route::one_way(
+            RouteSegment::new(
+                vec![&cryptde.public_key(), &next_key],
+                Component::Neighborhood,
+            ),
+            cryptde,
+            Some(consuming_wallet.clone()),
+        )
+        .unwrap();
+        let payload = PlainData::new(&b"abcd"[..]);
+        let lcp = LiveCoresPackage::new(route, cryptde.encode(&next_key, &payload).unwrap());
+        let data_ser = PlainData::new(&serde_cbor::ser::to_vec(&lcp).unwrap()[..]);
+        let data_enc = cryptde.encode(&cryptde.public_key(), &data_ser).unwrap();
+        let inbound_client_data = InboundClientData {
+            peer_addr: SocketAddr::from_str("1.2.3.4:5678").unwrap(),
+            reception_port: None,
+            last_data: false,
+            is_clandestine: true,
+            sequence_number: None,
+            data: data_enc.into(),
+            neighbor_key_opt: None,
+        };
+        let (neighborhood, neighborhood_awaiter, neighborhood_recording_arc) = make_recorder();
+        let (dispatcher, dispatcher_awaiter, dispatcher_recording_arc) = make_recorder();
+        thread::spawn(move || {
+            let system = System::new(
+                "relayed_bytes_are_reported_to_the_neighborhood_once_the_sample_interval_is_up",
+            );
+            let peer_actors = peer_actors_builder()
+                .neighborhood(neighborhood)
+                .dispatcher(dispatcher)
+                .build();
+            let mut subject = RoutingService::new(
+                cryptde,
+                false,
+                peer_actors.proxy_client.from_hopper,
+                peer_actors.proxy_server.from_hopper,
+                peer_actors.neighborhood.from_hopper,
+                peer_actors.dispatcher.from_dispatcher_client,
+                peer_actors.accountant.report_routing_service_provided,
+                peer_actors.neighborhood.routing_load,
+                peer_actors.neighborhood.neighbor_misbehavior,
+            );
+            subject.sample_load_every(Duration::from_millis(0));
+
+            subject.route(inbound_client_data);
+
+            system.run();
+        });
+        dispatcher_awaiter.await_message_count(1);
+        neighborhood_awaiter.await_message_count(1);
+        let dispatcher_recording = dispatcher_recording_arc.lock().unwrap();
+        let transmitted = dispatcher_recording.get_record::<TransmitDataMsg>(0);
+        let neighborhood_recording = neighborhood_recording_arc.lock().unwrap();
+        let load_message = neighborhood_recording.get_record::<RoutingLoadMessage>(0);
+        assert_eq!(load_message.bytes_routed, transmitted.data.len() as u64);
+    }
+
+    #[test]
+    fn relayed_packages_are_charged_for_at_the_rates_on_the_rate_card() {
+        let cryptde = cryptde();
+        let consuming_wallet = Wallet::new("wallet");
+        let next_key = PublicKey::new(&[65, 65, 65]);
+        let route = Route::one_way(
+            RouteSegment::new(
+                vec![&cryptde.public_key(), &next_key],
+                Component::Neighborhood,
+            ),
+            cryptde,
+            Some(consuming_wallet.clone()),
+        )
+        .unwrap();
+        let payload = PlainData::new(&b"abcd"[..]);
+        let lcp = LiveCoresPackage::new(route, cryptde.encode(&next_key, &payload).unwrap());
+        let data_ser = PlainData::new(&serde_cbor::ser::to_vec(&lcp).unwrap()[..]);
+        let data_enc = cryptde.encode(&cryptde.public_key(), &data_ser).unwrap();
+        let inbound_client_data = InboundClientData {
+            peer_addr: SocketAddr::from_str("1.2.3.4:5678").unwrap(),
+            reception_port: None,
+            last_data: false,
+            is_clandestine: true,
+            sequence_number: None,
+            data: data_enc.into(),
+            neighbor_key_opt: None,
+        };
+        let (accountant, accountant_awaiter, accountant_recording_arc) = make_recorder();
+        thread::spawn(move || {
+            let system =
+                System::new("relayed_packages_are_charged_for_at_the_rates_on_the_rate_card");
+            let peer_actors = peer_actors_builder().accountant(accountant).build();
+            let mut subject = RoutingService::new(
+                cryptde,
+                false,
+                peer_actors.proxy_client.from_hopper,
+                peer_actors.proxy_server.from_hopper,
+                peer_actors.neighborhood.from_hopper,
+                peer_actors.dispatcher.from_dispatcher_client,
+                peer_actors.accountant.report_routing_service_provided,
+                peer_actors.neighborhood.routing_load,
+                peer_actors.neighborhood.neighbor_misbehavior,
+            );
+            subject.charge(RateCard {
+                routing_service_rate: 10,
+                routing_byte_rate: 20,
+                exit_service_rate: 30,
+                exit_byte_rate: 40,
+            });
+
+            subject.route(inbound_client_data);
+
+            system.run();
+        });
+        accountant_awaiter.await_message_count(1);
+        let accountant_recording = accountant_recording_arc.lock().unwrap();
+        assert_eq!(
+            accountant_recording.get_record::<ReportRoutingServiceProvidedMessage>(0),
+            &ReportRoutingServiceProvidedMessage {
+                consuming_wallet,
+                payload_size: lcp.payload.len(),
+                service_rate: 10,
+                byte_rate: 20,
+                correlation_id_opt: None,
+            }
+        );
+    }
+
+    fn make_relayed_inbound_client_data(consuming_wallet: &Wallet) -> InboundClientData {
+        let cryptde = cryptde();
+        let next_key = PublicKey::new(&[65, 65, 65]);
+        let route = Route::one_way(
+            RouteSegment::new(
+                vec![&cryptde.public_key(), &next_key],
+                Component::Neighborhood,
+            ),
+            cryptde,
+            Some(consuming_wallet.clone()),
+        )
+        .unwrap();
+        let payload = PlainData::new(&b"abcd"[..]);
+        let lcp = LiveCoresPackage::new(route, cryptde.encode(&next_key, &payload).unwrap());
+        let data_ser = PlainData::new(&serde_cbor::ser::to_vec(&lcp).unwrap()[..]);
+        let data_enc = cryptde.encode(&cryptde.public_key(), &data_ser).unwrap();
+        InboundClientData {
+            peer_addr: SocketAddr::from_str("1.2.3.4:5678").unwrap(),
+            reception_port: None,
+            last_data: false,
+            is_clandestine: true,
+            sequence_number: None,
+            data: data_enc.into(),
+            neighbor_key_opt: None,
+        }
+    }
+
+    fn make_routing_service(peer_actors: &PeerActors) -> RoutingService {
+        RoutingService::new(
+            cryptde(),
+            false,
+            peer_actors.proxy_client.from_hopper.clone(),
+            peer_actors.proxy_server.from_hopper.clone(),
+            peer_actors.neighborhood.from_hopper.clone(),
+            peer_actors.dispatcher.from_dispatcher_client.clone(),
+            peer_actors
+                .accountant
+                .report_routing_service_provided
+                .clone(),
+            peer_actors.neighborhood.routing_load.clone(),
+            peer_actors.neighborhood.neighbor_misbehavior.clone(),
+        )
+    }
+
+    #[test]
+    fn limited_relay_throughput_holds_relayed_packages_until_they_are_released() {
+        let system =
+            System::new("limited_relay_throughput_holds_relayed_packages_until_they_are_released");
+        let (dispatcher, _, dispatcher_recording_arc) = make_recorder();
+        let (accountant, _, accountant_recording_arc) = make_recorder();
+        let peer_actors = peer_actors_builder()
+            .dispatcher(dispatcher)
+            .accountant(accountant)
+            .build();
+        let mut subject = make_routing_service(&peer_actors);
+        subject.limit_relay_throughput(&RelayFairnessConfig {
+            bytes_per_second: 1_000_000,
+            weighted_by_standing: false,
+        });
+        let consuming_wallet = Wallet::new("wallet");
+
+        subject.route(make_relayed_inbound_client_data(&consuming_wallet));
+        subject.route(make_relayed_inbound_client_data(&consuming_wallet));
+
+        assert_eq!(subject.statistics().forwarded, 0);
+        subject.release_relayed(Instant::now() + Duration::from_secs(1));
+        assert_eq!(subject.statistics().forwarded, 2);
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+        assert_eq!(dispatcher_recording_arc.lock().unwrap().len(), 2);
+        let accountant_recording = accountant_recording_arc.lock().unwrap();
+        assert_eq!(accountant_recording.len(), 2);
+        assert_eq!(
+            accountant_recording
+                .get_record::<ReportRoutingServiceProvidedMessage>(0)
+                .consuming_wallet,
+            consuming_wallet
+        );
+    }
+
+    #[test]
+    fn relayed_packages_are_dropped_when_their_consumers_queue_is_full() {
+        init_test_logging();
+        let system = System::new("relayed_packages_are_dropped_when_their_consumers_queue_is_full");
+        let (accountant, _, accountant_recording_arc) = make_recorder();
+        let peer_actors = peer_actors_builder().accountant(accountant).build();
+        let mut subject = make_routing_service(&peer_actors);
+        subject.limit_relay_throughput(&RelayFairnessConfig {
+            bytes_per_second: 10,
+            weighted_by_standing: false,
+        });
+        let greedy = Wallet::new("greedy");
+
+        subject.route(make_relayed_inbound_client_data(&greedy));
+        subject.route(make_relayed_inbound_client_data(&greedy));
+        subject.route(make_relayed_inbound_client_data(&Wallet::new("modest")));
+
+        let statistics = subject.statistics();
+        assert_eq!(statistics.dropped, 1);
+        assert_eq!(statistics.forwarded, 0);
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+        assert_eq!(accountant_recording_arc.lock().unwrap().len(), 0);
+        TestLogHandler::new().exists_log_containing(
+            "DEBUG: RoutingService: Relay queue for greedy is full: dropping CORES package with",
+        );
+    }
+
+    #[test]
+    fn every_package_taken_in_is_reported_to_metrics_along_with_those_dropped() {
+        let system =
+            System::new("every_package_taken_in_is_reported_to_metrics_along_with_those_dropped");
+        let (metrics, _, metrics_recording_arc) = make_recorder();
+        let peer_actors = peer_actors_builder().metrics(metrics).build();
+        let mut subject = make_routing_service(&peer_actors);
+        subject.report_metrics_to(peer_actors.metrics.report.clone());
+        let relayed = make_relayed_inbound_client_data(&Wallet::new("wallet"));
+        let relayed_size = relayed.data.len() as u64;
+        let mut undecryptable = relayed.clone();
+        undecryptable.data = vec![];
+
+        subject.route(relayed);
+        subject.route(undecryptable);
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+        let metrics_recording = metrics_recording_arc.lock().unwrap();
+        let reports: Vec<MetricsReport> = (0..metrics_recording.len())
+            .map(|index| metrics_recording.get_record::<MetricsReport>(index).clone())
+            .collect();
+        assert_eq!(
+            reports,
+            vec![
+                MetricsReport::CoresPackagesProcessed(1),
+                MetricsReport::BytesRouted(relayed_size),
+                MetricsReport::CoresPackagesProcessed(1),
+                MetricsReport::BytesRouted(0),
+                MetricsReport::CoresPackagesDropped(1),
+            ]
+        );
+    }
+
+    #[test]
+    fn payment_standing_weighs_relay_queues_only_when_configured_to() {
+        let _system = System::new("payment_standing_weighs_relay_queues_only_when_configured_to");
+        let peer_actors = peer_actors_builder().build();
+        let delinquent = Wallet::new("delinquent");
+        let paid_up = Wallet::new("paid up");
+        let mut weighted = make_routing_service(&peer_actors);
+        weighted.limit_relay_throughput(&RelayFairnessConfig {
+            bytes_per_second: 1000,
+            weighted_by_standing: true,
+        });
+        let mut unweighted = make_routing_service(&peer_actors);
+        unweighted.limit_relay_throughput(&RelayFairnessConfig {
+            bytes_per_second: 1000,
+            weighted_by_standing: false,
+        });
+
+        weighted.set_payment_standing(&[delinquent.clone()]);
+        unweighted.set_payment_standing(&[delinquent.clone()]);
+
+        let weight_of = |subject: &RoutingService, wallet: &Wallet| {
+            subject
+                .relay_throttle_opt
+                .as_ref()
+                .unwrap()
+                .borrow()
+                .queue
+                .weight_of(wallet)
+        };
+        assert_eq!(weight_of(&weighted, &delinquent), DELINQUENT_WEIGHT);
+        assert_eq!(weight_of(&weighted, &paid_up), GOOD_STANDING_WEIGHT);
+        assert_eq!(weight_of(&unweighted, &delinquent), GOOD_STANDING_WEIGHT);
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Composite packages are split up by the Hopper and can't have an endpoint"
+    )]
+    fn composite_cannot_be_registered_as_an_endpoint() {
+        let _system = System::new("composite_cannot_be_registered_as_an_endpoint");
+        let (recorder, _, _) = make_recorder();
+        let recorder_addr: Addr<Syn, Recorder> = recorder.start();
+        let peer_actors = peer_actors_builder().build();
+        let mut subject = RoutingService::new(
+            cryptde(),
+            false,
+            peer_actors.proxy_client.from_hopper,
+            peer_actors.proxy_server.from_hopper,
+            peer_actors.neighborhood.from_hopper,
+            peer_actors.dispatcher.from_dispatcher_client,
+            peer_actors.accountant.report_routing_service_provided,
+            peer_actors.neighborhood.routing_load,
+            peer_actors.neighborhood.neighbor_misbehavior,
+        );
+
+        subject.register_endpoint(RegisterEndpointMessage {
+            component: Component::Composite,
+            recipient: recorder_addr.recipient::<ExpiredCoresPackage>(),
+            available_on_bootstrap_node: false,
+        });
+    }
+
+    fn make_composite_inbound_client_data(
+        cryptde: &'static dyn CryptDE,
+        composite: &CompositePayload,
+    ) -> (LiveCoresPackage, InboundClientData) {
+        let mut route = Route::one_way(
+            RouteSegment::new(
+                vec![&cryptde.public_key(), &cryptde.public_key()],
+                Component::Composite,
+            ),
+            cryptde,
+            Some(Wallet::new("wallet")),
+        )
+        .unwrap();
+        route.shift(cryptde).unwrap();
+        let lcp = LiveCoresPackage::new(
+            route,
+            encodex(cryptde, &cryptde.public_key(), composite).unwrap(),
+        );
+        let data_ser = PlainData::new(&serde_cbor::ser::to_vec(&lcp).unwrap()[..]);
+        let data_enc = cryptde.encode(&cryptde.public_key(), &data_ser).unwrap();
+        let inbound_client_data = InboundClientData {
+            peer_addr: SocketAddr::from_str("1.2.3.4:5678").unwrap(),
+            reception_port: None,
+            last_data: false,
+            is_clandestine: true,
+            sequence_number: None,
+            data: data_enc.into(),
+            neighbor_key_opt: Some(PublicKey::new(&[9, 8, 7])),
+        };
+        (lcp, inbound_client_data)
+    }
+
+    #[test]
+    fn composite_package_is_split_up_among_its_components() {
+        let cryptde = cryptde();
+        let neighborhood_payload = PayloadMock::new();
+        let proxy_server_payload = PayloadMock {
+            data: vec![1, 2, 3],
+        };
+        let composite = CompositePayload::new()
+            .add(Component::Neighborhood, &neighborhood_payload)
+            .add(Component::ProxyServer, &proxy_server_payload);
+        let (lcp, inbound_client_data) = make_composite_inbound_client_data(cryptde, &composite);
+        let system = System::new("composite_package_is_split_up_among_its_components");
+        let (neighborhood, _, neighborhood_recording_arc) = make_recorder();
+        let (proxy_server, _, proxy_server_recording_arc) = make_recorder();
+        let peer_actors = peer_actors_builder()
+            .neighborhood(neighborhood)
+            .proxy_server(proxy_server)
+            .build();
+        let subject = RoutingService::new(
+            cryptde,
+            false,
+            peer_actors.proxy_client.from_hopper,
+            peer_actors.proxy_server.from_hopper,
+            peer_actors.neighborhood.from_hopper,
+            peer_actors.dispatcher.from_dispatcher_client,
+            peer_actors.accountant.report_routing_service_provided,
+            peer_actors.neighborhood.routing_load,
+            peer_actors.neighborhood.neighbor_misbehavior,
+        );
+
+        subject.route(inbound_client_data);
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+        let mut expected_ecp = lcp
+            .to_expired(IpAddr::from_str("1.2.3.4").unwrap(), cryptde)
+            .unwrap();
+        expected_ecp.immediate_neighbor_key_opt = Some(PublicKey::new(&[9, 8, 7]));
+        let neighborhood_recording = neighborhood_recording_arc.lock().unwrap();
+        let neighborhood_ecp = neighborhood_recording.get_record::<ExpiredCoresPackage>(0);
+        assert_eq!(
+            neighborhood_ecp.payload::<PayloadMock>(cryptde).unwrap(),
+            neighborhood_payload
+        );
+        assert_eq!(
+            neighborhood_ecp,
+            &ExpiredCoresPackage {
+                payload: neighborhood_ecp.payload.clone(),
+                ..expected_ecp.clone()
+            }
+        );
+        let proxy_server_recording = proxy_server_recording_arc.lock().unwrap();
+        let proxy_server_ecp = proxy_server_recording.get_record::<ExpiredCoresPackage>(0);
+        assert_eq!(
+            proxy_server_ecp.payload::<PayloadMock>(cryptde).unwrap(),
+            proxy_server_payload
+        );
+        assert_eq!(
+            proxy_server_ecp,
+            &ExpiredCoresPackage {
+                payload: proxy_server_ecp.payload.clone(),
+                ..expected_ecp
+            }
+        );
+        let statistics = subject.statistics();
+        assert_eq!(statistics.delivered_to(Component::Neighborhood), 1);
+        assert_eq!(statistics.delivered_to(Component::ProxyServer), 1);
+        assert_eq!(statistics.delivered_to(Component::Composite), 0);
+        assert_eq!(statistics.dropped, 0);
+    }
+
+    #[test]
+    fn composite_package_parts_that_cannot_be_delivered_are_dropped_without_disturbing_the_rest() {
+        init_test_logging();
+        let cryptde = cryptde();
+        let neighborhood_payload = PayloadMock::new();
+        let mut composite = CompositePayload::new()
+            .add(Component::ProxyClient, &PayloadMock::new())
+            .add(Component::Neighborhood, &neighborhood_payload);
+        composite.parts.push(CompositePart {
+            component: Component::Hopper,
+            data: PlainData::new(&b"abcd"[..]),
+        });
+        let (_, inbound_client_data) = make_composite_inbound_client_data(cryptde, &composite);
+        let system = System::new(
+            "composite_package_parts_that_cannot_be_delivered_are_dropped_without_disturbing_the_rest",
+        );
+        let (neighborhood, _, neighborhood_recording_arc) = make_recorder();
+        let (proxy_client, _, proxy_client_recording_arc) = make_recorder();
+        let peer_actors = peer_actors_builder()
+            .neighborhood(neighborhood)
+            .proxy_client(proxy_client)
+            .build();
+        let subject = RoutingService::new(
+            cryptde,
+            true,
+            peer_actors.proxy_client.from_hopper,
+            peer_actors.proxy_server.from_hopper,
+            peer_actors.neighborhood.from_hopper,
+            peer_actors.dispatcher.from_dispatcher_client,
+            peer_actors.accountant.report_routing_service_provided,
+            peer_actors.neighborhood.routing_load,
+            peer_actors.neighborhood.neighbor_misbehavior,
+        );
+
+        subject.route(inbound_client_data);
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+        let neighborhood_recording = neighborhood_recording_arc.lock().unwrap();
+        assert_eq!(
+            neighborhood_recording
+                .get_record::<ExpiredCoresPackage>(0)
+                .payload::<PayloadMock>(cryptde)
+                .unwrap(),
+            neighborhood_payload
+        );
+        assert_eq!(neighborhood_recording.len(), 1);
+        assert_eq!(proxy_client_recording_arc.lock().unwrap().len(), 0);
+        let statistics = subject.statistics();
+        assert_eq!(statistics.delivered_to(Component::Neighborhood), 1);
+        assert_eq!(statistics.dropped, 2);
+        let tlh = TestLogHandler::new();
+        tlh.exists_log_containing(
+            "ERROR: RoutingService: Request for Bootstrap Node to route data to ProxyClient: rejected",
+        );
+        tlh.exists_log_containing(
+            "ERROR: RoutingService: Composite payload can't carry a part for Hopper: discarding 4-byte part",
+        );
+    }
+
+    fn make_fragment_inbound_client_data(
+        cryptde: &'static dyn CryptDE,
+        fragment: &LiveCoresPackage,
+    ) -> InboundClientData {
+        let data_ser = PlainData::new(&serde_cbor::ser::to_vec(fragment).unwrap()[..]);
+        let data_enc = cryptde.encode(&cryptde.public_key(), &data_ser).unwrap();
+        InboundClientData {
+            peer_addr: SocketAddr::from_str("1.2.3.4:5678").unwrap(),
+            reception_port: None,
+            last_data: false,
+            is_clandestine: true,
+            sequence_number: None,
+            data: data_enc.into(),
+            neighbor_key_opt: None,
+        }
+    }
+
+    #[test]
+    fn fragmented_package_is_delivered_once_all_its_fragments_have_arrived() {
+        let cryptde = cryptde();
+        let payload = PayloadMock {
+            data: (0..100).collect(),
+        };
+        let mut route = Route::one_way(
+            RouteSegment::new(
+                vec![&cryptde.public_key(), &cryptde.public_key()],
+                Component::ProxyServer,
+            ),
+            cryptde,
+            Some(Wallet::new("wallet")),
+        )
+        .unwrap();
+        route.shift(cryptde).unwrap();
+        let lcp = LiveCoresPackage::new(
+            route,
+            encodex(cryptde, &cryptde.public_key(), &payload).unwrap(),
+        );
+        let fragments = fragmentation::fragment(lcp.clone(), 80, 1234);
+        assert_eq!(fragments.len(), 3);
+        let system =
+            System::new("fragmented_package_is_delivered_once_all_its_fragments_have_arrived");
+        let (proxy_server, _, proxy_server_recording_arc) = make_recorder();
+        let peer_actors = peer_actors_builder().proxy_server(proxy_server).build();
+        let subject = RoutingService::new(
+            cryptde,
+            false,
+            peer_actors.proxy_client.from_hopper,
+            peer_actors.proxy_server.from_hopper,
+            peer_actors.neighborhood.from_hopper,
+            peer_actors.dispatcher.from_dispatcher_client,
+            peer_actors.accountant.report_routing_service_provided,
+            peer_actors.neighborhood.routing_load,
+            peer_actors.neighborhood.neighbor_misbehavior,
+        );
+
+        fragments.iter().rev().for_each(|fragment| {
+            subject.route(make_fragment_inbound_client_data(cryptde, fragment))
+        });
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+        let proxy_server_recording = proxy_server_recording_arc.lock().unwrap();
+        let expected_ecp = lcp
+            .to_expired(IpAddr::from_str("1.2.3.4").unwrap(), cryptde)
+            .unwrap();
+        assert_eq!(
+            proxy_server_recording.get_record::<ExpiredCoresPackage>(0),
+            &expected_ecp
+        );
+        assert_eq!(
+            expected_ecp.payload::<PayloadMock>(cryptde).unwrap(),
+            payload
+        );
+        assert_eq!(proxy_server_recording.len(), 1);
+        let statistics = subject.statistics();
+        assert_eq!(statistics.delivered_to(Component::ProxyServer), 1);
+        assert_eq!(statistics.dropped, 0);
+    }
+
+    #[test]
+    fn fragment_that_makes_no_sense_is_dropped() {
+        init_test_logging();
+        let cryptde = cryptde();
+        let fragment = LiveCoresPackage {
+            fragment_opt: Some(Fragment {
+                id: 1234,
+                index: 3,
+                count: 3,
+            }),
+            ..LiveCoresPackage::new(
+                route_to_proxy_server(&cryptde.public_key(), cryptde),
+                CryptData::new(&[1, 2, 3]),
+            )
+        };
+        let system = System::new("fragment_that_makes_no_sense_is_dropped");
+        let (proxy_server, _, proxy_server_recording_arc) = make_recorder();
+        let peer_actors = peer_actors_builder().proxy_server(proxy_server).build();
+        let subject = RoutingService::new(
+            cryptde,
+            false,
+            peer_actors.proxy_client.from_hopper,
+            peer_actors.proxy_server.from_hopper,
+            peer_actors.neighborhood.from_hopper,
+            peer_actors.dispatcher.from_dispatcher_client,
+            peer_actors.accountant.report_routing_service_provided,
+            peer_actors.neighborhood.routing_load,
+            peer_actors.neighborhood.neighbor_misbehavior,
+        );
+
+        subject.route(make_fragment_inbound_client_data(cryptde, &fragment));
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+        assert_eq!(proxy_server_recording_arc.lock().unwrap().len(), 0);
+        assert_eq!(subject.statistics().dropped, 1);
+        TestLogHandler::new().exists_log_containing(
+            "ERROR: RoutingService: Discarding CORES package: Invalid fragment 3 of 3 for payload 1234",
+        );
+    }
+
+    #[test]
+    fn composite_package_that_cannot_be_decoded_is_dropped() {
+        init_test_logging();
+        let cryptde = cryptde();
+        let mut route = Route::one_way(
+            RouteSegment::new(
+                vec![&cryptde.public_key(), &cryptde.public_key()],
+                Component::Composite,
+            ),
+            cryptde,
+            Some(Wallet::new("wallet")),
+        )
+        .unwrap();
+        route.shift(cryptde).unwrap();
+        let lcp = LiveCoresPackage::new(
+            route,
+            cryptde
+                .encode(&cryptde.public_key(), &PlainData::new(&b"abcd"[..]))
+                .unwrap(),
+        );
+        let data_ser = PlainData::new(&serde_cbor::ser::to_vec(&lcp).unwrap()[..]);
+        let data_enc = cryptde.encode(&cryptde.public_key(), &data_ser).unwrap();
+        let inbound_client_data = InboundClientData {
+            peer_addr: SocketAddr::from_str("1.2.3.4:5678").unwrap(),
+            reception_port: None,
+            last_data: false,
+            is_clandestine: true,
+            sequence_number: None,
+            data: data_enc.into(),
+            neighbor_key_opt: None,
+        };
+        let system = System::new("composite_package_that_cannot_be_decoded_is_dropped");
+        let (neighborhood, _, neighborhood_recording_arc) = make_recorder();
+        let peer_actors = peer_actors_builder().neighborhood(neighborhood).build();
+        let subject = RoutingService::new(
+            cryptde,
+            false,
+            peer_actors.proxy_client.from_hopper,
+            peer_actors.proxy_server.from_hopper,
+            peer_actors.neighborhood.from_hopper,
+            peer_actors.dispatcher.from_dispatcher_client,
+            peer_actors.accountant.report_routing_service_provided,
+            peer_actors.neighborhood.routing_load,
+            peer_actors.neighborhood.neighbor_misbehavior,
+        );
+
+        subject.route(inbound_client_data);
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+        assert_eq!(neighborhood_recording_arc.lock().unwrap().len(), 0);
+        assert_eq!(subject.statistics().dropped, 1);
+        TestLogHandler::new()
+            .exists_log_containing("ERROR: RoutingService: Couldn't decode composite payload from");
+    }
+
+    #[test]
+    fn relayed_bytes_are_accumulated_until_the_sample_interval_is_up() {
+        let system = System::new("relayed_bytes_are_accumulated_until_the_sample_interval_is_up");
+        let (neighborhood, _, neighborhood_recording_arc) = make_recorder();
+        let peer_actors = peer_actors_builder().neighborhood(neighborhood).build();
+        let subject = RoutingService::new(
+            cryptde(),
+            false,
+            peer_actors.proxy_client.from_hopper,
+            peer_actors.proxy_server.from_hopper,
+            peer_actors.neighborhood.from_hopper,
+            peer_actors.dispatcher.from_dispatcher_client,
+            peer_actors.accountant.report_routing_service_provided,
+            peer_actors.neighborhood.routing_load,
+            peer_actors.neighborhood.neighbor_misbehavior,
+        );
+
+        subject.record_routed_bytes(100);
+        subject.record_routed_bytes(200);
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+        assert_eq!(subject.bytes_routed.get(), 300);
+        assert_eq!(neighborhood_recording_arc.lock().unwrap().len(), 0);
+    }
+
+    #[test]
+    fn route_logs_and_ignores_cores_package_that_demands_routing_without_consuming_wallet() {
+        init_test_logging();
+        let cryptde = cryptde();
+        let origin_key = PublicKey::new(&[1, 2]);
+        let origin_cryptde = CryptDENull::from(&origin_key);
+        let destination_key = PublicKey::new(&[3, 4]);
+        let payload = PayloadMock::new();
+        let route = Route::one_way(
+            RouteSegment::new(
+                vec![&origin_key, &cryptde.public_key(), &destination_key],
+                Component::ProxyClient,
+            ),
+            &origin_cryptde,
+            None,
+        )
+        .unwrap();
+        let icp =
+            IncipientCoresPackage::new(&origin_cryptde, route, payload, &destination_key).unwrap();
+        let (lcp, _) = LiveCoresPackage::from_incipient(icp, &origin_cryptde).unwrap();
+        let data_ser = PlainData::new(&serde_cbor::ser::to_vec(&lcp).unwrap()[..]);
+        let data_enc = cryptde.encode(&cryptde.public_key(), &data_ser).unwrap();
+        let inbound_client_data = InboundClientData {
+            peer_addr: SocketAddr::from_str("1.2.3.4:5678").unwrap(),
+            reception_port: None,
+            last_data: true,
+            is_clandestine: true,
+            sequence_number: None,
+            data: data_enc.into(),
+            neighbor_key_opt: None,
+        };
+        let system = System::new(
+            "route_logs_and_ignores_cores_package_that_demands_routing_without_consuming_wallet",
+        );
+        let (proxy_client, _, proxy_client_recording_arc) = make_recorder();
+        let (proxy_server, _, proxy_server_recording_arc) = make_recorder();
+        let (neighborhood, _, neighborhood_recording_arc) = make_recorder();
+        let (dispatcher, _, dispatcher_recording_arc) = make_recorder();
+        let peer_actors = peer_actors_builder()
+            .proxy_client(proxy_client)
+            .proxy_server(proxy_server)
+            .neighborhood(neighborhood)
+            .dispatcher(dispatcher)
+            .build();
+        let subject = RoutingService::new(
+            cryptde,
+            false,
+            peer_actors.proxy_client.from_hopper,
+            peer_actors.proxy_server.from_hopper,
+            peer_actors.neighborhood.from_hopper,
+            peer_actors.dispatcher.from_dispatcher_client,
+            peer_actors.accountant.report_routing_service_provided,
+            peer_actors.neighborhood.routing_load,
+            peer_actors.neighborhood.neighbor_misbehavior,
+        );
+
+        subject.route(inbound_client_data);
 
         Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
         system.run();
@@ -729,6 +2083,8 @@ mod tests {
         assert_eq!(proxy_server_recording_arc.lock().unwrap().len(), 0);
         assert_eq!(neighborhood_recording_arc.lock().unwrap().len(), 0);
         assert_eq!(dispatcher_recording_arc.lock().unwrap().len(), 0);
+        assert_eq!(subject.statistics().dropped, 1);
+        assert_eq!(subject.statistics().forwarded, 0);
     }
 
     #[test]
@@ -741,6 +2097,7 @@ mod tests {
             is_clandestine: true,
             sequence_number: None,
             data: vec![],
+            neighbor_key_opt: None,
         };
         let system = System::new("consume_logs_error_when_given_bad_input_data");
         let (proxy_client, _, proxy_client_recording_arc) = make_recorder();
@@ -761,6 +2118,8 @@ mod tests {
             peer_actors.neighborhood.from_hopper,
             peer_actors.dispatcher.from_dispatcher_client,
             peer_actors.accountant.report_routing_service_provided,
+            peer_actors.neighborhood.routing_load,
+            peer_actors.neighborhood.neighbor_misbehavior,
         );
 
         subject.route(inbound_client_data);
@@ -774,6 +2133,7 @@ mod tests {
         assert_eq!(proxy_server_recording_arc.lock().unwrap().len(), 0);
         assert_eq!(neighborhood_recording_arc.lock().unwrap().len(), 0);
         assert_eq!(dispatcher_recording_arc.lock().unwrap().len(), 0);
+        assert_eq!(subject.statistics().decrypt_failures, 1);
     }
 
     #[test]
@@ -790,6 +2150,7 @@ mod tests {
             is_clandestine: true,
             sequence_number: None,
             data: data_enc.into(),
+            neighbor_key_opt: None,
         };
         let system = System::new("consume_logs_error_when_given_bad_input_data");
         let (proxy_client, _, proxy_client_recording_arc) = make_recorder();
@@ -810,6 +2171,8 @@ mod tests {
             peer_actors.neighborhood.from_hopper,
             peer_actors.dispatcher.from_dispatcher_client,
             peer_actors.accountant.report_routing_service_provided,
+            peer_actors.neighborhood.routing_load,
+            peer_actors.neighborhood.neighbor_misbehavior,
         );
 
         subject.route(inbound_client_data);
@@ -823,5 +2186,174 @@ mod tests {
         assert_eq!(proxy_server_recording_arc.lock().unwrap().len(), 0);
         assert_eq!(neighborhood_recording_arc.lock().unwrap().len(), 0);
         assert_eq!(dispatcher_recording_arc.lock().unwrap().len(), 0);
+        assert_eq!(subject.statistics().dropped, 1);
+        assert_eq!(subject.statistics().total_delivered(), 0);
+    }
+
+    #[test]
+    fn route_reports_a_known_neighbor_that_sends_an_invalid_live_cores_package() {
+        let cryptde = cryptde();
+        let lcp = LiveCoresPackage::new(Route { hops: vec![] }, CryptData::new(&[]));
+        let data_ser = PlainData::new(&serde_cbor::ser::to_vec(&lcp).unwrap()[..]);
+        let data_enc = cryptde.encode(&cryptde.public_key(), &data_ser).unwrap();
+        let neighbor_key = PublicKey::new(&b"neighbor"[..]);
+        let inbound_client_data = InboundClientData {
+            peer_addr: SocketAddr::from_str("1.2.3.4:5678").unwrap(),
+            reception_port: None,
+            last_data: true,
+            is_clandestine: true,
+            sequence_number: None,
+            data: data_enc.into(),
+            neighbor_key_opt: Some(neighbor_key.clone()),
+        };
+        let system =
+            System::new("route_reports_a_known_neighbor_that_sends_an_invalid_live_cores_package");
+        let (neighborhood, _, neighborhood_recording_arc) = make_recorder();
+        let peer_actors = peer_actors_builder().neighborhood(neighborhood).build();
+        let subject = RoutingService::new(
+            cryptde,
+            false,
+            peer_actors.proxy_client.from_hopper,
+            peer_actors.proxy_server.from_hopper,
+            peer_actors.neighborhood.from_hopper,
+            peer_actors.dispatcher.from_dispatcher_client,
+            peer_actors.accountant.report_routing_service_provided,
+            peer_actors.neighborhood.routing_load,
+            peer_actors.neighborhood.neighbor_misbehavior,
+        );
+
+        subject.route(inbound_client_data);
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+        let neighborhood_recording = neighborhood_recording_arc.lock().unwrap();
+        assert_eq!(
+            neighborhood_recording.get_record::<NeighborMisbehaviorMessage>(0),
+            &NeighborMisbehaviorMessage {
+                public_key: neighbor_key,
+                misbehavior: Misbehavior::UndeliverablePackage,
+            }
+        );
+        assert_eq!(neighborhood_recording.len(), 1);
+    }
+
+    #[test]
+    fn route_passes_on_and_counts_the_key_of_a_neighbor_that_has_proven_its_identity() {
+        let cryptde = cryptde();
+        let route = route_to_proxy_client(&cryptde.public_key(), cryptde);
+        let payload = PlainData::new(&b"abcd"[..]);
+        let lcp = LiveCoresPackage::new(
+            route,
+            cryptde.encode(&cryptde.public_key(), &payload).unwrap(),
+        );
+        let data_ser = PlainData::new(&serde_cbor::ser::to_vec(&lcp).unwrap()[..]);
+        let data_enc = cryptde.encode(&cryptde.public_key(), &data_ser).unwrap();
+        let neighbor_key = PublicKey::new(&b"neighbor"[..]);
+        let inbound_client_data = InboundClientData {
+            peer_addr: SocketAddr::from_str("1.2.3.4:5678").unwrap(),
+            reception_port: None,
+            sequence_number: None,
+            last_data: false,
+            is_clandestine: true,
+            data: data_enc.into(),
+            neighbor_key_opt: Some(neighbor_key.clone()),
+        };
+        let system = System::new(
+            "route_passes_on_and_counts_the_key_of_a_neighbor_that_has_proven_its_identity",
+        );
+        let (proxy_client, _, proxy_client_recording_arc) = make_recorder();
+        let peer_actors = peer_actors_builder().proxy_client(proxy_client).build();
+        let subject = RoutingService::new(
+            cryptde,
+            false,
+            peer_actors.proxy_client.from_hopper,
+            peer_actors.proxy_server.from_hopper,
+            peer_actors.neighborhood.from_hopper,
+            peer_actors.dispatcher.from_dispatcher_client,
+            peer_actors.accountant.report_routing_service_provided,
+            peer_actors.neighborhood.routing_load,
+            peer_actors.neighborhood.neighbor_misbehavior,
+        );
+
+        subject.route(inbound_client_data.clone());
+        subject.route(inbound_client_data);
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+        let proxy_client_recording = proxy_client_recording_arc.lock().unwrap();
+        let mut expected_ecp = lcp
+            .to_expired(IpAddr::from_str("1.2.3.4").unwrap(), cryptde)
+            .unwrap();
+        expected_ecp.immediate_neighbor_key_opt = Some(neighbor_key.clone());
+        assert_eq!(
+            proxy_client_recording.get_record::<ExpiredCoresPackage>(0),
+            &expected_ecp
+        );
+        assert_eq!(
+            subject.statistics().received_from.get(&neighbor_key),
+            Some(&2)
+        );
+    }
+
+    #[cfg(feature = "fault_injection")]
+    #[test]
+    fn route_drops_and_corrupts_packages_when_told_to() {
+        init_test_logging();
+        let cryptde = cryptde();
+        let route = route_to_proxy_client(&cryptde.public_key(), cryptde);
+        let payload = PlainData::new(&b"abcd"[..]);
+        let lcp = LiveCoresPackage::new(
+            route,
+            cryptde.encode(&cryptde.public_key(), &payload).unwrap(),
+        );
+        let data_ser = PlainData::new(&serde_cbor::ser::to_vec(&lcp).unwrap()[..]);
+        let data_enc = cryptde.encode(&cryptde.public_key(), &data_ser).unwrap();
+        let inbound_client_data = InboundClientData {
+            peer_addr: SocketAddr::from_str("1.2.3.4:5678").unwrap(),
+            reception_port: None,
+            sequence_number: None,
+            last_data: false,
+            is_clandestine: false,
+            data: data_enc.into(),
+            neighbor_key_opt: None,
+        };
+        let system = System::new("route_drops_and_corrupts_packages_when_told_to");
+        let (proxy_client, _, proxy_client_recording_arc) = make_recorder();
+        let peer_actors = peer_actors_builder().proxy_client(proxy_client).build();
+        let mut subject = RoutingService::new(
+            cryptde,
+            false,
+            peer_actors.proxy_client.from_hopper,
+            peer_actors.proxy_server.from_hopper,
+            peer_actors.neighborhood.from_hopper,
+            peer_actors.dispatcher.from_dispatcher_client,
+            peer_actors.accountant.report_routing_service_provided,
+            peer_actors.neighborhood.routing_load,
+            peer_actors.neighborhood.neighbor_misbehavior,
+        );
+        subject.fault_injector = FaultInjector::new();
+        subject.fault_injector.apply_command("drop 1").unwrap();
+        subject.fault_injector.apply_command("corrupt 1").unwrap();
+
+        subject.route(inbound_client_data.clone());
+        subject.route(inbound_client_data.clone());
+        subject.route(inbound_client_data);
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+        let tlh = TestLogHandler::new();
+        tlh.exists_log_containing(
+            "WARN: RoutingService: Fault injection: dropping CORES package with",
+        );
+        tlh.exists_log_containing(
+            "WARN: RoutingService: Fault injection: corrupting CORES package with",
+        );
+        let proxy_client_recording = proxy_client_recording_arc.lock().unwrap();
+        assert_eq!(
+            proxy_client_recording.get_record::<ExpiredCoresPackage>(0),
+            &lcp.to_expired(IpAddr::from_str("1.2.3.4").unwrap(), cryptde)
+                .unwrap()
+        );
+        assert_eq!(proxy_client_recording.len(), 1);
     }
 }
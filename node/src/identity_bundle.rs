@@ -0,0 +1,337 @@
+// Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use crate::accountant::database_snapshot::DatabaseSnapshot;
+use crate::accountant::db_initializer::DATABASE_FILE;
+use crate::sub_lib::cryptde::PrivateKey;
+use base64;
+use serde_cbor;
+use serde_derive::Deserialize;
+use serde_derive::Serialize;
+use sodiumoxide::crypto::pwhash;
+use sodiumoxide::crypto::secretbox;
+use std::fs;
+use std::path::Path;
+
+pub const CLANDESTINE_KEY_FILE: &str = "clandestine_key";
+
+const ARCHIVE_MAGIC: &[u8] = b"SUBIDv1\0";
+
+// What it takes for a Node to be the same Node on different hardware: the private key behind its
+// public key (only if it was started with --persistent_identity; otherwise it gets a new one every
+// time anyway), and its database, which holds its configuration and the balances it owes and is
+// owed. Wallets aren't included, because they're given on the command line and never stored.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct IdentityBundle {
+    pub private_key: Option<Vec<u8>>,
+    pub database: Option<DatabaseSnapshot>,
+}
+
+impl IdentityBundle {
+    pub fn gather(data_directory: &Path) -> Result<IdentityBundle, String> {
+        let private_key = read_private_key(data_directory)?.map(|key| key.as_slice().to_vec());
+        let database = if data_directory.join(DATABASE_FILE).exists() {
+            Some(DatabaseSnapshot::take(data_directory)?)
+        } else {
+            None
+        };
+        if private_key.is_none() && database.is_none() {
+            return Err(format!(
+                "Found neither a {} nor a {} in {:?}: nothing to export",
+                CLANDESTINE_KEY_FILE, DATABASE_FILE, data_directory
+            ));
+        }
+        Ok(IdentityBundle {
+            private_key,
+            database,
+        })
+    }
+
+    // Never over the top of an existing identity: everything is checked before anything is written
+    pub fn install(&self, data_directory: &Path) -> Result<(), String> {
+        let key_path = data_directory.join(CLANDESTINE_KEY_FILE);
+        let database_path = data_directory.join(DATABASE_FILE);
+        if self.private_key.is_some() && key_path.exists() {
+            return Err(format!(
+                "There's already a {} at {:?}; not overwriting it",
+                CLANDESTINE_KEY_FILE, key_path
+            ));
+        }
+        if self.database.is_some() && database_path.exists() {
+            return Err(format!(
+                "There's already a Node database at {:?}; not overwriting it",
+                database_path
+            ));
+        }
+        fs::create_dir_all(data_directory)
+            .map_err(|e| format!("Couldn't create {:?}: {}", data_directory, e))?;
+        if let Some(ref database) = self.database {
+            database.restore(data_directory)?;
+        }
+        if let Some(ref private_key) = self.private_key {
+            write_private_key(data_directory, &PrivateKey::new(private_key))?;
+        }
+        Ok(())
+    }
+
+    pub fn export(&self, password: &str) -> Result<Vec<u8>, String> {
+        init_sodium()?;
+        let plaintext = serde_cbor::ser::to_vec(self)
+            .map_err(|e| format!("Couldn't serialize identity: {}", e))?;
+        let salt = pwhash::gen_salt();
+        let key = derive_key(password, &salt)?;
+        let nonce = secretbox::gen_nonce();
+        let mut archive = ARCHIVE_MAGIC.to_vec();
+        archive.extend_from_slice(&salt.0);
+        archive.extend_from_slice(&nonce.0);
+        archive.extend(secretbox::seal(&plaintext, &nonce, &key));
+        Ok(archive)
+    }
+
+    pub fn import(archive: &[u8], password: &str) -> Result<IdentityBundle, String> {
+        init_sodium()?;
+        let header_len = ARCHIVE_MAGIC.len() + pwhash::SALTBYTES + secretbox::NONCEBYTES;
+        if archive.len() < header_len || !archive.starts_with(ARCHIVE_MAGIC) {
+            return Err(String::from("Not a SubstratumNode identity archive"));
+        }
+        let salt_start = ARCHIVE_MAGIC.len();
+        let nonce_start = salt_start + pwhash::SALTBYTES;
+        let salt =
+            pwhash::Salt::from_slice(&archive[salt_start..nonce_start]).expect("Internal error");
+        let nonce = secretbox::Nonce::from_slice(&archive[nonce_start..header_len])
+            .expect("Internal error");
+        let key = derive_key(password, &salt)?;
+        let plaintext = secretbox::open(&archive[header_len..], &nonce, &key).map_err(|_| {
+            String::from(
+                "Couldn't decrypt identity archive: wrong password, or the archive is damaged",
+            )
+        })?;
+        serde_cbor::de::from_slice(&plaintext)
+            .map_err(|e| format!("Couldn't understand identity archive: {}", e))
+    }
+}
+
+pub fn read_private_key(data_directory: &Path) -> Result<Option<PrivateKey>, String> {
+    let key_path = data_directory.join(CLANDESTINE_KEY_FILE);
+    if !key_path.exists() {
+        return Ok(None);
+    }
+    let encoded = fs::read_to_string(&key_path)
+        .map_err(|e| format!("Couldn't read {:?}: {}", key_path, e))?;
+    match base64::decode(encoded.trim()) {
+        Ok(ref data) if !data.is_empty() => Ok(Some(PrivateKey::new(data))),
+        _ => Err(format!("{:?} does not contain a key", key_path)),
+    }
+}
+
+pub fn write_private_key(data_directory: &Path, private_key: &PrivateKey) -> Result<(), String> {
+    let key_path = data_directory.join(CLANDESTINE_KEY_FILE);
+    fs::create_dir_all(data_directory)
+        .map_err(|e| format!("Couldn't create {:?}: {}", data_directory, e))?;
+    fs::write(&key_path, base64::encode(private_key.as_slice()))
+        .map_err(|e| format!("Couldn't write {:?}: {}", key_path, e))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&key_path, fs::Permissions::from_mode(0o600))
+            .map_err(|e| format!("Couldn't protect {:?}: {}", key_path, e))?;
+    }
+    Ok(())
+}
+
+fn init_sodium() -> Result<(), String> {
+    sodiumoxide::init().map_err(|_| String::from("Couldn't initialize libsodium"))
+}
+
+fn derive_key(password: &str, salt: &pwhash::Salt) -> Result<secretbox::Key, String> {
+    let mut key = secretbox::Key([0; secretbox::KEYBYTES]);
+    pwhash::derive_key(
+        &mut key.0,
+        password.as_bytes(),
+        salt,
+        pwhash::OPSLIMIT_INTERACTIVE,
+        pwhash::MEMLIMIT_INTERACTIVE,
+    )
+    .map_err(|_| String::from("Couldn't derive a key from the password"))?;
+    Ok(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::accountant::db_initializer::DbInitializer;
+    use crate::accountant::db_initializer::DbInitializerReal;
+    use crate::sub_lib::wallet::Wallet;
+    use std::path::PathBuf;
+
+    const BASE_TEST_DIR: &str = "generated/test/identity_bundle";
+
+    fn make_directory(name: &str) -> PathBuf {
+        let directory = PathBuf::from(format!("{}/{}", BASE_TEST_DIR, name));
+        fs::remove_dir_all(&directory).is_ok();
+        fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    fn make_identity(name: &str) -> PathBuf {
+        let directory = make_directory(name);
+        write_private_key(&directory, &PrivateKey::new(b"private key")).unwrap();
+        let daos = DbInitializerReal::new().initialize(&directory).unwrap();
+        daos.receivable
+            .more_money_receivable(&Wallet::new("debtor"), 1234)
+            .unwrap();
+        directory
+    }
+
+    #[test]
+    fn private_key_survives_a_round_trip_through_its_file() {
+        let directory = make_directory("private_key_survives_a_round_trip_through_its_file");
+
+        write_private_key(&directory, &PrivateKey::new(b"booga")).unwrap();
+        let result = read_private_key(&directory).unwrap();
+
+        assert_eq!(result, Some(PrivateKey::new(b"booga")));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn private_key_file_is_readable_only_by_its_owner() {
+        use std::os::unix::fs::PermissionsExt;
+        let directory = make_directory("private_key_file_is_readable_only_by_its_owner");
+
+        write_private_key(&directory, &PrivateKey::new(b"booga")).unwrap();
+
+        let metadata = fs::metadata(directory.join(CLANDESTINE_KEY_FILE)).unwrap();
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+    }
+
+    #[test]
+    fn missing_private_key_is_none_but_empty_one_is_an_error() {
+        let directory = make_directory("missing_private_key_is_none_but_empty_one_is_an_error");
+
+        assert_eq!(read_private_key(&directory), Ok(None));
+
+        fs::write(directory.join(CLANDESTINE_KEY_FILE), "").unwrap();
+        assert_eq!(
+            read_private_key(&directory),
+            Err(format!(
+                "{:?} does not contain a key",
+                directory.join(CLANDESTINE_KEY_FILE)
+            ))
+        );
+    }
+
+    #[test]
+    fn identity_can_be_exported_and_imported_elsewhere() {
+        let old_directory = make_identity("identity_can_be_exported_and_imported_elsewhere/old");
+        let new_directory = PathBuf::from(format!(
+            "{}/identity_can_be_exported_and_imported_elsewhere/new",
+            BASE_TEST_DIR
+        ));
+        fs::remove_dir_all(&new_directory).is_ok();
+        let archive = IdentityBundle::gather(&old_directory)
+            .unwrap()
+            .export("password")
+            .unwrap();
+
+        IdentityBundle::import(&archive, "password")
+            .unwrap()
+            .install(&new_directory)
+            .unwrap();
+
+        assert_eq!(
+            read_private_key(&new_directory).unwrap(),
+            Some(PrivateKey::new(b"private key"))
+        );
+        let daos = DbInitializerReal::new().initialize(&new_directory).unwrap();
+        assert_eq!(
+            daos.receivable
+                .account_status(&Wallet::new("debtor"))
+                .unwrap()
+                .balance,
+            1234
+        );
+    }
+
+    #[test]
+    fn archive_does_not_contain_the_identity_in_the_clear() {
+        let bundle = IdentityBundle {
+            private_key: Some(b"recognizable private key".to_vec()),
+            database: None,
+        };
+
+        let archive = bundle.export("password").unwrap();
+
+        assert_eq!(
+            archive
+                .windows(b"recognizable".len())
+                .any(|window| window == b"recognizable"),
+            false
+        );
+    }
+
+    #[test]
+    fn import_with_the_wrong_password_fails() {
+        let bundle = IdentityBundle {
+            private_key: Some(b"booga".to_vec()),
+            database: None,
+        };
+        let archive = bundle.export("password").unwrap();
+
+        let result = IdentityBundle::import(&archive, "passw0rd");
+
+        assert_eq!(
+            result,
+            Err(String::from(
+                "Couldn't decrypt identity archive: wrong password, or the archive is damaged"
+            ))
+        );
+    }
+
+    #[test]
+    fn import_rejects_something_that_is_not_an_archive() {
+        let result = IdentityBundle::import(b"booga", "password");
+
+        assert_eq!(
+            result,
+            Err(String::from("Not a SubstratumNode identity archive"))
+        );
+    }
+
+    #[test]
+    fn gather_complains_about_an_empty_directory() {
+        let directory = make_directory("gather_complains_about_an_empty_directory");
+
+        let result = IdentityBundle::gather(&directory);
+
+        assert_eq!(
+            result,
+            Err(format!(
+                "Found neither a {} nor a {} in {:?}: nothing to export",
+                CLANDESTINE_KEY_FILE, DATABASE_FILE, directory
+            ))
+        );
+    }
+
+    #[test]
+    fn install_will_not_replace_an_existing_key() {
+        let directory = make_identity("install_will_not_replace_an_existing_key");
+        let bundle = IdentityBundle {
+            private_key: Some(b"another key".to_vec()),
+            database: None,
+        };
+
+        let result = bundle.install(&directory);
+
+        assert_eq!(
+            result,
+            Err(format!(
+                "There's already a {} at {:?}; not overwriting it",
+                CLANDESTINE_KEY_FILE,
+                directory.join(CLANDESTINE_KEY_FILE)
+            ))
+        );
+        assert_eq!(
+            read_private_key(&directory).unwrap(),
+            Some(PrivateKey::new(b"private key"))
+        );
+    }
+}
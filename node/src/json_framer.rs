@@ -1,17 +1,25 @@
 // Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
 use crate::sub_lib::framer::FramedChunk;
 use crate::sub_lib::framer::Framer;
+use crate::sub_lib::framer::FramingStats;
 use crate::sub_lib::logger::Logger;
 
+pub const MAX_JSON_FRAME_BYTES: usize = 0x100000;
+
+// Scans each byte only once, however the data is split up as it arrives. Anything outside a
+// top-level pair of braces is discarded, and so is a frame that grows past the size limit; in
+// both cases framing starts over at the next top-level opening brace.
 pub struct JsonFramer {
-    possible_start: Option<usize>,
-    possible_end: Option<usize>,
     data_so_far: Vec<u8>,
+    scanned: usize,
+    possible_start: Option<usize>,
     brace_nest_count: i32,
     in_single_quote_string: bool,
     in_double_quote_string: bool,
     after_backslash: bool,
-    _logger: Logger,
+    max_frame_bytes: usize,
+    stats: FramingStats,
+    logger: Logger,
 }
 
 impl Framer for JsonFramer {
@@ -20,71 +28,110 @@ impl Framer for JsonFramer {
     }
 
     fn take_frame(&mut self) -> Option<FramedChunk> {
-        self.reset();
-        for i in 0..self.data_so_far.len() {
-            let byte = self.data_so_far[i];
-            self.update_state(byte, i);
-            match self.check_data_chunk() {
-                Some(chunk) => {
-                    self.data_so_far = Vec::from(&self.data_so_far[(i + 1)..]);
-                    return Some(FramedChunk {
-                        chunk,
-                        last_chunk: true,
-                    });
+        while self.scanned < self.data_so_far.len() {
+            let offset = self.scanned;
+            let byte = self.data_so_far[offset];
+            self.scanned += 1;
+            match self.possible_start {
+                None => self.look_for_start(byte, offset),
+                Some(start) => {
+                    self.update_state(byte);
+                    if (self.scanned - start) > self.max_frame_bytes {
+                        self.abandon_oversized_frame();
+                    } else if self.brace_nest_count == 0 {
+                        return Some(self.cut_frame(start));
+                    }
                 }
-                None => (),
             }
         }
-        match (self.possible_start, self.possible_end) {
-            (None, None) => {
-                self.data_so_far.clear();
-                None
-            }
-            (Some(start), None) => {
-                self.data_so_far = Vec::from(&self.data_so_far[start..]);
-                self.possible_start = Some(0);
-                None
-            }
-            // crashpoint - return none?
-            _ => panic!("Internal error framing JSON"),
-        }
+        self.discard_garbage();
+        None
+    }
+
+    fn stats(&self) -> FramingStats {
+        self.stats.clone()
     }
 }
 
 impl JsonFramer {
     pub fn new() -> JsonFramer {
+        JsonFramer::with_max_frame_bytes(MAX_JSON_FRAME_BYTES)
+    }
+
+    pub fn with_max_frame_bytes(max_frame_bytes: usize) -> JsonFramer {
         JsonFramer {
-            possible_start: None,
-            possible_end: None,
             data_so_far: vec![],
+            scanned: 0,
+            possible_start: None,
             brace_nest_count: 0,
             in_single_quote_string: false,
             in_double_quote_string: false,
             after_backslash: false,
-            _logger: Logger::new("JsonFramer"),
+            max_frame_bytes,
+            stats: FramingStats::default(),
+            logger: Logger::new("JsonFramer"),
         }
     }
 
     fn reset(&mut self) {
         self.possible_start = None;
-        self.possible_end = None;
         self.brace_nest_count = 0;
         self.in_single_quote_string = false;
         self.in_double_quote_string = false;
         self.after_backslash = false;
     }
 
-    fn check_data_chunk(&mut self) -> Option<Vec<u8>> {
-        match (self.possible_start, self.possible_end) {
-            (Some(start), Some(end)) => {
-                self.reset();
-                Some(Vec::from(&self.data_so_far[start..end]))
+    fn look_for_start(&mut self, byte: u8, offset: usize) {
+        static OB: u8 = '{' as u8;
+
+        if byte == OB {
+            self.reset();
+            self.brace_nest_count = 1;
+            self.possible_start = Some(offset);
+        }
+    }
+
+    fn cut_frame(&mut self, start: usize) -> FramedChunk {
+        let chunk = Vec::from(&self.data_so_far[start..self.scanned]);
+        self.stats.discarded_bytes += start as u64;
+        self.data_so_far = Vec::from(&self.data_so_far[self.scanned..]);
+        self.scanned = 0;
+        self.reset();
+        self.stats.frames += 1;
+        FramedChunk {
+            chunk,
+            last_chunk: true,
+        }
+    }
+
+    fn abandon_oversized_frame(&mut self) {
+        self.logger.warning(format!(
+            "Discarding clandestine frame that grew past {} bytes",
+            self.max_frame_bytes
+        ));
+        self.stats.oversized_frames += 1;
+        self.stats.discarded_bytes += self.scanned as u64;
+        self.data_so_far = Vec::from(&self.data_so_far[self.scanned..]);
+        self.scanned = 0;
+        self.reset();
+    }
+
+    fn discard_garbage(&mut self) {
+        let keep_from = match self.possible_start {
+            Some(start) => start,
+            None => self.data_so_far.len(),
+        };
+        if keep_from > 0 {
+            self.stats.discarded_bytes += keep_from as u64;
+            self.data_so_far = Vec::from(&self.data_so_far[keep_from..]);
+            self.scanned -= keep_from;
+            if self.possible_start.is_some() {
+                self.possible_start = Some(0);
             }
-            _ => None,
         }
     }
 
-    fn update_state(&mut self, byte: u8, offset: usize) {
+    fn update_state(&mut self, byte: u8) {
         static BS: u8 = '\\' as u8;
 
         if self.after_backslash {
@@ -92,8 +139,8 @@ impl JsonFramer {
         } else {
             self.handle_quotes(byte);
             if !self.in_single_quote_string && !self.in_double_quote_string {
-                self.handle_braces(byte, offset);
-            } else if self.possible_start.is_some() && (byte == BS) {
+                self.handle_braces(byte);
+            } else if byte == BS {
                 self.after_backslash = true
             }
         }
@@ -103,9 +150,6 @@ impl JsonFramer {
         static SQ: u8 = '\'' as u8;
         static DQ: u8 = '"' as u8;
 
-        if self.possible_start.is_none() {
-            return;
-        }
         match (
             self.in_single_quote_string,
             byte == SQ,
@@ -120,25 +164,14 @@ impl JsonFramer {
         }
     }
 
-    fn handle_braces(&mut self, byte: u8, offset: usize) {
+    fn handle_braces(&mut self, byte: u8) {
         static OB: u8 = '{' as u8;
         static CB: u8 = '}' as u8;
 
         if byte == OB {
             self.brace_nest_count += 1;
-            if self.brace_nest_count == 1 {
-                self.reset();
-                self.brace_nest_count = 1;
-                self.possible_start = Some(offset)
-            }
         } else if byte == CB {
             self.brace_nest_count -= 1;
-            if self.brace_nest_count < 0 {
-                self.brace_nest_count = 0;
-                self.possible_start = None;
-            } else if self.brace_nest_count == 0 && self.possible_start.is_some() {
-                self.possible_end = Some(offset + 1);
-            }
         }
     }
 }
@@ -146,6 +179,60 @@ impl JsonFramer {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_utils::logging::init_test_logging;
+    use crate::test_utils::logging::TestLogHandler;
+    use rand::prng::XorShiftRng;
+    use rand::Rng;
+    use rand::SeedableRng;
+    use serde_json;
+
+    fn make_rng(seed: u8) -> XorShiftRng {
+        XorShiftRng::from_seed([seed; 16])
+    }
+
+    fn make_json_frame(rng: &mut XorShiftRng) -> Vec<u8> {
+        let alphabet: Vec<char> = "ab{}[]'\"\\ :,".chars().collect();
+        let length = rng.gen_range(0, 40);
+        let text: String = (0..length)
+            .map(|_| alphabet[rng.gen_range(0, alphabet.len())])
+            .collect();
+        format!(
+            "{{\"component\": \"NBHD\", \"nested\": {{\"bodyText\": {}}}}}",
+            serde_json::to_string(&text).unwrap()
+        )
+        .into_bytes()
+    }
+
+    fn make_garbage(rng: &mut XorShiftRng) -> Vec<u8> {
+        let length = rng.gen_range(0, 20);
+        (0..length)
+            .map(|_| rng.gen::<u8>())
+            .filter(|byte| *byte != b'{')
+            .collect()
+    }
+
+    fn feed_in_random_pieces(
+        subject: &mut JsonFramer,
+        data: &[u8],
+        rng: &mut XorShiftRng,
+    ) -> Vec<Vec<u8>> {
+        let mut frames = vec![];
+        let mut offset = 0;
+        while offset < data.len() {
+            let end = offset + rng.gen_range(1, 50).min(data.len() - offset);
+            subject.add_data(&data[offset..end]);
+            while let Some(frame) = subject.take_frame() {
+                frames.push(frame.chunk);
+            }
+            offset = end;
+        }
+        frames
+    }
+
+    #[test]
+    fn constant_values() {
+        assert_eq!(MAX_JSON_FRAME_BYTES, 0x100000);
+    }
 
     #[test]
     fn json_framer_handles_backslashed_characters() {
@@ -238,4 +325,105 @@ mod tests {
 
         assert_eq!(result, None);
     }
+
+    #[test]
+    fn json_framer_abandons_an_oversized_frame_and_resyncs_on_the_next_one() {
+        init_test_logging();
+        let mut subject = JsonFramer::with_max_frame_bytes(20);
+
+        subject.add_data("{\"bodyText\": \"this is far too long\"}".as_bytes());
+        assert_eq!(subject.take_frame(), None);
+        subject.add_data("}{\"bodyText\": \"ok\"}".as_bytes());
+        let result = subject.take_frame();
+
+        assert_eq!(
+            String::from_utf8(result.unwrap().chunk).unwrap(),
+            "{\"bodyText\": \"ok\"}"
+        );
+        assert_eq!(
+            subject.stats(),
+            FramingStats {
+                frames: 1,
+                discarded_bytes: 37,
+                oversized_frames: 1,
+                unmaskable_frames: 0,
+            }
+        );
+        TestLogHandler::new().exists_log_containing(
+            "WARN: JsonFramer: Discarding clandestine frame that grew past 20 bytes",
+        );
+    }
+
+    #[test]
+    fn json_framer_never_holds_much_more_than_one_maximum_frame() {
+        let mut subject = JsonFramer::with_max_frame_bytes(100);
+
+        for _ in 0..100 {
+            subject.add_data("{{{{{{{{{{".as_bytes());
+            assert_eq!(subject.take_frame(), None);
+            assert!(subject.data_so_far.len() <= 110);
+        }
+
+        assert_eq!(subject.stats().oversized_frames, 9);
+    }
+
+    #[test]
+    fn json_framer_counts_discarded_garbage() {
+        let mut subject = JsonFramer::new();
+
+        subject.add_data("garbage{\"a\": 1}more garbage".as_bytes());
+        subject.take_frame().unwrap();
+        assert_eq!(subject.take_frame(), None);
+
+        assert_eq!(subject.stats().frames, 1);
+        assert_eq!(subject.stats().discarded_bytes, 19);
+        assert_eq!(subject.data_so_far.len(), 0);
+    }
+
+    #[test]
+    fn fuzz_json_framer_finds_every_frame_however_the_stream_is_fragmented() {
+        for seed in 1..=50 {
+            let mut rng = make_rng(seed);
+            let mut subject = JsonFramer::new();
+            let mut expected = vec![];
+            let mut stream = vec![];
+            for _ in 0..20 {
+                stream.extend(make_garbage(&mut rng));
+                let frame = make_json_frame(&mut rng);
+                stream.extend(&frame);
+                expected.push(frame);
+            }
+            stream.extend(make_garbage(&mut rng));
+
+            let result = feed_in_random_pieces(&mut subject, &stream, &mut rng);
+
+            assert_eq!(result, expected, "seed {}", seed);
+            assert_eq!(subject.stats().frames, 20, "seed {}", seed);
+        }
+    }
+
+    #[test]
+    fn fuzz_json_framer_survives_random_bytes() {
+        for seed in 1..=50 {
+            let mut rng = make_rng(seed);
+            let mut subject = JsonFramer::with_max_frame_bytes(200);
+            let data: Vec<u8> = (0..5000)
+                .map(|_| match rng.gen_range(0, 4) {
+                    0 => b'{',
+                    1 => b'}',
+                    2 => b'"',
+                    _ => rng.gen::<u8>(),
+                })
+                .collect();
+
+            let result = feed_in_random_pieces(&mut subject, &data, &mut rng);
+
+            result.iter().for_each(|frame| {
+                assert_eq!(frame[0], b'{', "seed {}", seed);
+                assert_eq!(frame[frame.len() - 1], b'}', "seed {}", seed);
+                assert!(frame.len() <= 200, "seed {}", seed);
+            });
+            assert!(subject.data_so_far.len() <= 250, "seed {}", seed);
+        }
+    }
 }
@@ -2,25 +2,37 @@
 
 pub mod accountant;
 mod actor_system_factory;
+mod blockchain_bridge;
 mod bootstrapper;
+mod config_file;
 mod configuration;
+mod cover_traffic;
 mod crash_test_dummy;
+mod diagnostics;
 pub mod discriminator;
 mod dispatcher;
 pub mod entry_dns;
+#[cfg(feature = "fault_injection")]
+pub mod fault_injection;
+mod firewall;
 pub mod hopper;
 pub mod http_request_start_finder;
+pub mod identity_bundle;
 pub mod json_discriminator_factory;
 pub mod json_framer;
 pub mod json_masquerader;
 mod listener_handler;
 pub mod masquerader;
+mod metrics;
 pub mod neighborhood;
+pub mod network_probe;
 mod null_masquerader;
 mod privilege_drop;
+mod probe_resistance;
 mod proxy_client;
 mod proxy_server;
 pub mod server_initializer;
+pub mod socks_discriminator_factory;
 mod stream_handler_pool;
 mod stream_messages;
 mod stream_reader;
@@ -29,7 +41,9 @@ mod stream_writer_unsorted;
 pub mod sub_lib;
 pub mod test_utils;
 pub mod tls_discriminator_factory;
-mod ui_gateway;
+pub mod ui_gateway;
 
 #[cfg(test)]
 mod node_test_utils;
+#[cfg(test)]
+mod wire_compat;
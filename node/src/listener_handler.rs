@@ -12,6 +12,7 @@ use std::io;
 use std::marker::Send;
 use std::net::IpAddr;
 use std::net::Ipv4Addr;
+use std::net::Ipv6Addr;
 use std::net::SocketAddr;
 use tokio::prelude::Async;
 use tokio::prelude::Future;
@@ -43,11 +44,17 @@ impl ListenerHandler for ListenerHandlerReal {
         port: u16,
         port_configuration: PortConfiguration,
     ) -> io::Result<()> {
+        let ip_addr = if port_configuration.is_local_only {
+            IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1))
+        } else if port_configuration.is_ipv6 {
+            IpAddr::V6(Ipv6Addr::UNSPECIFIED)
+        } else {
+            IpAddr::V4(Ipv4Addr::from(0))
+        };
         self.port = Some(port);
         self.port_configuration = Some(port_configuration);
         self.logger = Logger::new(&format!("ListenerHandler {}", port));
-        self.listener
-            .bind(SocketAddr::new(IpAddr::V4(Ipv4Addr::from(0)), port))
+        self.listener.bind(SocketAddr::new(ip_addr, port))
     }
 
     fn bind_subs(&mut self, add_stream_sub: Recipient<Syn, AddStreamMsg>) {
@@ -237,6 +244,43 @@ mod tests {
         assert!(port_configuration.is_clandestine);
     }
 
+    #[test]
+    fn binds_local_only_ports_to_localhost() {
+        let listener = TokioListenerWrapperMock::new().bind_result(Ok(()));
+        let listener_log = listener.log.clone();
+        let discriminator_factory = NullDiscriminatorFactory::new();
+        let mut subject = ListenerHandlerReal::new();
+        subject.listener = Box::new(listener);
+
+        let result = subject.bind_port_and_configuration(
+            1080,
+            PortConfiguration::new(vec![Box::new(discriminator_factory)], false).local_only(),
+        );
+
+        assert_eq!(result.unwrap(), ());
+        assert_eq!(
+            listener_log.dump(),
+            vec!(format!("bind (V4(127.0.0.1:1080))"))
+        );
+    }
+
+    #[test]
+    fn binds_ipv6_ports_to_every_ipv6_interface() {
+        let listener = TokioListenerWrapperMock::new().bind_result(Ok(()));
+        let listener_log = listener.log.clone();
+        let discriminator_factory = NullDiscriminatorFactory::new();
+        let mut subject = ListenerHandlerReal::new();
+        subject.listener = Box::new(listener);
+
+        let result = subject.bind_port_and_configuration(
+            2345,
+            PortConfiguration::new(vec![Box::new(discriminator_factory)], true).ipv6(),
+        );
+
+        assert_eq!(result.unwrap(), ());
+        assert_eq!(listener_log.dump(), vec!(format!("bind (V6([::]:2345))")));
+    }
+
     #[test]
     fn handles_connection_errors() {
         init_test_logging();
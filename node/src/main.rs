@@ -4,12 +4,24 @@
 use tokio;
 
 use futures::future::lazy;
+use node_lib::network_probe::NetworkProbe;
 use node_lib::server_initializer::ServerInitializer;
 use node_lib::sub_lib::main_tools::Command;
 use node_lib::sub_lib::main_tools::StdStreams;
 use std::io;
 
 pub fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    if NetworkProbe::is_requested(&args) {
+        let mut streams: StdStreams<'_> = StdStreams {
+            stdin: &mut io::stdin(),
+            stdout: &mut io::stdout(),
+            stderr: &mut io::stderr(),
+        };
+        let exit_code = NetworkProbe::new().go(&mut streams, &args);
+        ::std::process::exit(exit_code as i32);
+    }
+
     let main_fn = move || {
         let mut streams: StdStreams<'_> = StdStreams {
             stdin: &mut io::stdin(),
@@ -19,7 +31,10 @@ pub fn main() {
 
         let mut command = ServerInitializer::new();
         let streams_ref: &mut StdStreams<'_> = &mut streams;
-        command.go(streams_ref, &std::env::args().collect());
+        let exit_code = command.go(streams_ref, &args);
+        if exit_code != 0 {
+            ::std::process::exit(exit_code as i32);
+        }
 
         tokio::spawn(command);
         Ok(())
@@ -0,0 +1,300 @@
+// Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use crate::metrics::metrics_server;
+use crate::metrics::registry::Gauges;
+use crate::metrics::registry::MetricsRegistry;
+use crate::sub_lib::accountant::BalancesQueryMessage;
+use crate::sub_lib::logger::Logger;
+use crate::sub_lib::metrics::MetricsReport;
+use crate::sub_lib::metrics::MetricsSubs;
+use crate::sub_lib::metrics::METRICS_GAUGE_INTERVAL;
+use crate::sub_lib::neighborhood::GossipStatsQueryMessage;
+use crate::sub_lib::neighborhood::NeighborhoodStatusQueryMessage;
+use crate::sub_lib::peer_actors::BindMessage;
+use crate::sub_lib::peer_actors::StreamCountQueryMessage;
+use crate::sub_lib::proxy_client::ExitThroughputQueryMessage;
+use crate::sub_lib::utils::or_default;
+use actix::Actor;
+use actix::Addr;
+use actix::AsyncContext;
+use actix::Context;
+use actix::Handler;
+use actix::Message;
+use actix::Recipient;
+use actix::Syn;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio;
+use tokio::prelude::Future;
+
+#[derive(Message)]
+struct GaugeReading(Gauges);
+
+// The actors that know the gauges
+#[derive(Clone)]
+struct GaugeSources {
+    neighborhood: Recipient<Syn, NeighborhoodStatusQueryMessage>,
+    proxy_server: Recipient<Syn, StreamCountQueryMessage>,
+    proxy_client: Recipient<Syn, StreamCountQueryMessage>,
+    accountant: Recipient<Syn, BalancesQueryMessage>,
+    exit_throughput: Recipient<Syn, ExitThroughputQueryMessage>,
+    gossip_stats: Recipient<Syn, GossipStatsQueryMessage>,
+}
+
+// Counts what the other actors report and serves it, along with gauges it asks them for now and
+// then, for Prometheus to scrape. Without a port it still counts, but serves nothing and asks
+// nobody anything.
+pub struct Metrics {
+    port_opt: Option<u16>,
+    registry: Arc<Mutex<MetricsRegistry>>,
+    gauge_sources: Option<GaugeSources>,
+    gauge_interval: Duration,
+    logger: Logger,
+}
+
+impl Actor for Metrics {
+    type Context = Context<Self>;
+}
+
+impl Handler<BindMessage> for Metrics {
+    type Result = ();
+
+    fn handle(&mut self, msg: BindMessage, ctx: &mut Self::Context) -> Self::Result {
+        let port = match self.port_opt {
+            Some(port) => port,
+            None => return (),
+        };
+        match metrics_server::listen(port, self.registry.clone()) {
+            Ok(local_addr) => self
+                .logger
+                .info(format!("Serving metrics at http://{}/metrics", local_addr)),
+            Err(e) => panic!("Couldn't serve metrics on port {}: {}", port, e),
+        }
+        self.gauge_sources = Some(GaugeSources {
+            neighborhood: msg.peer_actors.neighborhood.status_query.clone(),
+            proxy_server: msg.peer_actors.proxy_server.stream_count_query.clone(),
+            proxy_client: msg.peer_actors.proxy_client.stream_count_query.clone(),
+            accountant: msg.peer_actors.accountant.balances_query.clone(),
+            exit_throughput: msg.peer_actors.proxy_client.exit_throughput_query.clone(),
+            gossip_stats: msg.peer_actors.neighborhood.gossip_stats_query.clone(),
+        });
+        self.read_gauges(ctx);
+        ()
+    }
+}
+
+impl Handler<MetricsReport> for Metrics {
+    type Result = ();
+
+    fn handle(&mut self, msg: MetricsReport, _ctx: &mut Self::Context) -> Self::Result {
+        self.registry
+            .lock()
+            .expect("Metrics registry is poisoned")
+            .record(&msg);
+        ()
+    }
+}
+
+impl Handler<GaugeReading> for Metrics {
+    type Result = ();
+
+    fn handle(&mut self, msg: GaugeReading, _ctx: &mut Self::Context) -> Self::Result {
+        self.registry
+            .lock()
+            .expect("Metrics registry is poisoned")
+            .set_gauges(msg.0);
+        ()
+    }
+}
+
+impl Metrics {
+    pub fn new(port_opt: Option<u16>) -> Metrics {
+        Metrics {
+            port_opt,
+            registry: Arc::new(Mutex::new(MetricsRegistry::new())),
+            gauge_sources: None,
+            gauge_interval: METRICS_GAUGE_INTERVAL,
+            logger: Logger::new("Metrics"),
+        }
+    }
+
+    pub fn make_subs_from(addr: &Addr<Syn, Metrics>) -> MetricsSubs {
+        MetricsSubs {
+            bind: addr.clone().recipient::<BindMessage>(),
+            report: addr.clone().recipient::<MetricsReport>(),
+        }
+    }
+
+    // Like a UI's status report, an actor that can't answer leaves its gauges at zero
+    fn read_gauges(&self, ctx: &mut Context<Self>) {
+        ctx.run_later(self.gauge_interval, |act, ctx| act.read_gauges(ctx));
+        let sources = self.gauge_sources.as_ref().expect("Metrics is unbound");
+        let addr: Addr<Syn, Metrics> = ctx.address();
+        tokio::spawn(
+            or_default(sources.neighborhood.send(NeighborhoodStatusQueryMessage {}))
+                .join5(
+                    or_default(sources.proxy_server.send(StreamCountQueryMessage {})),
+                    or_default(sources.proxy_client.send(StreamCountQueryMessage {})),
+                    or_default(sources.accountant.send(BalancesQueryMessage {})),
+                    or_default(sources.exit_throughput.send(ExitThroughputQueryMessage {})),
+                )
+                .join(or_default(
+                    sources.gossip_stats.send(GossipStatsQueryMessage {}),
+                ))
+                .map(
+                    move |(
+                        (
+                            neighborhood,
+                            originating_streams,
+                            exit_streams,
+                            balances,
+                            exit_throughput,
+                        ),
+                        gossip,
+                    )| {
+                        addr.try_send(GaugeReading(Gauges {
+                            originating_streams,
+                            exit_streams,
+                            neighbors: neighborhood.neighbors,
+                            known_nodes: neighborhood.known_nodes,
+                            receivable_balance: balances.receivable,
+                            payable_balance: balances.payable,
+                            exit_throughput,
+                            gossip,
+                        }))
+                        .expect("Metrics is dead")
+                    },
+                ),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sub_lib::utils::localhost;
+    use crate::test_utils::recorder::make_recorder;
+    use crate::test_utils::recorder::peer_actors_builder;
+    use crate::test_utils::test_utils::find_free_port;
+    use crate::test_utils::test_utils::wait_for;
+    use actix::msgs;
+    use actix::Arbiter;
+    use actix::System;
+    use std::io::Read;
+    use std::io::Write;
+    use std::net::SocketAddr;
+    use std::net::TcpStream;
+    use std::thread;
+
+    fn scrape(port: u16) -> String {
+        let mut client = TcpStream::connect(SocketAddr::new(localhost(), port)).unwrap();
+        client.write_all(b"GET /metrics HTTP/1.1\r\n\r\n").unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn reports_are_counted_whether_or_not_they_are_served() {
+        let system = System::new("reports_are_counted_whether_or_not_they_are_served");
+        let subject = Metrics::new(None);
+        let registry = subject.registry.clone();
+        let addr: Addr<Syn, Metrics> = subject.start();
+        let subs = Metrics::make_subs_from(&addr);
+        subs.bind
+            .try_send(BindMessage {
+                peer_actors: peer_actors_builder().build(),
+            })
+            .unwrap();
+
+        subs.report
+            .try_send(MetricsReport::CoresPackagesProcessed(2))
+            .unwrap();
+        subs.report
+            .try_send(MetricsReport::CoresPackagesDropped(1))
+            .unwrap();
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+        let mut expected = MetricsRegistry::new();
+        expected.record(&MetricsReport::CoresPackagesProcessed(2));
+        expected.record(&MetricsReport::CoresPackagesDropped(1));
+        assert_eq!(*registry.lock().unwrap(), expected);
+    }
+
+    #[test]
+    fn gauges_are_read_from_the_other_actors_and_served_with_the_counters() {
+        let port = find_free_port();
+        let (neighborhood, _, neighborhood_recording_arc) = make_recorder();
+        let (proxy_server, _, proxy_server_recording_arc) = make_recorder();
+        let (proxy_client, _, proxy_client_recording_arc) = make_recorder();
+        let (accountant, _, accountant_recording_arc) = make_recorder();
+        thread::spawn(move || {
+            let system =
+                System::new("gauges_are_read_from_the_other_actors_and_served_with_the_counters");
+            let mut subject = Metrics::new(Some(port));
+            subject.gauge_interval = Duration::from_millis(10);
+            let addr: Addr<Syn, Metrics> = subject.start();
+            let peer_actors = peer_actors_builder()
+                .neighborhood(neighborhood)
+                .proxy_server(proxy_server)
+                .proxy_client(proxy_client)
+                .accountant(accountant)
+                .build();
+            addr.try_send(BindMessage { peer_actors }).unwrap();
+            addr.try_send(MetricsReport::BytesRouted(1234)).unwrap();
+            system.run();
+        });
+        wait_for(None, None, || {
+            accountant_recording_arc.lock().unwrap().len() > 1
+        });
+
+        let response = scrape(port);
+
+        assert_eq!(
+            response.contains("\nsubstratum_routed_bytes_total 1234\n"),
+            true,
+            "{}",
+            response
+        );
+        assert_eq!(
+            response.contains("\nsubstratum_neighborhood_nodes 0\n"),
+            true
+        );
+        assert_eq!(
+            neighborhood_recording_arc
+                .lock()
+                .unwrap()
+                .get_record::<NeighborhoodStatusQueryMessage>(0),
+            &NeighborhoodStatusQueryMessage {}
+        );
+        assert_eq!(
+            neighborhood_recording_arc
+                .lock()
+                .unwrap()
+                .get_record::<GossipStatsQueryMessage>(1),
+            &GossipStatsQueryMessage {}
+        );
+        assert_eq!(
+            response.contains("\nsubstratum_gossip_node_records_total{outcome=\"new\"} 0\n"),
+            true
+        );
+        vec![proxy_server_recording_arc, proxy_client_recording_arc]
+            .into_iter()
+            .for_each(|recording_arc| {
+                assert_eq!(
+                    recording_arc
+                        .lock()
+                        .unwrap()
+                        .get_record::<StreamCountQueryMessage>(0),
+                    &StreamCountQueryMessage {}
+                )
+            });
+        assert_eq!(
+            response.contains(
+                "\nsubstratum_exit_throughput_bytes_per_second{direction=\"upstream\"} 0\n"
+            ),
+            true
+        );
+    }
+}
@@ -0,0 +1,174 @@
+// Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use crate::metrics::registry::MetricsRegistry;
+use crate::sub_lib::logger::Logger;
+use crate::sub_lib::utils::localhost;
+use std::io;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Write;
+use std::net::SocketAddr;
+use std::net::TcpListener;
+use std::net::TcpStream;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+pub const METRICS_PATH: &str = "/metrics";
+// A scraper that connects and says nothing doesn't hold up the next one for longer than this
+pub const METRICS_REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Serves the registry over plain HTTP/1.1 on 127.0.0.1, one request per connection, on a thread of
+// its own: a scrape only ever has to wait for the registry's lock, never for the actor system.
+pub fn listen(port: u16, registry: Arc<Mutex<MetricsRegistry>>) -> io::Result<SocketAddr> {
+    let listener = TcpListener::bind(SocketAddr::new(localhost(), port))?;
+    let local_addr = listener.local_addr()?;
+    thread::spawn(move || {
+        let logger = Logger::new("Metrics");
+        listener
+            .incoming()
+            .for_each(|stream_result| match stream_result {
+                Ok(stream) => serve(stream, &registry, &logger),
+                Err(e) => logger.error(format!("Couldn't accept metrics connection: {}", e)),
+            })
+    });
+    Ok(local_addr)
+}
+
+fn serve(stream: TcpStream, registry: &Arc<Mutex<MetricsRegistry>>, logger: &Logger) {
+    let mut writer = match stream.try_clone() {
+        Ok(writer) => writer,
+        Err(e) => {
+            logger.error(format!("Couldn't answer metrics connection: {}", e));
+            return;
+        }
+    };
+    stream
+        .set_read_timeout(Some(METRICS_REQUEST_TIMEOUT))
+        .is_ok();
+    let mut lines = BufReader::new(stream).lines();
+    let request_line = match lines.next() {
+        Some(Ok(line)) => line,
+        _ => return,
+    };
+    // The headers say nothing we need, but they have to be read before the connection is closed
+    while let Some(Ok(line)) = lines.next() {
+        if line.is_empty() {
+            break;
+        }
+    }
+    let response = respond_to(&request_line, || {
+        registry
+            .lock()
+            .expect("Metrics registry is poisoned")
+            .render()
+    });
+    if let Err(e) = writer.write_all(response.as_bytes()) {
+        logger.debug(format!("Couldn't send metrics: {}", e));
+    }
+}
+
+fn respond_to<F>(request_line: &str, render: F) -> String
+where
+    F: FnOnce() -> String,
+{
+    let words: Vec<&str> = request_line.split_whitespace().collect();
+    let path = match words.as_slice() {
+        [_, target, _] => target.split('?').next().unwrap_or_default(),
+        _ => return response("400 Bad Request", "text/plain", "Bad request\n"),
+    };
+    if path != METRICS_PATH {
+        response("404 Not Found", "text/plain", "Not found\n")
+    } else if words[0] != "GET" {
+        response(
+            "405 Method Not Allowed",
+            "text/plain",
+            "Only GET is allowed\n",
+        )
+    } else {
+        response(
+            "200 OK",
+            "text/plain; version=0.0.4; charset=utf-8",
+            &render(),
+        )
+    }
+}
+
+fn response(status: &str, content_type: &str, body: &str) -> String {
+    format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sub_lib::metrics::MetricsReport;
+    use crate::test_utils::test_utils::find_free_port;
+    use std::io::Read;
+
+    #[test]
+    fn constants_have_correct_values() {
+        assert_eq!(METRICS_PATH, "/metrics");
+        assert_eq!(METRICS_REQUEST_TIMEOUT, Duration::from_secs(5));
+    }
+
+    #[test]
+    fn only_a_get_of_the_metrics_path_is_answered_with_metrics() {
+        let render = || String::from("booga 1\n");
+
+        assert_eq!(
+            respond_to("GET /metrics?format=text HTTP/1.1", render),
+            String::from(
+                "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4; charset=utf-8\r\n\
+                 Content-Length: 8\r\nConnection: close\r\n\r\nbooga 1\n"
+            )
+        );
+        assert_eq!(
+            respond_to("GET / HTTP/1.1", render).starts_with("HTTP/1.1 404 Not Found\r\n"),
+            true
+        );
+        assert_eq!(
+            respond_to("POST /metrics HTTP/1.1", render)
+                .starts_with("HTTP/1.1 405 Method Not Allowed\r\n"),
+            true
+        );
+        assert_eq!(
+            respond_to("booga", render).starts_with("HTTP/1.1 400 Bad Request\r\n"),
+            true
+        );
+    }
+
+    #[test]
+    fn a_scrape_gets_what_is_in_the_registry() {
+        let registry = Arc::new(Mutex::new(MetricsRegistry::new()));
+        let local_addr = listen(find_free_port(), registry.clone()).unwrap();
+        registry
+            .lock()
+            .unwrap()
+            .record(&MetricsReport::BytesExited(4321));
+        let mut client = TcpStream::connect(local_addr).unwrap();
+        client
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+
+        client
+            .write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .unwrap();
+
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        assert_eq!(local_addr.ip(), localhost());
+        assert_eq!(response.starts_with("HTTP/1.1 200 OK\r\n"), true);
+        assert_eq!(
+            response.contains("\nsubstratum_exit_bytes_total 4321\n"),
+            true,
+            "{}",
+            response
+        );
+    }
+}
@@ -0,0 +1,5 @@
+// Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+
+pub mod metrics;
+mod metrics_server;
+pub mod registry;
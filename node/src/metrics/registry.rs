@@ -0,0 +1,503 @@
+// Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use crate::sub_lib::metrics::MetricsReport;
+use crate::sub_lib::neighborhood::GossipAcceptanceStats;
+use crate::sub_lib::proxy_client::ExitThroughput;
+use crate::sub_lib::proxy_client::ThroughputUtilization;
+use std::time::Duration;
+
+// Upper bounds, in seconds, of the buckets DNS resolution times are counted in
+pub const DNS_RESOLUTION_BUCKETS: [f64; 10] =
+    [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0];
+
+// What the Metrics actor last heard when it asked the other actors
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Gauges {
+    pub originating_streams: usize,
+    pub exit_streams: usize,
+    pub neighbors: usize,
+    // This Node included
+    pub known_nodes: usize,
+    pub receivable_balance: i64,
+    pub payable_balance: i64,
+    pub exit_throughput: ExitThroughput,
+    // Running totals, but read like the gauges: they start over only if the Node does
+    pub gossip: GossipAcceptanceStats,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Histogram {
+    bounds: Vec<f64>,
+    // Each observation is counted only in the first bucket it fits; rendering adds them up
+    counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    pub fn new(bounds: &[f64]) -> Histogram {
+        Histogram {
+            bounds: bounds.to_vec(),
+            counts: vec![0; bounds.len()],
+            sum: 0.0,
+            count: 0,
+        }
+    }
+
+    pub fn observe(&mut self, value: f64) {
+        if let Some(index) = self.bounds.iter().position(|bound| value <= *bound) {
+            self.counts[index] += 1;
+        }
+        self.sum += value;
+        self.count += 1;
+    }
+
+    fn render(&self, name: &str, help: &str, out: &mut String) {
+        let mut samples: Vec<(String, String)> = vec![];
+        let mut cumulative = 0;
+        for (bound, count) in self.bounds.iter().zip(self.counts.iter()) {
+            cumulative += count;
+            samples.push((
+                format!("{}_bucket{{le=\"{}\"}}", name, bound),
+                format!("{}", cumulative),
+            ));
+        }
+        samples.push((
+            format!("{}_bucket{{le=\"+Inf\"}}", name),
+            format!("{}", self.count),
+        ));
+        samples.push((format!("{}_sum", name), format!("{}", self.sum)));
+        samples.push((format!("{}_count", name), format!("{}", self.count)));
+        render_metric(name, help, "histogram", samples, out);
+    }
+}
+
+// Everything the metrics endpoint serves, kept by the Metrics actor and read by the HTTP server
+#[derive(Clone, Debug, PartialEq)]
+pub struct MetricsRegistry {
+    bytes_exited: u64,
+    bytes_routed: u64,
+    cores_packages_processed: u64,
+    cores_packages_dropped: u64,
+    gauges: Gauges,
+    dns_resolution: Histogram,
+}
+
+impl MetricsRegistry {
+    pub fn new() -> MetricsRegistry {
+        MetricsRegistry {
+            bytes_exited: 0,
+            bytes_routed: 0,
+            cores_packages_processed: 0,
+            cores_packages_dropped: 0,
+            gauges: Gauges::default(),
+            dns_resolution: Histogram::new(&DNS_RESOLUTION_BUCKETS),
+        }
+    }
+
+    pub fn record(&mut self, report: &MetricsReport) {
+        match report {
+            MetricsReport::BytesExited(count) => self.bytes_exited += count,
+            MetricsReport::BytesRouted(count) => self.bytes_routed += count,
+            MetricsReport::CoresPackagesProcessed(count) => self.cores_packages_processed += count,
+            MetricsReport::CoresPackagesDropped(count) => self.cores_packages_dropped += count,
+            MetricsReport::DnsResolution(elapsed) => self.dns_resolution.observe(seconds(elapsed)),
+        }
+    }
+
+    pub fn set_gauges(&mut self, gauges: Gauges) {
+        self.gauges = gauges;
+    }
+
+    // In Prometheus's text exposition format
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let single = |value: String| vec![(String::new(), value)];
+        render_metric(
+            "substratum_exit_bytes_total",
+            "Bytes carried between this Node's exit and servers on the Internet",
+            "counter",
+            single(format!("{}", self.bytes_exited)),
+            &mut out,
+        );
+        render_metric(
+            "substratum_routed_bytes_total",
+            "Bytes of CORES packages taken in by the Hopper",
+            "counter",
+            single(format!("{}", self.bytes_routed)),
+            &mut out,
+        );
+        render_metric(
+            "substratum_cores_packages_processed_total",
+            "CORES packages taken in by the Hopper",
+            "counter",
+            single(format!("{}", self.cores_packages_processed)),
+            &mut out,
+        );
+        render_metric(
+            "substratum_cores_packages_dropped_total",
+            "CORES packages the Hopper took in but could not deliver or relay",
+            "counter",
+            single(format!("{}", self.cores_packages_dropped)),
+            &mut out,
+        );
+        render_metric(
+            "substratum_streams",
+            "Streams open through this Node",
+            "gauge",
+            vec![
+                (
+                    String::from("{role=\"originating\"}"),
+                    format!("{}", self.gauges.originating_streams),
+                ),
+                (
+                    String::from("{role=\"exit\"}"),
+                    format!("{}", self.gauges.exit_streams),
+                ),
+            ],
+            &mut out,
+        );
+        render_metric(
+            "substratum_neighbors",
+            "Nodes this Node is directly connected to",
+            "gauge",
+            single(format!("{}", self.gauges.neighbors)),
+            &mut out,
+        );
+        render_metric(
+            "substratum_neighborhood_nodes",
+            "Nodes in this Node's neighborhood database, this one included",
+            "gauge",
+            single(format!("{}", self.gauges.known_nodes)),
+            &mut out,
+        );
+        render_metric(
+            "substratum_balance",
+            "Total balance of the Accountant's receivable and payable accounts",
+            "gauge",
+            vec![
+                (
+                    String::from("{account=\"receivable\"}"),
+                    format!("{}", self.gauges.receivable_balance),
+                ),
+                (
+                    String::from("{account=\"payable\"}"),
+                    format!("{}", self.gauges.payable_balance),
+                ),
+            ],
+            &mut out,
+        );
+        let directions = vec![
+            ("upstream", &self.gauges.exit_throughput.upstream),
+            ("downstream", &self.gauges.exit_throughput.downstream),
+        ];
+        render_metric(
+            "substratum_exit_throughput_bytes_per_second",
+            "Bytes this Node's exit carried to servers (upstream) or from them (downstream) in the last whole second",
+            "gauge",
+            directions
+                .iter()
+                .map(|(direction, utilization)| {
+                    (
+                        format!("{{direction=\"{}\"}}", direction),
+                        format!("{}", utilization.bytes_last_second),
+                    )
+                })
+                .collect(),
+            &mut out,
+        );
+        // A direction with no cap has no utilization to report
+        render_metric(
+            "substratum_exit_throughput_utilization",
+            "Fraction of --exit_upstream_limit or --exit_downstream_limit used in the last whole second",
+            "gauge",
+            directions
+                .iter()
+                .filter_map(|(direction, utilization)| {
+                    utilization_of_cap(utilization).map(|fraction| {
+                        (
+                            format!("{{direction=\"{}\"}}", direction),
+                            format!("{}", fraction),
+                        )
+                    })
+                })
+                .collect(),
+            &mut out,
+        );
+        let gossip = &self.gauges.gossip;
+        render_metric(
+            "substratum_gossip_messages_total",
+            "Gossip messages the Neighborhood took in, full or delta",
+            "counter",
+            vec![
+                (
+                    String::from("{kind=\"full\"}"),
+                    format!("{}", gossip.gossip_messages - gossip.delta_gossip_messages),
+                ),
+                (
+                    String::from("{kind=\"delta\"}"),
+                    format!("{}", gossip.delta_gossip_messages),
+                ),
+            ],
+            &mut out,
+        );
+        render_metric(
+            "substratum_gossip_node_records_total",
+            "NodeRecords in incoming Gossip, by what the Neighborhood did with them",
+            "counter",
+            vec![
+                ("outcome=\"new\"", gossip.new_node_records),
+                ("outcome=\"updated\"", gossip.updated_node_records),
+                ("outcome=\"duplicate\"", gossip.duplicate_node_records),
+                ("outcome=\"stale\"", gossip.stale_node_records),
+                (
+                    "outcome=\"rejected\",reason=\"blank_public_key\"",
+                    gossip.rejected_blank_public_key,
+                ),
+                (
+                    "outcome=\"rejected\",reason=\"blank_neighbor_key\"",
+                    gossip.rejected_blank_neighbor_key,
+                ),
+                (
+                    "outcome=\"rejected\",reason=\"self_neighbor\"",
+                    gossip.rejected_self_neighbor,
+                ),
+            ]
+            .into_iter()
+            .map(|(labels, count)| (format!("{{{}}}", labels), format!("{}", count)))
+            .collect(),
+            &mut out,
+        );
+        self.dns_resolution.render(
+            "substratum_dns_resolution_seconds",
+            "Time the exit took to resolve hostnames",
+            &mut out,
+        );
+        out
+    }
+}
+
+// Each sample is the rest of its line between the metric name and the value: labels, or for a
+// histogram the whole name
+fn render_metric(
+    name: &str,
+    help: &str,
+    kind: &str,
+    samples: Vec<(String, String)>,
+    out: &mut String,
+) {
+    out.push_str(&format!(
+        "# HELP {} {}\n# TYPE {} {}\n",
+        name, help, name, kind
+    ));
+    samples.into_iter().for_each(|(suffix, value)| {
+        if kind == "histogram" {
+            out.push_str(&format!("{} {}\n", suffix, value))
+        } else {
+            out.push_str(&format!("{}{} {}\n", name, suffix, value))
+        }
+    });
+}
+
+fn utilization_of_cap(utilization: &ThroughputUtilization) -> Option<f64> {
+    match utilization.bytes_per_second_cap {
+        Some(0) | None => None,
+        Some(cap) => Some(utilization.bytes_last_second as f64 / cap as f64),
+    }
+}
+
+fn seconds(duration: &Duration) -> f64 {
+    duration.as_secs() as f64 + f64::from(duration.subsec_nanos()) / 1_000_000_000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn constants_have_correct_values() {
+        assert_eq!(
+            DNS_RESOLUTION_BUCKETS,
+            [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0]
+        );
+    }
+
+    #[test]
+    fn reports_add_up_and_gauges_are_replaced() {
+        let mut subject = MetricsRegistry::new();
+
+        subject.record(&MetricsReport::BytesExited(100));
+        subject.record(&MetricsReport::BytesExited(23));
+        subject.record(&MetricsReport::BytesRouted(456));
+        subject.record(&MetricsReport::CoresPackagesProcessed(3));
+        subject.record(&MetricsReport::CoresPackagesDropped(1));
+        subject.set_gauges(Gauges {
+            known_nodes: 99,
+            ..Gauges::default()
+        });
+        subject.set_gauges(Gauges {
+            originating_streams: 4,
+            exit_streams: 2,
+            neighbors: 3,
+            known_nodes: 7,
+            receivable_balance: 1234,
+            payable_balance: -56,
+            exit_throughput: ExitThroughput {
+                upstream: ThroughputUtilization {
+                    bytes_per_second_cap: Some(1000),
+                    bytes_last_second: 250,
+                    total_bytes: 9999,
+                },
+                downstream: ThroughputUtilization {
+                    bytes_per_second_cap: None,
+                    bytes_last_second: 4321,
+                    total_bytes: 99999,
+                },
+            },
+            gossip: GossipAcceptanceStats::default(),
+        });
+
+        let result = subject.render();
+
+        assert_eq!(
+            result.contains(
+                "# HELP substratum_exit_bytes_total Bytes carried between this Node's exit and servers on the Internet\n\
+                 # TYPE substratum_exit_bytes_total counter\n\
+                 substratum_exit_bytes_total 123\n"
+            ),
+            true,
+            "{}",
+            result
+        );
+        assert_eq!(
+            result.contains("\nsubstratum_routed_bytes_total 456\n"),
+            true
+        );
+        assert_eq!(
+            result.contains("\nsubstratum_cores_packages_processed_total 3\n"),
+            true
+        );
+        assert_eq!(
+            result.contains("\nsubstratum_cores_packages_dropped_total 1\n"),
+            true
+        );
+        assert_eq!(
+            result.contains(
+                "# TYPE substratum_streams gauge\n\
+                 substratum_streams{role=\"originating\"} 4\n\
+                 substratum_streams{role=\"exit\"} 2\n"
+            ),
+            true
+        );
+        assert_eq!(result.contains("\nsubstratum_neighbors 3\n"), true);
+        assert_eq!(result.contains("\nsubstratum_neighborhood_nodes 7\n"), true);
+        assert_eq!(
+            result.contains(
+                "substratum_balance{account=\"receivable\"} 1234\n\
+                 substratum_balance{account=\"payable\"} -56\n"
+            ),
+            true
+        );
+        assert_eq!(
+            result.contains(
+                "# TYPE substratum_exit_throughput_bytes_per_second gauge\n\
+                 substratum_exit_throughput_bytes_per_second{direction=\"upstream\"} 250\n\
+                 substratum_exit_throughput_bytes_per_second{direction=\"downstream\"} 4321\n"
+            ),
+            true,
+            "{}",
+            result
+        );
+        assert_eq!(
+            result.contains(
+                "# TYPE substratum_exit_throughput_utilization gauge\n\
+                 substratum_exit_throughput_utilization{direction=\"upstream\"} 0.25\n\
+                 # HELP"
+            ),
+            true,
+            "{}",
+            result
+        );
+    }
+
+    #[test]
+    fn gossip_acceptance_totals_are_served_as_counters() {
+        let mut subject = MetricsRegistry::new();
+        subject.set_gauges(Gauges {
+            gossip: GossipAcceptanceStats {
+                gossip_messages: 10,
+                delta_gossip_messages: 3,
+                new_node_records: 5,
+                updated_node_records: 4,
+                duplicate_node_records: 8,
+                stale_node_records: 2,
+                rejected_blank_public_key: 1,
+                rejected_blank_neighbor_key: 0,
+                rejected_self_neighbor: 6,
+            },
+            ..Gauges::default()
+        });
+
+        let result = subject.render();
+
+        assert_eq!(
+            result.contains(
+                "# TYPE substratum_gossip_messages_total counter\n\
+                 substratum_gossip_messages_total{kind=\"full\"} 7\n\
+                 substratum_gossip_messages_total{kind=\"delta\"} 3\n"
+            ),
+            true,
+            "{}",
+            result
+        );
+        assert_eq!(
+            result.contains(
+                "# TYPE substratum_gossip_node_records_total counter\n\
+                 substratum_gossip_node_records_total{outcome=\"new\"} 5\n\
+                 substratum_gossip_node_records_total{outcome=\"updated\"} 4\n\
+                 substratum_gossip_node_records_total{outcome=\"duplicate\"} 8\n\
+                 substratum_gossip_node_records_total{outcome=\"stale\"} 2\n\
+                 substratum_gossip_node_records_total{outcome=\"rejected\",reason=\"blank_public_key\"} 1\n\
+                 substratum_gossip_node_records_total{outcome=\"rejected\",reason=\"blank_neighbor_key\"} 0\n\
+                 substratum_gossip_node_records_total{outcome=\"rejected\",reason=\"self_neighbor\"} 6\n"
+            ),
+            true,
+            "{}",
+            result
+        );
+    }
+
+    #[test]
+    fn dns_resolutions_are_counted_in_cumulative_buckets() {
+        let mut subject = MetricsRegistry::new();
+
+        subject.record(&MetricsReport::DnsResolution(Duration::from_millis(3)));
+        subject.record(&MetricsReport::DnsResolution(Duration::from_millis(40)));
+        subject.record(&MetricsReport::DnsResolution(Duration::from_millis(50)));
+        subject.record(&MetricsReport::DnsResolution(Duration::from_secs(7)));
+
+        let result = subject.render();
+
+        assert_eq!(
+            result.ends_with(
+                "# HELP substratum_dns_resolution_seconds Time the exit took to resolve hostnames\n\
+                 # TYPE substratum_dns_resolution_seconds histogram\n\
+                 substratum_dns_resolution_seconds_bucket{le=\"0.005\"} 1\n\
+                 substratum_dns_resolution_seconds_bucket{le=\"0.01\"} 1\n\
+                 substratum_dns_resolution_seconds_bucket{le=\"0.025\"} 1\n\
+                 substratum_dns_resolution_seconds_bucket{le=\"0.05\"} 3\n\
+                 substratum_dns_resolution_seconds_bucket{le=\"0.1\"} 3\n\
+                 substratum_dns_resolution_seconds_bucket{le=\"0.25\"} 3\n\
+                 substratum_dns_resolution_seconds_bucket{le=\"0.5\"} 3\n\
+                 substratum_dns_resolution_seconds_bucket{le=\"1\"} 3\n\
+                 substratum_dns_resolution_seconds_bucket{le=\"2.5\"} 3\n\
+                 substratum_dns_resolution_seconds_bucket{le=\"5\"} 3\n\
+                 substratum_dns_resolution_seconds_bucket{le=\"+Inf\"} 4\n\
+                 substratum_dns_resolution_seconds_sum 7.093\n\
+                 substratum_dns_resolution_seconds_count 4\n"
+            ),
+            true,
+            "{}",
+            result
+        );
+    }
+}
@@ -0,0 +1,115 @@
+// Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use std::collections::VecDeque;
+use std::net::IpAddr;
+use std::time::Duration;
+use std::time::Instant;
+
+pub const DEBUT_WINDOW: Duration = Duration::from_secs(60);
+pub const MAX_DEBUTS_PER_WINDOW: usize = 60;
+pub const MAX_DEBUTS_PER_WINDOW_PER_IP: usize = 10;
+
+// Every Node record with a key the Neighborhood has never seen costs it CPU to check and a place in
+// the database to keep, so a flood of made-up newcomers could swamp it. This counts how many such
+// debuts have been let in during the last DEBUT_WINDOW, both altogether and from each neighbor IP
+// they arrived from, and turns away the ones over the limit. A real newcomer that's turned away
+// isn't lost: it'll be Gossiped about again.
+pub struct DebutLimiter {
+    window: Duration,
+    max_total: usize,
+    max_per_ip: usize,
+    admitted: VecDeque<(Instant, IpAddr)>,
+}
+
+impl DebutLimiter {
+    pub fn new() -> DebutLimiter {
+        DebutLimiter::with_limits(
+            DEBUT_WINDOW,
+            MAX_DEBUTS_PER_WINDOW,
+            MAX_DEBUTS_PER_WINDOW_PER_IP,
+        )
+    }
+
+    pub fn with_limits(window: Duration, max_total: usize, max_per_ip: usize) -> DebutLimiter {
+        DebutLimiter {
+            window,
+            max_total,
+            max_per_ip,
+            admitted: VecDeque::new(),
+        }
+    }
+
+    // Returns how many of the requested debuts from source_ip may go ahead now
+    pub fn admit(&mut self, source_ip: IpAddr, requested: usize, now: Instant) -> usize {
+        self.forget_before(now);
+        let from_source = self
+            .admitted
+            .iter()
+            .filter(|(_, ip)| ip == &source_ip)
+            .count();
+        let total_room = self.max_total.saturating_sub(self.admitted.len());
+        let source_room = self.max_per_ip.saturating_sub(from_source);
+        let allowed = requested.min(total_room).min(source_room);
+        (0..allowed).for_each(|_| self.admitted.push_back((now, source_ip)));
+        allowed
+    }
+
+    fn forget_before(&mut self, now: Instant) {
+        while let Some((when, _)) = self.admitted.front() {
+            if now.duration_since(*when) < self.window {
+                break;
+            }
+            self.admitted.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn ip(s: &str) -> IpAddr {
+        IpAddr::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn debuts_from_one_ip_are_limited() {
+        let mut subject = DebutLimiter::with_limits(Duration::from_secs(60), 100, 3);
+        let now = Instant::now();
+
+        assert_eq!(subject.admit(ip("1.2.3.4"), 2, now), 2);
+        assert_eq!(subject.admit(ip("1.2.3.4"), 2, now), 1);
+        assert_eq!(subject.admit(ip("1.2.3.4"), 1, now), 0);
+        assert_eq!(subject.admit(ip("2.3.4.5"), 1, now), 1);
+    }
+
+    #[test]
+    fn debuts_from_everywhere_are_limited_together() {
+        let mut subject = DebutLimiter::with_limits(Duration::from_secs(60), 4, 3);
+        let now = Instant::now();
+
+        assert_eq!(subject.admit(ip("1.2.3.4"), 3, now), 3);
+        assert_eq!(subject.admit(ip("2.3.4.5"), 3, now), 1);
+        assert_eq!(subject.admit(ip("3.4.5.6"), 3, now), 0);
+    }
+
+    #[test]
+    fn debuts_are_forgotten_once_the_window_has_passed() {
+        let mut subject = DebutLimiter::with_limits(Duration::from_secs(60), 4, 2);
+        let then = Instant::now();
+        subject.admit(ip("1.2.3.4"), 2, then);
+
+        assert_eq!(
+            subject.admit(ip("1.2.3.4"), 2, then + Duration::from_secs(59)),
+            0
+        );
+        assert_eq!(
+            subject.admit(ip("1.2.3.4"), 2, then + Duration::from_secs(60)),
+            2
+        );
+        assert_eq!(
+            subject.admit(ip("1.2.3.4"), 1, then + Duration::from_secs(60)),
+            0
+        );
+    }
+}
@@ -2,12 +2,15 @@
 use super::neighborhood_database::NodeRecord;
 use super::neighborhood_database::NodeRecordInner;
 use super::neighborhood_database::NodeSignatures;
+use crate::sub_lib::cryptde::CryptDE;
 use crate::sub_lib::cryptde::PublicKey;
+use crate::sub_lib::utils::is_false;
 use serde_derive::{Deserialize, Serialize};
 use std::collections::HashSet;
 use std::fmt::Debug;
 use std::fmt::Error;
 use std::fmt::Formatter;
+use std::net::IpAddr;
 
 #[derive(Clone, PartialEq, Hash, Eq, Serialize, Deserialize)]
 pub struct GossipNodeRecord {
@@ -39,6 +42,12 @@ impl GossipNodeRecord {
                 is_bootstrap_node: node_record_ref.is_bootstrap_node(),
                 neighbors: node_record_ref.neighbors().clone(),
                 version: node_record_ref.version(),
+                load: node_record_ref.load(),
+                offers_exit_tls: node_record_ref.offers_exit_tls(),
+                relay_only: node_record_ref.relay_only(),
+                rate_card: node_record_ref.rate_card(),
+                country_opt: node_record_ref.country_opt(),
+                exit_sla_opt: node_record_ref.exit_sla_opt(),
             },
             // crashpoint
             signatures: node_record_ref
@@ -60,9 +69,24 @@ impl GossipNodeRecord {
         node_record
             .neighbors_mut()
             .extend(self.inner.neighbors.clone());
+        node_record.set_load(self.inner.load);
+        node_record.set_offers_exit_tls(self.inner.offers_exit_tls);
+        node_record.set_relay_only(self.inner.relay_only);
+        node_record.set_rate_card(self.inner.rate_card);
+        node_record.set_country_opt(self.inner.country_opt.clone());
+        node_record.set_exit_sla_opt(self.inner.exit_sla_opt.clone());
         node_record
     }
 
+    // A record that comes without its NodeAddr carries the signature that was made without it
+    pub fn signature_is_valid(&self, cryptde: &dyn CryptDE) -> bool {
+        let signature = match self.inner.node_addr_opt {
+            Some(_) => self.signatures.complete(),
+            None => self.signatures.obscured(),
+        };
+        self.inner.signature_is_valid(signature, cryptde)
+    }
+
     // TODO - should we use a json serializer to make this?
     fn to_human_readable(&self) -> String {
         let mut human_readable = String::new();
@@ -87,6 +111,18 @@ impl GossipNodeRecord {
         ));
         human_readable.push_str(&format!("\n\t\tneighbors: {:?},", self.inner.neighbors));
         human_readable.push_str(&format!("\n\t\tversion: {:?},", self.inner.version));
+        human_readable.push_str(&format!("\n\t\tload: {:?},", self.inner.load));
+        human_readable.push_str(&format!(
+            "\n\t\toffers_exit_tls: {:?},",
+            self.inner.offers_exit_tls
+        ));
+        human_readable.push_str(&format!("\n\t\trelay_only: {:?},", self.inner.relay_only));
+        human_readable.push_str(&format!("\n\t\trate_card: {:?},", self.inner.rate_card));
+        human_readable.push_str(&format!("\n\t\tcountry_opt: {:?},", self.inner.country_opt));
+        human_readable.push_str(&format!(
+            "\n\t\texit_sla_opt: {:?},",
+            self.inner.exit_sla_opt
+        ));
         human_readable.push_str("\n\t},");
         human_readable.push_str("\n\tsignatures: Signatures {");
         human_readable.push_str(&format!(
@@ -110,6 +146,25 @@ impl GossipNodeRecord {
 #[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
 pub struct Gossip {
     pub node_records: Vec<GossipNodeRecord>,
+    // The IP address the sender has seen the target's traffic arrive from, and has confirmed it can
+    // connect to. Only offered to a target that advertised no IP address of its own; left off the
+    // wire otherwise, so older Nodes see the same Gossip they always have.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub observed_ip_opt: Option<IpAddr>,
+    // How many more times this Gossip may be relayed beyond its target. Older Nodes leave it off,
+    // and their Gossip is relayed as far as the change it makes deserves.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttl_opt: Option<u8>,
+    // Counts the Gossip this sender has sent to this target, so that the target can acknowledge it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sequence_opt: Option<u32>,
+    // The highest sequence number the sender has received from the target.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ack_opt: Option<u32>,
+    // Set when records the target has already acknowledged were left out. A delta says nothing
+    // about the Nodes it doesn't mention.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub delta: bool,
 }
 
 pub fn to_dot_graph(gossip: Gossip, target: &PublicKey, source: PublicKey) -> String {
@@ -163,6 +218,11 @@ impl GossipBuilder {
         GossipBuilder {
             gossip: Gossip {
                 node_records: vec![],
+                observed_ip_opt: None,
+                ttl_opt: None,
+                sequence_opt: None,
+                ack_opt: None,
+                delta: false,
             },
             keys_so_far: HashSet::new(),
         }
@@ -193,11 +253,45 @@ mod tests {
     use super::super::gossip::GossipBuilder;
     use super::super::neighborhood_test_utils::make_node_record;
     use super::*;
+    use crate::sub_lib::cryptde::PlainData;
+    use crate::sub_lib::cryptde_real::CryptDEReal;
     use crate::sub_lib::node_addr::NodeAddr;
     use crate::sub_lib::wallet::Wallet;
     use std::net::IpAddr;
     use std::str::FromStr;
 
+    #[test]
+    fn signatures_are_valid_only_for_the_content_their_node_signed() {
+        let cryptde = CryptDEReal::new();
+        let mut node_record = NodeRecord::new(
+            &cryptde.public_key(),
+            Some(&NodeAddr::new(
+                &IpAddr::from_str("1.2.3.4").unwrap(),
+                &vec![1234],
+            )),
+            Wallet::new("earning"),
+            None,
+            false,
+            None,
+            3,
+        );
+        node_record.sign(&cryptde);
+        let revealed = GossipNodeRecord::from(&node_record, true);
+        let obscured = GossipNodeRecord::from(&node_record, false);
+        let mut tampered = revealed.clone();
+        tampered.inner.version = 4;
+        let mut forged = revealed.clone();
+        forged.signatures = obscured.signatures.clone();
+        forged.signatures.complete = CryptDEReal::new()
+            .sign(&PlainData::new(b"anything"))
+            .unwrap();
+
+        assert_eq!(revealed.signature_is_valid(&cryptde), true);
+        assert_eq!(obscured.signature_is_valid(&cryptde), true);
+        assert_eq!(tampered.signature_is_valid(&cryptde), false);
+        assert_eq!(forged.signature_is_valid(&cryptde), false);
+    }
+
     #[test]
     fn can_create_a_node_record() {
         let mut expected_node_record = make_node_record(1234, true, true);
@@ -214,6 +308,64 @@ mod tests {
         assert_eq!(actual_node_record, expected_node_record);
     }
 
+    #[test]
+    fn observed_ip_is_left_off_the_wire_when_there_is_none() {
+        let node = make_node_record(1234, true, false);
+        let without = GossipBuilder::new().node(&node, true).build();
+        let mut with = without.clone();
+        with.observed_ip_opt = Some(IpAddr::from_str("3.4.5.6").unwrap());
+
+        let without_bytes = serde_cbor::ser::to_vec(&without).unwrap();
+        let with_bytes = serde_cbor::ser::to_vec(&with).unwrap();
+
+        assert_eq!(
+            String::from_utf8_lossy(&without_bytes).contains("observed_ip_opt"),
+            false
+        );
+        let with_decoded: Gossip = serde_cbor::de::from_slice(&with_bytes[..]).unwrap();
+        assert_eq!(with_decoded, with);
+        let without_decoded: Gossip = serde_cbor::de::from_slice(&without_bytes[..]).unwrap();
+        assert_eq!(without_decoded, without);
+    }
+
+    #[test]
+    fn ttl_is_left_off_the_wire_when_there_is_none() {
+        let node = make_node_record(1234, true, false);
+        let without = GossipBuilder::new().node(&node, true).build();
+        let mut with = without.clone();
+        with.ttl_opt = Some(3);
+
+        let without_bytes = serde_cbor::ser::to_vec(&without).unwrap();
+        let with_bytes = serde_cbor::ser::to_vec(&with).unwrap();
+
+        assert_eq!(
+            String::from_utf8_lossy(&without_bytes).contains("ttl_opt"),
+            false
+        );
+        let with_decoded: Gossip = serde_cbor::de::from_slice(&with_bytes[..]).unwrap();
+        assert_eq!(with_decoded, with);
+    }
+
+    #[test]
+    fn sequence_ack_and_delta_are_left_off_the_wire_when_unused() {
+        let node = make_node_record(1234, true, false);
+        let without = GossipBuilder::new().node(&node, true).build();
+        let mut with = without.clone();
+        with.sequence_opt = Some(5);
+        with.ack_opt = Some(4);
+        with.delta = true;
+
+        let without_bytes = serde_cbor::ser::to_vec(&without).unwrap();
+        let with_bytes = serde_cbor::ser::to_vec(&with).unwrap();
+
+        let without_text = String::from_utf8_lossy(&without_bytes);
+        assert_eq!(without_text.contains("sequence_opt"), false);
+        assert_eq!(without_text.contains("ack_opt"), false);
+        assert_eq!(without_text.contains("delta"), false);
+        let with_decoded: Gossip = serde_cbor::de::from_slice(&with_bytes[..]).unwrap();
+        assert_eq!(with_decoded, with);
+    }
+
     #[test]
     #[should_panic(expected = "GossipBuilder cannot add a node more than once")]
     fn adding_node_twice_to_gossip_builder_causes_panic() {
@@ -320,7 +472,7 @@ mod tests {
         let result = format!("{:?}", gossip);
         let expected = format!(
             "\nGossipNodeRecord {{{}{}\n}}",
-            "\n\tinner: NodeRecordInner {\n\t\tpublic_key: AQIDBA,\n\t\tnode_addr_opt: Some(1.2.3.4:[1234]),\n\t\tis_bootstrap_node: false,\n\t\tearning_wallet: Wallet { address: \"0x1234\" },\n\t\tconsuming_wallet: Some(Wallet { address: \"0x4321\" }),\n\t\tneighbors: [],\n\t\tversion: 0,\n\t},",
+            "\n\tinner: NodeRecordInner {\n\t\tpublic_key: AQIDBA,\n\t\tnode_addr_opt: Some(1.2.3.4:[1234]),\n\t\tis_bootstrap_node: false,\n\t\tearning_wallet: Wallet { address: \"0x1234\" },\n\t\tconsuming_wallet: Some(Wallet { address: \"0x4321\" }),\n\t\tneighbors: [],\n\t\tversion: 0,\n\t\tload: LoadAdvertisement { capacity_class: Medium, load_factor: 0 },\n\t\toffers_exit_tls: false,\n\t\trelay_only: false,\n\t\trate_card: RateCard { routing_service_rate: 3, routing_byte_rate: 4, exit_service_rate: 1, exit_byte_rate: 2 },\n\t\tcountry_opt: None,\n\t\texit_sla_opt: None,\n\t},",
             "\n\tsignatures: Signatures {\n\t\tcomplete: CryptData { data: [115, 105, 103, 110, 101, 100] },\n\t\tobscured: CryptData { data: [115, 105, 103, 110, 101, 100] },\n\t},"
         );
 
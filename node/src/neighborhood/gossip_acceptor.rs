@@ -6,8 +6,10 @@ use super::neighborhood_database::NeighborhoodDatabaseError;
 use super::neighborhood_database::NodeRecord;
 use crate::sub_lib::cryptde::PublicKey;
 use crate::sub_lib::logger::Logger;
+use crate::sub_lib::neighborhood::GossipAcceptanceStats;
 use crate::sub_lib::tcp_wrappers::TcpStreamWrapperFactory;
 use crate::sub_lib::tcp_wrappers::TcpStreamWrapperFactoryReal;
+use std::cell::RefCell;
 use std::collections::HashSet;
 use std::net::SocketAddr;
 
@@ -17,11 +19,13 @@ pub trait GossipAcceptor {
     // malformed Gossip accidentally at the beginning, but he will soon learn to generate valid
     // Gossip, whereupon effort spent detecting malformed Gossip will be wasted.
     fn handle(&self, database: &mut NeighborhoodDatabase, gossip: Gossip) -> bool;
+    fn stats(&self) -> GossipAcceptanceStats;
 }
 
 pub struct GossipAcceptorReal {
     pub logger: Logger,
     pub tcp_stream_factory: Box<dyn TcpStreamWrapperFactory>,
+    stats: RefCell<GossipAcceptanceStats>,
 }
 
 impl GossipAcceptor for GossipAcceptorReal {
@@ -36,12 +40,36 @@ impl GossipAcceptor for GossipAcceptorReal {
             `gossip`: the Gossip message with which to update the DB
     */
     fn handle(&self, database: &mut NeighborhoodDatabase, gossip: Gossip) -> bool {
+        let before = self.stats();
+        self.count(|stats| stats.gossip_messages += 1);
+        if gossip.delta {
+            self.count(|stats| stats.delta_gossip_messages += 1);
+        }
         let mut changed = self.handle_node_records(database, &gossip);
         changed = self.add_ip_neighbors(database, &gossip) || changed;
+        let after = self.stats();
+        self.logger.info(format!(
+            "{} about {} Nodes: {} new, {} updated, {} duplicate, {} stale, {} rejected",
+            if gossip.delta {
+                "Delta Gossip"
+            } else {
+                "Gossip"
+            },
+            gossip.node_records.len(),
+            after.new_node_records - before.new_node_records,
+            after.updated_node_records - before.updated_node_records,
+            after.duplicate_node_records - before.duplicate_node_records,
+            after.stale_node_records - before.stale_node_records,
+            after.rejected() - before.rejected()
+        ));
         self.logger
             .debug(format!("Database after accepting Gossip: {:?}", database));
         changed
     }
+
+    fn stats(&self) -> GossipAcceptanceStats {
+        self.stats.borrow().clone()
+    }
 }
 
 impl GossipAcceptorReal {
@@ -49,9 +77,17 @@ impl GossipAcceptorReal {
         GossipAcceptorReal {
             logger: Logger::new("GossipAcceptorReal"),
             tcp_stream_factory: Box::new(TcpStreamWrapperFactoryReal {}),
+            stats: RefCell::new(GossipAcceptanceStats::default()),
         }
     }
 
+    fn count<F>(&self, f: F)
+    where
+        F: FnOnce(&mut GossipAcceptanceStats),
+    {
+        f(&mut self.stats.borrow_mut())
+    }
+
     fn handle_node_records(
         &self,
         database: &mut NeighborhoodDatabase,
@@ -69,6 +105,7 @@ impl GossipAcceptorReal {
                         .expect("Key magically disappeared");
                     let node_addr_changed = self.update_node_addrs(gnr_ref, node_record);
                     if node_record.version() < gnr_ref.inner.version {
+                        self.count(|stats| stats.updated_node_records += 1);
                         self.update_version(gnr_ref, node_record);
 
                         let is_bootstrap_node_changed =
@@ -76,17 +113,27 @@ impl GossipAcceptorReal {
                         let neighbors_changed = self.update_neighbors(gnr_ref, node_record);
                         let signatures_changed = self.update_signatures(gnr_ref, node_record);
                         let wallet_changed = self.update_wallet(gnr_ref, node_record);
+                        let load_changed = self.update_load(gnr_ref, node_record);
+                        let exit_sla_changed = self.update_exit_sla(gnr_ref, node_record);
 
                         node_addr_changed
                             || is_bootstrap_node_changed
                             || neighbors_changed
                             || signatures_changed
                             || wallet_changed
+                            || load_changed
+                            || exit_sla_changed
                             || changed
                     } else {
+                        if node_record.version() == gnr_ref.inner.version {
+                            self.count(|stats| stats.duplicate_node_records += 1);
+                        } else {
+                            self.count(|stats| stats.stale_node_records += 1);
+                        }
                         node_addr_changed || changed
                     }
                 } else {
+                    self.count(|stats| stats.new_node_records += 1);
                     database
                         .add_node(&gnr_ref.to_node_record())
                         .expect("Key magically appeared");
@@ -130,17 +177,20 @@ impl GossipAcceptorReal {
         if gnr.inner.public_key.is_empty() {
             self.logger
                 .error(format!("Rejecting GossipNodeRecord with blank public key"));
+            self.count(|stats| stats.rejected_blank_public_key += 1);
             false
         } else if gnr.inner.neighbors.contains(&empty_key) {
             self.logger.error(format!(
                 "Rejecting neighbor reference with blank public key"
             ));
+            self.count(|stats| stats.rejected_blank_neighbor_key += 1);
             false
         } else if gnr.inner.neighbors.contains(&gnr.inner.public_key) {
             self.logger.error(format!(
                 "Gossip attempted to make node {} neighbor to itself: ignoring",
-                &gnr.inner.public_key
+                gnr.inner.public_key.fingerprint()
             ));
+            self.count(|stats| stats.rejected_self_neighbor += 1);
             false
         } else {
             true
@@ -149,12 +199,15 @@ impl GossipAcceptorReal {
 
     fn update_node_addrs(&self, gnr_ref: &GossipNodeRecord, node_record: &mut NodeRecord) -> bool {
         if let Some(new_node_addr_ref) = gnr_ref.inner.node_addr_opt.as_ref() {
+            if node_record.node_addr_opt().as_ref() == Some(new_node_addr_ref) {
+                return false;
+            }
             match node_record.set_node_addr(new_node_addr_ref) {
                 Ok(_) => true,
                 Err(NeighborhoodDatabaseError::NodeAddrAlreadySet(old_addr)) => {
                     self.logger.error(format!(
                         "Gossip attempted to change IP address of node {} from {} to {}: ignoring",
-                        &gnr_ref.inner.public_key,
+                        gnr_ref.inner.public_key.fingerprint(),
                         old_addr.ip_addr(),
                         new_node_addr_ref.ip_addr()
                     ));
@@ -194,6 +247,14 @@ impl GossipAcceptorReal {
         )
     }
 
+    fn update_load(&self, gnr_ref: &GossipNodeRecord, node_record: &mut NodeRecord) -> bool {
+        node_record.set_load(gnr_ref.inner.load)
+    }
+
+    fn update_exit_sla(&self, gnr_ref: &GossipNodeRecord, node_record: &mut NodeRecord) -> bool {
+        node_record.set_exit_sla_opt(gnr_ref.inner.exit_sla_opt.clone())
+    }
+
     fn update_version(&self, gnr_ref: &GossipNodeRecord, node_record: &mut NodeRecord) {
         node_record.set_version(gnr_ref.inner.version);
     }
@@ -213,12 +274,15 @@ mod tests {
 
     use super::super::gossip::GossipBuilder;
     use super::super::gossip::GossipNodeRecord;
+    use super::super::neighborhood_database::LoadAdvertisement;
     use super::super::neighborhood_database::NodeRecord;
     use super::super::neighborhood_database::NodeSignatures;
     use super::super::neighborhood_test_utils::make_node_record;
     use super::super::neighborhood_test_utils::*;
     use crate::sub_lib::cryptde::CryptData;
     use crate::sub_lib::cryptde::PublicKey;
+    use crate::sub_lib::neighborhood::CapacityClass;
+    use crate::sub_lib::neighborhood::ExitSla;
     use crate::sub_lib::node_addr::NodeAddr;
     use crate::sub_lib::wallet::Wallet;
     use crate::test_utils::logging::init_test_logging;
@@ -267,6 +331,11 @@ mod tests {
 
         let gossip = Gossip {
             node_records: vec![other_node_gossip],
+            observed_ip_opt: None,
+            ttl_opt: None,
+            sequence_opt: None,
+            ack_opt: None,
+            delta: false,
         };
 
         let result = subject.handle(&mut db, gossip);
@@ -293,6 +362,11 @@ mod tests {
 
         let gossip = Gossip {
             node_records: vec![other_node_gossip],
+            observed_ip_opt: None,
+            ttl_opt: None,
+            sequence_opt: None,
+            ack_opt: None,
+            delta: false,
         };
 
         subject.handle(&mut db, gossip);
@@ -326,6 +400,11 @@ mod tests {
 
         let gossip = Gossip {
             node_records: vec![other_node_gossip],
+            observed_ip_opt: None,
+            ttl_opt: None,
+            sequence_opt: None,
+            ack_opt: None,
+            delta: false,
         };
 
         subject.handle(&mut db, gossip);
@@ -518,7 +597,40 @@ mod tests {
             existing_node.node_addr_opt().unwrap().ip_addr()
         );
         let tlh = TestLogHandler::new();
-        tlh.assert_logs_contain_in_order(vec!("ERROR: GossipAcceptorReal: Gossip attempted to change IP address of node AgMEBQ from 2.3.4.5 to 3.4.5.6: ignoring"));
+        tlh.assert_logs_contain_in_order(vec!("ERROR: GossipAcceptorReal: Gossip attempted to change IP address of node aibqibi from 2.3.4.5 to 3.4.5.6: ignoring"));
+    }
+
+    #[test]
+    fn gossip_that_repeats_existing_node_ip_is_not_a_change() {
+        init_test_logging();
+        let this_node = make_node_record(1234, true, false);
+        let mut existing_node = make_node_record(3456, true, false);
+        let mut database = NeighborhoodDatabase::new(
+            this_node.public_key(),
+            this_node.node_addr_opt().as_ref().unwrap(),
+            this_node.earning_wallet(),
+            this_node.consuming_wallet(),
+            this_node.is_bootstrap_node(),
+            cryptde(),
+        );
+        database.add_node(&existing_node).unwrap();
+        database
+            .add_neighbor(this_node.public_key(), existing_node.public_key())
+            .unwrap();
+        existing_node.increment_version();
+        let gossip = GossipBuilder::new().node(&existing_node, true).build();
+        let subject = GossipAcceptorReal::new();
+
+        subject.handle(&mut database, gossip);
+
+        let existing_node_ref = database.node_by_key(existing_node.public_key()).unwrap();
+        assert_eq!(existing_node_ref.version(), existing_node.version());
+        assert_eq!(
+            existing_node_ref.node_addr_opt(),
+            existing_node.node_addr_opt()
+        );
+        TestLogHandler::new()
+            .exists_no_log_containing("Gossip attempted to change IP address of node amcakbq");
     }
 
     #[test]
@@ -587,6 +699,11 @@ mod tests {
 
         let gossip = Gossip {
             node_records: vec![GossipNodeRecord::from(&invalid_record, true)],
+            observed_ip_opt: None,
+            ttl_opt: None,
+            sequence_opt: None,
+            ack_opt: None,
+            delta: false,
         };
         let subject = GossipAcceptorReal::new();
 
@@ -601,7 +718,7 @@ mod tests {
             &vec!(PublicKey::new(&[5, 6, 7, 8]))
         );
         let tlh = TestLogHandler::new();
-        tlh.assert_logs_contain_in_order(vec!("ERROR: GossipAcceptorReal: Gossip attempted to make node AgMEBQ neighbor to itself: ignoring"));
+        tlh.assert_logs_contain_in_order(vec!("ERROR: GossipAcceptorReal: Gossip attempted to make node aibqibi neighbor to itself: ignoring"));
     }
 
     #[test]
@@ -999,6 +1116,76 @@ mod tests {
         assert_eq!(node.consuming_wallet(), newer_version.consuming_wallet());
     }
 
+    #[test]
+    fn handle_updates_load_when_a_newer_version_is_received_and_returns_true() {
+        let this_node = make_node_record(1234, true, false);
+        let existing_node = make_node_record(2345, true, false);
+        let mut newer_version = existing_node.clone();
+        newer_version.set_load(LoadAdvertisement {
+            capacity_class: CapacityClass::Low,
+            load_factor: 80,
+        });
+        newer_version
+            .neighbors_mut()
+            .push(this_node.public_key().clone());
+        newer_version.increment_version();
+
+        let mut database = NeighborhoodDatabase::new(
+            this_node.public_key(),
+            this_node.node_addr_opt().as_ref().unwrap(),
+            this_node.earning_wallet(),
+            this_node.consuming_wallet(),
+            this_node.is_bootstrap_node(),
+            cryptde(),
+        );
+        database.add_node(&existing_node).unwrap();
+        database
+            .add_neighbor(this_node.public_key(), existing_node.public_key())
+            .unwrap();
+        database
+            .add_neighbor(existing_node.public_key(), this_node.public_key())
+            .unwrap();
+
+        let gossip = GossipBuilder::new().node(&newer_version, true).build();
+        let subject = GossipAcceptorReal::new();
+
+        let result = subject.handle(&mut database, gossip);
+
+        assert!(result, "Gossip did not result in a change to the database");
+        let node = database.node_by_key(existing_node.public_key()).unwrap();
+        assert_eq!(node.load(), newer_version.load());
+    }
+
+    #[test]
+    fn handle_updates_exit_sla_when_a_newer_version_is_received() {
+        let this_node = make_node_record(1234, true, false);
+        let existing_node = make_node_record(2345, true, false);
+        let mut newer_version = existing_node.clone();
+        newer_version.set_exit_sla_opt(Some(ExitSla {
+            max_streams: 8,
+            max_stream_bytes_per_second: 65536,
+            content_policy_hash: String::from("booga"),
+        }));
+        newer_version.increment_version();
+        let mut database = NeighborhoodDatabase::new(
+            this_node.public_key(),
+            this_node.node_addr_opt().as_ref().unwrap(),
+            this_node.earning_wallet(),
+            this_node.consuming_wallet(),
+            this_node.is_bootstrap_node(),
+            cryptde(),
+        );
+        database.add_node(&existing_node).unwrap();
+        let gossip = GossipBuilder::new().node(&newer_version, false).build();
+        let subject = GossipAcceptorReal::new();
+
+        let result = subject.handle(&mut database, gossip);
+
+        assert!(result, "Gossip did not result in a change to the database");
+        let node = database.node_by_key(existing_node.public_key()).unwrap();
+        assert_eq!(node.exit_sla_opt(), newer_version.exit_sla_opt());
+    }
+
     #[test]
     fn handle_returns_false_when_gossip_results_in_no_change_to_an_existing_node_wallet() {
         let this_node = make_node_record(1234, true, false);
@@ -1162,4 +1349,107 @@ mod tests {
         assert_eq!(node.is_bootstrap_node(), newer_version.is_bootstrap_node());
         assert_eq!(node.consuming_wallet(), newer_version.consuming_wallet());
     }
+
+    #[test]
+    fn handle_counts_node_records_by_what_happened_to_them() {
+        init_test_logging();
+        let this_node = make_node_record(1234, true, false);
+        let mut database = NeighborhoodDatabase::new(
+            this_node.public_key(),
+            this_node.node_addr_opt().as_ref().unwrap(),
+            this_node.earning_wallet(),
+            this_node.consuming_wallet(),
+            this_node.is_bootstrap_node(),
+            cryptde(),
+        );
+        let duplicate_node = make_node_record(2345, true, false);
+        database.add_node(&duplicate_node).unwrap();
+        let stale_node = make_node_record(3456, true, false);
+        let mut current_node = stale_node.clone();
+        current_node.increment_version();
+        database.add_node(&current_node).unwrap();
+        let new_node = make_node_record(4567, true, false);
+        let mut blank_key = GossipNodeRecord::from(&make_node_record(5678, false, false), true);
+        blank_key.inner.public_key = PublicKey::new(&[]);
+        let mut self_neighbor_node = make_node_record(6789, false, false);
+        let self_neighbor_key = self_neighbor_node.public_key().clone();
+        self_neighbor_node.neighbors_mut().push(self_neighbor_key);
+        let gossip = Gossip {
+            node_records: vec![
+                GossipNodeRecord::from(&duplicate_node, true),
+                GossipNodeRecord::from(&stale_node, true),
+                GossipNodeRecord::from(&new_node, true),
+                blank_key,
+                GossipNodeRecord::from(&self_neighbor_node, true),
+            ],
+            observed_ip_opt: None,
+            ttl_opt: None,
+            sequence_opt: None,
+            ack_opt: None,
+            delta: false,
+        };
+        let subject = GossipAcceptorReal::new_for_tests(3);
+
+        subject.handle(&mut database, gossip);
+
+        assert_eq!(
+            subject.stats(),
+            GossipAcceptanceStats {
+                gossip_messages: 1,
+                delta_gossip_messages: 0,
+                new_node_records: 1,
+                updated_node_records: 0,
+                duplicate_node_records: 1,
+                stale_node_records: 1,
+                rejected_blank_public_key: 1,
+                rejected_blank_neighbor_key: 0,
+                rejected_self_neighbor: 1,
+            }
+        );
+        TestLogHandler::new().exists_log_containing(
+            "INFO: GossipAcceptorReal: Gossip about 5 Nodes: 1 new, 0 updated, 1 duplicate, 1 stale, 2 rejected",
+        );
+    }
+
+    #[test]
+    fn delta_gossip_leaves_the_nodes_it_does_not_mention_alone() {
+        init_test_logging();
+        let this_node = make_node_record(5678, true, false);
+        let mut database = NeighborhoodDatabase::new(
+            this_node.public_key(),
+            this_node.node_addr_opt().as_ref().unwrap(),
+            this_node.earning_wallet(),
+            this_node.consuming_wallet(),
+            this_node.is_bootstrap_node(),
+            cryptde(),
+        );
+        let unmentioned_node = make_node_record(6789, true, false);
+        database.add_node(&unmentioned_node).unwrap();
+        let mut changed_node = make_node_record(7890, false, false);
+        database.add_node(&changed_node).unwrap();
+        changed_node.set_wallets(Wallet::new("changed"), None);
+        changed_node.increment_version();
+        let mut gossip = GossipBuilder::new().node(&changed_node, false).build();
+        gossip.delta = true;
+        let subject = GossipAcceptorReal::new_for_tests(0);
+
+        let result = subject.handle(&mut database, gossip);
+
+        assert_eq!(result, true);
+        assert_eq!(
+            database.node_by_key(unmentioned_node.public_key()),
+            Some(&unmentioned_node)
+        );
+        assert_eq!(
+            database
+                .node_by_key(changed_node.public_key())
+                .unwrap()
+                .version(),
+            changed_node.version()
+        );
+        assert_eq!(subject.stats().delta_gossip_messages, 1);
+        TestLogHandler::new().exists_log_containing(
+            "INFO: GossipAcceptorReal: Delta Gossip about 1 Nodes: 0 new, 1 updated, 0 duplicate, 0 stale, 0 rejected",
+        );
+    }
 }
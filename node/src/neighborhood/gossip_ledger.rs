@@ -0,0 +1,405 @@
+// Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use super::gossip::Gossip;
+use super::gossip::GossipNodeRecord;
+use crate::sub_lib::cryptde::PublicKey;
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::time::Duration;
+use std::time::Instant;
+
+pub const FULL_SYNC_INTERVAL: Duration = Duration::from_secs(600);
+pub const UNACKNOWLEDGED_GOSSIP_LIMIT: usize = 16;
+
+// What a neighbor is known to have heard about a Node: a version, and whether the NodeAddr came
+// with it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Known {
+    version: u32,
+    revealed: bool,
+}
+
+impl Known {
+    fn of(gnr: &GossipNodeRecord) -> Known {
+        Known {
+            version: gnr.inner.version,
+            revealed: gnr.inner.node_addr_opt.is_some(),
+        }
+    }
+
+    fn covers(&self, gnr: &GossipNodeRecord) -> bool {
+        (self.version > gnr.inner.version)
+            || ((self.version == gnr.inner.version)
+                && (self.revealed || gnr.inner.node_addr_opt.is_none()))
+    }
+
+    fn merge(self, other: Known) -> Known {
+        if other.version > self.version {
+            other
+        } else if other.version == self.version {
+            Known {
+                version: self.version,
+                revealed: self.revealed || other.revealed,
+            }
+        } else {
+            self
+        }
+    }
+}
+
+struct NeighborLedger {
+    next_sequence: u32,
+    unacknowledged: VecDeque<(u32, HashMap<PublicKey, Known>)>,
+    known: HashMap<PublicKey, Known>,
+    last_received_opt: Option<u32>,
+    last_full_sync_opt: Option<Instant>,
+}
+
+impl NeighborLedger {
+    fn new() -> NeighborLedger {
+        NeighborLedger {
+            next_sequence: 1,
+            unacknowledged: VecDeque::new(),
+            known: HashMap::new(),
+            last_received_opt: None,
+            last_full_sync_opt: None,
+        }
+    }
+
+    fn learn(&mut self, key: &PublicKey, known: Known) {
+        let merged = match self.known.get(key) {
+            Some(existing) => existing.merge(known),
+            None => known,
+        };
+        self.known.insert(key.clone(), merged);
+    }
+
+    fn acknowledge(&mut self, ack: u32) {
+        while let Some((sequence, _)) = self.unacknowledged.front() {
+            if *sequence > ack {
+                break;
+            }
+            let (_, records) = self
+                .unacknowledged
+                .pop_front()
+                .expect("Gossip magically disappeared");
+            records
+                .into_iter()
+                .for_each(|(key, known)| self.learn(&key, known));
+        }
+    }
+
+    fn full_sync_due(&self, now: Instant) -> bool {
+        match (self.last_received_opt, self.last_full_sync_opt) {
+            (None, _) => true,
+            (_, None) => true,
+            (_, Some(last_full_sync)) => now.duration_since(last_full_sync) >= FULL_SYNC_INTERVAL,
+        }
+    }
+}
+
+// Keeps track, for each neighbor, of which NodeRecords it has acknowledged, so that Gossip to it can
+// leave out the ones it already has. A neighbor acknowledges Gossip by sending back the highest
+// sequence number it has received; the records it sends are acknowledged too, since it plainly
+// has them. Every FULL_SYNC_INTERVAL a neighbor gets everything anyway, in case it has lost track.
+// A neighbor that has never sent a sequence number is an older Node, and always gets everything.
+pub struct GossipLedger {
+    neighbors: HashMap<PublicKey, NeighborLedger>,
+}
+
+impl GossipLedger {
+    pub fn new() -> GossipLedger {
+        GossipLedger {
+            neighbors: HashMap::new(),
+        }
+    }
+
+    pub fn received(&mut self, neighbor: &PublicKey, gossip: &Gossip) {
+        let sequence = match gossip.sequence_opt {
+            Some(sequence) => sequence,
+            None => {
+                self.neighbors.remove(neighbor);
+                return;
+            }
+        };
+        let ledger = self
+            .neighbors
+            .entry(neighbor.clone())
+            .or_insert_with(NeighborLedger::new);
+        // Sequence numbers only go backward when the neighbor has restarted and forgotten it all
+        match ledger.last_received_opt {
+            Some(last_received) if sequence <= last_received => *ledger = NeighborLedger::new(),
+            _ => (),
+        }
+        ledger.last_received_opt = Some(sequence);
+        if let Some(ack) = gossip.ack_opt {
+            ledger.acknowledge(ack);
+        }
+        gossip
+            .node_records
+            .iter()
+            .for_each(|gnr| ledger.learn(&gnr.inner.public_key, Known::of(gnr)));
+    }
+
+    // Trims and stamps Gossip that's about to go to a neighbor. False if there's nothing left in
+    // it worth sending.
+    pub fn prepare(&mut self, neighbor: &PublicKey, gossip: &mut Gossip, now: Instant) -> bool {
+        let ledger = self
+            .neighbors
+            .entry(neighbor.clone())
+            .or_insert_with(NeighborLedger::new);
+        if ledger.full_sync_due(now) {
+            ledger.last_full_sync_opt = Some(now);
+            gossip.delta = false;
+        } else {
+            let known = &ledger.known;
+            gossip
+                .node_records
+                .retain(|gnr| match known.get(&gnr.inner.public_key) {
+                    Some(known) => !known.covers(gnr),
+                    None => true,
+                });
+            if gossip.node_records.is_empty() {
+                return false;
+            }
+            gossip.delta = true;
+        }
+        let sequence = ledger.next_sequence;
+        ledger.next_sequence += 1;
+        gossip.sequence_opt = Some(sequence);
+        gossip.ack_opt = ledger.last_received_opt;
+        ledger.unacknowledged.push_back((
+            sequence,
+            gossip
+                .node_records
+                .iter()
+                .map(|gnr| (gnr.inner.public_key.clone(), Known::of(gnr)))
+                .collect(),
+        ));
+        while ledger.unacknowledged.len() > UNACKNOWLEDGED_GOSSIP_LIMIT {
+            ledger.unacknowledged.pop_front();
+        }
+        true
+    }
+
+    pub fn forget(&mut self, neighbor: &PublicKey) {
+        self.neighbors.remove(neighbor);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::gossip::GossipBuilder;
+    use super::super::neighborhood_database::NodeRecord;
+    use super::super::neighborhood_test_utils::make_node_record;
+    use super::*;
+
+    fn gossip_about(nodes: &[&NodeRecord]) -> Gossip {
+        nodes
+            .iter()
+            .fold(GossipBuilder::new(), |builder, node| {
+                builder.node(node, true)
+            })
+            .build()
+    }
+
+    fn reply(sequence: u32, ack_opt: Option<u32>) -> Gossip {
+        let mut gossip = gossip_about(&[]);
+        gossip.sequence_opt = Some(sequence);
+        gossip.ack_opt = ack_opt;
+        gossip
+    }
+
+    fn keys_of(gossip: &Gossip) -> Vec<PublicKey> {
+        gossip
+            .node_records
+            .iter()
+            .map(|gnr| gnr.public_key())
+            .collect()
+    }
+
+    #[test]
+    fn constants_have_correct_values() {
+        assert_eq!(FULL_SYNC_INTERVAL, Duration::from_secs(600));
+        assert_eq!(UNACKNOWLEDGED_GOSSIP_LIMIT, 16);
+    }
+
+    #[test]
+    fn a_neighbor_that_has_never_sent_a_sequence_number_gets_everything_every_time() {
+        let mut subject = GossipLedger::new();
+        let neighbor = PublicKey::new(b"neighbor");
+        let one = make_node_record(1234, true, false);
+        let now = Instant::now();
+        let mut first = gossip_about(&[&one]);
+        let mut second = gossip_about(&[&one]);
+
+        assert_eq!(subject.prepare(&neighbor, &mut first, now), true);
+        subject.received(&neighbor, &gossip_about(&[]));
+        assert_eq!(subject.prepare(&neighbor, &mut second, now), true);
+
+        assert_eq!(keys_of(&second), vec![one.public_key().clone()]);
+        assert_eq!(second.delta, false);
+        assert_eq!(second.sequence_opt, Some(1));
+        assert_eq!(second.ack_opt, None);
+    }
+
+    #[test]
+    fn acknowledged_records_are_left_out_until_they_change() {
+        let mut subject = GossipLedger::new();
+        let neighbor = PublicKey::new(b"neighbor");
+        let one = make_node_record(1234, true, false);
+        let mut another = make_node_record(2345, true, false);
+        let now = Instant::now();
+        let mut first = gossip_about(&[&one, &another]);
+        subject.prepare(&neighbor, &mut first, now);
+        subject.received(&neighbor, &reply(1, Some(1)));
+        another.increment_version();
+        let mut second = gossip_about(&[&one, &another]);
+
+        let result = subject.prepare(&neighbor, &mut second, now);
+
+        assert_eq!(result, true);
+        assert_eq!(first.delta, false);
+        assert_eq!(keys_of(&second), vec![another.public_key().clone()]);
+        assert_eq!(second.delta, true);
+        assert_eq!(second.sequence_opt, Some(2));
+        assert_eq!(second.ack_opt, Some(1));
+    }
+
+    #[test]
+    fn records_a_neighbor_sends_count_as_acknowledged() {
+        let mut subject = GossipLedger::new();
+        let neighbor = PublicKey::new(b"neighbor");
+        let one = make_node_record(1234, true, false);
+        let another = make_node_record(2345, true, false);
+        let now = Instant::now();
+        subject.prepare(&neighbor, &mut gossip_about(&[]), now);
+        let mut incoming = gossip_about(&[&one]);
+        incoming.sequence_opt = Some(1);
+        subject.received(&neighbor, &incoming);
+        let mut outgoing = gossip_about(&[&one, &another]);
+
+        subject.prepare(&neighbor, &mut outgoing, now);
+
+        assert_eq!(keys_of(&outgoing), vec![another.public_key().clone()]);
+    }
+
+    #[test]
+    fn a_record_acknowledged_without_its_node_addr_is_sent_again_with_it() {
+        let mut subject = GossipLedger::new();
+        let neighbor = PublicKey::new(b"neighbor");
+        let one = make_node_record(1234, true, false);
+        let now = Instant::now();
+        subject.prepare(
+            &neighbor,
+            &mut GossipBuilder::new().node(&one, false).build(),
+            now,
+        );
+        subject.received(&neighbor, &reply(1, Some(1)));
+        let mut revealed = gossip_about(&[&one]);
+        let mut obscured = GossipBuilder::new().node(&one, false).build();
+
+        assert_eq!(subject.prepare(&neighbor, &mut obscured, now), false);
+        assert_eq!(subject.prepare(&neighbor, &mut revealed, now), true);
+
+        assert_eq!(keys_of(&revealed), vec![one.public_key().clone()]);
+    }
+
+    #[test]
+    fn unacknowledged_records_are_sent_again() {
+        let mut subject = GossipLedger::new();
+        let neighbor = PublicKey::new(b"neighbor");
+        let one = make_node_record(1234, true, false);
+        let another = make_node_record(2345, true, false);
+        let now = Instant::now();
+        subject.prepare(&neighbor, &mut gossip_about(&[&one]), now);
+        subject.received(&neighbor, &reply(1, Some(1)));
+        subject.prepare(&neighbor, &mut gossip_about(&[&one, &another]), now);
+        subject.received(&neighbor, &reply(2, Some(1)));
+        let mut outgoing = gossip_about(&[&one, &another]);
+
+        subject.prepare(&neighbor, &mut outgoing, now);
+
+        assert_eq!(keys_of(&outgoing), vec![another.public_key().clone()]);
+        assert_eq!(outgoing.sequence_opt, Some(3));
+        assert_eq!(outgoing.ack_opt, Some(2));
+    }
+
+    #[test]
+    fn gossip_with_nothing_new_is_not_worth_sending() {
+        let mut subject = GossipLedger::new();
+        let neighbor = PublicKey::new(b"neighbor");
+        let one = make_node_record(1234, true, false);
+        let now = Instant::now();
+        subject.prepare(&neighbor, &mut gossip_about(&[&one]), now);
+        subject.received(&neighbor, &reply(1, Some(1)));
+        let mut outgoing = gossip_about(&[&one]);
+
+        let result = subject.prepare(&neighbor, &mut outgoing, now);
+
+        assert_eq!(result, false);
+        assert_eq!(outgoing.sequence_opt, None);
+        let mut next = gossip_about(&[&one]);
+        subject.prepare(&neighbor, &mut next, now + FULL_SYNC_INTERVAL);
+        assert_eq!(next.sequence_opt, Some(2));
+    }
+
+    #[test]
+    fn everything_is_sent_again_after_the_full_sync_interval() {
+        let mut subject = GossipLedger::new();
+        let neighbor = PublicKey::new(b"neighbor");
+        let one = make_node_record(1234, true, false);
+        let then = Instant::now();
+        subject.prepare(&neighbor, &mut gossip_about(&[&one]), then);
+        subject.received(&neighbor, &reply(1, Some(1)));
+        let mut early = gossip_about(&[&one]);
+        let mut late = gossip_about(&[&one]);
+
+        subject.prepare(
+            &neighbor,
+            &mut early,
+            then + FULL_SYNC_INTERVAL - Duration::from_secs(1),
+        );
+        subject.prepare(&neighbor, &mut late, then + FULL_SYNC_INTERVAL);
+
+        assert_eq!(keys_of(&early), vec![]);
+        assert_eq!(keys_of(&late), vec![one.public_key().clone()]);
+        assert_eq!(late.delta, false);
+    }
+
+    #[test]
+    fn a_neighbor_whose_sequence_numbers_start_over_is_assumed_to_know_nothing() {
+        let mut subject = GossipLedger::new();
+        let neighbor = PublicKey::new(b"neighbor");
+        let one = make_node_record(1234, true, false);
+        let now = Instant::now();
+        subject.prepare(&neighbor, &mut gossip_about(&[&one]), now);
+        subject.received(&neighbor, &reply(1, Some(1)));
+        subject.prepare(&neighbor, &mut gossip_about(&[&one]), now);
+        subject.received(&neighbor, &reply(1, None));
+        let mut outgoing = gossip_about(&[&one]);
+
+        subject.prepare(&neighbor, &mut outgoing, now);
+
+        assert_eq!(keys_of(&outgoing), vec![one.public_key().clone()]);
+        assert_eq!(outgoing.delta, false);
+        assert_eq!(outgoing.sequence_opt, Some(1));
+        assert_eq!(outgoing.ack_opt, Some(1));
+    }
+
+    #[test]
+    fn a_forgotten_neighbor_gets_everything() {
+        let mut subject = GossipLedger::new();
+        let neighbor = PublicKey::new(b"neighbor");
+        let one = make_node_record(1234, true, false);
+        let now = Instant::now();
+        subject.prepare(&neighbor, &mut gossip_about(&[&one]), now);
+        subject.received(&neighbor, &reply(1, Some(1)));
+
+        subject.forget(&neighbor);
+
+        let mut outgoing = gossip_about(&[&one]);
+        subject.prepare(&neighbor, &mut outgoing, now);
+        assert_eq!(keys_of(&outgoing), vec![one.public_key().clone()]);
+        assert_eq!(outgoing.sequence_opt, Some(1));
+    }
+}
@@ -1,10 +1,17 @@
 // Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
 
+mod debut_limiter;
 pub mod gossip;
 mod gossip_acceptor;
+mod gossip_ledger;
 mod gossip_producer;
 pub mod neighborhood;
 pub mod neighborhood_database;
+mod node_problems;
+mod reputation;
+pub mod route_exclusion;
+pub mod route_scores;
+mod topology_query;
 
 #[cfg(test)]
 pub mod neighborhood_test_utils;
@@ -1,12 +1,31 @@
 // Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use super::debut_limiter::DebutLimiter;
 use super::gossip::to_dot_graph;
 use super::gossip::Gossip;
+use super::gossip::GossipNodeRecord;
 use super::gossip_acceptor::GossipAcceptor;
 use super::gossip_acceptor::GossipAcceptorReal;
+use super::gossip_ledger::GossipLedger;
 use super::gossip_producer::GossipProducer;
 use super::gossip_producer::GossipProducerReal;
+use super::neighborhood_database::LoadAdvertisement;
 use super::neighborhood_database::NeighborhoodDatabase;
 use super::neighborhood_database::NodeRecord;
+use super::node_problems::NodeProblems;
+use super::node_problems::NODE_PROBLEM_COOLDOWN;
+use super::reputation::Reputation;
+use super::route_exclusion::AsnDatabase;
+use super::route_exclusion::RouteExclusionConfig;
+use super::route_exclusion::RouteExclusions;
+use super::route_scores::RouteScores;
+use super::route_scores::MAXIMUM_ROUTE_SCORE;
+use super::topology_query::topology_page;
+use crate::accountant::banned_node_dao::BannedNodeDao;
+use crate::accountant::db_initializer::DbInitializer;
+use crate::accountant::db_initializer::DbInitializerReal;
+use crate::accountant::node_record_dao::NodeRecordDao;
+#[cfg(feature = "fault_injection")]
+use crate::fault_injection::FaultInjector;
 use crate::sub_lib::accountant;
 use crate::sub_lib::cryptde::CryptDE;
 use crate::sub_lib::cryptde::PublicKey;
@@ -16,31 +35,94 @@ use crate::sub_lib::hopper::IncipientCoresPackage;
 use crate::sub_lib::logger::Logger;
 use crate::sub_lib::neighborhood::sentinel_ip_addr;
 use crate::sub_lib::neighborhood::BootstrapNeighborhoodNowMessage;
+use crate::sub_lib::neighborhood::CapacityClass;
 use crate::sub_lib::neighborhood::DispatcherNodeQueryMessage;
+use crate::sub_lib::neighborhood::ExitSla;
 use crate::sub_lib::neighborhood::ExpectedService;
 use crate::sub_lib::neighborhood::ExpectedServices;
+use crate::sub_lib::neighborhood::GossipAcceptanceStats;
+use crate::sub_lib::neighborhood::GossipStatsQueryMessage;
+use crate::sub_lib::neighborhood::Misbehavior;
+use crate::sub_lib::neighborhood::NeighborBanConfig;
+use crate::sub_lib::neighborhood::NeighborMisbehaviorMessage;
 use crate::sub_lib::neighborhood::NeighborhoodConfig;
+use crate::sub_lib::neighborhood::NeighborhoodStatus;
+use crate::sub_lib::neighborhood::NeighborhoodStatusQueryMessage;
 use crate::sub_lib::neighborhood::NeighborhoodSubs;
 use crate::sub_lib::neighborhood::NodeDescriptor;
+use crate::sub_lib::neighborhood::NodeProblemReport;
 use crate::sub_lib::neighborhood::NodeQueryMessage;
+use crate::sub_lib::neighborhood::NodeRecordPersistenceConfig;
+use crate::sub_lib::neighborhood::RateCard;
 use crate::sub_lib::neighborhood::RemoveNeighborMessage;
+use crate::sub_lib::neighborhood::RouteFeedbackMessage;
 use crate::sub_lib::neighborhood::RouteQueryMessage;
 use crate::sub_lib::neighborhood::RouteQueryResponse;
+use crate::sub_lib::neighborhood::RouteRefusal;
+use crate::sub_lib::neighborhood::RoutingLoadMessage;
+use crate::sub_lib::neighborhood::SetConsumingWalletMessage;
 use crate::sub_lib::neighborhood::TargetType;
+use crate::sub_lib::neighborhood::TopologyQueryMessage;
 use crate::sub_lib::node_addr::NodeAddr;
 use crate::sub_lib::peer_actors::BindMessage;
+use crate::sub_lib::peer_actors::ShutdownMessage;
+use crate::sub_lib::performance_profile::PerformanceProfile;
 use crate::sub_lib::route::Route;
 use crate::sub_lib::route::RouteSegment;
 use crate::sub_lib::stream_handler_pool::DispatcherNodeQueryResponse;
+use crate::sub_lib::ui_gateway::ReloadConfigMessage;
 use crate::sub_lib::utils::plus;
 use crate::sub_lib::utils::NODE_MAILBOX_CAPACITY;
+use crate::sub_lib::wallet::Wallet;
+use crate::sub_lib::wallet::WalletRotation;
 use actix::Actor;
 use actix::Addr;
+use actix::AsyncContext;
 use actix::Context;
 use actix::Handler;
 use actix::MessageResult;
 use actix::Recipient;
 use actix::Syn;
+use rand::Rng;
+use std::cell::RefCell;
+use std::cmp::max;
+use std::cmp::min;
+use std::collections::HashMap;
+use std::fs;
+use std::net::IpAddr;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+use std::time::Instant;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+pub const LOAD_DAMPING_FACTOR: u32 = 4;
+pub const OVERLOADED_LOAD_FACTOR: u8 = 80;
+pub const OVERLOAD_PENALTY: u32 = 1000;
+pub const ROUTE_SCORE_WEIGHT: u32 = 4;
+pub const MINIMUM_ROUTE_VALIDITY: Duration = Duration::from_secs(5);
+pub const MAXIMUM_ROUTE_VALIDITY: Duration = Duration::from_secs(60);
+pub const VERSION_BUMP_GOSSIP_TTL: u8 = 2;
+pub const FULL_SYNC_GOSSIP_TTL: u8 = 8;
+
+// What a Gossip message did to the database, which decides how far it's worth passing on. A Node
+// whose address flaps bumps its version every time; there's no sense flooding the whole network
+// with that when its neighbors' neighbors are the ones routing through it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GossipChange {
+    VersionBump,
+    FullSync,
+}
+
+impl GossipChange {
+    pub fn ttl(self) -> u8 {
+        match self {
+            GossipChange::VersionBump => VERSION_BUMP_GOSSIP_TTL,
+            GossipChange::FullSync => FULL_SYNC_GOSSIP_TTL,
+        }
+    }
+}
 
 pub struct Neighborhood {
     cryptde: &'static dyn CryptDE,
@@ -49,6 +131,27 @@ pub struct Neighborhood {
     gossip_producer: Box<dyn GossipProducer>,
     neighborhood_database: NeighborhoodDatabase,
     next_return_route_id: u32,
+    damped_load_factor: u8,
+    last_load_sample: Instant,
+    record_changes: HashMap<PublicKey, Instant>,
+    started: Instant,
+    earning_wallet_rotation: Option<WalletRotation>,
+    observed_ips: HashMap<PublicKey, IpAddr>,
+    debut_limiter: DebutLimiter,
+    route_exclusions: Option<RouteExclusions>,
+    route_scores: RouteScores,
+    geoip_database_path: Option<PathBuf>,
+    performance_profile: PerformanceProfile,
+    db_initializer: Box<dyn DbInitializer>,
+    node_record_persistence: Option<NodeRecordPersistenceConfig>,
+    node_record_dao: Option<Box<dyn NodeRecordDao>>,
+    reputation: Reputation,
+    node_problems: NodeProblems,
+    ban_config: Option<NeighborBanConfig>,
+    banned_node_dao: Option<Box<dyn BannedNodeDao>>,
+    gossip_ledger: RefCell<GossipLedger>,
+    #[cfg(feature = "fault_injection")]
+    fault_injector: FaultInjector,
     logger: Logger,
 }
 
@@ -62,10 +165,119 @@ impl Handler<BindMessage> for Neighborhood {
     fn handle(&mut self, msg: BindMessage, ctx: &mut Self::Context) -> Self::Result {
         ctx.set_mailbox_capacity(NODE_MAILBOX_CAPACITY);
         self.hopper = Some(msg.peer_actors.hopper.from_hopper_client);
+        // The Hopper only reports when it has relayed something, so an idle Node has to notice
+        // for itself that its load has gone away.
+        self.schedule_idle_load_decay(ctx);
+        self.schedule_earning_wallet_rotation(ctx);
+        self.restore_node_records(SystemTime::now());
+        self.restore_ban_list();
+        self.report_known_nodes();
+        ()
+    }
+}
+
+impl Handler<RoutingLoadMessage> for Neighborhood {
+    type Result = ();
+
+    fn handle(&mut self, msg: RoutingLoadMessage, _ctx: &mut Self::Context) -> Self::Result {
+        let capacity_class = self.neighborhood_database.root().load().capacity_class;
+        let sample = Neighborhood::load_factor_of(msg.bytes_routed, msg.interval, capacity_class);
+        self.absorb_load_sample(sample);
+        ()
+    }
+}
+
+impl Handler<RouteFeedbackMessage> for Neighborhood {
+    type Result = ();
+
+    fn handle(&mut self, msg: RouteFeedbackMessage, _ctx: &mut Self::Context) -> Self::Result {
+        self.route_scores
+            .record(&msg.keys, msg.outcome, Instant::now());
+        ()
+    }
+}
+
+impl Handler<NeighborMisbehaviorMessage> for Neighborhood {
+    type Result = ();
+
+    fn handle(&mut self, msg: NeighborMisbehaviorMessage, ctx: &mut Self::Context) -> Self::Result {
+        self.charge_neighbor(&msg.public_key, msg.misbehavior, ctx);
+        ()
+    }
+}
+
+impl Handler<NodeProblemReport> for Neighborhood {
+    type Result = ();
+
+    fn handle(&mut self, msg: NodeProblemReport, _ctx: &mut Self::Context) -> Self::Result {
+        self.logger.for_peer(&msg.public_key).info(format!(
+            "Keeping new routes away from Node {} for {:?}: {:?}",
+            msg.public_key.fingerprint(),
+            NODE_PROBLEM_COOLDOWN,
+            msg.problem
+        ));
+        self.node_problems
+            .record(&msg.public_key, msg.problem, Instant::now());
         ()
     }
 }
 
+impl Handler<GossipStatsQueryMessage> for Neighborhood {
+    type Result = MessageResult<GossipStatsQueryMessage>;
+
+    fn handle(
+        &mut self,
+        _msg: GossipStatsQueryMessage,
+        _ctx: &mut Self::Context,
+    ) -> <Self as Handler<GossipStatsQueryMessage>>::Result {
+        MessageResult(self.gossip_acceptor.stats())
+    }
+}
+
+impl Handler<NeighborhoodStatusQueryMessage> for Neighborhood {
+    type Result = MessageResult<NeighborhoodStatusQueryMessage>;
+
+    fn handle(
+        &mut self,
+        _msg: NeighborhoodStatusQueryMessage,
+        _ctx: &mut Self::Context,
+    ) -> <Self as Handler<NeighborhoodStatusQueryMessage>>::Result {
+        MessageResult(NeighborhoodStatus {
+            neighbors: self.neighborhood_database.root().neighbors().len(),
+            known_nodes: self.neighborhood_database.keys().len(),
+        })
+    }
+}
+
+impl Handler<TopologyQueryMessage> for Neighborhood {
+    type Result = MessageResult<TopologyQueryMessage>;
+
+    fn handle(
+        &mut self,
+        msg: TopologyQueryMessage,
+        _ctx: &mut Self::Context,
+    ) -> <Self as Handler<TopologyQueryMessage>>::Result {
+        let route_exclusions = &self.route_exclusions;
+        let country_of = |node: &NodeRecord| match route_exclusions {
+            Some(route_exclusions) => route_exclusions.country_of(Neighborhood::ip_addr_of(node)),
+            None => None,
+        };
+        MessageResult(topology_page(
+            &self.neighborhood_database,
+            &msg,
+            &country_of,
+        ))
+    }
+}
+
+impl Handler<ReloadConfigMessage> for Neighborhood {
+    type Result = ();
+
+    fn handle(&mut self, _msg: ReloadConfigMessage, _ctx: &mut Self::Context) -> Self::Result {
+        self.reload_geoip_database();
+    }
+}
+
 impl Handler<BootstrapNeighborhoodNowMessage> for Neighborhood {
     type Result = ();
 
@@ -118,10 +330,10 @@ impl Handler<BootstrapNeighborhoodNowMessage> for Neighborhood {
                 )
                 .expect("Key magically disappeared");
 
-                self.logger.info(format!(
+                self.logger.for_peer(&bootstrap_node_key).info(format!(
                     "Sending initial Gossip about {} nodes to bootstrap Node at {}:{}",
                     gossip.node_records.len(),
-                    bootstrap_node_key,
+                    bootstrap_node_key.fingerprint(),
                     self.neighborhood_database
                         .node_by_key(&bootstrap_node_key)
                         .expect("Node magically disappeared")
@@ -150,6 +362,9 @@ impl Handler<NodeQueryMessage> for Neighborhood {
         let node_record_ref_opt = match msg {
             NodeQueryMessage::IpAddress(ip_addr) => self.neighborhood_database.node_by_ip(&ip_addr),
             NodeQueryMessage::PublicKey(key) => self.neighborhood_database.node_by_key(&key),
+            NodeQueryMessage::Fingerprint(fingerprint) => {
+                self.neighborhood_database.node_by_fingerprint(&fingerprint)
+            }
         };
 
         MessageResult(match node_record_ref_opt {
@@ -176,6 +391,9 @@ impl Handler<DispatcherNodeQueryMessage> for Neighborhood {
         let node_record_ref_opt = match msg.query {
             NodeQueryMessage::IpAddress(ip_addr) => self.neighborhood_database.node_by_ip(&ip_addr),
             NodeQueryMessage::PublicKey(key) => self.neighborhood_database.node_by_key(&key),
+            NodeQueryMessage::Fingerprint(fingerprint) => {
+                self.neighborhood_database.node_by_fingerprint(&fingerprint)
+            }
         };
 
         let node_descriptor = match node_record_ref_opt {
@@ -211,7 +429,13 @@ impl Handler<RouteQueryMessage> for Neighborhood {
     ) -> <Self as Handler<RouteQueryMessage>>::Result {
         let msg_str = format!("{:?}", msg);
         let result = if msg.minimum_hop_count == 0 {
-            Ok(self.zero_hop_route_response())
+            match &msg.exit_location_opt {
+                // With zero hops, this Node is its own exit
+                Some(location) if !self.neighborhood_database.root().is_in_country(location) => {
+                    Err(RouteRefusal::NoExitInLocation(location.clone()))
+                }
+                _ => Ok(self.zero_hop_route_response()),
+            }
         } else {
             self.make_round_trip_route(msg)
         };
@@ -219,12 +443,12 @@ impl Handler<RouteQueryMessage> for Neighborhood {
             Ok(response) => {
                 self.logger
                     .debug(format!("Processed {} into {:?}", msg_str, response.clone()));
-                Some(response)
+                Ok(response)
             }
-            Err(msg) => {
+            Err(refusal) => {
                 self.logger
-                    .error(format!("Unsatisfied route query: {}", msg));
-                None
+                    .error(format!("Unsatisfied route query: {}", refusal));
+                Err(refusal)
             }
         })
     }
@@ -233,8 +457,8 @@ impl Handler<RouteQueryMessage> for Neighborhood {
 impl Handler<ExpiredCoresPackage> for Neighborhood {
     type Result = ();
 
-    fn handle(&mut self, msg: ExpiredCoresPackage, _ctx: &mut Self::Context) -> Self::Result {
-        let incoming_gossip: Gossip = match msg.payload(self.cryptde) {
+    fn handle(&mut self, msg: ExpiredCoresPackage, ctx: &mut Self::Context) -> Self::Result {
+        let mut incoming_gossip: Gossip = match msg.payload(self.cryptde) {
             Ok(p) => p,
             Err(_) => {
                 self.logger
@@ -242,34 +466,90 @@ impl Handler<ExpiredCoresPackage> for Neighborhood {
                 return ();
             }
         };
+        if let Some(sender_key) = &msg.immediate_neighbor_key_opt {
+            if self.reputation.is_banned(sender_key) {
+                self.logger.for_peer(sender_key).debug(format!(
+                    "Ignoring Gossip from banned Node {}",
+                    sender_key.fingerprint()
+                ));
+                return ();
+            }
+            if !self.sender_record_is_signed(&incoming_gossip, sender_key) {
+                self.logger.for_peer(sender_key).warning(format!(
+                    "Gossip from {} carries its own record with an invalid signature: ignoring",
+                    sender_key.fingerprint()
+                ));
+                self.charge_neighbor(sender_key, Misbehavior::InvalidGossipSignature, ctx);
+                return ();
+            }
+        }
+        self.ignore_banned_nodes(&mut incoming_gossip);
+        let sender_key_opt = match &msg.immediate_neighbor_key_opt {
+            Some(key) => Some(key.clone()),
+            None => self
+                .neighborhood_database
+                .node_by_ip(&msg.immediate_neighbor_ip)
+                .map(|node| node.public_key().clone()),
+        };
+        if let Some(sender_key) = &sender_key_opt {
+            self.gossip_ledger
+                .borrow_mut()
+                .received(sender_key, &incoming_gossip);
+        }
         self.logger.trace(format!(
             "Received Gossip: {}",
             to_dot_graph(
                 incoming_gossip.clone(),
                 self.neighborhood_database.root().public_key(),
-                match self
-                    .neighborhood_database
-                    .node_by_ip(&msg.immediate_neighbor_ip)
-                {
-                    Some(node) => node.public_key().clone(),
-                    None => PublicKey::new(&[]),
-                }
+                sender_key_opt.unwrap_or_else(|| PublicKey::new(&[])),
             )
         ));
+        let incoming_ttl_opt = incoming_gossip.ttl_opt;
+        let incoming_delta = incoming_gossip.delta;
+        let root_ip_adopted = self.adopt_observed_ip(&incoming_gossip);
+        self.fill_in_observed_ip(
+            &mut incoming_gossip,
+            msg.immediate_neighbor_ip,
+            msg.immediate_neighbor_key_opt.as_ref(),
+        );
+        self.limit_debuts(
+            &mut incoming_gossip,
+            msg.immediate_neighbor_ip,
+            Instant::now(),
+        );
         let gossip_records = incoming_gossip.clone().node_records;
         let num_nodes = gossip_records.len();
         self.logger
             .info(format!("Processing Gossip about {} Nodes", num_nodes));
 
+        let versions_before = self.versions_of(&gossip_records);
         let db_changed = self
             .gossip_acceptor
             .handle(&mut self.neighborhood_database, incoming_gossip);
         if db_changed {
+            self.neighborhood_database.root_mut().sign(self.cryptde);
+        }
+        let change = Neighborhood::change_made(&self.neighborhood_database, &versions_before);
+        self.note_record_changes(versions_before);
+        self.save_gossiped_records(&gossip_records, SystemTime::now());
+        if root_ip_adopted {
+            self.gossip_to_neighbors(GossipChange::VersionBump.ttl());
+        } else if db_changed {
             match gossip_records.as_slice() {
-                [only] => self.gossip_to(&vec![only.public_key()]),
-                _ => self.gossip_to_neighbors(),
+                // A delta mentioning one Node is no debut
+                [only] if !incoming_delta => {
+                    self.gossip_to(&vec![only.public_key()], FULL_SYNC_GOSSIP_TTL)
+                }
+                _ => match Neighborhood::relay_ttl(incoming_ttl_opt, change) {
+                    Some(ttl) => self.gossip_to_neighbors(ttl),
+                    None => self.logger.debug(format!(
+                        "Gossip about {} Nodes ({:?}) has traveled far enough: not relaying it",
+                        num_nodes, change
+                    )),
+                },
             };
         }
+        self.report_known_nodes();
         self.logger.info(format!(
             "Finished processing Gossip about {} Nodes",
             num_nodes
@@ -283,14 +563,20 @@ impl Handler<RemoveNeighborMessage> for Neighborhood {
 
     fn handle(&mut self, msg: RemoveNeighborMessage, _ctx: &mut Self::Context) -> Self::Result {
         let public_key = &msg.public_key;
+        self.gossip_ledger.borrow_mut().forget(public_key);
         match self.neighborhood_database.remove_neighbor(public_key) {
             Err(s) => self.logger.error(s),
             Ok(db_changed) => {
                 if db_changed {
-                    self.neighborhood_database.root_mut().increment_version();
-                    self.gossip_to_neighbors();
-                    self.logger
-                        .info(format!("removed neighbor by public key: {}", public_key))
+                    let root = self.neighborhood_database.root_mut();
+                    root.increment_version();
+                    root.sign(self.cryptde);
+                    self.gossip_to_neighbors(GossipChange::FullSync.ttl());
+                    self.report_known_nodes();
+                    self.logger.for_peer(public_key).info(format!(
+                        "removed neighbor by public key: {}",
+                        public_key.fingerprint()
+                    ))
                 }
             }
         }
@@ -298,14 +584,49 @@ impl Handler<RemoveNeighborMessage> for Neighborhood {
     }
 }
 
+impl Handler<SetConsumingWalletMessage> for Neighborhood {
+    type Result = ();
+
+    fn handle(&mut self, msg: SetConsumingWalletMessage, _ctx: &mut Self::Context) -> Self::Result {
+        self.switch_consuming_wallet(msg.wallet);
+        ()
+    }
+}
+
+// The Node tells its neighbors it has none left, so they stop routing through it now instead of
+// finding out when their packets go nowhere.
+impl Handler<ShutdownMessage> for Neighborhood {
+    type Result = ();
+
+    fn handle(&mut self, _msg: ShutdownMessage, _ctx: &mut Self::Context) -> Self::Result {
+        let former_neighbors = self.neighborhood_database.root().neighbors().clone();
+        if former_neighbors.is_empty() {
+            return ();
+        }
+        let root = self.neighborhood_database.root_mut();
+        former_neighbors.iter().for_each(|neighbor| {
+            root.remove_neighbor(neighbor);
+        });
+        root.increment_version();
+        root.sign(self.cryptde);
+        self.gossip_to(&former_neighbors, GossipChange::FullSync.ttl());
+        self.logger.info(format!(
+            "Announced departure to {} neighbor(s)",
+            former_neighbors.len()
+        ));
+        ()
+    }
+}
+
 impl Neighborhood {
     pub fn new(cryptde: &'static dyn CryptDE, config: NeighborhoodConfig) -> Self {
         if config.local_ip_addr == sentinel_ip_addr() {
-            if !config.neighbor_configs.is_empty() {
-                panic! ("A SubstratumNode without an --ip setting is not decentralized and cannot have any --neighbor settings")
-            }
-            if !config.clandestine_port_list.is_empty() {
-                panic! ("A SubstratumNode without an --ip setting is not decentralized and cannot have any --port_count setting other than 0")
+            if config.neighbor_configs.is_empty() {
+                if !config.clandestine_port_list.is_empty() {
+                    panic! ("A SubstratumNode without an --ip setting is not decentralized and cannot have any --port_count setting other than 0")
+                }
+            } else if config.clandestine_port_list.is_empty() {
+                panic! ("A SubstratumNode with --neighbor settings but no --ip setting needs a --port_count greater than 0, so that its neighbors can tell it its IP address")
             }
             if config.is_bootstrap_node {
                 panic! ("A SubstratumNode without an --ip setting is not decentralized and cannot be --node_type bootstrap")
@@ -342,7 +663,10 @@ impl Neighborhood {
                     None,
                     0,
                 ))
-                .expect(&format!("Database already contains node {:?}", key));
+                .expect(&format!(
+                    "Database already contains node {}",
+                    key.fingerprint()
+                ));
             neighborhood_database
                 .add_neighbor(root_key_ref, &key)
                 .expect("internal error");
@@ -358,6 +682,7 @@ impl Neighborhood {
             .neighbor_configs
             .iter()
             .for_each(|neighbor| add_node(&mut neighborhood_database, neighbor, true));
+        neighborhood_database.root_mut().sign(cryptde);
 
         Neighborhood {
             cryptde,
@@ -366,26 +691,202 @@ impl Neighborhood {
             gossip_producer,
             neighborhood_database,
             next_return_route_id: 0,
+            damped_load_factor: 0,
+            last_load_sample: Instant::now(),
+            record_changes: HashMap::new(),
+            started: Instant::now(),
+            earning_wallet_rotation: None,
+            observed_ips: HashMap::new(),
+            debut_limiter: DebutLimiter::new(),
+            route_exclusions: None,
+            route_scores: RouteScores::new(),
+            geoip_database_path: None,
+            performance_profile: PerformanceProfile::standard(),
+            db_initializer: Box::new(DbInitializerReal::new()),
+            node_record_persistence: None,
+            node_record_dao: None,
+            reputation: Reputation::new(0),
+            node_problems: NodeProblems::new(NODE_PROBLEM_COOLDOWN),
+            ban_config: None,
+            banned_node_dao: None,
+            gossip_ledger: RefCell::new(GossipLedger::new()),
+            #[cfg(feature = "fault_injection")]
+            fault_injector: FaultInjector::global(),
             logger: Logger::new("Neighborhood"),
         }
     }
 
-    fn gossip_to_neighbors(&self) {
-        self.gossip_to(self.neighborhood_database.root().neighbors());
+    pub fn advertise_capacity(&mut self, capacity_class: CapacityClass) {
+        let root = self.neighborhood_database.root_mut();
+        let load_factor = root.load().load_factor;
+        if root.set_load(LoadAdvertisement {
+            capacity_class,
+            load_factor,
+        }) {
+            root.sign(self.cryptde);
+        }
+    }
+
+    pub fn advertise_rate_card(&mut self, rate_card: RateCard) {
+        let root = self.neighborhood_database.root_mut();
+        if root.set_rate_card(rate_card) {
+            root.sign(self.cryptde);
+        }
+    }
+
+    pub fn advertise_country(&mut self, country: String) {
+        let root = self.neighborhood_database.root_mut();
+        if root.set_country_opt(Some(country)) {
+            root.sign(self.cryptde);
+        }
+    }
+
+    pub fn advertise_exit_sla(&mut self, exit_sla: ExitSla) {
+        let root = self.neighborhood_database.root_mut();
+        if root.set_exit_sla_opt(Some(exit_sla)) {
+            root.sign(self.cryptde);
+        }
+    }
+
+    pub fn advertise_exit_tls(&mut self) {
+        let root = self.neighborhood_database.root_mut();
+        if root.set_offers_exit_tls(true) {
+            root.sign(self.cryptde);
+        }
+    }
+
+    // For a Node with no consumer-facing listeners, so that others can tell it won't originate
+    // traffic of its own
+    // A Node that keeps its identity from one run to the next has to start its NodeRecord past the
+    // versions its neighbors already have, or they'll keep the NodeAddr they already know
+    pub fn start_at_version(&mut self, version: u32) {
+        let root = self.neighborhood_database.root_mut();
+        if root.version() != version {
+            root.set_version(version);
+            root.sign(self.cryptde);
+        }
+    }
+
+    pub fn advertise_relay_only(&mut self) {
+        let root = self.neighborhood_database.root_mut();
+        if root.set_relay_only(true) {
+            root.sign(self.cryptde);
+        }
+    }
+
+    pub fn exclude_from_routes(&mut self, config: RouteExclusionConfig) {
+        self.route_exclusions = Some(RouteExclusions::new(config));
+    }
+
+    // The file exclude_from_routes' database came from, to be read again on a ReloadConfigMessage
+    pub fn reload_geoip_database_from(&mut self, path: &Path) {
+        self.geoip_database_path = Some(path.to_path_buf());
+    }
+
+    pub fn adopt_performance_profile(&mut self, profile: PerformanceProfile) {
+        self.performance_profile = profile;
+    }
+
+    pub fn persist_node_records(&mut self, config: NodeRecordPersistenceConfig) {
+        self.node_record_persistence = Some(config);
+    }
+
+    pub fn ban_misbehaving_neighbors(&mut self, config: NeighborBanConfig) {
+        self.reputation = Reputation::new(config.threshold);
+        self.ban_config = Some(config);
+    }
+
+    pub fn rotate_earning_wallets(&mut self, rotation: WalletRotation) {
+        let earning_wallet = rotation.wallet_at(SystemTime::now()).clone();
+        self.earning_wallet_rotation = Some(rotation);
+        let root = self.neighborhood_database.root_mut();
+        let consuming_wallet = root.consuming_wallet();
+        if root.set_wallets(earning_wallet, consuming_wallet) {
+            root.sign(self.cryptde);
+        }
+    }
+
+    fn schedule_earning_wallet_rotation(&self, ctx: &mut Context<Self>) {
+        if let Some(rotation) = &self.earning_wallet_rotation {
+            let interval = min(
+                rotation.period,
+                self.performance_profile.wallet_rotation_check_interval,
+            );
+            ctx.run_later(interval, |act, ctx| {
+                act.rotate_earning_wallet_at(SystemTime::now());
+                act.schedule_earning_wallet_rotation(ctx);
+            });
+        }
+    }
+
+    fn rotate_earning_wallet_at(&mut self, time: SystemTime) {
+        let earning_wallet = match &self.earning_wallet_rotation {
+            Some(rotation) => rotation.wallet_at(time).clone(),
+            None => return,
+        };
+        {
+            let root = self.neighborhood_database.root_mut();
+            let consuming_wallet = root.consuming_wallet();
+            if !root.set_wallets(earning_wallet.clone(), consuming_wallet) {
+                return;
+            }
+            root.increment_version();
+            root.sign(self.cryptde);
+        }
+        self.logger.info(format!(
+            "Earning wallet rotated to {}",
+            earning_wallet.address
+        ));
+        self.gossip_to_neighbors(GossipChange::VersionBump.ttl());
+    }
+
+    fn switch_consuming_wallet(&mut self, consuming_wallet: Wallet) {
+        {
+            let root = self.neighborhood_database.root_mut();
+            let earning_wallet = root.earning_wallet();
+            if !root.set_wallets(earning_wallet, Some(consuming_wallet.clone())) {
+                return;
+            }
+            root.increment_version();
+            root.sign(self.cryptde);
+        }
+        self.logger.info(format!(
+            "Consuming wallet switched to {}",
+            consuming_wallet.address
+        ));
+        self.gossip_to_neighbors(GossipChange::VersionBump.ttl());
+    }
+
+    fn gossip_to_neighbors(&self, ttl: u8) {
+        self.gossip_to(self.neighborhood_database.root().neighbors(), ttl);
     }
 
-    fn gossip_to(&self, neighbors: &Vec<PublicKey>) {
+    fn gossip_to(&self, neighbors: &Vec<PublicKey>, ttl: u8) {
         neighbors.iter().for_each(|neighbor| {
-            let gossip = self
+            let mut gossip = self
                 .gossip_producer
                 .produce(&self.neighborhood_database, neighbor);
+            if !self
+                .gossip_ledger
+                .borrow_mut()
+                .prepare(neighbor, &mut gossip, Instant::now())
+            {
+                self.logger.for_peer(neighbor).debug(format!(
+                    "{} has acknowledged everything there is to tell it: not gossiping",
+                    neighbor.fingerprint()
+                ));
+                return;
+            }
+            gossip.observed_ip_opt = self.confirmed_observed_ip(neighbor);
+            gossip.ttl_opt = Some(ttl);
             let gossip_len = gossip.node_records.len();
             let route = self.create_single_hop_route(neighbor);
             let package = IncipientCoresPackage::new(self.cryptde, route, gossip, neighbor)
                 .expect("Key magically disappeared");
-            self.logger.info(format!(
+            self.logger.for_peer(neighbor).info(format!(
                 "Relaying Gossip about {} nodes to {}",
-                gossip_len, neighbor
+                gossip_len,
+                neighbor.fingerprint()
             ));
             self.hopper
                 .as_ref()
@@ -395,6 +896,130 @@ impl Neighborhood {
         });
     }
 
+    // A Node that started without --ip advertises the sentinel IP address, so that its clandestine
+    // ports still get through. Nobody passes such a record on, so if a single one arrives it came
+    // straight from the Node that sent this Gossip, at the address the Gossip arrived from. More than
+    // one can't be sorted out, so none of them gets an address, unless the sender has proven its
+    // public key to the Dispatcher: then its own record is the one to fill in, and no other.
+    fn fill_in_observed_ip(
+        &mut self,
+        gossip: &mut Gossip,
+        immediate_neighbor_ip: IpAddr,
+        immediate_neighbor_key_opt: Option<&PublicKey>,
+    ) {
+        let root_key = self.neighborhood_database.root().public_key().clone();
+        let unaddressed: Vec<usize> = gossip
+            .node_records
+            .iter()
+            .enumerate()
+            .filter(|(_, gnr)| match &gnr.inner.node_addr_opt {
+                Some(node_addr) => node_addr.ip_addr() == sentinel_ip_addr(),
+                None => false,
+            })
+            .map(|(index, _)| index)
+            .collect();
+        let sender_index_opt = match immediate_neighbor_key_opt {
+            Some(key) => unaddressed
+                .iter()
+                .find(|index| &gossip.node_records[**index].inner.public_key == key)
+                .cloned(),
+            None => match unaddressed.as_slice() {
+                [index] => Some(*index),
+                _ => None,
+            },
+        }
+        .filter(|index| gossip.node_records[*index].inner.public_key != root_key);
+        unaddressed.into_iter().for_each(|index| {
+            let gnr = &mut gossip.node_records[index];
+            if Some(index) == sender_index_opt {
+                let ports = gnr
+                    .inner
+                    .node_addr_opt
+                    .as_ref()
+                    .expect("NodeAddr magically disappeared")
+                    .ports();
+                gnr.inner.node_addr_opt = Some(NodeAddr::new(&immediate_neighbor_ip, &ports));
+                self.logger.for_peer(&gnr.inner.public_key).debug(format!(
+                    "Node {} doesn't know its IP address; it's sending from {}",
+                    gnr.inner.public_key.fingerprint(),
+                    immediate_neighbor_ip
+                ));
+                self.observed_ips
+                    .insert(gnr.inner.public_key.clone(), immediate_neighbor_ip);
+            } else {
+                self.logger.for_peer(&gnr.inner.public_key).warning(format!(
+                    "Gossip from {} carries an unaddressed record for {} that can't be its own: ignoring the address",
+                    immediate_neighbor_ip,
+                    gnr.inner.public_key.fingerprint()
+                ));
+                gnr.inner.node_addr_opt = None;
+            }
+        });
+    }
+
+    fn limit_debuts(&mut self, gossip: &mut Gossip, source_ip: IpAddr, now: Instant) {
+        let known_keys = self.neighborhood_database.keys();
+        let debut_count = gossip
+            .node_records
+            .iter()
+            .filter(|gnr| !known_keys.contains(&gnr.inner.public_key))
+            .count();
+        if debut_count == 0 {
+            return;
+        }
+        let mut allowed = self.debut_limiter.admit(source_ip, debut_count, now);
+        if allowed == debut_count {
+            return;
+        }
+        self.logger.warning(format!(
+            "Too many new Nodes lately: ignoring {} of the {} new Nodes in Gossip from {}",
+            debut_count - allowed,
+            debut_count,
+            source_ip
+        ));
+        gossip.node_records.retain(|gnr| {
+            if known_keys.contains(&gnr.inner.public_key) {
+                true
+            } else if allowed > 0 {
+                allowed -= 1;
+                true
+            } else {
+                false
+            }
+        });
+    }
+
+    fn adopt_observed_ip(&mut self, gossip: &Gossip) -> bool {
+        let observed_ip = match gossip.observed_ip_opt {
+            Some(observed_ip) => observed_ip,
+            None => return false,
+        };
+        let root_ip_opt = self
+            .neighborhood_database
+            .root()
+            .node_addr_opt()
+            .map(|node_addr| node_addr.ip_addr());
+        if root_ip_opt != Some(sentinel_ip_addr()) {
+            return false;
+        }
+        self.neighborhood_database.set_root_ip_addr(&observed_ip);
+        let root = self.neighborhood_database.root_mut();
+        root.increment_version();
+        root.sign(self.cryptde);
+        self.logger.info(format!(
+            "Neighbors can reach this Node at {}; advertising it so that other Nodes can route through it",
+            observed_ip
+        ));
+        true
+    }
+
+    fn confirmed_observed_ip(&self, neighbor: &PublicKey) -> Option<IpAddr> {
+        if !self.neighborhood_database.root().has_neighbor(neighbor) {
+            return None;
+        }
+        self.observed_ips.get(neighbor).cloned()
+    }
+
     pub fn make_subs_from(addr: &Addr<Syn, Neighborhood>) -> NeighborhoodSubs {
         NeighborhoodSubs {
             bind: addr.clone().recipient::<BindMessage>(),
@@ -404,84 +1029,400 @@ impl Neighborhood {
             from_hopper: addr.clone().recipient::<ExpiredCoresPackage>(),
             dispatcher_node_query: addr.clone().recipient::<DispatcherNodeQueryMessage>(),
             remove_neighbor: addr.clone().recipient::<RemoveNeighborMessage>(),
+            set_consuming_wallet: addr.clone().recipient::<SetConsumingWalletMessage>(),
+            routing_load: addr.clone().recipient::<RoutingLoadMessage>(),
+            route_feedback: addr.clone().recipient::<RouteFeedbackMessage>(),
+            neighbor_misbehavior: addr.clone().recipient::<NeighborMisbehaviorMessage>(),
+            node_problem: addr.clone().recipient::<NodeProblemReport>(),
+            gossip_stats_query: addr.clone().recipient::<GossipStatsQueryMessage>(),
+            topology_query: addr.clone().recipient::<TopologyQueryMessage>(),
+            status_query: addr.clone().recipient::<NeighborhoodStatusQueryMessage>(),
+            reload_config: addr.clone().recipient::<ReloadConfigMessage>(),
+            shutdown: addr.clone().recipient::<ShutdownMessage>(),
         }
     }
 
-    fn create_single_hop_route(&self, destination: &PublicKey) -> Route {
-        Route::one_way(
-            RouteSegment::new(
-                vec![&self.cryptde.public_key(), destination],
-                Component::Neighborhood,
-            ),
-            self.cryptde,
-            None,
-        )
-        .expect("route creation error")
+    // A neighbor that runs up enough demerits is banned: it's disconnected, nothing it gossips is
+    // believed, and no route goes through it, on this run or any later one.
+    fn charge_neighbor(
+        &mut self,
+        key: &PublicKey,
+        misbehavior: Misbehavior,
+        ctx: &mut Context<Neighborhood>,
+    ) {
+        self.logger.for_peer(key).warning(format!(
+            "Neighbor {} misbehaved: {:?}",
+            key.fingerprint(),
+            misbehavior
+        ));
+        if !self.reputation.charge(key, misbehavior, Instant::now()) {
+            return;
+        }
+        self.logger.for_peer(key).warning(format!(
+            "Banning Node {} for repeated misbehavior",
+            key.fingerprint()
+        ));
+        if let Some(dao) = &self.banned_node_dao {
+            dao.ban(key, &SystemTime::now());
+        }
+        ctx.notify(RemoveNeighborMessage {
+            public_key: key.clone(),
+        });
     }
 
-    fn zero_hop_route_response(&mut self) -> RouteQueryResponse {
-        let return_route_id = self.advance_return_route_id();
-        let route = Route::round_trip(
-            RouteSegment::new(
-                vec![&self.cryptde.public_key(), &self.cryptde.public_key()],
-                Component::ProxyClient,
-            ),
-            RouteSegment::new(
-                vec![&self.cryptde.public_key(), &self.cryptde.public_key()],
-                Component::ProxyServer,
-            ),
-            self.cryptde,
-            None,
-            return_route_id,
-        )
-        .expect("Couldn't create route");
-        RouteQueryResponse {
-            route,
-            expected_services: ExpectedServices::RoundTrip(
-                vec![ExpectedService::Nothing, ExpectedService::Nothing],
-                vec![ExpectedService::Nothing, ExpectedService::Nothing],
-                return_route_id,
-            ),
+    // Records relayed from other Nodes may have been changed along the way without being signed
+    // again (filled-in IP addresses, for one), but the record a Node sends about itself is its own.
+    fn sender_record_is_signed(&self, gossip: &Gossip, sender_key: &PublicKey) -> bool {
+        match gossip
+            .node_records
+            .iter()
+            .find(|gnr| &gnr.inner.public_key == sender_key)
+        {
+            Some(gnr) => gnr.signature_is_valid(self.cryptde),
+            None => true,
         }
     }
 
-    fn make_round_trip_route(
-        &mut self,
-        msg: RouteQueryMessage,
-    ) -> Result<RouteQueryResponse, String> {
-        let local_target_type = if self.neighborhood_database.root().is_bootstrap_node() {
-            TargetType::Bootstrap
-        } else {
-            TargetType::Standard
+    fn ignore_banned_nodes(&self, gossip: &mut Gossip) {
+        let reputation = &self.reputation;
+        gossip
+            .node_records
+            .retain(|gnr| !reputation.is_banned(&gnr.inner.public_key));
+    }
+
+    fn restore_ban_list(&mut self) {
+        let config = match &self.ban_config {
+            Some(config) => config.clone(),
+            None => return,
         };
-        let over = self.make_route_segment(
-            &self.cryptde.public_key(),
-            msg.target_key_opt.as_ref(),
-            msg.target_type,
-            msg.minimum_hop_count,
-            msg.target_component,
-        )?;
-        self.logger.debug(format!("Route over: {:?}", over));
-        let back = self.make_route_segment(
-            over.keys.last().expect("Empty segment"),
-            Some(&self.cryptde.public_key()),
-            local_target_type,
-            msg.minimum_hop_count,
-            msg.return_component_opt.expect("No return component"),
-        )?;
-        self.logger.debug(format!("Route back: {:?}", back));
-        self.compose_route_query_response(over, back)
+        fs::create_dir_all(&config.data_directory).expect(&format!(
+            "Cannot create specified data directory at {:?}",
+            config.data_directory
+        ));
+        let dao = self
+            .db_initializer
+            .initialize(&config.data_directory)
+            .expect("Could not initialize database")
+            .banned_node;
+        let banned_keys = dao.load_all();
+        banned_keys.iter().for_each(|key| self.reputation.ban(key));
+        if !banned_keys.is_empty() {
+            self.logger.info(format!(
+                "{} Nodes are still banned from earlier runs",
+                banned_keys.len()
+            ));
+        }
+        self.banned_node_dao = Some(dao);
     }
 
-    fn compose_route_query_response(
-        &mut self,
+    // Only the database is read again; the exclusions themselves come from the command line. A
+    // database that can't be read leaves the old one in use.
+    fn reload_geoip_database(&mut self) {
+        let (path, exclusions) = match (&self.geoip_database_path, &self.route_exclusions) {
+            (Some(path), Some(route_exclusions)) => {
+                (path.clone(), route_exclusions.config().exclusions.clone())
+            }
+            _ => return,
+        };
+        match AsnDatabase::load(&path) {
+            Ok(database) => {
+                self.logger
+                    .info(format!("Reloaded GeoIP database from {:?}", path));
+                self.exclude_from_routes(RouteExclusionConfig {
+                    database,
+                    exclusions,
+                });
+            }
+            Err(msg) => self.logger.warning(format!(
+                "{}; keeping the GeoIP database already in use",
+                msg
+            )),
+        }
+    }
+
+    fn load_factor_of(bytes_routed: u64, interval: Duration, capacity_class: CapacityClass) -> u8 {
+        let interval_millis = interval.as_secs() * 1000 + u64::from(interval.subsec_millis());
+        let capacity = capacity_class.nominal_bytes_per_second() * interval_millis / 1000;
+        if capacity == 0 {
+            return if bytes_routed == 0 { 0 } else { 100 };
+        }
+        min(100, bytes_routed * 100 / capacity) as u8
+    }
+
+    fn damp(previous: u8, sample: u8) -> u8 {
+        ((u32::from(previous) * (LOAD_DAMPING_FACTOR - 1) + u32::from(sample))
+            / LOAD_DAMPING_FACTOR) as u8
+    }
+
+    // The damped load is re-advertised only when it has drifted far enough from what the rest of
+    // the Neighborhood was last told, so that a busy Node doesn't flood everyone with Gossip.
+    fn absorb_load_sample(&mut self, sample: u8) {
+        self.last_load_sample = Instant::now();
+        self.damped_load_factor = Neighborhood::damp(self.damped_load_factor, sample);
+        let advertised = self.neighborhood_database.root().load();
+        let drift = (i16::from(self.damped_load_factor) - i16::from(advertised.load_factor)).abs();
+        if drift < i16::from(self.performance_profile.load_advertisement_threshold) {
+            return;
+        }
+        self.logger.debug(format!(
+            "Load factor moved from {}% to {}%; re-advertising",
+            advertised.load_factor, self.damped_load_factor
+        ));
+        {
+            let root = self.neighborhood_database.root_mut();
+            root.set_load(LoadAdvertisement {
+                capacity_class: advertised.capacity_class,
+                load_factor: self.damped_load_factor,
+            });
+            root.increment_version();
+            root.sign(self.cryptde);
+        }
+        self.gossip_to_neighbors(GossipChange::VersionBump.ttl());
+    }
+
+    fn schedule_idle_load_decay(&self, ctx: &mut Context<Self>) {
+        ctx.run_later(
+            self.performance_profile.load_sample_interval * 2,
+            |act, ctx| {
+                act.decay_idle_load();
+                act.schedule_idle_load_decay(ctx);
+            },
+        );
+    }
+
+    fn decay_idle_load(&mut self) {
+        if (self.damped_load_factor > 0)
+            && (self.last_load_sample.elapsed()
+                >= self.performance_profile.load_sample_interval * 2)
+        {
+            self.absorb_load_sample(0);
+        }
+    }
+
+    // Lower is better: lightly loaded, high-capacity relays are cheap, and overloaded ones are
+    // used only when there's no way around them.
+    fn route_load_cost(&self, node_seq: &Vec<&PublicKey>) -> u32 {
+        let root_key = self.neighborhood_database.root().public_key();
+        node_seq
+            .iter()
+            .filter(|key| **key != root_key)
+            .map(|key| match self.neighborhood_database.node_by_key(key) {
+                Some(node) => Neighborhood::load_cost(node.load()),
+                None => 0,
+            })
+            .sum()
+    }
+
+    // Routes through Nodes that have been slow or have lost requests lately cost more, but never as
+    // much as one through an overloaded relay.
+    fn route_cost(&self, node_seq: &Vec<&PublicKey>, now: Instant) -> u32 {
+        let score = self.route_score(node_seq, now);
+        self.route_load_cost(node_seq) + u32::from(MAXIMUM_ROUTE_SCORE - score) * ROUTE_SCORE_WEIGHT
+    }
+
+    fn route_score(&self, node_seq: &Vec<&PublicKey>, now: Instant) -> u8 {
+        let root_key = self.neighborhood_database.root().public_key();
+        let keys: Vec<&PublicKey> = node_seq
+            .iter()
+            .filter(|key| **key != root_key)
+            .map(|key| *key)
+            .collect();
+        self.route_scores.route_score(&keys, now)
+    }
+
+    fn load_cost(load: LoadAdvertisement) -> u32 {
+        let weight = match load.capacity_class {
+            CapacityClass::High => 1,
+            CapacityClass::Medium => 2,
+            CapacityClass::Low => 4,
+        };
+        let penalty = if load.load_factor >= OVERLOADED_LOAD_FACTOR {
+            OVERLOAD_PENALTY
+        } else {
+            0
+        };
+        u32::from(load.load_factor) * weight + penalty
+    }
+
+    fn create_single_hop_route(&self, destination: &PublicKey) -> Route {
+        Route::one_way(
+            RouteSegment::new(
+                vec![&self.cryptde.public_key(), destination],
+                Component::Neighborhood,
+            ),
+            self.cryptde,
+            None,
+        )
+        .expect("route creation error")
+    }
+
+    fn zero_hop_route_response(&mut self) -> RouteQueryResponse {
+        let return_route_id = self.advance_return_route_id();
+        let route = Route::round_trip(
+            RouteSegment::new(
+                vec![&self.cryptde.public_key(), &self.cryptde.public_key()],
+                Component::ProxyClient,
+            ),
+            RouteSegment::new(
+                vec![&self.cryptde.public_key(), &self.cryptde.public_key()],
+                Component::ProxyServer,
+            ),
+            self.cryptde,
+            None,
+            return_route_id,
+        )
+        .expect("Couldn't create route");
+        RouteQueryResponse {
+            route,
+            expected_services: ExpectedServices::RoundTrip(
+                vec![ExpectedService::Nothing, ExpectedService::Nothing],
+                vec![ExpectedService::Nothing, ExpectedService::Nothing],
+                return_route_id,
+            ),
+            valid_for: MAXIMUM_ROUTE_VALIDITY,
+            score: MAXIMUM_ROUTE_SCORE,
+            exit_sla_opt: None,
+        }
+    }
+
+    fn make_round_trip_route(
+        &mut self,
+        msg: RouteQueryMessage,
+    ) -> Result<RouteQueryResponse, RouteRefusal> {
+        let local_target_type = if self.neighborhood_database.root().is_bootstrap_node() {
+            TargetType::Bootstrap
+        } else {
+            TargetType::Standard
+        };
+        if let Some(location) = &msg.exit_location_opt {
+            if !self.knows_exit_in(location) {
+                return Err(RouteRefusal::NoExitInLocation(location.clone()));
+            }
+        }
+        let over = if msg.random_route {
+            self.make_random_route_segment(
+                &self.cryptde.public_key(),
+                msg.minimum_hop_count,
+                msg.target_component,
+            )
+        } else {
+            self.make_route_segment(
+                &self.cryptde.public_key(),
+                msg.target_key_opt.as_ref(),
+                msg.target_type,
+                msg.minimum_hop_count,
+                msg.target_component,
+                &msg.avoided_exit_keys,
+                msg.exit_location_opt
+                    .as_ref()
+                    .map(|location| location.as_str()),
+            )
+        }
+        .map_err(|detail| {
+            self.unreachable_refusal(msg.minimum_hop_count, detail, RouteRefusal::NoExitNode)
+        })?;
+        self.logger.debug(format!("Route over: {:?}", over));
+        Neighborhood::check_segment_policy("over", &over, msg.minimum_hop_count)
+            .map_err(RouteRefusal::InsufficientHopDiversity)?;
+        let back = self
+            .make_route_segment(
+                over.keys.last().expect("Empty segment"),
+                Some(&self.cryptde.public_key()),
+                local_target_type,
+                msg.minimum_hop_count,
+                msg.return_component_opt.expect("No return component"),
+                &[],
+                None,
+            )
+            .map_err(|detail| {
+                self.unreachable_refusal(msg.minimum_hop_count, detail, RouteRefusal::NoReturnRoute)
+            })?;
+        self.logger.debug(format!("Route back: {:?}", back));
+        Neighborhood::check_segment_policy("back", &back, msg.minimum_hop_count)
+            .map_err(RouteRefusal::InsufficientHopDiversity)?;
+        self.compose_route_query_response(over, back)
+    }
+
+    // Whether there's any other Node that says it's in this country and could be an exit
+    fn knows_exit_in(&self, location: &str) -> bool {
+        let root_key = self.neighborhood_database.root().public_key();
+        self.neighborhood_database
+            .keys()
+            .into_iter()
+            .filter(|key| *key != root_key)
+            .filter_map(|key| self.neighborhood_database.node_by_key(key))
+            .any(|node| !node.is_bootstrap_node() && node.is_in_country(location))
+    }
+
+    // A segment can't be completed either because too few Nodes are known to make one that long
+    // at all, or because none of the ones that are known can be reached in the right way.
+    fn unreachable_refusal(
+        &self,
+        minimum_hop_count: usize,
+        detail: String,
+        refusal: fn(String) -> RouteRefusal,
+    ) -> RouteRefusal {
+        let root_key = self.neighborhood_database.root().public_key();
+        let known = self
+            .neighborhood_database
+            .keys()
+            .into_iter()
+            .filter(|key| *key != root_key)
+            .filter(|key| match self.neighborhood_database.node_by_key(key) {
+                Some(node) => !node.is_bootstrap_node(),
+                None => false,
+            })
+            .count();
+        if known < minimum_hop_count {
+            RouteRefusal::NotEnoughNodes {
+                known,
+                required: minimum_hop_count,
+            }
+        } else {
+            refusal(detail)
+        }
+    }
+
+    // Both halves of a round trip are held to the same standard: at least the minimum number of
+    // hops, and no Node used twice.
+    fn check_segment_policy(
+        direction: &str,
+        segment: &RouteSegment,
+        minimum_hop_count: usize,
+    ) -> Result<(), String> {
+        let hop_count = segment.keys.len().saturating_sub(1);
+        if hop_count < minimum_hop_count {
+            return Err(format!(
+                "Route {} has only {} hop(s); at least {} required",
+                direction, hop_count, minimum_hop_count
+            ));
+        }
+        match segment
+            .keys
+            .iter()
+            .enumerate()
+            .find(|(index, key)| segment.keys[(index + 1)..].contains(*key))
+        {
+            Some((_, key)) => Err(format!(
+                "Route {} passes through {} more than once",
+                direction,
+                key.fingerprint()
+            )),
+            None => Ok(()),
+        }
+    }
+
+    fn compose_route_query_response(
+        &mut self,
         over: RouteSegment,
         back: RouteSegment,
-    ) -> Result<RouteQueryResponse, String> {
+    ) -> Result<RouteQueryResponse, RouteRefusal> {
         let segments = vec![&over, &back];
 
         if segments.iter().any(|rs| rs.keys.is_empty()) {
-            return Err("Cannot make multi-hop route without segment keys".to_string());
+            return Err(RouteRefusal::Internal(
+                "Cannot make multi-hop route without segment keys".to_string(),
+            ));
         }
 
         let consuming_wallet_opt = self.neighborhood_database.root().consuming_wallet();
@@ -490,19 +1431,32 @@ impl Neighborhood {
             .find(|segment| segment.keys.len() > 2)
             .is_some();
         if consuming_wallet_opt.is_none() && has_long_segment {
-            return Err("Cannot make multi-hop route segment without consuming wallet".to_string());
+            return Err(RouteRefusal::NoConsumingWallet);
         }
 
         let expected_request_services = match self.make_expected_services(&over) {
             Ok(services) => services,
-            Err(e) => return Err(e),
+            Err(e) => return Err(RouteRefusal::Internal(e)),
         };
 
         let expected_response_services = match self.make_expected_services(&back) {
             Ok(services) => services,
-            Err(e) => return Err(e),
+            Err(e) => return Err(RouteRefusal::Internal(e)),
         };
 
+        let valid_for = self.route_validity(&segments);
+        let score = self.route_score(
+            &segments
+                .iter()
+                .flat_map(|segment| segment.keys.iter())
+                .collect(),
+            Instant::now(),
+        );
+        let exit_sla_opt = over
+            .keys
+            .last()
+            .and_then(|key| self.neighborhood_database.node_by_key(key))
+            .and_then(|node| node.exit_sla_opt());
         let return_route_id = self.advance_return_route_id();
         Ok(RouteQueryResponse {
             route: Route::round_trip(
@@ -518,9 +1472,135 @@ impl Neighborhood {
                 expected_response_services,
                 return_route_id,
             ),
+            valid_for,
+            score,
+            exit_sla_opt,
         })
     }
 
+    // A route is trusted for as long as the youngest NodeRecord in it has gone unchanged, within
+    // limits: records that have just changed are likely to change again soon. Records that haven't
+    // changed since this Node started are taken to be as old as the Neighborhood.
+    fn route_validity(&self, segments: &[&RouteSegment]) -> Duration {
+        let root_key = self.neighborhood_database.root().public_key();
+        let youngest_age_opt = segments
+            .iter()
+            .flat_map(|segment| segment.keys.iter())
+            .filter(|key| *key != root_key)
+            .map(|key| match self.record_changes.get(key) {
+                Some(changed) => changed.elapsed().as_secs(),
+                None => self.started.elapsed().as_secs(),
+            })
+            .min();
+        match youngest_age_opt {
+            None => MAXIMUM_ROUTE_VALIDITY,
+            Some(age) => Duration::from_secs(min(
+                max(age, MINIMUM_ROUTE_VALIDITY.as_secs()),
+                MAXIMUM_ROUTE_VALIDITY.as_secs(),
+            )),
+        }
+    }
+
+    fn versions_of(&self, gossip_records: &[GossipNodeRecord]) -> Vec<(PublicKey, Option<u32>)> {
+        gossip_records
+            .iter()
+            .map(|gnr| {
+                let key = gnr.public_key();
+                let version_opt = self
+                    .neighborhood_database
+                    .node_by_key(&key)
+                    .map(|node| node.version());
+                (key, version_opt)
+            })
+            .collect()
+    }
+
+    // Records the database had never seen before make a full sync; changes to records it already had
+    // are only version bumps.
+    fn change_made(
+        database: &NeighborhoodDatabase,
+        versions_before: &[(PublicKey, Option<u32>)],
+    ) -> GossipChange {
+        let learned_new_nodes = versions_before.iter().any(|(key, version_before)| {
+            version_before.is_none() && database.node_by_key(key).is_some()
+        });
+        if learned_new_nodes {
+            GossipChange::FullSync
+        } else {
+            GossipChange::VersionBump
+        }
+    }
+
+    // A TTL only shrinks as Gossip travels, and never stretches past what the change it made here
+    // deserves: Gossip that started as a full sync but only bumps versions here goes no farther than
+    // a version bump would. None means it shouldn't be relayed at all.
+    fn relay_ttl(incoming_ttl_opt: Option<u8>, change: GossipChange) -> Option<u8> {
+        let ttl = min(incoming_ttl_opt.unwrap_or(change.ttl()), change.ttl());
+        if ttl == 0 {
+            None
+        } else {
+            Some(ttl - 1)
+        }
+    }
+
+    // Records saved on an earlier run that have been gossiped about within the retention window go
+    // back into the database, unless it already knows the Node from --neighbor
+    fn restore_node_records(&mut self, now: SystemTime) {
+        let config = match &self.node_record_persistence {
+            Some(config) => config.clone(),
+            None => return,
+        };
+        fs::create_dir_all(&config.data_directory).expect(&format!(
+            "Cannot create specified data directory at {:?}",
+            config.data_directory
+        ));
+        let dao = self
+            .db_initializer
+            .initialize(&config.data_directory)
+            .expect("Could not initialize database")
+            .node_record;
+        let cutoff = now.checked_sub(config.retention).unwrap_or(UNIX_EPOCH);
+        let aged_out = dao.age_out(&cutoff);
+        let restored = dao
+            .load_all()
+            .into_iter()
+            .filter(|node_record| self.neighborhood_database.add_node(node_record).is_ok())
+            .count();
+        self.logger.info(format!(
+            "Restored {} saved Node records; {} had not been gossiped about for too long",
+            restored, aged_out
+        ));
+        self.node_record_dao = Some(dao);
+    }
+
+    fn save_gossiped_records(&self, gossip_records: &[GossipNodeRecord], now: SystemTime) {
+        let dao = match &self.node_record_dao {
+            Some(dao) => dao,
+            None => return,
+        };
+        let root_key = self.neighborhood_database.root().public_key();
+        gossip_records
+            .iter()
+            .map(|gnr| gnr.public_key())
+            .filter(|key| key != root_key)
+            .filter_map(|key| self.neighborhood_database.node_by_key(&key))
+            .filter(|node_record| node_record.signatures().is_some())
+            .for_each(|node_record| dao.save(node_record, &now));
+    }
+
+    fn note_record_changes(&mut self, versions_before: Vec<(PublicKey, Option<u32>)>) {
+        let now = Instant::now();
+        for (key, version_before) in versions_before {
+            let version_after = self
+                .neighborhood_database
+                .node_by_key(&key)
+                .map(|node| node.version());
+            if version_after.is_some() && (version_after != version_before) {
+                self.record_changes.insert(key, now);
+            }
+        }
+    }
+
     fn make_route_segment(
         &self,
         origin: &PublicKey,
@@ -528,25 +1608,95 @@ impl Neighborhood {
         target_type: TargetType,
         minimum_hop_count: usize,
         target_component: Component,
+        avoided_exit_keys: &[PublicKey],
+        exit_location_opt: Option<&str>,
     ) -> Result<RouteSegment, String> {
         let mut node_seqs =
             self.complete_routes(vec![origin], target, target_type, minimum_hop_count);
+        node_seqs.retain(|node_seq| !node_seq.iter().any(|key| self.reputation.is_banned(key)));
+        self.avoid_troubled_nodes(&mut node_seqs);
+        // Unlike an avoided exit, an exit in the wrong place is no exit at all
+        if let Some(location) = exit_location_opt {
+            node_seqs.retain(|node_seq| {
+                match self
+                    .neighborhood_database
+                    .node_by_key(node_seq.last().expect("Empty node sequence"))
+                {
+                    Some(exit) => exit.is_in_country(location),
+                    None => false,
+                }
+            });
+        }
+        // An avoided exit is still better than none at all
+        if node_seqs.iter().any(|node_seq| {
+            !avoided_exit_keys.contains(*node_seq.last().expect("Empty node sequence"))
+        }) {
+            node_seqs.retain(|node_seq| {
+                !avoided_exit_keys.contains(*node_seq.last().expect("Empty node sequence"))
+            });
+        }
 
         if node_seqs.is_empty() {
             let target_str = match target {
                 Some(t) => format!(" {}", t),
                 None => String::new(),
             };
+            let location_str = match exit_location_opt {
+                Some(location) => format!(" exiting in {}", location),
+                None => String::new(),
+            };
             Err(format!(
-                "Couldn't find any routes: at least {}-hop from {} to {:?} at {:?}{}",
-                minimum_hop_count, origin, target_component, target_type, target_str
+                "Couldn't find any routes: at least {}-hop from {} to {:?} at {:?}{}{}",
+                minimum_hop_count, origin, target_component, target_type, target_str, location_str
             ))
         } else {
-            let chosen_node_seq = node_seqs.remove(0);
+            let now = Instant::now();
+            let chosen_node_seq = node_seqs
+                .into_iter()
+                .min_by_key(|node_seq| self.route_cost(node_seq, now))
+                .expect("No routes after all");
             Ok(RouteSegment::new(chosen_node_seq, target_component))
         }
     }
 
+    // Like an avoided exit, a Node that was just reported failing is still better than no route
+    fn avoid_troubled_nodes(&self, node_seqs: &mut Vec<Vec<&PublicKey>>) {
+        let now = Instant::now();
+        let is_troubled = |node_seq: &Vec<&PublicKey>| {
+            node_seq
+                .iter()
+                .skip(1)
+                .any(|key| self.node_problems.is_cooling_down(key, now))
+        };
+        if node_seqs.iter().any(|node_seq| !is_troubled(node_seq)) {
+            node_seqs.retain(|node_seq| !is_troubled(node_seq));
+        }
+    }
+
+    // Any route at all that isn't through a banned Node, so that traffic along it has no pattern
+    fn make_random_route_segment(
+        &self,
+        origin: &PublicKey,
+        minimum_hop_count: usize,
+        target_component: Component,
+    ) -> Result<RouteSegment, String> {
+        let mut node_seqs =
+            self.complete_routes(vec![origin], None, TargetType::Standard, minimum_hop_count);
+        node_seqs.retain(|node_seq| !node_seq.iter().any(|key| self.reputation.is_banned(key)));
+        self.avoid_troubled_nodes(&mut node_seqs);
+        if node_seqs.is_empty() {
+            return Err(format!(
+                "Couldn't find any routes: at least {}-hop from {} to {:?} at {:?}",
+                minimum_hop_count,
+                origin,
+                target_component,
+                TargetType::Standard
+            ));
+        }
+        let index = rand::thread_rng().gen_range(0, node_seqs.len());
+        Ok(RouteSegment::new(node_seqs.remove(index), target_component))
+    }
+
     fn make_expected_services(
         &self,
         segment: &RouteSegment,
@@ -579,11 +1729,13 @@ impl Neighborhood {
                             Ok(ExpectedService::Exit(
                                 route_segment_key.clone(),
                                 node.earning_wallet(),
+                                node.rate_card(),
                             ))
                         }
                         (Some(_), Some(_)) => Ok(ExpectedService::Routing(
                             route_segment_key.clone(),
                             node.earning_wallet(),
+                            node.rate_card(),
                         )),
                         _ => Err(
                             "cannot calculate expected service, no keys provided in route segment"
@@ -612,7 +1764,67 @@ impl Neighborhood {
     }
 
     fn last_type_qualifies(&self, last_node_ref: &NodeRecord, target_type: TargetType) -> bool {
-        (target_type == TargetType::Bootstrap) == last_node_ref.is_bootstrap_node()
+        match target_type {
+            TargetType::Bootstrap => last_node_ref.is_bootstrap_node(),
+            TargetType::Standard => !last_node_ref.is_bootstrap_node(),
+            TargetType::ExitTls => {
+                !last_node_ref.is_bootstrap_node() && last_node_ref.offers_exit_tls()
+            }
+        }
+    }
+
+    // Route exclusions can keep a Node from being an exit; the end of a route back home, or to a
+    // bootstrap Node, isn't one.
+    fn last_exit_qualifies(&self, last_node_ref: &NodeRecord, target_type: TargetType) -> bool {
+        let route_exclusions = match &self.route_exclusions {
+            Some(route_exclusions) => route_exclusions,
+            None => return true,
+        };
+        if target_type == TargetType::Bootstrap
+            || last_node_ref.public_key() == self.neighborhood_database.root().public_key()
+        {
+            return true;
+        }
+        route_exclusions.permits_exit(Neighborhood::ip_addr_of(last_node_ref))
+    }
+
+    fn hop_qualifies(&self, key: &PublicKey) -> bool {
+        let route_exclusions = match &self.route_exclusions {
+            Some(route_exclusions) => route_exclusions,
+            None => return true,
+        };
+        let root = self.neighborhood_database.root();
+        if key == root.public_key() {
+            return true;
+        }
+        match self.neighborhood_database.node_by_key(key) {
+            Some(node) => route_exclusions.permits_hop(
+                Neighborhood::ip_addr_of(node),
+                Neighborhood::ip_addr_of(root),
+            ),
+            None => true,
+        }
+    }
+
+    fn ip_addr_of(node: &NodeRecord) -> Option<IpAddr> {
+        node.node_addr_opt()
+            .map(|node_addr| node_addr.ip_addr())
+            .filter(|ip_addr| *ip_addr != sentinel_ip_addr())
+    }
+
+    #[cfg(not(feature = "fault_injection"))]
+    fn report_known_nodes(&self) {}
+
+    // So that a multinode test can ask the control socket how far Gossip has spread
+    #[cfg(feature = "fault_injection")]
+    fn report_known_nodes(&self) {
+        self.fault_injector.report_known_nodes(
+            self.neighborhood_database
+                .keys()
+                .into_iter()
+                .cloned()
+                .collect(),
+        );
     }
 
     fn advance_return_route_id(&mut self) -> u32 {
@@ -638,10 +1850,11 @@ impl Neighborhood {
             .neighborhood_database
             .node_by_key(prefix.last().expect("Empty prefix"))
             .expect("Node magically disappeared");
-        // Check to see if we're done. If we are, all three of these qualifications will pass.
+        // Check to see if we're done. If we are, all four of these qualifications will pass.
         if self.route_length_qualifies(hops_remaining)
             && self.last_key_qualifies(last_node_ref, target)
             && self.last_type_qualifies(last_node_ref, target_type)
+            && self.last_exit_qualifies(last_node_ref, target_type)
         {
             vec![prefix]
         }
@@ -655,6 +1868,7 @@ impl Neighborhood {
                 .neighbors()
                 .iter()
                 .filter(|neighbor_key_ref_ref| !prefix.contains(neighbor_key_ref_ref))
+                .filter(|neighbor_key_ref_ref| self.hop_qualifies(neighbor_key_ref_ref))
                 .flat_map(|neighbor_key_ref_ref| {
                     let mut new_prefix = prefix.clone();
                     new_prefix.push(neighbor_key_ref_ref);
@@ -678,17 +1892,34 @@ impl Neighborhood {
 mod tests {
     use super::super::gossip::GossipBuilder;
     use super::super::gossip::GossipNodeRecord;
+    use super::super::neighborhood_database::NodeSignatures;
     use super::super::neighborhood_test_utils::make_node_record;
+    use super::super::route_exclusion::AsnDatabase;
+    use super::super::route_exclusion::RouteExclusion;
     use super::*;
+    use crate::accountant::db_initializer::Daos;
     use crate::sub_lib::cryptde::encodex;
     use crate::sub_lib::cryptde::CryptData;
     use crate::sub_lib::cryptde_null::CryptDENull;
+    use crate::sub_lib::cryptde_real::CryptDEReal;
     use crate::sub_lib::dispatcher::Endpoint;
     use crate::sub_lib::hopper::ExpiredCoresPackage;
     use crate::sub_lib::neighborhood::sentinel_ip_addr;
     use crate::sub_lib::neighborhood::ExpectedServices;
+    use crate::sub_lib::neighborhood::NodeProblem;
+    use crate::sub_lib::neighborhood::RouteOutcome;
+    use crate::sub_lib::neighborhood::TopologyFilter;
+    use crate::sub_lib::neighborhood::LOAD_ADVERTISEMENT_THRESHOLD;
+    use crate::sub_lib::neighborhood::WALLET_ROTATION_CHECK_INTERVAL;
     use crate::sub_lib::stream_handler_pool::TransmitDataMsg;
-    use crate::sub_lib::wallet::Wallet;
+    use crate::test_utils::accountant_mocks::BannedNodeDaoMock;
+    use crate::test_utils::accountant_mocks::ConfigDaoMock;
+    use crate::test_utils::accountant_mocks::DbInitializerMock;
+    use crate::test_utils::accountant_mocks::JournalDaoMock;
+    use crate::test_utils::accountant_mocks::MaintenanceDaoMock;
+    use crate::test_utils::accountant_mocks::NodeRecordDaoMock;
+    use crate::test_utils::accountant_mocks::PayableDaoMock;
+    use crate::test_utils::accountant_mocks::ReceivableDaoMock;
     use crate::test_utils::logging::init_test_logging;
     use crate::test_utils::logging::TestLogHandler;
     use crate::test_utils::recorder::make_recorder;
@@ -705,16 +1936,22 @@ mod tests {
     use actix::Recipient;
     use actix::System;
     use serde_cbor;
+    use std::collections::HashSet;
+    use std::fs;
     use std::net::IpAddr;
+    use std::path::PathBuf;
     use std::str::FromStr;
+    use std::sync::Arc;
+    use std::sync::Mutex;
     use std::thread;
+    use std::time::UNIX_EPOCH;
     use tokio::prelude::Future;
 
     #[test]
     #[should_panic(
-        expected = "A SubstratumNode without an --ip setting is not decentralized and cannot have any --neighbor settings"
+        expected = "A SubstratumNode with --neighbor settings but no --ip setting needs a --port_count greater than 0, so that its neighbors can tell it its IP address"
     )]
-    fn neighborhood_cannot_be_created_with_neighbors_and_default_ip() {
+    fn neighborhood_cannot_be_created_with_neighbors_and_default_ip_but_no_clandestine_ports() {
         let cryptde = cryptde();
         let earning_wallet = Wallet::new("earning");
         let consuming_wallet = Some(Wallet::new("consuming"));
@@ -1057,6 +2294,47 @@ mod tests {
         );
     }
 
+    #[test]
+    fn node_query_responds_with_result_when_fingerprint_query_matches_configured_data() {
+        let cryptde = cryptde();
+        let system = System::new(
+            "node_query_responds_with_result_when_fingerprint_query_matches_configured_data",
+        );
+        let one_neighbor = make_node_record(2345, true, false);
+        let another_neighbor = make_node_record(3456, true, false);
+        let subject = Neighborhood::new(
+            cryptde,
+            NeighborhoodConfig {
+                neighbor_configs: vec![
+                    node_record_to_pair(&one_neighbor),
+                    node_record_to_pair(&another_neighbor),
+                ],
+                is_bootstrap_node: false,
+                local_ip_addr: IpAddr::from_str("5.4.3.2").unwrap(),
+                clandestine_port_list: vec![5678],
+                earning_wallet: Wallet::new("earning"),
+                consuming_wallet: Some(Wallet::new("consuming")),
+            },
+        );
+        let addr: Addr<Syn, Neighborhood> = subject.start();
+        let sub: Recipient<Syn, NodeQueryMessage> = addr.recipient::<NodeQueryMessage>();
+
+        let future = sub.send(NodeQueryMessage::Fingerprint(
+            another_neighbor.public_key().fingerprint(),
+        ));
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+        let result = future.wait().unwrap();
+        assert_eq!(
+            result.unwrap(),
+            NodeDescriptor::new(
+                another_neighbor.public_key().clone(),
+                Some(another_neighbor.node_addr_opt().unwrap().clone())
+            )
+        );
+    }
+
     #[test]
     fn node_query_responds_with_none_when_ip_address_query_matches_no_configured_data() {
         let cryptde = cryptde();
@@ -1165,7 +2443,13 @@ mod tests {
         Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
         system.run();
         let result = future.wait().unwrap();
-        assert_eq!(result, None);
+        assert_eq!(
+            result,
+            Err(RouteRefusal::NotEnoughNodes {
+                known: 0,
+                required: 5
+            })
+        );
     }
 
     #[test]
@@ -1193,9 +2477,15 @@ mod tests {
         Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
         system.run();
         let result = future.wait().unwrap();
-        assert_eq!(result, None);
-    }
-
+        assert_eq!(
+            result,
+            Err(RouteRefusal::NotEnoughNodes {
+                known: 0,
+                required: 2
+            })
+        );
+    }
+
     #[test]
     fn route_query_succeeds_when_asked_for_one_hop_round_trip_route_without_consuming_wallet() {
         let cryptde = cryptde();
@@ -1249,18 +2539,68 @@ mod tests {
             expected_services: ExpectedServices::RoundTrip(
                 vec![
                     ExpectedService::Nothing,
-                    ExpectedService::Exit(a.public_key().clone(), a.earning_wallet()),
+                    ExpectedService::Exit(
+                        a.public_key().clone(),
+                        a.earning_wallet(),
+                        a.rate_card(),
+                    ),
                 ],
                 vec![
-                    ExpectedService::Exit(a.public_key().clone(), a.earning_wallet()),
+                    ExpectedService::Exit(
+                        a.public_key().clone(),
+                        a.earning_wallet(),
+                        a.rate_card(),
+                    ),
                     ExpectedService::Nothing,
                 ],
                 0,
             ),
+            valid_for: MINIMUM_ROUTE_VALIDITY,
+            score: 50,
+            exit_sla_opt: None,
         };
         assert_eq!(result, expected_response);
     }
 
+    #[test]
+    fn route_query_response_carries_what_the_exit_advertised() {
+        let cryptde = cryptde();
+        let system = System::new("route_query_response_carries_what_the_exit_advertised");
+        let mut subject = Neighborhood::new(
+            cryptde,
+            NeighborhoodConfig {
+                neighbor_configs: vec![],
+                is_bootstrap_node: false,
+                local_ip_addr: sentinel_ip_addr(),
+                clandestine_port_list: vec![],
+                earning_wallet: Wallet::new("earning"),
+                consuming_wallet: None,
+            },
+        );
+        let exit_sla = ExitSla {
+            max_streams: 8,
+            max_stream_bytes_per_second: 65536,
+            content_policy_hash: String::from("booga"),
+        };
+        let mut a = make_node_record(1234, true, false);
+        a.set_exit_sla_opt(Some(exit_sla.clone()));
+        let b = subject.neighborhood_database.root().clone();
+        {
+            let db = &mut subject.neighborhood_database;
+            db.add_node(&a).unwrap();
+            dual_edge_func(db, &a, &b);
+        }
+        let addr: Addr<Syn, Neighborhood> = subject.start();
+        let sub: Recipient<Syn, RouteQueryMessage> = addr.recipient::<RouteQueryMessage>();
+
+        let future = sub.send(RouteQueryMessage::data_indefinite_route_request(1));
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+        let result = future.wait().unwrap().unwrap();
+        assert_eq!(result.exit_sla_opt, Some(exit_sla));
+    }
+
     #[test]
     fn route_query_responds_with_none_when_asked_for_one_hop_round_trip_route_without_consuming_wallet_when_back_route_needs_two_hops(
     ) {
@@ -1299,7 +2639,7 @@ mod tests {
         Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
         system.run();
         let result = future.wait().unwrap();
-        assert_eq!(result, None);
+        assert_eq!(result, Err(RouteRefusal::NoConsumingWallet));
     }
 
     #[test]
@@ -1328,7 +2668,13 @@ mod tests {
         Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
         system.run();
         let result = future.wait().unwrap();
-        assert_eq!(result, None);
+        assert_eq!(
+            result,
+            Err(RouteRefusal::NotEnoughNodes {
+                known: 0,
+                required: 2
+            })
+        );
     }
 
     #[test]
@@ -1376,130 +2722,2187 @@ mod tests {
                 vec![ExpectedService::Nothing, ExpectedService::Nothing],
                 0,
             ),
+            valid_for: MAXIMUM_ROUTE_VALIDITY,
+            score: MAXIMUM_ROUTE_SCORE,
+            exit_sla_opt: None,
+        };
+        assert_eq!(result, expected_response);
+    }
+
+    #[test]
+    fn zero_hop_routing_handles_return_route_id_properly() {
+        let cryptde = cryptde();
+        let earning_wallet = Wallet::new("earning");
+        let mut subject = Neighborhood::new(
+            cryptde,
+            NeighborhoodConfig {
+                neighbor_configs: vec![],
+                is_bootstrap_node: false,
+                local_ip_addr: sentinel_ip_addr(),
+                clandestine_port_list: vec![],
+                earning_wallet: earning_wallet.clone(),
+                consuming_wallet: None,
+            },
+        );
+
+        let result0 = subject.zero_hop_route_response();
+        let result1 = subject.zero_hop_route_response();
+
+        let return_route_id_0 = match result0.expected_services {
+            ExpectedServices::RoundTrip(_, _, id) => id,
+            _ => panic!("expected RoundTrip got OneWay"),
+        };
+
+        let return_route_id_1 = match result1.expected_services {
+            ExpectedServices::RoundTrip(_, _, id) => id,
+            _ => panic!("expected RoundTrip got OneWay"),
+        };
+
+        assert_eq!(return_route_id_0, 0);
+        assert_eq!(return_route_id_1, 1);
+    }
+
+    /*
+            Database, where B is bootstrap and the rest are standard:
+
+                 +---+-B-+---+
+                 |   |   |   |
+                 P---Q---R---S
+
+            Tests will be written from the viewpoint of P.
+    */
+
+    #[test]
+    fn route_query_messages() {
+        let cryptde = cryptde();
+        let earning_wallet = Wallet::new("earning");
+        let consuming_wallet = Some(Wallet::new("consuming"));
+        let system = System::new("route_query_messages");
+        let mut subject = Neighborhood::new(
+            cryptde,
+            NeighborhoodConfig {
+                neighbor_configs: vec![],
+                is_bootstrap_node: false,
+                local_ip_addr: sentinel_ip_addr(),
+                clandestine_port_list: vec![],
+                earning_wallet: earning_wallet.clone(),
+                consuming_wallet: consuming_wallet.clone(),
+            },
+        );
+        let b = &make_node_record(1234, true, true);
+        let p = &subject.neighborhood_database.root().clone();
+        let q = &make_node_record(3456, true, false);
+        let r = &make_node_record(4567, false, false);
+        let s = &make_node_record(5678, false, false);
+        {
+            let db = &mut subject.neighborhood_database;
+            db.add_node(b).unwrap();
+            db.add_node(q).unwrap();
+            db.add_node(r).unwrap();
+            db.add_node(s).unwrap();
+            let mut dual_edge = |a: &NodeRecord, b: &NodeRecord| dual_edge_func(db, a, b);
+            dual_edge(b, p);
+            dual_edge(b, q);
+            dual_edge(b, r);
+            dual_edge(b, s);
+            dual_edge(p, q);
+            dual_edge(q, r);
+            dual_edge(r, s);
+        }
+
+        let addr: Addr<Syn, Neighborhood> = subject.start();
+        let sub: Recipient<Syn, RouteQueryMessage> = addr.recipient::<RouteQueryMessage>();
+
+        let data_route = sub.send(RouteQueryMessage::data_indefinite_route_request(2));
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+        let segment = |nodes: Vec<&NodeRecord>, component: Component| {
+            RouteSegment::new(
+                nodes.into_iter().map(|n| n.public_key()).collect(),
+                component,
+            )
+        };
+
+        let result = data_route.wait().unwrap().unwrap();
+        let expected_response = RouteQueryResponse {
+            route: Route::round_trip(
+                segment(vec![p, q, r], Component::ProxyClient),
+                segment(vec![r, q, p], Component::ProxyServer),
+                cryptde,
+                consuming_wallet,
+                0,
+            )
+            .unwrap(),
+            expected_services: ExpectedServices::RoundTrip(
+                vec![
+                    ExpectedService::Nothing,
+                    ExpectedService::Routing(
+                        q.public_key().clone(),
+                        q.earning_wallet(),
+                        q.rate_card(),
+                    ),
+                    ExpectedService::Exit(
+                        r.public_key().clone(),
+                        r.earning_wallet(),
+                        r.rate_card(),
+                    ),
+                ],
+                vec![
+                    ExpectedService::Exit(
+                        r.public_key().clone(),
+                        r.earning_wallet(),
+                        r.rate_card(),
+                    ),
+                    ExpectedService::Routing(
+                        q.public_key().clone(),
+                        q.earning_wallet(),
+                        q.rate_card(),
+                    ),
+                    ExpectedService::Nothing,
+                ],
+                0,
+            ),
+            valid_for: MINIMUM_ROUTE_VALIDITY,
+            score: 50,
+            exit_sla_opt: None,
         };
         assert_eq!(result, expected_response);
     }
 
     #[test]
-    fn zero_hop_routing_handles_return_route_id_properly() {
-        let cryptde = cryptde();
-        let earning_wallet = Wallet::new("earning");
-        let mut subject = Neighborhood::new(
-            cryptde,
-            NeighborhoodConfig {
-                neighbor_configs: vec![],
-                is_bootstrap_node: false,
-                local_ip_addr: sentinel_ip_addr(),
-                clandestine_port_list: vec![],
-                earning_wallet: earning_wallet.clone(),
-                consuming_wallet: None,
-            },
-        );
+    fn round_trip_route_back_is_held_to_minimum_hop_count_even_when_a_shortcut_exists() {
+        let cryptde = cryptde();
+        let earning_wallet = Wallet::new("earning");
+        let consuming_wallet = Some(Wallet::new("consuming"));
+        let system = System::new(
+            "round_trip_route_back_is_held_to_minimum_hop_count_even_when_a_shortcut_exists",
+        );
+        let mut subject = Neighborhood::new(
+            cryptde,
+            NeighborhoodConfig {
+                neighbor_configs: vec![],
+                is_bootstrap_node: false,
+                local_ip_addr: sentinel_ip_addr(),
+                clandestine_port_list: vec![],
+                earning_wallet: earning_wallet.clone(),
+                consuming_wallet: consuming_wallet.clone(),
+            },
+        );
+        let p = &subject.neighborhood_database.root().clone();
+        let q = &make_node_record(3456, true, false);
+        let r = &make_node_record(4567, false, false);
+        let s = &make_node_record(5678, false, false);
+        {
+            let db = &mut subject.neighborhood_database;
+            db.add_node(q).unwrap();
+            db.add_node(r).unwrap();
+            db.add_node(s).unwrap();
+            let mut single_edge = |a: &NodeRecord, b: &NodeRecord| single_edge_func(db, a, b);
+            single_edge(p, q);
+            single_edge(q, r);
+            single_edge(r, p);
+            single_edge(r, s);
+            single_edge(s, p);
+        }
+        let addr: Addr<Syn, Neighborhood> = subject.start();
+        let sub: Recipient<Syn, RouteQueryMessage> = addr.recipient::<RouteQueryMessage>();
+
+        let data_route = sub.send(RouteQueryMessage::data_indefinite_route_request(2));
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+        let result = data_route.wait().unwrap().unwrap();
+        match result.expected_services {
+            ExpectedServices::RoundTrip(over, back, _) => {
+                assert_eq!(
+                    over,
+                    vec![
+                        ExpectedService::Nothing,
+                        ExpectedService::Routing(
+                            q.public_key().clone(),
+                            q.earning_wallet(),
+                            q.rate_card()
+                        ),
+                        ExpectedService::Exit(
+                            r.public_key().clone(),
+                            r.earning_wallet(),
+                            r.rate_card()
+                        ),
+                    ]
+                );
+                assert_eq!(
+                    back,
+                    vec![
+                        ExpectedService::Exit(
+                            r.public_key().clone(),
+                            r.earning_wallet(),
+                            r.rate_card()
+                        ),
+                        ExpectedService::Routing(
+                            s.public_key().clone(),
+                            s.earning_wallet(),
+                            s.rate_card()
+                        ),
+                        ExpectedService::Nothing,
+                    ]
+                );
+            }
+            _ => panic!("expected RoundTrip got OneWay"),
+        }
+    }
+
+    #[test]
+    fn route_query_responds_with_none_when_only_the_route_back_is_too_short() {
+        let cryptde = cryptde();
+        let earning_wallet = Wallet::new("earning");
+        let consuming_wallet = Some(Wallet::new("consuming"));
+        let system =
+            System::new("route_query_responds_with_none_when_only_the_route_back_is_too_short");
+        let mut subject = Neighborhood::new(
+            cryptde,
+            NeighborhoodConfig {
+                neighbor_configs: vec![],
+                is_bootstrap_node: false,
+                local_ip_addr: sentinel_ip_addr(),
+                clandestine_port_list: vec![],
+                earning_wallet: earning_wallet.clone(),
+                consuming_wallet: consuming_wallet.clone(),
+            },
+        );
+        let p = &subject.neighborhood_database.root().clone();
+        let q = &make_node_record(3456, true, false);
+        let r = &make_node_record(4567, false, false);
+        {
+            let db = &mut subject.neighborhood_database;
+            db.add_node(q).unwrap();
+            db.add_node(r).unwrap();
+            let mut single_edge = |a: &NodeRecord, b: &NodeRecord| single_edge_func(db, a, b);
+            single_edge(p, q);
+            single_edge(q, r);
+            single_edge(r, p);
+        }
+        let addr: Addr<Syn, Neighborhood> = subject.start();
+        let sub: Recipient<Syn, RouteQueryMessage> = addr.recipient::<RouteQueryMessage>();
+
+        let data_route = sub.send(RouteQueryMessage::data_indefinite_route_request(2));
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+        let result = data_route.wait().unwrap();
+        assert_eq!(
+            result,
+            Err(RouteRefusal::NoReturnRoute(format!(
+                "Couldn't find any routes: at least 2-hop from {} to ProxyServer at Standard {}",
+                r.public_key(),
+                p.public_key()
+            )))
+        );
+    }
+
+    #[test]
+    fn check_segment_policy_accepts_segment_with_enough_distinct_hops() {
+        let segment = RouteSegment::new(
+            vec![
+                &PublicKey::new(&[1]),
+                &PublicKey::new(&[2]),
+                &PublicKey::new(&[3]),
+            ],
+            Component::ProxyServer,
+        );
+
+        let result = Neighborhood::check_segment_policy("back", &segment, 2);
+
+        assert_eq!(result, Ok(()));
+    }
+
+    #[test]
+    fn check_segment_policy_rejects_segment_with_too_few_hops() {
+        let segment = RouteSegment::new(
+            vec![&PublicKey::new(&[1]), &PublicKey::new(&[2])],
+            Component::ProxyServer,
+        );
+
+        let result = Neighborhood::check_segment_policy("back", &segment, 2);
+
+        assert_eq!(
+            result,
+            Err(String::from(
+                "Route back has only 1 hop(s); at least 2 required"
+            ))
+        );
+    }
+
+    #[test]
+    fn check_segment_policy_rejects_segment_that_reuses_a_node() {
+        let segment = RouteSegment::new(
+            vec![
+                &PublicKey::new(&[1]),
+                &PublicKey::new(&[2]),
+                &PublicKey::new(&[1]),
+            ],
+            Component::ProxyServer,
+        );
+
+        let result = Neighborhood::check_segment_policy("back", &segment, 2);
+
+        assert_eq!(
+            result,
+            Err(format!(
+                "Route back passes through {} more than once",
+                PublicKey::new(&[1]).fingerprint()
+            ))
+        );
+    }
+
+    #[test]
+    fn gossip_ttl_constants() {
+        assert_eq!(VERSION_BUMP_GOSSIP_TTL, 2);
+        assert_eq!(FULL_SYNC_GOSSIP_TTL, 8);
+        assert_eq!(GossipChange::VersionBump.ttl(), VERSION_BUMP_GOSSIP_TTL);
+        assert_eq!(GossipChange::FullSync.ttl(), FULL_SYNC_GOSSIP_TTL);
+    }
+
+    #[test]
+    fn relay_ttl_counts_down_and_stops_at_zero() {
+        assert_eq!(
+            Neighborhood::relay_ttl(Some(2), GossipChange::FullSync),
+            Some(1)
+        );
+        assert_eq!(
+            Neighborhood::relay_ttl(Some(1), GossipChange::FullSync),
+            Some(0)
+        );
+        assert_eq!(
+            Neighborhood::relay_ttl(Some(0), GossipChange::FullSync),
+            None
+        );
+    }
+
+    #[test]
+    fn relay_ttl_holds_a_full_sync_that_only_bumps_versions_here_to_the_version_bump_ttl() {
+        let result = Neighborhood::relay_ttl(Some(FULL_SYNC_GOSSIP_TTL), GossipChange::VersionBump);
+
+        assert_eq!(result, Some(VERSION_BUMP_GOSSIP_TTL - 1));
+    }
+
+    #[test]
+    fn relay_ttl_treats_gossip_without_a_ttl_as_starting_here() {
+        assert_eq!(
+            Neighborhood::relay_ttl(None, GossipChange::VersionBump),
+            Some(VERSION_BUMP_GOSSIP_TTL - 1)
+        );
+        assert_eq!(
+            Neighborhood::relay_ttl(None, GossipChange::FullSync),
+            Some(FULL_SYNC_GOSSIP_TTL - 1)
+        );
+    }
+
+    #[test]
+    fn change_made_is_a_full_sync_only_if_new_nodes_were_learned() {
+        let subject = make_standalone_neighborhood();
+        let mut database = subject.neighborhood_database;
+        let known = make_node_record(2345, true, false);
+        let unknown = make_node_record(3456, true, false);
+        let rejected = make_node_record(4567, true, false);
+        database.add_node(&known).unwrap();
+        let bumped = vec![(known.public_key().clone(), Some(0))];
+        database.add_node(&unknown).unwrap();
+        let learned = vec![
+            (known.public_key().clone(), Some(0)),
+            (unknown.public_key().clone(), None),
+        ];
+        let ignored = vec![(rejected.public_key().clone(), None)];
+
+        assert_eq!(
+            Neighborhood::change_made(&database, &bumped),
+            GossipChange::VersionBump
+        );
+        assert_eq!(
+            Neighborhood::change_made(&database, &learned),
+            GossipChange::FullSync
+        );
+        assert_eq!(
+            Neighborhood::change_made(&database, &ignored),
+            GossipChange::VersionBump
+        );
+    }
+
+    fn make_standalone_neighborhood() -> Neighborhood {
+        Neighborhood::new(
+            cryptde(),
+            NeighborhoodConfig {
+                neighbor_configs: vec![],
+                is_bootstrap_node: false,
+                local_ip_addr: sentinel_ip_addr(),
+                clandestine_port_list: vec![],
+                earning_wallet: Wallet::new("earning"),
+                consuming_wallet: Some(Wallet::new("consuming")),
+            },
+        )
+    }
+
+    fn make_originate_only_neighborhood(neighbor: &NodeRecord) -> Neighborhood {
+        Neighborhood::new(
+            cryptde(),
+            NeighborhoodConfig {
+                neighbor_configs: vec![(
+                    neighbor.public_key().clone(),
+                    neighbor.node_addr_opt().unwrap().clone(),
+                )],
+                is_bootstrap_node: false,
+                local_ip_addr: sentinel_ip_addr(),
+                clandestine_port_list: vec![1234],
+                earning_wallet: Wallet::new("earning"),
+                consuming_wallet: Some(Wallet::new("consuming")),
+            },
+        )
+    }
+
+    #[test]
+    fn neighborhood_with_neighbors_and_default_ip_starts_out_advertising_only_its_ports() {
+        let neighbor = make_node_record(2345, true, true);
+
+        let subject = make_originate_only_neighborhood(&neighbor);
+
+        let root = subject.neighborhood_database.root();
+        assert_eq!(
+            root.node_addr_opt(),
+            Some(NodeAddr::new(&sentinel_ip_addr(), &vec![1234]))
+        );
+        assert_eq!(root.has_neighbor(neighbor.public_key()), true);
+    }
+
+    #[test]
+    fn originate_only_node_adopts_observed_ip_and_readvertises_itself() {
+        let neighbor = make_node_record(2345, true, true);
+        let mut subject = make_originate_only_neighborhood(&neighbor);
+        let version_before = subject.neighborhood_database.root().version();
+        let mut gossip = GossipBuilder::new().node(&neighbor, true).build();
+        gossip.observed_ip_opt = Some(IpAddr::from_str("3.4.5.6").unwrap());
+
+        let result = subject.adopt_observed_ip(&gossip);
+
+        assert_eq!(result, true);
+        let root = subject.neighborhood_database.root();
+        assert_eq!(
+            root.node_addr_opt(),
+            Some(NodeAddr::new(
+                &IpAddr::from_str("3.4.5.6").unwrap(),
+                &vec![1234]
+            ))
+        );
+        assert_eq!(root.version(), version_before + 1);
+    }
+
+    #[test]
+    fn node_that_knows_its_ip_ignores_observed_ip() {
+        let mut subject = make_standalone_neighborhood();
+        let mut gossip = GossipBuilder::new().build();
+        gossip.observed_ip_opt = Some(IpAddr::from_str("3.4.5.6").unwrap());
+        subject
+            .neighborhood_database
+            .set_root_ip_addr(&IpAddr::from_str("5.4.3.2").unwrap());
+
+        let result = subject.adopt_observed_ip(&gossip);
+
+        assert_eq!(result, false);
+        assert_eq!(
+            subject
+                .neighborhood_database
+                .root()
+                .node_addr_opt()
+                .unwrap()
+                .ip_addr(),
+            IpAddr::from_str("5.4.3.2").unwrap()
+        );
+    }
+
+    #[test]
+    fn unaddressed_record_from_immediate_neighbor_gets_the_address_it_sent_from() {
+        let mut subject = make_standalone_neighborhood();
+        let addressed = make_node_record(2345, true, false);
+        let unaddressed = NodeRecord::new_for_tests(
+            &PublicKey::new(&b"unaddressed"[..]),
+            Some(&NodeAddr::new(&sentinel_ip_addr(), &vec![4567, 5678])),
+            false,
+        );
+        let mut gossip = GossipBuilder::new()
+            .node(&addressed, true)
+            .node(&unaddressed, true)
+            .build();
+
+        subject.fill_in_observed_ip(&mut gossip, IpAddr::from_str("3.4.5.6").unwrap(), None);
+
+        assert_eq!(
+            gossip.node_records[0].inner.node_addr_opt,
+            addressed.node_addr_opt()
+        );
+        assert_eq!(
+            gossip.node_records[1].inner.node_addr_opt,
+            Some(NodeAddr::new(
+                &IpAddr::from_str("3.4.5.6").unwrap(),
+                &vec![4567, 5678]
+            ))
+        );
+        assert_eq!(
+            subject.observed_ips.get(unaddressed.public_key()),
+            Some(&IpAddr::from_str("3.4.5.6").unwrap())
+        );
+    }
+
+    #[test]
+    fn several_unaddressed_records_in_one_gossip_lose_their_addresses() {
+        init_test_logging();
+        let mut subject = make_standalone_neighborhood();
+        let make_unaddressed = |name: &str| {
+            NodeRecord::new_for_tests(
+                &PublicKey::new(name.as_bytes()),
+                Some(&NodeAddr::new(&sentinel_ip_addr(), &vec![4567])),
+                false,
+            )
+        };
+        let one = make_unaddressed("one");
+        let another = make_unaddressed("another");
+        let mut gossip = GossipBuilder::new()
+            .node(&one, true)
+            .node(&another, true)
+            .build();
+
+        subject.fill_in_observed_ip(&mut gossip, IpAddr::from_str("3.4.5.6").unwrap(), None);
+
+        assert_eq!(gossip.node_records[0].inner.node_addr_opt, None);
+        assert_eq!(gossip.node_records[1].inner.node_addr_opt, None);
+        assert_eq!(subject.observed_ips.is_empty(), true);
+        TestLogHandler::new().exists_log_containing(&format!(
+            "WARN: Neighborhood: Gossip from 3.4.5.6 carries an unaddressed record for {} that can't be its own: ignoring the address",
+            one.public_key().fingerprint()
+        ));
+    }
+
+    #[test]
+    fn a_sender_with_a_proven_key_gets_its_own_unaddressed_record_filled_in_and_no_other() {
+        init_test_logging();
+        let mut subject = make_standalone_neighborhood();
+        let make_unaddressed = |name: &str| {
+            NodeRecord::new_for_tests(
+                &PublicKey::new(name.as_bytes()),
+                Some(&NodeAddr::new(&sentinel_ip_addr(), &vec![4567])),
+                false,
+            )
+        };
+        let sender = make_unaddressed("sender");
+        let other = make_unaddressed("other");
+        let mut gossip = GossipBuilder::new()
+            .node(&other, true)
+            .node(&sender, true)
+            .build();
+
+        subject.fill_in_observed_ip(
+            &mut gossip,
+            IpAddr::from_str("3.4.5.6").unwrap(),
+            Some(sender.public_key()),
+        );
+
+        assert_eq!(gossip.node_records[0].inner.node_addr_opt, None);
+        assert_eq!(
+            gossip.node_records[1].inner.node_addr_opt,
+            Some(NodeAddr::new(
+                &IpAddr::from_str("3.4.5.6").unwrap(),
+                &vec![4567]
+            ))
+        );
+        assert_eq!(
+            subject.observed_ips.get(sender.public_key()),
+            Some(&IpAddr::from_str("3.4.5.6").unwrap())
+        );
+        assert_eq!(subject.observed_ips.get(other.public_key()), None);
+        TestLogHandler::new().exists_log_containing(&format!(
+            "WARN: Neighborhood: Gossip from 3.4.5.6 carries an unaddressed record for {} that can't be its own: ignoring the address",
+            other.public_key().fingerprint()
+        ));
+    }
+
+    #[test]
+    fn debuts_over_the_limit_are_taken_out_of_gossip_but_known_nodes_are_not() {
+        init_test_logging();
+        let mut subject = make_standalone_neighborhood();
+        subject.debut_limiter = DebutLimiter::with_limits(Duration::from_secs(60), 10, 2);
+        let known = make_node_record(2345, true, false);
+        subject.neighborhood_database.add_node(&known).unwrap();
+        let first = make_node_record(3456, true, false);
+        let second = make_node_record(4567, true, false);
+        let third = make_node_record(5678, true, false);
+        let mut gossip = GossipBuilder::new()
+            .node(&first, true)
+            .node(&known, true)
+            .node(&second, true)
+            .node(&third, true)
+            .build();
+        let source_ip = IpAddr::from_str("3.4.5.6").unwrap();
+        let now = Instant::now();
+
+        subject.limit_debuts(&mut gossip, source_ip, now);
+
+        let keys: Vec<&PublicKey> = gossip
+            .node_records
+            .iter()
+            .map(|gnr| &gnr.inner.public_key)
+            .collect();
+        assert_eq!(
+            keys,
+            vec![first.public_key(), known.public_key(), second.public_key()]
+        );
+        TestLogHandler::new().exists_log_containing(
+            "WARN: Neighborhood: Too many new Nodes lately: ignoring 1 of the 3 new Nodes in Gossip from 3.4.5.6",
+        );
+
+        let mut gossip = GossipBuilder::new().node(&third, true).build();
+        subject.limit_debuts(&mut gossip, source_ip, now);
+        assert_eq!(gossip.node_records.is_empty(), true);
+
+        let mut gossip = GossipBuilder::new().node(&third, true).build();
+        subject.limit_debuts(&mut gossip, IpAddr::from_str("4.5.6.7").unwrap(), now);
+        assert_eq!(gossip.node_records.len(), 1);
+    }
+
+    #[test]
+    fn observed_ip_is_only_offered_to_neighbors_we_have_connected_to() {
+        let mut subject = make_standalone_neighborhood();
+        let root_key = subject.neighborhood_database.root().public_key().clone();
+        let connected = make_node_record(2345, true, false);
+        let unconnected = make_node_record(3456, true, false);
+        subject.neighborhood_database.add_node(&connected).unwrap();
+        subject
+            .neighborhood_database
+            .add_node(&unconnected)
+            .unwrap();
+        subject
+            .neighborhood_database
+            .add_neighbor(&root_key, connected.public_key())
+            .unwrap();
+        subject.observed_ips.insert(
+            connected.public_key().clone(),
+            IpAddr::from_str("3.4.5.6").unwrap(),
+        );
+        subject.observed_ips.insert(
+            unconnected.public_key().clone(),
+            IpAddr::from_str("4.5.6.7").unwrap(),
+        );
+
+        assert_eq!(
+            subject.confirmed_observed_ip(connected.public_key()),
+            Some(IpAddr::from_str("3.4.5.6").unwrap())
+        );
+        assert_eq!(
+            subject.confirmed_observed_ip(unconnected.public_key()),
+            None
+        );
+    }
+
+    #[test]
+    fn route_validity_follows_the_youngest_record_in_the_route_within_limits() {
+        let mut subject = make_standalone_neighborhood();
+        let root_key = subject.neighborhood_database.root().public_key().clone();
+        let old_key = PublicKey::new(&b"old"[..]);
+        let middle_aged_key = PublicKey::new(&b"middle aged"[..]);
+        let young_key = PublicKey::new(&b"young"[..]);
+        let now = Instant::now();
+        subject.started = now - Duration::from_secs(3600);
+        subject
+            .record_changes
+            .insert(middle_aged_key.clone(), now - Duration::from_secs(30));
+        subject
+            .record_changes
+            .insert(young_key.clone(), now - Duration::from_secs(1));
+        let segment = |keys: Vec<&PublicKey>| RouteSegment::new(keys, Component::ProxyClient);
+        let old_route = segment(vec![&root_key, &old_key]);
+        let middle_aged_route = segment(vec![&root_key, &old_key, &middle_aged_key]);
+        let young_route = segment(vec![&root_key, &young_key, &middle_aged_key]);
+        let local_route = segment(vec![&root_key, &root_key]);
+
+        assert_eq!(
+            subject.route_validity(&[&old_route, &old_route]),
+            MAXIMUM_ROUTE_VALIDITY
+        );
+        assert_eq!(
+            subject.route_validity(&[&middle_aged_route, &old_route]),
+            Duration::from_secs(30)
+        );
+        assert_eq!(
+            subject.route_validity(&[&young_route, &old_route]),
+            MINIMUM_ROUTE_VALIDITY
+        );
+        assert_eq!(
+            subject.route_validity(&[&local_route, &local_route]),
+            MAXIMUM_ROUTE_VALIDITY
+        );
+    }
+
+    #[test]
+    fn records_are_noted_as_changed_only_when_their_versions_change() {
+        let mut subject = make_standalone_neighborhood();
+        let unchanged = make_node_record(2345, true, false);
+        let changed = make_node_record(3456, true, false);
+        let added = make_node_record(4567, true, false);
+        subject.neighborhood_database.add_node(&unchanged).unwrap();
+        subject.neighborhood_database.add_node(&changed).unwrap();
+        let gossip = GossipBuilder::new()
+            .node(&unchanged, true)
+            .node(&changed, true)
+            .node(&added, true)
+            .build();
+        let versions_before = subject.versions_of(&gossip.node_records);
+        subject
+            .neighborhood_database
+            .node_by_key_mut(changed.public_key())
+            .unwrap()
+            .increment_version();
+        subject.neighborhood_database.add_node(&added).unwrap();
+
+        subject.note_record_changes(versions_before);
+
+        assert_eq!(
+            subject.record_changes.contains_key(unchanged.public_key()),
+            false
+        );
+        assert_eq!(
+            subject.record_changes.contains_key(changed.public_key()),
+            true
+        );
+        assert_eq!(
+            subject.record_changes.contains_key(added.public_key()),
+            true
+        );
+    }
+
+    #[test]
+    fn gossip_stats_query_reports_what_the_gossip_acceptor_has_counted() {
+        let system = System::new("gossip_stats_query_reports_what_the_gossip_acceptor_has_counted");
+        let mut subject = make_standalone_neighborhood();
+        let new_node = make_node_record(2345, true, false);
+        let gossip = GossipBuilder::new().node(&new_node, true).build();
+        subject
+            .gossip_acceptor
+            .handle(&mut subject.neighborhood_database, gossip.clone());
+        subject
+            .gossip_acceptor
+            .handle(&mut subject.neighborhood_database, gossip);
+        let addr: Addr<Syn, Neighborhood> = subject.start();
+
+        let future = addr.send(GossipStatsQueryMessage {});
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+        let result = future.wait().unwrap();
+        assert_eq!(
+            result,
+            GossipAcceptanceStats {
+                gossip_messages: 2,
+                new_node_records: 1,
+                duplicate_node_records: 1,
+                ..GossipAcceptanceStats::default()
+            }
+        );
+    }
+
+    #[test]
+    fn status_query_counts_neighbors_and_known_nodes() {
+        let system = System::new("status_query_counts_neighbors_and_known_nodes");
+        let mut subject = make_standalone_neighborhood();
+        let root_key = subject.neighborhood_database.root().public_key().clone();
+        let neighbor = make_node_record(2345, true, false);
+        let stranger = make_node_record(3456, true, false);
+        subject.neighborhood_database.add_node(&neighbor).unwrap();
+        subject.neighborhood_database.add_node(&stranger).unwrap();
+        subject
+            .neighborhood_database
+            .add_neighbor(&root_key, neighbor.public_key())
+            .unwrap();
+        let addr: Addr<Syn, Neighborhood> = subject.start();
+
+        let future = addr.send(NeighborhoodStatusQueryMessage {});
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+        assert_eq!(
+            future.wait().unwrap(),
+            NeighborhoodStatus {
+                neighbors: 1,
+                known_nodes: 3,
+            }
+        );
+    }
+
+    /*
+            +---Q---+
+            |       |
+            P       S
+            |       |
+            +---R---+
+    */
+    #[test]
+    fn topology_query_answers_with_a_filtered_page_of_the_database() {
+        let system = System::new("topology_query_answers_with_a_filtered_page_of_the_database");
+        let mut subject = make_standalone_neighborhood();
+        let german = make_node_record(2345, true, false);
+        let american = make_node_record(3456, true, false);
+        subject.neighborhood_database.add_node(&german).unwrap();
+        subject.neighborhood_database.add_node(&american).unwrap();
+        subject.exclude_from_routes(RouteExclusionConfig {
+            database: AsnDatabase::from_tsv(
+                "2.3.4.0\t2.3.4.255\t3320\tDE\tDTAG\n3.4.5.0\t3.4.5.255\t7922\tUS\tCOMCAST\n",
+            )
+            .unwrap(),
+            exclusions: vec![],
+        });
+        let addr: Addr<Syn, Neighborhood> = subject.start();
+        let unfiltered_query = TopologyQueryMessage {
+            page_size: 1,
+            ..TopologyQueryMessage::from_json("{}").unwrap()
+        };
+        let german_query = TopologyQueryMessage {
+            filter: TopologyFilter {
+                country: Some(String::from("de")),
+                ..TopologyFilter::default()
+            },
+            ..TopologyQueryMessage::from_json("{}").unwrap()
+        };
+
+        let unfiltered_future = addr.send(unfiltered_query);
+        let german_future = addr.send(german_query);
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+        let unfiltered_page = unfiltered_future.wait().unwrap();
+        assert_eq!(unfiltered_page.nodes.len(), 1);
+        assert_eq!(
+            unfiltered_page.next_cursor,
+            Some(unfiltered_page.nodes[0].public_key.clone())
+        );
+        let german_page = german_future.wait().unwrap();
+        assert_eq!(german_page.nodes.len(), 1);
+        assert_eq!(
+            german_page.nodes[0].public_key,
+            format!("{}", german.public_key())
+        );
+        assert_eq!(german_page.nodes[0].country, Some(String::from("DE")));
+        assert_eq!(german_page.next_cursor, None);
+    }
+
+    #[test]
+    fn make_route_segment_steers_around_an_overloaded_relay() {
+        let check = |overloaded_relay: u16, expected_relay: u16| {
+            let mut subject = make_standalone_neighborhood();
+            let p = &subject.neighborhood_database.root().clone();
+            let mut q = make_node_record(3456, false, false);
+            let mut r = make_node_record(4567, false, false);
+            let s = &make_node_record(5678, false, false);
+            let overloaded = LoadAdvertisement {
+                capacity_class: CapacityClass::Medium,
+                load_factor: 95,
+            };
+            if overloaded_relay == 3456 {
+                q.set_load(overloaded);
+            } else {
+                r.set_load(overloaded);
+            }
+            {
+                let db = &mut subject.neighborhood_database;
+                db.add_node(&q).unwrap();
+                db.add_node(&r).unwrap();
+                db.add_node(s).unwrap();
+                let mut dual_edge = |a: &NodeRecord, b: &NodeRecord| dual_edge_func(db, a, b);
+                dual_edge(p, &q);
+                dual_edge(p, &r);
+                dual_edge(&q, s);
+                dual_edge(&r, s);
+            }
+
+            let result = subject
+                .make_route_segment(
+                    p.public_key(),
+                    Some(s.public_key()),
+                    TargetType::Standard,
+                    2,
+                    Component::ProxyClient,
+                    &[],
+                    None,
+                )
+                .unwrap();
+
+            let expected_relay = make_node_record(expected_relay, false, false);
+            assert_eq!(
+                result.keys,
+                vec![
+                    p.public_key().clone(),
+                    expected_relay.public_key().clone(),
+                    s.public_key().clone()
+                ]
+            );
+        };
+
+        check(3456, 4567);
+        check(4567, 3456);
+    }
+
+    #[test]
+    fn make_route_segment_steers_around_a_relay_whose_routes_have_failed() {
+        let check = |failed_relay: u16, expected_relay: u16| {
+            let mut subject = make_standalone_neighborhood();
+            let p = &subject.neighborhood_database.root().clone();
+            let q = &make_node_record(3456, false, false);
+            let r = &make_node_record(4567, false, false);
+            let s = &make_node_record(5678, false, false);
+            {
+                let db = &mut subject.neighborhood_database;
+                db.add_node(q).unwrap();
+                db.add_node(r).unwrap();
+                db.add_node(s).unwrap();
+                let mut dual_edge = |a: &NodeRecord, b: &NodeRecord| dual_edge_func(db, a, b);
+                dual_edge(p, q);
+                dual_edge(p, r);
+                dual_edge(q, s);
+                dual_edge(r, s);
+            }
+            subject.route_scores.record(
+                &[make_node_record(failed_relay, false, false)
+                    .public_key()
+                    .clone()],
+                RouteOutcome::Failed,
+                Instant::now(),
+            );
+
+            let result = subject
+                .make_route_segment(
+                    p.public_key(),
+                    Some(s.public_key()),
+                    TargetType::Standard,
+                    2,
+                    Component::ProxyClient,
+                    &[],
+                    None,
+                )
+                .unwrap();
+
+            let expected_relay = make_node_record(expected_relay, false, false);
+            assert_eq!(
+                result.keys,
+                vec![
+                    p.public_key().clone(),
+                    expected_relay.public_key().clone(),
+                    s.public_key().clone()
+                ]
+            );
+        };
+
+        check(3456, 4567);
+        check(4567, 3456);
+    }
+
+    #[test]
+    fn route_query_response_is_scored_by_route_feedback() {
+        let cryptde = cryptde();
+        let system = System::new("route_query_response_is_scored_by_route_feedback");
+        let mut subject = Neighborhood::new(
+            cryptde,
+            NeighborhoodConfig {
+                neighbor_configs: vec![],
+                is_bootstrap_node: false,
+                local_ip_addr: sentinel_ip_addr(),
+                clandestine_port_list: vec![],
+                earning_wallet: Wallet::new("earning"),
+                consuming_wallet: Some(Wallet::new("consuming")),
+            },
+        );
+        let p = &subject.neighborhood_database.root().clone();
+        let q = &make_node_record(3456, true, false);
+        let r = &make_node_record(4567, false, false);
+        {
+            let db = &mut subject.neighborhood_database;
+            db.add_node(q).unwrap();
+            db.add_node(r).unwrap();
+            let mut dual_edge = |a: &NodeRecord, b: &NodeRecord| dual_edge_func(db, a, b);
+            dual_edge(p, q);
+            dual_edge(q, r);
+        }
+        let addr: Addr<Syn, Neighborhood> = subject.start();
+        addr.try_send(RouteFeedbackMessage {
+            keys: vec![q.public_key().clone(), r.public_key().clone()],
+            outcome: RouteOutcome::Responded(Duration::from_millis(250)),
+        })
+        .unwrap();
+
+        let route_future = addr.send(RouteQueryMessage::data_indefinite_route_request(2));
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+        let result = route_future.wait().unwrap().unwrap();
+        assert_eq!(result.score, 80);
+    }
+
+    /*
+            P---Q---S
+            |
+            R---T (offers exit TLS)
+    */
+    #[test]
+    fn make_route_segment_for_exit_tls_ends_only_at_a_node_that_offers_it() {
+        let mut subject = make_standalone_neighborhood();
+        let p = &subject.neighborhood_database.root().clone();
+        let q = &make_node_record(3456, false, false);
+        let r = &make_node_record(4567, false, false);
+        let s = &make_node_record(5678, false, false);
+        let mut t = make_node_record(6789, false, false);
+        t.set_offers_exit_tls(true);
+        {
+            let db = &mut subject.neighborhood_database;
+            db.add_node(q).unwrap();
+            db.add_node(r).unwrap();
+            db.add_node(s).unwrap();
+            db.add_node(&t).unwrap();
+            let mut dual_edge = |a: &NodeRecord, b: &NodeRecord| dual_edge_func(db, a, b);
+            dual_edge(p, q);
+            dual_edge(p, r);
+            dual_edge(q, s);
+            dual_edge(r, &t);
+        }
+
+        let result = subject
+            .make_route_segment(
+                p.public_key(),
+                None,
+                TargetType::ExitTls,
+                2,
+                Component::ProxyClient,
+                &[],
+                None,
+            )
+            .unwrap();
+
+        assert_eq!(
+            result.keys,
+            vec![
+                p.public_key().clone(),
+                r.public_key().clone(),
+                t.public_key().clone()
+            ]
+        );
+    }
+
+    /*
+            +---Q (in the root's own AS)---+
+            |                              |
+            P (1.2.3.4)                    S
+            |                              |
+            +---R (in AS 3320)-------------+
+    */
+    #[test]
+    fn make_route_segment_steers_around_relays_in_excluded_systems() {
+        let check = |exclusion: RouteExclusion, expected_relay: u16| {
+            let mut subject = make_standalone_neighborhood();
+            subject
+                .neighborhood_database
+                .set_root_ip_addr(&IpAddr::from_str("1.2.3.4").unwrap());
+            subject.exclude_from_routes(RouteExclusionConfig {
+                database: AsnDatabase::from_tsv(
+                    "1.2.3.0\t1.2.3.255\t7922\tUS\tCOMCAST\n\
+                     3.4.5.0\t3.4.5.255\t7922\tUS\tCOMCAST\n\
+                     4.5.6.0\t4.5.6.255\t3320\tDE\tDTAG\n",
+                )
+                .unwrap(),
+                exclusions: vec![exclusion],
+            });
+            let p = &subject.neighborhood_database.root().clone();
+            let q = &make_node_record(3456, true, false);
+            let r = &make_node_record(4567, true, false);
+            let s = &make_node_record(5678, true, false);
+            {
+                let db = &mut subject.neighborhood_database;
+                db.add_node(q).unwrap();
+                db.add_node(r).unwrap();
+                db.add_node(s).unwrap();
+                let mut dual_edge = |a: &NodeRecord, b: &NodeRecord| dual_edge_func(db, a, b);
+                dual_edge(p, q);
+                dual_edge(p, r);
+                dual_edge(q, s);
+                dual_edge(r, s);
+            }
+
+            let result = subject
+                .make_route_segment(
+                    p.public_key(),
+                    Some(s.public_key()),
+                    TargetType::Standard,
+                    2,
+                    Component::ProxyClient,
+                    &[],
+                    None,
+                )
+                .unwrap();
+
+            let expected_relay = make_node_record(expected_relay, true, false);
+            assert_eq!(
+                result.keys,
+                vec![
+                    p.public_key().clone(),
+                    expected_relay.public_key().clone(),
+                    s.public_key().clone()
+                ]
+            );
+        };
+
+        check(RouteExclusion::OwnAsn, 4567);
+        check(RouteExclusion::Asn(3320), 3456);
+        check(RouteExclusion::Country(String::from("DE")), 3456);
+    }
+
+    /*
+            P---Q (in DE)---S (in DE)
+            |
+            R---T (in DE)
+    */
+    #[test]
+    fn make_route_segment_finds_no_exit_when_every_exit_is_in_an_excluded_country() {
+        let mut subject = make_standalone_neighborhood();
+        subject.exclude_from_routes(RouteExclusionConfig {
+            database: AsnDatabase::from_tsv(
+                "3.4.5.0\t3.4.5.255\t3320\tDE\tDTAG\n\
+                 5.6.7.0\t5.6.7.255\t3320\tDE\tDTAG\n\
+                 6.7.8.0\t6.7.8.255\t3320\tDE\tDTAG\n",
+            )
+            .unwrap(),
+            exclusions: vec![RouteExclusion::ExitCountry(String::from("DE"))],
+        });
+        let p = &subject.neighborhood_database.root().clone();
+        let q = &make_node_record(3456, true, false);
+        let r = &make_node_record(4567, true, false);
+        let s = &make_node_record(5678, true, false);
+        let t = &make_node_record(6789, true, false);
+        {
+            let db = &mut subject.neighborhood_database;
+            db.add_node(q).unwrap();
+            db.add_node(r).unwrap();
+            db.add_node(s).unwrap();
+            db.add_node(t).unwrap();
+            let mut dual_edge = |a: &NodeRecord, b: &NodeRecord| dual_edge_func(db, a, b);
+            dual_edge(p, q);
+            dual_edge(p, r);
+            dual_edge(q, s);
+            dual_edge(r, t);
+        }
+
+        let one_hop_result = subject
+            .make_route_segment(
+                p.public_key(),
+                None,
+                TargetType::Standard,
+                1,
+                Component::ProxyClient,
+                &[],
+                None,
+            )
+            .unwrap();
+        let two_hop_result = subject.make_route_segment(
+            p.public_key(),
+            None,
+            TargetType::Standard,
+            2,
+            Component::ProxyClient,
+            &[],
+            None,
+        );
+
+        assert_eq!(
+            one_hop_result.keys,
+            vec![p.public_key().clone(), r.public_key().clone()]
+        );
+        assert_eq!(two_hop_result.is_err(), true);
+    }
+
+    /*
+            P---Q
+            |
+            R
+    */
+    #[test]
+    fn make_route_segment_passes_over_avoided_exits_unless_there_is_no_other_choice() {
+        let mut subject = make_standalone_neighborhood();
+        let p = &subject.neighborhood_database.root().clone();
+        let q = &make_node_record(3456, true, false);
+        let r = &make_node_record(4567, true, false);
+        {
+            let db = &mut subject.neighborhood_database;
+            db.add_node(q).unwrap();
+            db.add_node(r).unwrap();
+            let mut dual_edge = |a: &NodeRecord, b: &NodeRecord| dual_edge_func(db, a, b);
+            dual_edge(p, q);
+            dual_edge(p, r);
+        }
+        let segment = |avoided_exit_keys: &[PublicKey]| {
+            subject
+                .make_route_segment(
+                    p.public_key(),
+                    None,
+                    TargetType::Standard,
+                    1,
+                    Component::ProxyClient,
+                    avoided_exit_keys,
+                    None,
+                )
+                .unwrap()
+                .keys
+        };
+
+        let avoiding_q = segment(&[q.public_key().clone()]);
+        let avoiding_r = segment(&[r.public_key().clone()]);
+        let avoiding_both = segment(&[q.public_key().clone(), r.public_key().clone()]);
+
+        assert_eq!(
+            avoiding_q,
+            vec![p.public_key().clone(), r.public_key().clone()]
+        );
+        assert_eq!(
+            avoiding_r,
+            vec![p.public_key().clone(), q.public_key().clone()]
+        );
+        assert_eq!(avoiding_both.len(), 2);
+    }
+
+    /*
+            P---Q---S
+            |       |
+            R-------+
+    */
+    #[test]
+    fn make_route_segment_steers_clear_of_troubled_nodes_unless_there_is_no_other_choice() {
+        let mut subject = make_standalone_neighborhood();
+        let p = &subject.neighborhood_database.root().clone();
+        let q = &make_node_record(3456, true, false);
+        let r = &make_node_record(4567, true, false);
+        let s = &make_node_record(5678, true, false);
+        {
+            let db = &mut subject.neighborhood_database;
+            db.add_node(q).unwrap();
+            db.add_node(r).unwrap();
+            db.add_node(s).unwrap();
+            let mut dual_edge = |a: &NodeRecord, b: &NodeRecord| dual_edge_func(db, a, b);
+            dual_edge(p, q);
+            dual_edge(p, r);
+            dual_edge(q, s);
+            dual_edge(r, s);
+        }
+        let segment = |subject: &Neighborhood| {
+            subject
+                .make_route_segment(
+                    p.public_key(),
+                    Some(s.public_key()),
+                    TargetType::Standard,
+                    2,
+                    Component::ProxyClient,
+                    &[],
+                    None,
+                )
+                .unwrap()
+                .keys
+        };
+        let now = Instant::now();
+
+        subject
+            .node_problems
+            .record(q.public_key(), NodeProblem::StreamDied, now);
+        let around_q = segment(&subject);
+        subject
+            .node_problems
+            .record(r.public_key(), NodeProblem::StreamDied, now);
+        let around_both = segment(&subject);
+
+        assert_eq!(
+            around_q,
+            vec![
+                p.public_key().clone(),
+                r.public_key().clone(),
+                s.public_key().clone()
+            ]
+        );
+        assert_eq!(around_both.len(), 3);
+    }
+
+    /*
+            P---Q
+            |
+            R
+    */
+    #[test]
+    fn route_queries_avoid_an_exit_after_a_node_problem_report() {
+        let system = System::new("route_queries_avoid_an_exit_after_a_node_problem_report");
+        let mut subject = make_standalone_neighborhood();
+        let p = &subject.neighborhood_database.root().clone();
+        let q = &make_node_record(3456, true, false);
+        let r = &make_node_record(4567, true, false);
+        {
+            let db = &mut subject.neighborhood_database;
+            db.add_node(q).unwrap();
+            db.add_node(r).unwrap();
+            let mut dual_edge = |a: &NodeRecord, b: &NodeRecord| dual_edge_func(db, a, b);
+            dual_edge(p, q);
+            dual_edge(p, r);
+        }
+        let addr: Addr<Syn, Neighborhood> = subject.start();
+        addr.try_send(NodeProblemReport {
+            public_key: q.public_key().clone(),
+            problem: NodeProblem::TargetUnreachable,
+        })
+        .unwrap();
+
+        let route_future = addr.send(RouteQueryMessage::data_indefinite_route_request(1));
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+        let result = route_future.wait().unwrap().unwrap();
+        let exit_keys: Vec<PublicKey> = match result.expected_services {
+            ExpectedServices::RoundTrip(over, _, _) => over
+                .into_iter()
+                .filter_map(|service| match service {
+                    ExpectedService::Exit(public_key, _, _) => Some(public_key),
+                    _ => None,
+                })
+                .collect(),
+            _ => panic!("Expected a round trip"),
+        };
+        assert_eq!(exit_keys, vec![r.public_key().clone()]);
+    }
+
+    /*
+            P---Q
+            |
+            R
+    */
+    #[test]
+    fn make_random_route_segment_does_not_always_pick_the_same_route() {
+        let mut subject = make_standalone_neighborhood();
+        let p = &subject.neighborhood_database.root().clone();
+        let q = &make_node_record(3456, true, false);
+        let r = &make_node_record(4567, true, false);
+        {
+            let db = &mut subject.neighborhood_database;
+            db.add_node(q).unwrap();
+            db.add_node(r).unwrap();
+            let mut dual_edge = |a: &NodeRecord, b: &NodeRecord| dual_edge_func(db, a, b);
+            dual_edge(p, q);
+            dual_edge(p, r);
+        }
+
+        let exits: HashSet<PublicKey> = (0..50)
+            .map(|_| {
+                let segment = subject
+                    .make_random_route_segment(p.public_key(), 1, Component::Composite)
+                    .unwrap();
+                assert_eq!(segment.recipient, Component::Composite);
+                segment.keys.last().unwrap().clone()
+            })
+            .collect();
+
+        assert_eq!(
+            exits,
+            vec![q.public_key().clone(), r.public_key().clone()]
+                .into_iter()
+                .collect()
+        );
+    }
+
+    /*
+            P---Q
+            |
+            R (DE)
+    */
+    #[test]
+    fn make_route_segment_only_exits_in_the_requested_location() {
+        let mut subject = make_standalone_neighborhood();
+        let p = &subject.neighborhood_database.root().clone();
+        let q = &make_node_record(3456, true, false);
+        let r = &mut make_node_record(4567, true, false);
+        r.set_country_opt(Some(String::from("DE")));
+        {
+            let db = &mut subject.neighborhood_database;
+            db.add_node(q).unwrap();
+            db.add_node(r).unwrap();
+            let mut dual_edge = |a: &NodeRecord, b: &NodeRecord| dual_edge_func(db, a, b);
+            dual_edge(p, q);
+            dual_edge(p, r);
+        }
+        let segment = |exit_location_opt: Option<&str>| {
+            subject.make_route_segment(
+                p.public_key(),
+                None,
+                TargetType::Standard,
+                1,
+                Component::ProxyClient,
+                &[],
+                exit_location_opt,
+            )
+        };
+
+        let in_germany = segment(Some("de"));
+        let in_france = segment(Some("FR"));
+
+        assert_eq!(
+            in_germany.unwrap().keys,
+            vec![p.public_key().clone(), r.public_key().clone()]
+        );
+        assert_eq!(
+            in_france.err(),
+            Some(format!(
+                "Couldn't find any routes: at least 1-hop from {} to ProxyClient at Standard exiting in FR",
+                p.public_key()
+            ))
+        );
+    }
+
+    #[test]
+    fn round_trip_route_is_refused_when_no_known_exit_is_in_the_requested_location() {
+        let mut subject = make_standalone_neighborhood();
+        let p = &subject.neighborhood_database.root().clone();
+        let q = &mut make_node_record(3456, true, false);
+        q.set_country_opt(Some(String::from("DE")));
+        {
+            let db = &mut subject.neighborhood_database;
+            db.add_node(q).unwrap();
+            let mut dual_edge = |a: &NodeRecord, b: &NodeRecord| dual_edge_func(db, a, b);
+            dual_edge(p, q);
+        }
+
+        let result = subject.make_round_trip_route(
+            RouteQueryMessage::data_indefinite_route_request(1)
+                .exiting_in(Some(String::from("FR"))),
+        );
+
+        assert_eq!(
+            result,
+            Err(RouteRefusal::NoExitInLocation(String::from("FR")))
+        );
+    }
+
+    #[test]
+    fn zero_hop_route_exits_in_the_requested_location_only_if_this_node_is_there() {
+        let system = System::new(
+            "zero_hop_route_exits_in_the_requested_location_only_if_this_node_is_there",
+        );
+        let mut subject = make_standalone_neighborhood();
+        subject.advertise_country(String::from("DE"));
+        let addr: Addr<Syn, Neighborhood> = subject.start();
+        let sub: Recipient<Syn, RouteQueryMessage> = addr.recipient::<RouteQueryMessage>();
+
+        let in_germany = sub.send(
+            RouteQueryMessage::data_indefinite_route_request(0)
+                .exiting_in(Some(String::from("de"))),
+        );
+        let in_france = sub.send(
+            RouteQueryMessage::data_indefinite_route_request(0)
+                .exiting_in(Some(String::from("FR"))),
+        );
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+        assert_eq!(in_germany.wait().unwrap().is_ok(), true);
+        assert_eq!(
+            in_france.wait().unwrap(),
+            Err(RouteRefusal::NoExitInLocation(String::from("FR")))
+        );
+    }
+
+    #[test]
+    fn neighborhood_rereads_its_geoip_database_when_told_to_reload() {
+        init_test_logging();
+        let directory = PathBuf::from(
+            "generated/test/neighborhood/neighborhood_rereads_its_geoip_database_when_told_to_reload",
+        );
+        fs::remove_dir_all(&directory).is_ok();
+        fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("ip2asn.tsv");
+        let tsv = "3.4.5.0\t3.4.5.255\t3320\tDE\tDTAG\n";
+        fs::write(&path, tsv).unwrap();
+        let mut subject = make_standalone_neighborhood();
+        subject.exclude_from_routes(RouteExclusionConfig {
+            database: AsnDatabase::from_tsv("1.2.3.0\t1.2.3.255\t7922\tUS\tCOMCAST\n").unwrap(),
+            exclusions: vec![RouteExclusion::Country(String::from("DE"))],
+        });
+        subject.reload_geoip_database_from(&path);
+
+        subject.reload_geoip_database();
+
+        assert_eq!(
+            subject.route_exclusions.as_ref().unwrap().config(),
+            &RouteExclusionConfig {
+                database: AsnDatabase::from_tsv(tsv).unwrap(),
+                exclusions: vec![RouteExclusion::Country(String::from("DE"))],
+            }
+        );
+        TestLogHandler::new().exists_log_containing(&format!(
+            "INFO: Neighborhood: Reloaded GeoIP database from {:?}",
+            path
+        ));
+    }
+
+    #[test]
+    fn neighborhood_keeps_its_geoip_database_if_the_reloaded_one_is_missing() {
+        init_test_logging();
+        let path = PathBuf::from(
+            "generated/test/neighborhood/neighborhood_keeps_its_geoip_database_if_the_reloaded_one_is_missing/nonexistent.tsv",
+        );
+        let config = RouteExclusionConfig {
+            database: AsnDatabase::from_tsv("1.2.3.0\t1.2.3.255\t7922\tUS\tCOMCAST\n").unwrap(),
+            exclusions: vec![RouteExclusion::OwnAsn],
+        };
+        let mut subject = make_standalone_neighborhood();
+        subject.exclude_from_routes(config.clone());
+        subject.reload_geoip_database_from(&path);
+
+        subject.reload_geoip_database();
+
+        assert_eq!(subject.route_exclusions.as_ref().unwrap().config(), &config);
+        TestLogHandler::new().exists_log_containing("keeping the GeoIP database already in use");
+    }
+
+    #[test]
+    fn bound_neighborhood_restores_saved_records_it_does_not_already_know() {
+        init_test_logging();
+        let data_directory = PathBuf::from(
+            "generated/test/neighborhood/bound_neighborhood_restores_saved_records_it_does_not_already_know",
+        );
+        fs::remove_dir_all(&data_directory).is_ok();
+        let neighbor = make_node_record(1234, true, false);
+        let mut subject = make_originate_only_neighborhood(&neighbor);
+        let root = subject.neighborhood_database.root().clone();
+        let mut saved_neighbor = neighbor.clone();
+        saved_neighbor.set_version(5);
+        saved_neighbor.sign(&CryptDENull::from(saved_neighbor.public_key()));
+        let stranger = make_node_record(2345, true, false);
+        let initialize_parameters_arc = Arc::new(Mutex::new(vec![]));
+        let age_out_parameters_arc = Arc::new(Mutex::new(vec![]));
+        let daos = Daos {
+            payable: Box::new(PayableDaoMock::new()),
+            receivable: Box::new(ReceivableDaoMock::new()),
+            config: Box::new(ConfigDaoMock::new()),
+            node_record: Box::new(
+                NodeRecordDaoMock::new()
+                    .age_out_parameters(age_out_parameters_arc.clone())
+                    .load_all_result(vec![root, saved_neighbor, stranger.clone()]),
+            ),
+            banned_node: Box::new(BannedNodeDaoMock::new()),
+            maintenance: Box::new(MaintenanceDaoMock::new()),
+            journal: Box::new(JournalDaoMock::new()),
+        };
+        subject.db_initializer = Box::new(
+            DbInitializerMock::new()
+                .initialize_parameters(initialize_parameters_arc.clone())
+                .initialize_result(Ok(daos)),
+        );
+        subject.persist_node_records(NodeRecordPersistenceConfig {
+            data_directory: data_directory.clone(),
+            retention: Duration::from_secs(3600),
+        });
+        let now = SystemTime::now();
+
+        subject.restore_node_records(now);
+
+        assert_eq!(data_directory.is_dir(), true);
+        assert_eq!(
+            *initialize_parameters_arc.lock().unwrap(),
+            vec![data_directory]
+        );
+        assert_eq!(
+            *age_out_parameters_arc.lock().unwrap(),
+            vec![now - Duration::from_secs(3600)]
+        );
+        assert_eq!(
+            subject
+                .neighborhood_database
+                .node_by_key(stranger.public_key()),
+            Some(&stranger)
+        );
+        assert_eq!(
+            subject
+                .neighborhood_database
+                .node_by_key(neighbor.public_key())
+                .unwrap()
+                .version(),
+            0
+        );
+        assert_eq!(subject.neighborhood_database.keys().len(), 3);
+        TestLogHandler::new().exists_log_containing(
+            "INFO: Neighborhood: Restored 1 saved Node records; 0 had not been gossiped about for too long",
+        );
+    }
+
+    #[test]
+    fn only_signed_records_of_other_nodes_named_in_gossip_are_saved() {
+        let neighbor = make_node_record(1234, true, false);
+        let mut subject = make_originate_only_neighborhood(&neighbor);
+        let known = make_node_record(2345, true, false);
+        subject.neighborhood_database.add_node(&known).unwrap();
+        let save_parameters_arc = Arc::new(Mutex::new(vec![]));
+        subject.node_record_dao = Some(Box::new(
+            NodeRecordDaoMock::new().save_parameters(save_parameters_arc.clone()),
+        ));
+        let root = subject.neighborhood_database.root().clone();
+        let unknown = make_node_record(3456, true, false);
+        let gossip = GossipBuilder::new()
+            .node(&root, true)
+            .node(&neighbor, true)
+            .node(&known, true)
+            .node(&unknown, true)
+            .build();
+        let now = SystemTime::now();
+
+        subject.save_gossiped_records(&gossip.node_records, now);
+
+        assert_eq!(*save_parameters_arc.lock().unwrap(), vec![(known, now)]);
+    }
+
+    #[test]
+    fn neighbor_is_banned_and_disconnected_once_its_misbehavior_reaches_the_threshold() {
+        let system = System::new(
+            "neighbor_is_banned_and_disconnected_once_its_misbehavior_reaches_the_threshold",
+        );
+        let mut subject = make_standalone_neighborhood();
+        let p = &subject.neighborhood_database.root().clone();
+        let q = &make_node_record(3456, true, false);
+        let r = &make_node_record(4567, false, false);
+        {
+            let db = &mut subject.neighborhood_database;
+            db.add_node(q).unwrap();
+            db.add_node(r).unwrap();
+            let mut dual_edge = |a: &NodeRecord, b: &NodeRecord| dual_edge_func(db, a, b);
+            dual_edge(p, q);
+            dual_edge(q, r);
+        }
+        let ban_parameters_arc = Arc::new(Mutex::new(vec![]));
+        subject.reputation = Reputation::new(2);
+        subject.banned_node_dao = Some(Box::new(
+            BannedNodeDaoMock::new().ban_parameters(ban_parameters_arc.clone()),
+        ));
+        let addr: Addr<Syn, Neighborhood> = subject.start();
+        let misbehavior = NeighborMisbehaviorMessage {
+            public_key: q.public_key().clone(),
+            misbehavior: Misbehavior::UndeliverablePackage,
+        };
+
+        addr.try_send(misbehavior.clone()).unwrap();
+        let route_before = addr.send(RouteQueryMessage::data_indefinite_route_request(2));
+        addr.try_send(misbehavior).unwrap();
+        let route_after = addr.send(RouteQueryMessage::data_indefinite_route_request(2));
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+        assert_eq!(route_before.wait().unwrap().is_ok(), true);
+        assert_eq!(route_after.wait().unwrap().is_err(), true);
+        let ban_parameters = ban_parameters_arc.lock().unwrap();
+        assert_eq!(ban_parameters.len(), 1);
+        assert_eq!(&ban_parameters[0].0, q.public_key());
+    }
+
+    #[test]
+    fn bound_neighborhood_restores_its_ban_list() {
+        init_test_logging();
+        let data_directory =
+            PathBuf::from("generated/test/neighborhood/bound_neighborhood_restores_its_ban_list");
+        fs::remove_dir_all(&data_directory).is_ok();
+        let mut subject = make_standalone_neighborhood();
+        let banned = PublicKey::new(b"banned");
+        let initialize_parameters_arc = Arc::new(Mutex::new(vec![]));
+        let daos = Daos {
+            payable: Box::new(PayableDaoMock::new()),
+            receivable: Box::new(ReceivableDaoMock::new()),
+            config: Box::new(ConfigDaoMock::new()),
+            node_record: Box::new(NodeRecordDaoMock::new()),
+            banned_node: Box::new(BannedNodeDaoMock::new().load_all_result(vec![banned.clone()])),
+            maintenance: Box::new(MaintenanceDaoMock::new()),
+            journal: Box::new(JournalDaoMock::new()),
+        };
+        subject.db_initializer = Box::new(
+            DbInitializerMock::new()
+                .initialize_parameters(initialize_parameters_arc.clone())
+                .initialize_result(Ok(daos)),
+        );
+        subject.ban_misbehaving_neighbors(NeighborBanConfig {
+            data_directory: data_directory.clone(),
+            threshold: 20,
+        });
+
+        subject.restore_ban_list();
+
+        assert_eq!(data_directory.is_dir(), true);
+        assert_eq!(
+            *initialize_parameters_arc.lock().unwrap(),
+            vec![data_directory]
+        );
+        assert_eq!(subject.reputation.is_banned(&banned), true);
+        assert_eq!(subject.banned_node_dao.is_some(), true);
+        TestLogHandler::new().exists_log_containing(
+            "INFO: Neighborhood: 1 Nodes are still banned from earlier runs",
+        );
+    }
+
+    #[test]
+    fn records_of_banned_nodes_are_dropped_from_gossip() {
+        let neighbor = make_node_record(1234, true, false);
+        let mut subject = make_originate_only_neighborhood(&neighbor);
+        let banned = make_node_record(2345, true, false);
+        subject.reputation.ban(banned.public_key());
+        let mut gossip = GossipBuilder::new()
+            .node(&neighbor, true)
+            .node(&banned, true)
+            .build();
+
+        subject.ignore_banned_nodes(&mut gossip);
+
+        assert_eq!(
+            gossip
+                .node_records
+                .iter()
+                .map(|gnr| gnr.inner.public_key.clone())
+                .collect::<Vec<PublicKey>>(),
+            vec![neighbor.public_key().clone()]
+        );
+    }
+
+    #[test]
+    fn only_the_senders_own_record_is_held_to_its_signature() {
+        let sender_cryptde = CryptDEReal::new();
+        let relayed_cryptde = CryptDEReal::new();
+        let mut subject = make_standalone_neighborhood();
+        subject.cryptde = Box::leak(Box::new(CryptDEReal::new()));
+        let make_signed_record = |cryptde: &CryptDEReal, port: u16| {
+            let mut record = NodeRecord::new_for_tests(
+                &cryptde.public_key(),
+                Some(&NodeAddr::new(
+                    &IpAddr::from_str("1.2.3.4").unwrap(),
+                    &vec![port],
+                )),
+                false,
+            );
+            record.sign(cryptde);
+            record
+        };
+        let sender = make_signed_record(&sender_cryptde, 1234);
+        let mut relayed = make_signed_record(&relayed_cryptde, 2345);
+        relayed.set_version(5);
+        let mut gossip = GossipBuilder::new()
+            .node(&sender, true)
+            .node(&relayed, true)
+            .build();
+
+        assert_eq!(
+            subject.sender_record_is_signed(&gossip, sender.public_key()),
+            true
+        );
+
+        gossip.node_records[0].inner.version += 1;
+
+        assert_eq!(
+            subject.sender_record_is_signed(&gossip, sender.public_key()),
+            false
+        );
+        assert_eq!(
+            subject.sender_record_is_signed(&gossip, &PublicKey::new(b"absent")),
+            true
+        );
+    }
+
+    #[test]
+    fn make_route_segment_does_not_route_through_banned_nodes() {
+        let mut subject = make_standalone_neighborhood();
+        let p = &subject.neighborhood_database.root().clone();
+        let q = &make_node_record(3456, false, false);
+        let r = &make_node_record(4567, false, false);
+        let s = &make_node_record(5678, false, false);
+        {
+            let db = &mut subject.neighborhood_database;
+            db.add_node(q).unwrap();
+            db.add_node(r).unwrap();
+            db.add_node(s).unwrap();
+            let mut dual_edge = |a: &NodeRecord, b: &NodeRecord| dual_edge_func(db, a, b);
+            dual_edge(p, q);
+            dual_edge(p, r);
+            dual_edge(q, s);
+            dual_edge(r, s);
+        }
+        subject.reputation.ban(q.public_key());
+
+        let result = subject
+            .make_route_segment(
+                p.public_key(),
+                Some(s.public_key()),
+                TargetType::Standard,
+                2,
+                Component::ProxyClient,
+                &[],
+                None,
+            )
+            .unwrap();
+
+        assert_eq!(
+            result.keys,
+            vec![
+                p.public_key().clone(),
+                r.public_key().clone(),
+                s.public_key().clone()
+            ]
+        );
+    }
+
+    #[test]
+    fn make_route_segment_uses_an_overloaded_relay_when_there_is_no_way_around_it() {
+        let mut subject = make_standalone_neighborhood();
+        let p = &subject.neighborhood_database.root().clone();
+        let mut q = make_node_record(3456, false, false);
+        let s = &make_node_record(5678, false, false);
+        q.set_load(LoadAdvertisement {
+            capacity_class: CapacityClass::Low,
+            load_factor: 100,
+        });
+        {
+            let db = &mut subject.neighborhood_database;
+            db.add_node(&q).unwrap();
+            db.add_node(s).unwrap();
+            let mut dual_edge = |a: &NodeRecord, b: &NodeRecord| dual_edge_func(db, a, b);
+            dual_edge(p, &q);
+            dual_edge(&q, s);
+        }
+
+        let result = subject
+            .make_route_segment(
+                p.public_key(),
+                Some(s.public_key()),
+                TargetType::Standard,
+                2,
+                Component::ProxyClient,
+                &[],
+                None,
+            )
+            .unwrap();
+
+        assert_eq!(
+            result.keys,
+            vec![
+                p.public_key().clone(),
+                q.public_key().clone(),
+                s.public_key().clone()
+            ]
+        );
+    }
+
+    #[test]
+    fn load_cost_prefers_spare_capacity_and_punishes_overload() {
+        let cost = |capacity_class: CapacityClass, load_factor: u8| {
+            Neighborhood::load_cost(LoadAdvertisement {
+                capacity_class,
+                load_factor,
+            })
+        };
+
+        assert_eq!(cost(CapacityClass::Medium, 0), 0);
+        assert_eq!(cost(CapacityClass::High, 50), 50);
+        assert_eq!(cost(CapacityClass::Medium, 30), 60);
+        assert_eq!(cost(CapacityClass::Low, 30), 120);
+        assert_eq!(cost(CapacityClass::High, 80), 80 + OVERLOAD_PENALTY);
+    }
+
+    #[test]
+    fn load_factor_is_measured_against_nominal_capacity() {
+        let medium = CapacityClass::Medium.nominal_bytes_per_second();
+
+        assert_eq!(
+            Neighborhood::load_factor_of(
+                medium * 5 / 2,
+                Duration::from_secs(5),
+                CapacityClass::Medium
+            ),
+            50
+        );
+        assert_eq!(
+            Neighborhood::load_factor_of(
+                medium * 50,
+                Duration::from_secs(5),
+                CapacityClass::Medium
+            ),
+            100
+        );
+        assert_eq!(
+            Neighborhood::load_factor_of(0, Duration::from_secs(0), CapacityClass::Medium),
+            0
+        );
+        assert_eq!(
+            Neighborhood::load_factor_of(1, Duration::from_secs(0), CapacityClass::Medium),
+            100
+        );
+    }
+
+    #[test]
+    fn load_samples_are_damped_and_readvertised_only_after_significant_drift() {
+        let mut subject = make_standalone_neighborhood();
+        let initial_version = subject.neighborhood_database.root().version();
+
+        subject.absorb_load_sample(100);
+
+        assert_eq!(subject.damped_load_factor, 25);
+        let root = subject.neighborhood_database.root();
+        assert_eq!(root.load().load_factor, 25);
+        assert_eq!(root.version(), initial_version + 1);
+
+        subject.absorb_load_sample(30);
+
+        assert_eq!(subject.damped_load_factor, 26);
+        let root = subject.neighborhood_database.root();
+        assert_eq!(root.load().load_factor, 25);
+        assert_eq!(root.version(), initial_version + 1);
+    }
+
+    #[test]
+    fn a_low_power_profile_waits_for_more_drift_before_readvertising_load() {
+        let mut subject = make_standalone_neighborhood();
+        subject.adopt_performance_profile(PerformanceProfile::low_power());
+        let initial_version = subject.neighborhood_database.root().version();
+
+        subject.absorb_load_sample(100);
+        subject.absorb_load_sample(100);
+
+        assert_eq!(subject.damped_load_factor, 43);
+        let root = subject.neighborhood_database.root();
+        assert_eq!(root.load().load_factor, 25);
+        assert_eq!(root.version(), initial_version + 1);
+    }
+
+    #[test]
+    fn advertise_relay_only_puts_the_flag_in_the_root_record() {
+        let mut subject = make_standalone_neighborhood();
+
+        subject.advertise_relay_only();
+
+        let root = subject.neighborhood_database.root();
+        assert_eq!(root.relay_only(), true);
+        assert_eq!(
+            root.signatures(),
+            Some(NodeSignatures::from(
+                cryptde(),
+                &GossipNodeRecord::from(root, true).inner
+            ))
+        );
+    }
+
+    #[test]
+    fn start_at_version_sets_and_signs_the_root_version() {
+        let mut subject = make_standalone_neighborhood();
+
+        subject.start_at_version(4);
+
+        let root = subject.neighborhood_database.root();
+        assert_eq!(root.version(), 4);
+        assert_eq!(
+            root.signatures(),
+            Some(NodeSignatures::from(
+                cryptde(),
+                &GossipNodeRecord::from(root, true).inner
+            ))
+        );
+    }
+
+    #[test]
+    fn advertise_exit_tls_puts_the_capability_in_the_root_record() {
+        let mut subject = make_standalone_neighborhood();
+
+        subject.advertise_exit_tls();
+
+        let root = subject.neighborhood_database.root();
+        assert_eq!(root.offers_exit_tls(), true);
+        assert_eq!(
+            root.signatures(),
+            Some(NodeSignatures::from(
+                cryptde(),
+                &GossipNodeRecord::from(root, true).inner
+            ))
+        );
+    }
+
+    #[test]
+    fn advertise_capacity_changes_root_capacity_class() {
+        let mut subject = make_standalone_neighborhood();
+
+        subject.advertise_capacity(CapacityClass::High);
+
+        let root = subject.neighborhood_database.root();
+        assert_eq!(
+            root.load(),
+            LoadAdvertisement {
+                capacity_class: CapacityClass::High,
+                load_factor: 0,
+            }
+        );
+        assert_eq!(
+            root.signatures(),
+            Some(NodeSignatures::from(
+                cryptde(),
+                &GossipNodeRecord::from(root, true).inner
+            ))
+        );
+    }
+
+    #[test]
+    fn advertise_rate_card_changes_root_rate_card() {
+        let mut subject = make_standalone_neighborhood();
+        let rate_card = RateCard {
+            routing_service_rate: 10,
+            routing_byte_rate: 20,
+            exit_service_rate: 30,
+            exit_byte_rate: 40,
+        };
+
+        subject.advertise_rate_card(rate_card);
+
+        let root = subject.neighborhood_database.root();
+        assert_eq!(root.rate_card(), rate_card);
+        assert_eq!(
+            root.signatures(),
+            Some(NodeSignatures::from(
+                cryptde(),
+                &GossipNodeRecord::from(root, true).inner
+            ))
+        );
+    }
+
+    #[test]
+    fn advertise_country_puts_the_country_in_the_root_record() {
+        let mut subject = make_standalone_neighborhood();
 
-        let result0 = subject.zero_hop_route_response();
-        let result1 = subject.zero_hop_route_response();
+        subject.advertise_country(String::from("DE"));
 
-        let return_route_id_0 = match result0.expected_services {
-            ExpectedServices::RoundTrip(_, _, id) => id,
-            _ => panic!("expected RoundTrip got OneWay"),
-        };
+        let root = subject.neighborhood_database.root();
+        assert_eq!(root.country_opt(), Some(String::from("DE")));
+        assert_eq!(
+            root.signatures(),
+            Some(NodeSignatures::from(
+                cryptde(),
+                &GossipNodeRecord::from(root, true).inner
+            ))
+        );
+    }
 
-        let return_route_id_1 = match result1.expected_services {
-            ExpectedServices::RoundTrip(_, _, id) => id,
-            _ => panic!("expected RoundTrip got OneWay"),
+    #[test]
+    fn advertise_exit_sla_puts_the_exit_sla_in_the_root_record() {
+        let mut subject = make_standalone_neighborhood();
+        let exit_sla = ExitSla {
+            max_streams: 8,
+            max_stream_bytes_per_second: 65536,
+            content_policy_hash: String::from("booga"),
         };
 
-        assert_eq!(return_route_id_0, 0);
-        assert_eq!(return_route_id_1, 1);
+        subject.advertise_exit_sla(exit_sla.clone());
+
+        let root = subject.neighborhood_database.root();
+        assert_eq!(root.exit_sla_opt(), Some(exit_sla));
+        assert_eq!(
+            root.signatures(),
+            Some(NodeSignatures::from(
+                cryptde(),
+                &GossipNodeRecord::from(root, true).inner
+            ))
+        );
     }
 
-    /*
-            Database, where B is bootstrap and the rest are standard:
+    #[test]
+    fn rotate_earning_wallets_advertises_the_current_wallet_from_the_start() {
+        let mut subject = make_standalone_neighborhood();
+        let initial_version = subject.neighborhood_database.root().version();
+        let rotation = WalletRotation::new(vec![Wallet::new("rotating")], Duration::from_secs(10));
 
-                 +---+-B-+---+
-                 |   |   |   |
-                 P---Q---R---S
+        subject.rotate_earning_wallets(rotation.clone());
 
-            Tests will be written from the viewpoint of P.
-    */
+        assert_eq!(subject.earning_wallet_rotation, Some(rotation));
+        let root = subject.neighborhood_database.root();
+        assert_eq!(root.earning_wallet(), Wallet::new("rotating"));
+        assert_eq!(root.version(), initial_version);
+        assert_eq!(
+            root.signatures(),
+            Some(NodeSignatures::from(
+                cryptde(),
+                &GossipNodeRecord::from(root, true).inner
+            ))
+        );
+    }
 
     #[test]
-    fn route_query_messages() {
-        let cryptde = cryptde();
-        let earning_wallet = Wallet::new("earning");
-        let consuming_wallet = Some(Wallet::new("consuming"));
-        let system = System::new("route_query_messages");
-        let mut subject = Neighborhood::new(
-            cryptde,
-            NeighborhoodConfig {
-                neighbor_configs: vec![],
-                is_bootstrap_node: false,
-                local_ip_addr: sentinel_ip_addr(),
-                clandestine_port_list: vec![],
-                earning_wallet: earning_wallet.clone(),
-                consuming_wallet: consuming_wallet.clone(),
-            },
+    fn earning_wallet_is_readvertised_only_when_its_period_is_over() {
+        init_test_logging();
+        let mut subject = make_standalone_neighborhood();
+        let initial_version = subject.neighborhood_database.root().version();
+        subject.earning_wallet_rotation = Some(WalletRotation::new(
+            vec![Wallet::new("one"), Wallet::new("two"), Wallet::new("three")],
+            Duration::from_secs(10),
+        ));
+        let at = |seconds| UNIX_EPOCH + Duration::from_secs(seconds);
+
+        subject.rotate_earning_wallet_at(at(999_999_990));
+        subject.rotate_earning_wallet_at(at(999_999_995));
+
+        let root = subject.neighborhood_database.root();
+        assert_eq!(root.earning_wallet(), Wallet::new("one"));
+        assert_eq!(root.version(), initial_version + 1);
+
+        subject.rotate_earning_wallet_at(at(1_000_000_000));
+
+        let root = subject.neighborhood_database.root();
+        assert_eq!(root.earning_wallet(), Wallet::new("two"));
+        assert_eq!(root.version(), initial_version + 2);
+        assert_eq!(
+            root.signatures(),
+            Some(NodeSignatures::from(
+                cryptde(),
+                &GossipNodeRecord::from(root, true).inner
+            ))
         );
-        let b = &make_node_record(1234, true, true);
-        let p = &subject.neighborhood_database.root().clone();
-        let q = &make_node_record(3456, true, false);
-        let r = &make_node_record(4567, false, false);
-        let s = &make_node_record(5678, false, false);
-        {
-            let db = &mut subject.neighborhood_database;
-            db.add_node(b).unwrap();
-            db.add_node(q).unwrap();
-            db.add_node(r).unwrap();
-            db.add_node(s).unwrap();
-            let mut dual_edge = |a: &NodeRecord, b: &NodeRecord| dual_edge_func(db, a, b);
-            dual_edge(b, p);
-            dual_edge(b, q);
-            dual_edge(b, r);
-            dual_edge(b, s);
-            dual_edge(p, q);
-            dual_edge(q, r);
-            dual_edge(r, s);
-        }
+        TestLogHandler::new()
+            .exists_log_containing("INFO: Neighborhood: Earning wallet rotated to two");
+    }
 
-        let addr: Addr<Syn, Neighborhood> = subject.start();
-        let sub: Recipient<Syn, RouteQueryMessage> = addr.recipient::<RouteQueryMessage>();
+    #[test]
+    fn switching_the_consuming_wallet_readvertises_the_record_only_when_it_changes() {
+        init_test_logging();
+        let mut subject = make_standalone_neighborhood();
+        let initial_version = subject.neighborhood_database.root().version();
 
-        let data_route = sub.send(RouteQueryMessage::data_indefinite_route_request(2));
+        subject.switch_consuming_wallet(Wallet::new("consuming"));
 
-        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
-        system.run();
-        let segment = |nodes: Vec<&NodeRecord>, component: Component| {
-            RouteSegment::new(
-                nodes.into_iter().map(|n| n.public_key()).collect(),
-                component,
-            )
-        };
+        assert_eq!(
+            subject.neighborhood_database.root().version(),
+            initial_version
+        );
 
-        let result = data_route.wait().unwrap().unwrap();
-        let expected_response = RouteQueryResponse {
-            route: Route::round_trip(
-                segment(vec![p, q, r], Component::ProxyClient),
-                segment(vec![r, q, p], Component::ProxyServer),
-                cryptde,
-                consuming_wallet,
-                0,
-            )
-            .unwrap(),
-            expected_services: ExpectedServices::RoundTrip(
-                vec![
-                    ExpectedService::Nothing,
-                    ExpectedService::Routing(q.public_key().clone(), q.earning_wallet()),
-                    ExpectedService::Exit(r.public_key().clone(), r.earning_wallet()),
-                ],
-                vec![
-                    ExpectedService::Exit(r.public_key().clone(), r.earning_wallet()),
-                    ExpectedService::Routing(q.public_key().clone(), q.earning_wallet()),
-                    ExpectedService::Nothing,
-                ],
-                0,
-            ),
-        };
-        assert_eq!(result, expected_response);
+        subject.switch_consuming_wallet(Wallet::new("standby"));
+
+        let root = subject.neighborhood_database.root();
+        assert_eq!(root.consuming_wallet(), Some(Wallet::new("standby")));
+        assert_eq!(root.earning_wallet(), Wallet::new("earning"));
+        assert_eq!(root.version(), initial_version + 1);
+        assert_eq!(
+            root.signatures(),
+            Some(NodeSignatures::from(
+                cryptde(),
+                &GossipNodeRecord::from(root, true).inner
+            ))
+        );
+        TestLogHandler::new()
+            .exists_log_containing("INFO: Neighborhood: Consuming wallet switched to standby");
+    }
+
+    #[test]
+    fn constant_values_for_load_advertisement() {
+        assert_eq!(LOAD_DAMPING_FACTOR, 4);
+        assert_eq!(LOAD_ADVERTISEMENT_THRESHOLD, 10);
+        assert_eq!(OVERLOADED_LOAD_FACTOR, 80);
+        assert_eq!(OVERLOAD_PENALTY, 1000);
+        assert_eq!(ROUTE_SCORE_WEIGHT, 4);
+        assert_eq!(WALLET_ROTATION_CHECK_INTERVAL, Duration::from_secs(60));
     }
 
     #[test]
@@ -1519,15 +4922,18 @@ mod tests {
             },
         );
 
-        let result: Result<RouteQueryResponse, String> = subject.compose_route_query_response(
-            RouteSegment::new(vec![], Component::Neighborhood),
-            RouteSegment::new(vec![], Component::Neighborhood),
-        );
+        let result: Result<RouteQueryResponse, RouteRefusal> = subject
+            .compose_route_query_response(
+                RouteSegment::new(vec![], Component::Neighborhood),
+                RouteSegment::new(vec![], Component::Neighborhood),
+            );
         assert!(result.is_err());
-        let error_expectation: String = result.expect_err("Expected an Err but got:");
+        let error_expectation: RouteRefusal = result.expect_err("Expected an Err but got:");
         assert_eq!(
             error_expectation,
-            "Cannot make multi-hop route without segment keys"
+            RouteRefusal::Internal(String::from(
+                "Cannot make multi-hop route without segment keys"
+            ))
         );
     }
 
@@ -1635,15 +5041,18 @@ mod tests {
             },
         );
 
-        let result: Result<RouteQueryResponse, String> = subject.compose_route_query_response(
-            RouteSegment::new(vec![], Component::ProxyClient),
-            RouteSegment::new(vec![], Component::ProxyServer),
-        );
+        let result: Result<RouteQueryResponse, RouteRefusal> = subject
+            .compose_route_query_response(
+                RouteSegment::new(vec![], Component::ProxyClient),
+                RouteSegment::new(vec![], Component::ProxyServer),
+            );
         assert!(result.is_err());
-        let error_expectation: String = result.expect_err("Expected an Err but got:");
+        let error_expectation: RouteRefusal = result.expect_err("Expected an Err but got:");
         assert_eq!(
             error_expectation,
-            "Cannot make multi-hop route without segment keys"
+            RouteRefusal::Internal(String::from(
+                "Cannot make multi-hop route without segment keys"
+            ))
         );
     }
 
@@ -1663,15 +5072,16 @@ mod tests {
             },
         );
 
-        let result: Result<RouteQueryResponse, String> = subject.compose_route_query_response(
-            RouteSegment::new(vec![&PublicKey::new(&[3, 3, 8])], Component::ProxyClient),
-            RouteSegment::new(vec![&PublicKey::new(&[8, 3, 3])], Component::ProxyServer),
-        );
+        let result: Result<RouteQueryResponse, RouteRefusal> = subject
+            .compose_route_query_response(
+                RouteSegment::new(vec![&PublicKey::new(&[3, 3, 8])], Component::ProxyClient),
+                RouteSegment::new(vec![&PublicKey::new(&[8, 3, 3])], Component::ProxyServer),
+            );
         assert!(result.is_err());
-        let error_expectation: String = result.expect_err("Expected an Err but got:");
+        let error_expectation: RouteRefusal = result.expect_err("Expected an Err but got:");
         assert_eq!(
             error_expectation,
-            "Cannot make multi_hop with unknown neighbor"
+            RouteRefusal::Internal(String::from("Cannot make multi_hop with unknown neighbor"))
         );
         assert_eq!(subject.next_return_route_id, 0);
     }
@@ -1842,6 +5252,7 @@ mod tests {
             consuming_wallet: Some(Wallet::new("consuming")),
             remaining_route: make_meaningless_route(),
             payload: CryptData::new(&b"booga"[..]),
+            immediate_neighbor_key_opt: None,
         };
         let system = System::new("");
         let subject = Neighborhood::new(
@@ -1949,6 +5360,175 @@ mod tests {
             .inner
             .neighbors
             .contains(&removed_neighbor.public_key()));
+        assert_eq!(gossip.ttl_opt, Some(FULL_SYNC_GOSSIP_TTL));
+    }
+
+    #[test]
+    fn gossips_an_empty_neighbor_list_to_every_neighbor_on_shutdown() {
+        let hopper = Recorder::new();
+        let hopper_awaiter = hopper.get_awaiter();
+        let hopper_recording = hopper.get_recording();
+        let cryptde = cryptde();
+        let this_node = NodeRecord::new_for_tests(
+            &cryptde.public_key(),
+            Some(&NodeAddr::new(
+                &IpAddr::from_str("5.4.3.2").unwrap(),
+                &vec![1234],
+            )),
+            true,
+        );
+        let this_node_inside = this_node.clone();
+        let one_neighbor = make_node_record(2345, true, false);
+        let one_neighbor_inside = one_neighbor.clone();
+        let another_neighbor = make_node_record(3456, true, false);
+        let another_neighbor_inside = another_neighbor.clone();
+
+        thread::spawn(move || {
+            let system =
+                System::new("gossips_an_empty_neighbor_list_to_every_neighbor_on_shutdown");
+            let mut subject = Neighborhood::new(
+                cryptde,
+                NeighborhoodConfig {
+                    neighbor_configs: vec![],
+                    is_bootstrap_node: this_node_inside.is_bootstrap_node(),
+                    local_ip_addr: this_node_inside.node_addr_opt().unwrap().ip_addr(),
+                    clandestine_port_list: this_node_inside.node_addr_opt().unwrap().ports(),
+                    earning_wallet: Wallet::new("earning"),
+                    consuming_wallet: None,
+                },
+            );
+            vec![&one_neighbor_inside, &another_neighbor_inside]
+                .into_iter()
+                .for_each(|neighbor| {
+                    subject.neighborhood_database.add_node(neighbor).unwrap();
+                    subject
+                        .neighborhood_database
+                        .add_neighbor(&cryptde.public_key(), neighbor.public_key())
+                        .unwrap();
+                });
+            let addr: Addr<Syn, Neighborhood> = subject.start();
+            let peer_actors = peer_actors_builder().hopper(hopper).build();
+            addr.try_send(BindMessage { peer_actors }).unwrap();
+
+            addr.try_send(ShutdownMessage {}).unwrap();
+
+            system.run();
+        });
+
+        hopper_awaiter.await_message_count(2);
+        let locked_recording = hopper_recording.lock().unwrap();
+        vec![one_neighbor, another_neighbor]
+            .into_iter()
+            .enumerate()
+            .for_each(|(index, neighbor)| {
+                let package: &IncipientCoresPackage = locked_recording.get_record(index);
+                let neighbor_cryptde = CryptDENull::from(neighbor.public_key());
+                let decrypted_payload = neighbor_cryptde.decode(&package.payload).unwrap();
+                let gossip: Gossip =
+                    serde_cbor::de::from_slice(decrypted_payload.as_slice()).unwrap();
+                let the_node_record = gossip
+                    .node_records
+                    .iter()
+                    .find(|&x| x.inner.public_key == cryptde.public_key())
+                    .expect("should have the node record");
+                assert_eq!(the_node_record.inner.neighbors.is_empty(), true);
+                assert_eq!(gossip.ttl_opt, Some(FULL_SYNC_GOSSIP_TTL));
+            });
+        assert_eq!(locked_recording.len(), 2);
+    }
+
+    #[test]
+    fn gossips_only_what_a_neighbor_has_not_acknowledged() {
+        let hopper = Recorder::new();
+        let hopper_awaiter = hopper.get_awaiter();
+        let hopper_recording = hopper.get_recording();
+        let cryptde = cryptde();
+        let this_node = NodeRecord::new_for_tests(
+            &cryptde.public_key(),
+            Some(&NodeAddr::new(
+                &IpAddr::from_str("5.4.3.2").unwrap(),
+                &vec![1234],
+            )),
+            false,
+        );
+        let this_node_inside = this_node.clone();
+        let removed_neighbor = make_node_record(2345, true, false);
+        let removed_neighbor_inside = removed_neighbor.clone();
+        let other_neighbor = make_node_record(3456, true, false);
+        let other_neighbor_inside = other_neighbor.clone();
+
+        thread::spawn(move || {
+            let system = System::new("gossips_only_what_a_neighbor_has_not_acknowledged");
+            let mut subject = Neighborhood::new(
+                cryptde,
+                NeighborhoodConfig {
+                    neighbor_configs: vec![],
+                    is_bootstrap_node: true,
+                    local_ip_addr: this_node_inside.node_addr_opt().unwrap().ip_addr(),
+                    clandestine_port_list: this_node_inside.node_addr_opt().unwrap().ports(),
+                    earning_wallet: this_node_inside.earning_wallet(),
+                    consuming_wallet: this_node_inside.consuming_wallet(),
+                },
+            );
+            subject
+                .neighborhood_database
+                .add_node(&removed_neighbor_inside)
+                .unwrap();
+            subject
+                .neighborhood_database
+                .add_node(&other_neighbor_inside)
+                .unwrap();
+            subject
+                .neighborhood_database
+                .add_neighbor(&cryptde.public_key(), removed_neighbor_inside.public_key())
+                .unwrap();
+            subject
+                .neighborhood_database
+                .add_neighbor(&cryptde.public_key(), other_neighbor_inside.public_key())
+                .unwrap();
+            let root = subject.neighborhood_database.root().clone();
+            let mut incoming = GossipBuilder::new()
+                .node(&root, true)
+                .node(&removed_neighbor_inside, true)
+                .node(&other_neighbor_inside, true)
+                .build();
+            incoming.sequence_opt = Some(1);
+            {
+                let mut ledger = subject.gossip_ledger.borrow_mut();
+                ledger.prepare(
+                    other_neighbor_inside.public_key(),
+                    &mut GossipBuilder::new().build(),
+                    Instant::now(),
+                );
+                ledger.received(other_neighbor_inside.public_key(), &incoming);
+            }
+            let addr: Addr<Syn, Neighborhood> = subject.start();
+            let peer_actors = peer_actors_builder().hopper(hopper).build();
+            addr.try_send(BindMessage { peer_actors }).unwrap();
+
+            addr.try_send(RemoveNeighborMessage {
+                public_key: removed_neighbor_inside.public_key().clone(),
+            })
+            .unwrap();
+
+            system.run();
+        });
+
+        let other_neighbor_cryptde = CryptDENull::from(other_neighbor.public_key());
+        hopper_awaiter.await_message_count(1);
+        let locked_recording = hopper_recording.lock().unwrap();
+        let package: &IncipientCoresPackage = locked_recording.get_record(0);
+        let decrypted_payload = other_neighbor_cryptde.decode(&package.payload).unwrap();
+        let gossip: Gossip = serde_cbor::de::from_slice(decrypted_payload.as_slice()).unwrap();
+        let keys: Vec<PublicKey> = gossip
+            .node_records
+            .iter()
+            .map(|gnr| gnr.public_key())
+            .collect();
+        assert_eq!(keys, vec![cryptde.public_key()]);
+        assert_eq!(gossip.delta, true);
+        assert_eq!(gossip.sequence_opt, Some(2));
+        assert_eq!(gossip.ack_opt, Some(1));
     }
 
     #[test]
@@ -1972,6 +5552,7 @@ mod tests {
             consuming_wallet: Some(Wallet::new("consuming")),
             remaining_route: make_meaningless_route(),
             payload: encodex(cryptde, &cryptde.public_key(), &gossip).unwrap(),
+            immediate_neighbor_key_opt: None,
         };
         let hopper = Recorder::new();
         let hopper_awaiter = hopper.get_awaiter();
@@ -2023,6 +5604,7 @@ mod tests {
         let decrypted_payload = gossip_neighbor_cryptde.decode(&package.payload).unwrap();
         let gossip: Gossip = serde_cbor::de::from_slice(decrypted_payload.as_slice()).unwrap();
         assert_eq!(gossip.node_records.len(), 2);
+        assert_eq!(gossip.ttl_opt, Some(FULL_SYNC_GOSSIP_TTL));
         let gossip_node_records = gossip.node_records;
         assert_contains(
             &gossip_node_records,
@@ -2166,6 +5748,9 @@ mod tests {
             target_component: Component::ProxyClient,
             minimum_hop_count: 3,
             return_component_opt: None,
+            avoided_exit_keys: vec![],
+            exit_location_opt: None,
+            random_route: false,
         };
         let unsuccessful_three_hop_route = addr.send(three_hop_route_request);
         let public_key_query = addr.send(NodeQueryMessage::PublicKey(a.public_key().clone()));
@@ -2175,7 +5760,14 @@ mod tests {
         Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
 
         system.run();
-        assert_eq!(None, unsuccessful_three_hop_route.wait().unwrap());
+        assert_eq!(
+            Err(RouteRefusal::NoExitNode(format!(
+                "Couldn't find any routes: at least 3-hop from {} to ProxyClient at Standard {}",
+                n.public_key(),
+                c.public_key()
+            ))),
+            unsuccessful_three_hop_route.wait().unwrap()
+        );
         assert_eq!(
             a.public_key(),
             &public_key_query.wait().unwrap().unwrap().public_key
@@ -2524,6 +6116,7 @@ mod tests {
             consuming_wallet: Some(Wallet::new("consuming")),
             remaining_route: make_meaningless_route(),
             payload: encodex(cryptde, &cryptde.public_key(), &gossip).unwrap(),
+            immediate_neighbor_key_opt: None,
         };
         let hopper = Recorder::new();
         let hopper_recording = hopper.get_recording();
@@ -2599,6 +6192,7 @@ mod tests {
             consuming_wallet: Some(Wallet::new("consuming")),
             remaining_route: make_meaningless_route(),
             payload: encodex(cryptde, &cryptde.public_key(), &gossip).unwrap(),
+            immediate_neighbor_key_opt: None,
         };
         let hopper = Recorder::new();
         let hopper_recording = hopper.get_recording();
@@ -2669,6 +6263,7 @@ mod tests {
             consuming_wallet: Some(Wallet::new("consuming")),
             remaining_route: make_meaningless_route(),
             payload: encodex(cryptde, &cryptde.public_key(), &gossip).unwrap(),
+            immediate_neighbor_key_opt: None,
         };
         let hopper = Recorder::new();
         let hopper_recording = hopper.get_recording();
@@ -2720,8 +6315,8 @@ mod tests {
         tlh.await_log_containing(&format!("Finished processing Gossip about 1 Nodes"), 5000);
         tlh.await_log_containing(
             &format!(
-                "Relaying Gossip about 3 nodes to {:?}",
-                neighborless_node.public_key()
+                "Relaying Gossip about 3 nodes to {}",
+                neighborless_node.public_key().fingerprint()
             ),
             5000,
         );
@@ -2769,6 +6364,7 @@ mod tests {
             consuming_wallet: Some(Wallet::new("consuming")),
             remaining_route: make_meaningless_route(),
             payload: encodex(cryptde, &cryptde.public_key(), &gossip).unwrap(),
+            immediate_neighbor_key_opt: None,
         };
         let hopper = Recorder::new();
         let this_node_inside = this_node.clone();
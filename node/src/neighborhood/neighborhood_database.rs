@@ -4,7 +4,12 @@ use crate::sub_lib::cryptde::CryptDE;
 use crate::sub_lib::cryptde::CryptData;
 use crate::sub_lib::cryptde::PlainData;
 use crate::sub_lib::cryptde::PublicKey;
+use crate::sub_lib::key_fingerprint::KeyFingerprint;
+use crate::sub_lib::neighborhood::CapacityClass;
+use crate::sub_lib::neighborhood::ExitSla;
+use crate::sub_lib::neighborhood::RateCard;
 use crate::sub_lib::node_addr::NodeAddr;
+use crate::sub_lib::utils::is_false;
 use crate::sub_lib::wallet::Wallet;
 use serde_cbor;
 use serde_derive::{Deserialize, Serialize};
@@ -25,6 +30,29 @@ pub struct NodeRecordInner {
     pub is_bootstrap_node: bool,
     pub neighbors: Vec<PublicKey>,
     pub version: u32,
+    #[serde(default)]
+    pub load: LoadAdvertisement,
+    // Willing to fetch HTTPS on behalf of an originator's plain HTTP request; see tls_at_exit
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub offers_exit_tls: bool,
+    // Opens no listeners for consumers of its own (see --listen), so it only relays and exits
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub relay_only: bool,
+    // Left out when it's the default, so Nodes that charge the default rates gossip as before
+    #[serde(default, skip_serializing_if = "RateCard::is_default")]
+    pub rate_card: RateCard,
+    // ISO 3166 country code the Node says it's in (see --country); nobody checks it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub country_opt: Option<String>,
+    // What it promises the streams it exits (see --exit_sla); originators check it for themselves
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exit_sla_opt: Option<ExitSla>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default, Serialize, Deserialize)]
+pub struct LoadAdvertisement {
+    pub capacity_class: CapacityClass,
+    pub load_factor: u8, // percent of nominal capacity, damped
 }
 
 impl NodeRecordInner {
@@ -32,6 +60,18 @@ impl NodeRecordInner {
     // For now, this is only called at initialization time (NeighborhoodDatabase) and in tests, so panicking is OK.
     // When we start signing NodeRecords at other times, we should probably not panic
     pub fn generate_signature(&self, cryptde: &dyn CryptDE) -> CryptData {
+        cryptde.sign(&self.digest()).expect(&format!(
+            "NodeRecord content {:?} could not be signed",
+            &self
+        ))
+    }
+
+    // The signature has to have been made by the Node this content is about, over exactly this content
+    pub fn signature_is_valid(&self, signature: &CryptData, cryptde: &dyn CryptDE) -> bool {
+        cryptde.verify_signature(&self.digest(), signature, &self.public_key)
+    }
+
+    fn digest(&self) -> PlainData {
         let serialized = match serde_cbor::ser::to_vec(&self) {
             Ok(inner) => inner,
             Err(_) => panic!("NodeRecord content {:?} could not be serialized", &self),
@@ -39,13 +79,7 @@ impl NodeRecordInner {
 
         let mut hash = sha1::Sha1::new();
         hash.update(&serialized[..]);
-
-        cryptde
-            .sign(&PlainData::new(&hash.digest().bytes()))
-            .expect(&format!(
-                "NodeRecord content {:?} could not be signed",
-                &self
-            ))
+        PlainData::new(&hash.digest().bytes())
     }
 }
 
@@ -71,6 +105,12 @@ impl NodeSignatures {
             is_bootstrap_node: node_record_inner.is_bootstrap_node,
             neighbors: node_record_inner.neighbors.clone(),
             version: node_record_inner.version,
+            load: node_record_inner.load,
+            offers_exit_tls: node_record_inner.offers_exit_tls,
+            relay_only: node_record_inner.relay_only,
+            rate_card: node_record_inner.rate_card,
+            country_opt: node_record_inner.country_opt.clone(),
+            exit_sla_opt: node_record_inner.exit_sla_opt.clone(),
         };
         let obscured_signature = obscured_inner.generate_signature(cryptde);
 
@@ -115,6 +155,12 @@ impl NodeRecord {
                 is_bootstrap_node,
                 neighbors: vec![],
                 version,
+                load: LoadAdvertisement::default(),
+                offers_exit_tls: false,
+                relay_only: false,
+                rate_card: RateCard::default(),
+                country_opt: None,
+                exit_sla_opt: None,
             },
             signatures,
         }
@@ -236,6 +282,92 @@ impl NodeRecord {
         earning_change || consuming_change
     }
 
+    pub fn load(&self) -> LoadAdvertisement {
+        self.inner.load
+    }
+
+    pub fn set_load(&mut self, load: LoadAdvertisement) -> bool {
+        if self.inner.load == load {
+            false
+        } else {
+            self.inner.load = load;
+            true
+        }
+    }
+
+    pub fn offers_exit_tls(&self) -> bool {
+        self.inner.offers_exit_tls
+    }
+
+    pub fn set_offers_exit_tls(&mut self, offers_exit_tls: bool) -> bool {
+        if self.inner.offers_exit_tls == offers_exit_tls {
+            false
+        } else {
+            self.inner.offers_exit_tls = offers_exit_tls;
+            true
+        }
+    }
+
+    pub fn relay_only(&self) -> bool {
+        self.inner.relay_only
+    }
+
+    pub fn set_relay_only(&mut self, relay_only: bool) -> bool {
+        if self.inner.relay_only == relay_only {
+            false
+        } else {
+            self.inner.relay_only = relay_only;
+            true
+        }
+    }
+
+    pub fn rate_card(&self) -> RateCard {
+        self.inner.rate_card
+    }
+
+    pub fn set_rate_card(&mut self, rate_card: RateCard) -> bool {
+        if self.inner.rate_card == rate_card {
+            false
+        } else {
+            self.inner.rate_card = rate_card;
+            true
+        }
+    }
+
+    pub fn country_opt(&self) -> Option<String> {
+        self.inner.country_opt.clone()
+    }
+
+    pub fn set_country_opt(&mut self, country_opt: Option<String>) -> bool {
+        if self.inner.country_opt == country_opt {
+            false
+        } else {
+            self.inner.country_opt = country_opt;
+            true
+        }
+    }
+
+    pub fn exit_sla_opt(&self) -> Option<ExitSla> {
+        self.inner.exit_sla_opt.clone()
+    }
+
+    pub fn set_exit_sla_opt(&mut self, exit_sla_opt: Option<ExitSla>) -> bool {
+        if self.inner.exit_sla_opt == exit_sla_opt {
+            false
+        } else {
+            self.inner.exit_sla_opt = exit_sla_opt;
+            true
+        }
+    }
+
+    // Country codes from other Nodes' Gossip may be in either case
+    pub fn is_in_country(&self, country: &str) -> bool {
+        match &self.inner.country_opt {
+            Some(country_code) => country_code.eq_ignore_ascii_case(country),
+            None => false,
+        }
+    }
+
     pub fn set_is_bootstrap_node(&mut self, is_bootstrap_node: bool) -> bool {
         if self.inner.is_bootstrap_node == is_bootstrap_node {
             false
@@ -298,6 +430,25 @@ impl NeighborhoodDatabase {
         self.node_by_key_mut(root_key).expect("Internal error")
     }
 
+    // For a Node that started without knowing its own IP address: keeps the clandestine ports it
+    // already has, but moves it to the address its neighbors see it at
+    pub fn set_root_ip_addr(&mut self, ip_addr: &IpAddr) {
+        let old_node_addr_opt = self.root().node_addr_opt();
+        let ports = match &old_node_addr_opt {
+            Some(node_addr) => {
+                self.by_ip_addr.remove(&node_addr.ip_addr());
+                node_addr.ports()
+            }
+            None => vec![],
+        };
+        let root_key = self.this_node.clone();
+        self.by_ip_addr.insert(*ip_addr, root_key);
+        let root = self.root_mut();
+        root.unset_node_addr();
+        root.set_node_addr(&NodeAddr::new(ip_addr, &ports))
+            .expect("NodeAddr magically reappeared");
+    }
+
     pub fn keys(&self) -> HashSet<&PublicKey> {
         self.by_public_key.keys().into_iter().collect()
     }
@@ -317,6 +468,25 @@ impl NeighborhoodDatabase {
         }
     }
 
+    // Reverse lookups for diagnostics, which see only the fingerprints in the logs. More than one
+    // key can share a fingerprint; node_by_fingerprint finds nothing unless just one does.
+    pub fn keys_by_fingerprint(&self, fingerprint: &KeyFingerprint) -> Vec<&PublicKey> {
+        let mut keys: Vec<&PublicKey> = self
+            .by_public_key
+            .keys()
+            .filter(|key| fingerprint.matches(key))
+            .collect();
+        keys.sort_by(|a, b| a.as_slice().cmp(b.as_slice()));
+        keys
+    }
+
+    pub fn node_by_fingerprint(&self, fingerprint: &KeyFingerprint) -> Option<&NodeRecord> {
+        match self.keys_by_fingerprint(fingerprint).as_slice() {
+            [key] => self.node_by_key(key),
+            _ => None,
+        }
+    }
+
     pub fn has_neighbor(&self, from: &PublicKey, to: &PublicKey) -> bool {
         match self.node_by_key(from) {
             Some(f) => f.has_neighbor(to),
@@ -355,8 +525,8 @@ impl NeighborhoodDatabase {
                 }
                 None => {
                     return Err(format!(
-                        "could not remove nonexistent neighbor by public key: {:?}",
-                        node_key
+                        "could not remove nonexistent neighbor by public key: {}",
+                        node_key.fingerprint()
                     ));
                 }
             };
@@ -572,6 +742,47 @@ mod tests {
         assert_eq!(subject.node_by_key(&another_node.inner.public_key), None);
     }
 
+    #[test]
+    fn nodes_can_be_found_by_fingerprint_unless_it_is_ambiguous() {
+        let this_node = make_node_record(1234, true, false);
+        let one_node = NodeRecord::new_for_tests(&PublicKey::new(b"fingerprint one"), None, false);
+        let another_node =
+            NodeRecord::new_for_tests(&PublicKey::new(b"fingerprint two"), None, false);
+        let lonely_node = NodeRecord::new_for_tests(&PublicKey::new(b"lonely one"), None, false);
+        let mut subject = NeighborhoodDatabase::new(
+            &this_node.inner.public_key,
+            this_node.inner.node_addr_opt.as_ref().unwrap(),
+            Wallet::new("0x1234"),
+            Some(Wallet::new("0x4321")),
+            false,
+            &CryptDENull::from(this_node.public_key()),
+        );
+        subject.add_node(&one_node).unwrap();
+        subject.add_node(&another_node).unwrap();
+        subject.add_node(&lonely_node).unwrap();
+
+        assert_eq!(
+            subject.keys_by_fingerprint(&one_node.public_key().fingerprint()),
+            vec![one_node.public_key(), another_node.public_key()]
+        );
+        assert_eq!(
+            subject.node_by_fingerprint(&one_node.public_key().fingerprint()),
+            None
+        );
+        assert_eq!(
+            subject.node_by_fingerprint(&lonely_node.public_key().fingerprint()),
+            Some(&lonely_node)
+        );
+        assert_eq!(
+            subject.node_by_fingerprint(&this_node.public_key().fingerprint()),
+            subject.node_by_key(this_node.public_key())
+        );
+        assert_eq!(
+            subject.keys_by_fingerprint(&PublicKey::new(b"nobody").fingerprint()),
+            Vec::<&PublicKey>::new()
+        );
+    }
+
     #[test]
     fn node_by_ip_works() {
         let this_node = make_node_record(1234, true, false);
@@ -608,6 +819,43 @@ mod tests {
         );
     }
 
+    #[test]
+    fn set_root_ip_addr_keeps_ports_and_reindexes_the_root() {
+        let this_node = make_node_record(1234, true, false);
+        let mut subject = NeighborhoodDatabase::new(
+            &this_node.inner.public_key,
+            &NodeAddr::new(
+                &IpAddr::from_str("255.255.255.255").unwrap(),
+                &vec![1234, 2345],
+            ),
+            Wallet::new("0x1234"),
+            Some(Wallet::new("0x4321")),
+            false,
+            &CryptDENull::from(this_node.public_key()),
+        );
+
+        subject.set_root_ip_addr(&IpAddr::from_str("3.4.5.6").unwrap());
+
+        assert_eq!(
+            subject.root().node_addr_opt(),
+            Some(NodeAddr::new(
+                &IpAddr::from_str("3.4.5.6").unwrap(),
+                &vec![1234, 2345]
+            ))
+        );
+        assert_eq!(
+            subject
+                .node_by_ip(&IpAddr::from_str("3.4.5.6").unwrap())
+                .unwrap()
+                .public_key(),
+            this_node.public_key()
+        );
+        assert_eq!(
+            subject.node_by_ip(&IpAddr::from_str("255.255.255.255").unwrap()),
+            None
+        );
+    }
+
     #[test]
     fn add_neighbor_works() {
         let this_node = make_node_record(1234, true, false);
@@ -849,6 +1097,12 @@ mod tests {
             consuming_wallet: Some(Wallet::new("0x1234")),
             neighbors: Vec::new(),
             version: 0,
+            load: LoadAdvertisement::default(),
+            offers_exit_tls: false,
+            relay_only: false,
+            rate_card: RateCard::default(),
+            country_opt: None,
+            exit_sla_opt: None,
         };
         let cryptde = CryptDENull::from(&to_be_signed.public_key);
 
@@ -1146,8 +1400,8 @@ mod tests {
         let result = subject.remove_neighbor(nonexistent_key);
 
         let err_message = format!(
-            "could not remove nonexistent neighbor by public key: {:?}",
-            nonexistent_key
+            "could not remove nonexistent neighbor by public key: {}",
+            nonexistent_key.fingerprint()
         );
         assert_eq!(err_message, result.expect_err("not an error"));
     }
@@ -1312,4 +1566,201 @@ mod tests {
             "should still be non-bootsrap"
         );
     }
+
+    #[test]
+    fn set_load_reports_whether_the_load_advertisement_changed() {
+        let mut this_node = make_node_record(1234, true, false);
+        assert_eq!(this_node.load(), LoadAdvertisement::default());
+        let load = LoadAdvertisement {
+            capacity_class: CapacityClass::High,
+            load_factor: 45,
+        };
+
+        let first_result = this_node.set_load(load);
+        let second_result = this_node.set_load(load);
+
+        assert_eq!(first_result, true);
+        assert_eq!(second_result, false);
+        assert_eq!(this_node.load(), load);
+    }
+
+    #[test]
+    fn node_record_inner_without_a_load_advertisement_decodes_with_the_default() {
+        #[derive(Serialize)]
+        struct OldNodeRecordInner {
+            public_key: PublicKey,
+            node_addr_opt: Option<NodeAddr>,
+            earning_wallet: Wallet,
+            consuming_wallet: Option<Wallet>,
+            is_bootstrap_node: bool,
+            neighbors: Vec<PublicKey>,
+            version: u32,
+        }
+        let old = OldNodeRecordInner {
+            public_key: PublicKey::new(&[1, 2, 3, 4]),
+            node_addr_opt: None,
+            earning_wallet: Wallet::new("0x2345"),
+            consuming_wallet: None,
+            is_bootstrap_node: false,
+            neighbors: vec![],
+            version: 3,
+        };
+        let serialized = serde_cbor::ser::to_vec(&old).unwrap();
+
+        let result: NodeRecordInner = serde_cbor::de::from_slice(&serialized[..]).unwrap();
+
+        assert_eq!(result.version, 3);
+        assert_eq!(result.load, LoadAdvertisement::default());
+        assert_eq!(result.offers_exit_tls, false);
+        assert_eq!(result.relay_only, false);
+    }
+
+    #[test]
+    fn set_offers_exit_tls_reports_whether_it_changed() {
+        let mut this_node = make_node_record(1234, true, false);
+        assert_eq!(this_node.offers_exit_tls(), false);
+
+        let first_result = this_node.set_offers_exit_tls(true);
+        let second_result = this_node.set_offers_exit_tls(true);
+
+        assert_eq!(first_result, true);
+        assert_eq!(second_result, false);
+        assert_eq!(this_node.offers_exit_tls(), true);
+    }
+
+    #[test]
+    fn node_record_inner_that_does_not_offer_exit_tls_serializes_as_before() {
+        let mut this_node = make_node_record(1234, true, false);
+        let before = serde_cbor::ser::to_vec(&this_node.inner).unwrap();
+        this_node.set_offers_exit_tls(true);
+        let with_flag = serde_cbor::ser::to_vec(&this_node.inner).unwrap();
+        this_node.set_offers_exit_tls(false);
+
+        let after = serde_cbor::ser::to_vec(&this_node.inner).unwrap();
+
+        assert_eq!(after, before);
+        assert_ne!(with_flag, before);
+    }
+
+    #[test]
+    fn set_relay_only_reports_whether_it_changed() {
+        let mut this_node = make_node_record(1234, true, false);
+        assert_eq!(this_node.relay_only(), false);
+
+        let first_result = this_node.set_relay_only(true);
+        let second_result = this_node.set_relay_only(true);
+
+        assert_eq!(first_result, true);
+        assert_eq!(second_result, false);
+        assert_eq!(this_node.relay_only(), true);
+    }
+
+    #[test]
+    fn node_record_inner_that_is_not_relay_only_serializes_as_before() {
+        let mut this_node = make_node_record(1234, true, false);
+        let before = serde_cbor::ser::to_vec(&this_node.inner).unwrap();
+        this_node.set_relay_only(true);
+        let with_flag = serde_cbor::ser::to_vec(&this_node.inner).unwrap();
+        this_node.set_relay_only(false);
+
+        let after = serde_cbor::ser::to_vec(&this_node.inner).unwrap();
+
+        assert_eq!(after, before);
+        assert_ne!(with_flag, before);
+    }
+
+    #[test]
+    fn set_rate_card_reports_whether_it_changed() {
+        let mut this_node = make_node_record(1234, true, false);
+        assert_eq!(this_node.rate_card(), RateCard::default());
+        let rate_card = RateCard {
+            routing_service_rate: 10,
+            routing_byte_rate: 20,
+            exit_service_rate: 30,
+            exit_byte_rate: 40,
+        };
+
+        let first_result = this_node.set_rate_card(rate_card);
+        let second_result = this_node.set_rate_card(rate_card);
+
+        assert_eq!(first_result, true);
+        assert_eq!(second_result, false);
+        assert_eq!(this_node.rate_card(), rate_card);
+    }
+
+    #[test]
+    fn node_record_inner_with_the_default_rate_card_serializes_as_before() {
+        let mut this_node = make_node_record(1234, true, false);
+        let before = serde_cbor::ser::to_vec(&this_node.inner).unwrap();
+        this_node.set_rate_card(RateCard {
+            routing_service_rate: 10,
+            routing_byte_rate: 20,
+            exit_service_rate: 30,
+            exit_byte_rate: 40,
+        });
+        let with_rates = serde_cbor::ser::to_vec(&this_node.inner).unwrap();
+        this_node.set_rate_card(RateCard::default());
+
+        let after = serde_cbor::ser::to_vec(&this_node.inner).unwrap();
+
+        assert_eq!(after, before);
+        assert_ne!(with_rates, before);
+        let decoded: NodeRecordInner = serde_cbor::de::from_slice(&with_rates[..]).unwrap();
+        assert_eq!(decoded.rate_card.exit_byte_rate, 40);
+    }
+
+    #[test]
+    fn set_country_opt_reports_whether_it_changed() {
+        let mut this_node = make_node_record(1234, true, false);
+        assert_eq!(this_node.country_opt(), None);
+
+        let first_result = this_node.set_country_opt(Some(String::from("DE")));
+        let second_result = this_node.set_country_opt(Some(String::from("DE")));
+
+        assert_eq!(first_result, true);
+        assert_eq!(second_result, false);
+        assert_eq!(this_node.country_opt(), Some(String::from("DE")));
+        assert_eq!(this_node.is_in_country("de"), true);
+        assert_eq!(this_node.is_in_country("FR"), false);
+    }
+
+    #[test]
+    fn node_record_inner_without_a_country_serializes_as_before() {
+        let mut this_node = make_node_record(1234, true, false);
+        assert_eq!(this_node.is_in_country("DE"), false);
+        let before = serde_cbor::ser::to_vec(&this_node.inner).unwrap();
+        this_node.set_country_opt(Some(String::from("DE")));
+        let with_country = serde_cbor::ser::to_vec(&this_node.inner).unwrap();
+        this_node.set_country_opt(None);
+
+        let after = serde_cbor::ser::to_vec(&this_node.inner).unwrap();
+
+        assert_eq!(after, before);
+        assert_ne!(with_country, before);
+        let decoded: NodeRecordInner = serde_cbor::de::from_slice(&with_country[..]).unwrap();
+        assert_eq!(decoded.country_opt, Some(String::from("DE")));
+    }
+
+    #[test]
+    fn node_record_inner_without_an_exit_sla_serializes_as_before() {
+        let mut this_node = make_node_record(1234, true, false);
+        let before = serde_cbor::ser::to_vec(&this_node.inner).unwrap();
+        let exit_sla = ExitSla {
+            max_streams: 8,
+            max_stream_bytes_per_second: 65536,
+            content_policy_hash: String::from("booga"),
+        };
+
+        let first_result = this_node.set_exit_sla_opt(Some(exit_sla.clone()));
+        let second_result = this_node.set_exit_sla_opt(Some(exit_sla.clone()));
+        let with_exit_sla = serde_cbor::ser::to_vec(&this_node.inner).unwrap();
+        this_node.set_exit_sla_opt(None);
+        let after = serde_cbor::ser::to_vec(&this_node.inner).unwrap();
+
+        assert_eq!(first_result, true);
+        assert_eq!(second_result, false);
+        assert_eq!(after, before);
+        let decoded: NodeRecordInner = serde_cbor::de::from_slice(&with_exit_sla[..]).unwrap();
+        assert_eq!(decoded.exit_sla_opt, Some(exit_sla));
+    }
 }
@@ -0,0 +1,108 @@
+// Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use crate::sub_lib::cryptde::PublicKey;
+use crate::sub_lib::neighborhood::NodeProblem;
+use std::collections::HashMap;
+use std::time::Duration;
+use std::time::Instant;
+
+pub const NODE_PROBLEM_COOLDOWN: Duration = Duration::from_secs(60);
+
+// Nodes the ProxyServer has seen fail outright, and when it last said so. Unlike a demerit, a
+// problem isn't held against a Node for long: an exit that couldn't reach one server will probably
+// reach the next, and a Node that just restarted will soon be back.
+pub struct NodeProblems {
+    cooldown: Duration,
+    reported: HashMap<PublicKey, (NodeProblem, Instant)>,
+}
+
+impl NodeProblems {
+    pub fn new(cooldown: Duration) -> NodeProblems {
+        NodeProblems {
+            cooldown,
+            reported: HashMap::new(),
+        }
+    }
+
+    pub fn record(&mut self, key: &PublicKey, problem: NodeProblem, now: Instant) {
+        let cooldown = self.cooldown;
+        self.reported
+            .retain(|_, (_, reported_at)| now.duration_since(*reported_at) < cooldown);
+        self.reported.insert(key.clone(), (problem, now));
+    }
+
+    pub fn is_cooling_down(&self, key: &PublicKey, now: Instant) -> bool {
+        self.problem_of(key, now).is_some()
+    }
+
+    pub fn problem_of(&self, key: &PublicKey, now: Instant) -> Option<NodeProblem> {
+        match self.reported.get(key) {
+            Some((problem, reported_at)) if now.duration_since(*reported_at) < self.cooldown => {
+                Some(*problem)
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn constants_have_correct_values() {
+        assert_eq!(NODE_PROBLEM_COOLDOWN, Duration::from_secs(60));
+    }
+
+    #[test]
+    fn a_reported_node_cools_down_until_the_cooldown_is_over() {
+        let mut subject = NodeProblems::new(Duration::from_secs(10));
+        let now = Instant::now();
+        let key = PublicKey::new(b"booga");
+        let other_key = PublicKey::new(b"agoob");
+
+        subject.record(&key, NodeProblem::TargetUnreachable, now);
+
+        assert_eq!(subject.is_cooling_down(&key, now), true);
+        assert_eq!(
+            subject.problem_of(&key, now + Duration::from_secs(9)),
+            Some(NodeProblem::TargetUnreachable)
+        );
+        assert_eq!(
+            subject.is_cooling_down(&key, now + Duration::from_secs(10)),
+            false
+        );
+        assert_eq!(subject.is_cooling_down(&other_key, now), false);
+    }
+
+    #[test]
+    fn a_new_report_starts_the_cooldown_over() {
+        let mut subject = NodeProblems::new(Duration::from_secs(10));
+        let now = Instant::now();
+        let key = PublicKey::new(b"booga");
+
+        subject.record(&key, NodeProblem::TargetUnreachable, now);
+        subject.record(&key, NodeProblem::StreamDied, now + Duration::from_secs(5));
+
+        assert_eq!(
+            subject.problem_of(&key, now + Duration::from_secs(12)),
+            Some(NodeProblem::StreamDied)
+        );
+    }
+
+    #[test]
+    fn reports_whose_cooldown_is_over_are_forgotten() {
+        let mut subject = NodeProblems::new(Duration::from_secs(10));
+        let now = Instant::now();
+        let key = PublicKey::new(b"booga");
+
+        subject.record(&key, NodeProblem::StreamDied, now);
+        subject.record(
+            &PublicKey::new(b"agoob"),
+            NodeProblem::StreamDied,
+            now + Duration::from_secs(11),
+        );
+
+        assert_eq!(subject.reported.contains_key(&key), false);
+        assert_eq!(subject.reported.len(), 1);
+    }
+}
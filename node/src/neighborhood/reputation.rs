@@ -0,0 +1,165 @@
+// Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use crate::sub_lib::cryptde::PublicKey;
+use crate::sub_lib::neighborhood::Misbehavior;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::time::Duration;
+use std::time::Instant;
+
+pub const DEMERIT_MEMORY: Duration = Duration::from_secs(3600);
+
+#[derive(Clone, Debug, PartialEq)]
+struct Demerits {
+    count: u32,
+    last_charged: Instant,
+}
+
+// Demerits charged to each neighbor for misbehaving, and the neighbors that have run up enough of
+// them to be banned. A package that can't be delivered might be an accident once in a while; a
+// forged signature can't be. Demerits are forgotten once a neighbor has gone DEMERIT_MEMORY without
+// earning another one; bans aren't.
+pub struct Reputation {
+    threshold: u32,
+    demerits: HashMap<PublicKey, Demerits>,
+    banned: HashSet<PublicKey>,
+}
+
+impl Reputation {
+    pub fn new(threshold: u32) -> Reputation {
+        Reputation {
+            threshold,
+            demerits: HashMap::new(),
+            banned: HashSet::new(),
+        }
+    }
+
+    // True if this is the charge that gets the neighbor banned
+    pub fn charge(&mut self, key: &PublicKey, misbehavior: Misbehavior, now: Instant) -> bool {
+        if self.banned.contains(key) {
+            return false;
+        }
+        let previous = match self.demerits.get(key) {
+            Some(demerits) if now.duration_since(demerits.last_charged) < DEMERIT_MEMORY => {
+                demerits.count
+            }
+            _ => 0,
+        };
+        let count = previous + Reputation::demerits_for(misbehavior);
+        if self.threshold > 0 && count >= self.threshold {
+            self.demerits.remove(key);
+            self.ban(key);
+            true
+        } else {
+            self.demerits.insert(
+                key.clone(),
+                Demerits {
+                    count,
+                    last_charged: now,
+                },
+            );
+            false
+        }
+    }
+
+    pub fn ban(&mut self, key: &PublicKey) {
+        self.banned.insert(key.clone());
+    }
+
+    pub fn is_banned(&self, key: &PublicKey) -> bool {
+        self.banned.contains(key)
+    }
+
+    pub fn demerits(&self, key: &PublicKey, now: Instant) -> u32 {
+        match self.demerits.get(key) {
+            Some(demerits) if now.duration_since(demerits.last_charged) < DEMERIT_MEMORY => {
+                demerits.count
+            }
+            _ => 0,
+        }
+    }
+
+    fn demerits_for(misbehavior: Misbehavior) -> u32 {
+        match misbehavior {
+            Misbehavior::UndeliverablePackage => 1,
+            Misbehavior::InvalidGossipSignature => 5,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn constants_have_correct_values() {
+        assert_eq!(DEMERIT_MEMORY, Duration::from_secs(3600));
+    }
+
+    #[test]
+    fn a_neighbor_is_banned_by_the_charge_that_brings_it_to_the_threshold() {
+        let mut subject = Reputation::new(7);
+        let now = Instant::now();
+        let key = PublicKey::new(b"booga");
+        let other_key = PublicKey::new(b"agoob");
+
+        assert_eq!(
+            subject.charge(&key, Misbehavior::InvalidGossipSignature, now),
+            false
+        );
+        assert_eq!(
+            subject.charge(&key, Misbehavior::UndeliverablePackage, now),
+            false
+        );
+        assert_eq!(
+            subject.charge(&other_key, Misbehavior::UndeliverablePackage, now),
+            false
+        );
+        assert_eq!(subject.demerits(&key, now), 6);
+        assert_eq!(subject.is_banned(&key), false);
+
+        assert_eq!(
+            subject.charge(&key, Misbehavior::UndeliverablePackage, now),
+            true
+        );
+
+        assert_eq!(subject.is_banned(&key), true);
+        assert_eq!(subject.is_banned(&other_key), false);
+        assert_eq!(
+            subject.charge(&key, Misbehavior::UndeliverablePackage, now),
+            false
+        );
+    }
+
+    #[test]
+    fn demerits_are_forgotten_after_the_demerit_memory() {
+        let mut subject = Reputation::new(2);
+        let then = Instant::now();
+        let key = PublicKey::new(b"booga");
+        subject.charge(&key, Misbehavior::UndeliverablePackage, then);
+
+        assert_eq!(subject.demerits(&key, then + DEMERIT_MEMORY), 0);
+        assert_eq!(
+            subject.charge(
+                &key,
+                Misbehavior::UndeliverablePackage,
+                then + DEMERIT_MEMORY
+            ),
+            false
+        );
+        assert_eq!(subject.is_banned(&key), false);
+    }
+
+    #[test]
+    fn a_threshold_of_zero_never_bans_anybody() {
+        let mut subject = Reputation::new(0);
+        let now = Instant::now();
+        let key = PublicKey::new(b"booga");
+
+        (0..10).for_each(|_| {
+            subject.charge(&key, Misbehavior::InvalidGossipSignature, now);
+        });
+
+        assert_eq!(subject.is_banned(&key), false);
+        assert_eq!(subject.demerits(&key, now), 50);
+    }
+}
@@ -0,0 +1,406 @@
+// Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::net::IpAddr;
+use std::path::Path;
+use std::str::FromStr;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AsnEntry {
+    pub asn: u32,
+    pub country: String,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct AsnRange {
+    start: IpAddr,
+    end: IpAddr,
+    entry: AsnEntry,
+}
+
+// Which autonomous system, and which country, each block of IP addresses belongs to. Read from a
+// tab-separated file in the format of the freely-available iptoasn.com databases, one range per
+// line:
+//
+//     1.0.0.0	1.0.0.255	13335	US	CLOUDFLARENET
+//
+// Ranges may be IPv4 or IPv6. Ranges with AS number 0 are unrouted; addresses in them, like
+// addresses in no range at all, belong to no AS and no country.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AsnDatabase {
+    ranges: Vec<AsnRange>,
+}
+
+impl AsnDatabase {
+    pub fn load(path: &Path) -> Result<AsnDatabase, String> {
+        let tsv = match fs::read_to_string(path) {
+            Ok(tsv) => tsv,
+            Err(e) => return Err(format!("Couldn't read GeoIP database {:?}: {}", path, e)),
+        };
+        AsnDatabase::from_tsv(&tsv)
+            .map_err(|e| format!("Couldn't understand GeoIP database {:?}: {}", path, e))
+    }
+
+    pub fn from_tsv(tsv: &str) -> Result<AsnDatabase, String> {
+        let mut ranges = tsv
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(index, line)| {
+                AsnDatabase::parse_range(line).map_err(|e| format!("line {}: {}", index + 1, e))
+            })
+            .collect::<Result<Vec<AsnRange>, String>>()?;
+        ranges.retain(|range| range.entry.asn != 0);
+        ranges.sort_by(|a, b| a.start.cmp(&b.start));
+        Ok(AsnDatabase { ranges })
+    }
+
+    pub fn lookup(&self, ip_addr: IpAddr) -> Option<AsnEntry> {
+        let index = match self
+            .ranges
+            .binary_search_by(|range| range.start.cmp(&ip_addr))
+        {
+            Ok(index) => index,
+            Err(0) => return None,
+            Err(index) => index - 1,
+        };
+        let range = &self.ranges[index];
+        if ip_addr <= range.end {
+            Some(range.entry.clone())
+        } else {
+            None
+        }
+    }
+
+    fn parse_range(line: &str) -> Result<AsnRange, String> {
+        let fields: Vec<&str> = line.split('\t').map(|field| field.trim()).collect();
+        if fields.len() < 4 {
+            return Err(format!(
+                "expected at least 4 fields, found {}",
+                fields.len()
+            ));
+        }
+        let parse_ip = |field: &str| {
+            IpAddr::from_str(field).map_err(|_| format!("'{}' is not an IP address", field))
+        };
+        let start = parse_ip(fields[0])?;
+        let end = parse_ip(fields[1])?;
+        if start.is_ipv4() != end.is_ipv4() || end < start {
+            return Err(format!("'{}' to '{}' is not a range", start, end));
+        }
+        let asn =
+            u32::from_str(fields[2]).map_err(|_| format!("'{}' is not an AS number", fields[2]))?;
+        Ok(AsnRange {
+            start,
+            end,
+            entry: AsnEntry {
+                asn,
+                country: fields[3].to_uppercase(),
+            },
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum RouteExclusion {
+    OwnAsn,
+    Asn(u32),
+    Country(String),
+    ExitCountry(String),
+}
+
+impl RouteExclusion {
+    pub fn from_name(name: &str) -> Option<RouteExclusion> {
+        let name = name.trim();
+        if name == "own_asn" {
+            return Some(RouteExclusion::OwnAsn);
+        }
+        let mut parts = name.splitn(2, ':');
+        match (parts.next(), parts.next()) {
+            (Some("asn"), Some(asn)) => u32::from_str(asn).ok().map(RouteExclusion::Asn),
+            (Some("country"), Some(country)) if !country.is_empty() => {
+                Some(RouteExclusion::Country(country.to_uppercase()))
+            }
+            (Some("exit_country"), Some(country)) if !country.is_empty() => {
+                Some(RouteExclusion::ExitCountry(country.to_uppercase()))
+            }
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RouteExclusionConfig {
+    pub database: AsnDatabase,
+    pub exclusions: Vec<RouteExclusion>,
+}
+
+// Decides which Nodes the Neighborhood may not use in a route, from where their IP addresses are.
+// Each address is looked up only once. Nodes whose addresses the database doesn't know, or that
+// have no addresses, can't be shown to break any rule and so are allowed.
+pub struct RouteExclusions {
+    config: RouteExclusionConfig,
+    cache: RefCell<HashMap<IpAddr, Option<AsnEntry>>>,
+}
+
+impl RouteExclusions {
+    pub fn new(config: RouteExclusionConfig) -> RouteExclusions {
+        RouteExclusions {
+            config,
+            cache: RefCell::new(HashMap::new()),
+        }
+    }
+
+    pub fn config(&self) -> &RouteExclusionConfig {
+        &self.config
+    }
+
+    pub fn permits_hop(
+        &self,
+        ip_addr_opt: Option<IpAddr>,
+        own_ip_addr_opt: Option<IpAddr>,
+    ) -> bool {
+        let entry = match self.entry_for(ip_addr_opt) {
+            Some(entry) => entry,
+            None => return true,
+        };
+        let own_asn_opt = self.entry_for(own_ip_addr_opt).map(|own| own.asn);
+        !self
+            .config
+            .exclusions
+            .iter()
+            .any(|exclusion| match exclusion {
+                RouteExclusion::OwnAsn => own_asn_opt == Some(entry.asn),
+                RouteExclusion::Asn(asn) => *asn == entry.asn,
+                RouteExclusion::Country(country) => *country == entry.country,
+                RouteExclusion::ExitCountry(_) => false,
+            })
+    }
+
+    pub fn permits_exit(&self, ip_addr_opt: Option<IpAddr>) -> bool {
+        let entry = match self.entry_for(ip_addr_opt) {
+            Some(entry) => entry,
+            None => return true,
+        };
+        !self
+            .config
+            .exclusions
+            .iter()
+            .any(|exclusion| match exclusion {
+                RouteExclusion::ExitCountry(country) => *country == entry.country,
+                _ => false,
+            })
+    }
+
+    pub fn country_of(&self, ip_addr_opt: Option<IpAddr>) -> Option<String> {
+        self.entry_for(ip_addr_opt).map(|entry| entry.country)
+    }
+
+    fn entry_for(&self, ip_addr_opt: Option<IpAddr>) -> Option<AsnEntry> {
+        let ip_addr = ip_addr_opt?;
+        let mut cache = self.cache.borrow_mut();
+        let database = &self.config.database;
+        cache
+            .entry(ip_addr)
+            .or_insert_with(|| database.lookup(ip_addr))
+            .clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    const BASE_TEST_DIR: &str = "generated/test/route_exclusion";
+
+    const TSV: &str = "1.2.0.0\t1.2.255.255\t7922\tUS\tCOMCAST-7922\n\
+                       5.6.0.0\t5.6.255.255\t3320\tde\tDTAG\n\
+                       9.0.0.0\t9.0.0.255\t0\tNone\tNot routed\n\
+                       2001:db8::\t2001:db8::ffff\t7922\tUS\tCOMCAST-7922\n";
+
+    fn ip(s: &str) -> IpAddr {
+        IpAddr::from_str(s).unwrap()
+    }
+
+    fn make_subject(exclusions: Vec<RouteExclusion>) -> RouteExclusions {
+        RouteExclusions::new(RouteExclusionConfig {
+            database: AsnDatabase::from_tsv(TSV).unwrap(),
+            exclusions,
+        })
+    }
+
+    #[test]
+    fn lookup_finds_the_range_an_address_is_in() {
+        let subject = AsnDatabase::from_tsv(TSV).unwrap();
+
+        let us = Some(AsnEntry {
+            asn: 7922,
+            country: String::from("US"),
+        });
+        assert_eq!(subject.lookup(ip("1.2.0.0")), us);
+        assert_eq!(subject.lookup(ip("1.2.3.4")), us);
+        assert_eq!(subject.lookup(ip("1.2.255.255")), us);
+        assert_eq!(subject.lookup(ip("2001:db8::1234")), us);
+        assert_eq!(
+            subject.lookup(ip("5.6.7.8")),
+            Some(AsnEntry {
+                asn: 3320,
+                country: String::from("DE"),
+            })
+        );
+        assert_eq!(subject.lookup(ip("1.1.255.255")), None);
+        assert_eq!(subject.lookup(ip("1.3.0.0")), None);
+        assert_eq!(subject.lookup(ip("9.0.0.1")), None);
+        assert_eq!(subject.lookup(ip("0.0.0.1")), None);
+    }
+
+    #[test]
+    fn from_tsv_complains_about_a_bad_line() {
+        let result =
+            AsnDatabase::from_tsv("1.2.0.0\t1.2.255.255\t7922\tUS\tX\n1.3.0.0\tbooga\t1\tUS\n");
+
+        assert_eq!(
+            result,
+            Err(String::from("line 2: 'booga' is not an IP address"))
+        );
+    }
+
+    #[test]
+    fn from_tsv_complains_about_a_backwards_range() {
+        let result = AsnDatabase::from_tsv("1.2.255.255\t1.2.0.0\t7922\tUS\tX\n");
+
+        assert_eq!(
+            result,
+            Err(String::from(
+                "line 1: '1.2.255.255' to '1.2.0.0' is not a range"
+            ))
+        );
+    }
+
+    #[test]
+    fn load_complains_about_a_missing_file() {
+        let path = PathBuf::from(format!("{}/nonexistent.tsv", BASE_TEST_DIR));
+
+        let result = AsnDatabase::load(&path);
+
+        let message = result.err().unwrap();
+        assert_eq!(
+            message.starts_with(&format!("Couldn't read GeoIP database {:?}: ", path)),
+            true,
+            "{}",
+            message
+        );
+    }
+
+    #[test]
+    fn load_reads_the_database_from_a_file() {
+        let directory = PathBuf::from(format!(
+            "{}/load_reads_the_database_from_a_file",
+            BASE_TEST_DIR
+        ));
+        fs::remove_dir_all(&directory).is_ok();
+        fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("ip2asn.tsv");
+        fs::write(&path, TSV).unwrap();
+
+        let result = AsnDatabase::load(&path).unwrap();
+
+        assert_eq!(result, AsnDatabase::from_tsv(TSV).unwrap());
+    }
+
+    #[test]
+    fn exclusions_are_parsed_from_their_names() {
+        assert_eq!(
+            RouteExclusion::from_name("own_asn"),
+            Some(RouteExclusion::OwnAsn)
+        );
+        assert_eq!(
+            RouteExclusion::from_name("asn:7922"),
+            Some(RouteExclusion::Asn(7922))
+        );
+        assert_eq!(
+            RouteExclusion::from_name("country:de"),
+            Some(RouteExclusion::Country(String::from("DE")))
+        );
+        assert_eq!(
+            RouteExclusion::from_name(" exit_country:US "),
+            Some(RouteExclusion::ExitCountry(String::from("US")))
+        );
+        assert_eq!(RouteExclusion::from_name("asn:booga"), None);
+        assert_eq!(RouteExclusion::from_name("country:"), None);
+        assert_eq!(RouteExclusion::from_name("booga"), None);
+    }
+
+    #[test]
+    fn hops_in_excluded_systems_and_countries_are_not_permitted() {
+        let subject = make_subject(vec![
+            RouteExclusion::Asn(3320),
+            RouteExclusion::Country(String::from("FR")),
+        ]);
+
+        assert_eq!(subject.permits_hop(Some(ip("5.6.7.8")), None), false);
+        assert_eq!(subject.permits_hop(Some(ip("1.2.3.4")), None), true);
+        assert_eq!(subject.permits_hop(Some(ip("7.7.7.7")), None), true);
+        assert_eq!(subject.permits_hop(None, None), true);
+
+        let subject = make_subject(vec![RouteExclusion::Country(String::from("US"))]);
+
+        assert_eq!(subject.permits_hop(Some(ip("2001:db8::1")), None), false);
+    }
+
+    #[test]
+    fn hops_in_the_originators_own_system_are_not_permitted_if_it_is_known() {
+        let subject = make_subject(vec![RouteExclusion::OwnAsn]);
+
+        assert_eq!(
+            subject.permits_hop(Some(ip("1.2.3.4")), Some(ip("1.2.200.200"))),
+            false
+        );
+        assert_eq!(
+            subject.permits_hop(Some(ip("5.6.7.8")), Some(ip("1.2.200.200"))),
+            true
+        );
+        assert_eq!(
+            subject.permits_hop(Some(ip("1.2.3.4")), Some(ip("7.7.7.7"))),
+            true
+        );
+        assert_eq!(subject.permits_hop(Some(ip("1.2.3.4")), None), true);
+    }
+
+    #[test]
+    fn exits_in_excluded_countries_are_not_permitted_but_hops_there_are() {
+        let subject = make_subject(vec![RouteExclusion::ExitCountry(String::from("DE"))]);
+
+        assert_eq!(subject.permits_exit(Some(ip("5.6.7.8"))), false);
+        assert_eq!(subject.permits_exit(Some(ip("1.2.3.4"))), true);
+        assert_eq!(subject.permits_exit(None), true);
+        assert_eq!(subject.permits_hop(Some(ip("5.6.7.8")), None), true);
+    }
+
+    #[test]
+    fn countries_are_found_for_addresses_the_database_knows() {
+        let subject = make_subject(vec![]);
+
+        assert_eq!(
+            subject.country_of(Some(ip("5.6.7.8"))),
+            Some(String::from("DE"))
+        );
+        assert_eq!(subject.country_of(Some(ip("7.7.7.7"))), None);
+        assert_eq!(subject.country_of(None), None);
+    }
+
+    #[test]
+    fn each_address_is_looked_up_only_once() {
+        let subject = make_subject(vec![RouteExclusion::Asn(3320)]);
+
+        subject.permits_hop(Some(ip("5.6.7.8")), None);
+        subject.permits_exit(Some(ip("5.6.7.8")));
+        subject.permits_hop(Some(ip("7.7.7.7")), None);
+
+        let cache = subject.cache.borrow();
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get(&ip("7.7.7.7")), Some(&None));
+    }
+}
@@ -0,0 +1,224 @@
+// Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use crate::sub_lib::cryptde::PublicKey;
+use crate::sub_lib::neighborhood::RouteOutcome;
+use std::collections::HashMap;
+use std::time::Duration;
+use std::time::Instant;
+
+pub const MAXIMUM_ROUTE_SCORE: u8 = 100;
+pub const SCORE_DAMPING_FACTOR: u32 = 4;
+pub const HALF_SCORE_LATENCY_MILLIS: u32 = 1000;
+pub const SCORE_MEMORY: Duration = Duration::from_secs(600);
+
+#[derive(Clone, Debug, PartialEq)]
+struct NodeScore {
+    latency_millis_opt: Option<u32>,
+    delivery_percent: u8,
+    last_sample: Instant,
+}
+
+impl NodeScore {
+    fn stranger(now: Instant) -> NodeScore {
+        NodeScore {
+            latency_millis_opt: None,
+            delivery_percent: MAXIMUM_ROUTE_SCORE,
+            last_sample: now,
+        }
+    }
+
+    // Delivery counts for as much as it's worth; a Node whose round trips take
+    // HALF_SCORE_LATENCY_MILLIS scores half what it would if they took no time at all. Until a
+    // round trip has been seen, that's the latency the Node is assumed to have.
+    fn score(&self) -> u8 {
+        let latency_millis = self.latency_millis_opt.unwrap_or(HALF_SCORE_LATENCY_MILLIS);
+        (u32::from(self.delivery_percent) * HALF_SCORE_LATENCY_MILLIS
+            / (HALF_SCORE_LATENCY_MILLIS + latency_millis)) as u8
+    }
+}
+
+// How well the routes this Node has been given have actually worked, remembered for each Node they
+// passed through. A whole round trip's latency, or its failure, is charged to every Node on it:
+// there's no telling from here which one was slow. Nodes that have been through good routes and
+// bad ones alike come out in between, and a Node that hasn't been heard about for SCORE_MEMORY
+// is a stranger again.
+pub struct RouteScores {
+    scores: HashMap<PublicKey, NodeScore>,
+}
+
+impl RouteScores {
+    pub fn new() -> RouteScores {
+        RouteScores {
+            scores: HashMap::new(),
+        }
+    }
+
+    pub fn record(&mut self, keys: &[PublicKey], outcome: RouteOutcome, now: Instant) {
+        keys.iter().for_each(|key| {
+            let previous = match self.scores.get(key) {
+                Some(score) if now.duration_since(score.last_sample) < SCORE_MEMORY => {
+                    score.clone()
+                }
+                _ => NodeScore::stranger(now),
+            };
+            let next = match outcome {
+                RouteOutcome::Responded(latency) => {
+                    let sample = latency.as_secs() as u32 * 1000 + latency.subsec_millis();
+                    NodeScore {
+                        latency_millis_opt: Some(match previous.latency_millis_opt {
+                            Some(latency_millis) => RouteScores::damp(latency_millis, sample),
+                            None => sample,
+                        }),
+                        delivery_percent: RouteScores::damp(
+                            u32::from(previous.delivery_percent),
+                            u32::from(MAXIMUM_ROUTE_SCORE),
+                        ) as u8,
+                        last_sample: now,
+                    }
+                }
+                RouteOutcome::Failed => NodeScore {
+                    latency_millis_opt: previous.latency_millis_opt,
+                    delivery_percent: RouteScores::damp(u32::from(previous.delivery_percent), 0)
+                        as u8,
+                    last_sample: now,
+                },
+                // Delivered, but not the way it was advertised: half credit
+                RouteOutcome::Throttled => NodeScore {
+                    latency_millis_opt: previous.latency_millis_opt,
+                    delivery_percent: RouteScores::damp(
+                        u32::from(previous.delivery_percent),
+                        u32::from(MAXIMUM_ROUTE_SCORE / 2),
+                    ) as u8,
+                    last_sample: now,
+                },
+            };
+            self.scores.insert(key.clone(), next);
+        });
+    }
+
+    pub fn node_score(&self, key: &PublicKey, now: Instant) -> u8 {
+        match self.scores.get(key) {
+            Some(score) if now.duration_since(score.last_sample) < SCORE_MEMORY => score.score(),
+            _ => NodeScore::stranger(now).score(),
+        }
+    }
+
+    // A route is only as good as the worst Node on it
+    pub fn route_score(&self, keys: &[&PublicKey], now: Instant) -> u8 {
+        keys.iter()
+            .map(|key| self.node_score(key, now))
+            .min()
+            .unwrap_or(MAXIMUM_ROUTE_SCORE)
+    }
+
+    fn damp(previous: u32, sample: u32) -> u32 {
+        // Rounded up, so that a run of good samples can bring a score all the way back
+        (previous * (SCORE_DAMPING_FACTOR - 1) + sample + SCORE_DAMPING_FACTOR - 1)
+            / SCORE_DAMPING_FACTOR
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn constants_have_correct_values() {
+        assert_eq!(MAXIMUM_ROUTE_SCORE, 100);
+        assert_eq!(SCORE_DAMPING_FACTOR, 4);
+        assert_eq!(HALF_SCORE_LATENCY_MILLIS, 1000);
+        assert_eq!(SCORE_MEMORY, Duration::from_secs(600));
+    }
+
+    #[test]
+    fn nodes_nobody_has_reported_on_score_as_if_they_took_a_second_to_respond() {
+        let subject = RouteScores::new();
+
+        let result = subject.node_score(&PublicKey::new(b"booga"), Instant::now());
+
+        assert_eq!(result, 50);
+    }
+
+    #[test]
+    fn round_trips_and_failures_are_damped_into_the_score_of_every_node_on_the_route() {
+        let mut subject = RouteScores::new();
+        let now = Instant::now();
+        let one = PublicKey::new(b"one");
+        let another = PublicKey::new(b"another");
+
+        subject.record(
+            &[one.clone(), another.clone()],
+            RouteOutcome::Responded(Duration::from_millis(250)),
+            now,
+        );
+        assert_eq!(subject.node_score(&one, now), 80);
+        assert_eq!(subject.node_score(&another, now), 80);
+
+        subject.record(
+            &[one.clone()],
+            RouteOutcome::Responded(Duration::from_millis(1250)),
+            now,
+        );
+        assert_eq!(subject.node_score(&one, now), 66);
+
+        subject.record(&[one.clone()], RouteOutcome::Failed, now);
+        assert_eq!(subject.node_score(&one, now), 50);
+        assert_eq!(subject.node_score(&another, now), 80);
+    }
+
+    #[test]
+    fn throttling_costs_a_node_less_than_failing() {
+        let mut subject = RouteScores::new();
+        let now = Instant::now();
+        let throttled = PublicKey::new(b"throttled");
+        let failed = PublicKey::new(b"failed");
+        subject.record(
+            &[throttled.clone(), failed.clone()],
+            RouteOutcome::Responded(Duration::from_millis(0)),
+            now,
+        );
+
+        subject.record(&[throttled.clone()], RouteOutcome::Throttled, now);
+        subject.record(&[failed.clone()], RouteOutcome::Failed, now);
+
+        assert_eq!(subject.node_score(&throttled, now), 88);
+        assert_eq!(subject.node_score(&failed, now), 75);
+    }
+
+    #[test]
+    fn route_score_is_the_score_of_its_worst_node() {
+        let mut subject = RouteScores::new();
+        let now = Instant::now();
+        let fast = PublicKey::new(b"fast");
+        let slow = PublicKey::new(b"slow");
+        subject.record(
+            &[fast.clone()],
+            RouteOutcome::Responded(Duration::from_millis(0)),
+            now,
+        );
+        subject.record(
+            &[slow.clone()],
+            RouteOutcome::Responded(Duration::from_millis(3000)),
+            now,
+        );
+
+        assert_eq!(subject.route_score(&[&fast], now), 100);
+        assert_eq!(subject.route_score(&[&fast, &slow], now), 25);
+        assert_eq!(subject.route_score(&[], now), 100);
+    }
+
+    #[test]
+    fn reports_older_than_the_score_memory_are_forgotten() {
+        let mut subject = RouteScores::new();
+        let then = Instant::now();
+        let now = then + SCORE_MEMORY;
+        let key = PublicKey::new(b"booga");
+        subject.record(&[key.clone()], RouteOutcome::Failed, then);
+        subject.record(&[key.clone()], RouteOutcome::Failed, then);
+
+        assert_eq!(subject.node_score(&key, now), 50);
+
+        subject.record(&[key.clone()], RouteOutcome::Failed, now);
+
+        assert_eq!(subject.node_score(&key, now), 37);
+    }
+}
@@ -0,0 +1,305 @@
+// Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use super::neighborhood_database::NeighborhoodDatabase;
+use super::neighborhood_database::NodeRecord;
+use crate::sub_lib::cryptde::PublicKey;
+use crate::sub_lib::neighborhood::NodeCapability;
+use crate::sub_lib::neighborhood::TopologyFilter;
+use crate::sub_lib::neighborhood::TopologyNode;
+use crate::sub_lib::neighborhood::TopologyPage;
+use crate::sub_lib::neighborhood::TopologyQueryMessage;
+use crate::sub_lib::neighborhood::TopologyScope;
+
+// Every record in scope has to be looked at to find a page, but only the records on the page are
+// turned into TopologyNodes, so a big Neighborhood costs a sort, not a huge answer.
+pub fn topology_page(
+    database: &NeighborhoodDatabase,
+    query: &TopologyQueryMessage,
+    country_of: &dyn Fn(&NodeRecord) -> Option<String>,
+) -> TopologyPage {
+    let keys: Vec<&PublicKey> = match query.scope {
+        TopologyScope::All => database.keys().into_iter().collect(),
+        TopologyScope::Neighbors => database.root().neighbors().iter().collect(),
+    };
+    let mut matching: Vec<(String, &NodeRecord)> = keys
+        .into_iter()
+        .filter_map(|key| database.node_by_key(key))
+        .map(|node| (format!("{}", node.public_key()), node))
+        .filter(|(cursor_key, _)| match &query.cursor {
+            Some(cursor) => cursor_key > cursor,
+            None => true,
+        })
+        .filter(|(_, node)| matches_filter(node, &query.filter, country_of))
+        .collect();
+    matching.sort_by(|(a, _), (b, _)| a.cmp(b));
+    let page_size = query.effective_page_size();
+    let next_cursor = if matching.len() > page_size {
+        matching.truncate(page_size);
+        matching.last().map(|(cursor_key, _)| cursor_key.clone())
+    } else {
+        None
+    };
+    TopologyPage {
+        nodes: matching
+            .into_iter()
+            .map(|(public_key, node)| TopologyNode {
+                public_key,
+                node_addr: node
+                    .node_addr_opt()
+                    .map(|node_addr| format!("{}", node_addr)),
+                version: node.version(),
+                country: country_of(node),
+                is_bootstrap_node: node.is_bootstrap_node(),
+                offers_exit_tls: node.offers_exit_tls(),
+                relay_only: node.relay_only(),
+                neighbors: node
+                    .neighbors()
+                    .iter()
+                    .map(|key| format!("{}", key))
+                    .collect(),
+            })
+            .collect(),
+        next_cursor,
+    }
+}
+
+fn matches_filter(
+    node: &NodeRecord,
+    filter: &TopologyFilter,
+    country_of: &dyn Fn(&NodeRecord) -> Option<String>,
+) -> bool {
+    let can_exit = !node.is_bootstrap_node() && !node.relay_only();
+    let capable = match filter.capability {
+        None => true,
+        Some(NodeCapability::Exit) => can_exit,
+        Some(NodeCapability::ExitTls) => can_exit && node.offers_exit_tls(),
+        Some(NodeCapability::RelayOnly) => node.relay_only(),
+        Some(NodeCapability::Bootstrap) => node.is_bootstrap_node(),
+    };
+    let recent_enough = match filter.min_version {
+        None => true,
+        Some(min_version) => node.version() >= min_version,
+    };
+    let located = match &filter.country {
+        None => true,
+        Some(country) => match country_of(node) {
+            Some(node_country) => node_country.eq_ignore_ascii_case(country),
+            None => false,
+        },
+    };
+    capable && recent_enough && located
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::neighborhood::neighborhood_test_utils::make_node_record;
+    use crate::sub_lib::cryptde_null::CryptDENull;
+
+    fn make_database(
+        other_nodes: &[&NodeRecord],
+        neighbors: &[&NodeRecord],
+    ) -> NeighborhoodDatabase {
+        let this_node = make_node_record(1000, true, false);
+        let mut database = NeighborhoodDatabase::new(
+            this_node.public_key(),
+            this_node.node_addr_opt().as_ref().unwrap(),
+            this_node.earning_wallet(),
+            this_node.consuming_wallet(),
+            this_node.is_bootstrap_node(),
+            &CryptDENull::from(this_node.public_key()),
+        );
+        other_nodes
+            .iter()
+            .for_each(|node| database.add_node(node).unwrap());
+        neighbors.iter().for_each(|node| {
+            database
+                .add_neighbor(this_node.public_key(), node.public_key())
+                .unwrap();
+        });
+        database
+    }
+
+    fn make_query(page_size: usize) -> TopologyQueryMessage {
+        TopologyQueryMessage {
+            page_size,
+            ..TopologyQueryMessage::from_json("{}").unwrap()
+        }
+    }
+
+    fn no_country(_node: &NodeRecord) -> Option<String> {
+        None
+    }
+
+    fn keys_of(page: &TopologyPage) -> Vec<String> {
+        page.nodes
+            .iter()
+            .map(|node| node.public_key.clone())
+            .collect()
+    }
+
+    #[test]
+    fn pages_cover_every_record_once_in_key_order() {
+        let others: Vec<NodeRecord> = (2..7)
+            .map(|n| make_node_record(n * 1111, true, false))
+            .collect();
+        let database = make_database(&others.iter().collect::<Vec<_>>(), &[]);
+        let mut all_keys: Vec<String> = database
+            .keys()
+            .into_iter()
+            .map(|key| format!("{}", key))
+            .collect();
+        all_keys.sort();
+
+        let first_page = topology_page(&database, &make_query(4), &no_country);
+        let second_page = topology_page(
+            &database,
+            &TopologyQueryMessage {
+                cursor: first_page.next_cursor.clone(),
+                ..make_query(4)
+            },
+            &no_country,
+        );
+
+        assert_eq!(keys_of(&first_page), all_keys[0..4].to_vec());
+        assert_eq!(first_page.next_cursor, Some(all_keys[3].clone()));
+        assert_eq!(keys_of(&second_page), all_keys[4..].to_vec());
+        assert_eq!(second_page.next_cursor, None);
+    }
+
+    #[test]
+    fn a_cursor_that_matches_no_record_still_picks_up_after_where_it_would_be() {
+        let others: Vec<NodeRecord> = (2..7)
+            .map(|n| make_node_record(n * 1111, true, false))
+            .collect();
+        let database = make_database(&others.iter().collect::<Vec<_>>(), &[]);
+        let mut all_keys: Vec<String> = database
+            .keys()
+            .into_iter()
+            .map(|key| format!("{}", key))
+            .collect();
+        all_keys.sort();
+        let cursor = format!("{}0", all_keys[1]);
+
+        let result = topology_page(
+            &database,
+            &TopologyQueryMessage {
+                cursor: Some(cursor),
+                ..make_query(100)
+            },
+            &no_country,
+        );
+
+        assert_eq!(keys_of(&result), all_keys[2..].to_vec());
+        assert_eq!(result.next_cursor, None);
+    }
+
+    #[test]
+    fn neighbors_scope_reports_only_the_nodes_this_one_is_connected_to() {
+        let neighbor = make_node_record(2345, true, false);
+        let stranger = make_node_record(3456, true, false);
+        let database = make_database(&[&neighbor, &stranger], &[&neighbor]);
+
+        let result = topology_page(
+            &database,
+            &TopologyQueryMessage {
+                scope: TopologyScope::Neighbors,
+                ..make_query(100)
+            },
+            &no_country,
+        );
+
+        assert_eq!(
+            result,
+            TopologyPage {
+                nodes: vec![TopologyNode {
+                    public_key: format!("{}", neighbor.public_key()),
+                    node_addr: Some(format!("{}", neighbor.node_addr_opt().unwrap())),
+                    version: 0,
+                    country: None,
+                    is_bootstrap_node: false,
+                    offers_exit_tls: false,
+                    relay_only: false,
+                    neighbors: vec![],
+                }],
+                next_cursor: None,
+            }
+        );
+    }
+
+    #[test]
+    fn nodes_are_filtered_by_capability() {
+        let plain = make_node_record(2345, true, false);
+        let mut exit_tls = make_node_record(3456, true, false);
+        exit_tls.set_offers_exit_tls(true);
+        let mut relay_only = make_node_record(4567, true, false);
+        relay_only.set_relay_only(true);
+        relay_only.set_offers_exit_tls(true);
+        let bootstrap = make_node_record(5678, true, true);
+        let database = make_database(&[&plain, &exit_tls, &relay_only, &bootstrap], &[]);
+        let query = |capability| TopologyQueryMessage {
+            scope: TopologyScope::All,
+            filter: TopologyFilter {
+                capability: Some(capability),
+                ..TopologyFilter::default()
+            },
+            ..make_query(100)
+        };
+        let key = |node: &NodeRecord| format!("{}", node.public_key());
+
+        let exit_tls_result =
+            topology_page(&database, &query(NodeCapability::ExitTls), &no_country);
+        let relay_only_result =
+            topology_page(&database, &query(NodeCapability::RelayOnly), &no_country);
+        let bootstrap_result =
+            topology_page(&database, &query(NodeCapability::Bootstrap), &no_country);
+        let exit_result = topology_page(&database, &query(NodeCapability::Exit), &no_country);
+
+        assert_eq!(keys_of(&exit_tls_result), vec![key(&exit_tls)]);
+        assert_eq!(keys_of(&relay_only_result), vec![key(&relay_only)]);
+        assert_eq!(keys_of(&bootstrap_result), vec![key(&bootstrap)]);
+        assert_eq!(keys_of(&exit_result).contains(&key(&plain)), true);
+        assert_eq!(keys_of(&exit_result).contains(&key(&exit_tls)), true);
+        assert_eq!(keys_of(&exit_result).contains(&key(&relay_only)), false);
+        assert_eq!(keys_of(&exit_result).contains(&key(&bootstrap)), false);
+    }
+
+    #[test]
+    fn nodes_are_filtered_by_country_and_version() {
+        let mut german_new = make_node_record(2345, true, false);
+        german_new.set_version(5);
+        let german_old = make_node_record(3456, true, false);
+        let mut american_new = make_node_record(4567, true, false);
+        american_new.set_version(5);
+        let mut unknown_new = make_node_record(5678, false, false);
+        unknown_new.set_version(5);
+        let database = make_database(
+            &[&german_new, &german_old, &american_new, &unknown_new],
+            &[],
+        );
+        let american_key = american_new.public_key().clone();
+        let country_of = move |node: &NodeRecord| match node.node_addr_opt() {
+            None => None,
+            Some(_) if node.public_key() == &american_key => Some(String::from("US")),
+            Some(_) => Some(String::from("DE")),
+        };
+
+        let result = topology_page(
+            &database,
+            &TopologyQueryMessage {
+                filter: TopologyFilter {
+                    capability: None,
+                    country: Some(String::from("de")),
+                    min_version: Some(3),
+                },
+                ..make_query(100)
+            },
+            &country_of,
+        );
+
+        assert_eq!(
+            keys_of(&result),
+            vec![format!("{}", german_new.public_key())]
+        );
+        assert_eq!(result.nodes[0].country, Some(String::from("DE")));
+    }
+}
@@ -0,0 +1,987 @@
+// Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use crate::bootstrapper::Bootstrapper;
+use crate::proxy_client::resolver_wrapper::ResolverWrapperFactory;
+use crate::proxy_client::resolver_wrapper::ResolverWrapperFactoryReal;
+use crate::sub_lib::main_tools::Command;
+use crate::sub_lib::main_tools::StdStreams;
+use crate::sub_lib::node_addr::NodeAddr;
+use crate::sub_lib::parameter_finder::ParameterFinder;
+use futures::future::lazy;
+use rand;
+use serde_derive::Serialize;
+use serde_json;
+use std::fmt;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::io;
+use std::io::Write;
+use std::net::IpAddr;
+use std::net::Ipv4Addr;
+use std::net::Ipv6Addr;
+use std::net::SocketAddr;
+use std::net::TcpStream;
+use std::net::ToSocketAddrs;
+use std::net::UdpSocket;
+use std::time::Duration;
+use std::time::Instant;
+use tokio::runtime::Runtime;
+use trust_dns_resolver::config::NameServerConfig;
+use trust_dns_resolver::config::Protocol;
+use trust_dns_resolver::config::ResolverConfig;
+use trust_dns_resolver::config::ResolverOpts;
+
+pub const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+// IANA keeps this one around for exactly this kind of thing
+pub const PROBE_HOSTNAME: &str = "example.com";
+pub const STUN_SERVERS: &[&str] = &["stun.l.google.com:19302", "stun1.l.google.com:19302"];
+
+const STUN_BINDING_REQUEST: u16 = 0x0001;
+const STUN_BINDING_SUCCESS: u16 = 0x0101;
+const STUN_MAPPED_ADDRESS: u16 = 0x0001;
+const STUN_XOR_MAPPED_ADDRESS: u16 = 0x0020;
+const STUN_MAGIC_COOKIE: u32 = 0x2112_A442;
+
+// What the probe needs from the command line the Node would be started with
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProbeConfig {
+    pub ip_addr_opt: Option<IpAddr>,
+    pub neighbor_addrs: Vec<NodeAddr>,
+    pub dns_servers: Vec<SocketAddr>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProbeStatus {
+    Pass,
+    Warn,
+    Fail,
+    Skip,
+}
+
+impl Display for ProbeStatus {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ProbeStatus::Pass => "PASS",
+            ProbeStatus::Warn => "WARN",
+            ProbeStatus::Fail => "FAIL",
+            ProbeStatus::Skip => "SKIP",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ProbeResult {
+    pub check: String,
+    pub status: ProbeStatus,
+    pub detail: String,
+}
+
+impl ProbeResult {
+    fn new(check: &str, status: ProbeStatus, detail: String) -> ProbeResult {
+        ProbeResult {
+            check: String::from(check),
+            status,
+            detail,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ProbeReport {
+    pub results: Vec<ProbeResult>,
+}
+
+impl Display for ProbeReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for result in &self.results {
+            writeln!(
+                f,
+                "{}  {:<26}  {}",
+                result.status, result.check, result.detail
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl ProbeReport {
+    // Warnings are things to know about, not reasons a Node can't run
+    pub fn exit_code(&self) -> u8 {
+        if self
+            .results
+            .iter()
+            .any(|result| result.status == ProbeStatus::Fail)
+        {
+            1
+        } else {
+            0
+        }
+    }
+}
+
+pub trait ProbeNetwork {
+    fn resolve(&self, host_and_port: &str) -> io::Result<Vec<SocketAddr>>;
+    fn tcp_connect(&self, addr: SocketAddr, timeout: Duration) -> io::Result<()>;
+    fn local_ip_toward(&self, addr: SocketAddr) -> io::Result<IpAddr>;
+    // Asks each STUN server in turn, from the same local port, where the request came from
+    fn stun_bindings(
+        &self,
+        servers: &[SocketAddr],
+        timeout: Duration,
+    ) -> io::Result<Vec<Result<SocketAddr, String>>>;
+    fn dns_lookup(
+        &self,
+        dns_server: SocketAddr,
+        hostname: &str,
+        timeout: Duration,
+    ) -> Result<Vec<IpAddr>, String>;
+}
+
+pub struct ProbeNetworkReal {}
+
+impl ProbeNetwork for ProbeNetworkReal {
+    fn resolve(&self, host_and_port: &str) -> io::Result<Vec<SocketAddr>> {
+        host_and_port.to_socket_addrs().map(|addrs| addrs.collect())
+    }
+
+    fn tcp_connect(&self, addr: SocketAddr, timeout: Duration) -> io::Result<()> {
+        TcpStream::connect_timeout(&addr, timeout).map(|_| ())
+    }
+
+    fn local_ip_toward(&self, addr: SocketAddr) -> io::Result<IpAddr> {
+        // Connecting a UDP socket sends nothing; it only picks the interface
+        let socket = UdpSocket::bind(SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0))?;
+        socket.connect(addr)?;
+        Ok(socket.local_addr()?.ip())
+    }
+
+    fn stun_bindings(
+        &self,
+        servers: &[SocketAddr],
+        timeout: Duration,
+    ) -> io::Result<Vec<Result<SocketAddr, String>>> {
+        let socket = UdpSocket::bind(SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0))?;
+        socket.set_read_timeout(Some(timeout))?;
+        Ok(servers
+            .iter()
+            .map(|server| {
+                let transaction_id: [u8; 12] = rand::random();
+                socket
+                    .send_to(&make_stun_binding_request(&transaction_id), server)
+                    .map_err(|e| format!("{}", e))?;
+                let mut buffer = [0u8; 512];
+                loop {
+                    let (length, from) = socket
+                        .recv_from(&mut buffer)
+                        .map_err(|e| format!("{}", e))?;
+                    if from == *server {
+                        return parse_stun_binding_response(&buffer[..length], &transaction_id);
+                    }
+                }
+            })
+            .collect())
+    }
+
+    fn dns_lookup(
+        &self,
+        dns_server: SocketAddr,
+        hostname: &str,
+        timeout: Duration,
+    ) -> Result<Vec<IpAddr>, String> {
+        let mut config = ResolverConfig::new();
+        config.add_name_server(NameServerConfig {
+            socket_addr: dns_server,
+            protocol: Protocol::Udp,
+            tls_dns_name: None,
+        });
+        let mut options = ResolverOpts::default();
+        options.timeout = timeout;
+        options.attempts = 1;
+        let hostname = String::from(hostname);
+        let mut runtime = Runtime::new().map_err(|e| format!("{}", e))?;
+        runtime
+            .block_on(lazy(move || {
+                ResolverWrapperFactoryReal {}
+                    .make(config, options)
+                    .lookup_ip(Some(hostname))
+            }))
+            .map(|lookup| lookup.iter().collect())
+            .map_err(|e| format!("{}", e))
+    }
+}
+
+// A one-shot look at the network the Node would be running on, for support and for deciding
+// whether a machine is worth installing on: whether it can get out on the ports that matter,
+// whether UDP works, what the rest of the Internet thinks its IP address is, what kind of NAT
+// it's behind, and whether its DNS servers answer. It prints what it found and exits.
+pub struct NetworkProbe {
+    network: Box<dyn ProbeNetwork>,
+    timeout: Duration,
+}
+
+impl Command for NetworkProbe {
+    fn go(&mut self, streams: &mut StdStreams<'_>, args: &Vec<String>) -> u8 {
+        let finder = ParameterFinder::new(args.clone());
+        let json = match finder.find_value_for("--probe", "--probe text|json") {
+            Some(ref format) if format == "text" => false,
+            Some(ref format) if format == "json" => true,
+            Some(format) => panic!("Invalid format for --probe text|json: '{}'", format),
+            None => false,
+        };
+        let report = self.probe(&Bootstrapper::probe_config(args));
+        if json {
+            writeln!(
+                streams.stdout,
+                "{}",
+                serde_json::to_string_pretty(&report).expect("Couldn't serialize report")
+            )
+            .expect("Couldn't write report");
+        } else {
+            write!(streams.stdout, "{}", report).expect("Couldn't write report");
+        }
+        report.exit_code()
+    }
+}
+
+impl NetworkProbe {
+    pub fn new() -> NetworkProbe {
+        NetworkProbe {
+            network: Box::new(ProbeNetworkReal {}),
+            timeout: PROBE_TIMEOUT,
+        }
+    }
+
+    pub fn is_requested(args: &Vec<String>) -> bool {
+        args.iter().any(|arg| arg == "--probe")
+    }
+
+    pub fn probe(&self, config: &ProbeConfig) -> ProbeReport {
+        let mut results = vec![
+            self.probe_outbound_tcp(80),
+            self.probe_outbound_tcp(443),
+            self.probe_clandestine(&config.neighbor_addrs),
+        ];
+        results.extend(self.probe_udp(config.ip_addr_opt));
+        results.extend(self.probe_dns(&config.dns_servers));
+        ProbeReport { results }
+    }
+
+    fn probe_outbound_tcp(&self, port: u16) -> ProbeResult {
+        let check = format!("outbound TCP {}", port);
+        let addr = match self.resolve(&format!("{}:{}", PROBE_HOSTNAME, port)) {
+            Ok(addr) => addr,
+            Err(e) => return ProbeResult::new(&check, ProbeStatus::Fail, e),
+        };
+        let start = Instant::now();
+        match self.network.tcp_connect(addr, self.timeout) {
+            Ok(()) => ProbeResult::new(
+                &check,
+                ProbeStatus::Pass,
+                format!(
+                    "connected to {} ({}) in {}ms",
+                    PROBE_HOSTNAME,
+                    addr,
+                    NetworkProbe::millis(start.elapsed())
+                ),
+            ),
+            Err(e) => ProbeResult::new(
+                &check,
+                ProbeStatus::Fail,
+                format!("couldn't connect to {} ({}): {}", PROBE_HOSTNAME, addr, e),
+            ),
+        }
+    }
+
+    // Other Nodes listen wherever they like, so the only way to know whether this machine can
+    // reach them is to try the ones it has been told about.
+    fn probe_clandestine(&self, neighbor_addrs: &[NodeAddr]) -> ProbeResult {
+        let check = "outbound TCP clandestine";
+        let addrs: Vec<SocketAddr> = neighbor_addrs
+            .iter()
+            .flat_map(|node_addr| {
+                let addrs: Vec<SocketAddr> = node_addr.clone().into();
+                addrs
+            })
+            .collect();
+        if addrs.is_empty() {
+            return ProbeResult::new(
+                check,
+                ProbeStatus::Skip,
+                String::from("no --neighbor to try"),
+            );
+        }
+        let failures: Vec<String> = addrs
+            .iter()
+            .filter_map(|addr| match self.network.tcp_connect(*addr, self.timeout) {
+                Ok(()) => None,
+                Err(e) => Some(format!("{}: {}", addr, e)),
+            })
+            .collect();
+        let detail = format!(
+            "connected to {} of {} neighbor address(es)",
+            addrs.len() - failures.len(),
+            addrs.len()
+        );
+        if failures.is_empty() {
+            ProbeResult::new(check, ProbeStatus::Pass, detail)
+        } else {
+            let status = if failures.len() == addrs.len() {
+                ProbeStatus::Fail
+            } else {
+                ProbeStatus::Warn
+            };
+            ProbeResult::new(
+                check,
+                status,
+                format!("{}; {}", detail, failures.join("; ")),
+            )
+        }
+    }
+
+    fn probe_udp(&self, ip_addr_opt: Option<IpAddr>) -> Vec<ProbeResult> {
+        let servers: Vec<SocketAddr> = STUN_SERVERS
+            .iter()
+            .filter_map(|server| self.resolve(server).ok())
+            .collect();
+        if servers.is_empty() {
+            return NetworkProbe::udp_failure(String::from("couldn't look up any STUN server"));
+        }
+        let mapped_addrs: Vec<SocketAddr> = match self.network.stun_bindings(&servers, self.timeout)
+        {
+            Ok(results) => results.into_iter().filter_map(|r| r.ok()).collect(),
+            Err(e) => {
+                return NetworkProbe::udp_failure(format!("couldn't send UDP: {}", e));
+            }
+        };
+        if mapped_addrs.is_empty() {
+            return NetworkProbe::udp_failure(format!(
+                "no answer from any of {} STUN server(s); UDP may be blocked",
+                servers.len()
+            ));
+        }
+        let public_ip = mapped_addrs[0].ip();
+        vec![
+            ProbeResult::new(
+                "UDP",
+                ProbeStatus::Pass,
+                format!(
+                    "{} of {} STUN server(s) answered",
+                    mapped_addrs.len(),
+                    servers.len()
+                ),
+            ),
+            NetworkProbe::public_ip_result(public_ip, ip_addr_opt),
+            self.nat_result(&servers[0], &mapped_addrs),
+        ]
+    }
+
+    // Without UDP there's no asking anyone what this machine looks like from outside
+    fn udp_failure(detail: String) -> Vec<ProbeResult> {
+        vec![
+            ProbeResult::new("UDP", ProbeStatus::Fail, detail),
+            ProbeResult::new("public IP", ProbeStatus::Skip, String::from("needs UDP")),
+            ProbeResult::new("NAT", ProbeStatus::Skip, String::from("needs UDP")),
+        ]
+    }
+
+    fn public_ip_result(public_ip: IpAddr, ip_addr_opt: Option<IpAddr>) -> ProbeResult {
+        match ip_addr_opt {
+            Some(ip_addr) if ip_addr != public_ip => ProbeResult::new(
+                "public IP",
+                ProbeStatus::Fail,
+                format!(
+                    "--ip is {}, but the Internet sees this machine as {}",
+                    ip_addr, public_ip
+                ),
+            ),
+            _ => ProbeResult::new("public IP", ProbeStatus::Pass, format!("{}", public_ip)),
+        }
+    }
+
+    // A NAT that gives the same public port for the same local port no matter where the packets
+    // go can have a port forwarded through it; one that doesn't, can't be relied on to.
+    fn nat_result(&self, first_server: &SocketAddr, mapped_addrs: &[SocketAddr]) -> ProbeResult {
+        let local_ip_opt = self.network.local_ip_toward(*first_server).ok();
+        if local_ip_opt == Some(mapped_addrs[0].ip()) {
+            return ProbeResult::new(
+                "NAT",
+                ProbeStatus::Pass,
+                String::from("none; this machine is directly on the Internet"),
+            );
+        }
+        if mapped_addrs.len() < 2 {
+            return ProbeResult::new(
+                "NAT",
+                ProbeStatus::Skip,
+                String::from("telling what kind takes answers from two STUN servers"),
+            );
+        }
+        if mapped_addrs.iter().all(|addr| *addr == mapped_addrs[0]) {
+            ProbeResult::new(
+                "NAT",
+                ProbeStatus::Pass,
+                String::from(
+                    "endpoint-independent mapping; a forwarded clandestine port will work",
+                ),
+            )
+        } else {
+            ProbeResult::new(
+                "NAT",
+                ProbeStatus::Warn,
+                String::from(
+                    "symmetric; other Nodes won't be able to reach this one unless its clandestine port is forwarded",
+                ),
+            )
+        }
+    }
+
+    fn probe_dns(&self, dns_servers: &[SocketAddr]) -> Vec<ProbeResult> {
+        if dns_servers.is_empty() {
+            return vec![ProbeResult::new(
+                "DNS",
+                ProbeStatus::Skip,
+                String::from("no --dns_servers to try"),
+            )];
+        }
+        dns_servers
+            .iter()
+            .map(|dns_server| {
+                let check = format!("DNS {}", dns_server.ip());
+                let start = Instant::now();
+                match self
+                    .network
+                    .dns_lookup(*dns_server, PROBE_HOSTNAME, self.timeout)
+                {
+                    Ok(ref ip_addrs) if !ip_addrs.is_empty() => ProbeResult::new(
+                        &check,
+                        ProbeStatus::Pass,
+                        format!(
+                            "{} is {} ({}ms)",
+                            PROBE_HOSTNAME,
+                            ip_addrs[0],
+                            NetworkProbe::millis(start.elapsed())
+                        ),
+                    ),
+                    Ok(_) => ProbeResult::new(
+                        &check,
+                        ProbeStatus::Fail,
+                        format!("no addresses for {}", PROBE_HOSTNAME),
+                    ),
+                    Err(e) => ProbeResult::new(&check, ProbeStatus::Fail, e),
+                }
+            })
+            .collect()
+    }
+
+    // The first IPv4 address, since that's what the probe's sockets speak
+    fn resolve(&self, host_and_port: &str) -> Result<SocketAddr, String> {
+        match self.network.resolve(host_and_port) {
+            Ok(addrs) => match addrs.into_iter().find(|addr| addr.is_ipv4()) {
+                Some(addr) => Ok(addr),
+                None => Err(format!("no IPv4 address for {}", host_and_port)),
+            },
+            Err(e) => Err(format!("couldn't look up {}: {}", host_and_port, e)),
+        }
+    }
+
+    fn millis(duration: Duration) -> u64 {
+        duration.as_secs() * 1000 + u64::from(duration.subsec_millis())
+    }
+}
+
+fn make_stun_binding_request(transaction_id: &[u8; 12]) -> Vec<u8> {
+    let mut request = vec![];
+    request.extend_from_slice(&STUN_BINDING_REQUEST.to_be_bytes());
+    request.extend_from_slice(&0u16.to_be_bytes());
+    request.extend_from_slice(&STUN_MAGIC_COOKIE.to_be_bytes());
+    request.extend_from_slice(transaction_id);
+    request
+}
+
+fn parse_stun_binding_response(
+    response: &[u8],
+    transaction_id: &[u8; 12],
+) -> Result<SocketAddr, String> {
+    if response.len() < 20 {
+        return Err(format!("STUN response too short: {} bytes", response.len()));
+    }
+    if u16::from_be_bytes([response[0], response[1]]) != STUN_BINDING_SUCCESS {
+        return Err(String::from("STUN server refused the binding request"));
+    }
+    if response[8..20] != transaction_id[..] {
+        return Err(String::from("STUN response is for some other request"));
+    }
+    let length = u16::from_be_bytes([response[2], response[3]]) as usize;
+    let attributes = &response[20..response.len().min(20 + length)];
+    let mut offset = 0;
+    let mut mapped_addr_opt = None;
+    while offset + 4 <= attributes.len() {
+        let kind = u16::from_be_bytes([attributes[offset], attributes[offset + 1]]);
+        let value_length =
+            u16::from_be_bytes([attributes[offset + 2], attributes[offset + 3]]) as usize;
+        let value_end = offset + 4 + value_length;
+        if value_end > attributes.len() {
+            break;
+        }
+        let value = &attributes[(offset + 4)..value_end];
+        match kind {
+            STUN_XOR_MAPPED_ADDRESS => {
+                return parse_stun_address(value, Some(&response[4..20]));
+            }
+            STUN_MAPPED_ADDRESS => mapped_addr_opt = Some(parse_stun_address(value, None)),
+            _ => (),
+        }
+        // Attribute values are padded out to a multiple of four bytes
+        offset = value_end + ((4 - value_length % 4) % 4);
+    }
+    mapped_addr_opt.unwrap_or_else(|| Err(String::from("STUN response has no mapped address")))
+}
+
+// The XOR mask, when there is one, is the magic cookie followed by the transaction ID
+fn parse_stun_address(value: &[u8], mask_opt: Option<&[u8]>) -> Result<SocketAddr, String> {
+    let unmask = |bytes: &[u8]| -> Vec<u8> {
+        match mask_opt {
+            Some(mask) => bytes.iter().zip(mask.iter()).map(|(b, m)| b ^ m).collect(),
+            None => bytes.to_vec(),
+        }
+    };
+    if value.len() < 4 {
+        return Err(String::from("STUN address attribute too short"));
+    }
+    let port_bytes = unmask(&value[2..4]);
+    let port = u16::from_be_bytes([port_bytes[0], port_bytes[1]]);
+    let ip_addr = match (value[1], value.len()) {
+        (0x01, 8) => {
+            let b = unmask(&value[4..8]);
+            IpAddr::V4(Ipv4Addr::new(b[0], b[1], b[2], b[3]))
+        }
+        (0x02, 20) => {
+            let b = unmask(&value[4..20]);
+            let mut octets = [0u8; 16];
+            octets.copy_from_slice(&b);
+            IpAddr::V6(Ipv6Addr::from(octets))
+        }
+        _ => return Err(String::from("STUN address attribute is malformed")),
+    };
+    Ok(SocketAddr::new(ip_addr, port))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::test_utils::FakeStreamHolder;
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+    use std::io::ErrorKind;
+    use std::str::FromStr;
+    use std::sync::Arc;
+    use std::sync::Mutex;
+
+    struct ProbeNetworkMock {
+        resolve_results: HashMap<String, Vec<SocketAddr>>,
+        tcp_connect_parameters: Arc<Mutex<Vec<SocketAddr>>>,
+        tcp_connect_failures: Vec<SocketAddr>,
+        local_ip_toward_result: IpAddr,
+        stun_bindings_parameters: Arc<Mutex<Vec<Vec<SocketAddr>>>>,
+        stun_bindings_results: RefCell<Vec<io::Result<Vec<Result<SocketAddr, String>>>>>,
+        dns_lookup_parameters: Arc<Mutex<Vec<(SocketAddr, String)>>>,
+        dns_lookup_results: RefCell<Vec<Result<Vec<IpAddr>, String>>>,
+    }
+
+    impl ProbeNetwork for ProbeNetworkMock {
+        fn resolve(&self, host_and_port: &str) -> io::Result<Vec<SocketAddr>> {
+            match self.resolve_results.get(host_and_port) {
+                Some(addrs) => Ok(addrs.clone()),
+                None => Err(io::Error::from(ErrorKind::NotFound)),
+            }
+        }
+
+        fn tcp_connect(&self, addr: SocketAddr, _timeout: Duration) -> io::Result<()> {
+            self.tcp_connect_parameters.lock().unwrap().push(addr);
+            if self.tcp_connect_failures.contains(&addr) {
+                Err(io::Error::from(ErrorKind::TimedOut))
+            } else {
+                Ok(())
+            }
+        }
+
+        fn local_ip_toward(&self, _addr: SocketAddr) -> io::Result<IpAddr> {
+            Ok(self.local_ip_toward_result)
+        }
+
+        fn stun_bindings(
+            &self,
+            servers: &[SocketAddr],
+            _timeout: Duration,
+        ) -> io::Result<Vec<Result<SocketAddr, String>>> {
+            self.stun_bindings_parameters
+                .lock()
+                .unwrap()
+                .push(servers.to_vec());
+            self.stun_bindings_results.borrow_mut().remove(0)
+        }
+
+        fn dns_lookup(
+            &self,
+            dns_server: SocketAddr,
+            hostname: &str,
+            _timeout: Duration,
+        ) -> Result<Vec<IpAddr>, String> {
+            self.dns_lookup_parameters
+                .lock()
+                .unwrap()
+                .push((dns_server, String::from(hostname)));
+            self.dns_lookup_results.borrow_mut().remove(0)
+        }
+    }
+
+    impl ProbeNetworkMock {
+        fn new() -> ProbeNetworkMock {
+            let mut resolve_results = HashMap::new();
+            resolve_results.insert(
+                String::from("example.com:80"),
+                vec![
+                    SocketAddr::from_str("[2606:2800:220:1::1]:80").unwrap(),
+                    SocketAddr::from_str("93.184.216.34:80").unwrap(),
+                ],
+            );
+            resolve_results.insert(
+                String::from("example.com:443"),
+                vec![SocketAddr::from_str("93.184.216.34:443").unwrap()],
+            );
+            resolve_results.insert(
+                String::from(STUN_SERVERS[0]),
+                vec![SocketAddr::from_str("74.125.1.1:19302").unwrap()],
+            );
+            resolve_results.insert(
+                String::from(STUN_SERVERS[1]),
+                vec![SocketAddr::from_str("74.125.2.2:19302").unwrap()],
+            );
+            ProbeNetworkMock {
+                resolve_results,
+                tcp_connect_parameters: Arc::new(Mutex::new(vec![])),
+                tcp_connect_failures: vec![],
+                local_ip_toward_result: IpAddr::from_str("192.168.0.5").unwrap(),
+                stun_bindings_parameters: Arc::new(Mutex::new(vec![])),
+                stun_bindings_results: RefCell::new(vec![]),
+                dns_lookup_parameters: Arc::new(Mutex::new(vec![])),
+                dns_lookup_results: RefCell::new(vec![]),
+            }
+        }
+
+        fn stun_bindings_result(self, result: io::Result<Vec<Result<SocketAddr, String>>>) -> Self {
+            self.stun_bindings_results.borrow_mut().push(result);
+            self
+        }
+
+        fn dns_lookup_result(self, result: Result<Vec<IpAddr>, String>) -> Self {
+            self.dns_lookup_results.borrow_mut().push(result);
+            self
+        }
+    }
+
+    fn subject(network: ProbeNetworkMock) -> NetworkProbe {
+        NetworkProbe {
+            network: Box::new(network),
+            timeout: PROBE_TIMEOUT,
+        }
+    }
+
+    fn statuses(report: &ProbeReport) -> Vec<(String, ProbeStatus)> {
+        report
+            .results
+            .iter()
+            .map(|result| (result.check.clone(), result.status))
+            .collect()
+    }
+
+    fn stun_response(transaction_id: &[u8; 12], attributes: &[u8]) -> Vec<u8> {
+        let mut response = vec![];
+        response.extend_from_slice(&STUN_BINDING_SUCCESS.to_be_bytes());
+        response.extend_from_slice(&(attributes.len() as u16).to_be_bytes());
+        response.extend_from_slice(&STUN_MAGIC_COOKIE.to_be_bytes());
+        response.extend_from_slice(transaction_id);
+        response.extend_from_slice(attributes);
+        response
+    }
+
+    #[test]
+    fn constants_have_correct_values() {
+        assert_eq!(PROBE_TIMEOUT, Duration::from_secs(5));
+        assert_eq!(PROBE_HOSTNAME, "example.com");
+        assert_eq!(
+            STUN_SERVERS,
+            &["stun.l.google.com:19302", "stun1.l.google.com:19302"]
+        );
+    }
+
+    #[test]
+    fn stun_binding_request_is_a_bare_header() {
+        let result = make_stun_binding_request(&[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12]);
+
+        assert_eq!(
+            result,
+            vec![
+                0x00, 0x01, 0x00, 0x00, 0x21, 0x12, 0xA4, 0x42, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11,
+                12
+            ]
+        );
+    }
+
+    #[test]
+    fn stun_binding_response_prefers_the_xor_mapped_address() {
+        let transaction_id = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12];
+        let software = [0x80, 0x22, 0x00, 0x03, b'a', b'b', b'c', 0x00];
+        let mapped_address = [0x00, 0x01, 0x00, 0x08, 0x00, 0x01, 0x04, 0xD2, 10, 0, 0, 1];
+        // 1.2.3.4:5678, masked with the magic cookie
+        let xor_mapped_address = [
+            0x00, 0x20, 0x00, 0x08, 0x00, 0x01, 0x37, 0x3C, 0x20, 0x10, 0xA7, 0x46,
+        ];
+        let response = stun_response(
+            &transaction_id,
+            &[&software[..], &mapped_address[..], &xor_mapped_address[..]].concat(),
+        );
+
+        let result = parse_stun_binding_response(&response, &transaction_id);
+
+        assert_eq!(result, Ok(SocketAddr::from_str("1.2.3.4:5678").unwrap()));
+    }
+
+    #[test]
+    fn stun_binding_response_falls_back_to_the_mapped_address() {
+        let transaction_id = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12];
+        let response = stun_response(
+            &transaction_id,
+            &[0x00, 0x01, 0x00, 0x08, 0x00, 0x01, 0x04, 0xD2, 10, 0, 0, 1],
+        );
+
+        let result = parse_stun_binding_response(&response, &transaction_id);
+
+        assert_eq!(result, Ok(SocketAddr::from_str("10.0.0.1:1234").unwrap()));
+    }
+
+    #[test]
+    fn stun_binding_response_for_another_transaction_is_rejected() {
+        let response = stun_response(
+            &[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12],
+            &[0x00, 0x01, 0x00, 0x08, 0x00, 0x01, 0x04, 0xD2, 10, 0, 0, 1],
+        );
+
+        let result =
+            parse_stun_binding_response(&response, &[12, 11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1]);
+
+        assert_eq!(
+            result,
+            Err(String::from("STUN response is for some other request"))
+        );
+    }
+
+    #[test]
+    fn a_healthy_network_passes_everything() {
+        let network = ProbeNetworkMock::new()
+            .stun_bindings_result(Ok(vec![
+                Ok(SocketAddr::from_str("1.2.3.4:5000").unwrap()),
+                Ok(SocketAddr::from_str("1.2.3.4:5000").unwrap()),
+            ]))
+            .dns_lookup_result(Ok(vec![IpAddr::from_str("93.184.216.34").unwrap()]));
+        let tcp_connect_parameters = network.tcp_connect_parameters.clone();
+        let stun_bindings_parameters = network.stun_bindings_parameters.clone();
+        let dns_lookup_parameters = network.dns_lookup_parameters.clone();
+        let subject = subject(network);
+
+        let result = subject.probe(&ProbeConfig {
+            ip_addr_opt: Some(IpAddr::from_str("1.2.3.4").unwrap()),
+            neighbor_addrs: vec![NodeAddr::from_str("2.3.4.5:1234").unwrap()],
+            dns_servers: vec![SocketAddr::from_str("8.8.8.8:53").unwrap()],
+        });
+
+        assert_eq!(
+            statuses(&result),
+            vec![
+                (String::from("outbound TCP 80"), ProbeStatus::Pass),
+                (String::from("outbound TCP 443"), ProbeStatus::Pass),
+                (String::from("outbound TCP clandestine"), ProbeStatus::Pass),
+                (String::from("UDP"), ProbeStatus::Pass),
+                (String::from("public IP"), ProbeStatus::Pass),
+                (String::from("NAT"), ProbeStatus::Pass),
+                (String::from("DNS 8.8.8.8"), ProbeStatus::Pass),
+            ]
+        );
+        assert_eq!(result.exit_code(), 0);
+        assert_eq!(
+            *tcp_connect_parameters.lock().unwrap(),
+            vec![
+                SocketAddr::from_str("93.184.216.34:80").unwrap(),
+                SocketAddr::from_str("93.184.216.34:443").unwrap(),
+                SocketAddr::from_str("2.3.4.5:1234").unwrap(),
+            ]
+        );
+        assert_eq!(
+            *stun_bindings_parameters.lock().unwrap(),
+            vec![vec![
+                SocketAddr::from_str("74.125.1.1:19302").unwrap(),
+                SocketAddr::from_str("74.125.2.2:19302").unwrap(),
+            ]]
+        );
+        assert_eq!(
+            *dns_lookup_parameters.lock().unwrap(),
+            vec![(
+                SocketAddr::from_str("8.8.8.8:53").unwrap(),
+                String::from("example.com")
+            )]
+        );
+        assert_eq!(result.results[4].detail, "1.2.3.4");
+    }
+
+    #[test]
+    fn a_symmetric_nat_warns_and_a_wrong_ip_fails() {
+        let mut network = ProbeNetworkMock::new()
+            .stun_bindings_result(Ok(vec![
+                Ok(SocketAddr::from_str("5.6.7.8:5000").unwrap()),
+                Ok(SocketAddr::from_str("5.6.7.8:5001").unwrap()),
+            ]))
+            .dns_lookup_result(Err(String::from("request timed out")));
+        network.tcp_connect_failures = vec![SocketAddr::from_str("2.3.4.5:1234").unwrap()];
+        let subject = subject(network);
+
+        let result = subject.probe(&ProbeConfig {
+            ip_addr_opt: Some(IpAddr::from_str("1.2.3.4").unwrap()),
+            neighbor_addrs: vec![NodeAddr::from_str("2.3.4.5:1234,2345").unwrap()],
+            dns_servers: vec![SocketAddr::from_str("8.8.8.8:53").unwrap()],
+        });
+
+        assert_eq!(
+            statuses(&result),
+            vec![
+                (String::from("outbound TCP 80"), ProbeStatus::Pass),
+                (String::from("outbound TCP 443"), ProbeStatus::Pass),
+                (String::from("outbound TCP clandestine"), ProbeStatus::Warn),
+                (String::from("UDP"), ProbeStatus::Pass),
+                (String::from("public IP"), ProbeStatus::Fail),
+                (String::from("NAT"), ProbeStatus::Warn),
+                (String::from("DNS 8.8.8.8"), ProbeStatus::Fail),
+            ]
+        );
+        assert_eq!(
+            result.results[4].detail,
+            "--ip is 1.2.3.4, but the Internet sees this machine as 5.6.7.8"
+        );
+        assert_eq!(result.results[6].detail, "request timed out");
+        assert_eq!(result.exit_code(), 1);
+    }
+
+    #[test]
+    fn blocked_udp_skips_what_depends_on_it_and_missing_configuration_is_skipped() {
+        let network = ProbeNetworkMock::new().stun_bindings_result(Ok(vec![
+            Err(String::from("timed out")),
+            Err(String::from("timed out")),
+        ]));
+        let subject = subject(network);
+
+        let result = subject.probe(&ProbeConfig {
+            ip_addr_opt: None,
+            neighbor_addrs: vec![],
+            dns_servers: vec![],
+        });
+
+        assert_eq!(
+            statuses(&result),
+            vec![
+                (String::from("outbound TCP 80"), ProbeStatus::Pass),
+                (String::from("outbound TCP 443"), ProbeStatus::Pass),
+                (String::from("outbound TCP clandestine"), ProbeStatus::Skip),
+                (String::from("UDP"), ProbeStatus::Fail),
+                (String::from("public IP"), ProbeStatus::Skip),
+                (String::from("NAT"), ProbeStatus::Skip),
+                (String::from("DNS"), ProbeStatus::Skip),
+            ]
+        );
+        assert_eq!(result.exit_code(), 1);
+    }
+
+    #[test]
+    fn a_machine_directly_on_the_internet_has_no_nat() {
+        let mut network = ProbeNetworkMock::new().stun_bindings_result(Ok(vec![
+            Ok(SocketAddr::from_str("1.2.3.4:5000").unwrap()),
+            Err(String::from("timed out")),
+        ]));
+        network.local_ip_toward_result = IpAddr::from_str("1.2.3.4").unwrap();
+        let subject = subject(network);
+
+        let result = subject.probe(&ProbeConfig {
+            ip_addr_opt: None,
+            neighbor_addrs: vec![],
+            dns_servers: vec![],
+        });
+
+        assert_eq!(result.results[5].status, ProbeStatus::Pass);
+        assert_eq!(
+            result.results[5].detail,
+            "none; this machine is directly on the Internet"
+        );
+    }
+
+    #[test]
+    fn go_writes_text_by_default_and_json_on_request() {
+        let args_for = |format: &str| -> Vec<String> {
+            vec!["SubstratumNode", "--probe", format]
+                .into_iter()
+                .map(String::from)
+                .collect()
+        };
+        let udp_blocked = || {
+            ProbeNetworkMock::new()
+                .stun_bindings_result(Err(io::Error::from(ErrorKind::PermissionDenied)))
+        };
+        let mut holder = FakeStreamHolder::new();
+
+        let text_code = subject(udp_blocked()).go(&mut holder.streams(), &args_for("text"));
+
+        let text = holder.stdout.get_string();
+        assert_eq!(text_code, 1);
+        assert_eq!(
+            text.lines().next().unwrap(),
+            "PASS  outbound TCP 80             connected to example.com (93.184.216.34:80) in 0ms"
+        );
+        assert_eq!(
+            text.contains("FAIL  UDP                         couldn't send UDP: "),
+            true,
+            "{}",
+            text
+        );
+
+        let mut holder = FakeStreamHolder::new();
+
+        let json_code = subject(udp_blocked()).go(&mut holder.streams(), &args_for("json"));
+
+        let json: serde_json::Value = serde_json::from_str(&holder.stdout.get_string()).unwrap();
+        assert_eq!(json_code, 1);
+        assert_eq!(json["results"][3]["check"], "UDP");
+        assert_eq!(json["results"][3]["status"], "fail");
+        assert_eq!(json["results"][4]["status"], "skip");
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid format for --probe text|json: 'xml'")]
+    fn go_complains_about_unknown_formats() {
+        let mut holder = FakeStreamHolder::new();
+
+        subject(ProbeNetworkMock::new()).go(
+            &mut holder.streams(),
+            &vec![String::from("--probe"), String::from("xml")],
+        );
+    }
+
+    #[test]
+    fn is_requested_looks_for_the_probe_flag() {
+        assert_eq!(
+            NetworkProbe::is_requested(&vec![String::from("--probe"), String::from("json")]),
+            true
+        );
+        assert_eq!(
+            NetworkProbe::is_requested(&vec![String::from("--ip"), String::from("1.2.3.4")]),
+            false
+        );
+    }
+}
@@ -9,15 +9,36 @@ extern "C" {
     pub fn getgid() -> i32;
     pub fn setuid(uid: i32) -> i32;
     pub fn setgid(gid: i32) -> i32;
+    pub fn chown(path: *const c_char, uid: i32, gid: i32) -> i32;
 }
 
+#[cfg(windows)]
+use crate::sub_lib::logger::Logger;
+use crate::sub_lib::parameter_finder::ParameterFinder;
 use std::env::var;
+#[cfg(unix)]
+use std::ffi::CString;
+use std::fs;
+#[cfg(unix)]
+use std::io;
+#[cfg(unix)]
+use std::os::raw::c_char;
+#[cfg(unix)]
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::path::PathBuf;
+#[cfg(windows)]
+use std::process::Command;
 
 pub trait IdWrapper: Send {
     fn getuid(&self) -> i32;
     fn getgid(&self) -> i32;
     fn setuid(&self, uid: i32) -> i32;
     fn setgid(&self, gid: i32) -> i32;
+    #[cfg(unix)]
+    fn chown(&self, path: &Path, uid: i32, gid: i32) -> i32;
+    #[cfg(windows)]
+    fn grant_full_control(&self, path: &Path, account: &str) -> i32;
 }
 
 pub struct IdWrapperReal;
@@ -51,6 +72,14 @@ impl IdWrapper for IdWrapperReal {
     fn setgid(&self, gid: i32) -> i32 {
         unsafe { setgid(gid) }
     }
+    fn chown(&self, path: &Path, uid: i32, gid: i32) -> i32 {
+        let c_path =
+            CString::new(path.as_os_str().as_bytes()).expect("Path contains a NUL character");
+        match unsafe { chown(c_path.as_ptr(), uid, gid) } {
+            0 => 0,
+            _ => io::Error::last_os_error().raw_os_error().unwrap_or(-1),
+        }
+    }
 }
 
 #[cfg(windows)]
@@ -71,10 +100,53 @@ impl IdWrapper for IdWrapperReal {
     fn setgid(&self, _gid: i32) -> i32 {
         !unimplemented!()
     }
+    fn grant_full_control(&self, path: &Path, account: &str) -> i32 {
+        match Command::new("icacls")
+            .arg(path)
+            .arg("/grant")
+            .arg(format!("{}:(OI)(CI)F", account))
+            .arg("/T")
+            .arg("/Q")
+            .status()
+        {
+            Ok(status) => status.code().unwrap_or(-1),
+            Err(_) => -1,
+        }
+    }
+}
+
+// The user and group a Node started as root should end up running as, when they're given on the
+// command line rather than inherited from sudo: typically a non-root user in a container.
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct RunAsIds {
+    pub uid: Option<i32>,
+    pub gid: Option<i32>,
+}
+
+impl RunAsIds {
+    pub fn from_args(args: &Vec<String>) -> RunAsIds {
+        let finder = ParameterFinder::new(args.clone());
+        RunAsIds {
+            uid: RunAsIds::parse_id(&finder, "--run_as_uid", "user"),
+            gid: RunAsIds::parse_id(&finder, "--run_as_gid", "group"),
+        }
+    }
+
+    fn parse_id(finder: &ParameterFinder, parameter_tag: &str, kind: &str) -> Option<i32> {
+        let usage = format!("{} <numeric {} id>", parameter_tag, kind);
+        match finder.find_value_for(parameter_tag, &usage) {
+            Some(value) => match value.parse::<i32>() {
+                Ok(id) if id >= 0 => Some(id),
+                _ => panic!("Invalid {} id for {}: '{}'", kind, usage, value),
+            },
+            None => None,
+        }
+    }
 }
 
 pub trait PrivilegeDropper: Send {
-    fn drop_privileges(&self);
+    fn drop_privileges(&self, run_as: &RunAsIds);
+    fn chown(&self, data_directory: &Path, run_as: &RunAsIds);
 }
 
 pub struct PrivilegeDropperReal {
@@ -83,11 +155,11 @@ pub struct PrivilegeDropperReal {
 }
 
 impl PrivilegeDropper for PrivilegeDropperReal {
-    fn drop_privileges(&self) {
+    fn drop_privileges(&self, run_as: &RunAsIds) {
         #[cfg(unix)]
         {
-            let sudo_gid = self.id_from_env("SUDO_GID");
-            let sudo_uid = self.id_from_env("SUDO_UID");
+            let sudo_gid = run_as.gid.or(self.id_from_env("SUDO_GID"));
+            let sudo_uid = run_as.uid.or(self.id_from_env("SUDO_UID"));
             let gid = sudo_gid.unwrap_or(self.id_wrapper.getgid());
             let gid_result = self.id_wrapper.setgid(gid);
             if gid_result != 0 {
@@ -107,6 +179,53 @@ impl PrivilegeDropper for PrivilegeDropperReal {
             }
         }
     }
+
+    // Hands the data directory, and the database files directly inside it, over to the user and
+    // group given on the command line, so that the Node can still use them once it's dropped its
+    // privileges. On Windows, makes sure the account Node is running under has full control.
+    fn chown(&self, data_directory: &Path, run_as: &RunAsIds) {
+        #[cfg(unix)]
+        {
+            if (run_as.uid, run_as.gid) == (None, None) {
+                return;
+            }
+            if !data_directory.exists() {
+                fs::create_dir_all(data_directory).expect(&format!(
+                    "Cannot create specified data directory at {:?}",
+                    data_directory
+                ));
+            }
+            // -1 tells chown to leave that id alone
+            let uid = run_as.uid.unwrap_or(-1);
+            let gid = run_as.gid.unwrap_or(-1);
+            for path in PrivilegeDropperReal::owned_paths(data_directory) {
+                let result = self.id_wrapper.chown(&path, uid, gid);
+                if result != 0 {
+                    panic!(
+                        "Error code {} changing ownership of {:?} to {}:{}",
+                        result, path, uid, gid
+                    )
+                }
+            }
+        }
+        #[cfg(windows)]
+        {
+            let account = match self.environment_wrapper.var("USERNAME") {
+                Some(account) => account,
+                None => return,
+            };
+            if !data_directory.exists() {
+                return;
+            }
+            let result = self.id_wrapper.grant_full_control(data_directory, &account);
+            if result != 0 {
+                Logger::new("PrivilegeDropper").warning(format!(
+                    "Error code {} granting {} full control of {:?}",
+                    result, account, data_directory
+                ));
+            }
+        }
+    }
 }
 
 impl PrivilegeDropperReal {
@@ -118,6 +237,19 @@ impl PrivilegeDropperReal {
         }
     }
 
+    fn owned_paths(data_directory: &Path) -> Vec<PathBuf> {
+        let mut paths: Vec<PathBuf> = match fs::read_dir(data_directory) {
+            Ok(entries) => entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.path())
+                .collect(),
+            Err(_) => vec![],
+        };
+        paths.sort();
+        paths.insert(0, data_directory.to_path_buf());
+        paths
+    }
+
     fn id_from_env(&self, name: &str) -> Option<i32> {
         match self.environment_wrapper.var(name) {
             Some(s) => match s.parse::<i32>() {
@@ -133,13 +265,16 @@ impl PrivilegeDropperReal {
 mod tests {
     use super::*;
     use std::cell::RefCell;
+    use std::sync::Arc;
+    use std::sync::Mutex;
 
     struct IdWrapperMock {
         pub uids: RefCell<Vec<i32>>,
         pub uid_results: RefCell<Vec<i32>>,
         pub gids: RefCell<Vec<i32>>,
         pub gid_results: RefCell<Vec<i32>>,
-        pub log: RefCell<Vec<String>>,
+        pub chown_result: i32,
+        pub log: Arc<Mutex<Vec<String>>>,
     }
 
     impl IdWrapper for IdWrapperMock {
@@ -150,13 +285,29 @@ mod tests {
             self.gids.borrow_mut().pop().unwrap()
         }
         fn setuid(&self, uid: i32) -> i32 {
-            self.log.borrow_mut().push(format!("setuid ({})", uid));
+            self.log.lock().unwrap().push(format!("setuid ({})", uid));
             self.uid_results.borrow_mut().pop().unwrap()
         }
         fn setgid(&self, gid: i32) -> i32 {
-            self.log.borrow_mut().push(format!("setgid ({})", gid));
+            self.log.lock().unwrap().push(format!("setgid ({})", gid));
             self.gid_results.borrow_mut().pop().unwrap()
         }
+        #[cfg(unix)]
+        fn chown(&self, path: &Path, uid: i32, gid: i32) -> i32 {
+            self.log
+                .lock()
+                .unwrap()
+                .push(format!("chown ({:?}, {}, {})", path, uid, gid));
+            self.chown_result
+        }
+        #[cfg(windows)]
+        fn grant_full_control(&self, path: &Path, account: &str) -> i32 {
+            self.log
+                .lock()
+                .unwrap()
+                .push(format!("grant_full_control ({:?}, {})", path, account));
+            self.chown_result
+        }
     }
 
     impl IdWrapperMock {
@@ -173,7 +324,8 @@ mod tests {
                 uid_results: RefCell::new(vec![uid_result]),
                 gids: RefCell::new(vec![gid_final, gid_initial]),
                 gid_results: RefCell::new(vec![gid_result]),
-                log: RefCell::new(vec![]),
+                chown_result: 0,
+                log: Arc::new(Mutex::new(vec![])),
             }
         }
     }
@@ -188,6 +340,7 @@ mod tests {
             match key {
                 "SUDO_UID" => self.sudo_uid.clone(),
                 "SUDO_GID" => self.sudo_gid.clone(),
+                "USERNAME" => Some(String::from("booga")),
                 _ => None,
             }
         }
@@ -219,7 +372,7 @@ mod tests {
             environment_wrapper: Box::new(environment_wrapper),
         };
 
-        subject.drop_privileges();
+        subject.drop_privileges(&RunAsIds::default());
     }
 
     #[cfg(unix)]
@@ -233,7 +386,7 @@ mod tests {
             environment_wrapper: Box::new(environment_wrapper),
         };
 
-        subject.drop_privileges();
+        subject.drop_privileges(&RunAsIds::default());
     }
 
     #[cfg(unix)]
@@ -247,7 +400,7 @@ mod tests {
             environment_wrapper: Box::new(environment_wrapper),
         };
 
-        subject.drop_privileges();
+        subject.drop_privileges(&RunAsIds::default());
     }
 
     #[cfg(unix)]
@@ -261,7 +414,216 @@ mod tests {
             environment_wrapper: Box::new(environment_wrapper),
         };
 
-        subject.drop_privileges();
+        subject.drop_privileges(&RunAsIds::default());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn run_as_ids_take_precedence_over_sudo_ids() {
+        let id_wrapper = IdWrapperMock::new(0, 0, 2000, 0, 0, 3000);
+        let log = id_wrapper.log.clone();
+        let environment_wrapper = EnvironmentWrapperMock::new(Some("1000"), Some("1000"));
+        let subject = PrivilegeDropperReal {
+            id_wrapper: Box::new(id_wrapper),
+            environment_wrapper: Box::new(environment_wrapper),
+        };
+
+        subject.drop_privileges(&RunAsIds {
+            uid: Some(2000),
+            gid: Some(3000),
+        });
+
+        assert_eq!(
+            *log.lock().unwrap(),
+            vec![String::from("setgid (3000)"), String::from("setuid (2000)")]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn chown_leaves_data_directory_alone_without_run_as_ids() {
+        let id_wrapper = IdWrapperMock::new(0, 0, 1000, 0, 0, 1000);
+        let log = id_wrapper.log.clone();
+        let environment_wrapper = EnvironmentWrapperMock::new(Some("1000"), Some("1000"));
+        let subject = PrivilegeDropperReal {
+            id_wrapper: Box::new(id_wrapper),
+            environment_wrapper: Box::new(environment_wrapper),
+        };
+
+        subject.chown(
+            &PathBuf::from("generated/test/privilege_drop/nonexistent"),
+            &RunAsIds::default(),
+        );
+
+        assert_eq!(log.lock().unwrap().is_empty(), true);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn chown_hands_data_directory_and_its_files_to_run_as_ids() {
+        let data_directory = PathBuf::from(
+            "generated/test/privilege_drop/chown_hands_data_directory_and_its_files_to_run_as_ids",
+        );
+        fs::remove_dir_all(&data_directory).is_ok();
+        fs::create_dir_all(&data_directory).is_ok();
+        fs::write(data_directory.join("node.db"), b"").unwrap();
+        fs::write(data_directory.join("firewall_rules"), b"").unwrap();
+        let id_wrapper = IdWrapperMock::new(0, 0, 1000, 0, 0, 1000);
+        let log = id_wrapper.log.clone();
+        let environment_wrapper = EnvironmentWrapperMock::new(None, None);
+        let subject = PrivilegeDropperReal {
+            id_wrapper: Box::new(id_wrapper),
+            environment_wrapper: Box::new(environment_wrapper),
+        };
+
+        subject.chown(
+            &data_directory,
+            &RunAsIds {
+                uid: Some(2000),
+                gid: None,
+            },
+        );
+
+        assert_eq!(
+            *log.lock().unwrap(),
+            vec![
+                format!("chown ({:?}, 2000, -1)", data_directory),
+                format!(
+                    "chown ({:?}, 2000, -1)",
+                    data_directory.join("firewall_rules")
+                ),
+                format!("chown ({:?}, 2000, -1)", data_directory.join("node.db")),
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn chown_creates_missing_data_directory_before_handing_it_over() {
+        let data_directory = PathBuf::from(
+            "generated/test/privilege_drop/chown_creates_missing_data_directory_before_handing_it_over",
+        );
+        fs::remove_dir_all(&data_directory).is_ok();
+        let id_wrapper = IdWrapperMock::new(0, 0, 1000, 0, 0, 1000);
+        let log = id_wrapper.log.clone();
+        let environment_wrapper = EnvironmentWrapperMock::new(None, None);
+        let subject = PrivilegeDropperReal {
+            id_wrapper: Box::new(id_wrapper),
+            environment_wrapper: Box::new(environment_wrapper),
+        };
+
+        subject.chown(
+            &data_directory,
+            &RunAsIds {
+                uid: Some(2000),
+                gid: Some(3000),
+            },
+        );
+
+        assert_eq!(data_directory.is_dir(), true);
+        assert_eq!(
+            *log.lock().unwrap(),
+            vec![format!("chown ({:?}, 2000, 3000)", data_directory)]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    #[should_panic(expected = "Error code 1 changing ownership of")]
+    fn chown_error_code_causes_panic() {
+        let data_directory =
+            PathBuf::from("generated/test/privilege_drop/chown_error_code_causes_panic");
+        fs::create_dir_all(&data_directory).is_ok();
+        let mut id_wrapper = IdWrapperMock::new(0, 0, 1000, 0, 0, 1000);
+        id_wrapper.chown_result = 1;
+        let environment_wrapper = EnvironmentWrapperMock::new(None, None);
+        let subject = PrivilegeDropperReal {
+            id_wrapper: Box::new(id_wrapper),
+            environment_wrapper: Box::new(environment_wrapper),
+        };
+
+        subject.chown(
+            &data_directory,
+            &RunAsIds {
+                uid: Some(2000),
+                gid: Some(3000),
+            },
+        );
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn chown_grants_current_account_full_control_of_data_directory() {
+        let data_directory = PathBuf::from(
+            "generated/test/privilege_drop/chown_grants_current_account_full_control_of_data_directory",
+        );
+        fs::create_dir_all(&data_directory).is_ok();
+        let id_wrapper = IdWrapperMock::new(0, 0, 1000, 0, 0, 1000);
+        let log = id_wrapper.log.clone();
+        let subject = PrivilegeDropperReal {
+            id_wrapper: Box::new(id_wrapper),
+            environment_wrapper: Box::new(EnvironmentWrapperMock::new(None, None)),
+        };
+
+        subject.chown(&data_directory, &RunAsIds::default());
+
+        assert_eq!(
+            *log.lock().unwrap(),
+            vec![format!("grant_full_control ({:?}, booga)", data_directory)]
+        );
+    }
+
+    #[test]
+    fn run_as_ids_default_to_none() {
+        let result = RunAsIds::from_args(&vec![]);
+
+        assert_eq!(
+            result,
+            RunAsIds {
+                uid: None,
+                gid: None
+            }
+        );
+    }
+
+    #[test]
+    fn run_as_ids_come_from_args() {
+        let args: Vec<String> = vec!["--run_as_uid", "2000", "--run_as_gid", "3000"]
+            .into_iter()
+            .map(String::from)
+            .collect();
+
+        let result = RunAsIds::from_args(&args);
+
+        assert_eq!(
+            result,
+            RunAsIds {
+                uid: Some(2000),
+                gid: Some(3000)
+            }
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid group id for --run_as_gid <numeric group id>: 'booga'")]
+    fn run_as_ids_complain_about_nonnumeric_id() {
+        let args: Vec<String> = vec!["--run_as_gid", "booga"]
+            .into_iter()
+            .map(String::from)
+            .collect();
+
+        RunAsIds::from_args(&args);
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid user id for --run_as_uid <numeric user id>: '-5'")]
+    fn run_as_ids_complain_about_negative_id() {
+        let args: Vec<String> = vec!["--run_as_uid", "-5"]
+            .into_iter()
+            .map(String::from)
+            .collect();
+
+        RunAsIds::from_args(&args);
     }
 
     // TODO: Figure out how to make this test compile
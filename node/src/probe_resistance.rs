@@ -0,0 +1,236 @@
+// Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use crate::sub_lib::cryptde::CryptDE;
+use crate::sub_lib::cryptde::PublicKey;
+use crate::sub_lib::utils::make_hex_string;
+use sha1;
+
+const KNOCK_NONCE_LENGTH: usize = 16;
+
+// A Node that opens a clandestine stream sends this many hex digits before anything else: a random
+// nonce, then a hash of the nonce and the public key of the Node it's connecting to. Hex digits
+// can't be taken for part of a JSON frame, so a Node that doesn't look for knocks discards them.
+pub const KNOCK_LENGTH: usize = (KNOCK_NONCE_LENGTH + sha1::DIGEST_LENGTH) * 2;
+
+// Looks like what an nginx server sends back when it can't make sense of a request
+pub const DECOY_HTTP_RESPONSE: &[u8] = b"HTTP/1.1 400 Bad Request\r\n\
+Server: nginx\r\n\
+Content-Type: text/html\r\n\
+Content-Length: 150\r\n\
+Connection: close\r\n\
+\r\n\
+<html>\r\n\
+<head><title>400 Bad Request</title></head>\r\n\
+<body>\r\n\
+<center><h1>400 Bad Request</h1></center>\r\n\
+<hr><center>nginx</center>\r\n\
+</body>\r\n\
+</html>\r\n";
+
+// How a clandestine port treats a connection that doesn't start with a knock. This is to keep
+// scanners from picking Substratum Nodes out by what they do with junk, not to keep anybody out:
+// anyone who has seen a Node's public key in Gossip can knock, and a knock can be replayed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ProbeResistance {
+    Off,    // The data goes through anyway
+    Silent, // Everything is read and thrown away, and nothing is ever sent back
+    Http,   // The prober gets DECOY_HTTP_RESPONSE and the connection is closed
+}
+
+impl ProbeResistance {
+    pub fn from_name(name: &str) -> Option<ProbeResistance> {
+        match name {
+            "off" => Some(ProbeResistance::Off),
+            "silent" => Some(ProbeResistance::Silent),
+            "http" => Some(ProbeResistance::Http),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum KnockOutcome {
+    Waiting,
+    Admitted(Vec<u8>), // whatever came after the knock
+    Refused,
+}
+
+// Holds back the first data on an incoming clandestine stream until it's clear whether it starts
+// with a knock for this Node.
+pub struct KnockGate {
+    public_key: PublicKey,
+    probe_resistance: ProbeResistance,
+    data_so_far: Vec<u8>,
+}
+
+impl KnockGate {
+    pub fn new(public_key: PublicKey, probe_resistance: ProbeResistance) -> KnockGate {
+        KnockGate {
+            public_key,
+            probe_resistance,
+            data_so_far: vec![],
+        }
+    }
+
+    pub fn probe_resistance(&self) -> ProbeResistance {
+        self.probe_resistance
+    }
+
+    pub fn add_data(&mut self, data: &[u8]) -> KnockOutcome {
+        self.data_so_far.extend_from_slice(data);
+        let prefix_length = self.data_so_far.len().min(KNOCK_LENGTH);
+        if !self.data_so_far[..prefix_length]
+            .iter()
+            .all(|byte| is_knock_digit(*byte))
+        {
+            return self.no_knock();
+        }
+        if self.data_so_far.len() < KNOCK_LENGTH {
+            return KnockOutcome::Waiting;
+        }
+        let knock = decode_knock(&self.data_so_far[..KNOCK_LENGTH]);
+        let (nonce, hash) = knock.split_at(KNOCK_NONCE_LENGTH);
+        if hash == &knock_hash(nonce, &self.public_key)[..] {
+            KnockOutcome::Admitted(self.data_so_far.split_off(KNOCK_LENGTH))
+        } else {
+            self.no_knock()
+        }
+    }
+
+    fn no_knock(&mut self) -> KnockOutcome {
+        match self.probe_resistance {
+            ProbeResistance::Off => KnockOutcome::Admitted(self.data_so_far.split_off(0)),
+            _ => KnockOutcome::Refused,
+        }
+    }
+}
+
+pub fn make_knock(cryptde: &dyn CryptDE, public_key: &PublicKey) -> Vec<u8> {
+    let mut knock = vec![0u8; KNOCK_NONCE_LENGTH];
+    cryptde.random(&mut knock);
+    let hash = knock_hash(&knock, public_key);
+    knock.extend_from_slice(&hash);
+    make_hex_string(&knock).into_bytes()
+}
+
+fn knock_hash(nonce: &[u8], public_key: &PublicKey) -> [u8; sha1::DIGEST_LENGTH] {
+    let mut hash = sha1::Sha1::new();
+    hash.update(nonce);
+    hash.update(public_key.as_slice());
+    hash.digest().bytes()
+}
+
+// make_hex_string uses capitals
+fn is_knock_digit(byte: u8) -> bool {
+    (byte >= b'0' && byte <= b'9') || (byte >= b'A' && byte <= b'F')
+}
+
+fn decode_knock(digits: &[u8]) -> Vec<u8> {
+    let value = |digit: u8| match digit {
+        b'0'..=b'9' => digit - b'0',
+        _ => digit - b'A' + 10,
+    };
+    digits
+        .chunks(2)
+        .map(|pair| (value(pair[0]) << 4) | value(pair[1]))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sub_lib::cryptde_null::CryptDENull;
+
+    fn make_cryptde() -> CryptDENull {
+        let mut cryptde = CryptDENull::new();
+        cryptde.generate_key_pair();
+        cryptde
+    }
+
+    #[test]
+    fn constant_values() {
+        assert_eq!(KNOCK_LENGTH, 72);
+        let response = String::from_utf8(DECOY_HTTP_RESPONSE.to_vec()).unwrap();
+        let body_start = response.find("\r\n\r\n").unwrap() + 4;
+        assert_eq!(response.len() - body_start, 150);
+    }
+
+    #[test]
+    fn probe_resistance_names_are_recognized() {
+        assert_eq!(
+            ProbeResistance::from_name("off"),
+            Some(ProbeResistance::Off)
+        );
+        assert_eq!(
+            ProbeResistance::from_name("silent"),
+            Some(ProbeResistance::Silent)
+        );
+        assert_eq!(
+            ProbeResistance::from_name("http"),
+            Some(ProbeResistance::Http)
+        );
+        assert_eq!(ProbeResistance::from_name("ssh"), None);
+    }
+
+    #[test]
+    fn a_knock_for_this_node_is_stripped_off_however_it_arrives() {
+        let cryptde = make_cryptde();
+        let mut data = make_knock(&cryptde, &cryptde.public_key());
+        data.extend_from_slice(b"{\"component\": \"HOPR\"}");
+        let mut subject = KnockGate::new(cryptde.public_key(), ProbeResistance::Silent);
+
+        assert_eq!(subject.add_data(&data[..10]), KnockOutcome::Waiting);
+        assert_eq!(subject.add_data(&data[10..70]), KnockOutcome::Waiting);
+        let result = subject.add_data(&data[70..]);
+
+        assert_eq!(
+            result,
+            KnockOutcome::Admitted(b"{\"component\": \"HOPR\"}".to_vec())
+        );
+    }
+
+    #[test]
+    fn a_knock_for_some_other_node_is_refused() {
+        let cryptde = make_cryptde();
+        let other_cryptde = make_cryptde();
+        let knock = make_knock(&cryptde, &other_cryptde.public_key());
+        let mut subject = KnockGate::new(cryptde.public_key(), ProbeResistance::Http);
+
+        let result = subject.add_data(&knock);
+
+        assert_eq!(result, KnockOutcome::Refused);
+    }
+
+    #[test]
+    fn anything_that_cannot_be_a_knock_is_refused_right_away() {
+        let cryptde = make_cryptde();
+        let mut subject = KnockGate::new(cryptde.public_key(), ProbeResistance::Silent);
+
+        let result = subject.add_data(b"GET / HTTP/1.0\r\n\r\n");
+
+        assert_eq!(result, KnockOutcome::Refused);
+    }
+
+    #[test]
+    fn with_probe_resistance_off_data_without_a_knock_goes_through_whole() {
+        let cryptde = make_cryptde();
+        let mut subject = KnockGate::new(cryptde.public_key(), ProbeResistance::Off);
+
+        let result = subject.add_data(b"{\"component\": \"HOPR\"}");
+
+        assert_eq!(
+            result,
+            KnockOutcome::Admitted(b"{\"component\": \"HOPR\"}".to_vec())
+        );
+        assert_eq!(subject.probe_resistance(), ProbeResistance::Off);
+    }
+
+    #[test]
+    fn a_knock_is_all_capital_hex_digits() {
+        let cryptde = make_cryptde();
+
+        let result = make_knock(&cryptde, &cryptde.public_key());
+
+        assert_eq!(result.len(), KNOCK_LENGTH);
+        assert_eq!(result.iter().all(|byte| is_knock_digit(*byte)), true);
+    }
+}
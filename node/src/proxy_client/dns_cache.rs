@@ -0,0 +1,355 @@
+// Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use crate::proxy_client::resolver_wrapper::ResolverWrapper;
+use crate::proxy_client::resolver_wrapper::WrappedLookupIpFuture;
+use futures::future;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+use tokio::prelude::Future;
+use trust_dns_resolver::error::ResolveError;
+use trust_dns_resolver::error::ResolveErrorKind;
+use trust_dns_resolver::lookup_ip::LookupIp;
+
+pub const DNS_CACHE_MAX_ENTRIES: usize = 1000;
+// How long a hostname that doesn't exist is remembered. Failures of any other kind (timeouts,
+// unreachable servers) aren't remembered at all, so the next request tries again.
+pub const DNS_NEGATIVE_TTL: Duration = Duration::from_secs(60);
+
+struct DnsCacheEntry {
+    result: Result<LookupIp, ResolveError>,
+    valid_until: Instant,
+    last_used: u64,
+}
+
+// Lookup results by hostname, each kept until its TTL runs out. When the cache is full, expired
+// entries are thrown out first and then the one that has gone unused the longest.
+pub struct DnsCache {
+    max_entries: usize,
+    entries: HashMap<String, DnsCacheEntry>,
+    uses: u64,
+}
+
+impl DnsCache {
+    pub fn new(max_entries: usize) -> DnsCache {
+        DnsCache {
+            max_entries,
+            entries: HashMap::new(),
+            uses: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn get(&mut self, host: &str, now: Instant) -> Option<Result<LookupIp, ResolveError>> {
+        let key = host.to_lowercase();
+        let expired = match self.entries.get(&key) {
+            Some(entry) => entry.valid_until <= now,
+            None => return None,
+        };
+        if expired {
+            self.entries.remove(&key);
+            return None;
+        }
+        self.uses += 1;
+        let entry = self
+            .entries
+            .get_mut(&key)
+            .expect("DNS cache entry disappeared");
+        entry.last_used = self.uses;
+        Some(entry.result.clone())
+    }
+
+    pub fn put(&mut self, host: &str, result: &Result<LookupIp, ResolveError>, now: Instant) {
+        let valid_until = match result {
+            Ok(lookup_ip) => lookup_ip.valid_until(),
+            Err(e) => match e.kind() {
+                ResolveErrorKind::NoRecordsFound(_) => now + DNS_NEGATIVE_TTL,
+                _ => return,
+            },
+        };
+        if valid_until <= now || self.max_entries == 0 {
+            return;
+        }
+        let key = host.to_lowercase();
+        if !self.entries.contains_key(&key) && self.entries.len() >= self.max_entries {
+            self.make_room(now);
+        }
+        self.uses += 1;
+        self.entries.insert(
+            key,
+            DnsCacheEntry {
+                result: result.clone(),
+                valid_until,
+                last_used: self.uses,
+            },
+        );
+    }
+
+    fn make_room(&mut self, now: Instant) {
+        self.entries.retain(|_, entry| entry.valid_until > now);
+        if self.entries.len() < self.max_entries {
+            return;
+        }
+        let least_recently_used = self
+            .entries
+            .iter()
+            .min_by_key(|(_, entry)| entry.last_used)
+            .map(|(host, _)| host.clone())
+            .expect("Full DNS cache has no entries");
+        self.entries.remove(&least_recently_used);
+    }
+}
+
+// Answers repeated lookups of the same hostname from a DnsCache rather than asking the DNS
+// servers every time.
+pub struct ResolverWrapperCaching {
+    delegate: Box<dyn ResolverWrapper>,
+    cache: Arc<Mutex<DnsCache>>,
+}
+
+impl ResolverWrapper for ResolverWrapperCaching {
+    fn lookup_ip(&self, host_opt: Option<String>) -> Box<WrappedLookupIpFuture> {
+        let host = match host_opt {
+            Some(host) => host,
+            None => return self.delegate.lookup_ip(None),
+        };
+        if let Some(result) = self
+            .cache
+            .lock()
+            .expect("DNS cache is poisoned")
+            .get(&host, Instant::now())
+        {
+            return Box::new(future::result(result));
+        }
+        let cache = self.cache.clone();
+        Box::new(
+            self.delegate
+                .lookup_ip(Some(host.clone()))
+                .then(move |result| {
+                    cache.lock().expect("DNS cache is poisoned").put(
+                        &host,
+                        &result,
+                        Instant::now(),
+                    );
+                    result
+                }),
+        )
+    }
+}
+
+impl ResolverWrapperCaching {
+    pub fn new(delegate: Box<dyn ResolverWrapper>, max_entries: usize) -> ResolverWrapperCaching {
+        ResolverWrapperCaching {
+            delegate,
+            cache: Arc::new(Mutex::new(DnsCache::new(max_entries))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proxy_client::local_test_utils::ResolverWrapperMock;
+    use std::net::IpAddr;
+    use std::str::FromStr;
+    use trust_dns_proto::op::Query;
+    use trust_dns_proto::rr::RData;
+    use trust_dns_resolver::lookup::Lookup;
+
+    fn make_lookup_ip(ip_addr: &str, valid_until: Instant) -> LookupIp {
+        let rdata = match IpAddr::from_str(ip_addr).unwrap() {
+            IpAddr::V4(ip_addr) => RData::A(ip_addr),
+            IpAddr::V6(ip_addr) => RData::AAAA(ip_addr),
+        };
+        Lookup::new_with_deadline(Arc::new(vec![rdata]), valid_until).into()
+    }
+
+    fn ips_of(result: Option<Result<LookupIp, ResolveError>>) -> Vec<IpAddr> {
+        result.unwrap().unwrap().iter().collect()
+    }
+
+    #[test]
+    fn cache_remembers_a_lookup_until_its_ttl_runs_out() {
+        let now = Instant::now();
+        let mut subject = DnsCache::new(10);
+        let lookup_ip = make_lookup_ip("1.2.3.4", now + Duration::from_secs(10));
+
+        subject.put("booga.com", &Ok(lookup_ip), now);
+
+        assert_eq!(
+            ips_of(subject.get("BOOGA.com", now + Duration::from_secs(9))),
+            vec![IpAddr::from_str("1.2.3.4").unwrap()]
+        );
+        assert_eq!(
+            subject
+                .get("booga.com", now + Duration::from_secs(10))
+                .is_none(),
+            true
+        );
+        assert_eq!(subject.len(), 0);
+    }
+
+    #[test]
+    fn cache_remembers_a_nonexistent_hostname_for_the_negative_ttl() {
+        let now = Instant::now();
+        let mut subject = DnsCache::new(10);
+        let error = ResolveError::from(ResolveErrorKind::NoRecordsFound(Query::new()));
+
+        subject.put("nonexistent.com", &Err(error.clone()), now);
+
+        assert_eq!(
+            subject
+                .get(
+                    "nonexistent.com",
+                    now + DNS_NEGATIVE_TTL - Duration::from_secs(1)
+                )
+                .unwrap()
+                .unwrap_err()
+                .to_string(),
+            error.to_string()
+        );
+        assert_eq!(
+            subject
+                .get("nonexistent.com", now + DNS_NEGATIVE_TTL)
+                .is_none(),
+            true
+        );
+    }
+
+    #[test]
+    fn cache_does_not_remember_other_failures() {
+        let now = Instant::now();
+        let mut subject = DnsCache::new(10);
+
+        subject.put(
+            "booga.com",
+            &Err(ResolveError::from(ResolveErrorKind::Io)),
+            now,
+        );
+
+        assert_eq!(subject.len(), 0);
+    }
+
+    #[test]
+    fn full_cache_throws_out_expired_entries_before_live_ones() {
+        let now = Instant::now();
+        let mut subject = DnsCache::new(2);
+        subject.put(
+            "short.com",
+            &Ok(make_lookup_ip("1.1.1.1", now + Duration::from_secs(1))),
+            now,
+        );
+        subject.put(
+            "long.com",
+            &Ok(make_lookup_ip("2.2.2.2", now + Duration::from_secs(100))),
+            now,
+        );
+        subject.get("long.com", now);
+        let later = now + Duration::from_secs(2);
+
+        subject.put(
+            "new.com",
+            &Ok(make_lookup_ip("3.3.3.3", later + Duration::from_secs(100))),
+            later,
+        );
+
+        assert_eq!(subject.len(), 2);
+        assert_eq!(ips_of(subject.get("long.com", later)).len(), 1);
+        assert_eq!(ips_of(subject.get("new.com", later)).len(), 1);
+    }
+
+    #[test]
+    fn full_cache_throws_out_the_least_recently_used_entry() {
+        let now = Instant::now();
+        let valid_until = now + Duration::from_secs(100);
+        let mut subject = DnsCache::new(2);
+        subject.put("one.com", &Ok(make_lookup_ip("1.1.1.1", valid_until)), now);
+        subject.put("two.com", &Ok(make_lookup_ip("2.2.2.2", valid_until)), now);
+        subject.get("one.com", now);
+
+        subject.put(
+            "three.com",
+            &Ok(make_lookup_ip("3.3.3.3", valid_until)),
+            now,
+        );
+
+        assert_eq!(subject.len(), 2);
+        assert_eq!(subject.get("two.com", now).is_none(), true);
+        assert_eq!(
+            ips_of(subject.get("one.com", now)),
+            vec![IpAddr::from_str("1.1.1.1").unwrap()]
+        );
+        assert_eq!(
+            ips_of(subject.get("three.com", now)),
+            vec![IpAddr::from_str("3.3.3.3").unwrap()]
+        );
+    }
+
+    #[test]
+    fn repeated_lookups_of_the_same_host_are_answered_from_the_cache() {
+        let lookup_ip_parameters = Arc::new(Mutex::new(vec![]));
+        let delegate = ResolverWrapperMock::new()
+            .lookup_ip_parameters(&lookup_ip_parameters)
+            .lookup_ip_success(vec![IpAddr::from_str("2.3.4.5").unwrap()]);
+        let subject = ResolverWrapperCaching::new(Box::new(delegate), DNS_CACHE_MAX_ENTRIES);
+
+        let first: Vec<IpAddr> = subject
+            .lookup_ip(Some(String::from("booga.com")))
+            .wait()
+            .unwrap()
+            .iter()
+            .collect();
+        let second: Vec<IpAddr> = subject
+            .lookup_ip(Some(String::from("Booga.com")))
+            .wait()
+            .unwrap()
+            .iter()
+            .collect();
+
+        assert_eq!(first, vec![IpAddr::from_str("2.3.4.5").unwrap()]);
+        assert_eq!(second, first);
+        assert_eq!(
+            *lookup_ip_parameters.lock().unwrap(),
+            vec![Some(String::from("booga.com"))]
+        );
+    }
+
+    #[test]
+    fn lookups_that_fail_for_reasons_other_than_a_nonexistent_host_are_tried_again() {
+        let lookup_ip_parameters = Arc::new(Mutex::new(vec![]));
+        let delegate = ResolverWrapperMock::new()
+            .lookup_ip_parameters(&lookup_ip_parameters)
+            .lookup_ip_failure(ResolveError::from(ResolveErrorKind::Io))
+            .lookup_ip_success(vec![IpAddr::from_str("2.3.4.5").unwrap()]);
+        let subject = ResolverWrapperCaching::new(Box::new(delegate), DNS_CACHE_MAX_ENTRIES);
+
+        let first = subject.lookup_ip(Some(String::from("booga.com"))).wait();
+        let second = subject.lookup_ip(Some(String::from("booga.com"))).wait();
+
+        assert_eq!(first.is_err(), true);
+        assert_eq!(
+            second.unwrap().iter().collect::<Vec<IpAddr>>(),
+            vec![IpAddr::from_str("2.3.4.5").unwrap()]
+        );
+        assert_eq!(lookup_ip_parameters.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn lookups_without_a_host_are_not_cached() {
+        let lookup_ip_parameters = Arc::new(Mutex::new(vec![]));
+        let delegate = ResolverWrapperMock::new()
+            .lookup_ip_parameters(&lookup_ip_parameters)
+            .lookup_ip_success(vec![IpAddr::from_str("2.3.4.5").unwrap()])
+            .lookup_ip_success(vec![IpAddr::from_str("2.3.4.5").unwrap()]);
+        let subject = ResolverWrapperCaching::new(Box::new(delegate), DNS_CACHE_MAX_ENTRIES);
+
+        subject.lookup_ip(None).wait().unwrap();
+        subject.lookup_ip(None).wait().unwrap();
+
+        assert_eq!(*lookup_ip_parameters.lock().unwrap(), vec![None, None]);
+    }
+}
@@ -0,0 +1,330 @@
+// Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use crate::sub_lib::logger::Logger;
+use crate::sub_lib::proxy_client::ExitLogConfig;
+use crate::sub_lib::proxy_client::ExitLogLevel;
+use crate::sub_lib::stream_key::StreamKey;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::fs;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+use std::time::Instant;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+pub const EXIT_LOG_FILENAME: &str = "exit_requests.log";
+pub const EXIT_LOG_MAX_BYTES: u64 = 0x100000;
+pub const EXIT_LOG_GENERATIONS: usize = 5;
+pub const EXIT_LOG_AGGREGATE_INTERVAL: Duration = Duration::from_secs(3600);
+
+struct Exchange {
+    host: Option<String>,
+    upstream_bytes: u64,
+    downstream_bytes: u64,
+}
+
+#[derive(Default)]
+struct Tally {
+    streams: u64,
+    bytes: u64,
+}
+
+// Tells an exit operator what their Node has been exiting to, in a file of its own so it never
+// mixes with the Node's diagnostic log. Nothing from any payload is ever written; at the Aggregate
+// level not even host names are, only counts per top-level domain.
+pub struct ExitRequestLog {
+    level: ExitLogLevel,
+    path: PathBuf,
+    max_bytes: u64,
+    exchanges: HashMap<StreamKey, Exchange>,
+    tallies: BTreeMap<String, Tally>,
+    last_flush: Instant,
+    logger: Logger,
+}
+
+impl ExitRequestLog {
+    pub fn new(config: &ExitLogConfig) -> ExitRequestLog {
+        ExitRequestLog {
+            level: config.level,
+            path: config.data_directory.join(EXIT_LOG_FILENAME),
+            max_bytes: EXIT_LOG_MAX_BYTES,
+            exchanges: HashMap::new(),
+            tallies: BTreeMap::new(),
+            last_flush: Instant::now(),
+            logger: Logger::new("Exit Log"),
+        }
+    }
+
+    pub fn outbound(&mut self, stream_key: &StreamKey, host: &Option<String>, bytes: usize) {
+        if self.level == ExitLogLevel::Off {
+            return;
+        }
+        let exchange = self
+            .exchanges
+            .entry(*stream_key)
+            .or_insert_with(|| Exchange {
+                host: host.clone(),
+                upstream_bytes: 0,
+                downstream_bytes: 0,
+            });
+        exchange.upstream_bytes += bytes as u64;
+    }
+
+    pub fn inbound(&mut self, stream_key: &StreamKey, bytes: usize) {
+        if let Some(exchange) = self.exchanges.get_mut(stream_key) {
+            exchange.downstream_bytes += bytes as u64;
+        }
+    }
+
+    pub fn finish(&mut self, stream_key: &StreamKey) {
+        let exchange = match self.exchanges.remove(stream_key) {
+            Some(exchange) => exchange,
+            None => return,
+        };
+        match self.level {
+            ExitLogLevel::Off => (),
+            ExitLogLevel::Full => {
+                let line = format!(
+                    "{} {} up {} down {}",
+                    ExitRequestLog::now(),
+                    exchange
+                        .host
+                        .as_ref()
+                        .map(|h| h.as_str())
+                        .unwrap_or("(unknown)"),
+                    exchange.upstream_bytes,
+                    exchange.downstream_bytes
+                );
+                self.write_line(line);
+            }
+            ExitLogLevel::Aggregate => {
+                let tally = self
+                    .tallies
+                    .entry(ExitRequestLog::top_level_domain(&exchange.host))
+                    .or_insert_with(Tally::default);
+                tally.streams += 1;
+                tally.bytes += exchange.upstream_bytes + exchange.downstream_bytes;
+                if self.last_flush.elapsed() >= EXIT_LOG_AGGREGATE_INTERVAL {
+                    self.flush_aggregate();
+                }
+            }
+        }
+    }
+
+    pub fn flush_aggregate(&mut self) {
+        let now = ExitRequestLog::now();
+        let lines: Vec<String> = self
+            .tallies
+            .iter()
+            .map(|(tld, tally)| {
+                format!(
+                    "{} {} streams {} bytes {}",
+                    now, tld, tally.streams, tally.bytes
+                )
+            })
+            .collect();
+        lines.into_iter().for_each(|line| self.write_line(line));
+        self.tallies.clear();
+        self.last_flush = Instant::now();
+    }
+
+    fn write_line(&mut self, line: String) {
+        self.rotate_if_full();
+        let result = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| writeln!(file, "{}", line));
+        if let Err(e) = result {
+            self.logger.warning(format!(
+                "Could not write to exit log {:?}: {}",
+                self.path, e
+            ));
+        }
+    }
+
+    fn rotate_if_full(&self) {
+        match fs::metadata(&self.path) {
+            Ok(ref metadata) if metadata.len() >= self.max_bytes => (),
+            _ => return,
+        }
+        for generation in (1..EXIT_LOG_GENERATIONS).rev() {
+            fs::rename(
+                self.generation_path(generation),
+                self.generation_path(generation + 1),
+            )
+            .is_ok();
+        }
+        if let Err(e) = fs::rename(&self.path, self.generation_path(1)) {
+            self.logger
+                .warning(format!("Could not rotate exit log {:?}: {}", self.path, e));
+        }
+    }
+
+    fn generation_path(&self, generation: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{}", generation));
+        PathBuf::from(path)
+    }
+
+    fn top_level_domain(host: &Option<String>) -> String {
+        match host {
+            None => String::from("(unknown)"),
+            Some(host) if IpAddr::from_str(host).is_ok() => String::from("(ip address)"),
+            Some(host) => match host.trim_end_matches('.').rsplit('.').next() {
+                Some(tld) if !tld.is_empty() => tld.to_lowercase(),
+                _ => String::from("(unknown)"),
+            },
+        }
+    }
+
+    fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("System clock is before 1970")
+            .as_secs()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sub_lib::cryptde::PublicKey;
+    use std::net::SocketAddr;
+
+    const BASE_TEST_DIR: &str = "generated/test/exit_request_log";
+
+    fn make_subject(name: &str, level: ExitLogLevel) -> ExitRequestLog {
+        let data_directory = PathBuf::from(format!("{}/{}", BASE_TEST_DIR, name));
+        fs::remove_dir_all(&data_directory).is_ok();
+        fs::create_dir_all(&data_directory).is_ok();
+        ExitRequestLog::new(&ExitLogConfig {
+            level,
+            data_directory,
+        })
+    }
+
+    fn make_stream_key(peer_addr: &str) -> StreamKey {
+        StreamKey::new(
+            PublicKey::new(&b"booga"[..]),
+            SocketAddr::from_str(peer_addr).unwrap(),
+        )
+    }
+
+    fn log_lines(subject: &ExitRequestLog) -> Vec<String> {
+        let contents = fs::read_to_string(&subject.path).unwrap_or(String::new());
+        contents
+            .lines()
+            .map(|line| {
+                let mut words: Vec<&str> = line.split(' ').collect();
+                words.remove(0); // timestamp
+                words.join(" ")
+            })
+            .collect()
+    }
+
+    #[test]
+    fn constant_values() {
+        assert_eq!(EXIT_LOG_FILENAME, "exit_requests.log");
+        assert_eq!(EXIT_LOG_MAX_BYTES, 0x100000);
+        assert_eq!(EXIT_LOG_GENERATIONS, 5);
+        assert_eq!(EXIT_LOG_AGGREGATE_INTERVAL, Duration::from_secs(3600));
+    }
+
+    #[test]
+    fn off_level_writes_nothing() {
+        let mut subject = make_subject("off_level_writes_nothing", ExitLogLevel::Off);
+        let stream_key = make_stream_key("1.2.3.4:5678");
+
+        subject.outbound(&stream_key, &Some(String::from("example.com")), 100);
+        subject.inbound(&stream_key, 1000);
+        subject.finish(&stream_key);
+        subject.flush_aggregate();
+
+        assert_eq!(subject.path.exists(), false);
+    }
+
+    #[test]
+    fn full_level_writes_host_and_byte_counts_per_stream() {
+        let mut subject = make_subject(
+            "full_level_writes_host_and_byte_counts_per_stream",
+            ExitLogLevel::Full,
+        );
+        let one_key = make_stream_key("1.2.3.4:5678");
+        let another_key = make_stream_key("2.3.4.5:6789");
+
+        subject.outbound(&one_key, &Some(String::from("example.com")), 100);
+        subject.outbound(&another_key, &None, 10);
+        subject.outbound(&one_key, &None, 50);
+        subject.inbound(&one_key, 1000);
+        subject.inbound(&another_key, 20);
+        subject.finish(&one_key);
+        subject.finish(&another_key);
+
+        assert_eq!(
+            log_lines(&subject),
+            vec![
+                String::from("example.com up 150 down 1000"),
+                String::from("(unknown) up 10 down 20"),
+            ]
+        );
+    }
+
+    #[test]
+    fn aggregate_level_writes_only_counts_per_top_level_domain() {
+        let mut subject = make_subject(
+            "aggregate_level_writes_only_counts_per_top_level_domain",
+            ExitLogLevel::Aggregate,
+        );
+        let hosts = vec!["www.example.com", "other.COM", "bbc.co.uk", "1.2.3.4"];
+        hosts.iter().enumerate().for_each(|(index, host)| {
+            let stream_key = make_stream_key(&format!("5.6.7.8:{}", 1000 + index));
+            subject.outbound(&stream_key, &Some(String::from(*host)), 100);
+            subject.inbound(&stream_key, 1000);
+            subject.finish(&stream_key);
+        });
+        assert_eq!(subject.path.exists(), false);
+
+        subject.flush_aggregate();
+
+        assert_eq!(
+            log_lines(&subject),
+            vec![
+                String::from("(ip address) streams 1 bytes 1100"),
+                String::from("com streams 2 bytes 2200"),
+                String::from("uk streams 1 bytes 1100"),
+            ]
+        );
+        let contents = fs::read_to_string(&subject.path).unwrap();
+        assert_eq!(contents.contains("example"), false);
+    }
+
+    #[test]
+    fn full_log_is_rotated_when_it_gets_too_big() {
+        let mut subject = make_subject(
+            "full_log_is_rotated_when_it_gets_too_big",
+            ExitLogLevel::Full,
+        );
+        subject.max_bytes = 10;
+        (0..(EXIT_LOG_GENERATIONS + 3)).for_each(|index| {
+            let stream_key = make_stream_key(&format!("5.6.7.8:{}", 1000 + index));
+            subject.outbound(&stream_key, &Some(format!("host{}.com", index)), 1);
+            subject.finish(&stream_key);
+        });
+
+        assert_eq!(
+            log_lines(&subject),
+            vec![format!("host{}.com up 1 down 0", EXIT_LOG_GENERATIONS + 2)]
+        );
+        let oldest = fs::read_to_string(subject.generation_path(EXIT_LOG_GENERATIONS)).unwrap();
+        assert_eq!(oldest.contains("host2.com"), true);
+        assert_eq!(
+            subject.generation_path(EXIT_LOG_GENERATIONS + 1).exists(),
+            false
+        );
+    }
+}
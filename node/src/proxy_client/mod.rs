@@ -3,11 +3,15 @@
 //#[cfg(test)]
 //extern crate test_utils;
 
+mod dns_cache;
+mod exit_request_log;
 #[cfg(test)]
 mod local_test_utils;
 pub mod proxy_client;
-mod resolver_wrapper;
+pub mod resolver_wrapper;
 mod stream_establisher;
 mod stream_handler_pool;
 mod stream_reader;
 mod stream_writer;
+mod throughput_limiter;
+mod udp_socket_pool;
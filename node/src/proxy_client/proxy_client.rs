@@ -1,21 +1,39 @@
 // Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use crate::proxy_client::exit_request_log::ExitRequestLog;
+use crate::proxy_client::resolver_wrapper::ResolverWrapper;
 use crate::proxy_client::resolver_wrapper::ResolverWrapperFactory;
 use crate::proxy_client::resolver_wrapper::ResolverWrapperFactoryReal;
+use crate::proxy_client::resolver_wrapper::ResolverWrapperTimed;
 use crate::proxy_client::stream_handler_pool::StreamHandlerPool;
 use crate::proxy_client::stream_handler_pool::StreamHandlerPoolFactory;
 use crate::proxy_client::stream_handler_pool::StreamHandlerPoolFactoryReal;
+use crate::proxy_client::throughput_limiter::ExitThroughputLimiters;
+use crate::sub_lib::accountant::CorrelationId;
 use crate::sub_lib::accountant::ReportExitServiceProvidedMessage;
+use crate::sub_lib::accountant::DEFAULT_MAX_REPORT_DELAY;
 use crate::sub_lib::cryptde::CryptDE;
 use crate::sub_lib::cryptde::PublicKey;
 use crate::sub_lib::hopper::ExpiredCoresPackage;
 use crate::sub_lib::hopper::IncipientCoresPackage;
 use crate::sub_lib::logger::Logger;
+use crate::sub_lib::metrics::MetricsReport;
+use crate::sub_lib::neighborhood::RateCard;
 use crate::sub_lib::peer_actors::BindMessage;
+use crate::sub_lib::peer_actors::ShutdownMessage;
+use crate::sub_lib::peer_actors::StreamCountQueryMessage;
+use crate::sub_lib::proxy_client::error_socket_addr;
 use crate::sub_lib::proxy_client::ClientResponsePayload;
+use crate::sub_lib::proxy_client::DnsProtocol;
+use crate::sub_lib::proxy_client::DnsResolverConfig;
+use crate::sub_lib::proxy_client::ExitConnectTimeouts;
+use crate::sub_lib::proxy_client::ExitLogConfig;
+use crate::sub_lib::proxy_client::ExitLogLevel;
+use crate::sub_lib::proxy_client::ExitPolicy;
+use crate::sub_lib::proxy_client::ExitThroughput;
+use crate::sub_lib::proxy_client::ExitThroughputConfig;
+use crate::sub_lib::proxy_client::ExitThroughputQueryMessage;
 use crate::sub_lib::proxy_client::InboundServerData;
 use crate::sub_lib::proxy_client::ProxyClientSubs;
-use crate::sub_lib::proxy_client::TEMPORARY_PER_EXIT_BYTE_RATE;
-use crate::sub_lib::proxy_client::TEMPORARY_PER_EXIT_RATE;
 use crate::sub_lib::proxy_server::ClientRequestPayload;
 use crate::sub_lib::route::Route;
 use crate::sub_lib::sequence_buffer::SequencedPacket;
@@ -26,24 +44,45 @@ use actix::Actor;
 use actix::Addr;
 use actix::Context;
 use actix::Handler;
+use actix::MessageResult;
 use actix::Recipient;
 use actix::Syn;
+use std::cmp::max;
 use std::collections::HashMap;
 use std::net::SocketAddr;
+use std::time::Duration;
+use std::time::Instant;
+use std::time::SystemTime;
+use trust_dns_resolver::config::LookupIpStrategy;
 use trust_dns_resolver::config::NameServerConfig;
 use trust_dns_resolver::config::Protocol;
 use trust_dns_resolver::config::ResolverConfig;
 use trust_dns_resolver::config::ResolverOpts;
 
+// Originators can open streams and vanish without ever sending last_data; past this many, the
+// least-recently-used StreamContext is dropped to make room.
+pub const MAX_STREAM_CONTEXTS: usize = 10000;
+
 pub struct ProxyClient {
     dns_servers: Vec<SocketAddr>,
+    dns_resolver_config: DnsResolverConfig,
     resolver_wrapper_factory: Box<dyn ResolverWrapperFactory>,
     stream_handler_pool_factory: Box<dyn StreamHandlerPoolFactory>,
+    throughput_limiters: ExitThroughputLimiters,
+    connect_timeouts: ExitConnectTimeouts,
+    max_report_delay: Duration,
+    rate_card: RateCard,
+    exit_policy: ExitPolicy,
+    exit_request_log: Option<ExitRequestLog>,
+    offers_exit_tls: bool,
     cryptde: &'static dyn CryptDE,
     to_hopper: Option<Recipient<Syn, IncipientCoresPackage>>,
     to_accountant: Option<Recipient<Syn, ReportExitServiceProvidedMessage>>,
+    to_metrics: Option<Recipient<Syn, MetricsReport>>,
     pool: Option<Box<dyn StreamHandlerPool>>,
     stream_contexts: HashMap<StreamKey, StreamContext>,
+    stream_context_cap: usize,
+    stream_context_evictions: u64,
     logger: Logger,
 }
 
@@ -59,24 +98,52 @@ impl Handler<BindMessage> for ProxyClient {
         ctx.set_mailbox_capacity(NODE_MAILBOX_CAPACITY);
         self.to_hopper = Some(msg.peer_actors.hopper.from_hopper_client);
         self.to_accountant = Some(msg.peer_actors.accountant.report_exit_service_provided);
+        self.to_metrics = Some(msg.peer_actors.metrics.report.clone());
         let mut config = ResolverConfig::new();
         for dns_server_ref in &self.dns_servers {
             self.logger
                 .info(format!("Adding DNS server: {}", dns_server_ref.ip()));
             config.add_name_server(NameServerConfig {
                 socket_addr: *dns_server_ref,
-                protocol: Protocol::Udp,
-                tls_dns_name: None,
+                protocol: match self.dns_resolver_config.protocol {
+                    DnsProtocol::Udp => Protocol::Udp,
+                    DnsProtocol::Tcp => Protocol::Tcp,
+                    DnsProtocol::Tls => Protocol::Tls,
+                },
+                tls_dns_name: self
+                    .dns_resolver_config
+                    .tls_dns_names
+                    .get(&dns_server_ref.ip())
+                    .cloned(),
             })
         }
-        let opts = ResolverOpts::default();
-        let resolver = self.resolver_wrapper_factory.make(config, opts);
-        self.pool = Some(self.stream_handler_pool_factory.make(
+        let resolver = self.make_resolver(config);
+        let mut pool = self.stream_handler_pool_factory.make(
             resolver,
             self.cryptde,
             self.to_accountant.clone().expect("Accountant is unbound"),
             msg.peer_actors.proxy_client.inbound_server_data,
-        ));
+            self.throughput_limiters.clone(),
+            self.connect_timeouts.clone(),
+            self.max_report_delay,
+            self.rate_card,
+            self.exit_policy.clone(),
+        );
+        for dns_server in &self.exit_policy.offered_dns_servers {
+            self.logger.info(format!(
+                "Offering DNS server to originators: {}",
+                dns_server
+            ));
+            let mut config = ResolverConfig::new();
+            config.add_name_server(NameServerConfig {
+                socket_addr: SocketAddr::new(*dns_server, DnsProtocol::Udp.default_port()),
+                protocol: Protocol::Udp,
+                tls_dns_name: None,
+            });
+            let resolver = self.make_resolver(config);
+            pool.offer_dns_server(*dns_server, resolver);
+        }
+        self.pool = Some(pool);
         ()
     }
 }
@@ -96,17 +163,76 @@ impl Handler<ExpiredCoresPackage> for ProxyClient {
                 return ();
             }
         };
-        let pool = self.pool.as_mut().expect("StreamHandlerPool unbound");
+        if payload.cancel {
+            self.cancel_stream(payload, msg.consuming_wallet);
+            return ();
+        }
+        if payload.tls_at_exit && !self.offers_exit_tls {
+            self.logger.for_stream(&payload.stream_key).warning(format!(
+                "Refusing stream {:?}: originator asked for TLS at the exit to {}, but this Node doesn't offer it",
+                payload.stream_key,
+                payload
+                    .target_hostname
+                    .as_ref()
+                    .map(|h| h.as_str())
+                    .unwrap_or("(unknown)")
+            ));
+            return ();
+        }
+        if let Some(reason) = self
+            .exit_policy
+            .refusal(&payload.target_hostname, payload.target_port)
+        {
+            self.logger.for_stream(&payload.stream_key).warning(format!(
+                "Refusing stream {:?} by exit policy: {}",
+                payload.stream_key, reason
+            ));
+            self.refuse_stream(&payload, msg.remaining_route);
+            return ();
+        }
+        if let Some(reason) = self.exit_policy.dns_server_refusal(payload.dns_server) {
+            self.logger.for_stream(&payload.stream_key).warning(format!(
+                "Refusing stream {:?} by exit policy: {}",
+                payload.stream_key, reason
+            ));
+            self.refuse_stream(&payload, msg.remaining_route);
+            return ();
+        }
         let consuming_wallet = msg.consuming_wallet;
         let return_route = msg.remaining_route;
+        let (requests_received, requests_acknowledged) =
+            match self.stream_contexts.get(&payload.stream_key) {
+                Some(stream_context) => (
+                    stream_context.requests_received + 1,
+                    stream_context.requests_acknowledged,
+                ),
+                None => (1, 0),
+            };
         let latest_stream_context = StreamContext {
             return_route,
             payload_destination_key: payload.originator_public_key.clone(),
             consuming_wallet: consuming_wallet.clone(),
+            last_used: Instant::now(),
+            upload_window_opt: payload.upload_window,
+            requests_received,
+            requests_acknowledged,
+            udp: payload.udp,
         };
         self.stream_contexts
             .insert(payload.stream_key.clone(), latest_stream_context);
+        self.evict_stream_contexts_over_cap(&payload.stream_key);
+        self.acknowledge_requests_if_due(&payload.stream_key);
+        if let Some(exit_request_log) = self.exit_request_log.as_mut() {
+            exit_request_log.outbound(
+                &payload.stream_key,
+                &payload.target_hostname,
+                payload.sequenced_packet.data.len(),
+            );
+        }
+        let byte_count = payload.sequenced_packet.data.len();
+        let pool = self.pool.as_mut().expect("StreamHandlerPool unbound");
         pool.process_package(payload, consuming_wallet);
+        self.report_bytes_exited(byte_count);
         self.logger.debug(format!("ExpiredCoresPackage handled"));
         ()
     }
@@ -124,7 +250,7 @@ impl Handler<InboundServerData> for ProxyClient {
         let stream_context = match self.stream_contexts.get(&msg.stream_key) {
             Some(sc) => sc,
             None => {
-                self.logger.error(format!(
+                self.logger.for_stream(&msg_stream_key).error(format!(
                     "Received unsolicited {}-byte response from {}, seq {}: ignoring",
                     msg_data_len, msg_source, msg_sequence_number
                 ));
@@ -134,40 +260,181 @@ impl Handler<InboundServerData> for ProxyClient {
         if self.send_response_to_hopper(msg, &stream_context).is_err() {
             return ();
         };
-        self.report_response_exit_to_accountant(&stream_context, msg_data_len);
+        self.report_response_exit_to_accountant(&msg_stream_key, &stream_context, msg_data_len);
+        self.report_bytes_exited(msg_data_len);
+        if let Some(exit_request_log) = self.exit_request_log.as_mut() {
+            exit_request_log.inbound(&msg_stream_key, msg_data_len);
+            if msg_last_data {
+                exit_request_log.finish(&msg_stream_key);
+            }
+        }
         if msg_last_data {
             self.stream_contexts.remove(&msg_stream_key).is_some();
+            self.log_throughput_utilization();
+        } else if let Some(stream_context) = self.stream_contexts.get_mut(&msg_stream_key) {
+            stream_context.last_used = Instant::now();
+            stream_context.requests_acknowledged = stream_context.requests_received;
         }
         ()
     }
 }
 
+// Every originator with a stream still open through this exit is told it's over, so its browser
+// sees the connection close instead of hanging until the stream times out.
+impl Handler<ShutdownMessage> for ProxyClient {
+    type Result = ();
+
+    fn handle(&mut self, _msg: ShutdownMessage, _ctx: &mut Self::Context) -> Self::Result {
+        let stream_contexts: Vec<(StreamKey, StreamContext)> =
+            self.stream_contexts.drain().collect();
+        let stream_count = stream_contexts.len();
+        stream_contexts
+            .into_iter()
+            .for_each(|(stream_key, stream_context)| {
+                let terminating_payload =
+                    ClientResponsePayload::make_terminating_payload(stream_key);
+                if let Err(err) = self.send_payload_to_hopper(terminating_payload, &stream_context)
+                {
+                    self.logger.for_stream(&stream_key).error(format!(
+                        "Could not create CORES package to close stream {:?} for shutdown: {}",
+                        stream_key, err
+                    ));
+                }
+            });
+        self.logger.info(format!(
+            "Closed {} open stream(s) for shutdown",
+            stream_count
+        ));
+        ()
+    }
+}
+
+impl Handler<StreamCountQueryMessage> for ProxyClient {
+    type Result = MessageResult<StreamCountQueryMessage>;
+
+    fn handle(
+        &mut self,
+        _msg: StreamCountQueryMessage,
+        _ctx: &mut Self::Context,
+    ) -> <Self as Handler<StreamCountQueryMessage>>::Result {
+        MessageResult(self.stream_contexts.len())
+    }
+}
+
+impl Handler<ExitThroughputQueryMessage> for ProxyClient {
+    type Result = MessageResult<ExitThroughputQueryMessage>;
+
+    fn handle(
+        &mut self,
+        _msg: ExitThroughputQueryMessage,
+        _ctx: &mut Self::Context,
+    ) -> <Self as Handler<ExitThroughputQueryMessage>>::Result {
+        MessageResult(ExitThroughput {
+            upstream: self.throughput_limiters.upstream.utilization(),
+            downstream: self.throughput_limiters.downstream.utilization(),
+        })
+    }
+}
+
 impl ProxyClient {
+    // Both A and AAAA records, so servers that only have IPv6 addresses can be reached too
+    fn resolver_opts() -> ResolverOpts {
+        let mut opts = ResolverOpts::default();
+        opts.ip_strategy = LookupIpStrategy::Ipv4AndIpv6;
+        opts
+    }
+
+    fn make_resolver(&self, config: ResolverConfig) -> Box<dyn ResolverWrapper> {
+        Box::new(ResolverWrapperTimed::new(
+            self.resolver_wrapper_factory
+                .make(config, ProxyClient::resolver_opts()),
+            self.to_metrics.clone().expect("Metrics is unbound"),
+        ))
+    }
+
     pub fn new(cryptde: &'static dyn CryptDE, dns_servers: Vec<SocketAddr>) -> ProxyClient {
         if dns_servers.is_empty() {
             panic! ("Proxy Client requires at least one DNS server IP address after the --dns_servers parameter")
         }
         ProxyClient {
             dns_servers,
+            dns_resolver_config: DnsResolverConfig::default(),
             resolver_wrapper_factory: Box::new(ResolverWrapperFactoryReal {}),
             stream_handler_pool_factory: Box::new(StreamHandlerPoolFactoryReal {}),
+            throughput_limiters: ExitThroughputLimiters::unlimited(),
+            connect_timeouts: ExitConnectTimeouts::default(),
+            max_report_delay: DEFAULT_MAX_REPORT_DELAY,
+            rate_card: RateCard::default(),
+            exit_policy: ExitPolicy::default(),
+            exit_request_log: None,
+            offers_exit_tls: false,
             cryptde,
             to_hopper: None,
             to_accountant: None,
+            to_metrics: None,
             pool: None,
             stream_contexts: HashMap::new(),
+            stream_context_cap: MAX_STREAM_CONTEXTS,
+            stream_context_evictions: 0,
             logger: Logger::new("Proxy Client"),
         }
     }
 
+    pub fn resolve_dns_with(&mut self, config: &DnsResolverConfig) {
+        self.dns_resolver_config = config.clone();
+    }
+
+    pub fn limit_exit_throughput(&mut self, config: &ExitThroughputConfig) {
+        self.throughput_limiters = ExitThroughputLimiters::new(config);
+    }
+
+    pub fn limit_connect_time(&mut self, connect_timeouts: &ExitConnectTimeouts) {
+        self.connect_timeouts = connect_timeouts.clone();
+    }
+
+    pub fn limit_report_delay(&mut self, max_report_delay: Duration) {
+        self.max_report_delay = max_report_delay;
+    }
+
+    // What to charge for exit service, both for requests written and for responses relayed
+    pub fn charge(&mut self, rate_card: RateCard) {
+        self.rate_card = rate_card;
+    }
+
+    pub fn enforce_exit_policy(&mut self, exit_policy: &ExitPolicy) {
+        self.exit_policy = exit_policy.clone();
+    }
+
+    pub fn enable_exit_log(&mut self, config: &ExitLogConfig) {
+        self.exit_request_log = match config.level {
+            ExitLogLevel::Off => None,
+            _ => Some(ExitRequestLog::new(config)),
+        };
+    }
+
+    pub fn offer_exit_tls(&mut self) {
+        self.offers_exit_tls = true;
+    }
+
     pub fn make_subs_from(addr: &Addr<Syn, ProxyClient>) -> ProxyClientSubs {
         ProxyClientSubs {
             bind: addr.clone().recipient::<BindMessage>(),
             from_hopper: addr.clone().recipient::<ExpiredCoresPackage>(),
             inbound_server_data: addr.clone().recipient::<InboundServerData>(),
+            stream_count_query: addr.clone().recipient::<StreamCountQueryMessage>(),
+            exit_throughput_query: addr.clone().recipient::<ExitThroughputQueryMessage>(),
+            shutdown: addr.clone().recipient::<ShutdownMessage>(),
         }
     }
 
+    fn report_bytes_exited(&self, byte_count: usize) {
+        self.to_metrics
+            .as_ref()
+            .expect("Metrics is unbound")
+            .try_send(MetricsReport::BytesExited(byte_count as u64))
+            .expect("Metrics is dead");
+    }
+
     fn send_response_to_hopper(
         &self,
         msg: InboundServerData,
@@ -176,6 +443,7 @@ impl ProxyClient {
         let msg_data_len = msg.data.len() as u32;
         let msg_source = msg.source;
         let msg_sequence_number = msg.sequence_number;
+        let logger = self.logger.for_stream(&msg.stream_key);
         let payload = ClientResponsePayload {
             stream_key: msg.stream_key,
             sequenced_packet: SequencedPacket {
@@ -183,19 +451,84 @@ impl ProxyClient {
                 sequence_number: msg.sequence_number,
                 last_data: msg.last_data,
             },
+            requests_received: stream_context
+                .upload_window_opt
+                .map(|_| stream_context.requests_received),
+            truncated: msg.truncated,
+            udp: stream_context.udp,
+            exit_failed: msg_source == error_socket_addr(),
+        };
+        self.send_payload_to_hopper(payload, stream_context)
+            .map_err(|err| {
+                logger.error (format! ("Could not create CORES package for {}-byte response from {}, seq {}: {} - ignoring", msg_data_len, msg_source, msg_sequence_number, err));
+            })
+    }
+
+    // Data in responses already tells the originator how many requests have arrived; a stream
+    // whose server has been quiet for half the originator's window gets a separate acknowledgement,
+    // and so does the first packet, so that the originator soon knows this exit counts
+    fn acknowledge_requests_if_due(&mut self, stream_key: &StreamKey) {
+        let stream_context = match self.stream_contexts.get(stream_key) {
+            Some(stream_context) => stream_context,
+            None => return,
+        };
+        let upload_window = match stream_context.upload_window_opt {
+            Some(upload_window) => upload_window,
+            None => return,
+        };
+        let requests_received = stream_context.requests_received;
+        let unacknowledged = requests_received - stream_context.requests_acknowledged;
+        if requests_received > 1 && unacknowledged < max(1, upload_window / 2) {
+            return;
+        }
+        let payload =
+            ClientResponsePayload::make_acknowledgement_payload(*stream_key, requests_received);
+        match self.send_payload_to_hopper(payload, stream_context) {
+            Ok(()) => {
+                if let Some(stream_context) = self.stream_contexts.get_mut(stream_key) {
+                    stream_context.requests_acknowledged = requests_received;
+                }
+            }
+            Err(err) => self.logger.for_stream(stream_key).error(format!(
+                "Could not create CORES package to acknowledge {} requests on stream {:?}: {}",
+                requests_received, stream_key, err
+            )),
+        }
+    }
+
+    // The originator hears right away that the stream is over, rather than waiting for it to time out
+    fn refuse_stream(&self, payload: &ClientRequestPayload, return_route: Route) {
+        let stream_context = StreamContext {
+            return_route,
+            payload_destination_key: payload.originator_public_key.clone(),
+            consuming_wallet: None,
+            last_used: Instant::now(),
+            upload_window_opt: None,
+            requests_received: 0,
+            requests_acknowledged: 0,
+            udp: false,
         };
-        let icp = match IncipientCoresPackage::new(
+        let terminating_payload =
+            ClientResponsePayload::make_terminating_payload(payload.stream_key);
+        if let Err(err) = self.send_payload_to_hopper(terminating_payload, &stream_context) {
+            self.logger.for_stream(&payload.stream_key).error(format!(
+                "Could not create CORES package to refuse stream {:?}: {}",
+                payload.stream_key, err
+            ));
+        }
+    }
+
+    fn send_payload_to_hopper(
+        &self,
+        payload: ClientResponsePayload,
+        stream_context: &StreamContext,
+    ) -> Result<(), String> {
+        let icp = IncipientCoresPackage::new(
             self.cryptde,
             stream_context.return_route.clone(),
             payload,
             &stream_context.payload_destination_key,
-        ) {
-            Ok(icp) => icp,
-            Err(err) => {
-                self.logger.error (format! ("Could not create CORES package for {}-byte response from {}, seq {}: {} - ignoring", msg_data_len, msg_source, msg_sequence_number, err));
-                return Err(());
-            }
-        };
+        )?;
         self.to_hopper
             .as_ref()
             .expect("Hopper unbound")
@@ -206,6 +539,7 @@ impl ProxyClient {
 
     fn report_response_exit_to_accountant(
         &self,
+        stream_key: &StreamKey,
         stream_context: &StreamContext,
         msg_data_len: usize,
     ) {
@@ -213,8 +547,9 @@ impl ProxyClient {
             let exit_report = ReportExitServiceProvidedMessage {
                 consuming_wallet,
                 payload_size: msg_data_len,
-                service_rate: TEMPORARY_PER_EXIT_RATE,
-                byte_rate: TEMPORARY_PER_EXIT_BYTE_RATE,
+                service_rate: self.rate_card.exit_service_rate,
+                byte_rate: self.rate_card.exit_byte_rate,
+                correlation_id_opt: Some(CorrelationId::new(*stream_key, SystemTime::now())),
             };
             self.to_accountant
                 .as_ref()
@@ -228,12 +563,72 @@ impl ProxyClient {
             ));
         }
     }
+
+    // The originator's browser is gone, so the stream is forgotten here at once; whatever the server
+    // still sends is neither relayed nor charged for
+    fn cancel_stream(&mut self, payload: ClientRequestPayload, consuming_wallet: Option<Wallet>) {
+        let stream_key = payload.stream_key;
+        if self.stream_contexts.remove(&stream_key).is_some() {
+            if let Some(exit_request_log) = self.exit_request_log.as_mut() {
+                exit_request_log.finish(&stream_key);
+            }
+        }
+        self.logger
+            .for_stream(&stream_key)
+            .debug(format!("Stream {:?} canceled by originator", stream_key));
+        let pool = self.pool.as_mut().expect("StreamHandlerPool unbound");
+        pool.process_package(payload, consuming_wallet);
+    }
+
+    // The stream that has just been inserted is never the one evicted, even if the clock is too coarse
+    // to tell it apart from an older one
+    fn evict_stream_contexts_over_cap(&mut self, newest_stream_key: &StreamKey) {
+        while self.stream_contexts.len() > self.stream_context_cap {
+            let stream_key = match self
+                .stream_contexts
+                .iter()
+                .filter(|(stream_key, _)| *stream_key != newest_stream_key)
+                .min_by_key(|(_, stream_context)| stream_context.last_used)
+            {
+                Some((stream_key, _)) => stream_key.clone(),
+                None => return,
+            };
+            self.stream_contexts.remove(&stream_key);
+            self.stream_context_evictions += 1;
+            if let Some(exit_request_log) = self.exit_request_log.as_mut() {
+                exit_request_log.finish(&stream_key);
+            }
+            self.logger.for_stream(&stream_key).warning(format!(
+                "Stream context cap of {} reached: evicted least-recently-used stream {:?} ({} evicted so far)",
+                self.stream_context_cap, stream_key, self.stream_context_evictions
+            ));
+        }
+    }
+
+    fn log_throughput_utilization(&self) {
+        let upstream = self.throughput_limiters.upstream.utilization();
+        let downstream = self.throughput_limiters.downstream.utilization();
+        self.logger.debug(format!(
+            "Exit throughput: upstream {} B/s (cap {:?}, {} total), downstream {} B/s (cap {:?}, {} total)",
+            upstream.bytes_last_second,
+            upstream.bytes_per_second_cap,
+            upstream.total_bytes,
+            downstream.bytes_last_second,
+            downstream.bytes_per_second_cap,
+            downstream.total_bytes
+        ));
+    }
 }
 
 struct StreamContext {
     return_route: Route,
     payload_destination_key: PublicKey,
     consuming_wallet: Option<Wallet>,
+    last_used: Instant,
+    upload_window_opt: Option<u64>,
+    requests_received: u64,
+    requests_acknowledged: u64,
+    udp: bool,
 }
 
 #[cfg(test)]
@@ -244,13 +639,15 @@ mod tests {
     use crate::proxy_client::resolver_wrapper::ResolverWrapper;
     use crate::proxy_client::stream_handler_pool::StreamHandlerPool;
     use crate::proxy_client::stream_handler_pool::StreamHandlerPoolFactory;
+    use crate::sub_lib::accountant::CorrelationId;
     use crate::sub_lib::accountant::ReportExitServiceProvidedMessage;
     use crate::sub_lib::cryptde::encodex;
     use crate::sub_lib::cryptde::CryptData;
     use crate::sub_lib::cryptde::PublicKey;
+    use crate::sub_lib::hopper::IncipientCoresPackage;
     use crate::sub_lib::proxy_client::ClientResponsePayload;
-    use crate::sub_lib::proxy_client::TEMPORARY_PER_EXIT_BYTE_RATE;
-    use crate::sub_lib::proxy_client::TEMPORARY_PER_EXIT_RATE;
+    use crate::sub_lib::proxy_client::DEFAULT_PER_EXIT_BYTE_RATE;
+    use crate::sub_lib::proxy_client::DEFAULT_PER_EXIT_RATE;
     use crate::sub_lib::proxy_server::ClientRequestPayload;
     use crate::sub_lib::proxy_server::ProxyProtocol;
     use crate::sub_lib::route::Route;
@@ -261,6 +658,7 @@ mod tests {
     use crate::test_utils::recorder::make_recorder;
     use crate::test_utils::recorder::peer_actors_builder;
     use crate::test_utils::recorder::Recorder;
+    use crate::test_utils::recorder::Recording;
     use crate::test_utils::test_utils::cryptde;
     use crate::test_utils::test_utils::make_meaningless_route;
     use crate::test_utils::test_utils::make_meaningless_stream_key;
@@ -268,12 +666,16 @@ mod tests {
     use actix::msgs;
     use actix::Arbiter;
     use actix::System;
+    use futures::future::Future;
     use std::cell::RefCell;
+    use std::fs;
     use std::net::IpAddr;
     use std::net::SocketAddr;
+    use std::path::PathBuf;
     use std::str::FromStr;
     use std::sync::Arc;
     use std::sync::Mutex;
+    use std::time::Duration;
 
     fn dnss() -> Vec<SocketAddr> {
         vec![SocketAddr::from_str("8.8.8.8:53").unwrap()]
@@ -281,6 +683,7 @@ mod tests {
 
     pub struct StreamHandlerPoolMock {
         process_package_parameters: Arc<Mutex<Vec<(ClientRequestPayload, Option<Wallet>)>>>,
+        offer_dns_server_parameters: Arc<Mutex<Vec<IpAddr>>>,
     }
 
     impl StreamHandlerPool for StreamHandlerPoolMock {
@@ -290,15 +693,31 @@ mod tests {
                 .unwrap()
                 .push((payload, consuming_wallet));
         }
+
+        fn offer_dns_server(&mut self, dns_server: IpAddr, _resolver: Box<dyn ResolverWrapper>) {
+            self.offer_dns_server_parameters
+                .lock()
+                .unwrap()
+                .push(dns_server);
+        }
     }
 
     impl StreamHandlerPoolMock {
         pub fn new() -> StreamHandlerPoolMock {
             StreamHandlerPoolMock {
                 process_package_parameters: Arc::new(Mutex::new(vec![])),
+                offer_dns_server_parameters: Arc::new(Mutex::new(vec![])),
             }
         }
 
+        pub fn offer_dns_server_parameters(
+            self,
+            parameters: &mut Arc<Mutex<Vec<IpAddr>>>,
+        ) -> StreamHandlerPoolMock {
+            *parameters = self.offer_dns_server_parameters.clone();
+            self
+        }
+
         pub fn process_package_parameters(
             self,
             parameters: &mut Arc<Mutex<Vec<(ClientRequestPayload, Option<Wallet>)>>>,
@@ -316,6 +735,11 @@ mod tests {
                     &'static dyn CryptDE,
                     Recipient<Syn, ReportExitServiceProvidedMessage>,
                     Recipient<Syn, InboundServerData>,
+                    ExitThroughputLimiters,
+                    ExitConnectTimeouts,
+                    Duration,
+                    RateCard,
+                    ExitPolicy,
                 )>,
             >,
         >,
@@ -329,12 +753,22 @@ mod tests {
             cryptde: &'static dyn CryptDE,
             accountant_sub: Recipient<Syn, ReportExitServiceProvidedMessage>,
             proxy_client_sub: Recipient<Syn, InboundServerData>,
+            throughput_limiters: ExitThroughputLimiters,
+            connect_timeouts: ExitConnectTimeouts,
+            max_report_delay: Duration,
+            rate_card: RateCard,
+            exit_policy: ExitPolicy,
         ) -> Box<dyn StreamHandlerPool> {
             self.make_parameters.lock().unwrap().push((
                 resolver,
                 cryptde,
                 accountant_sub,
                 proxy_client_sub,
+                throughput_limiters,
+                connect_timeouts,
+                max_report_delay,
+                rate_card,
+                exit_policy,
             ));
             self.make_results.borrow_mut().remove(0)
         }
@@ -357,6 +791,11 @@ mod tests {
                         &'static dyn CryptDE,
                         Recipient<Syn, ReportExitServiceProvidedMessage>,
                         Recipient<Syn, InboundServerData>,
+                        ExitThroughputLimiters,
+                        ExitConnectTimeouts,
+                        Duration,
+                        RateCard,
+                        ExitPolicy,
                     )>,
                 >,
             >,
@@ -434,8 +873,256 @@ mod tests {
                 },
             ]
         );
-        assert_eq!(opts, ResolverOpts::default());
+        assert_eq!(opts.ip_strategy, LookupIpStrategy::Ipv4AndIpv6);
         assert_eq!(resolver_wrapper_new_parameters.is_empty(), true);
+        assert_eq!(
+            pool_factory_make_parameters.lock().unwrap()[0].5,
+            ExitConnectTimeouts::default()
+        );
+        assert_eq!(
+            pool_factory_make_parameters.lock().unwrap()[0].6,
+            DEFAULT_MAX_REPORT_DELAY
+        );
+        assert_eq!(
+            pool_factory_make_parameters.lock().unwrap()[0].7,
+            RateCard::default()
+        );
+        assert_eq!(
+            pool_factory_make_parameters.lock().unwrap()[0].8,
+            ExitPolicy::default()
+        );
+    }
+
+    #[test]
+    fn bind_makes_a_resolver_for_each_dns_server_offered_to_originators() {
+        let system =
+            System::new("bind_makes_a_resolver_for_each_dns_server_offered_to_originators");
+        let mut resolver_wrapper_new_parameters_arc: Arc<
+            Mutex<Vec<(ResolverConfig, ResolverOpts)>>,
+        > = Arc::new(Mutex::new(vec![]));
+        let resolver_wrapper_factory = ResolverWrapperFactoryMock::new()
+            .new_parameters(&mut resolver_wrapper_new_parameters_arc)
+            .new_result(Box::new(ResolverWrapperMock::new()))
+            .new_result(Box::new(ResolverWrapperMock::new()))
+            .new_result(Box::new(ResolverWrapperMock::new()));
+        let mut offer_dns_server_parameters = Arc::new(Mutex::new(vec![]));
+        let pool = StreamHandlerPoolMock::new()
+            .offer_dns_server_parameters(&mut offer_dns_server_parameters);
+        let pool_factory = StreamHandlerPoolFactoryMock::new().make_result(Box::new(pool));
+        let mut subject = ProxyClient::new(cryptde(), dnss());
+        subject.resolver_wrapper_factory = Box::new(resolver_wrapper_factory);
+        subject.stream_handler_pool_factory = Box::new(pool_factory);
+        subject.enforce_exit_policy(&ExitPolicy {
+            offered_dns_servers: vec![
+                IpAddr::from_str("9.9.9.9").unwrap(),
+                IpAddr::from_str("1.1.1.1").unwrap(),
+            ],
+            ..ExitPolicy::default()
+        });
+        let subject_addr: Addr<Syn, ProxyClient> = subject.start();
+
+        subject_addr
+            .try_send(BindMessage {
+                peer_actors: peer_actors_builder().build(),
+            })
+            .unwrap();
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+        let resolver_wrapper_new_parameters = resolver_wrapper_new_parameters_arc.lock().unwrap();
+        assert_eq!(resolver_wrapper_new_parameters.len(), 3);
+        assert_eq!(
+            resolver_wrapper_new_parameters[1].0.name_servers(),
+            &[NameServerConfig {
+                socket_addr: SocketAddr::from_str("9.9.9.9:53").unwrap(),
+                protocol: Protocol::Udp,
+                tls_dns_name: None
+            }]
+        );
+        assert_eq!(
+            resolver_wrapper_new_parameters[2].0.name_servers(),
+            &[NameServerConfig {
+                socket_addr: SocketAddr::from_str("1.1.1.1:53").unwrap(),
+                protocol: Protocol::Udp,
+                tls_dns_name: None
+            }]
+        );
+        assert_eq!(
+            *offer_dns_server_parameters.lock().unwrap(),
+            vec![
+                IpAddr::from_str("9.9.9.9").unwrap(),
+                IpAddr::from_str("1.1.1.1").unwrap(),
+            ]
+        );
+    }
+
+    #[test]
+    fn bind_tells_the_resolver_to_use_tls_with_each_servers_name() {
+        let system = System::new("bind_tells_the_resolver_to_use_tls_with_each_servers_name");
+        let mut resolver_wrapper_new_parameters_arc: Arc<
+            Mutex<Vec<(ResolverConfig, ResolverOpts)>>,
+        > = Arc::new(Mutex::new(vec![]));
+        let resolver_wrapper_factory = ResolverWrapperFactoryMock::new()
+            .new_parameters(&mut resolver_wrapper_new_parameters_arc)
+            .new_result(Box::new(ResolverWrapperMock::new()));
+        let pool_factory =
+            StreamHandlerPoolFactoryMock::new().make_result(Box::new(StreamHandlerPoolMock::new()));
+        let mut subject = ProxyClient::new(
+            cryptde(),
+            vec![
+                SocketAddr::from_str("1.1.1.1:853").unwrap(),
+                SocketAddr::from_str("9.9.9.9:853").unwrap(),
+            ],
+        );
+        subject.resolver_wrapper_factory = Box::new(resolver_wrapper_factory);
+        subject.stream_handler_pool_factory = Box::new(pool_factory);
+        subject.resolve_dns_with(&DnsResolverConfig {
+            protocol: DnsProtocol::Tls,
+            tls_dns_names: vec![
+                (
+                    IpAddr::from_str("1.1.1.1").unwrap(),
+                    String::from("cloudflare-dns.com"),
+                ),
+                (
+                    IpAddr::from_str("9.9.9.9").unwrap(),
+                    String::from("dns.quad9.net"),
+                ),
+            ]
+            .into_iter()
+            .collect(),
+        });
+        let subject_addr: Addr<Syn, ProxyClient> = subject.start();
+
+        subject_addr
+            .try_send(BindMessage {
+                peer_actors: peer_actors_builder().build(),
+            })
+            .unwrap();
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+        let (config, _) = resolver_wrapper_new_parameters_arc
+            .lock()
+            .unwrap()
+            .remove(0);
+        assert_eq!(
+            config.name_servers(),
+            &[
+                NameServerConfig {
+                    socket_addr: SocketAddr::from_str("1.1.1.1:853").unwrap(),
+                    protocol: Protocol::Tls,
+                    tls_dns_name: Some(String::from("cloudflare-dns.com")),
+                },
+                NameServerConfig {
+                    socket_addr: SocketAddr::from_str("9.9.9.9:853").unwrap(),
+                    protocol: Protocol::Tls,
+                    tls_dns_name: Some(String::from("dns.quad9.net")),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn limited_report_delay_is_handed_to_the_stream_handler_pool() {
+        let system = System::new("limited_report_delay_is_handed_to_the_stream_handler_pool");
+        let resolver_wrapper_factory =
+            ResolverWrapperFactoryMock::new().new_result(Box::new(ResolverWrapperMock::new()));
+        let mut pool_factory_make_parameters = Arc::new(Mutex::new(vec![]));
+        let pool_factory = StreamHandlerPoolFactoryMock::new()
+            .make_parameters(&mut pool_factory_make_parameters)
+            .make_result(Box::new(StreamHandlerPoolMock::new()));
+        let mut subject = ProxyClient::new(
+            cryptde(),
+            vec![SocketAddr::from_str("4.3.2.1:4321").unwrap()],
+        );
+        subject.resolver_wrapper_factory = Box::new(resolver_wrapper_factory);
+        subject.stream_handler_pool_factory = Box::new(pool_factory);
+        subject.limit_report_delay(Duration::from_secs(5));
+        let subject_addr: Addr<Syn, ProxyClient> = subject.start();
+
+        subject_addr
+            .try_send(BindMessage {
+                peer_actors: peer_actors_builder().build(),
+            })
+            .unwrap();
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+        assert_eq!(
+            pool_factory_make_parameters.lock().unwrap()[0].6,
+            Duration::from_secs(5)
+        );
+    }
+
+    #[test]
+    fn rate_card_is_handed_to_the_stream_handler_pool() {
+        let system = System::new("rate_card_is_handed_to_the_stream_handler_pool");
+        let resolver_wrapper_factory =
+            ResolverWrapperFactoryMock::new().new_result(Box::new(ResolverWrapperMock::new()));
+        let mut pool_factory_make_parameters = Arc::new(Mutex::new(vec![]));
+        let pool_factory = StreamHandlerPoolFactoryMock::new()
+            .make_parameters(&mut pool_factory_make_parameters)
+            .make_result(Box::new(StreamHandlerPoolMock::new()));
+        let mut subject = ProxyClient::new(
+            cryptde(),
+            vec![SocketAddr::from_str("4.3.2.1:4321").unwrap()],
+        );
+        subject.resolver_wrapper_factory = Box::new(resolver_wrapper_factory);
+        subject.stream_handler_pool_factory = Box::new(pool_factory);
+        let rate_card = RateCard {
+            routing_service_rate: 10,
+            routing_byte_rate: 20,
+            exit_service_rate: 30,
+            exit_byte_rate: 40,
+        };
+        subject.charge(rate_card);
+        let subject_addr: Addr<Syn, ProxyClient> = subject.start();
+
+        subject_addr
+            .try_send(BindMessage {
+                peer_actors: peer_actors_builder().build(),
+            })
+            .unwrap();
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+        assert_eq!(pool_factory_make_parameters.lock().unwrap()[0].7, rate_card);
+    }
+
+    #[test]
+    fn limited_connect_time_is_handed_to_the_stream_handler_pool() {
+        let system = System::new("limited_connect_time_is_handed_to_the_stream_handler_pool");
+        let resolver_wrapper_factory =
+            ResolverWrapperFactoryMock::new().new_result(Box::new(ResolverWrapperMock::new()));
+        let mut pool_factory_make_parameters = Arc::new(Mutex::new(vec![]));
+        let pool_factory = StreamHandlerPoolFactoryMock::new()
+            .make_parameters(&mut pool_factory_make_parameters)
+            .make_result(Box::new(StreamHandlerPoolMock::new()));
+        let mut subject = ProxyClient::new(
+            cryptde(),
+            vec![SocketAddr::from_str("4.3.2.1:4321").unwrap()],
+        );
+        subject.resolver_wrapper_factory = Box::new(resolver_wrapper_factory);
+        subject.stream_handler_pool_factory = Box::new(pool_factory);
+        let connect_timeouts = ExitConnectTimeouts {
+            default: Duration::from_secs(3),
+            by_port: vec![(993, Duration::from_secs(30))].into_iter().collect(),
+        };
+        subject.limit_connect_time(&connect_timeouts);
+        let subject_addr: Addr<Syn, ProxyClient> = subject.start();
+
+        subject_addr
+            .try_send(BindMessage {
+                peer_actors: peer_actors_builder().build(),
+            })
+            .unwrap();
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+        assert_eq!(
+            pool_factory_make_parameters.lock().unwrap()[0].5,
+            connect_timeouts
+        );
     }
 
     #[test]
@@ -452,6 +1139,11 @@ mod tests {
             target_port: 1234,
             protocol: ProxyProtocol::HTTP,
             originator_public_key: PublicKey::new(&b"originator_public_key"[..]),
+            tls_at_exit: false,
+            upload_window: None,
+            udp: false,
+            dns_server: None,
+            cancel: false,
         };
         let cryptde = cryptde();
         let package = ExpiredCoresPackage::new(
@@ -494,51 +1186,892 @@ mod tests {
     }
 
     #[test]
-    fn data_from_hopper_is_relayed_to_stream_handler_pool() {
-        let cryptde = cryptde();
-        let request = ClientRequestPayload {
-            stream_key: make_meaningless_stream_key(),
-            sequenced_packet: SequencedPacket {
-                data: b"inbound data".to_vec(),
-                sequence_number: 0,
+    fn stream_context_cap_evicts_least_recently_used_stream_and_complains() {
+        init_test_logging();
+        let mut subject = ProxyClient::new(cryptde(), dnss());
+        subject.stream_context_cap = 2;
+        let make_key = |port: u16| {
+            StreamKey::new(
+                PublicKey::new(&b"originator"[..]),
+                SocketAddr::from_str(&format!("1.2.3.4:{}", port)).unwrap(),
+            )
+        };
+        let make_context = |last_used: Instant| StreamContext {
+            return_route: make_meaningless_route(),
+            payload_destination_key: PublicKey::new(&b"abcd"[..]),
+            consuming_wallet: None,
+            last_used,
+            upload_window_opt: None,
+            requests_received: 0,
+            requests_acknowledged: 0,
+            udp: false,
+        };
+        let now = Instant::now();
+        subject
+            .stream_contexts
+            .insert(make_key(1000), make_context(now - Duration::from_secs(10)));
+        subject
+            .stream_contexts
+            .insert(make_key(1001), make_context(now - Duration::from_secs(20)));
+        subject
+            .stream_contexts
+            .insert(make_key(1002), make_context(now - Duration::from_secs(30)));
+
+        subject.evict_stream_contexts_over_cap(&make_key(1002));
+
+        assert_eq!(subject.stream_contexts.len(), 2);
+        assert_eq!(subject.stream_contexts.contains_key(&make_key(1000)), true);
+        assert_eq!(subject.stream_contexts.contains_key(&make_key(1002)), true);
+        assert_eq!(subject.stream_context_evictions, 1);
+        TestLogHandler::new().exists_log_containing(&format!(
+            "WARN: Proxy Client: Stream context cap of 2 reached: evicted least-recently-used stream {:?} (1 evicted so far)",
+            make_key(1001)
+        ));
+    }
+
+    #[test]
+    fn shutdown_sends_a_terminating_response_for_every_open_stream() {
+        init_test_logging();
+        let cryptde = cryptde();
+        let system = System::new("shutdown_sends_a_terminating_response_for_every_open_stream");
+        let (hopper, _, hopper_recording_arc) = make_recorder();
+        let pool_factory =
+            StreamHandlerPoolFactoryMock::new().make_result(Box::new(StreamHandlerPoolMock::new()));
+        let mut subject = ProxyClient::new(cryptde, dnss());
+        subject.resolver_wrapper_factory = Box::new(
+            ResolverWrapperFactoryMock::new().new_result(Box::new(ResolverWrapperMock::new())),
+        );
+        subject.stream_handler_pool_factory = Box::new(pool_factory);
+        let stream_key = make_meaningless_stream_key();
+        subject.stream_contexts.insert(
+            stream_key,
+            StreamContext {
+                return_route: make_meaningless_route(),
+                payload_destination_key: PublicKey::new(&b"originator"[..]),
+                consuming_wallet: None,
+                last_used: Instant::now(),
+                upload_window_opt: None,
+                requests_received: 3,
+                requests_acknowledged: 3,
+                udp: false,
+            },
+        );
+        let subject_addr: Addr<Syn, ProxyClient> = subject.start();
+        let peer_actors = peer_actors_builder().hopper(hopper).build();
+        subject_addr.try_send(BindMessage { peer_actors }).unwrap();
+
+        subject_addr.try_send(ShutdownMessage {}).unwrap();
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+        let hopper_recording = hopper_recording_arc.lock().unwrap();
+        assert_eq!(
+            hopper_recording.get_record::<IncipientCoresPackage>(0),
+            &IncipientCoresPackage::new(
+                cryptde,
+                make_meaningless_route(),
+                ClientResponsePayload::make_terminating_payload(stream_key),
+                &PublicKey::new(&b"originator"[..]),
+            )
+            .unwrap()
+        );
+        assert_eq!(hopper_recording.len(), 1);
+        TestLogHandler::new()
+            .exists_log_containing("INFO: Proxy Client: Closed 1 open stream(s) for shutdown");
+    }
+
+    #[test]
+    fn stream_count_query_counts_the_streams_being_exited() {
+        let system = System::new("stream_count_query_counts_the_streams_being_exited");
+        let mut subject = ProxyClient::new(cryptde(), dnss());
+        subject.stream_contexts.insert(
+            make_meaningless_stream_key(),
+            StreamContext {
+                return_route: make_meaningless_route(),
+                payload_destination_key: PublicKey::new(&b"originator"[..]),
+                consuming_wallet: None,
+                last_used: Instant::now(),
+                upload_window_opt: None,
+                requests_received: 1,
+                requests_acknowledged: 1,
+                udp: false,
+            },
+        );
+        let subject_addr: Addr<Syn, ProxyClient> = subject.start();
+
+        let future = subject_addr.send(StreamCountQueryMessage {});
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+        assert_eq!(future.wait().unwrap(), 1);
+    }
+
+    #[test]
+    fn exit_throughput_query_reports_both_caps_and_what_has_been_carried() {
+        let system =
+            System::new("exit_throughput_query_reports_both_caps_and_what_has_been_carried");
+        let mut subject = ProxyClient::new(cryptde(), dnss());
+        subject.limit_exit_throughput(&ExitThroughputConfig {
+            upstream_bytes_per_second: Some(1000),
+            downstream_bytes_per_second: None,
+        });
+        subject.throughput_limiters.downstream.consume(1234);
+        let subject_addr: Addr<Syn, ProxyClient> = subject.start();
+
+        let future = subject_addr.send(ExitThroughputQueryMessage {});
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+        let result = future.wait().unwrap();
+        assert_eq!(result.upstream.bytes_per_second_cap, Some(1000));
+        assert_eq!(result.upstream.total_bytes, 0);
+        assert_eq!(result.downstream.bytes_per_second_cap, None);
+        assert_eq!(result.downstream.total_bytes, 1234);
+    }
+
+    #[test]
+    fn stream_context_cap_defaults_to_maximum() {
+        let subject = ProxyClient::new(cryptde(), dnss());
+
+        assert_eq!(MAX_STREAM_CONTEXTS, 10000);
+        assert_eq!(subject.stream_context_cap, MAX_STREAM_CONTEXTS);
+    }
+
+    #[test]
+    fn data_from_hopper_is_relayed_to_stream_handler_pool() {
+        let cryptde = cryptde();
+        let request = ClientRequestPayload {
+            stream_key: make_meaningless_stream_key(),
+            sequenced_packet: SequencedPacket {
+                data: b"inbound data".to_vec(),
+                sequence_number: 0,
+                last_data: false,
+            },
+            target_hostname: None,
+            target_port: 0,
+            protocol: ProxyProtocol::HTTP,
+            originator_public_key: PublicKey::new(&b"originator"[..]),
+            tls_at_exit: false,
+            upload_window: None,
+            udp: false,
+            dns_server: None,
+            cancel: false,
+        };
+        let package = ExpiredCoresPackage::new(
+            IpAddr::from_str("1.2.3.4").unwrap(),
+            Some(Wallet::new("consuming")),
+            make_meaningless_route(),
+            encodex(cryptde, &cryptde.public_key(), &request).unwrap(),
+        );
+        let hopper = Recorder::new();
+
+        let system = System::new("unparseable_request_results_in_log_and_no_response");
+        let peer_actors = peer_actors_builder().hopper(hopper).build();
+        let mut process_package_parameters = Arc::new(Mutex::new(vec![]));
+        let pool = Box::new(
+            StreamHandlerPoolMock::new()
+                .process_package_parameters(&mut process_package_parameters),
+        );
+        let pool_factory = StreamHandlerPoolFactoryMock::new().make_result(pool);
+        let resolver = ResolverWrapperMock::new()
+            .lookup_ip_success(vec![IpAddr::from_str("4.3.2.1").unwrap()]);
+        let resolver_factory = ResolverWrapperFactoryMock::new().new_result(Box::new(resolver));
+        let mut subject = ProxyClient::new(cryptde, dnss());
+        subject.resolver_wrapper_factory = Box::new(resolver_factory);
+        subject.stream_handler_pool_factory = Box::new(pool_factory);
+        let subject_addr: Addr<Syn, ProxyClient> = subject.start();
+        subject_addr.try_send(BindMessage { peer_actors }).unwrap();
+
+        subject_addr.try_send(package).unwrap();
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+        let parameter = process_package_parameters.lock().unwrap().remove(0);
+        assert_eq!(parameter, (request, Some(Wallet::new("consuming")),));
+    }
+
+    fn exit_tls_request_outcome(
+        offers_exit_tls: bool,
+    ) -> Vec<(ClientRequestPayload, Option<Wallet>)> {
+        let cryptde = cryptde();
+        let request = ClientRequestPayload {
+            stream_key: make_meaningless_stream_key(),
+            sequenced_packet: SequencedPacket {
+                data: b"GET / HTTP/1.1\r\nHost: server.com\r\n\r\n".to_vec(),
+                sequence_number: 0,
+                last_data: false,
+            },
+            target_hostname: Some(String::from("server.com")),
+            target_port: 443,
+            protocol: ProxyProtocol::HTTP,
+            originator_public_key: PublicKey::new(&b"originator"[..]),
+            tls_at_exit: true,
+            upload_window: None,
+            udp: false,
+            dns_server: None,
+            cancel: false,
+        };
+        let package = ExpiredCoresPackage::new(
+            IpAddr::from_str("1.2.3.4").unwrap(),
+            Some(Wallet::new("consuming")),
+            make_meaningless_route(),
+            encodex(cryptde, &cryptde.public_key(), &request).unwrap(),
+        );
+        let system = System::new("exit_tls_request_outcome");
+        let peer_actors = peer_actors_builder().build();
+        let mut process_package_parameters = Arc::new(Mutex::new(vec![]));
+        let pool = Box::new(
+            StreamHandlerPoolMock::new()
+                .process_package_parameters(&mut process_package_parameters),
+        );
+        let pool_factory = StreamHandlerPoolFactoryMock::new().make_result(pool);
+        let resolver = ResolverWrapperMock::new()
+            .lookup_ip_success(vec![IpAddr::from_str("4.3.2.1").unwrap()]);
+        let resolver_factory = ResolverWrapperFactoryMock::new().new_result(Box::new(resolver));
+        let mut subject = ProxyClient::new(cryptde, dnss());
+        subject.resolver_wrapper_factory = Box::new(resolver_factory);
+        subject.stream_handler_pool_factory = Box::new(pool_factory);
+        if offers_exit_tls {
+            subject.offer_exit_tls();
+        }
+        let subject_addr: Addr<Syn, ProxyClient> = subject.start();
+        subject_addr.try_send(BindMessage { peer_actors }).unwrap();
+
+        subject_addr.try_send(package).unwrap();
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+        let parameters = process_package_parameters.lock().unwrap();
+        parameters.clone()
+    }
+
+    #[test]
+    fn exit_tls_request_is_refused_by_a_node_that_does_not_offer_it() {
+        init_test_logging();
+
+        let parameters = exit_tls_request_outcome(false);
+
+        assert_eq!(parameters.is_empty(), true);
+        TestLogHandler::new().exists_log_containing(&format!(
+            "WARN: Proxy Client: Refusing stream {:?}: originator asked for TLS at the exit to server.com, but this Node doesn't offer it",
+            make_meaningless_stream_key()
+        ));
+    }
+
+    #[test]
+    fn exit_tls_request_is_passed_along_by_a_node_that_offers_it() {
+        let parameters = exit_tls_request_outcome(true);
+
+        assert_eq!(parameters.len(), 1);
+        assert_eq!(parameters[0].0.tls_at_exit, true);
+    }
+
+    fn exit_policy_outcome(
+        exit_policy: ExitPolicy,
+        target_hostname: &str,
+        target_port: u16,
+    ) -> (
+        ClientRequestPayload,
+        Vec<(ClientRequestPayload, Option<Wallet>)>,
+        Arc<Mutex<Recording>>,
+    ) {
+        let request = ClientRequestPayload {
+            stream_key: make_meaningless_stream_key(),
+            sequenced_packet: SequencedPacket {
+                data: b"HELO booga\r\n".to_vec(),
+                sequence_number: 0,
+                last_data: false,
+            },
+            target_hostname: Some(String::from(target_hostname)),
+            target_port,
+            protocol: ProxyProtocol::TLS,
+            originator_public_key: PublicKey::new(&b"originator"[..]),
+            tls_at_exit: false,
+            upload_window: None,
+            udp: false,
+            dns_server: None,
+            cancel: false,
+        };
+        exit_policy_outcome_for(exit_policy, request)
+    }
+
+    fn exit_policy_outcome_for(
+        exit_policy: ExitPolicy,
+        request: ClientRequestPayload,
+    ) -> (
+        ClientRequestPayload,
+        Vec<(ClientRequestPayload, Option<Wallet>)>,
+        Arc<Mutex<Recording>>,
+    ) {
+        let cryptde = cryptde();
+        let package = ExpiredCoresPackage::new(
+            IpAddr::from_str("1.2.3.4").unwrap(),
+            Some(Wallet::new("consuming")),
+            make_meaningless_route(),
+            encodex(cryptde, &cryptde.public_key(), &request).unwrap(),
+        );
+        let system = System::new("exit_policy_outcome");
+        let (hopper, _, hopper_recording_arc) = make_recorder();
+        let mut process_package_parameters = Arc::new(Mutex::new(vec![]));
+        let pool = Box::new(
+            StreamHandlerPoolMock::new()
+                .process_package_parameters(&mut process_package_parameters),
+        );
+        let pool_factory = StreamHandlerPoolFactoryMock::new().make_result(pool);
+        let resolver_factory = exit_policy.offered_dns_servers.iter().fold(
+            ResolverWrapperFactoryMock::new().new_result(Box::new(ResolverWrapperMock::new())),
+            |factory, _| factory.new_result(Box::new(ResolverWrapperMock::new())),
+        );
+        let mut subject = ProxyClient::new(cryptde, dnss());
+        subject.resolver_wrapper_factory = Box::new(resolver_factory);
+        subject.stream_handler_pool_factory = Box::new(pool_factory);
+        subject.enforce_exit_policy(&exit_policy);
+        let subject_addr: Addr<Syn, ProxyClient> = subject.start();
+        let peer_actors = peer_actors_builder().hopper(hopper).build();
+        subject_addr.try_send(BindMessage { peer_actors }).unwrap();
+
+        subject_addr.try_send(package).unwrap();
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+        let parameters = process_package_parameters.lock().unwrap().clone();
+        (request, parameters, hopper_recording_arc)
+    }
+
+    fn make_exit_policy() -> ExitPolicy {
+        ExitPolicy {
+            blocked_ports: vec![25, 465],
+            blocked_hostnames: vec![String::from("blocked.com")],
+            blocked_ip_addrs: vec![IpAddr::from_str("6.6.6.6").unwrap()],
+            offered_dns_servers: vec![],
+        }
+    }
+
+    fn assert_refused_with_terminating_response(
+        request: &ClientRequestPayload,
+        parameters: Vec<(ClientRequestPayload, Option<Wallet>)>,
+        hopper_recording_arc: Arc<Mutex<Recording>>,
+    ) {
+        let cryptde = cryptde();
+        assert_eq!(parameters.is_empty(), true);
+        let hopper_recording = hopper_recording_arc.lock().unwrap();
+        assert_eq!(
+            hopper_recording.get_record::<IncipientCoresPackage>(0),
+            &IncipientCoresPackage::new(
+                cryptde,
+                make_meaningless_route(),
+                ClientResponsePayload::make_terminating_payload(request.stream_key),
+                &request.originator_public_key,
+            )
+            .unwrap()
+        );
+        assert_eq!(hopper_recording.len(), 1);
+    }
+
+    #[test]
+    fn exit_policy_refuses_a_blocked_port_with_a_terminating_response() {
+        init_test_logging();
+
+        let (request, parameters, hopper_recording_arc) =
+            exit_policy_outcome(make_exit_policy(), "mail.server.com", 25);
+
+        assert_refused_with_terminating_response(&request, parameters, hopper_recording_arc);
+        TestLogHandler::new().exists_log_containing(&format!(
+            "WARN: Proxy Client: Refusing stream {:?} by exit policy: port 25 is blocked",
+            make_meaningless_stream_key()
+        ));
+    }
+
+    #[test]
+    fn exit_policy_refuses_names_under_a_blocked_hostname_with_a_terminating_response() {
+        init_test_logging();
+
+        let (request, parameters, hopper_recording_arc) =
+            exit_policy_outcome(make_exit_policy(), "WWW.Blocked.com", 443);
+
+        assert_refused_with_terminating_response(&request, parameters, hopper_recording_arc);
+        TestLogHandler::new().exists_log_containing(&format!(
+            "WARN: Proxy Client: Refusing stream {:?} by exit policy: host blocked.com is blocked",
+            make_meaningless_stream_key()
+        ));
+    }
+
+    #[test]
+    fn exit_policy_refuses_a_blocked_ip_address_given_as_the_hostname() {
+        let (request, parameters, hopper_recording_arc) =
+            exit_policy_outcome(make_exit_policy(), "6.6.6.6", 443);
+
+        assert_refused_with_terminating_response(&request, parameters, hopper_recording_arc);
+    }
+
+    #[test]
+    fn exit_policy_passes_along_requests_it_does_not_block() {
+        let (request, parameters, hopper_recording_arc) =
+            exit_policy_outcome(make_exit_policy(), "notblocked.com", 443);
+
+        assert_eq!(parameters, vec![(request, Some(Wallet::new("consuming")))]);
+        assert_eq!(hopper_recording_arc.lock().unwrap().len(), 0);
+    }
+
+    fn make_request_for_dns_server(dns_server: &str) -> ClientRequestPayload {
+        ClientRequestPayload {
+            stream_key: make_meaningless_stream_key(),
+            sequenced_packet: SequencedPacket {
+                data: b"HELO booga\r\n".to_vec(),
+                sequence_number: 0,
+                last_data: false,
+            },
+            target_hostname: Some(String::from("server.com")),
+            target_port: 443,
+            protocol: ProxyProtocol::TLS,
+            originator_public_key: PublicKey::new(&b"originator"[..]),
+            tls_at_exit: false,
+            upload_window: None,
+            udp: false,
+            dns_server: Some(IpAddr::from_str(dns_server).unwrap()),
+            cancel: false,
+        }
+    }
+
+    #[test]
+    fn exit_policy_refuses_a_dns_server_it_does_not_offer_with_a_terminating_response() {
+        init_test_logging();
+        let mut exit_policy = make_exit_policy();
+        exit_policy.offered_dns_servers = vec![IpAddr::from_str("9.9.9.9").unwrap()];
+
+        let (request, parameters, hopper_recording_arc) =
+            exit_policy_outcome_for(exit_policy, make_request_for_dns_server("1.1.1.1"));
+
+        assert_refused_with_terminating_response(&request, parameters, hopper_recording_arc);
+        TestLogHandler::new().exists_log_containing(&format!(
+            "WARN: Proxy Client: Refusing stream {:?} by exit policy: looking hostnames up with DNS server 1.1.1.1 is not offered",
+            make_meaningless_stream_key()
+        ));
+    }
+
+    #[test]
+    fn exit_policy_passes_along_requests_for_a_dns_server_it_offers() {
+        let mut exit_policy = make_exit_policy();
+        exit_policy.offered_dns_servers = vec![IpAddr::from_str("9.9.9.9").unwrap()];
+
+        let (request, parameters, hopper_recording_arc) =
+            exit_policy_outcome_for(exit_policy, make_request_for_dns_server("9.9.9.9"));
+
+        assert_eq!(parameters, vec![(request, Some(Wallet::new("consuming")))]);
+        assert_eq!(hopper_recording_arc.lock().unwrap().len(), 0);
+    }
+
+    #[test]
+    fn exit_log_records_host_and_byte_counts_when_stream_ends() {
+        let data_directory =
+            PathBuf::from("generated/test/proxy_client/exit_log_records_host_and_byte_counts");
+        fs::remove_dir_all(&data_directory).is_ok();
+        fs::create_dir_all(&data_directory).is_ok();
+        let cryptde = cryptde();
+        let stream_key = make_meaningless_stream_key();
+        let request = ClientRequestPayload {
+            stream_key: stream_key.clone(),
+            sequenced_packet: SequencedPacket {
+                data: b"inbound data".to_vec(),
+                sequence_number: 0,
+                last_data: false,
+            },
+            target_hostname: Some(String::from("example.com")),
+            target_port: 80,
+            protocol: ProxyProtocol::HTTP,
+            originator_public_key: PublicKey::new(&b"originator"[..]),
+            tls_at_exit: false,
+            upload_window: None,
+            udp: false,
+            dns_server: None,
+            cancel: false,
+        };
+        let package = ExpiredCoresPackage::new(
+            IpAddr::from_str("1.2.3.4").unwrap(),
+            Some(Wallet::new("consuming")),
+            make_meaningless_route(),
+            encodex(cryptde, &cryptde.public_key(), &request).unwrap(),
+        );
+        let system = System::new("exit_log_records_host_and_byte_counts_when_stream_ends");
+        let peer_actors = peer_actors_builder().build();
+        let mut process_package_parameters = Arc::new(Mutex::new(vec![]));
+        let pool = Box::new(
+            StreamHandlerPoolMock::new()
+                .process_package_parameters(&mut process_package_parameters),
+        );
+        let pool_factory = StreamHandlerPoolFactoryMock::new().make_result(pool);
+        let resolver = ResolverWrapperMock::new()
+            .lookup_ip_success(vec![IpAddr::from_str("4.3.2.1").unwrap()]);
+        let resolver_factory = ResolverWrapperFactoryMock::new().new_result(Box::new(resolver));
+        let mut subject = ProxyClient::new(cryptde, dnss());
+        subject.resolver_wrapper_factory = Box::new(resolver_factory);
+        subject.stream_handler_pool_factory = Box::new(pool_factory);
+        subject.enable_exit_log(&ExitLogConfig {
+            level: ExitLogLevel::Full,
+            data_directory: data_directory.clone(),
+        });
+        let subject_addr: Addr<Syn, ProxyClient> = subject.start();
+        subject_addr.try_send(BindMessage { peer_actors }).unwrap();
+
+        subject_addr.try_send(package).unwrap();
+        subject_addr
+            .try_send(InboundServerData {
+                stream_key: stream_key.clone(),
+                last_data: true,
+                sequence_number: 0,
+                source: SocketAddr::from_str("4.3.2.1:80").unwrap(),
+                data: b"outbound data, longer".to_vec(),
+                truncated: false,
+            })
+            .unwrap();
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+        let contents = fs::read_to_string(data_directory.join("exit_requests.log")).unwrap();
+        assert_eq!(contents.ends_with(" example.com up 12 down 21\n"), true);
+        assert_eq!(contents.contains("inbound data"), false);
+    }
+
+    #[test]
+    fn bytes_exited_in_both_directions_are_reported_to_metrics() {
+        let cryptde = cryptde();
+        let stream_key = make_meaningless_stream_key();
+        let request = ClientRequestPayload {
+            stream_key: stream_key.clone(),
+            sequenced_packet: SequencedPacket {
+                data: b"inbound data".to_vec(),
+                sequence_number: 0,
+                last_data: false,
+            },
+            target_hostname: Some(String::from("example.com")),
+            target_port: 80,
+            protocol: ProxyProtocol::HTTP,
+            originator_public_key: PublicKey::new(&b"originator"[..]),
+            tls_at_exit: false,
+            upload_window: None,
+            udp: false,
+            dns_server: None,
+            cancel: false,
+        };
+        let package = ExpiredCoresPackage::new(
+            IpAddr::from_str("1.2.3.4").unwrap(),
+            Some(Wallet::new("consuming")),
+            make_meaningless_route(),
+            encodex(cryptde, &cryptde.public_key(), &request).unwrap(),
+        );
+        let system = System::new("bytes_exited_in_both_directions_are_reported_to_metrics");
+        let (metrics, _, metrics_recording_arc) = make_recorder();
+        let pool = Box::new(StreamHandlerPoolMock::new());
+        let pool_factory = StreamHandlerPoolFactoryMock::new().make_result(pool);
+        let resolver = ResolverWrapperMock::new();
+        let resolver_factory = ResolverWrapperFactoryMock::new().new_result(Box::new(resolver));
+        let mut subject = ProxyClient::new(cryptde, dnss());
+        subject.resolver_wrapper_factory = Box::new(resolver_factory);
+        subject.stream_handler_pool_factory = Box::new(pool_factory);
+        let subject_addr: Addr<Syn, ProxyClient> = subject.start();
+        let peer_actors = peer_actors_builder().metrics(metrics).build();
+        subject_addr.try_send(BindMessage { peer_actors }).unwrap();
+
+        subject_addr.try_send(package).unwrap();
+        subject_addr
+            .try_send(InboundServerData {
+                stream_key: stream_key.clone(),
+                last_data: true,
+                sequence_number: 0,
+                source: SocketAddr::from_str("4.3.2.1:80").unwrap(),
+                data: b"outbound data, longer".to_vec(),
+                truncated: false,
+            })
+            .unwrap();
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+        let metrics_recording = metrics_recording_arc.lock().unwrap();
+        assert_eq!(
+            metrics_recording.get_record::<MetricsReport>(0),
+            &MetricsReport::BytesExited(12)
+        );
+        assert_eq!(
+            metrics_recording.get_record::<MetricsReport>(1),
+            &MetricsReport::BytesExited(21)
+        );
+        assert_eq!(metrics_recording.len(), 2);
+    }
+
+    #[test]
+    fn cancel_from_originator_forgets_the_stream_and_is_passed_to_the_pool() {
+        init_test_logging();
+        let cryptde = cryptde();
+        let (hopper, _, hopper_recording_arc) = make_recorder();
+        let stream_key = make_meaningless_stream_key();
+        let request = ClientRequestPayload {
+            stream_key: stream_key.clone(),
+            sequenced_packet: SequencedPacket {
+                data: b"GET / HTTP/1.1\r\n\r\n".to_vec(),
+                sequence_number: 0,
+                last_data: false,
+            },
+            target_hostname: Some(String::from("example.com")),
+            target_port: 80,
+            protocol: ProxyProtocol::HTTP,
+            originator_public_key: PublicKey::new(&b"originator"[..]),
+            tls_at_exit: false,
+            upload_window: None,
+            udp: false,
+            dns_server: None,
+            cancel: false,
+        };
+        let cancel = ClientRequestPayload {
+            sequenced_packet: SequencedPacket {
+                data: vec![],
+                sequence_number: 1,
+                last_data: true,
+            },
+            cancel: true,
+            ..request.clone()
+        };
+        let make_package = |payload: &ClientRequestPayload| {
+            ExpiredCoresPackage::new(
+                IpAddr::from_str("1.2.3.4").unwrap(),
+                Some(Wallet::new("consuming")),
+                make_meaningless_route(),
+                encodex(cryptde, &cryptde.public_key(), payload).unwrap(),
+            )
+        };
+        let system =
+            System::new("cancel_from_originator_forgets_the_stream_and_is_passed_to_the_pool");
+        let peer_actors = peer_actors_builder().hopper(hopper).build();
+        let mut process_package_parameters = Arc::new(Mutex::new(vec![]));
+        let pool = Box::new(
+            StreamHandlerPoolMock::new()
+                .process_package_parameters(&mut process_package_parameters),
+        );
+        let pool_factory = StreamHandlerPoolFactoryMock::new().make_result(pool);
+        let resolver = ResolverWrapperMock::new()
+            .lookup_ip_success(vec![IpAddr::from_str("4.3.2.1").unwrap()]);
+        let resolver_factory = ResolverWrapperFactoryMock::new().new_result(Box::new(resolver));
+        let mut subject = ProxyClient::new(cryptde, dnss());
+        subject.resolver_wrapper_factory = Box::new(resolver_factory);
+        subject.stream_handler_pool_factory = Box::new(pool_factory);
+        let subject_addr: Addr<Syn, ProxyClient> = subject.start();
+        subject_addr.try_send(BindMessage { peer_actors }).unwrap();
+
+        subject_addr.try_send(make_package(&request)).unwrap();
+        subject_addr.try_send(make_package(&cancel)).unwrap();
+        subject_addr
+            .try_send(InboundServerData {
+                stream_key: stream_key.clone(),
                 last_data: false,
+                sequence_number: 0,
+                source: SocketAddr::from_str("4.3.2.1:80").unwrap(),
+                data: b"too late".to_vec(),
+                truncated: false,
+            })
+            .unwrap();
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+        let parameters = process_package_parameters.lock().unwrap();
+        assert_eq!(
+            *parameters,
+            vec![
+                (request, Some(Wallet::new("consuming"))),
+                (cancel, Some(Wallet::new("consuming"))),
+            ]
+        );
+        assert_eq!(hopper_recording_arc.lock().unwrap().len(), 0);
+        TestLogHandler::new().exists_log_containing(&format!(
+            "DEBUG: Proxy Client: Stream {:?} canceled by originator",
+            stream_key
+        ));
+        TestLogHandler::new().exists_log_containing(
+            "ERROR: Proxy Client: Received unsolicited 8-byte response from 4.3.2.1:80, seq 0: ignoring",
+        );
+    }
+
+    #[test]
+    fn truncated_inbound_server_data_is_marked_truncated_for_the_originator() {
+        let (hopper, _, hopper_recording_arc) = make_recorder();
+        let stream_key = make_meaningless_stream_key();
+        let system =
+            System::new("truncated_inbound_server_data_is_marked_truncated_for_the_originator");
+        let mut subject = ProxyClient::new(
+            cryptde(),
+            vec![SocketAddr::from_str("8.7.6.5:4321").unwrap()],
+        );
+        subject.stream_contexts.insert(
+            stream_key.clone(),
+            StreamContext {
+                return_route: make_meaningless_route(),
+                payload_destination_key: PublicKey::new(&b"abcd"[..]),
+                consuming_wallet: Some(Wallet::new("consuming")),
+                last_used: Instant::now(),
+                upload_window_opt: None,
+                requests_received: 0,
+                requests_acknowledged: 0,
+                udp: false,
             },
-            target_hostname: None,
-            target_port: 0,
-            protocol: ProxyProtocol::HTTP,
-            originator_public_key: PublicKey::new(&b"originator"[..]),
-        };
-        let package = ExpiredCoresPackage::new(
-            IpAddr::from_str("1.2.3.4").unwrap(),
-            Some(Wallet::new("consuming")),
-            make_meaningless_route(),
-            encodex(cryptde, &cryptde.public_key(), &request).unwrap(),
         );
-        let hopper = Recorder::new();
+        let subject_addr: Addr<Syn, ProxyClient> = subject.start();
+        let peer_actors = peer_actors_builder().hopper(hopper).build();
+        subject_addr.try_send(BindMessage { peer_actors }).unwrap();
 
-        let system = System::new("unparseable_request_results_in_log_and_no_response");
+        subject_addr
+            .try_send(InboundServerData {
+                stream_key: stream_key.clone(),
+                last_data: true,
+                sequence_number: 3,
+                source: SocketAddr::from_str("1.2.3.4:5678").unwrap(),
+                data: vec![],
+                truncated: true,
+            })
+            .unwrap();
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+        let hopper_recording = hopper_recording_arc.lock().unwrap();
+        assert_eq!(
+            hopper_recording.get_record::<IncipientCoresPackage>(0),
+            &IncipientCoresPackage::new(
+                cryptde(),
+                make_meaningless_route(),
+                ClientResponsePayload {
+                    stream_key: stream_key.clone(),
+                    sequenced_packet: SequencedPacket {
+                        data: vec![],
+                        sequence_number: 3,
+                        last_data: true
+                    },
+                    requests_received: None,
+                    truncated: true,
+                    udp: false,
+                    exit_failed: false,
+                },
+                &PublicKey::new(&b"abcd"[..]),
+            )
+            .unwrap()
+        );
+    }
+
+    #[test]
+    fn a_stream_the_exit_could_not_start_is_marked_exit_failed_for_the_originator() {
+        let (hopper, _, hopper_recording_arc) = make_recorder();
+        let stream_key = make_meaningless_stream_key();
+        let system = System::new(
+            "a_stream_the_exit_could_not_start_is_marked_exit_failed_for_the_originator",
+        );
+        let mut subject = ProxyClient::new(
+            cryptde(),
+            vec![SocketAddr::from_str("8.7.6.5:4321").unwrap()],
+        );
+        subject.stream_contexts.insert(
+            stream_key.clone(),
+            StreamContext {
+                return_route: make_meaningless_route(),
+                payload_destination_key: PublicKey::new(&b"abcd"[..]),
+                consuming_wallet: Some(Wallet::new("consuming")),
+                last_used: Instant::now(),
+                upload_window_opt: None,
+                requests_received: 0,
+                requests_acknowledged: 0,
+                udp: false,
+            },
+        );
+        let subject_addr: Addr<Syn, ProxyClient> = subject.start();
         let peer_actors = peer_actors_builder().hopper(hopper).build();
-        let mut process_package_parameters = Arc::new(Mutex::new(vec![]));
-        let pool = Box::new(
-            StreamHandlerPoolMock::new()
-                .process_package_parameters(&mut process_package_parameters),
+        subject_addr.try_send(BindMessage { peer_actors }).unwrap();
+
+        subject_addr
+            .try_send(InboundServerData {
+                stream_key: stream_key.clone(),
+                last_data: true,
+                sequence_number: 0,
+                source: error_socket_addr(),
+                data: vec![],
+                truncated: false,
+            })
+            .unwrap();
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+        let hopper_recording = hopper_recording_arc.lock().unwrap();
+        assert_eq!(
+            hopper_recording.get_record::<IncipientCoresPackage>(0),
+            &IncipientCoresPackage::new(
+                cryptde(),
+                make_meaningless_route(),
+                ClientResponsePayload {
+                    exit_failed: true,
+                    ..ClientResponsePayload::make_terminating_payload(stream_key)
+                },
+                &PublicKey::new(&b"abcd"[..]),
+            )
+            .unwrap()
+        );
+    }
+
+    #[test]
+    fn inbound_server_data_on_a_udp_stream_is_marked_udp_for_the_originator() {
+        let (hopper, _, hopper_recording_arc) = make_recorder();
+        let stream_key = make_meaningless_stream_key();
+        let system =
+            System::new("inbound_server_data_on_a_udp_stream_is_marked_udp_for_the_originator");
+        let mut subject = ProxyClient::new(
+            cryptde(),
+            vec![SocketAddr::from_str("8.7.6.5:4321").unwrap()],
+        );
+        subject.stream_contexts.insert(
+            stream_key.clone(),
+            StreamContext {
+                return_route: make_meaningless_route(),
+                payload_destination_key: PublicKey::new(&b"abcd"[..]),
+                consuming_wallet: None,
+                last_used: Instant::now(),
+                upload_window_opt: None,
+                requests_received: 0,
+                requests_acknowledged: 0,
+                udp: true,
+            },
         );
-        let pool_factory = StreamHandlerPoolFactoryMock::new().make_result(pool);
-        let resolver = ResolverWrapperMock::new()
-            .lookup_ip_success(vec![IpAddr::from_str("4.3.2.1").unwrap()]);
-        let resolver_factory = ResolverWrapperFactoryMock::new().new_result(Box::new(resolver));
-        let mut subject = ProxyClient::new(cryptde, dnss());
-        subject.resolver_wrapper_factory = Box::new(resolver_factory);
-        subject.stream_handler_pool_factory = Box::new(pool_factory);
         let subject_addr: Addr<Syn, ProxyClient> = subject.start();
+        let peer_actors = peer_actors_builder().hopper(hopper).build();
         subject_addr.try_send(BindMessage { peer_actors }).unwrap();
 
-        subject_addr.try_send(package).unwrap();
+        subject_addr
+            .try_send(InboundServerData {
+                stream_key: stream_key.clone(),
+                last_data: false,
+                sequence_number: 0,
+                source: SocketAddr::from_str("1.2.3.4:53").unwrap(),
+                data: b"answer".to_vec(),
+                truncated: false,
+            })
+            .unwrap();
 
         Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
         system.run();
-        let parameter = process_package_parameters.lock().unwrap().remove(0);
-        assert_eq!(parameter, (request, Some(Wallet::new("consuming")),));
+        let hopper_recording = hopper_recording_arc.lock().unwrap();
+        assert_eq!(
+            hopper_recording.get_record::<IncipientCoresPackage>(0),
+            &IncipientCoresPackage::new(
+                cryptde(),
+                make_meaningless_route(),
+                ClientResponsePayload {
+                    stream_key: stream_key.clone(),
+                    sequenced_packet: SequencedPacket {
+                        data: b"answer".to_vec(),
+                        sequence_number: 0,
+                        last_data: false
+                    },
+                    requests_received: None,
+                    truncated: false,
+                    udp: true,
+                    exit_failed: false,
+                },
+                &PublicKey::new(&b"abcd"[..]),
+            )
+            .unwrap()
+        );
     }
 
     #[test]
@@ -559,6 +2092,11 @@ mod tests {
                 return_route: make_meaningless_route(),
                 payload_destination_key: PublicKey::new(&b"abcd"[..]),
                 consuming_wallet: Some(Wallet::new("consuming")),
+                last_used: Instant::now(),
+                upload_window_opt: None,
+                requests_received: 0,
+                requests_acknowledged: 0,
+                udp: false,
             },
         );
         let subject_addr: Addr<Syn, ProxyClient> = subject.start();
@@ -576,6 +2114,7 @@ mod tests {
                 sequence_number: 1234,
                 source: SocketAddr::from_str("1.2.3.4:5678").unwrap(),
                 data: Vec::from(data),
+                truncated: false,
             })
             .unwrap();
         subject_addr
@@ -585,6 +2124,7 @@ mod tests {
                 sequence_number: 1235,
                 source: SocketAddr::from_str("1.2.3.4:5678").unwrap(),
                 data: Vec::from(data),
+                truncated: false,
             })
             .unwrap();
         subject_addr
@@ -594,6 +2134,7 @@ mod tests {
                 sequence_number: 1236,
                 source: SocketAddr::from_str("1.2.3.4:5678").unwrap(),
                 data: Vec::from(data),
+                truncated: false,
             })
             .unwrap();
 
@@ -612,6 +2153,10 @@ mod tests {
                         sequence_number: 1234,
                         last_data: false
                     },
+                    requests_received: None,
+                    truncated: false,
+                    udp: false,
+                    exit_failed: false,
                 },
                 &PublicKey::new(&b"abcd"[..]),
             )
@@ -629,6 +2174,10 @@ mod tests {
                         sequence_number: 1235,
                         last_data: true
                     },
+                    requests_received: None,
+                    truncated: false,
+                    udp: false,
+                    exit_failed: false,
                 },
                 &PublicKey::new(&b"abcd"[..]),
             )
@@ -642,8 +2191,9 @@ mod tests {
             &ReportExitServiceProvidedMessage {
                 consuming_wallet: Wallet::new("consuming"),
                 payload_size: data.len(),
-                service_rate: TEMPORARY_PER_EXIT_RATE,
-                byte_rate: TEMPORARY_PER_EXIT_BYTE_RATE
+                service_rate: DEFAULT_PER_EXIT_RATE,
+                byte_rate: DEFAULT_PER_EXIT_BYTE_RATE,
+                correlation_id_opt: Some(CorrelationId::new(stream_key, SystemTime::now())),
             }
         );
         assert_eq!(
@@ -651,14 +2201,80 @@ mod tests {
             &ReportExitServiceProvidedMessage {
                 consuming_wallet: Wallet::new("consuming"),
                 payload_size: data.len(),
-                service_rate: TEMPORARY_PER_EXIT_RATE,
-                byte_rate: TEMPORARY_PER_EXIT_BYTE_RATE
+                service_rate: DEFAULT_PER_EXIT_RATE,
+                byte_rate: DEFAULT_PER_EXIT_BYTE_RATE,
+                correlation_id_opt: Some(CorrelationId::new(stream_key, SystemTime::now())),
             }
         );
         assert_eq!(accountant_recording.len(), 2);
         TestLogHandler::new ().exists_log_containing(format!("ERROR: Proxy Client: Received unsolicited {}-byte response from 1.2.3.4:5678, seq 1236: ignoring", data.len ()).as_str ());
     }
 
+    #[test]
+    fn inbound_server_data_is_charged_for_at_the_exit_rates_on_the_rate_card() {
+        let (hopper, _, _) = make_recorder();
+        let (accountant, _, accountant_recording_arc) = make_recorder();
+        let stream_key = make_meaningless_stream_key();
+        let data: &[u8] = b"An honest politician is one who, when he is bought, will stay bought.";
+        let system =
+            System::new("inbound_server_data_is_charged_for_at_the_exit_rates_on_the_rate_card");
+        let mut subject = ProxyClient::new(
+            cryptde(),
+            vec![SocketAddr::from_str("8.7.6.5:4321").unwrap()],
+        );
+        subject.charge(RateCard {
+            routing_service_rate: 10,
+            routing_byte_rate: 20,
+            exit_service_rate: 30,
+            exit_byte_rate: 40,
+        });
+        subject.stream_contexts.insert(
+            stream_key.clone(),
+            StreamContext {
+                return_route: make_meaningless_route(),
+                payload_destination_key: PublicKey::new(&b"abcd"[..]),
+                consuming_wallet: Some(Wallet::new("consuming")),
+                last_used: Instant::now(),
+                upload_window_opt: None,
+                requests_received: 0,
+                requests_acknowledged: 0,
+                udp: false,
+            },
+        );
+        let subject_addr: Addr<Syn, ProxyClient> = subject.start();
+        let peer_actors = peer_actors_builder()
+            .hopper(hopper)
+            .accountant(accountant)
+            .build();
+        subject_addr.try_send(BindMessage { peer_actors }).unwrap();
+
+        subject_addr
+            .try_send(InboundServerData {
+                stream_key: stream_key.clone(),
+                last_data: false,
+                sequence_number: 1234,
+                source: SocketAddr::from_str("1.2.3.4:5678").unwrap(),
+                data: Vec::from(data),
+                truncated: false,
+            })
+            .unwrap();
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+        let accountant_recording = accountant_recording_arc.lock().unwrap();
+        assert_eq!(
+            accountant_recording.get_record::<ReportExitServiceProvidedMessage>(0),
+            &ReportExitServiceProvidedMessage {
+                consuming_wallet: Wallet::new("consuming"),
+                payload_size: data.len(),
+                service_rate: 30,
+                byte_rate: 40,
+                correlation_id_opt: Some(CorrelationId::new(stream_key, SystemTime::now())),
+            }
+        );
+        assert_eq!(accountant_recording.len(), 1);
+    }
+
     #[test]
     fn inbound_server_data_without_consuming_wallet_does_not_report_exit_service() {
         init_test_logging();
@@ -676,6 +2292,11 @@ mod tests {
                 return_route: make_meaningless_route(),
                 payload_destination_key: PublicKey::new(&b"abcd"[..]),
                 consuming_wallet: None,
+                last_used: Instant::now(),
+                upload_window_opt: None,
+                requests_received: 0,
+                requests_acknowledged: 0,
+                udp: false,
             },
         );
         let subject_addr: Addr<Syn, ProxyClient> = subject.start();
@@ -690,6 +2311,7 @@ mod tests {
                 sequence_number: 1234,
                 source: SocketAddr::from_str("1.2.3.4:5678").unwrap(),
                 data: Vec::from(data),
+                truncated: false,
             })
             .unwrap();
 
@@ -724,6 +2346,11 @@ mod tests {
                 return_route: make_meaningless_route(),
                 payload_destination_key: PublicKey::new(&[]),
                 consuming_wallet: Some(Wallet::new("consuming")),
+                last_used: Instant::now(),
+                upload_window_opt: None,
+                requests_received: 0,
+                requests_acknowledged: 0,
+                udp: false,
             },
         );
         let subject_addr: Addr<Syn, ProxyClient> = subject.start();
@@ -740,6 +2367,7 @@ mod tests {
                 sequence_number: 1234,
                 source: SocketAddr::from_str("1.2.3.4:5678").unwrap(),
                 data: Vec::from(data),
+                truncated: false,
             })
             .unwrap();
 
@@ -777,6 +2405,11 @@ mod tests {
                 return_route: old_return_route,
                 payload_destination_key: originator_public_key.clone(),
                 consuming_wallet: Some(Wallet::new("consuming")),
+                last_used: Instant::now(),
+                upload_window_opt: None,
+                requests_received: 0,
+                requests_acknowledged: 0,
+                udp: false,
             },
         );
         subject.stream_handler_pool_factory = Box::new(pool_factory);
@@ -797,6 +2430,11 @@ mod tests {
             target_port: 0,
             protocol: ProxyProtocol::HTTP,
             originator_public_key: originator_public_key.clone(),
+            tls_at_exit: false,
+            upload_window: None,
+            udp: false,
+            dns_server: None,
+            cancel: false,
         };
 
         subject_addr
@@ -815,6 +2453,7 @@ mod tests {
                 sequence_number: 1234,
                 source: SocketAddr::from_str("1.2.3.4:5678").unwrap(),
                 data: Vec::from(data.clone()),
+                truncated: false,
             })
             .unwrap();
         Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
@@ -834,6 +2473,10 @@ mod tests {
                     sequence_number: 1234,
                     last_data: false,
                 },
+                requests_received: None,
+                truncated: false,
+                udp: false,
+                exit_failed: false,
             },
             &originator_public_key,
         )
@@ -849,9 +2492,114 @@ mod tests {
             &ReportExitServiceProvidedMessage {
                 consuming_wallet: Wallet::new("gnimusnoc"),
                 payload_size: data.len(),
-                service_rate: TEMPORARY_PER_EXIT_RATE,
-                byte_rate: TEMPORARY_PER_EXIT_BYTE_RATE,
+                service_rate: DEFAULT_PER_EXIT_RATE,
+                byte_rate: DEFAULT_PER_EXIT_BYTE_RATE,
+                correlation_id_opt: Some(CorrelationId::new(stream_key, SystemTime::now())),
             }
         )
     }
+
+    #[test]
+    fn windowed_streams_get_acknowledgements_and_counts_in_responses() {
+        let cryptde = cryptde();
+        let (hopper, _, hopper_recording_arc) = make_recorder();
+        let (accountant, _, accountant_recording_arc) = make_recorder();
+        let stream_key = make_meaningless_stream_key();
+        let data: &[u8] = b"An honest politician is one who, when he is bought, will stay bought.";
+        let system = System::new("windowed_streams_get_acknowledgements_and_counts_in_responses");
+        let mut subject =
+            ProxyClient::new(cryptde, vec![SocketAddr::from_str("8.7.6.5:4321").unwrap()]);
+        let pool = StreamHandlerPoolMock::new();
+        let pool_factory = StreamHandlerPoolFactoryMock::new().make_result(Box::new(pool));
+        let return_route = make_meaningless_route();
+        let originator_public_key = PublicKey::new(&[4, 3, 2, 1]);
+        subject.stream_handler_pool_factory = Box::new(pool_factory);
+        let subject_addr: Addr<Syn, ProxyClient> = subject.start();
+        let peer_actors = peer_actors_builder()
+            .hopper(hopper)
+            .accountant(accountant)
+            .build();
+        subject_addr.try_send(BindMessage { peer_actors }).unwrap();
+        let payload = ClientRequestPayload {
+            stream_key: stream_key.clone(),
+            sequenced_packet: SequencedPacket {
+                data: vec![],
+                sequence_number: 0,
+                last_data: false,
+            },
+            target_hostname: None,
+            target_port: 0,
+            protocol: ProxyProtocol::HTTP,
+            originator_public_key: originator_public_key.clone(),
+            tls_at_exit: false,
+            upload_window: Some(4),
+            udp: false,
+            dns_server: None,
+            cancel: false,
+        };
+
+        (0..4).for_each(|_| {
+            subject_addr
+                .try_send(ExpiredCoresPackage::new(
+                    IpAddr::from_str("2.3.4.5").unwrap(),
+                    Some(Wallet::new("consuming")),
+                    return_route.clone(),
+                    encodex(cryptde, &cryptde.public_key(), &payload).unwrap(),
+                ))
+                .unwrap();
+        });
+        subject_addr
+            .try_send(InboundServerData {
+                stream_key: stream_key.clone(),
+                last_data: false,
+                sequence_number: 1234,
+                source: SocketAddr::from_str("1.2.3.4:5678").unwrap(),
+                data: Vec::from(data.clone()),
+                truncated: false,
+            })
+            .unwrap();
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+        let make_icp = |payload: ClientResponsePayload| {
+            IncipientCoresPackage::new(
+                cryptde,
+                return_route.clone(),
+                payload,
+                &originator_public_key,
+            )
+            .unwrap()
+        };
+        let hopper_recording = hopper_recording_arc.lock().unwrap();
+        assert_eq!(
+            hopper_recording.get_record::<IncipientCoresPackage>(0),
+            &make_icp(ClientResponsePayload::make_acknowledgement_payload(
+                stream_key, 1
+            ))
+        );
+        assert_eq!(
+            hopper_recording.get_record::<IncipientCoresPackage>(1),
+            &make_icp(ClientResponsePayload::make_acknowledgement_payload(
+                stream_key, 3
+            ))
+        );
+        assert_eq!(
+            hopper_recording.get_record::<IncipientCoresPackage>(2),
+            &make_icp(ClientResponsePayload {
+                stream_key,
+                sequenced_packet: SequencedPacket {
+                    data: Vec::from(data.clone()),
+                    sequence_number: 1234,
+                    last_data: false,
+                },
+                requests_received: Some(4),
+                truncated: false,
+                udp: false,
+                exit_failed: false,
+            })
+        );
+        assert_eq!(hopper_recording.len(), 3);
+        let accountant_recording = accountant_recording_arc.lock().unwrap();
+        assert_eq!(accountant_recording.len(), 1);
+    }
 }
@@ -1,4 +1,10 @@
 // Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use crate::proxy_client::dns_cache::ResolverWrapperCaching;
+use crate::proxy_client::dns_cache::DNS_CACHE_MAX_ENTRIES;
+use crate::sub_lib::metrics::MetricsReport;
+use actix::Recipient;
+use actix::Syn;
+use std::time::Instant;
 use tokio::prelude::Future;
 use trust_dns_resolver::config::ResolverConfig;
 use trust_dns_resolver::config::ResolverOpts;
@@ -28,6 +34,38 @@ impl ResolverWrapper for ResolverWrapperReal {
     }
 }
 
+// Tells the Metrics actor how long each lookup took, whether it succeeded or not and whether the
+// answer came from the cache or not, since that's how long the stream waiting on it waited.
+pub struct ResolverWrapperTimed {
+    delegate: Box<dyn ResolverWrapper>,
+    to_metrics: Recipient<Syn, MetricsReport>,
+}
+
+impl ResolverWrapper for ResolverWrapperTimed {
+    fn lookup_ip(&self, host_opt: Option<String>) -> Box<WrappedLookupIpFuture> {
+        let started = Instant::now();
+        let to_metrics = self.to_metrics.clone();
+        Box::new(self.delegate.lookup_ip(host_opt).then(move |result| {
+            to_metrics
+                .try_send(MetricsReport::DnsResolution(started.elapsed()))
+                .expect("Metrics is dead");
+            result
+        }))
+    }
+}
+
+impl ResolverWrapperTimed {
+    pub fn new(
+        delegate: Box<dyn ResolverWrapper>,
+        to_metrics: Recipient<Syn, MetricsReport>,
+    ) -> ResolverWrapperTimed {
+        ResolverWrapperTimed {
+            delegate,
+            to_metrics,
+        }
+    }
+}
+
 pub struct ResolverWrapperFactoryReal;
 impl ResolverWrapperFactory for ResolverWrapperFactoryReal {
     fn make(&self, config: ResolverConfig, options: ResolverOpts) -> Box<dyn ResolverWrapper> {
@@ -38,6 +76,64 @@ impl ResolverWrapperFactory for ResolverWrapperFactoryReal {
                 .expect("couldn't create resolver future"),
         );
 
-        Box::new(ResolverWrapperReal { delegate })
+        Box::new(ResolverWrapperCaching::new(
+            Box::new(ResolverWrapperReal { delegate }),
+            DNS_CACHE_MAX_ENTRIES,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proxy_client::local_test_utils::ResolverWrapperMock;
+    use crate::test_utils::recorder::make_recorder;
+    use crate::test_utils::recorder::peer_actors_builder;
+    use actix::msgs;
+    use actix::Arbiter;
+    use actix::System;
+    use std::net::IpAddr;
+    use std::str::FromStr;
+    use std::sync::Arc;
+    use std::sync::Mutex;
+    use trust_dns_resolver::error::ResolveErrorKind;
+
+    #[test]
+    fn timed_resolver_reports_every_lookup_and_passes_its_result_along() {
+        let system = System::new("timed_resolver_reports_every_lookup_and_passes_its_result_along");
+        let (metrics, _, metrics_recording_arc) = make_recorder();
+        let peer_actors = peer_actors_builder().metrics(metrics).build();
+        let lookup_ip_parameters = Arc::new(Mutex::new(vec![]));
+        let delegate = ResolverWrapperMock::new()
+            .lookup_ip_success(vec![IpAddr::from_str("1.2.3.4").unwrap()])
+            .lookup_ip_failure(ResolveError::from(ResolveErrorKind::Io))
+            .lookup_ip_parameters(&lookup_ip_parameters);
+        let subject = ResolverWrapperTimed::new(Box::new(delegate), peer_actors.metrics.report);
+
+        let success = subject.lookup_ip(Some(String::from("booga.com"))).wait();
+        let failure = subject.lookup_ip(Some(String::from("agoob.com"))).wait();
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+        assert_eq!(
+            success.unwrap().iter().collect::<Vec<IpAddr>>(),
+            vec![IpAddr::from_str("1.2.3.4").unwrap()]
+        );
+        assert_eq!(failure.is_err(), true);
+        assert_eq!(
+            *lookup_ip_parameters.lock().unwrap(),
+            vec![
+                Some(String::from("booga.com")),
+                Some(String::from("agoob.com"))
+            ]
+        );
+        let metrics_recording = metrics_recording_arc.lock().unwrap();
+        assert_eq!(metrics_recording.len(), 2);
+        (0..2).for_each(
+            |index| match metrics_recording.get_record::<MetricsReport>(index) {
+                MetricsReport::DnsResolution(_) => (),
+                report => panic!("Expected a DNS resolution, not {:?}", report),
+            },
+        );
     }
 }
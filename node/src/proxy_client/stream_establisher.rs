@@ -2,6 +2,7 @@
 
 use crate::proxy_client::stream_reader::StreamReader;
 use crate::proxy_client::stream_writer::StreamWriter;
+use crate::proxy_client::throughput_limiter::ExitThroughputLimiters;
 use crate::sub_lib::channel_wrappers::FuturesChannelFactory;
 use crate::sub_lib::channel_wrappers::FuturesChannelFactoryReal;
 use crate::sub_lib::channel_wrappers::SenderWrapper;
@@ -10,6 +11,8 @@ use crate::sub_lib::framer::Framer;
 use crate::sub_lib::http_packet_framer::HttpPacketFramer;
 use crate::sub_lib::http_response_start_finder::HttpResponseStartFinder;
 use crate::sub_lib::logger::Logger;
+use crate::sub_lib::proxy_client::ExitConnectTimeouts;
+use crate::sub_lib::proxy_client::ExitPolicy;
 use crate::sub_lib::proxy_client::InboundServerData;
 use crate::sub_lib::proxy_server::ClientRequestPayload;
 use crate::sub_lib::proxy_server::ProxyProtocol;
@@ -21,6 +24,9 @@ use crate::sub_lib::tls_framer::TlsFramer;
 use crate::sub_lib::tokio_wrappers::ReadHalfWrapper;
 use actix::Recipient;
 use actix::Syn;
+use futures::future;
+use futures::future::Either;
+use futures::sync::oneshot;
 use std::io;
 use std::io::Error;
 use std::io::ErrorKind;
@@ -28,6 +34,7 @@ use std::net::IpAddr;
 use std::net::SocketAddr;
 use std::sync::mpsc::Sender;
 use tokio;
+use tokio::prelude::Future;
 use trust_dns_resolver::error::ResolveError;
 use trust_dns_resolver::lookup_ip::LookupIp;
 
@@ -37,6 +44,9 @@ pub struct StreamEstablisher {
     pub stream_killer_tx: Sender<StreamKey>,
     pub stream_connector: Box<dyn StreamConnector>,
     pub proxy_client_sub: Recipient<Syn, InboundServerData>,
+    pub throughput_limiters: ExitThroughputLimiters,
+    pub connect_timeouts: ExitConnectTimeouts,
+    pub exit_policy: ExitPolicy,
     pub logger: Logger,
     pub channel_factory: Box<dyn FuturesChannelFactory<SequencedPacket>>,
 }
@@ -49,6 +59,9 @@ impl Clone for StreamEstablisher {
             stream_killer_tx: self.stream_killer_tx.clone(),
             stream_connector: Box::new(StreamConnectorReal {}),
             proxy_client_sub: self.proxy_client_sub.clone(),
+            throughput_limiters: self.throughput_limiters.clone(),
+            connect_timeouts: self.connect_timeouts.clone(),
+            exit_policy: self.exit_policy.clone(),
             logger: self.logger.clone(),
             channel_factory: Box::new(FuturesChannelFactoryReal {}),
         }
@@ -60,6 +73,7 @@ impl StreamEstablisher {
         &mut self,
         payload: &ClientRequestPayload,
         lookup_result: Result<LookupIp, ResolveError>,
+        killed: oneshot::Receiver<()>,
     ) -> io::Result<Box<dyn SenderWrapper<SequencedPacket>>> {
         let target_hostname = match &payload.target_hostname {
             Some(target_hostname) => target_hostname.clone(),
@@ -85,11 +99,16 @@ impl StreamEstablisher {
             "Found IP addresses for {}: {:?}",
             target_hostname, &ip_addrs
         ));
+        let ip_addrs = self.allowed_ip_addrs(&target_hostname, ip_addrs)?;
+        if payload.tls_at_exit {
+            return self.establish_tls_stream(payload, ip_addrs, &target_hostname, killed);
+        }
 
         let connection_info = self.stream_connector.connect_one(
             ip_addrs,
             &target_hostname,
             payload.target_port,
+            self.connect_timeouts.for_port(payload.target_port),
             &self.logger,
         )?;
 
@@ -97,6 +116,7 @@ impl StreamEstablisher {
             &payload.clone(),
             connection_info.reader,
             connection_info.peer_addr,
+            killed,
         )?;
 
         let (tx_to_write, rx_to_write) = self.channel_factory.make(connection_info.peer_addr);
@@ -105,6 +125,7 @@ impl StreamEstablisher {
             connection_info.peer_addr,
             rx_to_write,
             payload.stream_key,
+            self.throughput_limiters.upstream.clone(),
         );
         tokio::spawn(stream_writer);
 
@@ -114,11 +135,82 @@ impl StreamEstablisher {
         Ok(tx_to_write)
     }
 
+    // Addresses the exit policy blocks are left out; if that leaves none, the stream is refused
+    fn allowed_ip_addrs(
+        &self,
+        target_hostname: &String,
+        ip_addrs: Vec<IpAddr>,
+    ) -> io::Result<Vec<IpAddr>> {
+        let (blocked, allowed): (Vec<IpAddr>, Vec<IpAddr>) = ip_addrs
+            .into_iter()
+            .partition(|ip_addr| self.exit_policy.blocks_ip_addr(*ip_addr));
+        if !blocked.is_empty() && allowed.is_empty() {
+            self.logger.warning(format!(
+                "Refusing stream to {} by exit policy: every address it has ({:?}) is blocked",
+                target_hostname, blocked
+            ));
+            return Err(Error::from(ErrorKind::PermissionDenied));
+        }
+        Ok(allowed)
+    }
+
+    // The TLS handshake with the server finishes some time later, so anything the originator sends
+    // in the meantime waits in the writer's channel. If the handshake fails--for example, because
+    // the server's certificate doesn't check out--the stream is killed without sending anything.
+    fn establish_tls_stream(
+        &mut self,
+        payload: &ClientRequestPayload,
+        ip_addrs: Vec<IpAddr>,
+        target_hostname: &String,
+        killed: oneshot::Receiver<()>,
+    ) -> io::Result<Box<dyn SenderWrapper<SequencedPacket>>> {
+        let (peer_addr, connection_info_future) = self.stream_connector.connect_one_tls(
+            ip_addrs,
+            target_hostname,
+            payload.target_port,
+            self.connect_timeouts.for_port(payload.target_port),
+            &self.logger,
+        )?;
+        let (tx_to_write, rx_to_write) = self.channel_factory.make(peer_addr);
+        self.stream_adder_tx
+            .send((payload.stream_key, tx_to_write.clone()))
+            .expect("StreamHandlerPool died");
+
+        let establisher = self.clone();
+        let payload = payload.clone();
+        tokio::spawn(connection_info_future.then(move |result| {
+            match result {
+                Ok(connection_info) => {
+                    establisher
+                        .spawn_stream_reader(&payload, connection_info.reader, peer_addr, killed)
+                        .is_ok();
+                    let stream_writer = StreamWriter::new(
+                        connection_info.writer,
+                        peer_addr,
+                        rx_to_write,
+                        payload.stream_key,
+                        establisher.throughput_limiters.upstream.clone(),
+                    );
+                    tokio::spawn(stream_writer);
+                }
+                Err(_) => {
+                    establisher
+                        .stream_killer_tx
+                        .send(payload.stream_key)
+                        .is_ok();
+                }
+            }
+            Ok(())
+        }));
+        Ok(tx_to_write)
+    }
+
     fn spawn_stream_reader(
         &self,
         payload: &ClientRequestPayload,
         read_stream: Box<dyn ReadHalfWrapper>,
         peer_addr: SocketAddr,
+        killed: oneshot::Receiver<()>,
     ) -> io::Result<()> {
         let framer = Self::framer_from_protocol(payload.protocol);
 
@@ -129,10 +221,17 @@ impl StreamEstablisher {
             self.stream_killer_tx.clone(),
             peer_addr,
             framer,
+            self.throughput_limiters.downstream.clone(),
         );
         self.logger
             .debug(format!("Spawning StreamReader for {}", peer_addr));
-        tokio::spawn(stream_reader);
+        // The reader stops early only if the pool fires the stream's reader killer, as it does when
+        // the originator cancels the stream; a killer that's merely dropped changes nothing
+        let killed = killed.then(|result| match result {
+            Ok(()) => Either::A(future::ok(())),
+            Err(_) => Either::B(future::empty()),
+        });
+        tokio::spawn(stream_reader.select(killed).then(|_| Ok(())));
         Ok(())
     }
 
@@ -155,6 +254,9 @@ pub struct StreamEstablisherFactoryReal {
     pub stream_adder_tx: Sender<(StreamKey, Box<dyn SenderWrapper<SequencedPacket>>)>,
     pub stream_killer_tx: Sender<StreamKey>,
     pub proxy_client_sub: Recipient<Syn, InboundServerData>,
+    pub throughput_limiters: ExitThroughputLimiters,
+    pub connect_timeouts: ExitConnectTimeouts,
+    pub exit_policy: ExitPolicy,
     pub logger: Logger,
 }
 
@@ -166,6 +268,9 @@ impl StreamEstablisherFactory for StreamEstablisherFactoryReal {
             stream_killer_tx: self.stream_killer_tx.clone(),
             stream_connector: Box::new(StreamConnectorReal {}),
             proxy_client_sub: self.proxy_client_sub.clone(),
+            throughput_limiters: self.throughput_limiters.clone(),
+            connect_timeouts: self.connect_timeouts.clone(),
+            exit_policy: self.exit_policy.clone(),
             logger: self.logger.clone(),
             channel_factory: Box::new(FuturesChannelFactoryReal {}),
         }
@@ -176,19 +281,25 @@ impl StreamEstablisherFactory for StreamEstablisherFactoryReal {
 mod tests {
     use super::*;
     use crate::sub_lib::proxy_server::ProxyProtocol;
+    use crate::test_utils::logging::init_test_logging;
+    use crate::test_utils::logging::TestLogHandler;
     use crate::test_utils::recorder::make_recorder;
     use crate::test_utils::recorder::peer_actors_builder;
     use crate::test_utils::stream_connector_mock::StreamConnectorMock;
     use crate::test_utils::test_utils::cryptde;
     use crate::test_utils::test_utils::make_meaningless_stream_key;
     use crate::test_utils::tokio_wrapper_mocks::ReadHalfWrapperMock;
+    use crate::test_utils::tokio_wrapper_mocks::WriteHalfWrapperMock;
     use actix::System;
     use futures::future::lazy;
     use std::io::ErrorKind;
     use std::net::SocketAddr;
     use std::str::FromStr;
     use std::sync::mpsc;
+    use std::sync::Arc;
+    use std::sync::Mutex;
     use std::thread;
+    use std::time::Duration;
     use tokio::prelude::Async;
 
     #[test]
@@ -223,8 +334,11 @@ mod tests {
                 stream_killer_tx,
                 stream_connector: Box::new(StreamConnectorMock::new()), // only used in "establish_stream"
                 proxy_client_sub,
+                throughput_limiters: ExitThroughputLimiters::unlimited(),
+                connect_timeouts: ExitConnectTimeouts::default(),
                 logger: Logger::new("Proxy Client"),
                 channel_factory: Box::new(FuturesChannelFactoryReal {}),
+                exit_policy: ExitPolicy::default(),
             };
             subject
                 .spawn_stream_reader(
@@ -239,9 +353,15 @@ mod tests {
                         target_port: 0,
                         protocol: ProxyProtocol::HTTP,
                         originator_public_key: subject.cryptde.public_key(),
+                        tls_at_exit: false,
+                        upload_window: None,
+                        udp: false,
+                        dns_server: None,
+                        cancel: false,
                     },
                     read_stream,
                     SocketAddr::from_str("1.2.3.4:5678").unwrap(),
+                    oneshot::channel().1,
                 )
                 .expect("spawn_stream_reader () failed");
 
@@ -267,7 +387,8 @@ mod tests {
                 last_data: false,
                 sequence_number: 0,
                 source: SocketAddr::from_str("1.2.3.4:5678").unwrap(),
-                data: b"HTTP/1.1 200 OK\r\n\r\n".to_vec()
+                data: b"HTTP/1.1 200 OK\r\n\r\n".to_vec(),
+                truncated: false,
             }
         );
     }
@@ -303,8 +424,11 @@ mod tests {
                 stream_killer_tx,
                 stream_connector: Box::new(StreamConnectorMock::new()), // only used in "establish_stream"
                 proxy_client_sub,
+                throughput_limiters: ExitThroughputLimiters::unlimited(),
+                connect_timeouts: ExitConnectTimeouts::default(),
                 logger: Logger::new("Proxy Client"),
                 channel_factory: Box::new(FuturesChannelFactoryReal {}),
+                exit_policy: ExitPolicy::default(),
             };
 
             subject
@@ -320,9 +444,15 @@ mod tests {
                         target_port: 0,
                         protocol: ProxyProtocol::TLS,
                         originator_public_key: subject.cryptde.public_key(),
+                        tls_at_exit: false,
+                        upload_window: None,
+                        udp: false,
+                        dns_server: None,
+                        cancel: false,
                     },
                     read_stream,
                     SocketAddr::from_str("1.2.3.4:5678").unwrap(),
+                    oneshot::channel().1,
                 )
                 .expect("spawn_stream_reader () failed");
             proxy_client_awaiter.await_message_count(1);
@@ -347,8 +477,160 @@ mod tests {
                 last_data: false,
                 sequence_number: 0,
                 source: SocketAddr::from_str("1.2.3.4:5678").unwrap(),
-                data: vec!(0x16, 0x03, 0x03, 0x00, 0x00)
+                data: vec!(0x16, 0x03, 0x03, 0x00, 0x00),
+                truncated: false,
             }
         );
     }
+
+    #[test]
+    fn establish_tls_stream_connects_with_tls_and_adds_the_stream_right_away() {
+        let (proxy_client, _, _) = make_recorder();
+        let (sub_tx, sub_rx) = mpsc::channel();
+        thread::spawn(move || {
+            let system = System::new("test");
+            let peer_actors = peer_actors_builder().proxy_client(proxy_client).build();
+            sub_tx
+                .send(peer_actors.proxy_client.inbound_server_data)
+                .is_ok();
+            system.run();
+        });
+        let connect_one_tls_params_arc = Arc::new(Mutex::new(vec![]));
+        let peer_addr = SocketAddr::from_str("1.2.3.4:443").unwrap();
+        let stream_connector = StreamConnectorMock::new()
+            .connect_one_tls_params(&connect_one_tls_params_arc)
+            .with_connection(
+                SocketAddr::from_str("5.6.7.8:1234").unwrap(),
+                peer_addr,
+                ReadHalfWrapperMock::new()
+                    .poll_read_result(vec![], Err(Error::from(ErrorKind::BrokenPipe))),
+                WriteHalfWrapperMock::new(),
+            );
+        let (stream_adder_tx, stream_adder_rx) = mpsc::channel();
+        let (result_tx, result_rx) = mpsc::channel();
+        let test_future = lazy(move || {
+            let proxy_client_sub = sub_rx.recv().unwrap();
+            let (stream_killer_tx, _) = mpsc::channel();
+            let mut subject = StreamEstablisher {
+                cryptde: cryptde(),
+                stream_adder_tx,
+                stream_killer_tx,
+                stream_connector: Box::new(stream_connector),
+                proxy_client_sub,
+                throughput_limiters: ExitThroughputLimiters::unlimited(),
+                connect_timeouts: ExitConnectTimeouts {
+                    default: Duration::from_secs(10),
+                    by_port: vec![(443, Duration::from_secs(20))].into_iter().collect(),
+                },
+                logger: Logger::new("Proxy Client"),
+                channel_factory: Box::new(FuturesChannelFactoryReal {}),
+                exit_policy: ExitPolicy::default(),
+            };
+
+            let result = subject.establish_tls_stream(
+                &ClientRequestPayload {
+                    stream_key: make_meaningless_stream_key(),
+                    sequenced_packet: SequencedPacket {
+                        data: vec![],
+                        sequence_number: 0,
+                        last_data: false,
+                    },
+                    target_hostname: Some("server.com".to_string()),
+                    target_port: 443,
+                    protocol: ProxyProtocol::HTTP,
+                    originator_public_key: subject.cryptde.public_key(),
+                    tls_at_exit: true,
+                    upload_window: None,
+                    udp: false,
+                    dns_server: None,
+                    cancel: false,
+                },
+                vec![peer_addr.ip()],
+                &"server.com".to_string(),
+                oneshot::channel().1,
+            );
+            result_tx.send(result.map(|tx| tx.peer_addr())).unwrap();
+            Ok(())
+        });
+        thread::spawn(move || {
+            tokio::run(test_future);
+        });
+
+        let result = result_rx.recv().unwrap();
+
+        assert_eq!(result.unwrap(), peer_addr);
+        let (stream_key, _) = stream_adder_rx.recv().unwrap();
+        assert_eq!(stream_key, make_meaningless_stream_key());
+        assert_eq!(
+            *connect_one_tls_params_arc.lock().unwrap(),
+            vec![(String::from("server.com"), 443, Duration::from_secs(20))]
+        );
+    }
+
+    fn make_policed_establisher(exit_policy: ExitPolicy) -> StreamEstablisher {
+        let _system = System::new("make_policed_establisher");
+        let (proxy_client, _, _) = make_recorder();
+        let peer_actors = peer_actors_builder().proxy_client(proxy_client).build();
+        let (stream_adder_tx, _) = mpsc::channel();
+        let (stream_killer_tx, _) = mpsc::channel();
+        StreamEstablisher {
+            cryptde: cryptde(),
+            stream_adder_tx,
+            stream_killer_tx,
+            stream_connector: Box::new(StreamConnectorMock::new()),
+            proxy_client_sub: peer_actors.proxy_client.inbound_server_data,
+            throughput_limiters: ExitThroughputLimiters::unlimited(),
+            connect_timeouts: ExitConnectTimeouts::default(),
+            logger: Logger::new("Proxy Client"),
+            channel_factory: Box::new(FuturesChannelFactoryReal {}),
+            exit_policy,
+        }
+    }
+
+    #[test]
+    fn allowed_ip_addrs_leaves_out_blocked_addresses() {
+        let subject = make_policed_establisher(ExitPolicy {
+            blocked_ports: vec![],
+            blocked_hostnames: vec![],
+            blocked_ip_addrs: vec![IpAddr::from_str("6.6.6.6").unwrap()],
+            offered_dns_servers: vec![],
+        });
+
+        let result = subject.allowed_ip_addrs(
+            &String::from("server.com"),
+            vec![
+                IpAddr::from_str("6.6.6.6").unwrap(),
+                IpAddr::from_str("1.2.3.4").unwrap(),
+            ],
+        );
+
+        assert_eq!(result.unwrap(), vec![IpAddr::from_str("1.2.3.4").unwrap()]);
+    }
+
+    #[test]
+    fn allowed_ip_addrs_refuses_the_stream_if_every_address_is_blocked() {
+        init_test_logging();
+        let subject = make_policed_establisher(ExitPolicy {
+            blocked_ports: vec![],
+            blocked_hostnames: vec![],
+            blocked_ip_addrs: vec![
+                IpAddr::from_str("6.6.6.6").unwrap(),
+                IpAddr::from_str("7.7.7.7").unwrap(),
+            ],
+            offered_dns_servers: vec![],
+        });
+
+        let result = subject.allowed_ip_addrs(
+            &String::from("evil.com"),
+            vec![
+                IpAddr::from_str("6.6.6.6").unwrap(),
+                IpAddr::from_str("7.7.7.7").unwrap(),
+            ],
+        );
+
+        assert_eq!(result.err().unwrap().kind(), ErrorKind::PermissionDenied);
+        TestLogHandler::new().exists_log_containing(
+            "WARN: Proxy Client: Refusing stream to evil.com by exit policy: every address it has ([V4(6.6.6.6), V4(7.7.7.7)]) is blocked",
+        );
+    }
 }
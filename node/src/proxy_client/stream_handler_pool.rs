@@ -3,14 +3,19 @@
 use crate::proxy_client::resolver_wrapper::ResolverWrapper;
 use crate::proxy_client::stream_establisher::StreamEstablisherFactory;
 use crate::proxy_client::stream_establisher::StreamEstablisherFactoryReal;
+use crate::proxy_client::throughput_limiter::ExitThroughputLimiters;
+use crate::proxy_client::udp_socket_pool::UdpSocketPool;
+use crate::proxy_client::udp_socket_pool::UDP_IDLE_TIMEOUT;
+use crate::sub_lib::accountant::CorrelationId;
 use crate::sub_lib::accountant::ReportExitServiceProvidedMessage;
 use crate::sub_lib::channel_wrappers::SenderWrapper;
 use crate::sub_lib::cryptde::CryptDE;
 use crate::sub_lib::logger::Logger;
+use crate::sub_lib::neighborhood::RateCard;
 use crate::sub_lib::proxy_client::error_socket_addr;
+use crate::sub_lib::proxy_client::ExitConnectTimeouts;
+use crate::sub_lib::proxy_client::ExitPolicy;
 use crate::sub_lib::proxy_client::InboundServerData;
-use crate::sub_lib::proxy_client::TEMPORARY_PER_EXIT_BYTE_RATE;
-use crate::sub_lib::proxy_client::TEMPORARY_PER_EXIT_RATE;
 use crate::sub_lib::proxy_server::ClientRequestPayload;
 use crate::sub_lib::sequence_buffer::SequencedPacket;
 use crate::sub_lib::stream_key::StreamKey;
@@ -18,17 +23,23 @@ use crate::sub_lib::wallet::Wallet;
 use actix::Recipient;
 use actix::Syn;
 use futures::future::Future;
+use futures::sync::oneshot;
 use std::collections::HashMap;
+use std::net::IpAddr;
 use std::net::SocketAddr;
 use std::sync::mpsc;
 use std::sync::mpsc::Receiver;
 use std::sync::Arc;
 use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+use std::time::SystemTime;
 use tokio::prelude::future::FutureResult;
 use tokio::prelude::future::{err, ok};
 
 pub trait StreamHandlerPool {
     fn process_package(&self, payload: ClientRequestPayload, consuming_wallet: Option<Wallet>);
+    fn offer_dns_server(&mut self, dns_server: IpAddr, resolver: Box<dyn ResolverWrapper>);
 }
 
 pub struct StreamHandlerPoolReal {
@@ -41,16 +52,26 @@ struct StreamHandlerPoolRealInner {
     accountant_sub: Recipient<Syn, ReportExitServiceProvidedMessage>,
     proxy_client_sub: Recipient<Syn, InboundServerData>,
     stream_writer_channels: HashMap<StreamKey, Box<dyn SenderWrapper<SequencedPacket>>>,
+    stream_reader_killers: HashMap<StreamKey, oneshot::Sender<()>>, // firing one stops its reader
+    udp_sockets: UdpSocketPool,
     resolver: Box<dyn ResolverWrapper>,
+    offered_resolvers: HashMap<IpAddr, Box<dyn ResolverWrapper>>,
     logger: Logger,
     establisher_factory: Box<dyn StreamEstablisherFactory>,
+    max_report_delay: Duration,
+    rate_card: RateCard,
+    exit_policy: ExitPolicy,
 }
 
 impl StreamHandlerPool for StreamHandlerPoolReal {
     fn process_package(&self, payload: ClientRequestPayload, consuming_wallet: Option<Wallet>) {
         self.do_housekeeping();
 
-        if payload.sequenced_packet.last_data
+        if payload.udp {
+            Self::process_datagram(payload, consuming_wallet, self.inner.clone())
+        } else if payload.cancel {
+            self.cancel_stream(&payload.stream_key)
+        } else if payload.sequenced_packet.last_data
             && (payload.sequenced_packet.data.len() == 0)
             && Self::find_stream_with_key(&payload.stream_key, &self.inner).is_none()
         {
@@ -63,6 +84,27 @@ impl StreamHandlerPool for StreamHandlerPoolReal {
             Self::process_package(payload, consuming_wallet, self.inner.clone())
         }
     }
+
+    fn offer_dns_server(&mut self, dns_server: IpAddr, resolver: Box<dyn ResolverWrapper>) {
+        self.inner
+            .lock()
+            .expect("Stream handler pool is poisoned")
+            .offered_resolvers
+            .insert(dns_server, resolver);
+    }
+}
+
+impl StreamHandlerPoolRealInner {
+    // ProxyClient has already refused any stream that asks for a DNS server that isn't offered
+    fn resolver_for(&self, payload: &ClientRequestPayload) -> &dyn ResolverWrapper {
+        match payload
+            .dns_server
+            .and_then(|dns_server| self.offered_resolvers.get(&dns_server))
+        {
+            Some(resolver) => resolver.as_ref(),
+            None => self.resolver.as_ref(),
+        }
+    }
 }
 
 impl StreamHandlerPoolReal {
@@ -71,6 +113,11 @@ impl StreamHandlerPoolReal {
         cryptde: &'static dyn CryptDE,
         accountant_sub: Recipient<Syn, ReportExitServiceProvidedMessage>,
         proxy_client_sub: Recipient<Syn, InboundServerData>,
+        throughput_limiters: ExitThroughputLimiters,
+        connect_timeouts: ExitConnectTimeouts,
+        max_report_delay: Duration,
+        rate_card: RateCard,
+        exit_policy: ExitPolicy,
     ) -> StreamHandlerPoolReal {
         let (stream_killer_tx, stream_killer_rx) = mpsc::channel();
         let (stream_adder_tx, stream_adder_rx) = mpsc::channel();
@@ -81,41 +128,84 @@ impl StreamHandlerPoolReal {
                     stream_adder_tx,
                     stream_killer_tx,
                     proxy_client_sub: proxy_client_sub.clone(),
+                    throughput_limiters,
+                    connect_timeouts,
+                    exit_policy: exit_policy.clone(),
                     logger: Logger::new("Proxy Client"),
                 }),
                 accountant_sub,
-                proxy_client_sub,
+                proxy_client_sub: proxy_client_sub.clone(),
                 stream_writer_channels: HashMap::new(),
+                stream_reader_killers: HashMap::new(),
+                udp_sockets: UdpSocketPool::new(proxy_client_sub, UDP_IDLE_TIMEOUT),
                 resolver,
+                offered_resolvers: HashMap::new(),
                 logger: Logger::new("Proxy Client"),
+                max_report_delay,
+                rate_card,
+                exit_policy,
             })),
             stream_adder_rx,
             stream_killer_rx,
         }
     }
 
+    // The reader stops at once; the writer stops, and the connection to the server closes, as soon
+    // as the writer has written what it already has
+    fn cancel_stream(&self, stream_key: &StreamKey) {
+        let mut inner = self.inner.lock().expect("Stream handler pool is poisoned");
+        let writer_channel_opt = inner.stream_writer_channels.remove(stream_key);
+        let reader_killer_opt = inner.stream_reader_killers.remove(stream_key);
+        if writer_channel_opt.is_none() && reader_killer_opt.is_none() {
+            inner.logger.debug(format!(
+                "Cancel received for nonexistent stream {:?} - ignoring",
+                stream_key
+            ));
+            return;
+        }
+        if let Some(reader_killer) = reader_killer_opt {
+            reader_killer.send(()).is_ok();
+        }
+        inner.logger.debug(format!(
+            "Closing stream {:?} canceled by originator",
+            stream_key
+        ));
+    }
+
     fn process_package(
         payload: ClientRequestPayload,
         consuming_wallet: Option<Wallet>,
         inner_arc: Arc<Mutex<StreamHandlerPoolRealInner>>,
     ) {
         let stream_key = payload.stream_key;
+        let received_at = Instant::now();
         let inner_arc_1 = inner_arc.clone();
         match Self::find_stream_with_key(&stream_key, &inner_arc) {
             Some(sender_wrapper) => {
                 let source = sender_wrapper.peer_addr();
-                let future =
-                    Self::write_and_tend(sender_wrapper, payload, consuming_wallet, inner_arc)
-                        .map_err(move |error| {
-                            Self::clean_up_bad_stream(inner_arc_1, &stream_key, source, error);
-                            ()
-                        });
+                let future = Self::write_and_tend(
+                    sender_wrapper,
+                    payload,
+                    consuming_wallet,
+                    received_at,
+                    inner_arc,
+                )
+                .map_err(move |error| {
+                    Self::clean_up_bad_stream(inner_arc_1, &stream_key, source, error);
+                    ()
+                });
                 tokio::spawn(future);
             }
             None => {
                 let future = Self::make_stream_with_key(&payload, inner_arc_1.clone())
                     .and_then(move |sender_wrapper| {
-                        Self::write_and_tend(sender_wrapper, payload, consuming_wallet, inner_arc)
+                        Self::write_and_tend(
+                            sender_wrapper,
+                            payload,
+                            consuming_wallet,
+                            received_at,
+                            inner_arc,
+                        )
                     })
                     .map_err(move |error| {
                         Self::clean_up_bad_stream(
@@ -131,6 +221,102 @@ impl StreamHandlerPoolReal {
         };
     }
 
+    // A datagram goes out on the stream's UDP socket, which is opened for the first one
+    fn process_datagram(
+        payload: ClientRequestPayload,
+        consuming_wallet: Option<Wallet>,
+        inner_arc: Arc<Mutex<StreamHandlerPoolRealInner>>,
+    ) {
+        let stream_key = payload.stream_key;
+        let received_at = Instant::now();
+        let inner_arc_1 = inner_arc.clone();
+        let has_socket = inner_arc
+            .lock()
+            .expect("Stream handler pool is poisoned")
+            .udp_sockets
+            .contains(&stream_key);
+        if has_socket {
+            if let Err(error) =
+                Self::send_datagram(payload, consuming_wallet, received_at, &inner_arc)
+            {
+                Self::clean_up_bad_stream(inner_arc_1, &stream_key, error_socket_addr(), error);
+            }
+            return;
+        }
+        if payload.sequenced_packet.last_data && payload.sequenced_packet.data.is_empty() {
+            return;
+        }
+        let fqdn = match Self::make_fqdn(&payload.target_hostname) {
+            Some(fqdn) => fqdn,
+            None => {
+                return Self::clean_up_bad_stream(
+                    inner_arc_1,
+                    &stream_key,
+                    error_socket_addr(),
+                    format!("No hostname for datagram to port {}", payload.target_port),
+                )
+            }
+        };
+        let lookup = inner_arc
+            .lock()
+            .expect("Stream handler pool is poisoned")
+            .resolver_for(&payload)
+            .lookup_ip(Some(fqdn));
+        let future = lookup
+            .then(move |lookup_result| {
+                {
+                    let mut inner = inner_arc.lock().expect("Stream handler pool is poisoned");
+                    let peer_addr = match lookup_result {
+                        Ok(lookup_ip) => lookup_ip
+                            .iter()
+                            .find(|ip_addr| !inner.exit_policy.blocks_ip_addr(*ip_addr))
+                            .map(|ip_addr| SocketAddr::new(ip_addr, payload.target_port))
+                            .ok_or_else(|| {
+                                format!(
+                                    "No address for {:?} that the exit policy allows",
+                                    payload.target_hostname
+                                )
+                            }),
+                        Err(e) => Err(format!(
+                            "Could not resolve {:?}: {}",
+                            payload.target_hostname, e
+                        )),
+                    }?;
+                    inner.udp_sockets.open(stream_key, peer_addr)?;
+                }
+                Self::send_datagram(payload, consuming_wallet, received_at, &inner_arc)
+            })
+            .map_err(move |error| {
+                Self::clean_up_bad_stream(inner_arc_1, &stream_key, error_socket_addr(), error)
+            });
+        tokio::spawn(future);
+    }
+
+    fn send_datagram(
+        payload: ClientRequestPayload,
+        consuming_wallet: Option<Wallet>,
+        received_at: Instant,
+        inner_arc: &Arc<Mutex<StreamHandlerPoolRealInner>>,
+    ) -> Result<(), String> {
+        let mut inner = inner_arc.lock().expect("Stream handler pool is poisoned");
+        let stream_key = payload.stream_key;
+        let data = payload.sequenced_packet.data;
+        if !data.is_empty() {
+            inner.udp_sockets.send(&stream_key, &data, Instant::now())?;
+        }
+        if payload.sequenced_packet.last_data {
+            inner.udp_sockets.close(&stream_key);
+        }
+        Self::report_exit_service(
+            &inner,
+            &stream_key,
+            consuming_wallet,
+            data.len(),
+            received_at,
+        );
+        Ok(())
+    }
+
     fn clean_up_bad_stream(
         inner_arc: Arc<Mutex<StreamHandlerPoolRealInner>>,
         stream_key: &StreamKey,
@@ -148,6 +334,8 @@ impl StreamHandlerPoolReal {
                 sender_wrapper.peer_addr()
             ));
         }
+        inner.stream_reader_killers.remove(stream_key);
+        inner.udp_sockets.close(stream_key);
         Self::send_terminating_package(stream_key, source, &inner.proxy_client_sub);
     }
 
@@ -155,6 +343,7 @@ impl StreamHandlerPoolReal {
         sender_wrapper: Box<dyn SenderWrapper<SequencedPacket>>,
         payload: ClientRequestPayload,
         consuming_wallet: Option<Wallet>,
+        received_at: Instant,
         inner_arc: Arc<Mutex<StreamHandlerPoolRealInner>>,
     ) -> impl Future<Item = (), Error = String> {
         let stream_key = payload.stream_key.clone();
@@ -166,26 +355,52 @@ impl StreamHandlerPoolReal {
             if last_data {
                 inner.stream_writer_channels.remove(&stream_key);
             }
-            match consuming_wallet {
-                Some(wallet) => inner
-                    .accountant_sub
-                    .try_send(ReportExitServiceProvidedMessage {
-                        consuming_wallet: wallet,
-                        payload_size,
-                        service_rate: TEMPORARY_PER_EXIT_RATE,
-                        byte_rate: TEMPORARY_PER_EXIT_BYTE_RATE,
-                    })
-                    .expect("Accountant is dead"),
-                // This log is here mostly for testing, to prove that no Accountant message is sent in the no-wallet case
-                None => inner.logger.debug(format!(
-                    "Sent {}-byte request without consuming wallet for free",
-                    payload_size
-                )),
-            }
+            Self::report_exit_service(
+                &inner,
+                &stream_key,
+                consuming_wallet,
+                payload_size,
+                received_at,
+            );
             Ok(())
         })
     }
 
+    fn report_exit_service(
+        inner: &StreamHandlerPoolRealInner,
+        stream_key: &StreamKey,
+        consuming_wallet: Option<Wallet>,
+        payload_size: usize,
+        received_at: Instant,
+    ) {
+        let delay = received_at.elapsed();
+        match consuming_wallet {
+            // Resolving and connecting can take a long time; past max_report_delay the
+            // consumer couldn't be expected to recognize the charge, so it isn't made
+            Some(_) if delay > inner.max_report_delay => inner.logger.warning(format!(
+                "Not charging for {}-byte request written {}ms after it arrived: more than the {}ms allowed",
+                payload_size,
+                millis(delay),
+                millis(inner.max_report_delay)
+            )),
+            Some(wallet) => inner
+                .accountant_sub
+                .try_send(ReportExitServiceProvidedMessage {
+                    consuming_wallet: wallet,
+                    payload_size,
+                    service_rate: inner.rate_card.exit_service_rate,
+                    byte_rate: inner.rate_card.exit_byte_rate,
+                    correlation_id_opt: Some(CorrelationId::new(*stream_key, SystemTime::now())),
+                })
+                .expect("Accountant is dead"),
+            // This log is here mostly for testing, to prove that no Accountant message is sent in the no-wallet case
+            None => inner.logger.debug(format!(
+                "Sent {}-byte request without consuming wallet for free",
+                payload_size
+            )),
+        }
+    }
+
     fn make_stream_with_key(
         payload: &ClientRequestPayload,
         inner_arc: Arc<Mutex<StreamHandlerPoolRealInner>>,
@@ -205,13 +420,19 @@ impl StreamHandlerPoolReal {
         let fqdn_opt = Self::make_fqdn(&payload.target_hostname);
 
         let payload_clone = payload.clone();
+        let (reader_killer, killed) = oneshot::channel();
+        inner_arc
+            .lock()
+            .expect("Stream handler pool is poisoned")
+            .stream_reader_killers
+            .insert(payload.stream_key, reader_killer);
         inner_arc
             .lock()
             .expect("Stream handler pool is poisoned")
-            .resolver
+            .resolver_for(payload)
             .lookup_ip(fqdn_opt)
             .then(move |lookup_result| {
-                let result = establisher.establish_stream(&payload_clone, lookup_result);
+                let result = establisher.establish_stream(&payload_clone, lookup_result, killed);
                 result
             })
             .map_err(|io_error| format!("Could not establish stream: {:?}", io_error))
@@ -264,6 +485,7 @@ impl StreamHandlerPoolReal {
                 sequence_number: 0,
                 source,
                 data: vec![],
+                truncated: false,
             })
             .expect("Proxy Client is dead");
     }
@@ -271,22 +493,30 @@ impl StreamHandlerPoolReal {
     fn do_housekeeping(&self) {
         self.clean_up_dead_streams();
         self.add_new_streams();
+        self.inner
+            .lock()
+            .expect("Stream handler pool is poisoned")
+            .udp_sockets
+            .close_idle(Instant::now());
     }
 
     fn clean_up_dead_streams(&self) {
         let mut inner = self.inner.lock().expect("Stream handler pool is poisoned");
         loop {
             match self.stream_killer_rx.try_recv() {
-                Ok(stream_key) => match inner.stream_writer_channels.remove(&stream_key) {
-                    Some(writer_channel) => inner.logger.debug(format!(
-                        "Killed StreamWriter to {}",
-                        writer_channel.peer_addr()
-                    )),
-                    None => inner.logger.debug(format!(
-                        "Tried to kill StreamWriter for key {:?}, but it was not found",
-                        stream_key
-                    )),
-                },
+                Ok(stream_key) => {
+                    inner.stream_reader_killers.remove(&stream_key);
+                    match inner.stream_writer_channels.remove(&stream_key) {
+                        Some(writer_channel) => inner.logger.debug(format!(
+                            "Killed StreamWriter to {}",
+                            writer_channel.peer_addr()
+                        )),
+                        None => inner.logger.debug(format!(
+                            "Tried to kill StreamWriter for key {:?}, but it was not found",
+                            stream_key
+                        )),
+                    }
+                }
                 Err(_) => break,
             };
         }
@@ -319,6 +549,11 @@ pub trait StreamHandlerPoolFactory {
         cryptde: &'static dyn CryptDE,
         accountant_sub: Recipient<Syn, ReportExitServiceProvidedMessage>,
         proxy_client_sub: Recipient<Syn, InboundServerData>,
+        throughput_limiters: ExitThroughputLimiters,
+        connect_timeouts: ExitConnectTimeouts,
+        max_report_delay: Duration,
+        rate_card: RateCard,
+        exit_policy: ExitPolicy,
     ) -> Box<dyn StreamHandlerPool>;
 }
 
@@ -331,22 +566,37 @@ impl StreamHandlerPoolFactory for StreamHandlerPoolFactoryReal {
         cryptde: &'static dyn CryptDE,
         accountant_sub: Recipient<Syn, ReportExitServiceProvidedMessage>,
         proxy_client_sub: Recipient<Syn, InboundServerData>,
+        throughput_limiters: ExitThroughputLimiters,
+        connect_timeouts: ExitConnectTimeouts,
+        max_report_delay: Duration,
+        rate_card: RateCard,
+        exit_policy: ExitPolicy,
     ) -> Box<dyn StreamHandlerPool> {
         Box::new(StreamHandlerPoolReal::new(
             resolver,
             cryptde,
             accountant_sub,
             proxy_client_sub,
+            throughput_limiters,
+            connect_timeouts,
+            max_report_delay,
+            rate_card,
+            exit_policy,
         ))
     }
 }
 
+fn millis(duration: Duration) -> u64 {
+    duration.as_secs() * 1000 + u64::from(duration.subsec_millis())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::proxy_client::local_test_utils::make_send_error;
     use crate::proxy_client::local_test_utils::ResolverWrapperMock;
     use crate::proxy_client::stream_establisher::StreamEstablisher;
+    use crate::sub_lib::accountant::DEFAULT_MAX_REPORT_DELAY;
     use crate::sub_lib::channel_wrappers::FuturesChannelFactoryReal;
     use crate::sub_lib::channel_wrappers::SenderWrapperReal;
     use crate::sub_lib::cryptde::encodex;
@@ -362,6 +612,7 @@ mod tests {
     use crate::test_utils::logging::TestLogHandler;
     use crate::test_utils::recorder::make_recorder;
     use crate::test_utils::recorder::peer_actors_builder;
+    use crate::test_utils::recorder::Recorder;
     use crate::test_utils::stream_connector_mock::StreamConnectorMock;
     use crate::test_utils::test_utils::await_messages;
     use crate::test_utils::test_utils::cryptde;
@@ -383,6 +634,7 @@ mod tests {
     use std::io::ErrorKind;
     use std::net::IpAddr;
     use std::net::SocketAddr;
+    use std::net::UdpSocket;
     use std::ops::Deref;
     use std::str::FromStr;
     use std::sync::Arc;
@@ -460,6 +712,11 @@ mod tests {
             target_port: 80,
             protocol: ProxyProtocol::HTTP,
             originator_public_key: PublicKey::new(&b"men's souls"[..]),
+            tls_at_exit: false,
+            upload_window: None,
+            udp: false,
+            dns_server: None,
+            cancel: false,
         };
         let mut tx_to_write = Box::new(SenderWrapperMock::new(
             SocketAddr::from_str("1.2.3.4:5678").unwrap(),
@@ -482,6 +739,11 @@ mod tests {
                 cryptde,
                 peer_actors.accountant.report_exit_service_provided.clone(),
                 peer_actors.proxy_client.inbound_server_data.clone(),
+                ExitThroughputLimiters::unlimited(),
+                ExitConnectTimeouts::default(),
+                DEFAULT_MAX_REPORT_DELAY,
+                RateCard::default(),
+                ExitPolicy::default(),
             );
             subject
                 .inner
@@ -507,6 +769,166 @@ mod tests {
         );
     }
 
+    #[test]
+    fn written_request_is_reported_to_the_accountant() {
+        let cryptde = cryptde();
+        let stream_key = make_meaningless_stream_key();
+        let client_request_payload = ClientRequestPayload {
+            stream_key: stream_key.clone(),
+            sequenced_packet: SequencedPacket {
+                data: b"These are the times".to_vec(),
+                sequence_number: 0,
+                last_data: false,
+            },
+            target_hostname: None,
+            target_port: 80,
+            protocol: ProxyProtocol::HTTP,
+            originator_public_key: PublicKey::new(&b"men's souls"[..]),
+            tls_at_exit: false,
+            upload_window: None,
+            udp: false,
+            dns_server: None,
+            cancel: false,
+        };
+        let mut tx_to_write = Box::new(SenderWrapperMock::new(
+            SocketAddr::from_str("1.2.3.4:5678").unwrap(),
+        ));
+        tx_to_write.unbounded_send_results = RefCell::new(vec![Ok(())]);
+        let package = ExpiredCoresPackage::new(
+            IpAddr::from_str("1.2.3.4").unwrap(),
+            Some(Wallet::new("consuming")),
+            make_meaningless_route(),
+            encodex(cryptde, &cryptde.public_key(), &client_request_payload).unwrap(),
+        );
+        let (accountant, accountant_awaiter, accountant_recording_arc) = make_recorder();
+
+        thread::spawn(move || {
+            let system = System::new("written_request_is_reported_to_the_accountant");
+
+            let peer_actors = peer_actors_builder().accountant(accountant).build();
+            let subject = StreamHandlerPoolReal::new(
+                Box::new(ResolverWrapperMock::new()),
+                cryptde,
+                peer_actors.accountant.report_exit_service_provided.clone(),
+                peer_actors.proxy_client.inbound_server_data.clone(),
+                ExitThroughputLimiters::unlimited(),
+                ExitConnectTimeouts::default(),
+                DEFAULT_MAX_REPORT_DELAY,
+                RateCard {
+                    routing_service_rate: 10,
+                    routing_byte_rate: 20,
+                    exit_service_rate: 30,
+                    exit_byte_rate: 40,
+                },
+                ExitPolicy::default(),
+            );
+            subject
+                .inner
+                .lock()
+                .unwrap()
+                .stream_writer_channels
+                .insert(stream_key, tx_to_write);
+
+            let test_actor = TestActor::new(subject);
+            let addr: Addr<Syn, TestActor> = test_actor.start();
+            let test_trigger: Recipient<Syn, TriggerSubject> =
+                addr.clone().recipient::<TriggerSubject>();
+            test_trigger.try_send(TriggerSubject { package }).is_ok();
+
+            system.run();
+        });
+
+        accountant_awaiter.await_message_count(1);
+        let accountant_recording = accountant_recording_arc.lock().unwrap();
+        assert_eq!(
+            accountant_recording.get_record::<ReportExitServiceProvidedMessage>(0),
+            &ReportExitServiceProvidedMessage {
+                consuming_wallet: Wallet::new("consuming"),
+                payload_size: 19,
+                service_rate: 30,
+                byte_rate: 40,
+                correlation_id_opt: Some(CorrelationId::new(stream_key, SystemTime::now())),
+            }
+        );
+    }
+
+    #[test]
+    fn request_written_too_long_after_it_arrived_is_not_charged_for() {
+        init_test_logging();
+        let cryptde = cryptde();
+        let stream_key = make_meaningless_stream_key();
+        let client_request_payload = ClientRequestPayload {
+            stream_key: stream_key.clone(),
+            sequenced_packet: SequencedPacket {
+                data: b"These are the times".to_vec(),
+                sequence_number: 0,
+                last_data: false,
+            },
+            target_hostname: None,
+            target_port: 80,
+            protocol: ProxyProtocol::HTTP,
+            originator_public_key: PublicKey::new(&b"men's souls"[..]),
+            tls_at_exit: false,
+            upload_window: None,
+            udp: false,
+            dns_server: None,
+            cancel: false,
+        };
+        let mut tx_to_write = Box::new(SenderWrapperMock::new(
+            SocketAddr::from_str("1.2.3.4:5678").unwrap(),
+        ));
+        tx_to_write.unbounded_send_results = RefCell::new(vec![Ok(())]);
+        let write_parameters = tx_to_write.unbounded_send_params.clone();
+        let package = ExpiredCoresPackage::new(
+            IpAddr::from_str("1.2.3.4").unwrap(),
+            Some(Wallet::new("consuming")),
+            make_meaningless_route(),
+            encodex(cryptde, &cryptde.public_key(), &client_request_payload).unwrap(),
+        );
+        let (accountant, _, accountant_recording_arc) = make_recorder();
+
+        thread::spawn(move || {
+            let system =
+                System::new("request_written_too_long_after_it_arrived_is_not_charged_for");
+
+            let peer_actors = peer_actors_builder().accountant(accountant).build();
+            let subject = StreamHandlerPoolReal::new(
+                Box::new(ResolverWrapperMock::new()),
+                cryptde,
+                peer_actors.accountant.report_exit_service_provided.clone(),
+                peer_actors.proxy_client.inbound_server_data.clone(),
+                ExitThroughputLimiters::unlimited(),
+                ExitConnectTimeouts::default(),
+                Duration::from_secs(0),
+                RateCard::default(),
+                ExitPolicy::default(),
+            );
+            subject
+                .inner
+                .lock()
+                .unwrap()
+                .stream_writer_channels
+                .insert(stream_key, tx_to_write);
+
+            let test_actor = TestActor::new(subject);
+            let addr: Addr<Syn, TestActor> = test_actor.start();
+            let test_trigger: Recipient<Syn, TriggerSubject> =
+                addr.clone().recipient::<TriggerSubject>();
+            test_trigger.try_send(TriggerSubject { package }).is_ok();
+
+            system.run();
+        });
+
+        await_messages(1, &write_parameters);
+        TestLogHandler::new().await_log_containing(
+            "WARN: Proxy Client: Not charging for 19-byte request written",
+            1000,
+        );
+        TestLogHandler::new()
+            .exists_log_containing("ms after it arrived: more than the 0ms allowed");
+        assert_eq!(accountant_recording_arc.lock().unwrap().len(), 0);
+    }
+
     #[test]
     fn write_failure_for_nonexistent_stream_generates_termination_message() {
         init_test_logging();
@@ -525,6 +947,11 @@ mod tests {
                 target_port: 80,
                 protocol: ProxyProtocol::HTTP,
                 originator_public_key: originator_key,
+                tls_at_exit: false,
+                upload_window: None,
+                udp: false,
+                dns_server: None,
+                cancel: false,
             };
             let package = ExpiredCoresPackage::new(
                 IpAddr::from_str("1.2.3.4").unwrap(),
@@ -547,6 +974,11 @@ mod tests {
                 cryptde,
                 peer_actors.accountant.report_exit_service_provided.clone(),
                 peer_actors.proxy_client.inbound_server_data.clone(),
+                ExitThroughputLimiters::unlimited(),
+                ExitConnectTimeouts::default(),
+                DEFAULT_MAX_REPORT_DELAY,
+                RateCard::default(),
+                ExitPolicy::default(),
             );
             subject
                 .inner
@@ -572,7 +1004,8 @@ mod tests {
                 last_data: true,
                 sequence_number: 0,
                 source: SocketAddr::from_str("2.3.4.5:80").unwrap(),
-                data: vec![]
+                data: vec![],
+                truncated: false,
             }
         );
     }
@@ -597,6 +1030,11 @@ mod tests {
                 target_port: 80,
                 protocol: ProxyProtocol::HTTP,
                 originator_public_key: originator_key,
+                tls_at_exit: false,
+                upload_window: None,
+                udp: false,
+                dns_server: None,
+                cancel: false,
             };
             let package = ExpiredCoresPackage::new(
                 IpAddr::from_str("1.2.3.4").unwrap(),
@@ -611,6 +1049,11 @@ mod tests {
                 cryptde,
                 peer_actors.accountant.report_exit_service_provided.clone(),
                 peer_actors.proxy_client.inbound_server_data.clone(),
+                ExitThroughputLimiters::unlimited(),
+                ExitConnectTimeouts::default(),
+                DEFAULT_MAX_REPORT_DELAY,
+                RateCard::default(),
+                ExitPolicy::default(),
             );
 
             let test_actor = TestActor::new(subject);
@@ -631,7 +1074,8 @@ mod tests {
                 last_data: true,
                 sequence_number: 0,
                 source: error_socket_addr(),
-                data: vec![]
+                data: vec![],
+                truncated: false,
             }
         );
         TestLogHandler::new().exists_log_containing(
@@ -665,6 +1109,11 @@ mod tests {
                 target_port: 80,
                 protocol: ProxyProtocol::HTTP,
                 originator_public_key: PublicKey::new(&b"men's souls"[..]),
+                tls_at_exit: false,
+                upload_window: None,
+                udp: false,
+                dns_server: None,
+                cancel: false,
             };
             let package = ExpiredCoresPackage::new(
                 IpAddr::from_str("1.2.3.4").unwrap(),
@@ -695,6 +1144,11 @@ mod tests {
                 cryptde,
                 peer_actors.accountant.report_exit_service_provided.clone(),
                 peer_actors.proxy_client.inbound_server_data.clone(),
+                ExitThroughputLimiters::unlimited(),
+                ExitConnectTimeouts::default(),
+                DEFAULT_MAX_REPORT_DELAY,
+                RateCard::default(),
+                ExitPolicy::default(),
             );
             let (stream_killer_tx, stream_killer_rx) = mpsc::channel();
             subject.stream_killer_rx = stream_killer_rx;
@@ -712,8 +1166,11 @@ mod tests {
                         writer,
                     )),
                     proxy_client_sub: inner.proxy_client_sub.clone(),
+                    throughput_limiters: ExitThroughputLimiters::unlimited(),
+                    connect_timeouts: ExitConnectTimeouts::default(),
                     logger: inner.logger.clone(),
                     channel_factory: Box::new(FuturesChannelFactoryReal {}),
+                    exit_policy: ExitPolicy::default(),
                 };
 
                 inner.establisher_factory = Box::new(StreamEstablisherFactoryMock {
@@ -747,7 +1204,8 @@ mod tests {
                 last_data: false,
                 sequence_number: 0,
                 source: SocketAddr::from_str("3.4.5.6:80").unwrap(),
-                data: b"HTTP/1.1 200 OK\r\n\r\n".to_vec()
+                data: b"HTTP/1.1 200 OK\r\n\r\n".to_vec(),
+                truncated: false,
             }
         );
     }
@@ -773,6 +1231,11 @@ mod tests {
                 target_port: 80,
                 protocol: ProxyProtocol::HTTP,
                 originator_public_key: originator_key,
+                tls_at_exit: false,
+                upload_window: None,
+                udp: false,
+                dns_server: None,
+                cancel: false,
             };
             let package = ExpiredCoresPackage::new(
                 IpAddr::from_str("1.2.3.4").unwrap(),
@@ -792,6 +1255,11 @@ mod tests {
                 cryptde,
                 peer_actors.accountant.report_exit_service_provided.clone(),
                 proxy_client_sub.clone(),
+                ExitThroughputLimiters::unlimited(),
+                ExitConnectTimeouts::default(),
+                DEFAULT_MAX_REPORT_DELAY,
+                RateCard::default(),
+                ExitPolicy::default(),
             );
             let (stream_killer_tx, stream_killer_rx) = mpsc::channel();
             subject.stream_killer_rx = stream_killer_rx;
@@ -805,8 +1273,11 @@ mod tests {
                         .connect_pair_result(Err(Error::from(ErrorKind::Other))),
                 ),
                 proxy_client_sub,
+                throughput_limiters: ExitThroughputLimiters::unlimited(),
+                connect_timeouts: ExitConnectTimeouts::default(),
                 logger: subject.inner.lock().unwrap().logger.clone(),
                 channel_factory: Box::new(FuturesChannelFactoryReal {}),
+                exit_policy: ExitPolicy::default(),
             };
 
             subject.inner.lock().unwrap().establisher_factory =
@@ -832,7 +1303,8 @@ mod tests {
                 last_data: true,
                 sequence_number: 0,
                 source: error_socket_addr(),
-                data: vec![]
+                data: vec![],
+                truncated: false,
             }
         );
     }
@@ -861,6 +1333,11 @@ mod tests {
                 target_port: 80,
                 protocol: ProxyProtocol::HTTP,
                 originator_public_key: originator_key,
+                tls_at_exit: false,
+                upload_window: None,
+                udp: false,
+                dns_server: None,
+                cancel: false,
             };
             let package = ExpiredCoresPackage::new(
                 IpAddr::from_str("1.2.3.4").unwrap(),
@@ -891,6 +1368,11 @@ mod tests {
                 cryptde,
                 peer_actors.accountant.report_exit_service_provided.clone(),
                 peer_actors.proxy_client.inbound_server_data.clone(),
+                ExitThroughputLimiters::unlimited(),
+                ExitConnectTimeouts::default(),
+                DEFAULT_MAX_REPORT_DELAY,
+                RateCard::default(),
+                ExitPolicy::default(),
             );
             let disconnected_sender = Box::new(SenderWrapperMock {
                 peer_addr,
@@ -913,6 +1395,8 @@ mod tests {
                         writer,
                     )),
                     proxy_client_sub: inner.proxy_client_sub.clone(),
+                    throughput_limiters: ExitThroughputLimiters::unlimited(),
+                    connect_timeouts: ExitConnectTimeouts::default(),
                     logger: inner.logger.clone(),
                     channel_factory: Box::new(FuturesChannelFactoryMock {
                         results: vec![(
@@ -922,6 +1406,7 @@ mod tests {
                             }),
                         )],
                     }),
+                    exit_policy: ExitPolicy::default(),
                 };
 
                 inner.establisher_factory = Box::new(StreamEstablisherFactoryMock {
@@ -946,7 +1431,8 @@ mod tests {
                 last_data: true,
                 sequence_number: 0,
                 source: error_socket_addr(),
-                data: vec![]
+                data: vec![],
+                truncated: false,
             }
         );
     }
@@ -970,6 +1456,11 @@ mod tests {
                 target_port: 80,
                 protocol: ProxyProtocol::HTTP,
                 originator_public_key: originator_key,
+                tls_at_exit: false,
+                upload_window: None,
+                udp: false,
+                dns_server: None,
+                cancel: false,
             };
             let package = ExpiredCoresPackage::new(
                 IpAddr::from_str("1.2.3.4").unwrap(),
@@ -988,6 +1479,11 @@ mod tests {
                 cryptde,
                 peer_actors.accountant.report_exit_service_provided.clone(),
                 peer_actors.proxy_client.inbound_server_data.clone(),
+                ExitThroughputLimiters::unlimited(),
+                ExitConnectTimeouts::default(),
+                DEFAULT_MAX_REPORT_DELAY,
+                RateCard::default(),
+                ExitPolicy::default(),
             );
             let test_actor = TestActor::new(subject);
             let addr: Addr<Syn, TestActor> = test_actor.start();
@@ -1010,11 +1506,216 @@ mod tests {
                 last_data: true,
                 sequence_number: 0,
                 source: error_socket_addr(),
-                data: vec![]
+                data: vec![],
+                truncated: false,
             }
         );
     }
 
+    #[test]
+    fn hostname_is_looked_up_with_the_dns_server_the_originator_asks_for() {
+        let cryptde = cryptde();
+        let stream_key = make_meaningless_stream_key();
+        let (proxy_client, proxy_client_awaiter, _) = make_recorder();
+        let default_lookup_ip_parameters = Arc::new(Mutex::new(vec![]));
+        let offered_lookup_ip_parameters = Arc::new(Mutex::new(vec![]));
+        let expected_default_lookup_ip_parameters = default_lookup_ip_parameters.clone();
+        let expected_offered_lookup_ip_parameters = offered_lookup_ip_parameters.clone();
+        thread::spawn(move || {
+            let client_request_payload = ClientRequestPayload {
+                stream_key,
+                sequenced_packet: SequencedPacket {
+                    data: b"These are the times".to_vec(),
+                    sequence_number: 0,
+                    last_data: true,
+                },
+                target_hostname: Some(String::from("that.try")),
+                target_port: 80,
+                protocol: ProxyProtocol::HTTP,
+                originator_public_key: PublicKey::new(&b"men's souls"[..]),
+                tls_at_exit: false,
+                upload_window: None,
+                udp: false,
+                dns_server: Some(IpAddr::from_str("9.9.9.9").unwrap()),
+                cancel: false,
+            };
+            let package = ExpiredCoresPackage::new(
+                IpAddr::from_str("1.2.3.4").unwrap(),
+                Some(Wallet::new("consuming")),
+                make_meaningless_route(),
+                encodex(cryptde, &cryptde.public_key(), &client_request_payload).unwrap(),
+            );
+            let system = System::new("test");
+            let peer_actors = peer_actors_builder().proxy_client(proxy_client).build();
+            let default_resolver =
+                ResolverWrapperMock::new().lookup_ip_parameters(&default_lookup_ip_parameters);
+            let offered_resolver = ResolverWrapperMock::new()
+                .lookup_ip_parameters(&offered_lookup_ip_parameters)
+                .lookup_ip_failure(ResolveError::from(ResolveErrorKind::Io));
+            let mut subject = StreamHandlerPoolReal::new(
+                Box::new(default_resolver),
+                cryptde,
+                peer_actors.accountant.report_exit_service_provided.clone(),
+                peer_actors.proxy_client.inbound_server_data.clone(),
+                ExitThroughputLimiters::unlimited(),
+                ExitConnectTimeouts::default(),
+                DEFAULT_MAX_REPORT_DELAY,
+                RateCard::default(),
+                ExitPolicy::default(),
+            );
+            subject.offer_dns_server(
+                IpAddr::from_str("9.9.9.9").unwrap(),
+                Box::new(offered_resolver),
+            );
+            let test_actor = TestActor::new(subject);
+            let addr: Addr<Syn, TestActor> = test_actor.start();
+            let test_trigger: Recipient<Syn, TriggerSubject> =
+                addr.clone().recipient::<TriggerSubject>();
+            test_trigger.try_send(TriggerSubject { package }).is_ok();
+
+            system.run();
+        });
+
+        proxy_client_awaiter.await_message_count(1);
+        assert_eq!(
+            expected_offered_lookup_ip_parameters
+                .lock()
+                .unwrap()
+                .deref(),
+            &vec![Some(String::from("that.try."))]
+        );
+        assert_eq!(
+            expected_default_lookup_ip_parameters
+                .lock()
+                .unwrap()
+                .is_empty(),
+            true
+        );
+    }
+
+    fn make_datagram_package(target_port: u16) -> ExpiredCoresPackage {
+        let cryptde = cryptde();
+        let client_request_payload = ClientRequestPayload {
+            stream_key: make_meaningless_stream_key(),
+            sequenced_packet: SequencedPacket {
+                data: b"question".to_vec(),
+                sequence_number: 0,
+                last_data: false,
+            },
+            target_hostname: Some(String::from("that.try")),
+            target_port,
+            protocol: ProxyProtocol::HTTP,
+            originator_public_key: PublicKey::new(&b"men's souls"[..]),
+            tls_at_exit: false,
+            upload_window: None,
+            udp: true,
+            dns_server: None,
+            cancel: false,
+        };
+        ExpiredCoresPackage::new(
+            IpAddr::from_str("1.2.3.4").unwrap(),
+            Some(Wallet::new("consuming")),
+            make_meaningless_route(),
+            encodex(cryptde, &cryptde.public_key(), &client_request_payload).unwrap(),
+        )
+    }
+
+    fn start_datagram_subject(
+        package: ExpiredCoresPackage,
+        exit_policy: ExitPolicy,
+        proxy_client: Recorder,
+    ) {
+        thread::spawn(move || {
+            let system = System::new("test");
+            let peer_actors = peer_actors_builder().proxy_client(proxy_client).build();
+            let resolver = ResolverWrapperMock::new()
+                .lookup_ip_success(vec![IpAddr::from_str("127.0.0.1").unwrap()]);
+            let subject = StreamHandlerPoolReal::new(
+                Box::new(resolver),
+                cryptde(),
+                peer_actors.accountant.report_exit_service_provided.clone(),
+                peer_actors.proxy_client.inbound_server_data.clone(),
+                ExitThroughputLimiters::unlimited(),
+                ExitConnectTimeouts::default(),
+                DEFAULT_MAX_REPORT_DELAY,
+                RateCard::default(),
+                exit_policy,
+            );
+            let test_actor = TestActor::new(subject);
+            let addr: Addr<Syn, TestActor> = test_actor.start();
+            let test_trigger: Recipient<Syn, TriggerSubject> =
+                addr.clone().recipient::<TriggerSubject>();
+            test_trigger.try_send(TriggerSubject { package }).is_ok();
+
+            system.run();
+        });
+    }
+
+    #[test]
+    fn datagram_goes_out_over_udp_and_the_answer_comes_back() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        server
+            .set_read_timeout(Some(Duration::from_millis(1000)))
+            .unwrap();
+        let server_addr = server.local_addr().unwrap();
+        let (proxy_client, proxy_client_awaiter, proxy_client_recording_arc) = make_recorder();
+
+        start_datagram_subject(
+            make_datagram_package(server_addr.port()),
+            ExitPolicy::default(),
+            proxy_client,
+        );
+
+        let mut buf = [0u8; 100];
+        let (len, client_addr) = server.recv_from(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"question");
+        server.send_to(b"answer", client_addr).unwrap();
+        proxy_client_awaiter.await_message_count(1);
+        let recording = proxy_client_recording_arc.lock().unwrap();
+        assert_eq!(
+            recording.get_record::<InboundServerData>(0),
+            &InboundServerData {
+                stream_key: make_meaningless_stream_key(),
+                last_data: false,
+                sequence_number: 0,
+                source: server_addr,
+                data: b"answer".to_vec(),
+                truncated: false,
+            }
+        );
+    }
+
+    #[test]
+    fn datagram_to_an_address_the_exit_policy_blocks_gets_a_termination_message() {
+        init_test_logging();
+        let (proxy_client, proxy_client_awaiter, proxy_client_recording_arc) = make_recorder();
+        let exit_policy = ExitPolicy {
+            blocked_ports: vec![],
+            blocked_hostnames: vec![],
+            blocked_ip_addrs: vec![IpAddr::from_str("127.0.0.1").unwrap()],
+            offered_dns_servers: vec![],
+        };
+
+        start_datagram_subject(make_datagram_package(53), exit_policy, proxy_client);
+
+        proxy_client_awaiter.await_message_count(1);
+        let recording = proxy_client_recording_arc.lock().unwrap();
+        assert_eq!(
+            recording.get_record::<InboundServerData>(0),
+            &InboundServerData {
+                stream_key: make_meaningless_stream_key(),
+                last_data: true,
+                sequence_number: 0,
+                source: error_socket_addr(),
+                data: vec![],
+                truncated: false,
+            }
+        );
+        TestLogHandler::new().exists_log_containing(
+            "ERROR: Proxy Client: Couldn't process request from CORES package: No address for Some(\"that.try\") that the exit policy allows",
+        );
+    }
+
     #[test]
     #[ignore] // TODO: Play SC-696 card to re-write this test -- it is flaky
     fn after_writing_last_data_the_stream_should_close() {
@@ -1035,6 +1736,11 @@ mod tests {
             target_port: 80,
             protocol: ProxyProtocol::HTTP,
             originator_public_key: PublicKey::new(&b"men's souls"[..]),
+            tls_at_exit: false,
+            upload_window: None,
+            udp: false,
+            dns_server: None,
+            cancel: false,
         };
         let package = ExpiredCoresPackage::new(
             IpAddr::from_str("1.2.3.4").unwrap(),
@@ -1052,6 +1758,11 @@ mod tests {
                 cryptde,
                 peer_actors.accountant.report_exit_service_provided.clone(),
                 peer_actors.proxy_client.inbound_server_data.clone(),
+                ExitThroughputLimiters::unlimited(),
+                ExitConnectTimeouts::default(),
+                DEFAULT_MAX_REPORT_DELAY,
+                RateCard::default(),
+                ExitPolicy::default(),
             );
             subject.inner.lock().unwrap().stream_writer_channels.insert(
                 stream_key,
@@ -1091,6 +1802,95 @@ mod tests {
         assert_eq!(rx_to_write_result, Ok(Async::Ready(None))); // Ok(Async::Ready(None)) indicates that all TXs to the channel are gone
     }
 
+    #[test]
+    fn cancel_closes_the_stream_writer_and_stops_the_stream_reader() {
+        init_test_logging();
+        let cryptde = cryptde();
+        let stream_key = make_meaningless_stream_key();
+        let (proxy_client, _, _) = make_recorder();
+        let (accountant, _, accountant_recording_arc) = make_recorder();
+        let (tx_to_write, mut rx_to_write) = unbounded();
+        let (reader_killer, mut killed) = oneshot::channel();
+        let client_request_payload = ClientRequestPayload {
+            stream_key: stream_key.clone(),
+            sequenced_packet: SequencedPacket {
+                data: vec![],
+                sequence_number: 3,
+                last_data: true,
+            },
+            target_hostname: Some(String::from("that.try")),
+            target_port: 80,
+            protocol: ProxyProtocol::HTTP,
+            originator_public_key: PublicKey::new(&b"men's souls"[..]),
+            tls_at_exit: false,
+            upload_window: None,
+            udp: false,
+            dns_server: None,
+            cancel: true,
+        };
+        let package = ExpiredCoresPackage::new(
+            IpAddr::from_str("1.2.3.4").unwrap(),
+            Some(Wallet::new("consuming")),
+            make_meaningless_route(),
+            encodex(cryptde, &cryptde.public_key(), &client_request_payload).unwrap(),
+        );
+        thread::spawn(move || {
+            let system = System::new("test");
+            let peer_actors = peer_actors_builder()
+                .accountant(accountant)
+                .proxy_client(proxy_client)
+                .build();
+            let resolver = ResolverWrapperMock::new();
+            let subject = StreamHandlerPoolReal::new(
+                Box::new(resolver),
+                cryptde,
+                peer_actors.accountant.report_exit_service_provided.clone(),
+                peer_actors.proxy_client.inbound_server_data.clone(),
+                ExitThroughputLimiters::unlimited(),
+                ExitConnectTimeouts::default(),
+                DEFAULT_MAX_REPORT_DELAY,
+                RateCard::default(),
+                ExitPolicy::default(),
+            );
+            {
+                let mut inner = subject.inner.lock().unwrap();
+                inner.stream_writer_channels.insert(
+                    stream_key,
+                    Box::new(SenderWrapperReal::new(
+                        SocketAddr::from_str("1.2.3.4:5678").unwrap(),
+                        tx_to_write,
+                    )),
+                );
+                inner
+                    .stream_reader_killers
+                    .insert(stream_key, reader_killer);
+            }
+
+            let test_actor = TestActor::new(subject);
+            let addr: Addr<Syn, TestActor> = test_actor.start();
+            let test_trigger: Recipient<Syn, TriggerSubject> =
+                addr.clone().recipient::<TriggerSubject>();
+            test_trigger.try_send(TriggerSubject { package }).is_ok();
+
+            system.run();
+        });
+
+        TestLogHandler::new().await_log_containing(
+            &format!("Closing stream {:?} canceled by originator", stream_key),
+            1000,
+        );
+        let (tx, rx) = mpsc::channel();
+        tokio::run(lazy(move || {
+            tx.send((rx_to_write.poll(), killed.poll())).unwrap();
+            Ok(())
+        }));
+        assert_eq!(
+            rx.recv().unwrap(),
+            (Ok(Async::Ready(None)), Ok(Async::Ready(())))
+        );
+        assert_eq!(accountant_recording_arc.lock().unwrap().len(), 0);
+    }
+
     #[test]
     fn error_from_tx_to_writer_removes_stream() {
         init_test_logging();
@@ -1111,6 +1911,11 @@ mod tests {
             target_port: 80,
             protocol: ProxyProtocol::HTTP,
             originator_public_key: PublicKey::new(&b"men's souls"[..]),
+            tls_at_exit: false,
+            upload_window: None,
+            udp: false,
+            dns_server: None,
+            cancel: false,
         };
         let package = ExpiredCoresPackage::new(
             IpAddr::from_str("1.2.3.4").unwrap(),
@@ -1137,6 +1942,11 @@ mod tests {
                 cryptde,
                 peer_actors.accountant.report_exit_service_provided.clone(),
                 peer_actors.proxy_client.inbound_server_data.clone(),
+                ExitThroughputLimiters::unlimited(),
+                ExitConnectTimeouts::default(),
+                DEFAULT_MAX_REPORT_DELAY,
+                RateCard::default(),
+                ExitPolicy::default(),
             );
             subject
                 .inner
@@ -1187,6 +1997,11 @@ mod tests {
                 target_port: 80,
                 protocol: ProxyProtocol::HTTP,
                 originator_public_key: PublicKey::new(&b"men's souls"[..]),
+                tls_at_exit: false,
+                upload_window: None,
+                udp: false,
+                dns_server: None,
+                cancel: false,
             };
             let package = ExpiredCoresPackage::new(
                 IpAddr::from_str("1.2.3.4").unwrap(),
@@ -1200,6 +2015,11 @@ mod tests {
                 cryptde,
                 peer_actors.accountant.report_exit_service_provided.clone(),
                 peer_actors.proxy_client.inbound_server_data.clone(),
+                ExitThroughputLimiters::unlimited(),
+                ExitConnectTimeouts::default(),
+                DEFAULT_MAX_REPORT_DELAY,
+                RateCard::default(),
+                ExitPolicy::default(),
             );
 
             subject.inner.lock().unwrap().establisher_factory =
@@ -1,7 +1,9 @@
 // Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use crate::proxy_client::throughput_limiter::ThroughputLimiter;
 use crate::sub_lib::framer::Framer;
 use crate::sub_lib::logger::Logger;
 use crate::sub_lib::proxy_client::InboundServerData;
+use crate::sub_lib::proxy_client::MAX_RESPONSE_CHUNK_SIZE;
 use crate::sub_lib::sequencer::Sequencer;
 use crate::sub_lib::stream_key::StreamKey;
 use crate::sub_lib::tokio_wrappers::ReadHalfWrapper;
@@ -9,6 +11,7 @@ use crate::sub_lib::utils::indicates_dead_stream;
 use crate::sub_lib::utils::to_string;
 use actix::Recipient;
 use actix::Syn;
+use std::cmp::min;
 use std::net::SocketAddr;
 use std::sync::mpsc::Sender;
 use tokio::prelude::Async;
@@ -23,6 +26,7 @@ pub struct StreamReader {
     framer: Box<dyn Framer>,
     logger: Logger,
     sequencer: Sequencer,
+    throughput_limiter: ThroughputLimiter,
 }
 
 impl Future for StreamReader {
@@ -30,9 +34,13 @@ impl Future for StreamReader {
     type Error = ();
 
     fn poll(&mut self) -> Result<Async<<Self as Future>::Item>, <Self as Future>::Error> {
-        let mut buf: [u8; 16384] = [0; 16384];
+        let mut buf: [u8; MAX_RESPONSE_CHUNK_SIZE] = [0; MAX_RESPONSE_CHUNK_SIZE];
         loop {
-            match self.stream.poll_read(&mut buf) {
+            let allowance = match self.throughput_limiter.poll_allowance() {
+                Async::Ready(allowance) => min(allowance, buf.len()),
+                Async::NotReady => return Ok(Async::NotReady),
+            };
+            match self.stream.poll_read(&mut buf[0..allowance]) {
                 Ok(Async::NotReady) => return Ok(Async::NotReady),
                 Ok(Async::Ready(0)) => {
                     // see RETURN VALUE section of recv man page (Unix)
@@ -40,7 +48,7 @@ impl Future for StreamReader {
                         "Stream from {} was closed: (0-byte read)",
                         self.peer_addr
                     ));
-                    self.shutdown();
+                    self.shutdown(false);
                     return Ok(Async::Ready(()));
                 }
                 Ok(Async::Ready(len)) => {
@@ -50,14 +58,16 @@ impl Future for StreamReader {
                         self.peer_addr,
                         to_string(&Vec::from(&buf[0..len]))
                     ));
+                    self.throughput_limiter.consume(len);
                     self.framer.add_data(&buf[0..len]);
                     self.send_frames_loop();
                 }
                 Err(e) => {
                     if indicates_dead_stream(e.kind()) {
+                        // Unlike a 0-byte read, this can happen partway through a response
                         self.logger
                             .debug(format!("Stream from {} was closed: {}", self.peer_addr, e));
-                        self.shutdown();
+                        self.shutdown(true);
                         return Err(());
                     } else {
                         // TODO this could be exploitable and inefficient: if we keep getting non-dead-stream errors, we go into a tight loop and do not return
@@ -80,6 +90,7 @@ impl StreamReader {
         stream_killer: Sender<StreamKey>,
         peer_addr: SocketAddr,
         framer: Box<dyn Framer>,
+        throughput_limiter: ThroughputLimiter,
     ) -> StreamReader {
         StreamReader {
             stream_key,
@@ -90,12 +101,13 @@ impl StreamReader {
             framer,
             logger: Logger::new(&format!("StreamReader for {:?}/{}", stream_key, peer_addr)[..]),
             sequencer: Sequencer::new(),
+            throughput_limiter,
         }
     }
 
-    fn shutdown(&mut self) {
+    fn shutdown(&mut self, truncated: bool) {
         let stream_key = self.stream_key.clone();
-        self.send_inbound_server_data(stream_key, vec![], true);
+        self.send_inbound_server_data(stream_key, vec![], true, truncated);
         self.stream_killer.send(self.stream_key).is_ok();
     }
 
@@ -113,13 +125,9 @@ impl StreamReader {
                         },
                         to_string(&response_chunk.chunk)
                     ));
-                    let stream_key = self.stream_key.clone();
-                    self.send_inbound_server_data(
-                        stream_key.clone(),
-                        response_chunk.chunk,
-                        response_chunk.last_chunk,
-                    );
-                    if response_chunk.last_chunk {
+                    let last_chunk = response_chunk.last_chunk;
+                    self.send_response_chunk(response_chunk.chunk, last_chunk);
+                    if last_chunk {
                         // FIXME no production framer sets this to true...
                         self.stream_killer.send(self.stream_key).is_ok();
                         break;
@@ -130,7 +138,41 @@ impl StreamReader {
         }
     }
 
-    fn send_inbound_server_data(&mut self, stream_key: StreamKey, data: Vec<u8>, last_data: bool) {
+    fn send_response_chunk(&mut self, chunk: Vec<u8>, last_chunk: bool) {
+        let stream_key = self.stream_key.clone();
+        let mut pieces: Vec<Vec<u8>> = chunk
+            .chunks(MAX_RESPONSE_CHUNK_SIZE)
+            .map(|piece| piece.to_vec())
+            .collect();
+        if pieces.is_empty() {
+            pieces.push(vec![]);
+        }
+        if pieces.len() > 1 {
+            self.logger.debug(format!(
+                "Splitting {}-byte response chunk from {} into {} packets",
+                chunk.len(),
+                self.peer_addr,
+                pieces.len()
+            ));
+        }
+        let final_index = pieces.len() - 1;
+        pieces.into_iter().enumerate().for_each(|(index, piece)| {
+            self.send_inbound_server_data(
+                stream_key.clone(),
+                piece,
+                last_chunk && (index == final_index),
+                false,
+            )
+        });
+    }
+
+    fn send_inbound_server_data(
+        &mut self,
+        stream_key: StreamKey,
+        data: Vec<u8>,
+        last_data: bool,
+        truncated: bool,
+    ) {
         self.proxy_client_sub
             .try_send(InboundServerData {
                 stream_key,
@@ -138,6 +180,7 @@ impl StreamReader {
                 sequence_number: self.sequencer.next_sequence_number(),
                 source: self.peer_addr,
                 data,
+                truncated,
             })
             .expect("Proxy Client is dead");
     }
@@ -224,6 +267,7 @@ mod tests {
             framer: Box::new(HttpPacketFramer::new(Box::new(HttpResponseStartFinder {}))),
             logger: Logger::new("test"),
             sequencer: Sequencer::new(),
+            throughput_limiter: ThroughputLimiter::unlimited(),
         };
 
         let _res = subject.poll();
@@ -237,7 +281,8 @@ mod tests {
                 last_data: false,
                 sequence_number: 0,
                 source: SocketAddr::from_str("8.7.4.3:50").unwrap(),
-                data: b"HTTP/1.1 200 OK\r\n\r\n".to_vec()
+                data: b"HTTP/1.1 200 OK\r\n\r\n".to_vec(),
+                truncated: false,
             },
         );
         assert_eq!(
@@ -247,7 +292,8 @@ mod tests {
                 last_data: false,
                 sequence_number: 1,
                 source: SocketAddr::from_str("8.7.4.3:50").unwrap(),
-                data: b"HTTP/1.1 404 File not found\r\n\r\n".to_vec()
+                data: b"HTTP/1.1 404 File not found\r\n\r\n".to_vec(),
+                truncated: false,
             },
         );
         assert_eq!(
@@ -257,7 +303,8 @@ mod tests {
                 last_data: false,
                 sequence_number: 2,
                 source: SocketAddr::from_str("8.7.4.3:50").unwrap(),
-                data: b"HTTP/1.1 503 Server error\r\n\r\n".to_vec()
+                data: b"HTTP/1.1 503 Server error\r\n\r\n".to_vec(),
+                truncated: false,
             },
         );
         assert_eq!(
@@ -267,7 +314,8 @@ mod tests {
                 last_data: true,
                 sequence_number: 3,
                 source: SocketAddr::from_str("8.7.4.3:50").unwrap(),
-                data: vec![]
+                data: vec![],
+                truncated: false,
             },
         );
         let stream_killer_parameters = stream_killer_params.try_recv().unwrap();
@@ -307,6 +355,7 @@ mod tests {
             framer,
             logger,
             sequencer: Sequencer::new(),
+            throughput_limiter: ThroughputLimiter::unlimited(),
         };
 
         let result = subject.poll();
@@ -323,9 +372,221 @@ mod tests {
                 last_data: true,
                 sequence_number: 0,
                 source: SocketAddr::from_str("4.3.6.5:574").unwrap(),
-                data: vec![]
+                data: vec![],
+                truncated: false,
+            }
+        );
+    }
+
+    struct PassThroughFramer {
+        data: Vec<u8>,
+    }
+
+    impl Framer for PassThroughFramer {
+        fn add_data(&mut self, data: &[u8]) {
+            self.data.extend(data);
+        }
+        fn take_frame(&mut self) -> Option<FramedChunk> {
+            if self.data.is_empty() {
+                None
+            } else {
+                Some(FramedChunk {
+                    chunk: self.data.drain(..).collect(),
+                    last_chunk: false,
+                })
+            }
+        }
+    }
+
+    #[test]
+    fn stream_reader_reads_no_more_than_its_throughput_limiter_allows() {
+        let (proxy_client, proxy_client_awaiter, proxy_client_recording_arc) = make_recorder();
+        let stream_key = make_meaningless_stream_key();
+        let mut stream = Box::new(ReadHalfWrapperMock::new());
+        stream.poll_read_results = vec![(b"0123456789ABCDEF".to_vec(), Ok(Async::Ready(10)))];
+        let (stream_killer, _) = mpsc::channel();
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            let system = System::new("test");
+            let peer_actors = peer_actors_builder().proxy_client(proxy_client).build();
+            tx.send(peer_actors.proxy_client.inbound_server_data)
+                .is_ok();
+            system.run();
+        });
+        let proxy_client_sub = rx.recv().unwrap();
+        let mut subject = StreamReader {
+            stream_key,
+            proxy_client_sub,
+            stream,
+            stream_killer,
+            peer_addr: SocketAddr::from_str("4.3.6.5:574").unwrap(),
+            framer: Box::new(PassThroughFramer { data: vec![] }),
+            logger: Logger::new("test"),
+            sequencer: Sequencer::new(),
+            throughput_limiter: ThroughputLimiter::new(Some(10)),
+        };
+
+        let result = subject.poll();
+
+        assert_eq!(result, Ok(Async::NotReady));
+        proxy_client_awaiter.await_message_count(1);
+        let recording = proxy_client_recording_arc.lock().unwrap();
+        assert_eq!(
+            recording.get_record::<InboundServerData>(0),
+            &InboundServerData {
+                stream_key,
+                last_data: false,
+                sequence_number: 0,
+                source: SocketAddr::from_str("4.3.6.5:574").unwrap(),
+                data: b"0123456789".to_vec(),
+                truncated: false,
+            }
+        );
+        assert_eq!(recording.len(), 1);
+    }
+
+    struct CannedFramer {
+        frames: Vec<FramedChunk>,
+    }
+
+    impl Framer for CannedFramer {
+        fn add_data(&mut self, _data: &[u8]) {}
+        fn take_frame(&mut self) -> Option<FramedChunk> {
+            if self.frames.is_empty() {
+                None
+            } else {
+                Some(self.frames.remove(0))
             }
+        }
+    }
+
+    fn run_canned_frames(
+        frames: Vec<FramedChunk>,
+        expected_count: usize,
+    ) -> Vec<InboundServerData> {
+        let (proxy_client, proxy_client_awaiter, proxy_client_recording_arc) = make_recorder();
+        let mut stream = Box::new(ReadHalfWrapperMock::new());
+        stream.poll_read_results = vec![
+            (vec![4], Ok(Async::Ready(1))),
+            (vec![], Ok(Async::NotReady)),
+        ];
+        let (stream_killer, _) = mpsc::channel();
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            let system = System::new("test");
+            let peer_actors = peer_actors_builder().proxy_client(proxy_client).build();
+            tx.send(peer_actors.proxy_client.inbound_server_data)
+                .is_ok();
+            system.run();
+        });
+        let proxy_client_sub = rx.recv().unwrap();
+        let mut subject = StreamReader {
+            stream_key: make_meaningless_stream_key(),
+            proxy_client_sub,
+            stream,
+            stream_killer,
+            peer_addr: SocketAddr::from_str("4.3.6.5:574").unwrap(),
+            framer: Box::new(CannedFramer { frames }),
+            logger: Logger::new("test"),
+            sequencer: Sequencer::new(),
+            throughput_limiter: ThroughputLimiter::unlimited(),
+        };
+
+        let result = subject.poll();
+
+        assert_eq!(result, Ok(Async::NotReady));
+        proxy_client_awaiter.await_message_count(expected_count);
+        let recording = proxy_client_recording_arc.lock().unwrap();
+        assert_eq!(recording.len(), expected_count);
+        (0..expected_count)
+            .map(|index| recording.get_record::<InboundServerData>(index).clone())
+            .collect()
+    }
+
+    #[test]
+    fn chunk_of_exactly_the_maximum_size_goes_in_one_packet() {
+        let chunk = vec![1u8; MAX_RESPONSE_CHUNK_SIZE];
+
+        let result = run_canned_frames(
+            vec![FramedChunk {
+                chunk: chunk.clone(),
+                last_chunk: false,
+            }],
+            1,
+        );
+
+        assert_eq!(result[0].data, chunk);
+        assert_eq!(result[0].sequence_number, 0);
+        assert_eq!(result[0].last_data, false);
+    }
+
+    #[test]
+    fn chunk_one_byte_over_the_maximum_size_goes_in_two_packets() {
+        let mut chunk = vec![1u8; MAX_RESPONSE_CHUNK_SIZE];
+        chunk.push(2);
+
+        let result = run_canned_frames(
+            vec![FramedChunk {
+                chunk,
+                last_chunk: false,
+            }],
+            2,
+        );
+
+        assert_eq!(result[0].data, vec![1u8; MAX_RESPONSE_CHUNK_SIZE]);
+        assert_eq!(result[0].sequence_number, 0);
+        assert_eq!(result[0].last_data, false);
+        assert_eq!(result[1].data, vec![2u8]);
+        assert_eq!(result[1].sequence_number, 1);
+        assert_eq!(result[1].last_data, false);
+    }
+
+    #[test]
+    fn split_chunks_keep_sequence_numbers_continuous_and_mark_only_the_final_piece_last() {
+        let big_chunk: Vec<u8> = (0..((MAX_RESPONSE_CHUNK_SIZE * 2) + 10))
+            .map(|index| (index % 256) as u8)
+            .collect();
+
+        let result = run_canned_frames(
+            vec![
+                FramedChunk {
+                    chunk: b"before".to_vec(),
+                    last_chunk: false,
+                },
+                FramedChunk {
+                    chunk: big_chunk.clone(),
+                    last_chunk: true,
+                },
+            ],
+            4,
+        );
+
+        assert_eq!(
+            result
+                .iter()
+                .map(|isd| isd.sequence_number)
+                .collect::<Vec<u64>>(),
+            vec![0, 1, 2, 3]
+        );
+        assert_eq!(
+            result
+                .iter()
+                .map(|isd| isd.last_data)
+                .collect::<Vec<bool>>(),
+            vec![false, false, false, true]
+        );
+        assert_eq!(
+            result
+                .iter()
+                .map(|isd| isd.data.len())
+                .collect::<Vec<usize>>(),
+            vec![6, MAX_RESPONSE_CHUNK_SIZE, MAX_RESPONSE_CHUNK_SIZE, 10]
         );
+        let reassembled: Vec<u8> = result[1..]
+            .iter()
+            .flat_map(|isd| isd.data.clone())
+            .collect();
+        assert_eq!(reassembled, big_chunk);
     }
 
     #[test]
@@ -369,6 +630,7 @@ mod tests {
             framer: Box::new(HttpPacketFramer::new(Box::new(HttpResponseStartFinder {}))),
             logger: Logger::new("test"),
             sequencer: Sequencer::new(),
+            throughput_limiter: ThroughputLimiter::unlimited(),
         };
 
         let result = subject.poll();
@@ -383,7 +645,8 @@ mod tests {
                 last_data: false,
                 sequence_number: 0,
                 source: SocketAddr::from_str("5.7.9.0:95").unwrap(),
-                data: b"HTTP/1.1 200 OK\r\n\r\n".to_vec()
+                data: b"HTTP/1.1 200 OK\r\n\r\n".to_vec(),
+                truncated: false,
             }
         );
         assert_eq!(
@@ -393,7 +656,8 @@ mod tests {
                 last_data: false,
                 sequence_number: 1,
                 source: SocketAddr::from_str("5.7.9.0:95").unwrap(),
-                data: b"HTTP/1.1 404 File not found\r\n\r\n".to_vec()
+                data: b"HTTP/1.1 404 File not found\r\n\r\n".to_vec(),
+                truncated: false,
             }
         );
         assert_eq!(
@@ -403,7 +667,8 @@ mod tests {
                 last_data: false,
                 sequence_number: 2,
                 source: SocketAddr::from_str("5.7.9.0:95").unwrap(),
-                data: b"HTTP/1.1 503 Server error\r\n\r\n".to_vec()
+                data: b"HTTP/1.1 503 Server error\r\n\r\n".to_vec(),
+                truncated: false,
             }
         );
         assert_eq!(
@@ -413,7 +678,8 @@ mod tests {
                 last_data: true,
                 sequence_number: 3,
                 source: SocketAddr::from_str("5.7.9.0:95").unwrap(),
-                data: vec!()
+                data: vec!(),
+                truncated: true,
             }
         );
 
@@ -454,6 +720,7 @@ mod tests {
             framer: Box::new(HttpPacketFramer::new(Box::new(HttpResponseStartFinder {}))),
             logger: Logger::new("test"),
             sequencer: Sequencer::new(),
+            throughput_limiter: ThroughputLimiter::unlimited(),
         };
 
         let result = subject.poll();
@@ -469,7 +736,8 @@ mod tests {
                 last_data: true,
                 sequence_number: 0,
                 source: SocketAddr::from_str("5.3.4.3:654").unwrap(),
-                data: vec![]
+                data: vec![],
+                truncated: false,
             }
         );
         TestLogHandler::new()
@@ -513,6 +781,7 @@ mod tests {
             framer: Box::new(HttpPacketFramer::new(Box::new(HttpResponseStartFinder {}))),
             logger: Logger::new("test"),
             sequencer: Sequencer::new(),
+            throughput_limiter: ThroughputLimiter::unlimited(),
         };
 
         let result = subject.poll();
@@ -530,7 +799,8 @@ mod tests {
                 last_data: false,
                 sequence_number: 0,
                 source: SocketAddr::from_str("6.5.4.1:8325").unwrap(),
-                data: b"HTTP/1.1 200 OK\r\n\r\n".to_vec()
+                data: b"HTTP/1.1 200 OK\r\n\r\n".to_vec(),
+                truncated: false,
             }
         );
     }
@@ -1,4 +1,5 @@
 // Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use crate::proxy_client::throughput_limiter::ThroughputLimiter;
 use crate::sub_lib::channel_wrappers::ReceiverWrapper;
 use crate::sub_lib::logger::Logger;
 use crate::sub_lib::sequence_buffer::SequenceBuffer;
@@ -6,6 +7,7 @@ use crate::sub_lib::sequence_buffer::SequencedPacket;
 use crate::sub_lib::stream_key::StreamKey;
 use crate::sub_lib::tokio_wrappers::WriteHalfWrapper;
 use crate::sub_lib::utils::indicates_dead_stream;
+use std::cmp::min;
 use std::net::SocketAddr;
 use tokio::prelude::Async;
 use tokio::prelude::Future;
@@ -16,6 +18,7 @@ pub struct StreamWriter {
     sequence_buffer: SequenceBuffer,
     rx_to_write: Box<dyn ReceiverWrapper<SequencedPacket>>,
     shutting_down: bool,
+    throughput_limiter: ThroughputLimiter,
 }
 
 impl Future for StreamWriter {
@@ -44,6 +47,7 @@ impl StreamWriter {
         peer_addr: SocketAddr,
         rx_to_write: Box<dyn ReceiverWrapper<SequencedPacket>>,
         stream_key: StreamKey,
+        throughput_limiter: ThroughputLimiter,
     ) -> StreamWriter {
         let name = format!("StreamWriter for {:?}/{}", stream_key, peer_addr);
         let logger = Logger::new(&name[..]);
@@ -53,6 +57,7 @@ impl StreamWriter {
             sequence_buffer: SequenceBuffer::new(),
             rx_to_write,
             shutting_down: false,
+            throughput_limiter,
         }
     }
 
@@ -86,11 +91,20 @@ impl StreamWriter {
 
             match packet_opt {
                 Some(packet) => {
-                    self.logger.debug(format!(
-                        "Writing {} bytes over existing stream",
-                        packet.data.len()
-                    ));
-                    match self.stream.poll_write(&packet.data) {
+                    let allowance = if packet.data.is_empty() {
+                        0
+                    } else {
+                        match self.throughput_limiter.poll_allowance() {
+                            Async::Ready(allowance) => min(allowance, packet.data.len()),
+                            Async::NotReady => {
+                                self.sequence_buffer.repush(packet);
+                                return Ok(Async::NotReady);
+                            }
+                        }
+                    };
+                    self.logger
+                        .debug(format!("Writing {} bytes over existing stream", allowance));
+                    match self.stream.poll_write(&packet.data[0..allowance]) {
                         Err(e) => {
                             if indicates_dead_stream(e.kind()) {
                                 self.logger.error(format!(
@@ -111,6 +125,7 @@ impl StreamWriter {
                             return Ok(Async::NotReady);
                         }
                         Ok(Async::Ready(len)) => {
+                            self.throughput_limiter.consume(len);
                             self.logger.debug(format!(
                                 "Wrote {}/{} bytes of clear data (#{})",
                                 len,
@@ -197,7 +212,13 @@ mod tests {
         let write_params_mutex = writer.poll_write_params.clone();
         let peer_addr = SocketAddr::from_str("2.2.3.4:5678").unwrap();
 
-        let mut subject = StreamWriter::new(Box::new(writer), peer_addr, rx_to_write, stream_key);
+        let mut subject = StreamWriter::new(
+            Box::new(writer),
+            peer_addr,
+            rx_to_write,
+            stream_key,
+            ThroughputLimiter::unlimited(),
+        );
 
         let _res = subject.poll();
 
@@ -275,6 +296,7 @@ mod tests {
             SocketAddr::from_str("1.3.3.4:5678").unwrap(),
             rx_to_write,
             stream_key,
+            ThroughputLimiter::unlimited(),
         );
 
         let result = subject.poll();
@@ -295,6 +317,7 @@ mod tests {
             SocketAddr::from_str("1.2.4.4:5678").unwrap(),
             rx_to_write,
             stream_key,
+            ThroughputLimiter::unlimited(),
         );
 
         let result = subject.poll();
@@ -325,7 +348,13 @@ mod tests {
         let write_params = writer.poll_write_params.clone();
         let peer_addr = SocketAddr::from_str("1.3.3.4:5678").unwrap();
 
-        let mut subject = StreamWriter::new(Box::new(writer), peer_addr, rx_to_write, stream_key);
+        let mut subject = StreamWriter::new(
+            Box::new(writer),
+            peer_addr,
+            rx_to_write,
+            stream_key,
+            ThroughputLimiter::unlimited(),
+        );
 
         subject.poll().unwrap();
 
@@ -366,7 +395,13 @@ mod tests {
         let write_params = writer.poll_write_params.clone();
         let peer_addr = SocketAddr::from_str("1.2.3.9:5678").unwrap();
 
-        let mut subject = StreamWriter::new(Box::new(writer), peer_addr, rx_to_write, stream_key);
+        let mut subject = StreamWriter::new(
+            Box::new(writer),
+            peer_addr,
+            rx_to_write,
+            stream_key,
+            ThroughputLimiter::unlimited(),
+        );
 
         let result = subject.poll();
 
@@ -379,6 +414,36 @@ mod tests {
         assert_eq!(params.remove(0), second_data.to_vec());
     }
 
+    #[test]
+    fn stream_writer_writes_no_more_than_its_throughput_limiter_allows() {
+        let stream_key = make_meaningless_stream_key();
+        let mut rx_to_write = Box::new(ReceiverWrapperMock::new());
+        rx_to_write.poll_results = vec![
+            Ok(Async::Ready(Some(SequencedPacket {
+                data: b"01234567".to_vec(),
+                sequence_number: 0,
+                last_data: false,
+            }))),
+            Ok(Async::NotReady),
+        ];
+        let writer = WriteHalfWrapperMock::new().poll_write_result(Ok(Async::Ready(5)));
+        let write_params = writer.poll_write_params.clone();
+        let peer_addr = SocketAddr::from_str("1.2.3.4:999").unwrap();
+        let mut subject = StreamWriter::new(
+            Box::new(writer),
+            peer_addr,
+            rx_to_write,
+            stream_key,
+            ThroughputLimiter::new(Some(5)),
+        );
+
+        let result = subject.poll();
+
+        assert_eq!(result, Ok(Async::NotReady));
+        let params = write_params.lock().unwrap();
+        assert_eq!(*params, vec![b"01234".to_vec()]);
+    }
+
     #[test]
     fn stream_writer_exits_if_channel_is_closed() {
         let stream_key = make_meaningless_stream_key();
@@ -397,7 +462,13 @@ mod tests {
 
         let peer_addr = SocketAddr::from_str("1.2.3.4:999").unwrap();
 
-        let mut subject = StreamWriter::new(Box::new(writer), peer_addr, rx_to_write, stream_key);
+        let mut subject = StreamWriter::new(
+            Box::new(writer),
+            peer_addr,
+            rx_to_write,
+            stream_key,
+            ThroughputLimiter::unlimited(),
+        );
 
         let result = subject.poll();
 
@@ -415,7 +486,13 @@ mod tests {
         let stream_key = make_meaningless_stream_key();
         let peer_addr = SocketAddr::from_str("4.2.3.4:5678").unwrap();
 
-        let mut subject = StreamWriter::new(Box::new(writer), peer_addr, rx_to_write, stream_key);
+        let mut subject = StreamWriter::new(
+            Box::new(writer),
+            peer_addr,
+            rx_to_write,
+            stream_key,
+            ThroughputLimiter::unlimited(),
+        );
 
         subject.poll().unwrap();
     }
@@ -442,6 +519,7 @@ mod tests {
             SocketAddr::from_str("2.3.4.5:80").unwrap(),
             rx_to_write,
             stream_key,
+            ThroughputLimiter::unlimited(),
         );
 
         assert!(subject.poll().is_err());
@@ -477,7 +555,13 @@ mod tests {
         let write_params = writer.poll_write_params.clone();
         let peer_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
 
-        let mut subject = StreamWriter::new(Box::new(writer), peer_addr, rx, stream_key);
+        let mut subject = StreamWriter::new(
+            Box::new(writer),
+            peer_addr,
+            rx,
+            stream_key,
+            ThroughputLimiter::unlimited(),
+        );
 
         let result = subject.poll();
         assert_eq!(result, Ok(Async::NotReady));
@@ -512,7 +596,13 @@ mod tests {
         let write_params = writer.poll_write_params.clone();
         let peer_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
 
-        let mut subject = StreamWriter::new(Box::new(writer), peer_addr, rx, stream_key);
+        let mut subject = StreamWriter::new(
+            Box::new(writer),
+            peer_addr,
+            rx,
+            stream_key,
+            ThroughputLimiter::unlimited(),
+        );
 
         let result = subject.poll();
         assert_eq!(result, Ok(Async::NotReady));
@@ -555,7 +645,13 @@ mod tests {
         let write_params_mutex = writer.poll_write_params.clone();
         let peer_addr = SocketAddr::from_str("2.2.3.4:5678").unwrap();
 
-        let mut subject = StreamWriter::new(Box::new(writer), peer_addr, rx_to_write, stream_key);
+        let mut subject = StreamWriter::new(
+            Box::new(writer),
+            peer_addr,
+            rx_to_write,
+            stream_key,
+            ThroughputLimiter::unlimited(),
+        );
 
         let res = subject.poll();
 
@@ -608,7 +704,13 @@ mod tests {
         let write_params_mutex = writer.poll_write_params.clone();
         let peer_addr = SocketAddr::from_str("2.2.3.4:5678").unwrap();
 
-        let mut subject = StreamWriter::new(Box::new(writer), peer_addr, rx_to_write, stream_key);
+        let mut subject = StreamWriter::new(
+            Box::new(writer),
+            peer_addr,
+            rx_to_write,
+            stream_key,
+            ThroughputLimiter::unlimited(),
+        );
 
         let res = subject.poll();
 
@@ -662,7 +764,13 @@ mod tests {
         let write_params_mutex = writer.poll_write_params.clone();
         let peer_addr = SocketAddr::from_str("2.2.3.4:5678").unwrap();
 
-        let mut subject = StreamWriter::new(Box::new(writer), peer_addr, rx_to_write, stream_key);
+        let mut subject = StreamWriter::new(
+            Box::new(writer),
+            peer_addr,
+            rx_to_write,
+            stream_key,
+            ThroughputLimiter::unlimited(),
+        );
 
         let res = subject.poll();
 
@@ -0,0 +1,246 @@
+// Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use crate::sub_lib::proxy_client::ExitThroughputConfig;
+use crate::sub_lib::proxy_client::ThroughputUtilization;
+use std::cmp::min;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+use tokio::prelude::Async;
+use tokio::prelude::Future;
+use tokio::timer::Delay;
+
+pub const THROUGHPUT_WINDOW: Duration = Duration::from_secs(1);
+
+struct ThroughputLimiterInner {
+    bytes_per_window: Option<u64>,
+    window_start: Instant,
+    bytes_this_window: u64,
+    bytes_last_window: u64,
+    total_bytes: u64,
+}
+
+impl ThroughputLimiterInner {
+    fn roll(&mut self, now: Instant) {
+        if now < self.window_start + THROUGHPUT_WINDOW {
+            return;
+        }
+        let elapsed = now.duration_since(self.window_start);
+        self.bytes_last_window = if elapsed < THROUGHPUT_WINDOW * 2 {
+            self.bytes_this_window
+        } else {
+            0
+        };
+        self.window_start = now;
+        self.bytes_this_window = 0;
+    }
+}
+
+// One budget shared by every stream going the same direction through the exit; each clone keeps
+// its own timer so that the stream that owns it is woken when the next window opens.
+pub struct ThroughputLimiter {
+    inner: Arc<Mutex<ThroughputLimiterInner>>,
+    delay: Option<Delay>,
+}
+
+impl Clone for ThroughputLimiter {
+    fn clone(&self) -> Self {
+        ThroughputLimiter {
+            inner: self.inner.clone(),
+            delay: None,
+        }
+    }
+}
+
+impl ThroughputLimiter {
+    pub fn new(bytes_per_second: Option<u64>) -> ThroughputLimiter {
+        ThroughputLimiter {
+            inner: Arc::new(Mutex::new(ThroughputLimiterInner {
+                bytes_per_window: bytes_per_second,
+                window_start: Instant::now(),
+                bytes_this_window: 0,
+                bytes_last_window: 0,
+                total_bytes: 0,
+            })),
+            delay: None,
+        }
+    }
+
+    pub fn unlimited() -> ThroughputLimiter {
+        ThroughputLimiter::new(None)
+    }
+
+    pub fn poll_allowance(&mut self) -> Async<usize> {
+        loop {
+            match self.allowance_at(Instant::now()) {
+                Ok(bytes) => {
+                    self.delay = None;
+                    return Async::Ready(bytes);
+                }
+                Err(window_end) => {
+                    let mut delay = Delay::new(window_end);
+                    match delay.poll() {
+                        Ok(Async::Ready(())) => continue,
+                        _ => {
+                            self.delay = Some(delay);
+                            return Async::NotReady;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn consume(&self, bytes: usize) {
+        self.consume_at(Instant::now(), bytes)
+    }
+
+    pub fn utilization(&self) -> ThroughputUtilization {
+        let mut inner = self.inner.lock().expect("ThroughputLimiter poisoned");
+        inner.roll(Instant::now());
+        ThroughputUtilization {
+            bytes_per_second_cap: inner.bytes_per_window,
+            bytes_last_second: inner.bytes_last_window,
+            total_bytes: inner.total_bytes,
+        }
+    }
+
+    fn allowance_at(&self, now: Instant) -> Result<usize, Instant> {
+        let mut inner = self.inner.lock().expect("ThroughputLimiter poisoned");
+        inner.roll(now);
+        match inner.bytes_per_window {
+            None => Ok(usize::max_value()),
+            Some(cap) if inner.bytes_this_window < cap => {
+                Ok(min(cap - inner.bytes_this_window, usize::max_value() as u64) as usize)
+            }
+            Some(_) => Err(inner.window_start + THROUGHPUT_WINDOW),
+        }
+    }
+
+    fn consume_at(&self, now: Instant, bytes: usize) {
+        let mut inner = self.inner.lock().expect("ThroughputLimiter poisoned");
+        inner.roll(now);
+        inner.bytes_this_window += bytes as u64;
+        inner.total_bytes += bytes as u64;
+    }
+}
+
+#[derive(Clone)]
+pub struct ExitThroughputLimiters {
+    pub upstream: ThroughputLimiter,
+    pub downstream: ThroughputLimiter,
+}
+
+impl ExitThroughputLimiters {
+    pub fn new(config: &ExitThroughputConfig) -> ExitThroughputLimiters {
+        ExitThroughputLimiters {
+            upstream: ThroughputLimiter::new(config.upstream_bytes_per_second),
+            downstream: ThroughputLimiter::new(config.downstream_bytes_per_second),
+        }
+    }
+
+    pub fn unlimited() -> ExitThroughputLimiters {
+        ExitThroughputLimiters {
+            upstream: ThroughputLimiter::unlimited(),
+            downstream: ThroughputLimiter::unlimited(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn constant_values() {
+        assert_eq!(THROUGHPUT_WINDOW, Duration::from_secs(1));
+    }
+
+    #[test]
+    fn unlimited_limiter_always_allows_everything() {
+        let subject = ThroughputLimiter::unlimited();
+        let now = Instant::now();
+        subject.consume_at(now, 1_000_000_000);
+
+        let result = subject.allowance_at(now);
+
+        assert_eq!(result, Ok(usize::max_value()));
+    }
+
+    #[test]
+    fn limiter_allows_only_what_is_left_of_the_current_window() {
+        let subject = ThroughputLimiter::new(Some(1000));
+        let now = Instant::now();
+        subject.consume_at(now, 600);
+
+        let result = subject.allowance_at(now);
+
+        assert_eq!(result, Ok(400));
+    }
+
+    #[test]
+    fn exhausted_limiter_says_when_the_next_window_opens() {
+        let subject = ThroughputLimiter::new(Some(1000));
+        let window_start = subject.inner.lock().unwrap().window_start;
+        subject.consume_at(window_start, 1000);
+
+        let result = subject.allowance_at(window_start + Duration::from_millis(500));
+
+        assert_eq!(result, Err(window_start + THROUGHPUT_WINDOW));
+    }
+
+    #[test]
+    fn limiter_opens_up_again_in_the_next_window() {
+        let subject = ThroughputLimiter::new(Some(1000));
+        let window_start = subject.inner.lock().unwrap().window_start;
+        subject.consume_at(window_start, 1200);
+
+        let result = subject.allowance_at(window_start + THROUGHPUT_WINDOW);
+
+        assert_eq!(result, Ok(1000));
+    }
+
+    #[test]
+    fn clones_share_the_same_budget() {
+        let subject = ThroughputLimiter::new(Some(1000));
+        let clone = subject.clone();
+        let now = Instant::now();
+        clone.consume_at(now, 700);
+
+        let result = subject.allowance_at(now);
+
+        assert_eq!(result, Ok(300));
+    }
+
+    #[test]
+    fn utilization_reports_the_last_complete_window_and_the_total() {
+        let subject = ThroughputLimiter::new(Some(1000));
+        let window_start = subject.inner.lock().unwrap().window_start;
+        subject.consume_at(window_start, 300);
+        subject.consume_at(window_start + Duration::from_millis(100), 400);
+        subject.consume_at(window_start + THROUGHPUT_WINDOW, 50);
+
+        let result = subject.utilization();
+
+        assert_eq!(result.bytes_per_second_cap, Some(1000));
+        assert_eq!(result.bytes_last_second, 700);
+        assert_eq!(result.total_bytes, 750);
+    }
+
+    #[test]
+    fn exit_throughput_limiters_take_their_caps_from_configuration() {
+        let subject = ExitThroughputLimiters::new(&ExitThroughputConfig {
+            upstream_bytes_per_second: Some(1000),
+            downstream_bytes_per_second: Some(5000),
+        });
+
+        assert_eq!(
+            subject.upstream.utilization().bytes_per_second_cap,
+            Some(1000)
+        );
+        assert_eq!(
+            subject.downstream.utilization().bytes_per_second_cap,
+            Some(5000)
+        );
+    }
+}
@@ -0,0 +1,374 @@
+// Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use crate::sub_lib::logger::Logger;
+use crate::sub_lib::proxy_client::InboundServerData;
+use crate::sub_lib::stream_key::StreamKey;
+use actix::Recipient;
+use actix::Syn;
+use futures::sync::oneshot;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::net::Ipv4Addr;
+use std::net::Ipv6Addr;
+use std::net::SocketAddr;
+use std::net::UdpSocket;
+use std::time::Duration;
+use std::time::Instant;
+use tokio;
+use tokio::prelude::Async;
+use tokio::prelude::Future;
+use tokio::reactor::Handle;
+
+// How long an exit Node keeps a datagram stream's socket with nothing sent on it. UDP has no
+// close, so this is how most of them end.
+pub const UDP_IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+// The most a UDP datagram can carry over IPv4
+pub const MAX_DATAGRAM_SIZE: usize = 65507;
+
+struct UdpAssociation {
+    socket: UdpSocket,
+    peer_addr: SocketAddr,
+    last_used: Instant,
+    reader_killer: oneshot::Sender<()>, // dropping it stops the reader
+}
+
+// One UDP socket for each datagram stream the exit is carrying, connected to the server so that
+// nothing from anybody else gets back to the originator. Whatever the server sends back goes to
+// the ProxyClient as it arrives, without sequence numbers.
+pub struct UdpSocketPool {
+    associations: HashMap<StreamKey, UdpAssociation>,
+    idle_timeout: Duration,
+    proxy_client_sub: Recipient<Syn, InboundServerData>,
+    logger: Logger,
+}
+
+impl UdpSocketPool {
+    pub fn new(
+        proxy_client_sub: Recipient<Syn, InboundServerData>,
+        idle_timeout: Duration,
+    ) -> UdpSocketPool {
+        UdpSocketPool {
+            associations: HashMap::new(),
+            idle_timeout,
+            proxy_client_sub,
+            logger: Logger::new("Proxy Client"),
+        }
+    }
+
+    // A socket whose reader has stopped is as good as closed
+    pub fn contains(&self, stream_key: &StreamKey) -> bool {
+        match self.associations.get(stream_key) {
+            Some(association) => !association.reader_killer.is_canceled(),
+            None => false,
+        }
+    }
+
+    // Must be called from inside a running reactor, where the reader can be spawned
+    pub fn open(&mut self, stream_key: StreamKey, peer_addr: SocketAddr) -> Result<(), String> {
+        let local_addr = match peer_addr.ip() {
+            IpAddr::V4(_) => SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), 0),
+            IpAddr::V6(_) => SocketAddr::new(IpAddr::V6(Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 0)), 0),
+        };
+        let socket = UdpSocket::bind(local_addr)
+            .and_then(|socket| socket.connect(peer_addr).map(|_| socket))
+            .map_err(|e| format!("Could not open UDP socket to {}: {}", peer_addr, e))?;
+        let reader_socket = socket
+            .try_clone()
+            .and_then(|clone| tokio::net::UdpSocket::from_std(clone, &Handle::default()))
+            .map_err(|e| format!("Could not read from UDP socket to {}: {}", peer_addr, e))?;
+        let (reader_killer, killed) = oneshot::channel();
+        tokio::spawn(UdpReader {
+            stream_key,
+            socket: reader_socket,
+            killed,
+            proxy_client_sub: self.proxy_client_sub.clone(),
+            logger: self.logger.clone(),
+            buf: vec![0; MAX_DATAGRAM_SIZE],
+        });
+        self.logger.debug(format!(
+            "Opened UDP socket to {} for stream {:?}",
+            peer_addr, stream_key
+        ));
+        self.associations.insert(
+            stream_key,
+            UdpAssociation {
+                socket,
+                peer_addr,
+                last_used: Instant::now(),
+                reader_killer,
+            },
+        );
+        Ok(())
+    }
+
+    // A datagram that can't go right away is lost, as it might have been anywhere else on its way
+    pub fn send(
+        &mut self,
+        stream_key: &StreamKey,
+        data: &[u8],
+        now: Instant,
+    ) -> Result<(), String> {
+        let association = match self.associations.get_mut(stream_key) {
+            Some(association) => association,
+            None => return Err(format!("No UDP socket for stream {:?}", stream_key)),
+        };
+        association.last_used = now;
+        association.socket.send(data).map(|_| ()).map_err(|e| {
+            format!(
+                "Could not send {}-byte datagram to {}: {}",
+                data.len(),
+                association.peer_addr,
+                e
+            )
+        })
+    }
+
+    pub fn close(&mut self, stream_key: &StreamKey) {
+        if let Some(association) = self.associations.remove(stream_key) {
+            self.logger.debug(format!(
+                "Closed UDP socket to {} for stream {:?}",
+                association.peer_addr, stream_key
+            ));
+        }
+    }
+
+    // The originator hears that each stream closed here is over
+    pub fn close_idle(&mut self, now: Instant) {
+        let idle_timeout = self.idle_timeout;
+        let idle: Vec<StreamKey> = self
+            .associations
+            .iter()
+            .filter(|(_, association)| {
+                now.duration_since(association.last_used) >= idle_timeout
+                    || association.reader_killer.is_canceled()
+            })
+            .map(|(stream_key, _)| *stream_key)
+            .collect();
+        idle.into_iter().for_each(|stream_key| {
+            let association = self
+                .associations
+                .remove(&stream_key)
+                .expect("UDP socket disappeared");
+            self.logger.debug(format!(
+                "Closing idle UDP socket to {} for stream {:?}",
+                association.peer_addr, stream_key
+            ));
+            self.proxy_client_sub
+                .try_send(InboundServerData {
+                    stream_key,
+                    last_data: true,
+                    sequence_number: 0,
+                    source: association.peer_addr,
+                    data: vec![],
+                    truncated: false,
+                })
+                .expect("Proxy Client is dead");
+        });
+    }
+}
+
+struct UdpReader {
+    stream_key: StreamKey,
+    socket: tokio::net::UdpSocket,
+    killed: oneshot::Receiver<()>,
+    proxy_client_sub: Recipient<Syn, InboundServerData>,
+    logger: Logger,
+    buf: Vec<u8>,
+}
+
+impl Future for UdpReader {
+    type Item = ();
+    type Error = ();
+
+    fn poll(&mut self) -> Result<Async<()>, ()> {
+        match self.killed.poll() {
+            Ok(Async::NotReady) => (),
+            _ => return Ok(Async::Ready(())),
+        }
+        loop {
+            match self.socket.poll_recv_from(&mut self.buf) {
+                Ok(Async::Ready((length, source))) => self
+                    .proxy_client_sub
+                    .try_send(InboundServerData {
+                        stream_key: self.stream_key,
+                        last_data: false,
+                        sequence_number: 0,
+                        source,
+                        data: self.buf[..length].to_vec(),
+                        truncated: false,
+                    })
+                    .expect("Proxy Client is dead"),
+                Ok(Async::NotReady) => return Ok(Async::NotReady),
+                Err(e) => {
+                    // The socket will be closed as idle, and the originator told, at the next
+                    // chance; until then, whatever is sent on it goes nowhere
+                    self.logger.debug(format!(
+                        "Stopped reading UDP socket for stream {:?}: {}",
+                        self.stream_key, e
+                    ));
+                    return Ok(Async::Ready(()));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::recorder::make_recorder;
+    use crate::test_utils::recorder::peer_actors_builder;
+    use crate::test_utils::recorder::RecordAwaiter;
+    use crate::test_utils::recorder::Recording;
+    use crate::test_utils::test_utils::make_meaningless_stream_key;
+    use actix::System;
+    use futures::future::lazy;
+    use std::str::FromStr;
+    use std::sync::mpsc;
+    use std::sync::Arc;
+    use std::sync::Mutex;
+    use std::thread;
+
+    fn start_proxy_client() -> (
+        Recipient<Syn, InboundServerData>,
+        RecordAwaiter,
+        Arc<Mutex<Recording>>,
+    ) {
+        let (proxy_client, proxy_client_awaiter, proxy_client_recording_arc) = make_recorder();
+        let (sub_tx, sub_rx) = mpsc::channel();
+        thread::spawn(move || {
+            let system = System::new("test");
+            let peer_actors = peer_actors_builder().proxy_client(proxy_client).build();
+            sub_tx
+                .send(peer_actors.proxy_client.inbound_server_data)
+                .unwrap();
+            system.run();
+        });
+        (
+            sub_rx.recv().unwrap(),
+            proxy_client_awaiter,
+            proxy_client_recording_arc,
+        )
+    }
+
+    fn open_in_reactor(
+        mut subject: UdpSocketPool,
+        stream_key: StreamKey,
+        peer_addr: SocketAddr,
+    ) -> UdpSocketPool {
+        let (pool_tx, pool_rx) = mpsc::channel();
+        thread::spawn(move || {
+            tokio::run(lazy(move || {
+                subject.open(stream_key, peer_addr).unwrap();
+                pool_tx.send(subject).is_ok();
+                Ok(())
+            }))
+        });
+        pool_rx.recv().unwrap()
+    }
+
+    #[test]
+    fn constant_values() {
+        assert_eq!(UDP_IDLE_TIMEOUT, Duration::from_secs(60));
+        assert_eq!(MAX_DATAGRAM_SIZE, 65507);
+    }
+
+    #[test]
+    fn datagrams_go_to_the_server_and_its_answers_come_back() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        server
+            .set_read_timeout(Some(Duration::from_secs(1)))
+            .unwrap();
+        let server_addr = server.local_addr().unwrap();
+        let stream_key = make_meaningless_stream_key();
+        let (proxy_client_sub, proxy_client_awaiter, proxy_client_recording_arc) =
+            start_proxy_client();
+        let mut subject = open_in_reactor(
+            UdpSocketPool::new(proxy_client_sub, UDP_IDLE_TIMEOUT),
+            stream_key,
+            server_addr,
+        );
+
+        subject.send(&stream_key, b"query", Instant::now()).unwrap();
+
+        let mut buf = [0u8; 100];
+        let (length, client_addr) = server.recv_from(&mut buf).unwrap();
+        assert_eq!(&buf[..length], b"query");
+        server.send_to(b"answer", client_addr).unwrap();
+        proxy_client_awaiter.await_message_count(1);
+        let proxy_client_recording = proxy_client_recording_arc.lock().unwrap();
+        assert_eq!(
+            proxy_client_recording.get_record::<InboundServerData>(0),
+            &InboundServerData {
+                stream_key,
+                last_data: false,
+                sequence_number: 0,
+                source: server_addr,
+                data: b"answer".to_vec(),
+                truncated: false,
+            }
+        );
+        assert_eq!(subject.contains(&stream_key), true);
+    }
+
+    #[test]
+    fn sending_on_a_stream_without_a_socket_is_an_error() {
+        let (proxy_client_sub, _, _) = start_proxy_client();
+        let mut subject = UdpSocketPool::new(proxy_client_sub, UDP_IDLE_TIMEOUT);
+        let stream_key = make_meaningless_stream_key();
+
+        let result = subject.send(&stream_key, b"query", Instant::now());
+
+        assert_eq!(
+            result,
+            Err(format!("No UDP socket for stream {:?}", stream_key))
+        );
+    }
+
+    #[test]
+    fn idle_sockets_are_closed_and_the_originator_is_told() {
+        let server_addr = SocketAddr::from_str("127.0.0.1:9").unwrap();
+        let stream_key = make_meaningless_stream_key();
+        let (proxy_client_sub, proxy_client_awaiter, proxy_client_recording_arc) =
+            start_proxy_client();
+        let mut subject = open_in_reactor(
+            UdpSocketPool::new(proxy_client_sub, Duration::from_secs(10)),
+            stream_key,
+            server_addr,
+        );
+        let now = Instant::now();
+
+        subject.close_idle(now);
+        assert_eq!(subject.contains(&stream_key), true);
+        subject.close_idle(now + Duration::from_secs(10));
+
+        assert_eq!(subject.contains(&stream_key), false);
+        proxy_client_awaiter.await_message_count(1);
+        let proxy_client_recording = proxy_client_recording_arc.lock().unwrap();
+        assert_eq!(
+            proxy_client_recording.get_record::<InboundServerData>(0),
+            &InboundServerData {
+                stream_key,
+                last_data: true,
+                sequence_number: 0,
+                source: server_addr,
+                data: vec![],
+                truncated: false,
+            }
+        );
+    }
+
+    #[test]
+    fn closing_a_socket_forgets_it() {
+        let stream_key = make_meaningless_stream_key();
+        let (proxy_client_sub, _, _) = start_proxy_client();
+        let mut subject = open_in_reactor(
+            UdpSocketPool::new(proxy_client_sub, UDP_IDLE_TIMEOUT),
+            stream_key,
+            SocketAddr::from_str("127.0.0.1:9").unwrap(),
+        );
+
+        subject.close(&stream_key);
+
+        assert_eq!(subject.contains(&stream_key), false);
+    }
+}
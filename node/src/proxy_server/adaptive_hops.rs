@@ -0,0 +1,180 @@
+// Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use crate::sub_lib::proxy_server::AdaptiveHopsConfig;
+use std::time::Duration;
+
+pub const ADAPTIVE_HOPS_SAMPLE_SIZE: usize = 20;
+
+// The minimum hop count the ProxyServer asks for, moved a hop at a time by the round trips it times
+// over its routes. Every ADAPTIVE_HOPS_SAMPLE_SIZE round trips, their median decides: over the
+// threshold, one hop fewer; with room for one more hop under it, one hop more. A route of n hops
+// passes through n + 1 Nodes, and another hop is taken to cost as much as each of those.
+pub struct AdaptiveHops {
+    config: AdaptiveHopsConfig,
+    enabled: bool,
+    hop_count: usize,
+    samples: Vec<Duration>,
+}
+
+impl AdaptiveHops {
+    pub fn new(config: &AdaptiveHopsConfig, default_hop_count: usize) -> AdaptiveHops {
+        AdaptiveHops {
+            config: config.clone(),
+            enabled: true,
+            hop_count: default_hop_count.max(config.min_hops).min(config.max_hops),
+            samples: vec![],
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    // Round trips timed while it was off say nothing about the hop count it left off at
+    pub fn enable(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        self.samples.clear();
+    }
+
+    pub fn hop_count(&self, default_hop_count: usize) -> usize {
+        if self.enabled {
+            self.hop_count
+        } else {
+            default_hop_count
+        }
+    }
+
+    // The new hop count and the median that called for it, if this round trip completes a sample
+    // that calls for a change
+    pub fn measured(&mut self, round_trip: Duration) -> Option<(usize, Duration)> {
+        if !self.enabled {
+            return None;
+        }
+        self.samples.push(round_trip);
+        if self.samples.len() < ADAPTIVE_HOPS_SAMPLE_SIZE {
+            return None;
+        }
+        self.samples.sort();
+        let median = self.samples[self.samples.len() / 2];
+        self.samples.clear();
+        let per_hop = median / (self.hop_count as u32 + 1);
+        let new_hop_count =
+            if median > self.config.latency_threshold && self.hop_count > self.config.min_hops {
+                self.hop_count - 1
+            } else if median + per_hop <= self.config.latency_threshold
+                && self.hop_count < self.config.max_hops
+            {
+                self.hop_count + 1
+            } else {
+                return None;
+            };
+        self.hop_count = new_hop_count;
+        Some((new_hop_count, median))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> AdaptiveHopsConfig {
+        AdaptiveHopsConfig {
+            min_hops: 1,
+            max_hops: 4,
+            latency_threshold: Duration::from_millis(900),
+        }
+    }
+
+    fn sample(subject: &mut AdaptiveHops, round_trip: Duration) -> Option<(usize, Duration)> {
+        (1..ADAPTIVE_HOPS_SAMPLE_SIZE).for_each(|_| {
+            assert_eq!(subject.measured(round_trip), None);
+        });
+        subject.measured(round_trip)
+    }
+
+    #[test]
+    fn constants_have_correct_values() {
+        assert_eq!(ADAPTIVE_HOPS_SAMPLE_SIZE, 20);
+    }
+
+    #[test]
+    fn starts_from_the_default_within_bounds() {
+        assert_eq!(AdaptiveHops::new(&config(), 2).hop_count(2), 2);
+        assert_eq!(AdaptiveHops::new(&config(), 6).hop_count(6), 4);
+        assert_eq!(AdaptiveHops::new(&config(), 0).hop_count(0), 1);
+    }
+
+    #[test]
+    fn slow_round_trips_lower_the_hop_count_to_the_minimum() {
+        let mut subject = AdaptiveHops::new(&config(), 2);
+
+        assert_eq!(
+            sample(&mut subject, Duration::from_millis(1000)),
+            Some((1, Duration::from_millis(1000)))
+        );
+        assert_eq!(sample(&mut subject, Duration::from_millis(1000)), None);
+        assert_eq!(subject.hop_count(2), 1);
+    }
+
+    #[test]
+    fn round_trips_with_room_for_another_hop_raise_the_hop_count_to_the_maximum() {
+        let mut subject = AdaptiveHops::new(&config(), 2);
+
+        // Three Nodes at 200ms each; a fourth makes 800ms, under the threshold
+        assert_eq!(
+            sample(&mut subject, Duration::from_millis(600)),
+            Some((3, Duration::from_millis(600)))
+        );
+        // Four Nodes at 150ms each; a fifth makes 750ms
+        assert_eq!(
+            sample(&mut subject, Duration::from_millis(600)),
+            Some((4, Duration::from_millis(600)))
+        );
+        assert_eq!(sample(&mut subject, Duration::from_millis(100)), None);
+        assert_eq!(subject.hop_count(2), 4);
+    }
+
+    #[test]
+    fn round_trips_without_room_for_another_hop_leave_the_hop_count_alone() {
+        let mut subject = AdaptiveHops::new(&config(), 2);
+
+        // Three Nodes at 250ms each; a fourth would make 1000ms
+        let result = sample(&mut subject, Duration::from_millis(750));
+
+        assert_eq!(result, None);
+        assert_eq!(subject.hop_count(2), 2);
+    }
+
+    #[test]
+    fn the_median_decides_rather_than_the_outliers() {
+        let mut subject = AdaptiveHops::new(&config(), 2);
+        (0..(ADAPTIVE_HOPS_SAMPLE_SIZE / 2 - 1)).for_each(|_| {
+            subject.measured(Duration::from_secs(10));
+        });
+        (0..(ADAPTIVE_HOPS_SAMPLE_SIZE / 2)).for_each(|_| {
+            subject.measured(Duration::from_millis(750));
+        });
+
+        let result = subject.measured(Duration::from_millis(750));
+
+        assert_eq!(result, None);
+        assert_eq!(subject.hop_count(2), 2);
+    }
+
+    #[test]
+    fn when_disabled_the_default_is_used_and_round_trips_are_ignored() {
+        let mut subject = AdaptiveHops::new(&config(), 2);
+        sample(&mut subject, Duration::from_millis(600));
+        (1..ADAPTIVE_HOPS_SAMPLE_SIZE).for_each(|_| {
+            subject.measured(Duration::from_millis(100));
+        });
+
+        subject.enable(false);
+
+        assert_eq!(subject.is_enabled(), false);
+        assert_eq!(subject.hop_count(2), 2);
+        assert_eq!(subject.measured(Duration::from_millis(100)), None);
+        subject.enable(true);
+        assert_eq!(subject.hop_count(2), 3);
+        assert_eq!(subject.measured(Duration::from_millis(100)), None);
+    }
+}
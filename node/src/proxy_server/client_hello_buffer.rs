@@ -0,0 +1,287 @@
+// Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use crate::proxy_server::tls_protocol_pack::ClientHelloStatus;
+use crate::proxy_server::tls_protocol_pack::TlsProtocolPack;
+use crate::sub_lib::cryptde::PlainData;
+use crate::sub_lib::dispatcher::InboundClientData;
+use crate::sub_lib::logger::Logger;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::Duration;
+use std::time::Instant;
+
+pub const MAX_CLIENT_HELLO_BYTES: usize = 0x4000;
+pub const CLIENT_HELLO_TIMEOUT: Duration = Duration::from_secs(5);
+
+struct PendingClientHello {
+    packets: Vec<InboundClientData>,
+    data: Vec<u8>,
+    started: Instant,
+}
+
+// Holds back the first packets of a TLS stream until its ClientHello is complete enough to yield
+// a host name, or until the byte or time limit runs out. Packets are released in their original
+// order with their original sequence numbers; only the host name is shared among them.
+pub struct ClientHelloBuffer {
+    pending: HashMap<SocketAddr, PendingClientHello>,
+    byte_limit: usize,
+    time_limit: Duration,
+}
+
+impl ClientHelloBuffer {
+    pub fn new(byte_limit: usize, time_limit: Duration) -> ClientHelloBuffer {
+        ClientHelloBuffer {
+            pending: HashMap::new(),
+            byte_limit,
+            time_limit,
+        }
+    }
+
+    pub fn accept(
+        &mut self,
+        ibcd: InboundClientData,
+        logger: &Logger,
+    ) -> Option<(Vec<InboundClientData>, Option<String>)> {
+        let mut pending = match self.pending.remove(&ibcd.peer_addr) {
+            Some(pending) => pending,
+            None => PendingClientHello {
+                packets: vec![],
+                data: vec![],
+                started: Instant::now(),
+            },
+        };
+        let peer_addr = ibcd.peer_addr;
+        let last_data = ibcd.last_data;
+        pending.data.extend(&ibcd.data);
+        pending.packets.push(ibcd);
+        match TlsProtocolPack::client_hello_status(&PlainData::new(&pending.data)) {
+            ClientHelloStatus::Complete(host_name_opt) => Some((pending.packets, host_name_opt)),
+            ClientHelloStatus::NotClientHello => Some((pending.packets, None)),
+            ClientHelloStatus::Incomplete => {
+                if last_data {
+                    logger.debug(format!(
+                        "Stream from {} closed before its ClientHello was complete",
+                        peer_addr
+                    ));
+                    Some((pending.packets, None))
+                } else if pending.data.len() > self.byte_limit {
+                    logger.warning(format!(
+                        "Gave up on ClientHello from {} after {} bytes",
+                        peer_addr,
+                        pending.data.len()
+                    ));
+                    Some((pending.packets, None))
+                } else if pending.started.elapsed() > self.time_limit {
+                    logger.warning(format!(
+                        "Gave up on ClientHello from {} after {:?}",
+                        peer_addr, self.time_limit
+                    ));
+                    Some((pending.packets, None))
+                } else {
+                    self.pending.insert(peer_addr, pending);
+                    None
+                }
+            }
+        }
+    }
+
+    pub fn time_limit(&self) -> Duration {
+        self.time_limit
+    }
+
+    // A client that sends part of a ClientHello and then goes quiet never gets its packets released
+    // by accept(), so they have to be swept out on a timer instead
+    pub fn release_expired(
+        &mut self,
+        now: Instant,
+        logger: &Logger,
+    ) -> Vec<(Vec<InboundClientData>, Option<String>)> {
+        let time_limit = self.time_limit;
+        let expired: Vec<SocketAddr> = self
+            .pending
+            .iter()
+            .filter(|(_, pending)| pending.started + time_limit < now)
+            .map(|(peer_addr, _)| *peer_addr)
+            .collect();
+        expired
+            .into_iter()
+            .map(|peer_addr| {
+                let pending = self
+                    .pending
+                    .remove(&peer_addr)
+                    .expect("Pending ClientHello disappeared");
+                logger.warning(format!(
+                    "Gave up on ClientHello from {} after {:?} without hearing more",
+                    peer_addr, time_limit
+                ));
+                (pending.packets, None)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::logging::init_test_logging;
+    use crate::test_utils::logging::TestLogHandler;
+    use std::str::FromStr;
+    use std::thread;
+
+    fn ibcd(
+        peer_addr: &str,
+        sequence_number: u64,
+        last_data: bool,
+        data: &[u8],
+    ) -> InboundClientData {
+        InboundClientData {
+            peer_addr: SocketAddr::from_str(peer_addr).unwrap(),
+            reception_port: Some(443),
+            last_data,
+            is_clandestine: false,
+            sequence_number: Some(sequence_number),
+            data: data.to_vec(),
+            neighbor_key_opt: None,
+        }
+    }
+
+    fn client_hello_record() -> Vec<u8> {
+        let server_name = b"server.com";
+        let mut body = vec![0x03, 0x03];
+        body.extend(&[0x00; 32]);
+        body.extend(&[0x00, 0x00, 0x00, 0x00]);
+        body.extend(&[
+            0x00, 0x13, 0x00, 0x00, 0x00, 0x0F, 0x00, 0x0D, 0x00, 0x00, 0x0A,
+        ]);
+        body.extend(&server_name[..]);
+        let mut record = vec![0x16, 0x03, 0x01, 0x00, (body.len() + 4) as u8];
+        record.extend(&[0x01, 0x00, 0x00, body.len() as u8]);
+        record.extend(body);
+        record
+    }
+
+    #[test]
+    fn constant_values() {
+        assert_eq!(MAX_CLIENT_HELLO_BYTES, 0x4000);
+        assert_eq!(CLIENT_HELLO_TIMEOUT, Duration::from_secs(5));
+    }
+
+    #[test]
+    fn complete_client_hello_goes_straight_through() {
+        let mut subject = ClientHelloBuffer::new(MAX_CLIENT_HELLO_BYTES, CLIENT_HELLO_TIMEOUT);
+        let packet = ibcd("1.2.3.4:5678", 0, false, &client_hello_record());
+
+        let result = subject.accept(packet.clone(), &Logger::new("test"));
+
+        assert_eq!(
+            result,
+            Some((vec![packet], Some(String::from("server.com"))))
+        );
+    }
+
+    #[test]
+    fn non_tls_data_goes_straight_through() {
+        let mut subject = ClientHelloBuffer::new(MAX_CLIENT_HELLO_BYTES, CLIENT_HELLO_TIMEOUT);
+        let packet = ibcd("1.2.3.4:5678", 0, false, b"booga");
+
+        let result = subject.accept(packet.clone(), &Logger::new("test"));
+
+        assert_eq!(result, Some((vec![packet], None)));
+    }
+
+    #[test]
+    fn fragmented_client_hello_is_held_until_complete_and_released_in_order() {
+        let mut subject = ClientHelloBuffer::new(MAX_CLIENT_HELLO_BYTES, CLIENT_HELLO_TIMEOUT);
+        let record = client_hello_record();
+        let first = ibcd("1.2.3.4:5678", 0, false, &record[..20]);
+        let other_stream = ibcd("2.3.4.5:6789", 0, false, &record[..30]);
+        let second = ibcd("1.2.3.4:5678", 1, false, &record[20..50]);
+        let third = ibcd("1.2.3.4:5678", 2, false, &record[50..]);
+        let logger = Logger::new("test");
+
+        let first_result = subject.accept(first.clone(), &logger);
+        let other_result = subject.accept(other_stream, &logger);
+        let second_result = subject.accept(second.clone(), &logger);
+        let third_result = subject.accept(third.clone(), &logger);
+
+        assert_eq!(first_result, None);
+        assert_eq!(other_result, None);
+        assert_eq!(second_result, None);
+        assert_eq!(
+            third_result,
+            Some((vec![first, second, third], Some(String::from("server.com"))))
+        );
+    }
+
+    #[test]
+    fn incomplete_client_hello_is_released_when_stream_closes() {
+        let mut subject = ClientHelloBuffer::new(MAX_CLIENT_HELLO_BYTES, CLIENT_HELLO_TIMEOUT);
+        let record = client_hello_record();
+        let first = ibcd("1.2.3.4:5678", 0, false, &record[..20]);
+        let last = ibcd("1.2.3.4:5678", 1, true, &[]);
+        let logger = Logger::new("test");
+
+        subject.accept(first.clone(), &logger);
+        let result = subject.accept(last.clone(), &logger);
+
+        assert_eq!(result, Some((vec![first, last], None)));
+    }
+
+    #[test]
+    fn incomplete_client_hello_is_released_when_byte_limit_is_exceeded() {
+        init_test_logging();
+        let mut subject = ClientHelloBuffer::new(25, CLIENT_HELLO_TIMEOUT);
+        let record = client_hello_record();
+        let first = ibcd("1.2.3.4:5679", 0, false, &record[..20]);
+        let second = ibcd("1.2.3.4:5679", 1, false, &record[20..30]);
+        let logger = Logger::new("test");
+
+        subject.accept(first.clone(), &logger);
+        let result = subject.accept(second.clone(), &logger);
+
+        assert_eq!(result, Some((vec![first, second], None)));
+        TestLogHandler::new().exists_log_containing(
+            "WARN: test: Gave up on ClientHello from 1.2.3.4:5679 after 30 bytes",
+        );
+    }
+
+    #[test]
+    fn incomplete_client_hello_is_released_when_time_limit_is_exceeded() {
+        init_test_logging();
+        let mut subject = ClientHelloBuffer::new(MAX_CLIENT_HELLO_BYTES, Duration::from_millis(10));
+        let record = client_hello_record();
+        let first = ibcd("1.2.3.4:5680", 0, false, &record[..20]);
+        let second = ibcd("1.2.3.4:5680", 1, false, &record[20..30]);
+        let logger = Logger::new("test");
+
+        subject.accept(first.clone(), &logger);
+        thread::sleep(Duration::from_millis(20));
+        let result = subject.accept(second.clone(), &logger);
+
+        assert_eq!(result, Some((vec![first, second], None)));
+        TestLogHandler::new().exists_log_containing(
+            "WARN: test: Gave up on ClientHello from 1.2.3.4:5680 after 10ms",
+        );
+    }
+
+    #[test]
+    fn only_expired_client_hellos_are_released_by_a_sweep() {
+        init_test_logging();
+        let mut subject = ClientHelloBuffer::new(MAX_CLIENT_HELLO_BYTES, Duration::from_secs(5));
+        let record = client_hello_record();
+        let stale = ibcd("1.2.3.4:5681", 0, false, &record[..20]);
+        let fresh = ibcd("2.3.4.5:6781", 0, false, &record[..20]);
+        let logger = Logger::new("test");
+        subject.accept(stale.clone(), &logger);
+        subject.pending.get_mut(&stale.peer_addr).unwrap().started -= Duration::from_secs(6);
+        subject.accept(fresh.clone(), &logger);
+
+        let result = subject.release_expired(Instant::now(), &logger);
+
+        assert_eq!(result, vec![(vec![stale], None)]);
+        assert_eq!(subject.pending.len(), 1);
+        assert_eq!(subject.pending.contains_key(&fresh.peer_addr), true);
+        TestLogHandler::new().exists_log_containing(
+            "WARN: test: Gave up on ClientHello from 1.2.3.4:5681 after 5s without hearing more",
+        );
+    }
+}
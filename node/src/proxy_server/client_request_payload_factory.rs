@@ -1,26 +1,56 @@
 // Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
-use crate::proxy_server::http_protocol_pack::HttpProtocolPack;
+use crate::proxy_server::privacy_policy::PrivacyPolicy;
+use crate::proxy_server::protocol_pack::protocol_packs_for;
 use crate::proxy_server::protocol_pack::ProtocolPack;
-use crate::proxy_server::tls_protocol_pack::TlsProtocolPack;
 use crate::sub_lib::cryptde::CryptDE;
 use crate::sub_lib::cryptde::PlainData;
 use crate::sub_lib::dispatcher::InboundClientData;
 use crate::sub_lib::logger::Logger;
 use crate::sub_lib::proxy_server::ClientRequestPayload;
+use crate::sub_lib::proxy_server::ProxyProtocol;
+use crate::sub_lib::proxy_server::EXIT_TLS_TARGET_PORT;
 use crate::sub_lib::sequence_buffer::SequencedPacket;
 use crate::sub_lib::stream_key::StreamKey;
 use std::collections::HashMap;
+use std::net::IpAddr;
 
 pub struct ClientRequestPayloadFactory {
     protocol_packs: HashMap<u16, Box<dyn ProtocolPack>>,
+    privacy_policy_opt: Option<PrivacyPolicy>,
+    exit_tls_requested: bool,
+    exit_dns_server_opt: Option<IpAddr>,
 }
 
 impl ClientRequestPayloadFactory {
-    pub fn new() -> ClientRequestPayloadFactory {
-        let mut protocol_packs: HashMap<u16, Box<dyn ProtocolPack>> = HashMap::new();
-        protocol_packs.insert(80, Box::new(HttpProtocolPack {}));
-        protocol_packs.insert(443, Box::new(TlsProtocolPack {}));
-        ClientRequestPayloadFactory { protocol_packs }
+    pub fn new(protocol_pack_ports: &HashMap<u16, String>) -> ClientRequestPayloadFactory {
+        ClientRequestPayloadFactory {
+            protocol_packs: protocol_packs_for(protocol_pack_ports),
+            privacy_policy_opt: None,
+            exit_tls_requested: false,
+            exit_dns_server_opt: None,
+        }
+    }
+
+    pub fn request_exit_tls(&mut self) {
+        self.exit_tls_requested = true;
+    }
+
+    pub fn request_exit_dns_server(&mut self, dns_server: IpAddr) {
+        self.exit_dns_server_opt = Some(dns_server);
+    }
+
+    pub fn filter_with(&mut self, policy: PrivacyPolicy) {
+        self.privacy_policy_opt = Some(policy);
+    }
+
+    pub fn privacy_policy(&self) -> Option<&PrivacyPolicy> {
+        self.privacy_policy_opt.as_ref()
+    }
+
+    pub fn protocol_for(&self, reception_port: Option<u16>) -> Option<ProxyProtocol> {
+        self.protocol_packs
+            .get(&reception_port?)
+            .map(|protocol_pack| protocol_pack.proxy_protocol())
     }
 
     pub fn make(
@@ -63,18 +93,34 @@ impl ClientRequestPayloadFactory {
                 return None;
             }
         };
-        let host_name = protocol_pack.find_host_name(&PlainData::new(&ibcd.data));
+        let plain_data = PlainData::new(&ibcd.data);
+        let host_name = protocol_pack.find_host_name(&plain_data);
+        let data = match self.privacy_policy_opt {
+            Some(ref policy) => protocol_pack.filter_request(&plain_data, policy).into(),
+            None => ibcd.data.clone(),
+        };
+        let tls_at_exit =
+            self.exit_tls_requested && protocol_pack.proxy_protocol() == ProxyProtocol::HTTP;
         Some(ClientRequestPayload {
             stream_key,
             sequenced_packet: SequencedPacket {
-                data: ibcd.data.clone(),
+                data,
                 sequence_number,
                 last_data: ibcd.last_data,
             },
             target_hostname: host_name,
-            target_port: origin_port,
+            target_port: if tls_at_exit {
+                EXIT_TLS_TARGET_PORT
+            } else {
+                origin_port
+            },
             protocol: protocol_pack.proxy_protocol(),
             originator_public_key: cryptde.public_key().clone(),
+            tls_at_exit,
+            upload_window: None,
+            udp: false,
+            dns_server: self.exit_dns_server_opt,
+            cancel: false,
         })
     }
 }
@@ -82,14 +128,37 @@ impl ClientRequestPayloadFactory {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::proxy_server::protocol_pack::standard_protocol_pack_ports;
     use crate::sub_lib::cryptde_null::CryptDENull;
-    use crate::sub_lib::proxy_server::ProxyProtocol;
     use crate::test_utils::logging::init_test_logging;
     use crate::test_utils::logging::TestLogHandler;
     use crate::test_utils::test_utils::make_meaningless_stream_key;
     use std::net::SocketAddr;
     use std::str::FromStr;
 
+    #[test]
+    fn knows_protocols_for_reception_ports() {
+        let subject = ClientRequestPayloadFactory::new(&standard_protocol_pack_ports());
+
+        assert_eq!(subject.protocol_for(Some(80)), Some(ProxyProtocol::HTTP));
+        assert_eq!(subject.protocol_for(Some(443)), Some(ProxyProtocol::TLS));
+        assert_eq!(subject.protocol_for(Some(1234)), None);
+        assert_eq!(subject.protocol_for(None), None);
+    }
+
+    #[test]
+    fn protocols_follow_the_configured_port_mapping() {
+        let mut protocol_pack_ports = HashMap::new();
+        protocol_pack_ports.insert(8080, String::from("http"));
+        protocol_pack_ports.insert(8443, String::from("tls"));
+        let subject = ClientRequestPayloadFactory::new(&protocol_pack_ports);
+
+        assert_eq!(subject.protocol_for(Some(8080)), Some(ProxyProtocol::HTTP));
+        assert_eq!(subject.protocol_for(Some(8443)), Some(ProxyProtocol::TLS));
+        assert_eq!(subject.protocol_for(Some(80)), None);
+        assert_eq!(subject.protocol_for(Some(443)), None);
+    }
+
     #[test]
     fn handles_http() {
         let data = PlainData::new(&b"GET http://borkoed.com/fleebs.html HTTP/1.1\r\n\r\n"[..]);
@@ -100,10 +169,11 @@ mod tests {
             last_data: false,
             is_clandestine: false,
             data: data.clone().into(),
+            neighbor_key_opt: None,
         };
         let cryptde = CryptDENull::new();
         let logger = Logger::new("test");
-        let subject = ClientRequestPayloadFactory::new();
+        let subject = ClientRequestPayloadFactory::new(&standard_protocol_pack_ports());
 
         let result = subject.make(&ibcd, make_meaningless_stream_key(), &cryptde, &logger);
 
@@ -120,10 +190,112 @@ mod tests {
                 target_port: 80,
                 protocol: ProxyProtocol::HTTP,
                 originator_public_key: cryptde.public_key(),
+                tls_at_exit: false,
+                upload_window: None,
+                udp: false,
+                dns_server: None,
+                cancel: false,
             })
         );
     }
 
+    #[test]
+    fn filters_http_through_the_privacy_policy_if_there_is_one() {
+        let ibcd = InboundClientData {
+            peer_addr: SocketAddr::from_str("1.2.3.4:5678").unwrap(),
+            reception_port: Some(80),
+            sequence_number: Some(0),
+            last_data: false,
+            is_clandestine: false,
+            data: b"GET / HTTP/1.1\r\nDNT: 1\r\nHost: borkoed.com\r\n\r\n".to_vec(),
+            neighbor_key_opt: None,
+        };
+        let cryptde = CryptDENull::new();
+        let logger = Logger::new("test");
+        let mut subject = ClientRequestPayloadFactory::new(&standard_protocol_pack_ports());
+        subject.filter_with(PrivacyPolicy::from_json(r#"{"strip_headers": ["DNT"]}"#).unwrap());
+
+        let result = subject
+            .make(&ibcd, make_meaningless_stream_key(), &cryptde, &logger)
+            .unwrap();
+
+        assert_eq!(
+            result.sequenced_packet.data,
+            b"GET / HTTP/1.1\r\nHost: borkoed.com\r\n\r\n".to_vec()
+        );
+        assert_eq!(result.target_hostname, Some(String::from("borkoed.com")));
+    }
+
+    #[test]
+    fn asks_for_tls_at_exit_for_http_but_not_for_tls_when_requested() {
+        let make_ibcd = |reception_port: u16, data: &[u8]| InboundClientData {
+            peer_addr: SocketAddr::from_str("1.2.3.4:5678").unwrap(),
+            reception_port: Some(reception_port),
+            sequence_number: Some(0),
+            last_data: false,
+            is_clandestine: false,
+            data: data.to_vec(),
+            neighbor_key_opt: None,
+        };
+        let cryptde = CryptDENull::new();
+        let logger = Logger::new("test");
+        let mut subject = ClientRequestPayloadFactory::new(&standard_protocol_pack_ports());
+        subject.request_exit_tls();
+
+        let http_result = subject
+            .make(
+                &make_ibcd(80, b"GET / HTTP/1.1\r\nHost: borkoed.com\r\n\r\n"),
+                make_meaningless_stream_key(),
+                &cryptde,
+                &logger,
+            )
+            .unwrap();
+        let tls_result = subject
+            .make(
+                &make_ibcd(443, &[0x16, 0x03, 0x01, 0x00, 0x00]),
+                make_meaningless_stream_key(),
+                &cryptde,
+                &logger,
+            )
+            .unwrap();
+
+        assert_eq!(http_result.tls_at_exit, true);
+        assert_eq!(http_result.target_port, EXIT_TLS_TARGET_PORT);
+        assert_eq!(http_result.protocol, ProxyProtocol::HTTP);
+        assert_eq!(tls_result.tls_at_exit, false);
+        assert_eq!(tls_result.target_port, 443);
+    }
+
+    #[test]
+    fn asks_the_exit_for_the_requested_dns_server() {
+        let ibcd = InboundClientData {
+            peer_addr: SocketAddr::from_str("1.2.3.4:5678").unwrap(),
+            reception_port: Some(80),
+            sequence_number: Some(0),
+            last_data: false,
+            is_clandestine: false,
+            data: b"GET / HTTP/1.1\r\nHost: borkoed.com\r\n\r\n".to_vec(),
+            neighbor_key_opt: None,
+        };
+        let cryptde = CryptDENull::new();
+        let logger = Logger::new("test");
+        let mut subject = ClientRequestPayloadFactory::new(&standard_protocol_pack_ports());
+        let unrequested_result = subject
+            .make(&ibcd, make_meaningless_stream_key(), &cryptde, &logger)
+            .unwrap();
+        subject.request_exit_dns_server(IpAddr::from_str("9.9.9.9").unwrap());
+
+        let requested_result = subject
+            .make(&ibcd, make_meaningless_stream_key(), &cryptde, &logger)
+            .unwrap();
+
+        assert_eq!(unrequested_result.dns_server, None);
+        assert_eq!(
+            requested_result.dns_server,
+            Some(IpAddr::from_str("9.9.9.9").unwrap())
+        );
+    }
+
     #[test]
     fn handles_tls_with_hostname() {
         let data = PlainData::new(&[
@@ -154,10 +326,11 @@ mod tests {
             last_data: false,
             is_clandestine: false,
             data: data.clone().into(),
+            neighbor_key_opt: None,
         };
         let cryptde = CryptDENull::new();
         let logger = Logger::new("test");
-        let subject = ClientRequestPayloadFactory::new();
+        let subject = ClientRequestPayloadFactory::new(&standard_protocol_pack_ports());
 
         let result = subject.make(&ibcd, make_meaningless_stream_key(), &cryptde, &logger);
 
@@ -174,6 +347,11 @@ mod tests {
                 target_port: 443,
                 protocol: ProxyProtocol::TLS,
                 originator_public_key: cryptde.public_key(),
+                tls_at_exit: false,
+                upload_window: None,
+                udp: false,
+                dns_server: None,
+                cancel: false,
             })
         );
     }
@@ -201,10 +379,11 @@ mod tests {
             is_clandestine: false,
             sequence_number: Some(0),
             data: data.clone().into(),
+            neighbor_key_opt: None,
         };
         let cryptde = CryptDENull::new();
         let logger = Logger::new("test");
-        let subject = ClientRequestPayloadFactory::new();
+        let subject = ClientRequestPayloadFactory::new(&standard_protocol_pack_ports());
 
         let result = subject.make(&ibcd, make_meaningless_stream_key(), &cryptde, &logger);
 
@@ -221,6 +400,11 @@ mod tests {
                 target_port: 443,
                 protocol: ProxyProtocol::TLS,
                 originator_public_key: cryptde.public_key(),
+                tls_at_exit: false,
+                upload_window: None,
+                udp: false,
+                dns_server: None,
+                cancel: false,
             })
         );
     }
@@ -235,10 +419,11 @@ mod tests {
             last_data: false,
             is_clandestine: false,
             data: vec![0x10, 0x11, 0x12],
+            neighbor_key_opt: None,
         };
         let cryptde = CryptDENull::new();
         let logger = Logger::new("test");
-        let subject = ClientRequestPayloadFactory::new();
+        let subject = ClientRequestPayloadFactory::new(&standard_protocol_pack_ports());
 
         let result = subject.make(&ibcd, make_meaningless_stream_key(), &cryptde, &logger);
 
@@ -258,10 +443,11 @@ mod tests {
             last_data: false,
             is_clandestine: true,
             data: vec![0x10, 0x11, 0x12],
+            neighbor_key_opt: None,
         };
         let cryptde = CryptDENull::new();
         let logger = Logger::new("test");
-        let subject = ClientRequestPayloadFactory::new();
+        let subject = ClientRequestPayloadFactory::new(&standard_protocol_pack_ports());
 
         let result = subject.make(&ibcd, make_meaningless_stream_key(), &cryptde, &logger);
 
@@ -278,11 +464,12 @@ mod tests {
             last_data: false,
             data: vec![0x10, 0x11, 0x12],
             is_clandestine: false,
+            neighbor_key_opt: None,
         };
         let cryptde = CryptDENull::new();
         let logger = Logger::new("test");
 
-        let subject = ClientRequestPayloadFactory::new();
+        let subject = ClientRequestPayloadFactory::new(&standard_protocol_pack_ports());
 
         let result = subject
             .make(&ibcd, make_meaningless_stream_key(), &cryptde, &logger)
@@ -301,11 +488,12 @@ mod tests {
             is_clandestine: false,
             sequence_number: None,
             data: vec![1, 3, 5, 7],
+            neighbor_key_opt: None,
         };
         let cryptde = CryptDENull::new();
         let logger = Logger::new("test");
 
-        let subject = ClientRequestPayloadFactory::new();
+        let subject = ClientRequestPayloadFactory::new(&standard_protocol_pack_ports());
 
         let result = subject.make(&ibcd, make_meaningless_stream_key(), &cryptde, &logger);
 
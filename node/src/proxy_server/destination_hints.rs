@@ -0,0 +1,387 @@
+// Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use crate::sub_lib::proxy_server::ClientRequestPayload;
+use crate::sub_lib::proxy_server::ProxyProtocol;
+use crate::sub_lib::stream_key::StreamKey;
+use std::collections::HashMap;
+
+pub const DESTINATION_HINTS_CAPACITY: usize = 256;
+pub const LARGE_RESPONSE_BYTES: u64 = 1024 * 1024;
+
+const HTTP_METHODS: &[&[u8]] = &[
+    b"GET ",
+    b"HEAD ",
+    b"POST ",
+    b"PUT ",
+    b"DELETE ",
+    b"OPTIONS ",
+    b"PATCH ",
+];
+
+// What streams to one hostname have shown about it so far
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DestinationHints {
+    // A stream carried another HTTP request after the server had started answering
+    pub keep_alive: bool,
+    // A request went unanswered until it was retried
+    pub slow: bool,
+    // A request asked to upgrade its connection to a WebSocket
+    pub websocket: bool,
+    streams: u64,
+    response_bytes: u64,
+}
+
+impl DestinationHints {
+    // Finished streams have averaged at least LARGE_RESPONSE_BYTES of response
+    pub fn large_responses(&self) -> bool {
+        self.streams > 0 && (self.response_bytes / self.streams) >= LARGE_RESPONSE_BYTES
+    }
+}
+
+// How a stream is handled differently because of what its destination has shown
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct StreamParameters {
+    // Waits twice as long before retrying a request that hasn't been answered
+    pub patient: bool,
+    // Keeps its route past the Neighborhood's validity window, rather than moving to another exit
+    pub keep_route: bool,
+    // Goes out without waiting on the upload window; decided once, when the stream starts
+    pub unpaced: bool,
+    // Stays away from exits carrying other streams to destinations with large responses
+    pub isolated: bool,
+}
+
+struct StreamRecord {
+    hostname: String,
+    answered: bool,
+    response_bytes: u64,
+    unpaced: bool,
+}
+
+// DestinationHints for the DESTINATION_HINTS_CAPACITY hostnames requested most recently, and the
+// hostname each live stream is talking to
+pub struct DestinationHintsCache {
+    capacity: usize,
+    clock: u64,
+    hosts: HashMap<String, (DestinationHints, u64)>,
+    streams: HashMap<StreamKey, StreamRecord>,
+}
+
+impl DestinationHintsCache {
+    pub fn new(capacity: usize) -> DestinationHintsCache {
+        DestinationHintsCache {
+            capacity,
+            clock: 0,
+            hosts: HashMap::new(),
+            streams: HashMap::new(),
+        }
+    }
+
+    pub fn hints(&self, hostname: &str) -> Option<DestinationHints> {
+        self.hosts
+            .get(&hostname.to_lowercase())
+            .map(|(hints, _)| hints.clone())
+    }
+
+    pub fn parameters(&self, stream_key: &StreamKey) -> StreamParameters {
+        let record = match self.streams.get(stream_key) {
+            Some(record) => record,
+            None => return StreamParameters::default(),
+        };
+        let hints = self.hints(&record.hostname).unwrap_or_default();
+        StreamParameters {
+            patient: hints.slow,
+            keep_route: hints.keep_alive,
+            unpaced: record.unpaced,
+            isolated: hints.large_responses(),
+        }
+    }
+
+    // Live streams, other than this one, that stay away from each other's exits
+    pub fn isolated_streams(&self, except: &StreamKey) -> Vec<StreamKey> {
+        self.streams
+            .keys()
+            .filter(|stream_key| *stream_key != except && self.parameters(stream_key).isolated)
+            .cloned()
+            .collect()
+    }
+
+    // Called with every request packet on its way to the exit
+    pub fn request(&mut self, payload: &ClientRequestPayload) {
+        let hostname = match &payload.target_hostname {
+            Some(hostname) => hostname.to_lowercase(),
+            None => return,
+        };
+        let data = &payload.sequenced_packet.data;
+        let is_http = payload.protocol == ProxyProtocol::HTTP;
+        let is_request_line = is_http && HTTP_METHODS.iter().any(|method| data.starts_with(method));
+        let keep_alive = is_request_line
+            && self
+                .streams
+                .get(&payload.stream_key)
+                .map(|record| record.answered)
+                .unwrap_or(false);
+        let websocket = is_request_line
+            && String::from_utf8_lossy(data)
+                .to_lowercase()
+                .contains("upgrade: websocket");
+        self.learn(&hostname, |hints| {
+            hints.keep_alive |= keep_alive;
+            hints.websocket |= websocket;
+        });
+        let unpaced = self
+            .hints(&hostname)
+            .map(|hints| hints.websocket)
+            .unwrap_or(false);
+        self.streams
+            .entry(payload.stream_key)
+            .or_insert(StreamRecord {
+                hostname,
+                answered: false,
+                response_bytes: 0,
+                unpaced,
+            });
+    }
+
+    // Called with every response packet that carries data; the last one finishes the stream
+    pub fn response(&mut self, stream_key: &StreamKey, bytes: usize, last_data: bool) {
+        let (hostname, response_bytes) = match self.streams.get_mut(stream_key) {
+            Some(record) => {
+                record.answered = true;
+                record.response_bytes += bytes as u64;
+                (record.hostname.clone(), record.response_bytes)
+            }
+            None => return,
+        };
+        if last_data {
+            self.streams.remove(stream_key);
+            self.learn(&hostname, |hints| {
+                hints.streams += 1;
+                hints.response_bytes += response_bytes;
+            });
+        }
+    }
+
+    // Called when a stream's request has gone unanswered for too long
+    pub fn stalled(&mut self, stream_key: &StreamKey) {
+        if let Some(hostname) = self
+            .streams
+            .get(stream_key)
+            .map(|record| record.hostname.clone())
+        {
+            self.learn(&hostname, |hints| hints.slow = true);
+        }
+    }
+
+    // Called when a stream ends without its last response packet
+    pub fn forget(&mut self, stream_key: &StreamKey) {
+        self.streams.remove(stream_key);
+    }
+
+    fn learn<F>(&mut self, hostname: &str, f: F)
+    where
+        F: FnOnce(&mut DestinationHints),
+    {
+        self.clock += 1;
+        if !self.hosts.contains_key(hostname) && self.hosts.len() >= self.capacity {
+            let least_recent_opt = self
+                .hosts
+                .iter()
+                .min_by_key(|(_, (_, last_used))| *last_used)
+                .map(|(hostname, _)| hostname.clone());
+            if let Some(least_recent) = least_recent_opt {
+                self.hosts.remove(&least_recent);
+            }
+        }
+        let clock = self.clock;
+        let (hints, last_used) = self
+            .hosts
+            .entry(hostname.to_string())
+            .or_insert_with(|| (DestinationHints::default(), clock));
+        f(hints);
+        *last_used = clock;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sub_lib::cryptde::PublicKey;
+    use crate::sub_lib::sequence_buffer::SequencedPacket;
+    use std::net::SocketAddr;
+    use std::str::FromStr;
+
+    fn stream_key(port: u16) -> StreamKey {
+        StreamKey::new(
+            PublicKey::new(&[]),
+            SocketAddr::from_str(&format!("4.3.2.1:{}", port)).unwrap(),
+        )
+    }
+
+    fn make_payload(
+        stream_key: StreamKey,
+        hostname: &str,
+        protocol: ProxyProtocol,
+        data: &[u8],
+    ) -> ClientRequestPayload {
+        ClientRequestPayload {
+            stream_key,
+            sequenced_packet: SequencedPacket::new(data.to_vec(), 0, false),
+            target_hostname: Some(String::from(hostname)),
+            target_port: 80,
+            protocol,
+            originator_public_key: PublicKey::new(&b"originator"[..]),
+            tls_at_exit: false,
+            upload_window: None,
+            udp: false,
+            dns_server: None,
+            cancel: false,
+        }
+    }
+
+    fn get(stream_key: StreamKey, hostname: &str) -> ClientRequestPayload {
+        make_payload(
+            stream_key,
+            hostname,
+            ProxyProtocol::HTTP,
+            b"GET / HTTP/1.1\r\n\r\n",
+        )
+    }
+
+    #[test]
+    fn constants_have_correct_values() {
+        assert_eq!(DESTINATION_HINTS_CAPACITY, 256);
+        assert_eq!(LARGE_RESPONSE_BYTES, 1048576);
+    }
+
+    #[test]
+    fn nothing_is_known_about_a_new_destination() {
+        let mut subject = DestinationHintsCache::new(DESTINATION_HINTS_CAPACITY);
+
+        subject.request(&get(stream_key(1), "Example.com"));
+
+        assert_eq!(
+            subject.hints("example.com"),
+            Some(DestinationHints::default())
+        );
+        assert_eq!(subject.hints("elsewhere.com"), None);
+        assert_eq!(
+            subject.parameters(&stream_key(1)),
+            StreamParameters::default()
+        );
+        assert_eq!(
+            subject.parameters(&stream_key(2)),
+            StreamParameters::default()
+        );
+    }
+
+    #[test]
+    fn another_http_request_after_a_response_shows_keep_alive() {
+        let mut subject = DestinationHintsCache::new(DESTINATION_HINTS_CAPACITY);
+        subject.request(&get(stream_key(1), "example.com"));
+        subject.request(&get(stream_key(1), "example.com"));
+        assert_eq!(subject.hints("example.com").unwrap().keep_alive, false);
+        subject.response(&stream_key(1), 1000, false);
+        let tls = make_payload(stream_key(2), "tls.com", ProxyProtocol::TLS, b"GET ");
+        subject.request(&tls);
+        subject.response(&stream_key(2), 1000, false);
+        subject.request(&tls);
+
+        subject.request(&get(stream_key(1), "example.com"));
+
+        assert_eq!(subject.hints("example.com").unwrap().keep_alive, true);
+        assert_eq!(subject.parameters(&stream_key(1)).keep_route, true);
+        assert_eq!(subject.hints("tls.com").unwrap().keep_alive, false);
+    }
+
+    #[test]
+    fn a_websocket_upgrade_leaves_that_destination_unpaced_from_then_on() {
+        let mut subject = DestinationHintsCache::new(DESTINATION_HINTS_CAPACITY);
+        subject.request(&get(stream_key(1), "chat.com"));
+
+        subject.request(&make_payload(
+            stream_key(2),
+            "chat.com",
+            ProxyProtocol::HTTP,
+            b"GET /socket HTTP/1.1\r\nConnection: Upgrade\r\nUpgrade: WebSocket\r\n\r\n",
+        ));
+        subject.request(&get(stream_key(3), "chat.com"));
+
+        assert_eq!(subject.hints("chat.com").unwrap().websocket, true);
+        assert_eq!(subject.parameters(&stream_key(1)).unpaced, false);
+        assert_eq!(subject.parameters(&stream_key(2)).unpaced, true);
+        assert_eq!(subject.parameters(&stream_key(3)).unpaced, true);
+    }
+
+    #[test]
+    fn a_stalled_request_makes_its_destination_slow() {
+        let mut subject = DestinationHintsCache::new(DESTINATION_HINTS_CAPACITY);
+        subject.request(&get(stream_key(1), "slow.com"));
+
+        subject.stalled(&stream_key(1));
+        subject.stalled(&stream_key(2));
+
+        assert_eq!(subject.hints("slow.com").unwrap().slow, true);
+        assert_eq!(subject.parameters(&stream_key(1)).patient, true);
+    }
+
+    #[test]
+    fn large_responses_are_averaged_over_finished_streams_and_isolate_them() {
+        let mut subject = DestinationHintsCache::new(DESTINATION_HINTS_CAPACITY);
+        subject.request(&get(stream_key(1), "videos.com"));
+        subject.response(&stream_key(1), (LARGE_RESPONSE_BYTES * 2) as usize, false);
+        assert_eq!(
+            subject.hints("videos.com").unwrap().large_responses(),
+            false
+        );
+        subject.response(&stream_key(1), 0, true);
+        subject.request(&get(stream_key(2), "videos.com"));
+        subject.response(&stream_key(2), 1, true);
+        assert_eq!(subject.hints("videos.com").unwrap().large_responses(), true);
+        subject.request(&get(stream_key(3), "videos.com"));
+        subject.response(&stream_key(3), 1, true);
+        assert_eq!(
+            subject.hints("videos.com").unwrap().large_responses(),
+            false
+        );
+        subject.request(&get(stream_key(4), "videos.com"));
+        subject.response(&stream_key(4), (LARGE_RESPONSE_BYTES * 2) as usize, true);
+        subject.request(&get(stream_key(5), "videos.com"));
+        subject.request(&get(stream_key(6), "videos.com"));
+        subject.request(&get(stream_key(7), "example.com"));
+
+        let result = subject.isolated_streams(&stream_key(5));
+
+        assert_eq!(result, vec![stream_key(6)]);
+        assert_eq!(subject.parameters(&stream_key(5)).isolated, true);
+        assert_eq!(subject.parameters(&stream_key(7)).isolated, false);
+    }
+
+    #[test]
+    fn forgotten_streams_teach_nothing_more() {
+        let mut subject = DestinationHintsCache::new(DESTINATION_HINTS_CAPACITY);
+        subject.request(&get(stream_key(1), "example.com"));
+
+        subject.forget(&stream_key(1));
+        subject.response(&stream_key(1), LARGE_RESPONSE_BYTES as usize, true);
+        subject.stalled(&stream_key(1));
+
+        assert_eq!(
+            subject.hints("example.com"),
+            Some(DestinationHints::default())
+        );
+    }
+
+    #[test]
+    fn the_least_recently_requested_destination_is_forgotten_first() {
+        let mut subject = DestinationHintsCache::new(2);
+        subject.request(&get(stream_key(1), "first.com"));
+        subject.request(&get(stream_key(2), "second.com"));
+        subject.request(&get(stream_key(3), "first.com"));
+
+        subject.request(&get(stream_key(4), "third.com"));
+
+        assert_eq!(subject.hints("first.com").is_some(), true);
+        assert_eq!(subject.hints("second.com"), None);
+        assert_eq!(subject.hints("third.com").is_some(), true);
+    }
+}
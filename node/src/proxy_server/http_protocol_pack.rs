@@ -1,4 +1,8 @@
 // Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use crate::discriminator::DiscriminatorFactory;
+use crate::http_request_start_finder::HttpRequestDiscriminatorFactory;
+use crate::proxy_server::privacy_policy::HeaderTreatment;
+use crate::proxy_server::privacy_policy::PrivacyPolicy;
 use crate::proxy_server::protocol_pack::ProtocolPack;
 use crate::sub_lib::cryptde::PlainData;
 use crate::sub_lib::proxy_server::ProxyProtocol;
@@ -7,6 +11,18 @@ use crate::sub_lib::utils::index_of;
 pub struct HttpProtocolPack {}
 
 impl ProtocolPack for HttpProtocolPack {
+    fn name(&self) -> &'static str {
+        "http"
+    }
+
+    fn standard_ports(&self) -> Vec<u16> {
+        vec![80]
+    }
+
+    fn discriminator_factory(&self) -> Box<dyn DiscriminatorFactory> {
+        Box::new(HttpRequestDiscriminatorFactory::new())
+    }
+
     fn proxy_protocol(&self) -> ProxyProtocol {
         ProxyProtocol::HTTP
     }
@@ -18,9 +34,61 @@ impl ProtocolPack for HttpProtocolPack {
         }
         HttpProtocolPack::find_url_host_name(data.as_slice())
     }
+
+    // Only the header block of something that starts like a request is touched; anything else,
+    // including a body that arrives in later packets, goes through as it is.
+    fn filter_request(&self, data: &PlainData, policy: &PrivacyPolicy) -> PlainData {
+        let bytes = data.as_slice();
+        let headers_end = match index_of(bytes, &b"\r\n\r\n"[..]) {
+            Some(index) => index + 2,
+            None => return data.clone(),
+        };
+        let first_line_end = index_of(bytes, &b"\r\n"[..]).expect("CRLF magically disappeared");
+        if index_of(&bytes[0..first_line_end], &b" HTTP/1."[..]).is_none() {
+            return data.clone();
+        }
+        let mut filtered = Vec::from(&bytes[0..first_line_end + 2]);
+        let mut dropping_continuation = false;
+        let mut begin = first_line_end + 2;
+        while begin < headers_end {
+            let end = index_of(&bytes[begin..headers_end], &b"\r\n"[..])
+                .expect("CRLF magically disappeared")
+                + begin;
+            let line = &bytes[begin..end];
+            let line_with_crlf = &bytes[begin..end + 2];
+            begin = end + 2;
+            if line.starts_with(b" ") || line.starts_with(b"\t") {
+                if !dropping_continuation {
+                    filtered.extend_from_slice(line_with_crlf);
+                }
+                continue;
+            }
+            let name = match index_of(line, &b":"[..]) {
+                Some(colon) => String::from_utf8_lossy(&line[0..colon]).to_string(),
+                None => String::new(),
+            };
+            match policy.treatment_of(&name) {
+                HeaderTreatment::Keep => {
+                    dropping_continuation = false;
+                    filtered.extend_from_slice(line_with_crlf);
+                }
+                HeaderTreatment::Strip => dropping_continuation = true,
+                HeaderTreatment::Replace(value) => {
+                    dropping_continuation = true;
+                    filtered.extend_from_slice(format!("{}: {}\r\n", name, value).as_bytes());
+                }
+            }
+        }
+        filtered.extend_from_slice(&bytes[headers_end..]);
+        PlainData::from(filtered)
+    }
 }
 
 impl HttpProtocolPack {
+    pub fn make() -> Box<dyn ProtocolPack> {
+        Box::new(HttpProtocolPack {})
+    }
+
     fn find_header_host_name(data: &[u8]) -> Option<String> {
         let idx = index_of(data, &b"\r\n\r\n"[..])?;
         let headers = &data[0..idx + 2];
@@ -73,6 +141,14 @@ impl HttpProtocolPack {
 mod tests {
     use super::*;
 
+    #[test]
+    fn knows_its_name_and_standard_ports() {
+        let subject = HttpProtocolPack {};
+
+        assert_eq!(subject.name(), "http");
+        assert_eq!(subject.standard_ports(), vec![80]);
+    }
+
     #[test]
     fn knows_its_protocol() {
         let result = HttpProtocolPack {}.proxy_protocol();
@@ -172,4 +248,48 @@ mod tests {
 
         assert_eq!(result, Some(String::from("www.example.com")));
     }
+
+    fn make_policy() -> PrivacyPolicy {
+        PrivacyPolicy::from_json(
+            r#"{"strip_headers": ["X-Forwarded-For", "DNT"], "replace_headers": {"Accept-Language": "en"}}"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn filter_request_strips_and_replaces_headers_but_leaves_the_body_alone() {
+        let data = PlainData::new(b"POST /index.html HTTP/1.1\r\nHost: example.com\r\nx-forwarded-for: 192.168.1.2\r\nAccept-Language: en-US,en;q=0.9,fr-CA;q=0.8\r\nDNT: 1\r\nContent-Length: 12\r\n\r\nDNT: 1\r\n\r\nab");
+
+        let result = HttpProtocolPack {}.filter_request(&data, &make_policy());
+
+        assert_eq!(
+            result,
+            PlainData::new(b"POST /index.html HTTP/1.1\r\nHost: example.com\r\nAccept-Language: en\r\nContent-Length: 12\r\n\r\nDNT: 1\r\n\r\nab")
+        );
+    }
+
+    #[test]
+    fn filter_request_follows_continuation_lines_to_their_headers() {
+        let data = PlainData::new(b"GET / HTTP/1.1\r\nDNT: 1\r\n  still DNT\r\nUser-Agent: booga\r\n\tstill User-Agent\r\n\r\n");
+
+        let result = HttpProtocolPack {}.filter_request(&data, &make_policy());
+
+        assert_eq!(
+            result,
+            PlainData::new(b"GET / HTTP/1.1\r\nUser-Agent: booga\r\n\tstill User-Agent\r\n\r\n")
+        );
+    }
+
+    #[test]
+    fn filter_request_leaves_data_that_is_not_a_request_head_alone() {
+        let body_fragment = PlainData::new(b"DNT: 1\r\n\r\n");
+        let unfinished_head = PlainData::new(b"GET / HTTP/1.1\r\nDNT: 1\r\n");
+
+        let body_result = HttpProtocolPack {}.filter_request(&body_fragment, &make_policy());
+        let unfinished_result =
+            HttpProtocolPack {}.filter_request(&unfinished_head, &make_policy());
+
+        assert_eq!(body_result, body_fragment);
+        assert_eq!(unfinished_result, unfinished_head);
+    }
 }
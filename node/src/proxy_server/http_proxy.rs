@@ -0,0 +1,465 @@
+// Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use crate::sub_lib::utils::index_of;
+
+// Anything longer is taken for something other than an HTTP client
+const MAX_REQUEST_HEAD_LENGTH: usize = 16384;
+
+const CONNECTION_ESTABLISHED: &str = "200 Connection established";
+const BAD_REQUEST: &str = "400 Bad Request";
+const FORBIDDEN: &str = "403 Forbidden";
+const REQUEST_HEADER_FIELDS_TOO_LARGE: &str = "431 Request Header Fields Too Large";
+
+// Meant for the proxy, or for the connection to it, and not for the target
+const HOP_BY_HOP_HEADERS: &[&str] = &[
+    "connection",
+    "keep-alive",
+    "proxy-authorization",
+    "proxy-connection",
+];
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct HttpProxyTarget {
+    pub host: String,
+    pub port: u16,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum HttpProxyEvent {
+    // Goes back to the client; numbered ahead of anything the exit sends
+    Reply {
+        data: Vec<u8>,
+        last_data: bool,
+        sequence_number: u64,
+    },
+    // Goes on to the target; numbered from zero
+    Data {
+        data: Vec<u8>,
+        last_data: bool,
+        sequence_number: u64,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum State {
+    AwaitingRequest,
+    Connected(HttpProxyTarget),
+    Closed,
+}
+
+// One client of an HTTP proxy. A CONNECT becomes a tunnel: the client is told it's connected,
+// and everything after that goes to the target untouched. Any other method must name an
+// http:// URL; that request goes to the target in origin form with Connection: close, so that
+// the client can't send its next request, maybe for some other host, down the same stream.
+// As with SOCKS5, a permitted target is taken to be reachable; if it isn't, the stream closes.
+pub struct HttpProxySession {
+    state: State,
+    data_so_far: Vec<u8>,
+    replies_sent: u64,
+    requests_sent: u64,
+}
+
+impl HttpProxySession {
+    pub fn new() -> HttpProxySession {
+        HttpProxySession {
+            state: State::AwaitingRequest,
+            data_so_far: vec![],
+            replies_sent: 0,
+            requests_sent: 0,
+        }
+    }
+
+    pub fn accept<F>(&mut self, data: &[u8], last_data: bool, permits: F) -> Vec<HttpProxyEvent>
+    where
+        F: Fn(&HttpProxyTarget) -> bool,
+    {
+        self.data_so_far.extend(data);
+        let mut events = vec![];
+        match self.state.clone() {
+            State::AwaitingRequest => match self.take_request() {
+                None => (),
+                Some(Err(status)) => events.push(self.refuse(status)),
+                Some(Ok((target, request_head_opt))) => {
+                    if !permits(&target) {
+                        events.push(self.refuse(FORBIDDEN));
+                        return events;
+                    }
+                    match request_head_opt {
+                        None => events.push(self.reply(
+                            HttpProxySession::status_reply(CONNECTION_ESTABLISHED, false),
+                            false,
+                        )),
+                        Some(mut request_head) => {
+                            request_head.extend(self.data_so_far.drain(..));
+                            self.data_so_far = request_head;
+                        }
+                    }
+                    self.state = State::Connected(target);
+                    events.extend(self.pass_on(last_data));
+                }
+            },
+            State::Connected(_) => events.extend(self.pass_on(last_data)),
+            State::Closed => self.data_so_far.clear(),
+        }
+        events
+    }
+
+    pub fn target(&self) -> Option<&HttpProxyTarget> {
+        match self.state {
+            State::Connected(ref target) => Some(target),
+            _ => None,
+        }
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.state == State::Closed
+    }
+
+    // What to add to the exit's sequence numbers so that they follow the CONNECT reply
+    pub fn replies_sent(&self) -> u64 {
+        self.replies_sent
+    }
+
+    fn pass_on(&mut self, last_data: bool) -> Option<HttpProxyEvent> {
+        if self.data_so_far.is_empty() && !last_data {
+            return None;
+        }
+        let sequence_number = self.requests_sent;
+        self.requests_sent += 1;
+        Some(HttpProxyEvent::Data {
+            data: self.data_so_far.drain(..).collect(),
+            last_data,
+            sequence_number,
+        })
+    }
+
+    fn refuse(&mut self, status: &str) -> HttpProxyEvent {
+        self.state = State::Closed;
+        self.data_so_far.clear();
+        self.reply(HttpProxySession::status_reply(status, true), true)
+    }
+
+    fn reply(&mut self, data: Vec<u8>, last_data: bool) -> HttpProxyEvent {
+        let sequence_number = self.replies_sent;
+        self.replies_sent += 1;
+        HttpProxyEvent::Reply {
+            data,
+            last_data,
+            sequence_number,
+        }
+    }
+
+    // The target, plus the request head to send it; no head for a CONNECT
+    fn take_request(&mut self) -> Option<Result<(HttpProxyTarget, Option<Vec<u8>>), &'static str>> {
+        let head_length = match index_of(&self.data_so_far, &b"\r\n\r\n"[..]) {
+            Some(index) => index + 4,
+            None if self.data_so_far.len() > MAX_REQUEST_HEAD_LENGTH => {
+                return Some(Err(REQUEST_HEADER_FIELDS_TOO_LARGE))
+            }
+            None => return None,
+        };
+        let head: Vec<u8> = self.data_so_far.drain(..head_length).collect();
+        let head = match String::from_utf8(head) {
+            Ok(head) => head,
+            Err(_) => return Some(Err(BAD_REQUEST)),
+        };
+        let mut lines = head[..head_length - 4].split("\r\n");
+        let request_line: Vec<&str> = lines.next().unwrap_or("").split(' ').collect();
+        if request_line.len() != 3 || !request_line[2].starts_with("HTTP/1.") {
+            return Some(Err(BAD_REQUEST));
+        }
+        let (method, request_target, version) = (request_line[0], request_line[1], request_line[2]);
+        if method == "CONNECT" {
+            return Some(
+                HttpProxySession::parse_authority(request_target, None)
+                    .map(|target| (target, None))
+                    .ok_or(BAD_REQUEST),
+            );
+        }
+        if !request_target.to_lowercase().starts_with("http://") {
+            return Some(Err(BAD_REQUEST));
+        }
+        let after_scheme = &request_target["http://".len()..];
+        let authority_end = after_scheme
+            .find(|c: char| c == '/' || c == '?')
+            .unwrap_or(after_scheme.len());
+        let target =
+            match HttpProxySession::parse_authority(&after_scheme[..authority_end], Some(80)) {
+                Some(target) => target,
+                None => return Some(Err(BAD_REQUEST)),
+            };
+        let path = match &after_scheme[authority_end..] {
+            "" => String::from("/"),
+            path if path.starts_with('?') => format!("/{}", path),
+            path => String::from(path),
+        };
+        let mut request_head = format!("{} {} {}\r\n", method, path, version);
+        let mut dropping_continuation = false;
+        for line in lines {
+            if line.starts_with(' ') || line.starts_with('\t') {
+                if !dropping_continuation {
+                    request_head.push_str(&format!("{}\r\n", line));
+                }
+                continue;
+            }
+            let name = line.split(':').next().unwrap_or("").trim().to_lowercase();
+            dropping_continuation = HOP_BY_HOP_HEADERS.contains(&name.as_str());
+            if !dropping_continuation {
+                request_head.push_str(&format!("{}\r\n", line));
+            }
+        }
+        request_head.push_str("Connection: close\r\n\r\n");
+        Some(Ok((target, Some(request_head.into_bytes()))))
+    }
+
+    // host:port, with brackets around an IPv6 address
+    fn parse_authority(authority: &str, default_port: Option<u16>) -> Option<HttpProxyTarget> {
+        let (host, port_str) = if authority.starts_with('[') {
+            let close = authority.find(']')?;
+            let rest = &authority[close + 1..];
+            let port_str = if rest.is_empty() {
+                None
+            } else if rest.starts_with(':') {
+                Some(&rest[1..])
+            } else {
+                return None;
+            };
+            (&authority[1..close], port_str)
+        } else {
+            match authority.rfind(':') {
+                Some(colon) => (&authority[..colon], Some(&authority[colon + 1..])),
+                None => (authority, None),
+            }
+        };
+        let port = match port_str {
+            Some(port_str) => match port_str.parse::<u16>() {
+                Ok(0) | Err(_) => return None,
+                Ok(port) => port,
+            },
+            None => default_port?,
+        };
+        if host.is_empty() {
+            return None;
+        }
+        Some(HttpProxyTarget {
+            host: String::from(host),
+            port,
+        })
+    }
+
+    fn status_reply(status: &str, closing: bool) -> Vec<u8> {
+        let headers = if closing {
+            "Content-Length: 0\r\nConnection: close\r\n"
+        } else {
+            ""
+        };
+        format!("HTTP/1.1 {}\r\n{}\r\n", status, headers).into_bytes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn permit_all(_: &HttpProxyTarget) -> bool {
+        true
+    }
+
+    fn reply(status: &str, last_data: bool, sequence_number: u64) -> HttpProxyEvent {
+        HttpProxyEvent::Reply {
+            data: HttpProxySession::status_reply(status, last_data),
+            last_data,
+            sequence_number,
+        }
+    }
+
+    fn data(data: &[u8], last_data: bool, sequence_number: u64) -> HttpProxyEvent {
+        HttpProxyEvent::Data {
+            data: data.to_vec(),
+            last_data,
+            sequence_number,
+        }
+    }
+
+    #[test]
+    fn connect_leads_to_a_tunnel_and_data_numbered_from_zero() {
+        let mut subject = HttpProxySession::new();
+
+        let connect_events = subject.accept(
+            b"CONNECT booga.com:443 HTTP/1.1\r\nHost: booga.com:443\r\n\r\n",
+            false,
+            permit_all,
+        );
+        let data_events = subject.accept(b"hello", false, permit_all);
+        let closing_events = subject.accept(&[], true, permit_all);
+
+        assert_eq!(
+            connect_events,
+            vec![reply(CONNECTION_ESTABLISHED, false, 0)]
+        );
+        assert_eq!(
+            connect_events[0],
+            HttpProxyEvent::Reply {
+                data: b"HTTP/1.1 200 Connection established\r\n\r\n".to_vec(),
+                last_data: false,
+                sequence_number: 0
+            }
+        );
+        assert_eq!(data_events, vec![data(b"hello", false, 0)]);
+        assert_eq!(closing_events, vec![data(b"", true, 1)]);
+        assert_eq!(
+            subject.target(),
+            Some(&HttpProxyTarget {
+                host: String::from("booga.com"),
+                port: 443
+            })
+        );
+        assert_eq!(subject.replies_sent(), 1);
+    }
+
+    #[test]
+    fn connect_survives_fragmentation_and_pipelining() {
+        let mut subject = HttpProxySession::new();
+        let everything = b"CONNECT [2001:db8::1]:443 HTTP/1.1\r\n\r\nclient hello";
+
+        let mut events = subject.accept(&everything[..20], false, permit_all);
+        events.extend(subject.accept(&everything[20..], false, permit_all));
+
+        assert_eq!(
+            events,
+            vec![
+                reply(CONNECTION_ESTABLISHED, false, 0),
+                data(b"client hello", false, 0)
+            ]
+        );
+        assert_eq!(
+            subject.target(),
+            Some(&HttpProxyTarget {
+                host: String::from("2001:db8::1"),
+                port: 443
+            })
+        );
+    }
+
+    #[test]
+    fn absolute_uri_request_goes_to_the_target_in_origin_form_without_proxy_headers() {
+        let mut subject = HttpProxySession::new();
+
+        let events = subject.accept(
+            b"GET http://booga.com/index.html?q=1 HTTP/1.1\r\nHost: booga.com\r\nProxy-Connection: keep-alive\r\nProxy-Authorization: Basic Ym9vZ2E=\r\n  continued\r\nAccept: */*\r\n\r\n",
+            false,
+            permit_all,
+        );
+
+        assert_eq!(
+            events,
+            vec![data(
+                b"GET /index.html?q=1 HTTP/1.1\r\nHost: booga.com\r\nAccept: */*\r\nConnection: close\r\n\r\n",
+                false,
+                0
+            )]
+        );
+        assert_eq!(
+            subject.target(),
+            Some(&HttpProxyTarget {
+                host: String::from("booga.com"),
+                port: 80
+            })
+        );
+        assert_eq!(subject.replies_sent(), 0);
+    }
+
+    #[test]
+    fn absolute_uri_request_keeps_its_body_and_port() {
+        let mut subject = HttpProxySession::new();
+
+        let events = subject.accept(
+            b"POST http://booga.com:8080 HTTP/1.1\r\nConnection: keep-alive\r\nContent-Length: 8\r\n\r\nbody",
+            false,
+            permit_all,
+        );
+        let later_events = subject.accept(b"body", true, permit_all);
+
+        assert_eq!(
+            events,
+            vec![data(
+                b"POST / HTTP/1.1\r\nContent-Length: 8\r\nConnection: close\r\n\r\nbody",
+                false,
+                0
+            )]
+        );
+        assert_eq!(later_events, vec![data(b"body", true, 1)]);
+        assert_eq!(subject.target().unwrap().port, 8080);
+    }
+
+    #[test]
+    fn nothing_happens_until_the_request_head_is_complete() {
+        let mut subject = HttpProxySession::new();
+
+        let events = subject.accept(b"CONNECT booga.com:443 HTTP/1.1\r\n", false, permit_all);
+
+        assert_eq!(events, vec![]);
+        assert_eq!(subject.target(), None);
+        assert_eq!(subject.is_closed(), false);
+    }
+
+    #[test]
+    fn requests_that_are_not_for_a_proxy_are_refused() {
+        vec![
+            &b"GET /index.html HTTP/1.1\r\nHost: booga.com\r\n\r\n"[..],
+            &b"GET https://booga.com/ HTTP/1.1\r\n\r\n"[..],
+            &b"CONNECT booga.com HTTP/1.1\r\n\r\n"[..],
+            &b"CONNECT booga.com:0 HTTP/1.1\r\n\r\n"[..],
+            &b"\x16\x03\x01\x02\x00\x01\x00\x01\xfc\x03\x03\r\n\r\n"[..],
+        ]
+        .into_iter()
+        .for_each(|request| {
+            let mut subject = HttpProxySession::new();
+
+            let events = subject.accept(request, false, permit_all);
+
+            assert_eq!(
+                events,
+                vec![reply(BAD_REQUEST, true, 0)],
+                "{:?}",
+                String::from_utf8_lossy(request)
+            );
+            assert_eq!(subject.is_closed(), true);
+        });
+    }
+
+    #[test]
+    fn a_head_that_never_ends_is_refused() {
+        let mut subject = HttpProxySession::new();
+
+        let events = subject.accept(&[b'A'; MAX_REQUEST_HEAD_LENGTH + 1], false, permit_all);
+
+        assert_eq!(
+            events,
+            vec![reply(REQUEST_HEADER_FIELDS_TOO_LARGE, true, 0)]
+        );
+    }
+
+    #[test]
+    fn targets_that_are_not_permitted_are_refused_and_nothing_more_is_accepted() {
+        let mut subject = HttpProxySession::new();
+
+        let events = subject.accept(
+            b"CONNECT booga.com:6881 HTTP/1.1\r\n\r\n",
+            false,
+            |target| target.port != 6881,
+        );
+        let later_events = subject.accept(b"hello", false, permit_all);
+
+        assert_eq!(
+            events,
+            vec![HttpProxyEvent::Reply {
+                data: b"HTTP/1.1 403 Forbidden\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                    .to_vec(),
+                last_data: true,
+                sequence_number: 0
+            }]
+        );
+        assert_eq!(later_events, vec![]);
+        assert_eq!(subject.target(), None);
+    }
+}
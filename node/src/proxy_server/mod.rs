@@ -1,7 +1,17 @@
 // Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
 
+pub mod adaptive_hops;
+pub mod client_hello_buffer;
 pub mod client_request_payload_factory;
+pub mod destination_hints;
 pub mod http_protocol_pack;
+pub mod http_proxy;
+pub mod privacy_policy;
 pub mod protocol_pack;
 pub mod proxy_server;
+pub mod retransmission_buffer;
+pub mod retryable_requests;
+pub mod sla_monitor;
+pub mod socks5;
 pub mod tls_protocol_pack;
+pub mod upload_window;
@@ -0,0 +1,153 @@
+// Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use serde_derive::Deserialize;
+use serde_json;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum HeaderTreatment {
+    Keep,
+    Strip,
+    Replace(String),
+}
+
+// Which request headers the originating Node takes out, or rewrites, before a request leaves it.
+// Read from a JSON file named by --privacy_policy, for example:
+//
+//     {
+//         "strip_headers": ["X-Forwarded-For", "Via", "DNT"],
+//         "replace_headers": {"Accept-Language": "en-US,en;q=0.5"}
+//     }
+//
+// Header names are matched without regard to case. Only protocols whose requests the Node can read
+// can be filtered; TLS goes through untouched.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct PrivacyPolicy {
+    #[serde(default)]
+    strip_headers: Vec<String>,
+    #[serde(default)]
+    replace_headers: HashMap<String, String>,
+}
+
+impl PrivacyPolicy {
+    pub fn load(path: &Path) -> Result<PrivacyPolicy, String> {
+        let json = match fs::read_to_string(path) {
+            Ok(json) => json,
+            Err(e) => return Err(format!("Couldn't read privacy policy {:?}: {}", path, e)),
+        };
+        PrivacyPolicy::from_json(&json)
+            .map_err(|e| format!("Couldn't understand privacy policy {:?}: {}", path, e))
+    }
+
+    pub fn from_json(json: &str) -> Result<PrivacyPolicy, String> {
+        serde_json::from_str(json).map_err(|e| format!("{}", e))
+    }
+
+    pub fn treatment_of(&self, header_name: &str) -> HeaderTreatment {
+        let header_name = header_name.trim();
+        if self
+            .strip_headers
+            .iter()
+            .any(|name| name.eq_ignore_ascii_case(header_name))
+        {
+            return HeaderTreatment::Strip;
+        }
+        match self
+            .replace_headers
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(header_name))
+        {
+            Some((_, value)) => HeaderTreatment::Replace(value.clone()),
+            None => HeaderTreatment::Keep,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    const BASE_TEST_DIR: &str = "generated/test/privacy_policy";
+
+    fn write_policy_file(name: &str, contents: &str) -> PathBuf {
+        let directory = PathBuf::from(format!("{}/{}", BASE_TEST_DIR, name));
+        fs::remove_dir_all(&directory).is_ok();
+        fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("policy.json");
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn headers_are_treated_as_the_policy_says_regardless_of_case() {
+        let subject = PrivacyPolicy::from_json(
+            r#"{"strip_headers": ["X-Forwarded-For", "DNT"], "replace_headers": {"Accept-Language": "en"}}"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            subject.treatment_of("x-forwarded-for"),
+            HeaderTreatment::Strip
+        );
+        assert_eq!(subject.treatment_of(" DNT "), HeaderTreatment::Strip);
+        assert_eq!(
+            subject.treatment_of("ACCEPT-LANGUAGE"),
+            HeaderTreatment::Replace(String::from("en"))
+        );
+        assert_eq!(subject.treatment_of("Host"), HeaderTreatment::Keep);
+    }
+
+    #[test]
+    fn either_part_of_the_policy_may_be_left_out() {
+        let subject = PrivacyPolicy::from_json("{}").unwrap();
+
+        assert_eq!(subject.treatment_of("DNT"), HeaderTreatment::Keep);
+    }
+
+    #[test]
+    fn load_reads_the_policy_from_a_file() {
+        let path = write_policy_file(
+            "load_reads_the_policy_from_a_file",
+            r#"{"strip_headers": ["Via"]}"#,
+        );
+
+        let result = PrivacyPolicy::load(&path).unwrap();
+
+        assert_eq!(result.treatment_of("Via"), HeaderTreatment::Strip);
+    }
+
+    #[test]
+    fn load_complains_about_a_missing_file() {
+        let path = PathBuf::from(format!("{}/nonexistent/policy.json", BASE_TEST_DIR));
+
+        let result = PrivacyPolicy::load(&path);
+
+        let message = result.err().unwrap();
+        assert_eq!(
+            message.starts_with(&format!("Couldn't read privacy policy {:?}: ", path)),
+            true,
+            "{}",
+            message
+        );
+    }
+
+    #[test]
+    fn load_complains_about_a_file_that_is_not_a_policy() {
+        let path = write_policy_file(
+            "load_complains_about_a_file_that_is_not_a_policy",
+            r#"{"strip_headers": "Via"}"#,
+        );
+
+        let result = PrivacyPolicy::load(&path);
+
+        let message = result.err().unwrap();
+        assert_eq!(
+            message.starts_with(&format!("Couldn't understand privacy policy {:?}: ", path)),
+            true,
+            "{}",
+            message
+        );
+    }
+}
@@ -1,8 +1,117 @@
 // Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use crate::discriminator::DiscriminatorFactory;
+use crate::proxy_server::http_protocol_pack::HttpProtocolPack;
+use crate::proxy_server::privacy_policy::PrivacyPolicy;
+use crate::proxy_server::tls_protocol_pack::TlsProtocolPack;
 use crate::sub_lib::cryptde::PlainData;
 use crate::sub_lib::proxy_server::ProxyProtocol;
+use std::collections::HashMap;
 
 pub trait ProtocolPack: Send + Sync {
+    fn name(&self) -> &'static str;
+    fn standard_ports(&self) -> Vec<u16>;
+    fn discriminator_factory(&self) -> Box<dyn DiscriminatorFactory>;
     fn proxy_protocol(&self) -> ProxyProtocol;
     fn find_host_name(&self, data: &PlainData) -> Option<String>;
+    fn filter_request(&self, data: &PlainData, policy: &PrivacyPolicy) -> PlainData;
+}
+
+pub type ProtocolPackMaker = fn() -> Box<dyn ProtocolPack>;
+
+// Every protocol pack the Node knows about. A new pack is a module with a ProtocolPack
+// implementation plus a line here; which ports it serves can then be chosen with --protocol_packs.
+pub const PROTOCOL_PACK_MAKERS: &[ProtocolPackMaker] =
+    &[HttpProtocolPack::make, TlsProtocolPack::make];
+
+pub fn protocol_pack_named(name: &str) -> Option<Box<dyn ProtocolPack>> {
+    PROTOCOL_PACK_MAKERS
+        .iter()
+        .map(|maker| maker())
+        .find(|protocol_pack| protocol_pack.name() == name)
+}
+
+pub fn protocol_pack_names() -> Vec<&'static str> {
+    PROTOCOL_PACK_MAKERS
+        .iter()
+        .map(|maker| maker().name())
+        .collect()
+}
+
+// Each pack on its standard ports: what the Node does when --protocol_packs isn't given
+pub fn standard_protocol_pack_ports() -> HashMap<u16, String> {
+    PROTOCOL_PACK_MAKERS
+        .iter()
+        .map(|maker| maker())
+        .flat_map(|protocol_pack| {
+            let name = protocol_pack.name();
+            protocol_pack
+                .standard_ports()
+                .into_iter()
+                .map(move |port| (port, String::from(name)))
+        })
+        .collect()
+}
+
+pub fn protocol_packs_for(
+    protocol_pack_ports: &HashMap<u16, String>,
+) -> HashMap<u16, Box<dyn ProtocolPack>> {
+    protocol_pack_ports
+        .iter()
+        .map(|(port, name)| {
+            let protocol_pack = protocol_pack_named(name)
+                .expect(format!("No protocol pack named '{}'", name).as_str());
+            (*port, protocol_pack)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registry_knows_http_and_tls() {
+        assert_eq!(protocol_pack_names(), vec!["http", "tls"]);
+        assert_eq!(
+            protocol_pack_named("http").unwrap().proxy_protocol(),
+            ProxyProtocol::HTTP
+        );
+        assert_eq!(
+            protocol_pack_named("tls").unwrap().proxy_protocol(),
+            ProxyProtocol::TLS
+        );
+        assert_eq!(protocol_pack_named("booga").is_none(), true);
+    }
+
+    #[test]
+    fn standard_protocol_pack_ports_are_80_and_443() {
+        let result = standard_protocol_pack_ports();
+
+        let mut expected = HashMap::new();
+        expected.insert(80, String::from("http"));
+        expected.insert(443, String::from("tls"));
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn protocol_packs_for_maps_ports_to_named_packs() {
+        let mut protocol_pack_ports = HashMap::new();
+        protocol_pack_ports.insert(8080, String::from("http"));
+        protocol_pack_ports.insert(8443, String::from("tls"));
+
+        let result = protocol_packs_for(&protocol_pack_ports);
+
+        assert_eq!(result.len(), 2);
+        assert_eq!(result.get(&8080).unwrap().name(), "http");
+        assert_eq!(result.get(&8443).unwrap().name(), "tls");
+    }
+
+    #[test]
+    #[should_panic(expected = "No protocol pack named 'booga'")]
+    fn protocol_packs_for_complains_about_unknown_names() {
+        let mut protocol_pack_ports = HashMap::new();
+        protocol_pack_ports.insert(8080, String::from("booga"));
+
+        protocol_packs_for(&protocol_pack_ports);
+    }
 }
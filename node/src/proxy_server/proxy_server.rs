@@ -1,5 +1,23 @@
 // Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use crate::proxy_server::adaptive_hops::AdaptiveHops;
+use crate::proxy_server::client_hello_buffer::ClientHelloBuffer;
+use crate::proxy_server::client_hello_buffer::CLIENT_HELLO_TIMEOUT;
+use crate::proxy_server::client_hello_buffer::MAX_CLIENT_HELLO_BYTES;
 use crate::proxy_server::client_request_payload_factory::ClientRequestPayloadFactory;
+use crate::proxy_server::destination_hints::DestinationHintsCache;
+use crate::proxy_server::destination_hints::DESTINATION_HINTS_CAPACITY;
+use crate::proxy_server::http_proxy::HttpProxyEvent;
+use crate::proxy_server::http_proxy::HttpProxySession;
+use crate::proxy_server::privacy_policy::PrivacyPolicy;
+use crate::proxy_server::protocol_pack::standard_protocol_pack_ports;
+use crate::proxy_server::retransmission_buffer::RetransmissionBuffer;
+use crate::proxy_server::retryable_requests::RetryableRequests;
+use crate::proxy_server::retryable_requests::STALLED_REQUEST_TIMEOUT;
+use crate::proxy_server::sla_monitor::SlaMonitor;
+use crate::proxy_server::socks5::Socks5Event;
+use crate::proxy_server::socks5::Socks5Session;
+use crate::proxy_server::upload_window::UploadWindow;
+use crate::sub_lib::accountant::CorrelationId;
 use crate::sub_lib::accountant::ReportExitServiceConsumedMessage;
 use crate::sub_lib::accountant::ReportRoutingServiceConsumedMessage;
 use crate::sub_lib::bidi_hashmap::BidiHashMap;
@@ -9,41 +27,63 @@ use crate::sub_lib::dispatcher::Endpoint;
 use crate::sub_lib::dispatcher::InboundClientData;
 use crate::sub_lib::hopper::ExpiredCoresPackage;
 use crate::sub_lib::hopper::IncipientCoresPackage;
-use crate::sub_lib::hopper::TEMPORARY_PER_ROUTING_BYTE_RATE;
-use crate::sub_lib::hopper::TEMPORARY_PER_ROUTING_RATE;
 use crate::sub_lib::http_server_impersonator;
 use crate::sub_lib::logger::Logger;
 use crate::sub_lib::neighborhood::ExpectedService;
 use crate::sub_lib::neighborhood::ExpectedServices;
+use crate::sub_lib::neighborhood::NodeProblem;
+use crate::sub_lib::neighborhood::NodeProblemReport;
+use crate::sub_lib::neighborhood::RateCard;
+use crate::sub_lib::neighborhood::RouteFeedbackMessage;
+use crate::sub_lib::neighborhood::RouteOutcome;
 use crate::sub_lib::neighborhood::RouteQueryMessage;
 use crate::sub_lib::neighborhood::RouteQueryResponse;
+use crate::sub_lib::neighborhood::RouteRefusal;
 use crate::sub_lib::peer_actors::BindMessage;
+use crate::sub_lib::peer_actors::ShutdownMessage;
+use crate::sub_lib::peer_actors::StreamCountQueryMessage;
 use crate::sub_lib::proxy_client::ClientResponsePayload;
-use crate::sub_lib::proxy_client::TEMPORARY_PER_EXIT_BYTE_RATE;
-use crate::sub_lib::proxy_client::TEMPORARY_PER_EXIT_RATE;
+use crate::sub_lib::proxy_server::AdaptiveHopsConfig;
+use crate::sub_lib::proxy_server::AdaptiveHopsMessage;
 use crate::sub_lib::proxy_server::AddReturnRouteMessage;
+use crate::sub_lib::proxy_server::AddRouteMessage;
 use crate::sub_lib::proxy_server::ClientRequestPayload;
 use crate::sub_lib::proxy_server::ProxyProtocol;
 use crate::sub_lib::proxy_server::ProxyServerSubs;
+use crate::sub_lib::proxy_server::RetransmissionConfig;
 use crate::sub_lib::route::Route;
+use crate::sub_lib::sequence_buffer::SequencedPacket;
 use crate::sub_lib::stream_handler_pool::TransmitDataMsg;
 use crate::sub_lib::stream_key::StreamKey;
 use crate::sub_lib::ttl_hashmap::TtlHashMap;
+use crate::sub_lib::ui_gateway::ReloadConfigMessage;
+use crate::sub_lib::ui_gateway::UiAlert;
+use crate::sub_lib::ui_gateway::UiMessage;
 use crate::sub_lib::utils::NODE_MAILBOX_CAPACITY;
 use crate::sub_lib::wallet::Wallet;
 use actix::Actor;
 use actix::Addr;
+use actix::AsyncContext;
 use actix::Context;
 use actix::Handler;
 use actix::MailboxError;
+use actix::MessageResult;
 use actix::Recipient;
 use actix::Syn;
+use std::collections::HashMap;
+use std::fs;
+use std::net::IpAddr;
 use std::net::SocketAddr;
+use std::path::Path;
+use std::path::PathBuf;
 use std::time::Duration;
+use std::time::Instant;
+use std::time::SystemTime;
 use tokio;
 use tokio::prelude::Future;
 
 pub const RETURN_ROUTE_TTL: Duration = Duration::from_secs(120);
+pub const DEFAULT_MINIMUM_HOP_COUNT: usize = 2;
 
 pub struct ProxyServer {
     dispatcher: Option<Recipient<Syn, TransmitDataMsg>>,
@@ -51,14 +91,39 @@ pub struct ProxyServer {
     accountant_exit: Option<Recipient<Syn, ReportExitServiceConsumedMessage>>,
     accountant_routing: Option<Recipient<Syn, ReportRoutingServiceConsumedMessage>>,
     route_source: Option<Recipient<Syn, RouteQueryMessage>>,
+    route_feedback: Option<Recipient<Syn, RouteFeedbackMessage>>,
+    node_problem: Option<Recipient<Syn, NodeProblemReport>>,
     add_return_route: Option<Recipient<Syn, AddReturnRouteMessage>>,
+    add_route: Option<Recipient<Syn, AddRouteMessage>>,
+    ui_message_sub: Option<Recipient<Syn, UiMessage>>,
     client_request_payload_factory: ClientRequestPayloadFactory,
+    client_hello_buffer: ClientHelloBuffer,
     stream_key_factory: Box<dyn StreamKeyFactory>,
     keys_and_addrs: BidiHashMap<StreamKey, SocketAddr>,
     is_decentralized: bool, // TODO: This should be replaced by something more general and configurable.
     cryptde: &'static dyn CryptDE,
     logger: Logger,
     route_ids_to_services: TtlHashMap<u32, Vec<ExpectedService>>,
+    round_trips: HashMap<u32, (Vec<PublicKey>, Instant)>,
+    routes: HashMap<StreamKey, (RouteQueryResponse, Instant)>,
+    // For each stream still open, the empty last packet that would tell its exit it's over
+    closing_requests: HashMap<StreamKey, (ClientRequestPayload, SocketAddr)>,
+    upload_window_size: Option<u64>,
+    upload_windows: HashMap<StreamKey, UploadWindow<(ClientRequestPayload, SocketAddr)>>,
+    retransmission_config: Option<RetransmissionConfig>,
+    retransmission_buffers: HashMap<StreamKey, RetransmissionBuffer>,
+    retransmission_spills: u64,
+    retryable_requests: RetryableRequests,
+    stalled_request_timeout: Duration,
+    socks_port: Option<u16>,
+    socks_sessions: HashMap<SocketAddr, Socks5Session>,
+    http_proxy_port: Option<u16>,
+    http_proxy_sessions: HashMap<SocketAddr, HttpProxySession>,
+    privacy_policy_path: Option<PathBuf>,
+    exit_location_opt: Option<String>,
+    sla_monitor: SlaMonitor,
+    adaptive_hops: Option<AdaptiveHops>,
+    destination_hints: DestinationHintsCache,
 }
 
 impl Actor for ProxyServer {
@@ -75,7 +140,13 @@ impl Handler<BindMessage> for ProxyServer {
         self.accountant_exit = Some(msg.peer_actors.accountant.report_exit_service_consumed);
         self.accountant_routing = Some(msg.peer_actors.accountant.report_routing_service_consumed);
         self.route_source = Some(msg.peer_actors.neighborhood.route_query);
+        self.route_feedback = Some(msg.peer_actors.neighborhood.route_feedback);
+        self.node_problem = Some(msg.peer_actors.neighborhood.node_problem);
         self.add_return_route = Some(msg.peer_actors.proxy_server.add_return_route);
+        self.add_route = Some(msg.peer_actors.proxy_server.add_route);
+        self.ui_message_sub = Some(msg.peer_actors.ui_gateway.ui_message_sub);
+        self.schedule_stalled_request_check(ctx);
+        self.schedule_client_hello_sweep(ctx);
         ()
     }
 }
@@ -84,64 +155,26 @@ impl Handler<InboundClientData> for ProxyServer {
     type Result = ();
 
     fn handle(&mut self, msg: InboundClientData, _ctx: &mut Self::Context) -> Self::Result {
-        let cryptde = self.cryptde.clone();
-        let route_source = self
-            .route_source
-            .as_ref()
-            .expect("Neighborhood unbound in ProxyServer")
-            .clone();
-        let hopper = self
-            .hopper
-            .as_ref()
-            .expect("Hopper unbound in ProxyServer")
-            .clone();
-        let accountant_exit_sub = self
-            .accountant_exit
-            .as_ref()
-            .expect("Accountant unbound in ProxyServer")
-            .clone();
-        let accountant_routing_sub = self
-            .accountant_routing
-            .as_ref()
-            .expect("Accountant unbound in ProxyServer")
-            .clone();
-        let dispatcher = self
-            .dispatcher
-            .as_ref()
-            .expect("Dispatcher unbound in ProxyServer")
-            .clone();
-        let add_return_route_sub = self
-            .add_return_route
-            .as_ref()
-            .expect("ProxyServer unbound in ProxyServer")
-            .clone();
-        let source_addr = msg.peer_addr;
-        let payload = match self.make_payload(msg) {
-            Ok(payload) => payload,
-            Err(_) => return (),
+        if self.socks_port.is_some() && msg.reception_port == self.socks_port {
+            self.route_socks_client_data(msg);
+            return ();
+        }
+        if self.http_proxy_port.is_some() && msg.reception_port == self.http_proxy_port {
+            self.route_http_proxy_client_data(msg);
+            return ();
+        }
+        let (msgs, host_name_opt) = match self
+            .client_request_payload_factory
+            .protocol_for(msg.reception_port)
+        {
+            Some(ProxyProtocol::TLS) => match self.client_hello_buffer.accept(msg, &self.logger) {
+                Some(released) => released,
+                None => return (),
+            },
+            _ => (vec![msg], None),
         };
-        let logger = self.logger.clone();
-        let minimum_hop_count = if self.is_decentralized { 2 } else { 0 };
-        tokio::spawn(
-            route_source
-                .send(RouteQueryMessage::data_indefinite_route_request(
-                    minimum_hop_count,
-                ))
-                .then(move |route_result| {
-                    ProxyServer::try_transmit_to_hopper(
-                        cryptde,
-                        hopper,
-                        route_result,
-                        payload,
-                        logger,
-                        source_addr,
-                        dispatcher,
-                        accountant_exit_sub,
-                        accountant_routing_sub,
-                        add_return_route_sub,
-                    )
-                }),
-        );
+        msgs.into_iter()
+            .for_each(|msg| self.route_inbound_client_data(msg, &host_name_opt));
         ()
     }
 }
@@ -152,10 +185,112 @@ impl Handler<AddReturnRouteMessage> for ProxyServer {
     fn handle(&mut self, msg: AddReturnRouteMessage, _ctx: &mut Self::Context) -> Self::Result {
         self.route_ids_to_services
             .insert(msg.return_route_id, msg.expected_services);
+        // A cached route is timed from the first request sent over it that hasn't been answered
+        let now = Instant::now();
+        self.round_trips
+            .retain(|_, (_, sent)| now.duration_since(*sent) < RETURN_ROUTE_TTL);
+        if !msg.route_keys.is_empty() {
+            self.round_trips
+                .entry(msg.return_route_id)
+                .or_insert((msg.route_keys, now));
+        }
+        ()
+    }
+}
+
+// Each stream with a route still cached gets its closing request over that route, as a cancel, so
+// the exit closes its connection to the server now rather than when the stream times out. Requests still
+// held by an upload window are dropped; so are streams whose routes are gone, since asking the
+// Neighborhood for another would take longer than the Node has left.
+impl Handler<ShutdownMessage> for ProxyServer {
+    type Result = ();
+
+    fn handle(&mut self, _msg: ShutdownMessage, _ctx: &mut Self::Context) -> Self::Result {
+        let routes = &self.routes;
+        let closing_requests: Vec<(ClientRequestPayload, SocketAddr)> = self
+            .closing_requests
+            .drain()
+            .map(|(_, closing_request)| closing_request)
+            .filter(|(payload, _)| routes.contains_key(&payload.stream_key))
+            .collect();
+        let stream_count = closing_requests.len();
+        closing_requests
+            .into_iter()
+            .for_each(|(mut payload, source_addr)| {
+                payload.cancel = true;
+                self.send_request(payload, source_addr)
+            });
+        self.logger.info(format!(
+            "Closed {} open stream(s) for shutdown",
+            stream_count
+        ));
+        ()
+    }
+}
+
+impl Handler<StreamCountQueryMessage> for ProxyServer {
+    type Result = MessageResult<StreamCountQueryMessage>;
+
+    fn handle(
+        &mut self,
+        _msg: StreamCountQueryMessage,
+        _ctx: &mut Self::Context,
+    ) -> <Self as Handler<StreamCountQueryMessage>>::Result {
+        MessageResult(self.keys_and_addrs.len())
+    }
+}
+
+impl Handler<AddRouteMessage> for ProxyServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: AddRouteMessage, _ctx: &mut Self::Context) -> Self::Result {
+        let expiration = Instant::now() + msg.route.valid_for;
+        let exit_sla_opt = msg.route.exit_sla_opt.clone();
+        self.routes.insert(msg.stream_key, (msg.route, expiration));
+        if let (Some(exit_sla), Some(exit_key)) = (exit_sla_opt, self.exit_key_of(&msg.stream_key))
+        {
+            self.sla_monitor.watch(msg.stream_key, exit_key, &exit_sla);
+        }
         ()
     }
 }
 
+impl Handler<ReloadConfigMessage> for ProxyServer {
+    type Result = ();
+
+    fn handle(&mut self, _msg: ReloadConfigMessage, _ctx: &mut Self::Context) -> Self::Result {
+        self.reload_privacy_policy();
+    }
+}
+
+impl Handler<AdaptiveHopsMessage> for ProxyServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: AdaptiveHopsMessage, _ctx: &mut Self::Context) -> Self::Result {
+        let alert = match self.adaptive_hops.as_mut() {
+            Some(adaptive_hops) => {
+                adaptive_hops.enable(msg.enabled);
+                let hop_count = adaptive_hops.hop_count(DEFAULT_MINIMUM_HOP_COUNT);
+                self.logger.info(format!(
+                    "Adaptive hop count turned {}; new routes will have at least {} hops",
+                    if msg.enabled { "on" } else { "off" },
+                    hop_count
+                ));
+                UiAlert::new("adaptive_hops_toggled")
+                    .with("enabled", &format!("{}", msg.enabled))
+                    .with("hop_count", &format!("{}", hop_count))
+            }
+            None => {
+                self.logger.warning(String::from(
+                    "Can't turn adaptive hop count on or off: the Node wasn't started with --adaptive_hops",
+                ));
+                UiAlert::new("adaptive_hops_unavailable")
+            }
+        };
+        self.send_ui_alert(alert);
+    }
+}
+
 impl Handler<ExpiredCoresPackage> for ProxyServer {
     type Result = ();
 
@@ -176,29 +311,84 @@ impl Handler<ExpiredCoresPackage> for ProxyServer {
                                 return ();
                             }
                         };
+                        self.report_round_trip(return_route_id, Instant::now());
 
+                        if let Some(requests_received) = payload.requests_received {
+                            self.release_held_requests(&payload.stream_key, requests_received);
+                        }
+                        // The exit doesn't charge for an acknowledgement, but the relays do
+                        let exit_size_opt = if payload.is_acknowledgement_only() {
+                            None
+                        } else {
+                            Some(payload.sequenced_packet.data.len())
+                        };
                         match self.report_response_services_consumed(
+                            &payload.stream_key,
                             return_route_id,
-                            payload.sequenced_packet.data.len(),
+                            exit_size_opt,
                             payload_data_len,
                         ) {
                             Ok(_) => (),
                             Err(_) => return (),
                         }
+                        if exit_size_opt.is_none() {
+                            return ();
+                        }
+                        self.check_exit_sla(
+                            &payload.stream_key,
+                            payload.sequenced_packet.data.len(),
+                            Instant::now(),
+                        );
+                        if payload.exit_failed {
+                            self.report_exit_problem(
+                                &payload.stream_key,
+                                NodeProblem::TargetUnreachable,
+                            );
+                        }
 
                         let last_data = payload.sequenced_packet.last_data;
+                        if last_data && payload.truncated && self.retry_truncated(&payload) {
+                            return ();
+                        }
+                        self.destination_hints.response(
+                            &payload.stream_key,
+                            payload.sequenced_packet.data.len(),
+                            last_data,
+                        );
+                        self.retryable_requests.responded(&payload.stream_key);
+                        let handshake_replies_sent = self
+                            .socks_sessions
+                            .get(&socket_addr)
+                            .map(|session| session.replies_sent())
+                            .or_else(|| {
+                                self.http_proxy_sessions
+                                    .get(&socket_addr)
+                                    .map(|session| session.replies_sent())
+                            })
+                            .unwrap_or(0);
                         self.dispatcher
                             .as_ref()
                             .expect("Dispatcher unbound in ProxyServer")
                             .try_send(TransmitDataMsg {
                                 endpoint: Endpoint::Socket(socket_addr),
                                 last_data,
-                                sequence_number: Some(payload.sequenced_packet.sequence_number),
+                                sequence_number: Some(
+                                    payload.sequenced_packet.sequence_number
+                                        + handshake_replies_sent,
+                                ),
                                 data: payload.sequenced_packet.data.clone(),
                             })
                             .expect("Dispatcher is dead");
                         if last_data {
                             self.keys_and_addrs.remove_b(&socket_addr);
+                            self.routes.remove(&payload.stream_key);
+                            self.closing_requests.remove(&payload.stream_key);
+                            self.upload_windows.remove(&payload.stream_key);
+                            self.retransmission_buffers.remove(&payload.stream_key);
+                            self.retryable_requests.forget(&payload.stream_key);
+                            self.sla_monitor.forget(&payload.stream_key);
+                            self.socks_sessions.remove(&socket_addr);
+                            self.http_proxy_sessions.remove(&socket_addr);
                         }
                     }
                     None => self.logger.error(format!(
@@ -228,1736 +418,4987 @@ impl ProxyServer {
             accountant_exit: None,
             accountant_routing: None,
             route_source: None,
+            route_feedback: None,
+            node_problem: None,
             add_return_route: None,
-            client_request_payload_factory: ClientRequestPayloadFactory::new(),
+            add_route: None,
+            ui_message_sub: None,
+            client_request_payload_factory: ClientRequestPayloadFactory::new(
+                &standard_protocol_pack_ports(),
+            ),
+            client_hello_buffer: ClientHelloBuffer::new(
+                MAX_CLIENT_HELLO_BYTES,
+                CLIENT_HELLO_TIMEOUT,
+            ),
             stream_key_factory: Box::new(StreamKeyFactoryReal {}),
             keys_and_addrs: BidiHashMap::new(),
             is_decentralized,
             cryptde,
             logger: Logger::new("Proxy Server"),
             route_ids_to_services: TtlHashMap::new(RETURN_ROUTE_TTL),
+            round_trips: HashMap::new(),
+            routes: HashMap::new(),
+            closing_requests: HashMap::new(),
+            upload_window_size: None,
+            upload_windows: HashMap::new(),
+            retransmission_config: None,
+            retransmission_buffers: HashMap::new(),
+            retransmission_spills: 0,
+            retryable_requests: RetryableRequests::new(),
+            stalled_request_timeout: STALLED_REQUEST_TIMEOUT,
+            socks_port: None,
+            socks_sessions: HashMap::new(),
+            http_proxy_port: None,
+            http_proxy_sessions: HashMap::new(),
+            privacy_policy_path: None,
+            exit_location_opt: None,
+            sla_monitor: SlaMonitor::new(),
+            adaptive_hops: None,
+            destination_hints: DestinationHintsCache::new(DESTINATION_HINTS_CAPACITY),
         }
     }
 
+    pub fn map_protocol_packs(&mut self, protocol_pack_ports: &HashMap<u16, String>) {
+        self.client_request_payload_factory = ClientRequestPayloadFactory::new(protocol_pack_ports);
+    }
+
+    // Call after map_protocol_packs, which starts the payload factory over from scratch
+    pub fn enable_privacy_filter(&mut self, policy: &PrivacyPolicy) {
+        self.client_request_payload_factory
+            .filter_with(policy.clone());
+    }
+
+    // Same as with enable_privacy_filter: call after map_protocol_packs
+    pub fn request_exit_tls(&mut self) {
+        self.client_request_payload_factory.request_exit_tls();
+    }
+
+    // Same again: call after map_protocol_packs
+    pub fn request_exit_dns_server(&mut self, dns_server: IpAddr) {
+        self.client_request_payload_factory
+            .request_exit_dns_server(dns_server);
+    }
+
+    // Every route will exit from a Node that says it's in this country, or there'll be no route
+    pub fn request_exit_location(&mut self, country: String) {
+        self.exit_location_opt = Some(country);
+    }
+
+    // The file enable_privacy_filter's policy came from, to be read again on a ReloadConfigMessage
+    pub fn reload_privacy_policy_from(&mut self, path: &Path) {
+        self.privacy_policy_path = Some(path.to_path_buf());
+    }
+
+    pub fn limit_upload_window(&mut self, upload_window_size: u64) {
+        self.upload_window_size = Some(upload_window_size);
+    }
+
+    pub fn enable_retransmission(&mut self, config: &RetransmissionConfig) {
+        let mut config = config.clone();
+        if let Some(spill_directory) = config.spill_directory_opt.clone() {
+            // Whatever a previous run spilled is no use to this one
+            fs::remove_dir_all(&spill_directory).is_ok();
+            if let Err(e) = fs::create_dir_all(&spill_directory) {
+                self.logger.warning(format!(
+                    "Can't spill unacknowledged request packets to {:?}: {}",
+                    spill_directory, e
+                ));
+                config.spill_directory_opt = None;
+            }
+        }
+        self.retransmission_config = Some(config);
+    }
+
+    pub fn accept_socks_on(&mut self, socks_port: u16) {
+        self.socks_port = Some(socks_port);
+    }
+
+    pub fn accept_http_proxy_on(&mut self, http_proxy_port: u16) {
+        self.http_proxy_port = Some(http_proxy_port);
+    }
+
+    pub fn adapt_hop_count(&mut self, config: &AdaptiveHopsConfig) {
+        self.adaptive_hops = Some(AdaptiveHops::new(config, DEFAULT_MINIMUM_HOP_COUNT));
+    }
+
     pub fn make_subs_from(addr: &Addr<Syn, ProxyServer>) -> ProxyServerSubs {
         ProxyServerSubs {
             bind: addr.clone().recipient::<BindMessage>(),
             from_dispatcher: addr.clone().recipient::<InboundClientData>(),
             from_hopper: addr.clone().recipient::<ExpiredCoresPackage>(),
             add_return_route: addr.clone().recipient::<AddReturnRouteMessage>(),
+            add_route: addr.clone().recipient::<AddRouteMessage>(),
+            reload_config: addr.clone().recipient::<ReloadConfigMessage>(),
+            adaptive_hops: addr.clone().recipient::<AdaptiveHopsMessage>(),
+            stream_count_query: addr.clone().recipient::<StreamCountQueryMessage>(),
+            shutdown: addr.clone().recipient::<ShutdownMessage>(),
         }
     }
 
-    fn make_payload(&mut self, msg: InboundClientData) -> Result<ClientRequestPayload, ()> {
-        let stream_key = match self.keys_and_addrs.b_to_a(&msg.peer_addr) {
-            Some(stream_key) => stream_key,
-            None => {
-                let stream_key = self
-                    .stream_key_factory
-                    .make(&self.cryptde.public_key(), msg.peer_addr);
-                self.keys_and_addrs
-                    .insert(stream_key.clone(), msg.peer_addr);
-                stream_key
-            }
+    // A policy that can't be read or understood is logged and otherwise ignored: the one already in
+    // force is a better bet than none at all
+    fn reload_privacy_policy(&mut self) {
+        let path = match &self.privacy_policy_path {
+            Some(path) => path.clone(),
+            None => return,
         };
-        match self
-            .client_request_payload_factory
-            .make(&msg, stream_key, self.cryptde, &self.logger)
-        {
-            None => {
+        match PrivacyPolicy::load(&path) {
+            Ok(policy) => {
                 self.logger
-                    .error(format!("Couldn't create ClientRequestPayload"));
-                Err(())
+                    .info(format!("Reloaded privacy policy from {:?}", path));
+                self.client_request_payload_factory.filter_with(policy);
             }
-            Some(payload) => Ok(payload),
+            Err(msg) => self.logger.warning(format!(
+                "{}; keeping the privacy policy already in force",
+                msg
+            )),
         }
     }
 
-    fn try_transmit_to_hopper(
-        cryptde: &'static dyn CryptDE,
-        hopper: Recipient<Syn, IncipientCoresPackage>,
-        route_result: Result<Option<RouteQueryResponse>, MailboxError>,
-        payload: ClientRequestPayload,
-        logger: Logger,
-        source_addr: SocketAddr,
-        dispatcher: Recipient<Syn, TransmitDataMsg>,
-        accountant_exit_sub: Recipient<Syn, ReportExitServiceConsumedMessage>,
-        accountant_routing_sub: Recipient<Syn, ReportRoutingServiceConsumedMessage>,
-        add_return_route_sub: Recipient<Syn, AddReturnRouteMessage>,
-    ) -> Result<(), ()> {
-        match route_result {
-            Ok(Some(route_query_response)) => match route_query_response.expected_services {
-                ExpectedServices::RoundTrip(over, back, return_route_id) => {
-                    add_return_route_sub
-                        .try_send(AddReturnRouteMessage {
-                            return_route_id,
-                            expected_services: back.clone(),
-                        })
-                        .expect("ProxyServer is dead");
-                    ProxyServer::report_exit_service(
-                        accountant_exit_sub,
-                        over.clone(),
-                        &payload,
-                        &logger,
-                    );
-                    ProxyServer::transmit_to_hopper(
-                        cryptde,
-                        hopper,
-                        payload,
-                        &route_query_response.route,
-                        over.clone(),
-                        &logger,
-                        source_addr,
-                        dispatcher,
-                        accountant_routing_sub,
-                    );
+    // SOCKS5 clients name their targets in the handshake; what follows goes out as if it had
+    // arrived on the target's port, so only ports with a protocol pack are permitted
+    fn route_socks_client_data(&mut self, msg: InboundClientData) {
+        let peer_addr = msg.peer_addr;
+        let client_request_payload_factory = &self.client_request_payload_factory;
+        let session = self
+            .socks_sessions
+            .entry(peer_addr)
+            .or_insert_with(Socks5Session::new);
+        let events = session.accept(&msg.data, msg.last_data, |target| {
+            client_request_payload_factory
+                .protocol_for(Some(target.port))
+                .is_some()
+        });
+        let target_opt = session.target().cloned();
+        // A refused client's session stays closed, ignoring anything more it sends, until it hangs up
+        if msg.last_data && (session.is_closed() || target_opt.is_none()) {
+            self.socks_sessions.remove(&peer_addr);
+        }
+        events.into_iter().for_each(|event| match event {
+            Socks5Event::Reply {
+                data,
+                last_data,
+                sequence_number,
+            } => {
+                if last_data {
+                    self.logger.info(format!(
+                        "Refusing SOCKS5 client at {} with reply code 0x{:02X}",
+                        peer_addr, data[1]
+                    ));
                 }
-                _ => panic!("Expected RoundTrip ExpectedServices but got OneWay"),
-            },
-            Ok(None) => {
-                ProxyServer::handle_route_failure(payload, &logger, source_addr, dispatcher);
+                self.send_handshake_reply(peer_addr, data, last_data, sequence_number);
             }
-            Err(e) => {
-                let msg = format!("Neighborhood refused to answer route request: {}", e);
-                logger.error(msg);
+            Socks5Event::Data {
+                data,
+                last_data,
+                sequence_number,
+            } => {
+                let target = target_opt.clone().expect("SOCKS5 data without a target");
+                self.route_proxied_client_data(
+                    peer_addr,
+                    target.host,
+                    target.port,
+                    data,
+                    last_data,
+                    sequence_number,
+                );
             }
-        };
-        Ok(())
+        });
     }
 
-    fn report_routing_service(
-        accountant_routing_sub: Recipient<Syn, ReportRoutingServiceConsumedMessage>,
-        expected_services: Vec<ExpectedService>,
-        payload_size: usize,
-        logger: &Logger,
-    ) {
-        let earning_wallets: Vec<&Wallet> = expected_services
-            .iter()
-            .filter_map(|service| match service {
-                ExpectedService::Routing(_, earning_wallet) => Some(earning_wallet),
-                _ => None,
-            })
-            .collect();
-        if earning_wallets.is_empty() {
-            logger.debug("No routing services requested.".to_string());
+    // Just as with SOCKS5, except that the target is named in a CONNECT or in the URL of an
+    // ordinary request
+    fn route_http_proxy_client_data(&mut self, msg: InboundClientData) {
+        let peer_addr = msg.peer_addr;
+        let client_request_payload_factory = &self.client_request_payload_factory;
+        let session = self
+            .http_proxy_sessions
+            .entry(peer_addr)
+            .or_insert_with(HttpProxySession::new);
+        let events = session.accept(&msg.data, msg.last_data, |target| {
+            client_request_payload_factory
+                .protocol_for(Some(target.port))
+                .is_some()
+        });
+        let target_opt = session.target().cloned();
+        if session.is_closed() || (msg.last_data && target_opt.is_none()) {
+            self.http_proxy_sessions.remove(&peer_addr);
         }
-        earning_wallets.into_iter().for_each(|earning_wallet| {
-            let report_routing_service_consumed = ReportRoutingServiceConsumedMessage {
-                earning_wallet: earning_wallet.clone(),
-                payload_size,
-                service_rate: TEMPORARY_PER_ROUTING_RATE,
-                byte_rate: TEMPORARY_PER_ROUTING_BYTE_RATE,
-            };
-            accountant_routing_sub
-                .try_send(report_routing_service_consumed)
-                .expect("Accountant is dead");
+        events.into_iter().for_each(|event| match event {
+            HttpProxyEvent::Reply {
+                data,
+                last_data,
+                sequence_number,
+            } => {
+                if last_data {
+                    self.logger.info(format!(
+                        "Refusing HTTP proxy client at {} with '{}'",
+                        peer_addr,
+                        String::from_utf8_lossy(&data).lines().next().unwrap_or("")
+                    ));
+                }
+                self.send_handshake_reply(peer_addr, data, last_data, sequence_number);
+            }
+            HttpProxyEvent::Data {
+                data,
+                last_data,
+                sequence_number,
+            } => {
+                let target = target_opt
+                    .clone()
+                    .expect("HTTP proxy data without a target");
+                self.route_proxied_client_data(
+                    peer_addr,
+                    target.host,
+                    target.port,
+                    data,
+                    last_data,
+                    sequence_number,
+                );
+            }
         });
     }
 
-    fn report_exit_service(
-        accountant_exit_sub: Recipient<Syn, ReportExitServiceConsumedMessage>,
-        expected_services: Vec<ExpectedService>,
-        payload: &ClientRequestPayload,
-        logger: &Logger,
+    fn send_handshake_reply(
+        &self,
+        peer_addr: SocketAddr,
+        data: Vec<u8>,
+        last_data: bool,
+        sequence_number: u64,
     ) {
-        match expected_services
-            .iter()
-            .find_map(|expected_service| match expected_service {
-                ExpectedService::Exit(_, earning_wallet) => Some(earning_wallet),
-                _ => None,
-            }) {
-            Some(earning_wallet) => {
-                let payload_size = payload.sequenced_packet.data.len();
-                let report_exit_service_consumed_message = ReportExitServiceConsumedMessage {
-                    earning_wallet: earning_wallet.clone(),
-                    payload_size,
-                    service_rate: TEMPORARY_PER_EXIT_RATE,
-                    byte_rate: TEMPORARY_PER_EXIT_BYTE_RATE,
-                };
-                accountant_exit_sub
-                    .try_send(report_exit_service_consumed_message)
-                    .expect("Accountant is dead");
-            }
-            None => logger.debug("No exit service requested.".to_string()),
-        };
+        self.dispatcher
+            .as_ref()
+            .expect("Dispatcher unbound in ProxyServer")
+            .try_send(TransmitDataMsg {
+                endpoint: Endpoint::Socket(peer_addr),
+                last_data,
+                sequence_number: Some(sequence_number),
+                data,
+            })
+            .expect("Dispatcher is dead");
     }
 
-    fn transmit_to_hopper(
-        cryptde: &'static dyn CryptDE,
-        hopper: Recipient<Syn, IncipientCoresPackage>,
-        payload: ClientRequestPayload,
-        route: &Route,
-        expected_services: Vec<ExpectedService>,
-        logger: &Logger,
-        source_addr: SocketAddr,
-        dispatcher: Recipient<Syn, TransmitDataMsg>,
-        accountant_routing_sub: Recipient<Syn, ReportRoutingServiceConsumedMessage>,
+    // What a proxy client sends for its target goes out as if it had arrived on the target's port
+    fn route_proxied_client_data(
+        &mut self,
+        peer_addr: SocketAddr,
+        host: String,
+        port: u16,
+        data: Vec<u8>,
+        last_data: bool,
+        sequence_number: u64,
     ) {
-        let destination_key_opt = if !expected_services.is_empty()
-            && expected_services
-                .iter()
-                .all(|expected_service| match expected_service {
-                    ExpectedService::Nothing => true,
-                    _ => false,
-                }) {
-            Some(payload.originator_public_key.clone())
-        } else {
-            expected_services.iter().find_map(|service| match service {
-                ExpectedService::Exit(public_key, _) => Some(public_key.clone()),
-                _ => None,
-            })
+        let msg = InboundClientData {
+            peer_addr,
+            reception_port: Some(port),
+            sequence_number: Some(sequence_number),
+            last_data,
+            is_clandestine: false,
+            data,
+            neighbor_key_opt: None,
         };
+        self.route_inbound_client_data(msg, &Some(host));
+    }
 
-        match destination_key_opt {
-            None => ProxyServer::handle_route_failure(payload, &logger, source_addr, dispatcher),
-            Some(payload_destination_key) => {
-                logger.debug(format!(
-                    "transmit to hopper with destination key {:?}",
-                    payload_destination_key
-                ));
-                let pkg = IncipientCoresPackage::new(
-                    cryptde,
-                    route.clone(),
-                    payload,
-                    &payload_destination_key,
-                )
-                .expect("Key magically disappeared");
-                ProxyServer::report_routing_service(
-                    accountant_routing_sub,
-                    expected_services,
-                    pkg.payload.len(),
-                    &logger,
-                );
-                hopper.try_send(pkg).expect("Hopper is dead");
-            }
-        }
-    }
-
-    fn handle_route_failure(
-        payload: ClientRequestPayload,
-        logger: &Logger,
-        source_addr: SocketAddr,
-        dispatcher: Recipient<Syn, TransmitDataMsg>,
+    fn route_inbound_client_data(
+        &mut self,
+        msg: InboundClientData,
+        host_name_opt: &Option<String>,
     ) {
-        let target_hostname = ProxyServer::hostname(&payload);
-        ProxyServer::send_route_failure(payload, source_addr, dispatcher);
-        logger.error(format!("Failed to find route to {}", target_hostname));
+        let source_addr = msg.peer_addr;
+        let mut payload = match self.make_payload(msg) {
+            Ok(payload) => payload,
+            Err(_) => return (),
+        };
+        if payload.target_hostname.is_none() {
+            payload.target_hostname = host_name_opt.clone();
+        }
+        // The browser hung up while the exit was still answering: nobody will read the rest
+        if payload.sequenced_packet.last_data
+            && self.closing_requests.contains_key(&payload.stream_key)
+        {
+            payload.cancel = true;
+        }
+        self.retryable_requests
+            .record_request(&payload, source_addr);
+        self.destination_hints.request(&payload);
+        if payload.cancel {
+            // Whatever the upload window is still holding, the exit no longer wants
+            self.logger.for_stream(&payload.stream_key).debug(format!(
+                "Canceling stream {:?} at the exit",
+                payload.stream_key
+            ));
+            self.upload_windows.remove(&payload.stream_key);
+            self.retransmission_buffers.remove(&payload.stream_key);
+            self.send_request(payload, source_addr);
+            return ();
+        }
+        let unpaced = self
+            .destination_hints
+            .parameters(&payload.stream_key)
+            .unpaced;
+        if let (Some(upload_window_size), false) = (self.upload_window_size, unpaced) {
+            payload.upload_window = Some(upload_window_size);
+            let stream_key = payload.stream_key;
+            let sequence_number = payload.sequenced_packet.sequence_number;
+            let upload_window = self
+                .upload_windows
+                .entry(stream_key)
+                .or_insert_with(|| UploadWindow::new(upload_window_size));
+            payload = match upload_window.offer((payload, source_addr)) {
+                Some((payload, _)) => payload,
+                None => {
+                    self.logger.for_stream(&stream_key).debug(format!(
+                        "Holding packet {} of stream {:?} until the exit has more of the {} in flight",
+                        sequence_number,
+                        stream_key,
+                        upload_window.in_flight()
+                    ));
+                    return ();
+                }
+            };
+        }
+        self.retain_for_retransmission(&payload, source_addr);
+        self.send_request(payload, source_addr);
     }
 
-    fn send_route_failure(
-        payload: ClientRequestPayload,
+    // Only a stream paced by an upload window hears from the exit which packets have arrived, so
+    // only such a stream can ever let go of what it has kept
+    fn retain_for_retransmission(
+        &mut self,
+        payload: &ClientRequestPayload,
         source_addr: SocketAddr,
-        dispatcher: Recipient<Syn, TransmitDataMsg>,
     ) {
-        let data = match payload.protocol {
-            ProxyProtocol::HTTP => {
-                let target_hostname = ProxyServer::hostname(&payload);
-                http_server_impersonator::make_error_response (
-                    503,
-                   "Routing Problem",
-                    format! ("Can't find a route to {}", target_hostname).as_str (),
-                    format! ("Substratum can't find a route through the Network yet to a Node that knows \
-                    where to find {}. Maybe later enough will be known about the Network to \
-                    find that Node, but we can't guarantee it. We're sorry.", target_hostname).as_str ()
+        let (config, upload_window_size) =
+            match (&self.retransmission_config, self.upload_window_size) {
+                (Some(config), Some(upload_window_size)) => (config, upload_window_size),
+                _ => return,
+            };
+        let stream_key = payload.stream_key;
+        if self.destination_hints.parameters(&stream_key).unpaced {
+            return;
+        }
+        let spills = &mut self.retransmission_spills;
+        let buffer = self
+            .retransmission_buffers
+            .entry(stream_key)
+            .or_insert_with(|| {
+                let spill_path_opt = config.spill_directory_opt.as_ref().map(|directory| {
+                    *spills += 1;
+                    directory.join(format!("stream-{}.cbor", spills))
+                });
+                RetransmissionBuffer::new(
+                    upload_window_size as usize,
+                    config.memory_limit,
+                    spill_path_opt,
                 )
-            }
-            ProxyProtocol::TLS => vec![],
-        };
-        let msg = TransmitDataMsg {
-            endpoint: Endpoint::Socket(source_addr),
-            last_data: true,
-            sequence_number: Some(0),
-            data,
-        };
-        dispatcher.try_send(msg).expect("Dispatcher is dead");
-    }
-
-    fn hostname(payload: &ClientRequestPayload) -> String {
-        match payload.target_hostname {
-            Some(ref thn) => thn.clone(),
-            None => "<unknown>".to_string(),
+            });
+        if let Err(e) = buffer.retain(payload, source_addr) {
+            self.logger.for_stream(&stream_key).warning(format!(
+                "Stream {:?} can no longer be moved to another route: {}",
+                stream_key, e
+            ));
         }
     }
 
-    fn get_return_route_id(&self, remaining_route: &Route) -> Result<u32, ()> {
-        let mut mut_remaining_route = remaining_route.clone();
-        match mut_remaining_route.shift(self.cryptde) {
-            Ok(_) => (),
-            Err(_) => {
-                self.logger.error(
-                    "Can't report services consumed: return route ID is unspecified".to_string(),
-                );
-                return Err(());
+    // A server that resets the connection before saying anything gets another chance over a
+    // fresh route, if the request was one that can safely be made twice. Once any of the
+    // response has gone on to the browser, it's too late: the browser gets the truncation.
+    fn retry_truncated(&mut self, payload: &ClientResponsePayload) -> bool {
+        let stream_key = payload.stream_key;
+        let retry_opt = if payload.sequenced_packet.sequence_number == 0 {
+            self.retryable_requests.take_retry(&stream_key)
+        } else {
+            None
+        };
+        match retry_opt {
+            Some((request, source_addr)) => {
+                self.logger.for_stream(&stream_key).warning(format!(
+                    "Server reset stream {:?} before responding; retrying the request over a fresh route",
+                    stream_key
+                ));
+                self.routes.remove(&stream_key);
+                self.upload_windows.remove(&stream_key);
+                self.retransmission_buffers.remove(&stream_key);
+                self.send_request(request, source_addr);
+                true
             }
-        }
-        match mut_remaining_route.id(self.cryptde) {
-            Ok(return_route_id) => Ok(return_route_id),
-            Err(_) => {
-                self.logger.error(
-                    "Can't report services consumed: return route ID is unreadable".to_string(),
-                );
-                Err(())
+            None => {
+                self.logger.for_stream(&stream_key).warning(format!(
+                    "Server reset stream {:?} after {} response packets; the response may be incomplete",
+                    stream_key, payload.sequenced_packet.sequence_number
+                ));
+                false
             }
         }
     }
 
-    fn report_response_services_consumed(
-        &self,
-        request_id: u32,
-        exit_size: usize,
-        routing_size: usize,
-    ) -> Result<(), ()> {
-        let services = match self.route_ids_to_services.get(&request_id) {
-            Some(s) => s,
-            None => {
-                self.logger.error(format!(
-                    "Can't report services consumed: return route ID {} is not recognized",
-                    request_id
+    fn schedule_stalled_request_check(&self, ctx: &mut Context<Self>) {
+        ctx.run_later(self.stalled_request_timeout / 4, |act, ctx| {
+            act.retry_stalled_requests(Instant::now());
+            act.schedule_stalled_request_check(ctx);
+        });
+    }
+
+    fn schedule_client_hello_sweep(&self, ctx: &mut Context<Self>) {
+        ctx.run_later(self.client_hello_buffer.time_limit() / 2, |act, ctx| {
+            act.client_hello_buffer
+                .release_expired(Instant::now(), &act.logger)
+                .into_iter()
+                .for_each(|(msgs, host_name_opt)| {
+                    msgs.into_iter()
+                        .for_each(|msg| act.route_inbound_client_data(msg, &host_name_opt))
+                });
+            act.schedule_client_hello_sweep(ctx);
+        });
+    }
+
+    // A GET or HEAD that hears nothing at all back for too long goes out again, over a route that
+    // avoids the exit it went through before. Once the stream's retries are spent, the browser
+    // gets an error page instead of waiting forever.
+    fn retry_stalled_requests(&mut self, now: Instant) {
+        let destination_hints = &self.destination_hints;
+        let stalled_request_timeout = self.stalled_request_timeout;
+        let stalled = self.retryable_requests.stalled(now, |stream_key| {
+            ProxyServer::patience(destination_hints, stalled_request_timeout, stream_key)
+        });
+        stalled.into_iter().for_each(|stream_key| {
+            let timeout = ProxyServer::patience(
+                &self.destination_hints,
+                self.stalled_request_timeout,
+                &stream_key,
+            );
+            self.destination_hints.stalled(&stream_key);
+            let avoided_exit_keys: Vec<PublicKey> =
+                self.exit_key_of(&stream_key).into_iter().collect();
+            self.report_failed_round_trip(&stream_key);
+            self.report_exit_problem(&stream_key, NodeProblem::StreamDied);
+            self.routes.remove(&stream_key);
+            self.sla_monitor.forget(&stream_key);
+            self.upload_windows.remove(&stream_key);
+            self.retransmission_buffers.remove(&stream_key);
+            if let Some((request, source_addr)) = self.retryable_requests.take_retry(&stream_key) {
+                self.logger.for_stream(&stream_key).warning(format!(
+                    "No response on stream {:?} after {:?}; retrying the request over a different route",
+                    stream_key, timeout
                 ));
-                return Err(());
+                self.send_request_avoiding(request, source_addr, avoided_exit_keys);
+            } else if let Some((request, source_addr)) =
+                self.retryable_requests.abandon(&stream_key)
+            {
+                self.give_up_on_stalled_request(request, source_addr);
             }
-        };
-        services.iter().for_each(|service| match service {
-            ExpectedService::Nothing => (),
-            ExpectedService::Exit(_, wallet) => self
-                .accountant_exit
-                .as_ref()
-                .expect("ProxyServer unbound")
-                .try_send(ReportExitServiceConsumedMessage {
-                    earning_wallet: wallet.clone(),
-                    payload_size: exit_size,
-                    service_rate: TEMPORARY_PER_EXIT_RATE,
-                    byte_rate: TEMPORARY_PER_EXIT_BYTE_RATE,
-                })
-                .expect("Accountant is dead"),
-            ExpectedService::Routing(_, wallet) => self
-                .accountant_routing
-                .as_ref()
-                .expect("ProxyServer unbound")
-                .try_send(ReportRoutingServiceConsumedMessage {
-                    earning_wallet: wallet.clone(),
-                    payload_size: routing_size,
-                    service_rate: TEMPORARY_PER_ROUTING_RATE,
-                    byte_rate: TEMPORARY_PER_ROUTING_BYTE_RATE,
-                })
-                .expect("Accountant is dead"),
         });
-        Ok(())
     }
-}
-
-trait StreamKeyFactory: Send {
-    fn make(&self, public_key: &PublicKey, peer_addr: SocketAddr) -> StreamKey;
-}
 
-struct StreamKeyFactoryReal {}
+    // Destinations that have been slow to answer before get twice as long
+    fn patience(
+        destination_hints: &DestinationHintsCache,
+        stalled_request_timeout: Duration,
+        stream_key: &StreamKey,
+    ) -> Duration {
+        if destination_hints.parameters(stream_key).patient {
+            stalled_request_timeout * 2
+        } else {
+            stalled_request_timeout
+        }
+    }
 
-impl StreamKeyFactory for StreamKeyFactoryReal {
-    fn make(&self, public_key: &PublicKey, peer_addr: SocketAddr) -> StreamKey {
-        // TODO: Replace this implementation
-        StreamKey::new(public_key.clone(), peer_addr)
+    fn report_round_trip(&mut self, return_route_id: u32, now: Instant) {
+        if let Some((keys, sent)) = self.round_trips.remove(&return_route_id) {
+            let round_trip = now.duration_since(sent);
+            self.send_route_feedback(keys, RouteOutcome::Responded(round_trip));
+            self.adapt_hop_count_to(round_trip);
+        }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::sub_lib::accountant::ReportRoutingServiceConsumedMessage;
-    use crate::sub_lib::cryptde::encodex;
-    use crate::sub_lib::cryptde::CryptData;
-    use crate::sub_lib::cryptde::PlainData;
-    use crate::sub_lib::dispatcher::Component;
-    use crate::sub_lib::hop::LiveHop;
-    use crate::sub_lib::hopper::ExpiredCoresPackage;
-    use crate::sub_lib::http_server_impersonator;
-    use crate::sub_lib::neighborhood::ExpectedService;
-    use crate::sub_lib::neighborhood::ExpectedServices;
-    use crate::sub_lib::proxy_client::ClientResponsePayload;
-    use crate::sub_lib::proxy_server::ClientRequestPayload;
-    use crate::sub_lib::proxy_server::ProxyProtocol;
-    use crate::sub_lib::route::Route;
-    use crate::sub_lib::route::RouteSegment;
-    use crate::sub_lib::sequence_buffer::SequencedPacket;
-    use crate::sub_lib::ttl_hashmap::TtlHashMap;
-    use crate::sub_lib::wallet::Wallet;
-    use crate::test_utils::logging::init_test_logging;
-    use crate::test_utils::logging::TestLogHandler;
-    use crate::test_utils::recorder::make_recorder;
-    use crate::test_utils::recorder::peer_actors_builder;
-    use crate::test_utils::recorder::Recorder;
-    use crate::test_utils::recorder::Recording;
-    use crate::test_utils::test_utils::cryptde;
-    use crate::test_utils::test_utils::make_meaningless_route;
-    use crate::test_utils::test_utils::make_meaningless_stream_key;
-    use crate::test_utils::test_utils::zero_hop_route_response;
-    use actix::msgs;
-    use actix::Arbiter;
-    use actix::System;
-    use std::cell::RefCell;
-    use std::net::IpAddr;
-    use std::net::SocketAddr;
-    use std::str::FromStr;
-    use std::sync::mpsc;
-    use std::sync::Arc;
-    use std::sync::Mutex;
-    use std::sync::MutexGuard;
-    use std::thread;
+    fn adapt_hop_count_to(&mut self, round_trip: Duration) {
+        let (hop_count, median) = match self
+            .adaptive_hops
+            .as_mut()
+            .and_then(|adaptive_hops| adaptive_hops.measured(round_trip))
+        {
+            Some(change) => change,
+            None => return,
+        };
+        let median_ms = median.as_secs() * 1000 + u64::from(median.subsec_millis());
+        self.logger.info(format!(
+            "Median round trip is {}ms; new routes will have at least {} hops",
+            median_ms, hop_count
+        ));
+        self.send_ui_alert(
+            UiAlert::new("hop_count_adapted")
+                .with("hop_count", &format!("{}", hop_count))
+                .with("median_ms", &format!("{}", median_ms)),
+        );
+    }
 
-    struct StreamKeyFactoryMock {
-        make_parameters: Arc<Mutex<Vec<(PublicKey, SocketAddr)>>>,
-        make_results: RefCell<Vec<StreamKey>>,
+    fn send_ui_alert(&self, alert: UiAlert) {
+        self.ui_message_sub
+            .as_ref()
+            .expect("UiGateway is unbound")
+            .try_send(UiMessage::AlertMessage(alert))
+            .expect("UiGateway is dead");
     }
 
-    impl StreamKeyFactory for StreamKeyFactoryMock {
-        fn make(&self, key: &PublicKey, peer_addr: SocketAddr) -> StreamKey {
-            self.make_parameters
-                .lock()
-                .unwrap()
-                .push((key.clone(), peer_addr));
-            self.make_results.borrow_mut().remove(0)
+    fn report_failed_round_trip(&mut self, stream_key: &StreamKey) {
+        let (keys, return_route_id) = match &self.routes.get(stream_key) {
+            Some((
+                RouteQueryResponse {
+                    expected_services: ExpectedServices::RoundTrip(over, back, return_route_id),
+                    ..
+                },
+                _,
+            )) => (ProxyServer::route_keys(over, back), *return_route_id),
+            _ => return,
+        };
+        self.round_trips.remove(&return_route_id);
+        if !keys.is_empty() {
+            self.send_route_feedback(keys, RouteOutcome::Failed);
         }
     }
 
-    impl StreamKeyFactoryMock {
-        fn new() -> StreamKeyFactoryMock {
-            StreamKeyFactoryMock {
-                make_parameters: Arc::new(Mutex::new(vec![])),
-                make_results: RefCell::new(vec![]),
-            }
+    fn check_exit_sla(&mut self, stream_key: &StreamKey, bytes: usize, now: Instant) {
+        if let Some(exit_key) = self.sla_monitor.delivered(stream_key, bytes as u64, now) {
+            self.logger
+                .for_stream(stream_key)
+                .for_peer(&exit_key)
+                .warning(format!(
+                    "Exit {} is delivering stream {:?} well below the rate it advertised",
+                    exit_key, stream_key
+                ));
+            self.send_route_feedback(vec![exit_key], RouteOutcome::Throttled);
         }
+    }
 
-        fn make_parameters(
-            mut self,
-            params: &Arc<Mutex<Vec<(PublicKey, SocketAddr)>>>,
-        ) -> StreamKeyFactoryMock {
-            self.make_parameters = params.clone();
-            self
-        }
+    fn send_route_feedback(&self, keys: Vec<PublicKey>, outcome: RouteOutcome) {
+        self.route_feedback
+            .as_ref()
+            .expect("Neighborhood unbound in ProxyServer")
+            .try_send(RouteFeedbackMessage { keys, outcome })
+            .expect("Neighborhood is dead");
+    }
 
-        fn make_result(self, stream_key: StreamKey) -> StreamKeyFactoryMock {
-            self.make_results.borrow_mut().push(stream_key);
-            self
+    // Only the exit can be named: from here, a relay that dropped the stream looks just like an exit
+    // that did
+    fn report_exit_problem(&self, stream_key: &StreamKey, problem: NodeProblem) {
+        let exit_key = match self.exit_key_of(stream_key) {
+            Some(exit_key) => exit_key,
+            None => return,
+        };
+        self.logger
+            .for_stream(stream_key)
+            .for_peer(&exit_key)
+            .warning(format!(
+                "Exit {} failed stream {:?}: {:?}",
+                exit_key, stream_key, problem
+            ));
+        self.node_problem
+            .as_ref()
+            .expect("Neighborhood unbound in ProxyServer")
+            .try_send(NodeProblemReport {
+                public_key: exit_key,
+                problem,
+            })
+            .expect("Neighborhood is dead");
+    }
+
+    // Every Node a round trip passes through but this one, each named once
+    fn route_keys(over: &[ExpectedService], back: &[ExpectedService]) -> Vec<PublicKey> {
+        over.iter()
+            .chain(back.iter())
+            .fold(vec![], |mut keys, service| {
+                match service {
+                    ExpectedService::Routing(key, _, _) | ExpectedService::Exit(key, _, _)
+                        if !keys.contains(key) =>
+                    {
+                        keys.push(key.clone())
+                    }
+                    _ => (),
+                }
+                keys
+            })
+    }
+
+    fn exit_key_of(&self, stream_key: &StreamKey) -> Option<PublicKey> {
+        match &self.routes.get(stream_key)?.0.expected_services {
+            ExpectedServices::RoundTrip(over, _, _) => {
+                over.iter().find_map(|service| match service {
+                    ExpectedService::Exit(public_key, _, _) => Some(public_key.clone()),
+                    _ => None,
+                })
+            }
+            _ => None,
         }
     }
 
-    fn return_route_with_id(cryptde: &CryptDE, return_route_id: u32) -> Route {
-        let cover_hop = make_cover_hop(cryptde);
-        let id_hop = cryptde
-            .encode(
-                &cryptde.public_key(),
-                &PlainData::from(serde_cbor::ser::to_vec(&return_route_id).unwrap()),
+    fn give_up_on_stalled_request(
+        &mut self,
+        request: ClientRequestPayload,
+        source_addr: SocketAddr,
+    ) {
+        let target_hostname = ProxyServer::hostname(&request);
+        self.logger.for_stream(&request.stream_key).error(format!(
+            "No response on stream {:?} from {} even after retrying; giving up",
+            request.stream_key, target_hostname
+        ));
+        let data = http_server_impersonator::make_error_response(
+            504,
+            "Exit Timeout",
+            format!("No response from {}", target_hostname).as_str(),
+            format!(
+                "Substratum sent your request for {} through the Network more than once, but \
+                 nothing came back in time. The Network may be busy; try again in a moment.",
+                target_hostname
             )
-            .unwrap();
-        Route {
-            hops: vec![cover_hop, id_hop],
-        }
+            .as_str(),
+        );
+        self.dispatcher
+            .as_ref()
+            .expect("Dispatcher unbound in ProxyServer")
+            .try_send(TransmitDataMsg {
+                endpoint: Endpoint::Socket(source_addr),
+                last_data: true,
+                sequence_number: Some(0),
+                data,
+            })
+            .expect("Dispatcher is dead");
+        self.keys_and_addrs.remove_a(&request.stream_key);
+        self.closing_requests.remove(&request.stream_key);
+        self.retryable_requests.forget(&request.stream_key);
+        self.destination_hints.forget(&request.stream_key);
     }
 
-    fn make_cover_hop(cryptde: &dyn CryptDE) -> CryptData {
+    fn send_request(&mut self, payload: ClientRequestPayload, source_addr: SocketAddr) {
+        self.send_request_avoiding(payload, source_addr, vec![])
+    }
+
+    fn send_request_avoiding(
+        &mut self,
+        payload: ClientRequestPayload,
+        source_addr: SocketAddr,
+        avoided_exit_keys: Vec<PublicKey>,
+    ) {
+        let cryptde = self.cryptde.clone();
+        let route_source = self
+            .route_source
+            .as_ref()
+            .expect("Neighborhood unbound in ProxyServer")
+            .clone();
+        let hopper = self
+            .hopper
+            .as_ref()
+            .expect("Hopper unbound in ProxyServer")
+            .clone();
+        let accountant_exit_sub = self
+            .accountant_exit
+            .as_ref()
+            .expect("Accountant unbound in ProxyServer")
+            .clone();
+        let accountant_routing_sub = self
+            .accountant_routing
+            .as_ref()
+            .expect("Accountant unbound in ProxyServer")
+            .clone();
+        let dispatcher = self
+            .dispatcher
+            .as_ref()
+            .expect("Dispatcher unbound in ProxyServer")
+            .clone();
+        let add_return_route_sub = self
+            .add_return_route
+            .as_ref()
+            .expect("ProxyServer unbound in ProxyServer")
+            .clone();
+        let add_route_sub = self
+            .add_route
+            .as_ref()
+            .expect("ProxyServer unbound in ProxyServer")
+            .clone();
+        let logger = self.logger.clone();
+        let stream_key = payload.stream_key;
+        let last_data = payload.sequenced_packet.last_data;
+        if last_data {
+            self.closing_requests.remove(&stream_key);
+        } else {
+            self.closing_requests.insert(
+                stream_key,
+                (ProxyServer::closing_request_after(&payload), source_addr),
+            );
+        }
+        if let Some(route) = self.cached_route(&stream_key, last_data) {
+            ProxyServer::try_transmit_to_hopper(
+                cryptde,
+                hopper,
+                Ok(Ok(route)),
+                payload,
+                logger,
+                source_addr,
+                dispatcher,
+                accountant_exit_sub,
+                accountant_routing_sub,
+                add_return_route_sub,
+            )
+            .unwrap_or(());
+            return ();
+        }
+        // Exits already carrying as many of our streams as they advertised aren't asked for more
+        let mut avoided_exit_keys = avoided_exit_keys;
+        self.sla_monitor
+            .full_exits(&stream_key)
+            .into_iter()
+            .for_each(|exit_key| {
+                if !avoided_exit_keys.contains(&exit_key) {
+                    avoided_exit_keys.push(exit_key)
+                }
+            });
+        // So are the exits of our other streams to destinations with large responses, if this is one
+        if self.destination_hints.parameters(&stream_key).isolated {
+            self.destination_hints
+                .isolated_streams(&stream_key)
+                .iter()
+                .filter_map(|other_stream_key| self.exit_key_of(other_stream_key))
+                .for_each(|exit_key| {
+                    if !avoided_exit_keys.contains(&exit_key) {
+                        avoided_exit_keys.push(exit_key)
+                    }
+                });
+        }
+        let minimum_hop_count = match (self.is_decentralized, &self.adaptive_hops) {
+            (false, _) => 0,
+            (true, Some(adaptive_hops)) => adaptive_hops.hop_count(DEFAULT_MINIMUM_HOP_COUNT),
+            (true, None) => DEFAULT_MINIMUM_HOP_COUNT,
+        };
+        let route_query = if payload.tls_at_exit {
+            RouteQueryMessage::exit_tls_route_request(minimum_hop_count)
+        } else {
+            RouteQueryMessage::data_indefinite_route_request(minimum_hop_count)
+        }
+        .avoiding_exits(avoided_exit_keys)
+        .exiting_in(self.exit_location_opt.clone());
+        tokio::spawn(route_source.send(route_query).then(move |route_result| {
+            if let Ok(Ok(ref route)) = route_result {
+                if !last_data {
+                    add_route_sub
+                        .try_send(AddRouteMessage {
+                            stream_key,
+                            route: route.clone(),
+                        })
+                        .expect("ProxyServer is dead");
+                }
+            }
+            ProxyServer::try_transmit_to_hopper(
+                cryptde,
+                hopper,
+                route_result,
+                payload,
+                logger,
+                source_addr,
+                dispatcher,
+                accountant_exit_sub,
+                accountant_routing_sub,
+                add_return_route_sub,
+            )
+        }));
+    }
+
+    // Follows payload in sequence, carrying nothing but last_data
+    fn closing_request_after(payload: &ClientRequestPayload) -> ClientRequestPayload {
+        ClientRequestPayload {
+            stream_key: payload.stream_key,
+            sequenced_packet: SequencedPacket {
+                data: vec![],
+                sequence_number: payload.sequenced_packet.sequence_number + 1,
+                last_data: true,
+            },
+            target_hostname: payload.target_hostname.clone(),
+            target_port: payload.target_port,
+            protocol: payload.protocol,
+            originator_public_key: payload.originator_public_key.clone(),
+            tls_at_exit: payload.tls_at_exit,
+            upload_window: payload.upload_window,
+            udp: payload.udp,
+            dns_server: payload.dns_server,
+            cancel: false,
+        }
+    }
+
+    // A stream keeps using the route it was given until the Neighborhood's validity window for it
+    // runs out; then the next packet asks for a fresh one. Streams to destinations that keep their
+    // connections alive keep their routes, since another exit would mean another connection.
+    fn cached_route(
+        &mut self,
+        stream_key: &StreamKey,
+        last_data: bool,
+    ) -> Option<RouteQueryResponse> {
+        let keep_route = self.destination_hints.parameters(stream_key).keep_route;
+        let route_opt = match self.routes.get(stream_key) {
+            Some((route, expiration)) if keep_route || Instant::now() < *expiration => {
+                Some(route.clone())
+            }
+            Some(_) => {
+                self.logger.for_stream(stream_key).debug(format!(
+                    "Route for stream {:?} has expired; asking for a new one",
+                    stream_key
+                ));
+                None
+            }
+            None => None,
+        };
+        if last_data || route_opt.is_none() {
+            self.routes.remove(stream_key);
+        }
+        route_opt
+    }
+
+    fn make_payload(&mut self, msg: InboundClientData) -> Result<ClientRequestPayload, ()> {
+        let stream_key = match self.keys_and_addrs.b_to_a(&msg.peer_addr) {
+            Some(stream_key) => stream_key,
+            None => {
+                let stream_key = self
+                    .stream_key_factory
+                    .make(&self.cryptde.public_key(), msg.peer_addr);
+                self.keys_and_addrs
+                    .insert(stream_key.clone(), msg.peer_addr);
+                stream_key
+            }
+        };
+        match self
+            .client_request_payload_factory
+            .make(&msg, stream_key, self.cryptde, &self.logger)
+        {
+            None => {
+                self.logger
+                    .error(format!("Couldn't create ClientRequestPayload"));
+                Err(())
+            }
+            Some(payload) => Ok(payload),
+        }
+    }
+
+    fn try_transmit_to_hopper(
+        cryptde: &'static dyn CryptDE,
+        hopper: Recipient<Syn, IncipientCoresPackage>,
+        route_result: Result<Result<RouteQueryResponse, RouteRefusal>, MailboxError>,
+        payload: ClientRequestPayload,
+        logger: Logger,
+        source_addr: SocketAddr,
+        dispatcher: Recipient<Syn, TransmitDataMsg>,
+        accountant_exit_sub: Recipient<Syn, ReportExitServiceConsumedMessage>,
+        accountant_routing_sub: Recipient<Syn, ReportRoutingServiceConsumedMessage>,
+        add_return_route_sub: Recipient<Syn, AddReturnRouteMessage>,
+    ) -> Result<(), ()> {
+        match route_result {
+            Ok(Ok(route_query_response)) => match route_query_response.expected_services {
+                ExpectedServices::RoundTrip(over, back, return_route_id) => {
+                    add_return_route_sub
+                        .try_send(AddReturnRouteMessage {
+                            return_route_id,
+                            expected_services: back.clone(),
+                            route_keys: ProxyServer::route_keys(&over, &back),
+                        })
+                        .expect("ProxyServer is dead");
+                    ProxyServer::report_exit_service(
+                        accountant_exit_sub,
+                        over.clone(),
+                        &payload,
+                        &logger,
+                    );
+                    ProxyServer::transmit_to_hopper(
+                        cryptde,
+                        hopper,
+                        payload,
+                        &route_query_response.route,
+                        over.clone(),
+                        &logger,
+                        source_addr,
+                        dispatcher,
+                        accountant_routing_sub,
+                    );
+                }
+                _ => panic!("Expected RoundTrip ExpectedServices but got OneWay"),
+            },
+            Ok(Err(refusal)) => {
+                ProxyServer::handle_route_failure(
+                    payload,
+                    &refusal,
+                    &logger,
+                    source_addr,
+                    dispatcher,
+                );
+            }
+            Err(e) => {
+                let msg = format!("Neighborhood refused to answer route request: {}", e);
+                logger.error(msg);
+            }
+        };
+        Ok(())
+    }
+
+    fn report_routing_service(
+        accountant_routing_sub: Recipient<Syn, ReportRoutingServiceConsumedMessage>,
+        expected_services: Vec<ExpectedService>,
+        payload_size: usize,
+        logger: &Logger,
+    ) {
+        let routing_services: Vec<(&Wallet, &RateCard)> = expected_services
+            .iter()
+            .filter_map(|service| match service {
+                ExpectedService::Routing(_, earning_wallet, rate_card) => {
+                    Some((earning_wallet, rate_card))
+                }
+                _ => None,
+            })
+            .collect();
+        if routing_services.is_empty() {
+            logger.debug("No routing services requested.".to_string());
+        }
+        routing_services
+            .into_iter()
+            .for_each(|(earning_wallet, rate_card)| {
+                let report_routing_service_consumed = ReportRoutingServiceConsumedMessage {
+                    earning_wallet: earning_wallet.clone(),
+                    payload_size,
+                    service_rate: rate_card.routing_service_rate,
+                    byte_rate: rate_card.routing_byte_rate,
+                    correlation_id_opt: None,
+                };
+                accountant_routing_sub
+                    .try_send(report_routing_service_consumed)
+                    .expect("Accountant is dead");
+            });
+    }
+
+    fn report_exit_service(
+        accountant_exit_sub: Recipient<Syn, ReportExitServiceConsumedMessage>,
+        expected_services: Vec<ExpectedService>,
+        payload: &ClientRequestPayload,
+        logger: &Logger,
+    ) {
+        match expected_services
+            .iter()
+            .find_map(|expected_service| match expected_service {
+                ExpectedService::Exit(_, earning_wallet, rate_card) => {
+                    Some((earning_wallet, rate_card))
+                }
+                _ => None,
+            }) {
+            Some((earning_wallet, rate_card)) => {
+                let payload_size = payload.sequenced_packet.data.len();
+                let report_exit_service_consumed_message = ReportExitServiceConsumedMessage {
+                    earning_wallet: earning_wallet.clone(),
+                    payload_size,
+                    service_rate: rate_card.exit_service_rate,
+                    byte_rate: rate_card.exit_byte_rate,
+                    correlation_id_opt: Some(CorrelationId::new(
+                        payload.stream_key,
+                        SystemTime::now(),
+                    )),
+                };
+                accountant_exit_sub
+                    .try_send(report_exit_service_consumed_message)
+                    .expect("Accountant is dead");
+            }
+            None => logger.debug("No exit service requested.".to_string()),
+        };
+    }
+
+    fn transmit_to_hopper(
+        cryptde: &'static dyn CryptDE,
+        hopper: Recipient<Syn, IncipientCoresPackage>,
+        payload: ClientRequestPayload,
+        route: &Route,
+        expected_services: Vec<ExpectedService>,
+        logger: &Logger,
+        source_addr: SocketAddr,
+        dispatcher: Recipient<Syn, TransmitDataMsg>,
+        accountant_routing_sub: Recipient<Syn, ReportRoutingServiceConsumedMessage>,
+    ) {
+        let destination_key_opt = if !expected_services.is_empty()
+            && expected_services
+                .iter()
+                .all(|expected_service| match expected_service {
+                    ExpectedService::Nothing => true,
+                    _ => false,
+                }) {
+            Some(payload.originator_public_key.clone())
+        } else {
+            expected_services.iter().find_map(|service| match service {
+                ExpectedService::Exit(public_key, _, _) => Some(public_key.clone()),
+                _ => None,
+            })
+        };
+
+        match destination_key_opt {
+            None => ProxyServer::handle_route_failure(
+                payload,
+                &RouteRefusal::NoExitNode(String::from("route has no exit service")),
+                &logger,
+                source_addr,
+                dispatcher,
+            ),
+            Some(payload_destination_key) => {
+                logger.debug(format!(
+                    "transmit to hopper with destination key {}",
+                    payload_destination_key.fingerprint()
+                ));
+                let pkg = IncipientCoresPackage::new(
+                    cryptde,
+                    route.clone(),
+                    payload,
+                    &payload_destination_key,
+                )
+                .expect("Key magically disappeared");
+                ProxyServer::report_routing_service(
+                    accountant_routing_sub,
+                    expected_services,
+                    pkg.payload.len(),
+                    &logger,
+                );
+                hopper.try_send(pkg).expect("Hopper is dead");
+            }
+        }
+    }
+
+    fn handle_route_failure(
+        payload: ClientRequestPayload,
+        refusal: &RouteRefusal,
+        logger: &Logger,
+        source_addr: SocketAddr,
+        dispatcher: Recipient<Syn, TransmitDataMsg>,
+    ) {
+        let target_hostname = ProxyServer::hostname(&payload);
+        ProxyServer::send_route_failure(payload, refusal, source_addr, dispatcher);
+        logger.error(format!(
+            "Failed to find route to {}: {}",
+            target_hostname, refusal
+        ));
+    }
+
+    fn send_route_failure(
+        payload: ClientRequestPayload,
+        refusal: &RouteRefusal,
+        source_addr: SocketAddr,
+        dispatcher: Recipient<Syn, TransmitDataMsg>,
+    ) {
+        let data = match payload.protocol {
+            ProxyProtocol::HTTP => {
+                let target_hostname = ProxyServer::hostname(&payload);
+                http_server_impersonator::make_error_response (
+                    503,
+                   "Routing Problem",
+                    format! ("Can't find a route to {}", target_hostname).as_str (),
+                    format! ("Substratum can't find a route through the Network yet to a Node that knows \
+                    where to find {}. Maybe later enough will be known about the Network to \
+                    find that Node, but we can't guarantee it. We're sorry. ({})", target_hostname, refusal).as_str ()
+                )
+            }
+            ProxyProtocol::TLS => vec![],
+        };
+        let msg = TransmitDataMsg {
+            endpoint: Endpoint::Socket(source_addr),
+            last_data: true,
+            sequence_number: Some(0),
+            data,
+        };
+        dispatcher.try_send(msg).expect("Dispatcher is dead");
+    }
+
+    fn hostname(payload: &ClientRequestPayload) -> String {
+        match payload.target_hostname {
+            Some(ref thn) => thn.clone(),
+            None => "<unknown>".to_string(),
+        }
+    }
+
+    fn get_return_route_id(&self, remaining_route: &Route) -> Result<u32, ()> {
+        let mut mut_remaining_route = remaining_route.clone();
+        match mut_remaining_route.shift(self.cryptde) {
+            Ok(_) => (),
+            Err(_) => {
+                self.logger.error(
+                    "Can't report services consumed: return route ID is unspecified".to_string(),
+                );
+                return Err(());
+            }
+        }
+        match mut_remaining_route.id(self.cryptde) {
+            Ok(return_route_id) => Ok(return_route_id),
+            Err(_) => {
+                self.logger.error(
+                    "Can't report services consumed: return route ID is unreadable".to_string(),
+                );
+                Err(())
+            }
+        }
+    }
+
+    fn release_held_requests(&mut self, stream_key: &StreamKey, requests_received: u64) {
+        if let Some(buffer) = self.retransmission_buffers.get_mut(stream_key) {
+            buffer.acknowledge(requests_received);
+        }
+        let released = match self.upload_windows.get_mut(stream_key) {
+            Some(upload_window) => upload_window.acknowledge(requests_received),
+            None => return,
+        };
+        released.into_iter().for_each(|(payload, source_addr)| {
+            self.retain_for_retransmission(&payload, source_addr);
+            self.send_request(payload, source_addr)
+        });
+    }
+
+    fn report_response_services_consumed(
+        &self,
+        stream_key: &StreamKey,
+        request_id: u32,
+        exit_size_opt: Option<usize>,
+        routing_size: usize,
+    ) -> Result<(), ()> {
+        let services = match self.route_ids_to_services.get(&request_id) {
+            Some(s) => s,
+            None => {
+                self.logger.error(format!(
+                    "Can't report services consumed: return route ID {} is not recognized",
+                    request_id
+                ));
+                return Err(());
+            }
+        };
+        services.iter().for_each(|service| match service {
+            ExpectedService::Nothing => (),
+            ExpectedService::Exit(_, wallet, rate_card) => {
+                if let Some(exit_size) = exit_size_opt {
+                    self.accountant_exit
+                        .as_ref()
+                        .expect("ProxyServer unbound")
+                        .try_send(ReportExitServiceConsumedMessage {
+                            earning_wallet: wallet.clone(),
+                            payload_size: exit_size,
+                            service_rate: rate_card.exit_service_rate,
+                            byte_rate: rate_card.exit_byte_rate,
+                            correlation_id_opt: Some(CorrelationId::new(
+                                *stream_key,
+                                SystemTime::now(),
+                            )),
+                        })
+                        .expect("Accountant is dead")
+                }
+            }
+            ExpectedService::Routing(_, wallet, rate_card) => self
+                .accountant_routing
+                .as_ref()
+                .expect("ProxyServer unbound")
+                .try_send(ReportRoutingServiceConsumedMessage {
+                    earning_wallet: wallet.clone(),
+                    payload_size: routing_size,
+                    service_rate: rate_card.routing_service_rate,
+                    byte_rate: rate_card.routing_byte_rate,
+                    correlation_id_opt: None,
+                })
+                .expect("Accountant is dead"),
+        });
+        Ok(())
+    }
+}
+
+trait StreamKeyFactory: Send {
+    fn make(&self, public_key: &PublicKey, peer_addr: SocketAddr) -> StreamKey;
+}
+
+struct StreamKeyFactoryReal {}
+
+impl StreamKeyFactory for StreamKeyFactoryReal {
+    fn make(&self, public_key: &PublicKey, peer_addr: SocketAddr) -> StreamKey {
+        // TODO: Replace this implementation
+        StreamKey::new(public_key.clone(), peer_addr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::neighborhood::neighborhood::MAXIMUM_ROUTE_VALIDITY;
+    use crate::neighborhood::route_scores::MAXIMUM_ROUTE_SCORE;
+    use crate::proxy_server::adaptive_hops::ADAPTIVE_HOPS_SAMPLE_SIZE;
+    use crate::proxy_server::destination_hints::LARGE_RESPONSE_BYTES;
+    use crate::proxy_server::sla_monitor::SLA_SAMPLE_BYTES;
+    use crate::sub_lib::accountant::ReportRoutingServiceConsumedMessage;
+    use crate::sub_lib::cryptde::decodex;
+    use crate::sub_lib::cryptde::encodex;
+    use crate::sub_lib::cryptde::CryptData;
+    use crate::sub_lib::cryptde::PlainData;
+    use crate::sub_lib::dispatcher::Component;
+    use crate::sub_lib::hop::LiveHop;
+    use crate::sub_lib::hopper::ExpiredCoresPackage;
+    use crate::sub_lib::hopper::DEFAULT_PER_ROUTING_BYTE_RATE;
+    use crate::sub_lib::hopper::DEFAULT_PER_ROUTING_RATE;
+    use crate::sub_lib::http_server_impersonator;
+    use crate::sub_lib::neighborhood::ExitSla;
+    use crate::sub_lib::neighborhood::ExpectedService;
+    use crate::sub_lib::neighborhood::ExpectedServices;
+    use crate::sub_lib::proxy_client::ClientResponsePayload;
+    use crate::sub_lib::proxy_client::DEFAULT_PER_EXIT_BYTE_RATE;
+    use crate::sub_lib::proxy_client::DEFAULT_PER_EXIT_RATE;
+    use crate::sub_lib::proxy_server::ClientRequestPayload;
+    use crate::sub_lib::proxy_server::ProxyProtocol;
+    use crate::sub_lib::route::Route;
+    use crate::sub_lib::route::RouteSegment;
+    use crate::sub_lib::ttl_hashmap::TtlHashMap;
+    use crate::sub_lib::wallet::Wallet;
+    use crate::test_utils::logging::init_test_logging;
+    use crate::test_utils::logging::TestLogHandler;
+    use crate::test_utils::recorder::make_recorder;
+    use crate::test_utils::recorder::peer_actors_builder;
+    use crate::test_utils::recorder::Recorder;
+    use crate::test_utils::recorder::Recording;
+    use crate::test_utils::test_utils::cryptde;
+    use crate::test_utils::test_utils::make_meaningless_route;
+    use crate::test_utils::test_utils::make_meaningless_stream_key;
+    use crate::test_utils::test_utils::zero_hop_route_response;
+    use actix::msgs;
+    use actix::Arbiter;
+    use actix::System;
+    use std::cell::RefCell;
+    use std::fs;
+    use std::net::IpAddr;
+    use std::net::SocketAddr;
+    use std::path::PathBuf;
+    use std::str::FromStr;
+    use std::sync::mpsc;
+    use std::sync::Arc;
+    use std::sync::Mutex;
+    use std::sync::MutexGuard;
+    use std::thread;
+
+    struct StreamKeyFactoryMock {
+        make_parameters: Arc<Mutex<Vec<(PublicKey, SocketAddr)>>>,
+        make_results: RefCell<Vec<StreamKey>>,
+    }
+
+    impl StreamKeyFactory for StreamKeyFactoryMock {
+        fn make(&self, key: &PublicKey, peer_addr: SocketAddr) -> StreamKey {
+            self.make_parameters
+                .lock()
+                .unwrap()
+                .push((key.clone(), peer_addr));
+            self.make_results.borrow_mut().remove(0)
+        }
+    }
+
+    impl StreamKeyFactoryMock {
+        fn new() -> StreamKeyFactoryMock {
+            StreamKeyFactoryMock {
+                make_parameters: Arc::new(Mutex::new(vec![])),
+                make_results: RefCell::new(vec![]),
+            }
+        }
+
+        fn make_parameters(
+            mut self,
+            params: &Arc<Mutex<Vec<(PublicKey, SocketAddr)>>>,
+        ) -> StreamKeyFactoryMock {
+            self.make_parameters = params.clone();
+            self
+        }
+
+        fn make_result(self, stream_key: StreamKey) -> StreamKeyFactoryMock {
+            self.make_results.borrow_mut().push(stream_key);
+            self
+        }
+    }
+
+    fn return_route_with_id(cryptde: &CryptDE, return_route_id: u32) -> Route {
+        let cover_hop = make_cover_hop(cryptde);
+        let id_hop = cryptde
+            .encode(
+                &cryptde.public_key(),
+                &PlainData::from(serde_cbor::ser::to_vec(&return_route_id).unwrap()),
+            )
+            .unwrap();
+        Route {
+            hops: vec![cover_hop, id_hop],
+        }
+    }
+
+    fn make_cover_hop(cryptde: &dyn CryptDE) -> CryptData {
         encodex(
             cryptde,
-            &cryptde.public_key(),
-            &LiveHop {
-                public_key: cryptde.public_key(),
-                consuming_wallet: None,
-                component: Component::ProxyServer,
+            &cryptde.public_key(),
+            &LiveHop {
+                public_key: cryptde.public_key(),
+                consuming_wallet: None,
+                component: Component::ProxyServer,
+            },
+        )
+        .unwrap()
+    }
+
+    fn check_exit_report(
+        accountant_recording: &MutexGuard<Recording>,
+        idx: usize,
+        wallet: &Wallet,
+        payload_size: usize,
+        stream_key: StreamKey,
+    ) {
+        assert_eq!(
+            accountant_recording.get_record::<ReportExitServiceConsumedMessage>(idx),
+            &ReportExitServiceConsumedMessage {
+                earning_wallet: wallet.clone(),
+                payload_size,
+                service_rate: DEFAULT_PER_EXIT_RATE,
+                byte_rate: DEFAULT_PER_EXIT_BYTE_RATE,
+                correlation_id_opt: Some(CorrelationId::new(stream_key, SystemTime::now())),
+            }
+        );
+    }
+
+    fn check_routing_report(
+        accountant_recording: &MutexGuard<Recording>,
+        idx: usize,
+        wallet: &Wallet,
+        payload_size: usize,
+    ) {
+        assert_eq!(
+            accountant_recording.get_record::<ReportRoutingServiceConsumedMessage>(idx),
+            &ReportRoutingServiceConsumedMessage {
+                earning_wallet: wallet.clone(),
+                payload_size,
+                service_rate: DEFAULT_PER_ROUTING_RATE,
+                byte_rate: DEFAULT_PER_ROUTING_BYTE_RATE,
+                correlation_id_opt: None,
+            }
+        );
+    }
+
+    #[test]
+    fn proxy_server_uses_standard_protocol_packs_until_told_otherwise() {
+        let mut subject = ProxyServer::new(cryptde(), false);
+
+        assert_eq!(
+            subject
+                .client_request_payload_factory
+                .protocol_for(Some(80)),
+            Some(ProxyProtocol::HTTP)
+        );
+
+        let mut protocol_pack_ports = HashMap::new();
+        protocol_pack_ports.insert(8080, String::from("http"));
+        subject.map_protocol_packs(&protocol_pack_ports);
+
+        assert_eq!(
+            subject
+                .client_request_payload_factory
+                .protocol_for(Some(80)),
+            None
+        );
+        assert_eq!(
+            subject
+                .client_request_payload_factory
+                .protocol_for(Some(8080)),
+            Some(ProxyProtocol::HTTP)
+        );
+    }
+
+    #[test]
+    fn proxy_server_rereads_its_privacy_policy_when_told_to_reload() {
+        init_test_logging();
+        let directory = PathBuf::from(
+            "generated/test/proxy_server/proxy_server_rereads_its_privacy_policy_when_told_to_reload",
+        );
+        fs::remove_dir_all(&directory).is_ok();
+        fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("privacy.json");
+        fs::write(&path, r#"{"strip_headers": ["Via"]}"#).unwrap();
+        let mut subject = ProxyServer::new(cryptde(), false);
+        subject.enable_privacy_filter(
+            &PrivacyPolicy::from_json(r#"{"strip_headers": ["DNT"]}"#).unwrap(),
+        );
+        subject.reload_privacy_policy_from(&path);
+
+        subject.reload_privacy_policy();
+
+        assert_eq!(
+            subject.client_request_payload_factory.privacy_policy(),
+            Some(&PrivacyPolicy::from_json(r#"{"strip_headers": ["Via"]}"#).unwrap())
+        );
+        TestLogHandler::new().exists_log_containing(&format!(
+            "INFO: Proxy Server: Reloaded privacy policy from {:?}",
+            path
+        ));
+    }
+
+    #[test]
+    fn proxy_server_keeps_its_privacy_policy_if_the_reloaded_one_is_bad() {
+        init_test_logging();
+        let directory = PathBuf::from(
+            "generated/test/proxy_server/proxy_server_keeps_its_privacy_policy_if_the_reloaded_one_is_bad",
+        );
+        fs::remove_dir_all(&directory).is_ok();
+        fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("privacy.json");
+        fs::write(&path, "booga").unwrap();
+        let mut subject = ProxyServer::new(cryptde(), false);
+        subject.enable_privacy_filter(
+            &PrivacyPolicy::from_json(r#"{"strip_headers": ["DNT"]}"#).unwrap(),
+        );
+        subject.reload_privacy_policy_from(&path);
+
+        subject.reload_privacy_policy();
+
+        assert_eq!(
+            subject.client_request_payload_factory.privacy_policy(),
+            Some(&PrivacyPolicy::from_json(r#"{"strip_headers": ["DNT"]}"#).unwrap())
+        );
+        TestLogHandler::new().exists_log_containing(&format!(
+            "WARN: Proxy Server: Couldn't understand privacy policy {:?}",
+            path
+        ));
+        TestLogHandler::new().exists_log_containing("keeping the privacy policy already in force");
+    }
+
+    #[test]
+    fn proxy_server_without_a_privacy_policy_file_ignores_reloads() {
+        let mut subject = ProxyServer::new(cryptde(), false);
+
+        subject.reload_privacy_policy();
+
+        assert_eq!(
+            subject.client_request_payload_factory.privacy_policy(),
+            None
+        );
+    }
+
+    #[test]
+    fn proxy_server_receives_http_request_with_new_stream_key_from_dispatcher_then_sends_cores_package_to_hopper(
+    ) {
+        let cryptde = cryptde();
+        let http_request = b"GET /index.html HTTP/1.1\r\nHost: nowhere.com\r\n\r\n";
+        let hopper_mock = Recorder::new();
+        let hopper_log_arc = hopper_mock.get_recording();
+        let hopper_awaiter = hopper_mock.get_awaiter();
+        let (neighborhood_mock, _, neighborhood_recording_arc) = make_recorder();
+        let neighborhood_mock = neighborhood_mock
+            .route_query_response(Ok(zero_hop_route_response(&cryptde.public_key(), cryptde)));
+        let socket_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
+        let stream_key = make_meaningless_stream_key();
+        let expected_data = http_request.to_vec();
+        let msg_from_dispatcher = InboundClientData {
+            peer_addr: socket_addr.clone(),
+            reception_port: Some(80),
+            sequence_number: Some(0),
+            last_data: true,
+            is_clandestine: false,
+            data: expected_data.clone(),
+            neighbor_key_opt: None,
+        };
+        let expected_http_request = PlainData::new(http_request);
+        let key = cryptde.public_key();
+        let route = zero_hop_route_response(&key, cryptde).route;
+        let expected_payload = ClientRequestPayload {
+            stream_key: stream_key.clone(),
+            sequenced_packet: SequencedPacket {
+                data: expected_http_request.into(),
+                sequence_number: 0,
+                last_data: true,
+            },
+            target_hostname: Some(String::from("nowhere.com")),
+            target_port: 80,
+            protocol: ProxyProtocol::HTTP,
+            originator_public_key: key.clone(),
+            tls_at_exit: false,
+            upload_window: None,
+            udp: false,
+            dns_server: None,
+            cancel: false,
+        };
+        let expected_pkg =
+            IncipientCoresPackage::new(cryptde, route.clone(), expected_payload, &key).unwrap();
+        let make_parameters_arc = Arc::new(Mutex::new(vec![]));
+        let make_parameters_arc_a = make_parameters_arc.clone();
+        thread::spawn(move || {
+            let stream_key_factory = StreamKeyFactoryMock::new()
+                .make_parameters(&make_parameters_arc)
+                .make_result(stream_key);
+            let system = System::new("proxy_server_receives_http_request_from_dispatcher_then_sends_cores_package_to_hopper");
+            let mut subject = ProxyServer::new(cryptde, false);
+            subject.stream_key_factory = Box::new(stream_key_factory);
+            let subject_addr: Addr<Syn, ProxyServer> = subject.start();
+            let mut peer_actors = peer_actors_builder()
+                .hopper(hopper_mock)
+                .neighborhood(neighborhood_mock)
+                .build();
+            peer_actors.proxy_server = ProxyServer::make_subs_from(&subject_addr);
+            subject_addr.try_send(BindMessage { peer_actors }).unwrap();
+
+            subject_addr.try_send(msg_from_dispatcher).unwrap();
+
+            system.run();
+        });
+
+        hopper_awaiter.await_message_count(1);
+        let recording = hopper_log_arc.lock().unwrap();
+        let record = recording.get_record::<IncipientCoresPackage>(0);
+        assert_eq!(record, &expected_pkg);
+        let mut make_parameters = make_parameters_arc_a.lock().unwrap();
+        assert_eq!(
+            make_parameters.remove(0),
+            (cryptde.public_key(), socket_addr)
+        );
+        let recording = neighborhood_recording_arc.lock().unwrap();
+        let record = recording.get_record::<RouteQueryMessage>(0);
+        assert_eq!(record, &RouteQueryMessage::data_indefinite_route_request(0));
+    }
+
+    #[test]
+    fn proxy_server_receives_http_request_with_existing_stream_key_from_dispatcher_then_sends_cores_package_to_hopper(
+    ) {
+        let cryptde = cryptde();
+        let http_request = b"GET /index.html HTTP/1.1\r\nHost: nowhere.com\r\n\r\n";
+        let hopper_mock = Recorder::new();
+        let hopper_log_arc = hopper_mock.get_recording();
+        let hopper_awaiter = hopper_mock.get_awaiter();
+        let neighborhood_mock = Recorder::new()
+            .route_query_response(Ok(zero_hop_route_response(&cryptde.public_key(), cryptde)));
+        let socket_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
+        let stream_key = make_meaningless_stream_key();
+        let expected_data = http_request.to_vec();
+        let msg_from_dispatcher = InboundClientData {
+            peer_addr: socket_addr.clone(),
+            reception_port: Some(80),
+            sequence_number: Some(0),
+            last_data: true,
+            is_clandestine: false,
+            data: expected_data.clone(),
+            neighbor_key_opt: None,
+        };
+        let expected_http_request = PlainData::new(http_request);
+        let key = cryptde.public_key();
+        let route = zero_hop_route_response(&key, cryptde).route;
+        let expected_payload = ClientRequestPayload {
+            stream_key: stream_key.clone(),
+            sequenced_packet: SequencedPacket {
+                data: expected_http_request.into(),
+                sequence_number: 0,
+                last_data: true,
+            },
+            target_hostname: Some(String::from("nowhere.com")),
+            target_port: 80,
+            protocol: ProxyProtocol::HTTP,
+            originator_public_key: key.clone(),
+            tls_at_exit: false,
+            upload_window: None,
+            udp: false,
+            dns_server: None,
+            cancel: false,
+        };
+        let expected_pkg =
+            IncipientCoresPackage::new(cryptde, route.clone(), expected_payload, &key).unwrap();
+        thread::spawn(move || {
+            let stream_key_factory = StreamKeyFactoryMock::new(); // can't make any stream keys; shouldn't have to
+            let system = System::new("proxy_server_receives_http_request_from_dispatcher_then_sends_cores_package_to_hopper");
+            let mut subject = ProxyServer::new(cryptde, false);
+            subject.stream_key_factory = Box::new(stream_key_factory);
+            subject.keys_and_addrs.insert(stream_key, socket_addr);
+            let subject_addr: Addr<Syn, ProxyServer> = subject.start();
+            let mut peer_actors = peer_actors_builder()
+                .hopper(hopper_mock)
+                .neighborhood(neighborhood_mock)
+                .build();
+            peer_actors.proxy_server = ProxyServer::make_subs_from(&subject_addr);
+            subject_addr.try_send(BindMessage { peer_actors }).unwrap();
+
+            subject_addr.try_send(msg_from_dispatcher).unwrap();
+
+            system.run();
+        });
+
+        hopper_awaiter.await_message_count(1);
+        let recording = hopper_log_arc.lock().unwrap();
+        let record = recording.get_record::<IncipientCoresPackage>(0);
+        assert_eq!(record, &expected_pkg);
+    }
+
+    #[test]
+    fn proxy_server_receives_http_request_from_dispatcher_then_sends_multihop_cores_package_to_hopper(
+    ) {
+        let cryptde = cryptde();
+        let consuming_wallet = Wallet::new("consuming wallet");
+        let earning_wallet = Wallet::new("earning wallet");
+        let http_request = b"GET /index.html HTTP/1.1\r\nHost: nowhere.com\r\n\r\n";
+        let hopper_mock = Recorder::new();
+        let hopper_log_arc = hopper_mock.get_recording();
+        let hopper_awaiter = hopper_mock.get_awaiter();
+        let payload_destination_key = PublicKey::new(&[3]);
+        let route = Route::round_trip(
+            RouteSegment::new(
+                vec![
+                    &cryptde.public_key(),
+                    &PublicKey::new(&[1]),
+                    &PublicKey::new(&[2]),
+                    &payload_destination_key,
+                ],
+                Component::ProxyClient,
+            ),
+            RouteSegment::new(
+                vec![
+                    &payload_destination_key,
+                    &PublicKey::new(&[2]),
+                    &PublicKey::new(&[1]),
+                    &cryptde.public_key(),
+                ],
+                Component::ProxyServer,
+            ),
+            cryptde,
+            Some(consuming_wallet),
+            1234,
+        )
+        .unwrap();
+        let (neighborhood_mock, _, neighborhood_recording_arc) = make_recorder();
+        let neighborhood_mock = neighborhood_mock.route_query_response(Ok(RouteQueryResponse {
+            route: route.clone(),
+            expected_services: ExpectedServices::RoundTrip(
+                vec![
+                    ExpectedService::Exit(
+                        PublicKey::new(&[3]),
+                        earning_wallet.clone(),
+                        RateCard::default(),
+                    ),
+                    ExpectedService::Nothing,
+                ],
+                vec![
+                    ExpectedService::Nothing,
+                    ExpectedService::Exit(
+                        PublicKey::new(&[3]),
+                        earning_wallet,
+                        RateCard::default(),
+                    ),
+                ],
+                1234,
+            ),
+            valid_for: MAXIMUM_ROUTE_VALIDITY,
+            score: MAXIMUM_ROUTE_SCORE,
+            exit_sla_opt: None,
+        }));
+        let socket_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
+        let stream_key = make_meaningless_stream_key();
+        let expected_data = http_request.to_vec();
+        let msg_from_dispatcher = InboundClientData {
+            peer_addr: socket_addr.clone(),
+            reception_port: Some(80),
+            sequence_number: Some(0),
+            last_data: true,
+            is_clandestine: false,
+            data: expected_data.clone(),
+            neighbor_key_opt: None,
+        };
+        let expected_http_request = PlainData::new(http_request);
+        let key = cryptde.public_key();
+        let expected_payload = ClientRequestPayload {
+            stream_key: stream_key.clone(),
+            sequenced_packet: SequencedPacket {
+                data: expected_http_request.into(),
+                sequence_number: 0,
+                last_data: true,
+            },
+            target_hostname: Some(String::from("nowhere.com")),
+            target_port: 80,
+            protocol: ProxyProtocol::HTTP,
+            originator_public_key: key.clone(),
+            tls_at_exit: false,
+            upload_window: None,
+            udp: false,
+            dns_server: None,
+            cancel: false,
+        };
+        let expected_pkg = IncipientCoresPackage::new(
+            cryptde,
+            route.clone(),
+            expected_payload,
+            &payload_destination_key,
+        )
+        .unwrap();
+        thread::spawn(move || {
+            let stream_key_factory = StreamKeyFactoryMock::new().make_result(stream_key);
+            let system = System::new("proxy_server_receives_http_request_from_dispatcher_then_sends_cores_package_to_hopper");
+            let mut subject = ProxyServer::new(cryptde, true);
+            subject.stream_key_factory = Box::new(stream_key_factory);
+            let subject_addr: Addr<Syn, ProxyServer> = subject.start();
+            let mut peer_actors = peer_actors_builder()
+                .hopper(hopper_mock)
+                .neighborhood(neighborhood_mock)
+                .build();
+            peer_actors.proxy_server = ProxyServer::make_subs_from(&subject_addr);
+            subject_addr.try_send(BindMessage { peer_actors }).unwrap();
+
+            subject_addr.try_send(msg_from_dispatcher).unwrap();
+
+            system.run();
+        });
+
+        hopper_awaiter.await_message_count(1);
+        let recording = hopper_log_arc.lock().unwrap();
+        let record = recording.get_record::<IncipientCoresPackage>(0);
+        assert_eq!(record, &expected_pkg);
+        let recording = neighborhood_recording_arc.lock().unwrap();
+        let record = recording.get_record::<RouteQueryMessage>(0);
+        assert_eq!(record, &RouteQueryMessage::data_indefinite_route_request(2));
+    }
+
+    #[test]
+    fn proxy_server_sends_message_to_accountant_for_request_routing_service_consumed() {
+        let cryptde = cryptde();
+        let exit_earning_wallet = Wallet::new("exit earning wallet");
+        let route_1_earning_wallet = Wallet::new("route 1 earning wallet");
+        let route_2_earning_wallet = Wallet::new("route 2 earning wallet");
+        let http_request = b"GET /index.html HTTP/1.1\r\nHost: nowhere.com\r\n\r\n";
+        let (accountant_mock, accountant_awaiter, accountant_log_arc) = make_recorder();
+        let (neighborhood_mock, _, _) = make_recorder();
+        let neighborhood_mock = neighborhood_mock.route_query_response(Ok(RouteQueryResponse {
+            route: make_meaningless_route(),
+            expected_services: ExpectedServices::RoundTrip(
+                vec![
+                    ExpectedService::Nothing,
+                    ExpectedService::Routing(
+                        PublicKey::new(&[1]),
+                        route_1_earning_wallet.clone(),
+                        RateCard::default(),
+                    ),
+                    ExpectedService::Routing(
+                        PublicKey::new(&[2]),
+                        route_2_earning_wallet.clone(),
+                        RateCard::default(),
+                    ),
+                    ExpectedService::Exit(
+                        PublicKey::new(&[3]),
+                        exit_earning_wallet.clone(),
+                        RateCard::default(),
+                    ),
+                ],
+                vec![
+                    ExpectedService::Exit(
+                        PublicKey::new(&[3]),
+                        exit_earning_wallet.clone(),
+                        RateCard::default(),
+                    ),
+                    ExpectedService::Routing(
+                        PublicKey::new(&[2]),
+                        route_2_earning_wallet.clone(),
+                        RateCard::default(),
+                    ),
+                    ExpectedService::Routing(
+                        PublicKey::new(&[1]),
+                        route_1_earning_wallet.clone(),
+                        RateCard::default(),
+                    ),
+                    ExpectedService::Nothing,
+                ],
+                0,
+            ),
+            valid_for: MAXIMUM_ROUTE_VALIDITY,
+            score: MAXIMUM_ROUTE_SCORE,
+            exit_sla_opt: None,
+        }));
+        let socket_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
+        let stream_key = make_meaningless_stream_key();
+        let expected_data = http_request.to_vec();
+        let msg_from_dispatcher = InboundClientData {
+            peer_addr: socket_addr.clone(),
+            reception_port: Some(80),
+            sequence_number: Some(0),
+            last_data: true,
+            is_clandestine: false,
+            data: expected_data.clone(),
+            neighbor_key_opt: None,
+        };
+        thread::spawn(move || {
+            let stream_key_factory = StreamKeyFactoryMock::new().make_result(stream_key);
+            let system = System::new(
+                "proxy_server_sends_message_to_accountant_for_routing_service_consumed",
+            );
+            let mut subject = ProxyServer::new(cryptde, true);
+            subject.stream_key_factory = Box::new(stream_key_factory);
+            let subject_addr: Addr<Syn, ProxyServer> = subject.start();
+            let mut peer_actors = peer_actors_builder()
+                .accountant(accountant_mock)
+                .neighborhood(neighborhood_mock)
+                .build();
+            peer_actors.proxy_server = ProxyServer::make_subs_from(&subject_addr);
+            subject_addr.try_send(BindMessage { peer_actors }).unwrap();
+            subject_addr.try_send(msg_from_dispatcher).unwrap();
+            system.run();
+        });
+
+        let exit_key = PublicKey::new(&[3]);
+        let payload = ClientRequestPayload {
+            stream_key,
+            sequenced_packet: SequencedPacket::new(expected_data, 0, false),
+            target_hostname: Some("nowhere.com".to_string()),
+            target_port: 80,
+            protocol: ProxyProtocol::HTTP,
+            originator_public_key: exit_key,
+            tls_at_exit: false,
+            upload_window: None,
+            udp: false,
+            dns_server: None,
+            cancel: false,
+        };
+        let payload_ser = PlainData::from(serde_cbor::ser::to_vec(&payload).unwrap());
+        let payload_enc = cryptde.encode(&cryptde.public_key(), &payload_ser).unwrap();
+
+        accountant_awaiter.await_message_count(3);
+        let recording = accountant_log_arc.lock().unwrap();
+        let record = recording.get_record::<ReportRoutingServiceConsumedMessage>(1);
+        assert_eq!(
+            record,
+            &ReportRoutingServiceConsumedMessage {
+                earning_wallet: route_1_earning_wallet,
+                payload_size: payload_enc.len(),
+                service_rate: DEFAULT_PER_ROUTING_RATE,
+                byte_rate: DEFAULT_PER_ROUTING_BYTE_RATE,
+                correlation_id_opt: None,
+            }
+        );
+        let record = recording.get_record::<ReportRoutingServiceConsumedMessage>(2);
+        assert_eq!(
+            record,
+            &ReportRoutingServiceConsumedMessage {
+                earning_wallet: route_2_earning_wallet,
+                payload_size: payload_enc.len(),
+                service_rate: DEFAULT_PER_ROUTING_RATE,
+                byte_rate: DEFAULT_PER_ROUTING_BYTE_RATE,
+                correlation_id_opt: None,
+            }
+        );
+    }
+
+    #[test]
+    fn proxy_server_logs_messages_when_routing_services_are_not_requested() {
+        init_test_logging();
+        let cryptde = cryptde();
+        let http_request = b"GET /index.html HTTP/1.1\r\nHost: nowhere.com\r\n\r\n";
+        let (accountant_mock, _, accountant_log_arc) = make_recorder();
+        let (neighborhood_mock, _, _) = make_recorder();
+        let zero_hop_route_reponse = zero_hop_route_response(&cryptde.public_key(), cryptde);
+        let neighborhood_mock =
+            neighborhood_mock.route_query_response(Ok(zero_hop_route_reponse.clone()));
+        let socket_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
+        let stream_key = make_meaningless_stream_key();
+        let expected_data = http_request.to_vec();
+        let msg_from_dispatcher = InboundClientData {
+            peer_addr: socket_addr.clone(),
+            reception_port: Some(80),
+            sequence_number: Some(0),
+            last_data: true,
+            is_clandestine: false,
+            data: expected_data.clone(),
+            neighbor_key_opt: None,
+        };
+        thread::spawn(move || {
+            let stream_key_factory = StreamKeyFactoryMock::new().make_result(stream_key);
+            let system =
+                System::new("proxy_server_logs_messages_when_routing_services_are_not_requested");
+            let mut subject = ProxyServer::new(cryptde, true);
+            subject.stream_key_factory = Box::new(stream_key_factory);
+            let subject_addr: Addr<Syn, ProxyServer> = subject.start();
+            let mut peer_actors = peer_actors_builder()
+                .accountant(accountant_mock)
+                .neighborhood(neighborhood_mock)
+                .build();
+            peer_actors.proxy_server = ProxyServer::make_subs_from(&subject_addr);
+            subject_addr.try_send(BindMessage { peer_actors }).unwrap();
+            subject_addr.try_send(msg_from_dispatcher).unwrap();
+            system.run();
+        });
+
+        TestLogHandler::new()
+            .await_log_containing("DEBUG: Proxy Server: No routing services requested.", 1000);
+
+        assert_eq!(accountant_log_arc.lock().unwrap().len(), 0);
+    }
+
+    #[test]
+    fn proxy_server_sends_message_to_accountant_for_request_exit_service_consumed() {
+        let cryptde = cryptde();
+        let earning_wallet = Wallet::new("earning wallet");
+        let http_request = b"GET /index.html HTTP/1.1\r\nHost: nowhere.com\r\n\r\n";
+        let (accountant_mock, accountant_awaiter, accountant_log_arc) = make_recorder();
+        let (neighborhood_mock, _, _) = make_recorder();
+        let neighborhood_mock = neighborhood_mock.route_query_response(Ok(RouteQueryResponse {
+            route: make_meaningless_route(),
+            expected_services: ExpectedServices::RoundTrip(
+                vec![
+                    ExpectedService::Nothing,
+                    ExpectedService::Exit(
+                        PublicKey::new(&[3]),
+                        earning_wallet.clone(),
+                        RateCard::default(),
+                    ),
+                ],
+                vec![
+                    ExpectedService::Exit(
+                        PublicKey::new(&[3]),
+                        earning_wallet.clone(),
+                        RateCard::default(),
+                    ),
+                    ExpectedService::Nothing,
+                ],
+                0,
+            ),
+            valid_for: MAXIMUM_ROUTE_VALIDITY,
+            score: MAXIMUM_ROUTE_SCORE,
+            exit_sla_opt: None,
+        }));
+        let socket_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
+        let stream_key = make_meaningless_stream_key();
+        let expected_data = http_request.to_vec();
+        let msg_from_dispatcher = InboundClientData {
+            peer_addr: socket_addr.clone(),
+            reception_port: Some(80),
+            sequence_number: Some(0),
+            last_data: true,
+            is_clandestine: false,
+            data: expected_data.clone(),
+            neighbor_key_opt: None,
+        };
+        thread::spawn(move || {
+            let stream_key_factory = StreamKeyFactoryMock::new().make_result(stream_key);
+            let system =
+                System::new("proxy_server_sends_message_to_accountant_for_exit_service_consumed");
+            let mut subject = ProxyServer::new(cryptde, true);
+            subject.stream_key_factory = Box::new(stream_key_factory);
+            let subject_addr: Addr<Syn, ProxyServer> = subject.start();
+            let mut peer_actors = peer_actors_builder()
+                .accountant(accountant_mock)
+                .neighborhood(neighborhood_mock)
+                .build();
+            peer_actors.proxy_server = ProxyServer::make_subs_from(&subject_addr);
+            subject_addr.try_send(BindMessage { peer_actors }).unwrap();
+            subject_addr.try_send(msg_from_dispatcher).unwrap();
+            system.run();
+        });
+
+        accountant_awaiter.await_message_count(1);
+        let recording = accountant_log_arc.lock().unwrap();
+        let record = recording.get_record::<ReportExitServiceConsumedMessage>(0);
+        assert_eq!(
+            record,
+            &ReportExitServiceConsumedMessage {
+                earning_wallet,
+                payload_size: expected_data.len(),
+                service_rate: DEFAULT_PER_EXIT_RATE,
+                byte_rate: DEFAULT_PER_EXIT_BYTE_RATE,
+                correlation_id_opt: Some(CorrelationId::new(stream_key, SystemTime::now())),
+            }
+        );
+    }
+
+    #[test]
+    fn proxy_server_logs_message_when_exit_services_are_not_requested() {
+        init_test_logging();
+        let cryptde = cryptde();
+        let http_request = b"GET /index.html HTTP/1.1\r\nHost: nowhere.com\r\n\r\n";
+        let (accountant_mock, _, accountant_log_arc) = make_recorder();
+        let (neighborhood_mock, _, _) = make_recorder();
+        let zero_hop_route_reponse = zero_hop_route_response(&cryptde.public_key(), cryptde);
+        let neighborhood_mock =
+            neighborhood_mock.route_query_response(Ok(zero_hop_route_reponse.clone()));
+        let socket_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
+        let stream_key = make_meaningless_stream_key();
+        let expected_data = http_request.to_vec();
+        let msg_from_dispatcher = InboundClientData {
+            peer_addr: socket_addr.clone(),
+            reception_port: Some(80),
+            sequence_number: Some(0),
+            last_data: true,
+            is_clandestine: false,
+            data: expected_data.clone(),
+            neighbor_key_opt: None,
+        };
+        thread::spawn(move || {
+            let stream_key_factory = StreamKeyFactoryMock::new().make_result(stream_key);
+            let system =
+                System::new("proxy_server_logs_message_when_exit_services_are_not_consumed");
+            let mut subject = ProxyServer::new(cryptde, true);
+            subject.stream_key_factory = Box::new(stream_key_factory);
+            let subject_addr: Addr<Syn, ProxyServer> = subject.start();
+            let mut peer_actors = peer_actors_builder()
+                .accountant(accountant_mock)
+                .neighborhood(neighborhood_mock)
+                .build();
+            peer_actors.proxy_server = ProxyServer::make_subs_from(&subject_addr);
+            subject_addr.try_send(BindMessage { peer_actors }).unwrap();
+            subject_addr.try_send(msg_from_dispatcher).unwrap();
+            system.run();
+        });
+
+        TestLogHandler::new()
+            .await_log_containing("DEBUG: Proxy Server: No exit service requested.", 1000);
+
+        assert_eq!(accountant_log_arc.lock().unwrap().len(), 0);
+    }
+
+    #[test]
+    fn proxy_server_receives_http_request_from_dispatcher_but_neighborhood_cant_make_route() {
+        init_test_logging();
+        let cryptde = cryptde();
+        let http_request = b"GET /index.html HTTP/1.1\r\nHost: nowhere.com\r\n\r\n";
+        let (neighborhood_mock, _, neighborhood_recording_arc) = make_recorder();
+        let neighborhood_mock =
+            neighborhood_mock.route_query_response(Err(RouteRefusal::NotEnoughNodes {
+                known: 1,
+                required: 2,
+            }));
+        let dispatcher = Recorder::new();
+        let dispatcher_awaiter = dispatcher.get_awaiter();
+        let dispatcher_recording_arc = dispatcher.get_recording();
+        let socket_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
+        let expected_data = http_request.to_vec();
+        let msg_from_dispatcher = InboundClientData {
+            peer_addr: socket_addr.clone(),
+            reception_port: Some(80),
+            sequence_number: Some(0),
+            last_data: true,
+            data: expected_data.clone(),
+            is_clandestine: false,
+            neighbor_key_opt: None,
+        };
+        thread::spawn(move || {
+            let system = System::new("proxy_server_receives_http_request_from_dispatcher_but_neighborhood_cant_make_route");
+            let subject = ProxyServer::new(cryptde, true);
+            let subject_addr: Addr<Syn, ProxyServer> = subject.start();
+            let mut peer_actors = peer_actors_builder()
+                .dispatcher(dispatcher)
+                .neighborhood(neighborhood_mock)
+                .build();
+            peer_actors.proxy_server = ProxyServer::make_subs_from(&subject_addr);
+            subject_addr.try_send(BindMessage { peer_actors }).unwrap();
+
+            subject_addr.try_send(msg_from_dispatcher).unwrap();
+
+            system.run();
+        });
+
+        dispatcher_awaiter.await_message_count(1);
+        let recording = dispatcher_recording_arc.lock().unwrap();
+        let record = recording.get_record::<TransmitDataMsg>(0);
+        let expected_msg = TransmitDataMsg {
+            endpoint: Endpoint::Socket(SocketAddr::from_str("1.2.3.4:5678").unwrap()),
+            last_data: true,
+            sequence_number: Some(0),
+            data: http_server_impersonator::make_error_response(
+                503,
+                "Routing Problem",
+                "Can't find a route to nowhere.com",
+                "Substratum can't find a route through the Network yet to a Node that knows \
+                 where to find nowhere.com. Maybe later enough will be known about the Network to \
+                 find that Node, but we can't guarantee it. We're sorry. \
+                 (Only 1 Node(s) known that can carry traffic; at least 2 required)",
+            ),
+        };
+        assert_eq!(record, &expected_msg);
+        let recording = neighborhood_recording_arc.lock().unwrap();
+        let record = recording.get_record::<RouteQueryMessage>(0);
+        assert_eq!(record, &RouteQueryMessage::data_indefinite_route_request(2));
+        TestLogHandler::new()
+            .exists_log_containing("ERROR: Proxy Server: Failed to find route to nowhere.com: Only 1 Node(s) known that can carry traffic; at least 2 required");
+    }
+
+    #[test]
+    fn proxy_server_asks_for_an_exit_tls_route_when_exit_tls_is_requested() {
+        let cryptde = cryptde();
+        let (neighborhood_mock, neighborhood_awaiter, neighborhood_recording_arc) = make_recorder();
+        let neighborhood_mock =
+            neighborhood_mock.route_query_response(Err(RouteRefusal::NotEnoughNodes {
+                known: 1,
+                required: 2,
+            }));
+        let msg_from_dispatcher = InboundClientData {
+            peer_addr: SocketAddr::from_str("1.2.3.4:5678").unwrap(),
+            reception_port: Some(80),
+            sequence_number: Some(0),
+            last_data: true,
+            data: b"GET /index.html HTTP/1.1\r\nHost: nowhere.com\r\n\r\n".to_vec(),
+            is_clandestine: false,
+            neighbor_key_opt: None,
+        };
+        thread::spawn(move || {
+            let system =
+                System::new("proxy_server_asks_for_an_exit_tls_route_when_exit_tls_is_requested");
+            let mut subject = ProxyServer::new(cryptde, true);
+            subject.request_exit_tls();
+            let subject_addr: Addr<Syn, ProxyServer> = subject.start();
+            let mut peer_actors = peer_actors_builder()
+                .neighborhood(neighborhood_mock)
+                .build();
+            peer_actors.proxy_server = ProxyServer::make_subs_from(&subject_addr);
+            subject_addr.try_send(BindMessage { peer_actors }).unwrap();
+
+            subject_addr.try_send(msg_from_dispatcher).unwrap();
+
+            system.run();
+        });
+
+        neighborhood_awaiter.await_message_count(1);
+        let recording = neighborhood_recording_arc.lock().unwrap();
+        let record = recording.get_record::<RouteQueryMessage>(0);
+        assert_eq!(record, &RouteQueryMessage::exit_tls_route_request(2));
+    }
+
+    #[test]
+    fn proxy_server_asks_for_an_exit_in_the_requested_location() {
+        init_test_logging();
+        let cryptde = cryptde();
+        let (neighborhood_mock, neighborhood_awaiter, neighborhood_recording_arc) = make_recorder();
+        let neighborhood_mock = neighborhood_mock
+            .route_query_response(Err(RouteRefusal::NoExitInLocation(String::from("DE"))));
+        let msg_from_dispatcher = InboundClientData {
+            peer_addr: SocketAddr::from_str("1.2.3.4:5678").unwrap(),
+            reception_port: Some(80),
+            sequence_number: Some(0),
+            last_data: true,
+            data: b"GET /index.html HTTP/1.1\r\nHost: nowhere.com\r\n\r\n".to_vec(),
+            is_clandestine: false,
+            neighbor_key_opt: None,
+        };
+        thread::spawn(move || {
+            let system = System::new("proxy_server_asks_for_an_exit_in_the_requested_location");
+            let mut subject = ProxyServer::new(cryptde, true);
+            subject.request_exit_location(String::from("DE"));
+            let subject_addr: Addr<Syn, ProxyServer> = subject.start();
+            let mut peer_actors = peer_actors_builder()
+                .neighborhood(neighborhood_mock)
+                .build();
+            peer_actors.proxy_server = ProxyServer::make_subs_from(&subject_addr);
+            subject_addr.try_send(BindMessage { peer_actors }).unwrap();
+
+            subject_addr.try_send(msg_from_dispatcher).unwrap();
+
+            system.run();
+        });
+
+        neighborhood_awaiter.await_message_count(1);
+        let recording = neighborhood_recording_arc.lock().unwrap();
+        let record = recording.get_record::<RouteQueryMessage>(0);
+        assert_eq!(
+            record,
+            &RouteQueryMessage::data_indefinite_route_request(2)
+                .exiting_in(Some(String::from("DE")))
+        );
+        TestLogHandler::new().await_log_containing(
+            "ERROR: Proxy Server: Failed to find route to nowhere.com: No known exit Node declares itself to be in DE",
+            1000,
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Expected RoundTrip ExpectedServices but got OneWay")]
+    fn proxy_server_panics_if_it_receives_a_one_way_route_from_a_request_for_a_round_trip_route() {
+        let _system = System::new("proxy_server_panics_if_it_receives_a_one_way_route_from_a_request_for_a_round_trip_route");
+        let peer_actors = peer_actors_builder().build();
+
+        let cryptde = cryptde();
+        let route_result = Ok(Ok(RouteQueryResponse {
+            route: make_meaningless_route(),
+            expected_services: ExpectedServices::OneWay(vec![
+                ExpectedService::Nothing,
+                ExpectedService::Routing(
+                    PublicKey::new(&[1]),
+                    Wallet::new("earning wallet 1"),
+                    RateCard::default(),
+                ),
+                ExpectedService::Routing(
+                    PublicKey::new(&[2]),
+                    Wallet::new("earning wallet 2"),
+                    RateCard::default(),
+                ),
+                ExpectedService::Exit(
+                    PublicKey::new(&[3]),
+                    Wallet::new("exit earning wallet"),
+                    RateCard::default(),
+                ),
+            ]),
+            valid_for: MAXIMUM_ROUTE_VALIDITY,
+            score: MAXIMUM_ROUTE_SCORE,
+            exit_sla_opt: None,
+        }));
+        let payload = ClientRequestPayload {
+            stream_key: make_meaningless_stream_key(),
+            sequenced_packet: SequencedPacket {
+                data: vec![],
+                sequence_number: 0,
+                last_data: false,
+            },
+            target_hostname: None,
+            target_port: 0,
+            protocol: ProxyProtocol::TLS,
+            originator_public_key: cryptde.public_key(),
+            tls_at_exit: false,
+            upload_window: None,
+            udp: false,
+            dns_server: None,
+            cancel: false,
+        };
+        let logger = Logger::new("ProxyServer");
+        let source_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
+
+        ProxyServer::try_transmit_to_hopper(
+            cryptde,
+            peer_actors.hopper.from_hopper_client,
+            route_result,
+            payload,
+            logger,
+            source_addr,
+            peer_actors.dispatcher.from_dispatcher_client,
+            peer_actors.accountant.report_exit_service_consumed,
+            peer_actors.accountant.report_routing_service_consumed,
+            peer_actors.proxy_server.add_return_route,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn proxy_server_receives_http_request_from_dispatcher_but_neighborhood_cant_make_route_with_no_expected_services(
+    ) {
+        init_test_logging();
+        let cryptde = cryptde();
+        let public_key = &cryptde.public_key();
+        let http_request = b"GET /index.html HTTP/1.1\r\nHost: nowhere.com\r\n\r\n";
+        let (neighborhood_mock, _, neighborhood_recording_arc) = make_recorder();
+        let route_query_response = RouteQueryResponse {
+            route: Route::round_trip(
+                RouteSegment::new(vec![public_key, public_key], Component::ProxyClient),
+                RouteSegment::new(vec![public_key, public_key], Component::ProxyServer),
+                cryptde,
+                None,
+                1234,
+            )
+            .unwrap(),
+            expected_services: ExpectedServices::RoundTrip(vec![], vec![], 1234),
+            valid_for: MAXIMUM_ROUTE_VALIDITY,
+            score: MAXIMUM_ROUTE_SCORE,
+            exit_sla_opt: None,
+        };
+        let neighborhood_mock = neighborhood_mock.route_query_response(Ok(route_query_response));
+        let dispatcher = Recorder::new();
+        let dispatcher_awaiter = dispatcher.get_awaiter();
+        let dispatcher_recording_arc = dispatcher.get_recording();
+        let socket_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
+        let expected_data = http_request.to_vec();
+        let msg_from_dispatcher = InboundClientData {
+            peer_addr: socket_addr.clone(),
+            reception_port: Some(80),
+            sequence_number: Some(0),
+            last_data: true,
+            data: expected_data.clone(),
+            is_clandestine: false,
+            neighbor_key_opt: None,
+        };
+        thread::spawn(move || {
+            let system = System::new("proxy_server_receives_http_request_from_dispatcher_but_neighborhood_cant_make_route");
+            let subject = ProxyServer::new(cryptde, true);
+            let subject_addr: Addr<Syn, ProxyServer> = subject.start();
+            let mut peer_actors = peer_actors_builder()
+                .dispatcher(dispatcher)
+                .neighborhood(neighborhood_mock)
+                .build();
+            peer_actors.proxy_server = ProxyServer::make_subs_from(&subject_addr);
+            subject_addr.try_send(BindMessage { peer_actors }).unwrap();
+
+            subject_addr.try_send(msg_from_dispatcher).unwrap();
+
+            system.run();
+        });
+
+        dispatcher_awaiter.await_message_count(1);
+        let recording = dispatcher_recording_arc.lock().unwrap();
+        let record = recording.get_record::<TransmitDataMsg>(0);
+        let expected_msg = TransmitDataMsg {
+            endpoint: Endpoint::Socket(SocketAddr::from_str("1.2.3.4:5678").unwrap()),
+            last_data: true,
+            sequence_number: Some(0),
+            data: http_server_impersonator::make_error_response(
+                503,
+                "Routing Problem",
+                "Can't find a route to nowhere.com",
+                "Substratum can't find a route through the Network yet to a Node that knows \
+                 where to find nowhere.com. Maybe later enough will be known about the Network to \
+                 find that Node, but we can't guarantee it. We're sorry. \
+                 (No exit Node can be reached: route has no exit service)",
+            ),
+        };
+        assert_eq!(record, &expected_msg);
+        let recording = neighborhood_recording_arc.lock().unwrap();
+        let record = recording.get_record::<RouteQueryMessage>(0);
+        assert_eq!(record, &RouteQueryMessage::data_indefinite_route_request(2));
+        TestLogHandler::new()
+            .exists_log_containing("ERROR: Proxy Server: Failed to find route to nowhere.com: No exit Node can be reached: route has no exit service");
+    }
+
+    #[test]
+    fn proxy_server_receives_tls_client_hello_from_dispatcher_then_sends_cores_package_to_hopper() {
+        let tls_request = &[
+            0x16, // content_type: Handshake
+            0x00, 0x00, 0x00, 0x00, // version, length: don't care
+            0x01, // handshake_type: ClientHello
+            0x00, 0x00, 0x00, 0x00, 0x00, // length, version: don't care
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, // random: don't care
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, // random: don't care
+            0x00, // session_id_length
+            0x00, 0x00, // cipher_suites_length
+            0x00, // compression_methods_length
+            0x00, 0x13, // extensions_length
+            0x00, 0x00, // extension_type: server_name
+            0x00, 0x0F, // extension_length
+            0x00, 0x0D, // server_name_list_length
+            0x00, // server_name_type
+            0x00, 0x0A, // server_name_length
+            's' as u8, 'e' as u8, 'r' as u8, 'v' as u8, 'e' as u8, 'r' as u8, '.' as u8, 'c' as u8,
+            'o' as u8, 'm' as u8, // server_name
+        ];
+        let cryptde = cryptde();
+        let hopper_mock = Recorder::new();
+        let hopper_log_arc = hopper_mock.get_recording();
+        let hopper_awaiter = hopper_mock.get_awaiter();
+        let neighborhood_mock = Recorder::new()
+            .route_query_response(Ok(zero_hop_route_response(&cryptde.public_key(), cryptde)));
+        let stream_key = make_meaningless_stream_key();
+        let socket_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
+        let expected_data = tls_request.to_vec();
+        let msg_from_dispatcher = InboundClientData {
+            peer_addr: socket_addr.clone(),
+            reception_port: Some(443),
+            sequence_number: Some(0),
+            last_data: false,
+            is_clandestine: false,
+            data: expected_data.clone(),
+            neighbor_key_opt: None,
+        };
+        let expected_tls_request = PlainData::new(tls_request);
+        let key = cryptde.public_key();
+        let route = zero_hop_route_response(&key, cryptde).route;
+        let expected_payload = ClientRequestPayload {
+            stream_key: stream_key.clone(),
+            sequenced_packet: SequencedPacket {
+                data: expected_tls_request.into(),
+                sequence_number: 0,
+                last_data: false,
+            },
+            target_hostname: Some(String::from("server.com")),
+            target_port: 443,
+            protocol: ProxyProtocol::TLS,
+            originator_public_key: key.clone(),
+            tls_at_exit: false,
+            upload_window: None,
+            udp: false,
+            dns_server: None,
+            cancel: false,
+        };
+        let expected_pkg =
+            IncipientCoresPackage::new(cryptde, route.clone(), expected_payload, &key).unwrap();
+        thread::spawn(move || {
+            let mut subject = ProxyServer::new(cryptde, false);
+            subject.stream_key_factory =
+                Box::new(StreamKeyFactoryMock::new().make_result(stream_key.clone()));
+            let system = System::new("proxy_server_receives_tls_client_hello_from_dispatcher_then_sends_cores_package_to_hopper");
+            let subject_addr: Addr<Syn, ProxyServer> = subject.start();
+            let mut peer_actors = peer_actors_builder()
+                .hopper(hopper_mock)
+                .neighborhood(neighborhood_mock)
+                .build();
+            peer_actors.proxy_server = ProxyServer::make_subs_from(&subject_addr);
+            subject_addr.try_send(BindMessage { peer_actors }).unwrap();
+
+            subject_addr.try_send(msg_from_dispatcher).unwrap();
+
+            system.run();
+        });
+
+        hopper_awaiter.await_message_count(1);
+        let recording = hopper_log_arc.lock().unwrap();
+        let record = recording.get_record::<IncipientCoresPackage>(0);
+        assert_eq!(record, &expected_pkg);
+    }
+
+    #[test]
+    fn proxy_server_holds_fragmented_tls_client_hello_until_host_name_is_known() {
+        let mut client_hello = vec![
+            0x01, // handshake_type: ClientHello
+            0x00, 0x00, 0x3B, // length
+            0x03, 0x03, // version
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, // random: don't care
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, // random: don't care
+            0x00, // session_id_length
+            0x00, 0x00, // cipher_suites_length
+            0x00, // compression_methods_length
+            0x00, 0x13, // extensions_length
+            0x00, 0x00, // extension_type: server_name
+            0x00, 0x0F, // extension_length
+            0x00, 0x0D, // server_name_list_length
+            0x00, // server_name_type
+            0x00, 0x0A, // server_name_length
+        ];
+        client_hello.extend(b"server.com");
+        let mut tls_request = vec![0x16, 0x03, 0x01, 0x00, 0x3F];
+        tls_request.extend(client_hello);
+        let first_fragment = tls_request[..30].to_vec();
+        let second_fragment = tls_request[30..].to_vec();
+        let cryptde = cryptde();
+        let hopper_mock = Recorder::new();
+        let hopper_log_arc = hopper_mock.get_recording();
+        let hopper_awaiter = hopper_mock.get_awaiter();
+        let neighborhood_mock = Recorder::new()
+            .route_query_response(Ok(zero_hop_route_response(&cryptde.public_key(), cryptde)));
+        let stream_key = make_meaningless_stream_key();
+        let socket_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
+        let first_msg = InboundClientData {
+            peer_addr: socket_addr.clone(),
+            reception_port: Some(443),
+            sequence_number: Some(0),
+            last_data: false,
+            is_clandestine: false,
+            data: first_fragment.clone(),
+            neighbor_key_opt: None,
+        };
+        let second_msg = InboundClientData {
+            peer_addr: socket_addr.clone(),
+            reception_port: Some(443),
+            sequence_number: Some(1),
+            last_data: false,
+            is_clandestine: false,
+            data: second_fragment.clone(),
+            neighbor_key_opt: None,
+        };
+        thread::spawn(move || {
+            let mut subject = ProxyServer::new(cryptde, false);
+            subject.stream_key_factory =
+                Box::new(StreamKeyFactoryMock::new().make_result(stream_key.clone()));
+            let system = System::new(
+                "proxy_server_holds_fragmented_tls_client_hello_until_host_name_is_known",
+            );
+            let subject_addr: Addr<Syn, ProxyServer> = subject.start();
+            let mut peer_actors = peer_actors_builder()
+                .hopper(hopper_mock)
+                .neighborhood(neighborhood_mock)
+                .build();
+            peer_actors.proxy_server = ProxyServer::make_subs_from(&subject_addr);
+            subject_addr.try_send(BindMessage { peer_actors }).unwrap();
+
+            subject_addr.try_send(first_msg).unwrap();
+            subject_addr.try_send(second_msg).unwrap();
+
+            system.run();
+        });
+
+        hopper_awaiter.await_message_count(2);
+        let recording = hopper_log_arc.lock().unwrap();
+        let mut payloads: Vec<ClientRequestPayload> = (0..2)
+            .map(|idx| {
+                let record = recording.get_record::<IncipientCoresPackage>(idx);
+                decodex::<ClientRequestPayload>(cryptde, &record.payload).unwrap()
+            })
+            .collect();
+        payloads.sort_by_key(|payload| payload.sequenced_packet.sequence_number);
+        assert_eq!(payloads[0].sequenced_packet.data, first_fragment);
+        assert_eq!(payloads[0].sequenced_packet.sequence_number, 0);
+        assert_eq!(payloads[1].sequenced_packet.data, second_fragment);
+        assert_eq!(payloads[1].sequenced_packet.sequence_number, 1);
+        payloads.iter().for_each(|payload| {
+            assert_eq!(payload.target_hostname, Some(String::from("server.com")));
+            assert_eq!(payload.protocol, ProxyProtocol::TLS);
+        });
+    }
+
+    #[test]
+    fn proxy_server_releases_a_partial_tls_client_hello_from_a_client_that_goes_quiet() {
+        let tls_fragment = vec![
+            0x16, 0x03, 0x01, 0x00, 0x3F, 0x01, 0x00, 0x00, 0x3B, 0x03, 0x03,
+        ];
+        let cryptde = cryptde();
+        let hopper_mock = Recorder::new();
+        let hopper_log_arc = hopper_mock.get_recording();
+        let hopper_awaiter = hopper_mock.get_awaiter();
+        let neighborhood_mock = Recorder::new()
+            .route_query_response(Ok(zero_hop_route_response(&cryptde.public_key(), cryptde)));
+        let stream_key = make_meaningless_stream_key();
+        let msg = InboundClientData {
+            peer_addr: SocketAddr::from_str("1.2.3.4:5679").unwrap(),
+            reception_port: Some(443),
+            sequence_number: Some(0),
+            last_data: false,
+            is_clandestine: false,
+            data: tls_fragment.clone(),
+            neighbor_key_opt: None,
+        };
+        thread::spawn(move || {
+            let mut subject = ProxyServer::new(cryptde, false);
+            subject.stream_key_factory =
+                Box::new(StreamKeyFactoryMock::new().make_result(stream_key.clone()));
+            subject.client_hello_buffer =
+                ClientHelloBuffer::new(MAX_CLIENT_HELLO_BYTES, Duration::from_millis(50));
+            let system = System::new(
+                "proxy_server_releases_a_partial_tls_client_hello_from_a_client_that_goes_quiet",
+            );
+            let subject_addr: Addr<Syn, ProxyServer> = subject.start();
+            let mut peer_actors = peer_actors_builder()
+                .hopper(hopper_mock)
+                .neighborhood(neighborhood_mock)
+                .build();
+            peer_actors.proxy_server = ProxyServer::make_subs_from(&subject_addr);
+            subject_addr.try_send(BindMessage { peer_actors }).unwrap();
+
+            subject_addr.try_send(msg).unwrap();
+
+            system.run();
+        });
+
+        hopper_awaiter.await_message_count(1);
+        let recording = hopper_log_arc.lock().unwrap();
+        let record = recording.get_record::<IncipientCoresPackage>(0);
+        let payload = decodex::<ClientRequestPayload>(cryptde, &record.payload).unwrap();
+        assert_eq!(payload.sequenced_packet.data, tls_fragment);
+        assert_eq!(payload.target_hostname, None);
+        assert_eq!(payload.protocol, ProxyProtocol::TLS);
+    }
+
+    #[test]
+    fn proxy_server_receives_tls_handshake_packet_other_than_client_hello_from_dispatcher_then_sends_cores_package_to_hopper(
+    ) {
+        let tls_request = &[
+            0x16, // content_type: Handshake
+            0x00, 0x00, 0x00, 0x00, // version, length: don't care
+            0x10, // handshake_type: ClientKeyExchange (not important--just not ClientHello)
+            0x00, 0x00, 0x00, // length: 0
+        ];
+        let cryptde = cryptde();
+        let hopper_mock = Recorder::new();
+        let hopper_log_arc = hopper_mock.get_recording();
+        let hopper_awaiter = hopper_mock.get_awaiter();
+        let neighborhood_mock = Recorder::new()
+            .route_query_response(Ok(zero_hop_route_response(&cryptde.public_key(), cryptde)));
+        let stream_key = make_meaningless_stream_key();
+        let socket_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
+        let expected_data = tls_request.to_vec();
+        let msg_from_dispatcher = InboundClientData {
+            peer_addr: socket_addr.clone(),
+            reception_port: Some(443),
+            sequence_number: Some(0),
+            last_data: false,
+            is_clandestine: false,
+            data: expected_data.clone(),
+            neighbor_key_opt: None,
+        };
+        let expected_tls_request = PlainData::new(tls_request);
+        let key = cryptde.public_key();
+        let route = zero_hop_route_response(&key, cryptde).route;
+        let expected_payload = ClientRequestPayload {
+            stream_key: stream_key.clone(),
+            sequenced_packet: SequencedPacket {
+                data: expected_tls_request.into(),
+                sequence_number: 0,
+                last_data: false,
+            },
+            target_hostname: None,
+            target_port: 443,
+            protocol: ProxyProtocol::TLS,
+            originator_public_key: key.clone(),
+            tls_at_exit: false,
+            upload_window: None,
+            udp: false,
+            dns_server: None,
+            cancel: false,
+        };
+        let expected_pkg =
+            IncipientCoresPackage::new(cryptde, route.clone(), expected_payload, &key).unwrap();
+        thread::spawn(move || {
+            let mut subject = ProxyServer::new(cryptde, false);
+            subject.stream_key_factory =
+                Box::new(StreamKeyFactoryMock::new().make_result(stream_key.clone()));
+            let system = System::new("proxy_server_receives_tls_client_hello_from_dispatcher_then_sends_cores_package_to_hopper");
+            let subject_addr: Addr<Syn, ProxyServer> = subject.start();
+            let mut peer_actors = peer_actors_builder()
+                .hopper(hopper_mock)
+                .neighborhood(neighborhood_mock)
+                .build();
+            peer_actors.proxy_server = ProxyServer::make_subs_from(&subject_addr);
+            subject_addr.try_send(BindMessage { peer_actors }).unwrap();
+
+            subject_addr.try_send(msg_from_dispatcher).unwrap();
+
+            system.run();
+        });
+
+        hopper_awaiter.await_message_count(1);
+        let recording = hopper_log_arc.lock().unwrap();
+        let record = recording.get_record::<IncipientCoresPackage>(0);
+        assert_eq!(record, &expected_pkg);
+    }
+
+    #[test]
+    fn proxy_server_receives_tls_packet_other_than_handshake_from_dispatcher_then_sends_cores_package_to_hopper(
+    ) {
+        let tls_request = &[
+            0xFF, // content_type: don't care, just not Handshake
+            0x00, 0x00, 0x00, 0x00, // version, length: don't care
+        ];
+        let cryptde = cryptde();
+        let hopper_mock = Recorder::new();
+        let hopper_log_arc = hopper_mock.get_recording();
+        let hopper_awaiter = hopper_mock.get_awaiter();
+        let neighborhood_mock = Recorder::new()
+            .route_query_response(Ok(zero_hop_route_response(&cryptde.public_key(), cryptde)));
+        let stream_key = make_meaningless_stream_key();
+        let socket_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
+        let expected_data = tls_request.to_vec();
+        let msg_from_dispatcher = InboundClientData {
+            peer_addr: socket_addr.clone(),
+            reception_port: Some(443),
+            sequence_number: Some(0),
+            last_data: true,
+            is_clandestine: false,
+            data: expected_data.clone(),
+            neighbor_key_opt: None,
+        };
+        let expected_tls_request = PlainData::new(tls_request);
+        let key = cryptde.public_key();
+        let route = zero_hop_route_response(&key, cryptde).route;
+        let expected_payload = ClientRequestPayload {
+            stream_key: stream_key.clone(),
+            sequenced_packet: SequencedPacket {
+                data: expected_tls_request.into(),
+                sequence_number: 0,
+                last_data: true,
+            },
+            target_hostname: None,
+            target_port: 443,
+            protocol: ProxyProtocol::TLS,
+            originator_public_key: key.clone(),
+            tls_at_exit: false,
+            upload_window: None,
+            udp: false,
+            dns_server: None,
+            cancel: false,
+        };
+        let expected_pkg =
+            IncipientCoresPackage::new(cryptde, route.clone(), expected_payload, &key).unwrap();
+        thread::spawn(move || {
+            let mut subject = ProxyServer::new(cryptde, false);
+            subject.stream_key_factory =
+                Box::new(StreamKeyFactoryMock::new().make_result(stream_key.clone()));
+            let system = System::new("proxy_server_receives_tls_client_hello_from_dispatcher_then_sends_cores_package_to_hopper");
+            let subject_addr: Addr<Syn, ProxyServer> = subject.start();
+            let mut peer_actors = peer_actors_builder()
+                .hopper(hopper_mock)
+                .neighborhood(neighborhood_mock)
+                .build();
+            peer_actors.proxy_server = ProxyServer::make_subs_from(&subject_addr);
+            subject_addr.try_send(BindMessage { peer_actors }).unwrap();
+
+            subject_addr.try_send(msg_from_dispatcher).unwrap();
+
+            system.run();
+        });
+
+        hopper_awaiter.await_message_count(1);
+        let recording = hopper_log_arc.lock().unwrap();
+        let record = recording.get_record::<IncipientCoresPackage>(0);
+        assert_eq!(record, &expected_pkg);
+    }
+
+    #[test]
+    fn proxy_server_receives_tls_client_hello_from_dispatcher_but_neighborhood_cant_make_route() {
+        init_test_logging();
+        let cryptde = cryptde();
+        let tls_request = [
+            0x16, // content_type: Handshake
+            0x00, 0x00, 0x00, 0x00, // version, length: don't care
+            0x01, // handshake_type: ClientHello
+            0x00, 0x00, 0x00, 0x00, 0x00, // length, version: don't care
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, // random: don't care
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, // random: don't care
+            0x00, // session_id_length
+            0x00, 0x00, // cipher_suites_length
+            0x00, // compression_methods_length
+            0x00, 0x13, // extensions_length
+            0x00, 0x00, // extension_type: server_name
+            0x00, 0x0F, // extension_length
+            0x00, 0x0D, // server_name_list_length
+            0x00, // server_name_type
+            0x00, 0x0A, // server_name_length
+            's' as u8, 'e' as u8, 'r' as u8, 'v' as u8, 'e' as u8, 'r' as u8, '.' as u8, 'c' as u8,
+            'o' as u8, 'm' as u8, // server_name
+        ]
+        .to_vec();
+        let dispatcher = Recorder::new();
+        let dispatcher_awaiter = dispatcher.get_awaiter();
+        let dispatcher_recording_arc = dispatcher.get_recording();
+        let neighborhood =
+            Recorder::new().route_query_response(Err(RouteRefusal::NoConsumingWallet));
+        let socket_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
+        let msg_from_dispatcher = InboundClientData {
+            peer_addr: socket_addr.clone(),
+            reception_port: Some(443),
+            sequence_number: Some(0),
+            last_data: true,
+            data: tls_request,
+            is_clandestine: false,
+            neighbor_key_opt: None,
+        };
+        thread::spawn(move || {
+            let system = System::new("proxy_server_receives_tls_client_hello_from_dispatcher_but_neighborhood_cant_make_route");
+            let subject = ProxyServer::new(cryptde, false);
+            let subject_addr: Addr<Syn, ProxyServer> = subject.start();
+            let mut peer_actors = peer_actors_builder()
+                .dispatcher(dispatcher)
+                .neighborhood(neighborhood)
+                .build();
+            peer_actors.proxy_server = ProxyServer::make_subs_from(&subject_addr);
+            subject_addr.try_send(BindMessage { peer_actors }).unwrap();
+
+            subject_addr.try_send(msg_from_dispatcher).unwrap();
+
+            system.run();
+        });
+        dispatcher_awaiter.await_message_count(1);
+        let recording = dispatcher_recording_arc.lock().unwrap();
+        let record = recording.get_record::<TransmitDataMsg>(0);
+        let expected_msg = TransmitDataMsg {
+            endpoint: Endpoint::Socket(SocketAddr::from_str("1.2.3.4:5678").unwrap()),
+            last_data: true,
+            sequence_number: Some(0),
+            data: vec![],
+        };
+        assert_eq!(record, &expected_msg);
+
+        TestLogHandler::new()
+            .exists_log_containing("ERROR: Proxy Server: Failed to find route to server.com: Routes of more than one hop require a consuming wallet");
+    }
+
+    #[test]
+    fn proxy_server_receives_terminal_response_from_hopper() {
+        init_test_logging();
+        let system = System::new("proxy_server_receives_response_from_hopper");
+        let (dispatcher_mock, _, dispatcher_log_arc) = make_recorder();
+        let cryptde = cryptde();
+        let mut subject = ProxyServer::new(cryptde, false);
+        let socket_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
+        let stream_key = make_meaningless_stream_key();
+        subject
+            .keys_and_addrs
+            .insert(stream_key.clone(), socket_addr.clone());
+        subject
+            .route_ids_to_services
+            .insert(1234, vec![ExpectedService::Nothing]);
+        let subject_addr: Addr<Syn, ProxyServer> = subject.start();
+        let remaining_route = return_route_with_id(cryptde, 1234);
+        let client_response_payload = ClientResponsePayload {
+            stream_key: stream_key.clone(),
+            sequenced_packet: SequencedPacket {
+                data: b"16 bytes of data".to_vec(),
+                sequence_number: 12345678,
+                last_data: true,
+            },
+            requests_received: None,
+            truncated: false,
+            udp: false,
+            exit_failed: false,
+        };
+        let first_expired_cores_package = ExpiredCoresPackage::new(
+            IpAddr::from_str("1.2.3.4").unwrap(),
+            Some(Wallet::new("consuming")),
+            remaining_route,
+            encodex(cryptde, &cryptde.public_key(), &client_response_payload).unwrap(),
+        );
+        let second_expired_cores_package = first_expired_cores_package.clone();
+        let mut peer_actors = peer_actors_builder().dispatcher(dispatcher_mock).build();
+        peer_actors.proxy_server = ProxyServer::make_subs_from(&subject_addr);
+        subject_addr.try_send(BindMessage { peer_actors }).unwrap();
+
+        subject_addr.try_send(first_expired_cores_package).unwrap();
+        subject_addr.try_send(second_expired_cores_package).unwrap(); // should generate log because stream key is now unknown
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+
+        let recording = dispatcher_log_arc.lock().unwrap();
+        let record = recording.get_record::<TransmitDataMsg>(0);
+        assert_eq!(record.endpoint, Endpoint::Socket(socket_addr));
+        assert_eq!(record.last_data, true);
+        assert_eq!(record.data, b"16 bytes of data".to_vec());
+        TestLogHandler::new ().exists_log_containing (&format!("ERROR: Proxy Server: Discarding 16-byte packet 12345678 from an unrecognized stream key: {:?}", stream_key));
+    }
+
+    #[test]
+    fn proxy_server_tells_the_neighborhood_when_the_first_response_comes_back_over_a_route() {
+        let system = System::new(
+            "proxy_server_tells_the_neighborhood_when_the_first_response_comes_back_over_a_route",
+        );
+        let (dispatcher_mock, _, _) = make_recorder();
+        let (neighborhood_mock, _, neighborhood_recording_arc) = make_recorder();
+        let cryptde = cryptde();
+        let mut subject = ProxyServer::new(cryptde, false);
+        let socket_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
+        let stream_key = make_meaningless_stream_key();
+        subject
+            .keys_and_addrs
+            .insert(stream_key.clone(), socket_addr.clone());
+        let subject_addr: Addr<Syn, ProxyServer> = subject.start();
+        let relay_key = PublicKey::new(b"relay");
+        let exit_key = PublicKey::new(b"exit");
+        let client_response_payload = ClientResponsePayload {
+            stream_key: stream_key.clone(),
+            sequenced_packet: SequencedPacket {
+                data: b"16 bytes of data".to_vec(),
+                sequence_number: 0,
+                last_data: false,
+            },
+            requests_received: None,
+            truncated: false,
+            udp: false,
+            exit_failed: false,
+        };
+        let expired_cores_package = ExpiredCoresPackage::new(
+            IpAddr::from_str("1.2.3.4").unwrap(),
+            Some(Wallet::new("consuming")),
+            return_route_with_id(cryptde, 1234),
+            encodex(cryptde, &cryptde.public_key(), &client_response_payload).unwrap(),
+        );
+        let mut peer_actors = peer_actors_builder()
+            .dispatcher(dispatcher_mock)
+            .neighborhood(neighborhood_mock)
+            .build();
+        peer_actors.proxy_server = ProxyServer::make_subs_from(&subject_addr);
+        subject_addr.try_send(BindMessage { peer_actors }).unwrap();
+        subject_addr
+            .try_send(AddReturnRouteMessage {
+                return_route_id: 1234,
+                expected_services: vec![ExpectedService::Nothing],
+                route_keys: vec![relay_key.clone(), exit_key.clone()],
+            })
+            .unwrap();
+
+        subject_addr
+            .try_send(expired_cores_package.clone())
+            .unwrap();
+        subject_addr.try_send(expired_cores_package).unwrap();
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+        let neighborhood_recording = neighborhood_recording_arc.lock().unwrap();
+        let route_feedback = neighborhood_recording.get_record::<RouteFeedbackMessage>(0);
+        assert_eq!(route_feedback.keys, vec![relay_key, exit_key]);
+        match route_feedback.outcome {
+            RouteOutcome::Responded(_) => (),
+            outcome => panic!("Round trip should have succeeded: {:?}", outcome),
+        }
+        assert_eq!(neighborhood_recording.len(), 1);
+    }
+
+    #[test]
+    fn proxy_server_reports_an_exit_that_could_not_reach_the_server_to_the_neighborhood() {
+        init_test_logging();
+        let system = System::new(
+            "proxy_server_reports_an_exit_that_could_not_reach_the_server_to_the_neighborhood",
+        );
+        let (dispatcher_mock, _, dispatcher_recording_arc) = make_recorder();
+        let (neighborhood_mock, _, neighborhood_recording_arc) = make_recorder();
+        let cryptde = cryptde();
+        let mut subject = ProxyServer::new(cryptde, false);
+        let socket_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
+        let stream_key = make_meaningless_stream_key();
+        let exit_key = PublicKey::new(b"exit");
+        let mut route = zero_hop_route_response(&cryptde.public_key(), cryptde);
+        route.expected_services = ExpectedServices::RoundTrip(
+            vec![
+                ExpectedService::Nothing,
+                ExpectedService::Exit(exit_key.clone(), Wallet::new("exit"), RateCard::default()),
+            ],
+            vec![ExpectedService::Nothing, ExpectedService::Nothing],
+            1234,
+        );
+        subject.keys_and_addrs.insert(stream_key, socket_addr);
+        subject.routes.insert(
+            stream_key,
+            (route, Instant::now() + Duration::from_secs(60)),
+        );
+        let subject_addr: Addr<Syn, ProxyServer> = subject.start();
+        let client_response_payload = ClientResponsePayload {
+            exit_failed: true,
+            ..ClientResponsePayload::make_terminating_payload(stream_key)
+        };
+        let expired_cores_package = ExpiredCoresPackage::new(
+            IpAddr::from_str("1.2.3.4").unwrap(),
+            Some(Wallet::new("consuming")),
+            return_route_with_id(cryptde, 1234),
+            encodex(cryptde, &cryptde.public_key(), &client_response_payload).unwrap(),
+        );
+        let mut peer_actors = peer_actors_builder()
+            .dispatcher(dispatcher_mock)
+            .neighborhood(neighborhood_mock)
+            .build();
+        peer_actors.proxy_server = ProxyServer::make_subs_from(&subject_addr);
+        subject_addr.try_send(BindMessage { peer_actors }).unwrap();
+        subject_addr
+            .try_send(AddReturnRouteMessage {
+                return_route_id: 1234,
+                expected_services: vec![ExpectedService::Nothing],
+                route_keys: vec![],
+            })
+            .unwrap();
+
+        subject_addr.try_send(expired_cores_package).unwrap();
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+        let neighborhood_recording = neighborhood_recording_arc.lock().unwrap();
+        assert_eq!(
+            neighborhood_recording.get_record::<NodeProblemReport>(0),
+            &NodeProblemReport {
+                public_key: exit_key.clone(),
+                problem: NodeProblem::TargetUnreachable,
+            }
+        );
+        assert_eq!(neighborhood_recording.len(), 1);
+        let dispatcher_recording = dispatcher_recording_arc.lock().unwrap();
+        assert_eq!(
+            dispatcher_recording
+                .get_record::<TransmitDataMsg>(0)
+                .last_data,
+            true
+        );
+        TestLogHandler::new().exists_log_containing(&format!(
+            "WARN: Proxy Server: Exit {} failed stream {:?}: TargetUnreachable",
+            exit_key, stream_key
+        ));
+    }
+
+    #[test]
+    fn proxy_server_lowers_the_hop_count_when_round_trips_take_too_long() {
+        init_test_logging();
+        let system =
+            System::new("proxy_server_lowers_the_hop_count_when_round_trips_take_too_long");
+        let (ui_gateway, _, ui_gateway_recording_arc) = make_recorder();
+        let peer_actors = peer_actors_builder().ui_gateway(ui_gateway).build();
+        let mut subject = ProxyServer::new(cryptde(), true);
+        subject.adapt_hop_count(&AdaptiveHopsConfig {
+            min_hops: 1,
+            max_hops: 4,
+            latency_threshold: Duration::from_millis(900),
+        });
+        subject.ui_message_sub = Some(peer_actors.ui_gateway.ui_message_sub.clone());
+
+        (0..ADAPTIVE_HOPS_SAMPLE_SIZE)
+            .for_each(|_| subject.adapt_hop_count_to(Duration::from_millis(1500)));
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+        assert_eq!(
+            subject
+                .adaptive_hops
+                .as_ref()
+                .unwrap()
+                .hop_count(DEFAULT_MINIMUM_HOP_COUNT),
+            1
+        );
+        let ui_gateway_recording = ui_gateway_recording_arc.lock().unwrap();
+        assert_eq!(
+            ui_gateway_recording.get_record::<UiMessage>(0),
+            &UiMessage::AlertMessage(
+                UiAlert::new("hop_count_adapted")
+                    .with("hop_count", "1")
+                    .with("median_ms", "1500")
+            )
+        );
+        assert_eq!(ui_gateway_recording.len(), 1);
+        TestLogHandler::new().exists_log_containing(
+            "INFO: Proxy Server: Median round trip is 1500ms; new routes will have at least 1 hops",
+        );
+    }
+
+    #[test]
+    fn proxy_server_turns_adaptive_hop_count_on_and_off_when_the_ui_asks() {
+        init_test_logging();
+        let system =
+            System::new("proxy_server_turns_adaptive_hop_count_on_and_off_when_the_ui_asks");
+        let (ui_gateway, _, ui_gateway_recording_arc) = make_recorder();
+        let mut subject = ProxyServer::new(cryptde(), true);
+        subject.adapt_hop_count(&AdaptiveHopsConfig {
+            min_hops: 3,
+            max_hops: 5,
+            latency_threshold: Duration::from_millis(900),
+        });
+        let subject_addr: Addr<Syn, ProxyServer> = subject.start();
+        let mut peer_actors = peer_actors_builder().ui_gateway(ui_gateway).build();
+        peer_actors.proxy_server = ProxyServer::make_subs_from(&subject_addr);
+        subject_addr.try_send(BindMessage { peer_actors }).unwrap();
+
+        subject_addr
+            .try_send(AdaptiveHopsMessage { enabled: false })
+            .unwrap();
+        subject_addr
+            .try_send(AdaptiveHopsMessage { enabled: true })
+            .unwrap();
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+        let ui_gateway_recording = ui_gateway_recording_arc.lock().unwrap();
+        assert_eq!(
+            ui_gateway_recording.get_record::<UiMessage>(0),
+            &UiMessage::AlertMessage(
+                UiAlert::new("adaptive_hops_toggled")
+                    .with("enabled", "false")
+                    .with("hop_count", "2")
+            )
+        );
+        assert_eq!(
+            ui_gateway_recording.get_record::<UiMessage>(1),
+            &UiMessage::AlertMessage(
+                UiAlert::new("adaptive_hops_toggled")
+                    .with("enabled", "true")
+                    .with("hop_count", "3")
+            )
+        );
+        TestLogHandler::new().exists_log_containing(
+            "INFO: Proxy Server: Adaptive hop count turned off; new routes will have at least 2 hops",
+        );
+        TestLogHandler::new().exists_log_containing(
+            "INFO: Proxy Server: Adaptive hop count turned on; new routes will have at least 3 hops",
+        );
+    }
+
+    #[test]
+    fn proxy_server_tells_the_ui_when_adaptive_hop_count_wasnt_configured() {
+        init_test_logging();
+        let system =
+            System::new("proxy_server_tells_the_ui_when_adaptive_hop_count_wasnt_configured");
+        let (ui_gateway, _, ui_gateway_recording_arc) = make_recorder();
+        let subject = ProxyServer::new(cryptde(), true);
+        let subject_addr: Addr<Syn, ProxyServer> = subject.start();
+        let mut peer_actors = peer_actors_builder().ui_gateway(ui_gateway).build();
+        peer_actors.proxy_server = ProxyServer::make_subs_from(&subject_addr);
+        subject_addr.try_send(BindMessage { peer_actors }).unwrap();
+
+        subject_addr
+            .try_send(AdaptiveHopsMessage { enabled: true })
+            .unwrap();
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+        let ui_gateway_recording = ui_gateway_recording_arc.lock().unwrap();
+        assert_eq!(
+            ui_gateway_recording.get_record::<UiMessage>(0),
+            &UiMessage::AlertMessage(UiAlert::new("adaptive_hops_unavailable"))
+        );
+        TestLogHandler::new().exists_log_containing(
+            "WARN: Proxy Server: Can't turn adaptive hop count on or off: the Node wasn't started with --adaptive_hops",
+        );
+    }
+
+    #[test]
+    fn proxy_server_tells_the_neighborhood_when_an_exit_delivers_well_below_its_advertised_rate() {
+        init_test_logging();
+        let system = System::new(
+            "proxy_server_tells_the_neighborhood_when_an_exit_delivers_well_below_its_advertised_rate",
+        );
+        let (dispatcher_mock, _, _) = make_recorder();
+        let (neighborhood_mock, _, neighborhood_recording_arc) = make_recorder();
+        let cryptde = cryptde();
+        let mut subject = ProxyServer::new(cryptde, false);
+        let socket_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
+        let stream_key = make_meaningless_stream_key();
+        subject
+            .keys_and_addrs
+            .insert(stream_key.clone(), socket_addr.clone());
+        subject
+            .route_ids_to_services
+            .insert(1234, vec![ExpectedService::Nothing]);
+        let subject_addr: Addr<Syn, ProxyServer> = subject.start();
+        let exit_key = PublicKey::new(b"exit");
+        let mut route = zero_hop_route_response(&cryptde.public_key(), cryptde);
+        route.expected_services = ExpectedServices::RoundTrip(
+            vec![
+                ExpectedService::Nothing,
+                ExpectedService::Exit(exit_key.clone(), Wallet::new("exit"), RateCard::default()),
+            ],
+            vec![ExpectedService::Nothing, ExpectedService::Nothing],
+            1234,
+        );
+        // Nothing can come back fast enough to keep this promise
+        route.exit_sla_opt = Some(ExitSla {
+            max_streams: 10,
+            max_stream_bytes_per_second: u64::max_value(),
+            content_policy_hash: String::from("hash"),
+        });
+        let response = |sequence_number: u64, data: Vec<u8>| {
+            let client_response_payload = ClientResponsePayload {
+                stream_key: stream_key.clone(),
+                sequenced_packet: SequencedPacket {
+                    data,
+                    sequence_number,
+                    last_data: false,
+                },
+                requests_received: None,
+                truncated: false,
+                udp: false,
+                exit_failed: false,
+            };
+            ExpiredCoresPackage::new(
+                IpAddr::from_str("1.2.3.4").unwrap(),
+                Some(Wallet::new("consuming")),
+                return_route_with_id(cryptde, 1234),
+                encodex(cryptde, &cryptde.public_key(), &client_response_payload).unwrap(),
+            )
+        };
+        let mut peer_actors = peer_actors_builder()
+            .dispatcher(dispatcher_mock)
+            .neighborhood(neighborhood_mock)
+            .build();
+        peer_actors.proxy_server = ProxyServer::make_subs_from(&subject_addr);
+        subject_addr.try_send(BindMessage { peer_actors }).unwrap();
+        subject_addr
+            .try_send(AddRouteMessage {
+                stream_key: stream_key.clone(),
+                route,
+            })
+            .unwrap();
+
+        subject_addr
+            .try_send(response(0, b"first".to_vec()))
+            .unwrap();
+        subject_addr
+            .try_send(response(1, vec![0; SLA_SAMPLE_BYTES as usize]))
+            .unwrap();
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+        let neighborhood_recording = neighborhood_recording_arc.lock().unwrap();
+        assert_eq!(
+            neighborhood_recording.get_record::<RouteFeedbackMessage>(0),
+            &RouteFeedbackMessage {
+                keys: vec![exit_key.clone()],
+                outcome: RouteOutcome::Throttled,
+            }
+        );
+        assert_eq!(neighborhood_recording.len(), 1);
+        TestLogHandler::new().exists_log_containing(&format!(
+            "WARN: Proxy Server: Exit {} is delivering stream {:?} well below the rate it advertised",
+            exit_key, stream_key
+        ));
+    }
+
+    #[test]
+    fn route_keys_name_each_node_but_this_one_once() {
+        let service = |name: &str, exit: bool| {
+            let key = PublicKey::new(name.as_bytes());
+            if exit {
+                ExpectedService::Exit(key, Wallet::new(name), RateCard::default())
+            } else {
+                ExpectedService::Routing(key, Wallet::new(name), RateCard::default())
+            }
+        };
+
+        let result = ProxyServer::route_keys(
+            &[
+                ExpectedService::Nothing,
+                service("q", false),
+                service("r", true),
+            ],
+            &[
+                service("r", true),
+                service("s", false),
+                ExpectedService::Nothing,
+            ],
+        );
+
+        assert_eq!(
+            result,
+            vec![
+                PublicKey::new(b"q"),
+                PublicKey::new(b"r"),
+                PublicKey::new(b"s")
+            ]
+        );
+    }
+
+    #[test]
+    fn proxy_server_receives_nonterminal_response_from_hopper() {
+        let system = System::new("proxy_server_receives_response_from_hopper");
+        let (dispatcher_mock, _, dispatcher_log_arc) = make_recorder();
+        let (accountant, _, accountant_recording_arc) = make_recorder();
+        let cryptde = cryptde();
+        let mut subject = ProxyServer::new(cryptde, false);
+        let socket_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
+        let stream_key = make_meaningless_stream_key();
+        let irrelevant_public_key = PublicKey::from(&b"irrelevant"[..]);
+        subject
+            .keys_and_addrs
+            .insert(stream_key.clone(), socket_addr.clone());
+        let incoming_route_d_wallet = Wallet::new("D Earning");
+        let incoming_route_e_wallet = Wallet::new("E Earning");
+        let incoming_route_f_wallet = Wallet::new("F Earning");
+        subject.route_ids_to_services.insert(
+            1234,
+            vec![
+                ExpectedService::Exit(
+                    irrelevant_public_key.clone(),
+                    incoming_route_d_wallet.clone(),
+                    RateCard::default(),
+                ),
+                ExpectedService::Routing(
+                    irrelevant_public_key.clone(),
+                    incoming_route_e_wallet.clone(),
+                    RateCard::default(),
+                ),
+                ExpectedService::Routing(
+                    irrelevant_public_key.clone(),
+                    incoming_route_f_wallet.clone(),
+                    RateCard::default(),
+                ),
+                ExpectedService::Nothing,
+            ],
+        );
+        let incoming_route_g_wallet = Wallet::new("G Earning");
+        let incoming_route_h_wallet = Wallet::new("H Earning");
+        let incoming_route_i_wallet = Wallet::new("I Earning");
+        subject.route_ids_to_services.insert(
+            1235,
+            vec![
+                ExpectedService::Exit(
+                    irrelevant_public_key.clone(),
+                    incoming_route_g_wallet.clone(),
+                    RateCard::default(),
+                ),
+                ExpectedService::Routing(
+                    irrelevant_public_key.clone(),
+                    incoming_route_h_wallet.clone(),
+                    RateCard::default(),
+                ),
+                ExpectedService::Routing(
+                    irrelevant_public_key.clone(),
+                    incoming_route_i_wallet.clone(),
+                    RateCard::default(),
+                ),
+                ExpectedService::Nothing,
+            ],
+        );
+        let subject_addr: Addr<Syn, ProxyServer> = subject.start();
+        let first_client_response_payload = ClientResponsePayload {
+            stream_key,
+            sequenced_packet: SequencedPacket {
+                data: b"some data".to_vec(),
+                sequence_number: 4321,
+                last_data: false,
             },
-        )
-        .unwrap()
-    }
-
-    fn check_exit_report(
-        accountant_recording: &MutexGuard<Recording>,
-        idx: usize,
-        wallet: &Wallet,
-        payload_size: usize,
-    ) {
-        assert_eq!(
-            accountant_recording.get_record::<ReportExitServiceConsumedMessage>(idx),
-            &ReportExitServiceConsumedMessage {
-                earning_wallet: wallet.clone(),
-                payload_size,
-                service_rate: TEMPORARY_PER_EXIT_RATE,
-                byte_rate: TEMPORARY_PER_EXIT_BYTE_RATE,
-            }
+            requests_received: None,
+            truncated: false,
+            udp: false,
+            exit_failed: false,
+        };
+        let first_expired_cores_package = ExpiredCoresPackage::new(
+            IpAddr::from_str("1.2.3.4").unwrap(),
+            Some(Wallet::new("irrelevant")),
+            return_route_with_id(cryptde, 1234),
+            encodex(
+                cryptde,
+                &cryptde.public_key(),
+                &first_client_response_payload,
+            )
+            .unwrap(),
         );
-    }
+        let second_client_response_payload = ClientResponsePayload {
+            stream_key,
+            sequenced_packet: SequencedPacket {
+                data: b"other data".to_vec(),
+                sequence_number: 4322,
+                last_data: false,
+            },
+            requests_received: None,
+            truncated: false,
+            udp: false,
+            exit_failed: false,
+        };
+        let second_expired_cores_package = ExpiredCoresPackage::new(
+            IpAddr::from_str("1.2.3.5").unwrap(),
+            Some(Wallet::new("irrelevant")),
+            return_route_with_id(cryptde, 1235),
+            encodex(
+                cryptde,
+                &cryptde.public_key(),
+                &second_client_response_payload,
+            )
+            .unwrap(),
+        );
+        let mut peer_actors = peer_actors_builder()
+            .dispatcher(dispatcher_mock)
+            .accountant(accountant)
+            .build();
+        peer_actors.proxy_server = ProxyServer::make_subs_from(&subject_addr);
+        subject_addr.try_send(BindMessage { peer_actors }).unwrap();
 
-    fn check_routing_report(
-        accountant_recording: &MutexGuard<Recording>,
-        idx: usize,
-        wallet: &Wallet,
-        payload_size: usize,
-    ) {
-        assert_eq!(
-            accountant_recording.get_record::<ReportRoutingServiceConsumedMessage>(idx),
-            &ReportRoutingServiceConsumedMessage {
-                earning_wallet: wallet.clone(),
-                payload_size,
-                service_rate: TEMPORARY_PER_ROUTING_RATE,
-                byte_rate: TEMPORARY_PER_ROUTING_BYTE_RATE,
-            }
+        subject_addr
+            .try_send(first_expired_cores_package.clone())
+            .unwrap();
+        subject_addr
+            .try_send(second_expired_cores_package.clone())
+            .unwrap();
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+
+        let dispatcher_recording = dispatcher_log_arc.lock().unwrap();
+        let record = dispatcher_recording.get_record::<TransmitDataMsg>(0);
+        assert_eq!(record.endpoint, Endpoint::Socket(socket_addr));
+        assert_eq!(record.last_data, false);
+        assert_eq!(record.data, b"some data".to_vec());
+        let record = dispatcher_recording.get_record::<TransmitDataMsg>(1);
+        assert_eq!(record.endpoint, Endpoint::Socket(socket_addr));
+        assert_eq!(record.last_data, false);
+        assert_eq!(record.data, b"other data".to_vec());
+        let accountant_recording = accountant_recording_arc.lock().unwrap();
+        let exit_size = first_client_response_payload.sequenced_packet.data.len();
+        let routing_size = first_expired_cores_package.payload.len();
+        check_exit_report(
+            &accountant_recording,
+            0,
+            &incoming_route_d_wallet,
+            exit_size,
+            stream_key,
+        );
+        check_routing_report(
+            &accountant_recording,
+            1,
+            &incoming_route_e_wallet,
+            routing_size,
+        );
+        check_routing_report(
+            &accountant_recording,
+            2,
+            &incoming_route_f_wallet,
+            routing_size,
+        );
+        let exit_size = second_client_response_payload.sequenced_packet.data.len();
+        let routing_size = second_expired_cores_package.payload.len();
+        check_exit_report(
+            &accountant_recording,
+            3,
+            &incoming_route_g_wallet,
+            exit_size,
+            stream_key,
+        );
+        check_routing_report(
+            &accountant_recording,
+            4,
+            &incoming_route_h_wallet,
+            routing_size,
+        );
+        check_routing_report(
+            &accountant_recording,
+            5,
+            &incoming_route_i_wallet,
+            routing_size,
         );
+        assert_eq!(accountant_recording.len(), 6);
     }
 
     #[test]
-    fn proxy_server_receives_http_request_with_new_stream_key_from_dispatcher_then_sends_cores_package_to_hopper(
-    ) {
+    fn proxy_server_charges_for_response_services_at_the_advertised_rates() {
+        let system =
+            System::new("proxy_server_charges_for_response_services_at_the_advertised_rates");
+        let (dispatcher_mock, _, _) = make_recorder();
+        let (accountant, _, accountant_recording_arc) = make_recorder();
         let cryptde = cryptde();
-        let http_request = b"GET /index.html HTTP/1.1\r\nHost: nowhere.com\r\n\r\n";
-        let hopper_mock = Recorder::new();
-        let hopper_log_arc = hopper_mock.get_recording();
-        let hopper_awaiter = hopper_mock.get_awaiter();
-        let (neighborhood_mock, _, neighborhood_recording_arc) = make_recorder();
-        let neighborhood_mock = neighborhood_mock.route_query_response(Some(
-            zero_hop_route_response(&cryptde.public_key(), cryptde),
-        ));
+        let mut subject = ProxyServer::new(cryptde, false);
         let socket_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
         let stream_key = make_meaningless_stream_key();
-        let expected_data = http_request.to_vec();
-        let msg_from_dispatcher = InboundClientData {
-            peer_addr: socket_addr.clone(),
-            reception_port: Some(80),
-            sequence_number: Some(0),
-            last_data: true,
-            is_clandestine: false,
-            data: expected_data.clone(),
+        let irrelevant_public_key = PublicKey::from(&b"irrelevant"[..]);
+        subject
+            .keys_and_addrs
+            .insert(stream_key.clone(), socket_addr.clone());
+        let exit_wallet = Wallet::new("exit");
+        let exit_rate_card = RateCard {
+            routing_service_rate: 100,
+            routing_byte_rate: 101,
+            exit_service_rate: 102,
+            exit_byte_rate: 103,
         };
-        let expected_http_request = PlainData::new(http_request);
-        let key = cryptde.public_key();
-        let route = zero_hop_route_response(&key, cryptde).route;
-        let expected_payload = ClientRequestPayload {
-            stream_key: stream_key.clone(),
+        let relay_wallet = Wallet::new("relay");
+        let relay_rate_card = RateCard {
+            routing_service_rate: 200,
+            routing_byte_rate: 201,
+            exit_service_rate: 202,
+            exit_byte_rate: 203,
+        };
+        subject.route_ids_to_services.insert(
+            1234,
+            vec![
+                ExpectedService::Exit(
+                    irrelevant_public_key.clone(),
+                    exit_wallet.clone(),
+                    exit_rate_card,
+                ),
+                ExpectedService::Routing(
+                    irrelevant_public_key.clone(),
+                    relay_wallet.clone(),
+                    relay_rate_card,
+                ),
+                ExpectedService::Nothing,
+            ],
+        );
+        let subject_addr: Addr<Syn, ProxyServer> = subject.start();
+        let client_response_payload = ClientResponsePayload {
+            stream_key,
             sequenced_packet: SequencedPacket {
-                data: expected_http_request.into(),
+                data: b"some data".to_vec(),
                 sequence_number: 0,
-                last_data: true,
+                last_data: false,
             },
-            target_hostname: Some(String::from("nowhere.com")),
-            target_port: 80,
-            protocol: ProxyProtocol::HTTP,
-            originator_public_key: key.clone(),
+            requests_received: None,
+            truncated: false,
+            udp: false,
+            exit_failed: false,
         };
-        let expected_pkg =
-            IncipientCoresPackage::new(cryptde, route.clone(), expected_payload, &key).unwrap();
-        let make_parameters_arc = Arc::new(Mutex::new(vec![]));
-        let make_parameters_arc_a = make_parameters_arc.clone();
-        thread::spawn(move || {
-            let stream_key_factory = StreamKeyFactoryMock::new()
-                .make_parameters(&make_parameters_arc)
-                .make_result(stream_key);
-            let system = System::new("proxy_server_receives_http_request_from_dispatcher_then_sends_cores_package_to_hopper");
-            let mut subject = ProxyServer::new(cryptde, false);
-            subject.stream_key_factory = Box::new(stream_key_factory);
-            let subject_addr: Addr<Syn, ProxyServer> = subject.start();
-            let mut peer_actors = peer_actors_builder()
-                .hopper(hopper_mock)
-                .neighborhood(neighborhood_mock)
-                .build();
-            peer_actors.proxy_server = ProxyServer::make_subs_from(&subject_addr);
-            subject_addr.try_send(BindMessage { peer_actors }).unwrap();
+        let expired_cores_package = ExpiredCoresPackage::new(
+            IpAddr::from_str("1.2.3.4").unwrap(),
+            Some(Wallet::new("irrelevant")),
+            return_route_with_id(cryptde, 1234),
+            encodex(cryptde, &cryptde.public_key(), &client_response_payload).unwrap(),
+        );
+        let mut peer_actors = peer_actors_builder()
+            .dispatcher(dispatcher_mock)
+            .accountant(accountant)
+            .build();
+        peer_actors.proxy_server = ProxyServer::make_subs_from(&subject_addr);
+        subject_addr.try_send(BindMessage { peer_actors }).unwrap();
 
-            subject_addr.try_send(msg_from_dispatcher).unwrap();
+        subject_addr
+            .try_send(expired_cores_package.clone())
+            .unwrap();
 
-            system.run();
-        });
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
 
-        hopper_awaiter.await_message_count(1);
-        let recording = hopper_log_arc.lock().unwrap();
-        let record = recording.get_record::<IncipientCoresPackage>(0);
-        assert_eq!(record, &expected_pkg);
-        let mut make_parameters = make_parameters_arc_a.lock().unwrap();
+        let accountant_recording = accountant_recording_arc.lock().unwrap();
+        assert_eq!(
+            accountant_recording.get_record::<ReportExitServiceConsumedMessage>(0),
+            &ReportExitServiceConsumedMessage {
+                earning_wallet: exit_wallet,
+                payload_size: client_response_payload.sequenced_packet.data.len(),
+                service_rate: 102,
+                byte_rate: 103,
+                correlation_id_opt: Some(CorrelationId::new(stream_key, SystemTime::now())),
+            }
+        );
         assert_eq!(
-            make_parameters.remove(0),
-            (cryptde.public_key(), socket_addr)
+            accountant_recording.get_record::<ReportRoutingServiceConsumedMessage>(1),
+            &ReportRoutingServiceConsumedMessage {
+                earning_wallet: relay_wallet,
+                payload_size: expired_cores_package.payload.len(),
+                service_rate: 200,
+                byte_rate: 201,
+                correlation_id_opt: None,
+            }
         );
-        let recording = neighborhood_recording_arc.lock().unwrap();
-        let record = recording.get_record::<RouteQueryMessage>(0);
-        assert_eq!(record, &RouteQueryMessage::data_indefinite_route_request(0));
+        assert_eq!(accountant_recording.len(), 2);
     }
 
     #[test]
-    fn proxy_server_receives_http_request_with_existing_stream_key_from_dispatcher_then_sends_cores_package_to_hopper(
-    ) {
+    #[should_panic(expected = "Dispatcher unbound in ProxyServer")]
+    fn panics_if_dispatcher_is_unbound() {
+        let system = System::new("panics_if_dispatcher_is_unbound");
         let cryptde = cryptde();
-        let http_request = b"GET /index.html HTTP/1.1\r\nHost: nowhere.com\r\n\r\n";
-        let hopper_mock = Recorder::new();
-        let hopper_log_arc = hopper_mock.get_recording();
-        let hopper_awaiter = hopper_mock.get_awaiter();
-        let neighborhood_mock = Recorder::new().route_query_response(Some(
-            zero_hop_route_response(&cryptde.public_key(), cryptde),
-        ));
         let socket_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
         let stream_key = make_meaningless_stream_key();
-        let expected_data = http_request.to_vec();
-        let msg_from_dispatcher = InboundClientData {
-            peer_addr: socket_addr.clone(),
-            reception_port: Some(80),
-            sequence_number: Some(0),
-            last_data: true,
-            is_clandestine: false,
-            data: expected_data.clone(),
-        };
-        let expected_http_request = PlainData::new(http_request);
-        let key = cryptde.public_key();
-        let route = zero_hop_route_response(&key, cryptde).route;
-        let expected_payload = ClientRequestPayload {
-            stream_key: stream_key.clone(),
+        let mut subject = ProxyServer::new(cryptde, false);
+        subject
+            .keys_and_addrs
+            .insert(stream_key.clone(), socket_addr.clone());
+        let remaining_route = return_route_with_id(cryptde, 4321);
+        subject
+            .route_ids_to_services
+            .insert(4321, vec![ExpectedService::Nothing]);
+        let subject_addr: Addr<Syn, ProxyServer> = subject.start();
+
+        let client_response_payload = ClientResponsePayload {
+            stream_key,
             sequenced_packet: SequencedPacket {
-                data: expected_http_request.into(),
+                data: b"data".to_vec(),
                 sequence_number: 0,
                 last_data: true,
             },
-            target_hostname: Some(String::from("nowhere.com")),
-            target_port: 80,
-            protocol: ProxyProtocol::HTTP,
-            originator_public_key: key.clone(),
+            requests_received: None,
+            truncated: false,
+            udp: false,
+            exit_failed: false,
         };
-        let expected_pkg =
-            IncipientCoresPackage::new(cryptde, route.clone(), expected_payload, &key).unwrap();
-        thread::spawn(move || {
-            let stream_key_factory = StreamKeyFactoryMock::new(); // can't make any stream keys; shouldn't have to
-            let system = System::new("proxy_server_receives_http_request_from_dispatcher_then_sends_cores_package_to_hopper");
-            let mut subject = ProxyServer::new(cryptde, false);
-            subject.stream_key_factory = Box::new(stream_key_factory);
-            subject.keys_and_addrs.insert(stream_key, socket_addr);
-            let subject_addr: Addr<Syn, ProxyServer> = subject.start();
-            let mut peer_actors = peer_actors_builder()
-                .hopper(hopper_mock)
-                .neighborhood(neighborhood_mock)
-                .build();
-            peer_actors.proxy_server = ProxyServer::make_subs_from(&subject_addr);
-            subject_addr.try_send(BindMessage { peer_actors }).unwrap();
-
-            subject_addr.try_send(msg_from_dispatcher).unwrap();
+        let expired_cores_package = ExpiredCoresPackage::new(
+            IpAddr::from_str("1.2.3.4").unwrap(),
+            Some(Wallet::new("consuming")),
+            remaining_route,
+            encodex(cryptde, &cryptde.public_key(), &client_response_payload).unwrap(),
+        );
 
-            system.run();
-        });
+        subject_addr.try_send(expired_cores_package).unwrap();
 
-        hopper_awaiter.await_message_count(1);
-        let recording = hopper_log_arc.lock().unwrap();
-        let record = recording.get_record::<IncipientCoresPackage>(0);
-        assert_eq!(record, &expected_pkg);
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
     }
 
     #[test]
-    fn proxy_server_receives_http_request_from_dispatcher_then_sends_multihop_cores_package_to_hopper(
-    ) {
-        let cryptde = cryptde();
-        let consuming_wallet = Wallet::new("consuming wallet");
-        let earning_wallet = Wallet::new("earning wallet");
+    #[should_panic(expected = "Neighborhood unbound in ProxyServer")]
+    fn panics_if_hopper_is_unbound() {
+        let system = System::new("panics_if_hopper_is_unbound");
         let http_request = b"GET /index.html HTTP/1.1\r\nHost: nowhere.com\r\n\r\n";
-        let hopper_mock = Recorder::new();
-        let hopper_log_arc = hopper_mock.get_recording();
-        let hopper_awaiter = hopper_mock.get_awaiter();
-        let payload_destination_key = PublicKey::new(&[3]);
-        let route = Route::round_trip(
-            RouteSegment::new(
-                vec![
-                    &cryptde.public_key(),
-                    &PublicKey::new(&[1]),
-                    &PublicKey::new(&[2]),
-                    &payload_destination_key,
-                ],
-                Component::ProxyClient,
-            ),
-            RouteSegment::new(
-                vec![
-                    &payload_destination_key,
-                    &PublicKey::new(&[2]),
-                    &PublicKey::new(&[1]),
-                    &cryptde.public_key(),
-                ],
-                Component::ProxyServer,
-            ),
-            cryptde,
-            Some(consuming_wallet),
-            1234,
-        )
-        .unwrap();
-        let (neighborhood_mock, _, neighborhood_recording_arc) = make_recorder();
-        let neighborhood_mock = neighborhood_mock.route_query_response(Some(RouteQueryResponse {
-            route: route.clone(),
-            expected_services: ExpectedServices::RoundTrip(
-                vec![
-                    ExpectedService::Exit(PublicKey::new(&[3]), earning_wallet.clone()),
-                    ExpectedService::Nothing,
-                ],
-                vec![
-                    ExpectedService::Nothing,
-                    ExpectedService::Exit(PublicKey::new(&[3]), earning_wallet),
-                ],
-                1234,
-            ),
-        }));
+        let subject = ProxyServer::new(cryptde(), false);
         let socket_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
-        let stream_key = make_meaningless_stream_key();
         let expected_data = http_request.to_vec();
         let msg_from_dispatcher = InboundClientData {
             peer_addr: socket_addr.clone(),
             reception_port: Some(80),
             sequence_number: Some(0),
-            last_data: true,
+            last_data: false,
             is_clandestine: false,
             data: expected_data.clone(),
+            neighbor_key_opt: None,
         };
-        let expected_http_request = PlainData::new(http_request);
-        let key = cryptde.public_key();
-        let expected_payload = ClientRequestPayload {
-            stream_key: stream_key.clone(),
+        let subject_addr: Addr<Syn, ProxyServer> = subject.start();
+
+        subject_addr.try_send(msg_from_dispatcher).unwrap();
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+    }
+
+    #[test]
+    fn report_response_services_consumed_complains_and_drops_package_if_return_route_id_is_unrecognized(
+    ) {
+        init_test_logging();
+        let cryptde = cryptde();
+        let (dispatcher, _, dispatcher_recording_arc) = make_recorder();
+        let (accountant, _, accountant_recording_arc) = make_recorder();
+        let system = System::new ("report_response_services_consumed_complains_and_drops_package_if_return_route_id_does_not_exist");
+        let mut subject = ProxyServer::new(cryptde, true);
+        let stream_key = make_meaningless_stream_key();
+        subject
+            .keys_and_addrs
+            .insert(stream_key, SocketAddr::from_str("1.2.3.4:5678").unwrap());
+        let subject_addr: Addr<Syn, ProxyServer> = subject.start();
+        let mut peer_actors = peer_actors_builder()
+            .dispatcher(dispatcher)
+            .accountant(accountant)
+            .build();
+        peer_actors.proxy_server = ProxyServer::make_subs_from(&subject_addr);
+        let client_response_payload = ClientResponsePayload {
+            stream_key,
             sequenced_packet: SequencedPacket {
-                data: expected_http_request.into(),
-                sequence_number: 0,
-                last_data: true,
+                data: b"some data".to_vec(),
+                sequence_number: 4321,
+                last_data: false,
             },
-            target_hostname: Some(String::from("nowhere.com")),
-            target_port: 80,
-            protocol: ProxyProtocol::HTTP,
-            originator_public_key: key.clone(),
+            requests_received: None,
+            truncated: false,
+            udp: false,
+            exit_failed: false,
         };
-        let expected_pkg = IncipientCoresPackage::new(
-            cryptde,
-            route.clone(),
-            expected_payload,
-            &payload_destination_key,
-        )
-        .unwrap();
-        thread::spawn(move || {
-            let stream_key_factory = StreamKeyFactoryMock::new().make_result(stream_key);
-            let system = System::new("proxy_server_receives_http_request_from_dispatcher_then_sends_cores_package_to_hopper");
-            let mut subject = ProxyServer::new(cryptde, true);
-            subject.stream_key_factory = Box::new(stream_key_factory);
-            let subject_addr: Addr<Syn, ProxyServer> = subject.start();
-            let mut peer_actors = peer_actors_builder()
-                .hopper(hopper_mock)
-                .neighborhood(neighborhood_mock)
-                .build();
-            peer_actors.proxy_server = ProxyServer::make_subs_from(&subject_addr);
-            subject_addr.try_send(BindMessage { peer_actors }).unwrap();
+        let expired_cores_package = ExpiredCoresPackage::new(
+            IpAddr::from_str("1.2.3.4").unwrap(),
+            Some(Wallet::new("irrelevant")),
+            return_route_with_id(cryptde, 1234),
+            encodex(cryptde, &cryptde.public_key(), &client_response_payload).unwrap(),
+        );
+        subject_addr.try_send(BindMessage { peer_actors }).unwrap();
 
-            subject_addr.try_send(msg_from_dispatcher).unwrap();
+        subject_addr.try_send(expired_cores_package).unwrap();
 
-            system.run();
-        });
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+        TestLogHandler::new().exists_log_containing("ERROR: Proxy Server: Can't report services consumed: return route ID 1234 is not recognized");
+        assert_eq!(dispatcher_recording_arc.lock().unwrap().len(), 0);
+        assert_eq!(accountant_recording_arc.lock().unwrap().len(), 0);
+    }
 
-        hopper_awaiter.await_message_count(1);
-        let recording = hopper_log_arc.lock().unwrap();
-        let record = recording.get_record::<IncipientCoresPackage>(0);
-        assert_eq!(record, &expected_pkg);
-        let recording = neighborhood_recording_arc.lock().unwrap();
-        let record = recording.get_record::<RouteQueryMessage>(0);
-        assert_eq!(record, &RouteQueryMessage::data_indefinite_route_request(2));
+    #[test]
+    fn report_response_services_consumed_complains_and_drops_package_if_return_route_id_is_unspecified(
+    ) {
+        init_test_logging();
+        let cryptde = cryptde();
+        let (dispatcher, _, dispatcher_recording_arc) = make_recorder();
+        let (accountant, _, accountant_recording_arc) = make_recorder();
+        let system = System::new ("report_response_services_consumed_complains_and_drops_package_if_return_route_id_is_unspecified");
+        let mut subject = ProxyServer::new(cryptde, true);
+        let stream_key = make_meaningless_stream_key();
+        subject
+            .keys_and_addrs
+            .insert(stream_key, SocketAddr::from_str("1.2.3.4:5678").unwrap());
+        let subject_addr: Addr<Syn, ProxyServer> = subject.start();
+        let mut peer_actors = peer_actors_builder()
+            .dispatcher(dispatcher)
+            .accountant(accountant)
+            .build();
+        peer_actors.proxy_server = ProxyServer::make_subs_from(&subject_addr);
+        let client_response_payload = ClientResponsePayload {
+            stream_key,
+            sequenced_packet: SequencedPacket {
+                data: b"some data".to_vec(),
+                sequence_number: 4321,
+                last_data: false,
+            },
+            requests_received: None,
+            truncated: false,
+            udp: false,
+            exit_failed: false,
+        };
+        let expired_cores_package = ExpiredCoresPackage::new(
+            IpAddr::from_str("1.2.3.4").unwrap(),
+            Some(Wallet::new("irrelevant")),
+            Route {
+                hops: vec![make_cover_hop(cryptde)],
+            },
+            encodex(cryptde, &cryptde.public_key(), &client_response_payload).unwrap(),
+        );
+        subject_addr.try_send(BindMessage { peer_actors }).unwrap();
+
+        subject_addr.try_send(expired_cores_package).unwrap();
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+        TestLogHandler::new().exists_log_containing(
+            "ERROR: Proxy Server: Can't report services consumed: return route ID is unspecified",
+        );
+        assert_eq!(dispatcher_recording_arc.lock().unwrap().len(), 0);
+        assert_eq!(accountant_recording_arc.lock().unwrap().len(), 0);
     }
 
     #[test]
-    fn proxy_server_sends_message_to_accountant_for_request_routing_service_consumed() {
+    fn report_response_services_consumed_complains_and_drops_package_if_return_route_id_is_unreadable(
+    ) {
+        init_test_logging();
         let cryptde = cryptde();
-        let exit_earning_wallet = Wallet::new("exit earning wallet");
-        let route_1_earning_wallet = Wallet::new("route 1 earning wallet");
-        let route_2_earning_wallet = Wallet::new("route 2 earning wallet");
-        let http_request = b"GET /index.html HTTP/1.1\r\nHost: nowhere.com\r\n\r\n";
-        let (accountant_mock, accountant_awaiter, accountant_log_arc) = make_recorder();
-        let (neighborhood_mock, _, _) = make_recorder();
-        let neighborhood_mock = neighborhood_mock.route_query_response(Some(RouteQueryResponse {
-            route: make_meaningless_route(),
-            expected_services: ExpectedServices::RoundTrip(
-                vec![
-                    ExpectedService::Nothing,
-                    ExpectedService::Routing(PublicKey::new(&[1]), route_1_earning_wallet.clone()),
-                    ExpectedService::Routing(PublicKey::new(&[2]), route_2_earning_wallet.clone()),
-                    ExpectedService::Exit(PublicKey::new(&[3]), exit_earning_wallet.clone()),
-                ],
-                vec![
-                    ExpectedService::Exit(PublicKey::new(&[3]), exit_earning_wallet.clone()),
-                    ExpectedService::Routing(PublicKey::new(&[2]), route_2_earning_wallet.clone()),
-                    ExpectedService::Routing(PublicKey::new(&[1]), route_1_earning_wallet.clone()),
-                    ExpectedService::Nothing,
-                ],
-                0,
-            ),
-        }));
-        let socket_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
+        let (dispatcher, _, dispatcher_recording_arc) = make_recorder();
+        let (accountant, _, accountant_recording_arc) = make_recorder();
+        let system = System::new ("report_response_services_consumed_complains_and_drops_package_if_return_route_id_is_unreadable");
+        let mut subject = ProxyServer::new(cryptde, true);
         let stream_key = make_meaningless_stream_key();
-        let expected_data = http_request.to_vec();
-        let msg_from_dispatcher = InboundClientData {
-            peer_addr: socket_addr.clone(),
-            reception_port: Some(80),
-            sequence_number: Some(0),
-            last_data: true,
-            is_clandestine: false,
-            data: expected_data.clone(),
-        };
-        thread::spawn(move || {
-            let stream_key_factory = StreamKeyFactoryMock::new().make_result(stream_key);
-            let system = System::new(
-                "proxy_server_sends_message_to_accountant_for_routing_service_consumed",
-            );
-            let mut subject = ProxyServer::new(cryptde, true);
-            subject.stream_key_factory = Box::new(stream_key_factory);
-            let subject_addr: Addr<Syn, ProxyServer> = subject.start();
-            let mut peer_actors = peer_actors_builder()
-                .accountant(accountant_mock)
-                .neighborhood(neighborhood_mock)
-                .build();
-            peer_actors.proxy_server = ProxyServer::make_subs_from(&subject_addr);
-            subject_addr.try_send(BindMessage { peer_actors }).unwrap();
-            subject_addr.try_send(msg_from_dispatcher).unwrap();
-            system.run();
-        });
-
-        let exit_key = PublicKey::new(&[3]);
-        let payload = ClientRequestPayload {
+        subject
+            .keys_and_addrs
+            .insert(stream_key, SocketAddr::from_str("1.2.3.4:5678").unwrap());
+        let subject_addr: Addr<Syn, ProxyServer> = subject.start();
+        let mut peer_actors = peer_actors_builder()
+            .dispatcher(dispatcher)
+            .accountant(accountant)
+            .build();
+        peer_actors.proxy_server = ProxyServer::make_subs_from(&subject_addr);
+        let client_response_payload = ClientResponsePayload {
             stream_key,
-            sequenced_packet: SequencedPacket::new(expected_data, 0, false),
-            target_hostname: Some("nowhere.com".to_string()),
-            target_port: 80,
-            protocol: ProxyProtocol::HTTP,
-            originator_public_key: exit_key,
+            sequenced_packet: SequencedPacket {
+                data: b"some data".to_vec(),
+                sequence_number: 4321,
+                last_data: false,
+            },
+            requests_received: None,
+            truncated: false,
+            udp: false,
+            exit_failed: false,
         };
-        let payload_ser = PlainData::from(serde_cbor::ser::to_vec(&payload).unwrap());
-        let payload_enc = cryptde.encode(&cryptde.public_key(), &payload_ser).unwrap();
-
-        accountant_awaiter.await_message_count(3);
-        let recording = accountant_log_arc.lock().unwrap();
-        let record = recording.get_record::<ReportRoutingServiceConsumedMessage>(1);
-        assert_eq!(
-            record,
-            &ReportRoutingServiceConsumedMessage {
-                earning_wallet: route_1_earning_wallet,
-                payload_size: payload_enc.len(),
-                service_rate: TEMPORARY_PER_ROUTING_RATE,
-                byte_rate: TEMPORARY_PER_ROUTING_BYTE_RATE,
-            }
+        let expired_cores_package = ExpiredCoresPackage::new(
+            IpAddr::from_str("1.2.3.4").unwrap(),
+            Some(Wallet::new("irrelevant")),
+            Route {
+                hops: vec![make_cover_hop(cryptde), CryptData::new(&[0])],
+            },
+            encodex(cryptde, &cryptde.public_key(), &client_response_payload).unwrap(),
         );
-        let record = recording.get_record::<ReportRoutingServiceConsumedMessage>(2);
-        assert_eq!(
-            record,
-            &ReportRoutingServiceConsumedMessage {
-                earning_wallet: route_2_earning_wallet,
-                payload_size: payload_enc.len(),
-                service_rate: TEMPORARY_PER_ROUTING_RATE,
-                byte_rate: TEMPORARY_PER_ROUTING_BYTE_RATE,
-            }
+        subject_addr.try_send(BindMessage { peer_actors }).unwrap();
+
+        subject_addr.try_send(expired_cores_package).unwrap();
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+        TestLogHandler::new().exists_log_containing(
+            "ERROR: Proxy Server: Can't report services consumed: return route ID is unreadable",
         );
+        assert_eq!(dispatcher_recording_arc.lock().unwrap().len(), 0);
+        assert_eq!(accountant_recording_arc.lock().unwrap().len(), 0);
     }
 
     #[test]
-    fn proxy_server_logs_messages_when_routing_services_are_not_requested() {
-        init_test_logging();
+    fn proxy_server_remembers_the_route_it_was_given_for_a_stream() {
         let cryptde = cryptde();
-        let http_request = b"GET /index.html HTTP/1.1\r\nHost: nowhere.com\r\n\r\n";
-        let (accountant_mock, _, accountant_log_arc) = make_recorder();
+        let (proxy_server_mock, proxy_server_awaiter, proxy_server_recording_arc) = make_recorder();
+        let route_query_response = zero_hop_route_response(&cryptde.public_key(), cryptde);
         let (neighborhood_mock, _, _) = make_recorder();
-        let zero_hop_route_reponse = zero_hop_route_response(&cryptde.public_key(), cryptde);
         let neighborhood_mock =
-            neighborhood_mock.route_query_response(Some(zero_hop_route_reponse.clone()));
+            neighborhood_mock.route_query_response(Ok(route_query_response.clone()));
         let socket_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
         let stream_key = make_meaningless_stream_key();
-        let expected_data = http_request.to_vec();
         let msg_from_dispatcher = InboundClientData {
-            peer_addr: socket_addr.clone(),
+            peer_addr: socket_addr,
             reception_port: Some(80),
             sequence_number: Some(0),
-            last_data: true,
+            last_data: false,
             is_clandestine: false,
-            data: expected_data.clone(),
+            data: b"GET /index.html HTTP/1.1\r\nHost: nowhere.com\r\n\r\n".to_vec(),
+            neighbor_key_opt: None,
         };
         thread::spawn(move || {
-            let stream_key_factory = StreamKeyFactoryMock::new().make_result(stream_key);
-            let system =
-                System::new("proxy_server_logs_messages_when_routing_services_are_not_requested");
-            let mut subject = ProxyServer::new(cryptde, true);
-            subject.stream_key_factory = Box::new(stream_key_factory);
+            let system = System::new("proxy_server_remembers_the_route_it_was_given_for_a_stream");
+            let mut subject = ProxyServer::new(cryptde, false);
+            subject.stream_key_factory =
+                Box::new(StreamKeyFactoryMock::new().make_result(stream_key));
             let subject_addr: Addr<Syn, ProxyServer> = subject.start();
-            let mut peer_actors = peer_actors_builder()
-                .accountant(accountant_mock)
+            let peer_actors = peer_actors_builder()
+                .proxy_server(proxy_server_mock)
                 .neighborhood(neighborhood_mock)
                 .build();
-            peer_actors.proxy_server = ProxyServer::make_subs_from(&subject_addr);
             subject_addr.try_send(BindMessage { peer_actors }).unwrap();
+
             subject_addr.try_send(msg_from_dispatcher).unwrap();
+
             system.run();
         });
 
-        TestLogHandler::new()
-            .await_log_containing("DEBUG: Proxy Server: No routing services requested.", 1000);
-
-        assert_eq!(accountant_log_arc.lock().unwrap().len(), 0);
+        proxy_server_awaiter.await_message_count(2);
+        let recording = proxy_server_recording_arc.lock().unwrap();
+        let record = recording.get_record::<AddRouteMessage>(0);
+        assert_eq!(record.stream_key, stream_key);
+        assert_eq!(record.route, route_query_response);
     }
 
     #[test]
-    fn proxy_server_sends_message_to_accountant_for_request_exit_service_consumed() {
+    fn proxy_server_reuses_a_cached_route_until_it_expires() {
         let cryptde = cryptde();
-        let earning_wallet = Wallet::new("earning wallet");
-        let http_request = b"GET /index.html HTTP/1.1\r\nHost: nowhere.com\r\n\r\n";
-        let (accountant_mock, accountant_awaiter, accountant_log_arc) = make_recorder();
-        let (neighborhood_mock, _, _) = make_recorder();
-        let neighborhood_mock = neighborhood_mock.route_query_response(Some(RouteQueryResponse {
-            route: make_meaningless_route(),
-            expected_services: ExpectedServices::RoundTrip(
-                vec![
-                    ExpectedService::Nothing,
-                    ExpectedService::Exit(PublicKey::new(&[3]), earning_wallet.clone()),
-                ],
-                vec![
-                    ExpectedService::Exit(PublicKey::new(&[3]), earning_wallet.clone()),
-                    ExpectedService::Nothing,
-                ],
-                0,
-            ),
-        }));
-        let socket_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
-        let stream_key = make_meaningless_stream_key();
-        let expected_data = http_request.to_vec();
-        let msg_from_dispatcher = InboundClientData {
-            peer_addr: socket_addr.clone(),
+        let (hopper_mock, hopper_awaiter, _) = make_recorder();
+        let (neighborhood_mock, _, neighborhood_recording_arc) = make_recorder();
+        let route_query_response = zero_hop_route_response(&cryptde.public_key(), cryptde);
+        let neighborhood_mock =
+            neighborhood_mock.route_query_response(Ok(route_query_response.clone()));
+        let fresh_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
+        let fresh_key = StreamKey::new(cryptde.public_key(), fresh_addr);
+        let expired_addr = SocketAddr::from_str("2.3.4.5:6789").unwrap();
+        let expired_key = StreamKey::new(cryptde.public_key(), expired_addr);
+        let make_msg = |peer_addr: SocketAddr| InboundClientData {
+            peer_addr,
             reception_port: Some(80),
-            sequence_number: Some(0),
-            last_data: true,
+            sequence_number: Some(1),
+            last_data: false,
             is_clandestine: false,
-            data: expected_data.clone(),
+            data: b"GET /index.html HTTP/1.1\r\nHost: nowhere.com\r\n\r\n".to_vec(),
+            neighbor_key_opt: None,
         };
+        let fresh_msg = make_msg(fresh_addr);
+        let expired_msg = make_msg(expired_addr);
         thread::spawn(move || {
-            let stream_key_factory = StreamKeyFactoryMock::new().make_result(stream_key);
-            let system =
-                System::new("proxy_server_sends_message_to_accountant_for_exit_service_consumed");
-            let mut subject = ProxyServer::new(cryptde, true);
-            subject.stream_key_factory = Box::new(stream_key_factory);
+            let system = System::new("proxy_server_reuses_a_cached_route_until_it_expires");
+            let mut subject = ProxyServer::new(cryptde, false);
+            subject.keys_and_addrs.insert(fresh_key, fresh_addr);
+            subject.keys_and_addrs.insert(expired_key, expired_addr);
+            subject.routes.insert(
+                fresh_key,
+                (
+                    route_query_response.clone(),
+                    Instant::now() + Duration::from_secs(60),
+                ),
+            );
+            subject.routes.insert(
+                expired_key,
+                (
+                    route_query_response,
+                    Instant::now() - Duration::from_millis(1),
+                ),
+            );
             let subject_addr: Addr<Syn, ProxyServer> = subject.start();
-            let mut peer_actors = peer_actors_builder()
-                .accountant(accountant_mock)
+            let peer_actors = peer_actors_builder()
+                .hopper(hopper_mock)
                 .neighborhood(neighborhood_mock)
                 .build();
-            peer_actors.proxy_server = ProxyServer::make_subs_from(&subject_addr);
             subject_addr.try_send(BindMessage { peer_actors }).unwrap();
-            subject_addr.try_send(msg_from_dispatcher).unwrap();
+
+            subject_addr.try_send(fresh_msg).unwrap();
+            subject_addr.try_send(expired_msg).unwrap();
+
             system.run();
         });
 
-        accountant_awaiter.await_message_count(1);
-        let recording = accountant_log_arc.lock().unwrap();
-        let record = recording.get_record::<ReportExitServiceConsumedMessage>(0);
-        assert_eq!(
-            record,
-            &ReportExitServiceConsumedMessage {
-                earning_wallet,
-                payload_size: expected_data.len(),
-                service_rate: TEMPORARY_PER_EXIT_RATE,
-                byte_rate: TEMPORARY_PER_EXIT_BYTE_RATE,
-            }
+        hopper_awaiter.await_message_count(2);
+        let recording = neighborhood_recording_arc.lock().unwrap();
+        assert_eq!(recording.len(), 1);
+    }
+
+    #[test]
+    fn stream_count_query_counts_the_streams_browsers_have_open() {
+        let system = System::new("stream_count_query_counts_the_streams_browsers_have_open");
+        let cryptde = cryptde();
+        let mut subject = ProxyServer::new(cryptde, false);
+        let first_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
+        let second_addr = SocketAddr::from_str("1.2.3.4:5679").unwrap();
+        subject.keys_and_addrs.insert(
+            StreamKey::new(cryptde.public_key().clone(), first_addr),
+            first_addr,
         );
+        subject.keys_and_addrs.insert(
+            StreamKey::new(cryptde.public_key().clone(), second_addr),
+            second_addr,
+        );
+        let subject_addr: Addr<Syn, ProxyServer> = subject.start();
+
+        let future = subject_addr.send(StreamCountQueryMessage {});
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+        assert_eq!(future.wait().unwrap(), 2);
     }
 
     #[test]
-    fn proxy_server_logs_message_when_exit_services_are_not_requested() {
+    fn proxy_server_closes_each_open_stream_over_its_cached_route_on_shutdown() {
         init_test_logging();
         let cryptde = cryptde();
-        let http_request = b"GET /index.html HTTP/1.1\r\nHost: nowhere.com\r\n\r\n";
-        let (accountant_mock, _, accountant_log_arc) = make_recorder();
-        let (neighborhood_mock, _, _) = make_recorder();
-        let zero_hop_route_reponse = zero_hop_route_response(&cryptde.public_key(), cryptde);
-        let neighborhood_mock =
-            neighborhood_mock.route_query_response(Some(zero_hop_route_reponse.clone()));
-        let socket_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
-        let stream_key = make_meaningless_stream_key();
-        let expected_data = http_request.to_vec();
+        let (hopper_mock, hopper_awaiter, hopper_recording_arc) = make_recorder();
+        let key = cryptde.public_key();
+        let route_query_response = zero_hop_route_response(&key, cryptde);
+        let source_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
+        let stream_key = StreamKey::new(key.clone(), source_addr);
         let msg_from_dispatcher = InboundClientData {
-            peer_addr: socket_addr.clone(),
+            peer_addr: source_addr,
             reception_port: Some(80),
-            sequence_number: Some(0),
-            last_data: true,
+            sequence_number: Some(1),
+            last_data: false,
             is_clandestine: false,
-            data: expected_data.clone(),
+            data: b"GET /index.html HTTP/1.1\r\nHost: nowhere.com\r\n\r\n".to_vec(),
+            neighbor_key_opt: None,
         };
+        let expected_closing_pkg = IncipientCoresPackage::new(
+            cryptde,
+            route_query_response.route.clone(),
+            ClientRequestPayload {
+                stream_key,
+                sequenced_packet: SequencedPacket {
+                    data: vec![],
+                    sequence_number: 2,
+                    last_data: true,
+                },
+                target_hostname: Some(String::from("nowhere.com")),
+                target_port: 80,
+                protocol: ProxyProtocol::HTTP,
+                originator_public_key: key.clone(),
+                tls_at_exit: false,
+                upload_window: None,
+                udp: false,
+                dns_server: None,
+                cancel: true,
+            },
+            &key,
+        )
+        .unwrap();
         thread::spawn(move || {
-            let stream_key_factory = StreamKeyFactoryMock::new().make_result(stream_key);
-            let system =
-                System::new("proxy_server_logs_message_when_exit_services_are_not_consumed");
-            let mut subject = ProxyServer::new(cryptde, true);
-            subject.stream_key_factory = Box::new(stream_key_factory);
+            let system = System::new(
+                "proxy_server_closes_each_open_stream_over_its_cached_route_on_shutdown",
+            );
+            let mut subject = ProxyServer::new(cryptde, false);
+            subject.keys_and_addrs.insert(stream_key, source_addr);
+            subject.routes.insert(
+                stream_key,
+                (
+                    route_query_response,
+                    Instant::now() + Duration::from_secs(60),
+                ),
+            );
             let subject_addr: Addr<Syn, ProxyServer> = subject.start();
-            let mut peer_actors = peer_actors_builder()
-                .accountant(accountant_mock)
-                .neighborhood(neighborhood_mock)
-                .build();
-            peer_actors.proxy_server = ProxyServer::make_subs_from(&subject_addr);
+            let peer_actors = peer_actors_builder().hopper(hopper_mock).build();
             subject_addr.try_send(BindMessage { peer_actors }).unwrap();
+
             subject_addr.try_send(msg_from_dispatcher).unwrap();
+            subject_addr.try_send(ShutdownMessage {}).unwrap();
+
             system.run();
         });
 
+        hopper_awaiter.await_message_count(2);
+        let recording = hopper_recording_arc.lock().unwrap();
+        assert_eq!(
+            recording.get_record::<IncipientCoresPackage>(1),
+            &expected_closing_pkg
+        );
         TestLogHandler::new()
-            .await_log_containing("DEBUG: Proxy Server: No exit service requested.", 1000);
-
-        assert_eq!(accountant_log_arc.lock().unwrap().len(), 0);
+            .exists_log_containing("INFO: Proxy Server: Closed 1 open stream(s) for shutdown");
     }
 
     #[test]
-    fn proxy_server_receives_http_request_from_dispatcher_but_neighborhood_cant_make_route() {
+    fn proxy_server_cancels_a_stream_at_the_exit_when_the_browser_hangs_up_before_the_response_is_done(
+    ) {
         init_test_logging();
         let cryptde = cryptde();
-        let http_request = b"GET /index.html HTTP/1.1\r\nHost: nowhere.com\r\n\r\n";
-        let (neighborhood_mock, _, neighborhood_recording_arc) = make_recorder();
-        let neighborhood_mock = neighborhood_mock.route_query_response(None);
-        let dispatcher = Recorder::new();
-        let dispatcher_awaiter = dispatcher.get_awaiter();
-        let dispatcher_recording_arc = dispatcher.get_recording();
-        let socket_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
-        let expected_data = http_request.to_vec();
+        let (hopper_mock, hopper_awaiter, hopper_recording_arc) = make_recorder();
+        let key = cryptde.public_key();
+        let route_query_response = zero_hop_route_response(&key, cryptde);
+        let source_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
+        let stream_key = StreamKey::new(key.clone(), source_addr);
         let msg_from_dispatcher = InboundClientData {
-            peer_addr: socket_addr.clone(),
+            peer_addr: source_addr,
             reception_port: Some(80),
-            sequence_number: Some(0),
+            sequence_number: Some(1),
+            last_data: false,
+            is_clandestine: false,
+            data: b"GET /index.html HTTP/1.1\r\nHost: nowhere.com\r\n\r\n".to_vec(),
+            neighbor_key_opt: None,
+        };
+        let msg_from_browser_hangup = InboundClientData {
+            peer_addr: source_addr,
+            reception_port: Some(80),
+            sequence_number: Some(2),
             last_data: true,
-            data: expected_data.clone(),
             is_clandestine: false,
+            data: vec![],
+            neighbor_key_opt: None,
         };
+        let expected_cancel_pkg = IncipientCoresPackage::new(
+            cryptde,
+            route_query_response.route.clone(),
+            ClientRequestPayload {
+                stream_key,
+                sequenced_packet: SequencedPacket {
+                    data: vec![],
+                    sequence_number: 2,
+                    last_data: true,
+                },
+                target_hostname: None,
+                target_port: 80,
+                protocol: ProxyProtocol::HTTP,
+                originator_public_key: key.clone(),
+                tls_at_exit: false,
+                upload_window: None,
+                udp: false,
+                dns_server: None,
+                cancel: true,
+            },
+            &key,
+        )
+        .unwrap();
         thread::spawn(move || {
-            let system = System::new("proxy_server_receives_http_request_from_dispatcher_but_neighborhood_cant_make_route");
-            let subject = ProxyServer::new(cryptde, true);
+            let system = System::new(
+                "proxy_server_cancels_a_stream_at_the_exit_when_the_browser_hangs_up_before_the_response_is_done",
+            );
+            let mut subject = ProxyServer::new(cryptde, false);
+            subject.keys_and_addrs.insert(stream_key, source_addr);
+            subject.routes.insert(
+                stream_key,
+                (
+                    route_query_response,
+                    Instant::now() + Duration::from_secs(60),
+                ),
+            );
             let subject_addr: Addr<Syn, ProxyServer> = subject.start();
-            let mut peer_actors = peer_actors_builder()
-                .dispatcher(dispatcher)
-                .neighborhood(neighborhood_mock)
-                .build();
-            peer_actors.proxy_server = ProxyServer::make_subs_from(&subject_addr);
+            let peer_actors = peer_actors_builder().hopper(hopper_mock).build();
             subject_addr.try_send(BindMessage { peer_actors }).unwrap();
 
             subject_addr.try_send(msg_from_dispatcher).unwrap();
+            subject_addr.try_send(msg_from_browser_hangup).unwrap();
 
             system.run();
         });
 
-        dispatcher_awaiter.await_message_count(1);
-        let recording = dispatcher_recording_arc.lock().unwrap();
-        let record = recording.get_record::<TransmitDataMsg>(0);
-        let expected_msg = TransmitDataMsg {
-            endpoint: Endpoint::Socket(SocketAddr::from_str("1.2.3.4:5678").unwrap()),
-            last_data: true,
-            sequence_number: Some(0),
-            data: http_server_impersonator::make_error_response(
-                503,
-                "Routing Problem",
-                "Can't find a route to nowhere.com",
-                "Substratum can't find a route through the Network yet to a Node that knows \
-                 where to find nowhere.com. Maybe later enough will be known about the Network to \
-                 find that Node, but we can't guarantee it. We're sorry.",
-            ),
-        };
-        assert_eq!(record, &expected_msg);
-        let recording = neighborhood_recording_arc.lock().unwrap();
-        let record = recording.get_record::<RouteQueryMessage>(0);
-        assert_eq!(record, &RouteQueryMessage::data_indefinite_route_request(2));
-        TestLogHandler::new()
-            .exists_log_containing("ERROR: Proxy Server: Failed to find route to nowhere.com");
+        hopper_awaiter.await_message_count(2);
+        let recording = hopper_recording_arc.lock().unwrap();
+        assert_eq!(
+            recording.get_record::<IncipientCoresPackage>(1),
+            &expected_cancel_pkg
+        );
+        TestLogHandler::new().exists_log_containing("DEBUG: Proxy Server: Canceling stream");
     }
 
     #[test]
-    #[should_panic(expected = "Expected RoundTrip ExpectedServices but got OneWay")]
-    fn proxy_server_panics_if_it_receives_a_one_way_route_from_a_request_for_a_round_trip_route() {
-        let _system = System::new("proxy_server_panics_if_it_receives_a_one_way_route_from_a_request_for_a_round_trip_route");
-        let peer_actors = peer_actors_builder().build();
-
+    fn return_route_ids_expire_when_instructed() {
+        init_test_logging();
         let cryptde = cryptde();
-        let route_result = Ok(Some(RouteQueryResponse {
-            route: make_meaningless_route(),
-            expected_services: ExpectedServices::OneWay(vec![
-                ExpectedService::Nothing,
-                ExpectedService::Routing(PublicKey::new(&[1]), Wallet::new("earning wallet 1")),
-                ExpectedService::Routing(PublicKey::new(&[2]), Wallet::new("earning wallet 2")),
-                ExpectedService::Exit(PublicKey::new(&[3]), Wallet::new("exit earning wallet")),
-            ]),
-        }));
-        let payload = ClientRequestPayload {
-            stream_key: make_meaningless_stream_key(),
+        let stream_key = make_meaningless_stream_key();
+
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            let system = System::new ("report_response_services_consumed_complains_and_drops_package_if_return_route_id_does_not_exist");
+            let mut subject = ProxyServer::new(cryptde, true);
+            subject.route_ids_to_services = TtlHashMap::new(Duration::from_millis(250));
+            subject
+                .keys_and_addrs
+                .insert(stream_key, SocketAddr::from_str("1.2.3.4:5678").unwrap());
+            subject.route_ids_to_services.insert(1234, vec![]);
+            let subject_addr: Addr<Syn, ProxyServer> = subject.start();
+            let mut peer_actors = peer_actors_builder().build();
+            peer_actors.proxy_server = ProxyServer::make_subs_from(&subject_addr);
+            subject_addr.try_send(BindMessage { peer_actors }).unwrap();
+            tx.send(subject_addr).unwrap();
+
+            system.run();
+        });
+
+        let subject_addr = rx.recv().unwrap();
+
+        thread::sleep(Duration::from_millis(300));
+
+        let client_response_payload = ClientResponsePayload {
+            stream_key,
             sequenced_packet: SequencedPacket {
-                data: vec![],
-                sequence_number: 0,
+                data: b"some data".to_vec(),
+                sequence_number: 4321,
                 last_data: false,
             },
-            target_hostname: None,
-            target_port: 0,
-            protocol: ProxyProtocol::TLS,
-            originator_public_key: cryptde.public_key(),
+            requests_received: None,
+            truncated: false,
+            udp: false,
+            exit_failed: false,
         };
-        let logger = Logger::new("ProxyServer");
-        let source_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
+        let expired_cores_package = ExpiredCoresPackage::new(
+            IpAddr::from_str("1.2.3.4").unwrap(),
+            Some(Wallet::new("irrelevant")),
+            return_route_with_id(cryptde, 1234),
+            encodex(cryptde, &cryptde.public_key(), &client_response_payload).unwrap(),
+        );
+        subject_addr.try_send(expired_cores_package).unwrap();
 
-        ProxyServer::try_transmit_to_hopper(
-            cryptde,
-            peer_actors.hopper.from_hopper_client,
-            route_result,
-            payload,
-            logger,
-            source_addr,
-            peer_actors.dispatcher.from_dispatcher_client,
-            peer_actors.accountant.report_exit_service_consumed,
-            peer_actors.accountant.report_routing_service_consumed,
-            peer_actors.proxy_server.add_return_route,
-        )
-        .unwrap();
+        TestLogHandler::new().await_log_containing("ERROR: Proxy Server: Can't report services consumed: return route ID 1234 is not recognized", 1000);
     }
 
     #[test]
-    fn proxy_server_receives_http_request_from_dispatcher_but_neighborhood_cant_make_route_with_no_expected_services(
-    ) {
-        init_test_logging();
+    fn proxy_server_holds_requests_beyond_the_upload_window_until_the_exit_acknowledges_them() {
+        let system = System::new(
+            "proxy_server_holds_requests_beyond_the_upload_window_until_the_exit_acknowledges_them",
+        );
         let cryptde = cryptde();
-        let public_key = &cryptde.public_key();
-        let http_request = b"GET /index.html HTTP/1.1\r\nHost: nowhere.com\r\n\r\n";
-        let (neighborhood_mock, _, neighborhood_recording_arc) = make_recorder();
-        let route_query_response = RouteQueryResponse {
-            route: Route::round_trip(
-                RouteSegment::new(vec![public_key, public_key], Component::ProxyClient),
-                RouteSegment::new(vec![public_key, public_key], Component::ProxyServer),
-                cryptde,
-                None,
-                1234,
-            )
-            .unwrap(),
-            expected_services: ExpectedServices::RoundTrip(vec![], vec![], 1234),
-        };
-        let neighborhood_mock = neighborhood_mock.route_query_response(Some(route_query_response));
-        let dispatcher = Recorder::new();
-        let dispatcher_awaiter = dispatcher.get_awaiter();
-        let dispatcher_recording_arc = dispatcher.get_recording();
+        let (hopper_mock, _, hopper_recording_arc) = make_recorder();
+        let (dispatcher_mock, _, dispatcher_recording_arc) = make_recorder();
         let socket_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
-        let expected_data = http_request.to_vec();
-        let msg_from_dispatcher = InboundClientData {
-            peer_addr: socket_addr.clone(),
+        let stream_key = StreamKey::new(cryptde.public_key(), socket_addr);
+        let mut subject = ProxyServer::new(cryptde, false);
+        subject.limit_upload_window(1);
+        subject.keys_and_addrs.insert(stream_key, socket_addr);
+        subject.routes.insert(
+            stream_key,
+            (
+                zero_hop_route_response(&cryptde.public_key(), cryptde),
+                Instant::now() + Duration::from_secs(60),
+            ),
+        );
+        subject
+            .route_ids_to_services
+            .insert(1234, vec![ExpectedService::Nothing]);
+        let make_request = |sequence_number: u64| InboundClientData {
+            peer_addr: socket_addr,
             reception_port: Some(80),
-            sequence_number: Some(0),
-            last_data: true,
-            data: expected_data.clone(),
+            sequence_number: Some(sequence_number),
+            last_data: false,
             is_clandestine: false,
+            data: b"GET /index.html HTTP/1.1\r\nHost: nowhere.com\r\n\r\n".to_vec(),
+            neighbor_key_opt: None,
         };
-        thread::spawn(move || {
-            let system = System::new("proxy_server_receives_http_request_from_dispatcher_but_neighborhood_cant_make_route");
-            let subject = ProxyServer::new(cryptde, true);
-            let subject_addr: Addr<Syn, ProxyServer> = subject.start();
-            let mut peer_actors = peer_actors_builder()
-                .dispatcher(dispatcher)
-                .neighborhood(neighborhood_mock)
-                .build();
-            peer_actors.proxy_server = ProxyServer::make_subs_from(&subject_addr);
-            subject_addr.try_send(BindMessage { peer_actors }).unwrap();
+        let make_acknowledgement = |requests_received: u64| {
+            ExpiredCoresPackage::new(
+                IpAddr::from_str("1.2.3.4").unwrap(),
+                Some(Wallet::new("irrelevant")),
+                return_route_with_id(cryptde, 1234),
+                encodex(
+                    cryptde,
+                    &cryptde.public_key(),
+                    &ClientResponsePayload::make_acknowledgement_payload(
+                        stream_key,
+                        requests_received,
+                    ),
+                )
+                .unwrap(),
+            )
+        };
+        let subject_addr: Addr<Syn, ProxyServer> = subject.start();
+        let peer_actors = peer_actors_builder()
+            .hopper(hopper_mock)
+            .dispatcher(dispatcher_mock)
+            .build();
+        subject_addr.try_send(BindMessage { peer_actors }).unwrap();
 
-            subject_addr.try_send(msg_from_dispatcher).unwrap();
+        subject_addr.try_send(make_request(0)).unwrap();
+        subject_addr.try_send(make_acknowledgement(1)).unwrap();
+        subject_addr.try_send(make_request(1)).unwrap();
+        subject_addr.try_send(make_request(2)).unwrap();
+        subject_addr.try_send(make_request(3)).unwrap();
+        subject_addr.try_send(make_acknowledgement(2)).unwrap();
 
-            system.run();
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+        let hopper_recording = hopper_recording_arc.lock().unwrap();
+        let sequence_numbers: Vec<u64> = (0..hopper_recording.len())
+            .map(|index| {
+                let icp = hopper_recording.get_record::<IncipientCoresPackage>(index);
+                let payload: ClientRequestPayload = decodex(cryptde, &icp.payload).unwrap();
+                assert_eq!(payload.upload_window, Some(1));
+                payload.sequenced_packet.sequence_number
+            })
+            .collect();
+        assert_eq!(sequence_numbers, vec![0, 1, 2]);
+        let dispatcher_recording = dispatcher_recording_arc.lock().unwrap();
+        assert_eq!(dispatcher_recording.len(), 0);
+    }
+
+    fn make_windowed_payload(
+        cryptde: &'static dyn CryptDE,
+        stream_key: StreamKey,
+        sequence_number: u64,
+    ) -> ClientRequestPayload {
+        ClientRequestPayload {
+            stream_key,
+            sequenced_packet: SequencedPacket::new(vec![1; 100], sequence_number, false),
+            target_hostname: Some(String::from("nowhere.com")),
+            target_port: 80,
+            protocol: ProxyProtocol::HTTP,
+            originator_public_key: cryptde.public_key(),
+            tls_at_exit: false,
+            upload_window: Some(8),
+            udp: false,
+            dns_server: None,
+            cancel: false,
+        }
+    }
+
+    #[test]
+    fn proxy_server_keeps_unacknowledged_requests_only_for_streams_with_an_upload_window() {
+        let cryptde = cryptde();
+        let source_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
+        let stream_key = StreamKey::new(cryptde.public_key(), source_addr);
+        let mut subject = ProxyServer::new(cryptde, false);
+        subject.enable_retransmission(&RetransmissionConfig {
+            memory_limit: 1000,
+            spill_directory_opt: None,
         });
 
-        dispatcher_awaiter.await_message_count(1);
-        let recording = dispatcher_recording_arc.lock().unwrap();
-        let record = recording.get_record::<TransmitDataMsg>(0);
-        let expected_msg = TransmitDataMsg {
-            endpoint: Endpoint::Socket(SocketAddr::from_str("1.2.3.4:5678").unwrap()),
-            last_data: true,
-            sequence_number: Some(0),
-            data: http_server_impersonator::make_error_response(
-                503,
-                "Routing Problem",
-                "Can't find a route to nowhere.com",
-                "Substratum can't find a route through the Network yet to a Node that knows \
-                 where to find nowhere.com. Maybe later enough will be known about the Network to \
-                 find that Node, but we can't guarantee it. We're sorry.",
+        subject
+            .retain_for_retransmission(&make_windowed_payload(cryptde, stream_key, 0), source_addr);
+        assert_eq!(
+            subject.retransmission_buffers.contains_key(&stream_key),
+            false
+        );
+        subject.limit_upload_window(8);
+        (0..3).for_each(|sequence_number| {
+            subject.retain_for_retransmission(
+                &make_windowed_payload(cryptde, stream_key, sequence_number),
+                source_addr,
+            )
+        });
+        subject.release_held_requests(&stream_key, 2);
+
+        let replayed: Vec<u64> = subject.retransmission_buffers[&stream_key]
+            .replay()
+            .unwrap()
+            .into_iter()
+            .map(|(payload, _)| payload.sequenced_packet.sequence_number)
+            .collect();
+        assert_eq!(replayed, vec![2]);
+    }
+
+    fn make_request_payload(
+        cryptde: &'static dyn CryptDE,
+        stream_key: StreamKey,
+        hostname: &str,
+        data: &[u8],
+    ) -> ClientRequestPayload {
+        ClientRequestPayload {
+            sequenced_packet: SequencedPacket::new(data.to_vec(), 0, false),
+            target_hostname: Some(String::from(hostname)),
+            upload_window: None,
+            ..make_windowed_payload(cryptde, stream_key, 0)
+        }
+    }
+
+    #[test]
+    fn proxy_server_keeps_expired_routes_for_destinations_that_keep_connections_alive() {
+        let cryptde = cryptde();
+        let kept_key = StreamKey::new(
+            cryptde.public_key(),
+            SocketAddr::from_str("1.2.3.4:5678").unwrap(),
+        );
+        let dropped_key = StreamKey::new(
+            cryptde.public_key(),
+            SocketAddr::from_str("2.3.4.5:6789").unwrap(),
+        );
+        let mut subject = ProxyServer::new(cryptde, false);
+        let request = b"GET / HTTP/1.1\r\n\r\n";
+        subject.destination_hints.request(&make_request_payload(
+            cryptde,
+            kept_key,
+            "alive.com",
+            request,
+        ));
+        subject.destination_hints.response(&kept_key, 100, false);
+        subject.destination_hints.request(&make_request_payload(
+            cryptde,
+            kept_key,
+            "alive.com",
+            request,
+        ));
+        subject.destination_hints.request(&make_request_payload(
+            cryptde,
+            dropped_key,
+            "closed.com",
+            request,
+        ));
+        let route = zero_hop_route_response(&cryptde.public_key(), cryptde);
+        let expired = Instant::now() - Duration::from_millis(1);
+        subject.routes.insert(kept_key, (route.clone(), expired));
+        subject.routes.insert(dropped_key, (route.clone(), expired));
+
+        assert_eq!(subject.cached_route(&kept_key, false), Some(route));
+        assert_eq!(subject.cached_route(&dropped_key, false), None);
+    }
+
+    #[test]
+    fn proxy_server_sends_streams_to_websocket_destinations_without_an_upload_window() {
+        let system = System::new(
+            "proxy_server_sends_streams_to_websocket_destinations_without_an_upload_window",
+        );
+        let cryptde = cryptde();
+        let (hopper_mock, _, hopper_recording_arc) = make_recorder();
+        let socket_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
+        let stream_key = StreamKey::new(cryptde.public_key(), socket_addr);
+        let earlier_key = StreamKey::new(
+            cryptde.public_key(),
+            SocketAddr::from_str("2.3.4.5:6789").unwrap(),
+        );
+        let mut subject = ProxyServer::new(cryptde, false);
+        subject.limit_upload_window(1);
+        subject.destination_hints.request(&make_request_payload(
+            cryptde,
+            earlier_key,
+            "chat.com",
+            b"GET /live HTTP/1.1\r\nUpgrade: websocket\r\n\r\n",
+        ));
+        subject.keys_and_addrs.insert(stream_key, socket_addr);
+        subject.routes.insert(
+            stream_key,
+            (
+                zero_hop_route_response(&cryptde.public_key(), cryptde),
+                Instant::now() + Duration::from_secs(60),
             ),
+        );
+        let make_request = |sequence_number: u64| InboundClientData {
+            peer_addr: socket_addr,
+            reception_port: Some(80),
+            sequence_number: Some(sequence_number),
+            last_data: false,
+            is_clandestine: false,
+            data: b"GET /index.html HTTP/1.1\r\nHost: chat.com\r\n\r\n".to_vec(),
+            neighbor_key_opt: None,
         };
-        assert_eq!(record, &expected_msg);
-        let recording = neighborhood_recording_arc.lock().unwrap();
-        let record = recording.get_record::<RouteQueryMessage>(0);
-        assert_eq!(record, &RouteQueryMessage::data_indefinite_route_request(2));
-        TestLogHandler::new()
-            .exists_log_containing("ERROR: Proxy Server: Failed to find route to nowhere.com");
+        let subject_addr: Addr<Syn, ProxyServer> = subject.start();
+        let peer_actors = peer_actors_builder().hopper(hopper_mock).build();
+        subject_addr.try_send(BindMessage { peer_actors }).unwrap();
+
+        subject_addr.try_send(make_request(0)).unwrap();
+        subject_addr.try_send(make_request(1)).unwrap();
+        subject_addr.try_send(make_request(2)).unwrap();
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+        let hopper_recording = hopper_recording_arc.lock().unwrap();
+        assert_eq!(hopper_recording.len(), 3);
+        (0..3).for_each(|index| {
+            let icp = hopper_recording.get_record::<IncipientCoresPackage>(index);
+            let payload: ClientRequestPayload = decodex(cryptde, &icp.payload).unwrap();
+            assert_eq!(payload.upload_window, None);
+        });
     }
 
     #[test]
-    fn proxy_server_receives_tls_client_hello_from_dispatcher_then_sends_cores_package_to_hopper() {
-        let tls_request = &[
-            0x16, // content_type: Handshake
-            0x00, 0x00, 0x00, 0x00, // version, length: don't care
-            0x01, // handshake_type: ClientHello
-            0x00, 0x00, 0x00, 0x00, 0x00, // length, version: don't care
-            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-            0x00, 0x00, // random: don't care
-            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-            0x00, 0x00, // random: don't care
-            0x00, // session_id_length
-            0x00, 0x00, // cipher_suites_length
-            0x00, // compression_methods_length
-            0x00, 0x13, // extensions_length
-            0x00, 0x00, // extension_type: server_name
-            0x00, 0x0F, // extension_length
-            0x00, 0x0D, // server_name_list_length
-            0x00, // server_name_type
-            0x00, 0x0A, // server_name_length
-            's' as u8, 'e' as u8, 'r' as u8, 'v' as u8, 'e' as u8, 'r' as u8, '.' as u8, 'c' as u8,
-            'o' as u8, 'm' as u8, // server_name
-        ];
+    fn proxy_server_keeps_streams_with_large_responses_off_each_others_exits() {
         let cryptde = cryptde();
-        let hopper_mock = Recorder::new();
-        let hopper_log_arc = hopper_mock.get_recording();
-        let hopper_awaiter = hopper_mock.get_awaiter();
-        let neighborhood_mock = Recorder::new().route_query_response(Some(
-            zero_hop_route_response(&cryptde.public_key(), cryptde),
-        ));
-        let stream_key = make_meaningless_stream_key();
+        let (neighborhood_mock, neighborhood_awaiter, neighborhood_recording_arc) = make_recorder();
+        let neighborhood_mock = neighborhood_mock
+            .route_query_response(Ok(zero_hop_route_response(&cryptde.public_key(), cryptde)));
         let socket_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
-        let expected_data = tls_request.to_vec();
-        let msg_from_dispatcher = InboundClientData {
-            peer_addr: socket_addr.clone(),
-            reception_port: Some(443),
+        let stream_key = StreamKey::new(cryptde.public_key(), socket_addr);
+        let finished_key = StreamKey::new(
+            cryptde.public_key(),
+            SocketAddr::from_str("2.3.4.5:6789").unwrap(),
+        );
+        let downloading_key = StreamKey::new(
+            cryptde.public_key(),
+            SocketAddr::from_str("3.4.5.6:7890").unwrap(),
+        );
+        let exit_key = PublicKey::new(b"exit");
+        let mut route = zero_hop_route_response(&cryptde.public_key(), cryptde);
+        route.expected_services = ExpectedServices::RoundTrip(
+            vec![
+                ExpectedService::Nothing,
+                ExpectedService::Exit(exit_key.clone(), Wallet::new("exit"), RateCard::default()),
+            ],
+            vec![ExpectedService::Nothing, ExpectedService::Nothing],
+            0,
+        );
+        let request = InboundClientData {
+            peer_addr: socket_addr,
+            reception_port: Some(80),
             sequence_number: Some(0),
             last_data: false,
             is_clandestine: false,
-            data: expected_data.clone(),
-        };
-        let expected_tls_request = PlainData::new(tls_request);
-        let key = cryptde.public_key();
-        let route = zero_hop_route_response(&key, cryptde).route;
-        let expected_payload = ClientRequestPayload {
-            stream_key: stream_key.clone(),
-            sequenced_packet: SequencedPacket {
-                data: expected_tls_request.into(),
-                sequence_number: 0,
-                last_data: false,
-            },
-            target_hostname: Some(String::from("server.com")),
-            target_port: 443,
-            protocol: ProxyProtocol::TLS,
-            originator_public_key: key.clone(),
+            data: b"GET /movie.mp4 HTTP/1.1\r\nHost: big.com\r\n\r\n".to_vec(),
+            neighbor_key_opt: None,
         };
-        let expected_pkg =
-            IncipientCoresPackage::new(cryptde, route.clone(), expected_payload, &key).unwrap();
         thread::spawn(move || {
+            let system = System::new(
+                "proxy_server_keeps_streams_with_large_responses_off_each_others_exits",
+            );
             let mut subject = ProxyServer::new(cryptde, false);
-            subject.stream_key_factory =
-                Box::new(StreamKeyFactoryMock::new().make_result(stream_key.clone()));
-            let system = System::new("proxy_server_receives_tls_client_hello_from_dispatcher_then_sends_cores_package_to_hopper");
+            let get = b"GET /movie.mp4 HTTP/1.1\r\n\r\n";
+            subject.destination_hints.request(&make_request_payload(
+                cryptde,
+                finished_key,
+                "big.com",
+                get,
+            ));
+            subject
+                .destination_hints
+                .response(&finished_key, LARGE_RESPONSE_BYTES as usize, true);
+            subject.destination_hints.request(&make_request_payload(
+                cryptde,
+                downloading_key,
+                "big.com",
+                get,
+            ));
+            subject.routes.insert(
+                downloading_key,
+                (route, Instant::now() + Duration::from_secs(60)),
+            );
+            subject.keys_and_addrs.insert(stream_key, socket_addr);
             let subject_addr: Addr<Syn, ProxyServer> = subject.start();
-            let mut peer_actors = peer_actors_builder()
-                .hopper(hopper_mock)
+            let peer_actors = peer_actors_builder()
                 .neighborhood(neighborhood_mock)
                 .build();
-            peer_actors.proxy_server = ProxyServer::make_subs_from(&subject_addr);
             subject_addr.try_send(BindMessage { peer_actors }).unwrap();
 
-            subject_addr.try_send(msg_from_dispatcher).unwrap();
+            subject_addr.try_send(request).unwrap();
 
             system.run();
         });
 
-        hopper_awaiter.await_message_count(1);
-        let recording = hopper_log_arc.lock().unwrap();
-        let record = recording.get_record::<IncipientCoresPackage>(0);
-        assert_eq!(record, &expected_pkg);
+        neighborhood_awaiter.await_message_count(1);
+        let neighborhood_recording = neighborhood_recording_arc.lock().unwrap();
+        let route_query = neighborhood_recording.get_record::<RouteQueryMessage>(0);
+        assert_eq!(route_query.avoided_exit_keys, vec![exit_key]);
     }
 
     #[test]
-    fn proxy_server_receives_tls_handshake_packet_other_than_client_hello_from_dispatcher_then_sends_cores_package_to_hopper(
-    ) {
-        let tls_request = &[
-            0x16, // content_type: Handshake
-            0x00, 0x00, 0x00, 0x00, // version, length: don't care
-            0x10, // handshake_type: ClientKeyExchange (not important--just not ClientHello)
-            0x00, 0x00, 0x00, // length: 0
-        ];
+    fn proxy_server_complains_once_when_a_stream_can_no_longer_be_moved_to_another_route() {
+        init_test_logging();
         let cryptde = cryptde();
-        let hopper_mock = Recorder::new();
-        let hopper_log_arc = hopper_mock.get_recording();
-        let hopper_awaiter = hopper_mock.get_awaiter();
-        let neighborhood_mock = Recorder::new().route_query_response(Some(
-            zero_hop_route_response(&cryptde.public_key(), cryptde),
+        let source_addr = SocketAddr::from_str("1.2.3.5:5678").unwrap();
+        let stream_key = StreamKey::new(cryptde.public_key(), source_addr);
+        let mut subject = ProxyServer::new(cryptde, false);
+        subject.limit_upload_window(8);
+        subject.enable_retransmission(&RetransmissionConfig {
+            memory_limit: 150,
+            spill_directory_opt: None,
+        });
+
+        (0..3).for_each(|sequence_number| {
+            subject.retain_for_retransmission(
+                &make_windowed_payload(cryptde, stream_key, sequence_number),
+                source_addr,
+            )
+        });
+
+        assert_eq!(
+            subject.retransmission_buffers[&stream_key]
+                .replay()
+                .is_err(),
+            true
+        );
+        TestLogHandler::new().exists_log_containing(&format!(
+            "WARN: Proxy Server: Stream {:?} can no longer be moved to another route: more than 150 bytes went unacknowledged",
+            stream_key
         ));
-        let stream_key = make_meaningless_stream_key();
-        let socket_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
-        let expected_data = tls_request.to_vec();
-        let msg_from_dispatcher = InboundClientData {
-            peer_addr: socket_addr.clone(),
-            reception_port: Some(443),
-            sequence_number: Some(0),
-            last_data: false,
-            is_clandestine: false,
-            data: expected_data.clone(),
-        };
-        let expected_tls_request = PlainData::new(tls_request);
-        let key = cryptde.public_key();
-        let route = zero_hop_route_response(&key, cryptde).route;
-        let expected_payload = ClientRequestPayload {
-            stream_key: stream_key.clone(),
-            sequenced_packet: SequencedPacket {
-                data: expected_tls_request.into(),
-                sequence_number: 0,
-                last_data: false,
-            },
-            target_hostname: None,
-            target_port: 443,
-            protocol: ProxyProtocol::TLS,
-            originator_public_key: key.clone(),
+        TestLogHandler::new().exists_no_log_containing(&format!(
+            "Stream {:?} can no longer be moved to another route: more than 8 packets",
+            stream_key
+        ));
+    }
+
+    #[test]
+    fn enable_retransmission_clears_out_what_a_previous_run_spilled() {
+        let spill_directory = PathBuf::from(
+            "generated/test/proxy_server/enable_retransmission_clears_out_what_a_previous_run_spilled/retransmission",
+        );
+        fs::create_dir_all(&spill_directory).unwrap();
+        fs::write(spill_directory.join("stream-1.cbor"), "leftovers").unwrap();
+        let config = RetransmissionConfig {
+            memory_limit: 1000,
+            spill_directory_opt: Some(spill_directory.clone()),
         };
-        let expected_pkg =
-            IncipientCoresPackage::new(cryptde, route.clone(), expected_payload, &key).unwrap();
-        thread::spawn(move || {
-            let mut subject = ProxyServer::new(cryptde, false);
-            subject.stream_key_factory =
-                Box::new(StreamKeyFactoryMock::new().make_result(stream_key.clone()));
-            let system = System::new("proxy_server_receives_tls_client_hello_from_dispatcher_then_sends_cores_package_to_hopper");
-            let subject_addr: Addr<Syn, ProxyServer> = subject.start();
-            let mut peer_actors = peer_actors_builder()
-                .hopper(hopper_mock)
-                .neighborhood(neighborhood_mock)
-                .build();
-            peer_actors.proxy_server = ProxyServer::make_subs_from(&subject_addr);
-            subject_addr.try_send(BindMessage { peer_actors }).unwrap();
+        let mut subject = ProxyServer::new(cryptde(), false);
 
-            subject_addr.try_send(msg_from_dispatcher).unwrap();
+        subject.enable_retransmission(&config);
 
-            system.run();
-        });
+        assert_eq!(subject.retransmission_config, Some(config));
+        assert_eq!(spill_directory.is_dir(), true);
+        assert_eq!(spill_directory.join("stream-1.cbor").exists(), false);
+    }
 
-        hopper_awaiter.await_message_count(1);
-        let recording = hopper_log_arc.lock().unwrap();
-        let record = recording.get_record::<IncipientCoresPackage>(0);
-        assert_eq!(record, &expected_pkg);
+    fn make_truncated_response(
+        cryptde: &'static dyn CryptDE,
+        stream_key: StreamKey,
+        sequence_number: u64,
+    ) -> ExpiredCoresPackage {
+        ExpiredCoresPackage::new(
+            IpAddr::from_str("1.2.3.4").unwrap(),
+            Some(Wallet::new("irrelevant")),
+            return_route_with_id(cryptde, 1234),
+            encodex(
+                cryptde,
+                &cryptde.public_key(),
+                &ClientResponsePayload {
+                    stream_key,
+                    sequenced_packet: SequencedPacket::new(vec![], sequence_number, true),
+                    requests_received: None,
+                    truncated: true,
+                    udp: false,
+                    exit_failed: false,
+                },
+            )
+            .unwrap(),
+        )
     }
 
     #[test]
-    fn proxy_server_receives_tls_packet_other_than_handshake_from_dispatcher_then_sends_cores_package_to_hopper(
-    ) {
-        let tls_request = &[
-            0xFF, // content_type: don't care, just not Handshake
-            0x00, 0x00, 0x00, 0x00, // version, length: don't care
-        ];
+    fn proxy_server_retries_a_get_over_a_fresh_route_when_the_server_resets_before_responding() {
+        init_test_logging();
         let cryptde = cryptde();
         let hopper_mock = Recorder::new();
-        let hopper_log_arc = hopper_mock.get_recording();
+        let hopper_recording_arc = hopper_mock.get_recording();
         let hopper_awaiter = hopper_mock.get_awaiter();
-        let neighborhood_mock = Recorder::new().route_query_response(Some(
-            zero_hop_route_response(&cryptde.public_key(), cryptde),
-        ));
-        let stream_key = make_meaningless_stream_key();
+        let (dispatcher_mock, _, dispatcher_recording_arc) = make_recorder();
+        let (neighborhood_mock, _, neighborhood_recording_arc) = make_recorder();
+        let neighborhood_mock = neighborhood_mock
+            .route_query_response(Ok(zero_hop_route_response(&cryptde.public_key(), cryptde)));
         let socket_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
-        let expected_data = tls_request.to_vec();
-        let msg_from_dispatcher = InboundClientData {
-            peer_addr: socket_addr.clone(),
-            reception_port: Some(443),
+        let stream_key = StreamKey::new(cryptde.public_key(), socket_addr);
+        let request = InboundClientData {
+            peer_addr: socket_addr,
+            reception_port: Some(80),
             sequence_number: Some(0),
-            last_data: true,
+            last_data: false,
             is_clandestine: false,
-            data: expected_data.clone(),
-        };
-        let expected_tls_request = PlainData::new(tls_request);
-        let key = cryptde.public_key();
-        let route = zero_hop_route_response(&key, cryptde).route;
-        let expected_payload = ClientRequestPayload {
-            stream_key: stream_key.clone(),
-            sequenced_packet: SequencedPacket {
-                data: expected_tls_request.into(),
-                sequence_number: 0,
-                last_data: true,
-            },
-            target_hostname: None,
-            target_port: 443,
-            protocol: ProxyProtocol::TLS,
-            originator_public_key: key.clone(),
+            data: b"GET /index.html HTTP/1.1\r\nHost: nowhere.com\r\n\r\n".to_vec(),
+            neighbor_key_opt: None,
         };
-        let expected_pkg =
-            IncipientCoresPackage::new(cryptde, route.clone(), expected_payload, &key).unwrap();
         thread::spawn(move || {
+            let system = System::new(
+                "proxy_server_retries_a_get_over_a_fresh_route_when_the_server_resets_before_responding",
+            );
             let mut subject = ProxyServer::new(cryptde, false);
-            subject.stream_key_factory =
-                Box::new(StreamKeyFactoryMock::new().make_result(stream_key.clone()));
-            let system = System::new("proxy_server_receives_tls_client_hello_from_dispatcher_then_sends_cores_package_to_hopper");
+            subject.keys_and_addrs.insert(stream_key, socket_addr);
+            subject.routes.insert(
+                stream_key,
+                (
+                    zero_hop_route_response(&cryptde.public_key(), cryptde),
+                    Instant::now() + Duration::from_secs(60),
+                ),
+            );
+            subject
+                .route_ids_to_services
+                .insert(1234, vec![ExpectedService::Nothing]);
             let subject_addr: Addr<Syn, ProxyServer> = subject.start();
             let mut peer_actors = peer_actors_builder()
                 .hopper(hopper_mock)
+                .dispatcher(dispatcher_mock)
                 .neighborhood(neighborhood_mock)
                 .build();
             peer_actors.proxy_server = ProxyServer::make_subs_from(&subject_addr);
             subject_addr.try_send(BindMessage { peer_actors }).unwrap();
 
-            subject_addr.try_send(msg_from_dispatcher).unwrap();
+            subject_addr.try_send(request).unwrap();
+            subject_addr
+                .try_send(make_truncated_response(cryptde, stream_key, 0))
+                .unwrap();
 
             system.run();
         });
 
-        hopper_awaiter.await_message_count(1);
-        let recording = hopper_log_arc.lock().unwrap();
-        let record = recording.get_record::<IncipientCoresPackage>(0);
-        assert_eq!(record, &expected_pkg);
+        hopper_awaiter.await_message_count(2);
+        let hopper_recording = hopper_recording_arc.lock().unwrap();
+        let payloads: Vec<ClientRequestPayload> = (0..2)
+            .map(|index| {
+                let icp = hopper_recording.get_record::<IncipientCoresPackage>(index);
+                decodex(cryptde, &icp.payload).unwrap()
+            })
+            .collect();
+        assert_eq!(payloads[0].sequenced_packet.sequence_number, 0);
+        assert_eq!(payloads[1], payloads[0]);
+        let neighborhood_recording = neighborhood_recording_arc.lock().unwrap();
+        assert_eq!(neighborhood_recording.len(), 1);
+        let dispatcher_recording = dispatcher_recording_arc.lock().unwrap();
+        assert_eq!(dispatcher_recording.len(), 0);
+        TestLogHandler::new().exists_log_containing(&format!(
+            "WARN: Proxy Server: Server reset stream {:?} before responding; retrying the request over a fresh route",
+            stream_key
+        ));
     }
 
     #[test]
-    fn proxy_server_receives_tls_client_hello_from_dispatcher_but_neighborhood_cant_make_route() {
+    fn proxy_server_retries_a_stalled_get_around_its_exit_and_then_gives_up() {
         init_test_logging();
         let cryptde = cryptde();
-        let tls_request = [
-            0x16, // content_type: Handshake
-            0x00, 0x00, 0x00, 0x00, // version, length: don't care
-            0x01, // handshake_type: ClientHello
-            0x00, 0x00, 0x00, 0x00, 0x00, // length, version: don't care
-            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-            0x00, 0x00, // random: don't care
-            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-            0x00, 0x00, // random: don't care
-            0x00, // session_id_length
-            0x00, 0x00, // cipher_suites_length
-            0x00, // compression_methods_length
-            0x00, 0x13, // extensions_length
-            0x00, 0x00, // extension_type: server_name
-            0x00, 0x0F, // extension_length
-            0x00, 0x0D, // server_name_list_length
-            0x00, // server_name_type
-            0x00, 0x0A, // server_name_length
-            's' as u8, 'e' as u8, 'r' as u8, 'v' as u8, 'e' as u8, 'r' as u8, '.' as u8, 'c' as u8,
-            'o' as u8, 'm' as u8, // server_name
-        ]
-        .to_vec();
-        let dispatcher = Recorder::new();
-        let dispatcher_awaiter = dispatcher.get_awaiter();
-        let dispatcher_recording_arc = dispatcher.get_recording();
-        let neighborhood = Recorder::new().route_query_response(None);
+        let hopper_mock = Recorder::new();
+        let hopper_recording_arc = hopper_mock.get_recording();
+        let dispatcher_mock = Recorder::new();
+        let dispatcher_recording_arc = dispatcher_mock.get_recording();
+        let dispatcher_awaiter = dispatcher_mock.get_awaiter();
+        let (neighborhood_mock, _, neighborhood_recording_arc) = make_recorder();
+        let neighborhood_mock = neighborhood_mock
+            .route_query_response(Ok(zero_hop_route_response(&cryptde.public_key(), cryptde)));
         let socket_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
-        let msg_from_dispatcher = InboundClientData {
-            peer_addr: socket_addr.clone(),
-            reception_port: Some(443),
+        let stream_key = StreamKey::new(cryptde.public_key(), socket_addr);
+        let exit_key = PublicKey::new(b"exit");
+        let mut route = zero_hop_route_response(&cryptde.public_key(), cryptde);
+        route.expected_services = ExpectedServices::RoundTrip(
+            vec![
+                ExpectedService::Nothing,
+                ExpectedService::Exit(exit_key.clone(), Wallet::new("exit"), RateCard::default()),
+            ],
+            vec![ExpectedService::Nothing, ExpectedService::Nothing],
+            0,
+        );
+        let request = InboundClientData {
+            peer_addr: socket_addr,
+            reception_port: Some(80),
             sequence_number: Some(0),
-            last_data: true,
-            data: tls_request,
+            last_data: false,
             is_clandestine: false,
+            data: b"GET /index.html HTTP/1.1\r\nHost: nowhere.com\r\n\r\n".to_vec(),
+            neighbor_key_opt: None,
         };
         thread::spawn(move || {
-            let system = System::new("proxy_server_receives_tls_client_hello_from_dispatcher_but_neighborhood_cant_make_route");
-            let subject = ProxyServer::new(cryptde, false);
+            let system =
+                System::new("proxy_server_retries_a_stalled_get_around_its_exit_and_then_gives_up");
+            let mut subject = ProxyServer::new(cryptde, false);
+            subject.stalled_request_timeout = Duration::from_millis(100);
+            subject.keys_and_addrs.insert(stream_key, socket_addr);
+            subject.routes.insert(
+                stream_key,
+                (route, Instant::now() + Duration::from_secs(60)),
+            );
             let subject_addr: Addr<Syn, ProxyServer> = subject.start();
             let mut peer_actors = peer_actors_builder()
-                .dispatcher(dispatcher)
-                .neighborhood(neighborhood)
+                .hopper(hopper_mock)
+                .dispatcher(dispatcher_mock)
+                .neighborhood(neighborhood_mock)
                 .build();
             peer_actors.proxy_server = ProxyServer::make_subs_from(&subject_addr);
             subject_addr.try_send(BindMessage { peer_actors }).unwrap();
 
-            subject_addr.try_send(msg_from_dispatcher).unwrap();
+            subject_addr.try_send(request).unwrap();
 
             system.run();
         });
+
         dispatcher_awaiter.await_message_count(1);
-        let recording = dispatcher_recording_arc.lock().unwrap();
-        let record = recording.get_record::<TransmitDataMsg>(0);
-        let expected_msg = TransmitDataMsg {
-            endpoint: Endpoint::Socket(SocketAddr::from_str("1.2.3.4:5678").unwrap()),
-            last_data: true,
+        let hopper_recording = hopper_recording_arc.lock().unwrap();
+        assert_eq!(hopper_recording.len(), 2);
+        let neighborhood_recording = neighborhood_recording_arc.lock().unwrap();
+        let route_feedback = neighborhood_recording.get_record::<RouteFeedbackMessage>(0);
+        assert_eq!(
+            route_feedback,
+            &RouteFeedbackMessage {
+                keys: vec![exit_key.clone()],
+                outcome: RouteOutcome::Failed,
+            }
+        );
+        assert_eq!(
+            neighborhood_recording.get_record::<NodeProblemReport>(1),
+            &NodeProblemReport {
+                public_key: exit_key.clone(),
+                problem: NodeProblem::StreamDied,
+            }
+        );
+        let route_query = neighborhood_recording.get_record::<RouteQueryMessage>(2);
+        assert_eq!(route_query.avoided_exit_keys, vec![exit_key]);
+        assert_eq!(neighborhood_recording.len(), 3);
+        let dispatcher_recording = dispatcher_recording_arc.lock().unwrap();
+        let record = dispatcher_recording.get_record::<TransmitDataMsg>(0);
+        assert_eq!(record.endpoint, Endpoint::Socket(socket_addr));
+        assert_eq!(record.last_data, true);
+        assert_eq!(record.data.starts_with(b"HTTP/1.1 504 "), true);
+        let tlh = TestLogHandler::new();
+        tlh.exists_log_containing(&format!(
+            "WARN: Proxy Server: No response on stream {:?} after 100ms; retrying the request over a different route",
+            stream_key
+        ));
+        tlh.exists_log_containing(&format!(
+            "ERROR: Proxy Server: No response on stream {:?} from nowhere.com even after retrying; giving up",
+            stream_key
+        ));
+    }
+
+    #[test]
+    fn proxy_server_passes_on_a_truncated_response_once_part_of_it_has_gone_to_the_browser() {
+        init_test_logging();
+        let system = System::new(
+            "proxy_server_passes_on_a_truncated_response_once_part_of_it_has_gone_to_the_browser",
+        );
+        let cryptde = cryptde();
+        let (hopper_mock, _, hopper_recording_arc) = make_recorder();
+        let (dispatcher_mock, _, dispatcher_recording_arc) = make_recorder();
+        let socket_addr = SocketAddr::from_str("2.3.4.5:6789").unwrap();
+        let stream_key = StreamKey::new(cryptde.public_key(), socket_addr);
+        let mut subject = ProxyServer::new(cryptde, false);
+        subject.keys_and_addrs.insert(stream_key, socket_addr);
+        subject.routes.insert(
+            stream_key,
+            (
+                zero_hop_route_response(&cryptde.public_key(), cryptde),
+                Instant::now() + Duration::from_secs(60),
+            ),
+        );
+        subject
+            .route_ids_to_services
+            .insert(1234, vec![ExpectedService::Nothing]);
+        let request = InboundClientData {
+            peer_addr: socket_addr,
+            reception_port: Some(80),
             sequence_number: Some(0),
-            data: vec![],
+            last_data: false,
+            is_clandestine: false,
+            data: b"GET /index.html HTTP/1.1\r\nHost: nowhere.com\r\n\r\n".to_vec(),
+            neighbor_key_opt: None,
         };
-        assert_eq!(record, &expected_msg);
+        let subject_addr: Addr<Syn, ProxyServer> = subject.start();
+        let peer_actors = peer_actors_builder()
+            .hopper(hopper_mock)
+            .dispatcher(dispatcher_mock)
+            .build();
+        subject_addr.try_send(BindMessage { peer_actors }).unwrap();
 
-        TestLogHandler::new()
-            .exists_log_containing("ERROR: Proxy Server: Failed to find route to server.com");
+        subject_addr.try_send(request).unwrap();
+        subject_addr
+            .try_send(make_truncated_response(cryptde, stream_key, 3))
+            .unwrap();
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+        let hopper_recording = hopper_recording_arc.lock().unwrap();
+        assert_eq!(hopper_recording.len(), 1);
+        let dispatcher_recording = dispatcher_recording_arc.lock().unwrap();
+        let record = dispatcher_recording.get_record::<TransmitDataMsg>(0);
+        assert_eq!(record.endpoint, Endpoint::Socket(socket_addr));
+        assert_eq!(record.last_data, true);
+        assert_eq!(record.sequence_number, Some(3));
+        TestLogHandler::new().exists_log_containing(&format!(
+            "WARN: Proxy Server: Server reset stream {:?} after 3 response packets; the response may be incomplete",
+            stream_key
+        ));
     }
 
     #[test]
-    fn proxy_server_receives_terminal_response_from_hopper() {
+    fn proxy_server_does_not_retry_a_post_when_the_server_resets_before_responding() {
         init_test_logging();
-        let system = System::new("proxy_server_receives_response_from_hopper");
-        let (dispatcher_mock, _, dispatcher_log_arc) = make_recorder();
+        let system = System::new(
+            "proxy_server_does_not_retry_a_post_when_the_server_resets_before_responding",
+        );
         let cryptde = cryptde();
+        let (hopper_mock, _, hopper_recording_arc) = make_recorder();
+        let (dispatcher_mock, _, dispatcher_recording_arc) = make_recorder();
+        let socket_addr = SocketAddr::from_str("3.4.5.6:7890").unwrap();
+        let stream_key = StreamKey::new(cryptde.public_key(), socket_addr);
         let mut subject = ProxyServer::new(cryptde, false);
-        let socket_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
-        let stream_key = make_meaningless_stream_key();
-        subject
-            .keys_and_addrs
-            .insert(stream_key.clone(), socket_addr.clone());
+        subject.keys_and_addrs.insert(stream_key, socket_addr);
+        subject.routes.insert(
+            stream_key,
+            (
+                zero_hop_route_response(&cryptde.public_key(), cryptde),
+                Instant::now() + Duration::from_secs(60),
+            ),
+        );
         subject
             .route_ids_to_services
             .insert(1234, vec![ExpectedService::Nothing]);
-        let subject_addr: Addr<Syn, ProxyServer> = subject.start();
-        let remaining_route = return_route_with_id(cryptde, 1234);
-        let client_response_payload = ClientResponsePayload {
-            stream_key: stream_key.clone(),
-            sequenced_packet: SequencedPacket {
-                data: b"16 bytes of data".to_vec(),
-                sequence_number: 12345678,
-                last_data: true,
-            },
+        let request = InboundClientData {
+            peer_addr: socket_addr,
+            reception_port: Some(80),
+            sequence_number: Some(0),
+            last_data: false,
+            is_clandestine: false,
+            data: b"POST /form HTTP/1.1\r\nHost: nowhere.com\r\n\r\nname=value".to_vec(),
+            neighbor_key_opt: None,
         };
-        let first_expired_cores_package = ExpiredCoresPackage::new(
-            IpAddr::from_str("1.2.3.4").unwrap(),
-            Some(Wallet::new("consuming")),
-            remaining_route,
-            encodex(cryptde, &cryptde.public_key(), &client_response_payload).unwrap(),
-        );
-        let second_expired_cores_package = first_expired_cores_package.clone();
-        let mut peer_actors = peer_actors_builder().dispatcher(dispatcher_mock).build();
-        peer_actors.proxy_server = ProxyServer::make_subs_from(&subject_addr);
+        let subject_addr: Addr<Syn, ProxyServer> = subject.start();
+        let peer_actors = peer_actors_builder()
+            .hopper(hopper_mock)
+            .dispatcher(dispatcher_mock)
+            .build();
         subject_addr.try_send(BindMessage { peer_actors }).unwrap();
 
-        subject_addr.try_send(first_expired_cores_package).unwrap();
-        subject_addr.try_send(second_expired_cores_package).unwrap(); // should generate log because stream key is now unknown
+        subject_addr.try_send(request).unwrap();
+        subject_addr
+            .try_send(make_truncated_response(cryptde, stream_key, 0))
+            .unwrap();
 
         Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
         system.run();
-
-        let recording = dispatcher_log_arc.lock().unwrap();
-        let record = recording.get_record::<TransmitDataMsg>(0);
-        assert_eq!(record.endpoint, Endpoint::Socket(socket_addr));
+        let hopper_recording = hopper_recording_arc.lock().unwrap();
+        assert_eq!(hopper_recording.len(), 1);
+        let dispatcher_recording = dispatcher_recording_arc.lock().unwrap();
+        let record = dispatcher_recording.get_record::<TransmitDataMsg>(0);
         assert_eq!(record.last_data, true);
-        assert_eq!(record.data, b"16 bytes of data".to_vec());
-        TestLogHandler::new ().exists_log_containing (&format!("ERROR: Proxy Server: Discarding 16-byte packet 12345678 from an unrecognized stream key: {:?}", stream_key));
+        assert_eq!(record.data, Vec::<u8>::new());
+        TestLogHandler::new().exists_log_containing(&format!(
+            "WARN: Proxy Server: Server reset stream {:?} after 0 response packets; the response may be incomplete",
+            stream_key
+        ));
     }
 
     #[test]
-    fn proxy_server_receives_nonterminal_response_from_hopper() {
-        let system = System::new("proxy_server_receives_response_from_hopper");
-        let (dispatcher_mock, _, dispatcher_log_arc) = make_recorder();
-        let (accountant, _, accountant_recording_arc) = make_recorder();
+    fn acknowledgement_only_responses_pay_the_relays_but_not_the_exit() {
+        let system = System::new("acknowledgement_only_responses_pay_the_relays_but_not_the_exit");
         let cryptde = cryptde();
-        let mut subject = ProxyServer::new(cryptde, false);
+        let (dispatcher_mock, _, dispatcher_recording_arc) = make_recorder();
+        let (accountant, _, accountant_recording_arc) = make_recorder();
         let socket_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
         let stream_key = make_meaningless_stream_key();
         let irrelevant_public_key = PublicKey::from(&b"irrelevant"[..]);
-        subject
-            .keys_and_addrs
-            .insert(stream_key.clone(), socket_addr.clone());
-        let incoming_route_d_wallet = Wallet::new("D Earning");
-        let incoming_route_e_wallet = Wallet::new("E Earning");
-        let incoming_route_f_wallet = Wallet::new("F Earning");
+        let mut subject = ProxyServer::new(cryptde, false);
+        subject.keys_and_addrs.insert(stream_key, socket_addr);
         subject.route_ids_to_services.insert(
             1234,
             vec![
                 ExpectedService::Exit(
                     irrelevant_public_key.clone(),
-                    incoming_route_d_wallet.clone(),
-                ),
-                ExpectedService::Routing(
-                    irrelevant_public_key.clone(),
-                    incoming_route_e_wallet.clone(),
-                ),
-                ExpectedService::Routing(
-                    irrelevant_public_key.clone(),
-                    incoming_route_f_wallet.clone(),
-                ),
-                ExpectedService::Nothing,
-            ],
-        );
-        let incoming_route_g_wallet = Wallet::new("G Earning");
-        let incoming_route_h_wallet = Wallet::new("H Earning");
-        let incoming_route_i_wallet = Wallet::new("I Earning");
-        subject.route_ids_to_services.insert(
-            1235,
-            vec![
-                ExpectedService::Exit(
-                    irrelevant_public_key.clone(),
-                    incoming_route_g_wallet.clone(),
-                ),
-                ExpectedService::Routing(
-                    irrelevant_public_key.clone(),
-                    incoming_route_h_wallet.clone(),
+                    Wallet::new("exit"),
+                    RateCard::default(),
                 ),
                 ExpectedService::Routing(
-                    irrelevant_public_key.clone(),
-                    incoming_route_i_wallet.clone(),
+                    irrelevant_public_key,
+                    Wallet::new("relay"),
+                    RateCard::default(),
                 ),
                 ExpectedService::Nothing,
             ],
         );
-        let subject_addr: Addr<Syn, ProxyServer> = subject.start();
-        let first_client_response_payload = ClientResponsePayload {
-            stream_key,
-            sequenced_packet: SequencedPacket {
-                data: b"some data".to_vec(),
-                sequence_number: 4321,
-                last_data: false,
-            },
-        };
-        let first_expired_cores_package = ExpiredCoresPackage::new(
+        let expired_cores_package = ExpiredCoresPackage::new(
             IpAddr::from_str("1.2.3.4").unwrap(),
             Some(Wallet::new("irrelevant")),
             return_route_with_id(cryptde, 1234),
             encodex(
                 cryptde,
                 &cryptde.public_key(),
-                &first_client_response_payload,
-            )
-            .unwrap(),
-        );
-        let second_client_response_payload = ClientResponsePayload {
-            stream_key,
-            sequenced_packet: SequencedPacket {
-                data: b"other data".to_vec(),
-                sequence_number: 4322,
-                last_data: false,
-            },
-        };
-        let second_expired_cores_package = ExpiredCoresPackage::new(
-            IpAddr::from_str("1.2.3.5").unwrap(),
-            Some(Wallet::new("irrelevant")),
-            return_route_with_id(cryptde, 1235),
-            encodex(
-                cryptde,
-                &cryptde.public_key(),
-                &second_client_response_payload,
+                &ClientResponsePayload::make_acknowledgement_payload(stream_key, 5),
             )
             .unwrap(),
         );
-        let mut peer_actors = peer_actors_builder()
+        let routing_size = expired_cores_package.payload.len();
+        let subject_addr: Addr<Syn, ProxyServer> = subject.start();
+        let peer_actors = peer_actors_builder()
             .dispatcher(dispatcher_mock)
             .accountant(accountant)
             .build();
-        peer_actors.proxy_server = ProxyServer::make_subs_from(&subject_addr);
         subject_addr.try_send(BindMessage { peer_actors }).unwrap();
 
-        subject_addr
-            .try_send(first_expired_cores_package.clone())
-            .unwrap();
-        subject_addr
-            .try_send(second_expired_cores_package.clone())
-            .unwrap();
+        subject_addr.try_send(expired_cores_package).unwrap();
 
         Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
         system.run();
-
-        let dispatcher_recording = dispatcher_log_arc.lock().unwrap();
-        let record = dispatcher_recording.get_record::<TransmitDataMsg>(0);
-        assert_eq!(record.endpoint, Endpoint::Socket(socket_addr));
-        assert_eq!(record.last_data, false);
-        assert_eq!(record.data, b"some data".to_vec());
-        let record = dispatcher_recording.get_record::<TransmitDataMsg>(1);
-        assert_eq!(record.endpoint, Endpoint::Socket(socket_addr));
-        assert_eq!(record.last_data, false);
-        assert_eq!(record.data, b"other data".to_vec());
         let accountant_recording = accountant_recording_arc.lock().unwrap();
-        let exit_size = first_client_response_payload.sequenced_packet.data.len();
-        let routing_size = first_expired_cores_package.payload.len();
-        check_exit_report(
-            &accountant_recording,
-            0,
-            &incoming_route_d_wallet,
-            exit_size,
-        );
-        check_routing_report(
-            &accountant_recording,
-            1,
-            &incoming_route_e_wallet,
-            routing_size,
-        );
-        check_routing_report(
-            &accountant_recording,
-            2,
-            &incoming_route_f_wallet,
-            routing_size,
-        );
-        let exit_size = second_client_response_payload.sequenced_packet.data.len();
-        let routing_size = second_expired_cores_package.payload.len();
-        check_exit_report(
-            &accountant_recording,
-            3,
-            &incoming_route_g_wallet,
-            exit_size,
-        );
-        check_routing_report(
-            &accountant_recording,
-            4,
-            &incoming_route_h_wallet,
-            routing_size,
-        );
-        check_routing_report(
-            &accountant_recording,
-            5,
-            &incoming_route_i_wallet,
-            routing_size,
+        assert_eq!(
+            accountant_recording.get_record::<ReportRoutingServiceConsumedMessage>(0),
+            &ReportRoutingServiceConsumedMessage {
+                earning_wallet: Wallet::new("relay"),
+                payload_size: routing_size,
+                service_rate: DEFAULT_PER_ROUTING_RATE,
+                byte_rate: DEFAULT_PER_ROUTING_BYTE_RATE,
+                correlation_id_opt: None,
+            }
         );
-        assert_eq!(accountant_recording.len(), 6);
+        assert_eq!(accountant_recording.len(), 1);
+        let dispatcher_recording = dispatcher_recording_arc.lock().unwrap();
+        assert_eq!(dispatcher_recording.len(), 0);
+    }
+
+    fn socks_connect_request(host: &str, port: u16) -> Vec<u8> {
+        let mut request = vec![0x05, 0x01, 0x00, 0x03, host.len() as u8];
+        request.extend(host.as_bytes());
+        request.extend(&[(port >> 8) as u8, (port & 0xFF) as u8]);
+        request
+    }
+
+    fn make_socks_msg(
+        peer_addr: SocketAddr,
+        sequence_number: u64,
+        data: Vec<u8>,
+    ) -> InboundClientData {
+        InboundClientData {
+            peer_addr,
+            reception_port: Some(1080),
+            sequence_number: Some(sequence_number),
+            last_data: false,
+            is_clandestine: false,
+            data,
+            neighbor_key_opt: None,
+        }
     }
 
     #[test]
-    #[should_panic(expected = "Dispatcher unbound in ProxyServer")]
-    fn panics_if_dispatcher_is_unbound() {
-        let system = System::new("panics_if_dispatcher_is_unbound");
+    fn proxy_server_sends_socks_data_to_the_target_named_in_the_handshake() {
+        let system =
+            System::new("proxy_server_sends_socks_data_to_the_target_named_in_the_handshake");
         let cryptde = cryptde();
-        let socket_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
+        let (hopper_mock, _, hopper_recording_arc) = make_recorder();
+        let (dispatcher_mock, _, dispatcher_recording_arc) = make_recorder();
+        let socket_addr = SocketAddr::from_str("127.0.0.1:5678").unwrap();
         let stream_key = make_meaningless_stream_key();
+        let request = b"GET /index.html HTTP/1.1\r\n\r\n".to_vec();
         let mut subject = ProxyServer::new(cryptde, false);
-        subject
-            .keys_and_addrs
-            .insert(stream_key.clone(), socket_addr.clone());
-        let remaining_route = return_route_with_id(cryptde, 4321);
-        subject
-            .route_ids_to_services
-            .insert(4321, vec![ExpectedService::Nothing]);
-        let subject_addr: Addr<Syn, ProxyServer> = subject.start();
-
-        let client_response_payload = ClientResponsePayload {
+        subject.accept_socks_on(1080);
+        subject.stream_key_factory = Box::new(StreamKeyFactoryMock::new().make_result(stream_key));
+        subject.routes.insert(
             stream_key,
-            sequenced_packet: SequencedPacket {
-                data: b"data".to_vec(),
-                sequence_number: 0,
-                last_data: true,
-            },
-        };
-        let expired_cores_package = ExpiredCoresPackage::new(
-            IpAddr::from_str("1.2.3.4").unwrap(),
-            Some(Wallet::new("consuming")),
-            remaining_route,
-            encodex(cryptde, &cryptde.public_key(), &client_response_payload).unwrap(),
+            (
+                zero_hop_route_response(&cryptde.public_key(), cryptde),
+                Instant::now() + Duration::from_secs(60),
+            ),
         );
+        let subject_addr: Addr<Syn, ProxyServer> = subject.start();
+        let peer_actors = peer_actors_builder()
+            .hopper(hopper_mock)
+            .dispatcher(dispatcher_mock)
+            .build();
+        subject_addr.try_send(BindMessage { peer_actors }).unwrap();
 
-        subject_addr.try_send(expired_cores_package).unwrap();
+        subject_addr
+            .try_send(make_socks_msg(socket_addr, 0, vec![0x05, 0x01, 0x00]))
+            .unwrap();
+        subject_addr
+            .try_send(make_socks_msg(
+                socket_addr,
+                1,
+                socks_connect_request("nowhere.com", 80),
+            ))
+            .unwrap();
+        subject_addr
+            .try_send(make_socks_msg(socket_addr, 2, request.clone()))
+            .unwrap();
 
         Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
         system.run();
+        let dispatcher_recording = dispatcher_recording_arc.lock().unwrap();
+        assert_eq!(
+            dispatcher_recording.get_record::<TransmitDataMsg>(0),
+            &TransmitDataMsg {
+                endpoint: Endpoint::Socket(socket_addr),
+                last_data: false,
+                sequence_number: Some(0),
+                data: vec![0x05, 0x00],
+            }
+        );
+        assert_eq!(
+            dispatcher_recording.get_record::<TransmitDataMsg>(1),
+            &TransmitDataMsg {
+                endpoint: Endpoint::Socket(socket_addr),
+                last_data: false,
+                sequence_number: Some(1),
+                data: vec![0x05, 0x00, 0x00, 0x01, 0, 0, 0, 0, 0, 0],
+            }
+        );
+        assert_eq!(dispatcher_recording.len(), 2);
+        let hopper_recording = hopper_recording_arc.lock().unwrap();
+        let icp = hopper_recording.get_record::<IncipientCoresPackage>(0);
+        let payload: ClientRequestPayload = decodex(cryptde, &icp.payload).unwrap();
+        assert_eq!(payload.stream_key, stream_key);
+        assert_eq!(payload.target_hostname, Some(String::from("nowhere.com")));
+        assert_eq!(payload.target_port, 80);
+        assert_eq!(payload.protocol, ProxyProtocol::HTTP);
+        assert_eq!(
+            payload.sequenced_packet,
+            SequencedPacket {
+                data: request,
+                sequence_number: 0,
+                last_data: false,
+            }
+        );
+        assert_eq!(hopper_recording.len(), 1);
     }
 
     #[test]
-    #[should_panic(expected = "Neighborhood unbound in ProxyServer")]
-    fn panics_if_hopper_is_unbound() {
-        let system = System::new("panics_if_hopper_is_unbound");
-        let http_request = b"GET /index.html HTTP/1.1\r\nHost: nowhere.com\r\n\r\n";
-        let subject = ProxyServer::new(cryptde(), false);
-        let socket_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
-        let expected_data = http_request.to_vec();
-        let msg_from_dispatcher = InboundClientData {
-            peer_addr: socket_addr.clone(),
-            reception_port: Some(53),
-            sequence_number: Some(0),
-            last_data: false,
-            is_clandestine: false,
-            data: expected_data.clone(),
-        };
+    fn proxy_server_refuses_socks_targets_on_ports_without_a_protocol_pack() {
+        let system =
+            System::new("proxy_server_refuses_socks_targets_on_ports_without_a_protocol_pack");
+        let cryptde = cryptde();
+        let (hopper_mock, _, hopper_recording_arc) = make_recorder();
+        let (dispatcher_mock, _, dispatcher_recording_arc) = make_recorder();
+        let socket_addr = SocketAddr::from_str("127.0.0.1:5678").unwrap();
+        let mut subject = ProxyServer::new(cryptde, false);
+        subject.accept_socks_on(1080);
         let subject_addr: Addr<Syn, ProxyServer> = subject.start();
+        let peer_actors = peer_actors_builder()
+            .hopper(hopper_mock)
+            .dispatcher(dispatcher_mock)
+            .build();
+        subject_addr.try_send(BindMessage { peer_actors }).unwrap();
 
-        subject_addr.try_send(msg_from_dispatcher).unwrap();
+        subject_addr
+            .try_send(make_socks_msg(socket_addr, 0, vec![0x05, 0x01, 0x00]))
+            .unwrap();
+        subject_addr
+            .try_send(make_socks_msg(
+                socket_addr,
+                1,
+                socks_connect_request("nowhere.com", 6881),
+            ))
+            .unwrap();
+        subject_addr
+            .try_send(make_socks_msg(socket_addr, 2, b"booga".to_vec()))
+            .unwrap();
 
         Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
         system.run();
+        let dispatcher_recording = dispatcher_recording_arc.lock().unwrap();
+        assert_eq!(
+            dispatcher_recording.get_record::<TransmitDataMsg>(1),
+            &TransmitDataMsg {
+                endpoint: Endpoint::Socket(socket_addr),
+                last_data: true,
+                sequence_number: Some(1),
+                data: vec![0x05, 0x02, 0x00, 0x01, 0, 0, 0, 0, 0, 0],
+            }
+        );
+        assert_eq!(dispatcher_recording.len(), 2);
+        let hopper_recording = hopper_recording_arc.lock().unwrap();
+        assert_eq!(hopper_recording.len(), 0);
     }
 
     #[test]
-    fn report_response_services_consumed_complains_and_drops_package_if_return_route_id_is_unrecognized(
-    ) {
-        init_test_logging();
+    fn proxy_server_numbers_responses_to_socks_clients_after_the_handshake_replies() {
+        let system = System::new(
+            "proxy_server_numbers_responses_to_socks_clients_after_the_handshake_replies",
+        );
         let cryptde = cryptde();
-        let (dispatcher, _, dispatcher_recording_arc) = make_recorder();
-        let (accountant, _, accountant_recording_arc) = make_recorder();
-        let system = System::new ("report_response_services_consumed_complains_and_drops_package_if_return_route_id_does_not_exist");
-        let mut subject = ProxyServer::new(cryptde, true);
+        let (dispatcher_mock, _, dispatcher_recording_arc) = make_recorder();
+        let socket_addr = SocketAddr::from_str("127.0.0.1:5678").unwrap();
         let stream_key = make_meaningless_stream_key();
+        let mut subject = ProxyServer::new(cryptde, false);
+        subject.accept_socks_on(1080);
+        subject.keys_and_addrs.insert(stream_key, socket_addr);
         subject
-            .keys_and_addrs
-            .insert(stream_key, SocketAddr::from_str("1.2.3.4:5678").unwrap());
-        let subject_addr: Addr<Syn, ProxyServer> = subject.start();
-        let mut peer_actors = peer_actors_builder()
-            .dispatcher(dispatcher)
-            .accountant(accountant)
-            .build();
-        peer_actors.proxy_server = ProxyServer::make_subs_from(&subject_addr);
+            .route_ids_to_services
+            .insert(1234, vec![ExpectedService::Nothing]);
+        let mut session = Socks5Session::new();
+        session.accept(&[0x05, 0x01, 0x00], false, |_| true);
+        session.accept(&socks_connect_request("nowhere.com", 80), false, |_| true);
+        subject.socks_sessions.insert(socket_addr, session);
         let client_response_payload = ClientResponsePayload {
             stream_key,
             sequenced_packet: SequencedPacket {
-                data: b"some data".to_vec(),
-                sequence_number: 4321,
+                data: b"HTTP/1.1 200 OK\r\n\r\n".to_vec(),
+                sequence_number: 0,
                 last_data: false,
             },
+            requests_received: None,
+            truncated: false,
+            udp: false,
+            exit_failed: false,
         };
         let expired_cores_package = ExpiredCoresPackage::new(
             IpAddr::from_str("1.2.3.4").unwrap(),
@@ -1965,148 +5406,173 @@ mod tests {
             return_route_with_id(cryptde, 1234),
             encodex(cryptde, &cryptde.public_key(), &client_response_payload).unwrap(),
         );
+        let subject_addr: Addr<Syn, ProxyServer> = subject.start();
+        let peer_actors = peer_actors_builder().dispatcher(dispatcher_mock).build();
         subject_addr.try_send(BindMessage { peer_actors }).unwrap();
 
         subject_addr.try_send(expired_cores_package).unwrap();
 
         Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
         system.run();
-        TestLogHandler::new().exists_log_containing("ERROR: Proxy Server: Can't report services consumed: return route ID 1234 is not recognized");
-        assert_eq!(dispatcher_recording_arc.lock().unwrap().len(), 0);
-        assert_eq!(accountant_recording_arc.lock().unwrap().len(), 0);
+        let dispatcher_recording = dispatcher_recording_arc.lock().unwrap();
+        assert_eq!(
+            dispatcher_recording.get_record::<TransmitDataMsg>(0),
+            &TransmitDataMsg {
+                endpoint: Endpoint::Socket(socket_addr),
+                last_data: false,
+                sequence_number: Some(2),
+                data: b"HTTP/1.1 200 OK\r\n\r\n".to_vec(),
+            }
+        );
+    }
+
+    fn make_http_proxy_msg(
+        peer_addr: SocketAddr,
+        sequence_number: u64,
+        data: Vec<u8>,
+    ) -> InboundClientData {
+        InboundClientData {
+            peer_addr,
+            reception_port: Some(8118),
+            sequence_number: Some(sequence_number),
+            last_data: false,
+            is_clandestine: false,
+            data,
+            neighbor_key_opt: None,
+        }
     }
 
     #[test]
-    fn report_response_services_consumed_complains_and_drops_package_if_return_route_id_is_unspecified(
-    ) {
-        init_test_logging();
+    fn proxy_server_sends_http_proxy_requests_to_the_target_in_the_url() {
+        let system = System::new("proxy_server_sends_http_proxy_requests_to_the_target_in_the_url");
         let cryptde = cryptde();
-        let (dispatcher, _, dispatcher_recording_arc) = make_recorder();
-        let (accountant, _, accountant_recording_arc) = make_recorder();
-        let system = System::new ("report_response_services_consumed_complains_and_drops_package_if_return_route_id_is_unspecified");
-        let mut subject = ProxyServer::new(cryptde, true);
+        let (hopper_mock, _, hopper_recording_arc) = make_recorder();
+        let (dispatcher_mock, _, dispatcher_recording_arc) = make_recorder();
+        let socket_addr = SocketAddr::from_str("127.0.0.1:5678").unwrap();
         let stream_key = make_meaningless_stream_key();
-        subject
-            .keys_and_addrs
-            .insert(stream_key, SocketAddr::from_str("1.2.3.4:5678").unwrap());
-        let subject_addr: Addr<Syn, ProxyServer> = subject.start();
-        let mut peer_actors = peer_actors_builder()
-            .dispatcher(dispatcher)
-            .accountant(accountant)
-            .build();
-        peer_actors.proxy_server = ProxyServer::make_subs_from(&subject_addr);
-        let client_response_payload = ClientResponsePayload {
+        let mut subject = ProxyServer::new(cryptde, false);
+        subject.accept_http_proxy_on(8118);
+        subject.stream_key_factory = Box::new(StreamKeyFactoryMock::new().make_result(stream_key));
+        subject.routes.insert(
             stream_key,
-            sequenced_packet: SequencedPacket {
-                data: b"some data".to_vec(),
-                sequence_number: 4321,
-                last_data: false,
-            },
-        };
-        let expired_cores_package = ExpiredCoresPackage::new(
-            IpAddr::from_str("1.2.3.4").unwrap(),
-            Some(Wallet::new("irrelevant")),
-            Route {
-                hops: vec![make_cover_hop(cryptde)],
-            },
-            encodex(cryptde, &cryptde.public_key(), &client_response_payload).unwrap(),
+            (
+                zero_hop_route_response(&cryptde.public_key(), cryptde),
+                Instant::now() + Duration::from_secs(60),
+            ),
         );
+        let subject_addr: Addr<Syn, ProxyServer> = subject.start();
+        let peer_actors = peer_actors_builder()
+            .hopper(hopper_mock)
+            .dispatcher(dispatcher_mock)
+            .build();
         subject_addr.try_send(BindMessage { peer_actors }).unwrap();
 
-        subject_addr.try_send(expired_cores_package).unwrap();
+        subject_addr
+            .try_send(make_http_proxy_msg(
+                socket_addr,
+                0,
+                b"GET http://nowhere.com/index.html HTTP/1.1\r\nHost: nowhere.com\r\nProxy-Connection: keep-alive\r\n\r\n".to_vec(),
+            ))
+            .unwrap();
 
         Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
         system.run();
-        TestLogHandler::new().exists_log_containing(
-            "ERROR: Proxy Server: Can't report services consumed: return route ID is unspecified",
+        let dispatcher_recording = dispatcher_recording_arc.lock().unwrap();
+        assert_eq!(dispatcher_recording.len(), 0);
+        let hopper_recording = hopper_recording_arc.lock().unwrap();
+        let icp = hopper_recording.get_record::<IncipientCoresPackage>(0);
+        let payload: ClientRequestPayload = decodex(cryptde, &icp.payload).unwrap();
+        assert_eq!(payload.stream_key, stream_key);
+        assert_eq!(payload.target_hostname, Some(String::from("nowhere.com")));
+        assert_eq!(payload.target_port, 80);
+        assert_eq!(payload.protocol, ProxyProtocol::HTTP);
+        assert_eq!(
+            payload.sequenced_packet,
+            SequencedPacket {
+                data: b"GET /index.html HTTP/1.1\r\nHost: nowhere.com\r\nConnection: close\r\n\r\n"
+                    .to_vec(),
+                sequence_number: 0,
+                last_data: false,
+            }
         );
-        assert_eq!(dispatcher_recording_arc.lock().unwrap().len(), 0);
-        assert_eq!(accountant_recording_arc.lock().unwrap().len(), 0);
+        assert_eq!(hopper_recording.len(), 1);
     }
 
     #[test]
-    fn report_response_services_consumed_complains_and_drops_package_if_return_route_id_is_unreadable(
-    ) {
-        init_test_logging();
+    fn proxy_server_refuses_http_proxy_tunnels_to_ports_without_a_protocol_pack() {
+        let system =
+            System::new("proxy_server_refuses_http_proxy_tunnels_to_ports_without_a_protocol_pack");
         let cryptde = cryptde();
-        let (dispatcher, _, dispatcher_recording_arc) = make_recorder();
-        let (accountant, _, accountant_recording_arc) = make_recorder();
-        let system = System::new ("report_response_services_consumed_complains_and_drops_package_if_return_route_id_is_unreadable");
-        let mut subject = ProxyServer::new(cryptde, true);
-        let stream_key = make_meaningless_stream_key();
-        subject
-            .keys_and_addrs
-            .insert(stream_key, SocketAddr::from_str("1.2.3.4:5678").unwrap());
+        let (hopper_mock, _, hopper_recording_arc) = make_recorder();
+        let (dispatcher_mock, _, dispatcher_recording_arc) = make_recorder();
+        let socket_addr = SocketAddr::from_str("127.0.0.1:5678").unwrap();
+        let mut subject = ProxyServer::new(cryptde, false);
+        subject.accept_http_proxy_on(8118);
         let subject_addr: Addr<Syn, ProxyServer> = subject.start();
-        let mut peer_actors = peer_actors_builder()
-            .dispatcher(dispatcher)
-            .accountant(accountant)
+        let peer_actors = peer_actors_builder()
+            .hopper(hopper_mock)
+            .dispatcher(dispatcher_mock)
             .build();
-        peer_actors.proxy_server = ProxyServer::make_subs_from(&subject_addr);
-        let client_response_payload = ClientResponsePayload {
-            stream_key,
-            sequenced_packet: SequencedPacket {
-                data: b"some data".to_vec(),
-                sequence_number: 4321,
-                last_data: false,
-            },
-        };
-        let expired_cores_package = ExpiredCoresPackage::new(
-            IpAddr::from_str("1.2.3.4").unwrap(),
-            Some(Wallet::new("irrelevant")),
-            Route {
-                hops: vec![make_cover_hop(cryptde), CryptData::new(&[0])],
-            },
-            encodex(cryptde, &cryptde.public_key(), &client_response_payload).unwrap(),
-        );
         subject_addr.try_send(BindMessage { peer_actors }).unwrap();
 
-        subject_addr.try_send(expired_cores_package).unwrap();
+        subject_addr
+            .try_send(make_http_proxy_msg(
+                socket_addr,
+                0,
+                b"CONNECT nowhere.com:6881 HTTP/1.1\r\n\r\n".to_vec(),
+            ))
+            .unwrap();
+        subject_addr
+            .try_send(make_http_proxy_msg(socket_addr, 1, b"booga".to_vec()))
+            .unwrap();
 
         Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
         system.run();
-        TestLogHandler::new().exists_log_containing(
-            "ERROR: Proxy Server: Can't report services consumed: return route ID is unreadable",
+        let dispatcher_recording = dispatcher_recording_arc.lock().unwrap();
+        assert_eq!(
+            dispatcher_recording.get_record::<TransmitDataMsg>(0),
+            &TransmitDataMsg {
+                endpoint: Endpoint::Socket(socket_addr),
+                last_data: true,
+                sequence_number: Some(0),
+                data: b"HTTP/1.1 403 Forbidden\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                    .to_vec(),
+            }
         );
-        assert_eq!(dispatcher_recording_arc.lock().unwrap().len(), 0);
-        assert_eq!(accountant_recording_arc.lock().unwrap().len(), 0);
+        assert_eq!(dispatcher_recording.len(), 1);
+        let hopper_recording = hopper_recording_arc.lock().unwrap();
+        assert_eq!(hopper_recording.len(), 0);
     }
 
     #[test]
-    fn return_route_ids_expire_when_instructed() {
-        init_test_logging();
+    fn proxy_server_numbers_responses_to_http_proxy_clients_after_the_connect_reply() {
+        let system = System::new(
+            "proxy_server_numbers_responses_to_http_proxy_clients_after_the_connect_reply",
+        );
         let cryptde = cryptde();
+        let (dispatcher_mock, _, dispatcher_recording_arc) = make_recorder();
+        let socket_addr = SocketAddr::from_str("127.0.0.1:5678").unwrap();
         let stream_key = make_meaningless_stream_key();
-
-        let (tx, rx) = mpsc::channel();
-        thread::spawn(move || {
-            let system = System::new ("report_response_services_consumed_complains_and_drops_package_if_return_route_id_does_not_exist");
-            let mut subject = ProxyServer::new(cryptde, true);
-            subject.route_ids_to_services = TtlHashMap::new(Duration::from_millis(250));
-            subject
-                .keys_and_addrs
-                .insert(stream_key, SocketAddr::from_str("1.2.3.4:5678").unwrap());
-            subject.route_ids_to_services.insert(1234, vec![]);
-            let subject_addr: Addr<Syn, ProxyServer> = subject.start();
-            let mut peer_actors = peer_actors_builder().build();
-            peer_actors.proxy_server = ProxyServer::make_subs_from(&subject_addr);
-            subject_addr.try_send(BindMessage { peer_actors }).unwrap();
-            tx.send(subject_addr).unwrap();
-
-            system.run();
-        });
-
-        let subject_addr = rx.recv().unwrap();
-
-        thread::sleep(Duration::from_millis(300));
-
+        let mut subject = ProxyServer::new(cryptde, false);
+        subject.accept_http_proxy_on(8118);
+        subject.keys_and_addrs.insert(stream_key, socket_addr);
+        subject
+            .route_ids_to_services
+            .insert(1234, vec![ExpectedService::Nothing]);
+        let mut session = HttpProxySession::new();
+        session.accept(b"CONNECT nowhere.com:443 HTTP/1.1\r\n\r\n", false, |_| true);
+        subject.http_proxy_sessions.insert(socket_addr, session);
         let client_response_payload = ClientResponsePayload {
             stream_key,
             sequenced_packet: SequencedPacket {
-                data: b"some data".to_vec(),
-                sequence_number: 4321,
-                last_data: false,
+                data: b"server hello".to_vec(),
+                sequence_number: 0,
+                last_data: true,
             },
+            requests_received: None,
+            truncated: false,
+            udp: false,
+            exit_failed: false,
         };
         let expired_cores_package = ExpiredCoresPackage::new(
             IpAddr::from_str("1.2.3.4").unwrap(),
@@ -2114,8 +5580,23 @@ mod tests {
             return_route_with_id(cryptde, 1234),
             encodex(cryptde, &cryptde.public_key(), &client_response_payload).unwrap(),
         );
+        let subject_addr: Addr<Syn, ProxyServer> = subject.start();
+        let peer_actors = peer_actors_builder().dispatcher(dispatcher_mock).build();
+        subject_addr.try_send(BindMessage { peer_actors }).unwrap();
+
         subject_addr.try_send(expired_cores_package).unwrap();
 
-        TestLogHandler::new().await_log_containing("ERROR: Proxy Server: Can't report services consumed: return route ID 1234 is not recognized", 1000);
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+        let dispatcher_recording = dispatcher_recording_arc.lock().unwrap();
+        assert_eq!(
+            dispatcher_recording.get_record::<TransmitDataMsg>(0),
+            &TransmitDataMsg {
+                endpoint: Endpoint::Socket(socket_addr),
+                last_data: true,
+                sequence_number: Some(1),
+                data: b"server hello".to_vec(),
+            }
+        );
     }
 }
@@ -0,0 +1,404 @@
+// Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use crate::sub_lib::proxy_server::ClientRequestPayload;
+use serde_cbor;
+use std::collections::VecDeque;
+use std::fs;
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::io::Write;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+// How many bytes of unacknowledged request data one stream may keep in memory, unless
+// --retransmission_memory says otherwise
+pub const DEFAULT_RETRANSMISSION_MEMORY: usize = 256 * 1024;
+// Where spilled packets go, under the data directory
+pub const RETRANSMISSION_SPILL_DIRECTORY: &str = "retransmission";
+
+struct SpilledPacket {
+    sequence_number: u64,
+    offset: u64,
+    length: usize,
+}
+
+// Keeps the request packets of one stream that have gone into the route but that the exit hasn't
+// acknowledged yet, so that they could be sent again over a different route. Past memory_limit
+// bytes, the oldest are written to a spill file, if the buffer has one; if it hasn't, the buffer
+// gives up, because a replay with a packet missing would be worse than none. It also gives up if
+// more than max_packets go unacknowledged, since then the exit isn't counting what it receives.
+pub struct RetransmissionBuffer {
+    max_packets: usize,
+    memory_limit: usize,
+    memory_used: usize,
+    in_memory: VecDeque<(ClientRequestPayload, SocketAddr)>,
+    spill_path_opt: Option<PathBuf>,
+    spill_length: u64,
+    spilled: VecDeque<SpilledPacket>,
+    abandoned: bool,
+}
+
+impl Drop for RetransmissionBuffer {
+    fn drop(&mut self) {
+        self.discard_spill();
+    }
+}
+
+impl RetransmissionBuffer {
+    pub fn new(
+        max_packets: usize,
+        memory_limit: usize,
+        spill_path_opt: Option<PathBuf>,
+    ) -> RetransmissionBuffer {
+        RetransmissionBuffer {
+            max_packets,
+            memory_limit,
+            memory_used: 0,
+            in_memory: VecDeque::new(),
+            spill_path_opt,
+            spill_length: 0,
+            spilled: VecDeque::new(),
+            abandoned: false,
+        }
+    }
+
+    // Called with every request packet as it goes into the route. Returns the reason the buffer
+    // gave up, the one time it does; after that it keeps nothing.
+    pub fn retain(
+        &mut self,
+        payload: &ClientRequestPayload,
+        source_addr: SocketAddr,
+    ) -> Result<(), String> {
+        if self.abandoned {
+            return Ok(());
+        }
+        if self.unacknowledged() >= self.max_packets {
+            return self.abandon(format!(
+                "more than {} packets went unacknowledged",
+                self.max_packets
+            ));
+        }
+        self.memory_used += payload.sequenced_packet.data.len();
+        self.in_memory.push_back((payload.clone(), source_addr));
+        while self.memory_used > self.memory_limit {
+            let (oldest, oldest_source_addr) = match self.in_memory.pop_front() {
+                Some(packet) => packet,
+                None => break,
+            };
+            self.memory_used -= oldest.sequenced_packet.data.len();
+            if let Err(e) = self.spill(&oldest, oldest_source_addr) {
+                return self.abandon(e);
+            }
+        }
+        Ok(())
+    }
+
+    // Forgets the packets the exit has received
+    pub fn acknowledge(&mut self, requests_received: u64) {
+        while let Some(true) = self
+            .spilled
+            .front()
+            .map(|spilled| spilled.sequence_number < requests_received)
+        {
+            self.spilled.pop_front();
+        }
+        if self.spilled.is_empty() {
+            self.discard_spill();
+        }
+        while let Some(true) = self
+            .in_memory
+            .front()
+            .map(|(payload, _)| payload.sequenced_packet.sequence_number < requests_received)
+        {
+            let (payload, _) = self.in_memory.pop_front().expect("Packet disappeared");
+            self.memory_used -= payload.sequenced_packet.data.len();
+        }
+    }
+
+    // Every unacknowledged packet, oldest first, read back from the spill file where necessary
+    #[allow(dead_code)] // Until a stream can be moved to a new route in mid-flight
+    pub fn replay(&self) -> Result<Vec<(ClientRequestPayload, SocketAddr)>, String> {
+        if self.abandoned {
+            return Err(String::from("Unacknowledged packets were not all kept"));
+        }
+        let mut packets = match (&self.spill_path_opt, self.spilled.is_empty()) {
+            (_, true) => vec![],
+            (Some(spill_path), false) => self.read_spilled(spill_path)?,
+            (None, false) => panic!("Packets were spilled with no spill file"),
+        };
+        packets.extend(self.in_memory.iter().cloned());
+        Ok(packets)
+    }
+
+    fn unacknowledged(&self) -> usize {
+        self.spilled.len() + self.in_memory.len()
+    }
+
+    fn spill(
+        &mut self,
+        payload: &ClientRequestPayload,
+        source_addr: SocketAddr,
+    ) -> Result<(), String> {
+        let spill_path = match &self.spill_path_opt {
+            Some(spill_path) => spill_path,
+            None => {
+                return Err(format!(
+                    "more than {} bytes went unacknowledged",
+                    self.memory_limit
+                ))
+            }
+        };
+        let bytes = serde_cbor::ser::to_vec(&(payload, source_addr)).expect("Serialization failed");
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(spill_path)
+            .and_then(|mut file| file.write_all(&bytes))
+            .map_err(|e| format!("couldn't spill to {:?}: {}", spill_path, e))?;
+        self.spilled.push_back(SpilledPacket {
+            sequence_number: payload.sequenced_packet.sequence_number,
+            offset: self.spill_length,
+            length: bytes.len(),
+        });
+        self.spill_length += bytes.len() as u64;
+        Ok(())
+    }
+
+    fn read_spilled(
+        &self,
+        spill_path: &PathBuf,
+    ) -> Result<Vec<(ClientRequestPayload, SocketAddr)>, String> {
+        let mut file =
+            File::open(spill_path).map_err(|e| format!("Couldn't open {:?}: {}", spill_path, e))?;
+        self.spilled
+            .iter()
+            .map(|spilled| {
+                let mut bytes = vec![0; spilled.length];
+                file.seek(SeekFrom::Start(spilled.offset))
+                    .and_then(|_| file.read_exact(&mut bytes))
+                    .map_err(|e| format!("Couldn't read {:?}: {}", spill_path, e))?;
+                serde_cbor::de::from_slice(&bytes)
+                    .map_err(|e| format!("Couldn't parse {:?}: {}", spill_path, e))
+            })
+            .collect()
+    }
+
+    fn abandon(&mut self, reason: String) -> Result<(), String> {
+        self.abandoned = true;
+        self.in_memory.clear();
+        self.memory_used = 0;
+        self.spilled.clear();
+        self.discard_spill();
+        Err(reason)
+    }
+
+    fn discard_spill(&mut self) {
+        if self.spill_length == 0 {
+            return;
+        }
+        if let Some(spill_path) = &self.spill_path_opt {
+            fs::remove_file(spill_path).is_ok();
+        }
+        self.spill_length = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sub_lib::cryptde::PublicKey;
+    use crate::sub_lib::proxy_server::ProxyProtocol;
+    use crate::sub_lib::sequence_buffer::SequencedPacket;
+    use crate::test_utils::test_utils::make_meaningless_stream_key;
+    use std::str::FromStr;
+
+    const BASE_TEST_DIR: &str = "generated/test/retransmission_buffer";
+
+    fn make_spill_path(name: &str) -> PathBuf {
+        let directory = PathBuf::from(format!("{}/{}", BASE_TEST_DIR, name));
+        fs::remove_dir_all(&directory).is_ok();
+        fs::create_dir_all(&directory).unwrap();
+        directory.join("stream-0.cbor")
+    }
+
+    fn make_payload(size: usize, sequence_number: u64) -> ClientRequestPayload {
+        ClientRequestPayload {
+            stream_key: make_meaningless_stream_key(),
+            sequenced_packet: SequencedPacket::new(vec![1; size], sequence_number, false),
+            target_hostname: Some(String::from("nowhere.com")),
+            target_port: 80,
+            protocol: ProxyProtocol::HTTP,
+            originator_public_key: PublicKey::new(&b"originator"[..]),
+            tls_at_exit: false,
+            upload_window: Some(8),
+            udp: false,
+            dns_server: None,
+            cancel: false,
+        }
+    }
+
+    fn source_addr() -> SocketAddr {
+        SocketAddr::from_str("1.2.3.4:5678").unwrap()
+    }
+
+    fn sequence_numbers(subject: &RetransmissionBuffer) -> Vec<u64> {
+        subject
+            .replay()
+            .unwrap()
+            .into_iter()
+            .map(|(payload, _)| payload.sequenced_packet.sequence_number)
+            .collect()
+    }
+
+    #[test]
+    fn constant_values() {
+        assert_eq!(DEFAULT_RETRANSMISSION_MEMORY, 262144);
+        assert_eq!(RETRANSMISSION_SPILL_DIRECTORY, "retransmission");
+    }
+
+    #[test]
+    fn acknowledged_packets_are_forgotten() {
+        let mut subject = RetransmissionBuffer::new(8, 1000, None);
+        (0..4).for_each(|sequence_number| {
+            subject
+                .retain(&make_payload(100, sequence_number), source_addr())
+                .unwrap()
+        });
+
+        subject.acknowledge(2);
+
+        assert_eq!(sequence_numbers(&subject), vec![2, 3]);
+        assert_eq!(subject.memory_used, 200);
+        subject.acknowledge(1);
+        assert_eq!(subject.unacknowledged(), 2);
+    }
+
+    #[test]
+    fn without_a_spill_file_the_buffer_gives_up_when_memory_runs_out() {
+        let mut subject = RetransmissionBuffer::new(8, 250, None);
+        subject
+            .retain(&make_payload(100, 0), source_addr())
+            .unwrap();
+        subject
+            .retain(&make_payload(100, 1), source_addr())
+            .unwrap();
+
+        let result = subject.retain(&make_payload(100, 2), source_addr());
+
+        assert_eq!(
+            result,
+            Err(String::from("more than 250 bytes went unacknowledged"))
+        );
+        assert_eq!(subject.unacknowledged(), 0);
+        assert_eq!(subject.memory_used, 0);
+        assert_eq!(subject.retain(&make_payload(100, 3), source_addr()), Ok(()));
+        assert_eq!(subject.unacknowledged(), 0);
+        assert_eq!(
+            subject.replay(),
+            Err(String::from("Unacknowledged packets were not all kept"))
+        );
+    }
+
+    #[test]
+    fn the_buffer_gives_up_on_an_exit_that_does_not_acknowledge() {
+        let mut subject = RetransmissionBuffer::new(2, 1000, None);
+        subject.retain(&make_payload(10, 0), source_addr()).unwrap();
+        subject.retain(&make_payload(10, 1), source_addr()).unwrap();
+
+        let result = subject.retain(&make_payload(10, 2), source_addr());
+
+        assert_eq!(
+            result,
+            Err(String::from("more than 2 packets went unacknowledged"))
+        );
+        assert_eq!(subject.unacknowledged(), 0);
+    }
+
+    #[test]
+    fn the_oldest_packets_spill_to_disk_and_are_read_back_in_order() {
+        let spill_path =
+            make_spill_path("the_oldest_packets_spill_to_disk_and_are_read_back_in_order");
+        let mut subject = RetransmissionBuffer::new(8, 250, Some(spill_path.clone()));
+
+        (0..5).for_each(|sequence_number| {
+            subject
+                .retain(&make_payload(100, sequence_number), source_addr())
+                .unwrap()
+        });
+
+        assert_eq!(subject.spilled.len(), 3);
+        assert_eq!(subject.in_memory.len(), 2);
+        assert_eq!(subject.memory_used, 200);
+        assert_eq!(spill_path.exists(), true);
+        let replayed = subject.replay().unwrap();
+        assert_eq!(
+            replayed
+                .iter()
+                .map(|(payload, _)| payload.sequenced_packet.sequence_number)
+                .collect::<Vec<u64>>(),
+            vec![0, 1, 2, 3, 4]
+        );
+        assert_eq!(replayed[1], (make_payload(100, 1), source_addr()));
+    }
+
+    #[test]
+    fn the_spill_file_goes_away_once_everything_in_it_is_acknowledged() {
+        let spill_path =
+            make_spill_path("the_spill_file_goes_away_once_everything_in_it_is_acknowledged");
+        let mut subject = RetransmissionBuffer::new(8, 250, Some(spill_path.clone()));
+        (0..4).for_each(|sequence_number| {
+            subject
+                .retain(&make_payload(100, sequence_number), source_addr())
+                .unwrap()
+        });
+
+        subject.acknowledge(1);
+        assert_eq!(spill_path.exists(), true);
+        assert_eq!(sequence_numbers(&subject), vec![1, 2, 3]);
+        subject.acknowledge(2);
+
+        assert_eq!(spill_path.exists(), false);
+        assert_eq!(sequence_numbers(&subject), vec![2, 3]);
+        subject
+            .retain(&make_payload(100, 4), source_addr())
+            .unwrap();
+        assert_eq!(spill_path.exists(), true);
+        assert_eq!(sequence_numbers(&subject), vec![2, 3, 4]);
+    }
+
+    #[test]
+    fn dropping_the_buffer_removes_its_spill_file() {
+        let spill_path = make_spill_path("dropping_the_buffer_removes_its_spill_file");
+        let mut subject = RetransmissionBuffer::new(8, 50, Some(spill_path.clone()));
+        subject
+            .retain(&make_payload(100, 0), source_addr())
+            .unwrap();
+        assert_eq!(spill_path.exists(), true);
+
+        drop(subject);
+
+        assert_eq!(spill_path.exists(), false);
+    }
+
+    #[test]
+    fn a_spill_file_that_cannot_be_written_makes_the_buffer_give_up() {
+        let spill_path =
+            make_spill_path("a_spill_file_that_cannot_be_written_makes_the_buffer_give_up")
+                .join("nonexistent")
+                .join("stream-0.cbor");
+        let mut subject = RetransmissionBuffer::new(8, 50, Some(spill_path.clone()));
+
+        let result = subject.retain(&make_payload(100, 0), source_addr());
+
+        assert_eq!(
+            result
+                .err()
+                .unwrap()
+                .starts_with(&format!("couldn't spill to {:?}: ", spill_path)),
+            true
+        );
+        assert_eq!(subject.unacknowledged(), 0);
+    }
+}
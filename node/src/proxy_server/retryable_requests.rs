@@ -0,0 +1,250 @@
+// Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use crate::sub_lib::proxy_server::ClientRequestPayload;
+use crate::sub_lib::proxy_server::ProxyProtocol;
+use crate::sub_lib::stream_key::StreamKey;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::Duration;
+use std::time::Instant;
+
+// Retries a stream may use, whether the server reset it or the exit went quiet
+pub const RETRY_BUDGET: usize = 1;
+pub const STALLED_REQUEST_TIMEOUT: Duration = Duration::from_secs(20);
+
+// Keeps the first packet of each stream whose request can safely be made twice: a plain HTTP GET
+// or HEAD that the browser hasn't followed with anything else. If the server resets the connection
+// before any of its response has come back, or nothing at all comes back for too long, the
+// request can go out again over a fresh route. Once a stream's retries are spent, the next failure
+// is the browser's problem.
+pub struct RetryableRequests {
+    requests: HashMap<StreamKey, (ClientRequestPayload, SocketAddr, Instant)>,
+    retries: HashMap<StreamKey, usize>,
+}
+
+impl RetryableRequests {
+    pub fn new() -> RetryableRequests {
+        RetryableRequests {
+            requests: HashMap::new(),
+            retries: HashMap::new(),
+        }
+    }
+
+    // Called with every request packet on its way to the exit
+    pub fn record_request(&mut self, payload: &ClientRequestPayload, source_addr: SocketAddr) {
+        let stream_key = payload.stream_key;
+        if RetryableRequests::is_retryable(payload) {
+            self.requests
+                .insert(stream_key, (payload.clone(), source_addr, Instant::now()));
+        } else {
+            self.requests.remove(&stream_key);
+        }
+    }
+
+    // Called when some of the response has gone on to the browser, after which it's too late
+    pub fn responded(&mut self, stream_key: &StreamKey) {
+        self.requests.remove(stream_key);
+    }
+
+    // Returns the request to send again, if the stream has one and its retries aren't spent. The
+    // request is kept, so that a retry that goes unanswered too is noticed.
+    pub fn take_retry(
+        &mut self,
+        stream_key: &StreamKey,
+    ) -> Option<(ClientRequestPayload, SocketAddr)> {
+        let retries = self.retries.entry(*stream_key).or_insert(0);
+        if *retries >= RETRY_BUDGET {
+            return None;
+        }
+        let (payload, source_addr, sent_at) = self.requests.get_mut(stream_key)?;
+        *retries += 1;
+        *sent_at = Instant::now();
+        Some((payload.clone(), *source_addr))
+    }
+
+    // Streams whose requests have waited longer than their timeouts without any response
+    pub fn stalled<F>(&self, now: Instant, timeout_for: F) -> Vec<StreamKey>
+    where
+        F: Fn(&StreamKey) -> Duration,
+    {
+        self.requests
+            .iter()
+            .filter(|(stream_key, (_, _, sent_at))| {
+                now.duration_since(*sent_at) >= timeout_for(stream_key)
+            })
+            .map(|(stream_key, _)| *stream_key)
+            .collect()
+    }
+
+    // Stops watching a stream whose retries are spent, and returns what it was waiting for
+    pub fn abandon(
+        &mut self,
+        stream_key: &StreamKey,
+    ) -> Option<(ClientRequestPayload, SocketAddr)> {
+        self.requests
+            .remove(stream_key)
+            .map(|(payload, source_addr, _)| (payload, source_addr))
+    }
+
+    pub fn forget(&mut self, stream_key: &StreamKey) {
+        self.requests.remove(stream_key);
+        self.retries.remove(stream_key);
+    }
+
+    fn is_retryable(payload: &ClientRequestPayload) -> bool {
+        let data = &payload.sequenced_packet.data;
+        payload.protocol == ProxyProtocol::HTTP
+            && !payload.tls_at_exit
+            && payload.sequenced_packet.sequence_number == 0
+            && !payload.sequenced_packet.last_data
+            && (data.starts_with(b"GET ") || data.starts_with(b"HEAD "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sub_lib::cryptde::PublicKey;
+    use crate::sub_lib::sequence_buffer::SequencedPacket;
+    use crate::test_utils::test_utils::make_meaningless_stream_key;
+    use std::str::FromStr;
+
+    fn make_payload(data: &[u8], sequence_number: u64) -> ClientRequestPayload {
+        ClientRequestPayload {
+            stream_key: make_meaningless_stream_key(),
+            sequenced_packet: SequencedPacket::new(data.to_vec(), sequence_number, false),
+            target_hostname: Some(String::from("nowhere.com")),
+            target_port: 80,
+            protocol: ProxyProtocol::HTTP,
+            originator_public_key: PublicKey::new(&b"originator"[..]),
+            tls_at_exit: false,
+            upload_window: None,
+            udp: false,
+            dns_server: None,
+            cancel: false,
+        }
+    }
+
+    fn source_addr() -> SocketAddr {
+        SocketAddr::from_str("1.2.3.4:5678").unwrap()
+    }
+
+    #[test]
+    fn a_lone_get_can_be_retried_once() {
+        let mut subject = RetryableRequests::new();
+        let payload = make_payload(b"GET /index.html HTTP/1.1\r\n\r\n", 0);
+        subject.record_request(&payload, source_addr());
+
+        let first = subject.take_retry(&payload.stream_key);
+        subject.record_request(&payload, source_addr());
+        let second = subject.take_retry(&payload.stream_key);
+
+        assert_eq!(first, Some((payload, source_addr())));
+        assert_eq!(second, None);
+    }
+
+    #[test]
+    fn a_lone_head_can_be_retried() {
+        let mut subject = RetryableRequests::new();
+        let payload = make_payload(b"HEAD / HTTP/1.1\r\n\r\n", 0);
+        subject.record_request(&payload, source_addr());
+
+        let result = subject.take_retry(&payload.stream_key);
+
+        assert_eq!(result, Some((payload, source_addr())));
+    }
+
+    #[test]
+    fn requests_that_are_not_idempotent_are_not_retried() {
+        let mut subject = RetryableRequests::new();
+        let payload = make_payload(b"POST /form HTTP/1.1\r\n\r\nname=value", 0);
+        subject.record_request(&payload, source_addr());
+
+        let result = subject.take_retry(&payload.stream_key);
+
+        assert_eq!(result, None);
+    }
+
+    #[test]
+    fn tls_and_tls_at_exit_requests_are_not_retried() {
+        let mut subject = RetryableRequests::new();
+        let mut tls_payload = make_payload(b"GET / HTTP/1.1\r\n\r\n", 0);
+        tls_payload.protocol = ProxyProtocol::TLS;
+        subject.record_request(&tls_payload, source_addr());
+        let tls_result = subject.take_retry(&tls_payload.stream_key);
+        let mut tls_at_exit_payload = make_payload(b"GET / HTTP/1.1\r\n\r\n", 0);
+        tls_at_exit_payload.tls_at_exit = true;
+        subject.record_request(&tls_at_exit_payload, source_addr());
+        let tls_at_exit_result = subject.take_retry(&tls_at_exit_payload.stream_key);
+
+        assert_eq!(tls_result, None);
+        assert_eq!(tls_at_exit_result, None);
+    }
+
+    #[test]
+    fn a_get_followed_by_more_from_the_browser_is_not_retried() {
+        let mut subject = RetryableRequests::new();
+        let first = make_payload(b"GET / HTTP/1.1\r\n\r\n", 0);
+        let second = make_payload(b"GET /favicon.ico HTTP/1.1\r\n\r\n", 1);
+        subject.record_request(&first, source_addr());
+        subject.record_request(&second, source_addr());
+
+        let result = subject.take_retry(&first.stream_key);
+
+        assert_eq!(result, None);
+    }
+
+    #[test]
+    fn a_forgotten_stream_is_not_retried_but_may_start_over() {
+        let mut subject = RetryableRequests::new();
+        let payload = make_payload(b"GET / HTTP/1.1\r\n\r\n", 0);
+        subject.record_request(&payload, source_addr());
+        subject.take_retry(&payload.stream_key);
+        subject.forget(&payload.stream_key);
+
+        let forgotten = subject.take_retry(&payload.stream_key);
+        subject.record_request(&payload, source_addr());
+        let started_over = subject.take_retry(&payload.stream_key);
+
+        assert_eq!(forgotten, None);
+        assert_eq!(started_over, Some((payload, source_addr())));
+    }
+
+    #[test]
+    fn a_request_stalls_once_it_has_waited_long_enough_without_a_response() {
+        let mut subject = RetryableRequests::new();
+        let waiting = make_payload(b"GET / HTTP/1.1\r\n\r\n", 0);
+        let mut answered = make_payload(b"GET / HTTP/1.1\r\n\r\n", 0);
+        answered.stream_key = StreamKey::new(PublicKey::new(b"other"), source_addr());
+        subject.record_request(&waiting, source_addr());
+        subject.record_request(&answered, source_addr());
+        subject.responded(&answered.stream_key);
+        let timeout = Duration::from_secs(20);
+
+        let too_soon = subject.stalled(Instant::now(), |_| timeout);
+        let stalled = subject.stalled(Instant::now() + timeout, |_| timeout);
+
+        assert_eq!(too_soon, vec![]);
+        assert_eq!(stalled, vec![waiting.stream_key]);
+    }
+
+    #[test]
+    fn a_retried_request_is_watched_again_and_can_be_abandoned() {
+        let mut subject = RetryableRequests::new();
+        let payload = make_payload(b"GET / HTTP/1.1\r\n\r\n", 0);
+        subject.record_request(&payload, source_addr());
+        let timeout = Duration::from_secs(20);
+
+        subject.take_retry(&payload.stream_key);
+        let stalled = subject.stalled(Instant::now() + timeout, |_| timeout);
+        let retry = subject.take_retry(&payload.stream_key);
+        let abandoned = subject.abandon(&payload.stream_key);
+
+        assert_eq!(stalled, vec![payload.stream_key]);
+        assert_eq!(retry, None);
+        assert_eq!(abandoned, Some((payload.clone(), source_addr())));
+        assert_eq!(
+            subject.stalled(Instant::now() + timeout, |_| timeout),
+            vec![]
+        );
+    }
+}
@@ -0,0 +1,281 @@
+// Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use crate::sub_lib::cryptde::PublicKey;
+use crate::sub_lib::neighborhood::ExitSla;
+use crate::sub_lib::stream_key::StreamKey;
+use std::collections::HashMap;
+use std::time::Instant;
+
+pub const SLA_SAMPLE_BYTES: u64 = 256 * 1024;
+pub const SLA_SHORTFALL_DIVISOR: u64 = 4;
+
+#[derive(Clone, Debug, PartialEq)]
+struct Watched {
+    exit_key: PublicKey,
+    exit_sla: ExitSla,
+    first_response_opt: Option<Instant>,
+    bytes: u64,
+    judged: bool,
+}
+
+// The exits this Node's streams are leaving through, and what each of them advertised. A stream's
+// exit is judged once, after SLA_SAMPLE_BYTES of response have come back behind the first packet:
+// a little data says more about the server than about the exit. An exit that delivers less than
+// 1/SLA_SHORTFALL_DIVISOR of the rate it promised is reported.
+pub struct SlaMonitor {
+    streams: HashMap<StreamKey, Watched>,
+}
+
+impl SlaMonitor {
+    pub fn new() -> SlaMonitor {
+        SlaMonitor {
+            streams: HashMap::new(),
+        }
+    }
+
+    pub fn watch(&mut self, stream_key: StreamKey, exit_key: PublicKey, exit_sla: &ExitSla) {
+        match self.streams.get(&stream_key) {
+            Some(watched) if watched.exit_key == exit_key => (),
+            _ => {
+                self.streams.insert(
+                    stream_key,
+                    Watched {
+                        exit_key,
+                        exit_sla: exit_sla.clone(),
+                        first_response_opt: None,
+                        bytes: 0,
+                        judged: false,
+                    },
+                );
+            }
+        }
+    }
+
+    // The exit to report, if this response completes a sample that came in too slowly
+    pub fn delivered(
+        &mut self,
+        stream_key: &StreamKey,
+        bytes: u64,
+        now: Instant,
+    ) -> Option<PublicKey> {
+        let watched = self.streams.get_mut(stream_key)?;
+        if watched.judged {
+            return None;
+        }
+        let first_response = match watched.first_response_opt {
+            Some(first_response) => first_response,
+            None => {
+                watched.first_response_opt = Some(now);
+                return None;
+            }
+        };
+        watched.bytes += bytes;
+        if watched.bytes < SLA_SAMPLE_BYTES {
+            return None;
+        }
+        watched.judged = true;
+        let elapsed = now.duration_since(first_response);
+        let millis = (elapsed.as_secs() * 1000 + u64::from(elapsed.subsec_millis())).max(1);
+        let bytes_per_second = watched.bytes * 1000 / millis;
+        let promised = watched.exit_sla.max_stream_bytes_per_second;
+        if promised > 0 && bytes_per_second.saturating_mul(SLA_SHORTFALL_DIVISOR) < promised {
+            Some(watched.exit_key.clone())
+        } else {
+            None
+        }
+    }
+
+    pub fn forget(&mut self, stream_key: &StreamKey) {
+        self.streams.remove(stream_key);
+    }
+
+    // Exits already carrying as many of this Node's streams as they said they'd take, not counting
+    // the one that's asking
+    pub fn full_exits(&self, asking: &StreamKey) -> Vec<PublicKey> {
+        let mut counts: HashMap<&PublicKey, (u32, u32)> = HashMap::new();
+        self.streams
+            .iter()
+            .filter(|(stream_key, _)| *stream_key != asking)
+            .for_each(|(_, watched)| {
+                counts
+                    .entry(&watched.exit_key)
+                    .or_insert((0, watched.exit_sla.max_streams))
+                    .0 += 1;
+            });
+        let mut full = counts
+            .into_iter()
+            .filter(|(_, (count, max_streams))| *max_streams > 0 && count >= max_streams)
+            .map(|(exit_key, _)| exit_key.clone())
+            .collect::<Vec<PublicKey>>();
+        full.sort_by_key(|key| key.as_slice().to_vec());
+        full
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::SocketAddr;
+    use std::str::FromStr;
+    use std::time::Duration;
+
+    fn stream_key(port: u16) -> StreamKey {
+        StreamKey::new(
+            PublicKey::new(b"originator"),
+            SocketAddr::from_str(&format!("1.2.3.4:{}", port)).unwrap(),
+        )
+    }
+
+    fn exit_sla(max_streams: u32, max_stream_bytes_per_second: u64) -> ExitSla {
+        ExitSla {
+            max_streams,
+            max_stream_bytes_per_second,
+            content_policy_hash: String::new(),
+        }
+    }
+
+    #[test]
+    fn constants_have_correct_values() {
+        assert_eq!(SLA_SAMPLE_BYTES, 256 * 1024);
+        assert_eq!(SLA_SHORTFALL_DIVISOR, 4);
+    }
+
+    #[test]
+    fn an_exit_that_delivers_too_slowly_is_reported_once() {
+        let mut subject = SlaMonitor::new();
+        let exit_key = PublicKey::new(b"exit");
+        let then = Instant::now();
+        subject.watch(stream_key(1), exit_key.clone(), &exit_sla(10, 1024 * 1024));
+
+        assert_eq!(subject.delivered(&stream_key(1), 1000, then), None);
+        assert_eq!(
+            subject.delivered(
+                &stream_key(1),
+                SLA_SAMPLE_BYTES - 1,
+                then + Duration::from_secs(1)
+            ),
+            None
+        );
+        assert_eq!(
+            subject.delivered(&stream_key(1), 1, then + Duration::from_secs(2)),
+            Some(exit_key)
+        );
+        assert_eq!(
+            subject.delivered(
+                &stream_key(1),
+                SLA_SAMPLE_BYTES,
+                then + Duration::from_secs(10)
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn an_exit_that_delivers_a_quarter_of_its_promise_is_not_reported() {
+        let mut subject = SlaMonitor::new();
+        let then = Instant::now();
+        subject.watch(
+            stream_key(1),
+            PublicKey::new(b"exit"),
+            &exit_sla(10, SLA_SAMPLE_BYTES * SLA_SHORTFALL_DIVISOR),
+        );
+
+        subject.delivered(&stream_key(1), 1000, then);
+        let result = subject.delivered(
+            &stream_key(1),
+            SLA_SAMPLE_BYTES,
+            then + Duration::from_secs(1),
+        );
+
+        assert_eq!(result, None);
+    }
+
+    #[test]
+    fn an_exit_that_promises_no_rate_is_never_reported() {
+        let mut subject = SlaMonitor::new();
+        let then = Instant::now();
+        subject.watch(stream_key(1), PublicKey::new(b"exit"), &exit_sla(10, 0));
+
+        subject.delivered(&stream_key(1), 1000, then);
+        let result = subject.delivered(
+            &stream_key(1),
+            SLA_SAMPLE_BYTES,
+            then + Duration::from_secs(3600),
+        );
+
+        assert_eq!(result, None);
+    }
+
+    #[test]
+    fn unwatched_and_forgotten_streams_are_not_judged() {
+        let mut subject = SlaMonitor::new();
+        let then = Instant::now();
+        subject.watch(
+            stream_key(1),
+            PublicKey::new(b"exit"),
+            &exit_sla(10, u64::max_value()),
+        );
+        subject.delivered(&stream_key(1), 1000, then);
+
+        subject.forget(&stream_key(1));
+
+        assert_eq!(
+            subject.delivered(
+                &stream_key(1),
+                SLA_SAMPLE_BYTES,
+                then + Duration::from_secs(1)
+            ),
+            None
+        );
+        assert_eq!(
+            subject.delivered(
+                &stream_key(2),
+                SLA_SAMPLE_BYTES,
+                then + Duration::from_secs(1)
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn watching_a_stream_again_through_the_same_exit_keeps_its_sample() {
+        let mut subject = SlaMonitor::new();
+        let exit_key = PublicKey::new(b"exit");
+        let then = Instant::now();
+        subject.watch(
+            stream_key(1),
+            exit_key.clone(),
+            &exit_sla(10, u64::max_value()),
+        );
+        subject.delivered(&stream_key(1), 1000, then);
+
+        subject.watch(
+            stream_key(1),
+            exit_key.clone(),
+            &exit_sla(10, u64::max_value()),
+        );
+
+        assert_eq!(
+            subject.delivered(
+                &stream_key(1),
+                SLA_SAMPLE_BYTES,
+                then + Duration::from_secs(1)
+            ),
+            Some(exit_key)
+        );
+    }
+
+    #[test]
+    fn exits_carrying_as_many_streams_as_they_advertised_are_full() {
+        let mut subject = SlaMonitor::new();
+        let full_exit = PublicKey::new(b"full");
+        let roomy_exit = PublicKey::new(b"roomy");
+        let unlimited_exit = PublicKey::new(b"unlimited");
+        subject.watch(stream_key(1), full_exit.clone(), &exit_sla(2, 0));
+        subject.watch(stream_key(2), full_exit.clone(), &exit_sla(2, 0));
+        subject.watch(stream_key(3), roomy_exit.clone(), &exit_sla(2, 0));
+        subject.watch(stream_key(4), unlimited_exit.clone(), &exit_sla(0, 0));
+
+        assert_eq!(subject.full_exits(&stream_key(5)), vec![full_exit]);
+        assert_eq!(subject.full_exits(&stream_key(2)), Vec::<PublicKey>::new());
+    }
+}
@@ -0,0 +1,412 @@
+// Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use std::net::Ipv4Addr;
+use std::net::Ipv6Addr;
+
+const SOCKS_VERSION: u8 = 0x05;
+const NO_AUTHENTICATION: u8 = 0x00;
+const NO_ACCEPTABLE_METHODS: u8 = 0xFF;
+const CONNECT: u8 = 0x01;
+const IPV4_ADDRESS: u8 = 0x01;
+const DOMAIN_NAME: u8 = 0x03;
+const IPV6_ADDRESS: u8 = 0x04;
+
+const SUCCEEDED: u8 = 0x00;
+const GENERAL_FAILURE: u8 = 0x01;
+const NOT_ALLOWED: u8 = 0x02;
+const COMMAND_NOT_SUPPORTED: u8 = 0x07;
+const ADDRESS_TYPE_NOT_SUPPORTED: u8 = 0x08;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Socks5Target {
+    pub host: String,
+    pub port: u16,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Socks5Event {
+    // Goes back to the client; numbered ahead of anything the exit sends
+    Reply {
+        data: Vec<u8>,
+        last_data: bool,
+        sequence_number: u64,
+    },
+    // Goes on to the target; numbered from zero, as if the handshake had never happened
+    Data {
+        data: Vec<u8>,
+        last_data: bool,
+        sequence_number: u64,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum State {
+    AwaitingGreeting,
+    AwaitingRequest,
+    Connected(Socks5Target),
+    Closed,
+}
+
+// One client's side of RFC 1928, without authentication and with CONNECT as the only command.
+// The Node can't know whether the exit will reach the target, so a permitted CONNECT succeeds
+// at once; if the exit can't connect, the client sees the stream close.
+pub struct Socks5Session {
+    state: State,
+    data_so_far: Vec<u8>,
+    replies_sent: u64,
+    requests_sent: u64,
+}
+
+impl Socks5Session {
+    pub fn new() -> Socks5Session {
+        Socks5Session {
+            state: State::AwaitingGreeting,
+            data_so_far: vec![],
+            replies_sent: 0,
+            requests_sent: 0,
+        }
+    }
+
+    pub fn accept<F>(&mut self, data: &[u8], last_data: bool, permits: F) -> Vec<Socks5Event>
+    where
+        F: Fn(&Socks5Target) -> bool,
+    {
+        self.data_so_far.extend(data);
+        let mut events = vec![];
+        loop {
+            match self.state.clone() {
+                State::AwaitingGreeting => match self.take_greeting() {
+                    None => break,
+                    Some(true) => {
+                        events.push(self.reply(vec![SOCKS_VERSION, NO_AUTHENTICATION], false));
+                        self.state = State::AwaitingRequest;
+                    }
+                    Some(false) => {
+                        events.push(self.reply(vec![SOCKS_VERSION, NO_ACCEPTABLE_METHODS], true));
+                        self.state = State::Closed;
+                    }
+                },
+                State::AwaitingRequest => match self.take_request() {
+                    None => break,
+                    Some(Ok(target)) => {
+                        if permits(&target) {
+                            events.push(self.reply(Socks5Session::request_reply(SUCCEEDED), false));
+                            self.state = State::Connected(target);
+                        } else {
+                            events
+                                .push(self.reply(Socks5Session::request_reply(NOT_ALLOWED), true));
+                            self.state = State::Closed;
+                        }
+                    }
+                    Some(Err(reply_code)) => {
+                        events.push(self.reply(Socks5Session::request_reply(reply_code), true));
+                        self.state = State::Closed;
+                    }
+                },
+                State::Connected(_) => {
+                    if !self.data_so_far.is_empty() || last_data {
+                        events.push(Socks5Event::Data {
+                            data: self.data_so_far.drain(..).collect(),
+                            last_data,
+                            sequence_number: self.requests_sent,
+                        });
+                        self.requests_sent += 1;
+                    }
+                    break;
+                }
+                State::Closed => {
+                    self.data_so_far.clear();
+                    break;
+                }
+            }
+        }
+        events
+    }
+
+    pub fn target(&self) -> Option<&Socks5Target> {
+        match self.state {
+            State::Connected(ref target) => Some(target),
+            _ => None,
+        }
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.state == State::Closed
+    }
+
+    // What to add to the exit's sequence numbers so that they follow the handshake replies
+    pub fn replies_sent(&self) -> u64 {
+        self.replies_sent
+    }
+
+    fn reply(&mut self, data: Vec<u8>, last_data: bool) -> Socks5Event {
+        let sequence_number = self.replies_sent;
+        self.replies_sent += 1;
+        Socks5Event::Reply {
+            data,
+            last_data,
+            sequence_number,
+        }
+    }
+
+    // Some(true) if the client will do without authentication
+    fn take_greeting(&mut self) -> Option<bool> {
+        if self.data_so_far.len() < 2 {
+            return None;
+        }
+        if self.data_so_far[0] != SOCKS_VERSION {
+            return Some(false);
+        }
+        let greeting_len = 2 + self.data_so_far[1] as usize;
+        if self.data_so_far.len() < greeting_len {
+            return None;
+        }
+        let greeting: Vec<u8> = self.data_so_far.drain(..greeting_len).collect();
+        Some(greeting[2..].contains(&NO_AUTHENTICATION))
+    }
+
+    fn take_request(&mut self) -> Option<Result<Socks5Target, u8>> {
+        if self.data_so_far.len() < 5 {
+            return None;
+        }
+        if self.data_so_far[0] != SOCKS_VERSION {
+            return Some(Err(GENERAL_FAILURE));
+        }
+        let address_len = match self.data_so_far[3] {
+            IPV4_ADDRESS => 4,
+            DOMAIN_NAME => 1 + self.data_so_far[4] as usize,
+            IPV6_ADDRESS => 16,
+            _ => return Some(Err(ADDRESS_TYPE_NOT_SUPPORTED)),
+        };
+        let request_len = 4 + address_len + 2;
+        if self.data_so_far.len() < request_len {
+            return None;
+        }
+        let request: Vec<u8> = self.data_so_far.drain(..request_len).collect();
+        if request[1] != CONNECT {
+            return Some(Err(COMMAND_NOT_SUPPORTED));
+        }
+        let address = &request[4..(4 + address_len)];
+        let host = match request[3] {
+            IPV4_ADDRESS => {
+                Ipv4Addr::new(address[0], address[1], address[2], address[3]).to_string()
+            }
+            IPV6_ADDRESS => {
+                let mut octets = [0u8; 16];
+                octets.copy_from_slice(address);
+                Ipv6Addr::from(octets).to_string()
+            }
+            _ => String::from_utf8_lossy(&address[1..]).to_string(),
+        };
+        let port = ((request[request_len - 2] as u16) << 8) | (request[request_len - 1] as u16);
+        Some(Ok(Socks5Target { host, port }))
+    }
+
+    // The bound address in a reply means nothing to a client of a proxy like this one
+    fn request_reply(reply_code: u8) -> Vec<u8> {
+        let mut reply = vec![SOCKS_VERSION, reply_code, 0x00, IPV4_ADDRESS];
+        reply.extend(&[0u8; 6]);
+        reply
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn permit_all(_: &Socks5Target) -> bool {
+        true
+    }
+
+    fn connect_request(host: &str, port: u16) -> Vec<u8> {
+        let mut request = vec![SOCKS_VERSION, CONNECT, 0x00, DOMAIN_NAME, host.len() as u8];
+        request.extend(host.as_bytes());
+        request.extend(&[(port >> 8) as u8, (port & 0xFF) as u8]);
+        request
+    }
+
+    fn reply(data: Vec<u8>, last_data: bool, sequence_number: u64) -> Socks5Event {
+        Socks5Event::Reply {
+            data,
+            last_data,
+            sequence_number,
+        }
+    }
+
+    #[test]
+    fn handshake_leads_to_connection_and_data_numbered_from_zero() {
+        let mut subject = Socks5Session::new();
+
+        let greeting_events = subject.accept(&[0x05, 0x02, 0x02, 0x00], false, permit_all);
+        let request_events = subject.accept(&connect_request("booga.com", 443), false, permit_all);
+        let data_events = subject.accept(b"hello", false, permit_all);
+        let closing_events = subject.accept(&[], true, permit_all);
+
+        assert_eq!(greeting_events, vec![reply(vec![0x05, 0x00], false, 0)]);
+        assert_eq!(
+            request_events,
+            vec![reply(
+                vec![0x05, 0x00, 0x00, 0x01, 0, 0, 0, 0, 0, 0],
+                false,
+                1
+            )]
+        );
+        assert_eq!(
+            data_events,
+            vec![Socks5Event::Data {
+                data: b"hello".to_vec(),
+                last_data: false,
+                sequence_number: 0
+            }]
+        );
+        assert_eq!(
+            closing_events,
+            vec![Socks5Event::Data {
+                data: vec![],
+                last_data: true,
+                sequence_number: 1
+            }]
+        );
+        assert_eq!(
+            subject.target(),
+            Some(&Socks5Target {
+                host: String::from("booga.com"),
+                port: 443
+            })
+        );
+        assert_eq!(subject.replies_sent(), 2);
+    }
+
+    #[test]
+    fn handshake_survives_fragmentation_and_pipelining() {
+        let mut subject = Socks5Session::new();
+        let mut everything = vec![0x05, 0x01, 0x00];
+        everything.extend(connect_request("booga.com", 80));
+        everything.extend(b"GET / HTTP/1.1\r\n\r\n");
+
+        let mut events = subject.accept(&everything[..4], false, permit_all);
+        events.extend(subject.accept(&everything[4..], false, permit_all));
+
+        assert_eq!(
+            events,
+            vec![
+                reply(vec![0x05, 0x00], false, 0),
+                reply(vec![0x05, 0x00, 0x00, 0x01, 0, 0, 0, 0, 0, 0], false, 1),
+                Socks5Event::Data {
+                    data: b"GET / HTTP/1.1\r\n\r\n".to_vec(),
+                    last_data: false,
+                    sequence_number: 0
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn numeric_addresses_become_host_names() {
+        let mut ipv4_subject = Socks5Session::new();
+        let mut ipv6_subject = Socks5Session::new();
+        ipv4_subject.accept(&[0x05, 0x01, 0x00], false, permit_all);
+        ipv6_subject.accept(&[0x05, 0x01, 0x00], false, permit_all);
+        let mut ipv6_request = vec![0x05, 0x01, 0x00, 0x04];
+        ipv6_request.extend(&[0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+        ipv6_request.extend(&[0x00, 0x50]);
+
+        ipv4_subject.accept(
+            &[0x05, 0x01, 0x00, 0x01, 1, 2, 3, 4, 0x01, 0xBB],
+            false,
+            permit_all,
+        );
+        ipv6_subject.accept(&ipv6_request, false, permit_all);
+
+        assert_eq!(
+            ipv4_subject.target(),
+            Some(&Socks5Target {
+                host: String::from("1.2.3.4"),
+                port: 443
+            })
+        );
+        assert_eq!(
+            ipv6_subject.target(),
+            Some(&Socks5Target {
+                host: String::from("2001:db8::1"),
+                port: 80
+            })
+        );
+    }
+
+    #[test]
+    fn clients_that_insist_on_authentication_are_turned_away() {
+        let mut subject = Socks5Session::new();
+
+        let events = subject.accept(&[0x05, 0x01, 0x02], false, permit_all);
+
+        assert_eq!(events, vec![reply(vec![0x05, 0xFF], true, 0)]);
+        assert!(subject.is_closed());
+    }
+
+    #[test]
+    fn clients_that_speak_another_version_are_turned_away() {
+        let mut subject = Socks5Session::new();
+
+        let events = subject.accept(&[0x04, 0x01, 0x00, 0x50], false, permit_all);
+
+        assert_eq!(events, vec![reply(vec![0x05, 0xFF], true, 0)]);
+        assert!(subject.is_closed());
+    }
+
+    #[test]
+    fn commands_other_than_connect_are_refused() {
+        let mut subject = Socks5Session::new();
+        subject.accept(&[0x05, 0x01, 0x00], false, permit_all);
+
+        let events = subject.accept(
+            &[0x05, 0x02, 0x00, 0x01, 1, 2, 3, 4, 0x00, 0x50],
+            false,
+            permit_all,
+        );
+
+        assert_eq!(
+            events,
+            vec![reply(
+                Socks5Session::request_reply(COMMAND_NOT_SUPPORTED),
+                true,
+                1
+            )]
+        );
+        assert!(subject.is_closed());
+    }
+
+    #[test]
+    fn unknown_address_types_are_refused() {
+        let mut subject = Socks5Session::new();
+        subject.accept(&[0x05, 0x01, 0x00], false, permit_all);
+
+        let events = subject.accept(&[0x05, 0x01, 0x00, 0x09, 1], false, permit_all);
+
+        assert_eq!(
+            events,
+            vec![reply(
+                Socks5Session::request_reply(ADDRESS_TYPE_NOT_SUPPORTED),
+                true,
+                1
+            )]
+        );
+    }
+
+    #[test]
+    fn targets_that_are_not_permitted_are_refused_and_nothing_more_is_accepted() {
+        let mut subject = Socks5Session::new();
+        subject.accept(&[0x05, 0x01, 0x00], false, permit_all);
+
+        let events = subject.accept(&connect_request("booga.com", 6881), false, |target| {
+            target.port != 6881
+        });
+        let later_events = subject.accept(b"hello", false, permit_all);
+
+        assert_eq!(
+            events,
+            vec![reply(Socks5Session::request_reply(NOT_ALLOWED), true, 1)]
+        );
+        assert_eq!(later_events, vec![]);
+        assert_eq!(subject.target(), None);
+    }
+}
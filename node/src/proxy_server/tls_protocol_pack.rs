@@ -1,11 +1,33 @@
 // Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use crate::discriminator::DiscriminatorFactory;
+use crate::proxy_server::privacy_policy::PrivacyPolicy;
 use crate::proxy_server::protocol_pack::ProtocolPack;
 use crate::sub_lib::cryptde::PlainData;
 use crate::sub_lib::proxy_server::ProxyProtocol;
+use crate::tls_discriminator_factory::TlsDiscriminatorFactory;
 
 pub struct TlsProtocolPack {}
 
+#[derive(Clone, Debug, PartialEq)]
+pub enum ClientHelloStatus {
+    NotClientHello,
+    Incomplete,
+    Complete(Option<String>),
+}
+
 impl ProtocolPack for TlsProtocolPack {
+    fn name(&self) -> &'static str {
+        "tls"
+    }
+
+    fn standard_ports(&self) -> Vec<u16> {
+        vec![443]
+    }
+
+    fn discriminator_factory(&self) -> Box<dyn DiscriminatorFactory> {
+        Box::new(TlsDiscriminatorFactory::new())
+    }
+
     fn proxy_protocol(&self) -> ProxyProtocol {
         ProxyProtocol::TLS
     }
@@ -19,15 +41,84 @@ impl ProtocolPack for TlsProtocolPack {
         }
         TlsProtocolPack::find_host_name(&data)
     }
+
+    // Everything past the ClientHello is encrypted, and the ClientHello itself can't be changed
+    // without breaking the handshake.
+    fn filter_request(&self, data: &PlainData, _policy: &PrivacyPolicy) -> PlainData {
+        data.clone()
+    }
 }
 
 impl TlsProtocolPack {
+    pub fn make() -> Box<dyn ProtocolPack> {
+        Box::new(TlsProtocolPack {})
+    }
+
+    // Some clients split the ClientHello across several TLS records or TCP segments. This examines
+    // everything received so far on a stream and says whether more is needed before the host name
+    // can be known.
+    pub fn client_hello_status(data: &PlainData) -> ClientHelloStatus {
+        if !TlsProtocolPack::is_handshake(data) {
+            return ClientHelloStatus::NotClientHello;
+        }
+        let (handshake, truncated) = TlsProtocolPack::reassemble_handshake(data.as_slice());
+        if handshake.len() < 4 {
+            if truncated {
+                return ClientHelloStatus::Incomplete;
+            }
+            // Nothing usable in the record lengths; fall back to reading the data as it stands
+            return if TlsProtocolPack::is_client_hello(data) {
+                ClientHelloStatus::Complete(TlsProtocolPack::find_host_name(data))
+            } else {
+                ClientHelloStatus::NotClientHello
+            };
+        }
+        if handshake[0] != 0x01 {
+            return ClientHelloStatus::NotClientHello;
+        }
+        let client_hello_length = ((handshake[1] as usize) << 16)
+            | ((handshake[2] as usize) << 8)
+            | (handshake[3] as usize);
+        if handshake.len() < 4 + client_hello_length {
+            return ClientHelloStatus::Incomplete;
+        }
+        let mut reassembled = vec![0x16, 0x03, 0x01, 0x00, 0x00];
+        reassembled.extend(&handshake[..(4 + client_hello_length)]);
+        ClientHelloStatus::Complete(TlsProtocolPack::find_host_name(&PlainData::from(
+            reassembled,
+        )))
+    }
+
+    fn reassemble_handshake(data: &[u8]) -> (Vec<u8>, bool) {
+        let mut handshake = vec![];
+        let mut record_offset = 0;
+        while record_offset < data.len() {
+            if data[record_offset] != 0x16 {
+                return (handshake, false);
+            }
+            if record_offset + 5 > data.len() {
+                return (handshake, true);
+            }
+            let body_offset = record_offset + 5;
+            let body_end = body_offset
+                + ((data[record_offset + 3] as usize) << 8)
+                + (data[record_offset + 4] as usize);
+            if body_end > data.len() {
+                handshake.extend(&data[body_offset..]);
+                return (handshake, true);
+            }
+            handshake.extend(&data[body_offset..body_end]);
+            record_offset = body_end;
+        }
+        (handshake, false)
+    }
+
     fn is_handshake(data: &PlainData) -> bool {
         data.as_slice().first() == Some(&0x16)
     }
 
     fn is_client_hello(data: &PlainData) -> bool {
-        data.as_slice()[5] == 0x01
+        data.as_slice().get(5) == Some(&0x01)
     }
 
     fn find_host_name(data: &PlainData) -> Option<String> {
@@ -39,6 +130,9 @@ impl TlsProtocolPack {
         let extensions_end = TlsProtocolPack::advance_past(data, extensions_offset, 2)?;
         let mut extension_offset = extensions_offset + 2;
         while extension_offset < extensions_end {
+            if extension_offset + 2 > data.len() {
+                return None;
+            }
             let extension_type = TlsProtocolPack::u16_from(data, extension_offset);
             if extension_type == 0x0000 {
                 return TlsProtocolPack::host_name_from_extension(data, extension_offset);
@@ -53,6 +147,9 @@ impl TlsProtocolPack {
         let server_name_list_end = TlsProtocolPack::advance_past(data, server_name_list_offset, 2)?;
         let mut server_name_list_entry_offset = server_name_list_offset + 2;
         while server_name_list_entry_offset < server_name_list_end {
+            if server_name_list_entry_offset >= data.len() {
+                return None;
+            }
             let server_name_type = TlsProtocolPack::u8_from(data, server_name_list_entry_offset);
             if server_name_type == 0x00 {
                 return TlsProtocolPack::host_name_from_list_entry(
@@ -67,8 +164,14 @@ impl TlsProtocolPack {
     }
 
     fn host_name_from_list_entry(data: &PlainData, offset: usize) -> Option<String> {
+        if offset + 3 > data.len() {
+            return None;
+        }
         let server_name_length = TlsProtocolPack::u16_from(data, offset + 1);
         let server_name_offset = offset + 3;
+        if server_name_offset + server_name_length > data.len() {
+            return None;
+        }
         match String::from_utf8(Vec::from(
             &data.as_slice()[(server_name_offset)..(server_name_offset + server_name_length)],
         )) {
@@ -102,6 +205,24 @@ impl TlsProtocolPack {
 mod tests {
     use super::*;
 
+    #[test]
+    fn filter_request_leaves_tls_alone() {
+        let data = PlainData::new(&[0x16, 0x03, 0x01, 0x00, 0x00]);
+        let policy = PrivacyPolicy::from_json(r#"{"strip_headers": ["DNT"]}"#).unwrap();
+
+        let result = TlsProtocolPack {}.filter_request(&data, &policy);
+
+        assert_eq!(result, data);
+    }
+
+    #[test]
+    fn knows_its_name_and_standard_ports() {
+        let subject = TlsProtocolPack {};
+
+        assert_eq!(subject.name(), "tls");
+        assert_eq!(subject.standard_ports(), vec![443]);
+    }
+
     #[test]
     fn knows_its_protocol() {
         let result = TlsProtocolPack {}.proxy_protocol();
@@ -481,4 +602,128 @@ mod tests {
 
         assert_eq!(result, None);
     }
+
+    fn client_hello_handshake() -> Vec<u8> {
+        let server_name = b"server.com";
+        let mut server_name_extension = vec![0x00, 0x00]; // extension_type: server_name
+        let server_name_list_length = 3 + server_name.len();
+        let extension_length = 2 + server_name_list_length;
+        server_name_extension.extend(&[0x00, extension_length as u8]);
+        server_name_extension.extend(&[0x00, server_name_list_length as u8]);
+        server_name_extension.extend(&[0x00, 0x00, server_name.len() as u8]);
+        server_name_extension.extend(&server_name[..]);
+        let mut body = vec![0x03, 0x03]; // version
+        body.extend(&[0x00; 32]); // random
+        body.push(0x00); // session_id_length
+        body.extend(&[0x00, 0x02, 0x13, 0x01]); // cipher_suites
+        body.extend(&[0x01, 0x00]); // compression_methods
+        body.extend(&[0x00, server_name_extension.len() as u8]); // extensions_length
+        body.extend(server_name_extension);
+        let mut handshake = vec![0x01, 0x00, 0x00, body.len() as u8]; // ClientHello
+        handshake.extend(body);
+        handshake
+    }
+
+    fn tls_record(fragment: &[u8]) -> Vec<u8> {
+        let mut record = vec![
+            0x16, // content_type: Handshake
+            0x03,
+            0x01, // version
+            (fragment.len() >> 8) as u8,
+            (fragment.len() & 0xFF) as u8,
+        ];
+        record.extend(fragment);
+        record
+    }
+
+    #[test]
+    fn does_not_panic_for_lone_handshake_content_type_byte() {
+        let data = PlainData::new(&[0x16]);
+
+        let result = TlsProtocolPack {}.find_host_name(&data);
+
+        assert_eq!(result, None);
+    }
+
+    #[test]
+    fn client_hello_status_is_complete_for_client_hello_in_one_record() {
+        let data = PlainData::from(tls_record(&client_hello_handshake()));
+
+        let result = TlsProtocolPack::client_hello_status(&data);
+
+        assert_eq!(
+            result,
+            ClientHelloStatus::Complete(Some(String::from("server.com")))
+        );
+    }
+
+    #[test]
+    fn client_hello_status_is_incomplete_for_record_truncated_by_tcp_segmentation() {
+        let record = tls_record(&client_hello_handshake());
+        let data = PlainData::new(&record[..60]);
+
+        let result = TlsProtocolPack::client_hello_status(&data);
+
+        assert_eq!(result, ClientHelloStatus::Incomplete);
+    }
+
+    #[test]
+    fn client_hello_status_is_incomplete_for_partial_record_header() {
+        let data = PlainData::new(&[0x16, 0x03]);
+
+        let result = TlsProtocolPack::client_hello_status(&data);
+
+        assert_eq!(result, ClientHelloStatus::Incomplete);
+    }
+
+    #[test]
+    fn client_hello_status_reassembles_client_hello_split_across_records() {
+        let handshake = client_hello_handshake();
+        let first_record = tls_record(&handshake[..50]);
+        let mut both_records = first_record.clone();
+        both_records.extend(tls_record(&handshake[50..]));
+
+        let first_result = TlsProtocolPack::client_hello_status(&PlainData::from(first_record));
+        let both_result = TlsProtocolPack::client_hello_status(&PlainData::from(both_records));
+
+        assert_eq!(first_result, ClientHelloStatus::Incomplete);
+        assert_eq!(
+            both_result,
+            ClientHelloStatus::Complete(Some(String::from("server.com")))
+        );
+    }
+
+    #[test]
+    fn client_hello_status_reassembles_client_hello_split_into_many_tiny_records() {
+        let handshake = client_hello_handshake();
+        let data: Vec<u8> = handshake
+            .chunks(7)
+            .flat_map(|fragment| tls_record(fragment))
+            .collect();
+
+        let result = TlsProtocolPack::client_hello_status(&PlainData::from(data));
+
+        assert_eq!(
+            result,
+            ClientHelloStatus::Complete(Some(String::from("server.com")))
+        );
+    }
+
+    #[test]
+    fn client_hello_status_rejects_non_handshake() {
+        let data = PlainData::new(&[0x17, 0x03, 0x03, 0x00, 0x01, 0xFF]);
+
+        let result = TlsProtocolPack::client_hello_status(&data);
+
+        assert_eq!(result, ClientHelloStatus::NotClientHello);
+    }
+
+    #[test]
+    fn client_hello_status_rejects_handshake_other_than_client_hello() {
+        let data = PlainData::from(tls_record(&[0x10, 0x00, 0x00, 0x01, 0xFF]));
+
+        let result = TlsProtocolPack::client_hello_status(&data);
+
+        assert_eq!(result, ClientHelloStatus::NotClientHello);
+    }
 }
@@ -0,0 +1,129 @@
+// Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use std::cmp::max;
+use std::collections::VecDeque;
+
+// How many request packets of one stream the originator lets into the route at once, unless
+// --upload_window says otherwise
+pub const DEFAULT_UPLOAD_WINDOW: u64 = 64;
+
+// Paces the request packets of one stream against the exit's count of how many it has received.
+// Until the first count comes back, the exit may not be one that counts, so nothing is held.
+pub struct UploadWindow<T> {
+    size: u64,
+    sent: u64,
+    received_opt: Option<u64>,
+    held: VecDeque<T>,
+}
+
+impl<T> UploadWindow<T> {
+    pub fn new(size: u64) -> UploadWindow<T> {
+        UploadWindow {
+            size,
+            sent: 0,
+            received_opt: None,
+            held: VecDeque::new(),
+        }
+    }
+
+    // Returns the packet if it may go now; otherwise holds it, behind any others already held
+    pub fn offer(&mut self, packet: T) -> Option<T> {
+        if self.held.is_empty() && self.has_room() {
+            self.sent += 1;
+            Some(packet)
+        } else {
+            self.held.push_back(packet);
+            None
+        }
+    }
+
+    // Returns the held packets that may go now, in the order they were offered
+    pub fn acknowledge(&mut self, requests_received: u64) -> Vec<T> {
+        let received = self.received_opt.unwrap_or(0);
+        self.received_opt = Some(max(received, requests_received));
+        let mut released = vec![];
+        while self.has_room() {
+            match self.held.pop_front() {
+                Some(packet) => {
+                    self.sent += 1;
+                    released.push(packet)
+                }
+                None => break,
+            }
+        }
+        released
+    }
+
+    pub fn in_flight(&self) -> u64 {
+        self.sent.saturating_sub(self.received_opt.unwrap_or(0))
+    }
+
+    pub fn held_count(&self) -> usize {
+        self.held.len()
+    }
+
+    fn has_room(&self) -> bool {
+        match self.received_opt {
+            None => true,
+            Some(_) => self.in_flight() < self.size,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nothing_is_held_until_the_exit_counts_what_it_receives() {
+        let mut subject = UploadWindow::new(2);
+
+        let results: Vec<Option<u32>> = (0..5).map(|packet| subject.offer(packet)).collect();
+
+        assert_eq!(results, vec![Some(0), Some(1), Some(2), Some(3), Some(4)]);
+        assert_eq!(subject.held_count(), 0);
+    }
+
+    #[test]
+    fn packets_beyond_the_window_are_held_until_acknowledged() {
+        let mut subject = UploadWindow::new(2);
+        subject.offer(0).unwrap();
+        assert_eq!(subject.acknowledge(0), Vec::<u32>::new());
+
+        let results: Vec<Option<u32>> = (1..5).map(|packet| subject.offer(packet)).collect();
+
+        assert_eq!(results, vec![Some(1), None, None, None]);
+        assert_eq!(subject.in_flight(), 2);
+        assert_eq!(subject.held_count(), 3);
+        assert_eq!(subject.acknowledge(1), vec![2]);
+        assert_eq!(subject.acknowledge(3), vec![3, 4]);
+        assert_eq!(subject.held_count(), 0);
+        assert_eq!(subject.in_flight(), 2);
+    }
+
+    #[test]
+    fn held_packets_keep_their_place_in_line() {
+        let mut subject = UploadWindow::new(1);
+        subject.offer(0).unwrap();
+        subject.acknowledge(0);
+        assert_eq!(subject.offer(1), None);
+        assert_eq!(subject.offer(2), None);
+
+        assert_eq!(subject.acknowledge(1), vec![1]);
+        assert_eq!(subject.offer(3), None);
+        assert_eq!(subject.acknowledge(2), vec![2]);
+        assert_eq!(subject.acknowledge(3), vec![3]);
+    }
+
+    #[test]
+    fn stale_acknowledgements_are_ignored() {
+        let mut subject = UploadWindow::new(1);
+        subject.offer(0).unwrap();
+        subject.acknowledge(1);
+        subject.offer(1).unwrap();
+
+        assert_eq!(subject.offer(2), None);
+        assert_eq!(subject.acknowledge(0), Vec::<u32>::new());
+        assert_eq!(subject.in_flight(), 1);
+        assert_eq!(subject.acknowledge(2), vec![2]);
+    }
+}
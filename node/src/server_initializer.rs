@@ -3,6 +3,13 @@ use super::bootstrapper::Bootstrapper;
 use super::entry_dns::new_dns_socket_server;
 use super::privilege_drop::PrivilegeDropper;
 use super::privilege_drop::PrivilegeDropperReal;
+use super::privilege_drop::RunAsIds;
+use crate::config_file;
+use crate::diagnostics;
+use crate::sub_lib::log_rotation::LogJanitor;
+use crate::sub_lib::log_rotation::LOG_JANITOR_INTERVAL;
+use crate::sub_lib::logger;
+use crate::sub_lib::logger::LogFormat;
 use crate::sub_lib::main_tools::Command;
 use crate::sub_lib::main_tools::StdStreams;
 use crate::sub_lib::parameter_finder::ParameterFinder;
@@ -12,7 +19,9 @@ use flexi_logger::LevelFilter;
 use flexi_logger::LogSpecification;
 use flexi_logger::Logger;
 use futures::try_ready;
+use std::env;
 use std::env::temp_dir;
+use std::path::Path;
 use std::str::FromStr;
 use tokio::prelude::Async;
 use tokio::prelude::Future;
@@ -32,7 +41,15 @@ where
     P: PrivilegeDropper,
 {
     fn go(&mut self, streams: &mut StdStreams<'_>, args: &Vec<String>) -> u8 {
+        let args = &match config_file::complete_args(args, env::vars_os(), streams.stderr) {
+            Ok(args) => args,
+            Err(msg) => {
+                writeln!(streams.stderr, "{}", msg).expect("Internal error");
+                return 1;
+            }
+        };
         self.logger_initializer_wrapper.init(args);
+        let run_as = RunAsIds::from_args(args);
 
         self.dns_socket_server
             .as_mut()
@@ -41,12 +58,14 @@ where
             .as_mut()
             .initialize_as_privileged(args, streams);
 
-        self.privilege_dropper.drop_privileges();
+        self.privilege_dropper
+            .chown(&Bootstrapper::data_directory(args), &run_as);
+        self.privilege_dropper.drop_privileges(&run_as);
 
         self.dns_socket_server.as_mut().initialize_as_unprivileged();
         self.bootstrapper.as_mut().initialize_as_unprivileged();
 
-        1
+        0
     }
 }
 
@@ -86,17 +105,36 @@ struct LoggerInitializerWrapperReal {}
 
 impl LoggerInitializerWrapper for LoggerInitializerWrapperReal {
     fn init(&mut self, args: &Vec<String>) -> bool {
-        match Logger::with(
+        let log_format = LoggerInitializerWrapperReal::get_log_format(args);
+        logger::set_log_format(log_format);
+        let mut log_builder = Logger::with(
             LogSpecification::default(LoggerInitializerWrapperReal::get_log_level(args)).finalize(),
-        )
-        .log_to_file()
-        .directory(&temp_dir().to_str().expect("Bad temporary filename")[..])
-        .print_message()
-        .duplicate_to_stderr(Duplicate::Info)
-        .suppress_timestamp()
-        .start()
+        );
+        if log_format == LogFormat::Json {
+            log_builder = log_builder.format(logger::message_only);
+        }
+        let max_size = LoggerInitializerWrapperReal::get_log_max_size(args);
+        let max_files = LoggerInitializerWrapperReal::get_log_max_files(args);
+        let compress = LoggerInitializerWrapperReal::get_log_compress(args);
+        match log_builder
+            .log_to_file()
+            .directory(&temp_dir().to_str().expect("Bad temporary filename")[..])
+            .o_rotate_over_size(max_size)
+            .print_message()
+            .duplicate_to_stderr(Duplicate::Info)
+            .suppress_timestamp()
+            .start()
         {
-            Ok(_) => true,
+            Ok(handle) => {
+                logger::keep_reconfiguration_handle(handle);
+                diagnostics::capture_actor_panics(&Bootstrapper::data_directory(args));
+                // Without rotation there's only ever the one file
+                if max_size.is_some() && (max_files.is_some() || compress) {
+                    LogJanitor::new(&temp_dir(), &program_name(), max_files, compress)
+                        .start(LOG_JANITOR_INTERVAL);
+                }
+                true
+            }
             Err(_) => false,
         }
     }
@@ -115,6 +153,62 @@ impl LoggerInitializerWrapperReal {
             None => LevelFilter::Warn,
         }
     }
+
+    fn get_log_format(args: &Vec<String>) -> LogFormat {
+        let parameter_tag = "--log_format";
+        let usage = "should be one of <text|json> (default = text)";
+
+        match ParameterFinder::new(args.clone()).find_value_for(parameter_tag, usage) {
+            Some(ref value) if value == "text" => LogFormat::Text,
+            Some(ref value) if value == "json" => LogFormat::Json,
+            Some(value) => panic!("Bad value '{}' for {}: {}", value, parameter_tag, usage),
+            None => LogFormat::Text,
+        }
+    }
+
+    fn get_log_max_size(args: &Vec<String>) -> Option<usize> {
+        let usage = "--log_max_size <megabytes, or 0 for no rotation>";
+        match ParameterFinder::new(args.clone()).find_value_for("--log_max_size", usage) {
+            Some(megabytes_string) => match str::parse::<usize>(megabytes_string.as_str()) {
+                Ok(0) => None,
+                Ok(megabytes) => Some(megabytes * 1024 * 1024),
+                Err(_) => panic!("Invalid size for {}: '{}'", usage, megabytes_string),
+            },
+            None => None,
+        }
+    }
+
+    fn get_log_max_files(args: &Vec<String>) -> Option<usize> {
+        let usage = "--log_max_files <rotated files to keep, or 0 to keep them all>";
+        match ParameterFinder::new(args.clone()).find_value_for("--log_max_files", usage) {
+            Some(count_string) => match str::parse::<usize>(count_string.as_str()) {
+                Ok(0) => None,
+                Ok(count) => Some(count),
+                Err(_) => panic!("Invalid count for {}: '{}'", usage, count_string),
+            },
+            None => None,
+        }
+    }
+
+    fn get_log_compress(args: &Vec<String>) -> bool {
+        let parameter_tag = "--log_compress";
+        let usage = "--log_compress on|off";
+        match ParameterFinder::new(args.clone()).find_value_for(parameter_tag, usage) {
+            Some(ref value) if value == "on" => true,
+            Some(ref value) if value == "off" => false,
+            Some(value) => panic!("{} must be on or off, not {}", parameter_tag, value),
+            None => false,
+        }
+    }
+}
+
+// What flexi_logger names the log file after
+fn program_name() -> String {
+    let arg0 = env::args().nth(0).unwrap_or_else(|| String::from("rs"));
+    Path::new(&arg0)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_else(|| String::from("rs"))
 }
 
 #[cfg(test)]
@@ -125,6 +219,9 @@ mod tests {
     use crate::test_utils::logging::init_test_logging;
     use crate::test_utils::test_utils::ByteArrayReader;
     use crate::test_utils::test_utils::ByteArrayWriter;
+    use std::fs;
+    use std::path::Path;
+    use std::path::PathBuf;
     use std::sync::Arc;
     use std::sync::Mutex;
 
@@ -141,20 +238,35 @@ mod tests {
 
     struct PrivilegeDropperMock {
         call_count: Arc<Mutex<usize>>,
+        drop_privileges_parameters: Arc<Mutex<Vec<RunAsIds>>>,
+        chown_parameters: Arc<Mutex<Vec<(PathBuf, RunAsIds)>>>,
     }
 
     impl PrivilegeDropperMock {
         pub fn new() -> PrivilegeDropperMock {
             PrivilegeDropperMock {
                 call_count: Arc::new(Mutex::new(0)),
+                drop_privileges_parameters: Arc::new(Mutex::new(vec![])),
+                chown_parameters: Arc::new(Mutex::new(vec![])),
             }
         }
     }
 
     impl PrivilegeDropper for PrivilegeDropperMock {
-        fn drop_privileges(&self) {
+        fn drop_privileges(&self, run_as: &RunAsIds) {
             let mut calls = self.call_count.lock().unwrap();
             *calls += 1;
+            self.drop_privileges_parameters
+                .lock()
+                .unwrap()
+                .push(run_as.clone());
+        }
+
+        fn chown(&self, data_directory: &Path, run_as: &RunAsIds) {
+            self.chown_parameters
+                .lock()
+                .unwrap()
+                .push((data_directory.to_path_buf(), run_as.clone()));
         }
     }
 
@@ -215,6 +327,53 @@ mod tests {
         assert!(res.is_err());
     }
 
+    #[test]
+    fn go_completes_args_from_the_config_file_before_anything_reads_them() {
+        let directory = PathBuf::from(
+            "generated/test/server_initializer/go_completes_args_from_the_config_file_before_anything_reads_them",
+        );
+        fs::create_dir_all(&directory).is_ok();
+        let config_file = directory.join("config.toml");
+        fs::write(&config_file, "log_level = \"debug\"\n").unwrap();
+        let mut logger_initializer_wrapper_mock = LoggerInitializerWrapperMock::new();
+        let logger_init_parameters: Arc<Mutex<Vec<Vec<String>>>> = Arc::new(Mutex::new(vec![]));
+        logger_initializer_wrapper_mock.init_parameters(&logger_init_parameters);
+        let mut subject = ServerInitializer {
+            dns_socket_server: Box::new(CrashTestDummy::new(CrashPoint::None)),
+            bootstrapper: Box::new(CrashTestDummy::new(CrashPoint::None)),
+            privilege_dropper: PrivilegeDropperMock::new(),
+            logger_initializer_wrapper: Box::new(logger_initializer_wrapper_mock),
+        };
+        let stdin = &mut ByteArrayReader::new(&[0; 0]);
+        let stdout = &mut ByteArrayWriter::new();
+        let stderr = &mut ByteArrayWriter::new();
+        let streams = &mut StdStreams {
+            stdin,
+            stdout,
+            stderr,
+        };
+        let config_file_string = config_file.to_string_lossy().to_string();
+        let args = vec![
+            String::from("SubstratumNode"),
+            String::from("--config_file"),
+            config_file_string.clone(),
+        ];
+
+        let exit_code = subject.go(streams, &args);
+
+        assert_eq!(exit_code, 0);
+        assert_eq!(
+            *logger_init_parameters.lock().unwrap(),
+            vec![vec![
+                String::from("SubstratumNode"),
+                String::from("--config_file"),
+                config_file_string,
+                String::from("--log_level"),
+                String::from("debug"),
+            ]]
+        );
+    }
+
     #[test]
     fn server_initializer_as_a_future() {
         let dns_socket_server = CrashTestDummy::new(CrashPoint::None);
@@ -340,6 +499,94 @@ mod tests {
         LoggerInitializerWrapperReal::get_log_level(&args);
     }
 
+    #[test]
+    fn get_log_format_returns_text_by_default_and_json_if_asked() {
+        assert_eq!(
+            LoggerInitializerWrapperReal::get_log_format(&vec![]),
+            LogFormat::Text
+        );
+        assert_eq!(
+            LoggerInitializerWrapperReal::get_log_format(&vec![
+                String::from("--log_format"),
+                String::from("json")
+            ]),
+            LogFormat::Json
+        );
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Bad value 'xml' for --log_format: should be one of <text|json> (default = text)"
+    )]
+    fn get_log_format_panics_if_arg_makes_no_sense() {
+        let args = vec![String::from("--log_format"), String::from("xml")];
+
+        LoggerInitializerWrapperReal::get_log_format(&args);
+    }
+
+    #[test]
+    fn log_rotation_is_off_by_default() {
+        let args = vec![];
+
+        assert_eq!(LoggerInitializerWrapperReal::get_log_max_size(&args), None);
+        assert_eq!(LoggerInitializerWrapperReal::get_log_max_files(&args), None);
+        assert_eq!(LoggerInitializerWrapperReal::get_log_compress(&args), false);
+    }
+
+    #[test]
+    fn log_rotation_parameters_are_parsed() {
+        let args: Vec<String> = vec![
+            "--log_max_size",
+            "5",
+            "--log_max_files",
+            "3",
+            "--log_compress",
+            "on",
+        ]
+        .into_iter()
+        .map(String::from)
+        .collect();
+
+        assert_eq!(
+            LoggerInitializerWrapperReal::get_log_max_size(&args),
+            Some(5 * 1024 * 1024)
+        );
+        assert_eq!(
+            LoggerInitializerWrapperReal::get_log_max_files(&args),
+            Some(3)
+        );
+        assert_eq!(LoggerInitializerWrapperReal::get_log_compress(&args), true);
+    }
+
+    #[test]
+    fn zero_turns_log_rotation_and_pruning_off() {
+        let args: Vec<String> = vec!["--log_max_size", "0", "--log_max_files", "0"]
+            .into_iter()
+            .map(String::from)
+            .collect();
+
+        assert_eq!(LoggerInitializerWrapperReal::get_log_max_size(&args), None);
+        assert_eq!(LoggerInitializerWrapperReal::get_log_max_files(&args), None);
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Invalid size for --log_max_size <megabytes, or 0 for no rotation>: 'big'"
+    )]
+    fn get_log_max_size_panics_if_arg_makes_no_sense() {
+        let args = vec![String::from("--log_max_size"), String::from("big")];
+
+        LoggerInitializerWrapperReal::get_log_max_size(&args);
+    }
+
+    #[test]
+    #[should_panic(expected = "--log_compress must be on or off, not gzip")]
+    fn get_log_compress_panics_if_arg_makes_no_sense() {
+        let args = vec![String::from("--log_compress"), String::from("gzip")];
+
+        LoggerInitializerWrapperReal::get_log_compress(&args);
+    }
+
     #[test]
     fn go_should_drop_privileges() {
         let bootstrapper = CrashTestDummy::new(CrashPoint::None);
@@ -366,4 +613,48 @@ mod tests {
 
         assert_eq!(*call_count.lock().unwrap(), 1);
     }
+
+    #[test]
+    fn go_should_hand_data_directory_to_run_as_ids() {
+        let privilege_dropper = PrivilegeDropperMock::new();
+        let drop_privileges_parameters = privilege_dropper.drop_privileges_parameters.clone();
+        let chown_parameters = privilege_dropper.chown_parameters.clone();
+        let stdin = &mut ByteArrayReader::new(&[0; 0]);
+        let stdout = &mut ByteArrayWriter::new();
+        let stderr = &mut ByteArrayWriter::new();
+        let streams = &mut StdStreams {
+            stdin,
+            stdout,
+            stderr,
+        };
+        let mut subject = ServerInitializer {
+            dns_socket_server: Box::new(CrashTestDummy::new(CrashPoint::None)),
+            bootstrapper: Box::new(CrashTestDummy::new(CrashPoint::None)),
+            privilege_dropper,
+            logger_initializer_wrapper: Box::new(LoggerInitializerWrapperMock::new()),
+        };
+        let args: Vec<String> = vec![
+            "--data_directory",
+            "/node_root/home",
+            "--run_as_uid",
+            "2000",
+            "--run_as_gid",
+            "3000",
+        ]
+        .into_iter()
+        .map(String::from)
+        .collect();
+
+        subject.go(streams, &args);
+
+        let run_as = RunAsIds {
+            uid: Some(2000),
+            gid: Some(3000),
+        };
+        assert_eq!(
+            *chown_parameters.lock().unwrap(),
+            vec![(PathBuf::from("/node_root/home"), run_as.clone())]
+        );
+        assert_eq!(*drop_privileges_parameters.lock().unwrap(), vec![run_as]);
+    }
 }
@@ -0,0 +1,58 @@
+// Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use crate::discriminator::Discriminator;
+use crate::discriminator::DiscriminatorFactory;
+use crate::null_masquerader::NullMasquerader;
+use crate::sub_lib::pass_through_framer::PassThroughFramer;
+
+// The SOCKS5 handshake and whatever follows it have no framing the Node can use, so the
+// Proxy Server gets data from the SOCKS port just as it arrives. The HTTP proxy port works the
+// same way, since the Proxy Server has to see CONNECT requests as they come.
+pub struct SocksDiscriminatorFactory {}
+
+impl DiscriminatorFactory for SocksDiscriminatorFactory {
+    fn make(&self) -> Discriminator {
+        Discriminator::new(
+            Box::new(PassThroughFramer::new()),
+            vec![Box::new(NullMasquerader::new())],
+        )
+    }
+
+    fn duplicate(&self) -> Box<dyn DiscriminatorFactory> {
+        Box::new(SocksDiscriminatorFactory {})
+    }
+}
+
+impl SocksDiscriminatorFactory {
+    pub fn new() -> SocksDiscriminatorFactory {
+        SocksDiscriminatorFactory {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::discriminator::UnmaskedChunk;
+
+    #[test]
+    fn discriminator_factory_duplicate_works() {
+        let subject = SocksDiscriminatorFactory::new();
+
+        subject.duplicate();
+
+        // no panic; test passes
+    }
+
+    #[test]
+    fn factory_makes_discriminator_that_passes_data_through_as_sequenced_chunks() {
+        let subject = SocksDiscriminatorFactory::new();
+        let mut discriminator = subject.make();
+
+        discriminator.add_data(&[0x05, 0x01, 0x00]);
+        let result = discriminator.take_chunk();
+
+        assert_eq!(
+            result,
+            Some(UnmaskedChunk::new(vec![0x05, 0x01, 0x00], true, true))
+        );
+    }
+}
@@ -3,6 +3,9 @@ use crate::configuration::PortConfiguration;
 use crate::discriminator::DiscriminatorFactory;
 use crate::json_masquerader::JsonMasquerader;
 use crate::masquerader::Masquerader;
+use crate::probe_resistance::make_knock;
+use crate::probe_resistance::KnockGate;
+use crate::probe_resistance::ProbeResistance;
 use crate::stream_messages::*;
 use crate::stream_reader::StreamReaderReal;
 use crate::stream_writer_sorted::StreamWriterSorted;
@@ -10,10 +13,12 @@ use crate::stream_writer_unsorted::StreamWriterUnsorted;
 use crate::sub_lib::channel_wrappers::FuturesChannelFactory;
 use crate::sub_lib::channel_wrappers::FuturesChannelFactoryReal;
 use crate::sub_lib::channel_wrappers::SenderWrapper;
+use crate::sub_lib::cryptde::CryptDE;
 use crate::sub_lib::cryptde::PublicKey;
 use crate::sub_lib::dispatcher;
 use crate::sub_lib::dispatcher::DispatcherSubs;
 use crate::sub_lib::dispatcher::Endpoint;
+use crate::sub_lib::dispatcher::StreamShutdownMsg;
 use crate::sub_lib::logger::Logger;
 use crate::sub_lib::neighborhood::DispatcherNodeQueryMessage;
 use crate::sub_lib::neighborhood::NodeDescriptor;
@@ -25,6 +30,7 @@ use crate::sub_lib::stream_connector::StreamConnector;
 use crate::sub_lib::stream_connector::StreamConnectorReal;
 use crate::sub_lib::stream_handler_pool::DispatcherNodeQueryResponse;
 use crate::sub_lib::stream_handler_pool::TransmitDataMsg;
+use crate::sub_lib::stream_handler_pool::STREAM_READ_BUFFER_SIZE;
 use crate::sub_lib::tokio_wrappers::ReadHalfWrapper;
 use crate::sub_lib::tokio_wrappers::WriteHalfWrapper;
 use crate::sub_lib::utils::localhost;
@@ -78,6 +84,10 @@ pub struct StreamHandlerPool {
     channel_factory: Box<dyn FuturesChannelFactory<SequencedPacket>>,
     clandestine_discriminator_factories: Vec<Box<dyn DiscriminatorFactory>>,
     traffic_analyzer: Box<dyn TrafficAnalyzer>,
+    read_buffer_size: usize,
+    knock_cryptde_opt: Option<&'static dyn CryptDE>,
+    probe_resistance: ProbeResistance,
+    pending_knocks: HashMap<SocketAddr, PublicKey>,
 }
 
 impl Actor for StreamHandlerPool {
@@ -98,12 +108,18 @@ impl Handler<AddStreamMsg> for StreamHandlerPool {
             msg.connection_info.peer_addr,
             port_config.is_clandestine,
         );
+        let knock_gate_opt = if port_config.is_clandestine {
+            self.knock_or_expect_knock(msg.connection_info.peer_addr)
+        } else {
+            None
+        };
         self.set_up_stream_reader(
             msg.connection_info.reader,
             msg.origin_port,
             msg.port_configuration,
             msg.connection_info.peer_addr,
             msg.connection_info.local_addr,
+            knock_gate_opt,
         );
         ()
     }
@@ -114,6 +130,15 @@ impl Handler<RemoveStreamMsg> for StreamHandlerPool {
 
     fn handle(&mut self, msg: RemoveStreamMsg, _ctx: &mut Self::Context) {
         self.stream_writers.remove(&msg.socket_addr).is_some(); // can't do anything if it fails
+        self.pending_knocks.remove(&msg.socket_addr);
+        self.dispatcher_subs
+            .as_ref()
+            .expect("Dispatcher is unbound")
+            .stream_shutdown_sub
+            .try_send(StreamShutdownMsg {
+                peer_addr: msg.socket_addr,
+            })
+            .expect("Dispatcher is dead");
     }
 }
 
@@ -140,8 +165,10 @@ impl Handler<TransmitDataMsg> for StreamHandlerPool {
                     context: msg,
                     recipient: node_query_response_recipient,
                 };
-                self.logger
-                    .debug(format!("Sending node query about {} to Neighborhood", key));
+                self.logger.debug(format!(
+                    "Sending node query about {} to Neighborhood",
+                    key.fingerprint()
+                ));
                 self.ask_neighborhood
                     .as_ref()
                     .expect("StreamHandlerPool is unbound.")
@@ -184,7 +211,7 @@ impl Handler<DispatcherNodeQueryResponse> for StreamHandlerPool {
                 None => {
                     self.logger.error(format!(
                         "No known IP for neighbor in route with key: {}",
-                        node_descriptor.public_key
+                        node_descriptor.public_key.fingerprint()
                     ));
                     return;
                 }
@@ -299,6 +326,9 @@ impl Handler<DispatcherNodeQueryResponse> for StreamHandlerPool {
                 .clone()
                 .map(|d| d.public_key)
                 .expect("Key magically disappeared");
+            if self.knock_cryptde_opt.is_some() && !key.is_empty() {
+                self.pending_knocks.insert(peer_addr, key.clone());
+            }
 
             let connect_future = self.stream_connector.connect(peer_addr, &self.logger)
                 .map (move |connection_info| {
@@ -358,9 +388,24 @@ impl StreamHandlerPool {
             channel_factory: Box::new(FuturesChannelFactoryReal {}),
             clandestine_discriminator_factories,
             traffic_analyzer: Box::new(TrafficAnalyzerReal {}),
+            read_buffer_size: STREAM_READ_BUFFER_SIZE,
+            knock_cryptde_opt: None,
+            probe_resistance: ProbeResistance::Off,
+            pending_knocks: HashMap::new(),
         }
     }
 
+    pub fn read_in_chunks_of(&mut self, read_buffer_size: usize) {
+        self.read_buffer_size = read_buffer_size;
+    }
+
+    // Clandestine streams this pool opens will start with a knock, and those it accepts will be
+    // held to probe_resistance if they don't.
+    pub fn knock_with(&mut self, cryptde: &'static dyn CryptDE, probe_resistance: ProbeResistance) {
+        self.knock_cryptde_opt = Some(cryptde);
+        self.probe_resistance = probe_resistance;
+    }
+
     pub fn make_subs_from(pool_addr: &Addr<Syn, StreamHandlerPool>) -> StreamHandlerPoolSubs {
         StreamHandlerPoolSubs {
             add_sub: pool_addr.clone().recipient::<AddStreamMsg>(),
@@ -378,6 +423,7 @@ impl StreamHandlerPool {
         port_configuration: PortConfiguration,
         peer_addr: SocketAddr,
         local_addr: SocketAddr,
+        knock_gate_opt: Option<KnockGate>,
     ) {
         let ibcd_sub: Recipient<Syn, dispatcher::InboundClientData> = self
             .dispatcher_subs
@@ -391,7 +437,7 @@ impl StreamHandlerPool {
             .expect("StreamHandlerPool is unbound")
             .remove_sub
             .clone();
-        let stream_reader = StreamReaderReal::new(
+        let mut stream_reader = StreamReaderReal::new(
             read_stream,
             origin_port,
             ibcd_sub,
@@ -400,10 +446,37 @@ impl StreamHandlerPool {
             port_configuration.is_clandestine,
             peer_addr,
             local_addr,
+            self.read_buffer_size,
         );
+        if let Some(knock_gate) = knock_gate_opt {
+            let decoy_tx = self
+                .stream_writers
+                .get(&peer_addr)
+                .and_then(|tx_opt| tx_opt.as_ref())
+                .expect("StreamWriter magically disappeared")
+                .as_ref()
+                .clone();
+            stream_reader.require_knock(knock_gate, decoy_tx);
+        }
         tokio::spawn(stream_reader);
     }
 
+    // A clandestine stream this Node opened starts with a knock; one it accepted must start with one
+    fn knock_or_expect_knock(&mut self, peer_addr: SocketAddr) -> Option<KnockGate> {
+        let cryptde = self.knock_cryptde_opt?;
+        match self.pending_knocks.remove(&peer_addr) {
+            Some(public_key) => {
+                let knock = make_knock(cryptde, &public_key);
+                if let Some(Some(tx)) = self.stream_writers.get(&peer_addr) {
+                    tx.unbounded_send(SequencedPacket::new(knock, 0, false))
+                        .is_ok();
+                }
+                None
+            }
+            None => Some(KnockGate::new(cryptde.public_key(), self.probe_resistance)),
+        }
+    }
+
     fn set_up_stream_writer(
         &mut self,
         write_stream: Box<dyn WriteHalfWrapper>,
@@ -443,6 +516,8 @@ mod tests {
     use crate::json_masquerader::JsonMasquerader;
     use crate::masquerader::Masquerader;
     use crate::node_test_utils::FailingMasquerader;
+    use crate::probe_resistance::KnockOutcome;
+    use crate::probe_resistance::KNOCK_LENGTH;
     use crate::sub_lib::cryptde::CryptDE;
     use crate::sub_lib::cryptde_null::CryptDENull;
     use crate::sub_lib::dispatcher::InboundClientData;
@@ -456,6 +531,7 @@ mod tests {
     use crate::test_utils::recorder::Recording;
     use crate::test_utils::stream_connector_mock::StreamConnectorMock;
     use crate::test_utils::test_utils::await_messages;
+    use crate::test_utils::test_utils::cryptde;
     use crate::test_utils::tokio_wrapper_mocks::ReadHalfWrapperMock;
     use crate::test_utils::tokio_wrapper_mocks::WriteHalfWrapperMock;
     use actix::Actor;
@@ -551,7 +627,7 @@ mod tests {
             system.run();
         });
 
-        awaiter.await_message_count(4);
+        awaiter.await_message_count(5);
         let dispatcher_recording = dispatcher_recording_arc.lock().unwrap();
         assert_eq!(
             dispatcher_recording.get_record::<dispatcher::InboundClientData>(0),
@@ -561,7 +637,8 @@ mod tests {
                 last_data: false,
                 is_clandestine,
                 sequence_number: Some(0),
-                data: one_http_req_a
+                data: one_http_req_a,
+                neighbor_key_opt: None,
             }
         );
         assert_eq!(
@@ -572,7 +649,8 @@ mod tests {
                 last_data: false,
                 is_clandestine,
                 sequence_number: Some(1),
-                data: another_http_req_a
+                data: another_http_req_a,
+                neighbor_key_opt: None,
             }
         );
         assert_eq!(
@@ -583,21 +661,33 @@ mod tests {
                 last_data: false,
                 is_clandestine,
                 sequence_number: Some(2),
-                data: a_third_http_req_a
+                data: a_third_http_req_a,
+                neighbor_key_opt: None,
             }
         );
+        // The reader's last data races the pool's word that the stream is gone
+        let (last_data_index, shutdown_index) =
+            match dispatcher_recording.get_record_opt::<StreamShutdownMsg>(3) {
+                Some(_) => (4, 3),
+                None => (3, 4),
+            };
         assert_eq!(
-            dispatcher_recording.get_record::<dispatcher::InboundClientData>(3),
+            dispatcher_recording.get_record::<dispatcher::InboundClientData>(last_data_index),
             &dispatcher::InboundClientData {
                 peer_addr: peer_addr_a,
                 reception_port,
                 last_data: true,
                 is_clandestine,
                 sequence_number: Some(3),
-                data: Vec::new()
+                data: Vec::new(),
+                neighbor_key_opt: None,
             }
         );
-        assert_eq!(dispatcher_recording.len(), 4);
+        assert_eq!(
+            dispatcher_recording.get_record::<StreamShutdownMsg>(shutdown_index),
+            &StreamShutdownMsg { peer_addr }
+        );
+        assert_eq!(dispatcher_recording.len(), 5);
     }
 
     #[test]
@@ -1005,10 +1095,112 @@ mod tests {
                 is_clandestine: true,
                 sequence_number: None,
                 data: incoming_unmasked,
+                neighbor_key_opt: None,
             }
         );
     }
 
+    #[test]
+    fn stream_handler_pool_knocks_before_anything_else_on_a_stream_it_opens() {
+        let public_key = PublicKey::from(vec![0, 1, 2, 3]);
+        let masquerader = JsonMasquerader::new();
+        let incoming_unmasked = b"Incoming data".to_vec();
+        let incoming_masked = masquerader.mask(&incoming_unmasked).unwrap();
+        let outgoing_unmasked = b"Outgoing data".to_vec();
+        let outgoing_masked = masquerader.mask(&outgoing_unmasked).unwrap();
+        let outgoing_masked_len = outgoing_masked.len();
+        let (dispatcher, dispatcher_awaiter, dispatcher_recording_arc) = make_recorder();
+        let (neighborhood, neighborhood_awaiter, neighborhood_recording_arc) = make_recorder();
+        let poll_write_params_arc = Arc::new(Mutex::new(vec![]));
+        let poll_write_params_arc_a = poll_write_params_arc.clone();
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            let system =
+                System::new("stream_handler_pool_knocks_before_anything_else_on_a_stream_it_opens");
+            let discriminator_factory = JsonDiscriminatorFactory::new();
+            let mut subject = StreamHandlerPool::new(vec![Box::new(discriminator_factory)]);
+            subject.knock_with(cryptde(), ProbeResistance::Silent);
+            subject.stream_connector = Box::new(
+                StreamConnectorMock::new().connect_pair_result(Ok(ConnectionInfo {
+                    reader: Box::new(
+                        ReadHalfWrapperMock::new()
+                            .poll_read_ok(incoming_masked)
+                            .poll_read_result(vec![], Ok(Async::NotReady)),
+                    ),
+                    writer: Box::new(
+                        WriteHalfWrapperMock::new()
+                            .poll_write_ok(KNOCK_LENGTH)
+                            .poll_write_ok(outgoing_masked_len)
+                            .poll_write_result(Ok(Async::NotReady))
+                            .poll_write_params(&poll_write_params_arc),
+                    ),
+                    local_addr: SocketAddr::from_str("127.0.0.1:54321").unwrap(),
+                    peer_addr: SocketAddr::from_str("1.2.3.5:7000").unwrap(),
+                })),
+            );
+            let subject_addr: Addr<Syn, StreamHandlerPool> = subject.start();
+            let subject_subs = StreamHandlerPool::make_subs_from(&subject_addr);
+            let peer_actors = peer_actors_builder()
+                .dispatcher(dispatcher)
+                .neighborhood(neighborhood)
+                .build();
+            subject_subs
+                .bind
+                .try_send(PoolBindMessage {
+                    dispatcher_subs: peer_actors.dispatcher,
+                    stream_handler_pool_subs: subject_subs.clone(),
+                    neighborhood_subs: peer_actors.neighborhood,
+                })
+                .unwrap();
+
+            tx.send(subject_subs).unwrap();
+
+            system.run();
+        });
+        let subject_subs = rx.recv().unwrap();
+        subject_subs
+            .transmit_sub
+            .try_send(TransmitDataMsg {
+                endpoint: Endpoint::Key(public_key.clone()),
+                last_data: false,
+                sequence_number: None,
+                data: outgoing_unmasked,
+            })
+            .unwrap();
+        neighborhood_awaiter.await_message_count(1);
+        let node_query_msg =
+            Recording::get::<DispatcherNodeQueryMessage>(&neighborhood_recording_arc, 0);
+
+        subject_subs
+            .node_query_response
+            .try_send(DispatcherNodeQueryResponse {
+                result: Some(NodeDescriptor::new(
+                    public_key.clone(),
+                    Some(NodeAddr::new(
+                        &IpAddr::V4(Ipv4Addr::new(1, 2, 3, 5)),
+                        &vec![7000],
+                    )),
+                )),
+                context: node_query_msg.context,
+            })
+            .unwrap();
+
+        await_messages(2, &poll_write_params_arc_a);
+        let poll_write_params = poll_write_params_arc_a.lock().unwrap();
+        assert_eq!(
+            KnockGate::new(public_key, ProbeResistance::Silent).add_data(&poll_write_params[0]),
+            KnockOutcome::Admitted(vec![])
+        );
+        assert_eq!(poll_write_params[1], outgoing_masked);
+        // The Node this one connected to doesn't have to knock back
+        dispatcher_awaiter.await_message_count(1);
+        let dispatcher_recording = dispatcher_recording_arc.lock().unwrap();
+        assert_eq!(
+            dispatcher_recording.get_record::<InboundClientData>(0).data,
+            incoming_unmasked
+        );
+    }
+
     #[test]
     fn transmit_data_msg_handler_finds_ip_from_neighborhood_and_transmits_message() {
         init_test_logging();
@@ -1188,7 +1380,7 @@ mod tests {
         TestLogHandler::new().await_log_containing(
             format!(
                 "ERROR: Dispatcher: No known IP for neighbor in route with key: {}",
-                cryptde.public_key()
+                cryptde.public_key().fingerprint()
             )
             .as_str(),
             1000,
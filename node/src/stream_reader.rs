@@ -1,10 +1,16 @@
 // Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
 use crate::discriminator::Discriminator;
 use crate::discriminator::DiscriminatorFactory;
+use crate::probe_resistance::KnockGate;
+use crate::probe_resistance::KnockOutcome;
+use crate::probe_resistance::ProbeResistance;
+use crate::probe_resistance::DECOY_HTTP_RESPONSE;
 use crate::stream_messages::*;
+use crate::sub_lib::channel_wrappers::SenderWrapper;
 use crate::sub_lib::dispatcher;
 use crate::sub_lib::dispatcher::InboundClientData;
 use crate::sub_lib::logger::Logger;
+use crate::sub_lib::sequence_buffer::SequencedPacket;
 use crate::sub_lib::sequencer::Sequencer;
 use crate::sub_lib::tokio_wrappers::ReadHalfWrapper;
 use crate::sub_lib::utils::indicates_dead_stream;
@@ -25,6 +31,10 @@ pub struct StreamReaderReal {
     is_clandestine: bool,
     logger: Logger,
     sequencer: Sequencer,
+    read_buffer_size: usize,
+    knock_gate_opt: Option<KnockGate>,
+    decoy_tx_opt: Option<Box<dyn SenderWrapper<SequencedPacket>>>,
+    probe_refused: bool,
 }
 
 impl Future for StreamReaderReal {
@@ -33,7 +43,7 @@ impl Future for StreamReaderReal {
 
     fn poll(&mut self) -> Result<Async<()>, ()> {
         let port = self.local_addr.port();
-        let mut buf = [0u8; 0x10000];
+        let mut buf = vec![0u8; self.read_buffer_size];
         loop {
             match self.stream.poll_read(&mut buf) {
                 Ok(Async::NotReady) => return Ok(Async::NotReady),
@@ -49,7 +59,9 @@ impl Future for StreamReaderReal {
                 Ok(Async::Ready(length)) => {
                     self.logger
                         .debug(format!("Read {}-byte chunk from port {}", length, port));
-                    self.wrangle_discriminators(&buf, length)
+                    if !self.admit(&buf[..length]) {
+                        return Ok(Async::Ready(()));
+                    }
                 }
                 Err(e) => {
                     if indicates_dead_stream(e.kind()) {
@@ -81,6 +93,7 @@ impl StreamReaderReal {
         is_clandestine: bool,
         peer_addr: SocketAddr,
         local_addr: SocketAddr,
+        read_buffer_size: usize,
     ) -> StreamReaderReal {
         let name = format!("StreamReader for {}", peer_addr);
         if discriminator_factories.is_empty() {
@@ -98,6 +111,72 @@ impl StreamReaderReal {
             is_clandestine,
             logger: Logger::new(&name),
             sequencer: Sequencer::new(),
+            read_buffer_size,
+            knock_gate_opt: None,
+            decoy_tx_opt: None,
+            probe_refused: false,
+        }
+    }
+
+    // For incoming clandestine streams: nothing reaches the Discriminator until the KnockGate
+    // admits it, and decoy_tx is where an HTTP decoy response goes if it doesn't.
+    pub fn require_knock(
+        &mut self,
+        knock_gate: KnockGate,
+        decoy_tx: Box<dyn SenderWrapper<SequencedPacket>>,
+    ) {
+        self.knock_gate_opt = Some(knock_gate);
+        self.decoy_tx_opt = Some(decoy_tx);
+    }
+
+    // False if the stream is finished with
+    fn admit(&mut self, data: &[u8]) -> bool {
+        if self.probe_refused {
+            return true;
+        }
+        let outcome = match self.knock_gate_opt.as_mut() {
+            None => {
+                self.wrangle_discriminators(data, data.len());
+                return true;
+            }
+            Some(knock_gate) => knock_gate.add_data(data),
+        };
+        match outcome {
+            KnockOutcome::Waiting => true,
+            KnockOutcome::Admitted(data) => {
+                self.knock_gate_opt = None;
+                self.decoy_tx_opt = None;
+                self.wrangle_discriminators(&data, data.len());
+                true
+            }
+            KnockOutcome::Refused => self.refuse_probe(),
+        }
+    }
+
+    fn refuse_probe(&mut self) -> bool {
+        self.probe_refused = true;
+        self.logger.info(format!(
+            "Refusing clandestine connection from {} that didn't knock first",
+            self.peer_addr
+        ));
+        let probe_resistance = self
+            .knock_gate_opt
+            .as_ref()
+            .map(|knock_gate| knock_gate.probe_resistance());
+        match (probe_resistance, self.decoy_tx_opt.take()) {
+            (Some(ProbeResistance::Http), Some(decoy_tx)) => {
+                // Once the pool lets go of its sender too, the writer closes the stream
+                decoy_tx
+                    .unbounded_send(SequencedPacket::new(DECOY_HTTP_RESPONSE.to_vec(), 0, true))
+                    .is_ok();
+                self.remove_sub
+                    .try_send(RemoveStreamMsg {
+                        socket_addr: self.peer_addr,
+                    })
+                    .expect("StreamHandlerPool is dead");
+                false
+            }
+            _ => true,
         }
     }
 
@@ -132,6 +211,7 @@ impl StreamReaderReal {
                         is_clandestine: self.is_clandestine,
                         sequence_number,
                         data: unmasked_chunk.chunk.clone(),
+                        neighbor_key_opt: None,
                     };
                     self.logger.debug (format! ("Discriminator framed and unmasked {} bytes for {}; transmitting via Hopper",
                                                  unmasked_chunk.chunk.len (), msg.peer_addr));
@@ -147,6 +227,26 @@ impl StreamReaderReal {
     }
 
     fn shutdown(&mut self) {
+        if self.knock_gate_opt.is_some() {
+            // The Dispatcher never heard of this stream, so it needn't hear that it's gone
+            self.remove_sub
+                .try_send(RemoveStreamMsg {
+                    socket_addr: self.peer_addr,
+                })
+                .expect("StreamHandlerPool is dead");
+            return;
+        }
+        let stats = self.discriminators[0].stats();
+        if !stats.is_clean() {
+            self.logger.warning(format!(
+                "Framing trouble on stream from {}: {} frames, {} bytes discarded, {} oversized frames, {} unmaskable frames",
+                self.peer_addr,
+                stats.frames,
+                stats.discarded_bytes,
+                stats.oversized_frames,
+                stats.unmaskable_frames
+            ));
+        }
         self.remove_sub
             .try_send(RemoveStreamMsg {
                 socket_addr: self.peer_addr,
@@ -162,6 +262,7 @@ impl StreamReaderReal {
                 is_clandestine: self.is_clandestine,
                 sequence_number,
                 data: Vec::new(),
+                neighbor_key_opt: None,
             })
             .expect("Dispatcher is dead");
     }
@@ -175,8 +276,12 @@ mod tests {
     use crate::json_masquerader::JsonMasquerader;
     use crate::masquerader::Masquerader;
     use crate::node_test_utils::make_stream_handler_pool_subs_from;
+    use crate::probe_resistance::make_knock;
     use crate::stream_handler_pool::StreamHandlerPoolSubs;
+    use crate::sub_lib::cryptde::CryptDE;
     use crate::sub_lib::dispatcher::DispatcherSubs;
+    use crate::sub_lib::stream_handler_pool::STREAM_READ_BUFFER_SIZE;
+    use crate::test_utils::channel_wrapper_mocks::SenderWrapperMock;
     use crate::test_utils::logging::init_test_logging;
     use crate::test_utils::logging::TestLogHandler;
     use crate::test_utils::recorder::make_dispatcher_subs_from;
@@ -184,6 +289,7 @@ mod tests {
     use crate::test_utils::recorder::RecordAwaiter;
     use crate::test_utils::recorder::Recorder;
     use crate::test_utils::recorder::Recording;
+    use crate::test_utils::test_utils::cryptde;
     use crate::test_utils::tokio_wrapper_mocks::ReadHalfWrapperMock;
     use actix::msgs;
     use actix::Actor;
@@ -237,6 +343,7 @@ mod tests {
             true,
             peer_addr,
             local_addr,
+            STREAM_READ_BUFFER_SIZE,
         );
 
         let result = subject.poll();
@@ -264,6 +371,7 @@ mod tests {
                 is_clandestine: true,
                 sequence_number: Some(0),
                 data: Vec::new(),
+                neighbor_key_opt: None,
             }
         );
 
@@ -296,6 +404,7 @@ mod tests {
             true,
             peer_addr,
             local_addr,
+            STREAM_READ_BUFFER_SIZE,
         );
 
         let result = subject.poll();
@@ -323,6 +432,7 @@ mod tests {
                 is_clandestine: true,
                 sequence_number: Some(0),
                 data: Vec::new(),
+                neighbor_key_opt: None,
             }
         );
 
@@ -355,6 +465,7 @@ mod tests {
             true,
             peer_addr,
             local_addr,
+            STREAM_READ_BUFFER_SIZE,
         );
 
         let result = subject.poll();
@@ -398,6 +509,7 @@ mod tests {
             true,
             peer_addr,
             local_addr,
+            STREAM_READ_BUFFER_SIZE,
         );
 
         let _result = subject.poll();
@@ -438,6 +550,7 @@ mod tests {
             true,
             peer_addr,
             local_addr,
+            STREAM_READ_BUFFER_SIZE,
         );
     }
 
@@ -477,6 +590,7 @@ mod tests {
             true,
             peer_addr,
             local_addr,
+            STREAM_READ_BUFFER_SIZE,
         );
 
         subject.poll().err();
@@ -495,6 +609,7 @@ mod tests {
                 is_clandestine: true,
                 sequence_number: Some(0),
                 data: Vec::from("GET http://here.com HTTP/1.1\r\n\r\n".as_bytes()),
+                neighbor_key_opt: None,
             }
         );
 
@@ -532,6 +647,7 @@ mod tests {
             false,
             peer_addr,
             local_addr,
+            STREAM_READ_BUFFER_SIZE,
         );
 
         let _result = subject.poll();
@@ -551,6 +667,7 @@ mod tests {
                 is_clandestine: false,
                 sequence_number: Some(0),
                 data: Vec::from("GET http://here.com HTTP/1.1\r\n\r\n".as_bytes()),
+                neighbor_key_opt: None,
             }
         );
 
@@ -563,6 +680,7 @@ mod tests {
                 is_clandestine: false,
                 sequence_number: Some(1),
                 data: Vec::from("GET http://example.com HTTP/1.1\r\n\r\n".as_bytes()),
+                neighbor_key_opt: None,
             }
         );
     }
@@ -600,6 +718,7 @@ mod tests {
             true,
             peer_addr,
             local_addr,
+            STREAM_READ_BUFFER_SIZE,
         );
 
         let _result = subject.poll();
@@ -618,7 +737,125 @@ mod tests {
                 is_clandestine: true,
                 sequence_number: None,
                 data: Vec::from("GET http://here.com HTTP/1.1\r\n\r\n".as_bytes()),
+                neighbor_key_opt: None,
+            }
+        );
+    }
+
+    #[test]
+    fn stream_reader_strips_a_knock_before_the_discriminator_sees_the_data() {
+        let system = System::new("test");
+        let (_shp_awaiter, _shp_recording_arc, stream_handler_pool_subs) =
+            stream_handler_pool_stuff();
+        let (d_awaiter, d_recording_arc, dispatcher_subs) = dispatcher_stuff();
+        let peer_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
+        let local_addr = SocketAddr::from_str("1.2.3.5:6789").unwrap();
+        let discriminator_factories: Vec<Box<dyn DiscriminatorFactory>> =
+            vec![Box::new(JsonDiscriminatorFactory::new())];
+        let mut data = make_knock(cryptde(), &cryptde().public_key());
+        data.extend(JsonMasquerader::new().mask(b"booga").unwrap());
+        let reader = ReadHalfWrapperMock {
+            poll_read_results: vec![
+                (data[..40].to_vec(), Ok(Async::Ready(40))),
+                (data[40..].to_vec(), Ok(Async::Ready(data.len() - 40))),
+                (vec![], Ok(Async::NotReady)),
+            ],
+        };
+        let mut subject = StreamReaderReal::new(
+            Box::new(reader),
+            Some(1234 as u16),
+            dispatcher_subs.ibcd_sub,
+            stream_handler_pool_subs.remove_sub,
+            discriminator_factories,
+            true,
+            peer_addr,
+            local_addr,
+            STREAM_READ_BUFFER_SIZE,
+        );
+        subject.require_knock(
+            KnockGate::new(cryptde().public_key(), ProbeResistance::Http),
+            Box::new(SenderWrapperMock::new(peer_addr)),
+        );
+
+        let result = subject.poll();
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+        assert_eq!(result, Ok(Async::NotReady));
+        d_awaiter.await_message_count(1);
+        let d_recording = d_recording_arc.lock().unwrap();
+        assert_eq!(
+            d_recording.get_record::<dispatcher::InboundClientData>(0),
+            &dispatcher::InboundClientData {
+                peer_addr,
+                reception_port: Some(1234 as u16),
+                last_data: false,
+                is_clandestine: true,
+                sequence_number: None,
+                data: b"booga".to_vec(),
+                neighbor_key_opt: None,
+            }
+        );
+    }
+
+    #[test]
+    fn stream_reader_answers_a_probe_that_does_not_knock_with_a_decoy_and_drops_it() {
+        init_test_logging();
+        let system = System::new("test");
+        let (shp_awaiter, shp_recording_arc, stream_handler_pool_subs) =
+            stream_handler_pool_stuff();
+        let (_d_awaiter, d_recording_arc, dispatcher_subs) = dispatcher_stuff();
+        let peer_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
+        let local_addr = SocketAddr::from_str("1.2.3.5:6789").unwrap();
+        let discriminator_factories: Vec<Box<dyn DiscriminatorFactory>> =
+            vec![Box::new(JsonDiscriminatorFactory::new())];
+        let probe = b"GET / HTTP/1.1\r\n\r\n".to_vec();
+        let reader = ReadHalfWrapperMock {
+            poll_read_results: vec![
+                (probe.clone(), Ok(Async::Ready(probe.len()))),
+                (vec![], Ok(Async::NotReady)),
+            ],
+        };
+        let decoy_tx = SenderWrapperMock::new(peer_addr);
+        let decoy_params_arc = decoy_tx.unbounded_send_params.clone();
+        let mut subject = StreamReaderReal::new(
+            Box::new(reader),
+            Some(1234 as u16),
+            dispatcher_subs.ibcd_sub,
+            stream_handler_pool_subs.remove_sub,
+            discriminator_factories,
+            true,
+            peer_addr,
+            local_addr,
+            STREAM_READ_BUFFER_SIZE,
+        );
+        subject.require_knock(
+            KnockGate::new(cryptde().public_key(), ProbeResistance::Http),
+            Box::new(decoy_tx),
+        );
+
+        let result = subject.poll();
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap();
+        system.run();
+        assert_eq!(result, Ok(Async::Ready(())));
+        assert_eq!(
+            *decoy_params_arc.lock().unwrap(),
+            vec![SequencedPacket::new(DECOY_HTTP_RESPONSE.to_vec(), 0, true)]
+        );
+        shp_awaiter.await_message_count(1);
+        assert_eq!(
+            shp_recording_arc
+                .lock()
+                .unwrap()
+                .get_record::<RemoveStreamMsg>(0),
+            &RemoveStreamMsg {
+                socket_addr: peer_addr
             }
         );
+        assert_eq!(d_recording_arc.lock().unwrap().len(), 0);
+        TestLogHandler::new().exists_log_containing(
+            "INFO: StreamReader for 1.2.3.4:5678: Refusing clandestine connection from 1.2.3.4:5678 that didn't knock first",
+        );
     }
 }
@@ -1,11 +1,18 @@
 // Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
 use crate::sub_lib::peer_actors::BindMessage;
+use crate::sub_lib::peer_actors::ShutdownMessage;
+use crate::sub_lib::stream_key::StreamKey;
 use crate::sub_lib::wallet::Wallet;
 use actix::Message;
 use actix::Recipient;
 use actix::Syn;
 use lazy_static::lazy_static;
+use serde_derive::Serialize;
+use serde_json;
 use std::path::PathBuf;
+use std::time::Duration;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
 
 lazy_static! {
     // TODO: This is not a real wallet address. We need a Substratum wallet to accept default payments.
@@ -15,6 +22,13 @@ lazy_static! {
     pub static ref TEMPORARY_CONSUMING_WALLET: Wallet = Wallet::new ("0xFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF");
 }
 
+pub const AGING_BUCKET_WIDTH: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+// A consumer can only recognize a charge as its own if it arrives about when the service did. A
+// service that can't be reported to the Accountant within this long after it was asked for is
+// given away instead, unless --max_report_delay says otherwise.
+pub const DEFAULT_MAX_REPORT_DELAY: Duration = Duration::from_secs(60);
+pub const CORRELATION_EPOCH_WIDTH: Duration = Duration::from_secs(60 * 60);
+
 #[derive(Clone, PartialEq, Debug)]
 pub struct AccountantConfig {
     pub data_directory: PathBuf,
@@ -27,6 +41,45 @@ pub struct AccountantSubs {
     pub report_exit_service_provided: Recipient<Syn, ReportExitServiceProvidedMessage>,
     pub report_routing_service_consumed: Recipient<Syn, ReportRoutingServiceConsumedMessage>,
     pub report_exit_service_consumed: Recipient<Syn, ReportExitServiceConsumedMessage>,
+    pub subscribe_to_config_changes: Recipient<Syn, SubscribeToConfigChangesMessage>,
+    pub receivable_aging_query: Recipient<Syn, ReceivableAgingQueryMessage>,
+    pub balances_query: Recipient<Syn, BalancesQueryMessage>,
+    pub shutdown: Recipient<Syn, ShutdownMessage>,
+}
+
+// Sent to every actor that has subscribed to config changes whenever a value in the config table
+// is set to something different from what it was
+#[derive(Clone, PartialEq, Debug, Message)]
+pub struct ConfigChangedMessage {
+    pub name: String,
+    pub value: String,
+}
+
+#[derive(Clone, Message)]
+pub struct SubscribeToConfigChangesMessage {
+    pub subscriber: Recipient<Syn, ConfigChangedMessage>,
+}
+
+// Ties a service report to the stream it was for, so that what a consumer owes an exit for a
+// stream can be lined up against what the exit says it's owed. A browser that reuses a source
+// port gets the same stream key again later, so the hour of the report is part of the ID too.
+// Relays never learn stream keys, so routing reports don't carry one.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct CorrelationId {
+    pub stream_key: StreamKey,
+    pub epoch: u64,
+}
+
+impl CorrelationId {
+    pub fn new(stream_key: StreamKey, reported_at: SystemTime) -> CorrelationId {
+        let since_unix_epoch = reported_at
+            .duration_since(UNIX_EPOCH)
+            .expect("Time is before the Unix epoch");
+        CorrelationId {
+            stream_key,
+            epoch: since_unix_epoch.as_secs() / CORRELATION_EPOCH_WIDTH.as_secs(),
+        }
+    }
 }
 
 #[derive(Clone, PartialEq, Debug, Message)]
@@ -35,6 +88,7 @@ pub struct ReportRoutingServiceProvidedMessage {
     pub payload_size: usize,
     pub service_rate: u64,
     pub byte_rate: u64,
+    pub correlation_id_opt: Option<CorrelationId>,
 }
 
 #[derive(Clone, PartialEq, Debug, Message)]
@@ -43,6 +97,7 @@ pub struct ReportExitServiceProvidedMessage {
     pub payload_size: usize,
     pub service_rate: u64,
     pub byte_rate: u64,
+    pub correlation_id_opt: Option<CorrelationId>,
 }
 
 #[derive(Clone, PartialEq, Debug, Message)]
@@ -51,6 +106,7 @@ pub struct ReportRoutingServiceConsumedMessage {
     pub payload_size: usize,
     pub service_rate: u64,
     pub byte_rate: u64,
+    pub correlation_id_opt: Option<CorrelationId>,
 }
 
 #[derive(Clone, PartialEq, Debug, Message)]
@@ -59,4 +115,145 @@ pub struct ReportExitServiceConsumedMessage {
     pub payload_size: usize,
     pub service_rate: u64,
     pub byte_rate: u64,
+    pub correlation_id_opt: Option<CorrelationId>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct AgingBucket {
+    pub accounts: u64,
+    pub balance: i64,
+}
+
+// How long the money owed to this Node has been owed, judged by when each debtor last paid.
+// Only accounts with a positive balance are counted.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct ReceivableAgingReport {
+    pub under_30_days: AgingBucket,
+    pub from_30_to_60_days: AgingBucket,
+    pub over_60_days: AgingBucket,
+}
+
+#[derive(Serialize)]
+struct ReceivableAgingReportEnvelope<'a> {
+    message_type: &'a str,
+    payload: &'a ReceivableAgingReport,
+}
+
+impl ReceivableAgingReport {
+    pub fn total(&self) -> AgingBucket {
+        let buckets = [
+            self.under_30_days,
+            self.from_30_to_60_days,
+            self.over_60_days,
+        ];
+        AgingBucket {
+            accounts: buckets.iter().map(|bucket| bucket.accounts).sum(),
+            balance: buckets.iter().map(|bucket| bucket.balance).sum(),
+        }
+    }
+
+    pub fn to_ui_json(&self) -> String {
+        serde_json::to_string(&ReceivableAgingReportEnvelope {
+            message_type: "receivable_aging",
+            payload: self,
+        })
+        .expect("ReceivableAgingReport could not be serialized")
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct ReceivableAgingQueryMessage {}
+
+impl Message for ReceivableAgingQueryMessage {
+    type Result = ReceivableAgingReport;
+}
+
+// What this Node is owed and what it owes, all told. Accounts in credit aren't counted against
+// the ones that aren't.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct Balances {
+    pub receivable: i64,
+    pub payable: i64,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct BalancesQueryMessage {}
+
+impl Message for BalancesQueryMessage {
+    type Result = Balances;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::test_utils::make_meaningless_stream_key;
+
+    fn make_report() -> ReceivableAgingReport {
+        ReceivableAgingReport {
+            under_30_days: AgingBucket {
+                accounts: 3,
+                balance: 300,
+            },
+            from_30_to_60_days: AgingBucket {
+                accounts: 2,
+                balance: 20,
+            },
+            over_60_days: AgingBucket {
+                accounts: 1,
+                balance: 1,
+            },
+        }
+    }
+
+    #[test]
+    fn aging_bucket_width_is_thirty_days() {
+        assert_eq!(AGING_BUCKET_WIDTH, Duration::from_secs(2_592_000));
+    }
+
+    #[test]
+    fn correlation_epoch_width_is_an_hour() {
+        assert_eq!(CORRELATION_EPOCH_WIDTH, Duration::from_secs(3600));
+    }
+
+    #[test]
+    fn correlation_ids_within_the_same_hour_match() {
+        let stream_key = make_meaningless_stream_key();
+        let start_of_hour = UNIX_EPOCH + Duration::from_secs(3600 * 430_000);
+
+        let one = CorrelationId::new(stream_key, start_of_hour);
+        let another = CorrelationId::new(stream_key, start_of_hour + Duration::from_secs(3599));
+        let next_hour = CorrelationId::new(stream_key, start_of_hour + Duration::from_secs(3600));
+
+        assert_eq!(one.epoch, 430_000);
+        assert_eq!(one, another);
+        assert_ne!(one, next_hour);
+    }
+
+    #[test]
+    fn total_adds_up_every_bucket() {
+        let result = make_report().total();
+
+        assert_eq!(
+            result,
+            AgingBucket {
+                accounts: 6,
+                balance: 321
+            }
+        );
+    }
+
+    #[test]
+    fn receivable_aging_report_is_wrapped_for_the_ui() {
+        let result = make_report().to_ui_json();
+
+        assert_eq!(
+            result,
+            String::from(concat!(
+                r#"{"message_type":"receivable_aging","payload":{"#,
+                r#""under_30_days":{"accounts":3,"balance":300},"#,
+                r#""from_30_to_60_days":{"accounts":2,"balance":20},"#,
+                r#""over_60_days":{"accounts":1,"balance":1}}}"#
+            ))
+        );
+    }
 }
@@ -0,0 +1,195 @@
+// Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use crate::sub_lib::wallet::Wallet;
+use crate::sub_lib::wallet::WalletRotation;
+use std::time::Duration;
+
+// How often the BlockchainBridge looks at the balance of the consuming wallet in use
+pub const BALANCE_CHECK_INTERVAL: Duration = Duration::from_secs(300);
+// How long the BlockchainBridge waits for the blockchain service to answer a balance query
+pub const BLOCKCHAIN_SERVICE_TIMEOUT: Duration = Duration::from_secs(10);
+
+// A second consuming wallet to pay for routing with once the first one runs low
+#[derive(Clone, Debug, PartialEq)]
+pub struct StandbyConsumingWalletConfig {
+    pub wallet: Wallet,
+    // In wei: a consuming wallet with less than this is running low
+    pub threshold: u128,
+    pub blockchain_service_url: ServiceUrl,
+}
+
+// Earning wallets the Node has rotated through, to be watched until what's piled up in them is worth
+// moving to the one place the operator wants it
+#[derive(Clone, Debug, PartialEq)]
+pub struct EarningConsolidationConfig {
+    pub rotation: WalletRotation,
+    pub consolidation_wallet: Wallet,
+    // In wei: once the rotated-away wallets hold this much between them, it's time to consolidate
+    pub threshold: u128,
+    pub blockchain_service_url: ServiceUrl,
+}
+
+// What the BlockchainBridge watches; without either, it isn't started
+#[derive(Clone, Debug, PartialEq)]
+pub struct BlockchainBridgeConfig {
+    pub consuming_opt: Option<(Wallet, StandbyConsumingWalletConfig)>,
+    pub consolidation_opt: Option<EarningConsolidationConfig>,
+}
+
+impl BlockchainBridgeConfig {
+    pub fn has_work(&self) -> bool {
+        self.consuming_opt.is_some() || self.consolidation_opt.is_some()
+    }
+
+    // Both come from --blockchain_service_url, so it doesn't matter which is asked
+    pub fn blockchain_service_url(&self) -> Option<ServiceUrl> {
+        match (&self.consuming_opt, &self.consolidation_opt) {
+            (Some((_, standby_config)), _) => Some(standby_config.blockchain_service_url.clone()),
+            (None, Some(consolidation)) => Some(consolidation.blockchain_service_url.clone()),
+            (None, None) => None,
+        }
+    }
+}
+
+// Where an Ethereum JSON-RPC service can be reached, over HTTP or HTTPS
+#[derive(Clone, Debug, PartialEq)]
+pub struct ServiceUrl {
+    pub tls: bool,
+    pub host: String,
+    pub port: u16,
+    pub path: String,
+}
+
+impl ServiceUrl {
+    pub fn parse(url: &str) -> Result<ServiceUrl, String> {
+        let (tls, rest) = if url.starts_with("https://") {
+            (true, &url["https://".len()..])
+        } else if url.starts_with("http://") {
+            (false, &url["http://".len()..])
+        } else {
+            return Err(format!("'{}' doesn't start with http:// or https://", url));
+        };
+        let (authority, path) = match rest.find('/') {
+            Some(index) => (&rest[..index], &rest[index..]),
+            None => (rest, "/"),
+        };
+        let (host, port) = match authority.rfind(':') {
+            Some(index) if !authority[index..].contains(']') => {
+                match authority[(index + 1)..].parse::<u16>() {
+                    Ok(port) => (&authority[..index], port),
+                    Err(_) => return Err(format!("'{}' has an invalid port", url)),
+                }
+            }
+            _ => (authority, if tls { 443 } else { 80 }),
+        };
+        if host.is_empty() {
+            return Err(format!("'{}' has no host", url));
+        }
+        Ok(ServiceUrl {
+            tls,
+            host: String::from(host),
+            port,
+            path: String::from(path),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn constants_have_correct_values() {
+        assert_eq!(BALANCE_CHECK_INTERVAL, Duration::from_secs(300));
+        assert_eq!(BLOCKCHAIN_SERVICE_TIMEOUT, Duration::from_secs(10));
+    }
+
+    #[test]
+    fn blockchain_bridge_config_has_work_when_either_kind_of_wallet_is_watched() {
+        let url = ServiceUrl::parse("http://127.0.0.1:8545").unwrap();
+        let consuming = (
+            Wallet::new("consuming"),
+            StandbyConsumingWalletConfig {
+                wallet: Wallet::new("standby"),
+                threshold: 1000,
+                blockchain_service_url: url.clone(),
+            },
+        );
+        let consolidation = EarningConsolidationConfig {
+            rotation: WalletRotation::new(vec![Wallet::new("earning")], Duration::from_secs(60)),
+            consolidation_wallet: Wallet::new("consolidation"),
+            threshold: 1000,
+            blockchain_service_url: url.clone(),
+        };
+        let subject = |consuming_opt, consolidation_opt| BlockchainBridgeConfig {
+            consuming_opt,
+            consolidation_opt,
+        };
+
+        assert_eq!(subject(None, None).has_work(), false);
+        assert_eq!(subject(None, None).blockchain_service_url(), None);
+        assert_eq!(subject(Some(consuming.clone()), None).has_work(), true);
+        assert_eq!(
+            subject(None, Some(consolidation.clone())).blockchain_service_url(),
+            Some(url)
+        );
+        assert_eq!(
+            subject(Some(consuming), Some(consolidation)).has_work(),
+            true
+        );
+    }
+
+    #[test]
+    fn service_url_takes_its_port_and_path_from_the_url() {
+        let result = ServiceUrl::parse("http://127.0.0.1:8545/rpc/v1").unwrap();
+
+        assert_eq!(
+            result,
+            ServiceUrl {
+                tls: false,
+                host: String::from("127.0.0.1"),
+                port: 8545,
+                path: String::from("/rpc/v1"),
+            }
+        );
+    }
+
+    #[test]
+    fn service_url_port_and_path_default_by_scheme() {
+        assert_eq!(
+            ServiceUrl::parse("https://mainnet.example.com").unwrap(),
+            ServiceUrl {
+                tls: true,
+                host: String::from("mainnet.example.com"),
+                port: 443,
+                path: String::from("/"),
+            }
+        );
+        assert_eq!(
+            ServiceUrl::parse("http://[::1]/").unwrap(),
+            ServiceUrl {
+                tls: false,
+                host: String::from("[::1]"),
+                port: 80,
+                path: String::from("/"),
+            }
+        );
+    }
+
+    #[test]
+    fn service_url_rejects_what_it_cant_use() {
+        assert_eq!(
+            ServiceUrl::parse("ws://localhost:8546"),
+            Err(String::from(
+                "'ws://localhost:8546' doesn't start with http:// or https://"
+            ))
+        );
+        assert_eq!(
+            ServiceUrl::parse("http://localhost:booga"),
+            Err(String::from("'http://localhost:booga' has an invalid port"))
+        );
+        assert_eq!(
+            ServiceUrl::parse("https://:8545/"),
+            Err(String::from("'https://:8545/' has no host"))
+        );
+    }
+}
@@ -0,0 +1,144 @@
+// Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use crate::sub_lib::cryptde::CryptData;
+use crate::sub_lib::cryptde::PlainData;
+use crate::sub_lib::cryptde::PublicKey;
+use serde_cbor;
+use serde_derive::{Deserialize, Serialize};
+
+// Marks clandestine data as part of the identity exchange rather than a CORES package. A CORES
+// package is encrypted for the Node it's going to, so it can't be told from noise; this is long
+// enough that noise won't be taken for it.
+pub const IDENTITY_MAGIC: &[u8] = b"SUBAUTH1";
+
+pub const CHALLENGE_NONCE_LENGTH: usize = 32;
+
+// Says which way a signature goes, so that nothing signed here can be passed off as anything else
+pub const PROOF_DIRECTION_TAG: &[u8] = b"challenged to challenger";
+
+// The Dispatcher sends a Challenge the first time a clandestine peer sends it anything; the peer
+// answers with a Proof, which is the proof context signed with the private key behind its public
+// key.
+// Once the signature checks out, everything from that connection is known to come from that
+// Node, however many other Nodes share its IP address. A Node that doesn't know about this
+// never answers, and its traffic is attributed by IP address as before.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum IdentityMessage {
+    Challenge {
+        nonce: Vec<u8>,
+        public_key: PublicKey,
+    },
+    Proof {
+        public_key: PublicKey,
+        signature: CryptData,
+    },
+}
+
+impl IdentityMessage {
+    pub fn to_data(&self) -> Vec<u8> {
+        let mut data = IDENTITY_MAGIC.to_vec();
+        data.extend(
+            serde_cbor::ser::to_vec(self).expect("IdentityMessage could not be serialized"),
+        );
+        data
+    }
+
+    // None if the data isn't part of the identity exchange at all
+    pub fn from_data(data: &[u8]) -> Option<Result<IdentityMessage, String>> {
+        if !data.starts_with(IDENTITY_MAGIC) {
+            return None;
+        }
+        Some(
+            serde_cbor::de::from_slice::<IdentityMessage>(&data[IDENTITY_MAGIC.len()..])
+                .map_err(|e| format!("Malformed identity message: {:?}", e)),
+        )
+    }
+}
+
+// What a Proof signs: the nonce alone could be answered by a Node that's asked to sign it by
+// someone else, so the signature also covers who asked, who answered, and which way it goes.
+// The keys are length-prefixed so that no two pairs of keys run together the same way.
+pub fn proof_context(
+    challenger_key: &PublicKey,
+    prover_key: &PublicKey,
+    nonce: &[u8],
+) -> PlainData {
+    let mut context = IDENTITY_MAGIC.to_vec();
+    context.extend(PROOF_DIRECTION_TAG);
+    for key in &[challenger_key, prover_key] {
+        context.extend(&(key.len() as u32).to_be_bytes());
+        context.extend(key.as_slice());
+    }
+    context.extend(nonce);
+    PlainData::from(context)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn challenge_survives_a_round_trip() {
+        let subject = IdentityMessage::Challenge {
+            nonce: vec![1, 2, 3, 4],
+            public_key: PublicKey::new(&b"challenger"[..]),
+        };
+
+        let result = IdentityMessage::from_data(&subject.to_data());
+
+        assert_eq!(result, Some(Ok(subject)));
+    }
+
+    #[test]
+    fn proof_survives_a_round_trip() {
+        let subject = IdentityMessage::Proof {
+            public_key: PublicKey::new(&b"neighbor"[..]),
+            signature: CryptData::new(&b"signature"[..]),
+        };
+
+        let result = IdentityMessage::from_data(&subject.to_data());
+
+        assert_eq!(result, Some(Ok(subject)));
+    }
+
+    #[test]
+    fn data_without_the_magic_is_not_an_identity_message() {
+        let result = IdentityMessage::from_data(&b"some CORES package"[..]);
+
+        assert_eq!(result, None);
+    }
+
+    #[test]
+    fn data_with_the_magic_but_nothing_sensible_after_it_is_malformed() {
+        let mut data = IDENTITY_MAGIC.to_vec();
+        data.extend(&[0xFF, 0xFF, 0xFF]);
+
+        let result = IdentityMessage::from_data(&data);
+
+        match result {
+            Some(Err(ref msg)) if msg.starts_with("Malformed identity message: ") => (),
+            other => panic!("Expected a malformed-message error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn proof_context_covers_both_keys_their_order_and_the_nonce() {
+        let challenger = PublicKey::new(&b"challenger"[..]);
+        let prover = PublicKey::new(&b"prover"[..]);
+        let nonce = [1, 2, 3, 4];
+
+        let result = proof_context(&challenger, &prover, &nonce);
+
+        let mut expected = b"SUBAUTH1challenged to challenger".to_vec();
+        expected.extend(&[0, 0, 0, 10]);
+        expected.extend(b"challenger");
+        expected.extend(&[0, 0, 0, 6]);
+        expected.extend(b"prover");
+        expected.extend(&nonce);
+        assert_eq!(result, PlainData::from(expected));
+        assert_ne!(result, proof_context(&prover, &challenger, &nonce));
+        assert_ne!(
+            proof_context(&PublicKey::new(b"ab"), &PublicKey::new(b"c"), &nonce),
+            proof_context(&PublicKey::new(b"a"), &PublicKey::new(b"bc"), &nonce)
+        );
+    }
+}
@@ -1,4 +1,5 @@
 // Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use crate::sub_lib::key_fingerprint::KeyFingerprint;
 use base64;
 use serde;
 use serde::de::Visitor;
@@ -153,6 +154,11 @@ impl PublicKey {
     pub fn len(&self) -> usize {
         self.data.len()
     }
+
+    // What to show a person instead of the whole key
+    pub fn fingerprint(&self) -> KeyFingerprint {
+        KeyFingerprint::from(self)
+    }
 }
 
 struct KeyVisitor;
@@ -328,6 +334,38 @@ impl<'a> Visitor<'a> for PlainDataVisitor {
     }
 }
 
+// A key for encrypting data that only Nodes which already share it can decrypt
+#[derive(Clone, Debug, PartialEq)]
+pub struct SymmetricKey {
+    data: Vec<u8>,
+}
+
+impl From<Vec<u8>> for SymmetricKey {
+    fn from(data: Vec<u8>) -> Self {
+        SymmetricKey { data }
+    }
+}
+
+impl SymmetricKey {
+    pub fn new(data: &[u8]) -> SymmetricKey {
+        SymmetricKey {
+            data: Vec::from(data),
+        }
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        self.data.as_slice()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+}
+
 #[derive(PartialEq, Debug, Clone)]
 pub enum CryptdecError {
     EmptyKey,
@@ -352,6 +390,9 @@ pub trait CryptDE: Send + Sync {
         signature: &CryptData,
         public_key: &PublicKey,
     ) -> bool;
+    fn generate_symmetric_key(&self) -> SymmetricKey;
+    fn encode_sym(&self, key: &SymmetricKey, data: &PlainData) -> Result<CryptData, CryptdecError>;
+    fn decode_sym(&self, key: &SymmetricKey, data: &CryptData) -> Result<PlainData, CryptdecError>;
 }
 
 pub fn encodex<T>(cryptde: &CryptDE, public_key: &PublicKey, item: &T) -> Result<CryptData, String>
@@ -5,6 +5,7 @@ use crate::sub_lib::cryptde::CryptdecError;
 use crate::sub_lib::cryptde::PlainData;
 use crate::sub_lib::cryptde::PrivateKey;
 use crate::sub_lib::cryptde::PublicKey;
+use crate::sub_lib::cryptde::SymmetricKey;
 use rand::prelude::*;
 
 pub struct CryptDENull {
@@ -93,6 +94,40 @@ impl CryptDE for CryptDENull {
     ) -> bool {
         true
     }
+
+    fn generate_symmetric_key(&self) -> SymmetricKey {
+        let mut key = [0u8; 32];
+        self.random(&mut key);
+        SymmetricKey::new(&key)
+    }
+
+    fn encode_sym(&self, key: &SymmetricKey, data: &PlainData) -> Result<CryptData, CryptdecError> {
+        if key.is_empty() {
+            Err(CryptdecError::EmptyKey)
+        } else if data.is_empty() {
+            Err(CryptdecError::EmptyData)
+        } else {
+            Ok(CryptData::new(
+                &[key.as_slice(), data.as_slice()].concat()[..],
+            ))
+        }
+    }
+
+    fn decode_sym(&self, key: &SymmetricKey, data: &CryptData) -> Result<PlainData, CryptdecError> {
+        if key.is_empty() {
+            Err(CryptdecError::EmptyKey)
+        } else if data.is_empty() {
+            Err(CryptdecError::EmptyData)
+        } else if !data.as_slice().starts_with(key.as_slice()) {
+            Err(CryptdecError::InvalidKey(format!(
+                "Could not decrypt {} bytes of data with symmetric key {:?}",
+                data.len(),
+                key.as_slice()
+            )))
+        } else {
+            Ok(PlainData::new(&data.as_slice()[key.len()..]))
+        }
+    }
 }
 
 impl CryptDENull {
@@ -326,6 +361,29 @@ mod tests {
         assert_eq!(result.private_key(), subject.private_key());
     }
 
+    #[test]
+    fn symmetric_encoding_prefixes_the_data_with_the_key() {
+        let subject = CryptDENull::new();
+        let key = subject.generate_symmetric_key();
+
+        let encoded = subject.encode_sym(&key, &PlainData::new(b"data")).unwrap();
+
+        assert_eq!(
+            encoded,
+            CryptData::new(&[key.as_slice(), b"data"].concat()[..])
+        );
+        assert_eq!(
+            subject.decode_sym(&key, &encoded).unwrap(),
+            PlainData::new(b"data")
+        );
+        assert_eq!(
+            subject
+                .decode_sym(&SymmetricKey::new(b"other"), &encoded)
+                .is_err(),
+            true
+        );
+    }
+
     #[test]
     fn verifying_a_good_signature_works() {
         let data = PlainData::new(b"Fourscore and seven years ago");
@@ -0,0 +1,426 @@
+// Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use crate::sub_lib::cryptde::CryptDE;
+use crate::sub_lib::cryptde::CryptData;
+use crate::sub_lib::cryptde::CryptdecError;
+use crate::sub_lib::cryptde::PlainData;
+use crate::sub_lib::cryptde::PrivateKey;
+use crate::sub_lib::cryptde::PublicKey;
+use crate::sub_lib::cryptde::SymmetricKey;
+use sodiumoxide::crypto::box_;
+use sodiumoxide::crypto::scalarmult::curve25519;
+use sodiumoxide::crypto::sealedbox;
+use sodiumoxide::crypto::secretbox;
+use sodiumoxide::crypto::sign;
+use sodiumoxide::randombytes;
+
+// A private key is an X25519 secret key followed by the seed of an Ed25519 signing key; the public
+// key is the two public keys that go with them, in the same order. Data is encoded in a sealed
+// box (an ephemeral X25519 key agreement, then XSalsa20-Poly1305), so a recipient can tell that
+// it hasn't been tampered with, but not who sent it.
+const HALF_KEY_LENGTH: usize = 32;
+pub const KEY_LENGTH: usize = HALF_KEY_LENGTH * 2;
+
+pub struct CryptDEReal {
+    private_key: PrivateKey,
+    public_key: PublicKey,
+    encryption_public_key: box_::PublicKey,
+    encryption_secret_key: box_::SecretKey,
+    signing_secret_key: sign::SecretKey,
+}
+
+impl CryptDE for CryptDEReal {
+    fn generate_key_pair(&mut self) {
+        let mut private_key = [0; KEY_LENGTH];
+        randombytes::randombytes_into(&mut private_key);
+        *self =
+            CryptDEReal::from_private_key(&PrivateKey::new(&private_key)).expect("Internal error");
+    }
+
+    fn encode(&self, public_key: &PublicKey, data: &PlainData) -> Result<CryptData, CryptdecError> {
+        if public_key.is_empty() {
+            return Err(CryptdecError::EmptyKey);
+        }
+        if data.is_empty() {
+            return Err(CryptdecError::EmptyData);
+        }
+        let (encryption_public_key, _) = CryptDEReal::split_public_key(public_key)?;
+        Ok(CryptData::from(sealedbox::seal(
+            data.as_slice(),
+            &encryption_public_key,
+        )))
+    }
+
+    fn decode(&self, data: &CryptData) -> Result<PlainData, CryptdecError> {
+        if data.is_empty() {
+            return Err(CryptdecError::EmptyData);
+        }
+        match sealedbox::open(
+            data.as_slice(),
+            &self.encryption_public_key,
+            &self.encryption_secret_key,
+        ) {
+            Ok(plain) => Ok(PlainData::from(plain)),
+            Err(_) => Err(CryptdecError::InvalidKey(format!(
+                "Could not decrypt {} bytes of data with the private key for {}",
+                data.len(),
+                self.public_key.fingerprint()
+            ))),
+        }
+    }
+
+    fn random(&self, dest: &mut [u8]) {
+        randombytes::randombytes_into(dest)
+    }
+
+    fn private_key(&self) -> PrivateKey {
+        self.private_key.clone()
+    }
+
+    fn public_key(&self) -> PublicKey {
+        self.public_key.clone()
+    }
+
+    fn dup(&self) -> Box<dyn CryptDE> {
+        Box::new(CryptDEReal::from_private_key(&self.private_key).expect("Internal error"))
+    }
+
+    fn sign(&self, data: &PlainData) -> Result<CryptData, CryptdecError> {
+        let signature = sign::sign_detached(data.as_slice(), &self.signing_secret_key);
+        Ok(CryptData::new(signature.as_ref()))
+    }
+
+    fn verify_signature(
+        &self,
+        data: &PlainData,
+        signature: &CryptData,
+        public_key: &PublicKey,
+    ) -> bool {
+        let signing_public_key = match CryptDEReal::split_public_key(public_key) {
+            Ok((_, signing_public_key)) => signing_public_key,
+            Err(_) => return false,
+        };
+        match sign::Signature::from_bytes(signature.as_slice()) {
+            Ok(signature) => {
+                sign::verify_detached(&signature, data.as_slice(), &signing_public_key)
+            }
+            Err(_) => false,
+        }
+    }
+
+    fn generate_symmetric_key(&self) -> SymmetricKey {
+        SymmetricKey::new(&secretbox::gen_key().0[..])
+    }
+
+    // Symmetric data is a random nonce followed by XSalsa20-Poly1305 ciphertext
+    fn encode_sym(&self, key: &SymmetricKey, data: &PlainData) -> Result<CryptData, CryptdecError> {
+        if key.is_empty() {
+            return Err(CryptdecError::EmptyKey);
+        }
+        if data.is_empty() {
+            return Err(CryptdecError::EmptyData);
+        }
+        let secret_key = CryptDEReal::symmetric_key(key)?;
+        let nonce = secretbox::gen_nonce();
+        let mut encoded = nonce.0.to_vec();
+        encoded.extend(secretbox::seal(data.as_slice(), &nonce, &secret_key));
+        Ok(CryptData::from(encoded))
+    }
+
+    fn decode_sym(&self, key: &SymmetricKey, data: &CryptData) -> Result<PlainData, CryptdecError> {
+        if key.is_empty() {
+            return Err(CryptdecError::EmptyKey);
+        }
+        if data.is_empty() {
+            return Err(CryptdecError::EmptyData);
+        }
+        let secret_key = CryptDEReal::symmetric_key(key)?;
+        let failure = || {
+            CryptdecError::InvalidKey(format!(
+                "Could not decrypt {} bytes of data with the symmetric key given",
+                data.len()
+            ))
+        };
+        if data.len() < secretbox::NONCEBYTES {
+            return Err(failure());
+        }
+        let (nonce, ciphertext) = data.as_slice().split_at(secretbox::NONCEBYTES);
+        let nonce = secretbox::Nonce::from_slice(nonce).expect("Internal error");
+        match secretbox::open(ciphertext, &nonce, &secret_key) {
+            Ok(plain) => Ok(PlainData::from(plain)),
+            Err(_) => Err(failure()),
+        }
+    }
+}
+
+impl CryptDEReal {
+    pub fn new() -> CryptDEReal {
+        let mut private_key = [0; KEY_LENGTH];
+        CryptDEReal::init_sodium();
+        randombytes::randombytes_into(&mut private_key);
+        CryptDEReal::from_private_key(&PrivateKey::new(&private_key)).expect("Internal error")
+    }
+
+    // How a Node with a persistent identity gets its key pair back
+    pub fn from_private_key(private_key: &PrivateKey) -> Result<CryptDEReal, String> {
+        CryptDEReal::init_sodium();
+        if private_key.len() != KEY_LENGTH {
+            return Err(format!(
+                "A CryptDEReal private key is {} bytes long, not {}",
+                KEY_LENGTH,
+                private_key.len()
+            ));
+        }
+        let (encryption_half, signing_half) = private_key.as_slice().split_at(HALF_KEY_LENGTH);
+        let encryption_secret_key =
+            box_::SecretKey::from_slice(encryption_half).expect("Internal error");
+        let encryption_public_key = box_::PublicKey(
+            curve25519::scalarmult_base(&curve25519::Scalar(encryption_secret_key.0)).0,
+        );
+        let seed = sign::Seed::from_slice(signing_half).expect("Internal error");
+        let (signing_public_key, signing_secret_key) = sign::keypair_from_seed(&seed);
+        let mut public_key = encryption_public_key.0.to_vec();
+        public_key.extend_from_slice(&signing_public_key.0[..]);
+        Ok(CryptDEReal {
+            private_key: private_key.clone(),
+            public_key: PublicKey::from(public_key),
+            encryption_public_key,
+            encryption_secret_key,
+            signing_secret_key,
+        })
+    }
+
+    fn split_public_key(
+        public_key: &PublicKey,
+    ) -> Result<(box_::PublicKey, sign::PublicKey), CryptdecError> {
+        if public_key.len() != KEY_LENGTH {
+            return Err(CryptdecError::InvalidKey(format!(
+                "{} is not a CryptDEReal public key",
+                public_key.fingerprint()
+            )));
+        }
+        let (encryption_half, signing_half) = public_key.as_slice().split_at(HALF_KEY_LENGTH);
+        Ok((
+            box_::PublicKey::from_slice(encryption_half).expect("Internal error"),
+            sign::PublicKey::from_slice(signing_half).expect("Internal error"),
+        ))
+    }
+
+    fn symmetric_key(key: &SymmetricKey) -> Result<secretbox::Key, CryptdecError> {
+        match secretbox::Key::from_slice(key.as_slice()) {
+            Some(secret_key) => Ok(secret_key),
+            None => Err(CryptdecError::InvalidKey(format!(
+                "A CryptDEReal symmetric key is {} bytes long, not {}",
+                secretbox::KEYBYTES,
+                key.len()
+            ))),
+        }
+    }
+
+    fn init_sodium() {
+        sodiumoxide::init().expect("Couldn't initialize libsodium")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_with_empty_key() {
+        let subject = CryptDEReal::new();
+
+        let result = subject.encode(&PublicKey::new(b""), &PlainData::new(b"data"));
+
+        assert_eq!(result.err().unwrap(), CryptdecError::EmptyKey);
+    }
+
+    #[test]
+    fn encode_with_empty_data() {
+        let subject = CryptDEReal::new();
+
+        let result = subject.encode(&subject.public_key(), &PlainData::new(b""));
+
+        assert_eq!(result.err().unwrap(), CryptdecError::EmptyData);
+    }
+
+    #[test]
+    fn encode_with_a_key_that_is_not_a_cryptde_real_key() {
+        let subject = CryptDEReal::new();
+
+        let result = subject.encode(&PublicKey::new(b"key"), &PlainData::new(b"data"));
+
+        assert_eq!(
+            result.err().unwrap(),
+            CryptdecError::InvalidKey(String::from("nnsxs is not a CryptDEReal public key"))
+        );
+    }
+
+    #[test]
+    fn data_encoded_for_a_node_can_be_decoded_only_by_that_node() {
+        let subject = CryptDEReal::new();
+        let other = CryptDEReal::new();
+        let expected_data = PlainData::new(b"These are the times that try men's souls");
+
+        let encrypted_data = subject.encode(&other.public_key(), &expected_data).unwrap();
+
+        assert_ne!(encrypted_data.as_slice(), expected_data.as_slice());
+        assert_eq!(other.decode(&encrypted_data).unwrap(), expected_data);
+        assert_eq!(
+            subject.decode(&encrypted_data).err().unwrap(),
+            CryptdecError::InvalidKey(format!(
+                "Could not decrypt {} bytes of data with the private key for {}",
+                encrypted_data.len(),
+                subject.public_key().fingerprint()
+            ))
+        );
+    }
+
+    #[test]
+    fn tampered_data_is_not_decoded() {
+        let subject = CryptDEReal::new();
+        let encrypted_data = subject
+            .encode(&subject.public_key(), &PlainData::new(b"booga"))
+            .unwrap();
+        let mut tampered: Vec<u8> = encrypted_data.into();
+        let last = tampered.len() - 1;
+        tampered[last] ^= 0x01;
+
+        let result = subject.decode(&CryptData::from(tampered));
+
+        assert_eq!(result.is_err(), true);
+    }
+
+    #[test]
+    fn generation_produces_different_keys_each_time() {
+        let mut subject = CryptDEReal::new();
+        let first_public = subject.public_key();
+        let first_private = subject.private_key();
+
+        subject.generate_key_pair();
+
+        assert_ne!(subject.public_key(), first_public);
+        assert_ne!(subject.private_key(), first_private);
+        assert_eq!(subject.private_key().len(), KEY_LENGTH);
+        assert_eq!(subject.public_key().len(), KEY_LENGTH);
+    }
+
+    #[test]
+    fn the_same_private_key_always_makes_the_same_key_pair() {
+        let original = CryptDEReal::new();
+
+        let result = CryptDEReal::from_private_key(&original.private_key()).unwrap();
+
+        assert_eq!(result.public_key(), original.public_key());
+        let encrypted_data = original
+            .encode(&original.public_key(), &PlainData::new(b"booga"))
+            .unwrap();
+        assert_eq!(
+            result.decode(&encrypted_data).unwrap(),
+            PlainData::new(b"booga")
+        );
+    }
+
+    #[test]
+    fn private_keys_of_the_wrong_length_are_rejected() {
+        let result = CryptDEReal::from_private_key(&PrivateKey::new(b"uninitialized"));
+
+        assert_eq!(
+            result.err().unwrap(),
+            String::from("A CryptDEReal private key is 64 bytes long, not 13")
+        );
+    }
+
+    #[test]
+    fn random_is_not_predictable() {
+        let subject = CryptDEReal::new();
+        let mut first = [0u8; 32];
+        let mut second = [0u8; 32];
+
+        subject.random(&mut first);
+        subject.random(&mut second);
+
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn dup_works() {
+        let subject = CryptDEReal::new();
+
+        let result = subject.dup();
+
+        assert_eq!(result.public_key(), subject.public_key());
+        assert_eq!(result.private_key(), subject.private_key());
+    }
+
+    #[test]
+    fn signatures_verify_only_for_the_right_data_and_key() {
+        let data = PlainData::new(b"Fourscore and seven years ago");
+        let subject = CryptDEReal::new();
+        let other = CryptDEReal::new();
+
+        let signature = subject.sign(&data).unwrap();
+
+        assert_eq!(
+            subject.verify_signature(&data, &signature, &subject.public_key()),
+            true
+        );
+        assert_eq!(
+            subject.verify_signature(
+                &PlainData::new(b"Fivescore"),
+                &signature,
+                &subject.public_key()
+            ),
+            false
+        );
+        assert_eq!(
+            subject.verify_signature(&data, &signature, &other.public_key()),
+            false
+        );
+        assert_eq!(
+            subject.verify_signature(&data, &CryptData::new(b"signed"), &subject.public_key()),
+            false
+        );
+    }
+
+    #[test]
+    fn symmetrically_encoded_data_can_be_decoded_only_with_the_same_key() {
+        let subject = CryptDEReal::new();
+        let key = subject.generate_symmetric_key();
+        let other_key = subject.generate_symmetric_key();
+        let expected_data = PlainData::new(b"Give me liberty, or give me death");
+
+        let encrypted_data = subject.encode_sym(&key, &expected_data).unwrap();
+
+        assert_ne!(key, other_key);
+        assert_eq!(key.len(), secretbox::KEYBYTES);
+        assert_ne!(encrypted_data.as_slice(), expected_data.as_slice());
+        assert_eq!(
+            subject.decode_sym(&key, &encrypted_data).unwrap(),
+            expected_data
+        );
+        assert_eq!(
+            subject
+                .decode_sym(&other_key, &encrypted_data)
+                .err()
+                .unwrap(),
+            CryptdecError::InvalidKey(format!(
+                "Could not decrypt {} bytes of data with the symmetric key given",
+                encrypted_data.len()
+            ))
+        );
+    }
+
+    #[test]
+    fn symmetric_keys_of_the_wrong_length_are_rejected() {
+        let subject = CryptDEReal::new();
+
+        let result = subject.encode_sym(&SymmetricKey::new(b"short"), &PlainData::new(b"data"));
+
+        assert_eq!(
+            result.err().unwrap(),
+            CryptdecError::InvalidKey(String::from(
+                "A CryptDEReal symmetric key is 32 bytes long, not 5"
+            ))
+        );
+    }
+}
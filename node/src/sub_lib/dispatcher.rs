@@ -23,6 +23,36 @@ pub enum Component {
     Hopper,
     ProxyServer,
     ProxyClient,
+    // Not a component of its own: a package whose route ends here carries a CompositePayload,
+    // which the Hopper splits up among the Components named inside it
+    Composite,
+}
+
+// How each Component is identified on the wire. A new Component needs a variant above and a line
+// here; the Hopper finds out where to deliver its packages through RegisterEndpointMessage.
+const COMPONENT_CODES: &[(Component, u8)] = &[
+    (Component::Neighborhood, 0),
+    (Component::Hopper, 1),
+    (Component::ProxyServer, 2),
+    (Component::ProxyClient, 3),
+    (Component::Composite, 4),
+];
+
+impl Component {
+    pub fn code(&self) -> u8 {
+        COMPONENT_CODES
+            .iter()
+            .find(|(component, _)| component == self)
+            .map(|(_, code)| *code)
+            .expect(&format!("No wire code for {:?}", self))
+    }
+
+    pub fn from_code(code: u8) -> Option<Component> {
+        COMPONENT_CODES
+            .iter()
+            .find(|(_, candidate)| *candidate == code)
+            .map(|(component, _)| *component)
+    }
 }
 
 impl Serialize for Component {
@@ -30,13 +60,7 @@ impl Serialize for Component {
     where
         S: Serializer,
     {
-        let index: u8 = match *self {
-            Component::Neighborhood => 0,
-            Component::Hopper => 1,
-            Component::ProxyServer => 2,
-            Component::ProxyClient => 3,
-        };
-        serializer.serialize_u8(index)
+        serializer.serialize_u8(self.code())
     }
 }
 
@@ -62,12 +86,9 @@ impl<'a> Visitor<'a> for ComponentVisitor {
     where
         E: serde::de::Error,
     {
-        match v {
-            0 => Ok(Component::Neighborhood),
-            1 => Ok(Component::Hopper),
-            2 => Ok(Component::ProxyServer),
-            3 => Ok(Component::ProxyClient),
-            _ => Err(serde::de::Error::invalid_value(
+        match Component::from_code(v) {
+            Some(component) => Ok(component),
+            None => Err(serde::de::Error::invalid_value(
                 serde::de::Unexpected::Unsigned(v as u64),
                 &self,
             )),
@@ -99,6 +120,7 @@ impl Component {
             Component::Hopper,
             Component::ProxyServer,
             Component::ProxyClient,
+            Component::Composite,
         ]
     }
 }
@@ -120,6 +142,9 @@ pub struct InboundClientData {
     pub is_clandestine: bool,
     pub sequence_number: Option<u64>,
     pub data: Vec<u8>,
+    // Filled in by the Dispatcher for clandestine data, once the Node at the other end of the
+    // connection has proven which public key it holds
+    pub neighbor_key_opt: Option<PublicKey>,
 }
 
 impl Debug for InboundClientData {
@@ -133,10 +158,17 @@ impl Debug for InboundClientData {
     }
 }
 
+// Sent by the StreamHandlerPool whenever it lets go of a stream, however the stream ended
+#[derive(Clone, Debug, PartialEq, Message)]
+pub struct StreamShutdownMsg {
+    pub peer_addr: SocketAddr,
+}
+
 pub struct DispatcherSubs {
     pub ibcd_sub: Recipient<Syn, InboundClientData>,
     pub bind: Recipient<Syn, BindMessage>,
     pub from_dispatcher_client: Recipient<Syn, TransmitDataMsg>,
+    pub stream_shutdown_sub: Recipient<Syn, StreamShutdownMsg>,
 }
 
 impl Clone for DispatcherSubs {
@@ -145,6 +177,7 @@ impl Clone for DispatcherSubs {
             ibcd_sub: self.ibcd_sub.clone(),
             bind: self.bind.clone(),
             from_dispatcher_client: self.from_dispatcher_client.clone(),
+            stream_shutdown_sub: self.stream_shutdown_sub.clone(),
         }
     }
 }
@@ -191,6 +224,25 @@ mod tests {
         assert_eq!(result, String::from("Socket(1.2.3.4:5678)"))
     }
 
+    #[test]
+    fn every_component_has_a_distinct_wire_code() {
+        let components = vec![
+            Component::Neighborhood,
+            Component::Hopper,
+            Component::ProxyServer,
+            Component::ProxyClient,
+            Component::Composite,
+        ];
+
+        let codes: Vec<u8> = components.iter().map(|c| c.code()).collect();
+
+        assert_eq!(codes, vec![0, 1, 2, 3, 4]);
+        components
+            .iter()
+            .for_each(|c| assert_eq!(Component::from_code(c.code()), Some(*c)));
+        assert_eq!(Component::from_code(5), None);
+    }
+
     #[test]
     fn component_serializer_and_deserializer_talk_to_each_other() {
         let neighborhood_data = serde_cbor::ser::to_vec(&Component::Neighborhood).unwrap();
@@ -214,10 +266,10 @@ mod tests {
 
     #[test]
     fn component_deserializer_handles_unrecognized_component() {
-        let unrecognized_data: &[u8] = &[4];
+        let unrecognized_data: &[u8] = &[5];
 
         let unrecognized_result = serde_cbor::de::from_slice::<Component>(unrecognized_data);
 
-        assert_eq! (format! ("{:?}", unrecognized_result), String::from ("Err(ErrorImpl { code: Message(\"invalid value: integer `4`, expected a Component enum\"), offset: 0 })"))
+        assert_eq! (format! ("{:?}", unrecognized_result), String::from ("Err(ErrorImpl { code: Message(\"invalid value: integer `5`, expected a Component enum\"), offset: 0 })"))
     }
 }
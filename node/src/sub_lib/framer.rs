@@ -6,7 +6,24 @@ pub struct FramedChunk {
     pub last_chunk: bool,
 }
 
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FramingStats {
+    pub frames: u64,
+    pub discarded_bytes: u64,
+    pub oversized_frames: u64,
+    pub unmaskable_frames: u64,
+}
+
+impl FramingStats {
+    pub fn is_clean(&self) -> bool {
+        (self.discarded_bytes == 0) && (self.oversized_frames == 0) && (self.unmaskable_frames == 0)
+    }
+}
+
 pub trait Framer: Send {
     fn add_data(&mut self, data: &[u8]);
     fn take_frame(&mut self) -> Option<FramedChunk>;
+    fn stats(&self) -> FramingStats {
+        FramingStats::default()
+    }
 }
@@ -4,6 +4,7 @@ use crate::sub_lib::cryptde::CryptDE;
 use crate::sub_lib::cryptde::CryptData;
 use crate::sub_lib::cryptde::PlainData;
 use crate::sub_lib::cryptde::PublicKey;
+use crate::sub_lib::dispatcher::Component;
 use crate::sub_lib::dispatcher::InboundClientData;
 use crate::sub_lib::peer_actors::BindMessage;
 use crate::sub_lib::route::Route;
@@ -14,10 +15,15 @@ use actix::Syn;
 use serde::de::Deserialize;
 use serde::ser::Serialize;
 use serde_cbor;
+use serde_derive::Deserialize;
+use serde_derive::Serialize;
+use serde_json;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
 use std::net::IpAddr;
 
-pub const TEMPORARY_PER_ROUTING_BYTE_RATE: u64 = 4;
-pub const TEMPORARY_PER_ROUTING_RATE: u64 = 3;
+pub const DEFAULT_PER_ROUTING_BYTE_RATE: u64 = 4;
+pub const DEFAULT_PER_ROUTING_RATE: u64 = 3;
 
 /// New CORES package about to be sent to the Hopper and thence put on the Substratum Network
 #[derive(Clone, Debug, PartialEq, Message)]
@@ -57,6 +63,8 @@ impl IncipientCoresPackage {
 #[derive(Clone, Debug, PartialEq, Message)]
 pub struct ExpiredCoresPackage {
     pub immediate_neighbor_ip: IpAddr,
+    // Only known when the neighbor has answered the Dispatcher's identity challenge
+    pub immediate_neighbor_key_opt: Option<PublicKey>,
     pub consuming_wallet: Option<Wallet>,
     pub remaining_route: Route, // This is topped by the hop that brought the package here, not the next hop
     pub payload: CryptData,
@@ -71,6 +79,7 @@ impl ExpiredCoresPackage {
     ) -> ExpiredCoresPackage {
         ExpiredCoresPackage {
             immediate_neighbor_ip,
+            immediate_neighbor_key_opt: None,
             consuming_wallet,
             remaining_route,
             payload,
@@ -89,11 +98,160 @@ impl ExpiredCoresPackage {
     }
 }
 
+/// Payloads for several Components at the same Node, carried together in one CORES package whose
+/// route ends at Component::Composite. The destination's Hopper splits it up and hands each part to
+/// its Component as though it had come in a package of its own. Only Nodes whose Hopper knows
+/// Component::Composite can take one. So far the only sender is CoverTraffic, whose composites
+/// carry filler and no parts: nothing yet puts two real payloads in the same package.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct CompositePayload {
+    pub parts: Vec<CompositePart>,
+    // Bytes that mean nothing, so that a package with no parts can be any size; the Hopper ignores them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filler_opt: Option<PlainData>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CompositePart {
+    pub component: Component,
+    pub data: PlainData, // serialized payload, re-encrypted for the Component when the Hopper splits it out
+}
+
+impl CompositePayload {
+    pub fn new() -> CompositePayload {
+        CompositePayload {
+            parts: vec![],
+            filler_opt: None,
+        }
+    }
+
+    pub fn add<T>(mut self, component: Component, payload: &T) -> CompositePayload
+    where
+        T: Serialize,
+    {
+        if component == Component::Hopper || component == Component::Composite {
+            panic!("A composite payload can't carry a part for {:?}", component);
+        }
+        // crashpoint - TODO: Figure out how to log this serialization failure rather than letting data crash the Node.
+        let serialized_payload = serde_cbor::ser::to_vec(payload).expect("Serialization failure");
+        self.parts.push(CompositePart {
+            component,
+            data: PlainData::from(serialized_payload),
+        });
+        self
+    }
+}
+
 #[derive(Clone)]
 pub struct HopperSubs {
     pub bind: Recipient<Syn, BindMessage>,
     pub from_hopper_client: Recipient<Syn, IncipientCoresPackage>,
     pub from_dispatcher: Recipient<Syn, InboundClientData>,
+    pub register_endpoint: Recipient<Syn, RegisterEndpointMessage>,
+    pub statistics_query: Recipient<Syn, HopperStatisticsQueryMessage>,
+    pub payment_standing: Recipient<Syn, PaymentStandingMessage>,
+}
+
+// How fast a relay Node sends on packages it's routing for other Nodes' consumers. What won't go
+// out right away waits in a queue for its consuming wallet, and the queues take turns.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RelayFairnessConfig {
+    pub bytes_per_second: u64,
+    // If set, consumers who are behind on paying this Node get a smaller share of a busy relay
+    pub weighted_by_standing: bool,
+}
+
+/// Sent by the Accountant to let the Hopper know which consuming wallets are behind on paying.
+/// Each message replaces the last; a wallet not mentioned is in good standing.
+#[derive(Clone, Debug, PartialEq, Message)]
+pub struct PaymentStandingMessage {
+    pub delinquent_wallets: Vec<Wallet>,
+}
+
+/// Tells the Hopper where to deliver CORES packages whose route ends at the given Component.
+/// The Neighborhood, Proxy Server, and Proxy Client are registered when the Hopper is bound;
+/// anything else registers itself, usually from its own BindMessage handler.
+#[derive(Clone, Message)]
+pub struct RegisterEndpointMessage {
+    pub component: Component,
+    pub recipient: Recipient<Syn, ExpiredCoresPackage>,
+    pub available_on_bootstrap_node: bool,
+}
+
+// Running totals of what the Hopper has done with the CORES packages that have come to it since
+// startup. A package is forwarded when it goes on to another Node and delivered when its route ends
+// here; the rest are counted by why they went nowhere. Packages that come in over a connection
+// whose Node has proven its identity to the Dispatcher are also counted by that Node's public key.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct HopperStatistics {
+    pub forwarded: u64,
+    pub delivered: BTreeMap<Component, u64>,
+    pub decrypt_failures: u64,
+    pub expire_failures: u64,
+    pub dropped: u64,
+    pub received_from: HashMap<PublicKey, u64>,
+}
+
+#[derive(Serialize)]
+struct HopperStatisticsPayload {
+    forwarded: u64,
+    delivered: BTreeMap<String, u64>,
+    decrypt_failures: u64,
+    expire_failures: u64,
+    dropped: u64,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    received_from: BTreeMap<String, u64>,
+}
+
+#[derive(Serialize)]
+struct HopperStatisticsEnvelope<'a> {
+    message_type: &'a str,
+    payload: HopperStatisticsPayload,
+}
+
+impl HopperStatistics {
+    pub fn delivered_to(&self, component: Component) -> u64 {
+        *self.delivered.get(&component).unwrap_or(&0)
+    }
+
+    pub fn total_delivered(&self) -> u64 {
+        self.delivered.values().sum()
+    }
+
+    // Packages that came in and went nowhere, whatever the reason
+    pub fn total_dropped(&self) -> u64 {
+        self.dropped + self.decrypt_failures + self.expire_failures
+    }
+
+    pub fn to_ui_json(&self) -> String {
+        serde_json::to_string(&HopperStatisticsEnvelope {
+            message_type: "hopper_statistics",
+            payload: HopperStatisticsPayload {
+                forwarded: self.forwarded,
+                delivered: self
+                    .delivered
+                    .iter()
+                    .map(|(component, count)| (format!("{:?}", component), *count))
+                    .collect(),
+                decrypt_failures: self.decrypt_failures,
+                expire_failures: self.expire_failures,
+                dropped: self.dropped,
+                received_from: self
+                    .received_from
+                    .iter()
+                    .map(|(public_key, count)| (format!("{}", public_key.fingerprint()), *count))
+                    .collect(),
+            },
+        })
+        .expect("HopperStatistics could not be serialized")
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct HopperStatisticsQueryMessage {}
+
+impl Message for HopperStatisticsQueryMessage {
+    type Result = HopperStatistics;
 }
 
 #[cfg(test)]
@@ -101,7 +259,6 @@ mod tests {
     use super::*;
     use crate::sub_lib::cryptde::PlainData;
     use crate::sub_lib::cryptde_null::CryptDENull;
-    use crate::sub_lib::dispatcher::Component;
     use crate::sub_lib::route::RouteSegment;
     use crate::test_utils::test_utils::PayloadMock;
     use std::str::FromStr;
@@ -184,4 +341,86 @@ mod tests {
             deserialized_payload
         );
     }
+
+    #[test]
+    fn composite_payload_carries_each_part_serialized_for_its_component() {
+        let neighborhood_payload = PayloadMock::new();
+        let proxy_server_payload = PayloadMock {
+            data: vec![1, 2, 3],
+        };
+
+        let subject = CompositePayload::new()
+            .add(Component::Neighborhood, &neighborhood_payload)
+            .add(Component::ProxyServer, &proxy_server_payload);
+
+        assert_eq!(
+            subject.parts,
+            vec![
+                CompositePart {
+                    component: Component::Neighborhood,
+                    data: PlainData::from(serde_cbor::ser::to_vec(&neighborhood_payload).unwrap()),
+                },
+                CompositePart {
+                    component: Component::ProxyServer,
+                    data: PlainData::from(serde_cbor::ser::to_vec(&proxy_server_payload).unwrap()),
+                },
+            ]
+        );
+        let serialized = serde_cbor::ser::to_vec(&subject).unwrap();
+        assert_eq!(
+            serde_cbor::de::from_slice::<CompositePayload>(&serialized[..]).unwrap(),
+            subject
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "A composite payload can't carry a part for Hopper")]
+    fn composite_payload_cannot_carry_a_part_for_the_hopper() {
+        CompositePayload::new().add(Component::Hopper, &PayloadMock::new());
+    }
+
+    #[test]
+    #[should_panic(expected = "A composite payload can't carry a part for Composite")]
+    fn composite_payload_cannot_carry_a_composite_part() {
+        CompositePayload::new().add(Component::Composite, &CompositePayload::new());
+    }
+
+    #[test]
+    fn hopper_statistics_are_totaled_and_wrapped_for_the_ui() {
+        let mut subject = HopperStatistics::default();
+        subject.forwarded = 5;
+        subject.delivered.insert(Component::ProxyServer, 3);
+        subject.delivered.insert(Component::Neighborhood, 2);
+        subject.decrypt_failures = 1;
+        subject.dropped = 4;
+
+        assert_eq!(subject.delivered_to(Component::ProxyServer), 3);
+        assert_eq!(subject.delivered_to(Component::ProxyClient), 0);
+        assert_eq!(subject.total_delivered(), 5);
+        assert_eq!(
+            subject.to_ui_json(),
+            String::from(concat!(
+                r#"{"message_type":"hopper_statistics","payload":{"forwarded":5,"#,
+                r#""delivered":{"Neighborhood":2,"ProxyServer":3},"#,
+                r#""decrypt_failures":1,"expire_failures":0,"dropped":4}}"#
+            ))
+        );
+    }
+
+    #[test]
+    fn hopper_statistics_for_the_ui_count_packages_by_the_neighbor_they_came_from() {
+        let mut subject = HopperStatistics::default();
+        subject
+            .received_from
+            .insert(PublicKey::new(&b"neighbor"[..]), 7);
+
+        assert_eq!(
+            subject.to_ui_json(),
+            String::from(concat!(
+                r#"{"message_type":"hopper_statistics","payload":{"forwarded":0,"#,
+                r#""delivered":{},"decrypt_failures":0,"expire_failures":0,"dropped":0,"#,
+                r#""received_from":{"nzswsz3imjxxe":7}}}"#
+            ))
+        );
+    }
 }
@@ -0,0 +1,142 @@
+// Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use crate::sub_lib::cryptde::PublicKey;
+use std::fmt;
+use std::str::FromStr;
+
+pub const FINGERPRINT_LENGTH: usize = 8;
+
+// Lowercase RFC 4648 base32, unpadded, so that a fingerprint is easy to read aloud and to grep for
+const ALPHABET: &[u8; 32] = b"abcdefghijklmnopqrstuvwxyz234567";
+
+// The first few bytes of a public key: short enough for a log line, long enough to tell the Nodes
+// in one Neighborhood apart. It's not a key, though; two keys can share a fingerprint, so anything
+// that needs to be sure which Node it has must keep the whole PublicKey.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct KeyFingerprint {
+    data: Vec<u8>,
+}
+
+impl fmt::Display for KeyFingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        let mut result = String::new();
+        let mut buffer: u16 = 0;
+        let mut bits = 0;
+        for byte in &self.data {
+            buffer = (buffer << 8) | u16::from(*byte);
+            bits += 8;
+            while bits >= 5 {
+                bits -= 5;
+                result.push(ALPHABET[((buffer >> bits) & 0x1F) as usize] as char);
+            }
+        }
+        if bits > 0 {
+            result.push(ALPHABET[((buffer << (5 - bits)) & 0x1F) as usize] as char);
+        }
+        write!(f, "{}", result)
+    }
+}
+
+impl fmt::Debug for KeyFingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        write!(f, "{}", self)
+    }
+}
+
+impl FromStr for KeyFingerprint {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut data = vec![];
+        let mut buffer: u16 = 0;
+        let mut bits = 0;
+        for c in s.trim().to_lowercase().bytes() {
+            let value = match ALPHABET.iter().position(|a| *a == c) {
+                Some(value) => value as u16,
+                None => return Err(format!("'{}' is not a key fingerprint", s)),
+            };
+            buffer = (buffer << 5) | value;
+            bits += 5;
+            if bits >= 8 {
+                bits -= 8;
+                data.push((buffer >> bits) as u8);
+            }
+        }
+        if data.is_empty() || data.len() > FINGERPRINT_LENGTH {
+            return Err(format!("'{}' is not a key fingerprint", s));
+        }
+        Ok(KeyFingerprint { data })
+    }
+}
+
+impl<'a> From<&'a PublicKey> for KeyFingerprint {
+    fn from(public_key: &'a PublicKey) -> Self {
+        let length = public_key.len().min(FINGERPRINT_LENGTH);
+        KeyFingerprint {
+            data: public_key.as_slice()[..length].to_vec(),
+        }
+    }
+}
+
+impl KeyFingerprint {
+    pub fn matches(&self, public_key: &PublicKey) -> bool {
+        public_key.as_slice().starts_with(&self.data) && !self.data.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fingerprint_is_the_first_eight_bytes_in_base32() {
+        let public_key = PublicKey::new(b"foobarbazqux");
+
+        let result = KeyFingerprint::from(&public_key);
+
+        assert_eq!(format!("{}", result), String::from("mzxw6ytbojrgc"));
+        assert_eq!(format!("{:?}", result), String::from("mzxw6ytbojrgc"));
+        assert_eq!(
+            format!("{}", public_key.fingerprint()),
+            String::from("mzxw6ytbojrgc")
+        );
+    }
+
+    #[test]
+    fn short_keys_make_short_fingerprints() {
+        let result = KeyFingerprint::from(&PublicKey::new(&[1, 2, 3, 4]));
+
+        assert_eq!(format!("{}", result), String::from("aebagba"));
+    }
+
+    #[test]
+    fn fingerprint_survives_a_round_trip_through_its_string() {
+        let public_key = PublicKey::new(&[0xFF, 0x00, 0x7F, 0x80, 0x01, 0xFE, 0x42, 0x24, 0x99]);
+        let fingerprint = public_key.fingerprint();
+
+        let result = KeyFingerprint::from_str(&format!("{}", fingerprint).to_uppercase());
+
+        assert_eq!(result, Ok(fingerprint));
+    }
+
+    #[test]
+    fn strings_that_are_not_fingerprints_are_rejected() {
+        ["", "mzxw6ytb0i", "mzxw6ytboimzxw6ytboi"]
+            .iter()
+            .for_each(|s| {
+                assert_eq!(
+                    KeyFingerprint::from_str(s),
+                    Err(format!("'{}' is not a key fingerprint", s))
+                )
+            });
+    }
+
+    #[test]
+    fn fingerprint_matches_only_keys_that_start_with_it() {
+        let subject = PublicKey::new(b"foobarbazqux").fingerprint();
+
+        assert_eq!(subject.matches(&PublicKey::new(b"foobarbazqux")), true);
+        assert_eq!(subject.matches(&PublicKey::new(b"foobarbaloney")), true);
+        assert_eq!(subject.matches(&PublicKey::new(b"foobazbarqux")), false);
+        assert_eq!(subject.matches(&PublicKey::new(b"foo")), false);
+    }
+}
@@ -0,0 +1,321 @@
+// Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use crate::sub_lib::logger::Logger;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use regex::Regex;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+// How often the files the node log has been rotated into are looked over
+pub const LOG_JANITOR_INTERVAL: Duration = Duration::from_secs(10);
+pub const COMPRESSED_SUFFIX: &str = ".gz";
+
+struct RotatedFile {
+    path: PathBuf,
+    index: u32,
+    modified: SystemTime,
+}
+
+// flexi_logger writes the node log to <program>_rCURRENT.log and, once that's too big, renames it to
+// <program>_r00001.log, _r00002.log and so on, but never compresses or deletes any of them. This
+// does that, leaving the one being written alone.
+pub struct LogJanitor {
+    directory: PathBuf,
+    pattern: Regex,
+    max_files: Option<usize>,
+    compress: bool,
+    logger: Logger,
+}
+
+impl LogJanitor {
+    pub fn new(
+        directory: &Path,
+        program_name: &str,
+        max_files: Option<usize>,
+        compress: bool,
+    ) -> LogJanitor {
+        LogJanitor {
+            directory: directory.to_path_buf(),
+            pattern: Regex::new(&format!(
+                r"^{}_r(\d+)\.log(\.gz)?$",
+                regex::escape(program_name)
+            ))
+            .expect("Internal error"),
+            max_files,
+            compress,
+            logger: Logger::new("LogJanitor"),
+        }
+    }
+
+    pub fn start(self, interval: Duration) {
+        thread::spawn(move || loop {
+            self.tidy();
+            thread::sleep(interval);
+        });
+    }
+
+    pub fn tidy(&self) {
+        let mut rotated = self.rotated_files();
+        if self.compress {
+            rotated = self.compress_all(rotated);
+        }
+        if let Some(max_files) = self.max_files {
+            let excess = rotated.len().saturating_sub(max_files);
+            rotated
+                .into_iter()
+                .take(excess)
+                .for_each(|file| match fs::remove_file(&file.path) {
+                    Ok(()) => self
+                        .logger
+                        .debug(format!("Deleted old log file {}", file.path.display())),
+                    Err(e) => self.logger.warning(format!(
+                        "Couldn't delete old log file {}: {}",
+                        file.path.display(),
+                        e
+                    )),
+                });
+        }
+    }
+
+    // Oldest first. That's by time rather than number: after a restart, flexi_logger numbers from the
+    // highest uncompressed file it can find, which may be lower than the compressed ones.
+    fn rotated_files(&self) -> Vec<RotatedFile> {
+        let entries = match fs::read_dir(&self.directory) {
+            Ok(entries) => entries,
+            Err(e) => {
+                self.logger.warning(format!(
+                    "Couldn't look for old log files in {}: {}",
+                    self.directory.display(),
+                    e
+                ));
+                return vec![];
+            }
+        };
+        let mut rotated: Vec<RotatedFile> = entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                let index = match self.pattern.captures(&entry.file_name().to_string_lossy()) {
+                    Some(captures) => captures[1].parse::<u32>().ok()?,
+                    None => return None,
+                };
+                let modified = entry
+                    .metadata()
+                    .and_then(|metadata| metadata.modified())
+                    .unwrap_or(UNIX_EPOCH);
+                Some(RotatedFile {
+                    path: entry.path(),
+                    index,
+                    modified,
+                })
+            })
+            .collect();
+        rotated.sort_by_key(|file| (file.modified, file.index));
+        rotated
+    }
+
+    fn compress_all(&self, rotated: Vec<RotatedFile>) -> Vec<RotatedFile> {
+        let mut highest_index = rotated.iter().map(|file| file.index).max().unwrap_or(0);
+        rotated
+            .into_iter()
+            .map(|file| {
+                if is_compressed(&file.path) {
+                    return file;
+                }
+                let mut target = with_compressed_suffix(&file.path);
+                // Left over from before a restart, when flexi_logger gave out this number too
+                if target.exists() {
+                    highest_index += 1;
+                    target = renumbered(&file.path, highest_index);
+                }
+                match compress(&file.path, &target) {
+                    Ok(()) => {
+                        fs::remove_file(&file.path).is_ok();
+                        RotatedFile {
+                            path: target,
+                            ..file
+                        }
+                    }
+                    Err(e) => {
+                        self.logger.warning(format!(
+                            "Couldn't compress old log file {}: {}",
+                            file.path.display(),
+                            e
+                        ));
+                        fs::remove_file(&target).is_ok();
+                        file
+                    }
+                }
+            })
+            .collect()
+    }
+}
+
+fn compress(source: &Path, target: &Path) -> io::Result<()> {
+    let mut encoder = GzEncoder::new(File::create(target)?, Compression::default());
+    io::copy(&mut File::open(source)?, &mut encoder)?;
+    encoder.finish()?;
+    Ok(())
+}
+
+fn is_compressed(path: &Path) -> bool {
+    path.to_string_lossy().ends_with(COMPRESSED_SUFFIX)
+}
+
+fn with_compressed_suffix(path: &Path) -> PathBuf {
+    PathBuf::from(format!("{}{}", path.to_string_lossy(), COMPRESSED_SUFFIX))
+}
+
+// <program>_r00003.log to <program>_r<index>.log.gz
+fn renumbered(path: &Path, index: u32) -> PathBuf {
+    let name = path
+        .file_name()
+        .expect("Internal error")
+        .to_string_lossy()
+        .to_string();
+    let program_name = &name[..name.rfind("_r").expect("Internal error")];
+    path.with_file_name(format!(
+        "{}_r{:0>5}.log{}",
+        program_name, index, COMPRESSED_SUFFIX
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+
+    const BASE_TEST_DIR: &str = "generated/test/log_rotation";
+
+    fn make_directory(name: &str) -> PathBuf {
+        let directory = PathBuf::from(format!("{}/{}", BASE_TEST_DIR, name));
+        fs::remove_dir_all(&directory).is_ok();
+        fs::create_dir_all(&directory).is_ok();
+        directory
+    }
+
+    // Possibly all within the same tick of the filesystem's clock, in which case numbers decide
+    fn write_files(directory: &Path, names: &[&str]) {
+        names.iter().for_each(|name| {
+            fs::write(directory.join(name), format!("contents of {}", name)).unwrap();
+        });
+    }
+
+    fn file_names(directory: &Path) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(directory)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        names.sort();
+        names
+    }
+
+    fn decompressed(path: &Path) -> String {
+        let mut contents = String::new();
+        GzDecoder::new(File::open(path).unwrap())
+            .read_to_string(&mut contents)
+            .unwrap();
+        contents
+    }
+
+    #[test]
+    fn constants_have_correct_values() {
+        assert_eq!(LOG_JANITOR_INTERVAL, Duration::from_secs(10));
+        assert_eq!(COMPRESSED_SUFFIX, ".gz");
+    }
+
+    #[test]
+    fn only_the_oldest_rotated_files_of_this_program_are_deleted() {
+        let directory = make_directory("only_the_oldest_rotated_files_of_this_program_are_deleted");
+        write_files(
+            &directory,
+            &[
+                "SubstratumNode_r00001.log",
+                "SubstratumNode_r00002.log",
+                "OtherProgram_r00001.log",
+                "SubstratumNode_r00003.log",
+                "SubstratumNode_rCURRENT.log",
+            ],
+        );
+        let subject = LogJanitor::new(&directory, "SubstratumNode", Some(2), false);
+
+        subject.tidy();
+
+        assert_eq!(
+            file_names(&directory),
+            vec![
+                "OtherProgram_r00001.log",
+                "SubstratumNode_r00002.log",
+                "SubstratumNode_r00003.log",
+                "SubstratumNode_rCURRENT.log",
+            ]
+        );
+    }
+
+    #[test]
+    fn rotated_files_are_compressed_and_counted_with_those_compressed_before() {
+        let directory =
+            make_directory("rotated_files_are_compressed_and_counted_with_those_compressed_before");
+        write_files(
+            &directory,
+            &[
+                "SubstratumNode_r00001.log.gz",
+                "SubstratumNode_r00002.log",
+                "SubstratumNode_rCURRENT.log",
+            ],
+        );
+        let subject = LogJanitor::new(&directory, "SubstratumNode", Some(1), true);
+
+        subject.tidy();
+
+        assert_eq!(
+            file_names(&directory),
+            vec![
+                "SubstratumNode_r00002.log.gz",
+                "SubstratumNode_rCURRENT.log"
+            ]
+        );
+        assert_eq!(
+            decompressed(&directory.join("SubstratumNode_r00002.log.gz")),
+            "contents of SubstratumNode_r00002.log"
+        );
+    }
+
+    #[test]
+    fn a_number_given_out_again_after_a_restart_is_compressed_under_a_new_one() {
+        let directory = make_directory(
+            "a_number_given_out_again_after_a_restart_is_compressed_under_a_new_one",
+        );
+        write_files(
+            &directory,
+            &[
+                "SubstratumNode_r00001.log.gz",
+                "SubstratumNode_r00002.log.gz",
+                "SubstratumNode_r00001.log",
+            ],
+        );
+        let subject = LogJanitor::new(&directory, "SubstratumNode", None, true);
+
+        subject.tidy();
+
+        assert_eq!(
+            file_names(&directory),
+            vec![
+                "SubstratumNode_r00001.log.gz",
+                "SubstratumNode_r00002.log.gz",
+                "SubstratumNode_r00003.log.gz",
+            ]
+        );
+        assert_eq!(
+            decompressed(&directory.join("SubstratumNode_r00003.log.gz")),
+            "contents of SubstratumNode_r00001.log"
+        );
+    }
+}
@@ -1,22 +1,149 @@
 // Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use crate::sub_lib::cryptde::PublicKey;
+use crate::sub_lib::stream_key::StreamKey;
 use chrono::format::strftime::StrftimeItems;
 use chrono::NaiveDateTime;
+use flexi_logger::LevelFilter;
+use flexi_logger::LogSpecification;
+use flexi_logger::ReconfigurationHandle;
+use lazy_static::lazy_static;
 use log::logger;
 use log::Level;
+use log::Metadata;
 use log::Record;
+use serde_derive::Serialize;
+use std::collections::VecDeque;
+use std::io;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::Mutex;
 use std::thread;
 use std::time::SystemTime;
 use std::time::UNIX_EPOCH;
 
+// How many of the records most recently logged go into a diagnostic bundle
+pub const RECENT_RECORD_COUNT: usize = 200;
+
+// Set once at startup by --log_format json; every Logger then writes one JSON object per line
+static JSON_FORMAT: AtomicBool = AtomicBool::new(false);
+
+lazy_static! {
+    // Only there once the real logger has been started; test logging can't be reconfigured
+    static ref RECONFIGURATION_HANDLE: Mutex<Option<ReconfigurationHandle>> = Mutex::new(None);
+    static ref RECENT_RECORDS: Mutex<RecentRecords> =
+        Mutex::new(RecentRecords::new(RECENT_RECORD_COUNT));
+}
+
+pub fn keep_reconfiguration_handle(handle: ReconfigurationHandle) {
+    *RECONFIGURATION_HANDLE
+        .lock()
+        .expect("Reconfiguration handle is poisoned") = Some(handle);
+}
+
+// Changes how much the running Node logs from here on, as --log_level would have at startup.
+// Returns false if there's no real logger to change.
+pub fn set_log_level(level: LevelFilter) -> bool {
+    match RECONFIGURATION_HANDLE
+        .lock()
+        .expect("Reconfiguration handle is poisoned")
+        .as_mut()
+    {
+        Some(handle) => {
+            handle.set_new_spec(LogSpecification::default(level).finalize());
+            true
+        }
+        None => false,
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+pub fn set_log_format(format: LogFormat) {
+    JSON_FORMAT.store(format == LogFormat::Json, Ordering::Relaxed);
+}
+
+// Oldest first. Nothing that can panic happens while the records are locked, so this is safe to
+// call while panicking.
+pub fn recent_records() -> Vec<String> {
+    match RECENT_RECORDS.lock() {
+        Ok(recent_records) => recent_records.records.iter().cloned().collect(),
+        Err(_) => vec![],
+    }
+}
+
+struct RecentRecords {
+    capacity: usize,
+    records: VecDeque<String>,
+}
+
+impl RecentRecords {
+    fn new(capacity: usize) -> RecentRecords {
+        RecentRecords {
+            capacity,
+            records: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    fn remember(&mut self, line: &str) {
+        if self.records.len() == self.capacity {
+            self.records.pop_front();
+        }
+        self.records.push_back(String::from(line));
+    }
+}
+
+// For the real logger in JSON mode: the record is already a whole line, and anything flexi_logger
+// put in front of it would keep it from being JSON
+pub fn message_only(w: &mut dyn io::Write, record: &Record<'_>) -> Result<(), io::Error> {
+    write!(w, "{}", record.args())
+}
+
+#[derive(Serialize)]
+struct JsonRecord<'a> {
+    timestamp: String,
+    level: String,
+    thread: String,
+    component: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream_key: Option<&'a String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    peer_key: Option<&'a String>,
+    message: &'a str,
+}
+
+// A Logger made with for_stream or for_peer says which stream or Node each of its records is about.
+// Text records don't repeat it, since their messages already name it where it matters.
 #[derive(Clone)]
 pub struct Logger {
     name: String,
+    stream_key: Option<String>,
+    peer_key: Option<String>,
 }
 
 impl Logger {
     pub fn new(name: &str) -> Logger {
         Logger {
             name: String::from(name),
+            stream_key: None,
+            peer_key: None,
+        }
+    }
+
+    pub fn for_stream(&self, stream_key: &StreamKey) -> Logger {
+        Logger {
+            stream_key: Some(format!("{:?}", stream_key)),
+            ..self.clone()
+        }
+    }
+
+    pub fn for_peer(&self, public_key: &PublicKey) -> Logger {
+        Logger {
+            peer_key: Some(format!("{}", public_key)),
+            ..self.clone()
         }
     }
 
@@ -55,20 +182,53 @@ impl Logger {
     }
 
     fn generic_log(&self, level: Level, string: String) {
+        let format = if JSON_FORMAT.load(Ordering::Relaxed) {
+            LogFormat::Json
+        } else {
+            LogFormat::Text
+        };
+        let line = self.format_record(format, &SystemTime::now(), level, &string);
         let logger = logger();
+        // Only what actually goes into the log
+        if logger.enabled(&Metadata::builder().level(level).build()) {
+            if let Ok(mut recent_records) = RECENT_RECORDS.lock() {
+                recent_records.remember(&line);
+            }
+        }
         logger.log(
             &Record::builder()
-                .args(format_args!(
-                    "{} {:?}: {}: {}: {}",
-                    Logger::timestamp_as_string(&SystemTime::now()),
-                    thread::current().id(),
-                    level,
-                    self.name,
-                    string
-                ))
+                .level(level)
+                .args(format_args!("{}", line))
                 .build(),
         );
     }
+
+    fn format_record(
+        &self,
+        format: LogFormat,
+        timestamp: &SystemTime,
+        level: Level,
+        message: &str,
+    ) -> String {
+        let timestamp = Logger::timestamp_as_string(timestamp);
+        let thread = format!("{:?}", thread::current().id());
+        match format {
+            LogFormat::Text => format!(
+                "{} {}: {}: {}: {}",
+                timestamp, thread, level, self.name, message
+            ),
+            LogFormat::Json => serde_json::to_string(&JsonRecord {
+                timestamp,
+                level: format!("{}", level),
+                thread,
+                component: &self.name,
+                stream_key: self.stream_key.as_ref(),
+                peer_key: self.peer_key.as_ref(),
+                message,
+            })
+            .expect("Log record is not serializable"),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -76,6 +236,53 @@ mod tests {
     use super::*;
     use crate::test_utils::logging::init_test_logging;
     use crate::test_utils::logging::TestLogHandler;
+    use crate::test_utils::test_utils::make_meaningless_stream_key;
+    use std::time::Duration;
+
+    #[test]
+    fn set_log_level_fails_without_a_real_logger() {
+        let result = set_log_level(LevelFilter::Debug);
+
+        assert_eq!(result, false);
+    }
+
+    #[test]
+    fn constants_have_correct_values() {
+        assert_eq!(RECENT_RECORD_COUNT, 200);
+    }
+
+    #[test]
+    fn logged_records_are_remembered() {
+        init_test_logging();
+        let subject = Logger::new("logged_records_are_remembered");
+
+        subject.warning(String::from("remember me"));
+
+        let thread_id = thread::current().id();
+        assert_eq!(
+            recent_records()
+                .iter()
+                .any(|record| record.ends_with(&format!(
+                    " {:?}: WARN: logged_records_are_remembered: remember me",
+                    thread_id
+                ))),
+            true
+        );
+    }
+
+    #[test]
+    fn recent_records_forget_the_oldest_when_full() {
+        let mut subject = RecentRecords::new(2);
+
+        subject.remember("one");
+        subject.remember("two");
+        subject.remember("three");
+
+        assert_eq!(
+            subject.records.into_iter().collect::<Vec<String>>(),
+            vec![String::from("two"), String::from("three")]
+        );
+    }
 
     #[test]
     fn logger_format_is_correct() {
@@ -105,6 +312,63 @@ mod tests {
         assert_between(&another_log[..prefix_len], &before_str, &after_str);
     }
 
+    #[test]
+    fn json_records_carry_their_context_as_fields() {
+        let stream_key = make_meaningless_stream_key();
+        let public_key = PublicKey::new(b"peer");
+        let subject = Logger::new("Booga");
+        let timestamp = UNIX_EPOCH + Duration::from_millis(1_500_000_000_123);
+
+        let plain = subject.format_record(LogFormat::Json, &timestamp, Level::Info, "plain");
+        let contextual = subject
+            .for_stream(&stream_key)
+            .for_peer(&public_key)
+            .format_record(LogFormat::Json, &timestamp, Level::Warn, "with \"quotes\"");
+
+        let thread_id = format!("{:?}", thread::current().id());
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&plain).unwrap(),
+            serde_json::json!({
+                "timestamp": "2017-07-14 02:40:00.123",
+                "level": "INFO",
+                "thread": thread_id,
+                "component": "Booga",
+                "message": "plain",
+            })
+        );
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&contextual).unwrap(),
+            serde_json::json!({
+                "timestamp": "2017-07-14 02:40:00.123",
+                "level": "WARN",
+                "thread": thread_id,
+                "component": "Booga",
+                "stream_key": format!("{:?}", stream_key),
+                "peer_key": format!("{}", public_key),
+                "message": "with \"quotes\"",
+            })
+        );
+        assert_eq!(contextual.contains('\n'), false);
+    }
+
+    #[test]
+    fn text_records_look_the_same_with_or_without_context() {
+        let subject = Logger::new("Booga");
+        let timestamp = UNIX_EPOCH + Duration::from_millis(1_500_000_000_123);
+
+        let result = subject
+            .for_stream(&make_meaningless_stream_key())
+            .format_record(LogFormat::Text, &timestamp, Level::Error, "message");
+
+        assert_eq!(
+            result,
+            format!(
+                "2017-07-14 02:40:00.123 {:?}: ERROR: Booga: message",
+                thread::current().id()
+            )
+        );
+    }
+
     fn assert_between(candidate: &str, before: &str, after: &str) {
         assert_eq!(
             candidate >= before,
@@ -0,0 +1,39 @@
+// Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use crate::sub_lib::peer_actors::BindMessage;
+use actix::Message;
+use actix::Recipient;
+use actix::Syn;
+use std::time::Duration;
+
+// How often the Metrics actor asks the other actors for the gauges it serves, so a scrape may see
+// values this old
+pub const METRICS_GAUGE_INTERVAL: Duration = Duration::from_secs(5);
+
+// What the other actors tell the Metrics actor as it happens. Gauges aren't reported: the Metrics
+// actor asks for them itself.
+#[derive(Clone, Debug, PartialEq, Message)]
+pub enum MetricsReport {
+    // Between this Node's exit and servers on the Internet, in either direction
+    BytesExited(u64),
+    // In CORES packages taken in by the Hopper, whether relayed or delivered here
+    BytesRouted(u64),
+    CoresPackagesProcessed(u64),
+    CoresPackagesDropped(u64),
+    DnsResolution(Duration),
+}
+
+#[derive(Clone)]
+pub struct MetricsSubs {
+    pub bind: Recipient<Syn, BindMessage>,
+    pub report: Recipient<Syn, MetricsReport>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn constants_have_correct_values() {
+        assert_eq!(METRICS_GAUGE_INTERVAL, Duration::from_secs(5));
+    }
+}
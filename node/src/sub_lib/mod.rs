@@ -1,10 +1,13 @@
 // Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
 pub mod accountant;
 pub mod bidi_hashmap;
+pub mod blockchain_bridge;
 pub mod channel_wrappers;
+pub mod connection_identity;
 pub mod crash_point;
 pub mod cryptde;
 pub mod cryptde_null;
+pub mod cryptde_real;
 pub mod dispatcher;
 pub mod framer;
 pub mod framer_utils;
@@ -13,13 +16,18 @@ pub mod hopper;
 pub mod http_packet_framer;
 pub mod http_response_start_finder;
 pub mod http_server_impersonator;
+pub mod key_fingerprint;
 pub mod limiter;
+pub mod log_rotation;
 pub mod logger;
 pub mod main_tools;
+pub mod metrics;
 pub mod neighborhood;
 pub mod node_addr;
 pub mod parameter_finder;
+pub mod pass_through_framer;
 pub mod peer_actors;
+pub mod performance_profile;
 pub mod proxy_client;
 pub mod proxy_server;
 pub mod route;
@@ -30,6 +38,7 @@ pub mod stream_connector;
 pub mod stream_handler_pool;
 pub mod stream_key;
 pub mod tcp_wrappers;
+pub mod time_source;
 pub mod tls_framer;
 pub mod tokio_wrappers;
 pub mod ttl_hashmap;
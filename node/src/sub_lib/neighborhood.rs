@@ -2,19 +2,38 @@
 use crate::sub_lib::cryptde::PublicKey;
 use crate::sub_lib::dispatcher::Component;
 use crate::sub_lib::hopper::ExpiredCoresPackage;
+use crate::sub_lib::hopper::DEFAULT_PER_ROUTING_BYTE_RATE;
+use crate::sub_lib::hopper::DEFAULT_PER_ROUTING_RATE;
+use crate::sub_lib::key_fingerprint::KeyFingerprint;
 use crate::sub_lib::node_addr::NodeAddr;
 use crate::sub_lib::peer_actors::BindMessage;
+use crate::sub_lib::peer_actors::ShutdownMessage;
+use crate::sub_lib::proxy_client::DEFAULT_PER_EXIT_BYTE_RATE;
+use crate::sub_lib::proxy_client::DEFAULT_PER_EXIT_RATE;
 use crate::sub_lib::route::Route;
 use crate::sub_lib::stream_handler_pool::DispatcherNodeQueryResponse;
 use crate::sub_lib::stream_handler_pool::TransmitDataMsg;
+use crate::sub_lib::ui_gateway::ReloadConfigMessage;
 use crate::sub_lib::wallet::Wallet;
 use actix::Message;
 use actix::Recipient;
 use actix::Syn;
+use serde_derive::{Deserialize, Serialize};
+use serde_json;
+use std::cmp::min;
+use std::fmt;
+use std::fmt::Display;
+use std::fmt::Formatter;
 use std::net::IpAddr;
 use std::net::Ipv4Addr;
+use std::path::PathBuf;
+use std::time::Duration;
 
 pub const SENTINEL_IP_OCTETS: [u8; 4] = [255, 255, 255, 255];
+pub const LOAD_SAMPLE_INTERVAL: Duration = Duration::from_secs(5);
+pub const LOAD_ADVERTISEMENT_THRESHOLD: u8 = 10;
+pub const WALLET_ROTATION_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+pub const DEFAULT_BAN_THRESHOLD: u32 = 20;
 
 pub fn sentinel_ip_addr() -> IpAddr {
     IpAddr::V4(Ipv4Addr::new(
@@ -36,11 +55,31 @@ pub struct NeighborhoodConfig {
 }
 
 impl NeighborhoodConfig {
+    // A Node with neighbors but no --ip is still decentralized: it starts out originate-only and
+    // learns its IP address from its neighbors.
     pub fn is_decentralized(&self) -> bool {
-        !self.neighbor_configs.is_empty()
-            && (self.local_ip_addr != sentinel_ip_addr())
-            && !self.clandestine_port_list.is_empty()
+        !self.neighbor_configs.is_empty() && !self.clandestine_port_list.is_empty()
     }
+
+    pub fn is_originate_only(&self) -> bool {
+        self.is_decentralized() && (self.local_ip_addr == sentinel_ip_addr())
+    }
+}
+
+// Where the Neighborhood keeps other Nodes' records between runs, and how long it keeps one that
+// nobody has gossiped about
+#[derive(Clone, Debug, PartialEq)]
+pub struct NodeRecordPersistenceConfig {
+    pub data_directory: PathBuf,
+    pub retention: Duration,
+}
+
+// Where the Neighborhood keeps the Nodes it has banned, and how many demerits get a neighbor banned
+// (0 for never)
+#[derive(Clone, Debug, PartialEq)]
+pub struct NeighborBanConfig {
+    pub data_directory: PathBuf,
+    pub threshold: u32,
 }
 
 #[derive(Clone)]
@@ -52,6 +91,16 @@ pub struct NeighborhoodSubs {
     pub from_hopper: Recipient<Syn, ExpiredCoresPackage>,
     pub dispatcher_node_query: Recipient<Syn, DispatcherNodeQueryMessage>,
     pub remove_neighbor: Recipient<Syn, RemoveNeighborMessage>,
+    pub set_consuming_wallet: Recipient<Syn, SetConsumingWalletMessage>,
+    pub routing_load: Recipient<Syn, RoutingLoadMessage>,
+    pub route_feedback: Recipient<Syn, RouteFeedbackMessage>,
+    pub neighbor_misbehavior: Recipient<Syn, NeighborMisbehaviorMessage>,
+    pub node_problem: Recipient<Syn, NodeProblemReport>,
+    pub gossip_stats_query: Recipient<Syn, GossipStatsQueryMessage>,
+    pub topology_query: Recipient<Syn, TopologyQueryMessage>,
+    pub status_query: Recipient<Syn, NeighborhoodStatusQueryMessage>,
+    pub reload_config: Recipient<Syn, ReloadConfigMessage>,
+    pub shutdown: Recipient<Syn, ShutdownMessage>,
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -76,6 +125,8 @@ pub struct BootstrapNeighborhoodNowMessage {}
 pub enum NodeQueryMessage {
     IpAddress(IpAddr),
     PublicKey(PublicKey),
+    // Answered only if exactly one Node in the database has this fingerprint
+    Fingerprint(KeyFingerprint),
 }
 
 impl Message for NodeQueryMessage {
@@ -93,6 +144,7 @@ pub struct DispatcherNodeQueryMessage {
 pub enum TargetType {
     Bootstrap,
     Standard,
+    ExitTls, // a Standard Node that advertises offers_exit_tls
 }
 
 #[derive(PartialEq, Debug)]
@@ -102,10 +154,13 @@ pub struct RouteQueryMessage {
     pub target_component: Component,
     pub minimum_hop_count: usize,
     pub return_component_opt: Option<Component>,
+    pub avoided_exit_keys: Vec<PublicKey>, // exits to pass over if there's any other choice
+    pub exit_location_opt: Option<String>, // country code the exit must declare, or no route
+    pub random_route: bool,                // any workable route over, rather than the cheapest
 }
 
 impl Message for RouteQueryMessage {
-    type Result = Option<RouteQueryResponse>;
+    type Result = Result<RouteQueryResponse, RouteRefusal>;
 }
 
 impl RouteQueryMessage {
@@ -116,14 +171,49 @@ impl RouteQueryMessage {
             target_component: Component::ProxyClient,
             minimum_hop_count,
             return_component_opt: Some(Component::ProxyServer),
+            avoided_exit_keys: vec![],
+            exit_location_opt: None,
+            random_route: false,
+        }
+    }
+
+    // For packages that carry nothing, to make real traffic harder to pick out. They're split up
+    // at the far end like a composite and found to be empty.
+    pub fn cover_route_request(minimum_hop_count: usize) -> RouteQueryMessage {
+        RouteQueryMessage {
+            target_component: Component::Composite,
+            return_component_opt: Some(Component::Composite),
+            random_route: true,
+            ..RouteQueryMessage::data_indefinite_route_request(minimum_hop_count)
+        }
+    }
+
+    pub fn exit_tls_route_request(minimum_hop_count: usize) -> RouteQueryMessage {
+        RouteQueryMessage {
+            target_type: TargetType::ExitTls,
+            ..RouteQueryMessage::data_indefinite_route_request(minimum_hop_count)
+        }
+    }
+
+    pub fn avoiding_exits(self, avoided_exit_keys: Vec<PublicKey>) -> RouteQueryMessage {
+        RouteQueryMessage {
+            avoided_exit_keys,
+            ..self
+        }
+    }
+
+    pub fn exiting_in(self, exit_location_opt: Option<String>) -> RouteQueryMessage {
+        RouteQueryMessage {
+            exit_location_opt,
+            ..self
         }
     }
 }
 
 #[derive(PartialEq, Eq, Debug, Clone)]
 pub enum ExpectedService {
-    Routing(PublicKey, Wallet),
-    Exit(PublicKey, Wallet),
+    Routing(PublicKey, Wallet, RateCard),
+    Exit(PublicKey, Wallet, RateCard),
     Nothing,
 }
 
@@ -137,6 +227,53 @@ pub enum ExpectedServices {
 pub struct RouteQueryResponse {
     pub route: Route,
     pub expected_services: ExpectedServices,
+    pub valid_for: Duration, // after this, ask the Neighborhood again
+    pub score: u8,           // 0 to 100: how well routes through these Nodes have worked lately
+    pub exit_sla_opt: Option<ExitSla>, // what the exit advertised, if anything
+}
+
+// Why the Neighborhood couldn't produce a route, in terms a user can do something about
+#[derive(PartialEq, Debug, Clone)]
+pub enum RouteRefusal {
+    NotEnoughNodes { known: usize, required: usize },
+    NoExitNode(String),
+    NoExitInLocation(String),
+    NoReturnRoute(String),
+    InsufficientHopDiversity(String),
+    NoConsumingWallet,
+    Internal(String),
+}
+
+impl Display for RouteRefusal {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            RouteRefusal::NotEnoughNodes { known, required } => write!(
+                f,
+                "Only {} Node(s) known that can carry traffic; at least {} required",
+                known, required
+            ),
+            RouteRefusal::NoExitNode(detail) => {
+                write!(f, "No exit Node can be reached: {}", detail)
+            }
+            RouteRefusal::NoExitInLocation(location) => {
+                write!(
+                    f,
+                    "No known exit Node declares itself to be in {}",
+                    location
+                )
+            }
+            RouteRefusal::NoReturnRoute(detail) => {
+                write!(f, "No route back can be found: {}", detail)
+            }
+            RouteRefusal::InsufficientHopDiversity(detail) => {
+                write!(f, "Insufficient hop diversity: {}", detail)
+            }
+            RouteRefusal::NoConsumingWallet => {
+                write!(f, "Routes of more than one hop require a consuming wallet")
+            }
+            RouteRefusal::Internal(detail) => write!(f, "{}", detail),
+        }
+    }
 }
 
 #[derive(PartialEq, Debug, Message, Clone)]
@@ -144,11 +281,382 @@ pub struct RemoveNeighborMessage {
     pub public_key: PublicKey,
 }
 
+// From the BlockchainBridge, when it moves the Node to its standby consuming wallet: routes asked for
+// from then on are paid for from this one
+#[derive(PartialEq, Debug, Message, Clone)]
+pub struct SetConsumingWalletMessage {
+    pub wallet: Wallet,
+}
+
+// Running totals of what has happened to the NodeRecords in incoming Gossip since startup.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct GossipAcceptanceStats {
+    pub gossip_messages: u64,
+    pub delta_gossip_messages: u64,
+    pub new_node_records: u64,
+    pub updated_node_records: u64,
+    pub duplicate_node_records: u64,
+    pub stale_node_records: u64,
+    pub rejected_blank_public_key: u64,
+    pub rejected_blank_neighbor_key: u64,
+    pub rejected_self_neighbor: u64,
+}
+
+impl GossipAcceptanceStats {
+    pub fn accepted(&self) -> u64 {
+        self.new_node_records + self.updated_node_records
+    }
+
+    pub fn ignored(&self) -> u64 {
+        self.duplicate_node_records + self.stale_node_records
+    }
+
+    pub fn rejected(&self) -> u64 {
+        self.rejected_blank_public_key
+            + self.rejected_blank_neighbor_key
+            + self.rejected_self_neighbor
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct GossipStatsQueryMessage {}
+
+impl Message for GossipStatsQueryMessage {
+    type Result = GossipAcceptanceStats;
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct NeighborhoodStatus {
+    pub neighbors: usize,
+    // This Node included
+    pub known_nodes: usize,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct NeighborhoodStatusQueryMessage {}
+
+impl Message for NeighborhoodStatusQueryMessage {
+    type Result = NeighborhoodStatus;
+}
+
+pub const DEFAULT_TOPOLOGY_PAGE_SIZE: usize = 100;
+pub const MAX_TOPOLOGY_PAGE_SIZE: usize = 1000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TopologyScope {
+    All,
+    Neighbors,
+}
+
+impl Default for TopologyScope {
+    fn default() -> Self {
+        TopologyScope::All
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NodeCapability {
+    Exit,
+    ExitTls,
+    RelayOnly,
+    Bootstrap,
+}
+
+// Every condition that's present must hold for a Node to be reported. A country filter can only
+// be met by Nodes whose addresses the GeoIP database knows.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct TopologyFilter {
+    #[serde(default)]
+    pub capability: Option<NodeCapability>,
+    #[serde(default)]
+    pub country: Option<String>,
+    #[serde(default)]
+    pub min_version: Option<u32>,
+}
+
+// Asks the Neighborhood for one page of the Nodes it knows about. Pages are in public-key order,
+// and the cursor is the last key of the previous page, so Nodes learned or forgotten between pages
+// don't make the next page skip or repeat anything else. A Node with 10,000 records answers with
+// page_size of them at a time, never more than MAX_TOPOLOGY_PAGE_SIZE.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TopologyQueryMessage {
+    #[serde(default)]
+    pub scope: TopologyScope,
+    #[serde(default)]
+    pub filter: TopologyFilter,
+    #[serde(default)]
+    pub cursor: Option<String>,
+    #[serde(default = "default_topology_page_size")]
+    pub page_size: usize,
+}
+
+fn default_topology_page_size() -> usize {
+    DEFAULT_TOPOLOGY_PAGE_SIZE
+}
+
+impl Message for TopologyQueryMessage {
+    type Result = TopologyPage;
+}
+
+impl TopologyQueryMessage {
+    pub fn from_json(json: &str) -> Result<TopologyQueryMessage, String> {
+        serde_json::from_str(json).map_err(|e| format!("Bad topology query '{}': {}", json, e))
+    }
+
+    pub fn effective_page_size(&self) -> usize {
+        match self.page_size {
+            0 => DEFAULT_TOPOLOGY_PAGE_SIZE,
+            page_size => min(page_size, MAX_TOPOLOGY_PAGE_SIZE),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TopologyNode {
+    pub public_key: String,
+    pub node_addr: Option<String>,
+    pub version: u32,
+    pub country: Option<String>,
+    pub is_bootstrap_node: bool,
+    pub offers_exit_tls: bool,
+    pub relay_only: bool,
+    pub neighbors: Vec<String>,
+}
+
+// next_cursor is absent on the last page
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct TopologyPage {
+    pub nodes: Vec<TopologyNode>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Serialize)]
+struct TopologyPageEnvelope<'a> {
+    message_type: &'a str,
+    request_id: u64,
+    payload: &'a TopologyPage,
+}
+
+impl TopologyPage {
+    // request_id is whatever the UI put in its query, so it can tell which page is which
+    pub fn to_ui_json(&self, request_id: u64) -> String {
+        serde_json::to_string(&TopologyPageEnvelope {
+            message_type: "topology_page",
+            request_id,
+            payload: self,
+        })
+        .expect("TopologyPage could not be serialized")
+    }
+}
+
+// How much relaying a Node is prepared to do, very roughly. It's advertised in Gossip so that
+// other Nodes can tell how heavily loaded a given load factor really is.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub enum CapacityClass {
+    Low,
+    Medium,
+    High,
+}
+
+impl Default for CapacityClass {
+    fn default() -> Self {
+        CapacityClass::Medium
+    }
+}
+
+impl CapacityClass {
+    pub fn from_name(name: &str) -> Option<CapacityClass> {
+        match name {
+            "low" => Some(CapacityClass::Low),
+            "medium" => Some(CapacityClass::Medium),
+            "high" => Some(CapacityClass::High),
+            _ => None,
+        }
+    }
+
+    pub fn nominal_bytes_per_second(&self) -> u64 {
+        match self {
+            CapacityClass::Low => 128 * 1024,
+            CapacityClass::Medium => 1024 * 1024,
+            CapacityClass::High => 8 * 1024 * 1024,
+        }
+    }
+}
+
+// What a Node charges: a service rate for each CORES package it relays or exits, plus a byte rate
+// for each byte of its payload. It's advertised in Gossip so that an originator knows the price of
+// a route before it uses it, and pays each Node on the route what that Node asked.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub struct RateCard {
+    pub routing_service_rate: u64,
+    pub routing_byte_rate: u64,
+    pub exit_service_rate: u64,
+    pub exit_byte_rate: u64,
+}
+
+impl Default for RateCard {
+    fn default() -> Self {
+        RateCard {
+            routing_service_rate: DEFAULT_PER_ROUTING_RATE,
+            routing_byte_rate: DEFAULT_PER_ROUTING_BYTE_RATE,
+            exit_service_rate: DEFAULT_PER_EXIT_RATE,
+            exit_byte_rate: DEFAULT_PER_EXIT_BYTE_RATE,
+        }
+    }
+}
+
+impl RateCard {
+    pub fn is_default(&self) -> bool {
+        *self == RateCard::default()
+    }
+}
+
+// What an exit Node promises the streams it carries for any one originator (see --exit_sla), and
+// a hash of what it refuses to carry (see ExitPolicy::summary_hash), so that originators can tell
+// when that changes. Nobody holds an exit to it but the originators that notice it falling short.
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub struct ExitSla {
+    pub max_streams: u32,
+    pub max_stream_bytes_per_second: u64,
+    pub content_policy_hash: String,
+}
+
+// Sent periodically by the Hopper: how many bytes it relayed for other Nodes during the interval.
+#[derive(PartialEq, Debug, Message, Clone)]
+pub struct RoutingLoadMessage {
+    pub bytes_routed: u64,
+    pub interval: Duration,
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum RouteOutcome {
+    Responded(Duration), // how long the first response took to come back
+    Failed,
+    Throttled, // responses came back well below the rate the exit advertised
+}
+
+// Sent by the ProxyServer once it knows how a round trip over a route it was given turned out
+#[derive(PartialEq, Debug, Message, Clone)]
+pub struct RouteFeedbackMessage {
+    pub keys: Vec<PublicKey>, // every Node the round trip passed through but this one
+    pub outcome: RouteOutcome,
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Misbehavior {
+    UndeliverablePackage, // couldn't be decrypted, or had no usable next hop
+    InvalidGossipSignature,
+}
+
+// Sent by whatever catches an immediate neighbor doing something an honest Node wouldn't
+#[derive(PartialEq, Debug, Message, Clone)]
+pub struct NeighborMisbehaviorMessage {
+    pub public_key: PublicKey,
+    pub misbehavior: Misbehavior,
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum NodeProblem {
+    TargetUnreachable, // the exit couldn't look up or connect to the server
+    StreamDied,        // nothing came back over the stream at all
+}
+
+// Sent by the ProxyServer when a Node on one of its routes is found to be failing outright, rather
+// than merely slow, so that new routes stay clear of it for a while
+#[derive(PartialEq, Debug, Message, Clone)]
+pub struct NodeProblemReport {
+    pub public_key: PublicKey,
+    pub problem: NodeProblem,
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use std::str::FromStr;
 
+    #[test]
+    fn route_refusals_explain_themselves() {
+        assert_eq!(
+            RouteRefusal::NotEnoughNodes {
+                known: 1,
+                required: 3
+            }
+            .to_string(),
+            String::from("Only 1 Node(s) known that can carry traffic; at least 3 required")
+        );
+        assert_eq!(
+            RouteRefusal::NoExitNode(String::from("booga")).to_string(),
+            String::from("No exit Node can be reached: booga")
+        );
+        assert_eq!(
+            RouteRefusal::NoExitInLocation(String::from("DE")).to_string(),
+            String::from("No known exit Node declares itself to be in DE")
+        );
+        assert_eq!(
+            RouteRefusal::NoReturnRoute(String::from("booga")).to_string(),
+            String::from("No route back can be found: booga")
+        );
+        assert_eq!(
+            RouteRefusal::InsufficientHopDiversity(String::from("booga")).to_string(),
+            String::from("Insufficient hop diversity: booga")
+        );
+        assert_eq!(
+            RouteRefusal::NoConsumingWallet.to_string(),
+            String::from("Routes of more than one hop require a consuming wallet")
+        );
+        assert_eq!(
+            RouteRefusal::Internal(String::from("booga")).to_string(),
+            String::from("booga")
+        );
+    }
+
+    #[test]
+    fn gossip_acceptance_stats_are_summarized_by_outcome() {
+        let subject = GossipAcceptanceStats {
+            gossip_messages: 1,
+            delta_gossip_messages: 1,
+            new_node_records: 2,
+            updated_node_records: 3,
+            duplicate_node_records: 4,
+            stale_node_records: 5,
+            rejected_blank_public_key: 6,
+            rejected_blank_neighbor_key: 7,
+            rejected_self_neighbor: 8,
+        };
+
+        assert_eq!(subject.accepted(), 5);
+        assert_eq!(subject.ignored(), 9);
+        assert_eq!(subject.rejected(), 21);
+    }
+
+    #[test]
+    fn capacity_class_is_parsed_from_its_name() {
+        assert_eq!(CapacityClass::from_name("low"), Some(CapacityClass::Low));
+        assert_eq!(
+            CapacityClass::from_name("medium"),
+            Some(CapacityClass::Medium)
+        );
+        assert_eq!(CapacityClass::from_name("high"), Some(CapacityClass::High));
+        assert_eq!(CapacityClass::from_name("booga"), None);
+        assert_eq!(CapacityClass::default(), CapacityClass::Medium);
+    }
+
+    #[test]
+    fn default_rate_card_charges_the_default_rates() {
+        assert_eq!(
+            RateCard::default(),
+            RateCard {
+                routing_service_rate: DEFAULT_PER_ROUTING_RATE,
+                routing_byte_rate: DEFAULT_PER_ROUTING_BYTE_RATE,
+                exit_service_rate: DEFAULT_PER_EXIT_RATE,
+                exit_byte_rate: DEFAULT_PER_EXIT_BYTE_RATE,
+            }
+        );
+    }
+
     #[test]
     fn data_indefinite_route_request() {
         let result = RouteQueryMessage::data_indefinite_route_request(2);
@@ -161,10 +669,60 @@ mod tests {
                 target_component: Component::ProxyClient,
                 minimum_hop_count: 2,
                 return_component_opt: Some(Component::ProxyServer),
+                avoided_exit_keys: vec![],
+                exit_location_opt: None,
+                random_route: false,
+            }
+        );
+    }
+
+    #[test]
+    fn exit_tls_route_request() {
+        let result = RouteQueryMessage::exit_tls_route_request(2);
+
+        assert_eq!(
+            result,
+            RouteQueryMessage {
+                target_type: TargetType::ExitTls,
+                target_key_opt: None,
+                target_component: Component::ProxyClient,
+                minimum_hop_count: 2,
+                return_component_opt: Some(Component::ProxyServer),
+                avoided_exit_keys: vec![],
+                exit_location_opt: None,
+                random_route: false,
+            }
+        );
+    }
+
+    #[test]
+    fn cover_route_request() {
+        let result = RouteQueryMessage::cover_route_request(2);
+
+        assert_eq!(
+            result,
+            RouteQueryMessage {
+                target_type: TargetType::Standard,
+                target_key_opt: None,
+                target_component: Component::Composite,
+                minimum_hop_count: 2,
+                return_component_opt: Some(Component::Composite),
+                avoided_exit_keys: vec![],
+                exit_location_opt: None,
+                random_route: true,
             }
         );
     }
 
+    #[test]
+    fn exiting_in_constrains_the_exit_location() {
+        let result = RouteQueryMessage::data_indefinite_route_request(2)
+            .exiting_in(Some(String::from("DE")));
+
+        assert_eq!(result.exit_location_opt, Some(String::from("DE")));
+        assert_eq!(result.target_type, TargetType::Standard);
+    }
+
     #[test]
     fn neighborhood_config_is_not_decentralized_if_there_are_no_neighbor_configs() {
         let subject = NeighborhoodConfig {
@@ -182,7 +740,8 @@ mod tests {
     }
 
     #[test]
-    fn neighborhood_config_is_not_decentralized_if_the_sentinel_ip_address_is_used() {
+    fn neighborhood_config_with_neighbors_is_decentralized_but_originate_only_if_the_sentinel_ip_address_is_used(
+    ) {
         let subject = NeighborhoodConfig {
             neighbor_configs: vec![(
                 PublicKey::new(&b"key"[..]),
@@ -195,9 +754,8 @@ mod tests {
             clandestine_port_list: vec![1234],
         };
 
-        let result = subject.is_decentralized();
-
-        assert_eq!(result, false);
+        assert_eq!(subject.is_decentralized(), true);
+        assert_eq!(subject.is_originate_only(), true);
     }
 
     #[test]
@@ -234,8 +792,97 @@ mod tests {
             clandestine_port_list: vec![1234],
         };
 
-        let result = subject.is_decentralized();
+        assert_eq!(subject.is_decentralized(), true);
+        assert_eq!(subject.is_originate_only(), false);
+    }
+
+    #[test]
+    fn an_empty_topology_query_asks_for_the_first_default_page_of_everything() {
+        let result = TopologyQueryMessage::from_json("{}").unwrap();
+
+        assert_eq!(
+            result,
+            TopologyQueryMessage {
+                scope: TopologyScope::All,
+                filter: TopologyFilter::default(),
+                cursor: None,
+                page_size: DEFAULT_TOPOLOGY_PAGE_SIZE,
+            }
+        );
+    }
+
+    #[test]
+    fn a_full_topology_query_is_understood() {
+        let result = TopologyQueryMessage::from_json(
+            r#"{"scope": "neighbors", "filter": {"capability": "exit_tls", "country": "de", "min_version": 3}, "cursor": "AQIDBA", "page_size": 25}"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            result,
+            TopologyQueryMessage {
+                scope: TopologyScope::Neighbors,
+                filter: TopologyFilter {
+                    capability: Some(NodeCapability::ExitTls),
+                    country: Some(String::from("de")),
+                    min_version: Some(3),
+                },
+                cursor: Some(String::from("AQIDBA")),
+                page_size: 25,
+            }
+        );
+    }
+
+    #[test]
+    fn a_topology_query_that_makes_no_sense_is_refused() {
+        let result = TopologyQueryMessage::from_json(r#"{"scope": "everywhere"}"#);
+
+        let msg = result.err().unwrap();
+        assert_eq!(
+            msg.starts_with(r#"Bad topology query '{"scope": "everywhere"}': "#),
+            true,
+            "{}",
+            msg
+        );
+    }
+
+    #[test]
+    fn topology_page_size_is_kept_within_bounds() {
+        let query = |page_size| TopologyQueryMessage {
+            page_size,
+            ..TopologyQueryMessage::from_json("{}").unwrap()
+        };
+
+        assert_eq!(query(0).effective_page_size(), DEFAULT_TOPOLOGY_PAGE_SIZE);
+        assert_eq!(query(25).effective_page_size(), 25);
+        assert_eq!(
+            query(MAX_TOPOLOGY_PAGE_SIZE + 1).effective_page_size(),
+            MAX_TOPOLOGY_PAGE_SIZE
+        );
+    }
+
+    #[test]
+    fn topology_page_for_the_ui_is_wrapped_with_a_message_type() {
+        let subject = TopologyPage {
+            nodes: vec![TopologyNode {
+                public_key: String::from("AQIDBA"),
+                node_addr: Some(String::from("1.2.3.4:1234")),
+                version: 2,
+                country: None,
+                is_bootstrap_node: false,
+                offers_exit_tls: true,
+                relay_only: false,
+                neighbors: vec![String::from("BQYHCA")],
+            }],
+            next_cursor: None,
+        };
+
+        let result: serde_json::Value = serde_json::from_str(&subject.to_ui_json(42)).unwrap();
 
-        assert_eq!(result, true);
+        assert_eq!(result["message_type"], "topology_page");
+        assert_eq!(result["request_id"], 42);
+        assert_eq!(result["payload"]["nodes"][0]["public_key"], "AQIDBA");
+        assert_eq!(result["payload"]["nodes"][0]["neighbors"][0], "BQYHCA");
+        assert_eq!(result["payload"].get("next_cursor"), None);
     }
 }
@@ -34,6 +34,14 @@ impl NodeAddr {
     pub fn ports(&self) -> Vec<u16> {
         self.ports.clone()
     }
+
+    // IPv6 addresses are bracketed, as in URLs, so their colons can't be mistaken for the port separator
+    fn ip_addr_string(&self) -> String {
+        match self.ip_addr {
+            IpAddr::V4(ipv4_addr) => format!("{}", ipv4_addr),
+            IpAddr::V6(ipv6_addr) => format!("[{}]", ipv6_addr),
+        }
+    }
 }
 
 impl<'a> From<&'a SocketAddr> for NodeAddr {
@@ -59,7 +67,7 @@ impl Clone for NodeAddr {
 
 impl Debug for NodeAddr {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        write!(f, "{}:{:?}", self.ip_addr(), self.ports())
+        write!(f, "{}:{:?}", self.ip_addr_string(), self.ports())
     }
 }
 
@@ -70,7 +78,7 @@ impl Display for NodeAddr {
             .iter()
             .map(|x| format!("{}", x))
             .collect::<Vec<String>>();
-        write!(f, "{}:{}", self.ip_addr(), port_list.join(","))
+        write!(f, "{}:{}", self.ip_addr_string(), port_list.join(","))
     }
 }
 
@@ -78,10 +86,14 @@ impl FromStr for NodeAddr {
     type Err = String;
 
     fn from_str(input: &str) -> Result<NodeAddr, String> {
-        let pieces: Vec<&str> = input.split(":").collect();
+        let pieces: Vec<&str> = if input.starts_with("[") {
+            input[1..].splitn(2, "]:").collect()
+        } else {
+            input.split(":").collect()
+        };
         if pieces.len() != 2 {
             return Err(format!(
-                "NodeAddr should be expressed as '<IP address>:<port>,<port>,...', not '{}'",
+                "NodeAddr should be expressed as '<IP address>:<port>,<port>,...' or '[<IPv6 address>]:<port>,<port>,...', not '{}'",
                 input
             ));
         }
@@ -209,7 +221,7 @@ mod tests {
         assert_eq!(
             result,
             Err(String::from(
-                "NodeAddr should be expressed as '<IP address>:<port>,<port>,...', not 'Booga'"
+                "NodeAddr should be expressed as '<IP address>:<port>,<port>,...' or '[<IPv6 address>]:<port>,<port>,...', not 'Booga'"
             ))
         );
     }
@@ -262,4 +274,50 @@ mod tests {
             ))
         );
     }
+
+    #[test]
+    fn ipv6_node_addrs_produce_bracketed_strings() {
+        let ip_addr = IpAddr::from_str("2001:db8::1").unwrap();
+        let subject = NodeAddr::new(&ip_addr, &vec![2345, 1234]);
+
+        assert_eq!(format!("{}", subject), "[2001:db8::1]:1234,2345");
+        assert_eq!(format!("{:?}", subject), "[2001:db8::1]:[1234, 2345]");
+    }
+
+    #[test]
+    fn ipv6_node_addrs_from_str_follows_the_happy_path() {
+        let result = NodeAddr::from_str("[2001:db8::1]:1234,2345,3456");
+
+        assert_eq!(
+            result,
+            Ok(NodeAddr::new(
+                &IpAddr::from_str("2001:db8::1").unwrap(),
+                &vec!(1234, 2345, 3456)
+            ))
+        );
+    }
+
+    #[test]
+    fn ipv6_node_addrs_from_str_needs_brackets() {
+        let result = NodeAddr::from_str("2001:db8::1:1234");
+
+        assert_eq!(
+            result,
+            Err(String::from(
+                "NodeAddr should be expressed as '<IP address>:<port>,<port>,...' or '[<IPv6 address>]:<port>,<port>,...', not '2001:db8::1:1234'"
+            ))
+        );
+    }
+
+    #[test]
+    fn ipv6_node_addrs_from_str_needs_good_ip_address() {
+        let result = NodeAddr::from_str("[2001:db8::g]:1234");
+
+        assert_eq!(
+            result,
+            Err(String::from(
+                "NodeAddr must have a valid IP address, not '2001:db8::g'"
+            ))
+        );
+    }
 }
@@ -0,0 +1,66 @@
+// Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use crate::sub_lib::framer::FramedChunk;
+use crate::sub_lib::framer::Framer;
+
+// For streams with no framing of their own: whatever has arrived so far is one frame
+pub struct PassThroughFramer {
+    data_so_far: Vec<u8>,
+}
+
+impl Framer for PassThroughFramer {
+    fn add_data(&mut self, data: &[u8]) {
+        self.data_so_far.extend(data);
+    }
+
+    fn take_frame(&mut self) -> Option<FramedChunk> {
+        if self.data_so_far.is_empty() {
+            None
+        } else {
+            Some(FramedChunk {
+                chunk: self.data_so_far.drain(..).collect(),
+                last_chunk: false,
+            })
+        }
+    }
+}
+
+impl PassThroughFramer {
+    pub fn new() -> PassThroughFramer {
+        PassThroughFramer {
+            data_so_far: Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_framer_has_no_frame() {
+        let mut subject = PassThroughFramer::new();
+
+        let result = subject.take_frame();
+
+        assert_eq!(result, None);
+    }
+
+    #[test]
+    fn everything_added_since_the_last_frame_is_the_next_frame() {
+        let mut subject = PassThroughFramer::new();
+        subject.add_data(&b"booga"[..]);
+        subject.add_data(&b"wooga"[..]);
+
+        let first = subject.take_frame();
+        let second = subject.take_frame();
+
+        assert_eq!(
+            first,
+            Some(FramedChunk {
+                chunk: b"boogawooga".to_vec(),
+                last_chunk: false
+            })
+        );
+        assert_eq!(second, None);
+    }
+}
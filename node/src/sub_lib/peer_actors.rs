@@ -2,6 +2,7 @@
 use crate::sub_lib::accountant::AccountantSubs;
 use crate::sub_lib::dispatcher::DispatcherSubs;
 use crate::sub_lib::hopper::HopperSubs;
+use crate::sub_lib::metrics::MetricsSubs;
 use crate::sub_lib::neighborhood::NeighborhoodSubs;
 use crate::sub_lib::proxy_client::ProxyClientSubs;
 use crate::sub_lib::proxy_server::ProxyServerSubs;
@@ -20,6 +21,7 @@ pub struct PeerActors {
     pub neighborhood: NeighborhoodSubs,
     pub accountant: AccountantSubs,
     pub ui_gateway: UiGatewaySubs,
+    pub metrics: MetricsSubs,
 }
 
 impl Debug for PeerActors {
@@ -33,6 +35,19 @@ pub struct BindMessage {
     pub peer_actors: PeerActors,
 }
 
+// Sent, once the Node has been told to go down, to each actor with something to finish first. By
+// the time an actor has handled it, whatever it had to say has been sent on.
+#[derive(Debug, Message, Clone, PartialEq)]
+pub struct ShutdownMessage {}
+
+// Asks an actor that carries streams how many it has open right now
+#[derive(Debug, Clone, PartialEq)]
+pub struct StreamCountQueryMessage {}
+
+impl Message for StreamCountQueryMessage {
+    type Result = usize;
+}
+
 #[cfg(test)]
 mod tests {
     use crate::test_utils::recorder::peer_actors_builder;
@@ -47,5 +62,4 @@ mod tests {
 
         assert_eq!(result, String::from("PeerActors"))
     }
-
 }
@@ -0,0 +1,96 @@
+// Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use crate::sub_lib::neighborhood::LOAD_ADVERTISEMENT_THRESHOLD;
+use crate::sub_lib::neighborhood::LOAD_SAMPLE_INTERVAL;
+use crate::sub_lib::neighborhood::WALLET_ROTATION_CHECK_INTERVAL;
+use crate::sub_lib::stream_handler_pool::STREAM_READ_BUFFER_SIZE;
+use std::time::Duration;
+
+pub const LOW_POWER_LOAD_SAMPLE_INTERVAL: Duration = Duration::from_secs(30);
+pub const LOW_POWER_LOAD_ADVERTISEMENT_THRESHOLD: u8 = 25;
+pub const LOW_POWER_WALLET_ROTATION_CHECK_INTERVAL: Duration = Duration::from_secs(300);
+pub const LOW_POWER_STREAM_READ_BUFFER_SIZE: usize = 0x2000;
+
+// The timings and sizes that decide how much work a Node does when nothing much is happening.
+// Routers and single-board computers can't afford the standard ones, so --low_power trades
+// freshness of the Node's advertised load for fewer wakeups, less Gossip and less memory.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct PerformanceProfile {
+    // How often the Hopper reports how much it has relayed, and the Neighborhood decays idle load
+    pub load_sample_interval: Duration,
+    // How far the load factor must drift, in percentage points, before it's Gossiped again
+    pub load_advertisement_threshold: u8,
+    // How often the Neighborhood checks whether it's time to rotate earning wallets
+    pub wallet_rotation_check_interval: Duration,
+    // How many bytes each StreamReader reads from its stream at a time
+    pub stream_read_buffer_size: usize,
+}
+
+impl PerformanceProfile {
+    pub fn standard() -> PerformanceProfile {
+        PerformanceProfile {
+            load_sample_interval: LOAD_SAMPLE_INTERVAL,
+            load_advertisement_threshold: LOAD_ADVERTISEMENT_THRESHOLD,
+            wallet_rotation_check_interval: WALLET_ROTATION_CHECK_INTERVAL,
+            stream_read_buffer_size: STREAM_READ_BUFFER_SIZE,
+        }
+    }
+
+    pub fn low_power() -> PerformanceProfile {
+        PerformanceProfile {
+            load_sample_interval: LOW_POWER_LOAD_SAMPLE_INTERVAL,
+            load_advertisement_threshold: LOW_POWER_LOAD_ADVERTISEMENT_THRESHOLD,
+            wallet_rotation_check_interval: LOW_POWER_WALLET_ROTATION_CHECK_INTERVAL,
+            stream_read_buffer_size: LOW_POWER_STREAM_READ_BUFFER_SIZE,
+        }
+    }
+}
+
+impl Default for PerformanceProfile {
+    fn default() -> Self {
+        PerformanceProfile::standard()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn constants_have_correct_values() {
+        assert_eq!(LOW_POWER_LOAD_SAMPLE_INTERVAL, Duration::from_secs(30));
+        assert_eq!(LOW_POWER_LOAD_ADVERTISEMENT_THRESHOLD, 25);
+        assert_eq!(
+            LOW_POWER_WALLET_ROTATION_CHECK_INTERVAL,
+            Duration::from_secs(300)
+        );
+        assert_eq!(LOW_POWER_STREAM_READ_BUFFER_SIZE, 0x2000);
+        assert_eq!(STREAM_READ_BUFFER_SIZE, 0x10000);
+    }
+
+    #[test]
+    fn standard_profile_is_the_default() {
+        let result = PerformanceProfile::default();
+
+        assert_eq!(
+            result,
+            PerformanceProfile {
+                load_sample_interval: LOAD_SAMPLE_INTERVAL,
+                load_advertisement_threshold: LOAD_ADVERTISEMENT_THRESHOLD,
+                wallet_rotation_check_interval: WALLET_ROTATION_CHECK_INTERVAL,
+                stream_read_buffer_size: STREAM_READ_BUFFER_SIZE,
+            }
+        );
+    }
+
+    #[test]
+    fn low_power_profile_does_less_work_than_the_standard_one() {
+        let standard = PerformanceProfile::standard();
+
+        let result = PerformanceProfile::low_power();
+
+        assert!(result.load_sample_interval > standard.load_sample_interval);
+        assert!(result.load_advertisement_threshold > standard.load_advertisement_threshold);
+        assert!(result.wallet_rotation_check_interval > standard.wallet_rotation_check_interval);
+        assert!(result.stream_read_buffer_size < standard.stream_read_buffer_size);
+    }
+}
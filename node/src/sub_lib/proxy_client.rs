@@ -1,18 +1,219 @@
 // Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
 use crate::sub_lib::hopper::ExpiredCoresPackage;
 use crate::sub_lib::peer_actors::BindMessage;
+use crate::sub_lib::peer_actors::ShutdownMessage;
+use crate::sub_lib::peer_actors::StreamCountQueryMessage;
 use crate::sub_lib::sequence_buffer::SequencedPacket;
 use crate::sub_lib::stream_key::StreamKey;
+use crate::sub_lib::utils::is_false;
 use actix::Message;
 use actix::Recipient;
 use actix::Syn;
 use serde_derive::{Deserialize, Serialize};
+use sha1;
+use std::collections::HashMap;
+use std::net::IpAddr;
 use std::net::Ipv4Addr;
 use std::net::SocketAddr;
 use std::net::SocketAddrV4;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
 
-pub const TEMPORARY_PER_EXIT_BYTE_RATE: u64 = 2;
-pub const TEMPORARY_PER_EXIT_RATE: u64 = 1;
+pub const DEFAULT_PER_EXIT_BYTE_RATE: u64 = 2;
+pub const DEFAULT_PER_EXIT_RATE: u64 = 1;
+// The most server data an exit Node will put into a single ClientResponsePayload; anything bigger
+// from the framer goes back as several consecutively-sequenced packets.
+pub const MAX_RESPONSE_CHUNK_SIZE: usize = 16384;
+// How long an exit Node waits for a server to accept a connection, unless --exit_connect_timeout
+// says otherwise
+pub const DEFAULT_EXIT_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExitThroughputConfig {
+    pub upstream_bytes_per_second: Option<u64>,
+    pub downstream_bytes_per_second: Option<u64>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ThroughputUtilization {
+    pub bytes_per_second_cap: Option<u64>,
+    pub bytes_last_second: u64,
+    pub total_bytes: u64,
+}
+
+// Asks the ProxyClient how much of each exit throughput cap it's using
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExitThroughputQueryMessage {}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ExitThroughput {
+    pub upstream: ThroughputUtilization,
+    pub downstream: ThroughputUtilization,
+}
+
+impl Message for ExitThroughputQueryMessage {
+    type Result = ExitThroughput;
+}
+
+// How much an exit Node writes down about the traffic it exits. Off is the default: an exit
+// operator has to ask for anything else.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExitLogLevel {
+    Off,
+    Aggregate, // stream and byte counts per top-level domain
+    Full,      // host and byte counts per stream, never any payload
+}
+
+impl ExitLogLevel {
+    pub fn from_name(name: &str) -> Option<ExitLogLevel> {
+        match name {
+            "off" => Some(ExitLogLevel::Off),
+            "aggregate" => Some(ExitLogLevel::Aggregate),
+            "full" => Some(ExitLogLevel::Full),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExitLogConfig {
+    pub level: ExitLogLevel,
+    pub data_directory: PathBuf,
+}
+
+// Slow-to-answer services (IMAPS and POP3S, say) can be given longer than everybody else
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExitConnectTimeouts {
+    pub default: Duration,
+    pub by_port: HashMap<u16, Duration>,
+}
+
+impl Default for ExitConnectTimeouts {
+    fn default() -> Self {
+        ExitConnectTimeouts {
+            default: DEFAULT_EXIT_CONNECT_TIMEOUT,
+            by_port: HashMap::new(),
+        }
+    }
+}
+
+impl ExitConnectTimeouts {
+    pub fn for_port(&self, port: u16) -> Duration {
+        match self.by_port.get(&port) {
+            Some(timeout) => *timeout,
+            None => self.default,
+        }
+    }
+}
+
+// What an exit Node won't carry, whoever asks. A blocked hostname takes every name under it with
+// it; a blocked IP address is refused however the originator named it, even if it was only found
+// by resolving a hostname that isn't blocked. An originator may ask for its hostnames to be looked
+// up with one of offered_dns_servers, but with no other.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ExitPolicy {
+    pub blocked_ports: Vec<u16>,
+    pub blocked_hostnames: Vec<String>, // lowercase, without the trailing dot
+    pub blocked_ip_addrs: Vec<IpAddr>,
+    pub offered_dns_servers: Vec<IpAddr>,
+}
+
+impl ExitPolicy {
+    // Why a request must be refused before its hostname is even looked up, if it must
+    pub fn refusal(&self, target_hostname: &Option<String>, target_port: u16) -> Option<String> {
+        if self.blocked_ports.contains(&target_port) {
+            return Some(format!("port {} is blocked", target_port));
+        }
+        let hostname = target_hostname
+            .as_ref()?
+            .trim_end_matches('.')
+            .to_lowercase();
+        if let Ok(ip_addr) = IpAddr::from_str(&hostname) {
+            if self.blocks_ip_addr(ip_addr) {
+                return Some(format!("address {} is blocked", ip_addr));
+            }
+        }
+        self.blocked_hostnames
+            .iter()
+            .find(|blocked| hostname == **blocked || hostname.ends_with(&format!(".{}", blocked)))
+            .map(|blocked| format!("host {} is blocked", blocked))
+    }
+
+    pub fn blocks_ip_addr(&self, ip_addr: IpAddr) -> bool {
+        self.blocked_ip_addrs.contains(&ip_addr)
+    }
+
+    // The same for any two policies that refuse the same things, whatever order they were listed in
+    pub fn summary_hash(&self) -> String {
+        let mut ports = self.blocked_ports.clone();
+        ports.sort();
+        let mut hostnames = self.blocked_hostnames.clone();
+        hostnames.sort();
+        let mut ip_addrs = self.blocked_ip_addrs.clone();
+        ip_addrs.sort();
+        let summary = format!(
+            "ports:{:?};hostnames:{:?};ip_addrs:{:?}",
+            ports, hostnames, ip_addrs
+        );
+        let mut hash = sha1::Sha1::new();
+        hash.update(summary.as_bytes());
+        hash.digest().to_string()
+    }
+
+    pub fn dns_server_refusal(&self, dns_server_opt: Option<IpAddr>) -> Option<String> {
+        match dns_server_opt {
+            Some(dns_server) if !self.offered_dns_servers.contains(&dns_server) => Some(format!(
+                "looking hostnames up with DNS server {} is not offered",
+                dns_server
+            )),
+            _ => None,
+        }
+    }
+}
+
+// How an exit Node talks to its --dns_servers. Over UDP and TCP anyone on the path can read the
+// hostnames it looks up; over TLS they can't, but then each server needs the name on its
+// certificate.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DnsProtocol {
+    Udp,
+    Tcp,
+    Tls,
+}
+
+impl DnsProtocol {
+    pub fn from_name(name: &str) -> Option<DnsProtocol> {
+        match name {
+            "udp" => Some(DnsProtocol::Udp),
+            "tcp" => Some(DnsProtocol::Tcp),
+            "tls" => Some(DnsProtocol::Tls),
+            _ => None,
+        }
+    }
+
+    pub fn default_port(self) -> u16 {
+        match self {
+            DnsProtocol::Udp | DnsProtocol::Tcp => 53,
+            DnsProtocol::Tls => 853,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DnsResolverConfig {
+    pub protocol: DnsProtocol,
+    pub tls_dns_names: HashMap<IpAddr, String>,
+}
+
+impl Default for DnsResolverConfig {
+    fn default() -> Self {
+        DnsResolverConfig {
+            protocol: DnsProtocol::Udp,
+            tls_dns_names: HashMap::new(),
+        }
+    }
+}
 
 pub fn error_socket_addr() -> SocketAddr {
     SocketAddr::from(SocketAddrV4::new(Ipv4Addr::new(0, 0, 0, 0), 0))
@@ -22,6 +223,22 @@ pub fn error_socket_addr() -> SocketAddr {
 pub struct ClientResponsePayload {
     pub stream_key: StreamKey,
     pub sequenced_packet: SequencedPacket,
+    // How many request packets of this stream the exit has received so far; only sent in answer to
+    // an upload_window
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub requests_received: Option<u64>,
+    // Set only on a last packet, when the server reset the connection instead of closing it, so
+    // that the originator knows the response may have been cut short
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub truncated: bool,
+    // The data is one UDP datagram from the server, in answer to a udp request; the originator
+    // should pass datagrams on as they come rather than wait to put them in sequence
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub udp: bool,
+    // Set only on a last packet, when the exit never got the request to the server at all: it
+    // couldn't find the server, couldn't connect to it, or wouldn't, so another exit might do better
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub exit_failed: bool,
 }
 
 #[derive(Clone)]
@@ -29,6 +246,9 @@ pub struct ProxyClientSubs {
     pub bind: Recipient<Syn, BindMessage>,
     pub from_hopper: Recipient<Syn, ExpiredCoresPackage>,
     pub inbound_server_data: Recipient<Syn, InboundServerData>,
+    pub stream_count_query: Recipient<Syn, StreamCountQueryMessage>,
+    pub exit_throughput_query: Recipient<Syn, ExitThroughputQueryMessage>,
+    pub shutdown: Recipient<Syn, ShutdownMessage>,
 }
 
 impl ClientResponsePayload {
@@ -40,8 +260,38 @@ impl ClientResponsePayload {
                 sequence_number: 0,
                 last_data: true,
             },
+            requests_received: None,
+            truncated: false,
+            udp: false,
+            exit_failed: false,
         }
     }
+
+    // Carries nothing from the server: it only tells the originator how many request packets have
+    // arrived, so it doesn't take up a sequence number in the response stream
+    pub fn make_acknowledgement_payload(
+        stream_key: StreamKey,
+        requests_received: u64,
+    ) -> ClientResponsePayload {
+        ClientResponsePayload {
+            stream_key,
+            sequenced_packet: SequencedPacket {
+                data: vec![],
+                sequence_number: 0,
+                last_data: false,
+            },
+            requests_received: Some(requests_received),
+            truncated: false,
+            udp: false,
+            exit_failed: false,
+        }
+    }
+
+    pub fn is_acknowledgement_only(&self) -> bool {
+        self.requests_received.is_some()
+            && self.sequenced_packet.data.is_empty()
+            && !self.sequenced_packet.last_data
+    }
 }
 
 #[derive(PartialEq, Clone, Message, Debug)]
@@ -51,6 +301,7 @@ pub struct InboundServerData {
     pub sequence_number: u64,
     pub source: SocketAddr,
     pub data: Vec<u8>,
+    pub truncated: bool,
 }
 
 #[cfg(test)]
@@ -58,6 +309,172 @@ mod tests {
     use super::*;
     use crate::sub_lib::utils::tests::make_meaningless_stream_key;
 
+    #[test]
+    fn exit_log_levels_can_be_found_by_name() {
+        assert_eq!(ExitLogLevel::from_name("off"), Some(ExitLogLevel::Off));
+        assert_eq!(
+            ExitLogLevel::from_name("aggregate"),
+            Some(ExitLogLevel::Aggregate)
+        );
+        assert_eq!(ExitLogLevel::from_name("full"), Some(ExitLogLevel::Full));
+        assert_eq!(ExitLogLevel::from_name("booga"), None);
+    }
+
+    #[test]
+    fn dns_protocols_can_be_found_by_name() {
+        assert_eq!(DnsProtocol::from_name("udp"), Some(DnsProtocol::Udp));
+        assert_eq!(DnsProtocol::from_name("tcp"), Some(DnsProtocol::Tcp));
+        assert_eq!(DnsProtocol::from_name("tls"), Some(DnsProtocol::Tls));
+        assert_eq!(DnsProtocol::from_name("https"), None);
+    }
+
+    #[test]
+    fn dns_over_tls_has_a_port_of_its_own() {
+        assert_eq!(DnsProtocol::Udp.default_port(), 53);
+        assert_eq!(DnsProtocol::Tcp.default_port(), 53);
+        assert_eq!(DnsProtocol::Tls.default_port(), 853);
+    }
+
+    #[test]
+    fn dns_resolver_config_defaults_to_plain_udp() {
+        assert_eq!(
+            DnsResolverConfig::default(),
+            DnsResolverConfig {
+                protocol: DnsProtocol::Udp,
+                tls_dns_names: HashMap::new(),
+            }
+        );
+    }
+
+    #[test]
+    fn exit_connect_timeouts_fall_back_on_the_default_for_ports_without_an_override() {
+        let mut by_port = HashMap::new();
+        by_port.insert(993, Duration::from_secs(30));
+        let subject = ExitConnectTimeouts {
+            default: Duration::from_secs(5),
+            by_port,
+        };
+
+        assert_eq!(subject.for_port(993), Duration::from_secs(30));
+        assert_eq!(subject.for_port(443), Duration::from_secs(5));
+        assert_eq!(
+            ExitConnectTimeouts::default().for_port(993),
+            DEFAULT_EXIT_CONNECT_TIMEOUT
+        );
+    }
+
+    fn make_exit_policy() -> ExitPolicy {
+        ExitPolicy {
+            blocked_ports: vec![25],
+            blocked_hostnames: vec![String::from("blocked.com")],
+            blocked_ip_addrs: vec![IpAddr::from_str("6.6.6.6").unwrap()],
+            offered_dns_servers: vec![IpAddr::from_str("9.9.9.9").unwrap()],
+        }
+    }
+
+    #[test]
+    fn exit_policy_refuses_blocked_ports() {
+        let subject = make_exit_policy();
+
+        assert_eq!(
+            subject.refusal(&Some(String::from("server.com")), 25),
+            Some(String::from("port 25 is blocked"))
+        );
+        assert_eq!(
+            subject.refusal(&None, 25),
+            Some(String::from("port 25 is blocked"))
+        );
+        assert_eq!(
+            subject.refusal(&Some(String::from("server.com")), 443),
+            None
+        );
+    }
+
+    #[test]
+    fn exit_policy_refuses_blocked_hostnames_and_everything_under_them() {
+        let subject = make_exit_policy();
+
+        assert_eq!(
+            subject.refusal(&Some(String::from("blocked.com")), 443),
+            Some(String::from("host blocked.com is blocked"))
+        );
+        assert_eq!(
+            subject.refusal(&Some(String::from("WWW.Blocked.COM.")), 443),
+            Some(String::from("host blocked.com is blocked"))
+        );
+        assert_eq!(
+            subject.refusal(&Some(String::from("notblocked.com")), 443),
+            None
+        );
+        assert_eq!(
+            subject.refusal(&Some(String::from("blocked.com.au")), 443),
+            None
+        );
+        assert_eq!(subject.refusal(&None, 443), None);
+    }
+
+    #[test]
+    fn exit_policy_refuses_blocked_ip_addresses_given_as_hostnames() {
+        let subject = make_exit_policy();
+
+        assert_eq!(
+            subject.refusal(&Some(String::from("6.6.6.6")), 443),
+            Some(String::from("address 6.6.6.6 is blocked"))
+        );
+        assert_eq!(subject.refusal(&Some(String::from("6.6.6.7")), 443), None);
+        assert_eq!(
+            subject.blocks_ip_addr(IpAddr::from_str("6.6.6.6").unwrap()),
+            true
+        );
+        assert_eq!(
+            subject.blocks_ip_addr(IpAddr::from_str("6.6.6.7").unwrap()),
+            false
+        );
+    }
+
+    #[test]
+    fn exit_policy_refuses_dns_servers_it_does_not_offer() {
+        let subject = make_exit_policy();
+
+        assert_eq!(
+            subject.dns_server_refusal(Some(IpAddr::from_str("1.1.1.1").unwrap())),
+            Some(String::from(
+                "looking hostnames up with DNS server 1.1.1.1 is not offered"
+            ))
+        );
+        assert_eq!(
+            subject.dns_server_refusal(Some(IpAddr::from_str("9.9.9.9").unwrap())),
+            None
+        );
+        assert_eq!(subject.dns_server_refusal(None), None);
+    }
+
+    #[test]
+    fn exit_policy_summary_hash_depends_on_what_is_refused_but_not_its_order() {
+        let subject = make_exit_policy();
+        let mut reordered = make_exit_policy();
+        reordered.blocked_ports = vec![465, 25];
+        let mut reversed = reordered.clone();
+        reversed.blocked_ports = vec![25, 465];
+        reversed.offered_dns_servers = vec![];
+
+        assert_eq!(subject.summary_hash().len(), 40);
+        assert_eq!(reordered.summary_hash(), reversed.summary_hash());
+        assert_ne!(subject.summary_hash(), reordered.summary_hash());
+        assert_ne!(subject.summary_hash(), ExitPolicy::default().summary_hash());
+    }
+
+    #[test]
+    fn default_exit_policy_refuses_nothing() {
+        let subject = ExitPolicy::default();
+
+        assert_eq!(subject.refusal(&Some(String::from("server.com")), 25), None);
+        assert_eq!(
+            subject.blocks_ip_addr(IpAddr::from_str("6.6.6.6").unwrap()),
+            false
+        );
+    }
+
     #[test]
     fn make_terminating_payload_makes_terminating_payload() {
         let stream_key: StreamKey = make_meaningless_stream_key();
@@ -73,7 +490,28 @@ mod tests {
                     sequence_number: 0,
                     last_data: true
                 },
+                requests_received: None,
+                truncated: false,
+                udp: false,
+                exit_failed: false,
             }
         )
     }
+
+    #[test]
+    fn acknowledgement_payload_is_recognizable_as_such() {
+        let stream_key: StreamKey = make_meaningless_stream_key();
+
+        let payload = ClientResponsePayload::make_acknowledgement_payload(stream_key, 42);
+
+        assert_eq!(payload.requests_received, Some(42));
+        assert_eq!(payload.is_acknowledgement_only(), true);
+        assert_eq!(
+            ClientResponsePayload::make_terminating_payload(stream_key).is_acknowledgement_only(),
+            false
+        );
+        let mut data_payload = payload.clone();
+        data_payload.sequenced_packet.data = b"booga".to_vec();
+        assert_eq!(data_payload.is_acknowledgement_only(), false);
+    }
 }
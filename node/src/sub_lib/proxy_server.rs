@@ -3,13 +3,38 @@ use crate::sub_lib::cryptde::PublicKey;
 use crate::sub_lib::dispatcher::InboundClientData;
 use crate::sub_lib::hopper::ExpiredCoresPackage;
 use crate::sub_lib::neighborhood::ExpectedService;
+use crate::sub_lib::neighborhood::RouteQueryResponse;
 use crate::sub_lib::peer_actors::BindMessage;
+use crate::sub_lib::peer_actors::ShutdownMessage;
+use crate::sub_lib::peer_actors::StreamCountQueryMessage;
 use crate::sub_lib::sequence_buffer::SequencedPacket;
 use crate::sub_lib::stream_key::StreamKey;
+use crate::sub_lib::ui_gateway::ReloadConfigMessage;
+use crate::sub_lib::utils::is_false;
 use actix::Message;
 use actix::Recipient;
 use actix::Syn;
 use serde_derive::{Deserialize, Serialize};
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::time::Duration;
+
+// How much of each stream's unacknowledged request data the ProxyServer keeps in memory for
+// sending again over another route, and where it may put the rest
+#[derive(Clone, Debug, PartialEq)]
+pub struct RetransmissionConfig {
+    pub memory_limit: usize,
+    pub spill_directory_opt: Option<PathBuf>,
+}
+
+// Bounds on the minimum hop count the ProxyServer asks for, and the round trip it tries to keep
+// routes under by moving between them
+#[derive(Clone, Debug, PartialEq)]
+pub struct AdaptiveHopsConfig {
+    pub min_hops: usize,
+    pub max_hops: usize,
+    pub latency_threshold: Duration,
+}
 
 #[derive(Copy, Clone, Debug, PartialEq, Deserialize, Serialize)]
 pub enum ProxyProtocol {
@@ -17,6 +42,9 @@ pub enum ProxyProtocol {
     TLS,
 }
 
+// Where an exit sends a tls_at_exit request, no matter which port the originator took it in on
+pub const EXIT_TLS_TARGET_PORT: u16 = 443;
+
 // TODO: Based on the way it's used, this struct should comprise two elements: one, a nested
 // struct that contains all the small, quickly-cloned things, and the other the big,
 // expensively-cloned SequencedPacket.
@@ -28,12 +56,49 @@ pub struct ClientRequestPayload {
     pub target_port: u16,
     pub protocol: ProxyProtocol,
     pub originator_public_key: PublicKey,
+    // The originator wants the exit to make this plain HTTP request over TLS to the server, and
+    // trusts the exit to see it in the clear. Only exits that advertise offers_exit_tls will.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub tls_at_exit: bool,
+    // The originator holds this stream to this many request packets in flight, once the exit has
+    // shown that it counts them with requests_received in its responses. An exit that sees it
+    // should answer at least every half window, even if the server has nothing to say.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub upload_window: Option<u64>,
+    // The data is one UDP datagram, which the exit sends as it is to target_port. There's no
+    // connection and no ordering: the exit ignores the sequence number, and last_data only tells
+    // it to close the socket.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub udp: bool,
+    // The originator would rather the exit looked target_hostname up with this public DNS server
+    // than with its own --dns_servers. An exit that doesn't offer it refuses the stream instead of
+    // quietly using its own.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dns_server: Option<IpAddr>,
+    // The browser hung up before the response was done, so nobody will see the rest of it. The
+    // exit closes its connection to the server at once, both ways, instead of finishing the
+    // download. An exit that doesn't know about it takes this for an ordinary last_data.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub cancel: bool,
+}
+
+// Turns adaptive hop count on or off, for a Node started with --adaptive_hops
+#[derive(Message, Clone, PartialEq, Debug)]
+pub struct AdaptiveHopsMessage {
+    pub enabled: bool,
 }
 
 #[derive(Message)]
 pub struct AddReturnRouteMessage {
     pub return_route_id: u32,
     pub expected_services: Vec<ExpectedService>,
+    pub route_keys: Vec<PublicKey>, // the Nodes both ways, to tell the Neighborhood how they did
+}
+
+#[derive(Message)]
+pub struct AddRouteMessage {
+    pub stream_key: StreamKey,
+    pub route: RouteQueryResponse,
 }
 
 #[derive(Clone)]
@@ -43,4 +108,9 @@ pub struct ProxyServerSubs {
     pub from_dispatcher: Recipient<Syn, InboundClientData>,
     pub from_hopper: Recipient<Syn, ExpiredCoresPackage>,
     pub add_return_route: Recipient<Syn, AddReturnRouteMessage>,
+    pub add_route: Recipient<Syn, AddRouteMessage>,
+    pub reload_config: Recipient<Syn, ReloadConfigMessage>,
+    pub adaptive_hops: Recipient<Syn, AdaptiveHopsMessage>,
+    pub stream_count_query: Recipient<Syn, StreamCountQueryMessage>,
+    pub shutdown: Recipient<Syn, ShutdownMessage>,
 }
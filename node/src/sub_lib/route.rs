@@ -8,7 +8,6 @@ use crate::sub_lib::dispatcher::Component;
 use crate::sub_lib::hop::LiveHop;
 use crate::sub_lib::wallet::Wallet;
 use serde_derive::{Deserialize, Serialize};
-use std::iter;
 
 #[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
 pub struct Route {
@@ -51,23 +50,29 @@ impl Route {
     pub fn next_hop(&self, cryptde: &dyn CryptDE) -> Result<LiveHop, RouteError> {
         match self.hops.first() {
             None => Err(RouteError::EmptyRoute),
-            Some(first) => Route::decode_hop(cryptde, &first.clone()),
+            Some(first) => Route::decode_hop(cryptde, first),
         }
     }
 
     pub fn shift(&mut self, cryptde: &dyn CryptDE) -> Result<LiveHop, RouteError> {
+        let next_hop = self.next_hop(cryptde)?;
+        self.discard_top_hop(cryptde)?;
+        return Ok(next_hop);
+    }
+
+    // For callers that have already decoded the top hop with next_hop(): moves it to the bottom
+    // of the Route and overwrites it with garbage in place, so the rest of the hops are neither
+    // decoded again nor copied.
+    pub fn discard_top_hop(&mut self, cryptde: &dyn CryptDE) -> Result<(), RouteError> {
         if self.hops.is_empty() {
             return Err(RouteError::EmptyRoute);
         }
-        let top_hop = self.hops.remove(0);
-        let top_hop_len = top_hop.len();
-        let next_hop = Route::decode_hop(cryptde, &top_hop)?;
-
-        let mut garbage_can: Vec<u8> = iter::repeat(0u8).take(top_hop_len).collect();
+        self.hops.rotate_left(1);
+        let top_hop = self.hops.pop().expect("Route disappeared");
+        let mut garbage_can: Vec<u8> = top_hop.into();
         cryptde.random(&mut garbage_can[..]);
-        self.hops.push(CryptData::new(&garbage_can[..]));
-
-        return Ok(next_hop);
+        self.hops.push(CryptData::from(garbage_can));
+        Ok(())
     }
 
     fn construct(
@@ -185,6 +190,7 @@ mod tests {
     use super::*;
     use crate::sub_lib::cryptde_null::CryptDENull;
     use serde_cbor;
+    use std::iter;
 
     #[test]
     fn id_decodes_return_route_id() {
@@ -446,6 +452,38 @@ mod tests {
         assert_eq!(result, RouteError::EmptyRoute);
     }
 
+    #[test]
+    fn discard_top_hop_leaves_the_rest_of_the_route_alone_and_adds_garbage_at_the_bottom() {
+        let mut cryptde = CryptDENull::new();
+        cryptde.generate_key_pair();
+        let consuming_wallet = Wallet::new("wallet");
+        let key12 = cryptde.public_key();
+        let key34 = PublicKey::new(&[3, 4]);
+        let mut subject = Route::one_way(
+            RouteSegment::new(vec![&key12, &key34], Component::ProxyClient),
+            &cryptde,
+            Some(consuming_wallet.clone()),
+        )
+        .unwrap();
+        let mut expected_hops = subject.hops.clone();
+        let top_hop = expected_hops.remove(0);
+        expected_hops.push(CryptData::from(vec!['4' as u8; top_hop.len()]));
+
+        subject.discard_top_hop(&cryptde).unwrap();
+
+        assert_eq!(subject.hops, expected_hops);
+    }
+
+    #[test]
+    fn discard_top_hop_complains_about_empty_route() {
+        let cryptde = CryptDENull::new();
+        let mut subject = Route { hops: Vec::new() };
+
+        let result = subject.discard_top_hop(&cryptde).err().unwrap();
+
+        assert_eq!(result, RouteError::EmptyRoute);
+    }
+
     #[test]
     fn shift_says_none_when_asked_for_next_hop_on_empty_route() {
         let mut cryptde = CryptDENull::new();
@@ -4,7 +4,9 @@ use crate::sub_lib::tokio_wrappers::ReadHalfWrapper;
 use crate::sub_lib::tokio_wrappers::ReadHalfWrapperReal;
 use crate::sub_lib::tokio_wrappers::WriteHalfWrapper;
 use crate::sub_lib::tokio_wrappers::WriteHalfWrapperReal;
+use futures::future::err;
 use futures::future::ok;
+use native_tls;
 use std::io::ErrorKind;
 use std::net::IpAddr;
 use std::net::SocketAddr;
@@ -16,6 +18,7 @@ use tokio::net::TcpStream;
 use tokio::prelude::Future;
 use tokio::reactor::Handle;
 use tokio::timer::Timeout;
+use tokio_tls::TlsConnector;
 
 pub const CONNECT_TIMEOUT_MS: u64 = 5000;
 pub type ConnectionInfoFuture = Box<dyn Future<Item = ConnectionInfo, Error = io::Error> + Send>;
@@ -29,13 +32,26 @@ pub struct ConnectionInfo {
 
 pub trait StreamConnector: Send {
     fn connect(&self, socket_addr: SocketAddr, logger: &Logger) -> ConnectionInfoFuture;
+    // Tries each of ip_addrs in turn, giving each one no longer than timeout to accept
     fn connect_one(
         &self,
         ip_addrs: Vec<IpAddr>,
         target_hostname: &String,
         target_port: u16,
+        timeout: Duration,
         logger: &Logger,
     ) -> Result<ConnectionInfo, io::Error>;
+    // Like connect_one, but with a TLS handshake, including validation of the server's certificate
+    // against target_hostname, before the connection is usable. The TCP connection is made at
+    // once, so its peer address is known right away; the handshake finishes in the future.
+    fn connect_one_tls(
+        &self,
+        ip_addrs: Vec<IpAddr>,
+        target_hostname: &String,
+        target_port: u16,
+        timeout: Duration,
+        logger: &Logger,
+    ) -> Result<(SocketAddr, ConnectionInfoFuture), io::Error>;
     fn split_stream(&self, stream: TcpStream, logger: &Logger) -> ConnectionInfo;
     fn split_stream_fut(&self, stream: TcpStream, logger: &Logger) -> ConnectionInfoFuture;
 }
@@ -84,34 +100,62 @@ impl StreamConnector for StreamConnectorReal {
         ip_addrs: Vec<IpAddr>,
         target_hostname: &String,
         target_port: u16,
+        timeout: Duration,
         logger: &Logger,
     ) -> Result<ConnectionInfo, io::Error> {
-        let mut last_error = io::Error::from(ErrorKind::Other);
-        let mut socket_addrs_tried = vec![];
-
-        for ip_addr in ip_addrs {
-            let socket_addr = SocketAddr::new(ip_addr, target_port);
+        let stream = self.connect_first(ip_addrs, target_hostname, target_port, timeout, logger)?;
+        Ok(self.split_stream(stream, logger))
+    }
 
-            match StdTcpStream::connect(&socket_addr) {
-                Ok(stream) => {
-                    logger.debug(format!("Connected new stream to {}", socket_addr));
-                    let tokio_stream = TcpStream::from_std(stream, &Handle::default())
-                        .expect("Tokio could not create a TcpStream");
-                    return Ok(self.split_stream(tokio_stream, logger));
-                }
-                Err(e) => {
-                    last_error = e;
-                    socket_addrs_tried.push(format!("{}", socket_addr));
-                    continue;
+    fn connect_one_tls(
+        &self,
+        ip_addrs: Vec<IpAddr>,
+        target_hostname: &String,
+        target_port: u16,
+        timeout: Duration,
+        logger: &Logger,
+    ) -> Result<(SocketAddr, ConnectionInfoFuture), io::Error> {
+        let stream = self.connect_first(ip_addrs, target_hostname, target_port, timeout, logger)?;
+        let local_addr = stream
+            .local_addr()
+            .expect("Connected stream has no local_addr");
+        let peer_addr = stream
+            .peer_addr()
+            .expect("Connected stream has no peer_addr");
+        let connector = match native_tls::TlsConnector::new() {
+            Ok(connector) => TlsConnector::from(connector),
+            Err(e) => {
+                logger.error(format!(
+                    "Could not set up TLS for {}: {}",
+                    target_hostname, e
+                ));
+                return Ok((
+                    peer_addr,
+                    Box::new(err(io::Error::new(ErrorKind::Other, format!("{}", e)))),
+                ));
+            }
+        };
+        let future_logger = logger.clone();
+        let hostname = target_hostname.clone();
+        let connection_info_future = connector
+            .connect(target_hostname, stream)
+            .map(move |tls_stream| {
+                let (read_half, write_half) = tls_stream.split();
+                ConnectionInfo {
+                    reader: Box::new(ReadHalfWrapperReal::new(read_half)),
+                    writer: Box::new(WriteHalfWrapperReal::new(write_half)),
+                    local_addr,
+                    peer_addr,
                 }
-            };
-        }
-
-        logger.error(format!(
-            "Could not connect to any of the IP addresses supplied for {}: {:?}",
-            target_hostname, socket_addrs_tried
-        ));
-        Err(last_error)
+            })
+            .map_err(move |e| {
+                future_logger.error(format!(
+                    "TLS handshake with {} at {} failed: {}",
+                    hostname, peer_addr, e
+                ));
+                io::Error::new(ErrorKind::Other, format!("{}", e))
+            });
+        Ok((peer_addr, Box::new(connection_info_future)))
     }
 
     fn split_stream(&self, stream: TcpStream, _logger: &Logger) -> ConnectionInfo {
@@ -137,6 +181,50 @@ impl StreamConnector for StreamConnectorReal {
     }
 }
 
+impl StreamConnectorReal {
+    fn connect_first(
+        &self,
+        ip_addrs: Vec<IpAddr>,
+        target_hostname: &String,
+        target_port: u16,
+        timeout: Duration,
+        logger: &Logger,
+    ) -> Result<TcpStream, io::Error> {
+        let mut last_error = io::Error::from(ErrorKind::Other);
+        let mut socket_addrs_tried = vec![];
+
+        for ip_addr in ip_addrs {
+            let socket_addr = SocketAddr::new(ip_addr, target_port);
+
+            match StdTcpStream::connect_timeout(&socket_addr, timeout) {
+                Ok(stream) => {
+                    logger.debug(format!("Connected new stream to {}", socket_addr));
+                    let tokio_stream = TcpStream::from_std(stream, &Handle::default())
+                        .expect("Tokio could not create a TcpStream");
+                    return Ok(tokio_stream);
+                }
+                Err(e) => {
+                    if e.kind() == ErrorKind::TimedOut {
+                        logger.warning(format!(
+                            "{} did not accept a connection within {:?}",
+                            socket_addr, timeout
+                        ));
+                    }
+                    last_error = e;
+                    socket_addrs_tried.push(format!("{}", socket_addr));
+                    continue;
+                }
+            };
+        }
+
+        logger.error(format!(
+            "Could not connect to any of the IP addresses supplied for {}: {:?}",
+            target_hostname, socket_addrs_tried
+        ));
+        Err(last_error)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -249,6 +337,7 @@ mod tests {
                 ip_addrs,
                 &"some hostname".to_string(),
                 socket_addr.port(),
+                Duration::from_secs(5),
                 &logger,
             );
             tx.send(connection_result).unwrap();
@@ -285,6 +374,7 @@ mod tests {
                 ip_addrs,
                 &"some hostname".to_string(),
                 socket_addr.port(),
+                Duration::from_secs(5),
                 &logger,
             );
             connection_info_tx.send(connection_result).unwrap();
@@ -317,8 +407,13 @@ mod tests {
 
         let (tx, rx) = mpsc::channel();
         let test_future = lazy(move || {
-            let connection_result =
-                subject.connect_one(ip_addrs, &"some hostname".to_string(), 9876, &logger);
+            let connection_result = subject.connect_one(
+                ip_addrs,
+                &"some hostname".to_string(),
+                9876,
+                Duration::from_secs(5),
+                &logger,
+            );
             tx.send(connection_result).unwrap();
             Ok(())
         });
@@ -333,6 +428,50 @@ mod tests {
         TestLogHandler::new().exists_log_matching("Could not connect to any of the IP addresses supplied for some hostname: \\[\"255\\.255\\.255\\.255:\\d+\"\\]");
     }
 
+    #[test]
+    fn stream_connector_reports_a_failed_tls_handshake_with_a_server_that_does_not_speak_tls() {
+        init_test_logging();
+        let logger = Logger::new("test");
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let socket_addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            stream
+                .write_all(b"HTTP/1.1 400 Bad Request\r\n\r\n")
+                .unwrap();
+        });
+        let subject = StreamConnectorReal {};
+
+        let (tx, rx) = mpsc::channel();
+        let test_future = lazy(move || {
+            let (peer_addr, future) = subject
+                .connect_one_tls(
+                    vec![socket_addr.ip()],
+                    &"some hostname".to_string(),
+                    socket_addr.port(),
+                    Duration::from_secs(5),
+                    &logger,
+                )
+                .unwrap();
+            future.then(move |result| {
+                tx.send((peer_addr, result.is_err())).unwrap();
+                Ok(())
+            })
+        });
+        thread::spawn(move || {
+            tokio::run(test_future);
+        });
+
+        let (peer_addr, failed) = rx.recv().unwrap();
+
+        assert_eq!(peer_addr, socket_addr);
+        assert_eq!(failed, true);
+        TestLogHandler::new().exists_log_containing(&format!(
+            "ERROR: test: TLS handshake with some hostname at {} failed",
+            socket_addr
+        ));
+    }
+
     struct FutureAsserter<I: 'static, E: 'static> {
         future: Box<dyn Future<Item = I, Error = E> + Send>,
     }
@@ -3,6 +3,8 @@ use crate::sub_lib::dispatcher::Endpoint;
 use crate::sub_lib::neighborhood::NodeDescriptor;
 use actix::Message;
 
+pub const STREAM_READ_BUFFER_SIZE: usize = 0x10000;
+
 #[derive(PartialEq, Debug, Message, Clone)]
 pub struct TransmitDataMsg {
     pub endpoint: Endpoint,
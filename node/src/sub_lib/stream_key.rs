@@ -74,7 +74,7 @@ impl StreamKey {
         let mut hash = sha1::Sha1::new();
         match peer_addr.ip() {
             IpAddr::V4(ipv4) => hash.update(&ipv4.octets()),
-            IpAddr::V6(_ipv6) => unimplemented!(),
+            IpAddr::V6(ipv6) => hash.update(&ipv6.octets()),
         }
         hash.update(&[
             (peer_addr.port() >> 8) as u8,
@@ -141,6 +141,19 @@ mod tests {
         assert_ne!(one, another);
     }
 
+    #[test]
+    fn ipv6_addrs_make_stream_keys_too() {
+        let key = PublicKey::new(&b"These are the times"[..]);
+        let one_addr = SocketAddr::from_str("[2001:db8::1]:6789").unwrap();
+        let another_addr = SocketAddr::from_str("[2001:db8::2]:6789").unwrap();
+
+        let one = StreamKey::new(key.clone(), one_addr);
+        let another = StreamKey::new(key.clone(), another_addr);
+
+        assert_ne!(one, another);
+        assert_eq!(one, StreamKey::new(key, one_addr));
+    }
+
     #[test]
     fn debug_implementation() {
         let key = PublicKey::new(&b"These are the times"[..]);
@@ -0,0 +1,44 @@
+// Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use std::fmt::Debug;
+use std::time::SystemTime;
+
+// Where code that needs to write down when something happened gets the time, so that tests can
+// decide what time it is.
+pub trait TimeSource: Debug {
+    fn now(&self) -> SystemTime;
+}
+
+#[derive(Debug, Default)]
+pub struct TimeSourceReal {}
+
+impl TimeSource for TimeSourceReal {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn real_time_source_tells_the_real_time() {
+        let before = SystemTime::now();
+
+        let result = TimeSourceReal::default().now();
+
+        let after = SystemTime::now();
+        assert!(
+            result >= before,
+            "{:?} should be on or after {:?}",
+            result,
+            before
+        );
+        assert!(
+            result <= after,
+            "{:?} should be on or before {:?}",
+            result,
+            after
+        );
+    }
+}
@@ -1,14 +1,92 @@
 // Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use crate::sub_lib::cryptde::PublicKey;
+use crate::sub_lib::neighborhood::TopologyQueryMessage;
 use crate::sub_lib::peer_actors::BindMessage;
 use actix::Message;
 use actix::Recipient;
 use actix::Syn;
+use log::LevelFilter;
+use serde_derive::{Deserialize, Serialize};
+use serde_json;
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use std::path::PathBuf;
 
 pub const DEFAULT_UI_PORT: u16 = 5333;
+pub const MIN_UI_TOKEN_LENGTH: usize = 16;
+// Where a Node started with neither --ui_token nor --ui_remote leaves the token it generated
+pub const UI_TOKEN_FILE: &str = "ui_token";
 
 #[derive(Clone)]
 pub struct UiGatewayConfig {
     pub ui_port: u16,
+    pub startup_summary: Option<StartupSummary>,
+    // Where the record of the firewall rules opened at startup is kept, if --manage_firewall is on,
+    // so that they can be taken down at shutdown
+    pub firewall_directory: Option<PathBuf>,
+    // Where the operator's aliases for wallets and Nodes are kept; with none, they last until
+    // shutdown
+    pub alias_directory: Option<PathBuf>,
+    // With this, UIs connect over TLS on another interface instead of over plain TCP on localhost
+    pub remote_access: Option<UiRemoteAccessConfig>,
+    // With this, a UI on localhost must prove it knows the token too before the Node will talk to it
+    pub local_token: Option<String>,
+    // With neither of those, a UI on localhost is heard without a token, but the Node takes orders
+    // only from one that proves it knows the token generated at startup and left in UI_TOKEN_FILE
+    // here. With no directory, nobody can give orders over the UI port.
+    pub token_directory: Option<PathBuf>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct UiRemoteAccessConfig {
+    pub interface: IpAddr,
+    // The contents of a PKCS #12 file, with no password, holding the UI server's certificate and
+    // private key
+    pub identity: Vec<u8>,
+    // What a UI must prove it knows before the Node will talk to it
+    pub token: String,
+}
+
+// Everything about how this Node was started that a UI, or a test, is likely to want to know,
+// collected in one record so that nobody has to piece it together from the log.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct StartupSummary {
+    pub version: String,
+    pub mode: String,
+    pub node_type: String,
+    pub node_descriptor: String,
+    pub clandestine_ports: Vec<u16>,
+    pub ui_port: u16,
+    pub dns_servers: Vec<String>,
+    pub dns_subversion: String,
+    pub earning_wallet: String,
+    pub consuming_wallet: Option<String>,
+    pub crypto_backend: String,
+    pub db_schema_version: String,
+    // Whether the Node was started with --adaptive_hops, so a UI can offer to turn it on and off.
+    // Older Nodes don't say, and don't have it.
+    #[serde(default)]
+    pub adaptive_hops: bool,
+}
+
+#[derive(Serialize)]
+struct StartupSummaryEnvelope<'a> {
+    message_type: &'a str,
+    payload: &'a StartupSummary,
+}
+
+impl StartupSummary {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("StartupSummary could not be serialized")
+    }
+
+    pub fn to_ui_json(&self) -> String {
+        serde_json::to_string(&StartupSummaryEnvelope {
+            message_type: "startup_summary",
+            payload: self,
+        })
+        .expect("StartupSummary could not be serialized")
+    }
 }
 
 #[derive(Clone)]
@@ -18,15 +96,136 @@ pub struct UiGatewaySubs {
     pub from_ui_message_sub: Recipient<Syn, FromUiMessage>,
 }
 
-// TODO: Needs client_id
 #[derive(Message, PartialEq, Debug)]
 pub enum UiMessage {
     ShutdownMessage,
+    ReloadMessage,
+    // key is a wallet address or a Node's public key in base64
+    SetAliasMessage {
+        key: String,
+        label: String,
+    },
+    RemoveAliasMessage {
+        key: String,
+    },
+    SetAdaptiveHopsMessage {
+        enabled: bool,
+    },
+    // Answered with a status message, sent only to the UI that asked and marked with its request_id
+    StatusQueryMessage {
+        client_id: u64,
+        request_id: u64,
+    },
+    // Answered with one topology_page message, sent and marked the same way
+    TopologyQueryMessage {
+        client_id: u64,
+        request_id: u64,
+        query: TopologyQueryMessage,
+    },
+    SetLogLevelMessage {
+        level: LevelFilter,
+    },
+    RemoveNeighborMessage {
+        public_key: PublicKey,
+    },
+    // Something for the operator's attention, bound for every connected UI
+    AlertMessage(UiAlert),
+}
+
+impl UiMessage {
+    // Everything a UI can send but a query changes what the Node does, and is taken only from a UI
+    // that has authenticated
+    pub fn is_order(&self) -> bool {
+        match self {
+            UiMessage::StatusQueryMessage { .. }
+            | UiMessage::TopologyQueryMessage { .. }
+            | UiMessage::AlertMessage(_) => false,
+            _ => true,
+        }
+    }
+}
+
+// How the Node is doing right now, as a UI that asks is told
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct NodeStatus {
+    pub node_descriptor: String,
+    pub neighbors: usize,
+    // This Node included
+    pub known_nodes: usize,
+    // Streams this Node's own users have open, and streams it's exiting for other Nodes' users
+    pub originating_streams: usize,
+    pub exit_streams: usize,
+    pub receivable_balance: i64,
+    pub payable_balance: i64,
+}
+
+#[derive(Serialize)]
+struct NodeStatusEnvelope<'a> {
+    message_type: &'a str,
+    request_id: u64,
+    payload: &'a NodeStatus,
+}
+
+impl NodeStatus {
+    // request_id is whatever the UI put in its query, so it can tell which answer is which
+    pub fn to_ui_json(&self, request_id: u64) -> String {
+        serde_json::to_string(&NodeStatusEnvelope {
+            message_type: "status",
+            request_id,
+            payload: self,
+        })
+        .expect("NodeStatus could not be serialized")
+    }
+}
+
+// What a UI is told when something goes wrong: a stable code it can look up in its own translation
+// of the message catalog, and the values to fill in, rather than a sentence in English.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct UiAlert {
+    pub code: String,
+    pub parameters: BTreeMap<String, String>,
+}
+
+#[derive(Serialize)]
+struct UiAlertEnvelope<'a> {
+    message_type: &'a str,
+    payload: &'a UiAlert,
 }
 
-// TODO: Needs client_id
+impl UiAlert {
+    pub fn new(code: &str) -> UiAlert {
+        UiAlert {
+            code: String::from(code),
+            parameters: BTreeMap::new(),
+        }
+    }
+
+    pub fn with(mut self, name: &str, value: &str) -> UiAlert {
+        self.parameters
+            .insert(String::from(name), String::from(value));
+        self
+    }
+
+    pub fn to_ui_json(&self) -> String {
+        serde_json::to_string(&UiAlertEnvelope {
+            message_type: "alert",
+            payload: self,
+        })
+        .expect("UiAlert could not be serialized")
+    }
+}
+
+// Sent by the UiGateway when a UI asks for a reload. Each actor that receives it reads the files its
+// command-line parameters named again and puts whatever has changed into effect, leaving streams and
+// connections alone. A file that can't be read or understood changes nothing.
+#[derive(Message, Clone, PartialEq, Debug)]
+pub struct ReloadConfigMessage {}
+
 #[derive(Message, PartialEq, Debug)]
 pub struct FromUiMessage {
+    pub client_id: u64,
+    // Whether the UI has proven it knows the Node's token, and so may give orders
+    pub authenticated: bool,
     pub json: String,
 }
 
@@ -48,3 +247,113 @@ pub struct FromUiMessage {
 //// TODO: This can move into ui_gateway because it's never used anywhere but there
 //#[derive(Debug, PartialEq)]
 //pub struct ShutdownMessage;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_summary() -> StartupSummary {
+        StartupSummary {
+            version: String::from("1.2.3"),
+            mode: String::from("zero-hop"),
+            node_type: String::from("standard"),
+            node_descriptor: String::from("AQIDBA:1.2.3.4:"),
+            clandestine_ports: vec![],
+            ui_port: 5333,
+            dns_servers: vec![String::from("8.8.8.8:53")],
+            dns_subversion: String::from("unknown"),
+            earning_wallet: String::from("0xbDfe...58CF"),
+            consuming_wallet: None,
+            crypto_backend: String::from("CryptDENull"),
+            db_schema_version: String::from("0.0.1"),
+            adaptive_hops: false,
+        }
+    }
+
+    #[test]
+    fn startup_summary_round_trips_through_json() {
+        let subject = make_summary();
+
+        let result: StartupSummary = serde_json::from_str(&subject.to_json()).unwrap();
+
+        assert_eq!(result, subject);
+    }
+
+    #[test]
+    fn startup_summary_for_the_ui_is_wrapped_with_a_message_type() {
+        let subject = make_summary();
+
+        let result: serde_json::Value = serde_json::from_str(&subject.to_ui_json()).unwrap();
+
+        assert_eq!(result["message_type"], "startup_summary");
+        assert_eq!(result["payload"]["mode"], "zero-hop");
+        assert_eq!(result["payload"]["db_schema_version"], "0.0.1");
+    }
+
+    #[test]
+    fn status_for_the_ui_is_wrapped_with_a_message_type() {
+        let subject = NodeStatus {
+            node_descriptor: String::from("AQIDBA:1.2.3.4:1234"),
+            neighbors: 2,
+            known_nodes: 5,
+            originating_streams: 3,
+            exit_streams: 4,
+            receivable_balance: 1000,
+            payable_balance: 500,
+        };
+
+        let result: serde_json::Value = serde_json::from_str(&subject.to_ui_json(42)).unwrap();
+
+        assert_eq!(result["message_type"], "status");
+        assert_eq!(result["request_id"], 42);
+        assert_eq!(result["payload"]["node_descriptor"], "AQIDBA:1.2.3.4:1234");
+        assert_eq!(result["payload"]["known_nodes"], 5);
+        assert_eq!(result["payload"]["exit_streams"], 4);
+        assert_eq!(result["payload"]["payable_balance"], 500);
+    }
+
+    #[test]
+    fn only_queries_and_alerts_are_not_orders() {
+        assert_eq!(
+            UiMessage::StatusQueryMessage {
+                client_id: 1,
+                request_id: 2
+            }
+            .is_order(),
+            false
+        );
+        assert_eq!(
+            UiMessage::TopologyQueryMessage {
+                client_id: 1,
+                request_id: 2,
+                query: TopologyQueryMessage::from_json("{}").unwrap(),
+            }
+            .is_order(),
+            false
+        );
+        assert_eq!(
+            UiMessage::AlertMessage(UiAlert::new("booga")).is_order(),
+            false
+        );
+        assert_eq!(UiMessage::ShutdownMessage.is_order(), true);
+        assert_eq!(
+            UiMessage::SetAliasMessage {
+                key: String::from("QmlsbA"),
+                label: String::from("my VPS node"),
+            }
+            .is_order(),
+            true
+        );
+    }
+
+    #[test]
+    fn alert_for_the_ui_is_wrapped_with_a_message_type() {
+        let subject = UiAlert::new("blank_alias").with("key", "QmlsbA");
+
+        let result: serde_json::Value = serde_json::from_str(&subject.to_ui_json()).unwrap();
+
+        assert_eq!(result["message_type"], "alert");
+        assert_eq!(result["payload"]["code"], "blank_alias");
+        assert_eq!(result["payload"]["parameters"]["key"], "QmlsbA");
+    }
+}
@@ -2,6 +2,7 @@
 use std::io::ErrorKind;
 use std::net::IpAddr;
 use std::str::FromStr;
+use tokio::prelude::Future;
 
 static DEAD_STREAM_ERRORS: [ErrorKind; 5] = [
     ErrorKind::BrokenPipe,
@@ -110,6 +111,20 @@ pub fn to_string_s(data: &[u8]) -> String {
     }
 }
 
+// For serde's skip_serializing_if, so that a flag nobody has set costs nothing on the wire
+pub fn is_false(value: &bool) -> bool {
+    !*value
+}
+
+// For a query whose answer can go missing without spoiling the rest of what it was asked with
+pub fn or_default<F>(future: F) -> impl Future<Item = F::Item, Error = ()>
+where
+    F: Future,
+    F::Item: Default,
+{
+    future.then(|result| Ok::<F::Item, ()>(result.unwrap_or_default()))
+}
+
 pub fn plus<T>(mut source: Vec<T>, item: T) -> Vec<T> {
     let mut result = vec![];
     result.append(&mut source);
@@ -124,6 +139,13 @@ pub mod tests {
     use crate::sub_lib::stream_key::StreamKey;
     use std::net::SocketAddr;
     use std::str::FromStr;
+    use tokio::prelude::Future;
+
+    #[test]
+    fn is_false_is_true_only_for_false() {
+        assert_eq!(is_false(&false), true);
+        assert_eq!(is_false(&true), false);
+    }
 
     pub fn make_meaningless_stream_key() -> StreamKey {
         StreamKey::new(
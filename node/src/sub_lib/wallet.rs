@@ -1,5 +1,11 @@
 // Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
 use serde_derive::{Deserialize, Serialize};
+use std::cmp::max;
+use std::time::Duration;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+pub const DEFAULT_WALLET_ROTATION_PERIOD: Duration = Duration::from_secs(86400);
 
 #[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
 pub struct Wallet {
@@ -12,6 +18,44 @@ impl Wallet {
             address: String::from(address),
         }
     }
+
+    // Enough of the address to tell wallets apart in a log without copying the whole thing there.
+    pub fn fingerprint(&self) -> String {
+        let chars: Vec<char> = self.address.chars().collect();
+        if chars.len() <= 12 {
+            return self.address.clone();
+        }
+        let head: String = chars[..6].iter().collect();
+        let tail: String = chars[(chars.len() - 4)..].iter().collect();
+        format!("{}...{}", head, tail)
+    }
+}
+
+// Several earning addresses taken in turn, one per period, so that someone watching a single
+// address can't simply total up everything a Node has earned. Periods are counted from the epoch,
+// so the choice depends only on the time and not on when the Node was started.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WalletRotation {
+    pub wallets: Vec<Wallet>,
+    pub period: Duration,
+}
+
+impl WalletRotation {
+    pub fn new(wallets: Vec<Wallet>, period: Duration) -> WalletRotation {
+        if wallets.is_empty() {
+            panic!("A WalletRotation needs at least one wallet");
+        }
+        WalletRotation { wallets, period }
+    }
+
+    pub fn wallet_at(&self, time: SystemTime) -> &Wallet {
+        let seconds = time
+            .duration_since(UNIX_EPOCH)
+            .expect("System clock is before 1970")
+            .as_secs();
+        let period_index = seconds / max(self.period.as_secs(), 1);
+        &self.wallets[(period_index % self.wallets.len() as u64) as usize]
+    }
 }
 
 #[cfg(test)]
@@ -24,4 +68,42 @@ mod tests {
 
         assert_eq!("totally valid eth address", subject.address);
     }
+
+    #[test]
+    fn fingerprint_abbreviates_long_addresses_and_leaves_short_ones_alone() {
+        assert_eq!(
+            Wallet::new("0xbDfeFf9A1f4A1bdF483d680046344316019C58CF").fingerprint(),
+            String::from("0xbDfe...58CF")
+        );
+        assert_eq!(
+            Wallet::new("consuming").fingerprint(),
+            String::from("consuming")
+        );
+    }
+
+    #[test]
+    fn constant_values() {
+        assert_eq!(DEFAULT_WALLET_ROTATION_PERIOD, Duration::from_secs(86400));
+    }
+
+    #[test]
+    #[should_panic(expected = "A WalletRotation needs at least one wallet")]
+    fn wallet_rotation_needs_at_least_one_wallet() {
+        WalletRotation::new(vec![], Duration::from_secs(10));
+    }
+
+    #[test]
+    fn wallet_rotation_takes_wallets_in_turn_one_period_at_a_time() {
+        let subject = WalletRotation::new(
+            vec![Wallet::new("one"), Wallet::new("two"), Wallet::new("three")],
+            Duration::from_secs(10),
+        );
+        let at = |seconds| UNIX_EPOCH + Duration::from_secs(seconds);
+
+        assert_eq!(subject.wallet_at(at(999_999_990)), &Wallet::new("one"));
+        assert_eq!(subject.wallet_at(at(999_999_999)), &Wallet::new("one"));
+        assert_eq!(subject.wallet_at(at(1_000_000_000)), &Wallet::new("two"));
+        assert_eq!(subject.wallet_at(at(1_000_000_015)), &Wallet::new("three"));
+        assert_eq!(subject.wallet_at(at(1_000_000_020)), &Wallet::new("one"));
+    }
 }
@@ -0,0 +1,581 @@
+// Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use crate::accountant::banned_node_dao::BannedNodeDao;
+use crate::accountant::config_dao::ConfigDao;
+use crate::accountant::db_initializer::Daos;
+use crate::accountant::db_initializer::DbInitializer;
+use crate::accountant::db_initializer::InitializationError;
+use crate::accountant::journal_dao::Account;
+use crate::accountant::journal_dao::JournalDao;
+use crate::accountant::journal_dao::JournalEntry;
+use crate::accountant::maintenance_dao::MaintenanceDao;
+use crate::accountant::node_record_dao::NodeRecordDao;
+use crate::accountant::payable_dao::PayableAccount;
+use crate::accountant::payable_dao::PayableDao;
+use crate::accountant::receivable_dao::ReceivableAccount;
+use crate::accountant::receivable_dao::ReceivableDao;
+use crate::neighborhood::neighborhood_database::NodeRecord;
+use crate::sub_lib::accountant::ConfigChangedMessage;
+use crate::sub_lib::accountant::CorrelationId;
+use crate::sub_lib::accountant::ReceivableAgingReport;
+use crate::sub_lib::cryptde::PublicKey;
+use crate::sub_lib::wallet::Wallet;
+use actix::Recipient;
+use actix::Syn;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::fmt::Debug;
+use std::fmt::Formatter;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+pub struct DbInitializerMock {
+    initialize_parameters: Arc<Mutex<Vec<PathBuf>>>,
+    initialize_results: RefCell<Vec<Result<Daos, InitializationError>>>,
+}
+
+impl DbInitializer for DbInitializerMock {
+    fn initialize(&self, path: &PathBuf) -> Result<Daos, InitializationError> {
+        self.initialize_parameters
+            .lock()
+            .unwrap()
+            .push(path.clone());
+        self.initialize_results.borrow_mut().remove(0)
+    }
+}
+
+impl DbInitializerMock {
+    pub fn new() -> DbInitializerMock {
+        DbInitializerMock {
+            initialize_parameters: Arc::new(Mutex::new(vec![])),
+            initialize_results: RefCell::new(vec![]),
+        }
+    }
+
+    pub fn initialize_parameters(
+        mut self,
+        parameters: Arc<Mutex<Vec<PathBuf>>>,
+    ) -> DbInitializerMock {
+        self.initialize_parameters = parameters;
+        self
+    }
+
+    pub fn initialize_result(self, result: Result<Daos, InitializationError>) -> DbInitializerMock {
+        self.initialize_results.borrow_mut().push(result);
+        self
+    }
+}
+
+#[derive(Debug)]
+pub struct PayableDaoMock {
+    more_money_payable_parameters: Arc<Mutex<Vec<(Wallet, u64)>>>,
+    more_money_payable_for_stream_parameters: Arc<Mutex<Vec<(Wallet, CorrelationId, u64)>>>,
+    payment_sent_parameters: Arc<Mutex<Vec<(Wallet, String)>>>,
+    payment_confirmed_parameters: Arc<Mutex<Vec<(Wallet, u64, SystemTime)>>>,
+    account_status_results: RefCell<Vec<Option<PayableAccount>>>,
+    total_owed_results: RefCell<Vec<i64>>,
+}
+
+impl PayableDao for PayableDaoMock {
+    fn more_money_payable(&self, wallet_address: &Wallet, amount: u64) -> Result<(), String> {
+        self.more_money_payable_parameters
+            .lock()
+            .unwrap()
+            .push((wallet_address.clone(), amount));
+        Ok(())
+    }
+
+    fn more_money_payable_for_stream(
+        &self,
+        wallet_address: &Wallet,
+        correlation_id: &CorrelationId,
+        amount: u64,
+    ) -> Result<(), String> {
+        self.more_money_payable_for_stream_parameters
+            .lock()
+            .unwrap()
+            .push((wallet_address.clone(), *correlation_id, amount));
+        Ok(())
+    }
+
+    fn payment_sent(&self, wallet_address: &Wallet, pending_payment_transaction: &str) {
+        self.payment_sent_parameters.lock().unwrap().push((
+            wallet_address.clone(),
+            String::from(pending_payment_transaction),
+        ));
+    }
+
+    fn payment_confirmed(
+        &self,
+        wallet_address: &Wallet,
+        amount: u64,
+        confirmation_noticed_timestamp: &SystemTime,
+    ) {
+        self.payment_confirmed_parameters.lock().unwrap().push((
+            wallet_address.clone(),
+            amount,
+            confirmation_noticed_timestamp.clone(),
+        ));
+    }
+
+    fn account_status(&self, _wallet_address: &Wallet) -> Option<PayableAccount> {
+        self.account_status_results.borrow_mut().remove(0)
+    }
+
+    // Nothing is owed unless told otherwise
+    fn total_owed(&self) -> i64 {
+        let mut results = self.total_owed_results.borrow_mut();
+        if results.is_empty() {
+            0
+        } else {
+            results.remove(0)
+        }
+    }
+}
+
+impl PayableDaoMock {
+    pub fn new() -> PayableDaoMock {
+        PayableDaoMock {
+            more_money_payable_parameters: Arc::new(Mutex::new(vec![])),
+            more_money_payable_for_stream_parameters: Arc::new(Mutex::new(vec![])),
+            payment_sent_parameters: Arc::new(Mutex::new(vec![])),
+            payment_confirmed_parameters: Arc::new(Mutex::new(vec![])),
+            account_status_results: RefCell::new(vec![]),
+            total_owed_results: RefCell::new(vec![]),
+        }
+    }
+
+    pub fn more_money_payable_parameters(
+        mut self,
+        parameters: Arc<Mutex<Vec<(Wallet, u64)>>>,
+    ) -> Self {
+        self.more_money_payable_parameters = parameters;
+        self
+    }
+
+    pub fn more_money_payable_for_stream_parameters(
+        mut self,
+        parameters: Arc<Mutex<Vec<(Wallet, CorrelationId, u64)>>>,
+    ) -> Self {
+        self.more_money_payable_for_stream_parameters = parameters;
+        self
+    }
+
+    pub fn payment_sent_parameters(
+        mut self,
+        parameters: Arc<Mutex<Vec<(Wallet, String)>>>,
+    ) -> Self {
+        self.payment_sent_parameters = parameters;
+        self
+    }
+
+    pub fn payment_confirmed_parameters(
+        mut self,
+        parameters: Arc<Mutex<Vec<(Wallet, u64, SystemTime)>>>,
+    ) -> Self {
+        self.payment_confirmed_parameters = parameters;
+        self
+    }
+
+    pub fn account_status_result(self, result: Option<PayableAccount>) -> Self {
+        self.account_status_results.borrow_mut().push(result);
+        self
+    }
+
+    pub fn total_owed_result(self, result: i64) -> Self {
+        self.total_owed_results.borrow_mut().push(result);
+        self
+    }
+}
+
+#[derive(Debug)]
+pub struct ReceivableDaoMock {
+    more_money_receivable_parameters: Arc<Mutex<Vec<(Wallet, u64)>>>,
+    more_money_receivable_results: RefCell<Vec<Result<(), String>>>,
+    more_money_receivable_for_stream_parameters: Arc<Mutex<Vec<(Wallet, CorrelationId, u64)>>>,
+    more_money_received_parameters: Arc<Mutex<Vec<(Wallet, u64, SystemTime)>>>,
+    account_status_results: RefCell<Vec<Option<ReceivableAccount>>>,
+    receivable_aging_results: RefCell<Vec<ReceivableAgingReport>>,
+    delinquent_wallets_results: RefCell<Vec<Vec<Wallet>>>,
+    total_owed_results: RefCell<Vec<i64>>,
+}
+
+impl ReceivableDao for ReceivableDaoMock {
+    // Succeeds unless told otherwise
+    fn more_money_receivable(&self, wallet_address: &Wallet, amount: u64) -> Result<(), String> {
+        self.more_money_receivable_parameters
+            .lock()
+            .unwrap()
+            .push((wallet_address.clone(), amount));
+        let mut results = self.more_money_receivable_results.borrow_mut();
+        if results.is_empty() {
+            Ok(())
+        } else {
+            results.remove(0)
+        }
+    }
+
+    fn more_money_receivable_for_stream(
+        &self,
+        wallet_address: &Wallet,
+        correlation_id: &CorrelationId,
+        amount: u64,
+    ) -> Result<(), String> {
+        self.more_money_receivable_for_stream_parameters
+            .lock()
+            .unwrap()
+            .push((wallet_address.clone(), *correlation_id, amount));
+        Ok(())
+    }
+
+    fn more_money_received(&self, wallet_address: &Wallet, amount: u64, timestamp: &SystemTime) {
+        self.more_money_received_parameters.lock().unwrap().push((
+            wallet_address.clone(),
+            amount,
+            timestamp.clone(),
+        ));
+    }
+
+    fn account_status(&self, _wallet_address: &Wallet) -> Option<ReceivableAccount> {
+        self.account_status_results.borrow_mut().remove(0)
+    }
+
+    fn receivable_aging(&self) -> ReceivableAgingReport {
+        self.receivable_aging_results.borrow_mut().remove(0)
+    }
+
+    // Nobody is delinquent unless told otherwise
+    fn delinquent_wallets(&self) -> Vec<Wallet> {
+        let mut results = self.delinquent_wallets_results.borrow_mut();
+        if results.is_empty() {
+            vec![]
+        } else {
+            results.remove(0)
+        }
+    }
+
+    // Nothing is owed unless told otherwise
+    fn total_owed(&self) -> i64 {
+        let mut results = self.total_owed_results.borrow_mut();
+        if results.is_empty() {
+            0
+        } else {
+            results.remove(0)
+        }
+    }
+}
+
+impl ReceivableDaoMock {
+    pub fn new() -> ReceivableDaoMock {
+        ReceivableDaoMock {
+            more_money_receivable_parameters: Arc::new(Mutex::new(vec![])),
+            more_money_receivable_results: RefCell::new(vec![]),
+            more_money_receivable_for_stream_parameters: Arc::new(Mutex::new(vec![])),
+            more_money_received_parameters: Arc::new(Mutex::new(vec![])),
+            account_status_results: RefCell::new(vec![]),
+            receivable_aging_results: RefCell::new(vec![]),
+            delinquent_wallets_results: RefCell::new(vec![]),
+            total_owed_results: RefCell::new(vec![]),
+        }
+    }
+
+    pub fn more_money_receivable_parameters(
+        mut self,
+        parameters: Arc<Mutex<Vec<(Wallet, u64)>>>,
+    ) -> Self {
+        self.more_money_receivable_parameters = parameters;
+        self
+    }
+
+    pub fn more_money_receivable_result(self, result: Result<(), String>) -> Self {
+        self.more_money_receivable_results.borrow_mut().push(result);
+        self
+    }
+
+    pub fn more_money_receivable_for_stream_parameters(
+        mut self,
+        parameters: Arc<Mutex<Vec<(Wallet, CorrelationId, u64)>>>,
+    ) -> Self {
+        self.more_money_receivable_for_stream_parameters = parameters;
+        self
+    }
+
+    pub fn more_money_received_parameters(
+        mut self,
+        parameters: Arc<Mutex<Vec<(Wallet, u64, SystemTime)>>>,
+    ) -> Self {
+        self.more_money_received_parameters = parameters;
+        self
+    }
+
+    pub fn account_status_result(self, result: Option<ReceivableAccount>) -> Self {
+        self.account_status_results.borrow_mut().push(result);
+        self
+    }
+
+    pub fn receivable_aging_result(self, result: ReceivableAgingReport) -> Self {
+        self.receivable_aging_results.borrow_mut().push(result);
+        self
+    }
+
+    pub fn delinquent_wallets_result(self, result: Vec<Wallet>) -> Self {
+        self.delinquent_wallets_results.borrow_mut().push(result);
+        self
+    }
+
+    pub fn total_owed_result(self, result: i64) -> Self {
+        self.total_owed_results.borrow_mut().push(result);
+        self
+    }
+}
+
+pub struct ConfigDaoMock {
+    values: HashMap<String, String>,
+    set_string_parameters: Arc<Mutex<Vec<(String, String)>>>,
+    subscribe_parameters: Arc<Mutex<Vec<Recipient<Syn, ConfigChangedMessage>>>>,
+}
+
+impl Debug for ConfigDaoMock {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "ConfigDaoMock")
+    }
+}
+
+impl ConfigDao for ConfigDaoMock {
+    fn get_string(&self, name: &str) -> Option<String> {
+        self.values.get(name).cloned()
+    }
+
+    fn set_string(&mut self, name: &str, value: &str) {
+        self.set_string_parameters
+            .lock()
+            .unwrap()
+            .push((String::from(name), String::from(value)));
+        self.values.insert(String::from(name), String::from(value));
+    }
+
+    fn subscribe(&mut self, subscriber: Recipient<Syn, ConfigChangedMessage>) {
+        self.subscribe_parameters.lock().unwrap().push(subscriber);
+    }
+}
+
+impl ConfigDaoMock {
+    pub fn new() -> ConfigDaoMock {
+        ConfigDaoMock {
+            values: HashMap::new(),
+            set_string_parameters: Arc::new(Mutex::new(vec![])),
+            subscribe_parameters: Arc::new(Mutex::new(vec![])),
+        }
+    }
+
+    pub fn value(mut self, name: &str, value: &str) -> Self {
+        self.values.insert(String::from(name), String::from(value));
+        self
+    }
+
+    pub fn set_string_parameters(mut self, parameters: Arc<Mutex<Vec<(String, String)>>>) -> Self {
+        self.set_string_parameters = parameters;
+        self
+    }
+
+    pub fn subscribe_parameters(
+        mut self,
+        parameters: Arc<Mutex<Vec<Recipient<Syn, ConfigChangedMessage>>>>,
+    ) -> Self {
+        self.subscribe_parameters = parameters;
+        self
+    }
+}
+
+#[derive(Debug)]
+pub struct NodeRecordDaoMock {
+    save_parameters: Arc<Mutex<Vec<(NodeRecord, SystemTime)>>>,
+    load_all_results: RefCell<Vec<Vec<NodeRecord>>>,
+    age_out_parameters: Arc<Mutex<Vec<SystemTime>>>,
+}
+
+impl NodeRecordDao for NodeRecordDaoMock {
+    fn save(&self, node_record: &NodeRecord, last_gossiped: &SystemTime) {
+        self.save_parameters
+            .lock()
+            .unwrap()
+            .push((node_record.clone(), last_gossiped.clone()));
+    }
+
+    fn load_all(&self) -> Vec<NodeRecord> {
+        self.load_all_results.borrow_mut().remove(0)
+    }
+
+    fn age_out(&self, cutoff: &SystemTime) -> usize {
+        self.age_out_parameters.lock().unwrap().push(cutoff.clone());
+        0
+    }
+}
+
+impl NodeRecordDaoMock {
+    pub fn new() -> NodeRecordDaoMock {
+        NodeRecordDaoMock {
+            save_parameters: Arc::new(Mutex::new(vec![])),
+            load_all_results: RefCell::new(vec![]),
+            age_out_parameters: Arc::new(Mutex::new(vec![])),
+        }
+    }
+
+    pub fn save_parameters(
+        mut self,
+        parameters: Arc<Mutex<Vec<(NodeRecord, SystemTime)>>>,
+    ) -> Self {
+        self.save_parameters = parameters;
+        self
+    }
+
+    pub fn load_all_result(self, result: Vec<NodeRecord>) -> Self {
+        self.load_all_results.borrow_mut().push(result);
+        self
+    }
+
+    pub fn age_out_parameters(mut self, parameters: Arc<Mutex<Vec<SystemTime>>>) -> Self {
+        self.age_out_parameters = parameters;
+        self
+    }
+}
+
+#[derive(Debug)]
+pub struct BannedNodeDaoMock {
+    ban_parameters: Arc<Mutex<Vec<(PublicKey, SystemTime)>>>,
+    load_all_results: RefCell<Vec<Vec<PublicKey>>>,
+}
+
+impl BannedNodeDao for BannedNodeDaoMock {
+    fn ban(&self, public_key: &PublicKey, banned: &SystemTime) {
+        self.ban_parameters
+            .lock()
+            .unwrap()
+            .push((public_key.clone(), banned.clone()));
+    }
+
+    fn load_all(&self) -> Vec<PublicKey> {
+        self.load_all_results.borrow_mut().remove(0)
+    }
+}
+
+impl BannedNodeDaoMock {
+    pub fn new() -> BannedNodeDaoMock {
+        BannedNodeDaoMock {
+            ban_parameters: Arc::new(Mutex::new(vec![])),
+            load_all_results: RefCell::new(vec![]),
+        }
+    }
+
+    pub fn ban_parameters(mut self, parameters: Arc<Mutex<Vec<(PublicKey, SystemTime)>>>) -> Self {
+        self.ban_parameters = parameters;
+        self
+    }
+
+    pub fn load_all_result(self, result: Vec<PublicKey>) -> Self {
+        self.load_all_results.borrow_mut().push(result);
+        self
+    }
+}
+
+#[derive(Debug)]
+pub struct MaintenanceDaoMock {
+    check_integrity_results: RefCell<Vec<Result<(), String>>>,
+    vacuum_parameters: Arc<Mutex<Vec<()>>>,
+    vacuum_results: RefCell<Vec<Result<(), String>>>,
+}
+
+impl MaintenanceDao for MaintenanceDaoMock {
+    fn check_integrity(&self) -> Result<(), String> {
+        self.check_integrity_results.borrow_mut().remove(0)
+    }
+
+    fn vacuum(&self) -> Result<(), String> {
+        self.vacuum_parameters.lock().unwrap().push(());
+        self.vacuum_results.borrow_mut().remove(0)
+    }
+}
+
+impl MaintenanceDaoMock {
+    pub fn new() -> MaintenanceDaoMock {
+        MaintenanceDaoMock {
+            check_integrity_results: RefCell::new(vec![]),
+            vacuum_parameters: Arc::new(Mutex::new(vec![])),
+            vacuum_results: RefCell::new(vec![]),
+        }
+    }
+
+    pub fn check_integrity_result(self, result: Result<(), String>) -> Self {
+        self.check_integrity_results.borrow_mut().push(result);
+        self
+    }
+
+    pub fn vacuum_parameters(mut self, parameters: Arc<Mutex<Vec<()>>>) -> Self {
+        self.vacuum_parameters = parameters;
+        self
+    }
+
+    pub fn vacuum_result(self, result: Result<(), String>) -> Self {
+        self.vacuum_results.borrow_mut().push(result);
+        self
+    }
+}
+
+#[derive(Debug)]
+pub struct JournalDaoMock {
+    entries_results: RefCell<Vec<Vec<JournalEntry>>>,
+    checkpoint_parameters: Arc<Mutex<Vec<()>>>,
+    checkpoint_results: RefCell<Vec<Result<(), String>>>,
+    recalculate_balances_results: RefCell<Vec<Result<Vec<(Account, Wallet)>, String>>>,
+}
+
+impl JournalDao for JournalDaoMock {
+    fn entries(&self, _account: Account, _wallet_address: &Wallet) -> Vec<JournalEntry> {
+        self.entries_results.borrow_mut().remove(0)
+    }
+
+    fn checkpoint(&self) -> Result<(), String> {
+        self.checkpoint_parameters.lock().unwrap().push(());
+        self.checkpoint_results.borrow_mut().remove(0)
+    }
+
+    fn recalculate_balances(&self) -> Result<Vec<(Account, Wallet)>, String> {
+        self.recalculate_balances_results.borrow_mut().remove(0)
+    }
+}
+
+impl JournalDaoMock {
+    pub fn new() -> JournalDaoMock {
+        JournalDaoMock {
+            entries_results: RefCell::new(vec![]),
+            checkpoint_parameters: Arc::new(Mutex::new(vec![])),
+            checkpoint_results: RefCell::new(vec![]),
+            recalculate_balances_results: RefCell::new(vec![]),
+        }
+    }
+
+    pub fn entries_result(self, result: Vec<JournalEntry>) -> Self {
+        self.entries_results.borrow_mut().push(result);
+        self
+    }
+
+    pub fn checkpoint_parameters(mut self, parameters: Arc<Mutex<Vec<()>>>) -> Self {
+        self.checkpoint_parameters = parameters;
+        self
+    }
+
+    pub fn checkpoint_result(self, result: Result<(), String>) -> Self {
+        self.checkpoint_results.borrow_mut().push(result);
+        self
+    }
+
+    pub fn recalculate_balances_result(
+        self,
+        result: Result<Vec<(Account, Wallet)>, String>,
+    ) -> Self {
+        self.recalculate_balances_results.borrow_mut().push(result);
+        self
+    }
+}
@@ -2,6 +2,7 @@
 
 #[macro_use]
 pub mod test_utils;
+pub mod accountant_mocks;
 pub mod channel_wrapper_mocks;
 pub mod data_hunk;
 pub mod data_hunk_framer;
@@ -9,4 +10,5 @@ pub mod logging;
 pub mod recorder;
 pub mod stream_connector_mock;
 pub mod tcp_wrapper_mocks;
+pub mod time_source_mock;
 pub mod tokio_wrapper_mocks;
@@ -1,31 +1,63 @@
 // Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
 use crate::sub_lib::accountant::AccountantSubs;
+use crate::sub_lib::accountant::Balances;
+use crate::sub_lib::accountant::BalancesQueryMessage;
+use crate::sub_lib::accountant::ConfigChangedMessage;
+use crate::sub_lib::accountant::ReceivableAgingQueryMessage;
+use crate::sub_lib::accountant::ReceivableAgingReport;
 use crate::sub_lib::accountant::ReportExitServiceConsumedMessage;
 use crate::sub_lib::accountant::ReportExitServiceProvidedMessage;
 use crate::sub_lib::accountant::ReportRoutingServiceConsumedMessage;
 use crate::sub_lib::accountant::ReportRoutingServiceProvidedMessage;
+use crate::sub_lib::accountant::SubscribeToConfigChangesMessage;
 use crate::sub_lib::dispatcher::DispatcherSubs;
 use crate::sub_lib::dispatcher::InboundClientData;
+use crate::sub_lib::dispatcher::StreamShutdownMsg;
 use crate::sub_lib::hopper::ExpiredCoresPackage;
+use crate::sub_lib::hopper::HopperStatistics;
+use crate::sub_lib::hopper::HopperStatisticsQueryMessage;
 use crate::sub_lib::hopper::HopperSubs;
 use crate::sub_lib::hopper::IncipientCoresPackage;
+use crate::sub_lib::hopper::PaymentStandingMessage;
+use crate::sub_lib::hopper::RegisterEndpointMessage;
+use crate::sub_lib::metrics::MetricsReport;
+use crate::sub_lib::metrics::MetricsSubs;
 use crate::sub_lib::neighborhood::BootstrapNeighborhoodNowMessage;
 use crate::sub_lib::neighborhood::DispatcherNodeQueryMessage;
+use crate::sub_lib::neighborhood::GossipAcceptanceStats;
+use crate::sub_lib::neighborhood::GossipStatsQueryMessage;
+use crate::sub_lib::neighborhood::NeighborMisbehaviorMessage;
+use crate::sub_lib::neighborhood::NeighborhoodStatus;
+use crate::sub_lib::neighborhood::NeighborhoodStatusQueryMessage;
 use crate::sub_lib::neighborhood::NeighborhoodSubs;
 use crate::sub_lib::neighborhood::NodeDescriptor;
+use crate::sub_lib::neighborhood::NodeProblemReport;
 use crate::sub_lib::neighborhood::NodeQueryMessage;
 use crate::sub_lib::neighborhood::RemoveNeighborMessage;
+use crate::sub_lib::neighborhood::RouteFeedbackMessage;
 use crate::sub_lib::neighborhood::RouteQueryMessage;
 use crate::sub_lib::neighborhood::RouteQueryResponse;
+use crate::sub_lib::neighborhood::RouteRefusal;
+use crate::sub_lib::neighborhood::RoutingLoadMessage;
+use crate::sub_lib::neighborhood::SetConsumingWalletMessage;
+use crate::sub_lib::neighborhood::TopologyPage;
+use crate::sub_lib::neighborhood::TopologyQueryMessage;
 use crate::sub_lib::peer_actors::BindMessage;
 use crate::sub_lib::peer_actors::PeerActors;
+use crate::sub_lib::peer_actors::ShutdownMessage;
+use crate::sub_lib::peer_actors::StreamCountQueryMessage;
+use crate::sub_lib::proxy_client::ExitThroughput;
+use crate::sub_lib::proxy_client::ExitThroughputQueryMessage;
 use crate::sub_lib::proxy_client::InboundServerData;
 use crate::sub_lib::proxy_client::ProxyClientSubs;
+use crate::sub_lib::proxy_server::AdaptiveHopsMessage;
 use crate::sub_lib::proxy_server::AddReturnRouteMessage;
+use crate::sub_lib::proxy_server::AddRouteMessage;
 use crate::sub_lib::proxy_server::ProxyServerSubs;
 use crate::sub_lib::stream_handler_pool::DispatcherNodeQueryResponse;
 use crate::sub_lib::stream_handler_pool::TransmitDataMsg;
 use crate::sub_lib::ui_gateway::FromUiMessage;
+use crate::sub_lib::ui_gateway::ReloadConfigMessage;
 use crate::sub_lib::ui_gateway::UiGatewaySubs;
 use crate::sub_lib::ui_gateway::UiMessage;
 use crate::test_utils::test_utils::to_millis;
@@ -45,7 +77,7 @@ use std::time::Instant;
 pub struct Recorder {
     recording: Arc<Mutex<Recording>>,
     node_query_responses: Vec<Option<NodeDescriptor>>,
-    route_query_responses: Vec<Option<RouteQueryResponse>>,
+    route_query_responses: Vec<Result<RouteQueryResponse, RouteRefusal>>,
 }
 
 pub struct Recording {
@@ -68,6 +100,22 @@ impl Handler<AddReturnRouteMessage> for Recorder {
     }
 }
 
+impl Handler<AddRouteMessage> for Recorder {
+    type Result = ();
+
+    fn handle(&mut self, msg: AddRouteMessage, _ctx: &mut Self::Context) {
+        self.record(msg);
+    }
+}
+
+impl Handler<AdaptiveHopsMessage> for Recorder {
+    type Result = ();
+
+    fn handle(&mut self, msg: AdaptiveHopsMessage, _ctx: &mut Self::Context) {
+        self.record(msg);
+    }
+}
+
 impl Handler<TransmitDataMsg> for Recorder {
     type Result = ();
 
@@ -84,6 +132,22 @@ impl Handler<BindMessage> for Recorder {
     }
 }
 
+impl Handler<ShutdownMessage> for Recorder {
+    type Result = ();
+
+    fn handle(&mut self, msg: ShutdownMessage, _ctx: &mut Self::Context) {
+        self.record(msg);
+    }
+}
+
+impl Handler<PaymentStandingMessage> for Recorder {
+    type Result = ();
+
+    fn handle(&mut self, msg: PaymentStandingMessage, _ctx: &mut Self::Context) {
+        self.record(msg);
+    }
+}
+
 impl Handler<IncipientCoresPackage> for Recorder {
     type Result = ();
 
@@ -108,6 +172,14 @@ impl Handler<InboundClientData> for Recorder {
     }
 }
 
+impl Handler<StreamShutdownMsg> for Recorder {
+    type Result = ();
+
+    fn handle(&mut self, msg: StreamShutdownMsg, _ctx: &mut Self::Context) {
+        self.record(msg);
+    }
+}
+
 impl Handler<InboundServerData> for Recorder {
     type Result = ();
 
@@ -164,6 +236,140 @@ impl Handler<RemoveNeighborMessage> for Recorder {
     }
 }
 
+impl Handler<SetConsumingWalletMessage> for Recorder {
+    type Result = ();
+
+    fn handle(&mut self, msg: SetConsumingWalletMessage, _ctx: &mut Self::Context) {
+        self.record(msg);
+    }
+}
+
+impl Handler<RegisterEndpointMessage> for Recorder {
+    type Result = ();
+
+    fn handle(&mut self, msg: RegisterEndpointMessage, _ctx: &mut Self::Context) {
+        self.record(msg);
+    }
+}
+
+impl Handler<RoutingLoadMessage> for Recorder {
+    type Result = ();
+
+    fn handle(&mut self, msg: RoutingLoadMessage, _ctx: &mut Self::Context) {
+        self.record(msg);
+    }
+}
+
+impl Handler<RouteFeedbackMessage> for Recorder {
+    type Result = ();
+
+    fn handle(&mut self, msg: RouteFeedbackMessage, _ctx: &mut Self::Context) {
+        self.record(msg);
+    }
+}
+
+impl Handler<NeighborMisbehaviorMessage> for Recorder {
+    type Result = ();
+
+    fn handle(&mut self, msg: NeighborMisbehaviorMessage, _ctx: &mut Self::Context) {
+        self.record(msg);
+    }
+}
+
+impl Handler<NodeProblemReport> for Recorder {
+    type Result = ();
+
+    fn handle(&mut self, msg: NodeProblemReport, _ctx: &mut Self::Context) {
+        self.record(msg);
+    }
+}
+
+impl Handler<ReloadConfigMessage> for Recorder {
+    type Result = ();
+
+    fn handle(&mut self, msg: ReloadConfigMessage, _ctx: &mut Self::Context) {
+        self.record(msg);
+    }
+}
+
+impl Handler<GossipStatsQueryMessage> for Recorder {
+    type Result = MessageResult<GossipStatsQueryMessage>;
+
+    fn handle(
+        &mut self,
+        msg: GossipStatsQueryMessage,
+        _ctx: &mut Self::Context,
+    ) -> <Self as Handler<GossipStatsQueryMessage>>::Result {
+        self.record(msg);
+        MessageResult(GossipAcceptanceStats::default())
+    }
+}
+
+impl Handler<StreamCountQueryMessage> for Recorder {
+    type Result = MessageResult<StreamCountQueryMessage>;
+
+    fn handle(
+        &mut self,
+        msg: StreamCountQueryMessage,
+        _ctx: &mut Self::Context,
+    ) -> <Self as Handler<StreamCountQueryMessage>>::Result {
+        self.record(msg);
+        MessageResult(0)
+    }
+}
+
+impl Handler<ExitThroughputQueryMessage> for Recorder {
+    type Result = MessageResult<ExitThroughputQueryMessage>;
+
+    fn handle(
+        &mut self,
+        msg: ExitThroughputQueryMessage,
+        _ctx: &mut Self::Context,
+    ) -> <Self as Handler<ExitThroughputQueryMessage>>::Result {
+        self.record(msg);
+        MessageResult(ExitThroughput::default())
+    }
+}
+
+impl Handler<NeighborhoodStatusQueryMessage> for Recorder {
+    type Result = MessageResult<NeighborhoodStatusQueryMessage>;
+
+    fn handle(
+        &mut self,
+        msg: NeighborhoodStatusQueryMessage,
+        _ctx: &mut Self::Context,
+    ) -> <Self as Handler<NeighborhoodStatusQueryMessage>>::Result {
+        self.record(msg);
+        MessageResult(NeighborhoodStatus::default())
+    }
+}
+
+impl Handler<TopologyQueryMessage> for Recorder {
+    type Result = MessageResult<TopologyQueryMessage>;
+
+    fn handle(
+        &mut self,
+        msg: TopologyQueryMessage,
+        _ctx: &mut Self::Context,
+    ) -> <Self as Handler<TopologyQueryMessage>>::Result {
+        self.record(msg);
+        MessageResult(TopologyPage::default())
+    }
+}
+
+impl Handler<HopperStatisticsQueryMessage> for Recorder {
+    type Result = MessageResult<HopperStatisticsQueryMessage>;
+
+    fn handle(
+        &mut self,
+        msg: HopperStatisticsQueryMessage,
+        _ctx: &mut Self::Context,
+    ) -> <Self as Handler<HopperStatisticsQueryMessage>>::Result {
+        self.record(msg);
+        MessageResult(HopperStatistics::default())
+    }
+}
+
 impl Handler<DispatcherNodeQueryResponse> for Recorder {
     type Result = ();
 
@@ -228,6 +434,56 @@ impl Handler<ReportExitServiceConsumedMessage> for Recorder {
     }
 }
 
+impl Handler<SubscribeToConfigChangesMessage> for Recorder {
+    type Result = ();
+
+    fn handle(&mut self, msg: SubscribeToConfigChangesMessage, _ctx: &mut Self::Context) {
+        self.record(msg);
+    }
+}
+
+impl Handler<ReceivableAgingQueryMessage> for Recorder {
+    type Result = MessageResult<ReceivableAgingQueryMessage>;
+
+    fn handle(
+        &mut self,
+        msg: ReceivableAgingQueryMessage,
+        _ctx: &mut Self::Context,
+    ) -> <Self as Handler<ReceivableAgingQueryMessage>>::Result {
+        self.record(msg);
+        MessageResult(ReceivableAgingReport::default())
+    }
+}
+
+impl Handler<BalancesQueryMessage> for Recorder {
+    type Result = MessageResult<BalancesQueryMessage>;
+
+    fn handle(
+        &mut self,
+        msg: BalancesQueryMessage,
+        _ctx: &mut Self::Context,
+    ) -> <Self as Handler<BalancesQueryMessage>>::Result {
+        self.record(msg);
+        MessageResult(Balances::default())
+    }
+}
+
+impl Handler<MetricsReport> for Recorder {
+    type Result = ();
+
+    fn handle(&mut self, msg: MetricsReport, _ctx: &mut Self::Context) {
+        self.record(msg);
+    }
+}
+
+impl Handler<ConfigChangedMessage> for Recorder {
+    type Result = ();
+
+    fn handle(&mut self, msg: ConfigChangedMessage, _ctx: &mut Self::Context) {
+        self.record(msg);
+    }
+}
+
 fn extract_response<T>(responses: &mut Vec<T>, err_msg: &str) -> T
 where
     T: Clone,
@@ -273,7 +529,10 @@ impl Recorder {
         self
     }
 
-    pub fn route_query_response(mut self, response: Option<RouteQueryResponse>) -> Recorder {
+    pub fn route_query_response(
+        mut self,
+        response: Result<RouteQueryResponse, RouteRefusal>,
+    ) -> Recorder {
         self.route_query_responses.push(response);
         self
     }
@@ -309,6 +568,16 @@ impl Recording {
         };
         item_success_ref
     }
+
+    // For messages whose order relative to each other isn't fixed
+    pub fn get_record_opt<T>(&self, index: usize) -> Option<&T>
+    where
+        T: Any + Send,
+    {
+        self.messages
+            .get(index)
+            .and_then(|item_box| item_box.downcast_ref::<T>())
+    }
 }
 
 impl RecordAwaiter {
@@ -350,6 +619,11 @@ pub fn make_proxy_server_subs_from(addr: &Addr<Syn, Recorder>) -> ProxyServerSub
         from_dispatcher: addr.clone().recipient::<InboundClientData>(),
         from_hopper: addr.clone().recipient::<ExpiredCoresPackage>(),
         add_return_route: addr.clone().recipient::<AddReturnRouteMessage>(),
+        add_route: addr.clone().recipient::<AddRouteMessage>(),
+        reload_config: addr.clone().recipient::<ReloadConfigMessage>(),
+        adaptive_hops: addr.clone().recipient::<AdaptiveHopsMessage>(),
+        stream_count_query: addr.clone().recipient::<StreamCountQueryMessage>(),
+        shutdown: addr.clone().recipient::<ShutdownMessage>(),
     }
 }
 
@@ -358,6 +632,7 @@ pub fn make_dispatcher_subs_from(addr: &Addr<Syn, Recorder>) -> DispatcherSubs {
         ibcd_sub: addr.clone().recipient::<InboundClientData>(),
         bind: addr.clone().recipient::<BindMessage>(),
         from_dispatcher_client: addr.clone().recipient::<TransmitDataMsg>(),
+        stream_shutdown_sub: addr.clone().recipient::<StreamShutdownMsg>(),
     }
 }
 
@@ -366,6 +641,9 @@ pub fn make_hopper_subs_from(addr: &Addr<Syn, Recorder>) -> HopperSubs {
         bind: addr.clone().recipient::<BindMessage>(),
         from_hopper_client: addr.clone().recipient::<IncipientCoresPackage>(),
         from_dispatcher: addr.clone().recipient::<InboundClientData>(),
+        register_endpoint: addr.clone().recipient::<RegisterEndpointMessage>(),
+        statistics_query: addr.clone().recipient::<HopperStatisticsQueryMessage>(),
+        payment_standing: addr.clone().recipient::<PaymentStandingMessage>(),
     }
 }
 
@@ -374,6 +652,9 @@ pub fn make_proxy_client_subs_from(addr: &Addr<Syn, Recorder>) -> ProxyClientSub
         bind: addr.clone().recipient::<BindMessage>(),
         from_hopper: addr.clone().recipient::<ExpiredCoresPackage>(),
         inbound_server_data: addr.clone().recipient::<InboundServerData>(),
+        stream_count_query: addr.clone().recipient::<StreamCountQueryMessage>(),
+        exit_throughput_query: addr.clone().recipient::<ExitThroughputQueryMessage>(),
+        shutdown: addr.clone().recipient::<ShutdownMessage>(),
     }
 }
 
@@ -386,6 +667,16 @@ pub fn make_neighborhood_subs_from(addr: &Addr<Syn, Recorder>) -> NeighborhoodSu
         from_hopper: addr.clone().recipient::<ExpiredCoresPackage>(),
         dispatcher_node_query: addr.clone().recipient::<DispatcherNodeQueryMessage>(),
         remove_neighbor: addr.clone().recipient::<RemoveNeighborMessage>(),
+        set_consuming_wallet: addr.clone().recipient::<SetConsumingWalletMessage>(),
+        routing_load: addr.clone().recipient::<RoutingLoadMessage>(),
+        route_feedback: addr.clone().recipient::<RouteFeedbackMessage>(),
+        neighbor_misbehavior: addr.clone().recipient::<NeighborMisbehaviorMessage>(),
+        node_problem: addr.clone().recipient::<NodeProblemReport>(),
+        gossip_stats_query: addr.clone().recipient::<GossipStatsQueryMessage>(),
+        topology_query: addr.clone().recipient::<TopologyQueryMessage>(),
+        status_query: addr.clone().recipient::<NeighborhoodStatusQueryMessage>(),
+        reload_config: addr.clone().recipient::<ReloadConfigMessage>(),
+        shutdown: addr.clone().recipient::<ShutdownMessage>(),
     }
 }
 
@@ -400,6 +691,10 @@ pub fn make_accountant_subs_from(addr: &Addr<Syn, Recorder>) -> AccountantSubs {
             .clone()
             .recipient::<ReportRoutingServiceConsumedMessage>(),
         report_exit_service_consumed: addr.clone().recipient::<ReportExitServiceConsumedMessage>(),
+        subscribe_to_config_changes: addr.clone().recipient::<SubscribeToConfigChangesMessage>(),
+        receivable_aging_query: addr.clone().recipient::<ReceivableAgingQueryMessage>(),
+        balances_query: addr.clone().recipient::<BalancesQueryMessage>(),
+        shutdown: addr.clone().recipient::<ShutdownMessage>(),
     }
 }
 
@@ -411,6 +706,13 @@ pub fn make_ui_gateway_subs_from(addr: &Addr<Syn, Recorder>) -> UiGatewaySubs {
     }
 }
 
+pub fn make_metrics_subs_from(addr: &Addr<Syn, Recorder>) -> MetricsSubs {
+    MetricsSubs {
+        bind: addr.clone().recipient::<BindMessage>(),
+        report: addr.clone().recipient::<MetricsReport>(),
+    }
+}
+
 pub fn peer_actors_builder() -> PeerActorsBuilder {
     PeerActorsBuilder::new()
 }
@@ -423,6 +725,7 @@ pub struct PeerActorsBuilder {
     neighborhood: Recorder,
     accountant: Recorder,
     ui_gateway: Recorder,
+    metrics: Recorder,
 }
 
 impl PeerActorsBuilder {
@@ -435,6 +738,7 @@ impl PeerActorsBuilder {
             neighborhood: Recorder::new(),
             accountant: Recorder::new(),
             ui_gateway: Recorder::new(),
+            metrics: Recorder::new(),
         }
     }
 
@@ -473,6 +777,11 @@ impl PeerActorsBuilder {
         self
     }
 
+    pub fn metrics(mut self, recorder: Recorder) -> PeerActorsBuilder {
+        self.metrics = recorder;
+        self
+    }
+
     // This must be called after System.new and before System.run
     pub fn build(self) -> PeerActors {
         let proxy_server_addr = self.proxy_server.start();
@@ -482,6 +791,7 @@ impl PeerActorsBuilder {
         let neighborhood_addr = self.neighborhood.start();
         let accountant_addr = self.accountant.start();
         let ui_gateway_addr = self.ui_gateway.start();
+        let metrics_addr = self.metrics.start();
 
         PeerActors {
             proxy_server: make_proxy_server_subs_from(&proxy_server_addr),
@@ -491,6 +801,7 @@ impl PeerActorsBuilder {
             neighborhood: make_neighborhood_subs_from(&neighborhood_addr),
             accountant: make_accountant_subs_from(&accountant_addr),
             ui_gateway: make_ui_gateway_subs_from(&ui_gateway_addr),
+            metrics: make_metrics_subs_from(&metrics_addr),
         }
     }
 }
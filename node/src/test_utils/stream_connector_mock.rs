@@ -5,6 +5,7 @@ use crate::sub_lib::stream_connector::ConnectionInfoFuture;
 use crate::sub_lib::stream_connector::StreamConnector;
 use crate::test_utils::tokio_wrapper_mocks::ReadHalfWrapperMock;
 use crate::test_utils::tokio_wrapper_mocks::WriteHalfWrapperMock;
+use futures::future::ok;
 use futures::future::result;
 use std::cell::RefCell;
 use std::io;
@@ -12,12 +13,15 @@ use std::net::IpAddr;
 use std::net::SocketAddr;
 use std::sync::Arc;
 use std::sync::Mutex;
+use std::time::Duration;
 use tokio::net::TcpStream;
 use tokio::prelude::Async;
 
 pub struct StreamConnectorMock {
     connect_pair_params: Arc<Mutex<Vec<SocketAddr>>>,
     connect_pair_results: RefCell<Vec<Result<ConnectionInfo, io::Error>>>,
+    connect_one_params: Arc<Mutex<Vec<(String, u16, Duration)>>>,
+    connect_one_tls_params: Arc<Mutex<Vec<(String, u16, Duration)>>>,
 }
 
 impl StreamConnector for StreamConnectorMock {
@@ -30,13 +34,36 @@ impl StreamConnector for StreamConnectorMock {
     fn connect_one(
         &self,
         _ip_addrs: Vec<IpAddr>,
-        _target_hostname: &String,
-        _target_port: u16,
+        target_hostname: &String,
+        target_port: u16,
+        timeout: Duration,
         _logger: &Logger,
     ) -> Result<ConnectionInfo, io::Error> {
+        self.connect_one_params.lock().unwrap().push((
+            target_hostname.clone(),
+            target_port,
+            timeout,
+        ));
         self.connect_pair_results.borrow_mut().remove(0)
     }
 
+    fn connect_one_tls(
+        &self,
+        _ip_addrs: Vec<IpAddr>,
+        target_hostname: &String,
+        target_port: u16,
+        timeout: Duration,
+        _logger: &Logger,
+    ) -> Result<(SocketAddr, ConnectionInfoFuture), io::Error> {
+        self.connect_one_tls_params.lock().unwrap().push((
+            target_hostname.clone(),
+            target_port,
+            timeout,
+        ));
+        let connection_info = self.connect_pair_results.borrow_mut().remove(0)?;
+        Ok((connection_info.peer_addr, Box::new(ok(connection_info))))
+    }
+
     fn split_stream(&self, _stream: TcpStream, _logger: &Logger) -> ConnectionInfo {
         unimplemented!()
     }
@@ -51,6 +78,8 @@ impl StreamConnectorMock {
         Self {
             connect_pair_params: Arc::new(Mutex::new(vec![])),
             connect_pair_results: RefCell::new(vec![]),
+            connect_one_params: Arc::new(Mutex::new(vec![])),
+            connect_one_tls_params: Arc::new(Mutex::new(vec![])),
         }
     }
 
@@ -104,6 +133,22 @@ impl StreamConnectorMock {
         self
     }
 
+    pub fn connect_one_params(
+        mut self,
+        params_arc: &Arc<Mutex<Vec<(String, u16, Duration)>>>,
+    ) -> StreamConnectorMock {
+        self.connect_one_params = params_arc.clone();
+        self
+    }
+
+    pub fn connect_one_tls_params(
+        mut self,
+        params_arc: &Arc<Mutex<Vec<(String, u16, Duration)>>>,
+    ) -> StreamConnectorMock {
+        self.connect_one_tls_params = params_arc.clone();
+        self
+    }
+
     pub fn connect_pair_result(
         self,
         result: Result<ConnectionInfo, io::Error>,
@@ -1,4 +1,6 @@
 // Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use crate::neighborhood::neighborhood::MAXIMUM_ROUTE_VALIDITY;
+use crate::neighborhood::route_scores::MAXIMUM_ROUTE_SCORE;
 use crate::sub_lib::cryptde::CryptDE;
 use crate::sub_lib::cryptde::CryptData;
 use crate::sub_lib::cryptde::PlainData;
@@ -257,6 +259,9 @@ pub fn zero_hop_route_response(
             vec![ExpectedService::Nothing, ExpectedService::Nothing],
             0,
         ),
+        valid_for: MAXIMUM_ROUTE_VALIDITY,
+        score: MAXIMUM_ROUTE_SCORE,
+        exit_sla_opt: None,
     }
 }
 
@@ -0,0 +1,56 @@
+// Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use crate::sub_lib::time_source::TimeSource;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::SystemTime;
+
+// Clones share the same clock, so a test can keep one and move time along while the code under
+// test holds another.
+#[derive(Clone, Debug)]
+pub struct TimeSourceMock {
+    now: Arc<Mutex<SystemTime>>,
+}
+
+impl TimeSource for TimeSourceMock {
+    fn now(&self) -> SystemTime {
+        *self.now.lock().unwrap()
+    }
+}
+
+impl TimeSourceMock {
+    pub fn new(now: SystemTime) -> TimeSourceMock {
+        TimeSourceMock {
+            now: Arc::new(Mutex::new(now)),
+        }
+    }
+
+    pub fn set(&self, now: SystemTime) {
+        *self.now.lock().unwrap() = now;
+    }
+
+    pub fn advance(&self, interval: Duration) {
+        let mut now = self.now.lock().unwrap();
+        *now = *now + interval;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::UNIX_EPOCH;
+
+    #[test]
+    fn clones_share_the_same_clock() {
+        let subject = TimeSourceMock::new(UNIX_EPOCH + Duration::from_secs(1000));
+        let clone = subject.clone();
+
+        clone.advance(Duration::from_secs(5));
+
+        assert_eq!(subject.now(), UNIX_EPOCH + Duration::from_secs(1005));
+
+        subject.set(UNIX_EPOCH + Duration::from_secs(2000));
+
+        assert_eq!(clone.now(), UNIX_EPOCH + Duration::from_secs(2000));
+    }
+}
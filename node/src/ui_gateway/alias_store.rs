@@ -0,0 +1,230 @@
+// Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use crate::sub_lib::ui_gateway::UiAlert;
+use serde_derive::Serialize;
+use serde_json;
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+
+pub const ALIAS_FILE: &str = "aliases.json";
+
+// Names the operator has given to wallet addresses and Node public keys, so that a UI can show
+// "my VPS node" instead of a string of hex or base64. They're kept in a file of their own beside
+// the database rather than in it, because the database can be exported, and they're never put
+// into Gossip: aliases don't leave this machine.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AliasStore {
+    file_path: Option<PathBuf>,
+    aliases: BTreeMap<String, String>,
+}
+
+#[derive(Serialize)]
+struct AliasesEnvelope<'a> {
+    message_type: &'a str,
+    payload: &'a BTreeMap<String, String>,
+}
+
+impl AliasStore {
+    // For when there's nowhere to keep them: aliases last until shutdown
+    pub fn in_memory() -> AliasStore {
+        AliasStore {
+            file_path: None,
+            aliases: BTreeMap::new(),
+        }
+    }
+
+    pub fn load(directory: &Path) -> Result<AliasStore, UiAlert> {
+        let file_path = directory.join(ALIAS_FILE);
+        let aliases = if file_path.exists() {
+            let json = fs::read_to_string(&file_path)
+                .map_err(|e| file_alert("unreadable_alias_file", &file_path, e))?;
+            serde_json::from_str(&json)
+                .map_err(|e| file_alert("unparseable_alias_file", &file_path, e))?
+        } else {
+            BTreeMap::new()
+        };
+        Ok(AliasStore {
+            file_path: Some(file_path),
+            aliases,
+        })
+    }
+
+    pub fn set(&mut self, key: &str, label: &str) -> Result<(), UiAlert> {
+        let key = AliasStore::normalize(key)?;
+        let label = label.trim();
+        if label.is_empty() {
+            return Err(UiAlert::new("blank_alias").with("key", &key));
+        }
+        self.aliases.insert(key, String::from(label));
+        self.save()
+    }
+
+    // The label that was removed, if there was one
+    pub fn remove(&mut self, key: &str) -> Result<Option<String>, UiAlert> {
+        let key = AliasStore::normalize(key)?;
+        match self.aliases.remove(&key) {
+            Some(label) => self.save().map(|_| Some(label)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn to_ui_json(&self) -> String {
+        serde_json::to_string(&AliasesEnvelope {
+            message_type: "aliases",
+            payload: &self.aliases,
+        })
+        .expect("Aliases could not be serialized")
+    }
+
+    // Wallet addresses are the same address whatever their capitalization; public keys aren't
+    fn normalize(key: &str) -> Result<String, UiAlert> {
+        let key = key.trim();
+        if key.is_empty() {
+            Err(UiAlert::new("blank_alias_key"))
+        } else if key.starts_with("0x") || key.starts_with("0X") {
+            Ok(key.to_lowercase())
+        } else {
+            Ok(String::from(key))
+        }
+    }
+
+    fn save(&self) -> Result<(), UiAlert> {
+        let file_path = match &self.file_path {
+            Some(file_path) => file_path,
+            None => return Ok(()),
+        };
+        let json =
+            serde_json::to_string_pretty(&self.aliases).expect("Aliases could not be serialized");
+        fs::write(file_path, json).map_err(|e| file_alert("unwritable_alias_file", file_path, e))
+    }
+}
+
+fn file_alert(code: &str, file_path: &Path, error: impl Display) -> UiAlert {
+    UiAlert::new(code)
+        .with("path", &format!("{:?}", file_path))
+        .with("error", &format!("{}", error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE_TEST_DIR: &str = "generated/test/alias_store";
+
+    fn make_directory(name: &str) -> PathBuf {
+        let directory = PathBuf::from(format!("{}/{}", BASE_TEST_DIR, name));
+        fs::remove_dir_all(&directory).is_ok();
+        fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    fn aliases_of(subject: &AliasStore) -> Vec<(&str, &str)> {
+        subject
+            .aliases
+            .iter()
+            .map(|(key, label)| (key.as_str(), label.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn labels_are_trimmed_and_wallet_addresses_ignore_capitalization() {
+        let mut subject = AliasStore::in_memory();
+
+        subject
+            .set(
+                "0xbDfeFf9A1f4A1bdF483d680046344316019C58CF",
+                " my VPS node ",
+            )
+            .unwrap();
+        subject
+            .set(
+                "0xBDFEFF9A1F4A1BDF483D680046344316019C58CF",
+                "my old VPS node",
+            )
+            .unwrap();
+        subject.set("QmlsbA", "brother's node").unwrap();
+        subject.set("qmlsba", "somebody else's node").unwrap();
+
+        assert_eq!(
+            aliases_of(&subject),
+            vec![
+                (
+                    "0xbdfeff9a1f4a1bdf483d680046344316019c58cf",
+                    "my old VPS node"
+                ),
+                ("QmlsbA", "brother's node"),
+                ("qmlsba", "somebody else's node"),
+            ]
+        );
+    }
+
+    #[test]
+    fn blank_keys_and_labels_are_refused() {
+        let mut subject = AliasStore::in_memory();
+
+        assert_eq!(
+            subject.set("  ", "nobody"),
+            Err(UiAlert::new("blank_alias_key"))
+        );
+        assert_eq!(
+            subject.set("QmlsbA", "  "),
+            Err(UiAlert::new("blank_alias").with("key", "QmlsbA"))
+        );
+        assert_eq!(subject, AliasStore::in_memory());
+    }
+
+    #[test]
+    fn removing_an_alias_reports_the_label_there_was() {
+        let mut subject = AliasStore::in_memory();
+        subject.set("QmlsbA", "brother's node").unwrap();
+
+        assert_eq!(
+            subject.remove("QmlsbA"),
+            Ok(Some(String::from("brother's node")))
+        );
+        assert_eq!(subject.remove("QmlsbA"), Ok(None));
+        assert_eq!(aliases_of(&subject), vec![]);
+    }
+
+    #[test]
+    fn aliases_survive_being_loaded_again() {
+        let directory = make_directory("aliases_survive_being_loaded_again");
+        let mut subject = AliasStore::load(&directory).unwrap();
+        subject.set("QmlsbA", "brother's node").unwrap();
+        subject.set("VGVk", "doomed").unwrap();
+        subject.remove("VGVk").unwrap();
+
+        let result = AliasStore::load(&directory).unwrap();
+
+        assert_eq!(result, subject);
+        assert_eq!(aliases_of(&result), vec![("QmlsbA", "brother's node")]);
+    }
+
+    #[test]
+    fn a_corrupt_alias_file_is_reported() {
+        let directory = make_directory("a_corrupt_alias_file_is_reported");
+        fs::write(directory.join(ALIAS_FILE), "booga").unwrap();
+
+        let result = AliasStore::load(&directory);
+
+        let alert = result.err().unwrap();
+        assert_eq!(alert.code, String::from("unparseable_alias_file"));
+        assert_eq!(
+            alert.parameters["path"],
+            format!("{:?}", directory.join(ALIAS_FILE))
+        );
+    }
+
+    #[test]
+    fn aliases_for_the_ui_are_wrapped_with_a_message_type() {
+        let mut subject = AliasStore::in_memory();
+        subject.set("QmlsbA", "brother's node").unwrap();
+
+        let result: serde_json::Value = serde_json::from_str(&subject.to_ui_json()).unwrap();
+
+        assert_eq!(result["message_type"], "aliases");
+        assert_eq!(result["payload"]["QmlsbA"], "brother's node");
+    }
+}
@@ -0,0 +1,183 @@
+// Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use crate::sub_lib::ui_gateway::UiAlert;
+use serde_derive::Serialize;
+use serde_json;
+use std::collections::BTreeMap;
+
+pub const CATALOG_LANGUAGE: &str = "en";
+
+// The English text for every alert code this Node sends to UIs. A parameter's name in braces is
+// replaced by its value. Codes never change meaning once released: a UI that ships its own
+// translations looks alerts up by code, and falls back to this text for codes it doesn't know yet.
+pub const MESSAGE_CATALOG: &[(&str, &str)] = &[
+    ("unparseable_ui_message", "Couldn't parse '{json}': {error}"),
+    ("missing_message_type", "No message_type in '{json}'"),
+    (
+        "unsupported_message_type",
+        "Unsupported message_type '{message_type}'",
+    ),
+    ("missing_payload_field", "No payload {field} in '{json}'"),
+    (
+        "blank_alias_key",
+        "Can't alias a blank wallet address or public key",
+    ),
+    ("blank_alias", "Alias for {key} is blank"),
+    ("unreadable_alias_file", "Couldn't read {path}: {error}"),
+    ("unparseable_alias_file", "Couldn't parse {path}: {error}"),
+    ("unwritable_alias_file", "Couldn't write {path}: {error}"),
+    (
+        "database_checked",
+        "Database passed its integrity check and was compacted",
+    ),
+    (
+        "database_not_compacted",
+        "Database passed its integrity check, but couldn't be compacted: {error}",
+    ),
+    (
+        "database_corrupt",
+        "Database is corrupt ({error}); charges are no longer recorded, so service is free until it's repaired",
+    ),
+    (
+        "hop_count_adapted",
+        "Median round trip is {median_ms}ms; new routes will have at least {hop_count} hops",
+    ),
+    (
+        "adaptive_hops_toggled",
+        "Adaptive hop count turned {enabled}; new routes will have at least {hop_count} hops",
+    ),
+    (
+        "adaptive_hops_unavailable",
+        "Adaptive hop count can't be turned on or off: the Node wasn't started with --adaptive_hops",
+    ),
+    (
+        "bad_log_level",
+        "Log level must be off, error, warn, info, debug or trace, not '{level}'",
+    ),
+    (
+        "log_level_unchangeable",
+        "Log level can't be changed: the Node's logger isn't running",
+    ),
+    ("unparseable_public_key", "'{key}' isn't a public key in base64"),
+    ("actor_panicked", "{actor} crashed; what led up to it is in {path}"),
+    (
+        "consuming_wallet_switched",
+        "Consuming wallet {wallet} is down to {balance} wei, below the threshold of {threshold}; switched to standby {standby}",
+    ),
+    (
+        "consuming_wallets_low",
+        "Consuming wallet {wallet} is down to {balance} wei and standby {standby} to {standby_balance} wei, both below the threshold of {threshold}; top one of them up before {wallet} runs out",
+    ),
+    (
+        "standby_consuming_wallet_low",
+        "Standby consuming wallet {wallet} is down to {balance} wei, below the threshold of {threshold}; top it up before it runs out",
+    ),
+    ("missing_request_id", "No request_id in '{json}'"),
+    (
+        "unauthenticated_order",
+        "Order refused: authenticate with the Node's UI token first",
+    ),
+    (
+        "unwritable_ui_token",
+        "Couldn't write {path}: {error}; no UI can give the Node orders",
+    ),
+    (
+        "bad_topology_query",
+        "Can't understand topology query '{json}': {error}",
+    ),
+    (
+        "earning_wallets_to_consolidate",
+        "{total} wei has piled up in earning wallets the Node no longer advertises: {wallets}; move it to {wallet}",
+    ),
+];
+
+#[derive(Serialize)]
+struct CatalogPayload<'a> {
+    language: &'a str,
+    messages: BTreeMap<&'a str, &'a str>,
+}
+
+#[derive(Serialize)]
+struct CatalogEnvelope<'a> {
+    message_type: &'a str,
+    payload: CatalogPayload<'a>,
+}
+
+// The English text of an alert, for the log and for UIs that don't translate
+pub fn render(alert: &UiAlert) -> String {
+    match MESSAGE_CATALOG
+        .iter()
+        .find(|(code, _)| *code == alert.code.as_str())
+    {
+        Some((_, template)) => alert
+            .parameters
+            .iter()
+            .fold(String::from(*template), |text, (name, value)| {
+                text.replace(&format!("{{{}}}", name), value)
+            }),
+        None => format!("{} {:?}", alert.code, alert.parameters),
+    }
+}
+
+pub fn catalog_to_ui_json() -> String {
+    serde_json::to_string(&CatalogEnvelope {
+        message_type: "message_catalog",
+        payload: CatalogPayload {
+            language: CATALOG_LANGUAGE,
+            messages: MESSAGE_CATALOG.iter().cloned().collect(),
+        },
+    })
+    .expect("Message catalog could not be serialized")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn constants_have_correct_values() {
+        assert_eq!(CATALOG_LANGUAGE, "en");
+    }
+
+    #[test]
+    fn codes_are_not_repeated() {
+        let codes = MESSAGE_CATALOG
+            .iter()
+            .map(|(code, _)| *code)
+            .collect::<HashSet<&str>>();
+
+        assert_eq!(codes.len(), MESSAGE_CATALOG.len());
+    }
+
+    #[test]
+    fn alerts_are_rendered_with_their_parameters_filled_in() {
+        let alert = UiAlert::new("missing_payload_field")
+            .with("field", "label")
+            .with("json", "{}");
+
+        let result = render(&alert);
+
+        assert_eq!(result, String::from("No payload label in '{}'"));
+    }
+
+    #[test]
+    fn alerts_with_unknown_codes_are_rendered_with_their_code() {
+        let alert = UiAlert::new("booga").with("key", "QmlsbA");
+
+        let result = render(&alert);
+
+        assert_eq!(result, String::from("booga {\"key\": \"QmlsbA\"}"));
+    }
+
+    #[test]
+    fn catalog_for_the_ui_is_wrapped_with_a_message_type() {
+        let result: serde_json::Value = serde_json::from_str(&catalog_to_ui_json()).unwrap();
+
+        assert_eq!(result["message_type"], "message_catalog");
+        assert_eq!(result["payload"]["language"], "en");
+        assert_eq!(
+            result["payload"]["messages"]["blank_alias"],
+            "Alias for {key} is blank"
+        );
+    }
+}
@@ -1,6 +1,9 @@
 // Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
 
+pub mod alias_store;
+pub mod message_catalog;
+pub mod remote_access;
 mod shutdown_supervisor;
 pub mod ui_gateway;
-mod ui_traffic_converter;
+pub mod ui_traffic_converter;
 mod websocket_supervisor;
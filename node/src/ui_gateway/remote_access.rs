@@ -0,0 +1,527 @@
+// Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use serde_derive::{Deserialize, Serialize};
+use serde_json;
+use sodiumoxide::crypto::auth;
+use sodiumoxide::crypto::hash::sha256;
+use sodiumoxide::randombytes;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::Duration;
+use std::time::Instant;
+
+pub const NONCE_LENGTH: usize = 32;
+pub const MAX_FAILED_ATTEMPTS: usize = 5;
+pub const LOCKOUT_PERIOD: Duration = Duration::from_secs(15 * 60);
+pub const GENERATED_TOKEN_BYTES: usize = 24;
+
+// Each side labels what it authenticates, so that a proof from one side can't be played back as
+// a proof from the other
+const UI_LABEL: &[u8] = b"ui:";
+const NODE_LABEL: &[u8] = b"node:";
+
+// Proofs that a side knows the token the operator gave both the Node and the UI, without the token
+// itself going over the wire
+pub struct TokenKey {
+    key: auth::Key,
+}
+
+impl TokenKey {
+    pub fn new(token: &str) -> TokenKey {
+        sodiumoxide::init().expect("Couldn't initialize libsodium");
+        TokenKey {
+            key: auth::Key(sha256::hash(token.as_bytes()).0),
+        }
+    }
+
+    pub fn ui_proof(&self, nonce: &[u8]) -> Vec<u8> {
+        auth::authenticate(&labeled(UI_LABEL, nonce), &self.key)
+            .0
+            .to_vec()
+    }
+
+    pub fn node_proof(&self, nonce: &[u8]) -> Vec<u8> {
+        auth::authenticate(&labeled(NODE_LABEL, nonce), &self.key)
+            .0
+            .to_vec()
+    }
+
+    pub fn verify_ui_proof(&self, nonce: &[u8], proof: &[u8]) -> bool {
+        match auth::Tag::from_slice(proof) {
+            Some(tag) => auth::verify(&tag, &labeled(UI_LABEL, nonce), &self.key),
+            None => false,
+        }
+    }
+}
+
+fn labeled(label: &[u8], nonce: &[u8]) -> Vec<u8> {
+    label.iter().chain(nonce.iter()).cloned().collect()
+}
+
+// For a Node whose operator didn't give it one; long enough for MIN_UI_TOKEN_LENGTH, and with
+// nothing in it that needs quoting in a file or on a command line
+pub fn generate_token() -> String {
+    sodiumoxide::init().expect("Couldn't initialize libsodium");
+    base64::encode_config(
+        &randombytes::randombytes(GENERATED_TOKEN_BYTES),
+        base64::URL_SAFE_NO_PAD,
+    )
+}
+
+// Whether a UI's message is its half of the handshake, rather than something for the UiGateway
+pub fn is_authentication(message: &str) -> bool {
+    match serde_json::from_str::<serde_json::Value>(message) {
+        Ok(value) => value["message_type"] == "auth",
+        Err(_) => false,
+    }
+}
+
+struct Failures {
+    count: usize,
+    last: Instant,
+}
+
+// Counts failed authentications from each IP address. Once an address has failed max_failures
+// times, it's refused until period has passed since its last failure.
+pub struct Lockout {
+    max_failures: usize,
+    period: Duration,
+    failures: HashMap<IpAddr, Failures>,
+}
+
+impl Lockout {
+    pub fn new(max_failures: usize, period: Duration) -> Lockout {
+        Lockout {
+            max_failures,
+            period,
+            failures: HashMap::new(),
+        }
+    }
+
+    pub fn is_locked_out(&self, ip: IpAddr, now: Instant) -> bool {
+        match self.failures.get(&ip) {
+            Some(failures) => {
+                failures.count >= self.max_failures
+                    && now.duration_since(failures.last) < self.period
+            }
+            None => false,
+        }
+    }
+
+    pub fn record_failure(&mut self, ip: IpAddr, now: Instant) {
+        let period = self.period;
+        // Old failures are forgotten, so addresses that tried once long ago don't pile up
+        self.failures
+            .retain(|_, failures| now.duration_since(failures.last) < period);
+        let failures = self.failures.entry(ip).or_insert(Failures {
+            count: 0,
+            last: now,
+        });
+        failures.count += 1;
+        failures.last = now;
+    }
+
+    pub fn record_success(&mut self, ip: IpAddr) {
+        self.failures.remove(&ip);
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct ChallengePayload {
+    nonce: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct AuthenticatedPayload {
+    proof: String,
+}
+
+#[derive(Serialize)]
+struct Envelope<'a, T> {
+    message_type: &'a str,
+    payload: T,
+}
+
+#[derive(Serialize, Deserialize)]
+struct AuthPayload {
+    proof: String,
+    nonce: String,
+}
+
+#[derive(Deserialize)]
+struct ReceivedEnvelope<T> {
+    message_type: String,
+    payload: T,
+}
+
+// The handshake a remote UI goes through before it hears anything else. When it connects, the
+// Node sends
+//   {"message_type":"auth_challenge","payload":{"nonce":<base64>}}
+// and the UI answers with its proof over that nonce, and a nonce of its own:
+//   {"message_type":"auth","payload":{"proof":<base64>,"nonce":<base64>}}
+// If the proof is good, the Node answers with its proof over the UI's nonce
+//   {"message_type":"authenticated","payload":{"proof":<base64>}}
+// and carries on as it would with a local UI. Anything else is a failure.
+pub struct RemoteAccess {
+    token_key: TokenKey,
+    lockout: Lockout,
+    // The nonce sent to each UI that hasn't authenticated yet
+    challenge_by_client_id: HashMap<u64, Vec<u8>>,
+}
+
+impl RemoteAccess {
+    pub fn new(token: &str) -> RemoteAccess {
+        RemoteAccess {
+            token_key: TokenKey::new(token),
+            lockout: Lockout::new(MAX_FAILED_ATTEMPTS, LOCKOUT_PERIOD),
+            challenge_by_client_id: HashMap::new(),
+        }
+    }
+
+    pub fn is_locked_out(&self, ip: IpAddr, now: Instant) -> bool {
+        self.lockout.is_locked_out(ip, now)
+    }
+
+    pub fn is_awaiting(&self, client_id: u64) -> bool {
+        self.challenge_by_client_id.contains_key(&client_id)
+    }
+
+    pub fn challenge(&mut self, client_id: u64) -> String {
+        let nonce = randombytes::randombytes(NONCE_LENGTH);
+        let json = serde_json::to_string(&Envelope {
+            message_type: "auth_challenge",
+            payload: ChallengePayload {
+                nonce: base64::encode(&nonce),
+            },
+        })
+        .expect("Challenge could not be serialized");
+        self.challenge_by_client_id.insert(client_id, nonce);
+        json
+    }
+
+    // The Node's answer, if the UI's message proves it knows the token
+    pub fn authenticate(
+        &mut self,
+        client_id: u64,
+        ip: IpAddr,
+        message: &str,
+        now: Instant,
+    ) -> Result<String, String> {
+        let challenge = self
+            .challenge_by_client_id
+            .remove(&client_id)
+            .expect("UI authenticated without being challenged");
+        match self.check(&challenge, message) {
+            Ok(ui_nonce) => {
+                self.lockout.record_success(ip);
+                Ok(serde_json::to_string(&Envelope {
+                    message_type: "authenticated",
+                    payload: AuthenticatedPayload {
+                        proof: base64::encode(&self.token_key.node_proof(&ui_nonce)),
+                    },
+                })
+                .expect("Proof could not be serialized"))
+            }
+            Err(e) => {
+                self.lockout.record_failure(ip, now);
+                Err(e)
+            }
+        }
+    }
+
+    pub fn forget(&mut self, client_id: u64) {
+        self.challenge_by_client_id.remove(&client_id);
+    }
+
+    fn check(&self, challenge: &[u8], message: &str) -> Result<Vec<u8>, String> {
+        let envelope: ReceivedEnvelope<AuthPayload> = serde_json::from_str(message)
+            .map_err(|e| format!("expected authentication, got '{}': {}", message, e))?;
+        if envelope.message_type != "auth" {
+            return Err(format!(
+                "expected authentication, got '{}'",
+                envelope.message_type
+            ));
+        }
+        let proof = base64::decode(&envelope.payload.proof)
+            .map_err(|e| format!("unreadable proof: {}", e))?;
+        let ui_nonce = base64::decode(&envelope.payload.nonce)
+            .map_err(|e| format!("unreadable nonce: {}", e))?;
+        if ui_nonce.len() != NONCE_LENGTH {
+            return Err(format!(
+                "nonce is {} bytes, not {}",
+                ui_nonce.len(),
+                NONCE_LENGTH
+            ));
+        }
+        if !self.token_key.verify_ui_proof(challenge, &proof) {
+            return Err(String::from("wrong token"));
+        }
+        Ok(ui_nonce)
+    }
+}
+
+// The UI's half of the handshake, for UIs written in Rust
+pub struct UiHandshake {
+    token_key: TokenKey,
+    ui_nonce: Vec<u8>,
+}
+
+impl UiHandshake {
+    pub fn new(token: &str) -> UiHandshake {
+        let token_key = TokenKey::new(token);
+        UiHandshake {
+            token_key,
+            ui_nonce: randombytes::randombytes(NONCE_LENGTH),
+        }
+    }
+
+    // The auth message that answers the Node's auth_challenge
+    pub fn answer(&self, challenge: &str) -> Result<String, String> {
+        let envelope: ReceivedEnvelope<ChallengePayload> = serde_json::from_str(challenge)
+            .map_err(|e| format!("expected a challenge, got '{}': {}", challenge, e))?;
+        if envelope.message_type != "auth_challenge" {
+            return Err(format!(
+                "expected a challenge, got '{}'",
+                envelope.message_type
+            ));
+        }
+        let node_nonce = base64::decode(&envelope.payload.nonce)
+            .map_err(|e| format!("unreadable nonce: {}", e))?;
+        Ok(serde_json::to_string(&Envelope {
+            message_type: "auth",
+            payload: AuthPayload {
+                proof: base64::encode(&self.token_key.ui_proof(&node_nonce)),
+                nonce: base64::encode(&self.ui_nonce),
+            },
+        })
+        .expect("Proof could not be serialized"))
+    }
+
+    // Whether the Node's answer proves that it, too, knows the token
+    pub fn check(&self, answer: &str) -> Result<(), String> {
+        let envelope: ReceivedEnvelope<AuthenticatedPayload> = serde_json::from_str(answer)
+            .map_err(|e| format!("expected the Node's proof, got '{}': {}", answer, e))?;
+        if envelope.message_type != "authenticated" {
+            return Err(format!(
+                "expected the Node's proof, got '{}'",
+                envelope.message_type
+            ));
+        }
+        let proof = base64::decode(&envelope.payload.proof)
+            .map_err(|e| format!("unreadable proof: {}", e))?;
+        if proof != self.token_key.node_proof(&self.ui_nonce) {
+            return Err(String::from("the Node doesn't know the token"));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn challenge_nonce(challenge_json: &str) -> Vec<u8> {
+        let value: serde_json::Value = serde_json::from_str(challenge_json).unwrap();
+        assert_eq!(value["message_type"], "auth_challenge");
+        base64::decode(value["payload"]["nonce"].as_str().unwrap()).unwrap()
+    }
+
+    fn auth_json(proof: &[u8], nonce: &[u8]) -> String {
+        format!(
+            "{{\"message_type\":\"auth\",\"payload\":{{\"proof\":\"{}\",\"nonce\":\"{}\"}}}}",
+            base64::encode(proof),
+            base64::encode(nonce)
+        )
+    }
+
+    fn ip() -> IpAddr {
+        IpAddr::from_str("1.2.3.4").unwrap()
+    }
+
+    #[test]
+    fn constants_have_correct_values() {
+        assert_eq!(NONCE_LENGTH, 32);
+        assert_eq!(MAX_FAILED_ATTEMPTS, 5);
+        assert_eq!(LOCKOUT_PERIOD, Duration::from_secs(900));
+        assert_eq!(GENERATED_TOKEN_BYTES, 24);
+    }
+
+    #[test]
+    fn generated_tokens_are_long_enough_and_different() {
+        let one = generate_token();
+        let another = generate_token();
+
+        assert_eq!(one.len(), 32);
+        assert_eq!(
+            one.len() >= crate::sub_lib::ui_gateway::MIN_UI_TOKEN_LENGTH,
+            true
+        );
+        assert_ne!(one, another);
+    }
+
+    #[test]
+    fn only_an_auth_message_is_authentication() {
+        assert_eq!(is_authentication(&auth_json(&[1], &[2])), true);
+        assert_eq!(is_authentication("{\"message_type\":\"shutdown\"}"), false);
+        assert_eq!(is_authentication("booga"), false);
+    }
+
+    #[test]
+    fn a_ui_handshake_authenticates_to_a_node_and_checks_its_answer() {
+        let mut node = RemoteAccess::new("correct horse battery staple");
+        let subject = UiHandshake::new("correct horse battery staple");
+
+        let auth = subject.answer(&node.challenge(1234)).unwrap();
+        let answer = node
+            .authenticate(1234, ip(), &auth, Instant::now())
+            .unwrap();
+
+        assert_eq!(subject.check(&answer), Ok(()));
+        assert_eq!(
+            UiHandshake::new("wrong horse battery staple").check(&answer),
+            Err(String::from("the Node doesn't know the token"))
+        );
+        assert_eq!(
+            subject.answer("{\"message_type\":\"aliases\",\"payload\":{\"nonce\":\"\"}}"),
+            Err(String::from("expected a challenge, got 'aliases'"))
+        );
+    }
+
+    #[test]
+    fn proofs_depend_on_the_token_and_the_side() {
+        let subject = TokenKey::new("correct horse battery staple");
+        let nonce = [7u8; NONCE_LENGTH];
+
+        let proof = subject.ui_proof(&nonce);
+
+        assert_eq!(subject.verify_ui_proof(&nonce, &proof), true);
+        assert_eq!(subject.verify_ui_proof(&[8u8; NONCE_LENGTH], &proof), false);
+        assert_eq!(
+            TokenKey::new("wrong horse battery staple").verify_ui_proof(&nonce, &proof),
+            false
+        );
+        assert_eq!(
+            subject.verify_ui_proof(&nonce, &subject.node_proof(&nonce)),
+            false
+        );
+        assert_eq!(subject.verify_ui_proof(&nonce, &proof[1..]), false);
+    }
+
+    #[test]
+    fn an_address_is_locked_out_after_too_many_failures_until_the_period_passes() {
+        let now = Instant::now();
+        let mut subject = Lockout::new(3, Duration::from_secs(60));
+        subject.record_failure(ip(), now);
+        subject.record_failure(ip(), now);
+        assert_eq!(subject.is_locked_out(ip(), now), false);
+
+        subject.record_failure(ip(), now + Duration::from_secs(10));
+
+        assert_eq!(
+            subject.is_locked_out(ip(), now + Duration::from_secs(69)),
+            true
+        );
+        assert_eq!(
+            subject.is_locked_out(IpAddr::from_str("4.3.2.1").unwrap(), now),
+            false
+        );
+        assert_eq!(
+            subject.is_locked_out(ip(), now + Duration::from_secs(70)),
+            false
+        );
+    }
+
+    #[test]
+    fn success_and_time_both_wipe_out_failures() {
+        let now = Instant::now();
+        let mut subject = Lockout::new(2, Duration::from_secs(60));
+        subject.record_failure(ip(), now);
+        subject.record_success(ip());
+        subject.record_failure(ip(), now);
+        assert_eq!(subject.is_locked_out(ip(), now), false);
+
+        subject.record_failure(ip(), now + Duration::from_secs(60));
+
+        assert_eq!(
+            subject.is_locked_out(ip(), now + Duration::from_secs(60)),
+            false
+        );
+    }
+
+    #[test]
+    fn a_ui_that_knows_the_token_is_answered_with_the_nodes_proof() {
+        let mut subject = RemoteAccess::new("correct horse battery staple");
+        let ui_key = TokenKey::new("correct horse battery staple");
+        let node_nonce = challenge_nonce(&subject.challenge(1234));
+        let ui_nonce = [3u8; NONCE_LENGTH];
+        assert_eq!(subject.is_awaiting(1234), true);
+
+        let result = subject.authenticate(
+            1234,
+            ip(),
+            &auth_json(&ui_key.ui_proof(&node_nonce), &ui_nonce),
+            Instant::now(),
+        );
+
+        let value: serde_json::Value = serde_json::from_str(&result.unwrap()).unwrap();
+        assert_eq!(value["message_type"], "authenticated");
+        assert_eq!(
+            base64::decode(value["payload"]["proof"].as_str().unwrap()).unwrap(),
+            ui_key.node_proof(&ui_nonce)
+        );
+        assert_eq!(subject.is_awaiting(1234), false);
+    }
+
+    #[test]
+    fn a_ui_that_fails_to_authenticate_counts_toward_a_lockout() {
+        let now = Instant::now();
+        let mut subject = RemoteAccess::new("correct horse battery staple");
+        let wrong_key = TokenKey::new("wrong horse battery staple");
+
+        let results: Vec<Result<String, String>> = (0..MAX_FAILED_ATTEMPTS as u64)
+            .map(|client_id| {
+                let node_nonce = challenge_nonce(&subject.challenge(client_id));
+                subject.authenticate(
+                    client_id,
+                    ip(),
+                    &auth_json(&wrong_key.ui_proof(&node_nonce), &[3u8; NONCE_LENGTH]),
+                    now,
+                )
+            })
+            .collect();
+
+        assert_eq!(
+            results,
+            vec![Err(String::from("wrong token")); MAX_FAILED_ATTEMPTS]
+        );
+        assert_eq!(subject.is_locked_out(ip(), now), true);
+    }
+
+    #[test]
+    fn malformed_authentication_is_a_failure() {
+        let now = Instant::now();
+        let key = TokenKey::new("correct horse battery staple");
+        let mut subject = RemoteAccess::new("correct horse battery staple");
+        let mut attempt = |message: &dyn Fn(&[u8]) -> String| {
+            let node_nonce = challenge_nonce(&subject.challenge(1));
+            subject.authenticate(1, ip(), &message(&node_nonce), now)
+        };
+
+        assert_eq!(
+            attempt(&|_| String::from(
+                "{\"message_type\":\"aliases\",\"payload\":{\"proof\":\"\",\"nonce\":\"\"}}"
+            )),
+            Err(String::from("expected authentication, got 'aliases'"))
+        );
+        assert_eq!(
+            attempt(&|nonce| auth_json(&key.ui_proof(nonce), &[3u8; 8])),
+            Err(String::from("nonce is 8 bytes, not 32"))
+        );
+        assert_eq!(
+            attempt(&|_| String::from("booga"))
+                .unwrap_err()
+                .starts_with("expected authentication, got 'booga': "),
+            true
+        );
+    }
+}
@@ -1,4 +1,6 @@
 // Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use crate::firewall::FirewallManager;
+use std::path::PathBuf;
 
 trait Signaler {
     fn sigterm(&self);
@@ -24,19 +26,24 @@ pub trait ShutdownSupervisor {
 
 pub struct ShutdownSupervisorReal {
     signaler: Box<dyn Signaler>,
+    firewall_directory: Option<PathBuf>,
 }
 
 impl ShutdownSupervisor for ShutdownSupervisorReal {
     fn shutdown(&self) {
-        // TODO: Additional graceful-shutdown code should go here
+        // By now the UiGateway has seen every actor through its own shutdown
+        if let Some(ref firewall_directory) = self.firewall_directory {
+            FirewallManager::new(firewall_directory).close_recorded();
+        }
         self.signaler.sigterm();
     }
 }
 
 impl ShutdownSupervisorReal {
-    pub fn new() -> ShutdownSupervisorReal {
+    pub fn new(firewall_directory: Option<PathBuf>) -> ShutdownSupervisorReal {
         ShutdownSupervisorReal {
             signaler: Box::new(SignalerReal::new()),
+            firewall_directory,
         }
     }
 }
@@ -72,7 +79,7 @@ mod tests {
 
     #[test]
     fn shutdown_demand_triggers_signaler() {
-        let mut subject = ShutdownSupervisorReal::new();
+        let mut subject = ShutdownSupervisorReal::new(None);
         let mut sigterm_parameters = Arc::new(Mutex::new(vec![]));
         let signaler = SignalerMock::new().sigterm_parameters(&mut sigterm_parameters);
         subject.signaler = Box::new(signaler);
@@ -1,41 +1,323 @@
 // Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use crate::sub_lib::accountant::BalancesQueryMessage;
+use crate::sub_lib::logger;
 use crate::sub_lib::logger::Logger;
+use crate::sub_lib::neighborhood::NeighborhoodStatusQueryMessage;
+use crate::sub_lib::neighborhood::RemoveNeighborMessage;
+use crate::sub_lib::neighborhood::TopologyPage;
+use crate::sub_lib::neighborhood::TopologyQueryMessage;
 use crate::sub_lib::peer_actors::BindMessage;
+use crate::sub_lib::peer_actors::ShutdownMessage;
+use crate::sub_lib::peer_actors::StreamCountQueryMessage;
+use crate::sub_lib::proxy_server::AdaptiveHopsMessage;
 use crate::sub_lib::ui_gateway::FromUiMessage;
+use crate::sub_lib::ui_gateway::NodeStatus;
+use crate::sub_lib::ui_gateway::ReloadConfigMessage;
+use crate::sub_lib::ui_gateway::UiAlert;
 use crate::sub_lib::ui_gateway::UiGatewayConfig;
 use crate::sub_lib::ui_gateway::UiGatewaySubs;
 use crate::sub_lib::ui_gateway::UiMessage;
+use crate::sub_lib::ui_gateway::UiRemoteAccessConfig;
+use crate::sub_lib::ui_gateway::UI_TOKEN_FILE;
+use crate::sub_lib::utils::or_default;
+use crate::ui_gateway::alias_store::AliasStore;
+use crate::ui_gateway::message_catalog;
+use crate::ui_gateway::remote_access;
 use crate::ui_gateway::shutdown_supervisor::ShutdownSupervisor;
 use crate::ui_gateway::shutdown_supervisor::ShutdownSupervisorReal;
 use crate::ui_gateway::ui_traffic_converter::UiTrafficConverter;
 use crate::ui_gateway::ui_traffic_converter::UiTrafficConverterReal;
+use crate::ui_gateway::ui_traffic_converter::BROADCAST;
 use crate::ui_gateway::websocket_supervisor::WebSocketSupervisor;
 use crate::ui_gateway::websocket_supervisor::WebSocketSupervisorReal;
+use actix::actors::signal::ProcessSignals;
+use actix::actors::signal::Signal;
+use actix::actors::signal::SignalType;
+use actix::actors::signal::Subscribe;
 use actix::Actor;
 use actix::Addr;
+use actix::Arbiter;
+use actix::AsyncContext;
 use actix::Context;
 use actix::Handler;
+use actix::Message;
 use actix::Recipient;
 use actix::Syn;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+use tokio;
+use tokio::prelude::future;
+use tokio::prelude::Future;
+
+// Once the last actor has finished shutting down, how long its final packets have to get out
+pub const SHUTDOWN_LINGER: Duration = Duration::from_millis(500);
+// The Node goes down after this long even if some actor never finishes shutting down
+pub const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Message)]
+struct ShutdownStage {
+    index: usize,
+}
+
+#[derive(Message)]
+struct StatusReport {
+    client_id: u64,
+    request_id: u64,
+    status: NodeStatus,
+}
+
+#[derive(Message)]
+struct TopologyReport {
+    client_id: u64,
+    request_id: u64,
+    page: TopologyPage,
+}
+
+// How a UI proves it may give the Node orders
+enum UiAccess {
+    // Over TLS, before it hears or says anything
+    Remote(UiRemoteAccessConfig),
+    // On localhost, before it hears or says anything
+    Token(String),
+    // On localhost, whenever it likes; until it does, it can only listen and ask
+    ControlToken(String),
+}
+
+// The actors that know the parts of a status report
+#[derive(Clone)]
+struct StatusSources {
+    neighborhood: Recipient<Syn, NeighborhoodStatusQueryMessage>,
+    proxy_server: Recipient<Syn, StreamCountQueryMessage>,
+    proxy_client: Recipient<Syn, StreamCountQueryMessage>,
+    accountant: Recipient<Syn, BalancesQueryMessage>,
+}
 
 pub struct UiGateway {
     port: u16,
+    access: UiAccess,
     converter: Box<dyn UiTrafficConverter>,
     ui_message_sub: Option<Recipient<Syn, UiMessage>>,
+    reload_config_subs: Vec<Recipient<Syn, ReloadConfigMessage>>,
+    adaptive_hops_sub: Option<Recipient<Syn, AdaptiveHopsMessage>>,
+    remove_neighbor_sub: Option<Recipient<Syn, RemoveNeighborMessage>>,
+    status_sources: Option<StatusSources>,
+    topology_sub: Option<Recipient<Syn, TopologyQueryMessage>>,
+    node_descriptor: String,
     websocket_supervisor: Option<Box<dyn WebSocketSupervisor>>,
     shutdown_supervisor: Box<dyn ShutdownSupervisor>,
+    // Every actor in a stage is told to shut down only once every actor in the stage before it has
+    // finished: streams are closed before the Node leaves the Neighborhood, and the books are
+    // closed last so that they hold everything the others reported on the way out.
+    shutdown_stages: Vec<Vec<Recipient<Syn, ShutdownMessage>>>,
+    shutdown_started: bool,
+    shutdown_finished: bool,
+    shutdown_linger: Duration,
+    shutdown_timeout: Duration,
+    handles_signals: bool,
+    greeting: Option<String>,
+    aliases: AliasStore,
+    // Alerts raised before any UI could have connected, so every UI that connects hears them
+    startup_alerts: Vec<UiAlert>,
     logger: Logger,
 }
 
 impl UiGateway {
     pub fn new(config: &UiGatewayConfig) -> UiGateway {
+        let logger = Logger::new("UiGateway");
+        let mut startup_alerts = vec![];
+        // An alias file that can't be read is left alone, rather than overwritten with new aliases
+        let aliases = match &config.alias_directory {
+            Some(directory) => AliasStore::load(directory).unwrap_or_else(|e| {
+                logger.warning(format!(
+                    "Aliases won't be kept past shutdown: {}",
+                    message_catalog::render(&e)
+                ));
+                startup_alerts.push(e);
+                AliasStore::in_memory()
+            }),
+            None => AliasStore::in_memory(),
+        };
+        let access = match (&config.remote_access, &config.local_token) {
+            (Some(remote_access), _) => UiAccess::Remote(remote_access.clone()),
+            (None, Some(token)) => UiAccess::Token(token.clone()),
+            (None, None) => {
+                let token = remote_access::generate_token();
+                if let Some(directory) = &config.token_directory {
+                    if let Err(e) = UiGateway::write_token(directory, &token) {
+                        logger.warning(message_catalog::render(&e));
+                        startup_alerts.push(e);
+                    }
+                }
+                UiAccess::ControlToken(token)
+            }
+        };
         UiGateway {
             port: config.ui_port,
+            access,
             converter: Box::new(UiTrafficConverterReal::new()),
             ui_message_sub: None,
+            reload_config_subs: vec![],
+            adaptive_hops_sub: None,
+            remove_neighbor_sub: None,
+            status_sources: None,
+            topology_sub: None,
+            node_descriptor: config
+                .startup_summary
+                .as_ref()
+                .map(|summary| summary.node_descriptor.clone())
+                .unwrap_or_default(),
             websocket_supervisor: None,
-            shutdown_supervisor: Box::new(ShutdownSupervisorReal::new()),
-            logger: Logger::new("UiGateway"),
+            shutdown_supervisor: Box::new(ShutdownSupervisorReal::new(
+                config.firewall_directory.clone(),
+            )),
+            shutdown_stages: vec![],
+            shutdown_started: false,
+            shutdown_finished: false,
+            shutdown_linger: SHUTDOWN_LINGER,
+            shutdown_timeout: SHUTDOWN_TIMEOUT,
+            handles_signals: false,
+            greeting: config
+                .startup_summary
+                .as_ref()
+                .map(|summary| summary.to_ui_json()),
+            aliases,
+            startup_alerts,
+            logger,
+        }
+    }
+
+    // SIGTERM and SIGINT shut the Node down the same way a UI can
+    pub fn handle_signals(&mut self) {
+        self.handles_signals = true;
+    }
+
+    fn start_shutdown(&mut self, ctx: &mut Context<Self>) {
+        if self.shutdown_started {
+            return;
+        }
+        self.shutdown_started = true;
+        ctx.run_later(self.shutdown_timeout, |act, _ctx| {
+            if !act.shutdown_finished {
+                act.logger.warning(format!(
+                    "Shutdown took longer than {:?}; going down anyway",
+                    act.shutdown_timeout
+                ));
+                act.finish_shutdown();
+            }
+        });
+        ctx.notify(ShutdownStage { index: 0 });
+    }
+
+    fn finish_shutdown(&mut self) {
+        if self.shutdown_finished {
+            return;
+        }
+        self.shutdown_finished = true;
+        self.logger.info(String::from("Shutdown complete"));
+        self.shutdown_supervisor.shutdown();
+    }
+
+    fn greetings(&self) -> Vec<String> {
+        self.greeting
+            .iter()
+            .cloned()
+            .chain(vec![
+                message_catalog::catalog_to_ui_json(),
+                self.aliases.to_ui_json(),
+            ])
+            .chain(self.startup_alerts.iter().map(|alert| alert.to_ui_json()))
+            .collect()
+    }
+
+    // Readable only by the user the Node runs as, and by root
+    fn write_token(directory: &Path, token: &str) -> Result<(), UiAlert> {
+        let path = directory.join(UI_TOKEN_FILE);
+        let unwritable = |e: std::io::Error| {
+            UiAlert::new("unwritable_ui_token")
+                .with("path", &format!("{:?}", path))
+                .with("error", &format!("{}", e))
+        };
+        fs::write(&path, token).map_err(unwritable)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).map_err(unwritable)?;
+        }
+        Ok(())
+    }
+
+    fn alert(&self, context: &str, alert: UiAlert) {
+        self.alert_ui(BROADCAST, context, alert)
+    }
+
+    // For something only one UI needs to hear about, like a mistake in what it sent
+    fn alert_ui(&self, client_id: u64, context: &str, alert: UiAlert) {
+        self.logger
+            .warning(format!("{}{}", context, message_catalog::render(&alert)));
+        if let Some(websocket_supervisor) = &self.websocket_supervisor {
+            websocket_supervisor.receive(client_id, &alert.to_ui_json());
+        }
+    }
+
+    // Every actor is asked at once, and the UI that asked hears the answer once they've all replied.
+    // An actor that can't answer leaves its part of the report at zero rather than holding up the
+    // rest.
+    fn report_status(&self, client_id: u64, request_id: u64, ctx: &mut Context<Self>) {
+        let sources = self.status_sources.as_ref().expect("UiGateway is unbound");
+        let node_descriptor = self.node_descriptor.clone();
+        let addr: Addr<Syn, UiGateway> = ctx.address();
+        tokio::spawn(
+            or_default(sources.neighborhood.send(NeighborhoodStatusQueryMessage {}))
+                .join4(
+                    or_default(sources.proxy_server.send(StreamCountQueryMessage {})),
+                    or_default(sources.proxy_client.send(StreamCountQueryMessage {})),
+                    or_default(sources.accountant.send(BalancesQueryMessage {})),
+                )
+                .map(
+                    move |(neighborhood, originating_streams, exit_streams, balances)| {
+                        addr.try_send(StatusReport {
+                            client_id,
+                            request_id,
+                            status: NodeStatus {
+                                node_descriptor,
+                                neighbors: neighborhood.neighbors,
+                                known_nodes: neighborhood.known_nodes,
+                                originating_streams,
+                                exit_streams,
+                                receivable_balance: balances.receivable,
+                                payable_balance: balances.payable,
+                            },
+                        })
+                        .expect("UiGateway is dead")
+                    },
+                ),
+        );
+    }
+
+    // A Neighborhood that can't answer leaves the page empty, with no cursor to a next one
+    fn report_topology(
+        &self,
+        client_id: u64,
+        request_id: u64,
+        query: TopologyQueryMessage,
+        ctx: &mut Context<Self>,
+    ) {
+        let topology_sub = self.topology_sub.as_ref().expect("UiGateway is unbound");
+        let addr: Addr<Syn, UiGateway> = ctx.address();
+        tokio::spawn(or_default(topology_sub.send(query)).map(move |page| {
+            addr.try_send(TopologyReport {
+                client_id,
+                request_id,
+                page,
+            })
+            .expect("UiGateway is dead")
+        }));
+    }
+
+    fn update_greetings(&self) {
+        if let Some(websocket_supervisor) = &self.websocket_supervisor {
+            websocket_supervisor.set_greetings(self.greetings());
         }
     }
 
@@ -55,13 +337,57 @@ impl Actor for UiGateway {
 impl Handler<BindMessage> for UiGateway {
     type Result = ();
 
-    fn handle(&mut self, msg: BindMessage, _ctx: &mut Self::Context) -> Self::Result {
+    fn handle(&mut self, msg: BindMessage, ctx: &mut Self::Context) -> Self::Result {
         //        ctx.set_mailbox_capacity(?);
         self.ui_message_sub = Some(msg.peer_actors.ui_gateway.ui_message_sub.clone());
-        self.websocket_supervisor = Some(Box::new(WebSocketSupervisorReal::new(
-            self.port,
-            msg.peer_actors.ui_gateway.from_ui_message_sub.clone(),
-        )));
+        self.shutdown_stages = vec![
+            vec![
+                msg.peer_actors.proxy_server.shutdown.clone(),
+                msg.peer_actors.proxy_client.shutdown.clone(),
+            ],
+            vec![msg.peer_actors.neighborhood.shutdown.clone()],
+            vec![msg.peer_actors.accountant.shutdown.clone()],
+        ];
+        if self.handles_signals {
+            let addr: Addr<Syn, UiGateway> = ctx.address();
+            Arbiter::system_registry()
+                .get::<ProcessSignals>()
+                .do_send(Subscribe(addr.recipient::<Signal>()));
+        }
+        self.reload_config_subs = vec![
+            msg.peer_actors.proxy_server.reload_config.clone(),
+            msg.peer_actors.neighborhood.reload_config.clone(),
+        ];
+        self.adaptive_hops_sub = Some(msg.peer_actors.proxy_server.adaptive_hops.clone());
+        self.remove_neighbor_sub = Some(msg.peer_actors.neighborhood.remove_neighbor.clone());
+        self.status_sources = Some(StatusSources {
+            neighborhood: msg.peer_actors.neighborhood.status_query.clone(),
+            proxy_server: msg.peer_actors.proxy_server.stream_count_query.clone(),
+            proxy_client: msg.peer_actors.proxy_client.stream_count_query.clone(),
+            accountant: msg.peer_actors.accountant.balances_query.clone(),
+        });
+        self.topology_sub = Some(msg.peer_actors.neighborhood.topology_query.clone());
+        let from_ui_message_sub = msg.peer_actors.ui_gateway.from_ui_message_sub.clone();
+        self.websocket_supervisor = Some(Box::new(match &self.access {
+            UiAccess::Remote(remote_access) => WebSocketSupervisorReal::new_remote(
+                self.port,
+                from_ui_message_sub,
+                self.greetings(),
+                remote_access,
+            ),
+            UiAccess::Token(token) => WebSocketSupervisorReal::new_with_token(
+                self.port,
+                from_ui_message_sub,
+                self.greetings(),
+                token,
+            ),
+            UiAccess::ControlToken(token) => WebSocketSupervisorReal::new_with_control_token(
+                self.port,
+                from_ui_message_sub,
+                self.greetings(),
+                token,
+            ),
+        }));
         ()
     }
 }
@@ -70,10 +396,149 @@ impl Handler<UiMessage> for UiGateway {
     type Result = ();
 
     // All UI messages, both inbound and outbound, come through here
-    fn handle(&mut self, _msg: UiMessage, _ctx: &mut Self::Context) -> Self::Result {
-        // TODO: The assumption here is that every UiMessage means, "Shut down!" This assumption is only temporarily valid.
-        self.logger.info(String::from("Received shutdown order"));
-        self.shutdown_supervisor.shutdown();
+    fn handle(&mut self, msg: UiMessage, ctx: &mut Self::Context) -> Self::Result {
+        match msg {
+            UiMessage::ShutdownMessage => {
+                self.logger.info(String::from("Received shutdown order"));
+                self.start_shutdown(ctx);
+            }
+            // Connections and streams are left alone; only the actors that read files at startup
+            // are told to read them again
+            UiMessage::ReloadMessage => {
+                self.logger.info(String::from("Received reload order"));
+                self.reload_config_subs.iter().for_each(|sub| {
+                    sub.try_send(ReloadConfigMessage {})
+                        .expect("Reloading actor is dead")
+                });
+            }
+            // The label itself isn't logged, since logs tend to be passed around
+            UiMessage::SetAliasMessage { key, label } => match self.aliases.set(&key, &label) {
+                Ok(()) => {
+                    self.logger.info(format!("Set alias for {}", key));
+                    self.update_greetings();
+                }
+                Err(e) => self.alert("Couldn't set alias: ", e),
+            },
+            UiMessage::RemoveAliasMessage { key } => match self.aliases.remove(&key) {
+                Ok(Some(_)) => {
+                    self.logger.info(format!("Removed alias for {}", key));
+                    self.update_greetings();
+                }
+                Ok(None) => self.logger.info(format!("No alias for {} to remove", key)),
+                Err(e) => self.alert("Couldn't remove alias: ", e),
+            },
+            UiMessage::SetAdaptiveHopsMessage { enabled } => {
+                self.logger.info(format!(
+                    "Received order to turn adaptive hop count {}",
+                    if enabled { "on" } else { "off" }
+                ));
+                self.adaptive_hops_sub
+                    .as_ref()
+                    .expect("UiGateway is unbound")
+                    .try_send(AdaptiveHopsMessage { enabled })
+                    .expect("ProxyServer is dead");
+            }
+            UiMessage::StatusQueryMessage {
+                client_id,
+                request_id,
+            } => self.report_status(client_id, request_id, ctx),
+            UiMessage::TopologyQueryMessage {
+                client_id,
+                request_id,
+                query,
+            } => self.report_topology(client_id, request_id, query, ctx),
+            // Logged first, since the new level may be too quiet to say so afterward
+            UiMessage::SetLogLevelMessage { level } => {
+                self.logger
+                    .info(format!("Received order to set log level to {}", level));
+                if !logger::set_log_level(level) {
+                    self.alert(
+                        "Couldn't set log level: ",
+                        UiAlert::new("log_level_unchangeable"),
+                    );
+                }
+            }
+            UiMessage::RemoveNeighborMessage { public_key } => {
+                self.logger
+                    .info(format!("Received order to remove neighbor {}", public_key));
+                self.remove_neighbor_sub
+                    .as_ref()
+                    .expect("UiGateway is unbound")
+                    .try_send(RemoveNeighborMessage { public_key })
+                    .expect("Neighborhood is dead");
+            }
+            UiMessage::AlertMessage(alert) => self.alert("", alert),
+        }
+        ()
+    }
+}
+
+impl Handler<Signal> for UiGateway {
+    type Result = ();
+
+    fn handle(&mut self, msg: Signal, ctx: &mut Self::Context) -> Self::Result {
+        let name = match msg.0 {
+            SignalType::Term => "SIGTERM",
+            SignalType::Int => "SIGINT",
+            _ => return (),
+        };
+        self.logger
+            .info(format!("Received {}: shutting down", name));
+        self.start_shutdown(ctx);
+        ()
+    }
+}
+
+// Once every actor in the stage has finished shutting down, the next stage starts. After the last
+// one, outgoing packets get a moment to leave before the process exits.
+impl Handler<ShutdownStage> for UiGateway {
+    type Result = ();
+
+    fn handle(&mut self, msg: ShutdownStage, ctx: &mut Self::Context) -> Self::Result {
+        let stage = match self.shutdown_stages.get(msg.index) {
+            Some(stage) => stage.clone(),
+            None => {
+                ctx.run_later(self.shutdown_linger, |act, _ctx| act.finish_shutdown());
+                return ();
+            }
+        };
+        let addr: Addr<Syn, UiGateway> = ctx.address();
+        let next_index = msg.index + 1;
+        tokio::spawn(
+            future::join_all(
+                stage
+                    .iter()
+                    .map(|sub| sub.send(ShutdownMessage {}))
+                    .collect::<Vec<_>>(),
+            )
+            .then(move |_| {
+                addr.try_send(ShutdownStage { index: next_index })
+                    .expect("UiGateway is dead");
+                Ok(())
+            }),
+        );
+        ()
+    }
+}
+
+impl Handler<StatusReport> for UiGateway {
+    type Result = ();
+
+    fn handle(&mut self, msg: StatusReport, _ctx: &mut Self::Context) -> Self::Result {
+        if let Some(websocket_supervisor) = &self.websocket_supervisor {
+            websocket_supervisor.receive(msg.client_id, &msg.status.to_ui_json(msg.request_id));
+        }
+        ()
+    }
+}
+
+impl Handler<TopologyReport> for UiGateway {
+    type Result = ();
+
+    fn handle(&mut self, msg: TopologyReport, _ctx: &mut Self::Context) -> Self::Result {
+        if let Some(websocket_supervisor) = &self.websocket_supervisor {
+            websocket_supervisor.receive(msg.client_id, &msg.page.to_ui_json(msg.request_id));
+        }
         ()
     }
 }
@@ -83,11 +548,18 @@ impl Handler<FromUiMessage> for UiGateway {
 
     // JSON messages from external UIs come in here, are translated to UiMessages, and sent to the handler above
     fn handle(&mut self, msg: FromUiMessage, _ctx: &mut Self::Context) -> Self::Result {
-        match self.converter.unmarshal(&msg.json) {
-            Err(e) => self.logger.warning(format!(
-                "Error unmarshalling message from UI - ignoring: '{}'",
-                e
-            )),
+        match self.converter.unmarshal(msg.client_id, &msg.json) {
+            Err(e) => self.alert_ui(
+                msg.client_id,
+                "Error unmarshalling message from UI - ignoring: ",
+                e,
+            ),
+            // What the order was isn't logged, since an alias order carries the label
+            Ok(ref ui_message) if ui_message.is_order() && !msg.authenticated => self.alert_ui(
+                msg.client_id,
+                &format!("UI {} hasn't authenticated: ", msg.client_id),
+                UiAlert::new("unauthenticated_order"),
+            ),
             Ok(ui_message) => self
                 .ui_message_sub
                 .as_ref()
@@ -102,15 +574,23 @@ impl Handler<FromUiMessage> for UiGateway {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::sub_lib::cryptde::PublicKey;
     use crate::sub_lib::ui_gateway::DEFAULT_UI_PORT;
     use crate::test_utils::logging::init_test_logging;
     use crate::test_utils::logging::TestLogHandler;
     use crate::test_utils::recorder::make_recorder;
     use crate::test_utils::recorder::peer_actors_builder;
+    use crate::test_utils::recorder::PeerActorsBuilder;
+    use crate::test_utils::recorder::Recorder;
     use crate::test_utils::test_utils::find_free_port;
     use crate::test_utils::test_utils::wait_for;
+    use crate::ui_gateway::alias_store::ALIAS_FILE;
+    use actix::msgs;
     use actix::System;
+    use log::LevelFilter;
     use std::cell::RefCell;
+    use std::fs;
+    use std::path::PathBuf;
     use std::sync::Arc;
     use std::sync::Mutex;
     use std::thread;
@@ -118,8 +598,8 @@ mod tests {
     pub struct UiTrafficConverterMock {
         marshal_parameters: Arc<Mutex<Vec<UiMessage>>>,
         marshal_results: RefCell<Vec<Result<String, String>>>,
-        unmarshal_parameters: Arc<Mutex<Vec<String>>>,
-        unmarshal_results: RefCell<Vec<Result<UiMessage, String>>>,
+        unmarshal_parameters: Arc<Mutex<Vec<(u64, String)>>>,
+        unmarshal_results: RefCell<Vec<Result<UiMessage, UiAlert>>>,
     }
 
     impl UiTrafficConverter for UiTrafficConverterMock {
@@ -128,11 +608,11 @@ mod tests {
             self.marshal_results.borrow_mut().remove(0)
         }
 
-        fn unmarshal(&self, json: &str) -> Result<UiMessage, String> {
+        fn unmarshal(&self, client_id: u64, json: &str) -> Result<UiMessage, UiAlert> {
             self.unmarshal_parameters
                 .lock()
                 .unwrap()
-                .push(String::from(json));
+                .push((client_id, String::from(json)));
             self.unmarshal_results.borrow_mut().remove(0)
         }
     }
@@ -164,13 +644,13 @@ mod tests {
 
         fn unmarshal_parameters(
             mut self,
-            parameters: &Arc<Mutex<Vec<String>>>,
+            parameters: &Arc<Mutex<Vec<(u64, String)>>>,
         ) -> UiTrafficConverterMock {
             self.unmarshal_parameters = parameters.clone();
             self
         }
 
-        fn unmarshal_result(self, result: Result<UiMessage, String>) -> UiTrafficConverterMock {
+        fn unmarshal_result(self, result: Result<UiMessage, UiAlert>) -> UiTrafficConverterMock {
             self.unmarshal_results.borrow_mut().push(result);
             self
         }
@@ -178,8 +658,8 @@ mod tests {
 
     #[derive(Default)]
     struct WebSocketSupervisorMock {
-        receive_results: RefCell<Vec<()>>,
         receive_parameters: Arc<Mutex<Vec<(u64, String)>>>,
+        set_greetings_parameters: Arc<Mutex<Vec<Vec<String>>>>,
     }
 
     impl WebSocketSupervisor for WebSocketSupervisorMock {
@@ -188,7 +668,13 @@ mod tests {
                 .lock()
                 .unwrap()
                 .push((client_id, String::from(message_json)));
-            self.receive_results.borrow_mut().remove(0)
+        }
+
+        fn set_greetings(&self, greetings: Vec<String>) {
+            self.set_greetings_parameters
+                .lock()
+                .unwrap()
+                .push(greetings);
         }
     }
 
@@ -197,6 +683,22 @@ mod tests {
         fn new() -> WebSocketSupervisorMock {
             Default::default()
         }
+
+        fn receive_parameters(
+            mut self,
+            parameters: &Arc<Mutex<Vec<(u64, String)>>>,
+        ) -> WebSocketSupervisorMock {
+            self.receive_parameters = parameters.clone();
+            self
+        }
+
+        fn set_greetings_parameters(
+            mut self,
+            parameters: &Arc<Mutex<Vec<Vec<String>>>>,
+        ) -> WebSocketSupervisorMock {
+            self.set_greetings_parameters = parameters.clone();
+            self
+        }
     }
 
     pub struct ShutdownSupervisorMock {
@@ -226,31 +728,389 @@ mod tests {
     }
 
     #[test]
-    fn receiving_a_shutdown_message_triggers_the_shutdown_supervisor() {
+    fn constants_have_correct_values() {
+        assert_eq!(SHUTDOWN_LINGER, Duration::from_millis(500));
+        assert_eq!(SHUTDOWN_TIMEOUT, Duration::from_secs(10));
+    }
+
+    fn shutdown_outcome<F>(
+        name: &'static str,
+        peer_actors_builder: PeerActorsBuilder,
+        trigger: F,
+    ) -> Arc<Mutex<Vec<()>>>
+    where
+        F: FnOnce(&Addr<Syn, UiGateway>) + Send + 'static,
+    {
         let shutdown_parameters = Arc::new(Mutex::new(vec![]));
         let shutdown_parameters_inside = shutdown_parameters.clone();
-
         thread::spawn(move || {
             let supervisor =
                 ShutdownSupervisorMock::new().shutdown_parameters(&shutdown_parameters_inside);
             let mut subject = UiGateway::new(&UiGatewayConfig {
                 ui_port: find_free_port(),
+                startup_summary: None,
+                firewall_directory: None,
+                alias_directory: None,
+                remote_access: None,
+                local_token: None,
+                token_directory: None,
             });
             subject.shutdown_supervisor = Box::new(supervisor);
-            let system =
-                System::new("receiving_a_shutdown_message_triggers_the_shutdown_supervisor");
+            subject.shutdown_linger = Duration::from_millis(10);
+            let system = System::new(name);
             let addr: Addr<Syn, UiGateway> = subject.start();
-            let mut peer_actors = peer_actors_builder().build();
+            let mut peer_actors = peer_actors_builder.build();
             peer_actors.ui_gateway = UiGateway::make_subs_from(&addr);
             addr.try_send(BindMessage { peer_actors }).unwrap();
 
-            addr.try_send(UiMessage::ShutdownMessage).unwrap();
+            trigger(&addr);
 
             system.run();
         });
+        shutdown_parameters
+    }
+
+    #[test]
+    fn receiving_a_shutdown_message_triggers_the_shutdown_supervisor() {
+        let shutdown_parameters = shutdown_outcome(
+            "receiving_a_shutdown_message_triggers_the_shutdown_supervisor",
+            peer_actors_builder(),
+            |addr| addr.try_send(UiMessage::ShutdownMessage).unwrap(),
+        );
+
         wait_for(None, None, || shutdown_parameters.lock().unwrap().len() > 0)
     }
 
+    #[test]
+    fn shutdown_tells_every_actor_with_something_to_finish_before_going_down_once() {
+        init_test_logging();
+        let (proxy_server, proxy_server_awaiter, proxy_server_recording_arc) = make_recorder();
+        let (proxy_client, proxy_client_awaiter, proxy_client_recording_arc) = make_recorder();
+        let (neighborhood, neighborhood_awaiter, neighborhood_recording_arc) = make_recorder();
+        let (accountant, accountant_awaiter, accountant_recording_arc) = make_recorder();
+        let peer_actors_builder = peer_actors_builder()
+            .proxy_server(proxy_server)
+            .proxy_client(proxy_client)
+            .neighborhood(neighborhood)
+            .accountant(accountant);
+
+        let shutdown_parameters = shutdown_outcome(
+            "shutdown_tells_every_actor_with_something_to_finish_before_going_down_once",
+            peer_actors_builder,
+            |addr| {
+                addr.try_send(UiMessage::ShutdownMessage).unwrap();
+                addr.try_send(UiMessage::ShutdownMessage).unwrap();
+            },
+        );
+
+        proxy_server_awaiter.await_message_count(1);
+        proxy_client_awaiter.await_message_count(1);
+        neighborhood_awaiter.await_message_count(1);
+        accountant_awaiter.await_message_count(1);
+        wait_for(None, None, || shutdown_parameters.lock().unwrap().len() > 0);
+        thread::sleep(Duration::from_millis(100));
+        vec![
+            proxy_server_recording_arc,
+            proxy_client_recording_arc,
+            neighborhood_recording_arc,
+            accountant_recording_arc,
+        ]
+        .into_iter()
+        .for_each(|recording_arc| {
+            let recording = recording_arc.lock().unwrap();
+            assert_eq!(
+                recording.get_record::<ShutdownMessage>(0),
+                &ShutdownMessage {}
+            );
+            assert_eq!(recording.len(), 1);
+        });
+        assert_eq!(shutdown_parameters.lock().unwrap().len(), 1);
+        TestLogHandler::new().exists_log_containing("INFO: UiGateway: Shutdown complete");
+    }
+
+    #[test]
+    fn sigterm_and_sigint_trigger_the_shutdown_supervisor_but_other_signals_do_not() {
+        init_test_logging();
+        let ignored_parameters = shutdown_outcome(
+            "sigterm_and_sigint_trigger_the_shutdown_supervisor_but_other_signals_do_not_1",
+            peer_actors_builder(),
+            |addr| addr.try_send(Signal(SignalType::Hup)).unwrap(),
+        );
+        let term_parameters = shutdown_outcome(
+            "sigterm_and_sigint_trigger_the_shutdown_supervisor_but_other_signals_do_not_2",
+            peer_actors_builder(),
+            |addr| addr.try_send(Signal(SignalType::Term)).unwrap(),
+        );
+        let int_parameters = shutdown_outcome(
+            "sigterm_and_sigint_trigger_the_shutdown_supervisor_but_other_signals_do_not_3",
+            peer_actors_builder(),
+            |addr| addr.try_send(Signal(SignalType::Int)).unwrap(),
+        );
+
+        wait_for(None, None, || term_parameters.lock().unwrap().len() > 0);
+        wait_for(None, None, || int_parameters.lock().unwrap().len() > 0);
+        assert_eq!(ignored_parameters.lock().unwrap().len(), 0);
+        let tlh = TestLogHandler::new();
+        tlh.exists_log_containing("INFO: UiGateway: Received SIGTERM: shutting down");
+        tlh.exists_log_containing("INFO: UiGateway: Received SIGINT: shutting down");
+    }
+
+    #[test]
+    fn shutdown_goes_ahead_when_an_actor_never_finishes() {
+        init_test_logging();
+        let shutdown_parameters = Arc::new(Mutex::new(vec![]));
+        let supervisor = ShutdownSupervisorMock::new().shutdown_parameters(&shutdown_parameters);
+        let mut subject = UiGateway::new(&UiGatewayConfig {
+            ui_port: find_free_port(),
+            startup_summary: None,
+            firewall_directory: None,
+            alias_directory: None,
+            remote_access: None,
+            local_token: None,
+            token_directory: None,
+        });
+        subject.shutdown_supervisor = Box::new(supervisor);
+        subject.shutdown_timeout = Duration::from_millis(10);
+        subject.shutdown_linger = Duration::from_secs(60);
+        let system = System::new("shutdown_goes_ahead_when_an_actor_never_finishes");
+        let addr: Addr<Syn, UiGateway> = subject.start();
+
+        addr.try_send(UiMessage::ShutdownMessage).unwrap();
+
+        let system_addr = Arbiter::system();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(200));
+            system_addr.do_send(msgs::SystemExit(0));
+        });
+        system.run();
+        assert_eq!(shutdown_parameters.lock().unwrap().len(), 1);
+        TestLogHandler::new().exists_log_containing(
+            "WARN: UiGateway: Shutdown took longer than 10ms; going down anyway",
+        );
+    }
+
+    #[test]
+    fn receiving_a_set_adaptive_hops_message_tells_the_proxy_server() {
+        let (proxy_server, proxy_server_awaiter, proxy_server_recording_arc) = make_recorder();
+
+        thread::spawn(move || {
+            let subject = UiGateway::new(&UiGatewayConfig {
+                ui_port: find_free_port(),
+                startup_summary: None,
+                firewall_directory: None,
+                alias_directory: None,
+                remote_access: None,
+                local_token: None,
+                token_directory: None,
+            });
+            let system =
+                System::new("receiving_a_set_adaptive_hops_message_tells_the_proxy_server");
+            let addr: Addr<Syn, UiGateway> = subject.start();
+            let mut peer_actors = peer_actors_builder().proxy_server(proxy_server).build();
+            peer_actors.ui_gateway = UiGateway::make_subs_from(&addr);
+            addr.try_send(BindMessage { peer_actors }).unwrap();
+
+            addr.try_send(UiMessage::SetAdaptiveHopsMessage { enabled: false })
+                .unwrap();
+
+            system.run();
+        });
+        proxy_server_awaiter.await_message_count(1);
+        assert_eq!(
+            proxy_server_recording_arc
+                .lock()
+                .unwrap()
+                .get_record::<AdaptiveHopsMessage>(0),
+            &AdaptiveHopsMessage { enabled: false }
+        );
+    }
+
+    #[test]
+    fn receiving_a_remove_neighbor_message_tells_the_neighborhood() {
+        let (neighborhood, neighborhood_awaiter, neighborhood_recording_arc) = make_recorder();
+
+        thread::spawn(move || {
+            let subject = UiGateway::new(&make_config(None));
+            let system = System::new("receiving_a_remove_neighbor_message_tells_the_neighborhood");
+            let addr: Addr<Syn, UiGateway> = subject.start();
+            let mut peer_actors = peer_actors_builder().neighborhood(neighborhood).build();
+            peer_actors.ui_gateway = UiGateway::make_subs_from(&addr);
+            addr.try_send(BindMessage { peer_actors }).unwrap();
+
+            addr.try_send(UiMessage::RemoveNeighborMessage {
+                public_key: PublicKey::new(&[1, 2, 3, 4]),
+            })
+            .unwrap();
+
+            system.run();
+        });
+        neighborhood_awaiter.await_message_count(1);
+        assert_eq!(
+            neighborhood_recording_arc
+                .lock()
+                .unwrap()
+                .get_record::<RemoveNeighborMessage>(0),
+            &RemoveNeighborMessage {
+                public_key: PublicKey::new(&[1, 2, 3, 4]),
+            }
+        );
+    }
+
+    #[test]
+    fn a_log_level_that_cannot_be_set_is_an_alert() {
+        init_test_logging();
+
+        thread::spawn(move || {
+            let subject = UiGateway::new(&make_config(None));
+            let system = System::new("a_log_level_that_cannot_be_set_is_an_alert");
+            let addr: Addr<Syn, UiGateway> = subject.start();
+
+            addr.try_send(UiMessage::SetLogLevelMessage {
+                level: LevelFilter::Debug,
+            })
+            .unwrap();
+
+            system.run();
+        });
+        let tlh = TestLogHandler::new();
+        tlh.await_log_containing(
+            "WARN: UiGateway: Couldn't set log level: Log level can't be changed: the Node's logger isn't running",
+            1000,
+        );
+        tlh.exists_log_containing("INFO: UiGateway: Received order to set log level to DEBUG");
+    }
+
+    #[test]
+    fn status_query_is_answered_to_the_ui_that_asked_once_every_actor_has_reported() {
+        let receive_parameters = Arc::new(Mutex::new(vec![]));
+        let receive_parameters_inside = receive_parameters.clone();
+        let (neighborhood, _, neighborhood_recording_arc) = make_recorder();
+        let (proxy_server, _, proxy_server_recording_arc) = make_recorder();
+        let (proxy_client, _, proxy_client_recording_arc) = make_recorder();
+        let (accountant, _, accountant_recording_arc) = make_recorder();
+
+        thread::spawn(move || {
+            let system = System::new(
+                "status_query_is_answered_to_the_ui_that_asked_once_every_actor_has_reported",
+            );
+            let peer_actors = peer_actors_builder()
+                .neighborhood(neighborhood)
+                .proxy_server(proxy_server)
+                .proxy_client(proxy_client)
+                .accountant(accountant)
+                .build();
+            let mut subject = UiGateway::new(&make_config(None));
+            subject.node_descriptor = String::from("AQIDBA:1.2.3.4:1234");
+            subject.websocket_supervisor = Some(Box::new(
+                WebSocketSupervisorMock::new().receive_parameters(&receive_parameters_inside),
+            ));
+            subject.status_sources = Some(StatusSources {
+                neighborhood: peer_actors.neighborhood.status_query.clone(),
+                proxy_server: peer_actors.proxy_server.stream_count_query.clone(),
+                proxy_client: peer_actors.proxy_client.stream_count_query.clone(),
+                accountant: peer_actors.accountant.balances_query.clone(),
+            });
+            let addr: Addr<Syn, UiGateway> = subject.start();
+
+            addr.try_send(UiMessage::StatusQueryMessage {
+                client_id: 1234,
+                request_id: 42,
+            })
+            .unwrap();
+
+            system.run();
+        });
+        wait_for(None, None, || receive_parameters.lock().unwrap().len() > 0);
+        assert_eq!(
+            *receive_parameters.lock().unwrap(),
+            vec![(
+                1234,
+                NodeStatus {
+                    node_descriptor: String::from("AQIDBA:1.2.3.4:1234"),
+                    ..NodeStatus::default()
+                }
+                .to_ui_json(42)
+            )]
+        );
+        assert_eq!(
+            neighborhood_recording_arc
+                .lock()
+                .unwrap()
+                .get_record::<NeighborhoodStatusQueryMessage>(0),
+            &NeighborhoodStatusQueryMessage {}
+        );
+        vec![proxy_server_recording_arc, proxy_client_recording_arc]
+            .into_iter()
+            .for_each(|recording_arc| {
+                assert_eq!(
+                    recording_arc
+                        .lock()
+                        .unwrap()
+                        .get_record::<StreamCountQueryMessage>(0),
+                    &StreamCountQueryMessage {}
+                )
+            });
+        assert_eq!(
+            accountant_recording_arc
+                .lock()
+                .unwrap()
+                .get_record::<BalancesQueryMessage>(0),
+            &BalancesQueryMessage {}
+        );
+    }
+
+    #[test]
+    fn receiving_a_reload_message_tells_the_proxy_server_and_neighborhood_to_reload() {
+        let shutdown_parameters = Arc::new(Mutex::new(vec![]));
+        let supervisor = ShutdownSupervisorMock::new().shutdown_parameters(&shutdown_parameters);
+        let (proxy_server, proxy_server_awaiter, proxy_server_recording_arc) = make_recorder();
+        let (neighborhood, neighborhood_awaiter, neighborhood_recording_arc) = make_recorder();
+
+        thread::spawn(move || {
+            let mut subject = UiGateway::new(&UiGatewayConfig {
+                ui_port: find_free_port(),
+                startup_summary: None,
+                firewall_directory: None,
+                alias_directory: None,
+                remote_access: None,
+                local_token: None,
+                token_directory: None,
+            });
+            subject.shutdown_supervisor = Box::new(supervisor);
+            let system = System::new(
+                "receiving_a_reload_message_tells_the_proxy_server_and_neighborhood_to_reload",
+            );
+            let addr: Addr<Syn, UiGateway> = subject.start();
+            let mut peer_actors = peer_actors_builder()
+                .proxy_server(proxy_server)
+                .neighborhood(neighborhood)
+                .build();
+            peer_actors.ui_gateway = UiGateway::make_subs_from(&addr);
+            addr.try_send(BindMessage { peer_actors }).unwrap();
+
+            addr.try_send(UiMessage::ReloadMessage).unwrap();
+
+            system.run();
+        });
+        proxy_server_awaiter.await_message_count(1);
+        neighborhood_awaiter.await_message_count(1);
+        assert_eq!(
+            proxy_server_recording_arc
+                .lock()
+                .unwrap()
+                .get_record::<ReloadConfigMessage>(0),
+            &ReloadConfigMessage {}
+        );
+        assert_eq!(
+            neighborhood_recording_arc
+                .lock()
+                .unwrap()
+                .get_record::<ReloadConfigMessage>(0),
+            &ReloadConfigMessage {}
+        );
+        assert_eq!(shutdown_parameters.lock().unwrap().len(), 0);
+    }
+
     #[test]
     fn good_from_ui_message_is_unmarshalled_and_resent() {
         let unmarshal_parameters = Arc::new(Mutex::new(vec![]));
@@ -262,6 +1122,12 @@ mod tests {
         thread::spawn(move || {
             let mut subject = UiGateway::new(&UiGatewayConfig {
                 ui_port: find_free_port(),
+                startup_summary: None,
+                firewall_directory: None,
+                alias_directory: None,
+                remote_access: None,
+                local_token: None,
+                token_directory: None,
             });
             subject.converter = Box::new(handler);
             let system = System::new("good_from_ui_message_is_unmarshalled_and_resent");
@@ -270,6 +1136,8 @@ mod tests {
             addr.try_send(BindMessage { peer_actors }).unwrap();
 
             addr.try_send(FromUiMessage {
+                client_id: 1234,
+                authenticated: true,
                 json: String::from("pretend I'm JSON"),
             })
             .unwrap();
@@ -280,7 +1148,7 @@ mod tests {
         let unmarshal_parameters_locked = unmarshal_parameters.lock().unwrap();
         assert_eq!(
             unmarshal_parameters_locked[0],
-            String::from("pretend I'm JSON")
+            (1234, String::from("pretend I'm JSON"))
         );
         assert_eq!(unmarshal_parameters_locked.len(), 1);
         let ui_gateway_recording = ui_gateway_recording_arc.lock().unwrap();
@@ -290,24 +1158,86 @@ mod tests {
         );
     }
 
+    #[test]
+    fn topology_query_from_a_ui_is_answered_to_that_ui_with_a_page_from_the_neighborhood() {
+        let receive_parameters = Arc::new(Mutex::new(vec![]));
+        let receive_parameters_inside = receive_parameters.clone();
+        let (neighborhood, _, neighborhood_recording_arc) = make_recorder();
+
+        thread::spawn(move || {
+            let system = System::new(
+                "topology_query_from_a_ui_is_answered_to_that_ui_with_a_page_from_the_neighborhood",
+            );
+            let peer_actors = peer_actors_builder().neighborhood(neighborhood).build();
+            let mut subject = UiGateway::new(&make_config(None));
+            subject.websocket_supervisor = Some(Box::new(
+                WebSocketSupervisorMock::new().receive_parameters(&receive_parameters_inside),
+            ));
+            subject.topology_sub = Some(peer_actors.neighborhood.topology_query.clone());
+            let addr: Addr<Syn, UiGateway> = UiGateway::create(move |ctx| {
+                let self_addr: Addr<Syn, UiGateway> = ctx.address();
+                subject.ui_message_sub = Some(self_addr.recipient::<UiMessage>());
+                subject
+            });
+
+            addr.try_send(FromUiMessage {
+                client_id: 1234,
+                authenticated: false,
+                json: String::from(
+                    r#"{"message_type": "topology", "request_id": 42, "payload": {"scope": "neighbors"}}"#,
+                ),
+            })
+            .unwrap();
+
+            system.run();
+        });
+        wait_for(None, None, || receive_parameters.lock().unwrap().len() > 0);
+        assert_eq!(
+            *receive_parameters.lock().unwrap(),
+            vec![(1234, TopologyPage::default().to_ui_json(42))]
+        );
+        assert_eq!(
+            neighborhood_recording_arc
+                .lock()
+                .unwrap()
+                .get_record::<TopologyQueryMessage>(0),
+            &TopologyQueryMessage::from_json(r#"{"scope": "neighbors"}"#).unwrap()
+        );
+    }
+
     #[test]
     fn bad_from_ui_message_is_logged_and_ignored() {
         init_test_logging();
-        let handler =
-            UiTrafficConverterMock::new().unmarshal_result(Err(String::from("I have a tummyache")));
+        let handler = UiTrafficConverterMock::new()
+            .unmarshal_result(Err(
+                UiAlert::new("missing_message_type").with("json", "I have a tummyache")
+            ));
         let (ui_gateway, _, ui_gateway_recording_arc) = make_recorder();
+        let receive_parameters = Arc::new(Mutex::new(vec![]));
+        let receive_parameters_inside = receive_parameters.clone();
 
         thread::spawn(move || {
             let mut subject = UiGateway::new(&UiGatewayConfig {
                 ui_port: DEFAULT_UI_PORT,
+                startup_summary: None,
+                firewall_directory: None,
+                alias_directory: None,
+                remote_access: None,
+                local_token: None,
+                token_directory: None,
             });
             subject.converter = Box::new(handler);
             let system = System::new("bad_from_ui_message_is_logged_and_ignored");
+            let recorder_addr: Addr<Syn, Recorder> = ui_gateway.start();
+            subject.ui_message_sub = Some(recorder_addr.recipient::<UiMessage>());
+            subject.websocket_supervisor = Some(Box::new(
+                WebSocketSupervisorMock::new().receive_parameters(&receive_parameters_inside),
+            ));
             let addr: Addr<Syn, UiGateway> = subject.start();
-            let peer_actors = peer_actors_builder().ui_gateway(ui_gateway).build();
-            addr.try_send(BindMessage { peer_actors }).unwrap();
 
             addr.try_send(FromUiMessage {
+                client_id: 1234,
+                authenticated: true,
                 json: String::from("pretend I'm JSON"),
             })
             .unwrap();
@@ -315,10 +1245,298 @@ mod tests {
             system.run();
         });
         TestLogHandler::new().await_log_containing(
-            "Error unmarshalling message from UI - ignoring: 'I have a tummyache'",
+            "Error unmarshalling message from UI - ignoring: No message_type in 'I have a tummyache'",
             1000,
         );
+        wait_for(None, None, || receive_parameters.lock().unwrap().len() > 0);
+        assert_eq!(
+            *receive_parameters.lock().unwrap(),
+            vec![(
+                1234,
+                UiAlert::new("missing_message_type")
+                    .with("json", "I have a tummyache")
+                    .to_ui_json()
+            )]
+        );
         let ui_gateway_recording = ui_gateway_recording_arc.lock().unwrap();
         assert_eq!(ui_gateway_recording.len(), 0);
     }
+
+    #[test]
+    fn an_order_from_a_ui_that_has_not_authenticated_is_refused_but_a_query_is_not() {
+        init_test_logging();
+        let handler = UiTrafficConverterMock::new()
+            .unmarshal_result(Ok(UiMessage::ShutdownMessage))
+            .unmarshal_result(Ok(UiMessage::StatusQueryMessage {
+                client_id: 1234,
+                request_id: 42,
+            }));
+        let (ui_gateway, ui_gateway_awaiter, ui_gateway_recording_arc) = make_recorder();
+        let receive_parameters = Arc::new(Mutex::new(vec![]));
+        let receive_parameters_inside = receive_parameters.clone();
+
+        thread::spawn(move || {
+            let mut subject = UiGateway::new(&make_config(None));
+            subject.converter = Box::new(handler);
+            let system = System::new(
+                "an_order_from_a_ui_that_has_not_authenticated_is_refused_but_a_query_is_not",
+            );
+            let recorder_addr: Addr<Syn, Recorder> = ui_gateway.start();
+            subject.ui_message_sub = Some(recorder_addr.recipient::<UiMessage>());
+            subject.websocket_supervisor = Some(Box::new(
+                WebSocketSupervisorMock::new().receive_parameters(&receive_parameters_inside),
+            ));
+            let addr: Addr<Syn, UiGateway> = subject.start();
+
+            vec!["shutdown", "status"].into_iter().for_each(|json| {
+                addr.try_send(FromUiMessage {
+                    client_id: 1234,
+                    authenticated: false,
+                    json: String::from(json),
+                })
+                .unwrap()
+            });
+
+            system.run();
+        });
+        ui_gateway_awaiter.await_message_count(1);
+        wait_for(None, None, || receive_parameters.lock().unwrap().len() > 0);
+        assert_eq!(
+            *receive_parameters.lock().unwrap(),
+            vec![(1234, UiAlert::new("unauthenticated_order").to_ui_json())]
+        );
+        let ui_gateway_recording = ui_gateway_recording_arc.lock().unwrap();
+        assert_eq!(
+            ui_gateway_recording.get_record::<UiMessage>(0),
+            &UiMessage::StatusQueryMessage {
+                client_id: 1234,
+                request_id: 42,
+            }
+        );
+        assert_eq!(ui_gateway_recording.len(), 1);
+        TestLogHandler::new().exists_log_containing(
+            "WARN: UiGateway: UI 1234 hasn't authenticated: Order refused: authenticate with the Node's UI token first",
+        );
+    }
+
+    const BASE_TEST_DIR: &str = "generated/test/ui_gateway";
+
+    fn make_directory(name: &str) -> PathBuf {
+        let directory = PathBuf::from(format!("{}/{}", BASE_TEST_DIR, name));
+        fs::remove_dir_all(&directory).is_ok();
+        fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    #[test]
+    fn a_generated_token_is_left_where_only_the_nodes_user_can_read_it() {
+        let directory =
+            make_directory("a_generated_token_is_left_where_only_the_nodes_user_can_read_it");
+        let mut config = make_config(None);
+        config.token_directory = Some(directory.clone());
+
+        let subject = UiGateway::new(&config);
+
+        let token = match &subject.access {
+            UiAccess::ControlToken(token) => token.clone(),
+            _ => panic!("Expected a control token"),
+        };
+        assert_eq!(
+            fs::read_to_string(directory.join(UI_TOKEN_FILE)).unwrap(),
+            token
+        );
+        assert_eq!(subject.startup_alerts, vec![]);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(directory.join(UI_TOKEN_FILE))
+                .unwrap()
+                .permissions()
+                .mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+    }
+
+    #[test]
+    fn a_token_that_cannot_be_written_is_a_startup_alert() {
+        init_test_logging();
+        let directory = make_directory("a_token_that_cannot_be_written_is_a_startup_alert");
+        let mut config = make_config(None);
+        config.token_directory = Some(directory.join("nonexistent"));
+
+        let subject = UiGateway::new(&config);
+
+        assert_eq!(subject.startup_alerts.len(), 1);
+        assert_eq!(subject.startup_alerts[0].code, "unwritable_ui_token");
+        TestLogHandler::new().exists_log_containing(&format!(
+            "WARN: UiGateway: Couldn't write {:?}: ",
+            directory.join("nonexistent").join(UI_TOKEN_FILE)
+        ));
+    }
+
+    #[test]
+    fn no_token_is_generated_when_the_operator_gave_one() {
+        let directory = make_directory("no_token_is_generated_when_the_operator_gave_one");
+        let mut config = make_config(None);
+        config.token_directory = Some(directory.clone());
+        config.local_token = Some(String::from("correct horse battery staple"));
+
+        let subject = UiGateway::new(&config);
+
+        match &subject.access {
+            UiAccess::Token(token) => assert_eq!(token, "correct horse battery staple"),
+            _ => panic!("Expected the operator's token"),
+        };
+        assert_eq!(directory.join(UI_TOKEN_FILE).exists(), false);
+    }
+
+    fn make_config(alias_directory: Option<PathBuf>) -> UiGatewayConfig {
+        UiGatewayConfig {
+            ui_port: find_free_port(),
+            startup_summary: None,
+            firewall_directory: None,
+            alias_directory,
+            remote_access: None,
+            local_token: None,
+            token_directory: None,
+        }
+    }
+
+    #[test]
+    fn ui_is_greeted_with_the_aliases_after_the_startup_summary() {
+        let directory = make_directory("ui_is_greeted_with_the_aliases_after_the_startup_summary");
+        let mut aliases = AliasStore::load(&directory).unwrap();
+        aliases.set("QmlsbA", "brother's node").unwrap();
+        let mut subject = UiGateway::new(&make_config(Some(directory)));
+        subject.greeting = Some(String::from("{\"message_type\":\"startup_summary\"}"));
+
+        let result = subject.greetings();
+
+        assert_eq!(
+            result,
+            vec![
+                String::from("{\"message_type\":\"startup_summary\"}"),
+                message_catalog::catalog_to_ui_json(),
+                aliases.to_ui_json(),
+            ]
+        );
+    }
+
+    #[test]
+    fn ui_gateway_starts_without_aliases_if_their_file_is_corrupt() {
+        init_test_logging();
+        let directory =
+            make_directory("ui_gateway_starts_without_aliases_if_their_file_is_corrupt");
+        fs::write(directory.join(ALIAS_FILE), "booga").unwrap();
+
+        let subject = UiGateway::new(&make_config(Some(directory.clone())));
+
+        assert_eq!(subject.aliases, AliasStore::in_memory());
+        assert_eq!(subject.startup_alerts.len(), 1);
+        assert_eq!(
+            subject.greetings().last(),
+            Some(&subject.startup_alerts[0].to_ui_json())
+        );
+        assert_eq!(
+            fs::read_to_string(directory.join(ALIAS_FILE)).unwrap(),
+            String::from("booga")
+        );
+        TestLogHandler::new().exists_log_containing(&format!(
+            "WARN: UiGateway: Aliases won't be kept past shutdown: Couldn't parse {:?}: ",
+            directory.join(ALIAS_FILE)
+        ));
+    }
+
+    #[test]
+    fn connected_websocket_supervisor_learns_of_new_greetings() {
+        let set_greetings_parameters = Arc::new(Mutex::new(vec![]));
+        let mut subject = UiGateway::new(&make_config(None));
+        subject.websocket_supervisor = Some(Box::new(
+            WebSocketSupervisorMock::new().set_greetings_parameters(&set_greetings_parameters),
+        ));
+        subject.aliases.set("QmlsbA", "brother's node").unwrap();
+
+        subject.update_greetings();
+
+        assert_eq!(
+            *set_greetings_parameters.lock().unwrap(),
+            vec![vec![
+                message_catalog::catalog_to_ui_json(),
+                subject.aliases.to_ui_json()
+            ]]
+        );
+    }
+
+    #[test]
+    fn alerts_are_logged_in_english_and_sent_to_every_ui_by_code() {
+        init_test_logging();
+        let receive_parameters = Arc::new(Mutex::new(vec![]));
+        let mut subject = UiGateway::new(&make_config(None));
+        subject.websocket_supervisor = Some(Box::new(
+            WebSocketSupervisorMock::new().receive_parameters(&receive_parameters),
+        ));
+        let alert = UiAlert::new("blank_alias").with("key", "QmlsbA");
+
+        subject.alert("Couldn't set alias: ", alert.clone());
+
+        assert_eq!(
+            *receive_parameters.lock().unwrap(),
+            vec![(BROADCAST, alert.to_ui_json())]
+        );
+        TestLogHandler::new().exists_log_containing(
+            "WARN: UiGateway: Couldn't set alias: Alias for QmlsbA is blank",
+        );
+    }
+
+    #[test]
+    fn alias_messages_change_the_alias_file() {
+        init_test_logging();
+        let directory = make_directory("alias_messages_change_the_alias_file");
+        let config = make_config(Some(directory.clone()));
+
+        thread::spawn(move || {
+            let subject = UiGateway::new(&config);
+            let system = System::new("alias_messages_change_the_alias_file");
+            let addr: Addr<Syn, UiGateway> = subject.start();
+            let mut peer_actors = peer_actors_builder().build();
+            peer_actors.ui_gateway = UiGateway::make_subs_from(&addr);
+            addr.try_send(BindMessage { peer_actors }).unwrap();
+
+            addr.try_send(UiMessage::SetAliasMessage {
+                key: String::from("QmlsbA"),
+                label: String::from("brother's node"),
+            })
+            .unwrap();
+            addr.try_send(UiMessage::SetAliasMessage {
+                key: String::from("VGVk"),
+                label: String::from("doomed"),
+            })
+            .unwrap();
+            addr.try_send(UiMessage::RemoveAliasMessage {
+                key: String::from("VGVk"),
+            })
+            .unwrap();
+            addr.try_send(UiMessage::SetAliasMessage {
+                key: String::from("VGVk"),
+                label: String::from(" "),
+            })
+            .unwrap();
+
+            system.run();
+        });
+        TestLogHandler::new().await_log_containing(
+            "WARN: UiGateway: Couldn't set alias: Alias for VGVk is blank",
+            1000,
+        );
+        let mut expected = AliasStore::in_memory();
+        expected.set("QmlsbA", "brother's node").unwrap();
+        assert_eq!(
+            AliasStore::load(&directory).unwrap().to_ui_json(),
+            expected.to_ui_json()
+        );
+        let tlh = TestLogHandler::new();
+        tlh.exists_log_containing("INFO: UiGateway: Set alias for QmlsbA");
+        tlh.exists_log_containing("INFO: UiGateway: Removed alias for VGVk");
+        tlh.exists_no_log_containing("brother's node");
+    }
 }
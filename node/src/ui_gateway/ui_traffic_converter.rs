@@ -1,13 +1,20 @@
 // Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
 
+use crate::sub_lib::cryptde::PublicKey;
+use crate::sub_lib::neighborhood::TopologyQueryMessage;
+use crate::sub_lib::ui_gateway::UiAlert;
 use crate::sub_lib::ui_gateway::UiMessage;
+use log::LevelFilter;
+use serde_json;
+use serde_json::Map;
+use serde_json::Value;
+use std::str::FromStr;
 
-#[allow(dead_code)]
 pub const BROADCAST: u64 = 0xFFFFFFFFFFFFFFFF;
 
 pub trait UiTrafficConverter {
     fn marshal(&self, ui_message: UiMessage) -> Result<String, String>;
-    fn unmarshal(&self, json: &str) -> Result<UiMessage, String>;
+    fn unmarshal(&self, client_id: u64, json: &str) -> Result<UiMessage, UiAlert>;
 }
 
 pub struct UiTrafficConverterReal {}
@@ -17,8 +24,116 @@ impl UiTrafficConverter for UiTrafficConverterReal {
         unimplemented!()
     }
 
-    fn unmarshal(&self, _json: &str) -> Result<UiMessage, String> {
-        Ok(UiMessage::ShutdownMessage)
+    // Anything this Node doesn't recognize is refused rather than guessed at, so that a UI asking for something
+    // it can't do yet doesn't shut it down instead
+    fn unmarshal(&self, client_id: u64, json: &str) -> Result<UiMessage, UiAlert> {
+        let value: Value = match serde_json::from_str(json) {
+            Ok(value) => value,
+            Err(e) => {
+                return Err(UiAlert::new("unparseable_ui_message")
+                    .with("json", json)
+                    .with("error", &format!("{}", e)))
+            }
+        };
+        match value
+            .get("message_type")
+            .and_then(|message_type| message_type.as_str())
+        {
+            Some("shutdown") => Ok(UiMessage::ShutdownMessage),
+            Some("reload") => Ok(UiMessage::ReloadMessage),
+            Some("set_alias") => Ok(UiMessage::SetAliasMessage {
+                key: payload_string(&value, "key", json)?,
+                label: payload_string(&value, "label", json)?,
+            }),
+            Some("remove_alias") => Ok(UiMessage::RemoveAliasMessage {
+                key: payload_string(&value, "key", json)?,
+            }),
+            Some("set_adaptive_hops") => Ok(UiMessage::SetAdaptiveHopsMessage {
+                enabled: payload_bool(&value, "enabled", json)?,
+            }),
+            Some("status") => Ok(UiMessage::StatusQueryMessage {
+                client_id,
+                request_id: request_id(&value, json)?,
+            }),
+            Some("topology") => Ok(UiMessage::TopologyQueryMessage {
+                client_id,
+                request_id: request_id(&value, json)?,
+                query: payload_topology_query(&value, json)?,
+            }),
+            Some("set_log_level") => Ok(UiMessage::SetLogLevelMessage {
+                level: payload_log_level(&value, "level", json)?,
+            }),
+            Some("remove_neighbor") => Ok(UiMessage::RemoveNeighborMessage {
+                public_key: payload_public_key(&value, "key", json)?,
+            }),
+            Some(message_type) => {
+                Err(UiAlert::new("unsupported_message_type").with("message_type", message_type))
+            }
+            None => Err(UiAlert::new("missing_message_type").with("json", json)),
+        }
+    }
+}
+
+// A query carries a request_id beside its message_type, which the answer carries back
+fn request_id(value: &Value, json: &str) -> Result<u64, UiAlert> {
+    match value
+        .get("request_id")
+        .and_then(|request_id| request_id.as_u64())
+    {
+        Some(request_id) => Ok(request_id),
+        None => Err(UiAlert::new("missing_request_id").with("json", json)),
+    }
+}
+
+fn payload_string(value: &Value, field: &str, json: &str) -> Result<String, UiAlert> {
+    match value
+        .get("payload")
+        .and_then(|payload| payload.get(field))
+        .and_then(|field_value| field_value.as_str())
+    {
+        Some(field_value) => Ok(String::from(field_value)),
+        None => Err(UiAlert::new("missing_payload_field")
+            .with("field", field)
+            .with("json", json)),
+    }
+}
+
+fn payload_bool(value: &Value, field: &str, json: &str) -> Result<bool, UiAlert> {
+    match value
+        .get("payload")
+        .and_then(|payload| payload.get(field))
+        .and_then(|field_value| field_value.as_bool())
+    {
+        Some(field_value) => Ok(field_value),
+        None => Err(UiAlert::new("missing_payload_field")
+            .with("field", field)
+            .with("json", json)),
+    }
+}
+
+// A topology query with no payload asks for the first page of every Node the Neighborhood knows
+fn payload_topology_query(value: &Value, json: &str) -> Result<TopologyQueryMessage, UiAlert> {
+    let payload = value
+        .get("payload")
+        .cloned()
+        .unwrap_or_else(|| Value::Object(Map::new()));
+    serde_json::from_value(payload).map_err(|e| {
+        UiAlert::new("bad_topology_query")
+            .with("json", json)
+            .with("error", &format!("{}", e))
+    })
+}
+
+fn payload_log_level(value: &Value, field: &str, json: &str) -> Result<LevelFilter, UiAlert> {
+    let level = payload_string(value, field, json)?;
+    LevelFilter::from_str(&level).map_err(|_| UiAlert::new("bad_log_level").with("level", &level))
+}
+
+fn payload_public_key(value: &Value, field: &str, json: &str) -> Result<PublicKey, UiAlert> {
+    let key = payload_string(value, field, json)?;
+    match base64::decode(&key) {
+        Ok(ref data) if !data.is_empty() => Ok(PublicKey::new(data)),
+        _ => Err(UiAlert::new("unparseable_public_key").with("key", &key)),
     }
 }
 
@@ -32,13 +147,302 @@ impl UiTrafficConverterReal {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::sub_lib::neighborhood::TopologyScope;
 
     #[test]
     fn a_shutdown_message_is_properly_unmarshalled() {
         let subject = UiTrafficConverterReal::new();
 
-        let result = subject.unmarshal("{\"message_type\": \"shutdown\"}");
+        let result = subject.unmarshal(1234, "{\"message_type\": \"shutdown\"}");
 
         assert_eq!(result, Ok(UiMessage::ShutdownMessage));
     }
+
+    #[test]
+    fn a_reload_message_is_properly_unmarshalled() {
+        let subject = UiTrafficConverterReal::new();
+
+        let result = subject.unmarshal(1234, "{\"message_type\": \"reload\"}");
+
+        assert_eq!(result, Ok(UiMessage::ReloadMessage));
+    }
+
+    #[test]
+    fn a_set_alias_message_is_properly_unmarshalled() {
+        let subject = UiTrafficConverterReal::new();
+
+        let result = subject.unmarshal(
+            1234,
+            "{\"message_type\": \"set_alias\", \"payload\": {\"key\": \"QmlsbA\", \"label\": \"my VPS node\"}}",
+        );
+
+        assert_eq!(
+            result,
+            Ok(UiMessage::SetAliasMessage {
+                key: String::from("QmlsbA"),
+                label: String::from("my VPS node"),
+            })
+        );
+    }
+
+    #[test]
+    fn a_remove_alias_message_is_properly_unmarshalled() {
+        let subject = UiTrafficConverterReal::new();
+
+        let result = subject.unmarshal(
+            1234,
+            "{\"message_type\": \"remove_alias\", \"payload\": {\"key\": \"QmlsbA\"}}",
+        );
+
+        assert_eq!(
+            result,
+            Ok(UiMessage::RemoveAliasMessage {
+                key: String::from("QmlsbA"),
+            })
+        );
+    }
+
+    #[test]
+    fn a_set_alias_message_without_a_label_is_refused() {
+        let subject = UiTrafficConverterReal::new();
+
+        let result = subject.unmarshal(
+            1234,
+            "{\"message_type\": \"set_alias\", \"payload\": {\"key\": \"QmlsbA\"}}",
+        );
+
+        assert_eq!(
+            result,
+            Err(UiAlert::new("missing_payload_field")
+                .with("field", "label")
+                .with(
+                    "json",
+                    "{\"message_type\": \"set_alias\", \"payload\": {\"key\": \"QmlsbA\"}}"
+                ))
+        );
+    }
+
+    #[test]
+    fn a_set_adaptive_hops_message_is_properly_unmarshalled() {
+        let subject = UiTrafficConverterReal::new();
+
+        let result = subject.unmarshal(
+            1234,
+            "{\"message_type\": \"set_adaptive_hops\", \"payload\": {\"enabled\": false}}",
+        );
+
+        assert_eq!(
+            result,
+            Ok(UiMessage::SetAdaptiveHopsMessage { enabled: false })
+        );
+    }
+
+    #[test]
+    fn a_set_adaptive_hops_message_without_a_boolean_is_refused() {
+        let subject = UiTrafficConverterReal::new();
+
+        let result = subject.unmarshal(
+            1234,
+            "{\"message_type\": \"set_adaptive_hops\", \"payload\": {\"enabled\": \"yes\"}}",
+        );
+
+        assert_eq!(
+            result,
+            Err(UiAlert::new("missing_payload_field")
+                .with("field", "enabled")
+                .with(
+                    "json",
+                    "{\"message_type\": \"set_adaptive_hops\", \"payload\": {\"enabled\": \"yes\"}}"
+                ))
+        );
+    }
+
+    #[test]
+    fn a_status_message_is_properly_unmarshalled() {
+        let subject = UiTrafficConverterReal::new();
+
+        let result = subject.unmarshal(1234, "{\"message_type\": \"status\", \"request_id\": 42}");
+
+        assert_eq!(
+            result,
+            Ok(UiMessage::StatusQueryMessage {
+                client_id: 1234,
+                request_id: 42
+            })
+        );
+    }
+
+    #[test]
+    fn a_status_message_without_a_request_id_is_refused() {
+        let subject = UiTrafficConverterReal::new();
+
+        let result = subject.unmarshal(1234, "{\"message_type\": \"status\"}");
+
+        assert_eq!(
+            result,
+            Err(UiAlert::new("missing_request_id").with("json", "{\"message_type\": \"status\"}"))
+        );
+    }
+
+    #[test]
+    fn a_topology_message_is_properly_unmarshalled() {
+        let subject = UiTrafficConverterReal::new();
+
+        let result = subject.unmarshal(
+            1234,
+            r#"{"message_type": "topology", "request_id": 42, "payload": {"scope": "neighbors", "cursor": "AQIDBA", "page_size": 10}}"#,
+        );
+
+        assert_eq!(
+            result,
+            Ok(UiMessage::TopologyQueryMessage {
+                client_id: 1234,
+                request_id: 42,
+                query: TopologyQueryMessage {
+                    scope: TopologyScope::Neighbors,
+                    cursor: Some(String::from("AQIDBA")),
+                    page_size: 10,
+                    ..TopologyQueryMessage::from_json("{}").unwrap()
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn a_topology_message_without_a_payload_asks_for_the_first_page_of_everything() {
+        let subject = UiTrafficConverterReal::new();
+
+        let result = subject.unmarshal(1234, r#"{"message_type": "topology", "request_id": 42}"#);
+
+        assert_eq!(
+            result,
+            Ok(UiMessage::TopologyQueryMessage {
+                client_id: 1234,
+                request_id: 42,
+                query: TopologyQueryMessage::from_json("{}").unwrap(),
+            })
+        );
+    }
+
+    #[test]
+    fn a_topology_message_that_makes_no_sense_is_refused() {
+        let subject = UiTrafficConverterReal::new();
+        let json =
+            r#"{"message_type": "topology", "request_id": 42, "payload": {"scope": "everywhere"}}"#;
+
+        let result = subject.unmarshal(1234, json);
+
+        let alert = result.err().unwrap();
+        assert_eq!(alert.code, String::from("bad_topology_query"));
+        assert_eq!(alert.parameters["json"], String::from(json));
+        assert_eq!(alert.parameters.contains_key("error"), true);
+    }
+
+    #[test]
+    fn a_set_log_level_message_is_properly_unmarshalled() {
+        let subject = UiTrafficConverterReal::new();
+
+        let result = subject.unmarshal(
+            1234,
+            "{\"message_type\": \"set_log_level\", \"payload\": {\"level\": \"debug\"}}",
+        );
+
+        assert_eq!(
+            result,
+            Ok(UiMessage::SetLogLevelMessage {
+                level: LevelFilter::Debug
+            })
+        );
+    }
+
+    #[test]
+    fn a_set_log_level_message_with_an_unknown_level_is_refused() {
+        let subject = UiTrafficConverterReal::new();
+
+        let result = subject.unmarshal(
+            1234,
+            "{\"message_type\": \"set_log_level\", \"payload\": {\"level\": \"loud\"}}",
+        );
+
+        assert_eq!(
+            result,
+            Err(UiAlert::new("bad_log_level").with("level", "loud"))
+        );
+    }
+
+    #[test]
+    fn a_remove_neighbor_message_is_properly_unmarshalled() {
+        let subject = UiTrafficConverterReal::new();
+
+        let result = subject.unmarshal(
+            1234,
+            "{\"message_type\": \"remove_neighbor\", \"payload\": {\"key\": \"AQIDBA\"}}",
+        );
+
+        assert_eq!(
+            result,
+            Ok(UiMessage::RemoveNeighborMessage {
+                public_key: PublicKey::new(&[1, 2, 3, 4]),
+            })
+        );
+    }
+
+    #[test]
+    fn a_remove_neighbor_message_with_a_bad_key_is_refused() {
+        let subject = UiTrafficConverterReal::new();
+
+        let bad_base64 = subject.unmarshal(
+            1234,
+            "{\"message_type\": \"remove_neighbor\", \"payload\": {\"key\": \"b@@ga\"}}",
+        );
+        let blank = subject.unmarshal(
+            1234,
+            "{\"message_type\": \"remove_neighbor\", \"payload\": {\"key\": \"\"}}",
+        );
+
+        assert_eq!(
+            bad_base64,
+            Err(UiAlert::new("unparseable_public_key").with("key", "b@@ga"))
+        );
+        assert_eq!(
+            blank,
+            Err(UiAlert::new("unparseable_public_key").with("key", ""))
+        );
+    }
+
+    #[test]
+    fn an_unsupported_message_type_is_refused() {
+        let subject = UiTrafficConverterReal::new();
+
+        let result = subject.unmarshal(1234, "{\"message_type\": \"financials\"}");
+
+        assert_eq!(
+            result,
+            Err(UiAlert::new("unsupported_message_type").with("message_type", "financials"))
+        );
+    }
+
+    #[test]
+    fn a_message_without_a_message_type_is_refused() {
+        let subject = UiTrafficConverterReal::new();
+
+        let result = subject.unmarshal(1234, "{\"booga\": \"shutdown\"}");
+
+        assert_eq!(
+            result,
+            Err(UiAlert::new("missing_message_type").with("json", "{\"booga\": \"shutdown\"}"))
+        );
+    }
+
+    #[test]
+    fn unparseable_json_is_refused() {
+        let subject = UiTrafficConverterReal::new();
+
+        let result = subject.unmarshal(1234, "booga");
+
+        let alert = result.err().unwrap();
+        assert_eq!(alert.code, String::from("unparseable_ui_message"));
+        assert_eq!(alert.parameters["json"], String::from("booga"));
+        assert_eq!(alert.parameters.contains_key("error"), true);
+    }
 }
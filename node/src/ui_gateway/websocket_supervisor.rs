@@ -6,22 +6,29 @@ use bytes::BytesMut;
 use futures::future::FutureResult;
 use futures::future::{err, ok};
 use futures::sink::Wait;
-use futures::stream::SplitSink;
 use futures::Future;
 use futures::Sink;
 use futures::Stream;
+use native_tls::Identity;
+use native_tls::TlsAcceptor;
 use std::collections::HashMap;
 use std::net::IpAddr;
 use std::net::Ipv4Addr;
 use std::net::SocketAddr;
 use std::sync::Arc;
 use std::sync::Mutex;
+use std::time::Instant;
+use tokio::io::AsyncRead;
+use tokio::io::AsyncWrite;
 use tokio::reactor::Handle;
 // The following r# notation is a way to get around the fact that 'async' is now a keyword in Rust
 use crate::sub_lib::ui_gateway::FromUiMessage;
+use crate::sub_lib::ui_gateway::UiRemoteAccessConfig;
+use crate::ui_gateway::remote_access;
+use crate::ui_gateway::remote_access::RemoteAccess;
+use crate::ui_gateway::ui_traffic_converter::BROADCAST;
 use websocket::client::r#async::Framed;
 use websocket::r#async::MessageCodec;
-use websocket::r#async::TcpStream;
 use websocket::server::r#async::Server;
 use websocket::server::upgrade::WsUpgrade;
 use websocket::OwnedMessage;
@@ -29,43 +36,196 @@ use websocket::WebSocketError;
 
 pub trait WebSocketSupervisor {
     fn receive(&self, client_id: u64, message_json: &str);
+    fn set_greetings(&self, greetings: Vec<String>);
 }
 
 pub struct WebSocketSupervisorReal {
-    #[allow(dead_code)]
     inner: Arc<Mutex<WebSocketSupervisorInner>>,
+    logger: Logger,
 }
 
 impl WebSocketSupervisor for WebSocketSupervisorReal {
-    fn receive(&self, _client_id: u64, _message_json: &str) {
-        unimplemented!()
+    // client_id BROADCAST sends the message to every UI that's connected at the moment. A UI that
+    // must authenticate before it hears anything hears nothing until it has.
+    fn receive(&self, client_id: u64, message_json: &str) {
+        let mut locked_inner = self.inner.lock().expect("WebSocketSupervisor is poisoned");
+        let client_ids: Vec<u64> = if client_id == BROADCAST {
+            locked_inner.client_by_id.keys().cloned().collect()
+        } else {
+            vec![client_id]
+        };
+        for client_id in client_ids {
+            if locked_inner.is_awaiting_authentication(client_id)
+                && !locked_inner.open_until_authenticated
+            {
+                continue;
+            }
+            let client = match locked_inner.client_by_id.get_mut(&client_id) {
+                Some(client) => client,
+                None => {
+                    self.logger
+                        .warning(format!("No UI with client ID {} to send to", client_id));
+                    continue;
+                }
+            };
+            let result = client
+                .send(OwnedMessage::Text(String::from(message_json)))
+                .and_then(|_| client.flush());
+            if let Err(e) = result {
+                self.logger.warning(format!(
+                    "Error sending message to UI with client ID {}: {:?}",
+                    client_id, e
+                ));
+            }
+        }
+    }
+
+    // For UIs that connect from now on; those already connected aren't greeted again
+    fn set_greetings(&self, greetings: Vec<String>) {
+        self.inner
+            .lock()
+            .expect("WebSocketSupervisor is poisoned")
+            .greetings = greetings;
     }
 }
 
+// Plain TCP for a local UI, TLS for a remote one
+type ClientSink = Wait<Box<dyn Sink<SinkItem = OwnedMessage, SinkError = WebSocketError> + Send>>;
+
 struct WebSocketSupervisorInner {
     next_client_id: u64,
     from_ui_message: Recipient<Syn, FromUiMessage>,
     client_id_by_socket_addr: HashMap<SocketAddr, u64>,
-    client_by_id: HashMap<u64, Wait<SplitSink<Framed<TcpStream, MessageCodec<OwnedMessage>>>>>,
+    client_by_id: HashMap<u64, ClientSink>,
+    greetings: Vec<String>,
+    remote_access_opt: Option<RemoteAccess>,
+    // Whether a UI that hasn't authenticated is greeted, heard and told things anyway, with what it
+    // says marked unauthenticated, rather than hearing and saying nothing until it authenticates
+    open_until_authenticated: bool,
+}
+
+impl WebSocketSupervisorInner {
+    fn is_awaiting_authentication(&self, client_id: u64) -> bool {
+        match &self.remote_access_opt {
+            Some(remote_access) => remote_access.is_awaiting(client_id),
+            None => false,
+        }
+    }
 }
 
 impl WebSocketSupervisorReal {
+    // Every UI is trusted with everything; the Node itself always has a token of some kind
+    #[cfg(test)]
     pub fn new(
         port: u16,
         from_ui_message: Recipient<Syn, FromUiMessage>,
+        greetings: Vec<String>,
+    ) -> WebSocketSupervisorReal {
+        Self::new_local(port, from_ui_message, greetings, None, false)
+    }
+
+    // Any UI on localhost is greeted and can ask questions, and is challenged after its greeting;
+    // it can answer the challenge whenever it likes, and what it says is marked authenticated once
+    // it has
+    pub fn new_with_control_token(
+        port: u16,
+        from_ui_message: Recipient<Syn, FromUiMessage>,
+        greetings: Vec<String>,
+        token: &str,
+    ) -> WebSocketSupervisorReal {
+        Self::new_local(
+            port,
+            from_ui_message,
+            greetings,
+            Some(RemoteAccess::new(token)),
+            true,
+        )
+    }
+
+    // Still plain TCP on localhost, but every UI must authenticate as a remote one would, so that
+    // other users of the machine can't control the Node
+    pub fn new_with_token(
+        port: u16,
+        from_ui_message: Recipient<Syn, FromUiMessage>,
+        greetings: Vec<String>,
+        token: &str,
     ) -> WebSocketSupervisorReal {
+        Self::new_local(
+            port,
+            from_ui_message,
+            greetings,
+            Some(RemoteAccess::new(token)),
+            false,
+        )
+    }
+
+    fn new_local(
+        port: u16,
+        from_ui_message: Recipient<Syn, FromUiMessage>,
+        greetings: Vec<String>,
+        remote_access_opt: Option<RemoteAccess>,
+        open_until_authenticated: bool,
+    ) -> WebSocketSupervisorReal {
+        let server_address = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), port);
+        let server = Server::bind(server_address, &Handle::default())
+            .expect(format!("Could not start UI server at {}", server_address).as_str());
+        Self::start(
+            server.incoming(),
+            from_ui_message,
+            greetings,
+            remote_access_opt,
+            open_until_authenticated,
+        )
+    }
+
+    // Listens on the configured interface instead of localhost, over TLS, and makes every UI
+    // authenticate before it hears or says anything
+    pub fn new_remote(
+        port: u16,
+        from_ui_message: Recipient<Syn, FromUiMessage>,
+        greetings: Vec<String>,
+        config: &UiRemoteAccessConfig,
+    ) -> WebSocketSupervisorReal {
+        let server_address = SocketAddr::new(config.interface, port);
+        let identity = Identity::from_pkcs12(&config.identity, "")
+            .unwrap_or_else(|e| panic!("Could not load UI server identity: {}", e));
+        let acceptor = TlsAcceptor::new(identity)
+            .unwrap_or_else(|e| panic!("Could not set up TLS for UI server: {}", e));
+        let server = Server::bind_secure(server_address, acceptor, &Handle::default())
+            .expect(format!("Could not start UI server at {}", server_address).as_str());
+        Self::start(
+            server.incoming(),
+            from_ui_message,
+            greetings,
+            Some(RemoteAccess::new(&config.token)),
+            false,
+        )
+    }
+
+    fn start<S, E>(
+        incoming: impl Stream<Item = (WsUpgrade<S, BytesMut>, SocketAddr), Error = E> + Send + 'static,
+        from_ui_message: Recipient<Syn, FromUiMessage>,
+        greetings: Vec<String>,
+        remote_access_opt: Option<RemoteAccess>,
+        open_until_authenticated: bool,
+    ) -> WebSocketSupervisorReal
+    where
+        S: AsyncRead + AsyncWrite + Send + 'static,
+        E: Send + 'static,
+    {
         let inner = Arc::new(Mutex::new(WebSocketSupervisorInner {
             next_client_id: 0,
             from_ui_message,
             client_id_by_socket_addr: HashMap::new(),
             client_by_id: HashMap::new(),
+            greetings,
+            remote_access_opt,
+            open_until_authenticated,
         }));
         let logger = Logger::new("WebSocketSupervisor");
         let logger_1 = logger.clone();
-        let server_address = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), port);
-        let server = Server::bind(server_address, &Handle::default())
-            .expect(format!("Could not start UI server at {}", server_address).as_str());
-        let upgrade_tuple_stream = Self::remove_failures(server.incoming(), &logger);
+        let logger_2 = logger.clone();
+        let upgrade_tuple_stream = Self::remove_failures(incoming, &logger);
         let inner_clone = inner.clone();
         let foreach_result = upgrade_tuple_stream.for_each(move |(upgrade, socket_addr)| {
             Self::handle_upgrade_request(upgrade, socket_addr, inner_clone.clone(), &logger);
@@ -81,7 +241,10 @@ impl WebSocketSupervisorReal {
                 Err(())
             }
         }));
-        WebSocketSupervisorReal { inner }
+        WebSocketSupervisorReal {
+            inner,
+            logger: logger_2,
+        }
     }
 
     fn remove_failures<I, E>(
@@ -101,13 +264,21 @@ impl WebSocketSupervisorReal {
             .map(|option| option.expect("A None magically got through the filter"))
     }
 
-    fn handle_upgrade_request(
-        upgrade: WsUpgrade<TcpStream, BytesMut>,
+    fn handle_upgrade_request<S>(
+        upgrade: WsUpgrade<S, BytesMut>,
         socket_addr: SocketAddr,
         inner: Arc<Mutex<WebSocketSupervisorInner>>,
         logger: &Logger,
-    ) {
-        if !upgrade
+    ) where
+        S: AsyncRead + AsyncWrite + Send + 'static,
+    {
+        if Self::is_locked_out(&inner, socket_addr.ip()) {
+            logger.warning(format!(
+                "UI at {} failed to authenticate too many times; refusing connection",
+                socket_addr
+            ));
+            tokio::spawn(upgrade.reject().then(|_| ok::<(), ()>(())));
+        } else if !upgrade
             .protocols()
             .contains(&String::from("SubstratumNode-UI"))
         {
@@ -117,12 +288,25 @@ impl WebSocketSupervisorReal {
         }
     }
 
-    fn accept_upgrade_request(
-        upgrade: WsUpgrade<TcpStream, BytesMut>,
+    fn is_locked_out(inner: &Arc<Mutex<WebSocketSupervisorInner>>, ip: IpAddr) -> bool {
+        match &inner
+            .lock()
+            .expect("WebSocketSupervisor is poisoned")
+            .remote_access_opt
+        {
+            Some(remote_access) => remote_access.is_locked_out(ip, Instant::now()),
+            None => false,
+        }
+    }
+
+    fn accept_upgrade_request<S>(
+        upgrade: WsUpgrade<S, BytesMut>,
         socket_addr: SocketAddr,
         inner: Arc<Mutex<WebSocketSupervisorInner>>,
         logger: &Logger,
-    ) {
+    ) where
+        S: AsyncRead + AsyncWrite + Send + 'static,
+    {
         let logger_clone = logger.clone();
         logger_clone.info(format!("UI connected at {}", socket_addr));
         let upgrade_future =
@@ -140,7 +324,10 @@ impl WebSocketSupervisorReal {
         }));
     }
 
-    fn reject_upgrade_request(upgrade: WsUpgrade<TcpStream, BytesMut>, logger: &Logger) {
+    fn reject_upgrade_request<S>(upgrade: WsUpgrade<S, BytesMut>, logger: &Logger)
+    where
+        S: AsyncRead + AsyncWrite + Send + 'static,
+    {
         logger.info(format!(
             "UI attempted connection without protocol SubstratumNode-UI: {:?}",
             upgrade.protocols()
@@ -148,18 +335,22 @@ impl WebSocketSupervisorReal {
         tokio::spawn(upgrade.reject().then(|_| ok::<(), ()>(())));
     }
 
-    fn handle_connection(
-        client: Framed<TcpStream, MessageCodec<OwnedMessage>>,
+    fn handle_connection<S>(
+        client: Framed<S, MessageCodec<OwnedMessage>>,
         inner: &Arc<Mutex<WebSocketSupervisorInner>>,
         logger: &Logger,
         socket_addr: SocketAddr,
-    ) {
+    ) where
+        S: AsyncRead + AsyncWrite + Send + 'static,
+    {
         let logger_1 = logger.clone();
         let logger_2 = logger.clone();
         let inner_1 = inner.clone();
         let (outgoing, incoming) = client.split();
+        let outgoing: Box<dyn Sink<SinkItem = OwnedMessage, SinkError = WebSocketError> + Send> =
+            Box::new(outgoing);
         // "Going synchronous" here to avoid calling .send() on an async Sink, which consumes it
-        let sync_outgoing: Wait<SplitSink<_>> = outgoing.wait();
+        let sync_outgoing: ClientSink = outgoing.wait();
         let mut locked_inner = inner.lock().expect("WebSocketSupervisor is poisoned");
         let client_id = locked_inner.next_client_id;
         locked_inner.next_client_id += 1;
@@ -167,6 +358,23 @@ impl WebSocketSupervisorReal {
             .client_id_by_socket_addr
             .insert(socket_addr, client_id);
         locked_inner.client_by_id.insert(client_id, sync_outgoing);
+        let challenge_opt = locked_inner
+            .remote_access_opt
+            .as_mut()
+            .map(|remote_access| remote_access.challenge(client_id));
+        if challenge_opt.is_none() || locked_inner.open_until_authenticated {
+            Self::send_greeting(&mut locked_inner, client_id, socket_addr, logger);
+        }
+        if let Some(challenge) = challenge_opt {
+            Self::send_texts(
+                &mut locked_inner,
+                client_id,
+                socket_addr,
+                vec![challenge],
+                "challenge",
+                logger,
+            );
+        }
         let incoming_future = incoming
             .then(move |result| Self::handle_websocket_errors(result, &logger_2, socket_addr))
             .map(move |owned_message| match owned_message {
@@ -187,30 +395,130 @@ impl WebSocketSupervisorReal {
         tokio::spawn(incoming_future);
     }
 
+    // A UI that connects is told right away how this Node was started, and what the operator
+    // calls the wallets and Nodes it will see, so it doesn't have to ask.
+    fn send_greeting(
+        locked_inner: &mut WebSocketSupervisorInner,
+        client_id: u64,
+        socket_addr: SocketAddr,
+        logger: &Logger,
+    ) {
+        let greetings = locked_inner.greetings.clone();
+        Self::send_texts(
+            locked_inner,
+            client_id,
+            socket_addr,
+            greetings,
+            "greeting",
+            logger,
+        )
+    }
+
+    fn send_texts(
+        locked_inner: &mut WebSocketSupervisorInner,
+        client_id: u64,
+        socket_addr: SocketAddr,
+        texts: Vec<String>,
+        description: &str,
+        logger: &Logger,
+    ) {
+        let client = locked_inner
+            .client_by_id
+            .get_mut(&client_id)
+            .expect("Client disappeared before it could be greeted");
+        for text in texts {
+            if let Err(e) = client.send(OwnedMessage::Text(text)) {
+                logger.warning(format!(
+                    "Error sending {} to UI at {}: {:?}",
+                    description, socket_addr, e
+                ));
+                return;
+            }
+            client
+                .flush()
+                .expect(format!("Couldn't flush transmission to UI at {}", socket_addr).as_str());
+        }
+    }
+
     fn handle_text_message(
         inner_arc: &Arc<Mutex<WebSocketSupervisorInner>>,
         logger: &Logger,
         socket_addr: SocketAddr,
         message: &str,
     ) -> FutureResult<(), ()> {
-        let locked_inner = inner_arc.lock().expect("WebSocketSupervisor is poisoned");
-        match locked_inner.client_id_by_socket_addr.get(&socket_addr) {
+        let mut locked_inner = inner_arc.lock().expect("WebSocketSupervisor is poisoned");
+        let client_id = match locked_inner.client_id_by_socket_addr.get(&socket_addr) {
             None => {
                 logger.warning(
                     "WebSocketSupervisor got a message from a client that never connected!"
                         .to_string(),
                 );
-                err::<(), ()>(()) // end the stream
+                return err::<(), ()>(()); // end the stream
             }
-            Some(_client_id_ref) => {
-                locked_inner
-                    .from_ui_message
-                    .try_send(FromUiMessage {
-                        json: String::from(message),
-                    })
-                    .expect("UiGateway is dead");
+            Some(client_id) => *client_id,
+        };
+        let awaiting = locked_inner.is_awaiting_authentication(client_id);
+        if awaiting
+            && (!locked_inner.open_until_authenticated || remote_access::is_authentication(message))
+        {
+            return Self::handle_authentication(
+                &mut locked_inner,
+                logger,
+                client_id,
+                socket_addr,
+                message,
+            );
+        }
+        locked_inner
+            .from_ui_message
+            .try_send(FromUiMessage {
+                client_id,
+                authenticated: !awaiting,
+                json: String::from(message),
+            })
+            .expect("UiGateway is dead");
+        ok::<(), ()>(())
+    }
+
+    // A UI that tries to prove it knows the token and fails is disconnected
+    fn handle_authentication(
+        locked_inner: &mut WebSocketSupervisorInner,
+        logger: &Logger,
+        client_id: u64,
+        socket_addr: SocketAddr,
+        message: &str,
+    ) -> FutureResult<(), ()> {
+        let result = locked_inner
+            .remote_access_opt
+            .as_mut()
+            .expect("Remote access disappeared")
+            .authenticate(client_id, socket_addr.ip(), message, Instant::now());
+        match result {
+            Ok(answer) => {
+                logger.info(format!("UI at {} authenticated", socket_addr));
+                Self::send_texts(
+                    locked_inner,
+                    client_id,
+                    socket_addr,
+                    vec![answer],
+                    "proof",
+                    logger,
+                );
+                if !locked_inner.open_until_authenticated {
+                    Self::send_greeting(locked_inner, client_id, socket_addr, logger);
+                }
                 ok::<(), ()>(())
             }
+            Err(e) => {
+                logger.warning(format!(
+                    "UI at {} failed to authenticate: {}",
+                    socket_addr, e
+                ));
+                locked_inner.client_id_by_socket_addr.remove(&socket_addr);
+                Self::close_connection(locked_inner, client_id, socket_addr, logger);
+                locked_inner.client_by_id.remove(&client_id);
+                err::<(), ()>(()) // end the stream
+            }
         }
     }
 
@@ -228,6 +536,10 @@ impl WebSocketSupervisorReal {
             Some(client_id) => client_id,
         };
         Self::close_connection(&mut locked_inner, client_id, socket_addr, &logger);
+        locked_inner.client_by_id.remove(&client_id);
+        if let Some(remote_access) = locked_inner.remote_access_opt.as_mut() {
+            remote_access.forget(client_id);
+        }
 
         err::<(), ()>(()) // end the stream
     }
@@ -293,6 +605,9 @@ mod tests {
     use crate::test_utils::recorder::Recorder;
     use crate::test_utils::test_utils::find_free_port;
     use crate::test_utils::test_utils::wait_for;
+    use crate::ui_gateway::remote_access::TokenKey;
+    use crate::ui_gateway::remote_access::MAX_FAILED_ATTEMPTS;
+    use crate::ui_gateway::remote_access::NONCE_LENGTH;
     use actix::Actor;
     use actix::Addr;
     use actix::Arbiter;
@@ -300,6 +615,7 @@ mod tests {
     use futures::future::lazy;
     use std::collections::HashSet;
     use std::net::Shutdown;
+    use std::sync::mpsc;
     use std::thread;
     use std::time::Duration;
     use websocket::client::sync::Client;
@@ -329,6 +645,40 @@ mod tests {
         one_client_opt.unwrap()
     }
 
+    // A supervisor that demands authentication like a remote one, but over plain TCP on localhost,
+    // so that the test clients don't need a certificate
+    fn start_remote_subject(
+        port: u16,
+        from_ui_message: Recipient<Syn, FromUiMessage>,
+        greetings: Vec<String>,
+    ) -> WebSocketSupervisorReal {
+        WebSocketSupervisorReal::new_with_token(port, from_ui_message, greetings, TOKEN)
+    }
+
+    const TOKEN: &str = "correct horse battery staple";
+
+    fn text_of(message: OwnedMessage) -> serde_json::Value {
+        match message {
+            OwnedMessage::Text(text) => serde_json::from_str(&text).unwrap(),
+            x => panic!("Expected text, got {:?}", x),
+        }
+    }
+
+    fn answer_challenge(client: &mut Client<TcpStream>, token: &str) -> Vec<u8> {
+        let challenge = text_of(client.recv_message().unwrap());
+        assert_eq!(challenge["message_type"], "auth_challenge");
+        let node_nonce = base64::decode(challenge["payload"]["nonce"].as_str().unwrap()).unwrap();
+        let ui_nonce = vec![5u8; NONCE_LENGTH];
+        client
+            .send_message(&Message::text(format!(
+                "{{\"message_type\":\"auth\",\"payload\":{{\"proof\":\"{}\",\"nonce\":\"{}\"}}}}",
+                base64::encode(&TokenKey::new(token).ui_proof(&node_nonce)),
+                base64::encode(&ui_nonce)
+            )))
+            .unwrap();
+        ui_nonce
+    }
+
     fn wait_for_server(port: u16) {
         wait_for(None, None, || {
             match TcpStream::connect(SocketAddr::new(
@@ -357,7 +707,7 @@ mod tests {
                 addr.recipient::<FromUiMessage>()
             };
             let subject = lazy(move || {
-                let _subject = WebSocketSupervisorReal::new(port, from_ui_message);
+                let _subject = WebSocketSupervisorReal::new(port, from_ui_message, vec![]);
                 Ok(())
             });
             Arbiter::handle().spawn(subject);
@@ -382,7 +732,7 @@ mod tests {
                 addr.recipient::<FromUiMessage>()
             };
             let subject = lazy(move || {
-                let _subject = WebSocketSupervisorReal::new(port, from_ui_message);
+                let _subject = WebSocketSupervisorReal::new(port, from_ui_message, vec![]);
                 Ok(())
             });
             Arbiter::handle().spawn(subject);
@@ -412,7 +762,7 @@ mod tests {
                 addr.recipient::<FromUiMessage>()
             };
             let subject = lazy(move || {
-                let _subject = WebSocketSupervisorReal::new(port, from_ui_message);
+                let _subject = WebSocketSupervisorReal::new(port, from_ui_message, vec![]);
                 Ok(())
             });
             Arbiter::handle().spawn(subject);
@@ -459,7 +809,7 @@ mod tests {
                 addr.recipient::<FromUiMessage>()
             };
             let subject = lazy(move || {
-                let _subject = WebSocketSupervisorReal::new(port, from_ui_message);
+                let _subject = WebSocketSupervisorReal::new(port, from_ui_message, vec![]);
                 Ok(())
             });
             Arbiter::handle().spawn(subject);
@@ -507,6 +857,87 @@ mod tests {
         assert_eq!(another_close_msg, OwnedMessage::Close(None));
     }
 
+    #[test]
+    fn each_client_is_greeted_in_order_when_it_connects() {
+        let port = find_free_port();
+        let (ui_gateway, _, _) = make_recorder();
+
+        thread::spawn(move || {
+            let system = System::new("each_client_is_greeted_in_order_when_it_connects");
+            let from_ui_message = {
+                let addr: Addr<Syn, Recorder> = ui_gateway.start();
+                addr.recipient::<FromUiMessage>()
+            };
+            let subject = lazy(move || {
+                let subject = WebSocketSupervisorReal::new(
+                    port,
+                    from_ui_message,
+                    vec![String::from("{\"message_type\":\"startup_summary\"}")],
+                );
+                subject.set_greetings(vec![
+                    String::from("{\"message_type\":\"startup_summary\"}"),
+                    String::from("{\"message_type\":\"aliases\"}"),
+                ]);
+                Ok(())
+            });
+            Arbiter::handle().spawn(subject);
+            system.run();
+        });
+
+        let mut one_client = wait_for_client(port, "SubstratumNode-UI");
+        let mut another_client = make_client(port, "SubstratumNode-UI").unwrap();
+
+        let one_greetings = vec![
+            one_client.recv_message().unwrap(),
+            one_client.recv_message().unwrap(),
+        ];
+        let another_greetings = vec![
+            another_client.recv_message().unwrap(),
+            another_client.recv_message().unwrap(),
+        ];
+
+        let expected = vec![
+            OwnedMessage::Text(String::from("{\"message_type\":\"startup_summary\"}")),
+            OwnedMessage::Text(String::from("{\"message_type\":\"aliases\"}")),
+        ];
+        assert_eq!(one_greetings, expected);
+        assert_eq!(another_greetings, expected);
+    }
+
+    #[test]
+    fn a_broadcast_reaches_every_connected_client() {
+        let port = find_free_port();
+        let (ui_gateway, _, _) = make_recorder();
+        let (tx, rx) = mpsc::channel();
+
+        thread::spawn(move || {
+            let system = System::new("a_broadcast_reaches_every_connected_client");
+            let from_ui_message = {
+                let addr: Addr<Syn, Recorder> = ui_gateway.start();
+                addr.recipient::<FromUiMessage>()
+            };
+            let subject = lazy(move || {
+                tx.send(WebSocketSupervisorReal::new(port, from_ui_message, vec![]))
+                    .unwrap();
+                Ok(())
+            });
+            Arbiter::handle().spawn(subject);
+            system.run();
+        });
+        let subject = rx.recv().unwrap();
+        let mut one_client = wait_for_client(port, "SubstratumNode-UI");
+        let mut another_client = make_client(port, "SubstratumNode-UI").unwrap();
+        wait_for(None, None, || {
+            subject.inner.lock().unwrap().client_by_id.len() == 2
+        });
+
+        subject.receive(BROADCAST, "{\"message_type\":\"alert\"}");
+
+        let expected = OwnedMessage::Text(String::from("{\"message_type\":\"alert\"}"));
+        assert_eq!(one_client.recv_message().unwrap(), expected);
+        assert_eq!(another_client.recv_message().unwrap(), expected);
+    }
+
     #[test]
     fn once_a_client_sends_a_close_no_more_data_is_accepted() {
         let port = find_free_port();
@@ -519,7 +950,7 @@ mod tests {
                 addr.recipient::<FromUiMessage>()
             };
             let subject = lazy(move || {
-                let _subject = WebSocketSupervisorReal::new(port, from_ui_message);
+                let _subject = WebSocketSupervisorReal::new(port, from_ui_message, vec![]);
                 Ok(())
             });
             Arbiter::handle().spawn(subject);
@@ -539,6 +970,8 @@ mod tests {
         assert_eq!(
             ui_gateway_recording.get_record::<FromUiMessage>(0),
             &FromUiMessage {
+                client_id: 0,
+                authenticated: true,
                 json: String::from("One")
             }
         );
@@ -557,7 +990,7 @@ mod tests {
                 addr.recipient::<FromUiMessage>()
             };
             let subject = lazy(move || {
-                let _subject = WebSocketSupervisorReal::new(port, from_ui_message);
+                let _subject = WebSocketSupervisorReal::new(port, from_ui_message, vec![]);
                 Ok(())
             });
             Arbiter::handle().spawn(subject);
@@ -578,9 +1011,179 @@ mod tests {
         assert_eq!(
             ui_gateway_recording.get_record::<FromUiMessage>(0),
             &FromUiMessage {
+                client_id: 0,
+                authenticated: true,
                 json: String::from("One")
             }
         );
         assert_eq!(ui_gateway_recording.len(), 1);
     }
+
+    #[test]
+    fn a_remote_ui_hears_and_says_nothing_until_it_authenticates() {
+        let port = find_free_port();
+        let (ui_gateway, ui_gateway_awaiter, ui_gateway_recording_arc) = make_recorder();
+        let (tx, rx) = mpsc::channel();
+
+        thread::spawn(move || {
+            let system = System::new("a_remote_ui_hears_and_says_nothing_until_it_authenticates");
+            let from_ui_message = {
+                let addr: Addr<Syn, Recorder> = ui_gateway.start();
+                addr.recipient::<FromUiMessage>()
+            };
+            let subject = lazy(move || {
+                tx.send(start_remote_subject(
+                    port,
+                    from_ui_message,
+                    vec![String::from("{\"message_type\":\"startup_summary\"}")],
+                ))
+                .unwrap();
+                Ok(())
+            });
+            Arbiter::handle().spawn(subject);
+            system.run();
+        });
+        let subject = rx.recv().unwrap();
+        let mut client = wait_for_client(port, "SubstratumNode-UI");
+        wait_for(None, None, || {
+            subject.inner.lock().unwrap().client_by_id.len() == 1
+        });
+        subject.receive(BROADCAST, "{\"message_type\":\"alert\"}");
+
+        let ui_nonce = answer_challenge(&mut client, TOKEN);
+
+        let answer = text_of(client.recv_message().unwrap());
+        assert_eq!(answer["message_type"], "authenticated");
+        assert_eq!(
+            base64::decode(answer["payload"]["proof"].as_str().unwrap()).unwrap(),
+            TokenKey::new(TOKEN).node_proof(&ui_nonce)
+        );
+        assert_eq!(
+            client.recv_message().unwrap(),
+            OwnedMessage::Text(String::from("{\"message_type\":\"startup_summary\"}"))
+        );
+        client.send_message(&Message::text("One")).unwrap();
+        ui_gateway_awaiter.await_message_count(1);
+        let ui_gateway_recording = ui_gateway_recording_arc.lock().unwrap();
+        assert_eq!(
+            ui_gateway_recording.get_record::<FromUiMessage>(0),
+            &FromUiMessage {
+                client_id: 0,
+                authenticated: true,
+                json: String::from("One")
+            }
+        );
+    }
+
+    #[test]
+    fn a_ui_with_a_control_token_is_heard_at_once_but_authenticated_only_once_it_proves_it() {
+        let port = find_free_port();
+        let (ui_gateway, ui_gateway_awaiter, ui_gateway_recording_arc) = make_recorder();
+        let (tx, rx) = mpsc::channel();
+
+        thread::spawn(move || {
+            let system = System::new(
+                "a_ui_with_a_control_token_is_heard_at_once_but_authenticated_only_once_it_proves_it",
+            );
+            let from_ui_message = {
+                let addr: Addr<Syn, Recorder> = ui_gateway.start();
+                addr.recipient::<FromUiMessage>()
+            };
+            let subject = lazy(move || {
+                tx.send(WebSocketSupervisorReal::new_with_control_token(
+                    port,
+                    from_ui_message,
+                    vec![String::from("{\"message_type\":\"startup_summary\"}")],
+                    TOKEN,
+                ))
+                .unwrap();
+                Ok(())
+            });
+            Arbiter::handle().spawn(subject);
+            system.run();
+        });
+        let subject = rx.recv().unwrap();
+        let mut client = wait_for_client(port, "SubstratumNode-UI");
+        assert_eq!(
+            client.recv_message().unwrap(),
+            OwnedMessage::Text(String::from("{\"message_type\":\"startup_summary\"}"))
+        );
+        client.send_message(&Message::text("One")).unwrap();
+        ui_gateway_awaiter.await_message_count(1);
+        subject.receive(BROADCAST, "{\"message_type\":\"alert\"}");
+
+        let ui_nonce = answer_challenge(&mut client, TOKEN);
+
+        assert_eq!(
+            client.recv_message().unwrap(),
+            OwnedMessage::Text(String::from("{\"message_type\":\"alert\"}"))
+        );
+        let answer = text_of(client.recv_message().unwrap());
+        assert_eq!(answer["message_type"], "authenticated");
+        assert_eq!(
+            base64::decode(answer["payload"]["proof"].as_str().unwrap()).unwrap(),
+            TokenKey::new(TOKEN).node_proof(&ui_nonce)
+        );
+        client.send_message(&Message::text("Two")).unwrap();
+        ui_gateway_awaiter.await_message_count(2);
+        let ui_gateway_recording = ui_gateway_recording_arc.lock().unwrap();
+        assert_eq!(
+            ui_gateway_recording.get_record::<FromUiMessage>(0),
+            &FromUiMessage {
+                client_id: 0,
+                authenticated: false,
+                json: String::from("One")
+            }
+        );
+        assert_eq!(
+            ui_gateway_recording.get_record::<FromUiMessage>(1),
+            &FromUiMessage {
+                client_id: 0,
+                authenticated: true,
+                json: String::from("Two")
+            }
+        );
+    }
+
+    #[test]
+    fn a_remote_ui_with_the_wrong_token_is_disconnected_and_eventually_locked_out() {
+        init_test_logging();
+        let port = find_free_port();
+        let (ui_gateway, _, ui_gateway_recording_arc) = make_recorder();
+
+        thread::spawn(move || {
+            let system = System::new(
+                "a_remote_ui_with_the_wrong_token_is_disconnected_and_eventually_locked_out",
+            );
+            let from_ui_message = {
+                let addr: Addr<Syn, Recorder> = ui_gateway.start();
+                addr.recipient::<FromUiMessage>()
+            };
+            let subject = lazy(move || {
+                let _subject = start_remote_subject(port, from_ui_message, vec![]);
+                Ok(())
+            });
+            Arbiter::handle().spawn(subject);
+            system.run();
+        });
+        wait_for_server(port);
+
+        (0..MAX_FAILED_ATTEMPTS).for_each(|_| {
+            let mut client = make_client(port, "SubstratumNode-UI").unwrap();
+            answer_challenge(&mut client, "wrong horse battery staple");
+            assert_eq!(client.recv_message().unwrap(), OwnedMessage::Close(None));
+        });
+
+        make_client(port, "SubstratumNode-UI").err().unwrap();
+        let tlh = TestLogHandler::new();
+        tlh.await_log_matching(
+            "UI at 127\\.0\\.0\\.1:\\d+ failed to authenticate: wrong token",
+            1000,
+        );
+        tlh.await_log_matching(
+            "UI at 127\\.0\\.0\\.1:\\d+ failed to authenticate too many times; refusing connection",
+            1000,
+        );
+        assert_eq!(ui_gateway_recording_arc.lock().unwrap().len(), 0);
+    }
 }
@@ -0,0 +1,232 @@
+// Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+
+// Golden serializations of the structures that travel between Nodes (or between Node and UI), kept
+// in golden/<version>/ as hex. Every version that's still supported must decode into the same
+// value it was written from; the current version must also re-encode to exactly the same bytes.
+// If one of these tests fails, something has changed on the wire. If that was on purpose, start a
+// new version directory (keeping the old one if the old format must still be readable), add it
+// to PROTOCOL_VERSIONS, and regenerate its files by running the tests with WIRE_COMPAT_BLESS set.
+
+use crate::hopper::live_cores_package::LiveCoresPackage;
+use crate::neighborhood::gossip::Gossip;
+use crate::neighborhood::gossip::GossipNodeRecord;
+use crate::neighborhood::neighborhood_database::LoadAdvertisement;
+use crate::neighborhood::neighborhood_database::NodeRecordInner;
+use crate::neighborhood::neighborhood_database::NodeSignatures;
+use crate::sub_lib::cryptde::CryptData;
+use crate::sub_lib::cryptde::PublicKey;
+use crate::sub_lib::dispatcher::Component;
+use crate::sub_lib::hop::LiveHop;
+use crate::sub_lib::neighborhood::RateCard;
+use crate::sub_lib::proxy_client::ClientResponsePayload;
+use crate::sub_lib::proxy_server::ClientRequestPayload;
+use crate::sub_lib::proxy_server::ProxyProtocol;
+use crate::sub_lib::route::Route;
+use crate::sub_lib::sequence_buffer::SequencedPacket;
+use crate::sub_lib::stream_key::StreamKey;
+use crate::sub_lib::ui_gateway::UiMessage;
+use crate::sub_lib::wallet::Wallet;
+use crate::ui_gateway::ui_traffic_converter::UiTrafficConverter;
+use crate::ui_gateway::ui_traffic_converter::UiTrafficConverterReal;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_cbor;
+use std::env;
+use std::fmt::Debug;
+use std::fs;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+const GOLDEN_DIR: &str = "golden";
+const PROTOCOL_VERSIONS: &[&str] = &["v0", "v1"];
+const CURRENT_PROTOCOL_VERSION: &str = "v1";
+
+fn golden_path(version: &str, name: &str, extension: &str) -> PathBuf {
+    PathBuf::from(GOLDEN_DIR)
+        .join(version)
+        .join(format!("{}.{}", name, extension))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    let hex: Vec<String> = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    let lines: Vec<String> = hex.chunks(32).map(|line| line.concat()).collect();
+    format!("{}\n", lines.join("\n"))
+}
+
+fn from_hex(hex: &str) -> Vec<u8> {
+    let digits: Vec<char> = hex.chars().filter(|c| !c.is_whitespace()).collect();
+    digits
+        .chunks(2)
+        .map(|pair| {
+            let pair: String = pair.iter().collect();
+            u8::from_str_radix(&pair, 16).expect(&format!("Bad hex digits: '{}'", pair))
+        })
+        .collect()
+}
+
+fn read_golden(version: &str, name: &str) -> Vec<u8> {
+    let path = golden_path(version, name, "hex");
+    if (version == CURRENT_PROTOCOL_VERSION) && env::var("WIRE_COMPAT_BLESS").is_ok() {
+        return vec![]; // will be rewritten
+    }
+    let hex = fs::read_to_string(&path).expect(&format!("Missing golden file {:?}", path));
+    from_hex(&hex)
+}
+
+fn assert_wire_compatible<T>(name: &str, expected: T)
+where
+    T: Serialize + DeserializeOwned + PartialEq + Debug,
+{
+    for version in PROTOCOL_VERSIONS {
+        let golden = read_golden(version, name);
+        if *version == CURRENT_PROTOCOL_VERSION {
+            let encoded = serde_cbor::ser::to_vec(&expected).unwrap();
+            if env::var("WIRE_COMPAT_BLESS").is_ok() {
+                fs::write(golden_path(version, name, "hex"), to_hex(&encoded)).unwrap();
+                continue;
+            }
+            assert_eq!(
+                to_hex(&encoded),
+                to_hex(&golden),
+                "{} no longer encodes as it does in {}",
+                name,
+                version
+            );
+        }
+        let decoded: T = serde_cbor::de::from_slice(&golden[..]).expect(&format!(
+            "{} from {} can no longer be decoded",
+            name, version
+        ));
+        assert_eq!(
+            decoded, expected,
+            "{} from {} decodes differently",
+            name, version
+        );
+    }
+}
+
+fn stream_key() -> StreamKey {
+    StreamKey::new(
+        PublicKey::new(b"originator"),
+        SocketAddr::from_str("1.2.3.4:5678").unwrap(),
+    )
+}
+
+#[test]
+fn hex_round_trips() {
+    let bytes: Vec<u8> = (0..=255).collect();
+
+    let result = from_hex(&to_hex(&bytes));
+
+    assert_eq!(result, bytes);
+}
+
+#[test]
+fn gossip_is_wire_compatible() {
+    let gossip = Gossip {
+        node_records: vec![GossipNodeRecord {
+            inner: NodeRecordInner {
+                public_key: PublicKey::new(b"ABCD"),
+                node_addr_opt: None,
+                earning_wallet: Wallet::new("earning"),
+                consuming_wallet: Some(Wallet::new("consuming")),
+                is_bootstrap_node: true,
+                neighbors: vec![PublicKey::new(b"EFGH"), PublicKey::new(b"IJKL")],
+                version: 5,
+                load: LoadAdvertisement::default(),
+                offers_exit_tls: false,
+                relay_only: false,
+                rate_card: RateCard::default(),
+                country_opt: None,
+                exit_sla_opt: None,
+            },
+            signatures: NodeSignatures::new(
+                CryptData::new(b"complete signature"),
+                CryptData::new(b"obscured signature"),
+            ),
+        }],
+        observed_ip_opt: None,
+        ttl_opt: None,
+        sequence_opt: None,
+        ack_opt: None,
+        delta: false,
+    };
+
+    assert_wire_compatible("gossip", gossip);
+}
+
+#[test]
+fn live_hop_is_wire_compatible() {
+    let live_hop = LiveHop::new(
+        &PublicKey::new(b"ABCD"),
+        Some(Wallet::new("consuming")),
+        Component::ProxyClient,
+    );
+
+    assert_wire_compatible("live_hop", live_hop);
+}
+
+#[test]
+fn live_cores_package_is_wire_compatible() {
+    let package = LiveCoresPackage::new(
+        Route {
+            hops: vec![CryptData::new(b"first hop"), CryptData::new(b"second hop")],
+        },
+        CryptData::new(b"payload"),
+    );
+
+    assert_wire_compatible("live_cores_package", package);
+}
+
+#[test]
+fn client_request_payload_is_wire_compatible() {
+    let payload = ClientRequestPayload {
+        stream_key: stream_key(),
+        sequenced_packet: SequencedPacket::new(b"GET / HTTP/1.1\r\n\r\n".to_vec(), 42, false),
+        target_hostname: Some(String::from("example.com")),
+        target_port: 80,
+        protocol: ProxyProtocol::HTTP,
+        originator_public_key: PublicKey::new(b"originator"),
+        tls_at_exit: false,
+        upload_window: None,
+        udp: false,
+        dns_server: None,
+        cancel: false,
+    };
+
+    assert_wire_compatible("client_request_payload", payload);
+}
+
+#[test]
+fn client_response_payload_is_wire_compatible() {
+    let payload = ClientResponsePayload {
+        stream_key: stream_key(),
+        sequenced_packet: SequencedPacket::new(b"HTTP/1.1 200 OK\r\n\r\n".to_vec(), 43, true),
+        requests_received: None,
+        truncated: false,
+        udp: false,
+        exit_failed: false,
+    };
+
+    assert_wire_compatible("client_response_payload", payload);
+}
+
+// The UI protocol is JSON. What UIs send has to keep being understood; what Node sends them is new.
+#[test]
+fn ui_shutdown_message_is_wire_compatible() {
+    let subject = UiTrafficConverterReal::new();
+    for version in PROTOCOL_VERSIONS {
+        let path = golden_path(version, "ui_shutdown_message", "json");
+        let json = fs::read_to_string(&path).expect(&format!("Missing golden file {:?}", path));
+
+        let result = subject.unmarshal(0, &json);
+
+        assert_eq!(
+            result,
+            Ok(UiMessage::ShutdownMessage),
+            "ui_shutdown_message from {} decodes differently",
+            version
+        );
+    }
+}
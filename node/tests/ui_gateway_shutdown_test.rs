@@ -3,9 +3,28 @@
 mod utils;
 
 use node_lib::sub_lib::ui_gateway::DEFAULT_UI_PORT;
+use node_lib::sub_lib::ui_gateway::UI_TOKEN_FILE;
+use node_lib::ui_gateway::remote_access::UiHandshake;
+use std::fs;
+use websocket::stream::sync::TcpStream;
+use websocket::sync::Client;
 use websocket::ClientBuilder;
 use websocket::OwnedMessage;
 
+// Skips the greetings
+fn receive(ui_client: &mut Client<TcpStream>, message_type: &str) -> String {
+    loop {
+        match ui_client.recv_message().unwrap() {
+            OwnedMessage::Text(json) => {
+                if json.contains(&format!("\"message_type\":\"{}\"", message_type)) {
+                    return json;
+                }
+            }
+            message => panic!("Expected text, got {:?}", message),
+        }
+    }
+}
+
 #[test]
 fn ui_gateway_shutdown_integration() {
     let mut node = utils::SubstratumNode::start(None);
@@ -15,6 +34,15 @@ fn ui_gateway_shutdown_integration() {
         .add_protocol("SubstratumNode-UI")
         .connect_insecure()
         .unwrap();
+    let challenge = receive(&mut ui_client, "auth_challenge");
+    // The test Node's data directory is the current one
+    let handshake = UiHandshake::new(&fs::read_to_string(UI_TOKEN_FILE).unwrap());
+    ui_client
+        .send_message(&OwnedMessage::Text(handshake.answer(&challenge).unwrap()))
+        .unwrap();
+    handshake
+        .check(&receive(&mut ui_client, "authenticated"))
+        .unwrap();
 
     ui_client
         .send_message(&OwnedMessage::Text(String::from(
@@ -0,0 +1,5 @@
+#!/bin/bash -xev
+# Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+CI_DIR="$( cd "$( dirname "$0" )" && pwd )"
+
+"$CI_DIR/unit_tests.sh"
@@ -0,0 +1,19 @@
+// Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+
+use node_lib::sub_lib::main_tools::Command;
+use node_lib::sub_lib::main_tools::StdStreams;
+use std::io;
+use substratum_cli_lib::substratum_cli::SubstratumCli;
+
+pub fn main() {
+    let mut streams: StdStreams<'_> = StdStreams {
+        stdin: &mut io::stdin(),
+        stdout: &mut io::stdout(),
+        stderr: &mut io::stderr(),
+    };
+
+    let mut command = SubstratumCli::new();
+    let streams_ref: &mut StdStreams<'_> = &mut streams;
+    let exit_code = command.go(streams_ref, &std::env::args().collect());
+    ::std::process::exit(exit_code as i32);
+}
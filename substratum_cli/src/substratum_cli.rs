@@ -0,0 +1,1715 @@
+// Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use crate::ui_connection::UiConnection;
+use crate::ui_connection::UiConnectionFactory;
+use crate::ui_connection::UiConnectionFactoryReal;
+use node_lib::identity_bundle::IdentityBundle;
+use node_lib::sub_lib::main_tools::Command;
+use node_lib::sub_lib::main_tools::StdStreams;
+use node_lib::sub_lib::neighborhood::TopologyPage;
+use node_lib::sub_lib::ui_gateway::NodeStatus;
+use node_lib::sub_lib::ui_gateway::StartupSummary;
+use node_lib::sub_lib::ui_gateway::DEFAULT_UI_PORT;
+use node_lib::sub_lib::ui_gateway::UI_TOKEN_FILE;
+use node_lib::sub_lib::wallet::Wallet;
+use node_lib::ui_gateway::remote_access::UiHandshake;
+use serde_json;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Read;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+
+const USAGE: &str =
+    "Usage: substratum-cli [--ui_port <port number>] [--ui_token <token file>] <subcommand>
+Subcommands:
+    status                   Show how the Node was started
+    financials               Show what the Node owes and is owed
+    neighbors                Show the Node's immediate neighbors
+    add-neighbor <neighbor>  Introduce the Node to a neighbor, as with --neighbor
+    set log_level <level>    Make the Node log more or less: off, error, warn, info, debug or trace
+    alias <wallet or key> <label>
+                             Show a label in place of a wallet address or Node public key
+    unalias <wallet or key>  Stop showing a label for a wallet address or Node public key
+    aliases                  List the labels the Node keeps for wallets and Nodes
+    adaptive-hops on|off     Let a Node started with --adaptive_hops move its hop count, or stop it
+    reload                   Make the Node read its privacy policy and GeoIP database files again
+    shutdown                 Shut the Node down
+    export-identity <data directory> <archive file>
+                             Save the key and database of a stopped Node to an encrypted archive
+    import-identity <archive file> <data directory>
+                             Restore a Node from an archive into an empty data directory
+Every subcommand but status, financials, neighbors, aliases and the export and import
+subcommands is an order, which
+the Node takes only from a UI that knows its token: the file given to the Node with --ui_token,
+or else the ui_token file a Node leaves in its data directory, which is where orders look unless
+--ui_token says otherwise.
+The export and import subcommands need no running Node; they read a password from the first
+line of standard input.";
+
+// Each query this CLI sends is the only one on its connection, so any request_id will do; the pages
+// of a neighbors listing are numbered from here
+const FIRST_REQUEST_ID: u64 = 1;
+
+pub struct SubstratumCli {
+    factory: Box<dyn UiConnectionFactory>,
+    // Where a Node started without --ui_token or --data_directory leaves its token
+    default_token_file: Option<PathBuf>,
+}
+
+#[derive(Debug, PartialEq)]
+struct Options {
+    ui_port: u16,
+    ui_token: Option<PathBuf>,
+}
+
+#[derive(Debug, PartialEq)]
+enum Subcommand {
+    Status,
+    Financials,
+    Neighbors,
+    AddNeighbor(String),
+    Set(String, String),
+    Alias(String, String),
+    Unalias(String),
+    Aliases,
+    AdaptiveHops(bool),
+    Reload,
+    Shutdown,
+    ExportIdentity(String, String),
+    ImportIdentity(String, String),
+}
+
+impl Command for SubstratumCli {
+    fn go(&mut self, streams: &mut StdStreams<'_>, args: &Vec<String>) -> u8 {
+        let (options, subcommand) = match SubstratumCli::parse_args(args) {
+            Ok(parsed) => parsed,
+            Err(msg) => {
+                writeln!(streams.stderr, "{}\n{}", msg, USAGE).expect("Could not writeln");
+                return 1;
+            }
+        };
+        match self.perform(&options, subcommand, streams) {
+            Ok(_) => 0,
+            Err(msg) => {
+                writeln!(streams.stderr, "{}", msg).expect("Could not writeln");
+                1
+            }
+        }
+    }
+}
+
+impl SubstratumCli {
+    pub fn new() -> SubstratumCli {
+        SubstratumCli {
+            factory: Box::new(UiConnectionFactoryReal::new()),
+            default_token_file: dirs::data_dir().map(|directory| directory.join(UI_TOKEN_FILE)),
+        }
+    }
+
+    fn parse_args(args: &Vec<String>) -> Result<(Options, Subcommand), String> {
+        let mut words: Vec<&str> = args.iter().skip(1).map(|arg| arg.as_str()).collect();
+        let mut options = Options {
+            ui_port: DEFAULT_UI_PORT,
+            ui_token: None,
+        };
+        while let Some(option) = words.first().cloned().filter(|word| word.starts_with("--")) {
+            let value = match words.get(1) {
+                Some(value) => value.to_string(),
+                None if option == "--ui_port" => {
+                    return Err(String::from("--ui_port needs a port number"))
+                }
+                None if option == "--ui_token" => {
+                    return Err(String::from("--ui_token needs a token file"))
+                }
+                None => return Err(format!("Can't understand '{}'", words.join(" "))),
+            };
+            match option {
+                "--ui_port" => match value.parse::<u16>() {
+                    Ok(port) => options.ui_port = port,
+                    Err(_) => {
+                        return Err(format!(
+                            "Invalid port for --ui_port <port number>: '{}'",
+                            value
+                        ))
+                    }
+                },
+                "--ui_token" => options.ui_token = Some(PathBuf::from(value)),
+                _ => return Err(format!("Can't understand '{}'", words.join(" "))),
+            }
+            words.drain(0..2);
+        }
+        let subcommand = match words.as_slice() {
+            ["status"] => Subcommand::Status,
+            ["financials"] => Subcommand::Financials,
+            ["neighbors"] => Subcommand::Neighbors,
+            ["add-neighbor", neighbor] => Subcommand::AddNeighbor(neighbor.to_string()),
+            ["set", name, value] => Subcommand::Set(name.to_string(), value.to_string()),
+            ["alias", key, label] => Subcommand::Alias(key.to_string(), label.to_string()),
+            ["unalias", key] => Subcommand::Unalias(key.to_string()),
+            ["aliases"] => Subcommand::Aliases,
+            ["adaptive-hops", "on"] => Subcommand::AdaptiveHops(true),
+            ["adaptive-hops", "off"] => Subcommand::AdaptiveHops(false),
+            ["reload"] => Subcommand::Reload,
+            ["shutdown"] => Subcommand::Shutdown,
+            ["export-identity", data_directory, archive_file] => {
+                Subcommand::ExportIdentity(data_directory.to_string(), archive_file.to_string())
+            }
+            ["import-identity", archive_file, data_directory] => {
+                Subcommand::ImportIdentity(archive_file.to_string(), data_directory.to_string())
+            }
+            [] => return Err(String::from("No subcommand given")),
+            _ => return Err(format!("Can't understand '{}'", words.join(" "))),
+        };
+        Ok((options, subcommand))
+    }
+
+    fn perform(
+        &self,
+        options: &Options,
+        subcommand: Subcommand,
+        streams: &mut StdStreams<'_>,
+    ) -> Result<(), String> {
+        let port = options.ui_port;
+        match subcommand {
+            Subcommand::Status => self.status(port, streams),
+            Subcommand::Reload => self.reload(options, streams),
+            Subcommand::Shutdown => self.shutdown(options, streams),
+            Subcommand::Alias(key, label) => self.alias(options, &key, &label, streams),
+            Subcommand::Unalias(key) => self.unalias(options, &key, streams),
+            Subcommand::Financials => self.financials(port, streams),
+            Subcommand::Neighbors => self.neighbors(port, streams),
+            Subcommand::Set(name, value) => self.set(options, &name, &value, streams),
+            Subcommand::Aliases => self.aliases(port, streams),
+            Subcommand::AdaptiveHops(enabled) => self.adaptive_hops(options, enabled, streams),
+            Subcommand::ExportIdentity(data_directory, archive_file) => {
+                SubstratumCli::export_identity(&data_directory, &archive_file, streams)
+            }
+            Subcommand::ImportIdentity(archive_file, data_directory) => {
+                SubstratumCli::import_identity(&archive_file, &data_directory, streams)
+            }
+            // The Node's UI gateway has no way to introduce it to a neighbor yet, and refuses what it
+            // doesn't understand; there's no point asking it.
+            Subcommand::AddNeighbor(_) => Err(SubstratumCli::unsupported("add-neighbor")),
+        }
+    }
+
+    fn status(&self, port: u16, streams: &mut StdStreams<'_>) -> Result<(), String> {
+        let mut connection = self.factory.connect(port)?;
+        let greeting = connection.receive_greeting()?;
+        let summary = SubstratumCli::parse_startup_summary(&greeting)?;
+        // A Node too old to send aliases shows none
+        let aliases = SubstratumCli::receive_aliases(&mut *connection).unwrap_or_default();
+        let consuming_wallet = match &summary.consuming_wallet {
+            Some(wallet) => SubstratumCli::wallet_display(&aliases, wallet),
+            None => String::from("(none)"),
+        };
+        let public_key = summary.node_descriptor.split(':').next().unwrap_or("");
+        let clandestine_ports: Vec<String> = summary
+            .clandestine_ports
+            .iter()
+            .map(|port| format!("{}", port))
+            .collect();
+        let lines = vec![
+            ("Version", summary.version.clone()),
+            ("Mode", summary.mode.clone()),
+            ("Node type", summary.node_type.clone()),
+            (
+                "Node descriptor",
+                SubstratumCli::labelled(&summary.node_descriptor, aliases.get(public_key)),
+            ),
+            ("Clandestine ports", clandestine_ports.join(",")),
+            ("UI port", format!("{}", summary.ui_port)),
+            ("DNS servers", summary.dns_servers.join(",")),
+            ("DNS subversion", summary.dns_subversion.clone()),
+            (
+                "Earning wallet",
+                SubstratumCli::wallet_display(&aliases, &summary.earning_wallet),
+            ),
+            ("Consuming wallet", consuming_wallet),
+            ("Crypto backend", summary.crypto_backend.clone()),
+            ("Database schema", summary.db_schema_version.clone()),
+            (
+                "Adaptive hops",
+                String::from(if summary.adaptive_hops {
+                    "available"
+                } else {
+                    "not configured"
+                }),
+            ),
+        ];
+        lines.into_iter().for_each(|(name, value)| {
+            writeln!(streams.stdout, "{:18}{}", format!("{}:", name), value)
+                .expect("Could not writeln")
+        });
+        Ok(())
+    }
+
+    fn financials(&self, port: u16, streams: &mut StdStreams<'_>) -> Result<(), String> {
+        let mut connection = self.factory.connect(port)?;
+        let payload = SubstratumCli::query(
+            &mut *connection,
+            "status",
+            FIRST_REQUEST_ID,
+            serde_json::json!({}),
+            "status",
+        )?;
+        let status: NodeStatus = serde_json::from_value(payload)
+            .map_err(|e| format!("Node sent unreadable status: {}", e))?;
+        vec![
+            ("Owed to the Node", status.receivable_balance),
+            ("Owed by the Node", status.payable_balance),
+        ]
+        .into_iter()
+        .for_each(|(name, value)| {
+            writeln!(streams.stdout, "{:18}{}", format!("{}:", name), value)
+                .expect("Could not writeln")
+        });
+        Ok(())
+    }
+
+    // The Neighborhood answers a page at a time, so the listing follows the cursor to the last one
+    fn neighbors(&self, port: u16, streams: &mut StdStreams<'_>) -> Result<(), String> {
+        let mut connection = self.factory.connect(port)?;
+        let greeting = connection.receive_greeting()?;
+        SubstratumCli::parse_startup_summary(&greeting)?;
+        // A Node too old to send aliases shows none
+        let aliases = SubstratumCli::receive_aliases(&mut *connection).unwrap_or_default();
+        let mut request_id = FIRST_REQUEST_ID;
+        let mut cursor: Option<String> = None;
+        let mut count = 0;
+        loop {
+            let payload = SubstratumCli::query(
+                &mut *connection,
+                "topology",
+                request_id,
+                serde_json::json!({"scope": "neighbors", "cursor": cursor}),
+                "topology_page",
+            )?;
+            let page: TopologyPage = serde_json::from_value(payload)
+                .map_err(|e| format!("Node sent unreadable neighbors: {}", e))?;
+            page.nodes.iter().for_each(|node| {
+                writeln!(
+                    streams.stdout,
+                    "{}: {}",
+                    SubstratumCli::labelled(&node.public_key, aliases.get(&node.public_key)),
+                    node.node_addr
+                        .as_ref()
+                        .map(|node_addr| node_addr.as_str())
+                        .unwrap_or("(no address)")
+                )
+                .expect("Could not writeln")
+            });
+            count += page.nodes.len();
+            match page.next_cursor {
+                Some(next_cursor) => cursor = Some(next_cursor),
+                None => break,
+            }
+            request_id += 1;
+        }
+        if count == 0 {
+            writeln!(streams.stdout, "No neighbors").expect("Could not writeln");
+        }
+        Ok(())
+    }
+
+    // log_level is the only setting a running Node can change but adaptive hops, which has a
+    // subcommand of its own; the Node judges the level itself
+    fn set(
+        &self,
+        options: &Options,
+        name: &str,
+        value: &str,
+        streams: &mut StdStreams<'_>,
+    ) -> Result<(), String> {
+        if name != "log_level" {
+            return Err(format!(
+                "Can't set '{}': log_level is the only setting a running Node can change",
+                name
+            ));
+        }
+        let port = options.ui_port;
+        let mut connection = self.connect_to_order(options)?;
+        let order = serde_json::json!({
+            "message_type": "set_log_level",
+            "payload": {"level": value},
+        });
+        connection.send(&order.to_string())?;
+        writeln!(
+            streams.stdout,
+            "Log level {} order sent to Node on UI port {}",
+            value, port
+        )
+        .expect("Could not writeln");
+        Ok(())
+    }
+
+    fn reload(&self, options: &Options, streams: &mut StdStreams<'_>) -> Result<(), String> {
+        let port = options.ui_port;
+        let mut connection = self.connect_to_order(options)?;
+        connection.send("{\"message_type\": \"reload\"}")?;
+        writeln!(
+            streams.stdout,
+            "Reload order sent to Node on UI port {}",
+            port
+        )
+        .expect("Could not writeln");
+        Ok(())
+    }
+
+    fn shutdown(&self, options: &Options, streams: &mut StdStreams<'_>) -> Result<(), String> {
+        let port = options.ui_port;
+        let mut connection = self.connect_to_order(options)?;
+        connection.send("{\"message_type\": \"shutdown\"}")?;
+        writeln!(
+            streams.stdout,
+            "Shutdown order sent to Node on UI port {}",
+            port
+        )
+        .expect("Could not writeln");
+        Ok(())
+    }
+
+    fn alias(
+        &self,
+        options: &Options,
+        key: &str,
+        label: &str,
+        streams: &mut StdStreams<'_>,
+    ) -> Result<(), String> {
+        let port = options.ui_port;
+        let mut connection = self.connect_to_order(options)?;
+        let order = serde_json::json!({
+            "message_type": "set_alias",
+            "payload": {"key": key, "label": label},
+        });
+        connection.send(&order.to_string())?;
+        writeln!(
+            streams.stdout,
+            "Alias for {} sent to Node on UI port {}",
+            key, port
+        )
+        .expect("Could not writeln");
+        Ok(())
+    }
+
+    fn adaptive_hops(
+        &self,
+        options: &Options,
+        enabled: bool,
+        streams: &mut StdStreams<'_>,
+    ) -> Result<(), String> {
+        let port = options.ui_port;
+        let mut connection = self.connect_to_order(options)?;
+        let order = serde_json::json!({
+            "message_type": "set_adaptive_hops",
+            "payload": {"enabled": enabled},
+        });
+        connection.send(&order.to_string())?;
+        writeln!(
+            streams.stdout,
+            "Adaptive hop count {} order sent to Node on UI port {}",
+            if enabled { "on" } else { "off" },
+            port
+        )
+        .expect("Could not writeln");
+        Ok(())
+    }
+
+    fn unalias(
+        &self,
+        options: &Options,
+        key: &str,
+        streams: &mut StdStreams<'_>,
+    ) -> Result<(), String> {
+        let port = options.ui_port;
+        let mut connection = self.connect_to_order(options)?;
+        let order = serde_json::json!({
+            "message_type": "remove_alias",
+            "payload": {"key": key},
+        });
+        connection.send(&order.to_string())?;
+        writeln!(
+            streams.stdout,
+            "Alias removal for {} sent to Node on UI port {}",
+            key, port
+        )
+        .expect("Could not writeln");
+        Ok(())
+    }
+
+    fn aliases(&self, port: u16, streams: &mut StdStreams<'_>) -> Result<(), String> {
+        let mut connection = self.factory.connect(port)?;
+        let greeting = connection.receive_greeting()?;
+        SubstratumCli::parse_startup_summary(&greeting)?;
+        let aliases = SubstratumCli::receive_aliases(&mut *connection)?;
+        if aliases.is_empty() {
+            writeln!(streams.stdout, "No aliases").expect("Could not writeln");
+        }
+        aliases.iter().for_each(|(key, label)| {
+            writeln!(streams.stdout, "{}: {}", key, label).expect("Could not writeln")
+        });
+        Ok(())
+    }
+
+    // The token is read before connecting, so that a missing one doesn't cost a connection
+    fn connect_to_order(&self, options: &Options) -> Result<Box<dyn UiConnection>, String> {
+        let token_file = match options
+            .ui_token
+            .as_ref()
+            .or(self.default_token_file.as_ref())
+        {
+            Some(token_file) => token_file,
+            None => return Err(String::from(
+                "No data directory to find the Node's UI token in; give --ui_token <token file>",
+            )),
+        };
+        let token = fs::read_to_string(token_file)
+            .map_err(|e| {
+                format!(
+                    "Couldn't read the Node's UI token from {:?}: {}; give --ui_token <token file>",
+                    token_file, e
+                )
+            })?
+            .trim()
+            .to_string();
+        let mut connection = self.factory.connect(options.ui_port)?;
+        SubstratumCli::authenticate(&mut *connection, &token)
+            .map_err(|e| format!("Couldn't authenticate to the Node: {}", e))?;
+        Ok(connection)
+    }
+
+    // A Node started with --ui_token challenges a UI before greeting it; any other Node local to the
+    // UI greets it first. Either way, the challenge is the first thing that isn't a greeting.
+    fn authenticate(connection: &mut dyn UiConnection, token: &str) -> Result<(), String> {
+        let handshake = UiHandshake::new(token);
+        let challenge = SubstratumCli::receive_message_type(connection, "auth_challenge")?;
+        connection.send(&handshake.answer(&challenge)?)?;
+        let answer = SubstratumCli::receive_message_type(connection, "authenticated")?;
+        handshake.check(&answer)
+    }
+
+    // Greetings and alerts for other UIs may come before the answer, which is the first message of
+    // answer_type with the query's request_id. A Node too old to know the query says so in an alert.
+    fn query(
+        connection: &mut dyn UiConnection,
+        message_type: &str,
+        request_id: u64,
+        payload: Value,
+        answer_type: &str,
+    ) -> Result<Value, String> {
+        let query = serde_json::json!({
+            "message_type": message_type,
+            "request_id": request_id,
+            "payload": payload,
+        });
+        connection.send(&query.to_string())?;
+        loop {
+            let json = connection.receive()?;
+            let value: Value = serde_json::from_str(&json)
+                .map_err(|e| format!("Node sent unparseable message: {}", e))?;
+            match value.get("message_type").and_then(|mt| mt.as_str()) {
+                Some("alert") if value["payload"]["code"] == "unsupported_message_type" => {
+                    return Err(format!(
+                        "This Node's UI gateway can't answer '{}' queries",
+                        message_type
+                    ))
+                }
+                Some(mt)
+                    if mt == answer_type
+                        && value.get("request_id").and_then(|id| id.as_u64())
+                            == Some(request_id) =>
+                {
+                    return Ok(value["payload"].clone())
+                }
+                _ => (),
+            }
+        }
+    }
+
+    fn receive_message_type(
+        connection: &mut dyn UiConnection,
+        message_type: &str,
+    ) -> Result<String, String> {
+        loop {
+            let json = connection.receive()?;
+            let value: Value = serde_json::from_str(&json)
+                .map_err(|e| format!("Node sent unparseable message: {}", e))?;
+            if value.get("message_type").and_then(|mt| mt.as_str()) == Some(message_type) {
+                return Ok(json);
+            }
+        }
+    }
+
+    fn export_identity(
+        data_directory: &str,
+        archive_file: &str,
+        streams: &mut StdStreams<'_>,
+    ) -> Result<(), String> {
+        let password = SubstratumCli::read_password(streams)?;
+        let archive = IdentityBundle::gather(Path::new(data_directory))?.export(&password)?;
+        fs::write(archive_file, archive)
+            .map_err(|e| format!("Couldn't write {}: {}", archive_file, e))?;
+        writeln!(
+            streams.stdout,
+            "Identity in {} exported to {}",
+            data_directory, archive_file
+        )
+        .expect("Could not writeln");
+        Ok(())
+    }
+
+    fn import_identity(
+        archive_file: &str,
+        data_directory: &str,
+        streams: &mut StdStreams<'_>,
+    ) -> Result<(), String> {
+        let password = SubstratumCli::read_password(streams)?;
+        let archive =
+            fs::read(archive_file).map_err(|e| format!("Couldn't read {}: {}", archive_file, e))?;
+        IdentityBundle::import(&archive, &password)?.install(Path::new(data_directory))?;
+        writeln!(
+            streams.stdout,
+            "Identity in {} imported to {}",
+            archive_file, data_directory
+        )
+        .expect("Could not writeln");
+        Ok(())
+    }
+
+    // One byte at a time, so that nothing past the end of the line is consumed
+    fn read_password(streams: &mut StdStreams<'_>) -> Result<String, String> {
+        let mut bytes = vec![];
+        let mut byte = [0u8; 1];
+        loop {
+            match streams.stdin.read(&mut byte) {
+                Ok(0) => break,
+                Ok(_) if byte[0] == b'\n' => break,
+                Ok(_) => bytes.push(byte[0]),
+                Err(e) => return Err(format!("Couldn't read password: {}", e)),
+            }
+        }
+        let password = String::from_utf8(bytes)
+            .map_err(|_| String::from("Password must be UTF-8"))?
+            .trim_end_matches('\r')
+            .to_string();
+        if password.is_empty() {
+            return Err(String::from(
+                "No password given: type it as the first line of standard input",
+            ));
+        }
+        Ok(password)
+    }
+
+    fn parse_startup_summary(greeting: &str) -> Result<StartupSummary, String> {
+        let value: Value = match serde_json::from_str(greeting) {
+            Ok(value) => value,
+            Err(e) => return Err(format!("Node sent unparseable startup summary: {}", e)),
+        };
+        if value.get("message_type").and_then(|mt| mt.as_str()) != Some("startup_summary") {
+            return Err(format!(
+                "Node sent something other than a startup summary: {}",
+                greeting
+            ));
+        }
+        match serde_json::from_value(value["payload"].clone()) {
+            Ok(summary) => Ok(summary),
+            Err(e) => Err(format!("Node sent unreadable startup summary: {}", e)),
+        }
+    }
+
+    // The Node sends the operator's aliases after its startup summary and, if it's new enough to
+    // have one, its message catalog
+    fn receive_aliases(
+        connection: &mut dyn UiConnection,
+    ) -> Result<BTreeMap<String, String>, String> {
+        let parse = |json: &str| -> Result<Value, String> {
+            serde_json::from_str(json).map_err(|e| format!("Node sent unparseable aliases: {}", e))
+        };
+        let mut json = connection.receive_greeting()?;
+        let mut value = parse(&json)?;
+        if value.get("message_type").and_then(|mt| mt.as_str()) == Some("message_catalog") {
+            json = connection.receive_greeting()?;
+            value = parse(&json)?;
+        }
+        if value.get("message_type").and_then(|mt| mt.as_str()) != Some("aliases") {
+            return Err(format!(
+                "Node sent something other than its aliases: {}",
+                json
+            ));
+        }
+        match serde_json::from_value(value["payload"].clone()) {
+            Ok(aliases) => Ok(aliases),
+            Err(e) => Err(format!("Node sent unreadable aliases: {}", e)),
+        }
+    }
+
+    fn labelled(value: &str, label: Option<&String>) -> String {
+        match label {
+            Some(label) => format!("{} ({})", label, value),
+            None => String::from(value),
+        }
+    }
+
+    // The startup summary has only a wallet's fingerprint, so that's what aliases are matched by
+    fn wallet_display(aliases: &BTreeMap<String, String>, fingerprint: &str) -> String {
+        let label = aliases
+            .iter()
+            .find(|(key, _)| {
+                key.starts_with("0x")
+                    && Wallet::new(key)
+                        .fingerprint()
+                        .eq_ignore_ascii_case(fingerprint)
+            })
+            .map(|(_, label)| label);
+        SubstratumCli::labelled(fingerprint, label)
+    }
+
+    fn unsupported(subcommand: &str) -> String {
+        format!(
+            "This Node's UI gateway can't handle '{}' yet; every other subcommand is available",
+            subcommand
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use node_lib::identity_bundle::read_private_key;
+    use node_lib::identity_bundle::write_private_key;
+    use node_lib::sub_lib::cryptde::PrivateKey;
+    use node_lib::sub_lib::neighborhood::TopologyNode;
+    use node_lib::test_utils::test_utils::ByteArrayReader;
+    use node_lib::test_utils::test_utils::FakeStreamHolder;
+    use node_lib::ui_gateway::message_catalog::catalog_to_ui_json;
+    use node_lib::ui_gateway::remote_access;
+    use node_lib::ui_gateway::remote_access::RemoteAccess;
+    use std::cell::RefCell;
+    use std::net::IpAddr;
+    use std::net::Ipv4Addr;
+    use std::sync::Arc;
+    use std::sync::Mutex;
+    use std::time::Instant;
+
+    const TOKEN: &str = "correct horse battery staple";
+
+    struct UiConnectionMock {
+        greetings: Vec<Result<String, String>>,
+        sent: Arc<Mutex<Vec<String>>>,
+        // Plays the Node's part in the handshake; only what's sent after it is recorded
+        node_opt: Option<RemoteAccess>,
+    }
+
+    impl UiConnection for UiConnectionMock {
+        fn receive_greeting(&mut self) -> Result<String, String> {
+            self.receive()
+        }
+
+        fn receive(&mut self) -> Result<String, String> {
+            if self.greetings.is_empty() {
+                return Err(String::from("No more greetings"));
+            }
+            self.greetings.remove(0)
+        }
+
+        fn send(&mut self, json: &str) -> Result<(), String> {
+            if let Some(node) = self.node_opt.as_mut() {
+                if remote_access::is_authentication(json) {
+                    let answer = node
+                        .authenticate(
+                            0,
+                            IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+                            json,
+                            Instant::now(),
+                        )
+                        .map_err(|e| format!("Disconnected: {}", e));
+                    self.greetings.push(answer);
+                    return Ok(());
+                }
+            }
+            self.sent.lock().unwrap().push(String::from(json));
+            Ok(())
+        }
+    }
+
+    impl UiConnectionMock {
+        fn new(greetings: Vec<Result<String, String>>, sent: &Arc<Mutex<Vec<String>>>) -> Self {
+            UiConnectionMock {
+                greetings,
+                sent: sent.clone(),
+                node_opt: None,
+            }
+        }
+
+        // Like a Node started without --ui_token: greetings first, then the challenge
+        fn authenticating(token: &str, sent: &Arc<Mutex<Vec<String>>>) -> Self {
+            let mut node = RemoteAccess::new(token);
+            let challenge = node.challenge(0);
+            UiConnectionMock {
+                greetings: vec![
+                    Ok(make_summary().to_ui_json()),
+                    Ok(catalog_to_ui_json()),
+                    Ok(String::from(
+                        "{\"message_type\":\"aliases\",\"payload\":{}}",
+                    )),
+                    Ok(challenge),
+                ],
+                sent: sent.clone(),
+                node_opt: Some(node),
+            }
+        }
+    }
+
+    struct UiConnectionFactoryMock {
+        connect_parameters: Arc<Mutex<Vec<u16>>>,
+        connect_results: RefCell<Vec<Result<Box<dyn UiConnection>, String>>>,
+    }
+
+    impl UiConnectionFactory for UiConnectionFactoryMock {
+        fn connect(&self, port: u16) -> Result<Box<dyn UiConnection>, String> {
+            self.connect_parameters.lock().unwrap().push(port);
+            self.connect_results.borrow_mut().remove(0)
+        }
+    }
+
+    impl UiConnectionFactoryMock {
+        fn new() -> UiConnectionFactoryMock {
+            UiConnectionFactoryMock {
+                connect_parameters: Arc::new(Mutex::new(vec![])),
+                connect_results: RefCell::new(vec![]),
+            }
+        }
+
+        fn connect_result(self, result: Result<Box<dyn UiConnection>, String>) -> Self {
+            self.connect_results.borrow_mut().push(result);
+            self
+        }
+    }
+
+    fn make_args(words: Vec<&str>) -> Vec<String> {
+        let mut args = vec![String::from("substratum-cli")];
+        args.extend(words.into_iter().map(String::from));
+        args
+    }
+
+    fn make_summary() -> StartupSummary {
+        StartupSummary {
+            version: String::from("0.4.2"),
+            mode: String::from("decentralized"),
+            node_type: String::from("standard"),
+            node_descriptor: String::from("AQIDBA:1.2.3.4:1234,2345"),
+            clandestine_ports: vec![1234, 2345],
+            ui_port: 5333,
+            dns_servers: vec![String::from("8.8.8.8"), String::from("8.8.4.4")],
+            dns_subversion: String::from("unknown"),
+            earning_wallet: String::from("0xabcd"),
+            consuming_wallet: None,
+            crypto_backend: String::from("null"),
+            db_schema_version: String::from("0.0.1"),
+            adaptive_hops: true,
+        }
+    }
+
+    fn make_subject(factory: UiConnectionFactoryMock) -> SubstratumCli {
+        SubstratumCli {
+            factory: Box::new(factory),
+            default_token_file: None,
+        }
+    }
+
+    // With the Node's token where a Node would have left it
+    fn make_order_subject(factory: UiConnectionFactoryMock, name: &str) -> SubstratumCli {
+        let directory = PathBuf::from(format!("generated/test/substratum_cli/{}", name));
+        fs::remove_dir_all(&directory).is_ok();
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(directory.join(UI_TOKEN_FILE), format!("{}\n", TOKEN)).unwrap();
+        SubstratumCli {
+            factory: Box::new(factory),
+            default_token_file: Some(directory.join(UI_TOKEN_FILE)),
+        }
+    }
+
+    #[test]
+    fn status_prints_the_startup_summary() {
+        let connection = UiConnectionMock::new(
+            vec![Ok(make_summary().to_ui_json())],
+            &Arc::new(Mutex::new(vec![])),
+        );
+        let factory = UiConnectionFactoryMock::new().connect_result(Ok(Box::new(connection)));
+        let connect_parameters = factory.connect_parameters.clone();
+        let mut subject = make_subject(factory);
+        let mut holder = FakeStreamHolder::new();
+
+        let result = subject.go(
+            &mut holder.streams(),
+            &make_args(vec!["--ui_port", "4444", "status"]),
+        );
+
+        assert_eq!(result, 0);
+        assert_eq!(*connect_parameters.lock().unwrap(), vec![4444]);
+        let stdout = holder.stdout.get_string();
+        assert_eq!(
+            stdout.contains("Version:          0.4.2\n"),
+            true,
+            "{}",
+            stdout
+        );
+        assert_eq!(
+            stdout.contains("Node descriptor:  AQIDBA:1.2.3.4:1234,2345\n"),
+            true,
+            "{}",
+            stdout
+        );
+        assert_eq!(
+            stdout.contains("Clandestine ports:1234,2345\n"),
+            true,
+            "{}",
+            stdout
+        );
+        assert_eq!(
+            stdout.contains("Consuming wallet: (none)\n"),
+            true,
+            "{}",
+            stdout
+        );
+        assert_eq!(
+            stdout.contains("Adaptive hops:    available\n"),
+            true,
+            "{}",
+            stdout
+        );
+        assert_eq!(holder.stderr.get_string(), String::new());
+    }
+
+    #[test]
+    fn status_complains_when_there_is_no_node_to_talk_to() {
+        let factory = UiConnectionFactoryMock::new()
+            .connect_result(Err(String::from("Couldn't connect: booga")));
+        let connect_parameters = factory.connect_parameters.clone();
+        let mut subject = make_subject(factory);
+        let mut holder = FakeStreamHolder::new();
+
+        let result = subject.go(&mut holder.streams(), &make_args(vec!["status"]));
+
+        assert_eq!(result, 1);
+        assert_eq!(*connect_parameters.lock().unwrap(), vec![DEFAULT_UI_PORT]);
+        assert_eq!(holder.stderr.get_string(), "Couldn't connect: booga\n");
+    }
+
+    #[test]
+    fn status_complains_about_a_greeting_that_is_not_a_startup_summary() {
+        let connection = UiConnectionMock::new(
+            vec![Ok(String::from("{\"message_type\": \"booga\"}"))],
+            &Arc::new(Mutex::new(vec![])),
+        );
+        let factory = UiConnectionFactoryMock::new().connect_result(Ok(Box::new(connection)));
+        let mut subject = make_subject(factory);
+        let mut holder = FakeStreamHolder::new();
+
+        let result = subject.go(&mut holder.streams(), &make_args(vec!["status"]));
+
+        assert_eq!(result, 1);
+        assert_eq!(
+            holder.stderr.get_string(),
+            "Node sent something other than a startup summary: {\"message_type\": \"booga\"}\n"
+        );
+    }
+
+    #[test]
+    fn status_shows_aliases_for_the_node_and_its_wallets() {
+        let mut summary = make_summary();
+        summary.earning_wallet = String::from("0xbDfe...58CF");
+        summary.consuming_wallet = Some(String::from("0x9707...A26B"));
+        let connection = UiConnectionMock::new(
+            vec![
+                Ok(summary.to_ui_json()),
+                Ok(String::from("{\"message_type\":\"aliases\",\"payload\":{\"0xbdfeff9a1f4a1bdf483d680046344316019c58cf\":\"my VPS node\",\"AQIDBA\":\"this Node\"}}")),
+            ],
+            &Arc::new(Mutex::new(vec![])),
+        );
+        let factory = UiConnectionFactoryMock::new().connect_result(Ok(Box::new(connection)));
+        let mut subject = make_subject(factory);
+        let mut holder = FakeStreamHolder::new();
+
+        let result = subject.go(&mut holder.streams(), &make_args(vec!["status"]));
+
+        assert_eq!(result, 0);
+        let stdout = holder.stdout.get_string();
+        assert_eq!(
+            stdout.contains("Node descriptor:  this Node (AQIDBA:1.2.3.4:1234,2345)\n"),
+            true,
+            "{}",
+            stdout
+        );
+        assert_eq!(
+            stdout.contains("Earning wallet:   my VPS node (0xbDfe...58CF)\n"),
+            true,
+            "{}",
+            stdout
+        );
+        assert_eq!(
+            stdout.contains("Consuming wallet: 0x9707...A26B\n"),
+            true,
+            "{}",
+            stdout
+        );
+    }
+
+    #[test]
+    fn aliases_lists_the_aliases_the_node_keeps() {
+        let connection = UiConnectionMock::new(
+            vec![
+                Ok(make_summary().to_ui_json()),
+                Ok(String::from("{\"message_type\":\"aliases\",\"payload\":{\"QmlsbA\":\"brother's node\",\"0xabcd\":\"my wallet\"}}")),
+            ],
+            &Arc::new(Mutex::new(vec![])),
+        );
+        let factory = UiConnectionFactoryMock::new().connect_result(Ok(Box::new(connection)));
+        let mut subject = make_subject(factory);
+        let mut holder = FakeStreamHolder::new();
+
+        let result = subject.go(&mut holder.streams(), &make_args(vec!["aliases"]));
+
+        assert_eq!(result, 0);
+        assert_eq!(
+            holder.stdout.get_string(),
+            "0xabcd: my wallet\nQmlsbA: brother's node\n"
+        );
+    }
+
+    #[test]
+    fn aliases_are_found_past_the_message_catalog() {
+        let connection = UiConnectionMock::new(
+            vec![
+                Ok(make_summary().to_ui_json()),
+                Ok(catalog_to_ui_json()),
+                Ok(String::from(
+                    "{\"message_type\":\"aliases\",\"payload\":{\"QmlsbA\":\"brother's node\"}}",
+                )),
+            ],
+            &Arc::new(Mutex::new(vec![])),
+        );
+        let factory = UiConnectionFactoryMock::new().connect_result(Ok(Box::new(connection)));
+        let mut subject = make_subject(factory);
+        let mut holder = FakeStreamHolder::new();
+
+        let result = subject.go(&mut holder.streams(), &make_args(vec!["aliases"]));
+
+        assert_eq!(result, 0, "{}", holder.stderr.get_string());
+        assert_eq!(holder.stdout.get_string(), "QmlsbA: brother's node\n");
+    }
+
+    #[test]
+    fn aliases_says_so_when_there_are_none() {
+        let connection = UiConnectionMock::new(
+            vec![
+                Ok(make_summary().to_ui_json()),
+                Ok(String::from(
+                    "{\"message_type\":\"aliases\",\"payload\":{}}",
+                )),
+            ],
+            &Arc::new(Mutex::new(vec![])),
+        );
+        let factory = UiConnectionFactoryMock::new().connect_result(Ok(Box::new(connection)));
+        let mut subject = make_subject(factory);
+        let mut holder = FakeStreamHolder::new();
+
+        let result = subject.go(&mut holder.streams(), &make_args(vec!["aliases"]));
+
+        assert_eq!(result, 0);
+        assert_eq!(holder.stdout.get_string(), "No aliases\n");
+    }
+
+    #[test]
+    fn aliases_complains_about_a_node_that_sends_none() {
+        let connection = UiConnectionMock::new(
+            vec![
+                Ok(make_summary().to_ui_json()),
+                Ok(String::from("{\"message_type\": \"booga\"}")),
+            ],
+            &Arc::new(Mutex::new(vec![])),
+        );
+        let factory = UiConnectionFactoryMock::new().connect_result(Ok(Box::new(connection)));
+        let mut subject = make_subject(factory);
+        let mut holder = FakeStreamHolder::new();
+
+        let result = subject.go(&mut holder.streams(), &make_args(vec!["aliases"]));
+
+        assert_eq!(result, 1);
+        assert_eq!(
+            holder.stderr.get_string(),
+            "Node sent something other than its aliases: {\"message_type\": \"booga\"}\n"
+        );
+    }
+
+    #[test]
+    fn alias_and_unalias_send_alias_orders() {
+        let sent = Arc::new(Mutex::new(vec![]));
+        let factory = UiConnectionFactoryMock::new()
+            .connect_result(Ok(Box::new(UiConnectionMock::authenticating(TOKEN, &sent))))
+            .connect_result(Ok(Box::new(UiConnectionMock::authenticating(TOKEN, &sent))));
+        let mut subject = make_order_subject(factory, "alias_and_unalias_send_alias_orders");
+        let mut holder = FakeStreamHolder::new();
+
+        let alias_result = subject.go(
+            &mut holder.streams(),
+            &make_args(vec!["alias", "QmlsbA", "brother's \"fast\" node"]),
+        );
+        let unalias_result =
+            subject.go(&mut holder.streams(), &make_args(vec!["unalias", "QmlsbA"]));
+
+        assert_eq!(alias_result, 0);
+        assert_eq!(unalias_result, 0);
+        let sent: Vec<Value> = sent
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|json| serde_json::from_str(json).unwrap())
+            .collect();
+        assert_eq!(
+            sent,
+            vec![
+                serde_json::json!({
+                    "message_type": "set_alias",
+                    "payload": {"key": "QmlsbA", "label": "brother's \"fast\" node"},
+                }),
+                serde_json::json!({
+                    "message_type": "remove_alias",
+                    "payload": {"key": "QmlsbA"},
+                }),
+            ]
+        );
+        assert_eq!(
+            holder.stdout.get_string(),
+            format!(
+                "Alias for QmlsbA sent to Node on UI port {}\nAlias removal for QmlsbA sent to Node on UI port {}\n",
+                DEFAULT_UI_PORT, DEFAULT_UI_PORT
+            )
+        );
+    }
+
+    #[test]
+    fn adaptive_hops_sends_adaptive_hop_count_orders() {
+        let sent = Arc::new(Mutex::new(vec![]));
+        let factory = UiConnectionFactoryMock::new()
+            .connect_result(Ok(Box::new(UiConnectionMock::authenticating(TOKEN, &sent))))
+            .connect_result(Ok(Box::new(UiConnectionMock::authenticating(TOKEN, &sent))));
+        let mut subject =
+            make_order_subject(factory, "adaptive_hops_sends_adaptive_hop_count_orders");
+        let mut holder = FakeStreamHolder::new();
+
+        let off_result = subject.go(
+            &mut holder.streams(),
+            &make_args(vec!["adaptive-hops", "off"]),
+        );
+        let on_result = subject.go(
+            &mut holder.streams(),
+            &make_args(vec!["adaptive-hops", "on"]),
+        );
+
+        assert_eq!(off_result, 0);
+        assert_eq!(on_result, 0);
+        let sent: Vec<Value> = sent
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|json| serde_json::from_str(json).unwrap())
+            .collect();
+        assert_eq!(
+            sent,
+            vec![
+                serde_json::json!({
+                    "message_type": "set_adaptive_hops",
+                    "payload": {"enabled": false},
+                }),
+                serde_json::json!({
+                    "message_type": "set_adaptive_hops",
+                    "payload": {"enabled": true},
+                }),
+            ]
+        );
+        assert_eq!(
+            holder.stdout.get_string(),
+            format!(
+                "Adaptive hop count off order sent to Node on UI port {}\nAdaptive hop count on order sent to Node on UI port {}\n",
+                DEFAULT_UI_PORT, DEFAULT_UI_PORT
+            )
+        );
+    }
+
+    fn make_greetings(aliases: &str) -> Vec<Result<String, String>> {
+        vec![
+            Ok(make_summary().to_ui_json()),
+            Ok(catalog_to_ui_json()),
+            Ok(format!(
+                "{{\"message_type\":\"aliases\",\"payload\":{}}}",
+                aliases
+            )),
+        ]
+    }
+
+    fn make_neighbor(public_key: &str, node_addr: Option<&str>) -> TopologyNode {
+        TopologyNode {
+            public_key: String::from(public_key),
+            node_addr: node_addr.map(String::from),
+            version: 2,
+            country: None,
+            is_bootstrap_node: false,
+            offers_exit_tls: false,
+            relay_only: false,
+            neighbors: vec![],
+        }
+    }
+
+    fn sent_values(sent: &Arc<Mutex<Vec<String>>>) -> Vec<Value> {
+        sent.lock()
+            .unwrap()
+            .iter()
+            .map(|json| serde_json::from_str(json).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn financials_shows_the_balances_in_the_answer_to_its_query() {
+        let sent = Arc::new(Mutex::new(vec![]));
+        let mut greetings = make_greetings("{}");
+        greetings.push(Ok(NodeStatus::default().to_ui_json(FIRST_REQUEST_ID + 1)));
+        greetings.push(Ok(NodeStatus {
+            receivable_balance: 1234,
+            payable_balance: -56,
+            ..NodeStatus::default()
+        }
+        .to_ui_json(FIRST_REQUEST_ID)));
+        let factory = UiConnectionFactoryMock::new()
+            .connect_result(Ok(Box::new(UiConnectionMock::new(greetings, &sent))));
+        let mut subject = make_subject(factory);
+        let mut holder = FakeStreamHolder::new();
+
+        let result = subject.go(&mut holder.streams(), &make_args(vec!["financials"]));
+
+        assert_eq!(result, 0, "{}", holder.stderr.get_string());
+        assert_eq!(
+            sent_values(&sent),
+            vec![serde_json::json!({
+                "message_type": "status",
+                "request_id": FIRST_REQUEST_ID,
+                "payload": {},
+            })]
+        );
+        assert_eq!(
+            holder.stdout.get_string(),
+            "Owed to the Node: 1234\nOwed by the Node: -56\n"
+        );
+    }
+
+    #[test]
+    fn neighbors_lists_every_page_with_aliases() {
+        let sent = Arc::new(Mutex::new(vec![]));
+        let mut greetings = make_greetings("{\"BQYHCA\":\"brother's node\"}");
+        greetings.push(Ok(TopologyPage {
+            nodes: vec![make_neighbor("AQIDBA", Some("1.2.3.4:1234"))],
+            next_cursor: Some(String::from("AQIDBA")),
+        }
+        .to_ui_json(FIRST_REQUEST_ID)));
+        greetings.push(Ok(TopologyPage {
+            nodes: vec![make_neighbor("BQYHCA", None)],
+            next_cursor: None,
+        }
+        .to_ui_json(FIRST_REQUEST_ID + 1)));
+        let factory = UiConnectionFactoryMock::new()
+            .connect_result(Ok(Box::new(UiConnectionMock::new(greetings, &sent))));
+        let mut subject = make_subject(factory);
+        let mut holder = FakeStreamHolder::new();
+
+        let result = subject.go(&mut holder.streams(), &make_args(vec!["neighbors"]));
+
+        assert_eq!(result, 0, "{}", holder.stderr.get_string());
+        assert_eq!(
+            sent_values(&sent),
+            vec![
+                serde_json::json!({
+                    "message_type": "topology",
+                    "request_id": FIRST_REQUEST_ID,
+                    "payload": {"scope": "neighbors", "cursor": null},
+                }),
+                serde_json::json!({
+                    "message_type": "topology",
+                    "request_id": FIRST_REQUEST_ID + 1,
+                    "payload": {"scope": "neighbors", "cursor": "AQIDBA"},
+                }),
+            ]
+        );
+        assert_eq!(
+            holder.stdout.get_string(),
+            "AQIDBA: 1.2.3.4:1234\nbrother's node (BQYHCA): (no address)\n"
+        );
+    }
+
+    #[test]
+    fn neighbors_says_so_when_there_are_none() {
+        let mut greetings = make_greetings("{}");
+        greetings.push(Ok(TopologyPage::default().to_ui_json(FIRST_REQUEST_ID)));
+        let factory = UiConnectionFactoryMock::new().connect_result(Ok(Box::new(
+            UiConnectionMock::new(greetings, &Arc::new(Mutex::new(vec![]))),
+        )));
+        let mut subject = make_subject(factory);
+        let mut holder = FakeStreamHolder::new();
+
+        let result = subject.go(&mut holder.streams(), &make_args(vec!["neighbors"]));
+
+        assert_eq!(result, 0, "{}", holder.stderr.get_string());
+        assert_eq!(holder.stdout.get_string(), "No neighbors\n");
+    }
+
+    #[test]
+    fn a_query_the_node_is_too_old_to_answer_is_reported() {
+        let mut greetings = make_greetings("{}");
+        greetings.push(Ok(String::from(
+            "{\"message_type\":\"alert\",\"payload\":{\"code\":\"unsupported_message_type\",\"parameters\":{\"message_type\":\"topology\"}}}",
+        )));
+        let factory = UiConnectionFactoryMock::new().connect_result(Ok(Box::new(
+            UiConnectionMock::new(greetings, &Arc::new(Mutex::new(vec![]))),
+        )));
+        let mut subject = make_subject(factory);
+        let mut holder = FakeStreamHolder::new();
+
+        let result = subject.go(&mut holder.streams(), &make_args(vec!["neighbors"]));
+
+        assert_eq!(result, 1);
+        assert_eq!(
+            holder.stderr.get_string(),
+            "This Node's UI gateway can't answer 'topology' queries\n"
+        );
+    }
+
+    #[test]
+    fn set_sends_a_log_level_order() {
+        let sent = Arc::new(Mutex::new(vec![]));
+        let connection = UiConnectionMock::authenticating(TOKEN, &sent);
+        let factory = UiConnectionFactoryMock::new().connect_result(Ok(Box::new(connection)));
+        let mut subject = make_order_subject(factory, "set_sends_a_log_level_order");
+        let mut holder = FakeStreamHolder::new();
+
+        let result = subject.go(
+            &mut holder.streams(),
+            &make_args(vec!["set", "log_level", "debug"]),
+        );
+
+        assert_eq!(result, 0, "{}", holder.stderr.get_string());
+        assert_eq!(
+            sent_values(&sent),
+            vec![serde_json::json!({
+                "message_type": "set_log_level",
+                "payload": {"level": "debug"},
+            })]
+        );
+        assert_eq!(
+            holder.stdout.get_string(),
+            format!(
+                "Log level debug order sent to Node on UI port {}\n",
+                DEFAULT_UI_PORT
+            )
+        );
+    }
+
+    #[test]
+    fn set_refuses_a_setting_a_running_node_cannot_change_without_connecting() {
+        let factory = UiConnectionFactoryMock::new();
+        let connect_parameters = factory.connect_parameters.clone();
+        let mut subject = make_subject(factory);
+        let mut holder = FakeStreamHolder::new();
+
+        let result = subject.go(
+            &mut holder.streams(),
+            &make_args(vec!["set", "dns_servers", "1.1.1.1"]),
+        );
+
+        assert_eq!(result, 1);
+        assert_eq!(connect_parameters.lock().unwrap().is_empty(), true);
+        assert_eq!(
+            holder.stderr.get_string(),
+            "Can't set 'dns_servers': log_level is the only setting a running Node can change\n"
+        );
+    }
+
+    #[test]
+    fn reload_sends_a_reload_order() {
+        let sent = Arc::new(Mutex::new(vec![]));
+        let connection = UiConnectionMock::authenticating(TOKEN, &sent);
+        let factory = UiConnectionFactoryMock::new().connect_result(Ok(Box::new(connection)));
+        let mut subject = make_order_subject(factory, "reload_sends_a_reload_order");
+        let mut holder = FakeStreamHolder::new();
+
+        let result = subject.go(&mut holder.streams(), &make_args(vec!["reload"]));
+
+        assert_eq!(result, 0);
+        assert_eq!(
+            *sent.lock().unwrap(),
+            vec![String::from("{\"message_type\": \"reload\"}")]
+        );
+        assert_eq!(
+            holder.stdout.get_string(),
+            format!("Reload order sent to Node on UI port {}\n", DEFAULT_UI_PORT)
+        );
+    }
+
+    #[test]
+    fn shutdown_sends_a_shutdown_order() {
+        let sent = Arc::new(Mutex::new(vec![]));
+        let connection = UiConnectionMock::authenticating(TOKEN, &sent);
+        let factory = UiConnectionFactoryMock::new().connect_result(Ok(Box::new(connection)));
+        let mut subject = make_order_subject(factory, "shutdown_sends_a_shutdown_order");
+        let mut holder = FakeStreamHolder::new();
+
+        let result = subject.go(&mut holder.streams(), &make_args(vec!["shutdown"]));
+
+        assert_eq!(result, 0);
+        assert_eq!(
+            *sent.lock().unwrap(),
+            vec![String::from("{\"message_type\": \"shutdown\"}")]
+        );
+        assert_eq!(
+            holder.stdout.get_string(),
+            format!(
+                "Shutdown order sent to Node on UI port {}\n",
+                DEFAULT_UI_PORT
+            )
+        );
+    }
+
+    #[test]
+    fn an_order_is_sent_with_the_token_given_on_the_command_line() {
+        let sent = Arc::new(Mutex::new(vec![]));
+        let factory = UiConnectionFactoryMock::new().connect_result(Ok(Box::new(
+            UiConnectionMock::authenticating("the token on the command line", &sent),
+        )));
+        let mut subject = make_order_subject(
+            factory,
+            "an_order_is_sent_with_the_token_given_on_the_command_line",
+        );
+        let token_file = PathBuf::from(
+            "generated/test/substratum_cli/an_order_is_sent_with_the_token_given_on_the_command_line/other_token",
+        );
+        fs::write(&token_file, "the token on the command line").unwrap();
+        let mut holder = FakeStreamHolder::new();
+
+        let result = subject.go(
+            &mut holder.streams(),
+            &make_args(vec!["--ui_token", token_file.to_str().unwrap(), "reload"]),
+        );
+
+        assert_eq!(result, 0, "{}", holder.stderr.get_string());
+        assert_eq!(
+            *sent.lock().unwrap(),
+            vec![String::from("{\"message_type\": \"reload\"}")]
+        );
+    }
+
+    #[test]
+    fn an_order_is_not_sent_with_the_wrong_token() {
+        let sent = Arc::new(Mutex::new(vec![]));
+        let factory = UiConnectionFactoryMock::new().connect_result(Ok(Box::new(
+            UiConnectionMock::authenticating("wrong horse battery staple", &sent),
+        )));
+        let mut subject = make_order_subject(factory, "an_order_is_not_sent_with_the_wrong_token");
+        let mut holder = FakeStreamHolder::new();
+
+        let result = subject.go(&mut holder.streams(), &make_args(vec!["shutdown"]));
+
+        assert_eq!(result, 1);
+        assert_eq!(
+            holder.stderr.get_string(),
+            "Couldn't authenticate to the Node: Disconnected: wrong token\n"
+        );
+        assert_eq!(sent.lock().unwrap().is_empty(), true);
+    }
+
+    #[test]
+    fn an_order_without_a_token_is_refused_without_connecting() {
+        let factory = UiConnectionFactoryMock::new();
+        let connect_parameters = factory.connect_parameters.clone();
+        let mut subject = make_subject(factory);
+        let mut holder = FakeStreamHolder::new();
+
+        let no_directory = subject.go(&mut holder.streams(), &make_args(vec!["shutdown"]));
+        let no_file = subject.go(
+            &mut holder.streams(),
+            &make_args(vec!["--ui_token", "/nonexistent/ui_token", "shutdown"]),
+        );
+
+        assert_eq!(no_directory, 1);
+        assert_eq!(no_file, 1);
+        assert_eq!(connect_parameters.lock().unwrap().is_empty(), true);
+        let stderr = holder.stderr.get_string();
+        assert_eq!(
+            stderr.starts_with("No data directory to find the Node's UI token in; give --ui_token <token file>\nCouldn't read the Node's UI token from \"/nonexistent/ui_token\": "),
+            true,
+            "{}",
+            stderr
+        );
+    }
+
+    #[test]
+    fn a_node_started_with_a_token_is_answered_before_it_greets() {
+        let sent = Arc::new(Mutex::new(vec![]));
+        let mut node = RemoteAccess::new(TOKEN);
+        let connection = UiConnectionMock {
+            greetings: vec![Ok(node.challenge(0))],
+            sent: sent.clone(),
+            node_opt: Some(node),
+        };
+        let factory = UiConnectionFactoryMock::new().connect_result(Ok(Box::new(connection)));
+        let mut subject = make_order_subject(
+            factory,
+            "a_node_started_with_a_token_is_answered_before_it_greets",
+        );
+        let mut holder = FakeStreamHolder::new();
+
+        let result = subject.go(&mut holder.streams(), &make_args(vec!["reload"]));
+
+        assert_eq!(result, 0, "{}", holder.stderr.get_string());
+        assert_eq!(
+            *sent.lock().unwrap(),
+            vec![String::from("{\"message_type\": \"reload\"}")]
+        );
+    }
+
+    #[test]
+    fn add_neighbor_is_refused_without_connecting() {
+        let factory = UiConnectionFactoryMock::new();
+        let connect_parameters = factory.connect_parameters.clone();
+        let mut subject = make_subject(factory);
+        let mut holder = FakeStreamHolder::new();
+
+        let result = subject.go(
+            &mut holder.streams(),
+            &make_args(vec!["add-neighbor", "AQIDBA:1.2.3.4:1234"]),
+        );
+
+        assert_eq!(result, 1);
+        assert_eq!(connect_parameters.lock().unwrap().is_empty(), true);
+        assert_eq!(
+            holder.stderr.get_string(),
+            "This Node's UI gateway can't handle 'add-neighbor' yet; every other subcommand is available\n"
+        );
+    }
+
+    fn options(ui_port: u16) -> Options {
+        Options {
+            ui_port,
+            ui_token: None,
+        }
+    }
+
+    #[test]
+    fn parse_args_understands_options_in_either_order() {
+        let parse = |words: Vec<&str>| SubstratumCli::parse_args(&make_args(words));
+        let expected = Ok((
+            Options {
+                ui_port: 1234,
+                ui_token: Some(PathBuf::from("/tmp/token")),
+            },
+            Subcommand::Reload,
+        ));
+
+        assert_eq!(
+            parse(vec![
+                "--ui_port",
+                "1234",
+                "--ui_token",
+                "/tmp/token",
+                "reload"
+            ]),
+            expected
+        );
+        assert_eq!(
+            parse(vec![
+                "--ui_token",
+                "/tmp/token",
+                "--ui_port",
+                "1234",
+                "reload"
+            ]),
+            expected
+        );
+    }
+
+    #[test]
+    fn parse_args_understands_every_subcommand() {
+        let parse = |words: Vec<&str>| SubstratumCli::parse_args(&make_args(words));
+
+        assert_eq!(
+            parse(vec!["status"]),
+            Ok((options(DEFAULT_UI_PORT), Subcommand::Status))
+        );
+        assert_eq!(
+            parse(vec!["financials"]),
+            Ok((options(DEFAULT_UI_PORT), Subcommand::Financials))
+        );
+        assert_eq!(
+            parse(vec!["neighbors"]),
+            Ok((options(DEFAULT_UI_PORT), Subcommand::Neighbors))
+        );
+        assert_eq!(
+            parse(vec!["add-neighbor", "booga"]),
+            Ok((
+                options(DEFAULT_UI_PORT),
+                Subcommand::AddNeighbor(String::from("booga"))
+            ))
+        );
+        assert_eq!(
+            parse(vec!["--ui_port", "1234", "set", "name", "value"]),
+            Ok((
+                options(1234),
+                Subcommand::Set(String::from("name"), String::from("value"))
+            ))
+        );
+        assert_eq!(
+            parse(vec!["alias", "QmlsbA", "brother's node"]),
+            Ok((
+                options(DEFAULT_UI_PORT),
+                Subcommand::Alias(String::from("QmlsbA"), String::from("brother's node"))
+            ))
+        );
+        assert_eq!(
+            parse(vec!["unalias", "QmlsbA"]),
+            Ok((
+                options(DEFAULT_UI_PORT),
+                Subcommand::Unalias(String::from("QmlsbA"))
+            ))
+        );
+        assert_eq!(
+            parse(vec!["aliases"]),
+            Ok((options(DEFAULT_UI_PORT), Subcommand::Aliases))
+        );
+        assert_eq!(
+            parse(vec!["adaptive-hops", "on"]),
+            Ok((options(DEFAULT_UI_PORT), Subcommand::AdaptiveHops(true)))
+        );
+        assert_eq!(
+            parse(vec!["adaptive-hops", "off"]),
+            Ok((options(DEFAULT_UI_PORT), Subcommand::AdaptiveHops(false)))
+        );
+        assert_eq!(
+            parse(vec!["reload"]),
+            Ok((options(DEFAULT_UI_PORT), Subcommand::Reload))
+        );
+        assert_eq!(
+            parse(vec!["shutdown"]),
+            Ok((options(DEFAULT_UI_PORT), Subcommand::Shutdown))
+        );
+        assert_eq!(
+            parse(vec!["export-identity", "data", "archive"]),
+            Ok((
+                options(DEFAULT_UI_PORT),
+                Subcommand::ExportIdentity(String::from("data"), String::from("archive"))
+            ))
+        );
+        assert_eq!(
+            parse(vec!["import-identity", "archive", "data"]),
+            Ok((
+                options(DEFAULT_UI_PORT),
+                Subcommand::ImportIdentity(String::from("archive"), String::from("data"))
+            ))
+        );
+    }
+
+    #[test]
+    fn identity_can_be_exported_and_imported_without_a_node() {
+        let base_dir = PathBuf::from(
+            "generated/test/substratum_cli/identity_can_be_exported_and_imported_without_a_node",
+        );
+        fs::remove_dir_all(&base_dir).is_ok();
+        let old_dir = base_dir.join("old");
+        let new_dir = base_dir.join("new");
+        let archive_file = base_dir.join("identity.archive");
+        write_private_key(&old_dir, &PrivateKey::new(b"booga")).unwrap();
+        let mut subject = make_subject(UiConnectionFactoryMock::new());
+
+        let mut export_holder = FakeStreamHolder::new();
+        export_holder.stdin = ByteArrayReader::new(b"password\n");
+        let export_result = subject.go(
+            &mut export_holder.streams(),
+            &make_args(vec![
+                "export-identity",
+                old_dir.to_str().unwrap(),
+                archive_file.to_str().unwrap(),
+            ]),
+        );
+        let mut import_holder = FakeStreamHolder::new();
+        import_holder.stdin = ByteArrayReader::new(b"password\n");
+        let import_result = subject.go(
+            &mut import_holder.streams(),
+            &make_args(vec![
+                "import-identity",
+                archive_file.to_str().unwrap(),
+                new_dir.to_str().unwrap(),
+            ]),
+        );
+
+        assert_eq!(export_result, 0, "{}", export_holder.stderr.get_string());
+        assert_eq!(import_result, 0, "{}", import_holder.stderr.get_string());
+        assert_eq!(
+            read_private_key(&new_dir).unwrap(),
+            Some(PrivateKey::new(b"booga"))
+        );
+        assert_eq!(
+            import_holder.stdout.get_string(),
+            format!(
+                "Identity in {} imported to {}\n",
+                archive_file.to_str().unwrap(),
+                new_dir.to_str().unwrap()
+            )
+        );
+    }
+
+    #[test]
+    fn export_identity_refuses_an_empty_password() {
+        let mut subject = make_subject(UiConnectionFactoryMock::new());
+        let mut holder = FakeStreamHolder::new();
+        holder.stdin = ByteArrayReader::new(b"\n");
+
+        let result = subject.go(
+            &mut holder.streams(),
+            &make_args(vec!["export-identity", "data", "archive"]),
+        );
+
+        assert_eq!(result, 1);
+        assert_eq!(
+            holder.stderr.get_string(),
+            String::from("No password given: type it as the first line of standard input\n")
+        );
+    }
+
+    #[test]
+    fn bad_arguments_produce_usage() {
+        vec![
+            (vec![], "No subcommand given"),
+            (vec!["booga"], "Can't understand 'booga'"),
+            (vec!["set", "name"], "Can't understand 'set name'"),
+            (vec!["alias", "QmlsbA"], "Can't understand 'alias QmlsbA'"),
+            (vec!["--ui_port"], "--ui_port needs a port number"),
+            (vec!["--ui_token"], "--ui_token needs a token file"),
+            (
+                vec!["--booga", "booga", "status"],
+                "Can't understand '--booga booga status'",
+            ),
+            (
+                vec!["--ui_port", "booga", "status"],
+                "Invalid port for --ui_port <port number>: 'booga'",
+            ),
+        ]
+        .into_iter()
+        .for_each(|(words, msg)| {
+            let mut subject = make_subject(UiConnectionFactoryMock::new());
+            let mut holder = FakeStreamHolder::new();
+
+            let result = subject.go(&mut holder.streams(), &make_args(words));
+
+            assert_eq!(result, 1);
+            assert_eq!(holder.stderr.get_string(), format!("{}\n{}\n", msg, USAGE));
+        });
+    }
+}
@@ -0,0 +1,86 @@
+// Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use std::net::TcpStream;
+use std::time::Duration;
+use websocket::sync::Client;
+use websocket::ClientBuilder;
+use websocket::OwnedMessage;
+
+pub const UI_PROTOCOL: &str = "SubstratumNode-UI";
+pub const GREETING_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub trait UiConnection {
+    // A Node greets a newly-connected UI with its startup summary, its message catalog, then its
+    // operator's aliases
+    fn receive_greeting(&mut self) -> Result<String, String>;
+    // Whatever the Node says next
+    fn receive(&mut self) -> Result<String, String>;
+    fn send(&mut self, json: &str) -> Result<(), String>;
+}
+
+pub trait UiConnectionFactory {
+    fn connect(&self, port: u16) -> Result<Box<dyn UiConnection>, String>;
+}
+
+pub struct UiConnectionReal {
+    client: Client<TcpStream>,
+}
+
+impl UiConnection for UiConnectionReal {
+    fn receive_greeting(&mut self) -> Result<String, String> {
+        match self.client.recv_message() {
+            Ok(OwnedMessage::Text(json)) => Ok(json),
+            Ok(message) => Err(format!("Expected a startup summary, got {:?}", message)),
+            Err(e) => Err(format!("No startup summary from the Node: {}", e)),
+        }
+    }
+
+    fn receive(&mut self) -> Result<String, String> {
+        match self.client.recv_message() {
+            Ok(OwnedMessage::Text(json)) => Ok(json),
+            Ok(message) => Err(format!("Expected text from the Node, got {:?}", message)),
+            Err(e) => Err(format!("No answer from the Node: {}", e)),
+        }
+    }
+
+    fn send(&mut self, json: &str) -> Result<(), String> {
+        match self
+            .client
+            .send_message(&OwnedMessage::Text(String::from(json)))
+        {
+            Ok(_) => Ok(()),
+            Err(e) => Err(format!("Couldn't send to the Node: {}", e)),
+        }
+    }
+}
+
+pub struct UiConnectionFactoryReal {}
+
+impl UiConnectionFactory for UiConnectionFactoryReal {
+    fn connect(&self, port: u16) -> Result<Box<dyn UiConnection>, String> {
+        let url = format!("ws://127.0.0.1:{}", port);
+        let builder = match ClientBuilder::new(&url) {
+            Ok(builder) => builder,
+            Err(e) => return Err(format!("Bad UI gateway URL {}: {}", url, e)),
+        };
+        let client = match builder.add_protocol(UI_PROTOCOL).connect_insecure() {
+            Ok(client) => client,
+            Err(e) => {
+                return Err(format!(
+                    "Couldn't connect to a Node's UI gateway at {}: {}",
+                    url, e
+                ))
+            }
+        };
+        client
+            .stream_ref()
+            .set_read_timeout(Some(GREETING_TIMEOUT))
+            .expect("Couldn't set read timeout");
+        Ok(Box::new(UiConnectionReal { client }))
+    }
+}
+
+impl UiConnectionFactoryReal {
+    pub fn new() -> UiConnectionFactoryReal {
+        UiConnectionFactoryReal {}
+    }
+}